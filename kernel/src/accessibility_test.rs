@@ -0,0 +1,134 @@
+//! Accessibility Test
+//! Builds the accessibility tree of a sign-in window and checks the roles, names, values
+//! and states it reports, and the focus and value notifications sent to assistive services
+
+use alloc::format;
+use alloc::string::String;
+use crate::graphics::{self, Rect, WidgetType, WindowId, WindowManager};
+use crate::serial::_print;
+use crate::ui::accessibility::{self, AccessibilityEvent, AccessibleNode, Role};
+
+const KEY_TAB: u32 = 9;
+
+/// A sign-in window: a label, a text input named by it, a button and a disabled button
+fn sign_in_window(wm: &mut WindowManager) -> Result<WindowId, &'static str> {
+    let window_id = wm.create_window(String::from("Sign in"), Rect::new(50, 50, 320, 200), 1);
+    let window = wm.get_window_mut(window_id).ok_or("Sign-in window missing")?;
+    window.add_widget(WidgetType::Label { text: String::from("User name") }, Rect::new(10, 10, 100, 20));
+    let field = window.add_widget(WidgetType::TextInput { text: String::new(), cursor_pos: 0 }, Rect::new(120, 10, 180, 20));
+    window.add_widget(WidgetType::Button { text: String::from("Log in") }, Rect::new(10, 150, 90, 30));
+    let reset = window.add_widget(WidgetType::Button { text: String::from("Reset") }, Rect::new(110, 150, 90, 30));
+    for widget in window.widgets.iter_mut() {
+        if widget.id == field {
+            widget.accessible_name = Some(String::from("User name"));
+        }
+        if widget.id == reset {
+            widget.enabled = false;
+        }
+    }
+    Ok(window_id)
+}
+
+fn tree(wm: &WindowManager, window_id: WindowId) -> Result<AccessibleNode, &'static str> {
+    wm.get_window(window_id).map(accessibility::build_tree).ok_or("Window missing")
+}
+
+fn node<'a>(tree: &'a AccessibleNode, role: Role, name: &str) -> Result<&'a AccessibleNode, &'static str> {
+    tree.find(&|node: &AccessibleNode| node.role == role && node.name == name).ok_or("Node missing from the tree")
+}
+
+pub fn run_accessibility_tests() -> Result<(), &'static str> {
+    _print(format_args!("[A11y Test] Starting accessibility tests...\n"));
+    let mut wm = WindowManager::new(640, 480);
+    let window_id = sign_in_window(&mut wm)?;
+    accessibility::take_events();
+
+    // Test 1: Every widget appears with its role, name and state
+    _print(format_args!("[A11y Test] Test 1: Accessibility tree of a sign-in window...\n"));
+    let root = tree(&wm, window_id)?;
+    if root.role != Role::Window || root.name != "Sign in" || root.children.len() != 4 {
+        return Err("Window node wrong");
+    }
+    let label = node(&root, Role::Label, "User name")?;
+    let field = node(&root, Role::TextField, "User name")?;
+    let login = node(&root, Role::Button, "Log in")?;
+    let reset = node(&root, Role::Button, "Reset")?;
+    if label.value.is_some() || field.value.as_deref() != Some("") || login.value.is_some() {
+        return Err("Only the text field should carry a value");
+    }
+    if login.disabled || !reset.disabled || field.focused {
+        return Err("Widget states wrong");
+    }
+    let global = graphics::create_window("Global", 0, 0, 100, 100, 1);
+    let dumped = accessibility::dump_tree(global);
+    graphics::destroy_window(global);
+    if dumped.map(|node| (node.role, node.name)) != Some((Role::Window, String::from("Global"))) {
+        return Err("dump_tree did not return the window manager's window");
+    }
+    _print(format_args!("[A11y Test] ✓ Roles, names, values and states reported\n"));
+
+    // Test 2: Focusing a widget sends a focus-change notification
+    _print(format_args!("[A11y Test] Test 2: Focus-change notifications...\n"));
+    let (field_id, login_id, label_id) = (field.id, login.id, label.id);
+    // Click the text field
+    wm.dispatch_mouse_event(50 + 130, 50 + 15, 0, true);
+    wm.dispatch_mouse_event(50 + 130, 50 + 15, 0, false);
+    if accessibility::take_events() != [AccessibilityEvent::FocusChanged { window: window_id, widget: Some(field_id) }] {
+        return Err("Clicking the text field did not announce its focus");
+    }
+    if !node(&tree(&wm, window_id)?, Role::TextField, "User name")?.focused {
+        return Err("Focused text field not marked focused");
+    }
+    // Tab moves on to the button, then skips the disabled one
+    wm.dispatch_keyboard_event(KEY_TAB, true);
+    wm.dispatch_keyboard_event(KEY_TAB, true);
+    let expected = [
+        AccessibilityEvent::FocusChanged { window: window_id, widget: Some(login_id) },
+        AccessibilityEvent::FocusChanged { window: window_id, widget: Some(label_id) },
+    ];
+    if accessibility::take_events() != expected {
+        return Err("Tab focus changes not announced, or a disabled widget took focus");
+    }
+    _print(format_args!("[A11y Test] ✓ Focus changes announced\n"));
+
+    // Test 3: Typing into the text field sends value-change notifications
+    _print(format_args!("[A11y Test] Test 3: Value-change notifications...\n"));
+    wm.dispatch_mouse_event(50 + 130, 50 + 15, 0, true);
+    accessibility::take_events();
+    for key_code in [b'b', b'o', b'b'] {
+        wm.dispatch_keyboard_event(u32::from(key_code), true);
+        wm.dispatch_keyboard_event(u32::from(key_code), false);
+    }
+    let values: alloc::vec::Vec<String> = accessibility::take_events()
+        .into_iter()
+        .filter_map(|event| match event {
+            AccessibilityEvent::ValueChanged { window, widget, value } if window == window_id && widget == field_id => Some(value),
+            _ => None,
+        })
+        .collect();
+    if values != ["b", "bo", "bob"] {
+        return Err("Value changes not announced");
+    }
+    let dump = format!("{}", tree(&wm, window_id)?);
+    if !dump.contains("  textfield \"User name\" = \"bob\" [focused]\n") || !dump.contains("  button \"Reset\" [disabled]\n") {
+        return Err("Tree dump does not show the field's value and states");
+    }
+    _print(format_args!("[A11y Test] ✓ Value changes announced\n"));
+
+    _print(format_args!("[A11y Test] ✓ All accessibility tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for the accessibility tree
+pub fn test_accessibility() {
+    _print(format_args!("[A11y Test] ===========================================\n"));
+    _print(format_args!("[A11y Test]            ACCESSIBILITY TESTS\n"));
+    _print(format_args!("[A11y Test] ===========================================\n"));
+
+    match run_accessibility_tests() {
+        Ok(_) => _print(format_args!("[A11y Test] ✓ All accessibility tests PASSED\n")),
+        Err(e) => _print(format_args!("[A11y Test] ✗ Accessibility tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[A11y Test] ===========================================\n"));
+}
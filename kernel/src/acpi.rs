@@ -0,0 +1,158 @@
+//! ACPI table discovery for RaeenOS
+//! Locates the RSDP (from UEFI or the legacy BIOS areas) and walks the RSDT/XSDT
+//! so subsystems can find the tables they need by signature
+
+use spin::Mutex;
+use x86_64::PhysAddr;
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+
+/// Legacy BIOS areas searched for the RSDP
+const EBDA_POINTER: u64 = 0x40E;
+const BIOS_AREA_START: u64 = 0xE0000;
+const BIOS_AREA_END: u64 = 0x100000;
+
+/// Common header of every System Description Table
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+/// ACPI Generic Address Structure
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct GenericAddress {
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+pub const ADDRESS_SPACE_SYSTEM_MEMORY: u8 = 0;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    // ACPI 2.0+
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RootTable {
+    Rsdt(PhysAddr),
+    Xsdt(PhysAddr),
+}
+
+static ROOT_TABLE: Mutex<Option<RootTable>> = Mutex::new(None);
+
+fn checksum_ok(addr: PhysAddr, len: usize) -> bool {
+    let ptr = crate::memory::phys_to_virt(addr).as_ptr::<u8>();
+    let sum = (0..len).fold(0u8, |sum, i| sum.wrapping_add(unsafe { core::ptr::read_volatile(ptr.add(i)) }));
+    sum == 0
+}
+
+fn read_rsdp(addr: PhysAddr) -> Option<Rsdp> {
+    let rsdp = unsafe { core::ptr::read_unaligned(crate::memory::phys_to_virt(addr).as_ptr::<Rsdp>()) };
+    if &rsdp.signature != RSDP_SIGNATURE || !checksum_ok(addr, 20) {
+        return None;
+    }
+    Some(rsdp)
+}
+
+/// Scan a physical range on 16-byte boundaries for a valid RSDP
+fn scan_for_rsdp(start: u64, end: u64) -> Option<PhysAddr> {
+    (start..end).step_by(16).map(PhysAddr::new).find(|&addr| read_rsdp(addr).is_some())
+}
+
+fn find_rsdp() -> Option<PhysAddr> {
+    if let Some(addr) = crate::uefi::get_rsdp_addr() {
+        return Some(addr);
+    }
+
+    let ebda_segment = unsafe {
+        core::ptr::read_volatile(crate::memory::phys_to_virt(PhysAddr::new(EBDA_POINTER)).as_ptr::<u16>())
+    };
+    let ebda = (ebda_segment as u64) << 4;
+    if ebda != 0 {
+        if let Some(addr) = scan_for_rsdp(ebda, ebda + 1024) {
+            return Some(addr);
+        }
+    }
+
+    scan_for_rsdp(BIOS_AREA_START, BIOS_AREA_END)
+}
+
+fn root_table() -> Option<RootTable> {
+    let mut root = ROOT_TABLE.lock();
+    if root.is_none() {
+        let addr = find_rsdp()?;
+        let rsdp = read_rsdp(addr)?;
+        *root = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 && checksum_ok(addr, rsdp.length as usize) {
+            Some(RootTable::Xsdt(PhysAddr::new(rsdp.xsdt_address)))
+        } else {
+            Some(RootTable::Rsdt(PhysAddr::new(rsdp.rsdt_address as u64)))
+        };
+    }
+    *root
+}
+
+/// Read the header of the table at `addr`
+pub fn read_header(addr: PhysAddr) -> SdtHeader {
+    unsafe { core::ptr::read_unaligned(crate::memory::phys_to_virt(addr).as_ptr::<SdtHeader>()) }
+}
+
+/// Read a table structure (header included) at `addr`
+///
+/// # Safety
+/// `T` must be a `repr(C, packed)` layout no longer than the table at `addr`.
+pub unsafe fn read_table<T: Copy>(addr: PhysAddr) -> T {
+    core::ptr::read_unaligned(crate::memory::phys_to_virt(addr).as_ptr::<T>())
+}
+
+/// Find the first table with `signature` and a valid checksum
+pub fn find_table(signature: &[u8; 4]) -> Option<PhysAddr> {
+    let (root, entry_size) = match root_table()? {
+        RootTable::Rsdt(addr) => (addr, 4),
+        RootTable::Xsdt(addr) => (addr, 8),
+    };
+
+    let header = read_header(root);
+    let entries = (header.length as usize).saturating_sub(core::mem::size_of::<SdtHeader>()) / entry_size;
+    let first = crate::memory::phys_to_virt(root + core::mem::size_of::<SdtHeader>() as u64);
+
+    (0..entries)
+        .map(|i| {
+            let ptr = first + (i * entry_size) as u64;
+            let addr = unsafe {
+                if entry_size == 8 {
+                    core::ptr::read_unaligned(ptr.as_ptr::<u64>())
+                } else {
+                    core::ptr::read_unaligned(ptr.as_ptr::<u32>()) as u64
+                }
+            };
+            PhysAddr::new(addr)
+        })
+        .find(|&addr| {
+            let table = read_header(addr);
+            &table.signature == signature && checksum_ok(addr, table.length as usize)
+        })
+}
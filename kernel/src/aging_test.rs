@@ -0,0 +1,152 @@
+//! Priority Aging Test
+//! Saturates a CPU's High queue and checks, on a simulated clock, that waiting Normal and Low
+//! processes move up a queue every `PRIORITY_AGING_MS` until they run, drop back to their own
+//! priority once they have, and so keep running at a bounded interval, that a Low process
+//! also runs under continuous Normal load, and that renicing moves a process to the queue of
+//! its new priority, or, while it holds a lent priority, to that queue once it is given back
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use x86_64::VirtAddr;
+use crate::arch::topology::CpuTopology;
+use crate::process::{CpuScheduler, Priority, Process, SmpScheduler, NICE_MAX, NICE_MIN, PRIORITY_AGING_MS};
+use crate::serial::_print;
+use crate::vmm;
+
+/// Scheduler time slice, the simulated time between scheduling decisions
+const SLICE_MS: u64 = 10;
+const HIGH: [u64; 4] = [1, 2, 3, 4];
+const NORMAL: u64 = 11;
+const LOW: u64 = 12;
+
+/// Schedule every `SLICE_MS` until `until_ms`, returning when each process was picked
+fn run(scheduler: &mut CpuScheduler, until_ms: u64) -> Vec<(u64, u64)> {
+    (0..until_ms / SLICE_MS)
+        .map(|tick| tick * SLICE_MS)
+        .filter_map(|now| scheduler.schedule_at(now, false, &[]).map(|pid| (now, pid)))
+        .collect()
+}
+
+/// When `pid` was picked
+fn runs_of(picks: &[(u64, u64)], pid: u64) -> Vec<u64> {
+    picks.iter().filter(|&&(_, picked)| picked == pid).map(|&(now, _)| now).collect()
+}
+
+pub fn run_aging_tests() -> Result<(), &'static str> {
+    let mut scheduler = CpuScheduler::new(0);
+    for pid in HIGH {
+        scheduler.add_process(pid, Priority::High);
+    }
+    scheduler.add_process(NORMAL, Priority::Normal);
+    scheduler.add_process(LOW, Priority::Low);
+    let picks = run(&mut scheduler, 10 * PRIORITY_AGING_MS);
+    let (normal, low) = (runs_of(&picks, NORMAL), runs_of(&picks, LOW));
+
+    // Test 1: Starved processes wait out their aging before running, and no longer than that
+    _print(format_args!("[Aging Test] Test 1: Starved processes run...\n"));
+    // Each move up lands at the back of the next queue, behind at most every High process
+    let behind_high = (HIGH.len() as u64 + 1) * SLICE_MS;
+    match (normal.first(), low.first()) {
+        (Some(&normal), Some(&low))
+            if (PRIORITY_AGING_MS..=PRIORITY_AGING_MS + behind_high).contains(&normal)
+                && (2 * PRIORITY_AGING_MS..=2 * PRIORITY_AGING_MS + behind_high).contains(&low) => {}
+        _ => return Err("Starved processes did not run when aged"),
+    }
+    _print(format_args!("[Aging Test] ✓ Normal first ran at {}ms, Low at {}ms\n", normal[0], low[0]));
+
+    // Test 2: Once run, a process is back at its own priority and has to age again
+    _print(format_args!("[Aging Test] Test 2: Boost resets after running...\n"));
+    let gaps = |runs: &[u64], levels: u64| {
+        runs.windows(2).all(|pair| (levels * PRIORITY_AGING_MS..=levels * PRIORITY_AGING_MS + behind_high).contains(&(pair[1] - pair[0])))
+    };
+    if low.len() < 3 || normal.len() < 5 || !gaps(&low, 2) || !gaps(&normal, 1) {
+        return Err("Aged processes kept their boost or stopped running");
+    }
+    _print(format_args!("[Aging Test] ✓ Low ran {} times and Normal {} times in {}ms\n", low.len(), normal.len(), 10 * PRIORITY_AGING_MS));
+
+    // Test 3: The High processes still get nearly all of the CPU, in turn
+    _print(format_args!("[Aging Test] Test 3: High processes keep running...\n"));
+    let high: Vec<usize> = HIGH.iter().map(|&pid| runs_of(&picks, pid).len()).collect();
+    let (fewest, most) = (high.iter().min().copied().unwrap_or(0), high.iter().max().copied().unwrap_or(0));
+    if high.iter().sum::<usize>() + normal.len() + low.len() != picks.len() || most - fewest > 1 || fewest * HIGH.len() < picks.len() * 3 / 4 {
+        return Err("High processes lost their share");
+    }
+    _print(format_args!("[Aging Test] ✓ High processes ran {:?} times\n", high));
+
+    // Test 4: A Low process runs under continuous Normal load
+    _print(format_args!("[Aging Test] Test 4: Low runs under Normal load...\n"));
+    let mut scheduler = CpuScheduler::new(0);
+    for pid in HIGH {
+        scheduler.add_process(pid, Priority::Normal);
+    }
+    scheduler.add_process(LOW, Priority::Low);
+    let low = runs_of(&run(&mut scheduler, 10 * PRIORITY_AGING_MS), LOW);
+    match low.first() {
+        Some(&first) if (PRIORITY_AGING_MS..=PRIORITY_AGING_MS + behind_high).contains(&first) && low.len() >= 5 && gaps(&low, 1) => {}
+        _ => return Err("Low process starved by Normal load"),
+    }
+    _print(format_args!("[Aging Test] ✓ Low ran {} times, first at {}ms\n", low.len(), low[0]));
+
+    // Test 5: Renicing moves a process between queues, after any lent priority
+    _print(format_args!("[Aging Test] Test 5: Renice...\n"));
+    let mut address_spaces = Vec::new();
+    let result = renice(&mut address_spaces);
+    for id in address_spaces {
+        let _ = vmm::destroy_address_space(id);
+    }
+    result?;
+
+    _print(format_args!("[Aging Test] ✓ All priority aging tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Processes queued at each priority on the one CPU of `scheduler`
+fn queued(scheduler: &SmpScheduler) -> [usize; 4] {
+    scheduler.sched_stats().first().map_or([0; 4], |cpu| cpu.queue_lengths)
+}
+
+fn renice(address_spaces: &mut Vec<u64>) -> Result<(), &'static str> {
+    if [NICE_MIN - 1, NICE_MIN, 0, 10, NICE_MAX, NICE_MAX + 1].map(Priority::from_nice)
+        != [None, Some(Priority::High), Some(Priority::Normal), Some(Priority::Low), Some(Priority::Low), None]
+    {
+        return Err("Nice values mapped to the wrong priorities");
+    }
+    let mut topology = CpuTopology::from_cpuid(&|_, _| (0, 0, 0, 0));
+    topology.add_cpu(0, 0);
+    let mut scheduler = SmpScheduler::with_topology(topology);
+    let mut spawn = |priority: Priority| -> Result<u64, &'static str> {
+        let process = Process::new(String::from("aging-test"), VirtAddr::new(0x400000), priority).map_err(|_| "Failed to create process")?;
+        address_spaces.extend(process.address_space_id);
+        Ok(scheduler.add_process(process))
+    };
+    let (reniced, holder) = (spawn(Priority::Low)?, spawn(Priority::High)?);
+    let normal = Priority::from_nice(0).ok_or("No priority for nice 0")?;
+    if !scheduler.set_process_priority(reniced, normal) || queued(&scheduler) != [1, 1, 0, 0] {
+        return Err("Reniced process not moved to its new queue");
+    }
+    scheduler.inherit_priority_across_cpus(reniced, holder);
+    let low = Priority::from_nice(NICE_MAX).ok_or("No priority for the highest nice")?;
+    if !scheduler.set_process_priority(reniced, low) || queued(&scheduler) != [2, 0, 0, 0] {
+        return Err("Renice took away a lent priority");
+    }
+    scheduler.restore_priority_across_cpus(reniced);
+    if queued(&scheduler) != [1, 0, 1, 0] || scheduler.set_process_priority(u64::MAX, normal) {
+        return Err("Renice not applied once the lent priority was given back");
+    }
+    _print(format_args!("[Aging Test] ✓ PID {} reniced to Normal, then to Low under a lent High\n", reniced));
+    Ok(())
+}
+
+/// Main test runner for priority aging
+pub fn test_aging() {
+    _print(format_args!("[Aging Test] ===========================================\n"));
+    _print(format_args!("[Aging Test]        PRIORITY AGING TESTS\n"));
+    _print(format_args!("[Aging Test] ===========================================\n"));
+
+    match run_aging_tests() {
+        Ok(_) => _print(format_args!("[Aging Test] ✓ All priority aging tests PASSED\n")),
+        Err(e) => _print(format_args!("[Aging Test] ✗ Priority aging tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Aging Test] ===========================================\n"));
+}
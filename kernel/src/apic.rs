@@ -746,6 +746,23 @@ pub fn get_apic_id() -> u32 {
     controller.local_apic().get_apic_id()
 }
 
+/// Route an I/O APIC input (GSI) to `vector` on the current CPU
+pub fn route_irq(gsi: u8, vector: u8) -> Result<(), &'static str> {
+    let controller = SMP_CONTROLLER.lock();
+    let apic_id = controller.local_apic().get_apic_id();
+    let io_apic = controller.io_apic(0).ok_or("No I/O APIC available")?;
+    io_apic.set_redirection_entry(gsi, vector, apic_id, DeliveryMode::Fixed, DestinationMode::Physical);
+    Ok(())
+}
+
+/// Mask an I/O APIC input (GSI)
+pub fn mask_irq(gsi: u8) {
+    let controller = SMP_CONTROLLER.lock();
+    if let Some(io_apic) = controller.io_apic(0) {
+        io_apic.mask_irq(gsi);
+    }
+}
+
 /// Set timer for one-shot mode
 pub fn set_timer_oneshot(microseconds: u64) {
     let controller = SMP_CONTROLLER.lock();
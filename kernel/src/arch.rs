@@ -5,6 +5,8 @@
 
 use core::arch::global_asm;
 
+pub mod topology;
+
 // Context layout (offsets in bytes) must match `process::ProcessContext`:
 //  0  rax, 8  rbx, 16 rcx, 24 rdx, 32 rsi, 40 rdi, 48 rbp, 56 rsp,
 //  64 r8,  72 r9,  80 r10, 88 r11, 96 r12, 104 r13, 112 r14, 120 r15,
@@ -792,10 +794,14 @@ pub mod tsc {
         Ok(())
     }
     
-    /// Calibrate TSC frequency using APIC timer or PIT
+    /// Calibrate TSC frequency against the HPET, or the PIT without one
     fn calibrate_tsc_frequency() -> Result<(), &'static str> {
-        // Use a known timer source to calibrate TSC
-        // For now, we'll use a simple PIT-based calibration
+        if let Some(tsc_freq) = crate::time::hpet::calibrate_tsc() {
+            TSC_FREQUENCY.store(tsc_freq, Ordering::SeqCst);
+            return Ok(());
+        }
+        
+        // Fall back to a simple PIT-based calibration
         let start_tsc = read_tsc_serialized();
         
         // Wait approximately 10ms using PIT
@@ -1172,6 +1178,18 @@ pub mod uaccess {
         Ok(())
     }
     
+    /// Give the user range being written frames of its own, copying pages shared copy-on-write,
+    /// since CR0.WP makes the kernel's writes obey read-only mappings as user code's do
+    fn prepare_user_write(ptr: u64, len: usize) -> Result<(), UAccessError> {
+        use crate::vmm::VmError;
+        crate::vmm::prepare_user_write(ptr, len as u64).map_err(|error| match error {
+            VmError::PermissionDenied => UAccessError::PermissionDenied,
+            VmError::SegmentationFault => UAccessError::InvalidPointer,
+            VmError::InvalidAddressSpace => UAccessError::AddressSpaceError,
+            _ => UAccessError::PageFault,
+        })
+    }
+    
     /// Copy data from user space to kernel space
     pub fn copy_from_user(dst: &mut [u8], src_ptr: u64) -> Result<(), UAccessError> {
         validate_user_range(src_ptr, dst.len())?;
@@ -1195,6 +1213,7 @@ pub mod uaccess {
     /// Copy data from kernel space to user space
     pub fn copy_to_user(dst_ptr: u64, src: &[u8]) -> Result<(), UAccessError> {
         validate_user_range(dst_ptr, src.len())?;
+        prepare_user_write(dst_ptr, src.len())?;
         
         unsafe {
             enable_user_access();
@@ -1258,6 +1277,7 @@ pub mod uaccess {
     /// Write a single value to user space
     pub fn write_user_value<T: Copy>(ptr: u64, value: T) -> Result<(), UAccessError> {
         validate_user_range(ptr, core::mem::size_of::<T>())?;
+        prepare_user_write(ptr, core::mem::size_of::<T>())?;
         
         unsafe {
             enable_user_access();
@@ -1266,6 +1286,26 @@ pub mod uaccess {
             Ok(())
         }
     }
+
+    /// Atomically replace the aligned `u32` at `ptr` with `new` if it holds `current`,
+    /// returning the value found there
+    pub fn cmpxchg_user_u32(ptr: u64, current: u32, new: u32) -> Result<u32, UAccessError> {
+        validate_user_range(ptr, core::mem::size_of::<u32>())?;
+        if ptr % 4 != 0 {
+            return Err(UAccessError::InvalidPointer);
+        }
+        prepare_user_write(ptr, core::mem::size_of::<u32>())?;
+
+        unsafe {
+            enable_user_access();
+            let word = &*(ptr as *const core::sync::atomic::AtomicU32);
+            let found = match word.compare_exchange(current, new, core::sync::atomic::Ordering::SeqCst, core::sync::atomic::Ordering::SeqCst) {
+                Ok(found) | Err(found) => found,
+            };
+            disable_user_access();
+            Ok(found)
+        }
+    }
 }
 
 /// FPU/SIMD context management
@@ -0,0 +1,336 @@
+//! CPU topology
+//! Packages, cores and SMT threads, and which of them share each cache, worked out from the
+//! x2APIC ID layout CPUID reports (leaf 0x1F, else 0x0B, else the legacy leaf 1 and 4 counts)
+//! and the cache descriptors of leaf 0x04 (0x8000001D on AMD). Every logical CPU brought
+//! online is placed in the tree by its APIC ID; the scheduler asks it for SMT siblings and
+//! shared caches, and it is published as `/proc/cpuinfo`.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use lazy_static::lazy_static;
+use spin::RwLock;
+
+/// EAX, EBX, ECX and EDX of a CPUID leaf
+pub type CpuidRegisters = (u32, u32, u32, u32);
+
+const CPUINFO_PATH: &str = "/proc/cpuinfo";
+/// Extended topology leaves, newest first
+const V2_TOPOLOGY_LEAF: u32 = 0x1F;
+const TOPOLOGY_LEAF: u32 = 0x0B;
+const CACHE_LEAF: u32 = 0x04;
+const AMD_CACHE_LEAF: u32 = 0x8000_001D;
+/// Level types of the extended topology leaves
+const LEVEL_INVALID: u32 = 0;
+const LEVEL_SMT: u32 = 1;
+/// Leaf 1 EDX: more than one logical processor per package
+const HTT: u32 = 1 << 28;
+/// Subleaves looked at before giving up on a terminator
+const MAX_SUBLEAVES: u32 = 16;
+/// CPUs with a cached sibling mask, as many as a CPU affinity mask covers
+const MASK_CPUS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    Data,
+    Instruction,
+    Unified,
+}
+
+/// A cache level, one instance of which is shared by every logical CPU whose APIC ID agrees
+/// above `sharing_shift`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cache {
+    pub level: u8,
+    pub kind: CacheKind,
+    /// In bytes
+    pub size: u32,
+    pub line_size: u32,
+    pub ways: u32,
+    pub sharing_shift: u32,
+}
+
+impl Cache {
+    /// Whether the CPUs with these APIC IDs use the same instance of this cache
+    pub fn shared_by(&self, apic_a: u32, apic_b: u32) -> bool {
+        apic_a.checked_shr(self.sharing_shift).unwrap_or(0) == apic_b.checked_shr(self.sharing_shift).unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thread {
+    /// Logical CPU number, as the scheduler and per-CPU data know it
+    pub cpu: u32,
+    pub apic_id: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Core {
+    pub id: u32,
+    pub threads: Vec<Thread>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Package {
+    pub id: u32,
+    pub cores: Vec<Core>,
+}
+
+impl Package {
+    pub fn threads(&self) -> impl Iterator<Item = &Thread> {
+        self.cores.iter().flat_map(|core| core.threads.iter())
+    }
+}
+
+/// The packages, cores and threads online and the caches each CPU has
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CpuTopology {
+    pub packages: Vec<Package>,
+    pub caches: Vec<Cache>,
+    /// APIC ID bits below the core ID
+    smt_shift: u32,
+    /// APIC ID bits below the package ID
+    package_shift: u32,
+}
+
+/// `ceil(log2(count))`: the APIC ID bits `count` IDs take
+fn id_bits(count: u32) -> u32 {
+    count.max(1).next_power_of_two().trailing_zeros()
+}
+
+/// The SMT and package shifts of the extended topology leaf, if the CPU reports it
+fn extended_levels(cpuid: &dyn Fn(u32, u32) -> CpuidRegisters, leaf: u32) -> Option<(u32, u32)> {
+    let mut smt_shift = 0;
+    let mut package_shift = None;
+    for subleaf in 0..MAX_SUBLEAVES {
+        let (eax, _, ecx, _) = cpuid(leaf, subleaf);
+        let level_type = (ecx >> 8) & 0xFF;
+        if level_type == LEVEL_INVALID {
+            break;
+        }
+        let shift = eax & 0x1F;
+        if level_type == LEVEL_SMT {
+            smt_shift = shift;
+        }
+        // Every level above the thread narrows down to the package's shift
+        package_shift = Some(shift);
+    }
+    package_shift.map(|package_shift| (smt_shift, package_shift.max(smt_shift)))
+}
+
+/// The shifts from leaf 1's logical processor count and leaf 4's core count
+fn legacy_levels(cpuid: &dyn Fn(u32, u32) -> CpuidRegisters, max_leaf: u32) -> (u32, u32) {
+    let (_, ebx, _, edx) = cpuid(1, 0);
+    if edx & HTT == 0 {
+        return (0, 0);
+    }
+    let logical = (ebx >> 16) & 0xFF;
+    let cores = if max_leaf >= CACHE_LEAF { (cpuid(CACHE_LEAF, 0).0 >> 26) + 1 } else { 1 };
+    let package_shift = id_bits(logical);
+    let threads_per_core = (logical / cores).max(1);
+    (id_bits(threads_per_core).min(package_shift), package_shift)
+}
+
+/// The caches a deterministic cache parameters leaf describes
+fn cache_descriptors(cpuid: &dyn Fn(u32, u32) -> CpuidRegisters, leaf: u32) -> Vec<Cache> {
+    let mut caches = Vec::new();
+    for subleaf in 0..MAX_SUBLEAVES {
+        let (eax, ebx, ecx, _) = cpuid(leaf, subleaf);
+        let kind = match eax & 0x1F {
+            1 => CacheKind::Data,
+            2 => CacheKind::Instruction,
+            3 => CacheKind::Unified,
+            _ => break,
+        };
+        let line_size = (ebx & 0xFFF) + 1;
+        let partitions = ((ebx >> 12) & 0x3FF) + 1;
+        let ways = ((ebx >> 22) & 0x3FF) + 1;
+        let sets = ecx.saturating_add(1);
+        caches.push(Cache {
+            level: ((eax >> 5) & 0x7) as u8,
+            kind,
+            size: ways.saturating_mul(partitions).saturating_mul(line_size).saturating_mul(sets),
+            line_size,
+            ways,
+            sharing_shift: id_bits(((eax >> 14) & 0xFFF) + 1),
+        });
+    }
+    caches
+}
+
+impl CpuTopology {
+    /// An empty tree laid out the way this CPUID describes APIC IDs and caches
+    pub fn from_cpuid(cpuid: &dyn Fn(u32, u32) -> CpuidRegisters) -> Self {
+        let max_leaf = cpuid(0, 0).0;
+        let (smt_shift, package_shift) = [V2_TOPOLOGY_LEAF, TOPOLOGY_LEAF]
+            .into_iter()
+            .filter(|&leaf| max_leaf >= leaf)
+            .find_map(|leaf| extended_levels(cpuid, leaf))
+            .unwrap_or_else(|| legacy_levels(cpuid, max_leaf));
+        let mut caches = if max_leaf >= CACHE_LEAF { cache_descriptors(cpuid, CACHE_LEAF) } else { Vec::new() };
+        if caches.is_empty() && cpuid(0x8000_0000, 0).0 >= AMD_CACHE_LEAF {
+            caches = cache_descriptors(cpuid, AMD_CACHE_LEAF);
+        }
+        Self { packages: Vec::new(), caches, smt_shift, package_shift }
+    }
+
+    pub fn package_id(&self, apic_id: u32) -> u32 {
+        apic_id.checked_shr(self.package_shift).unwrap_or(0)
+    }
+
+    /// The core's ID within its package
+    pub fn core_id(&self, apic_id: u32) -> u32 {
+        let bits = self.package_shift - self.smt_shift;
+        apic_id.checked_shr(self.smt_shift).unwrap_or(0) & 1u32.checked_shl(bits).map_or(u32::MAX, |limit| limit - 1)
+    }
+
+    /// Place a CPU that has come online in its package and core
+    pub fn add_cpu(&mut self, cpu: u32, apic_id: u32) {
+        self.remove_cpu(cpu);
+        let (package_id, core_id) = (self.package_id(apic_id), self.core_id(apic_id));
+        let package_index = match self.packages.binary_search_by_key(&package_id, |package| package.id) {
+            Ok(index) => index,
+            Err(index) => {
+                self.packages.insert(index, Package { id: package_id, cores: Vec::new() });
+                index
+            }
+        };
+        let cores = &mut self.packages[package_index].cores;
+        let core_index = match cores.binary_search_by_key(&core_id, |core| core.id) {
+            Ok(index) => index,
+            Err(index) => {
+                cores.insert(index, Core { id: core_id, threads: Vec::new() });
+                index
+            }
+        };
+        let threads = &mut cores[core_index].threads;
+        let thread_index = threads.partition_point(|thread| thread.apic_id < apic_id);
+        threads.insert(thread_index, Thread { cpu, apic_id });
+    }
+
+    /// Take a CPU out of the tree, dropping a core or package left empty
+    pub fn remove_cpu(&mut self, cpu: u32) {
+        for package in &mut self.packages {
+            for core in &mut package.cores {
+                core.threads.retain(|thread| thread.cpu != cpu);
+            }
+            package.cores.retain(|core| !core.threads.is_empty());
+        }
+        self.packages.retain(|package| !package.cores.is_empty());
+    }
+
+    pub fn threads(&self) -> impl Iterator<Item = &Thread> {
+        self.packages.iter().flat_map(Package::threads)
+    }
+
+    pub fn apic_id(&self, cpu: u32) -> Option<u32> {
+        self.threads().find(|thread| thread.cpu == cpu).map(|thread| thread.apic_id)
+    }
+
+    /// The other hardware threads of `cpu`'s core, as a mask of CPU numbers below 64
+    pub fn smt_sibling_mask(&self, cpu: u32) -> u64 {
+        self.packages
+            .iter()
+            .flat_map(|package| package.cores.iter())
+            .find(|core| core.threads.iter().any(|thread| thread.cpu == cpu))
+            .map_or(0, |core| {
+                core.threads
+                    .iter()
+                    .filter(|thread| thread.cpu != cpu)
+                    .fold(0, |mask, thread| mask | 1u64.checked_shl(thread.cpu).unwrap_or(0))
+            })
+    }
+
+    /// The lowest data or unified cache level the two CPUs share, if any
+    pub fn shared_cache_level(&self, cpu_a: u32, cpu_b: u32) -> Option<u8> {
+        let (apic_a, apic_b) = (self.apic_id(cpu_a)?, self.apic_id(cpu_b)?);
+        self.caches
+            .iter()
+            .filter(|cache| cache.kind != CacheKind::Instruction && cache.shared_by(apic_a, apic_b))
+            .map(|cache| cache.level)
+            .min()
+    }
+
+    /// The largest-level data or unified cache
+    pub fn last_level_cache(&self) -> Option<&Cache> {
+        self.caches.iter().filter(|cache| cache.kind != CacheKind::Instruction).max_by_key(|cache| cache.level)
+    }
+
+    /// The `/proc/cpuinfo` text: one block per CPU in CPU order
+    pub fn cpuinfo(&self, vendor: &str, model_name: &str) -> String {
+        let mut threads: Vec<(&Package, Thread)> = self
+            .packages
+            .iter()
+            .flat_map(|package| package.threads().map(move |thread| (package, *thread)))
+            .collect();
+        threads.sort_by_key(|(_, thread)| thread.cpu);
+        let mut text = String::new();
+        for (package, thread) in threads {
+            text.push_str(&format!("processor\t: {}\n", thread.cpu));
+            text.push_str(&format!("vendor_id\t: {}\n", vendor));
+            text.push_str(&format!("model name\t: {}\n", model_name));
+            if let Some(cache) = self.last_level_cache() {
+                text.push_str(&format!("cache size\t: {} KB\n", cache.size / 1024));
+            }
+            text.push_str(&format!("physical id\t: {}\n", package.id));
+            text.push_str(&format!("siblings\t: {}\n", package.threads().count()));
+            text.push_str(&format!("core id\t\t: {}\n", self.core_id(thread.apic_id)));
+            text.push_str(&format!("cpu cores\t: {}\n", package.cores.len()));
+            text.push_str(&format!("apicid\t\t: {}\n", thread.apic_id));
+            if let Some(cache) = self.caches.iter().find(|cache| cache.level == 1) {
+                text.push_str(&format!("cache_alignment\t: {}\n", cache.line_size));
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+lazy_static! {
+    static ref TOPOLOGY: RwLock<CpuTopology> = RwLock::new(CpuTopology::from_cpuid(&super::cpuid));
+}
+
+/// Each CPU's SMT sibling mask, refreshed as CPUs come online, so the scheduler can place
+/// processes from interrupt context without walking or copying the tree
+static SIBLING_MASKS: [AtomicU64; MASK_CPUS] = [const { AtomicU64::new(0) }; MASK_CPUS];
+
+/// Place a CPU that has come online and refresh the sibling masks and `/proc/cpuinfo`
+pub fn add_cpu(cpu: u32, apic_id: u32) {
+    // The timer interrupt reads the tree, so it must not find it locked on this CPU
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut topology = TOPOLOGY.write();
+        topology.add_cpu(cpu, apic_id);
+        for (cpu, mask) in SIBLING_MASKS.iter().enumerate() {
+            mask.store(topology.smt_sibling_mask(cpu as u32), Ordering::Relaxed);
+        }
+    });
+    let _ = write_cpuinfo();
+}
+
+/// The other hardware threads of `cpu`'s core among the CPUs online, as a CPU mask
+pub fn smt_sibling_mask(cpu: u32) -> u64 {
+    SIBLING_MASKS.get(cpu as usize).map_or(0, |mask| mask.load(Ordering::Relaxed))
+}
+
+/// The lowest data or unified cache level two online CPUs share, if any
+pub fn shared_cache_level(cpu_a: u32, cpu_b: u32) -> Option<u8> {
+    TOPOLOGY.read().shared_cache_level(cpu_a, cpu_b)
+}
+
+/// Text of a CPUID string register dump, without its padding
+fn cpuid_string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim_matches(|ch: char| ch == '\0' || ch == ' ').into()
+}
+
+/// Write the topology to `/proc/cpuinfo`
+pub fn write_cpuinfo() -> Result<(), ()> {
+    let info = super::detect_cpu_info();
+    let text = TOPOLOGY.read().cpuinfo(&cpuid_string(&info.vendor_string), &cpuid_string(&info.brand_string));
+    let _ = crate::filesystem::remove(CPUINFO_PATH);
+    crate::filesystem::create_file(CPUINFO_PATH).map_err(|_| ())?;
+    let fd = crate::filesystem::open_file(CPUINFO_PATH)?;
+    let written = crate::filesystem::write_file(fd, text.as_bytes());
+    let _ = crate::filesystem::close_file(fd);
+    written.map(|_| ())
+}
@@ -0,0 +1,20 @@
+// Low-level architecture support (aarch64)
+// Early-boot CPU helpers for the aarch64 port; the x86_64 equivalents live in arch.rs.
+
+use core::arch::asm;
+
+/// Affinity level 0 of MPIDR_EL1, the core number within its cluster
+pub fn cpu_id() -> u64 {
+    let mpidr: u64;
+    // SAFETY: reading MPIDR_EL1 has no side effects and is permitted at EL1
+    unsafe { asm!("mrs {}, mpidr_el1", out(reg) mpidr, options(nomem, nostack, preserves_flags)) };
+    mpidr & 0xff
+}
+
+/// Park the current core until an event arrives, forever
+pub fn halt() -> ! {
+    loop {
+        // SAFETY: wfe only suspends the core until the next event or interrupt
+        unsafe { asm!("wfe", options(nomem, nostack, preserves_flags)) };
+    }
+}
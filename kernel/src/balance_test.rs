@@ -0,0 +1,152 @@
+//! Load Balancing Test
+//! Piles processes onto one CPU of a four-core scheduler and checks that balancing rounds
+//! run from the tick spread them until no CPU is more than two processes busier than
+//! another, moving at most the capped number each round and leaving the running process and
+//! a process pinned to the CPU in place, and that work only leaves its NUMA node when the
+//! imbalance is severe
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use x86_64::VirtAddr;
+use crate::arch::topology::CpuTopology;
+use crate::process::{CpuAffinity, NumaNode, Priority, Process, SmpScheduler, LOAD_BALANCE_INTERVAL_MS};
+use crate::serial::_print;
+use crate::vmm;
+
+const CPUS: u32 = 4;
+
+/// Four cores of one thread each, with no caches shared
+fn four_cores() -> SmpScheduler {
+    let mut topology = CpuTopology::from_cpuid(&|_, _| (0, 0, 0, 0));
+    for cpu in 0..CPUS {
+        topology.add_cpu(cpu, cpu);
+    }
+    SmpScheduler::with_topology(topology)
+}
+
+/// Queue a process on CPU 0, free to move unless `pinned`, and note its address space for
+/// cleanup
+fn spawn_on_cpu0(
+    scheduler: &mut SmpScheduler,
+    priority: Priority,
+    pinned: bool,
+    numa_node: Option<NumaNode>,
+    address_spaces: &mut Vec<u64>,
+) -> Result<u64, &'static str> {
+    let mut process = Process::new(String::from("balance-test"), VirtAddr::new(0x400000), priority).map_err(|_| "Failed to create process")?;
+    address_spaces.extend(process.address_space_id);
+    process.cpu_affinity = CpuAffinity::single_cpu(0);
+    process.numa_node = numa_node;
+    let pid = scheduler.add_process(process);
+    if !pinned {
+        scheduler.set_process_affinity(pid, CpuAffinity::all_cpus());
+    }
+    Ok(pid)
+}
+
+fn loads(scheduler: &SmpScheduler) -> Vec<u32> {
+    scheduler.sched_stats().iter().map(|cpu| cpu.load).collect()
+}
+
+fn balancing(address_spaces: &mut Vec<u64>) -> Result<(), &'static str> {
+    // Test 1: Rounds from the tick spread a pile of work until the loads are within two
+    _print(format_args!("[Balance Test] Test 1: Convergence...\n"));
+    let mut scheduler = four_cores();
+    scheduler.set_balance_policy(LOAD_BALANCE_INTERVAL_MS, 2);
+    let running = spawn_on_cpu0(&mut scheduler, Priority::High, false, None, address_spaces)?;
+    let pinned = spawn_on_cpu0(&mut scheduler, Priority::Low, true, None, address_spaces)?;
+    for _ in 0..12 {
+        spawn_on_cpu0(&mut scheduler, Priority::Normal, false, None, address_spaces)?;
+    }
+    if scheduler.schedule_on_cpu(0) != Some(running) || loads(&scheduler) != [14, 0, 0, 0] {
+        return Err("Work not piled onto CPU 0");
+    }
+    if scheduler.balance_tick(LOAD_BALANCE_INTERVAL_MS - 1) != 0 {
+        return Err("Balanced before the interval passed");
+    }
+    let mut now_ms = LOAD_BALANCE_INTERVAL_MS;
+    let mut rounds = 0;
+    loop {
+        let moved = scheduler.balance_tick(now_ms);
+        if moved > 2 {
+            return Err("Round moved more processes than its cap");
+        }
+        if moved == 0 {
+            break;
+        }
+        // A second tick in the same interval does nothing
+        if scheduler.balance_tick(now_ms + 1) != 0 {
+            return Err("Balanced twice in one interval");
+        }
+        rounds += 1;
+        now_ms += LOAD_BALANCE_INTERVAL_MS;
+        if rounds > 10 {
+            return Err("Load did not converge");
+        }
+    }
+    let balanced = loads(&scheduler);
+    let (max, min) = (balanced.iter().max().copied().unwrap_or(0), balanced.iter().min().copied().unwrap_or(0));
+    if max > min + 2 || balanced.iter().sum::<u32>() != 14 {
+        return Err("Loads still more than two apart");
+    }
+    let stats = scheduler.sched_stats();
+    if stats[0].current != Some(running) || stats[0].queue_lengths[Priority::High as usize] != 1 {
+        return Err("Running process migrated");
+    }
+    if stats[0].queue_lengths[Priority::Low as usize] != 1 {
+        return Err("Process pinned to CPU 0 migrated");
+    }
+    _print(format_args!("[Balance Test] ✓ Loads {:?} after {} rounds, PID {} stayed\n", balanced, rounds, pinned));
+
+    // Test 2: Work stays on its NUMA node unless the imbalance is severe
+    _print(format_args!("[Balance Test] Test 2: NUMA locality...\n"));
+    let mut scheduler = four_cores();
+    let node = |id: u8, cpu_mask: u64| NumaNode { id, cpu_mask, memory_base: 0, memory_size: 0 };
+    let (near, far) = (node(0, 0b0001), node(1, 0b1110));
+    scheduler.set_numa_topology(&[near, far]);
+    let remote = spawn_on_cpu0(&mut scheduler, Priority::Low, false, Some(far), address_spaces)?;
+    for _ in 0..4 {
+        spawn_on_cpu0(&mut scheduler, Priority::Normal, false, Some(near), address_spaces)?;
+    }
+    // Five against none is severe enough, but the process whose memory is on the far node
+    // goes first, and after it four against one is not
+    if scheduler.balance_load() != 1 {
+        return Err("Work left its NUMA node for a mild imbalance");
+    }
+    let stats = scheduler.sched_stats();
+    let moved_to = stats.iter().find(|cpu| cpu.load == 1).map(|cpu| cpu.cpu_id);
+    if stats[0].load != 4 || stats.iter().any(|cpu| cpu.cpu_id != 0 && cpu.queue_lengths[Priority::Normal as usize] != 0) {
+        return Err("Process moved off the node its memory is on");
+    }
+    if moved_to.and_then(|cpu| stats.get(cpu as usize)).map(|cpu| cpu.queue_lengths[Priority::Low as usize]) != Some(1) {
+        return Err("Process on the far node not moved there");
+    }
+    _print(format_args!("[Balance Test] ✓ PID {} moved to CPU {:?} on its node\n", remote, moved_to));
+    Ok(())
+}
+
+pub fn run_balance_tests() -> Result<(), &'static str> {
+    let mut address_spaces = Vec::new();
+    let result = balancing(&mut address_spaces);
+    for id in address_spaces {
+        let _ = vmm::destroy_address_space(id);
+    }
+    result?;
+
+    _print(format_args!("[Balance Test] ✓ All load balancing tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for load balancing
+pub fn test_balance() {
+    _print(format_args!("[Balance Test] ===========================================\n"));
+    _print(format_args!("[Balance Test]            LOAD BALANCING TESTS\n"));
+    _print(format_args!("[Balance Test] ===========================================\n"));
+
+    match run_balance_tests() {
+        Ok(_) => _print(format_args!("[Balance Test] ✓ All load balancing tests PASSED\n")),
+        Err(e) => _print(format_args!("[Balance Test] ✗ Load balancing tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Balance Test] ===========================================\n"));
+}
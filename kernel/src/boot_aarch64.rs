@@ -0,0 +1,39 @@
+// aarch64 entry point
+// The boot core gets a stack and enters `kernel_main`; secondary cores stay parked. The
+// loader (QEMU `-kernel` or firmware) has already zeroed .bss from the ELF segment sizes.
+
+use core::arch::global_asm;
+use kernel as k;
+
+const BOOT_STACK_SIZE: usize = 0x8000;
+
+#[repr(C, align(16))]
+struct BootStack([u8; BOOT_STACK_SIZE]);
+
+static mut BOOT_STACK: BootStack = BootStack([0; BOOT_STACK_SIZE]);
+
+global_asm!(
+    ".section .text._start",
+    ".globl _start",
+    "_start:",
+    "    mrs x0, mpidr_el1",
+    "    and x0, x0, #0xff",
+    "    cbnz x0, 2f",
+    "    adrp x1, {stack}",
+    "    add x1, x1, :lo12:{stack}",
+    "    mov x2, #{size}",
+    "    add sp, x1, x2",
+    "    bl {main}",
+    "2:  wfe",
+    "    b 2b",
+    stack = sym BOOT_STACK,
+    size = const BOOT_STACK_SIZE,
+    main = sym kernel_main,
+);
+
+extern "C" fn kernel_main() -> ! {
+    k::serial::init();
+    k::serial::_print(format_args!("RaeenOS: booting kernel (aarch64, core {})...\n", k::arch::cpu_id()));
+    k::serial::_print(format_args!("[aarch64] Serial console up; remaining subsystems are x86_64-only\n"));
+    k::arch::halt();
+}
@@ -70,6 +70,7 @@ pub enum CapabilityType {
     SystemReboot,
     SystemTime,
     SystemConfiguration,
+    SystemMetrics,
 }
 
 /// Capability permissions and metadata
@@ -298,6 +299,14 @@ pub fn check_capability(
        (capability.permissions & required_permissions) == required_permissions)
 }
 
+/// Whether a process holds a capability of `capability_type` still in force, through any of
+/// its handles
+pub fn holds_capability(process_id: ProcessId, capability_type: CapabilityType) -> bool {
+    list_process_capabilities(process_id).into_iter().any(|(handle, held_type, _)| {
+        held_type == capability_type && check_capability(process_id, handle, capability_type, 0) == Ok(true)
+    })
+}
+
 /// Transfer a capability from one process to another
 pub fn transfer_capability(
     from_process: ProcessId,
@@ -0,0 +1,153 @@
+//! Clone Test
+//! Clones a user process into a thread sharing its address space and descriptor table and
+//! into a process with copies of both, and checks that a write by one thread is seen by the
+//! other but not by the copy, that the threads share their descriptors and PID, and that
+//! the address space and descriptors last until both threads have exited
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use x86_64::VirtAddr;
+use crate::filesystem::{self, O_CREAT, O_RDWR};
+use crate::process::{self, CloneFlags, Process, ProcessError};
+use crate::serial::_print;
+use crate::test_threads::add_blocked;
+use crate::vmm::{self, MappingSource, VmPermissions};
+
+const PATH: &str = "/tmp/clone-test";
+const ENTRY: u64 = 0x40_0000;
+const THREAD_STACK: u64 = 0x7FFF_FFFE_0000;
+const THREAD_TLS: u64 = 0x7000_0000;
+
+fn clone_blocked(parent: u64, flags: CloneFlags, tls: u64) -> Result<u64, ProcessError> {
+    let child = process::with_process(parent, |parent| {
+        process::clone_process(parent, flags, VirtAddr::new(ENTRY), VirtAddr::new(THREAD_STACK), tls)
+    });
+    Ok(add_blocked(child.ok_or(ProcessError::NoCurrentProcess)??))
+}
+
+/// Run `f` in the address space of process `pid`
+fn in_space_of<T>(pid: u64, f: impl FnOnce() -> T) -> Result<T, &'static str> {
+    let address_space_id = process::with_process(pid, |process| process.address_space_id)
+        .flatten()
+        .ok_or("Process has no address space")?;
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let previous = vmm::current_address_space().ok_or("No address space active")?;
+        vmm::switch_address_space(address_space_id).map_err(|_| "Failed to switch address space")?;
+        let result = f();
+        vmm::switch_address_space(previous).map_err(|_| "Failed to switch back")?;
+        Ok(result)
+    })
+}
+
+fn address_space_of(pid: u64) -> Option<u64> {
+    process::with_process(pid, |process| process.address_space_id).flatten()
+}
+
+fn clone_threads(pids: &mut Vec<u64>) -> Result<(), &'static str> {
+    let parent = Process::user_process(String::from("clone-test"), VirtAddr::new(ENTRY))
+        .map_err(|_| "Failed to create process")?;
+    let parent = add_blocked(parent);
+    pids.push(parent);
+    let address_space_id = address_space_of(parent).ok_or("Process has no address space")?;
+    // Mapped without USER so the test can touch it, and faulted in before cloning so the
+    // copy starts out sharing the frame
+    let anonymous = MappingSource::Anonymous { shared: false };
+    let shared = vmm::map_memory(address_space_id, None, 4096, VmPermissions::READ | VmPermissions::WRITE, false, anonymous)
+        .map_err(|_| "Failed to map memory")?;
+    // SAFETY: `in_space_of` runs these with the mapping's address space active, where the
+    // fault handler backs the page on first access, and nothing else uses the page
+    let read = || unsafe { shared.as_ptr::<u64>().read_volatile() };
+    let write = |value: u64| unsafe { shared.as_mut_ptr::<u64>().write_volatile(value) };
+    in_space_of(parent, || write(1))?;
+
+    // Test 1: A thread runs in the same address space, where its writes are seen
+    _print(format_args!("[Clone Test] Test 1: Shared address space...\n"));
+    let thread = clone_blocked(parent, CloneFlags::VM | CloneFlags::FILES | CloneFlags::THREAD, THREAD_TLS)
+        .map_err(|_| "Failed to clone a thread")?;
+    pids.push(thread);
+    let copy = clone_blocked(parent, CloneFlags::empty(), 0).map_err(|_| "Failed to clone a process")?;
+    pids.push(copy);
+    let started = process::with_process(thread, |thread| {
+        thread.context.rip == ENTRY && thread.context.rsp == THREAD_STACK && thread.context.rax == 0 && thread.fs_base == THREAD_TLS
+    });
+    if started != Some(true) {
+        return Err("Thread not started at the entry on its own stack");
+    }
+    if address_space_of(thread) != Some(address_space_id) || address_space_of(copy) == Some(address_space_id) {
+        return Err("Address space not shared by the thread alone");
+    }
+    in_space_of(thread, || write(2))?;
+    in_space_of(copy, || write(3))?;
+    if in_space_of(parent, read)? != 2 || in_space_of(copy, read)? != 3 {
+        return Err("Write not seen by the other thread, or seen by the copy");
+    }
+    let rejected = [
+        (CloneFlags::THREAD, THREAD_STACK),
+        (CloneFlags::VM, 0),
+    ].map(|(flags, stack)| process::with_process(parent, |parent| {
+        matches!(process::clone_process(parent, flags, VirtAddr::new(ENTRY), VirtAddr::new(stack), 0), Err(ProcessError::InvalidClone))
+    }));
+    if rejected != [Some(true), Some(true)] {
+        return Err("Thread without its own stack or address space made");
+    }
+    _print(format_args!("[Clone Test] ✓ Thread {} saw the write of process {}\n", thread, parent));
+
+    // Test 2: The thread shares the descriptor table and the PID, the copy neither
+    _print(format_args!("[Clone Test] Test 2: Shared descriptors...\n"));
+    let fd = filesystem::open(PATH, O_RDWR | O_CREAT).map_err(|_| "Failed to open the test file")?;
+    process::with_process(parent, |parent| parent.open_files.lock().push(fd));
+    let holds_fd = |pid: u64| process::with_process(pid, |process| process.open_files.lock().contains(&fd));
+    if holds_fd(thread) != Some(true) || holds_fd(copy) != Some(false) {
+        return Err("Descriptor table not shared by the thread alone");
+    }
+    let thread_groups = [thread, copy].map(|pid| process::with_process(pid, |process| process.thread_group));
+    if thread_groups != [Some(parent), Some(copy)] {
+        return Err("Thread group wrong");
+    }
+    _print(format_args!("[Clone Test] ✓ Descriptor {} shared by thread group {}\n", fd, parent));
+
+    // Test 3: The address space and descriptors last until the last thread exits
+    _print(format_args!("[Clone Test] Test 3: Exit of each thread...\n"));
+    process::terminate_process(parent);
+    pids.retain(|&pid| pid != parent);
+    if vmm::get_address_space_info(address_space_id).is_none() || filesystem::file_path(fd).is_none() {
+        return Err("Address space or descriptors freed while a thread still uses them");
+    }
+    if in_space_of(thread, read)? != 2 {
+        return Err("Memory lost when the first thread exited");
+    }
+    process::terminate_process(thread);
+    pids.retain(|&pid| pid != thread);
+    if vmm::get_address_space_info(address_space_id).is_some() || filesystem::file_path(fd).is_some() {
+        return Err("Address space or descriptors kept after the last thread exited");
+    }
+    _print(format_args!("[Clone Test] ✓ Address space {} freed with the last thread\n", address_space_id));
+    Ok(())
+}
+
+pub fn run_clone_tests() -> Result<(), &'static str> {
+    let mut pids = Vec::new();
+    let result = clone_threads(&mut pids);
+    for pid in pids {
+        process::terminate_process(pid);
+    }
+    let _ = filesystem::remove(PATH);
+    result?;
+
+    _print(format_args!("[Clone Test] ✓ All clone tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for clone
+pub fn test_clone() {
+    _print(format_args!("[Clone Test] ===========================================\n"));
+    _print(format_args!("[Clone Test]               CLONE TESTS\n"));
+    _print(format_args!("[Clone Test] ===========================================\n"));
+
+    match run_clone_tests() {
+        Ok(_) => _print(format_args!("[Clone Test] ✓ All clone tests PASSED\n")),
+        Err(e) => _print(format_args!("[Clone Test] ✗ Clone tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Clone Test] ===========================================\n"));
+}
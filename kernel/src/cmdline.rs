@@ -0,0 +1,22 @@
+//! Kernel command line for RaeenOS
+//! The bootloader does not pass a command line, so one is embedded at compile time from
+//! the RAEENOS_CMDLINE environment variable (space-separated `key=value` or bare `flag` options)
+
+/// The embedded kernel command line
+pub fn raw() -> &'static str {
+    option_env!("RAEENOS_CMDLINE").unwrap_or("")
+}
+
+/// Value of `key=value`, or `Some("")` for a bare `key` flag
+pub fn get(key: &str) -> Option<&'static str> {
+    raw().split_whitespace().find_map(|option| match option.split_once('=') {
+        Some((k, v)) if k == key => Some(v),
+        None if option == key => Some(""),
+        _ => None,
+    })
+}
+
+/// Whether a bare flag or `key=value` option is present
+pub fn has(key: &str) -> bool {
+    get(key).is_some()
+}
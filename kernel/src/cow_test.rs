@@ -0,0 +1,108 @@
+//! Copy-on-Write Test
+//! Checks that kernel writes into user memory respect the page protections CR0.WP enforces:
+//! a write through uaccess into a page a copied address space shares copy-on-write gives
+//! that space its own copy and leaves the original alone, and a write into a read-only
+//! mapping is refused instead of faulting, in the copy as much as in the original
+
+use x86_64::VirtAddr;
+use crate::arch::uaccess::{self, UAccessError};
+use crate::serial::_print;
+use crate::vmm::{self, MappingSource, VmPermissions};
+
+const PAGE: u64 = 4096;
+const HINT: u64 = 0x5000_0000_0000;
+
+/// Run `f` with address space `address_space_id` active
+fn in_space<T>(address_space_id: u64, f: impl FnOnce() -> T) -> Result<T, &'static str> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let previous = vmm::current_address_space().ok_or("No address space active")?;
+        vmm::switch_address_space(address_space_id).map_err(|_| "Failed to switch address space")?;
+        let result = f();
+        vmm::switch_address_space(previous).map_err(|_| "Failed to switch back")?;
+        Ok(result)
+    })
+}
+
+fn copy_on_write(original: u64) -> Result<(), &'static str> {
+    // Test 1: A kernel write shared by a copied address space lands in a copy of its own
+    _print(format_args!("[CoW Test] Test 1: Writing a page shared copy-on-write...\n"));
+    let permissions = VmPermissions::READ | VmPermissions::WRITE | VmPermissions::USER;
+    let address = vmm::map_memory(original, Some(VirtAddr::new(HINT)), PAGE, permissions, false, MappingSource::Anonymous { shared: false })
+        .map_err(|_| "Failed to map a writable page")?
+        .as_u64();
+    in_space(original, || uaccess::write_user_value(address, 1u64))?
+        .map_err(|_| "Failed to write the original page")?;
+
+    let copy = vmm::copy_address_space(original).map_err(|_| "Failed to copy the address space")?;
+    let result = in_space(copy, || uaccess::write_user_value(address, 2u64)).and_then(|written| {
+        written.map_err(|_| "Write into the copy-on-write page refused")?;
+        let seen = in_space(copy, || uaccess::read_user_value::<u64>(address))?;
+        let kept = in_space(original, || uaccess::read_user_value::<u64>(address))?;
+        match (seen, kept) {
+            (Ok(2), Ok(1)) => Ok(()),
+            (Ok(_), Ok(2)) => Err("Kernel write reached the original's frame"),
+            _ => Err("Unexpected contents after the write"),
+        }
+    });
+    let _ = vmm::destroy_address_space(copy);
+    result?;
+    _print(format_args!("[CoW Test] ✓ Copy written, original untouched\n"));
+
+    // Test 2: Read-only user memory cannot be written through uaccess
+    _print(format_args!("[CoW Test] Test 2: Writing a read-only page...\n"));
+    let read_only = vmm::map_memory(original, None, PAGE, VmPermissions::READ | VmPermissions::USER, false, MappingSource::Anonymous { shared: false })
+        .map_err(|_| "Failed to map a read-only page")?
+        .as_u64();
+    for write in [
+        in_space(original, || uaccess::copy_to_user(read_only, &[1, 2, 3, 4]))?,
+        in_space(original, || uaccess::write_user_value(read_only, 1u32))?,
+        in_space(original, || uaccess::cmpxchg_user_u32(read_only, 0, 1).map(|_| ()))?,
+    ] {
+        if write != Err(UAccessError::PermissionDenied) {
+            return Err("Write into a read-only page not refused");
+        }
+    }
+    _print(format_args!("[CoW Test] ✓ Read-only page refused\n"));
+
+    // Test 3: Copying an address space leaves its read-only pages read-only, so the copy
+    // cannot write them either
+    _print(format_args!("[CoW Test] Test 3: Writing a read-only page after a copy...\n"));
+    let frame = crate::memory::allocate_frame().ok_or("Failed to allocate a frame")?;
+    if vmm::map_page(original, VirtAddr::new(read_only), frame.start_address(), VmPermissions::READ | VmPermissions::USER).is_err() {
+        crate::memory::deallocate_frame(frame);
+        return Err("Failed to back the read-only page");
+    }
+    let copy = vmm::copy_address_space(original).map_err(|_| "Failed to copy the address space")?;
+    let written = in_space(copy, || uaccess::copy_to_user(read_only, &[1, 2, 3, 4]));
+    let _ = vmm::destroy_address_space(copy);
+    let refused = Err(UAccessError::PermissionDenied);
+    if written? != refused || in_space(original, || uaccess::copy_to_user(read_only, &[1, 2, 3, 4]))? != refused {
+        return Err("Write into a copied read-only page not refused");
+    }
+    _print(format_args!("[CoW Test] ✓ Copied read-only page refused\n"));
+    Ok(())
+}
+
+pub fn run_cow_tests() -> Result<(), &'static str> {
+    _print(format_args!("[CoW Test] Starting copy-on-write tests...\n"));
+    let address_space_id = vmm::create_address_space().map_err(|_| "Failed to create address space")?;
+    let result = copy_on_write(address_space_id);
+    let _ = vmm::destroy_address_space(address_space_id);
+    result?;
+    _print(format_args!("[CoW Test] ✓ All copy-on-write tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for kernel writes to copy-on-write memory
+pub fn test_cow() {
+    _print(format_args!("[CoW Test] ===========================================\n"));
+    _print(format_args!("[CoW Test]          COPY-ON-WRITE TESTS\n"));
+    _print(format_args!("[CoW Test] ===========================================\n"));
+
+    match run_cow_tests() {
+        Ok(_) => _print(format_args!("[CoW Test] ✓ All copy-on-write tests PASSED\n")),
+        Err(e) => _print(format_args!("[CoW Test] ✗ Copy-on-write tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[CoW Test] ===========================================\n"));
+}
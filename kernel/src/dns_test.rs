@@ -0,0 +1,226 @@
+//! DNS Lookup Test
+//! Resolves names against a canned name server answering from a small zone: addresses come
+//! back with their TTLs from the server that answered, CNAME chains are followed and shown in
+//! order whether or not the server follows them itself, and reverse lookups give PTR names
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddrV4};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::network::dns::{self, DnsError, Message, Record, RecordData, RecordType, Resolver, Transport};
+use crate::network::{NetworkError, NetworkResult};
+use crate::raeshell::dnsutils::{host, nslookup};
+use crate::raeshell::ShellResult;
+use crate::serial::_print;
+
+const SERVER: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(192, 0, 2, 53), 53);
+/// A server that never answers
+const SILENT: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(192, 0, 2, 54), 53);
+const WWW: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 10);
+const WWW_V6: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x10);
+
+fn record(name: &str, ttl: u32, data: RecordData) -> Record {
+    Record { name: String::from(name), ttl, data }
+}
+
+fn zone() -> Vec<Record> {
+    alloc::vec![
+        record("www.raeen.test", 3600, RecordData::A(WWW)),
+        record("www.raeen.test", 3600, RecordData::Aaaa(WWW_V6)),
+        record("docs.raeen.test", 300, RecordData::Cname(String::from("pages.raeen.test"))),
+        record("pages.raeen.test", 600, RecordData::Cname(String::from("cdn.raeen.test"))),
+        record("cdn.raeen.test", 60, RecordData::A(Ipv4Addr::new(198, 51, 100, 7))),
+        record("loop-a.raeen.test", 60, RecordData::Cname(String::from("loop-b.raeen.test"))),
+        record("loop-b.raeen.test", 60, RecordData::Cname(String::from("loop-a.raeen.test"))),
+        record(&dns::reverse_name(IpAddr::V4(WWW)), 86400, RecordData::Ptr(String::from("www.raeen.test"))),
+        record(&dns::reverse_name(IpAddr::V6(WWW_V6)), 86400, RecordData::Ptr(String::from("www.raeen.test"))),
+    ]
+}
+
+/// A name server answering from `zone()`, as a recursive resolver would when `follows_aliases`
+/// and otherwise giving only the records of the name asked about
+struct CannedServer {
+    zone: Vec<Record>,
+    follows_aliases: bool,
+    queries: AtomicUsize,
+}
+
+impl CannedServer {
+    fn new(follows_aliases: bool) -> Self {
+        Self { zone: zone(), follows_aliases, queries: AtomicUsize::new(0) }
+    }
+}
+
+impl Transport for CannedServer {
+    fn exchange(&self, server: SocketAddrV4, query: &[u8], _timeout_ms: u64) -> NetworkResult<Vec<u8>> {
+        if server != SERVER {
+            return Err(NetworkError::Timeout);
+        }
+        self.queries.fetch_add(1, Ordering::SeqCst);
+        let query = Message::decode(query).map_err(|_| NetworkError::InvalidAddress)?;
+        let question = query.questions.first().ok_or(NetworkError::InvalidAddress)?;
+        let mut answers = Vec::new();
+        let mut name = question.name.clone();
+        for _ in 0..4 {
+            let held: Vec<&Record> = self.zone.iter().filter(|record| record.name.eq_ignore_ascii_case(&name)).collect();
+            answers.extend(held.iter().filter(|record| record.data.record_type() == question.record_type).map(|record| (*record).clone()));
+            let alias = held.iter().find_map(|record| match &record.data {
+                RecordData::Cname(target) if question.record_type != RecordType::Cname => Some(target.clone()),
+                _ => None,
+            });
+            match alias {
+                Some(target) => {
+                    answers.extend(held.iter().map(|record| (*record).clone()));
+                    if !self.follows_aliases {
+                        break;
+                    }
+                    name = target;
+                }
+                None => break,
+            }
+        }
+        let known = self.zone.iter().any(|record| record.name.eq_ignore_ascii_case(&question.name));
+        let reply = Message {
+            id: query.id,
+            response: true,
+            authoritative: false,
+            recursion_desired: query.recursion_desired,
+            recursion_available: true,
+            rcode: if known { dns::RCODE_NO_ERROR } else { dns::RCODE_NAME_ERROR },
+            questions: query.questions.clone(),
+            answers,
+        };
+        Ok(reply.encode())
+    }
+}
+
+fn output(result: ShellResult) -> Result<String, &'static str> {
+    match result {
+        ShellResult::Success(output) => Ok(output),
+        _ => Err("Lookup command failed"),
+    }
+}
+
+pub fn run_dns_tests() -> Result<(), &'static str> {
+    _print(format_args!("[DNS Test] Starting DNS lookup tests...\n"));
+
+    // Test 1: A canned name resolves to its addresses and TTL, from the server that answers
+    _print(format_args!("[DNS Test] Test 1: Resolving a canned name...\n"));
+    let resolver = Resolver::new(alloc::vec![SILENT, SERVER], CannedServer::new(true));
+    let answer = resolver.lookup("www.raeen.test", RecordType::A).map_err(|_| "Canned name not resolved")?;
+    if answer.records != [record("www.raeen.test", 3600, RecordData::A(WWW))] || answer.server != SERVER {
+        return Err("Wrong address, TTL or server for a canned name");
+    }
+    // Names compare without regard to case, and the query is compressed and decoded whole
+    let query = Message::query(7, "WWW.Raeen.Test.", RecordType::Aaaa);
+    if Message::decode(&query.encode()).map_err(|_| "Query not decoded")? != Message::query(7, "WWW.Raeen.Test", RecordType::Aaaa) {
+        return Err("Query did not survive encoding");
+    }
+    if resolver.lookup("WWW.RAEEN.TEST.", RecordType::Aaaa).map_err(|_| "IPv6 address not resolved")?.records.first().map(|r| &r.data) != Some(&RecordData::Aaaa(WWW_V6)) {
+        return Err("Wrong IPv6 address");
+    }
+    let shown = output(host(&resolver, &["host", "www.raeen.test"]))?;
+    let expected = "www.raeen.test has address 192.0.2.10 (TTL 3600)\n\
+                    www.raeen.test has IPv6 address 2001:db8::10 (TTL 3600)\n\
+                    Received from 192.0.2.53#53 in ";
+    if !shown.starts_with(expected) || !shown.ends_with(" ms") {
+        return Err("host did not show the addresses, TTLs and server");
+    }
+    let shown = output(nslookup(&resolver, &["nslookup", "-type=A", "www.raeen.test"]))?;
+    if !shown.starts_with("Server:\t\t192.0.2.53\nAddress:\t192.0.2.53#53\n\nNon-authoritative answer:\nName:\twww.raeen.test\nAddress: 192.0.2.10\tttl = 3600\n")
+        || !shown.contains("\nQuery time: ")
+    {
+        return Err("nslookup did not show the address, TTL and server");
+    }
+    if !matches!(resolver.lookup("missing.raeen.test", RecordType::A), Err(DnsError::NameError))
+        || !matches!(host(&resolver, &["host", "missing.raeen.test"]), ShellResult::Error(message) if message == "Host missing.raeen.test not found: 3(NXDOMAIN)")
+    {
+        return Err("Unknown name not reported as NXDOMAIN");
+    }
+    let unreachable = Resolver::new(alloc::vec![SILENT], CannedServer::new(true));
+    if !matches!(unreachable.lookup("www.raeen.test", RecordType::A), Err(DnsError::Network(NetworkError::Timeout))) {
+        return Err("Silent server not reported");
+    }
+    _print(format_args!("[DNS Test] ✓ www.raeen.test is {} for 3600s, answered by {}\n", WWW, SERVER));
+
+    // Test 2: CNAME chains are followed to the addresses and shown in order
+    _print(format_args!("[DNS Test] Test 2: CNAME chains...\n"));
+    let chain = [
+        record("docs.raeen.test", 300, RecordData::Cname(String::from("pages.raeen.test"))),
+        record("pages.raeen.test", 600, RecordData::Cname(String::from("cdn.raeen.test"))),
+        record("cdn.raeen.test", 60, RecordData::A(Ipv4Addr::new(198, 51, 100, 7))),
+    ];
+    for follows_aliases in [true, false] {
+        let server = CannedServer::new(follows_aliases);
+        let resolver = Resolver::new(alloc::vec![SERVER], &server);
+        let answer = resolver.lookup("docs.raeen.test", RecordType::A).map_err(|_| "Alias not resolved")?;
+        if answer.records != chain {
+            return Err("Alias chain not followed in order");
+        }
+        // A server that stops at each alias is asked again about its target
+        let queries = server.queries.load(Ordering::SeqCst);
+        if queries != if follows_aliases { 1 } else { 3 } {
+            return Err("Alias targets asked about needlessly or not at all");
+        }
+    }
+    let resolver = Resolver::new(alloc::vec![SERVER], CannedServer::new(false));
+    let shown = output(host(&resolver, &["host", "docs.raeen.test"]))?;
+    if !shown.starts_with(
+        "docs.raeen.test is an alias for pages.raeen.test. (TTL 300)\n\
+         pages.raeen.test is an alias for cdn.raeen.test. (TTL 600)\n\
+         cdn.raeen.test has address 198.51.100.7 (TTL 60)\n\
+         Received from",
+    ) {
+        return Err("host did not show the alias chain once, in order");
+    }
+    let shown = output(nslookup(&resolver, &["nslookup", "docs.raeen.test"]))?;
+    if !shown.contains("docs.raeen.test\tcanonical name = pages.raeen.test.\tttl = 300\npages.raeen.test\tcanonical name = cdn.raeen.test.\tttl = 600\nName:\tcdn.raeen.test\nAddress: 198.51.100.7\tttl = 60\n") {
+        return Err("nslookup did not show the alias chain");
+    }
+    if !matches!(resolver.lookup("loop-a.raeen.test", RecordType::A), Err(DnsError::CnameLoop)) {
+        return Err("Alias loop not detected");
+    }
+    _print(format_args!("[DNS Test] ✓ docs.raeen.test reaches 198.51.100.7 through two aliases\n"));
+
+    // Test 3: Reverse lookups give the PTR name of an address
+    _print(format_args!("[DNS Test] Test 3: Reverse lookups...\n"));
+    if dns::reverse_name(IpAddr::V4(WWW)) != "10.2.0.192.in-addr.arpa"
+        || dns::reverse_name(IpAddr::V6(WWW_V6)) != "0.1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+    {
+        return Err("Reverse names misformed");
+    }
+    let resolver = Resolver::new(alloc::vec![SERVER], CannedServer::new(true));
+    let answer = resolver.reverse(IpAddr::V4(WWW)).map_err(|_| "Reverse lookup failed")?;
+    if answer.records != [record("10.2.0.192.in-addr.arpa", 86400, RecordData::Ptr(String::from("www.raeen.test")))] {
+        return Err("Reverse lookup did not return the PTR name");
+    }
+    let shown = output(host(&resolver, &["host", "192.0.2.10"]))?;
+    if !shown.starts_with("10.2.0.192.in-addr.arpa domain name pointer www.raeen.test. (TTL 86400)\nReceived from 192.0.2.53#53") {
+        return Err("host did not show the PTR name");
+    }
+    let shown = output(nslookup(&resolver, &["nslookup", "2001:db8::10", "192.0.2.53"]))?;
+    if !shown.contains(".ip6.arpa\tname = www.raeen.test.\tttl = 86400\n") {
+        return Err("nslookup did not show the IPv6 PTR name");
+    }
+    if !matches!(nslookup(&resolver, &["nslookup", "198.51.100.99"]), ShellResult::Error(message) if message == "** server can't find 99.100.51.198.in-addr.arpa: NXDOMAIN") {
+        return Err("Address without a name not reported");
+    }
+    _print(format_args!("[DNS Test] ✓ {} is www.raeen.test, and so is {}\n", WWW, WWW_V6));
+
+    _print(format_args!("[DNS Test] ✓ All DNS lookup tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for DNS lookups
+pub fn test_dns() {
+    _print(format_args!("[DNS Test] ===========================================\n"));
+    _print(format_args!("[DNS Test]            DNS LOOKUP TESTS\n"));
+    _print(format_args!("[DNS Test] ===========================================\n"));
+
+    match run_dns_tests() {
+        Ok(_) => _print(format_args!("[DNS Test] ✓ All DNS lookup tests PASSED\n")),
+        Err(e) => _print(format_args!("[DNS Test] ✗ DNS lookup tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[DNS Test] ===========================================\n"));
+}
@@ -7,7 +7,13 @@ use spin::RwLock;
 use x86_64::instructions::port::Port;
 use x86_64::VirtAddr;
 
+pub mod rtc;
+pub mod touchscreen;
+pub mod virtio;
+pub mod virtio_gpu;
+
 static DEVICE_MANAGER: RwLock<DeviceManager> = RwLock::new(DeviceManager::new());
+static DRIVER_REGISTRY: RwLock<DriverRegistry> = RwLock::new(DriverRegistry::new());
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum DeviceType {
@@ -93,6 +99,33 @@ impl fmt::Display for DeviceError {
 
 pub type DeviceResult<T> = Result<T, DeviceError>;
 
+/// Sector-addressed storage exposed by block drivers
+pub trait BlockDevice: Send + Sync {
+    fn block_size(&self) -> usize;
+    fn block_count(&self) -> u64;
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> DeviceResult<()>;
+    fn write_blocks(&mut self, lba: u64, buffer: &[u8]) -> DeviceResult<()>;
+}
+
+/// A disk shared between owners; the lock is held for the duration of each call
+impl<T: BlockDevice> BlockDevice for alloc::sync::Arc<spin::Mutex<T>> {
+    fn block_size(&self) -> usize {
+        self.lock().block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.lock().block_count()
+    }
+
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> DeviceResult<()> {
+        self.lock().read_blocks(lba, buffer)
+    }
+
+    fn write_blocks(&mut self, lba: u64, buffer: &[u8]) -> DeviceResult<()> {
+        self.lock().write_blocks(lba, buffer)
+    }
+}
+
 // Device Manager
 pub struct DeviceManager {
     devices: BTreeMap<u64, Box<dyn Device>>,
@@ -164,6 +197,118 @@ impl DeviceManager {
     }
 }
 
+// Driver Model
+
+/// A device discovered by bus enumeration that drivers can be probed against
+#[derive(Debug, Clone)]
+pub enum BusDevice {
+    Pci(PciDevice),
+    Platform(&'static str),
+}
+
+impl BusDevice {
+    /// Whether two handles refer to the same physical device
+    pub fn same_device(&self, other: &BusDevice) -> bool {
+        match (self, other) {
+            (BusDevice::Pci(a), BusDevice::Pci(b)) => {
+                a.bus == b.bus && a.device == b.device && a.function == b.function
+                    && a.vendor_id == b.vendor_id && a.device_id == b.device_id
+            }
+            (BusDevice::Platform(a), BusDevice::Platform(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Driver lifecycle: the registry probes each enumerated device against
+/// registered drivers in init order and attaches the first that claims it.
+/// Each driver instance is bound to at most one device.
+pub trait Driver: Send + Sync {
+    fn name(&self) -> &str;
+    /// Lower values attach first and detach last
+    fn init_order(&self) -> u32 {
+        100
+    }
+    fn probe(&self, device: &BusDevice) -> bool;
+    fn attach(&mut self, device: &BusDevice) -> DeviceResult<()>;
+    fn detach(&mut self);
+}
+
+struct RegisteredDriver {
+    driver: Box<dyn Driver>,
+    bound: Option<BusDevice>,
+}
+
+/// Registry of typed drivers and their current bindings
+pub struct DriverRegistry {
+    drivers: Vec<RegisteredDriver>,
+}
+
+impl DriverRegistry {
+    pub const fn new() -> Self {
+        Self {
+            drivers: Vec::new(),
+        }
+    }
+    
+    pub fn register(&mut self, driver: Box<dyn Driver>) {
+        let order = driver.init_order();
+        // Keep the list sorted by init order, stable for equal orders
+        let index = self.drivers.iter()
+            .position(|d| d.driver.init_order() > order)
+            .unwrap_or(self.drivers.len());
+        self.drivers.insert(index, RegisteredDriver { driver, bound: None });
+    }
+    
+    /// Attach the first free driver that claims the device.
+    /// Returns the driver name, or `None` if the device is already bound or unclaimed.
+    pub fn attach_device(&mut self, device: &BusDevice) -> DeviceResult<Option<String>> {
+        if self.drivers.iter().any(|d| d.bound.as_ref().map_or(false, |b| b.same_device(device))) {
+            return Ok(None);
+        }
+        
+        for entry in self.drivers.iter_mut() {
+            if entry.bound.is_some() || !entry.driver.probe(device) {
+                continue;
+            }
+            entry.driver.attach(device)?;
+            entry.bound = Some(device.clone());
+            return Ok(Some(entry.driver.name().to_string()));
+        }
+        
+        Ok(None)
+    }
+    
+    /// Detach whichever driver is bound to the device
+    pub fn detach_device(&mut self, device: &BusDevice) -> Option<String> {
+        let entry = self.drivers.iter_mut()
+            .find(|d| d.bound.as_ref().map_or(false, |b| b.same_device(device)))?;
+        entry.driver.detach();
+        entry.bound = None;
+        Some(entry.driver.name().to_string())
+    }
+    
+    /// Detach every bound driver in reverse init order.
+    /// Returns the devices that were bound so they can be re-attached on resume.
+    pub fn detach_all(&mut self) -> Vec<BusDevice> {
+        let mut devices = Vec::new();
+        for entry in self.drivers.iter_mut().rev() {
+            if let Some(device) = entry.bound.take() {
+                entry.driver.detach();
+                devices.push(device);
+            }
+        }
+        devices.reverse();
+        devices
+    }
+    
+    pub fn bound_driver(&self, device: &BusDevice) -> Option<&str> {
+        self.drivers.iter()
+            .find(|d| d.bound.as_ref().map_or(false, |b| b.same_device(device)))
+            .map(|d| d.driver.name())
+    }
+}
+
 // VGA Graphics Driver
 #[derive(Debug)]
 pub struct VgaDriver {
@@ -1888,7 +2033,7 @@ impl Device for Ps2MouseDriver {
     fn info(&self) -> DeviceInfo {
         DeviceInfo {
             id: 3,
-            name: self.name().to_string(),
+            name: Device::name(self).to_string(),
             device_type: self.device_type(),
             status: self.status,
             vendor_id: 0x0000,
@@ -1915,6 +2060,30 @@ impl Device for Ps2MouseDriver {
     }
 }
 
+impl Driver for Ps2MouseDriver {
+    fn name(&self) -> &str {
+        "ps2-mouse"
+    }
+    
+    fn init_order(&self) -> u32 {
+        20
+    }
+    
+    fn probe(&self, device: &BusDevice) -> bool {
+        matches!(device, BusDevice::Platform("i8042-aux"))
+    }
+    
+    fn attach(&mut self, _device: &BusDevice) -> DeviceResult<()> {
+        Device::init(self)
+    }
+    
+    fn detach(&mut self) {
+        // Disable data reporting so the controller stops raising IRQ 12
+        let _ = self.send_command(0xF5);
+        self.status = DeviceStatus::Disabled;
+    }
+}
+
 // Public API functions
 pub fn init() {
     let mut manager = DEVICE_MANAGER.write();
@@ -1945,6 +2114,66 @@ pub fn register_device(device: Box<dyn Device>) -> DeviceResult<u64> {
     DEVICE_MANAGER.write().register_device(device)
 }
 
+/// Register a typed driver with the driver model
+pub fn register_driver(driver: Box<dyn Driver>) {
+    DRIVER_REGISTRY.write().register(driver);
+}
+
+/// Offer a newly discovered device to the registered drivers
+pub fn device_added(device: &BusDevice) -> DeviceResult<Option<String>> {
+    let attached = DRIVER_REGISTRY.write().attach_device(device)?;
+    if let Some(ref name) = attached {
+        crate::serial::_print(format_args!("[Drivers] Attached {} to {}\n", name, bus_device_label(device)));
+    }
+    Ok(attached)
+}
+
+/// Detach the driver bound to a device that has gone away
+pub fn device_removed(device: &BusDevice) {
+    if let Some(name) = DRIVER_REGISTRY.write().detach_device(device) {
+        crate::serial::_print(format_args!("[Drivers] Detached {} from {}\n", name, bus_device_label(device)));
+    }
+}
+
+/// Name of the driver bound to a device, if any
+pub fn bound_driver(device: &BusDevice) -> Option<String> {
+    DRIVER_REGISTRY.read().bound_driver(device).map(String::from)
+}
+
+/// Tear down all drivers in reverse init order before power-off
+pub fn shutdown_drivers() {
+    let detached = DRIVER_REGISTRY.write().detach_all();
+    crate::serial::_print(format_args!("[Drivers] Detached {} driver(s) for shutdown\n", detached.len()));
+}
+
+static SUSPENDED_DEVICES: RwLock<Vec<BusDevice>> = RwLock::new(Vec::new());
+
+/// Detach all drivers for suspend, remembering what was bound
+pub fn suspend_drivers() {
+    let detached = DRIVER_REGISTRY.write().detach_all();
+    *SUSPENDED_DEVICES.write() = detached;
+}
+
+/// Re-attach drivers to the devices that were bound before suspend
+pub fn resume_drivers() {
+    let devices = core::mem::take(&mut *SUSPENDED_DEVICES.write());
+    for device in devices {
+        if let Err(e) = device_added(&device) {
+            crate::serial::_print(format_args!("[Drivers] Resume attach failed: {}\n", e));
+        }
+    }
+}
+
+fn bus_device_label(device: &BusDevice) -> String {
+    match device {
+        BusDevice::Pci(pci) => alloc::format!(
+            "pci {:02X}:{:02X}.{} ({:04X}:{:04X})",
+            pci.bus, pci.device, pci.function, pci.vendor_id, pci.device_id
+        ),
+        BusDevice::Platform(name) => alloc::format!("platform {}", name),
+    }
+}
+
 pub fn get_device_info(id: u64) -> Option<DeviceInfo> {
     DEVICE_MANAGER.read().get_device(id).map(|d| d.info())
 }
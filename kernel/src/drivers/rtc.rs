@@ -0,0 +1,312 @@
+//! CMOS real-time clock driver for RaeenOS
+//! Reads the wall-clock date/time (BCD or binary, 12 or 24 hour) and drives the
+//! RTC periodic and alarm interrupts on IRQ 8
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+use crate::time::DateTime;
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+/// Keeps NMIs masked while a CMOS register is selected
+const NMI_DISABLE: u8 = 0x80;
+
+const RTC_SECONDS: u8 = 0x00;
+const RTC_SECONDS_ALARM: u8 = 0x01;
+const RTC_MINUTES: u8 = 0x02;
+const RTC_MINUTES_ALARM: u8 = 0x03;
+const RTC_HOURS: u8 = 0x04;
+const RTC_HOURS_ALARM: u8 = 0x05;
+const RTC_DAY: u8 = 0x07;
+const RTC_MONTH: u8 = 0x08;
+const RTC_YEAR: u8 = 0x09;
+const RTC_STATUS_A: u8 = 0x0A;
+const RTC_STATUS_B: u8 = 0x0B;
+const RTC_STATUS_C: u8 = 0x0C;
+
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 0x80;
+const STATUS_B_24_HOUR: u8 = 0x02;
+const STATUS_B_BINARY: u8 = 0x04;
+const STATUS_B_UPDATE_IRQ: u8 = 0x10;
+const STATUS_B_ALARM_IRQ: u8 = 0x20;
+const STATUS_B_PERIODIC_IRQ: u8 = 0x40;
+const STATUS_C_UPDATE: u8 = 0x10;
+const STATUS_C_ALARM: u8 = 0x20;
+const STATUS_C_PERIODIC: u8 = 0x40;
+
+const HOUR_PM: u8 = 0x80;
+/// Alarm field value matching any time
+const ALARM_DONT_CARE: u8 = 0xC0;
+
+/// Offset of the century register index in the ACPI FADT
+const FADT_CENTURY_OFFSET: u64 = 108;
+const DEFAULT_CENTURY: u16 = 20;
+
+/// ISA IRQ line of the RTC
+pub const RTC_IRQ: u8 = 8;
+
+/// Raw register snapshot, before decoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RtcRegisters {
+    pub second: u8,
+    pub minute: u8,
+    pub hour: u8,
+    pub day: u8,
+    pub month: u8,
+    pub year: u8,
+    /// Zero when the platform has no century register
+    pub century: u8,
+    pub status_b: u8,
+}
+
+/// Callback run from the RTC interrupt when the alarm matches
+pub type AlarmHandler = fn();
+
+static CENTURY_REGISTER: Mutex<Option<u8>> = Mutex::new(None);
+/// Read by the RTC interrupt, so only locked elsewhere with interrupts off
+static ALARM_HANDLER: Mutex<Option<AlarmHandler>> = Mutex::new(None);
+static PERIODIC_INTERRUPTS: AtomicU64 = AtomicU64::new(0);
+static ALARM_INTERRUPTS: AtomicU64 = AtomicU64::new(0);
+static UPDATE_INTERRUPTS: AtomicU64 = AtomicU64::new(0);
+
+fn read_register(reg: u8) -> u8 {
+    unsafe {
+        // SAFETY: This is unsafe because:
+        // - CMOS_ADDRESS (0x70) and CMOS_DATA (0x71) are standard x86 CMOS/RTC I/O ports
+        // - The two-step process (write address, read data) must not be interleaved
+        //   with other CMOS accesses, so callers run with interrupts disabled
+        let mut addr_port = Port::new(CMOS_ADDRESS);
+        let mut data_port = Port::new(CMOS_DATA);
+
+        addr_port.write(reg | NMI_DISABLE);
+        data_port.read()
+    }
+}
+
+fn write_register(reg: u8, value: u8) {
+    unsafe {
+        // SAFETY: Same port protocol as read_register; reg must be a writable RTC register
+        let mut addr_port = Port::new(CMOS_ADDRESS);
+        let mut data_port = Port::new(CMOS_DATA);
+
+        addr_port.write(reg | NMI_DISABLE);
+        data_port.write(value);
+    }
+}
+
+fn bcd_to_binary(bcd: u8) -> u8 {
+    (bcd & 0x0F) + ((bcd >> 4) * 10)
+}
+
+fn binary_to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// Century register index from the ACPI FADT, if the platform provides one
+fn century_register() -> Option<u8> {
+    let mut cached = CENTURY_REGISTER.lock();
+    if cached.is_none() {
+        let index = crate::acpi::find_table(b"FACP")
+            .filter(|&fadt| crate::acpi::read_header(fadt).length as u64 > FADT_CENTURY_OFFSET)
+            .map(|fadt| unsafe {
+                core::ptr::read_volatile(crate::memory::phys_to_virt(fadt + FADT_CENTURY_OFFSET).as_ptr::<u8>())
+            })
+            .unwrap_or(0);
+        *cached = Some(index);
+    }
+    cached.filter(|&index| index != 0)
+}
+
+fn is_updating() -> bool {
+    read_register(RTC_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0
+}
+
+fn read_registers_once() -> RtcRegisters {
+    while is_updating() {
+        core::hint::spin_loop();
+    }
+
+    RtcRegisters {
+        second: read_register(RTC_SECONDS),
+        minute: read_register(RTC_MINUTES),
+        hour: read_register(RTC_HOURS),
+        day: read_register(RTC_DAY),
+        month: read_register(RTC_MONTH),
+        year: read_register(RTC_YEAR),
+        century: century_register().map_or(0, read_register),
+        status_b: read_register(RTC_STATUS_B),
+    }
+}
+
+/// Read the raw registers, retrying until two consecutive reads agree
+/// so an update can't tear the result
+pub fn read_registers() -> RtcRegisters {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut last = read_registers_once();
+        loop {
+            let current = read_registers_once();
+            if current == last {
+                return current;
+            }
+            last = current;
+        }
+    })
+}
+
+/// Decode a register snapshot, handling BCD/binary and 12/24-hour encodings
+pub fn decode(raw: &RtcRegisters) -> DateTime {
+    let binary = (raw.status_b & STATUS_B_BINARY) != 0;
+    let convert = |value: u8| if binary { value } else { bcd_to_binary(value) };
+
+    // 12-hour mode keeps the PM flag in bit 7, and 12 AM is midnight
+    let mut hour = convert(raw.hour & !HOUR_PM);
+    if (raw.status_b & STATUS_B_24_HOUR) == 0 {
+        let pm = (raw.hour & HOUR_PM) != 0;
+        hour = match (hour, pm) {
+            (12, false) => 0,
+            (12, true) => 12,
+            (h, true) => h + 12,
+            (h, false) => h,
+        };
+    }
+
+    let century = if raw.century != 0 { convert(raw.century) as u16 } else { DEFAULT_CENTURY };
+
+    DateTime {
+        year: century * 100 + convert(raw.year) as u16,
+        month: convert(raw.month),
+        day: convert(raw.day),
+        hour,
+        minute: convert(raw.minute),
+        second: convert(raw.second),
+    }
+}
+
+/// Current date and time from the RTC (UTC)
+pub fn read() -> DateTime {
+    decode(&read_registers())
+}
+
+/// Current time from the RTC as a Unix timestamp
+pub fn read_timestamp() -> u64 {
+    read().to_timestamp()
+}
+
+/// Route the RTC interrupt and acknowledge anything already pending
+pub fn init() -> Result<(), &'static str> {
+    if crate::apic::is_apic_enabled() {
+        crate::apic::route_irq(RTC_IRQ, crate::interrupts::InterruptIndex::Rtc.as_u8())?;
+    }
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        read_register(RTC_STATUS_C);
+    });
+    Ok(())
+}
+
+fn update_status_b(set: u8, clear: u8) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let status_b = read_register(RTC_STATUS_B);
+        write_register(RTC_STATUS_B, (status_b & !clear) | set);
+        read_register(RTC_STATUS_C);
+    });
+}
+
+/// Enable the periodic interrupt at 32768 >> (rate - 1) Hz; rate is 3..=15
+pub fn enable_periodic(rate: u8) -> Result<(), &'static str> {
+    if !(3..=15).contains(&rate) {
+        return Err("RTC periodic rate must be between 3 and 15");
+    }
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let status_a = read_register(RTC_STATUS_A);
+        write_register(RTC_STATUS_A, (status_a & 0xF0) | rate);
+    });
+    update_status_b(STATUS_B_PERIODIC_IRQ, 0);
+    Ok(())
+}
+
+/// Periodic interrupt frequency for a rate value
+pub fn periodic_frequency(rate: u8) -> u32 {
+    32768 >> (rate.clamp(3, 15) - 1)
+}
+
+pub fn disable_periodic() {
+    update_status_b(0, STATUS_B_PERIODIC_IRQ);
+}
+
+/// Fire `handler` every day at the given time (UTC). `None` fields match any value.
+pub fn set_alarm(hour: Option<u8>, minute: Option<u8>, second: Option<u8>, handler: AlarmHandler) -> Result<(), &'static str> {
+    if hour.map_or(false, |h| h > 23) || minute.map_or(false, |m| m > 59) || second.map_or(false, |s| s > 59) {
+        return Err("Invalid RTC alarm time");
+    }
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        *ALARM_HANDLER.lock() = Some(handler);
+        let status_b = read_register(RTC_STATUS_B);
+        let binary = (status_b & STATUS_B_BINARY) != 0;
+        let encode = |value: u8| if binary { value } else { binary_to_bcd(value) };
+
+        let hour_value = hour.map_or(ALARM_DONT_CARE, |h| {
+            if (status_b & STATUS_B_24_HOUR) != 0 {
+                encode(h)
+            } else {
+                let pm = if h >= 12 { HOUR_PM } else { 0 };
+                let h12 = match h % 12 { 0 => 12, h => h };
+                encode(h12) | pm
+            }
+        });
+
+        write_register(RTC_SECONDS_ALARM, second.map_or(ALARM_DONT_CARE, encode));
+        write_register(RTC_MINUTES_ALARM, minute.map_or(ALARM_DONT_CARE, encode));
+        write_register(RTC_HOURS_ALARM, hour_value);
+    });
+    update_status_b(STATUS_B_ALARM_IRQ, 0);
+    Ok(())
+}
+
+pub fn clear_alarm() {
+    update_status_b(0, STATUS_B_ALARM_IRQ);
+    x86_64::instructions::interrupts::without_interrupts(|| *ALARM_HANDLER.lock() = None);
+}
+
+/// Enable the once-per-second update-ended interrupt
+pub fn enable_update_interrupt(enabled: bool) {
+    if enabled {
+        update_status_b(STATUS_B_UPDATE_IRQ, 0);
+    } else {
+        update_status_b(0, STATUS_B_UPDATE_IRQ);
+    }
+}
+
+pub fn periodic_interrupt_count() -> u64 {
+    PERIODIC_INTERRUPTS.load(Ordering::SeqCst)
+}
+
+pub fn alarm_interrupt_count() -> u64 {
+    ALARM_INTERRUPTS.load(Ordering::SeqCst)
+}
+
+pub fn update_interrupt_count() -> u64 {
+    UPDATE_INTERRUPTS.load(Ordering::SeqCst)
+}
+
+/// Called from the RTC interrupt vector. Status C must be read for the RTC
+/// to raise further interrupts.
+pub fn handle_interrupt() {
+    let status_c = read_register(RTC_STATUS_C);
+
+    if (status_c & STATUS_C_PERIODIC) != 0 {
+        PERIODIC_INTERRUPTS.fetch_add(1, Ordering::SeqCst);
+    }
+    if (status_c & STATUS_C_UPDATE) != 0 {
+        UPDATE_INTERRUPTS.fetch_add(1, Ordering::SeqCst);
+    }
+    if (status_c & STATUS_C_ALARM) != 0 {
+        ALARM_INTERRUPTS.fetch_add(1, Ordering::SeqCst);
+        let handler = *ALARM_HANDLER.lock();
+        if let Some(handler) = handler {
+            handler();
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! HID multi-touch touchscreen driver for RaeenOS
+//! Decodes digitizer input reports into frames of touch contacts for the input handler's
+//! gesture recognizer. The HID transport hands over each input report with `submit_report`;
+//! every report lists all contacts the digitizer is tracking in this layout:
+//!
+//! | offset | field                                              |
+//! |--------|----------------------------------------------------|
+//! | 0      | report ID, `TOUCH_REPORT_ID`                       |
+//! | 1      | contact count                                      |
+//! | 2 + 8n | contact n flags, bit 0 is the tip switch           |
+//! | 3 + 8n | contact n ID, stable while the finger stays down   |
+//! | 4 + 8n | contact n X, little-endian logical units           |
+//! | 6 + 8n | contact n Y, little-endian logical units           |
+//! | 8 + 8n | contact n pressure, little-endian                  |
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::{DeviceError, DeviceResult};
+
+pub const TOUCH_REPORT_ID: u8 = 0x01;
+
+/// Most simultaneous contacts a report may carry
+pub const MAX_CONTACTS: usize = 10;
+
+const CONTACT_SIZE: usize = 8;
+const TIP_SWITCH: u8 = 0x01;
+
+/// Frames kept while the input handler falls behind; the oldest are dropped first
+const MAX_QUEUED_FRAMES: usize = 64;
+
+/// One finger on the screen, in screen coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TouchPoint {
+    pub id: u8,
+    pub x: i32,
+    pub y: i32,
+    pub pressure: u16,
+}
+
+/// The contacts of one digitizer scan. A finger that left the screen is reported once more
+/// with its tip switch clear, or simply stops being listed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TouchFrame {
+    pub timestamp_ns: u64,
+    pub contacts: Vec<TouchPoint>,
+}
+
+#[derive(Debug)]
+pub struct HidTouchscreen {
+    logical_max_x: u16,
+    logical_max_y: u16,
+    screen_width: u32,
+    screen_height: u32,
+    frames: VecDeque<TouchFrame>,
+}
+
+impl HidTouchscreen {
+    /// A digitizer reporting coordinates up to `logical_max_x`/`logical_max_y`, mapped onto a
+    /// `screen_width` by `screen_height` display
+    pub fn new(logical_max_x: u16, logical_max_y: u16, screen_width: u32, screen_height: u32) -> Self {
+        Self {
+            logical_max_x: logical_max_x.max(1),
+            logical_max_y: logical_max_y.max(1),
+            screen_width: screen_width.max(1),
+            screen_height: screen_height.max(1),
+            frames: VecDeque::new(),
+        }
+    }
+
+    fn scale(value: u16, logical_max: u16, extent: u32) -> i32 {
+        let value = u64::from(value.min(logical_max));
+        (value * u64::from(extent - 1) / u64::from(logical_max)) as i32
+    }
+
+    /// Decode an input report into a frame, keeping only contacts whose tip is down
+    pub fn parse_report(&self, report: &[u8], timestamp_ns: u64) -> DeviceResult<TouchFrame> {
+        let (&report_id, rest) = report.split_first().ok_or(DeviceError::InvalidParameter)?;
+        if report_id != TOUCH_REPORT_ID {
+            return Err(DeviceError::NotSupported);
+        }
+        let (&count, rest) = rest.split_first().ok_or(DeviceError::InvalidParameter)?;
+        let count = count as usize;
+        if count > MAX_CONTACTS || rest.len() < count * CONTACT_SIZE {
+            return Err(DeviceError::InvalidParameter);
+        }
+
+        let contacts = rest
+            .chunks_exact(CONTACT_SIZE)
+            .take(count)
+            .filter(|contact| contact[0] & TIP_SWITCH != 0)
+            .map(|contact| {
+                let word = |offset: usize| u16::from_le_bytes([contact[offset], contact[offset + 1]]);
+                TouchPoint {
+                    id: contact[1],
+                    x: Self::scale(word(2), self.logical_max_x, self.screen_width),
+                    y: Self::scale(word(4), self.logical_max_y, self.screen_height),
+                    pressure: word(6),
+                }
+            })
+            .collect();
+        Ok(TouchFrame { timestamp_ns, contacts })
+    }
+
+    pub fn handle_report(&mut self, report: &[u8], timestamp_ns: u64) -> DeviceResult<()> {
+        let frame = self.parse_report(report, timestamp_ns)?;
+        if self.frames.len() >= MAX_QUEUED_FRAMES {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+        Ok(())
+    }
+
+    pub fn next_frame(&mut self) -> Option<TouchFrame> {
+        self.frames.pop_front()
+    }
+}
+
+static TOUCHSCREEN: Mutex<Option<HidTouchscreen>> = Mutex::new(None);
+
+/// Bind the touchscreen once its HID transport has read the digitizer's logical ranges
+pub fn attach(logical_max_x: u16, logical_max_y: u16, screen_width: u32, screen_height: u32) {
+    *TOUCHSCREEN.lock() = Some(HidTouchscreen::new(logical_max_x, logical_max_y, screen_width, screen_height));
+}
+
+pub fn detach() {
+    *TOUCHSCREEN.lock() = None;
+}
+
+/// Queue an input report received from the HID transport
+pub fn submit_report(report: &[u8]) -> DeviceResult<()> {
+    let timestamp_ns = crate::time::get_timestamp_ns();
+    TOUCHSCREEN.lock().as_mut().ok_or(DeviceError::NotFound)?.handle_report(report, timestamp_ns)
+}
+
+/// The oldest touch frame not yet handed to the input handler
+pub fn poll_frame() -> Option<TouchFrame> {
+    TOUCHSCREEN.lock().as_mut()?.next_frame()
+}
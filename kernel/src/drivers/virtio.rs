@@ -0,0 +1,1016 @@
+//! VirtIO drivers for RaeenOS
+//! Implements the modern virtio-pci transport (capability-based register layout, feature
+//! negotiation, split virtqueues with MSI-X) and the virtio-blk and virtio-net drivers on top
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{fence, Ordering};
+use spin::Mutex;
+use x86_64::structures::paging::PhysFrame;
+use x86_64::{PhysAddr, VirtAddr};
+
+use super::{BlockDevice, BusDevice, DeviceError, DeviceResult, Driver};
+use crate::pci::{self, PciDevice};
+
+/// VirtIO PCI vendor ID
+pub const VIRTIO_VENDOR_ID: u16 = 0x1AF4;
+
+/// PCI device IDs (transitional, modern)
+const VIRTIO_NET_IDS: [u16; 2] = [0x1000, 0x1041];
+const VIRTIO_BLK_IDS: [u16; 2] = [0x1001, 0x1042];
+
+/// Vendor-specific PCI capability carrying virtio structure locations
+const PCI_CAP_ID_VENDOR: u8 = 0x09;
+const VIRTIO_PCI_CAP_COMMON_CFG: u8 = 1;
+const VIRTIO_PCI_CAP_NOTIFY_CFG: u8 = 2;
+const VIRTIO_PCI_CAP_ISR_CFG: u8 = 3;
+const VIRTIO_PCI_CAP_DEVICE_CFG: u8 = 4;
+
+/// Common configuration structure offsets
+const COMMON_DEVICE_FEATURE_SELECT: u64 = 0x00;
+const COMMON_DEVICE_FEATURE: u64 = 0x04;
+const COMMON_DRIVER_FEATURE_SELECT: u64 = 0x08;
+const COMMON_DRIVER_FEATURE: u64 = 0x0C;
+const COMMON_MSIX_CONFIG: u64 = 0x10;
+const COMMON_NUM_QUEUES: u64 = 0x12;
+const COMMON_DEVICE_STATUS: u64 = 0x14;
+const COMMON_QUEUE_SELECT: u64 = 0x16;
+const COMMON_QUEUE_SIZE: u64 = 0x18;
+const COMMON_QUEUE_MSIX_VECTOR: u64 = 0x1A;
+const COMMON_QUEUE_ENABLE: u64 = 0x1C;
+const COMMON_QUEUE_NOTIFY_OFF: u64 = 0x1E;
+const COMMON_QUEUE_DESC: u64 = 0x20;
+const COMMON_QUEUE_DRIVER: u64 = 0x28;
+const COMMON_QUEUE_DEVICE: u64 = 0x30;
+
+/// Device status bits
+const STATUS_ACKNOWLEDGE: u8 = 1;
+const STATUS_DRIVER: u8 = 2;
+const STATUS_DRIVER_OK: u8 = 4;
+const STATUS_FEATURES_OK: u8 = 8;
+const STATUS_FAILED: u8 = 0x80;
+
+/// Feature bits
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+const VIRTIO_NET_F_MAC: u64 = 1 << 5;
+
+/// Descriptor flags
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+const VIRTIO_MSI_NO_VECTOR: u16 = 0xFFFF;
+
+/// Queues are capped so descriptors and the available ring share one page
+const MAX_QUEUE_SIZE: u16 = 128;
+const AVAIL_RING_OFFSET: u64 = 2048;
+
+/// Spin budget when polling for a synchronous completion
+const COMPLETION_TIMEOUT_SPINS: u32 = 10_000_000;
+
+/// Split virtqueue descriptor
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+struct VirtqDesc {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// A buffer handed to the device: physical address, length, device-writable
+#[derive(Debug, Clone, Copy)]
+pub struct VirtqBuffer {
+    pub addr: PhysAddr,
+    pub len: u32,
+    pub device_writable: bool,
+}
+
+/// Split virtqueue (descriptor table, available ring, used ring)
+#[derive(Debug)]
+pub struct Virtqueue {
+    index: u16,
+    size: u16,
+    notify_offset: u16,
+    ring_frame: PhysFrame,
+    used_frame: PhysFrame,
+    desc: VirtAddr,
+    avail: VirtAddr,
+    used: VirtAddr,
+    free_head: u16,
+    num_free: u16,
+    avail_idx: u16,
+    last_used_idx: u16,
+}
+
+impl Virtqueue {
+    fn new(index: u16, size: u16, notify_offset: u16) -> DeviceResult<Self> {
+        let ring_frame = crate::memory::allocate_frame().ok_or(DeviceError::OutOfMemory)?;
+        let used_frame = match crate::memory::allocate_frame() {
+            Some(frame) => frame,
+            None => {
+                crate::memory::deallocate_frame(ring_frame);
+                return Err(DeviceError::OutOfMemory);
+            }
+        };
+
+        let ring_virt = crate::memory::phys_to_virt(ring_frame.start_address());
+        let used_virt = crate::memory::phys_to_virt(used_frame.start_address());
+
+        unsafe {
+            core::ptr::write_bytes(ring_virt.as_mut_ptr::<u8>(), 0, 4096);
+            core::ptr::write_bytes(used_virt.as_mut_ptr::<u8>(), 0, 4096);
+        }
+
+        let mut queue = Self {
+            index,
+            size,
+            notify_offset,
+            ring_frame,
+            used_frame,
+            desc: ring_virt,
+            avail: ring_virt + AVAIL_RING_OFFSET,
+            used: used_virt,
+            free_head: 0,
+            num_free: size,
+            avail_idx: 0,
+            last_used_idx: 0,
+        };
+
+        // Chain every descriptor into the free list
+        for i in 0..size {
+            let desc = queue.desc_mut(i);
+            desc.next = if i + 1 < size { i + 1 } else { 0 };
+        }
+
+        Ok(queue)
+    }
+
+    fn desc_mut(&mut self, i: u16) -> &mut VirtqDesc {
+        unsafe { &mut *(self.desc.as_mut_ptr::<VirtqDesc>().add(i as usize)) }
+    }
+
+    fn desc_phys(&self) -> u64 {
+        self.ring_frame.start_address().as_u64()
+    }
+
+    fn avail_phys(&self) -> u64 {
+        self.ring_frame.start_address().as_u64() + AVAIL_RING_OFFSET
+    }
+
+    fn used_phys(&self) -> u64 {
+        self.used_frame.start_address().as_u64()
+    }
+
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    pub fn num_free(&self) -> u16 {
+        self.num_free
+    }
+
+    /// Chain buffers into descriptors and publish the chain on the available ring.
+    /// Returns the head descriptor index, which the device echoes back on completion.
+    pub fn add(&mut self, buffers: &[VirtqBuffer]) -> DeviceResult<u16> {
+        if buffers.is_empty() {
+            return Err(DeviceError::InvalidParameter);
+        }
+        if buffers.len() > self.num_free as usize {
+            return Err(DeviceError::Busy);
+        }
+
+        let head = self.free_head;
+        let mut index = head;
+        for (i, buffer) in buffers.iter().enumerate() {
+            let last = i + 1 == buffers.len();
+            let desc = self.desc_mut(index);
+            let next_free = desc.next;
+            desc.addr = buffer.addr.as_u64();
+            desc.len = buffer.len;
+            desc.flags = if buffer.device_writable { VIRTQ_DESC_F_WRITE } else { 0 }
+                | if last { 0 } else { VIRTQ_DESC_F_NEXT };
+            if last {
+                self.free_head = next_free;
+            } else {
+                index = next_free;
+            }
+        }
+        self.num_free -= buffers.len() as u16;
+
+        // avail ring: flags (u16), idx (u16), ring[size] (u16)
+        unsafe {
+            let ring = self.avail.as_mut_ptr::<u16>();
+            let slot = (self.avail_idx % self.size) as usize;
+            core::ptr::write_volatile(ring.add(2 + slot), head);
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            core::ptr::write_volatile(ring.add(1), self.avail_idx);
+        }
+        fence(Ordering::SeqCst);
+
+        Ok(head)
+    }
+
+    /// Whether the device has returned buffers we have not consumed yet
+    pub fn has_used(&self) -> bool {
+        let used_idx = unsafe { core::ptr::read_volatile(self.used.as_ptr::<u16>().add(1)) };
+        used_idx != self.last_used_idx
+    }
+
+    /// Take the next completed chain off the used ring, returning its head
+    /// descriptor and the number of bytes the device wrote
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        if !self.has_used() {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+
+        // used ring: flags (u16), idx (u16), ring[size] of { id: u32, len: u32 }
+        let slot = (self.last_used_idx % self.size) as usize;
+        let (id, len) = unsafe {
+            let elem = self.used.as_ptr::<u8>().add(4 + slot * 8) as *const u32;
+            (core::ptr::read_volatile(elem), core::ptr::read_volatile(elem.add(1)))
+        };
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+
+        // Return the chain to the free list
+        let head = id as u16;
+        let mut tail = head;
+        let mut count = 1;
+        loop {
+            let desc = *self.desc_mut(tail);
+            if (desc.flags & VIRTQ_DESC_F_NEXT) == 0 {
+                break;
+            }
+            tail = desc.next;
+            count += 1;
+        }
+        let free_head = self.free_head;
+        self.desc_mut(tail).next = free_head;
+        self.free_head = head;
+        self.num_free += count;
+
+        Some((head, len))
+    }
+
+    pub(super) fn release(self) {
+        crate::memory::deallocate_frame(self.ring_frame);
+        crate::memory::deallocate_frame(self.used_frame);
+    }
+}
+
+/// Modern virtio-pci transport located through the device's vendor capabilities
+#[derive(Debug)]
+pub struct VirtioTransport {
+    pci_device: PciDevice,
+    common: VirtAddr,
+    notify_base: VirtAddr,
+    notify_multiplier: u32,
+    isr: VirtAddr,
+    device_cfg: VirtAddr,
+    msix_vectors: Vec<u8>,
+}
+
+impl VirtioTransport {
+    /// Locate the common/notify/ISR/device configuration structures
+    pub fn new(pci_device: &PciDevice) -> DeviceResult<Self> {
+        let (bus, dev, func) = (pci_device.bus, pci_device.device, pci_device.function);
+        let mut common = None;
+        let mut notify = None;
+        let mut isr = None;
+        let mut device_cfg = None;
+
+        for cap in pci_device.capabilities.iter().filter(|c| c.id == PCI_CAP_ID_VENDOR) {
+            // virtio_pci_cap: vndr, next, len, cfg_type, bar, pad[3], offset (u32), length (u32)
+            let cfg_type = cap.data[3];
+            let bar = cap.data[4];
+            let offset = pci::read_config_dword(bus, dev, func, cap.offset + 8);
+            let base = match pci_device.memory_bar(bar) {
+                Some(base) => crate::memory::phys_to_virt(PhysAddr::new(base + offset as u64)),
+                None => continue,
+            };
+
+            match cfg_type {
+                VIRTIO_PCI_CAP_COMMON_CFG if common.is_none() => common = Some(base),
+                VIRTIO_PCI_CAP_NOTIFY_CFG if notify.is_none() => {
+                    let multiplier = pci::read_config_dword(bus, dev, func, cap.offset + 16);
+                    notify = Some((base, multiplier));
+                }
+                VIRTIO_PCI_CAP_ISR_CFG if isr.is_none() => isr = Some(base),
+                VIRTIO_PCI_CAP_DEVICE_CFG if device_cfg.is_none() => device_cfg = Some(base),
+                _ => {}
+            }
+        }
+
+        let common = common.ok_or(DeviceError::NotSupported)?;
+        let (notify_base, notify_multiplier) = notify.ok_or(DeviceError::NotSupported)?;
+
+        pci::enable_memory_space(bus, dev, func);
+        pci::enable_bus_mastering(bus, dev, func);
+
+        Ok(Self {
+            pci_device: pci_device.clone(),
+            common,
+            notify_base,
+            notify_multiplier,
+            isr: isr.ok_or(DeviceError::NotSupported)?,
+            device_cfg: device_cfg.unwrap_or(VirtAddr::new(0)),
+            msix_vectors: Vec::new(),
+        })
+    }
+
+    pub fn pci_device(&self) -> &PciDevice {
+        &self.pci_device
+    }
+
+    fn read8(&self, offset: u64) -> u8 {
+        unsafe { core::ptr::read_volatile((self.common.as_u64() + offset) as *const u8) }
+    }
+
+    fn write8(&self, offset: u64, value: u8) {
+        unsafe { core::ptr::write_volatile((self.common.as_u64() + offset) as *mut u8, value) }
+    }
+
+    fn read16(&self, offset: u64) -> u16 {
+        unsafe { core::ptr::read_volatile((self.common.as_u64() + offset) as *const u16) }
+    }
+
+    fn write16(&self, offset: u64, value: u16) {
+        unsafe { core::ptr::write_volatile((self.common.as_u64() + offset) as *mut u16, value) }
+    }
+
+    fn read32(&self, offset: u64) -> u32 {
+        unsafe { core::ptr::read_volatile((self.common.as_u64() + offset) as *const u32) }
+    }
+
+    fn write32(&self, offset: u64, value: u32) {
+        unsafe { core::ptr::write_volatile((self.common.as_u64() + offset) as *mut u32, value) }
+    }
+
+    fn write64(&self, offset: u64, value: u64) {
+        // 64-bit fields are written as two 32-bit halves per the spec
+        self.write32(offset, value as u32);
+        self.write32(offset + 4, (value >> 32) as u32);
+    }
+
+    /// Read from the device-specific configuration structure
+    pub fn config_read8(&self, offset: u64) -> u8 {
+        unsafe { core::ptr::read_volatile((self.device_cfg.as_u64() + offset) as *const u8) }
+    }
+
+    pub fn config_read32(&self, offset: u64) -> u32 {
+        unsafe { core::ptr::read_volatile((self.device_cfg.as_u64() + offset) as *const u32) }
+    }
+
+    pub fn config_write32(&self, offset: u64, value: u32) {
+        unsafe { core::ptr::write_volatile((self.device_cfg.as_u64() + offset) as *mut u32, value) }
+    }
+
+    pub fn config_read64(&self, offset: u64) -> u64 {
+        self.config_read32(offset) as u64 | ((self.config_read32(offset + 4) as u64) << 32)
+    }
+
+    pub fn status(&self) -> u8 {
+        self.read8(COMMON_DEVICE_STATUS)
+    }
+
+    pub fn reset(&self) {
+        self.write8(COMMON_DEVICE_STATUS, 0);
+        let mut timeout = COMPLETION_TIMEOUT_SPINS;
+        while self.read8(COMMON_DEVICE_STATUS) != 0 && timeout > 0 {
+            core::hint::spin_loop();
+            timeout -= 1;
+        }
+    }
+
+    /// Reset the device and negotiate features; VERSION_1 is always required.
+    /// Returns the accepted feature set.
+    pub fn negotiate(&self, wanted: u64) -> DeviceResult<u64> {
+        self.reset();
+        self.write8(COMMON_DEVICE_STATUS, STATUS_ACKNOWLEDGE);
+        self.write8(COMMON_DEVICE_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+        self.write32(COMMON_DEVICE_FEATURE_SELECT, 0);
+        let low = self.read32(COMMON_DEVICE_FEATURE) as u64;
+        self.write32(COMMON_DEVICE_FEATURE_SELECT, 1);
+        let high = self.read32(COMMON_DEVICE_FEATURE) as u64;
+        let offered = low | (high << 32);
+
+        if (offered & VIRTIO_F_VERSION_1) == 0 {
+            self.write8(COMMON_DEVICE_STATUS, STATUS_FAILED);
+            return Err(DeviceError::NotSupported);
+        }
+
+        let accepted = offered & (wanted | VIRTIO_F_VERSION_1);
+        self.write32(COMMON_DRIVER_FEATURE_SELECT, 0);
+        self.write32(COMMON_DRIVER_FEATURE, accepted as u32);
+        self.write32(COMMON_DRIVER_FEATURE_SELECT, 1);
+        self.write32(COMMON_DRIVER_FEATURE, (accepted >> 32) as u32);
+
+        let status = self.status() | STATUS_FEATURES_OK;
+        self.write8(COMMON_DEVICE_STATUS, status);
+        if (self.status() & STATUS_FEATURES_OK) == 0 {
+            self.write8(COMMON_DEVICE_STATUS, STATUS_FAILED);
+            return Err(DeviceError::InitializationFailed);
+        }
+
+        Ok(accepted)
+    }
+
+    /// Allocate MSI-X vectors: one per queue, configuration changes are not signalled.
+    /// Falls back to polling (no vector) when MSI-X is unavailable.
+    pub fn setup_msix(&mut self, queues: u16) {
+        let dev = &self.pci_device;
+        match pci::get_manager().lock().configure_msix(dev.bus, dev.device, dev.function, queues) {
+            Ok(vectors) => self.msix_vectors = vectors,
+            Err(e) => {
+                crate::serial::_print(format_args!("[VirtIO] MSI-X unavailable ({}), polling\n", e));
+                self.msix_vectors.clear();
+            }
+        }
+        self.write16(COMMON_MSIX_CONFIG, VIRTIO_MSI_NO_VECTOR);
+    }
+
+    /// Interrupt vector assigned to a queue, if MSI-X is active
+    pub fn queue_vector(&self, index: u16) -> Option<u8> {
+        self.msix_vectors.get(index as usize).copied()
+    }
+
+    pub fn num_queues(&self) -> u16 {
+        self.read16(COMMON_NUM_QUEUES)
+    }
+
+    /// Create and enable a split virtqueue
+    pub fn setup_queue(&self, index: u16) -> DeviceResult<Virtqueue> {
+        self.write16(COMMON_QUEUE_SELECT, index);
+        let device_size = self.read16(COMMON_QUEUE_SIZE);
+        if device_size == 0 {
+            return Err(DeviceError::NotFound);
+        }
+
+        let size = device_size.min(MAX_QUEUE_SIZE);
+        let notify_offset = self.read16(COMMON_QUEUE_NOTIFY_OFF);
+        let queue = Virtqueue::new(index, size, notify_offset)?;
+
+        self.write16(COMMON_QUEUE_SIZE, size);
+        let vector = if self.msix_vectors.len() > index as usize { index } else { VIRTIO_MSI_NO_VECTOR };
+        self.write16(COMMON_QUEUE_MSIX_VECTOR, vector);
+        self.write64(COMMON_QUEUE_DESC, queue.desc_phys());
+        self.write64(COMMON_QUEUE_DRIVER, queue.avail_phys());
+        self.write64(COMMON_QUEUE_DEVICE, queue.used_phys());
+        self.write16(COMMON_QUEUE_ENABLE, 1);
+
+        Ok(queue)
+    }
+
+    /// Tell the device the driver is fully set up
+    pub fn driver_ok(&self) {
+        let status = self.status() | STATUS_DRIVER_OK;
+        self.write8(COMMON_DEVICE_STATUS, status);
+    }
+
+    /// Ring the queue's doorbell
+    pub fn notify(&self, queue: &Virtqueue) {
+        let addr = self.notify_base.as_u64() + queue.notify_offset as u64 * self.notify_multiplier as u64;
+        unsafe { core::ptr::write_volatile(addr as *mut u16, queue.index) }
+    }
+
+    /// Read (and thereby acknowledge) the legacy interrupt status
+    pub fn read_isr(&self) -> u8 {
+        unsafe { core::ptr::read_volatile(self.isr.as_ptr::<u8>()) }
+    }
+
+    /// Poll a queue until a chain completes or the spin budget runs out
+    pub fn wait_used(&self, queue: &mut Virtqueue) -> DeviceResult<(u16, u32)> {
+        let mut timeout = COMPLETION_TIMEOUT_SPINS;
+        loop {
+            if let Some(used) = queue.pop_used() {
+                return Ok(used);
+            }
+            if timeout == 0 {
+                return Err(DeviceError::Timeout);
+            }
+            timeout -= 1;
+            core::hint::spin_loop();
+        }
+    }
+}
+
+pub(super) fn is_virtio(device: &BusDevice, ids: &[u16]) -> bool {
+    matches!(device, BusDevice::Pci(d) if d.vendor_id == VIRTIO_VENDOR_ID && ids.contains(&d.device_id))
+}
+
+pub(super) fn pci_of(device: &BusDevice) -> DeviceResult<&PciDevice> {
+    match device {
+        BusDevice::Pci(pci) => Ok(pci),
+        _ => Err(DeviceError::InvalidParameter),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// virtio-blk
+// ---------------------------------------------------------------------------
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_S_OK: u8 = 0;
+const VIRTIO_BLK_SECTOR_SIZE: usize = 512;
+
+/// Bounce page layout: request header, status byte, then one sector of data
+const BLK_HEADER_OFFSET: u64 = 0;
+const BLK_STATUS_OFFSET: u64 = 16;
+const BLK_DATA_OFFSET: u64 = 512;
+
+/// virtio-blk request header
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+struct VirtioBlkReqHeader {
+    req_type: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+/// An attached virtio-blk disk
+#[derive(Debug)]
+pub struct VirtioBlk {
+    transport: VirtioTransport,
+    queue: Virtqueue,
+    capacity: u64,
+    bounce: PhysFrame,
+}
+
+impl VirtioBlk {
+    fn new(pci_device: &PciDevice) -> DeviceResult<Self> {
+        let mut transport = VirtioTransport::new(pci_device)?;
+        transport.negotiate(0)?;
+        transport.setup_msix(1);
+        let queue = transport.setup_queue(0)?;
+        let Some(bounce) = crate::memory::allocate_frame() else {
+            // The device knows the queue's frames until it is reset
+            transport.reset();
+            queue.release();
+            return Err(DeviceError::OutOfMemory);
+        };
+        transport.driver_ok();
+
+        // capacity (u64, in 512-byte sectors) is the first device config field
+        let capacity = transport.config_read64(0);
+        crate::serial::_print(format_args!(
+            "[VirtIO] virtio-blk ready: {} sectors, queue size {}\n", capacity, queue.size()
+        ));
+
+        Ok(Self { transport, queue, capacity, bounce })
+    }
+
+    fn bounce_virt(&self, offset: u64) -> VirtAddr {
+        crate::memory::phys_to_virt(self.bounce.start_address() + offset)
+    }
+
+    /// Submit a single-sector request through the bounce page and wait for it
+    fn transfer_sector(&mut self, req_type: u32, sector: u64) -> DeviceResult<()> {
+        if sector >= self.capacity {
+            return Err(DeviceError::InvalidParameter);
+        }
+
+        let header = VirtioBlkReqHeader { req_type, reserved: 0, sector };
+        unsafe {
+            core::ptr::write_volatile(self.bounce_virt(BLK_HEADER_OFFSET).as_mut_ptr::<VirtioBlkReqHeader>(), header);
+            core::ptr::write_volatile(self.bounce_virt(BLK_STATUS_OFFSET).as_mut_ptr::<u8>(), 0xFF);
+        }
+
+        let base = self.bounce.start_address();
+        self.queue.add(&[
+            VirtqBuffer { addr: base + BLK_HEADER_OFFSET, len: 16, device_writable: false },
+            VirtqBuffer {
+                addr: base + BLK_DATA_OFFSET,
+                len: VIRTIO_BLK_SECTOR_SIZE as u32,
+                device_writable: req_type == VIRTIO_BLK_T_IN,
+            },
+            VirtqBuffer { addr: base + BLK_STATUS_OFFSET, len: 1, device_writable: true },
+        ])?;
+        self.transport.notify(&self.queue);
+        self.transport.wait_used(&mut self.queue)?;
+
+        let status = unsafe { core::ptr::read_volatile(self.bounce_virt(BLK_STATUS_OFFSET).as_ptr::<u8>()) };
+        if status != VIRTIO_BLK_S_OK {
+            return Err(DeviceError::IoError);
+        }
+        Ok(())
+    }
+
+    fn shutdown(self) {
+        self.transport.reset();
+        self.queue.release();
+        crate::memory::deallocate_frame(self.bounce);
+    }
+}
+
+impl BlockDevice for VirtioBlk {
+    fn block_size(&self) -> usize {
+        VIRTIO_BLK_SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.capacity
+    }
+
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> DeviceResult<()> {
+        if buffer.len() % VIRTIO_BLK_SECTOR_SIZE != 0 {
+            return Err(DeviceError::InvalidParameter);
+        }
+        for (i, chunk) in buffer.chunks_mut(VIRTIO_BLK_SECTOR_SIZE).enumerate() {
+            self.transfer_sector(VIRTIO_BLK_T_IN, lba + i as u64)?;
+            let data = self.bounce_virt(BLK_DATA_OFFSET);
+            unsafe {
+                core::ptr::copy_nonoverlapping(data.as_ptr::<u8>(), chunk.as_mut_ptr(), VIRTIO_BLK_SECTOR_SIZE);
+            }
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buffer: &[u8]) -> DeviceResult<()> {
+        if buffer.len() % VIRTIO_BLK_SECTOR_SIZE != 0 {
+            return Err(DeviceError::InvalidParameter);
+        }
+        for (i, chunk) in buffer.chunks(VIRTIO_BLK_SECTOR_SIZE).enumerate() {
+            let data = self.bounce_virt(BLK_DATA_OFFSET);
+            unsafe {
+                core::ptr::copy_nonoverlapping(chunk.as_ptr(), data.as_mut_ptr::<u8>(), VIRTIO_BLK_SECTOR_SIZE);
+            }
+            self.transfer_sector(VIRTIO_BLK_T_OUT, lba + i as u64)?;
+        }
+        Ok(())
+    }
+}
+
+static BLOCK_DEVICES: Mutex<Vec<Arc<Mutex<VirtioBlk>>>> = Mutex::new(Vec::new());
+
+/// Attached virtio-blk disks, in attach order
+pub fn block_devices() -> Vec<Arc<Mutex<VirtioBlk>>> {
+    BLOCK_DEVICES.lock().clone()
+}
+
+/// Driver-model binding for virtio-blk
+pub struct VirtioBlkDriver {
+    device: Option<Arc<Mutex<VirtioBlk>>>,
+}
+
+impl VirtioBlkDriver {
+    pub fn new() -> Self {
+        Self { device: None }
+    }
+}
+
+impl Driver for VirtioBlkDriver {
+    fn name(&self) -> &str {
+        "virtio-blk"
+    }
+
+    fn init_order(&self) -> u32 {
+        50
+    }
+
+    fn probe(&self, device: &BusDevice) -> bool {
+        is_virtio(device, &VIRTIO_BLK_IDS)
+    }
+
+    fn attach(&mut self, device: &BusDevice) -> DeviceResult<()> {
+        let blk = Arc::new(Mutex::new(VirtioBlk::new(pci_of(device)?)?));
+        BLOCK_DEVICES.lock().push(blk.clone());
+        self.device = Some(blk);
+        Ok(())
+    }
+
+    fn detach(&mut self) {
+        if let Some(blk) = self.device.take() {
+            BLOCK_DEVICES.lock().retain(|d| !Arc::ptr_eq(d, &blk));
+            if let Ok(mutex) = Arc::try_unwrap(blk) {
+                mutex.into_inner().shutdown();
+            }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// virtio-net
+// ---------------------------------------------------------------------------
+
+const VIRTIO_NET_RX_QUEUE: u16 = 0;
+const VIRTIO_NET_TX_QUEUE: u16 = 1;
+
+/// virtio_net_hdr with num_buffers (always present with VERSION_1)
+const VIRTIO_NET_HDR_LEN: usize = 12;
+const VIRTIO_NET_BUFFER_LEN: usize = 2048;
+const VIRTIO_NET_MAX_FRAME: usize = 1514;
+const VIRTIO_NET_RX_FRAMES: usize = 8;
+const VIRTIO_NET_RX_BACKLOG: usize = 64;
+
+/// Which way a frame handed to the tap handler crossed its interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDirection {
+    Received,
+    Sent,
+}
+
+/// Handler given a copy of each raw Ethernet frame an interface receives or sends, which way
+/// it went and the device it crossed, such as the network layer's packet capture
+pub type FrameHandler = fn(&Arc<Mutex<VirtioNet>>, FrameDirection, &[u8]);
+
+static TAP_HANDLER: Mutex<Option<FrameHandler>> = Mutex::new(None);
+
+/// Have `poll_net` hand a copy of every frame received or sent to a handler. Received frames
+/// still queue for `receive_frame`, so whoever waits on the device sees them too
+pub fn set_tap_handler(handler: FrameHandler) {
+    *TAP_HANDLER.lock() = Some(handler);
+}
+
+/// Frames and bytes a virtio-net interface moved, Ethernet headers included
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetStatistics {
+    pub rx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_packets: u64,
+    pub tx_bytes: u64,
+}
+
+/// An attached virtio-net interface
+#[derive(Debug)]
+pub struct VirtioNet {
+    transport: VirtioTransport,
+    rx: Virtqueue,
+    tx: Virtqueue,
+    mac: [u8; 6],
+    rx_frames: Vec<PhysFrame>,
+    rx_buffers: Vec<Option<PhysAddr>>, // indexed by head descriptor
+    tx_frame: PhysFrame,
+    backlog: VecDeque<Vec<u8>>,
+    /// Copies of frames received and sent, in order, for the tap handler, which runs without
+    /// the device locked
+    tapped: VecDeque<(FrameDirection, Vec<u8>)>,
+    statistics: NetStatistics,
+}
+
+impl VirtioNet {
+    fn new(pci_device: &PciDevice) -> DeviceResult<Self> {
+        let mut transport = VirtioTransport::new(pci_device)?;
+        let features = transport.negotiate(VIRTIO_NET_F_MAC)?;
+        transport.setup_msix(2);
+        let rx = transport.setup_queue(VIRTIO_NET_RX_QUEUE)?;
+        // The device knows the queues' frames until it is reset
+        let tx = match transport.setup_queue(VIRTIO_NET_TX_QUEUE) {
+            Ok(tx) => tx,
+            Err(e) => {
+                transport.reset();
+                rx.release();
+                return Err(e);
+            }
+        };
+        let Some(tx_frame) = crate::memory::allocate_frame() else {
+            transport.reset();
+            rx.release();
+            tx.release();
+            return Err(DeviceError::OutOfMemory);
+        };
+
+        let mut mac = [0u8; 6];
+        if (features & VIRTIO_NET_F_MAC) != 0 {
+            for (i, byte) in mac.iter_mut().enumerate() {
+                *byte = transport.config_read8(i as u64);
+            }
+        }
+
+        let rx_size = rx.size() as usize;
+        let mut net = Self {
+            transport,
+            rx,
+            tx,
+            mac,
+            rx_frames: Vec::new(),
+            rx_buffers: alloc::vec![None; rx_size],
+            tx_frame,
+            backlog: VecDeque::new(),
+            tapped: VecDeque::new(),
+            statistics: NetStatistics::default(),
+        };
+        if let Err(e) = net.fill_rx() {
+            net.shutdown();
+            return Err(e);
+        }
+        net.transport.driver_ok();
+        net.transport.notify(&net.rx);
+
+        crate::serial::_print(format_args!(
+            "[VirtIO] virtio-net ready: MAC {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}\n",
+            mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+        ));
+        Ok(net)
+    }
+
+    /// Post receive buffers (two per page) until the RX queue is full
+    fn fill_rx(&mut self) -> DeviceResult<()> {
+        let per_frame = 4096 / VIRTIO_NET_BUFFER_LEN;
+        while self.rx_frames.len() < VIRTIO_NET_RX_FRAMES && self.rx.num_free() as usize >= per_frame {
+            let frame = crate::memory::allocate_frame().ok_or(DeviceError::OutOfMemory)?;
+            self.rx_frames.push(frame);
+            for i in 0..per_frame {
+                let addr = frame.start_address() + (i * VIRTIO_NET_BUFFER_LEN) as u64;
+                self.post_rx(addr)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn post_rx(&mut self, addr: PhysAddr) -> DeviceResult<()> {
+        let head = self.rx.add(&[VirtqBuffer {
+            addr,
+            len: VIRTIO_NET_BUFFER_LEN as u32,
+            device_writable: true,
+        }])?;
+        self.rx_buffers[head as usize] = Some(addr);
+        Ok(())
+    }
+
+    pub fn mac_address(&self) -> [u8; 6] {
+        self.mac
+    }
+
+    pub fn statistics(&self) -> NetStatistics {
+        self.statistics
+    }
+
+    /// Transmit one Ethernet frame and wait for the device to consume it
+    pub fn send_frame(&mut self, frame: &[u8]) -> DeviceResult<()> {
+        if frame.len() > VIRTIO_NET_MAX_FRAME {
+            return Err(DeviceError::InvalidParameter);
+        }
+
+        let base = crate::memory::phys_to_virt(self.tx_frame.start_address());
+        unsafe {
+            core::ptr::write_bytes(base.as_mut_ptr::<u8>(), 0, VIRTIO_NET_HDR_LEN);
+            core::ptr::copy_nonoverlapping(
+                frame.as_ptr(),
+                base.as_mut_ptr::<u8>().add(VIRTIO_NET_HDR_LEN),
+                frame.len(),
+            );
+        }
+
+        self.tx.add(&[VirtqBuffer {
+            addr: self.tx_frame.start_address(),
+            len: (VIRTIO_NET_HDR_LEN + frame.len()) as u32,
+            device_writable: false,
+        }])?;
+        self.transport.notify(&self.tx);
+        self.transport.wait_used(&mut self.tx)?;
+        self.statistics.tx_packets += 1;
+        self.statistics.tx_bytes += frame.len() as u64;
+        if TAP_HANDLER.lock().is_some() {
+            self.tee(FrameDirection::Sent, frame);
+        }
+        Ok(())
+    }
+
+    /// Keep a copy of a frame for the tap handler, dropping the oldest beyond the backlog
+    fn tee(&mut self, direction: FrameDirection, frame: &[u8]) {
+        if self.tapped.len() >= VIRTIO_NET_RX_BACKLOG {
+            self.tapped.pop_front();
+        }
+        self.tapped.push_back((direction, frame.to_vec()));
+    }
+
+    /// Drain completed receive buffers into the backlog, and copies of them for the tap
+    /// handler when one is registered, and repost the buffers
+    pub fn poll(&mut self) -> usize {
+        let tapping = TAP_HANDLER.lock().is_some();
+        let mut received = 0;
+
+        while let Some((head, len)) = self.rx.pop_used() {
+            let addr = match self.rx_buffers.get_mut(head as usize).and_then(|b| b.take()) {
+                Some(addr) => addr,
+                None => continue,
+            };
+
+            let len = (len as usize).min(VIRTIO_NET_BUFFER_LEN);
+            if len > VIRTIO_NET_HDR_LEN {
+                let virt = crate::memory::phys_to_virt(addr);
+                let payload = unsafe {
+                    core::slice::from_raw_parts(virt.as_ptr::<u8>().add(VIRTIO_NET_HDR_LEN), len - VIRTIO_NET_HDR_LEN)
+                };
+                self.statistics.rx_packets += 1;
+                self.statistics.rx_bytes += payload.len() as u64;
+                if tapping {
+                    self.tee(FrameDirection::Received, payload);
+                }
+                if self.backlog.len() >= VIRTIO_NET_RX_BACKLOG {
+                    self.backlog.pop_front();
+                }
+                self.backlog.push_back(payload.to_vec());
+                received += 1;
+            }
+
+            if self.post_rx(addr).is_err() {
+                break;
+            }
+        }
+
+        if received > 0 {
+            self.transport.notify(&self.rx);
+        }
+        received
+    }
+
+    /// Pop a received frame from the backlog
+    pub fn receive_frame(&mut self) -> Option<Vec<u8>> {
+        self.poll();
+        self.backlog.pop_front()
+    }
+
+    fn shutdown(self) {
+        self.transport.reset();
+        self.rx.release();
+        self.tx.release();
+        for frame in self.rx_frames {
+            crate::memory::deallocate_frame(frame);
+        }
+        crate::memory::deallocate_frame(self.tx_frame);
+    }
+}
+
+static NET_DEVICES: Mutex<Vec<Arc<Mutex<VirtioNet>>>> = Mutex::new(Vec::new());
+
+/// Attached virtio-net interfaces, in attach order
+pub fn net_devices() -> Vec<Arc<Mutex<VirtioNet>>> {
+    NET_DEVICES.lock().clone()
+}
+
+/// Poll every attached virtio-net interface for received frames, then hand the tap handler
+/// the frames received and sent since it last ran, with no device locked
+pub fn poll_net() -> usize {
+    let handler = *TAP_HANDLER.lock();
+    let mut received = 0;
+    for net in net_devices() {
+        let tapped = {
+            let mut device = net.lock();
+            received += device.poll();
+            core::mem::take(&mut device.tapped)
+        };
+        if let Some(handler) = handler {
+            for (direction, frame) in &tapped {
+                handler(&net, *direction, frame);
+            }
+        }
+    }
+    received
+}
+
+/// Driver-model binding for virtio-net
+pub struct VirtioNetDriver {
+    device: Option<Arc<Mutex<VirtioNet>>>,
+}
+
+impl VirtioNetDriver {
+    pub fn new() -> Self {
+        Self { device: None }
+    }
+}
+
+impl Driver for VirtioNetDriver {
+    fn name(&self) -> &str {
+        "virtio-net"
+    }
+
+    fn init_order(&self) -> u32 {
+        60
+    }
+
+    fn probe(&self, device: &BusDevice) -> bool {
+        is_virtio(device, &VIRTIO_NET_IDS)
+    }
+
+    fn attach(&mut self, device: &BusDevice) -> DeviceResult<()> {
+        let net = Arc::new(Mutex::new(VirtioNet::new(pci_of(device)?)?));
+        NET_DEVICES.lock().push(net.clone());
+        self.device = Some(net);
+        Ok(())
+    }
+
+    fn detach(&mut self) {
+        if let Some(net) = self.device.take() {
+            NET_DEVICES.lock().retain(|d| !Arc::ptr_eq(d, &net));
+            if let Ok(mutex) = Arc::try_unwrap(net) {
+                mutex.into_inner().shutdown();
+            }
+        }
+    }
+}
+
+/// Register the virtio drivers with the driver model.
+/// Several instances are registered so multiple disks/NICs can bind.
+pub fn register_drivers() {
+    for _ in 0..4 {
+        super::register_driver(alloc::boxed::Box::new(VirtioBlkDriver::new()));
+        super::register_driver(alloc::boxed::Box::new(VirtioNetDriver::new()));
+    }
+}
@@ -0,0 +1,543 @@
+//! VirtIO GPU driver for RaeenOS
+//! Creates a 2D host resource backed by guest memory, scans it out, and presents through
+//! transfer + flush so the framebuffer compositor can render into it like a linear framebuffer
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use spin::Mutex;
+use x86_64::structures::paging::PhysFrame;
+use x86_64::VirtAddr;
+
+use super::virtio::{self, VirtioTransport, VirtqBuffer, Virtqueue};
+use super::{BusDevice, DeviceError, DeviceResult, Driver};
+use crate::graphics::{FramebufferBackend, Rect};
+use crate::pci::PciDevice;
+
+/// PCI device ID of virtio-gpu (modern only)
+const VIRTIO_GPU_IDS: [u16; 1] = [0x1050];
+
+const VIRTIO_GPU_CONTROL_QUEUE: u16 = 0;
+
+/// Control commands
+const VIRTIO_GPU_CMD_GET_DISPLAY_INFO: u32 = 0x0100;
+const VIRTIO_GPU_CMD_RESOURCE_CREATE_2D: u32 = 0x0101;
+const VIRTIO_GPU_CMD_RESOURCE_UNREF: u32 = 0x0102;
+const VIRTIO_GPU_CMD_SET_SCANOUT: u32 = 0x0103;
+const VIRTIO_GPU_CMD_RESOURCE_FLUSH: u32 = 0x0104;
+const VIRTIO_GPU_CMD_TRANSFER_TO_HOST_2D: u32 = 0x0105;
+const VIRTIO_GPU_CMD_RESOURCE_ATTACH_BACKING: u32 = 0x0106;
+const VIRTIO_GPU_CMD_RESOURCE_DETACH_BACKING: u32 = 0x0107;
+
+/// Responses
+const VIRTIO_GPU_RESP_OK_NODATA: u32 = 0x1100;
+const VIRTIO_GPU_RESP_OK_DISPLAY_INFO: u32 = 0x1101;
+
+/// B8G8R8X8 matches the compositor's 0x00RRGGBB little-endian pixels
+const VIRTIO_GPU_FORMAT_B8G8R8X8_UNORM: u32 = 2;
+
+const VIRTIO_GPU_MAX_SCANOUTS: usize = 16;
+const VIRTIO_GPU_EVENT_DISPLAY: u32 = 1;
+
+/// Device configuration offsets
+const CONFIG_EVENTS_READ: u64 = 0;
+const CONFIG_EVENTS_CLEAR: u64 = 4;
+
+/// Command page layout: request at the start, response in the upper half
+const RESPONSE_OFFSET: u64 = 2048;
+
+const SCANOUT_ID: u32 = 0;
+const RESOURCE_ID: u32 = 1;
+
+/// Used when the host reports no enabled display
+const FALLBACK_WIDTH: u32 = 1024;
+const FALLBACK_HEIGHT: u32 = 768;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+#[allow(dead_code)]
+struct GpuCtrlHeader {
+    cmd_type: u32,
+    flags: u32,
+    fence_id: u64,
+    ctx_id: u32,
+    ring_idx: u8,
+    padding: [u8; 3],
+}
+
+impl GpuCtrlHeader {
+    fn command(cmd_type: u32) -> Self {
+        Self { cmd_type, ..Default::default() }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct GpuRect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct GpuDisplayOne {
+    rect: GpuRect,
+    enabled: u32,
+    flags: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct GpuRespDisplayInfo {
+    header: GpuCtrlHeader,
+    pmodes: [GpuDisplayOne; VIRTIO_GPU_MAX_SCANOUTS],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+struct GpuResourceCreate2d {
+    header: GpuCtrlHeader,
+    resource_id: u32,
+    format: u32,
+    width: u32,
+    height: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+struct GpuResourceUnref {
+    header: GpuCtrlHeader,
+    resource_id: u32,
+    padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+struct GpuSetScanout {
+    header: GpuCtrlHeader,
+    rect: GpuRect,
+    scanout_id: u32,
+    resource_id: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+struct GpuResourceFlush {
+    header: GpuCtrlHeader,
+    rect: GpuRect,
+    resource_id: u32,
+    padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+struct GpuTransferToHost2d {
+    header: GpuCtrlHeader,
+    rect: GpuRect,
+    offset: u64,
+    resource_id: u32,
+    padding: u32,
+}
+
+/// Attach-backing request with a single contiguous memory entry
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+struct GpuResourceAttachBacking {
+    header: GpuCtrlHeader,
+    resource_id: u32,
+    nr_entries: u32,
+    addr: u64,
+    length: u32,
+    padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+struct GpuResourceDetachBacking {
+    header: GpuCtrlHeader,
+    resource_id: u32,
+    padding: u32,
+}
+
+/// Guest memory backing the scanout resource
+#[derive(Debug, Clone, Copy)]
+pub struct GpuFramebuffer {
+    pub address: VirtAddr,
+    pub width: u32,
+    pub height: u32,
+    pub pitch: u32,
+}
+
+#[derive(Debug)]
+struct Backing {
+    first_frame: PhysFrame,
+    frame_count: usize,
+}
+
+/// An attached virtio-gpu device with one scanout
+#[derive(Debug)]
+pub struct VirtioGpu {
+    transport: VirtioTransport,
+    control: Virtqueue,
+    command_page: PhysFrame,
+    width: u32,
+    height: u32,
+    backing: Option<Backing>,
+}
+
+impl VirtioGpu {
+    fn new(pci_device: &PciDevice) -> DeviceResult<Self> {
+        let mut transport = VirtioTransport::new(pci_device)?;
+        transport.negotiate(0)?;
+        transport.setup_msix(1);
+        let control = transport.setup_queue(VIRTIO_GPU_CONTROL_QUEUE)?;
+        let command_page = crate::memory::allocate_frame().ok_or(DeviceError::OutOfMemory)?;
+        transport.driver_ok();
+
+        let mut gpu = Self {
+            transport,
+            control,
+            command_page,
+            width: 0,
+            height: 0,
+            backing: None,
+        };
+
+        let (width, height) = gpu.query_display_info()?.unwrap_or((FALLBACK_WIDTH, FALLBACK_HEIGHT));
+        gpu.create_scanout(width, height)?;
+
+        crate::serial::_print(format_args!("[VirtIO] virtio-gpu ready: {}x{} scanout\n", width, height));
+        Ok(gpu)
+    }
+
+    /// Send a request and wait for the response, returning the response header type.
+    /// The full response is left in the upper half of the command page.
+    fn command<T: Copy>(&mut self, request: &T, response_len: usize) -> DeviceResult<u32> {
+        let page = self.command_page.start_address();
+        let virt = crate::memory::phys_to_virt(page);
+        unsafe {
+            core::ptr::write_volatile(virt.as_mut_ptr::<T>(), *request);
+            core::ptr::write_bytes((virt + RESPONSE_OFFSET).as_mut_ptr::<u8>(), 0, response_len);
+        }
+
+        self.control.add(&[
+            VirtqBuffer { addr: page, len: core::mem::size_of::<T>() as u32, device_writable: false },
+            VirtqBuffer { addr: page + RESPONSE_OFFSET, len: response_len as u32, device_writable: true },
+        ])?;
+        self.transport.notify(&self.control);
+        self.transport.wait_used(&mut self.control)?;
+
+        let header = unsafe { core::ptr::read_volatile((virt + RESPONSE_OFFSET).as_ptr::<GpuCtrlHeader>()) };
+        Ok(header.cmd_type)
+    }
+
+    fn command_nodata<T: Copy>(&mut self, request: &T) -> DeviceResult<()> {
+        match self.command(request, core::mem::size_of::<GpuCtrlHeader>())? {
+            VIRTIO_GPU_RESP_OK_NODATA => Ok(()),
+            _ => Err(DeviceError::HardwareError),
+        }
+    }
+
+    /// Preferred resolution of our scanout, if the host reports it enabled
+    fn query_display_info(&mut self) -> DeviceResult<Option<(u32, u32)>> {
+        let request = GpuCtrlHeader::command(VIRTIO_GPU_CMD_GET_DISPLAY_INFO);
+        let response_type = self.command(&request, core::mem::size_of::<GpuRespDisplayInfo>())?;
+        if response_type != VIRTIO_GPU_RESP_OK_DISPLAY_INFO {
+            return Err(DeviceError::HardwareError);
+        }
+
+        let virt = crate::memory::phys_to_virt(self.command_page.start_address() + RESPONSE_OFFSET);
+        let info = unsafe { core::ptr::read_volatile(virt.as_ptr::<GpuRespDisplayInfo>()) };
+        let mode = info.pmodes[SCANOUT_ID as usize];
+        if mode.enabled != 0 && mode.rect.width > 0 && mode.rect.height > 0 {
+            Ok(Some((mode.rect.width, mode.rect.height)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Create the 2D resource, back it with contiguous guest memory and scan it out
+    fn create_scanout(&mut self, width: u32, height: u32) -> DeviceResult<()> {
+        let bytes = width as usize * height as usize * 4;
+        let frame_count = (bytes + 4095) / 4096;
+        let first_frame = crate::memory::allocate_contiguous_frames(frame_count).ok_or(DeviceError::OutOfMemory)?;
+        let backing = Backing { first_frame, frame_count };
+        unsafe {
+            core::ptr::write_bytes(crate::memory::phys_to_virt(first_frame.start_address()).as_mut_ptr::<u8>(), 0, bytes);
+        }
+
+        self.command_nodata(&GpuResourceCreate2d {
+            header: GpuCtrlHeader::command(VIRTIO_GPU_CMD_RESOURCE_CREATE_2D),
+            resource_id: RESOURCE_ID,
+            format: VIRTIO_GPU_FORMAT_B8G8R8X8_UNORM,
+            width,
+            height,
+        })?;
+        self.command_nodata(&GpuResourceAttachBacking {
+            header: GpuCtrlHeader::command(VIRTIO_GPU_CMD_RESOURCE_ATTACH_BACKING),
+            resource_id: RESOURCE_ID,
+            nr_entries: 1,
+            addr: first_frame.start_address().as_u64(),
+            length: bytes as u32,
+            padding: 0,
+        })?;
+        self.command_nodata(&GpuSetScanout {
+            header: GpuCtrlHeader::command(VIRTIO_GPU_CMD_SET_SCANOUT),
+            rect: GpuRect { x: 0, y: 0, width, height },
+            scanout_id: SCANOUT_ID,
+            resource_id: RESOURCE_ID,
+        })?;
+
+        self.backing = Some(backing);
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
+    /// Disable the scanout and release the resource and its backing memory
+    fn destroy_scanout(&mut self) -> DeviceResult<()> {
+        let backing = match self.backing.take() {
+            Some(backing) => backing,
+            None => return Ok(()),
+        };
+
+        self.command_nodata(&GpuSetScanout {
+            header: GpuCtrlHeader::command(VIRTIO_GPU_CMD_SET_SCANOUT),
+            rect: GpuRect::default(),
+            scanout_id: SCANOUT_ID,
+            resource_id: 0,
+        })?;
+        self.command_nodata(&GpuResourceDetachBacking {
+            header: GpuCtrlHeader::command(VIRTIO_GPU_CMD_RESOURCE_DETACH_BACKING),
+            resource_id: RESOURCE_ID,
+            padding: 0,
+        })?;
+        self.command_nodata(&GpuResourceUnref {
+            header: GpuCtrlHeader::command(VIRTIO_GPU_CMD_RESOURCE_UNREF),
+            resource_id: RESOURCE_ID,
+            padding: 0,
+        })?;
+
+        for i in 0..backing.frame_count {
+            crate::memory::deallocate_frame(backing.first_frame + i as u64);
+        }
+        Ok(())
+    }
+
+    /// The guest framebuffer the compositor renders into
+    pub fn framebuffer(&self) -> Option<GpuFramebuffer> {
+        let backing = self.backing.as_ref()?;
+        Some(GpuFramebuffer {
+            address: crate::memory::phys_to_virt(backing.first_frame.start_address()),
+            width: self.width,
+            height: self.height,
+            pitch: self.width * 4,
+        })
+    }
+
+    /// Current scanout resolution
+    pub fn resolution(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Re-read the display info reported by the host
+    pub fn display_info(&mut self) -> DeviceResult<Option<(u32, u32)>> {
+        self.query_display_info()
+    }
+
+    /// Copy a region of guest memory to the host resource and flush it to the display
+    pub fn flush(&mut self, rect: Rect) -> DeviceResult<()> {
+        if self.backing.is_none() {
+            return Err(DeviceError::InvalidOperation);
+        }
+
+        // Clip to the resource
+        let x = rect.x.max(0) as u32;
+        let y = rect.y.max(0) as u32;
+        let right = ((rect.x + rect.width as i32).max(0) as u32).min(self.width);
+        let bottom = ((rect.y + rect.height as i32).max(0) as u32).min(self.height);
+        if x >= right || y >= bottom {
+            return Ok(());
+        }
+        let region = GpuRect { x, y, width: right - x, height: bottom - y };
+
+        self.command_nodata(&GpuTransferToHost2d {
+            header: GpuCtrlHeader::command(VIRTIO_GPU_CMD_TRANSFER_TO_HOST_2D),
+            rect: region,
+            offset: (y as u64 * self.width as u64 + x as u64) * 4,
+            resource_id: RESOURCE_ID,
+            padding: 0,
+        })?;
+        self.command_nodata(&GpuResourceFlush {
+            header: GpuCtrlHeader::command(VIRTIO_GPU_CMD_RESOURCE_FLUSH),
+            rect: region,
+            resource_id: RESOURCE_ID,
+            padding: 0,
+        })
+    }
+
+    /// Recreate the scanout at a new resolution
+    pub fn set_resolution(&mut self, width: u32, height: u32) -> DeviceResult<()> {
+        if width == 0 || height == 0 {
+            return Err(DeviceError::InvalidParameter);
+        }
+        if (width, height) == (self.width, self.height) && self.backing.is_some() {
+            return Ok(());
+        }
+        self.destroy_scanout()?;
+        self.create_scanout(width, height)
+    }
+
+    /// Check and acknowledge a pending display-change event.
+    /// Returns the new preferred resolution if the host changed it.
+    fn take_display_event(&mut self) -> DeviceResult<Option<(u32, u32)>> {
+        let events = self.transport.config_read32(CONFIG_EVENTS_READ);
+        if (events & VIRTIO_GPU_EVENT_DISPLAY) == 0 {
+            return Ok(None);
+        }
+        self.transport.config_write32(CONFIG_EVENTS_CLEAR, VIRTIO_GPU_EVENT_DISPLAY);
+
+        match self.query_display_info()? {
+            Some(size) if size != (self.width, self.height) => Ok(Some(size)),
+            _ => Ok(None),
+        }
+    }
+
+    fn shutdown(mut self) {
+        let _ = self.destroy_scanout();
+        self.transport.reset();
+        crate::memory::deallocate_frame(self.command_page);
+        self.control.release();
+    }
+}
+
+static PRIMARY_GPU: Mutex<Option<Arc<Mutex<VirtioGpu>>>> = Mutex::new(None);
+
+/// The first attached virtio-gpu, used as the compositor's display
+pub fn primary() -> Option<Arc<Mutex<VirtioGpu>>> {
+    PRIMARY_GPU.lock().clone()
+}
+
+/// Framebuffer of the primary virtio-gpu, if one is attached
+pub fn framebuffer() -> Option<GpuFramebuffer> {
+    primary()?.lock().framebuffer()
+}
+
+/// Compositor backend presenting through the primary virtio-gpu
+pub struct VirtioGpuBackend;
+
+impl FramebufferBackend for VirtioGpuBackend {
+    fn name(&self) -> &str {
+        "virtio-gpu"
+    }
+
+    fn flush(&mut self, rect: Rect) {
+        if let Some(gpu) = primary() {
+            let _ = gpu.lock().flush(rect);
+        }
+    }
+}
+
+/// Point the compositor at the primary virtio-gpu's framebuffer
+pub fn attach_compositor() -> Result<(), &'static str> {
+    let fb = framebuffer().ok_or("No virtio-gpu scanout available")?;
+    crate::graphics::init_framebuffer_compositor_with_backend(
+        fb.address,
+        fb.width,
+        fb.height,
+        fb.pitch,
+        32,
+        Box::new(VirtioGpuBackend),
+    )
+}
+
+/// Follow host-side display resizes: reallocate the scanout and
+/// re-target the compositor. Call from the main loop, not an ISR.
+pub fn process_display_events() {
+    let gpu = match primary() {
+        Some(gpu) => gpu,
+        None => return,
+    };
+
+    let resized = {
+        let mut gpu = gpu.lock();
+        match gpu.take_display_event() {
+            Ok(Some((width, height))) => match gpu.set_resolution(width, height) {
+                Ok(()) => true,
+                Err(e) => {
+                    crate::serial::_print(format_args!("[VirtIO] virtio-gpu resize failed: {}\n", e));
+                    false
+                }
+            },
+            _ => false,
+        }
+    };
+
+    if resized {
+        if let Err(e) = attach_compositor() {
+            crate::serial::_print(format_args!("[VirtIO] Failed to re-attach compositor: {}\n", e));
+        }
+    }
+}
+
+/// Driver-model binding for virtio-gpu
+pub struct VirtioGpuDriver {
+    device: Option<Arc<Mutex<VirtioGpu>>>,
+}
+
+impl VirtioGpuDriver {
+    pub fn new() -> Self {
+        Self { device: None }
+    }
+}
+
+impl Driver for VirtioGpuDriver {
+    fn name(&self) -> &str {
+        "virtio-gpu"
+    }
+
+    fn init_order(&self) -> u32 {
+        40
+    }
+
+    fn probe(&self, device: &BusDevice) -> bool {
+        virtio::is_virtio(device, &VIRTIO_GPU_IDS)
+    }
+
+    fn attach(&mut self, device: &BusDevice) -> DeviceResult<()> {
+        let gpu = Arc::new(Mutex::new(VirtioGpu::new(virtio::pci_of(device)?)?));
+        let mut primary = PRIMARY_GPU.lock();
+        if primary.is_none() {
+            *primary = Some(gpu.clone());
+        }
+        self.device = Some(gpu);
+        Ok(())
+    }
+
+    fn detach(&mut self) {
+        if let Some(gpu) = self.device.take() {
+            {
+                let mut primary = PRIMARY_GPU.lock();
+                if primary.as_ref().map_or(false, |p| Arc::ptr_eq(p, &gpu)) {
+                    *primary = None;
+                }
+            }
+            if let Ok(mutex) = Arc::try_unwrap(gpu) {
+                mutex.into_inner().shutdown();
+            }
+        }
+    }
+}
+
+/// Register the virtio-gpu driver with the driver model
+pub fn register_driver() {
+    super::register_driver(Box::new(VirtioGpuDriver::new()));
+}
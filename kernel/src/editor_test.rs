@@ -0,0 +1,210 @@
+//! Line Editor Test
+//! Types key sequences, as a terminal in raw mode sends them, into the shell's line editor and
+//! checks the edited line, the cursor and what the editor's output leaves on a terminal:
+//! motion and killing by word, history browsing and incremental search, multi-byte UTF-8
+//! characters edited as one, completion, and lines wrapped over several rows
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::raekit::terminal::Terminal;
+use crate::raeshell::editor::{EditResult, LineEditor};
+use crate::serial::_print;
+
+/// Feed `input` to the editor and show its output on `terminal`, returning the last result
+fn type_keys(editor: &mut LineEditor, terminal: &mut Terminal, input: &[u8]) -> Option<EditResult> {
+    let mut result = None;
+    for &byte in input {
+        if let Some(ended) = editor.feed_byte(byte) {
+            result = Some(ended);
+        }
+    }
+    terminal.write(&editor.take_output());
+    result
+}
+
+/// Completes the last word from a few command names
+fn complete_word(line: &str) -> Vec<String> {
+    let word = line.rsplit(' ').next().unwrap_or(line);
+    ["help", "history", "hostname"].into_iter().filter(|name| name.starts_with(word)).map(String::from).collect()
+}
+
+pub fn run_editor_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Editor Test] Starting line editor tests...\n"));
+
+    // Test 1: Word motion stops at word boundaries and kills gather into the kill buffer
+    _print(format_args!("[Editor Test] Test 1: Word motion and kill...\n"));
+    let mut terminal = Terminal::new(40, 3);
+    let mut editor = LineEditor::new(40);
+    editor.start("$ ");
+    type_keys(&mut editor, &mut terminal, b"git commit --amend -m fix");
+    // Ctrl-Left twice, then Alt-b over the dashes
+    let mut stops = Vec::new();
+    for keys in [&b"\x1b[1;5D"[..], b"\x1b[1;5D", b"\x1bb", b"\x1bf", b"\x1b[1;5C"] {
+        type_keys(&mut editor, &mut terminal, keys);
+        stops.push(editor.cursor());
+    }
+    if stops != [22, 20, 13, 18, 21] {
+        return Err("Word motion stopped off word boundaries");
+    }
+    // Alt-Backspace kills the word "m", Ctrl-W the dash before it up to the space
+    type_keys(&mut editor, &mut terminal, b"\x1b\x7f\x17");
+    if editor.line() != "git commit --amend  fix" || editor.cursor() != 19 {
+        return Err("Word kill removed the wrong text");
+    }
+    // Consecutive kills yank back together
+    type_keys(&mut editor, &mut terminal, b"\x19");
+    if editor.line() != "git commit --amend -m fix" || editor.cursor() != 21 {
+        return Err("Consecutive kills not yanked as one");
+    }
+    // Alt-d kills the word ahead, and Ctrl-K following it appends the rest
+    type_keys(&mut editor, &mut terminal, b"\x01\x1bd");
+    if editor.line() != " commit --amend -m fix" {
+        return Err("Alt-d removed the wrong text");
+    }
+    type_keys(&mut editor, &mut terminal, b"\x0b\x19");
+    if editor.line() != "git commit --amend -m fix" {
+        return Err("Forward kills not appended");
+    }
+    if terminal.row_text(0) != "$ git commit --amend -m fix" || terminal.cursor() != Some((0, 27)) {
+        return Err("Edited line drawn wrong");
+    }
+    _print(format_args!("[Editor Test] ✓ Words moved over and killed on their boundaries\n"));
+
+    // Test 2: Ctrl-R searches the history back from the newest entry
+    _print(format_args!("[Editor Test] Test 2: History search...\n"));
+    let mut terminal = Terminal::new(40, 3);
+    let mut editor = LineEditor::new(40);
+    editor.set_history(["ls /bin", "cat notes.txt", "echo hello", "cat readme"].into_iter().map(String::from).collect());
+    editor.start("$ ");
+    type_keys(&mut editor, &mut terminal, b"\x12cat");
+    if editor.line() != "cat readme" || editor.cursor() != 0 {
+        return Err("Search did not find the newest match");
+    }
+    // Ctrl-R again moves on to an older match
+    type_keys(&mut editor, &mut terminal, b"\x12");
+    if terminal.row_text(0) != "(reverse-i-search)`cat': cat notes.txt" || terminal.cursor() != Some((0, 25)) {
+        return Err("Search state drawn wrong");
+    }
+    if type_keys(&mut editor, &mut terminal, b"\r") != Some(EditResult::Line(String::from("cat notes.txt"))) {
+        return Err("Found entry not accepted by Enter");
+    }
+    if terminal.row_text(0) != "$ cat notes.txt" || terminal.cursor() != Some((1, 0)) {
+        return Err("Accepted line not redrawn at the prompt");
+    }
+    // Ctrl-G restores the line typed before the search, which a failed search keeps
+    editor.start("$ ");
+    type_keys(&mut editor, &mut terminal, b"xy\x12zz");
+    if terminal.row_text(1) != "(failed reverse-i-search)`zz': xy" {
+        return Err("Failed search not shown");
+    }
+    type_keys(&mut editor, &mut terminal, b"\x08\x08echo\x07");
+    if editor.line() != "xy" || editor.cursor() != 2 {
+        return Err("Aborted search did not restore the line");
+    }
+    // Another key ends the search and acts on the entry found
+    type_keys(&mut editor, &mut terminal, b"\x12notes\x1b[C");
+    if editor.line() != "cat notes.txt" || editor.cursor() != 5 {
+        return Err("Key after a search not applied to the match");
+    }
+    // Up and Down browse, returning to the typed line past the newest entry
+    editor.start("$ ");
+    let mut shown = Vec::new();
+    for keys in [&b"x\x1b[A"[..], b"\x1b[A", b"\x1b[B", b"\x1b[B", b"\x1b[B"] {
+        type_keys(&mut editor, &mut terminal, keys);
+        shown.push(editor.line());
+    }
+    if shown != ["cat readme", "echo hello", "cat readme", "x", "x"] {
+        return Err("History browsed out of order");
+    }
+    _print(format_args!("[Editor Test] ✓ Ctrl-R found matching history entries\n"));
+
+    // Test 3: Multi-byte characters are inserted, moved over and deleted whole
+    _print(format_args!("[Editor Test] Test 3: UTF-8 characters...\n"));
+    let mut terminal = Terminal::new(40, 3);
+    let mut editor = LineEditor::new(40);
+    editor.start("$ ");
+    for &byte in "naïve café".as_bytes() {
+        type_keys(&mut editor, &mut terminal, &[byte]);
+    }
+    if editor.line() != "naïve café" || editor.cursor() != 10 {
+        return Err("UTF-8 text not decoded into characters");
+    }
+    type_keys(&mut editor, &mut terminal, b"\x7f\x01\x1b[C\x1b[C\x1b[3~");
+    if editor.line() != "nave caf" || editor.cursor() != 2 {
+        return Err("Multi-byte characters not deleted whole");
+    }
+    // A character split across reads is inserted once complete
+    type_keys(&mut editor, &mut terminal, &[0xc3]);
+    if editor.line() != "nave caf" {
+        return Err("Partial character inserted");
+    }
+    type_keys(&mut editor, &mut terminal, &[0xb6]);
+    // Invalid bytes are dropped, and a character cut short gives way to what follows
+    type_keys(&mut editor, &mut terminal, &[0xff, 0x80, 0xe2, b'x']);
+    if editor.line() != "naöxve caf" || editor.cursor() != 4 {
+        return Err("Split or invalid UTF-8 edited wrong");
+    }
+    type_keys(&mut editor, &mut terminal, b"\x05\x1bb");
+    if terminal.row_text(0) != "$ naöxve caf" || terminal.cursor() != Some((0, 9)) {
+        return Err("Cursor misplaced over multi-byte characters");
+    }
+    _print(format_args!("[Editor Test] ✓ UTF-8 characters edited as single units\n"));
+
+    // Test 4: Tab completes through the hook, and long lines wrap over rows
+    _print(format_args!("[Editor Test] Test 4: Completion and wrapped lines...\n"));
+    let mut terminal = Terminal::new(40, 4);
+    let mut editor = LineEditor::new(40);
+    editor.set_completer(complete_word);
+    editor.start("$ ");
+    type_keys(&mut editor, &mut terminal, b"hi\t");
+    if editor.line() != "history " {
+        return Err("Single completion not applied");
+    }
+    // An ambiguous word completes nothing, and a second Tab lists the choices
+    type_keys(&mut editor, &mut terminal, b"\x15h\t");
+    if editor.line() != "h" || !terminal.row_text(1).is_empty() {
+        return Err("Ambiguous completion applied");
+    }
+    type_keys(&mut editor, &mut terminal, b"\t");
+    if terminal.row_text(1) != "help  history  hostname" || terminal.row_text(2) != "$ h" || terminal.cursor() != Some((2, 3)) {
+        return Err("Completions not listed");
+    }
+    type_keys(&mut editor, &mut terminal, b"o\t");
+    if editor.line() != "hostname " {
+        return Err("Narrowed completion not applied");
+    }
+    let mut terminal = Terminal::new(10, 4);
+    let mut editor = LineEditor::new(10);
+    editor.start("$ ");
+    type_keys(&mut editor, &mut terminal, b"abcdefghijklmn\x01X");
+    if terminal.row_text(0) != "$ Xabcdefg" || terminal.row_text(1) != "hijklmn" || terminal.cursor() != Some((0, 3)) {
+        return Err("Insertion not redrawn across rows");
+    }
+    // Filling the last row exactly puts the cursor on the next one
+    type_keys(&mut editor, &mut terminal, b"\x05opq");
+    if terminal.row_text(1) != "hijklmnopq" || terminal.cursor() != Some((2, 0)) {
+        return Err("Cursor held at the end of a full row");
+    }
+    type_keys(&mut editor, &mut terminal, b"\x7f");
+    if terminal.row_text(1) != "hijklmnop" || terminal.cursor() != Some((1, 9)) {
+        return Err("Wrapped line not redrawn from its first row");
+    }
+    _print(format_args!("[Editor Test] ✓ Words completed and wrapped lines redrawn\n"));
+
+    _print(format_args!("[Editor Test] ✓ All line editor tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for the line editor
+pub fn test_editor() {
+    _print(format_args!("[Editor Test] ===========================================\n"));
+    _print(format_args!("[Editor Test]             LINE EDITOR TESTS\n"));
+    _print(format_args!("[Editor Test] ===========================================\n"));
+
+    match run_editor_tests() {
+        Ok(_) => _print(format_args!("[Editor Test] ✓ All line editor tests PASSED\n")),
+        Err(e) => _print(format_args!("[Editor Test] ✗ Line editor tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Editor Test] ===========================================\n"));
+}
@@ -6,7 +6,6 @@
 use alloc::vec::Vec;
 
 use x86_64::VirtAddr;
-use x86_64::structures::paging::Mapper;
 use crate::vmm::{VmArea, VmAreaType, VmPermissions, VmError};
 
 /// ELF file header
@@ -48,6 +47,7 @@ const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
 const ET_EXEC: u16 = 2;        // Executable file
 const EM_X86_64: u16 = 62;     // AMD x86-64 architecture
 const PT_LOAD: u32 = 1;        // Loadable segment
+const PT_TLS: u32 = 7;         // Thread-local storage template
 const PF_X: u32 = 1;           // Execute permission
 const PF_W: u32 = 2;           // Write permission
 const PF_R: u32 = 4;           // Read permission
@@ -70,6 +70,88 @@ impl From<VmError> for ElfError {
     }
 }
 
+/// Bytes of thread control block above the thread pointer. Its first word points to
+/// itself, as `%fs:0` must, and the rest is left zeroed for what libc keeps there, such as
+/// the stack guard at `%fs:0x28`
+pub const TLS_TCB_SIZE: u64 = 64;
+
+/// Largest thread-local storage segment a program may have
+const MAX_TLS_SIZE: u64 = 0x10_0000;
+
+fn align_up(value: u64, align: u64) -> u64 {
+    (value + align - 1) & !(align - 1)
+}
+
+/// The initial thread-local storage of a program, from its PT_TLS segment, out of which
+/// each of its threads gets a block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsTemplate {
+    /// Initialized thread-locals, `.tdata`
+    pub init_image: Vec<u8>,
+    /// Size of all thread-locals, `.tdata` followed by the zeroed `.tbss`
+    pub mem_size: u64,
+    pub align: u64,
+}
+
+impl TlsTemplate {
+    /// Offset of the thread pointer into a block. x86-64 uses TLS variant II: the
+    /// thread-locals end at the thread pointer, so compiled code reaches them at negative
+    /// offsets from `%fs`, and the TCB starts there
+    pub fn thread_pointer_offset(&self) -> u64 {
+        align_up(align_up(self.mem_size, self.align), self.align.max(8))
+    }
+    
+    /// Contents of a new block whose thread pointer will be `thread_pointer`
+    pub fn block_image(&self, thread_pointer: u64) -> Vec<u8> {
+        let tp_offset = self.thread_pointer_offset() as usize;
+        let data_offset = tp_offset - align_up(self.mem_size, self.align) as usize;
+        let mut block = alloc::vec![0u8; tp_offset + TLS_TCB_SIZE as usize];
+        block[data_offset..data_offset + self.init_image.len()].copy_from_slice(&self.init_image);
+        block[tp_offset..tp_offset + 8].copy_from_slice(&thread_pointer.to_le_bytes());
+        block
+    }
+    
+    /// Allocate a block for a new thread in address space `address_space_id`, filled in from
+    /// the template, and return the thread pointer to load into its FS base
+    pub fn allocate_block(&self, address_space_id: u64) -> Result<VirtAddr, VmError> {
+        use crate::memory;
+        
+        let size = align_up(self.thread_pointer_offset() + TLS_TCB_SIZE, 4096);
+        let permissions = VmPermissions::READ | VmPermissions::WRITE | VmPermissions::USER;
+        crate::vmm::with_vmm(|vmm| {
+            let address_space = vmm.get_address_space_mut(address_space_id)
+                .ok_or(VmError::InvalidAddressSpace)?;
+            let start = address_space.place_mapping(None, size, false)?;
+            address_space.add_area(VmArea::new(start, start + size, VmAreaType::Data, permissions))?;
+            
+            let thread_pointer = start + self.thread_pointer_offset();
+            let image = self.block_image(thread_pointer.as_u64());
+            for (index, chunk) in image.chunks(4096).enumerate() {
+                let mapped = memory::allocate_frame().ok_or(VmError::OutOfMemory).and_then(|frame| {
+                    // SAFETY: The frame was just allocated, so nothing else uses it, and the
+                    // physical memory offset maps all of it
+                    unsafe {
+                        let page = memory::phys_to_virt(frame.start_address()).as_mut_ptr::<u8>();
+                        core::ptr::write_bytes(page, 0, 4096);
+                        core::ptr::copy_nonoverlapping(chunk.as_ptr(), page, chunk.len());
+                    }
+                    let page = start + (index as u64) * 4096;
+                    vmm.map_page(address_space_id, page, frame.start_address(), permissions.to_page_table_flags())
+                        .map_err(|error| {
+                            memory::deallocate_frame(frame);
+                            error
+                        })
+                });
+                if let Err(error) = mapped {
+                    let _ = vmm.unmap_memory(address_space_id, start, size);
+                    return Err(error);
+                }
+            }
+            Ok(thread_pointer)
+        })
+    }
+}
+
 /// ELF loader
 pub struct ElfLoader {
     data: Vec<u8>,
@@ -117,38 +199,56 @@ impl ElfLoader {
         VirtAddr::new(self.header.e_entry)
     }
     
-    /// Load the ELF into the specified address space
-    pub fn load_into_address_space(&self, address_space_id: u64) -> Result<(), ElfError> {
-        // Parse program headers
+    /// The program headers, read out of the file
+    fn program_headers(&self) -> Result<Vec<ProgramHeader>, ElfError> {
         let ph_offset = self.header.e_phoff as usize;
         let ph_size = self.header.e_phentsize as usize;
         let ph_count = self.header.e_phnum as usize;
         
-        if ph_offset + (ph_size * ph_count) > self.data.len() {
+        if ph_size < core::mem::size_of::<ProgramHeader>() && ph_count > 0 {
+            return Err(ElfError::InvalidProgramHeader);
+        }
+        let table_end = ph_size.checked_mul(ph_count).and_then(|size| size.checked_add(ph_offset));
+        if table_end.map_or(true, |end| end > self.data.len()) {
             return Err(ElfError::InvalidProgramHeader);
         }
         
+        Ok((0..ph_count).map(|i| {
+            // SAFETY: This is unsafe because:
+            // - `self.data.as_ptr().add()` performs pointer arithmetic
+            // - We've validated that the whole table, `ph_offset + (i * ph_size)` plus a full
+            //   header for every entry, is within bounds above
+            // - `core::ptr::read_unaligned` makes no assumption about the alignment of the entry
+            // - The ProgramHeader struct layout matches the ELF specification
+            unsafe {
+                core::ptr::read_unaligned(self.data.as_ptr().add(ph_offset + (i * ph_size)) as *const ProgramHeader)
+            }
+        }).collect())
+    }
+    
+    /// The thread-local storage template, if the program has a PT_TLS segment
+    pub fn tls_template(&self) -> Result<Option<TlsTemplate>, ElfError> {
+        let Some(ph) = self.program_headers()?.into_iter().find(|ph| ph.p_type == PT_TLS) else {
+            return Ok(None);
+        };
+        let align = ph.p_align.max(1);
+        if ph.p_filesz > ph.p_memsz || ph.p_memsz > MAX_TLS_SIZE || !align.is_power_of_two() || align > 4096 {
+            return Err(ElfError::InvalidProgramHeader);
+        }
+        let start = ph.p_offset as usize;
+        let init_image = start.checked_add(ph.p_filesz as usize)
+            .and_then(|end| self.data.get(start..end))
+            .ok_or(ElfError::InvalidProgramHeader)?;
+        Ok(Some(TlsTemplate { init_image: init_image.to_vec(), mem_size: ph.p_memsz, align }))
+    }
+    
+    /// Load the ELF into the specified address space
+    pub fn load_into_address_space(&self, address_space_id: u64) -> Result<(), ElfError> {
+        let program_headers = self.program_headers()?;
+        
         crate::vmm::with_vmm(|vmm| {
-            let address_space = vmm.get_address_space_mut(address_space_id)
-                .ok_or(ElfError::InvalidAddress)?;
-            
             // Process each loadable segment
-            for i in 0..ph_count {
-                // SAFETY: This is unsafe because:
-                // - `self.data.as_ptr().add()` performs pointer arithmetic
-                // - We've validated that `ph_offset + (i * ph_size)` is within bounds above
-                // - The cast to `*const ProgramHeader` assumes proper struct layout
-                // - The data buffer remains valid throughout this operation
-                let ph_ptr = unsafe {
-                    self.data.as_ptr().add(ph_offset + (i * ph_size)) as *const ProgramHeader
-                };
-                // SAFETY: This is unsafe because:
-                // - `ph_ptr` points to valid memory within the data buffer
-                // - We've verified the bounds above to ensure the read is within the buffer
-                // - `core::ptr::read` performs an unaligned read which is safe for packed structs
-                // - The ProgramHeader struct layout matches the ELF specification
-                let ph = unsafe { core::ptr::read(ph_ptr) };
-                
+            for ph in program_headers {
                 // Only process loadable segments
                 if ph.p_type != PT_LOAD {
                     continue;
@@ -197,130 +297,66 @@ impl ElfLoader {
                 );
                 
                 // Add area to address space
-                address_space.add_area(area)?;
+                vmm.get_address_space_mut(address_space_id)
+                    .ok_or(ElfError::InvalidAddress)?
+                    .add_area(area)?;
                 
-                // Copy segment data
-                if ph.p_filesz > 0 {
-                    let src_data = &self.data[ph.p_offset as usize..(ph.p_offset + ph.p_filesz) as usize];
-                    
-                    // Map pages and copy data
-                    self.copy_segment_data(start_addr, src_data, ph.p_memsz as usize)?;
-                }
-                
-                // Zero-fill remaining memory if memsz > filesz
-                if ph.p_memsz > ph.p_filesz {
-                    let zero_start = start_addr + ph.p_filesz;
-                    let zero_size = ph.p_memsz - ph.p_filesz;
-                    self.zero_memory(zero_start, zero_size as usize)?;
-                }
-            }
-            
-            Ok(())
-        })
-    }
-    
-    /// Copy segment data to virtual memory
-    fn copy_segment_data(&self, virt_addr: VirtAddr, data: &[u8], total_size: usize) -> Result<(), ElfError> {
-        use x86_64::structures::paging::{Page, Size4KiB, PageTableFlags};
-        use crate::memory;
-        
-        if data.len() > total_size {
-            return Err(ElfError::InvalidProgramHeader);
-        }
-        
-        // Calculate page range
-        let start_page = Page::<Size4KiB>::containing_address(virt_addr);
-        let end_addr = virt_addr + total_size - 1u64;
-        let end_page = Page::<Size4KiB>::containing_address(end_addr);
-        
-        // Map pages and copy data
-        memory::with_mapper(|mapper| {
-            let mut data_offset = 0;
-            
-            for page in Page::range_inclusive(start_page, end_page) {
-                // Allocate a physical frame for this page
-                let frame = memory::allocate_frame().ok_or(ElfError::MemoryError(VmError::OutOfMemory))?;
-                
-                // Map the page with appropriate flags
-                let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
-                
-                if let Some(frame_alloc) = memory::FRAME_ALLOC.lock().as_mut() {
-                    let mapping = unsafe { mapper.map_to(page, frame, flags, &mut *frame_alloc) }
-                        .map_err(|_| ElfError::MemoryError(VmError::MapError))?;
-                    mapping.flush();
-                }
-                
-                // Calculate how much data to copy to this page
-                let page_start_addr = page.start_address();
-                let copy_start = if page_start_addr < virt_addr {
-                    virt_addr.as_u64() - page_start_addr.as_u64()
-                } else {
-                    0
-                };
-                
-                let copy_end = core::cmp::min(4096, total_size - (page_start_addr.as_u64() - virt_addr.as_u64()) as usize + copy_start as usize);
-                let copy_size = copy_end - copy_start as usize;
-                
-                if data_offset < data.len() && copy_size > 0 {
-                    let copy_amount = core::cmp::min(copy_size, data.len() - data_offset);
-                    
-                    // Copy data to the mapped page
-                    unsafe {
-                        let dest_ptr = (page_start_addr + copy_start).as_mut_ptr::<u8>();
-                        let src_ptr = data.as_ptr().add(data_offset);
-                        core::ptr::copy_nonoverlapping(src_ptr, dest_ptr, copy_amount);
-                    }
-                    
-                    data_offset += copy_amount;
-                }
+                let file_data = &self.data[ph.p_offset as usize..(ph.p_offset + ph.p_filesz) as usize];
+                Self::populate_segment(vmm, address_space_id, start_addr, ph.p_memsz, file_data, permissions)?;
             }
             
             Ok(())
         })
     }
     
-    /// Zero-fill memory region
-    fn zero_memory(&self, virt_addr: VirtAddr, size: usize) -> Result<(), ElfError> {
+    /// Back the segment of `mem_size` bytes at `virt_addr` with zeroed frames holding
+    /// `file_data`, mapped into address space `address_space_id` with the segment's own
+    /// permissions. The frames are filled through the physical memory map before they are
+    /// mapped, so read-only segments are never written through a user mapping
+    fn populate_segment(
+        vmm: &mut crate::vmm::VirtualMemoryManager,
+        address_space_id: u64,
+        virt_addr: VirtAddr,
+        mem_size: u64,
+        file_data: &[u8],
+        permissions: VmPermissions,
+    ) -> Result<(), ElfError> {
         use x86_64::structures::paging::{Page, Size4KiB};
+        use crate::memory;
         
-        if size == 0 {
+        if mem_size == 0 {
             return Ok(());
         }
-        
-        // Calculate page range
         let start_page = Page::<Size4KiB>::containing_address(virt_addr);
-        let end_addr = virt_addr + size - 1u64;
-        let end_page = Page::<Size4KiB>::containing_address(end_addr);
-        
-        // Zero-fill the memory range
+        let end_page = Page::<Size4KiB>::containing_address(virt_addr + (mem_size - 1));
         for page in Page::range_inclusive(start_page, end_page) {
-            let page_start_addr = page.start_address();
-            
-            // Calculate the range within this page to zero
-            let zero_start = if page_start_addr < virt_addr {
-                virt_addr.as_u64() - page_start_addr.as_u64()
-            } else {
-                0
-            };
-            
-            let page_end = page_start_addr + 4096u64;
-            let zero_end = if virt_addr + size < page_end {
-                (virt_addr + size).as_u64() - page_start_addr.as_u64()
-            } else {
-                4096
-            };
+            let frame = memory::allocate_frame().ok_or(ElfError::MemoryError(VmError::OutOfMemory))?;
             
-            let zero_size = zero_end - zero_start;
-            
-            if zero_size > 0 {
-                // Zero the memory range
-                unsafe {
-                    let dest_ptr = (page_start_addr + zero_start).as_mut_ptr::<u8>();
-                    core::ptr::write_bytes(dest_ptr, 0, zero_size as usize);
+            // The part of the file data that lands in this page
+            let page_start = page.start_address().as_u64();
+            let data_start = page_start.max(virt_addr.as_u64());
+            let data_end = (page_start + 4096).min(virt_addr.as_u64() + file_data.len() as u64);
+            // SAFETY: The frame was just allocated, so nothing else uses it, and the physical
+            // memory offset maps all of it
+            unsafe {
+                let dest = memory::phys_to_virt(frame.start_address()).as_mut_ptr::<u8>();
+                core::ptr::write_bytes(dest, 0, 4096);
+                if data_start < data_end {
+                    let offset = (data_start - virt_addr.as_u64()) as usize;
+                    let length = (data_end - data_start) as usize;
+                    core::ptr::copy_nonoverlapping(
+                        file_data.as_ptr().add(offset),
+                        dest.add((data_start - page_start) as usize),
+                        length,
+                    );
                 }
             }
+            
+            if let Err(error) = vmm.map_page(address_space_id, page.start_address(), frame.start_address(), permissions.to_page_table_flags()) {
+                memory::deallocate_frame(frame);
+                return Err(error.into());
+            }
         }
-        
         Ok(())
     }
 }
@@ -335,6 +371,17 @@ pub fn load_elf(data: Vec<u8>, address_space_id: u64) -> Result<VirtAddr, ElfErr
     Ok(entry_point)
 }
 
+/// Load an ELF executable, returning its entry point and its thread-local storage template
+/// if it has one
+pub fn load_elf_with_tls(data: Vec<u8>, address_space_id: u64) -> Result<(VirtAddr, Option<TlsTemplate>), ElfError> {
+    let loader = ElfLoader::new(data)?;
+    let tls_template = loader.tls_template()?;
+    
+    loader.load_into_address_space(address_space_id)?;
+    
+    Ok((loader.entry_point(), tls_template))
+}
+
 /// Validate ELF file without loading
 pub fn validate_elf(data: &[u8]) -> Result<VirtAddr, ElfError> {
     if data.len() < core::mem::size_of::<ElfHeader>() {
@@ -0,0 +1,137 @@
+//! Eventfd and Signalfd Test
+//! Checks that writing to an eventfd makes it readable and poll report it, that reads drain
+//! the counter, or take one at a time in semaphore mode, that a wait queue counts its
+//! wake-ups so a late waiter is turned away, and that a signal in a signalfd's mask pending
+//! on its thread group polls readable and reads back as the signal number while others do
+//! not
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use x86_64::VirtAddr;
+use crate::filesystem::eventfd::{self, EFD_NONBLOCK, EFD_SEMAPHORE};
+use crate::filesystem::poll::{self, PollFd, WaitQueue, POLLIN, POLLOUT};
+use crate::filesystem::{self, signalfd, FileSystemError};
+use crate::process::{self, Process, Signal};
+use crate::serial::_print;
+use crate::test_threads::parked_thread;
+
+fn read_counter(fd: u64) -> Result<u64, FileSystemError> {
+    let mut value = [0; 8];
+    filesystem::read(fd, &mut value)?;
+    Ok(u64::from_ne_bytes(value))
+}
+
+/// Events ready on `fd` without waiting
+fn ready(fd: u64) -> u16 {
+    let mut fds = [PollFd::new(fd, POLLIN | POLLOUT)];
+    poll::poll(&mut fds, Some(0));
+    fds[0].revents
+}
+
+fn events(fds: &mut Vec<u64>, pids: &mut Vec<u64>) -> Result<(), &'static str> {
+    // Test 1: A write makes the eventfd readable, and a read drains it
+    _print(format_args!("[Eventfd Test] Test 1: Eventfd counter...\n"));
+    let fd = eventfd::create(0, EFD_NONBLOCK).map_err(|_| "Failed to create an eventfd")?;
+    fds.push(fd);
+    if ready(fd) != POLLOUT || read_counter(fd) != Err(FileSystemError::WouldBlock) {
+        return Err("Empty eventfd readable");
+    }
+    for value in [2u64, 3] {
+        filesystem::write(fd, &value.to_ne_bytes()).map_err(|_| "Failed to write the eventfd")?;
+    }
+    if ready(fd) != POLLIN | POLLOUT {
+        return Err("Poll did not report the written eventfd readable");
+    }
+    if read_counter(fd) != Ok(5) || ready(fd) != POLLOUT {
+        return Err("Read did not drain the counter");
+    }
+    if filesystem::write(fd, &u64::MAX.to_ne_bytes()).is_ok() || filesystem::write(fd, &[1]).is_ok() {
+        return Err("Invalid write accepted");
+    }
+    let semaphore = eventfd::create(2, EFD_SEMAPHORE | EFD_NONBLOCK).map_err(|_| "Failed to create an eventfd")?;
+    fds.push(semaphore);
+    let taken = [read_counter(semaphore), read_counter(semaphore), read_counter(semaphore)];
+    if taken != [Ok(1), Ok(1), Err(FileSystemError::WouldBlock)] {
+        return Err("Semaphore eventfd not taken one at a time");
+    }
+    _print(format_args!("[Eventfd Test] ✓ Eventfd {} read 5 after writes of 2 and 3\n", fd));
+
+    // Test 2: Waiters are woken, and one counting wake-ups from before is turned away
+    _print(format_args!("[Eventfd Test] Test 2: Wait queues...\n"));
+    let queue = WaitQueue::new();
+    let waiter = process::spawn_kernel_thread("eventfd-test", parked_thread).map_err(|_| "Failed to spawn a thread")?;
+    pids.push(waiter);
+    let wakes = queue.wakes();
+    if !queue.register(waiter, wakes) || !queue.is_waiting(waiter) {
+        return Err("Waiter not queued");
+    }
+    queue.wake_all();
+    if queue.is_waiting(waiter) || queue.register(waiter, wakes) {
+        return Err("Stale waiter queued after a wake-up");
+    }
+    _print(format_args!("[Eventfd Test] ✓ Waiter {} woken once\n", waiter));
+
+    // Test 3: A signal in the mask surfaces on the signalfd instead of a handler
+    _print(format_args!("[Eventfd Test] Test 3: Signalfd...\n"));
+    let target = Process::user_process(String::from("signalfd-test"), VirtAddr::new(0x40_0000)).map_err(|_| "Failed to create process")?;
+    let target = {
+        let mut scheduler = process::get_smp_scheduler().lock();
+        let pid = scheduler.add_process(target);
+        scheduler.block_process(pid);
+        pid
+    };
+    pids.push(target);
+    let fd = signalfd::create(target, 1 << Signal::SIGUSR1 as u8).map_err(|_| "Failed to create a signalfd")?;
+    fds.push(fd);
+    if signalfd::routed_signals(target) != 1 << Signal::SIGUSR1 as u8 {
+        return Err("Signal not routed to the signalfd");
+    }
+    process::send_signal(target, Signal::SIGUSR2).map_err(|_| "Failed to send a signal")?;
+    if ready(fd) != 0 {
+        return Err("Signal outside the mask made the signalfd readable");
+    }
+    process::send_signal(target, Signal::SIGUSR1).map_err(|_| "Failed to send a signal")?;
+    if ready(fd) != POLLIN {
+        return Err("Poll did not report the pending signal");
+    }
+    let mut records = [0; 2 * signalfd::SIGNAL_RECORD_SIZE];
+    let read = filesystem::read(fd, &mut records).map_err(|_| "Failed to read the signalfd")?;
+    if read != signalfd::SIGNAL_RECORD_SIZE || records[..read] != (Signal::SIGUSR1 as u32).to_ne_bytes() {
+        return Err("Signalfd did not carry the signal number");
+    }
+    if ready(fd) != 0 || process::pending_signals_of(target, u64::MAX) != 1 << Signal::SIGUSR2 as u8 {
+        return Err("Read signal still pending, or another taken");
+    }
+    _print(format_args!("[Eventfd Test] ✓ Read SIGUSR1 of PID {} from signalfd {}\n", target, fd));
+    Ok(())
+}
+
+pub fn run_eventfd_tests() -> Result<(), &'static str> {
+    let mut fds = Vec::new();
+    let mut pids = Vec::new();
+    let result = events(&mut fds, &mut pids);
+    for fd in fds {
+        let _ = filesystem::close(fd);
+    }
+    for pid in pids.into_iter().rev() {
+        process::terminate_process(pid);
+    }
+    result?;
+
+    _print(format_args!("[Eventfd Test] ✓ All eventfd and signalfd tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for eventfds and signalfds
+pub fn test_eventfd() {
+    _print(format_args!("[Eventfd Test] ===========================================\n"));
+    _print(format_args!("[Eventfd Test]          EVENTFD AND SIGNALFD TESTS\n"));
+    _print(format_args!("[Eventfd Test] ===========================================\n"));
+
+    match run_eventfd_tests() {
+        Ok(_) => _print(format_args!("[Eventfd Test] ✓ All eventfd and signalfd tests PASSED\n")),
+        Err(e) => _print(format_args!("[Eventfd Test] ✗ Eventfd and signalfd tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Eventfd Test] ===========================================\n"));
+}
@@ -4,11 +4,23 @@ use alloc::string::String;
 use alloc::vec::Vec;
 use alloc::vec;
 use alloc::boxed::Box;
+use alloc::sync::Arc;
 use core::fmt;
 use spin::{Mutex, RwLock};
 use crate::time::get_timestamp;
 use crate::slo_measure;
 use alloc::string::ToString;
+use poll::WaitQueue;
+use watch::WatchEvent;
+
+pub mod epoll;
+pub mod eventfd;
+pub mod inotify;
+pub mod lock;
+pub mod poll;
+pub mod signalfd;
+pub mod timerfd;
+pub mod watch;
 
 // Define SeekFrom for no_std environment
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -18,6 +30,23 @@ pub enum SeekFrom {
     Current(i64),
 }
 
+/// `open` flags, with the Linux values
+pub const O_RDONLY: u32 = 0;
+pub const O_WRONLY: u32 = 0o1;
+pub const O_RDWR: u32 = 0o2;
+pub const O_ACCMODE: u32 = 0o3;
+/// Create the file if it does not exist
+pub const O_CREAT: u32 = 0o100;
+/// Empty the file on open
+pub const O_TRUNC: u32 = 0o1000;
+/// Write at the end of the file whatever the position
+pub const O_APPEND: u32 = 0o2000;
+/// Fail with `WouldBlock` rather than wait, for files that can block
+pub const O_NONBLOCK: u32 = 0o4000;
+
+/// Most symbolic links followed looking up one path, as on Linux
+pub const MAX_SYMLINK_HOPS: usize = 40;
+
 static VFS: RwLock<VirtualFileSystem> = RwLock::new(VirtualFileSystem::new());
 static NEXT_FD: Mutex<u64> = Mutex::new(3); // Start after stdin, stdout, stderr
 
@@ -63,7 +92,7 @@ impl Default for FileMetadata {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum FileSystemError {
     NotFound,
     PermissionDenied,
@@ -75,6 +104,10 @@ pub enum FileSystemError {
     NoSpace,
     ReadOnly,
     InvalidOperation,
+    /// Following symbolic links went past `MAX_SYMLINK_HOPS`, `ELOOP`
+    TooManyLinks,
+    /// The operation would have to wait, `EWOULDBLOCK`
+    WouldBlock,
 }
 
 impl fmt::Display for FileSystemError {
@@ -90,6 +123,8 @@ impl fmt::Display for FileSystemError {
             FileSystemError::NoSpace => write!(f, "No space left on device"),
             FileSystemError::ReadOnly => write!(f, "Read-only file system"),
             FileSystemError::InvalidOperation => write!(f, "Invalid operation"),
+            FileSystemError::TooManyLinks => write!(f, "Too many levels of symbolic links"),
+            FileSystemError::WouldBlock => write!(f, "Resource temporarily unavailable"),
         }
     }
 }
@@ -106,6 +141,14 @@ pub trait FileSystem: Send + Sync {
     fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>>;
     fn rename(&mut self, old_path: &str, new_path: &str) -> FileSystemResult<()>;
     fn sync(&mut self) -> FileSystemResult<()>;
+    /// Create a symbolic link at `path` holding `target`, which need not exist
+    fn symlink(&mut self, _target: &str, _path: &str) -> FileSystemResult<()> {
+        Err(FileSystemError::InvalidOperation)
+    }
+    /// The target held by the symbolic link at `path`
+    fn readlink(&self, _path: &str) -> FileSystemResult<String> {
+        Err(FileSystemError::InvalidOperation)
+    }
 }
 
 // File trait for file operations
@@ -113,9 +156,19 @@ pub trait File: Send + Sync {
     fn read(&mut self, buffer: &mut [u8]) -> FileSystemResult<usize>;
     fn write(&mut self, buffer: &[u8]) -> FileSystemResult<usize>;
     fn seek(&mut self, pos: SeekFrom) -> FileSystemResult<u64>;
+    /// Cut the file to `size` bytes, or zero-fill it out to `size`. The position is unchanged
+    fn truncate(&mut self, size: u64) -> FileSystemResult<()>;
     fn flush(&mut self) -> FileSystemResult<()>;
     fn metadata(&self) -> FileSystemResult<FileMetadata>;
     fn set_permissions(&mut self, permissions: u32) -> FileSystemResult<()>;
+    /// Readiness as `poll::POLLIN`/`POLLOUT` bits. Files that never block are always ready
+    fn poll(&self) -> u16 {
+        poll::POLLIN | poll::POLLOUT
+    }
+    /// Queue woken whenever `poll` may change, for files that can block
+    fn wait_queue(&self) -> Option<Arc<WaitQueue>> {
+        None
+    }
 }
 
 // In-memory file system implementation
@@ -131,7 +184,8 @@ struct MemoryNode {
     _inode: u64,
     name: String,
     metadata: FileMetadata,
-    data: Vec<u8>,
+    /// Shared with every open file of the node so that writes through a descriptor persist
+    data: Arc<Mutex<Vec<u8>>>,
     children: BTreeMap<String, MemoryNode>,
 }
 
@@ -144,7 +198,7 @@ impl MemoryNode {
             _inode: inode,
             name,
             metadata,
-            data: Vec::new(),
+            data: Arc::new(Mutex::new(Vec::new())),
             children: BTreeMap::new(),
         }
     }
@@ -168,7 +222,7 @@ impl MemoryNode {
         Some(current)
     }
     
-    fn _find_node_mut(&mut self, path: &str) -> Option<&mut MemoryNode> {
+    fn find_node_mut(&mut self, path: &str) -> Option<&mut MemoryNode> {
         if path.is_empty() || path == "/" {
             return Some(self);
         }
@@ -255,9 +309,11 @@ impl FileSystem for MemoryFileSystem {
             return Err(FileSystemError::IsADirectory);
         }
         
+        let mut metadata = node.metadata.clone();
+        metadata.size = node.data.lock().len() as u64;
         Ok(Box::new(MemoryFile {
-            data: node.data.clone(),
-            metadata: node.metadata.clone(),
+            data: Arc::clone(&node.data),
+            metadata,
             position: 0,
         }))
     }
@@ -309,7 +365,9 @@ impl FileSystem for MemoryFileSystem {
     fn metadata(&self, path: &str) -> FileSystemResult<FileMetadata> {
         let node = self.root.find_node(path)
             .ok_or(FileSystemError::NotFound)?;
-        Ok(node.metadata.clone())
+        let mut metadata = node.metadata.clone();
+        metadata.size = node.data.lock().len() as u64;
+        Ok(metadata)
     }
     
     fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
@@ -380,6 +438,26 @@ impl FileSystem for MemoryFileSystem {
         // Memory filesystem doesn't need explicit sync
         Ok(())
     }
+    
+    /// The link is a node whose data is the target, so its size is the target's length
+    fn symlink(&mut self, target: &str, path: &str) -> FileSystemResult<()> {
+        let inode = self.allocate_inode();
+        self.root.create_node(path, FileType::SymbolicLink, inode)?;
+        let node = self.root.find_node_mut(path)
+            .ok_or(FileSystemError::NotFound)?;
+        node.metadata.permissions = 0o777;
+        *node.data.lock() = target.as_bytes().to_vec();
+        Ok(())
+    }
+    
+    fn readlink(&self, path: &str) -> FileSystemResult<String> {
+        let node = self.root.find_node(path)
+            .ok_or(FileSystemError::NotFound)?;
+        if node.metadata.file_type != FileType::SymbolicLink {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        String::from_utf8(node.data.lock().clone()).map_err(|_| FileSystemError::InvalidPath)
+    }
 }
 
 // Crash-Safe Filesystem Implementation
@@ -1225,6 +1303,11 @@ impl File for CrashSafeFile {
         }
     }
 
+    fn truncate(&mut self, _size: u64) -> FileSystemResult<()> {
+        // A crash-safe file is a single block of fixed size
+        Err(FileSystemError::InvalidOperation)
+    }
+
     fn flush(&mut self) -> FileSystemResult<()> {
         unsafe {
             // SAFETY: This is unsafe because:
@@ -1318,39 +1401,41 @@ pub fn document_fsync_guarantees() {
 
 #[derive(Debug)]
 struct MemoryFile {
-    data: Vec<u8>,
+    data: Arc<Mutex<Vec<u8>>>,
     metadata: FileMetadata,
     position: u64,
 }
 
 impl File for MemoryFile {
     fn read(&mut self, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        let data = self.data.lock();
         let start = self.position as usize;
-        let end = core::cmp::min(start + buffer.len(), self.data.len());
+        let end = core::cmp::min(start + buffer.len(), data.len());
         
-        if start >= self.data.len() {
+        if start >= data.len() {
             return Ok(0);
         }
         
         let bytes_read = end - start;
-        buffer[..bytes_read].copy_from_slice(&self.data[start..end]);
+        buffer[..bytes_read].copy_from_slice(&data[start..end]);
         self.position += bytes_read as u64;
         
         Ok(bytes_read)
     }
     
     fn write(&mut self, buffer: &[u8]) -> FileSystemResult<usize> {
+        let mut data = self.data.lock();
         let start = self.position as usize;
         let end = start + buffer.len();
         
         // Extend data if necessary
-        if end > self.data.len() {
-            self.data.resize(end, 0);
+        if end > data.len() {
+            data.resize(end, 0);
         }
         
-        self.data[start..end].copy_from_slice(buffer);
+        data[start..end].copy_from_slice(buffer);
         self.position += buffer.len() as u64;
-        self.metadata.size = self.data.len() as u64;
+        self.metadata.size = data.len() as u64;
         self.metadata.modified = crate::time::get_timestamp();
         
         Ok(buffer.len())
@@ -1360,10 +1445,11 @@ impl File for MemoryFile {
         let new_pos = match pos {
             SeekFrom::Start(offset) => offset,
             SeekFrom::End(offset) => {
-                if offset < 0 && (-offset) as u64 > self.data.len() as u64 {
+                let len = self.data.lock().len() as u64;
+                if offset < 0 && (-offset) as u64 > len {
                     0
                 } else {
-                    (self.data.len() as i64 + offset) as u64
+                    (len as i64 + offset) as u64
                 }
             }
             SeekFrom::Current(offset) => {
@@ -1379,13 +1465,23 @@ impl File for MemoryFile {
         Ok(self.position)
     }
     
+    fn truncate(&mut self, size: u64) -> FileSystemResult<()> {
+        let mut data = self.data.lock();
+        data.resize(size as usize, 0);
+        self.metadata.size = size;
+        self.metadata.modified = crate::time::get_timestamp();
+        Ok(())
+    }
+    
     fn flush(&mut self) -> FileSystemResult<()> {
         // Memory file doesn't need flushing
         Ok(())
     }
     
     fn metadata(&self) -> FileSystemResult<FileMetadata> {
-        Ok(self.metadata.clone())
+        let mut metadata = self.metadata.clone();
+        metadata.size = self.data.lock().len() as u64;
+        Ok(metadata)
     }
     
     fn set_permissions(&mut self, permissions: u32) -> FileSystemResult<()> {
@@ -1399,6 +1495,8 @@ pub struct VirtualFileSystem {
     filesystems: BTreeMap<String, Box<dyn FileSystem>>,
     mount_points: BTreeMap<String, String>, // mount_point -> filesystem_name
     open_files: BTreeMap<u64, Box<dyn File>>, // fd -> file
+    open_paths: BTreeMap<u64, String>, // fd -> resolved path it was opened at
+    open_flags: BTreeMap<u64, u32>, // fd -> flags it was opened with
 }
 
 impl VirtualFileSystem {
@@ -1407,6 +1505,8 @@ impl VirtualFileSystem {
             filesystems: BTreeMap::new(),
             mount_points: BTreeMap::new(),
             open_files: BTreeMap::new(),
+            open_paths: BTreeMap::new(),
+            open_flags: BTreeMap::new(),
         }
     }
     
@@ -1439,33 +1539,144 @@ impl VirtualFileSystem {
         }
         
         if best_len > 0 {
-            Some((best_match.0, path[best_len..].to_owned()))
+            Some((best_match.1.to_owned(), path[best_len..].to_owned()))
         } else {
             None
         }
     }
     
-    pub fn open(&mut self, path: &str, flags: u32) -> FileSystemResult<u64> {
+    /// Metadata of `path` as its filesystem has it, without following links
+    fn metadata_at(&self, path: &str) -> FileSystemResult<FileMetadata> {
         let (fs_name, relative_path) = self.resolve_path(path)
             .ok_or(FileSystemError::NotFound)?;
         
+        let filesystem = self.filesystems.get(&fs_name)
+            .ok_or(FileSystemError::NotFound)?;
+        
+        filesystem.metadata(&relative_path)
+    }
+    
+    /// `path` made absolute and free of `.`, `..` and symbolic links, leaving a link in the
+    /// last component alone unless `follow_last`. The last component need not exist, so a
+    /// dangling link resolves to its missing target. Relative link targets are taken from
+    /// the link's directory
+    fn lookup(&self, path: &str, follow_last: bool) -> FileSystemResult<String> {
+        // Components still to walk, the next one last
+        let mut pending: Vec<String> = path.split('/').rev().filter(|c| !c.is_empty()).map(String::from).collect();
+        // Empty for the root
+        let mut resolved = String::new();
+        let mut hops = 0;
+        
+        while let Some(component) = pending.pop() {
+            match component.as_str() {
+                "." => continue,
+                ".." => {
+                    let parent = resolved.rfind('/').unwrap_or(0);
+                    resolved.truncate(parent);
+                    continue;
+                }
+                _ => {}
+            }
+            let candidate = alloc::format!("{}/{}", resolved, component);
+            let is_last = pending.is_empty();
+            match self.metadata_at(&candidate) {
+                Ok(metadata) if metadata.file_type == FileType::SymbolicLink && (follow_last || !is_last) => {
+                    hops += 1;
+                    if hops > MAX_SYMLINK_HOPS {
+                        return Err(FileSystemError::TooManyLinks);
+                    }
+                    let (fs_name, relative_path) = self.resolve_path(&candidate)
+                        .ok_or(FileSystemError::NotFound)?;
+                    let target = self.filesystems.get(&fs_name)
+                        .ok_or(FileSystemError::NotFound)?
+                        .readlink(&relative_path)?;
+                    if target.starts_with('/') {
+                        resolved.clear();
+                    }
+                    pending.extend(target.split('/').rev().filter(|c| !c.is_empty()).map(String::from));
+                }
+                Ok(metadata) if !is_last && metadata.file_type != FileType::Directory => {
+                    return Err(FileSystemError::NotADirectory);
+                }
+                Err(e) if !is_last => return Err(e),
+                _ => resolved = candidate,
+            }
+        }
+        
+        if resolved.is_empty() {
+            resolved.push('/');
+        }
+        Ok(resolved)
+    }
+    
+    pub fn open(&mut self, path: &str, flags: u32) -> FileSystemResult<u64> {
+        let path = self.lookup(path, true)?;
+        let (fs_name, relative_path) = self.resolve_path(&path)
+            .ok_or(FileSystemError::NotFound)?;
+        
         let filesystem = self.filesystems.get_mut(&fs_name)
             .ok_or(FileSystemError::NotFound)?;
         
-        let file = filesystem.open(&relative_path, flags)?;
+        let mut file = match filesystem.open(&relative_path, flags) {
+            Err(FileSystemError::NotFound) if flags & O_CREAT != 0 => {
+                // Fails with NotFound when the parent directory does not exist either
+                filesystem.create(&relative_path, FileType::Regular)?;
+                let file = filesystem.open(&relative_path, flags)?;
+                watch::notify(WatchEvent::Created(path.clone()));
+                file
+            }
+            opened => opened?,
+        };
+        if flags & O_TRUNC != 0 {
+            file.truncate(0)?;
+            watch::notify(WatchEvent::Modified(path.clone()));
+        }
+        let fd = self.install(flags, |_| file);
+        self.open_paths.insert(fd, path);
+        Ok(fd)
+    }
+    
+    /// Open a file with no path on a new descriptor with `flags`, made by `make` from the
+    /// descriptor, and return it
+    pub fn install(&mut self, flags: u32, make: impl FnOnce(u64) -> Box<dyn File>) -> u64 {
         let fd = *NEXT_FD.lock();
         *NEXT_FD.lock() += 1;
         
-        self.open_files.insert(fd, file);
-        Ok(fd)
+        self.open_files.insert(fd, make(fd));
+        self.open_flags.insert(fd, flags);
+        fd
     }
     
     pub fn close(&mut self, fd: u64) -> FileSystemResult<()> {
         self.open_files.remove(&fd)
             .ok_or(FileSystemError::NotFound)?;
+        self.open_paths.remove(&fd);
+        self.open_flags.remove(&fd);
+        lock::release_fd(fd);
         Ok(())
     }
     
+    /// Path an open file was opened at, with links resolved
+    pub fn file_path(&self, fd: u64) -> Option<&str> {
+        self.open_paths.get(&fd).map(String::as_str)
+    }
+    
+    /// Whether operations on `fd` that would wait fail instead
+    pub fn is_nonblocking(&self, fd: u64) -> bool {
+        self.open_flags.get(&fd).is_some_and(|flags| flags & O_NONBLOCK != 0)
+    }
+    
+    /// Readiness of `fd` and the queue woken when it changes, with the queue's wake count
+    /// taken before the readiness was read, `None` if it is not open
+    pub fn readiness(&self, fd: u64) -> Option<(u16, Option<(Arc<WaitQueue>, u64)>)> {
+        let file = self.open_files.get(&fd)?;
+        let queue = file.wait_queue().map(|queue| {
+            let wakes = queue.wakes();
+            (queue, wakes)
+        });
+        Some((file.poll(), queue))
+    }
+    
     pub fn read(&mut self, fd: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
         let file = self.open_files.get_mut(&fd)
             .ok_or(FileSystemError::NotFound)?;
@@ -1475,7 +1686,24 @@ impl VirtualFileSystem {
     pub fn write(&mut self, fd: u64, buffer: &[u8]) -> FileSystemResult<usize> {
         let file = self.open_files.get_mut(&fd)
             .ok_or(FileSystemError::NotFound)?;
-        file.write(buffer)
+        if self.open_flags.get(&fd).is_some_and(|flags| flags & O_APPEND != 0) {
+            file.seek(SeekFrom::End(0))?;
+        }
+        let written = file.write(buffer)?;
+        if let Some(path) = self.open_paths.get(&fd) {
+            watch::notify(WatchEvent::Modified(path.clone()));
+        }
+        Ok(written)
+    }
+    
+    pub fn truncate(&mut self, fd: u64, size: u64) -> FileSystemResult<()> {
+        let file = self.open_files.get_mut(&fd)
+            .ok_or(FileSystemError::NotFound)?;
+        file.truncate(size)?;
+        if let Some(path) = self.open_paths.get(&fd) {
+            watch::notify(WatchEvent::Modified(path.clone()));
+        }
+        Ok(())
     }
     
     pub fn seek(&mut self, fd: u64, pos: SeekFrom) -> FileSystemResult<u64> {
@@ -1485,37 +1713,62 @@ impl VirtualFileSystem {
     }
     
     pub fn create(&mut self, path: &str, file_type: FileType) -> FileSystemResult<()> {
-        let (fs_name, relative_path) = self.resolve_path(path)
+        let path = self.lookup(path, false)?;
+        let (fs_name, relative_path) = self.resolve_path(&path)
             .ok_or(FileSystemError::NotFound)?;
         
         let filesystem = self.filesystems.get_mut(&fs_name)
             .ok_or(FileSystemError::NotFound)?;
         
-        filesystem.create(&relative_path, file_type)
+        filesystem.create(&relative_path, file_type)?;
+        watch::notify(WatchEvent::Created(path));
+        Ok(())
     }
     
     pub fn remove(&mut self, path: &str) -> FileSystemResult<()> {
-        let (fs_name, relative_path) = self.resolve_path(path)
+        let path = self.lookup(path, false)?;
+        let (fs_name, relative_path) = self.resolve_path(&path)
             .ok_or(FileSystemError::NotFound)?;
         
         let filesystem = self.filesystems.get_mut(&fs_name)
             .ok_or(FileSystemError::NotFound)?;
         
-        filesystem.remove(&relative_path)
+        filesystem.remove(&relative_path)?;
+        watch::notify(WatchEvent::Deleted(path));
+        Ok(())
     }
     
-    pub fn metadata(&self, path: &str) -> FileSystemResult<FileMetadata> {
-        let (fs_name, relative_path) = self.resolve_path(path)
+    /// Move `old_path` to `new_path` within one filesystem
+    pub fn rename(&mut self, old_path: &str, new_path: &str) -> FileSystemResult<()> {
+        let old_path = self.lookup(old_path, false)?;
+        let new_path = self.lookup(new_path, false)?;
+        let (fs_name, old_relative) = self.resolve_path(&old_path)
+            .ok_or(FileSystemError::NotFound)?;
+        let (new_fs_name, new_relative) = self.resolve_path(&new_path)
             .ok_or(FileSystemError::NotFound)?;
+        if new_fs_name != fs_name {
+            return Err(FileSystemError::InvalidOperation);
+        }
         
-        let filesystem = self.filesystems.get(&fs_name)
+        let filesystem = self.filesystems.get_mut(&fs_name)
             .ok_or(FileSystemError::NotFound)?;
         
-        filesystem.metadata(&relative_path)
+        filesystem.rename(&old_relative, &new_relative)?;
+        watch::notify(WatchEvent::Renamed { from: old_path, to: new_path });
+        Ok(())
+    }
+    
+    pub fn metadata(&self, path: &str) -> FileSystemResult<FileMetadata> {
+        self.metadata_at(&self.lookup(path, true)?)
+    }
+    
+    /// Metadata of `path` itself when it is a symbolic link, like `lstat`
+    pub fn symlink_metadata(&self, path: &str) -> FileSystemResult<FileMetadata> {
+        self.metadata_at(&self.lookup(path, false)?)
     }
     
     pub fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
-        let (fs_name, relative_path) = self.resolve_path(path)
+        let (fs_name, relative_path) = self.resolve_path(&self.lookup(path, true)?)
             .ok_or(FileSystemError::NotFound)?;
         
         let filesystem = self.filesystems.get(&fs_name)
@@ -1523,6 +1776,29 @@ impl VirtualFileSystem {
         
         filesystem.list_directory(&relative_path)
     }
+    
+    pub fn symlink(&mut self, target: &str, link_path: &str) -> FileSystemResult<()> {
+        let link_path = self.lookup(link_path, false)?;
+        let (fs_name, relative_path) = self.resolve_path(&link_path)
+            .ok_or(FileSystemError::NotFound)?;
+        
+        let filesystem = self.filesystems.get_mut(&fs_name)
+            .ok_or(FileSystemError::NotFound)?;
+        
+        filesystem.symlink(target, &relative_path)?;
+        watch::notify(WatchEvent::Created(link_path));
+        Ok(())
+    }
+    
+    pub fn readlink(&self, path: &str) -> FileSystemResult<String> {
+        let (fs_name, relative_path) = self.resolve_path(&self.lookup(path, false)?)
+            .ok_or(FileSystemError::NotFound)?;
+        
+        let filesystem = self.filesystems.get(&fs_name)
+            .ok_or(FileSystemError::NotFound)?;
+        
+        filesystem.readlink(&relative_path)
+    }
 }
 
 // Public API functions
@@ -1564,18 +1840,42 @@ pub fn close(fd: u64) -> FileSystemResult<()> {
     VFS.write().close(fd)
 }
 
+/// Read from `fd`, waiting until it is readable if it would block unless opened with
+/// `O_NONBLOCK`
 pub fn read(fd: u64, buffer: &mut [u8]) -> FileSystemResult<usize> {
-    VFS.write().read(fd, buffer)
+    loop {
+        let result = VFS.write().read(fd, buffer);
+        match result {
+            Err(FileSystemError::WouldBlock) if !VFS.read().is_nonblocking(fd) => poll::wait_for(fd, poll::POLLIN),
+            result => return result,
+        }
+    }
 }
 
+/// Write to `fd`, waiting until it is writable if it would block unless opened with
+/// `O_NONBLOCK`
 pub fn write(fd: u64, buffer: &[u8]) -> FileSystemResult<usize> {
-    VFS.write().write(fd, buffer)
+    loop {
+        let result = VFS.write().write(fd, buffer);
+        match result {
+            Err(FileSystemError::WouldBlock) if !VFS.read().is_nonblocking(fd) => poll::wait_for(fd, poll::POLLOUT),
+            result => return result,
+        }
+    }
 }
 
 pub fn seek(fd: u64, pos: SeekFrom) -> FileSystemResult<u64> {
     VFS.write().seek(fd, pos)
 }
 
+pub fn truncate(fd: u64, size: u64) -> FileSystemResult<()> {
+    VFS.write().truncate(fd, size)
+}
+
+pub fn file_path(fd: u64) -> Option<String> {
+    VFS.read().file_path(fd).map(String::from)
+}
+
 pub fn create_file(path: &str) -> FileSystemResult<()> {
     VFS.write().create(path, FileType::Regular)
 }
@@ -1588,14 +1888,31 @@ pub fn remove(path: &str) -> FileSystemResult<()> {
     VFS.write().remove(path)
 }
 
+pub fn rename(old_path: &str, new_path: &str) -> FileSystemResult<()> {
+    VFS.write().rename(old_path, new_path)
+}
+
 pub fn metadata(path: &str) -> FileSystemResult<FileMetadata> {
     VFS.read().metadata(path)
 }
 
+pub fn symlink_metadata(path: &str) -> FileSystemResult<FileMetadata> {
+    VFS.read().symlink_metadata(path)
+}
+
 pub fn list_directory(path: &str) -> FileSystemResult<Vec<String>> {
     VFS.read().list_directory(path)
 }
 
+/// Create a symbolic link at `link_path` pointing at `target`
+pub fn symlink(target: &str, link_path: &str) -> FileSystemResult<()> {
+    VFS.write().symlink(target, link_path)
+}
+
+pub fn readlink(path: &str) -> FileSystemResult<String> {
+    VFS.read().readlink(path)
+}
+
 pub fn mount_filesystem(filesystem: Box<dyn FileSystem>, mount_point: &str) -> FileSystemResult<()> {
     VFS.write().mount(filesystem, mount_point)
 }
@@ -0,0 +1,164 @@
+//! Interest lists of descriptors
+//!
+//! An epoll instance holds the descriptors an event loop waits on, each with the events it
+//! wants and a word of its own handed back with them. `wait` reports the ones ready, level
+//! triggered: a descriptor is reported on every wait for as long as it stays ready.
+//! Descriptors closed while in the list leave it. Epoll descriptors themselves cannot be
+//! waited on.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::poll::{self, PollFd, POLLNVAL};
+use super::{File, FileMetadata, FileSystemError, FileSystemResult, FileType, SeekFrom, O_RDWR, VFS};
+
+/// `epoll_ctl` operations, with the Linux values
+pub const EPOLL_CTL_ADD: u32 = 1;
+pub const EPOLL_CTL_DEL: u32 = 2;
+pub const EPOLL_CTL_MOD: u32 = 3;
+
+/// Event bits, the `poll` ones
+pub const EPOLLIN: u32 = poll::POLLIN as u32;
+pub const EPOLLOUT: u32 = poll::POLLOUT as u32;
+
+/// An event wanted or reported, laid out as Linux's packed `struct epoll_event`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpollEvent {
+    pub events: u32,
+    pub data: u64,
+}
+
+impl EpollEvent {
+    pub const SIZE: usize = 12;
+
+    pub fn from_bytes(bytes: [u8; Self::SIZE]) -> Self {
+        let [e0, e1, e2, e3, d @ ..] = bytes;
+        Self { events: u32::from_ne_bytes([e0, e1, e2, e3]), data: u64::from_ne_bytes(d) }
+    }
+
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..4].copy_from_slice(&self.events.to_ne_bytes());
+        bytes[4..].copy_from_slice(&self.data.to_ne_bytes());
+        bytes
+    }
+}
+
+/// Interest lists of the open epoll instances by descriptor, each by the descriptor watched
+static EPOLLS: Mutex<BTreeMap<u64, BTreeMap<u64, EpollEvent>>> = Mutex::new(BTreeMap::new());
+
+struct EpollFd {
+    fd: u64,
+    metadata: FileMetadata,
+}
+
+impl File for EpollFd {
+    fn read(&mut self, _buffer: &mut [u8]) -> FileSystemResult<usize> {
+        Err(FileSystemError::InvalidOperation)
+    }
+
+    fn write(&mut self, _buffer: &[u8]) -> FileSystemResult<usize> {
+        Err(FileSystemError::InvalidOperation)
+    }
+
+    fn seek(&mut self, _pos: SeekFrom) -> FileSystemResult<u64> {
+        Err(FileSystemError::InvalidOperation)
+    }
+
+    fn truncate(&mut self, _size: u64) -> FileSystemResult<()> {
+        Err(FileSystemError::InvalidOperation)
+    }
+
+    fn flush(&mut self) -> FileSystemResult<()> {
+        Ok(())
+    }
+
+    fn metadata(&self) -> FileSystemResult<FileMetadata> {
+        Ok(self.metadata.clone())
+    }
+
+    fn set_permissions(&mut self, permissions: u32) -> FileSystemResult<()> {
+        self.metadata.permissions = permissions;
+        Ok(())
+    }
+
+    fn poll(&self) -> u16 {
+        0
+    }
+}
+
+impl Drop for EpollFd {
+    fn drop(&mut self) {
+        EPOLLS.lock().remove(&self.fd);
+    }
+}
+
+/// Open an epoll instance with an empty interest list and return its descriptor
+pub fn create() -> FileSystemResult<u64> {
+    let metadata = FileMetadata { file_type: FileType::CharacterDevice, permissions: 0o600, ..FileMetadata::default() };
+    let epfd = VFS.write().install(O_RDWR, |fd| Box::new(EpollFd { fd, metadata }));
+    EPOLLS.lock().insert(epfd, BTreeMap::new());
+    Ok(epfd)
+}
+
+/// Add `fd` to the interest list of `epfd` with `event`, change what it is watched for, or
+/// take it out, as `op` says
+pub fn ctl(epfd: u64, op: u32, fd: u64, event: EpollEvent) -> FileSystemResult<()> {
+    let open = VFS.read().readiness(fd).is_some();
+    let mut epolls = EPOLLS.lock();
+    if fd == epfd || epolls.contains_key(&fd) {
+        return Err(FileSystemError::InvalidOperation);
+    }
+    let interests = epolls.get_mut(&epfd).ok_or(FileSystemError::NotFound)?;
+    match op {
+        EPOLL_CTL_ADD if !open => Err(FileSystemError::NotFound),
+        EPOLL_CTL_ADD if interests.contains_key(&fd) => Err(FileSystemError::AlreadyExists),
+        EPOLL_CTL_ADD => {
+            interests.insert(fd, event);
+            Ok(())
+        }
+        EPOLL_CTL_MOD => {
+            *interests.get_mut(&fd).ok_or(FileSystemError::NotFound)? = event;
+            Ok(())
+        }
+        EPOLL_CTL_DEL => interests.remove(&fd).map(|_| ()).ok_or(FileSystemError::NotFound),
+        _ => Err(FileSystemError::InvalidOperation),
+    }
+}
+
+/// Up to `max_events` of the descriptors in the interest list of `epfd` that are ready,
+/// each as the events ready among those wanted with its word. When none is, wait until one
+/// is or `timeout_ns` passes, as `poll` does
+pub fn wait(epfd: u64, max_events: usize, timeout_ns: Option<u64>) -> FileSystemResult<Vec<EpollEvent>> {
+    let deadline_ns = timeout_ns.map(|timeout| crate::time::get_precise_time_ns().saturating_add(timeout));
+    loop {
+        let interests: Vec<(u64, EpollEvent)> = EPOLLS.lock().get(&epfd)
+            .ok_or(FileSystemError::NotFound)?
+            .iter()
+            .map(|(&fd, &event)| (fd, event))
+            .collect();
+        let mut fds: Vec<PollFd> = interests.iter().map(|&(fd, event)| PollFd::new(fd, event.events as u16)).collect();
+        let remaining_ns = deadline_ns.map(|deadline| deadline.saturating_sub(crate::time::get_precise_time_ns()));
+        poll::poll(&mut fds, remaining_ns);
+
+        let closed: Vec<u64> = interests.iter().zip(&fds)
+            .filter(|(_, entry)| entry.revents & POLLNVAL != 0)
+            .map(|(&(fd, _), _)| fd)
+            .collect();
+        if let Some(interests) = EPOLLS.lock().get_mut(&epfd) {
+            interests.retain(|fd, _| !closed.contains(fd));
+        }
+        let ready: Vec<EpollEvent> = interests.iter().zip(&fds)
+            .filter(|(_, entry)| entry.revents != 0 && entry.revents & POLLNVAL == 0)
+            .map(|(&(_, event), entry)| EpollEvent { events: entry.revents as u32, data: event.data })
+            .take(max_events)
+            .collect();
+        // Closed descriptors end the poll as well: wait on for the others unless time is up
+        let timed_out = deadline_ns.is_some_and(|deadline| crate::time::get_precise_time_ns() >= deadline);
+        if !ready.is_empty() || closed.is_empty() || timed_out {
+            return Ok(ready);
+        }
+    }
+}
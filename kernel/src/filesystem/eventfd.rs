@@ -0,0 +1,106 @@
+//! Event counters as descriptors
+//!
+//! An eventfd holds a 64-bit counter. Writing 8 bytes adds their value to it; reading 8
+//! bytes returns the counter and resets it to 0, or, with `EFD_SEMAPHORE`, returns 1 and
+//! takes 1 off. Reading a zero counter waits, as does a write that would take the counter
+//! past `EVENTFD_MAX`, unless the descriptor is non-blocking. The descriptor polls readable
+//! while the counter is above 0, so one thread can wake another's event loop through it.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+
+use super::poll::{WaitQueue, POLLIN, POLLOUT};
+use super::{File, FileMetadata, FileSystemError, FileSystemResult, FileType, SeekFrom, O_NONBLOCK, O_RDWR, VFS};
+
+/// `eventfd` flags, with the Linux values
+pub const EFD_SEMAPHORE: u32 = 0x1;
+pub const EFD_NONBLOCK: u32 = O_NONBLOCK;
+
+/// Highest value the counter holds
+pub const EVENTFD_MAX: u64 = u64::MAX - 1;
+
+const VALUE_SIZE: usize = 8;
+
+struct EventFd {
+    counter: u64,
+    semaphore: bool,
+    queue: Arc<WaitQueue>,
+    metadata: FileMetadata,
+}
+
+impl File for EventFd {
+    fn read(&mut self, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        let value_bytes = buffer.get_mut(..VALUE_SIZE).ok_or(FileSystemError::InvalidOperation)?;
+        if self.counter == 0 {
+            return Err(FileSystemError::WouldBlock);
+        }
+        let value = if self.semaphore { 1 } else { self.counter };
+        self.counter -= value;
+        value_bytes.copy_from_slice(&value.to_ne_bytes());
+        // Writers may have been waiting for room
+        self.queue.wake_all();
+        Ok(VALUE_SIZE)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> FileSystemResult<usize> {
+        let value = buffer.get(..VALUE_SIZE)
+            .and_then(|bytes| <[u8; VALUE_SIZE]>::try_from(bytes).ok())
+            .map(u64::from_ne_bytes)
+            .filter(|&value| value <= EVENTFD_MAX)
+            .ok_or(FileSystemError::InvalidOperation)?;
+        if value > EVENTFD_MAX - self.counter {
+            return Err(FileSystemError::WouldBlock);
+        }
+        self.counter += value;
+        if value > 0 {
+            self.queue.wake_all();
+        }
+        Ok(VALUE_SIZE)
+    }
+
+    fn seek(&mut self, _pos: SeekFrom) -> FileSystemResult<u64> {
+        Err(FileSystemError::InvalidOperation)
+    }
+
+    fn truncate(&mut self, _size: u64) -> FileSystemResult<()> {
+        Err(FileSystemError::InvalidOperation)
+    }
+
+    fn flush(&mut self) -> FileSystemResult<()> {
+        Ok(())
+    }
+
+    fn metadata(&self) -> FileSystemResult<FileMetadata> {
+        Ok(self.metadata.clone())
+    }
+
+    fn set_permissions(&mut self, permissions: u32) -> FileSystemResult<()> {
+        self.metadata.permissions = permissions;
+        Ok(())
+    }
+
+    fn poll(&self) -> u16 {
+        let readable = if self.counter > 0 { POLLIN } else { 0 };
+        let writable = if self.counter < EVENTFD_MAX { POLLOUT } else { 0 };
+        readable | writable
+    }
+
+    fn wait_queue(&self) -> Option<Arc<WaitQueue>> {
+        Some(self.queue.clone())
+    }
+}
+
+/// Open an eventfd with its counter at `initval`, taking `EFD_SEMAPHORE` and `EFD_NONBLOCK`
+/// in `flags`, and return its descriptor
+pub fn create(initval: u64, flags: u32) -> FileSystemResult<u64> {
+    if flags & !(EFD_SEMAPHORE | EFD_NONBLOCK) != 0 || initval > EVENTFD_MAX {
+        return Err(FileSystemError::InvalidOperation);
+    }
+    let eventfd = EventFd {
+        counter: initval,
+        semaphore: flags & EFD_SEMAPHORE != 0,
+        queue: Arc::new(WaitQueue::new()),
+        metadata: FileMetadata { file_type: FileType::CharacterDevice, permissions: 0o600, ..FileMetadata::default() },
+    };
+    Ok(VFS.write().install(O_RDWR | (flags & EFD_NONBLOCK), |_| Box::new(eventfd)))
+}
@@ -0,0 +1,304 @@
+//! Filesystem watches as descriptors
+//!
+//! An inotify descriptor gathers the events of any number of watches, each added with a
+//! mask of the `IN_*` events it wants and identified by a watch descriptor. Reads return
+//! whole records laid out as Linux's `struct inotify_event`, naming the entry an event
+//! concerns relative to the watched directory, or with no name when it concerns the watched
+//! path itself, and wait while there are none unless the descriptor is non-blocking. The
+//! descriptor polls readable while records are queued. The events come from `watch` sinks,
+//! so they are raised by the same VFS paths; `IN_RECURSIVE` watches report entries below
+//! subdirectories too, with names that are paths.
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::poll::{WaitQueue, POLLIN};
+use super::watch::{self, WatchEvent, WatchId, WatchSink, MAX_QUEUED_EVENTS};
+use super::watch::{WATCH_CREATED, WATCH_DELETED, WATCH_MODIFIED, WATCH_RECURSIVE, WATCH_RENAMED};
+use super::{File, FileMetadata, FileSystemError, FileSystemResult, FileType, SeekFrom, O_NONBLOCK, O_RDONLY, VFS};
+
+/// `inotify_init1` flag, with the Linux value
+pub const IN_NONBLOCK: u32 = O_NONBLOCK;
+
+/// Event bits, with the Linux values
+pub const IN_MODIFY: u32 = 0x2;
+pub const IN_MOVED_FROM: u32 = 0x40;
+pub const IN_MOVED_TO: u32 = 0x80;
+pub const IN_CREATE: u32 = 0x100;
+pub const IN_DELETE: u32 = 0x200;
+pub const IN_DELETE_SELF: u32 = 0x400;
+pub const IN_MOVE_SELF: u32 = 0x800;
+pub const IN_ALL_EVENTS: u32 = IN_MODIFY | IN_MOVED_FROM | IN_MOVED_TO | IN_CREATE | IN_DELETE | IN_DELETE_SELF | IN_MOVE_SELF;
+/// Reported whatever the mask: events were dropped, or a watch is gone
+pub const IN_Q_OVERFLOW: u32 = 0x4000;
+pub const IN_IGNORED: u32 = 0x8000;
+/// Watch flag, a RaeenOS extension: also report entries below subdirectories
+pub const IN_RECURSIVE: u32 = 0x0800_0000;
+
+/// One event as read from an inotify descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InotifyEvent {
+    /// -1 for `IN_Q_OVERFLOW`
+    pub wd: i32,
+    pub mask: u32,
+    /// Shared by the `IN_MOVED_FROM` and `IN_MOVED_TO` of one rename
+    pub cookie: u32,
+    pub name: String,
+}
+
+impl InotifyEvent {
+    pub const HEADER_SIZE: usize = 16;
+
+    /// Bytes the name takes, NUL terminated and padded to a multiple of the header
+    fn name_size(&self) -> usize {
+        if self.name.is_empty() {
+            0
+        } else {
+            (self.name.len() + 1).next_multiple_of(Self::HEADER_SIZE)
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        Self::HEADER_SIZE + self.name_size()
+    }
+
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.wd.to_ne_bytes());
+        out.extend_from_slice(&self.mask.to_ne_bytes());
+        out.extend_from_slice(&self.cookie.to_ne_bytes());
+        out.extend_from_slice(&(self.name_size() as u32).to_ne_bytes());
+        out.extend_from_slice(self.name.as_bytes());
+        out.resize(out.len() + self.name_size() - self.name.len(), 0);
+    }
+
+    /// The records in `bytes`, as returned by a read, up to any that is cut short
+    pub fn parse(mut bytes: &[u8]) -> Vec<Self> {
+        let mut events = Vec::new();
+        while bytes.len() >= Self::HEADER_SIZE {
+            let field = |at: usize| u32::from_ne_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+            let name_size = field(12) as usize;
+            let Some(name) = bytes.get(Self::HEADER_SIZE..Self::HEADER_SIZE + name_size) else {
+                break;
+            };
+            let name = name.split(|&byte| byte == 0).next().unwrap_or_default();
+            events.push(Self {
+                wd: field(0) as i32,
+                mask: field(4),
+                cookie: field(8),
+                name: String::from_utf8_lossy(name).into_owned(),
+            });
+            bytes = &bytes[Self::HEADER_SIZE + name_size..];
+        }
+        events
+    }
+}
+
+#[derive(Default)]
+struct InotifyState {
+    records: VecDeque<InotifyEvent>,
+    /// `IN_*` mask of each watch
+    masks: BTreeMap<WatchId, u32>,
+    next_cookie: u32,
+}
+
+impl InotifyState {
+    fn push(&mut self, record: InotifyEvent) {
+        // Identical consecutive events, such as a run of writes, are reported once
+        let last = self.records.back();
+        if last == Some(&record) || last.is_some_and(|last| last.mask == IN_Q_OVERFLOW) {
+            return;
+        }
+        if self.records.len() + 1 >= MAX_QUEUED_EVENTS {
+            self.records.push_back(InotifyEvent { wd: -1, mask: IN_Q_OVERFLOW, cookie: 0, name: String::new() });
+        } else {
+            self.records.push_back(record);
+        }
+    }
+}
+
+struct Inotify {
+    state: Mutex<InotifyState>,
+    queue: Arc<WaitQueue>,
+}
+
+/// Where `path` lies relative to `watched`: the empty name for the watched path itself
+fn relative_name(watched: &str, path: &str) -> String {
+    let name = path.strip_prefix(watched).unwrap_or(path);
+    String::from(name.trim_start_matches('/'))
+}
+
+/// The name of `path` when it is an entry of `watched` that the watch reports, directly
+/// inside it or, when `recursive`, at any depth
+fn entry_name(watched: &str, path: &str, recursive: bool) -> Option<String> {
+    if path == watched || !watch::is_at_or_below(path, watched) {
+        return None;
+    }
+    let name = relative_name(watched, path);
+    (recursive || !name.contains('/')).then_some(name)
+}
+
+impl WatchSink for Inotify {
+    fn deliver(&self, id: WatchId, watched: &str, event: &WatchEvent, ended: bool) {
+        {
+            let mut state = self.state.lock();
+            // A watch whose mask is not yet recorded was added moments ago: the watch's own
+            // mask has already filtered the event
+            let mask = state.masks.get(&id).copied().unwrap_or(IN_ALL_EVENTS | IN_RECURSIVE);
+            let recursive = mask & IN_RECURSIVE != 0;
+            let mut records: Vec<(u32, u32, String)> = Vec::new();
+            match event {
+                WatchEvent::Created(path) => records.push((IN_CREATE, 0, relative_name(watched, path))),
+                WatchEvent::Modified(path) => records.push((IN_MODIFY, 0, relative_name(watched, path))),
+                WatchEvent::Deleted(_) if ended => {
+                    records.push((IN_DELETE_SELF, 0, String::new()));
+                    records.push((IN_IGNORED, 0, String::new()));
+                    state.masks.remove(&id);
+                }
+                WatchEvent::Deleted(path) => records.push((IN_DELETE, 0, relative_name(watched, path))),
+                WatchEvent::Renamed { from, .. } if watch::is_at_or_below(watched, from) => {
+                    records.push((IN_MOVE_SELF, 0, String::new()));
+                }
+                WatchEvent::Renamed { from, to } => {
+                    state.next_cookie = state.next_cookie.wrapping_add(1).max(1);
+                    let cookie = state.next_cookie;
+                    records.extend(entry_name(watched, from, recursive).map(|name| (IN_MOVED_FROM, cookie, name)));
+                    records.extend(entry_name(watched, to, recursive).map(|name| (IN_MOVED_TO, cookie, name)));
+                }
+                WatchEvent::Overflow => records.push((IN_Q_OVERFLOW, 0, String::new())),
+            }
+            for (kind, cookie, name) in records {
+                if kind & (mask | IN_IGNORED | IN_Q_OVERFLOW) != 0 {
+                    let wd = if kind == IN_Q_OVERFLOW { -1 } else { id as i32 };
+                    state.push(InotifyEvent { wd, mask: kind, cookie, name });
+                }
+            }
+        }
+        self.queue.wake_all();
+    }
+}
+
+/// Open inotify descriptors
+static INOTIFIES: Mutex<BTreeMap<u64, Arc<Inotify>>> = Mutex::new(BTreeMap::new());
+
+struct InotifyFd {
+    fd: u64,
+    inotify: Arc<Inotify>,
+    metadata: FileMetadata,
+}
+
+impl File for InotifyFd {
+    fn read(&mut self, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        let mut state = self.inotify.state.lock();
+        if state.records.is_empty() {
+            return Err(FileSystemError::WouldBlock);
+        }
+        let mut read = Vec::new();
+        while let Some(record) = state.records.front() {
+            if read.len() + record.size() > buffer.len() {
+                break;
+            }
+            record.write_to(&mut read);
+            state.records.pop_front();
+        }
+        // Too small for the next record
+        if read.is_empty() {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        buffer[..read.len()].copy_from_slice(&read);
+        Ok(read.len())
+    }
+
+    fn write(&mut self, _buffer: &[u8]) -> FileSystemResult<usize> {
+        Err(FileSystemError::InvalidOperation)
+    }
+
+    fn seek(&mut self, _pos: SeekFrom) -> FileSystemResult<u64> {
+        Err(FileSystemError::InvalidOperation)
+    }
+
+    fn truncate(&mut self, _size: u64) -> FileSystemResult<()> {
+        Err(FileSystemError::InvalidOperation)
+    }
+
+    fn flush(&mut self) -> FileSystemResult<()> {
+        Ok(())
+    }
+
+    fn metadata(&self) -> FileSystemResult<FileMetadata> {
+        Ok(self.metadata.clone())
+    }
+
+    fn set_permissions(&mut self, permissions: u32) -> FileSystemResult<()> {
+        self.metadata.permissions = permissions;
+        Ok(())
+    }
+
+    fn poll(&self) -> u16 {
+        if self.inotify.state.lock().records.is_empty() { 0 } else { POLLIN }
+    }
+
+    fn wait_queue(&self) -> Option<Arc<WaitQueue>> {
+        Some(self.inotify.queue.clone())
+    }
+}
+
+impl Drop for InotifyFd {
+    fn drop(&mut self) {
+        INOTIFIES.lock().remove(&self.fd);
+        let watches: Vec<WatchId> = self.inotify.state.lock().masks.keys().copied().collect();
+        for id in watches {
+            let _ = watch::remove_watch(id);
+        }
+    }
+}
+
+/// Open an inotify descriptor with no watches, taking `IN_NONBLOCK` in `flags`
+pub fn create(flags: u32) -> FileSystemResult<u64> {
+    if flags & !IN_NONBLOCK != 0 {
+        return Err(FileSystemError::InvalidOperation);
+    }
+    let inotify = Arc::new(Inotify { state: Mutex::new(InotifyState::default()), queue: Arc::new(WaitQueue::new()) });
+    let metadata = FileMetadata { file_type: FileType::CharacterDevice, permissions: 0o400, ..FileMetadata::default() };
+    let fd_inotify = inotify.clone();
+    let fd = VFS.write().install(O_RDONLY | flags, |fd| Box::new(InotifyFd { fd, inotify: fd_inotify, metadata }));
+    INOTIFIES.lock().insert(fd, inotify);
+    Ok(fd)
+}
+
+/// Watch `path`, which must exist, for the `IN_*` events in `mask` on inotify descriptor
+/// `fd`, and return the watch descriptor its events carry
+pub fn add_watch(fd: u64, path: &str, mask: u32) -> FileSystemResult<WatchId> {
+    if mask & IN_ALL_EVENTS == 0 || mask & !(IN_ALL_EVENTS | IN_RECURSIVE) != 0 {
+        return Err(FileSystemError::InvalidOperation);
+    }
+    let inotify = INOTIFIES.lock().get(&fd).cloned().ok_or(FileSystemError::NotFound)?;
+    let watch_mask = [
+        (IN_CREATE, WATCH_CREATED),
+        (IN_MODIFY, WATCH_MODIFIED),
+        (IN_DELETE | IN_DELETE_SELF, WATCH_DELETED),
+        (IN_MOVED_FROM | IN_MOVED_TO | IN_MOVE_SELF, WATCH_RENAMED),
+        (IN_RECURSIVE, WATCH_RECURSIVE),
+    ]
+    .iter()
+    .filter(|&&(bits, _)| mask & bits != 0)
+    .fold(0, |watch_mask, &(_, bit)| watch_mask | bit);
+    let id = watch::add_sink_watch(path, watch_mask, inotify.clone())?;
+    inotify.state.lock().masks.insert(id, mask);
+    Ok(id)
+}
+
+/// Stop watch `wd` of inotify descriptor `fd`, which reads back as `IN_IGNORED`
+pub fn rm_watch(fd: u64, wd: WatchId) -> FileSystemResult<()> {
+    let inotify = INOTIFIES.lock().get(&fd).cloned().ok_or(FileSystemError::NotFound)?;
+    if inotify.state.lock().masks.remove(&wd).is_none() {
+        return Err(FileSystemError::NotFound);
+    }
+    watch::remove_watch(wd)?;
+    inotify.state.lock().push(InotifyEvent { wd: wd as i32, mask: IN_IGNORED, cookie: 0, name: String::new() });
+    inotify.queue.wake_all();
+    Ok(())
+}
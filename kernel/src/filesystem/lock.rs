@@ -0,0 +1,197 @@
+//! Advisory file locks
+//!
+//! `flock`-style whole-file locks: any number of shared holders or a single exclusive one.
+//! Locks belong to an open file descriptor and are kept per file, by the path the
+//! descriptor was opened at. A request that conflicts either fails with `WouldBlock` or
+//! waits in line, and releases grant the waiting requests in order. Converting a lock
+//! drops it before waiting, as on Linux, so two shared holders both asking for exclusive
+//! cannot deadlock: the first to ask lets the second through. Closing the descriptor or
+//! the exit of the process that took the lock releases it.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::{FileSystemError, FileSystemResult, VFS};
+
+/// `flock` operations, with the Linux values
+pub const LOCK_SH: u32 = 1;
+pub const LOCK_EX: u32 = 2;
+pub const LOCK_UN: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    Shared,
+    Exclusive,
+}
+
+/// Where a descriptor's lock request stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockState {
+    Held(LockMode),
+    Waiting(LockMode),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LockRequest {
+    fd: u64,
+    pid: u64,
+    mode: LockMode,
+}
+
+impl LockRequest {
+    fn conflicts_with(&self, other: &LockRequest) -> bool {
+        self.fd != other.fd && (self.mode == LockMode::Exclusive || other.mode == LockMode::Exclusive)
+    }
+}
+
+#[derive(Debug, Default)]
+struct FileLock {
+    holders: Vec<LockRequest>,
+    waiters: VecDeque<LockRequest>,
+}
+
+impl FileLock {
+    /// Grant waiting requests in order until one conflicts, returning the processes to wake
+    fn grant_waiters(&mut self) -> Vec<u64> {
+        let mut woken = Vec::new();
+        while let Some(waiter) = self.waiters.front().copied() {
+            if self.holders.iter().any(|holder| waiter.conflicts_with(holder)) {
+                break;
+            }
+            self.waiters.pop_front();
+            self.holders.push(waiter);
+            woken.push(waiter.pid);
+        }
+        woken
+    }
+}
+
+/// Lock state of every locked file, by path
+pub struct LockTable {
+    locks: BTreeMap<String, FileLock>,
+}
+
+impl Default for LockTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LockTable {
+    pub const fn new() -> Self {
+        Self { locks: BTreeMap::new() }
+    }
+
+    /// Lock `path` through `fd` for process `pid`, or convert the lock `fd` holds. A
+    /// conflicting request fails with `WouldBlock`, keeping any lock already held, unless
+    /// `wait`, when it gives up the held lock and queues. Returns the resulting state and
+    /// the processes whose queued requests were granted
+    pub fn lock(&mut self, path: &str, fd: u64, pid: u64, mode: LockMode, wait: bool) -> FileSystemResult<(LockState, Vec<u64>)> {
+        let request = LockRequest { fd, pid, mode };
+        let file = self.locks.entry(String::from(path)).or_default();
+        if file.holders.iter().any(|holder| request.conflicts_with(holder)) {
+            if !wait {
+                return Err(FileSystemError::WouldBlock);
+            }
+            file.holders.retain(|holder| holder.fd != fd);
+            file.waiters.retain(|waiter| waiter.fd != fd);
+            file.waiters.push_back(request);
+            return Ok((LockState::Waiting(mode), file.grant_waiters()));
+        }
+        file.holders.retain(|holder| holder.fd != fd);
+        file.holders.push(request);
+        // Downgrading may let shared waiters in
+        Ok((LockState::Held(mode), file.grant_waiters()))
+    }
+
+    /// Drop whatever `fd` holds or waits for, returning the processes granted a lock
+    pub fn unlock(&mut self, fd: u64) -> Vec<u64> {
+        self.release(|request| request.fd == fd)
+    }
+
+    /// Drop the locks and requests of process `pid`
+    pub fn release_process(&mut self, pid: u64) -> Vec<u64> {
+        self.release(|request| request.pid == pid)
+    }
+
+    fn release(&mut self, released: impl Fn(&LockRequest) -> bool) -> Vec<u64> {
+        let mut woken = Vec::new();
+        for file in self.locks.values_mut() {
+            file.holders.retain(|holder| !released(holder));
+            file.waiters.retain(|waiter| !released(waiter));
+            woken.extend(file.grant_waiters());
+        }
+        self.locks.retain(|_, file| !file.holders.is_empty() || !file.waiters.is_empty());
+        woken
+    }
+
+    pub fn state(&self, fd: u64) -> Option<LockState> {
+        self.locks.values().find_map(|file| {
+            let held = file.holders.iter().find(|holder| holder.fd == fd).map(|holder| LockState::Held(holder.mode));
+            held.or_else(|| file.waiters.iter().find(|waiter| waiter.fd == fd).map(|waiter| LockState::Waiting(waiter.mode)))
+        })
+    }
+}
+
+static LOCKS: Mutex<LockTable> = Mutex::new(LockTable::new());
+
+fn wake(pids: Vec<u64>) {
+    for pid in pids {
+        crate::process::unblock_process(pid);
+    }
+}
+
+/// Apply `operation` to the lock of `fd` on behalf of process `pid` without blocking, queueing
+/// the request if `wait` and it conflicts
+pub fn request_lock(fd: u64, pid: u64, operation: u32, wait: bool) -> FileSystemResult<Option<LockState>> {
+    let mode = match operation {
+        LOCK_SH => LockMode::Shared,
+        LOCK_EX => LockMode::Exclusive,
+        LOCK_UN => {
+            let woken = LOCKS.lock().unlock(fd);
+            wake(woken);
+            return Ok(None);
+        }
+        _ => return Err(FileSystemError::InvalidOperation),
+    };
+    let path = VFS.read().file_path(fd).map(String::from).ok_or(FileSystemError::NotFound)?;
+    let (state, woken) = LOCKS.lock().lock(&path, fd, pid, mode, wait)?;
+    wake(woken);
+    Ok(Some(state))
+}
+
+/// Take a shared (`LOCK_SH`) or exclusive (`LOCK_EX`) lock on the file open as `fd`, or
+/// drop it (`LOCK_UN`). A conflicting lock blocks the current process until it is granted,
+/// or fails with `WouldBlock` if `nonblocking`
+pub fn flock(fd: u64, operation: u32, nonblocking: bool) -> FileSystemResult<()> {
+    let state = request_lock(fd, crate::process::get_current_process_id(), operation, !nonblocking)?;
+    if let Some(LockState::Waiting(_)) = state {
+        while let Some(LockState::Waiting(_)) = lock_state(fd) {
+            crate::process::block_current();
+        }
+        // Closing the descriptor while waiting drops the request
+        if lock_state(fd).is_none() {
+            return Err(FileSystemError::NotFound);
+        }
+    }
+    Ok(())
+}
+
+/// The lock `fd` holds or waits for
+pub fn lock_state(fd: u64) -> Option<LockState> {
+    LOCKS.lock().state(fd)
+}
+
+/// Drop the locks of a descriptor being closed
+pub(super) fn release_fd(fd: u64) {
+    let woken = LOCKS.lock().unlock(fd);
+    wake(woken);
+}
+
+/// Drop every lock process `pid` took, as it exits
+pub fn release_process_locks(pid: u64) {
+    let woken = LOCKS.lock().release_process(pid);
+    wake(woken);
+}
@@ -0,0 +1,190 @@
+//! Waiting on descriptors
+//!
+//! Files whose readiness changes, such as eventfds, hand out a `WaitQueue` that they wake
+//! after every change. `poll` checks a set of descriptors and, when none is ready, parks the
+//! caller on their queues until one is or its timeout passes. Blocking reads and writes of
+//! such files wait the same way. A queue counts its wake-ups, so a change landing between
+//! checking a descriptor and parking on its queue is not missed.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::VFS;
+
+/// `poll` event bits, with the Linux values
+pub const POLLIN: u16 = 0x1;
+pub const POLLOUT: u16 = 0x4;
+/// The descriptor is not open
+pub const POLLNVAL: u16 = 0x20;
+
+/// One entry of a `poll` set, laid out as Linux's `struct pollfd`. Negative descriptors are
+/// skipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: u16,
+    pub revents: u16,
+}
+
+impl PollFd {
+    pub const SIZE: usize = 8;
+
+    pub fn new(fd: u64, events: u16) -> Self {
+        Self { fd: fd as i32, events, revents: 0 }
+    }
+
+    pub fn from_bytes(bytes: [u8; Self::SIZE]) -> Self {
+        Self {
+            fd: i32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            events: u16::from_ne_bytes([bytes[4], bytes[5]]),
+            revents: u16::from_ne_bytes([bytes[6], bytes[7]]),
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..4].copy_from_slice(&self.fd.to_ne_bytes());
+        bytes[4..6].copy_from_slice(&self.events.to_ne_bytes());
+        bytes[6..].copy_from_slice(&self.revents.to_ne_bytes());
+        bytes
+    }
+}
+
+#[derive(Debug, Default)]
+struct WaitState {
+    waiters: Vec<u64>,
+    wakes: u64,
+    /// How many waiters at the front were queued before the wake-up under way
+    waking: usize,
+}
+
+/// Processes waiting for a file to change. Timers wake theirs from the timer interrupt, so
+/// the state is only touched with interrupts held off
+#[derive(Debug, Default)]
+pub struct WaitQueue {
+    state: Mutex<WaitState>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self { state: Mutex::new(WaitState { waiters: Vec::new(), wakes: 0, waking: 0 }) }
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut WaitState) -> R) -> R {
+        without_interrupts(|| f(&mut self.state.lock()))
+    }
+
+    /// Times the queue has been woken, taken before checking the file
+    pub fn wakes(&self) -> u64 {
+        self.with_state(|state| state.wakes)
+    }
+
+    /// Queue `pid` unless the queue was woken since it counted `wakes`
+    pub fn register(&self, pid: u64, wakes: u64) -> bool {
+        self.with_state(|state| {
+            if state.wakes != wakes {
+                return false;
+            }
+            if !state.waiters.contains(&pid) {
+                state.waiters.push(pid);
+            }
+            true
+        })
+    }
+
+    pub fn cancel(&self, pid: u64) {
+        self.with_state(|state| {
+            if let Some(index) = state.waiters.iter().position(|&waiter| waiter == pid) {
+                state.waiters.remove(index);
+                if index < state.waking {
+                    state.waking -= 1;
+                }
+            }
+        });
+    }
+
+    pub fn is_waiting(&self, pid: u64) -> bool {
+        self.with_state(|state| state.waiters.contains(&pid))
+    }
+
+    /// Wake everyone queued, after the file has changed. Must not be called under the
+    /// scheduler lock
+    pub fn wake_all(&self) {
+        self.begin_wake();
+        while let Some(pid) = self.next_woken() {
+            crate::process::unblock_process(pid);
+        }
+    }
+
+    /// Count a wake-up of everyone queued so far, to be taken by `next_woken`
+    pub(super) fn begin_wake(&self) {
+        self.with_state(|state| {
+            state.wakes = state.wakes.wrapping_add(1);
+            state.waking = state.waiters.len();
+        });
+    }
+
+    /// The next waiter a wake-up under way has to unblock. Waiters are taken one at a time
+    /// rather than as a list, so the timer interrupt can wake them without allocating or
+    /// freeing
+    pub(super) fn next_woken(&self) -> Option<u64> {
+        self.with_state(|state| {
+            if state.waking == 0 {
+                return None;
+            }
+            state.waking -= 1;
+            Some(state.waiters.remove(0))
+        })
+    }
+}
+
+/// Set `revents` of each entry of `fds` to the events among its `events` that are ready, or
+/// to `POLLNVAL` when its descriptor is not open, and return how many entries have any.
+/// When none has, wait until one does or `timeout_ns` passes: `None` waits for ever and
+/// `Some(0)` not at all
+pub fn poll(fds: &mut [PollFd], timeout_ns: Option<u64>) -> usize {
+    let deadline_ns = timeout_ns.map(|timeout| crate::time::get_precise_time_ns().saturating_add(timeout));
+    loop {
+        let mut queues: Vec<(Arc<WaitQueue>, u64)> = Vec::new();
+        let ready = {
+            let vfs = VFS.read();
+            let mut ready = 0;
+            for entry in fds.iter_mut() {
+                entry.revents = 0;
+                let Ok(fd) = u64::try_from(entry.fd) else {
+                    continue;
+                };
+                match vfs.readiness(fd) {
+                    Some((events, queue)) => {
+                        queues.extend(queue);
+                        entry.revents = events & entry.events;
+                    }
+                    None => entry.revents = POLLNVAL,
+                }
+                if entry.revents != 0 {
+                    ready += 1;
+                }
+            }
+            ready
+        };
+        let now_ns = crate::time::get_precise_time_ns();
+        if ready > 0 || deadline_ns.is_some_and(|deadline| now_ns >= deadline) {
+            return ready;
+        }
+        let pid = crate::process::get_current_process_id();
+        crate::process::block_current_until(deadline_ns, |process| {
+            queues.iter().all(|(queue, wakes)| queue.register(process.pid, *wakes))
+        });
+        for (queue, _) in &queues {
+            queue.cancel(pid);
+        }
+    }
+}
+
+/// Wait until `fd` is ready for one of `events`, or is closed
+pub(super) fn wait_for(fd: u64, events: u16) {
+    poll(&mut [PollFd::new(fd, events)], None);
+}
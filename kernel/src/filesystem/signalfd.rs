@@ -0,0 +1,138 @@
+//! Signals as descriptors
+//!
+//! A signalfd takes the signals in its mask off the handler path: while it is open they
+//! stay pending on the thread group that made it rather than running a handler, and each
+//! read takes the lowest numbered of them pending on any thread of the group, returning it
+//! as a `u32` signal number, as many as fit. The descriptor polls readable while one is
+//! pending, so an event loop waits for signals alongside its other descriptors. A mask has
+//! bit `1 << signal` set for each signal, as `pending_signals` does; SIGKILL and SIGSTOP
+//! are never taken this way.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::process::Signal;
+use super::poll::{WaitQueue, POLLIN};
+use super::{File, FileMetadata, FileSystemError, FileSystemResult, FileType, SeekFrom, O_RDONLY, VFS};
+
+/// Size of the record a read returns per signal
+pub const SIGNAL_RECORD_SIZE: usize = 4;
+
+const UNCATCHABLE: u64 = (1 << Signal::SIGKILL as u8) | (1 << Signal::SIGSTOP as u8);
+
+struct Registration {
+    thread_group: u64,
+    mask: u64,
+    queue: Arc<WaitQueue>,
+}
+
+/// Open signalfds by descriptor. The timer interrupt reads it when it delivers signals, so
+/// it is only locked with interrupts off
+static SIGNALFDS: Mutex<BTreeMap<u64, Registration>> = Mutex::new(BTreeMap::new());
+
+struct SignalFd {
+    fd: u64,
+    thread_group: u64,
+    mask: u64,
+    queue: Arc<WaitQueue>,
+    metadata: FileMetadata,
+}
+
+impl File for SignalFd {
+    fn read(&mut self, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        if buffer.len() < SIGNAL_RECORD_SIZE {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        let mut read = 0;
+        for record in buffer.chunks_exact_mut(SIGNAL_RECORD_SIZE) {
+            let Some(signal) = crate::process::take_pending_signal(self.thread_group, self.mask) else {
+                break;
+            };
+            record.copy_from_slice(&(signal as u32).to_ne_bytes());
+            read += SIGNAL_RECORD_SIZE;
+        }
+        if read == 0 {
+            return Err(FileSystemError::WouldBlock);
+        }
+        Ok(read)
+    }
+
+    fn write(&mut self, _buffer: &[u8]) -> FileSystemResult<usize> {
+        Err(FileSystemError::InvalidOperation)
+    }
+
+    fn seek(&mut self, _pos: SeekFrom) -> FileSystemResult<u64> {
+        Err(FileSystemError::InvalidOperation)
+    }
+
+    fn truncate(&mut self, _size: u64) -> FileSystemResult<()> {
+        Err(FileSystemError::InvalidOperation)
+    }
+
+    fn flush(&mut self) -> FileSystemResult<()> {
+        Ok(())
+    }
+
+    fn metadata(&self) -> FileSystemResult<FileMetadata> {
+        Ok(self.metadata.clone())
+    }
+
+    fn set_permissions(&mut self, permissions: u32) -> FileSystemResult<()> {
+        self.metadata.permissions = permissions;
+        Ok(())
+    }
+
+    fn poll(&self) -> u16 {
+        if crate::process::pending_signals_of(self.thread_group, self.mask) != 0 { POLLIN } else { 0 }
+    }
+
+    fn wait_queue(&self) -> Option<Arc<WaitQueue>> {
+        Some(self.queue.clone())
+    }
+}
+
+impl Drop for SignalFd {
+    fn drop(&mut self) {
+        without_interrupts(|| SIGNALFDS.lock().remove(&self.fd));
+    }
+}
+
+/// Open a signalfd taking the signals in `mask` for `thread_group`, and return its
+/// descriptor
+pub fn create(thread_group: u64, mask: u64) -> FileSystemResult<u64> {
+    let mask = mask & !UNCATCHABLE;
+    let queue = Arc::new(WaitQueue::new());
+    let metadata = FileMetadata { file_type: FileType::CharacterDevice, permissions: 0o400, ..FileMetadata::default() };
+    let signalfd_queue = queue.clone();
+    let fd = VFS.write().install(O_RDONLY, |fd| Box::new(SignalFd { fd, thread_group, mask, queue: signalfd_queue, metadata }));
+    without_interrupts(|| SIGNALFDS.lock().insert(fd, Registration { thread_group, mask, queue }));
+    Ok(fd)
+}
+
+/// Signals of `thread_group` that its open signalfds take, left pending for them rather
+/// than handled
+pub fn routed_signals(thread_group: u64) -> u64 {
+    without_interrupts(|| {
+        SIGNALFDS.lock().values()
+            .filter(|registration| registration.thread_group == thread_group)
+            .fold(0, |mask, registration| mask | registration.mask)
+    })
+}
+
+/// Wake readers of the signalfds of `thread_group` taking `signal`, once it is pending.
+/// Must not be called under the scheduler lock
+pub fn notify(thread_group: u64, signal: Signal) {
+    let queues: Vec<Arc<WaitQueue>> = without_interrupts(|| {
+        SIGNALFDS.lock().values()
+            .filter(|registration| registration.thread_group == thread_group && registration.mask & (1 << signal as u8) != 0)
+            .map(|registration| registration.queue.clone())
+            .collect()
+    });
+    for queue in queues {
+        queue.wake_all();
+    }
+}
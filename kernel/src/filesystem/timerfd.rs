@@ -0,0 +1,214 @@
+//! Timers as descriptors
+//!
+//! A timerfd expires at a deadline on the monotonic clock and, when it has an interval,
+//! every interval after that. Reading 8 bytes returns how many times it has expired since it
+//! was last read or set, waiting for the next expiry while there is none unless the
+//! descriptor is non-blocking, and it polls readable while that count is above 0. Expiries
+//! are counted from the clock whenever a timer is looked at. The timer tick wakes the
+//! waiters of timers that have come due, and the earliest deadline arms the tickless timer
+//! so they wake on time.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::poll::{WaitQueue, POLLIN};
+use super::{File, FileMetadata, FileSystemError, FileSystemResult, FileType, SeekFrom, O_NONBLOCK, O_RDONLY, VFS};
+
+/// The clock timers run on, with the Linux value
+pub const CLOCK_MONOTONIC: u32 = 1;
+
+/// `timerfd_create` flags, with the Linux values
+pub const TFD_NONBLOCK: u32 = O_NONBLOCK;
+/// `timerfd_settime` flag: the expiry is a time on the clock rather than from now
+pub const TFD_TIMER_ABSTIME: u32 = 0x1;
+
+const COUNT_SIZE: usize = 8;
+
+/// When a timer next expires and how often it repeats, in nanoseconds. An expiry of 0
+/// disarms the timer, and an interval of 0 makes it one-shot
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimerSpec {
+    pub value_ns: u64,
+    pub interval_ns: u64,
+}
+
+struct Timer {
+    next_ns: Option<u64>,
+    interval_ns: u64,
+    expirations: u64,
+    queue: Arc<WaitQueue>,
+}
+
+impl Timer {
+    /// Count the expiries due by `now_ns`, returning how many there were
+    fn expire(&mut self, now_ns: u64) -> u64 {
+        let Some(next_ns) = self.next_ns.filter(|&next_ns| now_ns >= next_ns) else {
+            return 0;
+        };
+        let count = match self.interval_ns {
+            0 => {
+                self.next_ns = None;
+                1
+            }
+            interval_ns => {
+                let count = (now_ns - next_ns) / interval_ns + 1;
+                self.next_ns = Some(next_ns.saturating_add(count.saturating_mul(interval_ns)));
+                count
+            }
+        };
+        self.expirations = self.expirations.saturating_add(count);
+        count
+    }
+
+    /// How long from `now_ns` until the timer next expires, 0 when disarmed, and its interval
+    fn spec(&mut self, now_ns: u64) -> TimerSpec {
+        self.expire(now_ns);
+        TimerSpec {
+            value_ns: self.next_ns.map_or(0, |next_ns| next_ns.saturating_sub(now_ns)),
+            interval_ns: self.interval_ns,
+        }
+    }
+}
+
+/// Open timerfds by descriptor. The timer tick reads them, so they are only touched with
+/// interrupts held off
+static TIMERFDS: Mutex<BTreeMap<u64, Timer>> = Mutex::new(BTreeMap::new());
+
+fn with_timer<R>(fd: u64, f: impl FnOnce(&mut Timer) -> R) -> FileSystemResult<R> {
+    without_interrupts(|| TIMERFDS.lock().get_mut(&fd).map(f).ok_or(FileSystemError::NotFound))
+}
+
+struct TimerFd {
+    fd: u64,
+    queue: Arc<WaitQueue>,
+    metadata: FileMetadata,
+}
+
+impl File for TimerFd {
+    fn read(&mut self, buffer: &mut [u8]) -> FileSystemResult<usize> {
+        let count_bytes = buffer.get_mut(..COUNT_SIZE).ok_or(FileSystemError::InvalidOperation)?;
+        let now_ns = crate::time::get_precise_time_ns();
+        let count = with_timer(self.fd, |timer| {
+            timer.expire(now_ns);
+            core::mem::take(&mut timer.expirations)
+        })?;
+        if count == 0 {
+            return Err(FileSystemError::WouldBlock);
+        }
+        count_bytes.copy_from_slice(&count.to_ne_bytes());
+        Ok(COUNT_SIZE)
+    }
+
+    fn write(&mut self, _buffer: &[u8]) -> FileSystemResult<usize> {
+        Err(FileSystemError::InvalidOperation)
+    }
+
+    fn seek(&mut self, _pos: SeekFrom) -> FileSystemResult<u64> {
+        Err(FileSystemError::InvalidOperation)
+    }
+
+    fn truncate(&mut self, _size: u64) -> FileSystemResult<()> {
+        Err(FileSystemError::InvalidOperation)
+    }
+
+    fn flush(&mut self) -> FileSystemResult<()> {
+        Ok(())
+    }
+
+    fn metadata(&self) -> FileSystemResult<FileMetadata> {
+        Ok(self.metadata.clone())
+    }
+
+    fn set_permissions(&mut self, permissions: u32) -> FileSystemResult<()> {
+        self.metadata.permissions = permissions;
+        Ok(())
+    }
+
+    fn poll(&self) -> u16 {
+        let now_ns = crate::time::get_precise_time_ns();
+        let expired = with_timer(self.fd, |timer| {
+            timer.expire(now_ns);
+            timer.expirations > 0
+        });
+        if expired.unwrap_or(false) { POLLIN } else { 0 }
+    }
+
+    fn wait_queue(&self) -> Option<Arc<WaitQueue>> {
+        Some(self.queue.clone())
+    }
+}
+
+impl Drop for TimerFd {
+    fn drop(&mut self) {
+        without_interrupts(|| TIMERFDS.lock().remove(&self.fd));
+    }
+}
+
+/// Open a disarmed timer on `clock`, which must be `CLOCK_MONOTONIC`, taking
+/// `TFD_NONBLOCK` in `flags`, and return its descriptor
+pub fn create(clock: u32, flags: u32) -> FileSystemResult<u64> {
+    if clock != CLOCK_MONOTONIC || flags & !TFD_NONBLOCK != 0 {
+        return Err(FileSystemError::InvalidOperation);
+    }
+    let queue = Arc::new(WaitQueue::new());
+    let metadata = FileMetadata { file_type: FileType::CharacterDevice, permissions: 0o400, ..FileMetadata::default() };
+    let timerfd_queue = queue.clone();
+    let fd = VFS.write().install(O_RDONLY | flags, |fd| Box::new(TimerFd { fd, queue: timerfd_queue, metadata }));
+    let timer = Timer { next_ns: None, interval_ns: 0, expirations: 0, queue };
+    without_interrupts(|| TIMERFDS.lock().insert(fd, timer));
+    Ok(fd)
+}
+
+/// How long until timer `fd` next expires, 0 when disarmed, and its interval
+pub fn gettime(fd: u64) -> FileSystemResult<TimerSpec> {
+    let now_ns = crate::time::get_precise_time_ns();
+    with_timer(fd, |timer| timer.spec(now_ns))
+}
+
+/// Arm timer `fd` to expire as `spec` says, after `spec.value_ns` or, with
+/// `TFD_TIMER_ABSTIME` in `flags`, at that time on the monotonic clock, or disarm it. Drops
+/// any expiries not yet read, and returns the setting it had
+pub fn settime(fd: u64, flags: u32, spec: TimerSpec) -> FileSystemResult<TimerSpec> {
+    if flags & !TFD_TIMER_ABSTIME != 0 {
+        return Err(FileSystemError::InvalidOperation);
+    }
+    let now_ns = crate::time::get_precise_time_ns();
+    let next_ns = match spec.value_ns {
+        0 => None,
+        value_ns if flags & TFD_TIMER_ABSTIME != 0 => Some(value_ns),
+        value_ns => Some(now_ns.saturating_add(value_ns)),
+    };
+    let previous = with_timer(fd, |timer| {
+        let previous = timer.spec(now_ns);
+        timer.next_ns = next_ns;
+        timer.interval_ns = spec.interval_ns;
+        timer.expirations = 0;
+        previous
+    })?;
+    crate::time::schedule_next_timer_deadline();
+    Ok(previous)
+}
+
+/// When the earliest armed timer expires
+pub fn next_deadline_ns() -> Option<u64> {
+    without_interrupts(|| TIMERFDS.lock().values().filter_map(|timer| timer.next_ns).min())
+}
+
+/// Count the expiries of every timer due by `now_ns` and wake their waiters. Called from
+/// the timer tick, so nothing is allocated or freed: the waiters are taken one at a time and
+/// unblocked with the timers unlocked
+pub fn expire_due(now_ns: u64) {
+    without_interrupts(|| {
+        for timer in TIMERFDS.lock().values_mut() {
+            if timer.expire(now_ns) > 0 {
+                timer.queue.begin_wake();
+            }
+        }
+    });
+    while let Some(pid) = without_interrupts(|| TIMERFDS.lock().values().find_map(|timer| timer.queue.next_woken())) {
+        crate::process::unblock_process(pid);
+    }
+}
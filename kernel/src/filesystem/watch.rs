@@ -0,0 +1,235 @@
+//! Filesystem watches
+//!
+//! A watch on a path queues an event whenever the VFS creates, modifies, deletes or renames
+//! that path, or, when it is a directory, one of the entries directly inside it. Paths in
+//! events are absolute with links resolved. Removing a watched path queues a final
+//! `Deleted` for it whatever the mask; once that has been read the watch is gone and its
+//! id no longer valid. Renaming a watched path moves the watch along with it. A recursive
+//! watch on a directory reports entries at any depth below it.
+//!
+//! A watch added with a `WatchSink` hands its events to the sink as they happen instead of
+//! queueing them, and is gone as soon as its final `Deleted` is delivered. Inotify
+//! descriptors are built on this.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::{FileSystemError, FileSystemResult, VFS};
+
+pub type WatchId = u64;
+
+/// Event mask bits, chosen per watch
+pub const WATCH_CREATED: u32 = 0x1;
+pub const WATCH_MODIFIED: u32 = 0x2;
+pub const WATCH_DELETED: u32 = 0x4;
+pub const WATCH_RENAMED: u32 = 0x8;
+pub const WATCH_ALL: u32 = WATCH_CREATED | WATCH_MODIFIED | WATCH_DELETED | WATCH_RENAMED;
+/// Also report entries below subdirectories of the watched directory
+pub const WATCH_RECURSIVE: u32 = 0x10;
+
+/// Events held for a watch before further ones are dropped
+pub const MAX_QUEUED_EVENTS: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchEvent {
+    Created(String),
+    Modified(String),
+    Deleted(String),
+    Renamed { from: String, to: String },
+    /// The queue was full and later events were dropped
+    Overflow,
+}
+
+impl WatchEvent {
+    fn mask(&self) -> u32 {
+        match self {
+            WatchEvent::Created(_) => WATCH_CREATED,
+            WatchEvent::Modified(_) => WATCH_MODIFIED,
+            WatchEvent::Deleted(_) => WATCH_DELETED,
+            WatchEvent::Renamed { .. } => WATCH_RENAMED,
+            WatchEvent::Overflow => WATCH_ALL,
+        }
+    }
+}
+
+/// Receives the events of the watches added with it, as they happen
+pub trait WatchSink: Send + Sync {
+    /// `event` happened on watch `id`, whose path was `watched` just before it; `ended` when
+    /// this is the final `Deleted` and the watch is gone
+    fn deliver(&self, id: WatchId, watched: &str, event: &WatchEvent, ended: bool);
+}
+
+struct Watch {
+    path: String,
+    mask: u32,
+    sink: Option<Arc<dyn WatchSink>>,
+    events: Vec<WatchEvent>,
+    /// The watched path was removed: the watch goes once its events are read
+    removed: bool,
+    /// Processes blocked in `read_events`
+    waiters: Vec<u64>,
+}
+
+impl Watch {
+    fn queue(&mut self, event: WatchEvent) {
+        // Consecutive identical events, such as a run of writes, are reported once
+        if self.events.last() == Some(&event) || self.events.last() == Some(&WatchEvent::Overflow) {
+            return;
+        }
+        if self.events.len() + 1 >= MAX_QUEUED_EVENTS {
+            self.events.push(WatchEvent::Overflow);
+        } else {
+            self.events.push(event);
+        }
+    }
+}
+
+struct WatchTable {
+    watches: BTreeMap<WatchId, Watch>,
+    next_id: WatchId,
+}
+
+static WATCHES: Mutex<WatchTable> = Mutex::new(WatchTable { watches: BTreeMap::new(), next_id: 1 });
+
+/// Whether an event at `path` concerns a watch on `watched`: the path itself or an entry
+/// directly inside it, or, for a recursive watch, at any depth below it
+fn concerns(watched: &str, path: &str, recursive: bool) -> bool {
+    if recursive {
+        return is_at_or_below(path, watched);
+    }
+    let parent = match path.rsplit_once('/') {
+        Some(("", _)) => "/",
+        Some((parent, _)) => parent,
+        None => return false,
+    };
+    path == watched || parent == watched
+}
+
+/// Whether `path` is `ancestor` or lies inside it
+pub(super) fn is_at_or_below(path: &str, ancestor: &str) -> bool {
+    ancestor == "/" || path.strip_prefix(ancestor).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Watch `path`, which must exist, for the events in `mask`
+pub fn add_watch(path: &str, mask: u32) -> FileSystemResult<WatchId> {
+    insert_watch(path, mask, None)
+}
+
+/// Watch `path`, which must exist, for the events in `mask`, handing them to `sink`
+pub fn add_sink_watch(path: &str, mask: u32, sink: Arc<dyn WatchSink>) -> FileSystemResult<WatchId> {
+    insert_watch(path, mask, Some(sink))
+}
+
+fn insert_watch(path: &str, mask: u32, sink: Option<Arc<dyn WatchSink>>) -> FileSystemResult<WatchId> {
+    let path = {
+        let vfs = VFS.read();
+        let resolved = vfs.lookup(path, true)?;
+        vfs.metadata_at(&resolved)?;
+        resolved
+    };
+    let mut table = WATCHES.lock();
+    let id = table.next_id;
+    table.next_id += 1;
+    table.watches.insert(id, Watch { path, mask, sink, events: Vec::new(), removed: false, waiters: Vec::new() });
+    Ok(id)
+}
+
+/// Stop watching, dropping any unread events and waking anyone blocked on the watch
+pub fn remove_watch(id: WatchId) -> FileSystemResult<()> {
+    let watch = WATCHES.lock().watches.remove(&id).ok_or(FileSystemError::NotFound)?;
+    for pid in watch.waiters {
+        crate::process::unblock_process(pid);
+    }
+    Ok(())
+}
+
+/// Take the events queued for watch `id`, forgetting the watch if its path was removed
+fn take_events(table: &mut WatchTable, id: WatchId) -> FileSystemResult<Vec<WatchEvent>> {
+    let watch = table.watches.get_mut(&id).ok_or(FileSystemError::NotFound)?;
+    let events = core::mem::take(&mut watch.events);
+    if watch.removed {
+        table.watches.remove(&id);
+    }
+    Ok(events)
+}
+
+/// The events queued for watch `id`, oldest first, without waiting for any
+pub fn poll_events(id: WatchId) -> FileSystemResult<Vec<WatchEvent>> {
+    take_events(&mut WATCHES.lock(), id)
+}
+
+/// The events queued for watch `id`, blocking the current process until there is one
+pub fn read_events(id: WatchId) -> FileSystemResult<Vec<WatchEvent>> {
+    loop {
+        {
+            let mut table = WATCHES.lock();
+            let watch = table.watches.get_mut(&id).ok_or(FileSystemError::NotFound)?;
+            if !watch.events.is_empty() || watch.removed {
+                return take_events(&mut table, id);
+            }
+            watch.waiters.push(crate::process::get_current_process_id());
+        }
+        crate::process::block_current();
+    }
+}
+
+/// Queue `event` on every watch it concerns, or hand it to their sinks, and wake their
+/// readers. Called by the VFS after each change
+pub(super) fn notify(event: WatchEvent) {
+    let mut woken = Vec::new();
+    let mut delivered: Vec<(Arc<dyn WatchSink>, WatchId, String, WatchEvent, bool)> = Vec::new();
+    {
+        let mut table = WATCHES.lock();
+        for (&id, watch) in table.watches.iter_mut().filter(|(_, watch)| !watch.removed) {
+            let mut moved_to = None;
+            let recursive = watch.mask & WATCH_RECURSIVE != 0;
+            let relevant = match &event {
+                WatchEvent::Created(path) | WatchEvent::Modified(path) => concerns(&watch.path, path, recursive),
+                WatchEvent::Deleted(path) if is_at_or_below(&watch.path, path) => {
+                    let last = WatchEvent::Deleted(watch.path.clone());
+                    watch.removed = true;
+                    match &watch.sink {
+                        Some(sink) => delivered.push((sink.clone(), id, watch.path.clone(), last, true)),
+                        None => {
+                            watch.events.push(last);
+                            woken.append(&mut watch.waiters);
+                        }
+                    }
+                    continue;
+                }
+                WatchEvent::Deleted(path) => concerns(&watch.path, path, recursive),
+                WatchEvent::Renamed { from, to } => {
+                    let relevant = concerns(&watch.path, from, recursive) || concerns(&watch.path, to, recursive);
+                    if is_at_or_below(&watch.path, from) && from != "/" {
+                        moved_to = Some(alloc::format!("{}{}", to, &watch.path[from.len()..]));
+                    }
+                    relevant
+                }
+                WatchEvent::Overflow => false,
+            };
+            if relevant && watch.mask & event.mask() != 0 {
+                match &watch.sink {
+                    Some(sink) => delivered.push((sink.clone(), id, watch.path.clone(), event.clone(), false)),
+                    None => {
+                        watch.queue(event.clone());
+                        woken.append(&mut watch.waiters);
+                    }
+                }
+            }
+            if let Some(path) = moved_to {
+                watch.path = path;
+            }
+        }
+        // Nothing reads a sink's watches, so those removed go now
+        table.watches.retain(|_, watch| !(watch.removed && watch.sink.is_some()));
+    }
+    for (sink, id, watched, event, ended) in delivered {
+        sink.deliver(id, &watched, &event, ended);
+    }
+    for pid in woken {
+        crate::process::unblock_process(pid);
+    }
+}
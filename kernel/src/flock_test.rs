@@ -0,0 +1,115 @@
+//! Advisory File Lock Test
+//! Two kernel threads, each with its own descriptor of one file, contend for its lock:
+//! exclusive locks shut the other thread out or queue it until released, two shared
+//! holders that both ask for exclusive are served one after the other instead of
+//! deadlocking, and closing the descriptor or exiting releases the lock
+
+use alloc::vec::Vec;
+use crate::filesystem::lock::{self, request_lock, LockMode, LockState, LOCK_EX, LOCK_SH, LOCK_UN};
+use crate::filesystem::{self, FileSystemError, O_CREAT, O_RDWR};
+use crate::process;
+use crate::serial::_print;
+use crate::test_threads::parked_thread;
+
+const PATH: &str = "/tmp/flock-test";
+
+fn contend(threads: &mut Vec<u64>, fds: &mut Vec<u64>) -> Result<(), &'static str> {
+    for _ in 0..2 {
+        threads.push(process::spawn_kernel_thread("flock-test", parked_thread).map_err(|_| "Failed to spawn a thread")?);
+        fds.push(filesystem::open(PATH, O_RDWR | O_CREAT).map_err(|_| "Failed to open the test file")?);
+    }
+    let ((a, fd_a), (b, fd_b)) = ((threads[0], fds[0]), (threads[1], fds[1]));
+
+    // Test 1: An exclusive lock shuts out or queues the other thread until released
+    _print(format_args!("[Flock Test] Test 1: Exclusive contention...\n"));
+    if request_lock(fd_a, a, LOCK_EX, false).map_err(|_| "Failed to lock")? != Some(LockState::Held(LockMode::Exclusive)) {
+        return Err("Exclusive lock not taken");
+    }
+    let refused = [LOCK_EX, LOCK_SH].map(|operation| matches!(request_lock(fd_b, b, operation, false), Err(FileSystemError::WouldBlock)));
+    if refused != [true, true] {
+        return Err("Conflicting nonblocking request did not fail with WouldBlock");
+    }
+    if request_lock(fd_b, b, LOCK_EX, true) != Ok(Some(LockState::Waiting(LockMode::Exclusive))) {
+        return Err("Conflicting request not queued");
+    }
+    request_lock(fd_a, a, LOCK_UN, false).map_err(|_| "Failed to unlock")?;
+    if lock::lock_state(fd_b) != Some(LockState::Held(LockMode::Exclusive)) || lock::lock_state(fd_a).is_some() {
+        return Err("Queued request not granted on unlock");
+    }
+    _print(format_args!("[Flock Test] ✓ Thread {} got the lock once thread {} let go\n", b, a));
+
+    // Test 2: Both holding shared and both asking for exclusive does not deadlock
+    _print(format_args!("[Flock Test] Test 2: Shared to exclusive upgrades...\n"));
+    request_lock(fd_b, b, LOCK_SH, false).map_err(|_| "Failed to downgrade")?;
+    if request_lock(fd_a, a, LOCK_SH, false) != Ok(Some(LockState::Held(LockMode::Shared))) {
+        return Err("Second shared lock refused");
+    }
+    if request_lock(fd_a, a, LOCK_EX, false) != Err(FileSystemError::WouldBlock) || lock::lock_state(fd_a) != Some(LockState::Held(LockMode::Shared)) {
+        return Err("Failed nonblocking upgrade lost the shared lock");
+    }
+    // A waits, giving up its shared lock, so B's upgrade goes through
+    if request_lock(fd_a, a, LOCK_EX, true) != Ok(Some(LockState::Waiting(LockMode::Exclusive))) || request_lock(fd_b, b, LOCK_EX, true) != Ok(Some(LockState::Held(LockMode::Exclusive))) {
+        return Err("Upgrades deadlocked");
+    }
+    // Downgrading keeps A waiting behind B's shared lock, unlocking lets it in
+    request_lock(fd_b, b, LOCK_SH, false).map_err(|_| "Failed to downgrade")?;
+    if lock::lock_state(fd_a) != Some(LockState::Waiting(LockMode::Exclusive)) {
+        return Err("Exclusive waiter granted beside a shared holder");
+    }
+    request_lock(fd_b, b, LOCK_UN, false).map_err(|_| "Failed to unlock")?;
+    if lock::lock_state(fd_a) != Some(LockState::Held(LockMode::Exclusive)) {
+        return Err("Upgrade not granted after the other holder let go");
+    }
+    _print(format_args!("[Flock Test] ✓ Thread {} upgraded first, then thread {}\n", b, a));
+
+    // Test 3: Closing the descriptor or exiting releases the lock
+    _print(format_args!("[Flock Test] Test 3: Release on close and exit...\n"));
+    if request_lock(fd_b, b, LOCK_EX, true) != Ok(Some(LockState::Waiting(LockMode::Exclusive))) {
+        return Err("Request behind a held lock not queued");
+    }
+    filesystem::close(fd_a).map_err(|_| "Failed to close")?;
+    fds.remove(0);
+    if lock::lock_state(fd_b) != Some(LockState::Held(LockMode::Exclusive)) {
+        return Err("Closing the descriptor kept its lock");
+    }
+    process::terminate_process(b);
+    threads.pop();
+    if lock::lock_state(fd_b).is_some() {
+        return Err("Exiting kept the thread's lock");
+    }
+    if !matches!(request_lock(fd_a, a, LOCK_SH, false), Err(FileSystemError::NotFound)) {
+        return Err("Locked a closed descriptor");
+    }
+    _print(format_args!("[Flock Test] ✓ Locks released on close and exit\n"));
+    Ok(())
+}
+
+pub fn run_flock_tests() -> Result<(), &'static str> {
+    let (mut threads, mut fds) = (Vec::new(), Vec::new());
+    let result = contend(&mut threads, &mut fds);
+    for fd in fds {
+        let _ = filesystem::close(fd);
+    }
+    for pid in threads {
+        process::terminate_process(pid);
+    }
+    let _ = filesystem::remove(PATH);
+    result?;
+
+    _print(format_args!("[Flock Test] ✓ All file lock tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for advisory file locks
+pub fn test_flock() {
+    _print(format_args!("[Flock Test] ===========================================\n"));
+    _print(format_args!("[Flock Test]         ADVISORY FILE LOCK TESTS\n"));
+    _print(format_args!("[Flock Test] ===========================================\n"));
+
+    match run_flock_tests() {
+        Ok(_) => _print(format_args!("[Flock Test] ✓ All file lock tests PASSED\n")),
+        Err(e) => _print(format_args!("[Flock Test] ✗ File lock tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Flock Test] ===========================================\n"));
+}
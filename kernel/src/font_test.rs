@@ -0,0 +1,315 @@
+//! TrueType Font Test
+//! Builds a small TrueType font with rectangle, triangle, curved and composite glyphs, loads
+//! it from the VFS, and checks rasterized glyph bitmaps, metrics, kerning, the glyph cache
+//! and text drawn into a window with it as the system font
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use crate::filesystem;
+use crate::graphics::font::{self, Font, FontError};
+use crate::graphics::{self, Color};
+use crate::serial::_print;
+
+const FONT_PATH: &str = "/tmp/font_test.ttf";
+const SIZE: u32 = 20;
+
+fn put16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+/// A glyph of one contour through `points`, with `on_curve` flags
+pub(crate) fn simple_glyph(points: &[(i16, i16, bool)]) -> Vec<u8> {
+    let mut glyph = Vec::new();
+    let (xs, ys) = (points.iter().map(|p| p.0), points.iter().map(|p| p.1));
+    put16(&mut glyph, 1);
+    for bound in [xs.clone().min(), ys.clone().min(), xs.max(), ys.max()] {
+        put16(&mut glyph, bound.unwrap_or(0) as u16);
+    }
+    put16(&mut glyph, points.len() as u16 - 1);
+    put16(&mut glyph, 0);
+    glyph.extend(points.iter().map(|p| p.2 as u8));
+    // Word deltas for every coordinate
+    for axis in 0..2 {
+        let mut previous = 0i16;
+        for point in points {
+            let value = if axis == 0 { point.0 } else { point.1 };
+            put16(&mut glyph, (value - previous) as u16);
+            previous = value;
+        }
+    }
+    glyph
+}
+
+/// A font of 1000 units per em, ascender 800, descender -200 and line gap 100, with glyph
+/// `i` of `glyphs` advancing `advances[i]`, the `cmap` table given and a `kern` table of
+/// `kerning` pairs
+pub(crate) fn assemble_font(glyphs: &[Vec<u8>], advances: &[u16], cmap: &[u8], kerning: &[(u16, u16, i16)]) -> Vec<u8> {
+    let (mut glyf, mut loca) = (Vec::new(), Vec::new());
+    for glyph in glyphs {
+        put16(&mut loca, (glyf.len() / 2) as u16);
+        glyf.extend_from_slice(glyph);
+        // Short offsets count words
+        glyf.resize((glyf.len() + 1) & !1, 0);
+    }
+    put16(&mut loca, (glyf.len() / 2) as u16);
+
+    let mut head = Vec::new();
+    for value in [0x0001_0000u32, 0, 0, 0x5F0F_3CF5] {
+        put32(&mut head, value);
+    }
+    put16(&mut head, 0);
+    put16(&mut head, 1000);
+    head.resize(50, 0);
+    put16(&mut head, 0);
+    put16(&mut head, 0);
+
+    let mut hhea = Vec::new();
+    put32(&mut hhea, 0x0001_0000);
+    for value in [800i16, -200, 100] {
+        put16(&mut hhea, value as u16);
+    }
+    hhea.resize(34, 0);
+    put16(&mut hhea, advances.len() as u16);
+
+    let mut maxp = Vec::new();
+    put32(&mut maxp, 0x0000_5000);
+    put16(&mut maxp, glyphs.len() as u16);
+
+    let mut hmtx = Vec::new();
+    for &advance in advances {
+        put16(&mut hmtx, advance);
+        put16(&mut hmtx, 0);
+    }
+
+    let mut kern = Vec::new();
+    for value in [0, 1, 0, 14 + 6 * kerning.len() as u16, 0x0001, kerning.len() as u16, 6, 0, 0] {
+        put16(&mut kern, value);
+    }
+    for &(left, right, value) in kerning {
+        for value in [left, right, value as u16] {
+            put16(&mut kern, value);
+        }
+    }
+
+    let tables: [(&[u8; 4], &[u8]); 8] = [
+        (b"cmap", cmap),
+        (b"glyf", &glyf),
+        (b"head", &head),
+        (b"hhea", &hhea),
+        (b"hmtx", &hmtx),
+        (b"kern", &kern),
+        (b"loca", &loca),
+        (b"maxp", &maxp),
+    ];
+    let mut font = Vec::new();
+    put32(&mut font, 0x0001_0000);
+    for value in [tables.len() as u16, 128, 3, 0] {
+        put16(&mut font, value);
+    }
+    let mut offset = 12 + tables.len() * 16;
+    for (tag, data) in &tables {
+        font.extend_from_slice(*tag);
+        put32(&mut font, 0);
+        put32(&mut font, offset as u32);
+        put32(&mut font, data.len() as u32);
+        offset += (data.len() + 3) & !3;
+    }
+    for (_, data) in &tables {
+        font.extend_from_slice(data);
+        font.resize((font.len() + 3) & !3, 0);
+    }
+    font
+}
+
+/// A `cmap` table mapping each character of `mapping`, all in the Basic Multilingual Plane,
+/// to its glyph
+pub(crate) fn unicode_cmap(mapping: &[(char, u16)]) -> Vec<u8> {
+    let mut mapping = mapping.to_vec();
+    mapping.sort_by_key(|&(ch, _)| ch);
+    // One segment per character, then the closing segment
+    let segments = mapping.len() as u16 + 1;
+    let mut cmap = Vec::new();
+    for value in [0, 1, 3, 1, 0, 0x0C, 4, 16 + 8 * segments, 0, 2 * segments, 0, 0, 0] {
+        put16(&mut cmap, value);
+    }
+    let codes: Vec<u16> = mapping.iter().map(|&(ch, _)| ch as u16).chain([0xFFFF]).collect();
+    codes.iter().for_each(|&code| put16(&mut cmap, code));
+    put16(&mut cmap, 0);
+    codes.iter().for_each(|&code| put16(&mut cmap, code));
+    for (i, &code) in codes.iter().enumerate() {
+        put16(&mut cmap, mapping.get(i).map_or(1, |&(_, glyph)| glyph.wrapping_sub(code)));
+    }
+    codes.iter().for_each(|_| put16(&mut cmap, 0));
+    cmap
+}
+
+/// Glyphs for A (rectangle), B (A moved right as a composite), O (curves through off-curve
+/// points only) and V (triangle), kerning A–V by -100
+fn build_font() -> Vec<u8> {
+    let mut composite = Vec::new();
+    for value in [0xFFFF, 100, 0, 800, 700, 0x0003, 1, 200, 0] {
+        put16(&mut composite, value);
+    }
+    let glyphs = [
+        Vec::new(),
+        simple_glyph(&[(100, 0, true), (100, 700, true), (600, 700, true), (600, 0, true)]),
+        composite,
+        simple_glyph(&[(0, 700, true), (500, 700, true), (250, 0, true)]),
+        simple_glyph(&[(100, 100, false), (100, 600, false), (600, 600, false), (600, 100, false)]),
+    ];
+
+    // Format 4: A–B and V by delta, O through the glyph id array
+    let mut cmap = Vec::new();
+    for value in [0, 1, 3, 1, 0, 0x0C] {
+        put16(&mut cmap, value);
+    }
+    for value in [4, 50, 0, 8, 8, 2, 0, 0x42, 0x4F, 0x56, 0xFFFF, 0, 0x41, 0x4F, 0x56, 0xFFFF] {
+        put16(&mut cmap, value);
+    }
+    for value in [1u16.wrapping_sub(0x41), 0, 3u16.wrapping_sub(0x56), 1, 0, 6, 0, 0, 4] {
+        put16(&mut cmap, value);
+    }
+
+    assemble_font(&glyphs, &[500, 700, 900, 500, 700], &cmap, &[(1, 3, -100)])
+}
+
+fn write_font(data: &[u8]) -> Result<(), &'static str> {
+    let _ = filesystem::remove(FONT_PATH);
+    filesystem::create_file(FONT_PATH).map_err(|_| "Font file not created")?;
+    let fd = filesystem::open_file(FONT_PATH).map_err(|_| "Font file not opened")?;
+    let written = filesystem::write_file(fd, data);
+    let _ = filesystem::close_file(fd);
+    written.map_err(|_| "Font file not written").map(|_| ())
+}
+
+pub fn run_font_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Font Test] Starting TrueType font tests...\n"));
+    let data = build_font();
+
+    // Test 1: A font loaded from the VFS maps characters to glyphs and scales its metrics
+    _print(format_args!("[Font Test] Test 1: Loading and metrics...\n"));
+    write_font(&data)?;
+    let mut font = Font::load(FONT_PATH).map_err(|_| "Font not loaded")?;
+    if font.units_per_em() != 1000 || font.num_glyphs() != 5 {
+        return Err("Font header read wrong");
+    }
+    let indices = ['A', 'B', 'V', 'O', 'Z'].map(|ch| font.glyph_index(ch));
+    if indices != [1, 2, 3, 4, 0] {
+        return Err("Characters mapped to the wrong glyphs");
+    }
+    let metrics = font.line_metrics(SIZE);
+    if (metrics.ascent, metrics.descent, metrics.line_gap, metrics.line_height) != (16.0, -4.0, 2.0, 22.0) {
+        return Err("Line metrics not scaled to the size");
+    }
+    if font.advance('A', SIZE) != 14.0 || font.kerning('A', 'V', SIZE) != -2.0 || font.kerning('V', 'A', SIZE) != 0.0 {
+        return Err("Advance or kerning wrong");
+    }
+    if font.text_width("AV", SIZE) != 22.0 {
+        return Err("Kerning not applied to the text width");
+    }
+    _print(format_args!("[Font Test] ✓ 5 glyphs, 22 px lines and A–V kerning at 20 px\n"));
+
+    // Test 2: Rasterized bitmaps have the outline's extent at the requested size
+    _print(format_args!("[Font Test] Test 2: Rasterizing glyphs...\n"));
+    let a = font.glyph('A', SIZE).map_err(|_| "A not rasterized")?;
+    if (a.width, a.height, a.left, a.top, a.advance) != (10, 14, 2, 14, 14.0) {
+        return Err("A bitmap does not match its outline and advance");
+    }
+    if a.coverage.iter().any(|&coverage| coverage != 255) {
+        return Err("Pixel-aligned rectangle not fully covered");
+    }
+    let b = font.glyph('B', SIZE).map_err(|_| "B not rasterized")?;
+    if (b.width, b.height, b.left, b.advance) != (10, 14, 6, 18.0) || b.coverage != a.coverage {
+        return Err("Composite glyph not placed at its offset");
+    }
+    let v = font.glyph('V', SIZE).map_err(|_| "V not rasterized")?;
+    let at = |x: u32, y: u32| v.coverage[(y * v.width + x) as usize];
+    if (v.width, v.height, v.left, v.top) != (10, 14, 0, 14) || at(5, 0) != 255 || at(0, 13) != 0 || at(9, 13) != 0 {
+        return Err("Triangle rasterized wrong");
+    }
+    // Anti-aliasing leaves the pixels along the slanted edges partly covered
+    if !v.coverage.iter().any(|&coverage| coverage > 0 && coverage < 255) {
+        return Err("Slanted edges not anti-aliased");
+    }
+    let o = font.glyph('O', SIZE).map_err(|_| "O not rasterized")?;
+    let at = |x: u32, y: u32| o.coverage[(y * o.width + x) as usize];
+    if (o.width, o.height, o.left, o.top) != (10, 10, 2, 12) || at(5, 5) != 255 || at(0, 0) >= 128 {
+        return Err("Curved glyph rasterized wrong");
+    }
+    let space = font.glyph(' ', SIZE).map_err(|_| "Space not rasterized")?;
+    if space.width != 0 || !space.coverage.is_empty() || space.advance != 10.0 {
+        return Err("Glyph without an outline not empty");
+    }
+    _print(format_args!("[Font Test] ✓ Rectangle, composite, triangle and curve rasterized\n"));
+
+    // Test 3: The cache hands back the glyph rasterized before, per codepoint and size
+    _print(format_args!("[Font Test] Test 3: Glyph cache...\n"));
+    let again = font.glyph('A', SIZE).map_err(|_| "A not rasterized")?;
+    let larger = font.glyph('A', 2 * SIZE).map_err(|_| "A not rasterized")?;
+    if !Arc::ptr_eq(&a, &again) || Arc::ptr_eq(&a, &larger) || (larger.width, larger.height) != (20, 28) {
+        return Err("Cache did not key glyphs by codepoint and size");
+    }
+    if font.cached_glyphs() != 6 {
+        return Err("Cache holds the wrong number of glyphs");
+    }
+    _print(format_args!("[Font Test] ✓ Repeated glyph served from the cache\n"));
+
+    // Test 4: draw_text uses the system font and falls back to the bitmap font without one
+    _print(format_args!("[Font Test] Test 4: Drawing text with the system font...\n"));
+    font::load_system_font(FONT_PATH, SIZE).map_err(|_| "System font not loaded")?;
+    let window = graphics::create_window("Font", 0, 0, 40, 30, 1);
+    let drawn = graphics::draw_text(window, 0, 0, "AV", Color::WHITE);
+    let (width, height) = (graphics::get_text_width("AV"), graphics::get_text_height());
+    // The A sits between x 2 and 12 and from the line top at 16 - 14 = 2 down to the baseline
+    let pixels = graphics::with_window(window, |window| {
+        window.buffer.as_ref().map(|buffer| [(5, 8), (1, 8), (5, 1), (5, 17)].map(|(x, y)| buffer.get_pixel(x, y)))
+    });
+    font::unload_system_font();
+    let fallback_height = graphics::get_text_height();
+    graphics::destroy_window(window);
+    let _ = filesystem::remove(FONT_PATH);
+    drawn?;
+    if (width, height, fallback_height) != (22, 22, 16) {
+        return Err("Text metrics not taken from the system font");
+    }
+    let blank = Color::TRANSPARENT;
+    if pixels.flatten() != Some([Color::WHITE, blank, blank, blank]) {
+        return Err("Text not drawn with the system font");
+    }
+    _print(format_args!("[Font Test] ✓ Text drawn and measured with the system font\n"));
+
+    // Test 5: Damaged fonts and bad sizes are rejected
+    _print(format_args!("[Font Test] Test 5: Errors...\n"));
+    let mut cff = data.clone();
+    cff[..4].copy_from_slice(b"OTTO");
+    if !matches!(Font::from_bytes(cff), Err(FontError::UnsupportedFormat))
+        || !matches!(Font::from_bytes(data[..data.len() / 2].to_vec()), Err(FontError::Truncated))
+        || !matches!(Font::load("/tmp/no_such_font.ttf"), Err(FontError::Unreadable))
+        || font.glyph('A', 0) != Err(FontError::InvalidSize)
+        || font::load_system_font(FONT_PATH, SIZE) != Err(FontError::Unreadable)
+    {
+        return Err("Invalid font or size accepted");
+    }
+    _print(format_args!("[Font Test] ✓ Invalid fonts and sizes rejected\n"));
+
+    _print(format_args!("[Font Test] ✓ All TrueType font tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for TrueType fonts
+pub fn test_font() {
+    _print(format_args!("[Font Test] ===========================================\n"));
+    _print(format_args!("[Font Test]             TRUETYPE FONT TESTS\n"));
+    _print(format_args!("[Font Test] ===========================================\n"));
+
+    match run_font_tests() {
+        Ok(_) => _print(format_args!("[Font Test] ✓ All TrueType font tests PASSED\n")),
+        Err(e) => _print(format_args!("[Font Test] ✗ TrueType font tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Font Test] ===========================================\n"));
+}
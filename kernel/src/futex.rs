@@ -0,0 +1,411 @@
+//! Futexes
+//!
+//! A futex is an aligned 32-bit word in user memory that threads wait on and wake each
+//! other through. Futexes are keyed by address space and address, so the threads of a
+//! process share them; a word in a shared mapping is keyed by its physical address instead,
+//! so every process mapping it shares it. Plain futexes (`FUTEX_WAIT`/`FUTEX_WAKE`) leave the word's meaning to
+//! user space. Priority-inheritance futexes hold the TID of the owning thread, with
+//! `FUTEX_WAITERS` set while threads wait in the kernel: a thread finding the lock taken
+//! queues and lends the owner its priority, and unlocking hands the lock straight to the
+//! waiter of the highest priority. Robust futexes are listed by each thread in its own
+//! memory, and when a thread exits holding one, its word gets `FUTEX_OWNER_DIED` and a
+//! waiter is woken, or for a priority-inheritance futex handed the lock, to recover it.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use crate::arch::uaccess::{self, UAccessError};
+use crate::process::{self, Priority};
+
+/// `futex` operations, with the Linux values
+pub const FUTEX_WAIT: u32 = 0;
+pub const FUTEX_WAKE: u32 = 1;
+pub const FUTEX_LOCK_PI: u32 = 6;
+pub const FUTEX_UNLOCK_PI: u32 = 7;
+pub const FUTEX_TRYLOCK_PI: u32 = 8;
+/// Accepted with any operation; a futex in a shared mapping is shared with every process
+/// mapping it either way
+pub const FUTEX_PRIVATE_FLAG: u32 = 128;
+
+/// Bits of a priority-inheritance or robust futex word
+pub const FUTEX_WAITERS: u32 = 0x8000_0000;
+pub const FUTEX_OWNER_DIED: u32 = 0x4000_0000;
+pub const FUTEX_TID_MASK: u32 = 0x3fff_ffff;
+
+/// Size of the robust list head `set_robust_list` takes: the pointer to the first entry,
+/// the offset of an entry's futex word from the entry, and the entry being taken or released
+pub const ROBUST_LIST_HEAD_SIZE: u64 = 24;
+/// Most robust list entries walked at exit, so a corrupt or circular list still ends
+const ROBUST_LIST_LIMIT: usize = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FutexError {
+    /// The word no longer held the expected value, or a trylock found the lock taken
+    WouldBlock,
+    InvalidArgument,
+    /// The word could not be read or written
+    Fault,
+    /// The caller already owns the lock
+    Deadlock,
+    /// The caller does not own the lock it unlocks
+    NotOwner,
+}
+
+pub type FutexResult<T> = Result<T, FutexError>;
+
+impl From<UAccessError> for FutexError {
+    fn from(_: UAccessError) -> Self {
+        FutexError::Fault
+    }
+}
+
+/// Address space of the keys of futexes in shared mappings, whose address is physical
+pub const SHARED_ADDRESS_SPACE: u64 = u64::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FutexKey {
+    pub address_space: u64,
+    pub address: u64,
+}
+
+/// Where a thread's request for a priority-inheritance futex stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PiLockState {
+    Acquired,
+    Waiting,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Waiter {
+    pid: u64,
+    priority: Priority,
+}
+
+/// Threads waiting on each futex, in arrival order
+pub struct FutexTable {
+    queues: BTreeMap<FutexKey, VecDeque<Waiter>>,
+}
+
+impl Default for FutexTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FutexTable {
+    pub const fn new() -> Self {
+        Self { queues: BTreeMap::new() }
+    }
+
+    /// Queue `pid`, running at `priority`, on `key`
+    pub fn wait(&mut self, key: FutexKey, pid: u64, priority: Priority) {
+        self.queues.entry(key).or_default().push_back(Waiter { pid, priority });
+    }
+
+    /// Take up to `count` waiters off `key`, longest waiting first
+    pub fn wake(&mut self, key: FutexKey, count: usize) -> Vec<u64> {
+        let Some(queue) = self.queues.get_mut(&key) else {
+            return Vec::new();
+        };
+        let woken = queue.drain(..count.min(queue.len())).map(|waiter| waiter.pid).collect();
+        self.prune(key);
+        woken
+    }
+
+    /// The waiter on `key` that `take_top` takes, with its priority: the highest priority in
+    /// the order the scheduler serves its queues, the longest waiting of equals
+    pub fn top(&self, key: FutexKey) -> Option<(u64, Priority)> {
+        self.queues
+            .get(&key)?
+            .iter()
+            .enumerate()
+            .min_by_key(|&(position, waiter)| (waiter.priority as usize, position))
+            .map(|(_, waiter)| (waiter.pid, waiter.priority))
+    }
+
+    /// Take the waiter `top` names off `key`
+    pub fn take_top(&mut self, key: FutexKey) -> Option<u64> {
+        let (pid, _) = self.top(key)?;
+        if let Some(queue) = self.queues.get_mut(&key) {
+            queue.retain(|waiter| waiter.pid != pid);
+        }
+        self.prune(key);
+        Some(pid)
+    }
+
+    pub fn waiters(&self, key: FutexKey) -> usize {
+        self.queues.get(&key).map_or(0, VecDeque::len)
+    }
+
+    pub fn is_waiting(&self, pid: u64) -> bool {
+        self.queues.values().flatten().any(|waiter| waiter.pid == pid)
+    }
+
+    /// Take `pid` off every queue
+    pub fn cancel(&mut self, pid: u64) {
+        for queue in self.queues.values_mut() {
+            queue.retain(|waiter| waiter.pid != pid);
+        }
+        self.queues.retain(|_, queue| !queue.is_empty());
+    }
+
+    fn prune(&mut self, key: FutexKey) {
+        if self.queues.get(&key).is_some_and(VecDeque::is_empty) {
+            self.queues.remove(&key);
+        }
+    }
+}
+
+static FUTEXES: Mutex<FutexTable> = Mutex::new(FutexTable::new());
+
+/// The key of the futex word at `address` in the current address space, or of the memory
+/// behind it when it lies in a shared mapping
+fn key(address: u64) -> FutexResult<FutexKey> {
+    if address % 4 != 0 {
+        return Err(FutexError::InvalidArgument);
+    }
+    let address_space = crate::vmm::current_address_space().ok_or(FutexError::Fault)?;
+    let virt = x86_64::VirtAddr::try_new(address).map_err(|_| FutexError::Fault)?;
+    match crate::vmm::shared_phys_addr(address_space, virt) {
+        Some(phys) => Ok(FutexKey { address_space: SHARED_ADDRESS_SPACE, address: phys.as_u64() }),
+        None => Ok(FutexKey { address_space, address }),
+    }
+}
+
+/// The TID of thread `pid` as a futex word holds it
+fn tid_word(pid: u64) -> FutexResult<u32> {
+    u32::try_from(pid).ok().filter(|&tid| tid & !FUTEX_TID_MASK == 0).ok_or(FutexError::InvalidArgument)
+}
+
+fn priority_of(pid: u64) -> FutexResult<Priority> {
+    process::with_process(pid, |process| process.priority).ok_or(FutexError::InvalidArgument)
+}
+
+fn wake_all(pids: &[u64]) {
+    for &pid in pids {
+        process::unblock_process(pid);
+    }
+}
+
+/// Whether thread `pid` waits on a futex
+pub fn is_waiting(pid: u64) -> bool {
+    FUTEXES.lock().is_waiting(pid)
+}
+
+/// Block the current thread while it is queued on a futex
+pub fn block_while_queued() {
+    if let Some(pid) = process::block_current_if(|process| is_waiting(process.pid)) {
+        process::wait_until_unblocked(pid);
+    }
+}
+
+/// Queue thread `pid` on the futex at `address` if its word holds `expected`. The word is
+/// read under the table lock, so a wake after a change to it cannot be missed
+pub fn request_wait(address: u64, pid: u64, expected: u32) -> FutexResult<()> {
+    let key = key(address)?;
+    let priority = priority_of(pid)?;
+    let mut table = FUTEXES.lock();
+    if uaccess::read_user_value::<u32>(address)? != expected {
+        return Err(FutexError::WouldBlock);
+    }
+    table.wait(key, pid, priority);
+    Ok(())
+}
+
+/// Wait on the futex at `address` until woken, if its word holds `expected`
+pub fn wait(address: u64, expected: u32) -> FutexResult<()> {
+    request_wait(address, process::get_current_process_id(), expected)?;
+    block_while_queued();
+    Ok(())
+}
+
+/// Wake up to `count` threads waiting on the futex at `address`, returning how many woke
+pub fn wake(address: u64, count: usize) -> FutexResult<usize> {
+    let key = key(address)?;
+    let woken = FUTEXES.lock().wake(key, count);
+    wake_all(&woken);
+    Ok(woken.len())
+}
+
+/// Lend `owner` the priority of the top waiter on `key` if it ranks above `owner`'s own
+fn boost(key: FutexKey, owner: u64) {
+    process::restore_priority(owner);
+    let Some((waiter, priority)) = FUTEXES.lock().top(key) else {
+        return;
+    };
+    if priority_of(owner).is_ok_and(|own| (priority as usize) < (own as usize)) {
+        process::inherit_priority(owner, waiter);
+    }
+}
+
+/// Take the priority-inheritance futex at `address` for thread `pid`. If another thread
+/// owns it and `wait`, `pid` is queued behind it with `FUTEX_WAITERS` set, and the owner is
+/// lent the priority of the top waiter. A lock whose owner exited without releasing it is
+/// taken over with `FUTEX_OWNER_DIED` set
+pub fn request_pi_lock(address: u64, pid: u64, wait: bool) -> FutexResult<PiLockState> {
+    let key = key(address)?;
+    let tid = tid_word(pid)?;
+    let priority = priority_of(pid)?;
+    loop {
+        let word = uaccess::read_user_value::<u32>(address)?;
+        let owner = word & FUTEX_TID_MASK;
+        if owner == tid {
+            return Err(FutexError::Deadlock);
+        }
+        if owner == 0 || !process::is_process_alive(owner as u64) {
+            let died = if owner == 0 { word & FUTEX_OWNER_DIED } else { FUTEX_OWNER_DIED };
+            let table = FUTEXES.lock();
+            let waiters = if table.waiters(key) > 0 { FUTEX_WAITERS } else { 0 };
+            if uaccess::cmpxchg_user_u32(address, word, tid | died | waiters)? == word {
+                return Ok(PiLockState::Acquired);
+            }
+            continue;
+        }
+        if !wait {
+            return Err(FutexError::WouldBlock);
+        }
+        {
+            let mut table = FUTEXES.lock();
+            if uaccess::cmpxchg_user_u32(address, word, word | FUTEX_WAITERS)? != word {
+                continue;
+            }
+            table.wait(key, pid, priority);
+        }
+        boost(key, owner as u64);
+        return Ok(PiLockState::Waiting);
+    }
+}
+
+/// Take the priority-inheritance futex at `address`, waiting for it unless `try_only`
+pub fn lock_pi(address: u64, try_only: bool) -> FutexResult<()> {
+    if request_pi_lock(address, process::get_current_process_id(), !try_only)? == PiLockState::Waiting {
+        block_while_queued();
+    }
+    Ok(())
+}
+
+/// Release the priority-inheritance futex at `address` that thread `pid` owns, handing it
+/// to the top waiter, which inherits from those left behind it. `pid` gets its own priority
+/// back. Returns the new owner, if any
+pub fn unlock_pi(address: u64, pid: u64) -> FutexResult<Option<u64>> {
+    let key = key(address)?;
+    let tid = tid_word(pid)?;
+    let next = loop {
+        let mut table = FUTEXES.lock();
+        let word = uaccess::read_user_value::<u32>(address)?;
+        if word & FUTEX_TID_MASK != tid {
+            return Err(FutexError::NotOwner);
+        }
+        let top = table.top(key);
+        let new = match top {
+            Some((next, _)) => next as u32 | if table.waiters(key) > 1 { FUTEX_WAITERS } else { 0 },
+            None => 0,
+        };
+        if uaccess::cmpxchg_user_u32(address, word, new)? == word {
+            break top.and_then(|_| table.take_top(key));
+        }
+    };
+    process::restore_priority(pid);
+    if let Some(next) = next {
+        boost(key, next);
+        process::unblock_process(next);
+    }
+    Ok(next)
+}
+
+/// What to wake once the futex of an exited owner has been marked
+enum Recovery {
+    Woken(Vec<u64>),
+    HandedTo(FutexKey, u64),
+}
+
+/// Mark the futex at `address` if thread `tid` still owns it: a priority-inheritance futex
+/// goes to its top waiter with `FUTEX_OWNER_DIED` set, and any other is left free with the
+/// bit set and a waiter woken to take it
+fn owner_died(address: u64, tid: u32, pi: bool) -> FutexResult<Option<Recovery>> {
+    let key = key(address)?;
+    loop {
+        let mut table = FUTEXES.lock();
+        let word = uaccess::read_user_value::<u32>(address)?;
+        if word & FUTEX_TID_MASK != tid {
+            return Ok(None);
+        }
+        let top = table.top(key).filter(|_| pi);
+        let new = match top {
+            Some((next, _)) => next as u32 | FUTEX_OWNER_DIED | if table.waiters(key) > 1 { FUTEX_WAITERS } else { 0 },
+            None => (word & FUTEX_WAITERS) | FUTEX_OWNER_DIED,
+        };
+        if uaccess::cmpxchg_user_u32(address, word, new)? != word {
+            continue;
+        }
+        return Ok(Some(match top.and_then(|_| table.take_top(key)) {
+            Some(next) => Recovery::HandedTo(key, next),
+            None => Recovery::Woken(table.wake(key, 1)),
+        }));
+    }
+}
+
+/// The futex words on the robust list at `head`, each with whether it is a
+/// priority-inheritance futex, flagged by bit 0 of the pointer to its entry
+fn robust_futexes(head: u64) -> FutexResult<Vec<(u64, bool)>> {
+    let futex_offset = uaccess::read_user_value::<i64>(head + 8)?;
+    let pending = uaccess::read_user_value::<u64>(head + 16)?;
+    let mut futexes = Vec::new();
+    let mut next = uaccess::read_user_value::<u64>(head)?;
+    while next & !1 != head && futexes.len() < ROBUST_LIST_LIMIT {
+        let entry = next & !1;
+        futexes.push((entry.wrapping_add_signed(futex_offset), next & 1 != 0));
+        next = uaccess::read_user_value::<u64>(entry)?;
+    }
+    // A lock being taken or released when the thread died may not be on the list yet
+    if pending != 0 {
+        let futex = ((pending & !1).wrapping_add_signed(futex_offset), pending & 1 != 0);
+        if !futexes.contains(&futex) {
+            futexes.push(futex);
+        }
+    }
+    Ok(futexes)
+}
+
+/// Run `f` with address space `id` active
+fn in_address_space<T>(id: u64, f: impl FnOnce() -> T) -> Option<T> {
+    interrupts::without_interrupts(|| {
+        let previous = crate::vmm::current_address_space()?;
+        if previous == id {
+            return Some(f());
+        }
+        crate::vmm::switch_address_space(id).ok()?;
+        let result = f();
+        let _ = crate::vmm::switch_address_space(previous);
+        Some(result)
+    })
+}
+
+/// Take thread `pid` off the futex queues as it exits, and recover the robust futexes it
+/// still owns for their waiters
+pub fn exit_thread(pid: u64) {
+    FUTEXES.lock().cancel(pid);
+    let Some((head, address_space_id)) = process::with_process(pid, |process| (process.robust_list, process.address_space_id)) else {
+        return;
+    };
+    let (Some(address_space_id), Ok(tid)) = (address_space_id.filter(|_| head != 0), tid_word(pid)) else {
+        return;
+    };
+    let recovered = in_address_space(address_space_id, || {
+        let futexes = robust_futexes(head).unwrap_or_default();
+        futexes
+            .into_iter()
+            .filter_map(|(address, pi)| owner_died(address, tid, pi).ok().flatten())
+            .collect::<Vec<_>>()
+    });
+    for recovery in recovered.into_iter().flatten() {
+        match recovery {
+            Recovery::Woken(pids) => wake_all(&pids),
+            Recovery::HandedTo(key, next) => {
+                boost(key, next);
+                process::unblock_process(next);
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Futex Test
+//! Checks that futex waiters queue and wake in arrival order with the top waiter the one of
+//! highest priority, that a thread only waits while the word holds what it expects, that a
+//! priority-inheritance futex lends its owner the priority of a waiter above it under
+//! contention and hands the lock over on unlock, and that a thread exiting while it holds a
+//! robust priority-inheritance futex leaves it to the waiter with the owner-dead bit set
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use x86_64::VirtAddr;
+use crate::futex::{self, FutexError, FutexKey, FutexTable, PiLockState, FUTEX_OWNER_DIED, FUTEX_WAITERS};
+use crate::process::{self, CloneFlags, Priority, PriorityInheritanceState, Process};
+use crate::serial::_print;
+use crate::test_threads::{add_blocked, parked_thread};
+use crate::vmm::{self, MappingSource, VmPermissions};
+
+const ENTRY: u64 = 0x40_0000;
+const THREAD_STACK: u64 = 0x7FFF_FFFE_0000;
+/// Offsets in the test page of the lock word, the robust list head and the lock's entry
+const LOCK: u64 = 0;
+const ROBUST_HEAD: u64 = 64;
+const ROBUST_ENTRY: u64 = 128;
+
+/// A parked thread of `parent` at `priority`, with its robust list at `robust_list`
+fn thread_of(parent: u64, priority: Priority, robust_list: u64) -> Result<u64, &'static str> {
+    let thread = process::with_process(parent, |parent| {
+        process::clone_process(parent, CloneFlags::VM | CloneFlags::FILES | CloneFlags::THREAD, VirtAddr::new(ENTRY), VirtAddr::new(THREAD_STACK), 0)
+    });
+    let mut thread = thread.ok_or("Parent gone")?.map_err(|_| "Failed to clone a thread")?;
+    thread.priority = priority;
+    thread.robust_list = robust_list;
+    Ok(add_blocked(thread))
+}
+
+/// Run `f` in the address space of process `pid`
+fn in_space_of<T>(pid: u64, f: impl FnOnce() -> T) -> Result<T, &'static str> {
+    let address_space_id = process::with_process(pid, |process| process.address_space_id)
+        .flatten()
+        .ok_or("Process has no address space")?;
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let previous = vmm::current_address_space().ok_or("No address space active")?;
+        vmm::switch_address_space(address_space_id).map_err(|_| "Failed to switch address space")?;
+        let result = f();
+        vmm::switch_address_space(previous).map_err(|_| "Failed to switch back")?;
+        Ok(result)
+    })
+}
+
+fn priority_of(pid: u64) -> Option<(Priority, PriorityInheritanceState)> {
+    process::with_process(pid, |process| (process.priority, process.rt_params.priority_inheritance))
+}
+
+fn contend(pids: &mut Vec<u64>) -> Result<(), &'static str> {
+    // Test 1: Waiters wake in arrival order, and the top waiter is the highest priority one
+    _print(format_args!("[Futex Test] Test 1: Wait queues...\n"));
+    let mut table = FutexTable::new();
+    let key = FutexKey { address_space: 1, address: 0x1000 };
+    table.wait(key, 10, Priority::Low);
+    table.wait(key, 11, Priority::High);
+    table.wait(key, 12, Priority::High);
+    if table.top(key) != Some((11, Priority::High)) {
+        return Err("Top waiter not the first of the highest priority");
+    }
+    if table.wake(key, 2) != [10, 11] || table.take_top(key) != Some(12) || table.waiters(key) != 0 {
+        return Err("Waiters not woken in arrival order");
+    }
+    table.wait(key, 13, Priority::Normal);
+    table.cancel(13);
+    if table.is_waiting(13) {
+        return Err("Cancelled waiter still queued");
+    }
+
+    let parent = Process::user_process(String::from("futex-test"), VirtAddr::new(ENTRY)).map_err(|_| "Failed to create process")?;
+    let parent = add_blocked(parent);
+    pids.push(parent);
+    let address_space_id = process::with_process(parent, |process| process.address_space_id)
+        .flatten()
+        .ok_or("Process has no address space")?;
+    // Mapped without USER so the test can touch it
+    let page = vmm::map_memory(address_space_id, None, 4096, VmPermissions::READ | VmPermissions::WRITE, false, MappingSource::Anonymous { shared: false })
+        .map_err(|_| "Failed to map memory")?;
+    let [lock, head, entry] = [LOCK, ROBUST_HEAD, ROBUST_ENTRY].map(|offset| page.as_u64() + offset);
+    // SAFETY: `in_space_of` runs these with the mapping's address space active, where the
+    // fault handler backs the page on first access, and nothing else uses the page
+    let word = || unsafe { (lock as *const u32).read_volatile() };
+    let set_word = |value: u32| unsafe { (lock as *mut u32).write_volatile(value) };
+    let write = |address: u64, value: u64| unsafe { (address as *mut u64).write_volatile(value) };
+
+    let waiter = process::spawn_kernel_thread("futex-test", parked_thread).map_err(|_| "Failed to spawn a thread")?;
+    pids.push(waiter);
+    let waited = in_space_of(parent, || {
+        set_word(5);
+        let stale = futex::request_wait(lock, waiter, 4);
+        let queued = futex::request_wait(lock, waiter, 5).is_ok() && futex::is_waiting(waiter);
+        (stale, queued, futex::wake(lock, 8), futex::is_waiting(waiter))
+    })?;
+    if waited != (Err(FutexError::WouldBlock), true, Ok(1), false) {
+        return Err("Wait not queued on the expected word alone, or not woken");
+    }
+    _print(format_args!("[Futex Test] ✓ Woke waiter {} of the futex at {:#x}\n", waiter, lock));
+
+    // Test 2: Contention lends the owner the waiter's priority until it unlocks
+    _print(format_args!("[Futex Test] Test 2: Priority inheritance...\n"));
+    let holder = thread_of(parent, Priority::Low, 0)?;
+    pids.push(holder);
+    let contended = in_space_of(parent, || {
+        set_word(0);
+        [
+            futex::request_pi_lock(lock, holder, true),
+            futex::request_pi_lock(lock, holder, true),
+            futex::request_pi_lock(lock, waiter, false),
+            futex::request_pi_lock(lock, waiter, true),
+        ]
+    })?;
+    let expected = [Ok(PiLockState::Acquired), Err(FutexError::Deadlock), Err(FutexError::WouldBlock), Ok(PiLockState::Waiting)];
+    if contended != expected || in_space_of(parent, word)? != holder as u32 | FUTEX_WAITERS {
+        return Err("Contended lock not queued behind its owner");
+    }
+    let lent = PriorityInheritanceState::Inherited { original_priority: Priority::Low, inherited_from: waiter };
+    if priority_of(holder) != Some((Priority::High, lent)) {
+        return Err("Owner not lent the waiter's priority");
+    }
+    let unlocked = in_space_of(parent, || (futex::unlock_pi(lock, waiter), futex::unlock_pi(lock, holder), word()))?;
+    if unlocked != (Err(FutexError::NotOwner), Ok(Some(waiter)), waiter as u32) || futex::is_waiting(waiter) {
+        return Err("Unlock did not hand the lock to the waiter");
+    }
+    if priority_of(holder) != Some((Priority::Low, PriorityInheritanceState::None)) {
+        return Err("Owner kept the lent priority after unlocking");
+    }
+    if in_space_of(parent, || (futex::unlock_pi(lock, waiter), word()))? != (Ok(None), 0) {
+        return Err("Unlock without waiters did not free the lock");
+    }
+    _print(format_args!("[Futex Test] ✓ Owner {} ran at High until it handed the lock to {}\n", holder, waiter));
+
+    // Test 3: A robust lock whose owner exits goes to the waiter marked owner-dead
+    _print(format_args!("[Futex Test] Test 3: Owner death...\n"));
+    let holder = thread_of(parent, Priority::Low, head)?;
+    pids.push(holder);
+    let contended = in_space_of(parent, || {
+        set_word(0);
+        // A list of the one entry, flagged as a priority-inheritance futex
+        write(head, entry | 1);
+        write(head + 8, lock.wrapping_sub(entry));
+        write(head + 16, 0);
+        write(entry, head);
+        [futex::request_pi_lock(lock, holder, false), futex::request_pi_lock(lock, waiter, true)]
+    })?;
+    if contended != [Ok(PiLockState::Acquired), Ok(PiLockState::Waiting)] {
+        return Err("Robust lock not contended");
+    }
+    process::terminate_process(holder);
+    pids.retain(|&pid| pid != holder);
+    if in_space_of(parent, word)? != waiter as u32 | FUTEX_OWNER_DIED || futex::is_waiting(waiter) {
+        return Err("Waiter not handed the lock of the dead owner");
+    }
+    if in_space_of(parent, || futex::unlock_pi(lock, waiter))? != Ok(None) {
+        return Err("Recovered lock not released");
+    }
+    _print(format_args!("[Futex Test] ✓ Waiter {} recovered the lock of exited owner {}\n", waiter, holder));
+    Ok(())
+}
+
+pub fn run_futex_tests() -> Result<(), &'static str> {
+    let mut pids = Vec::new();
+    let result = contend(&mut pids);
+    for pid in pids.into_iter().rev() {
+        process::terminate_process(pid);
+    }
+    result?;
+
+    _print(format_args!("[Futex Test] ✓ All futex tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for futexes
+pub fn test_futex() {
+    _print(format_args!("[Futex Test] ===========================================\n"));
+    _print(format_args!("[Futex Test]               FUTEX TESTS\n"));
+    _print(format_args!("[Futex Test] ===========================================\n"));
+
+    match run_futex_tests() {
+        Ok(_) => _print(format_args!("[Futex Test] ✓ All futex tests PASSED\n")),
+        Err(e) => _print(format_args!("[Futex Test] ✗ Futex tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Futex Test] ===========================================\n"));
+}
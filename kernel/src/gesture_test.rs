@@ -0,0 +1,204 @@
+//! Gesture Recognition Test
+//! Feeds synthetic multi-touch digitizer reports through the touchscreen driver and the
+//! input handler's gesture recognizer, and checks which gestures come out and where they go
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::drivers::touchscreen::{HidTouchscreen, TouchPoint, TOUCH_REPORT_ID};
+use crate::graphics::{Gesture, Rect, SwipeDirection, WindowEvent, WindowManager};
+use crate::input::{GestureRecognizer, RecognizedGesture};
+use crate::serial::_print;
+
+const PRESSURE: u16 = 120;
+const MS: u64 = 1_000_000;
+
+/// A contact as the digitizer reports it: ID, tip switch, X, Y
+type Contact = (u8, bool, u16, u16);
+
+fn report(contacts: &[Contact]) -> Vec<u8> {
+    let mut report = alloc::vec![TOUCH_REPORT_ID, contacts.len() as u8];
+    for &(id, tip, x, y) in contacts {
+        report.extend_from_slice(&[u8::from(tip), id]);
+        report.extend_from_slice(&x.to_le_bytes());
+        report.extend_from_slice(&y.to_le_bytes());
+        report.extend_from_slice(&PRESSURE.to_le_bytes());
+    }
+    report
+}
+
+/// Pass one report at `time_ms` through the driver and the recognizer
+fn feed(
+    screen: &mut HidTouchscreen,
+    recognizer: &mut GestureRecognizer,
+    time_ms: u64,
+    contacts: &[Contact],
+) -> Result<Vec<RecognizedGesture>, &'static str> {
+    screen.handle_report(&report(contacts), time_ms * MS).map_err(|_| "Touch report rejected")?;
+    let frame = screen.next_frame().ok_or("Touch report produced no frame")?;
+    Ok(recognizer.process_frame(&frame))
+}
+
+fn only(gestures: &[RecognizedGesture]) -> Option<Gesture> {
+    match gestures {
+        [recognized] => Some(recognized.gesture),
+        _ => None,
+    }
+}
+
+pub fn run_gesture_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Gesture Test] Starting gesture recognition tests...\n"));
+    // Logical units map one-to-one onto the screen, so positions below are in pixels
+    let mut screen = HidTouchscreen::new(4095, 4095, 4096, 4096);
+    let mut recognizer = GestureRecognizer::new();
+
+    // Test 1: Digitizer reports decode into scaled contacts whose tip is down
+    _print(format_args!("[Gesture Test] Test 1: Decoding touch reports...\n"));
+    let scaled = HidTouchscreen::new(4095, 4095, 1024, 768);
+    let frame = scaled
+        .parse_report(&report(&[(3, true, 4095, 2048), (4, false, 100, 100)]), 7)
+        .map_err(|_| "Valid touch report rejected")?;
+    if frame.timestamp_ns != 7 || frame.contacts != [TouchPoint { id: 3, x: 1023, y: 383, pressure: PRESSURE }] {
+        return Err("Touch report decoded wrongly");
+    }
+    let mut truncated = report(&[(1, true, 10, 10)]);
+    truncated.pop();
+    let mut foreign = report(&[(1, true, 10, 10)]);
+    foreign[0] = TOUCH_REPORT_ID + 1;
+    if scaled.parse_report(&truncated, 0).is_ok() || scaled.parse_report(&foreign, 0).is_ok() {
+        return Err("Malformed touch report accepted");
+    }
+    _print(format_args!("[Gesture Test] ✓ Contacts scaled to the screen and lifted contacts dropped\n"));
+
+    // Test 2: Single-finger tap, double tap and long press
+    _print(format_args!("[Gesture Test] Test 2: Tap, double tap and long press...\n"));
+    feed(&mut screen, &mut recognizer, 0, &[(1, true, 100, 100)])?;
+    feed(&mut screen, &mut recognizer, 40, &[(1, true, 103, 101)])?;
+    let tap = feed(&mut screen, &mut recognizer, 80, &[(1, false, 103, 101)])?;
+    if tap != [RecognizedGesture { gesture: Gesture::Tap, x: 103, y: 101 }] {
+        return Err("Tap not recognized");
+    }
+    feed(&mut screen, &mut recognizer, 200, &[(2, true, 110, 98)])?;
+    if only(&feed(&mut screen, &mut recognizer, 250, &[])?) != Some(Gesture::DoubleTap) {
+        return Err("Double tap not recognized");
+    }
+    // A third tap starts over rather than doubling again
+    feed(&mut screen, &mut recognizer, 400, &[(1, true, 110, 98)])?;
+    if only(&feed(&mut screen, &mut recognizer, 450, &[])?) != Some(Gesture::Tap) {
+        return Err("Tap after a double tap not recognized");
+    }
+    feed(&mut screen, &mut recognizer, 2000, &[(1, true, 300, 300)])?;
+    if !feed(&mut screen, &mut recognizer, 2300, &[(1, true, 302, 300)])?.is_empty() {
+        return Err("Long press reported early");
+    }
+    let points = recognizer.touch_points();
+    if points != [TouchPoint { id: 1, x: 302, y: 300, pressure: PRESSURE }] {
+        return Err("Held touch point not tracked");
+    }
+    let held = recognizer.tick(2600 * MS);
+    if held != [RecognizedGesture { gesture: Gesture::LongPress, x: 302, y: 300 }] {
+        return Err("Long press not recognized");
+    }
+    if !recognizer.tick(2700 * MS).is_empty() || !feed(&mut screen, &mut recognizer, 2800, &[])?.is_empty() {
+        return Err("Long press reported twice or ended in a tap");
+    }
+    _print(format_args!("[Gesture Test] ✓ Tap, double tap and long press recognized\n"));
+
+    // Test 3: Two fingers spreading pinch, two fingers moving together scroll, a fling swipes
+    _print(format_args!("[Gesture Test] Test 3: Pinch, scroll and swipe...\n"));
+    feed(&mut screen, &mut recognizer, 5000, &[(1, true, 400, 500), (2, true, 600, 500)])?;
+    let zoom = feed(&mut screen, &mut recognizer, 5050, &[(1, true, 350, 500), (2, true, 650, 500)])?;
+    if zoom != [RecognizedGesture { gesture: Gesture::Pinch { scale: 1.5 }, x: 500, y: 500 }] {
+        return Err("Spreading fingers not recognized as a pinch");
+    }
+    let zoom = feed(&mut screen, &mut recognizer, 5100, &[(1, true, 300, 500), (2, true, 700, 500)])?;
+    if only(&zoom) != Some(Gesture::Pinch { scale: 2.0 }) {
+        return Err("Pinch scale not relative to its start");
+    }
+    feed(&mut screen, &mut recognizer, 5150, &[(2, true, 700, 500)])?;
+    if !feed(&mut screen, &mut recognizer, 5200, &[])?.is_empty() {
+        return Err("Ending a pinch reported a gesture");
+    }
+
+    feed(&mut screen, &mut recognizer, 6000, &[(1, true, 400, 500), (2, true, 500, 500)])?;
+    let scroll = feed(&mut screen, &mut recognizer, 6050, &[(1, true, 400, 470), (2, true, 500, 470)])?;
+    if scroll != [RecognizedGesture { gesture: Gesture::Scroll { delta_x: 0, delta_y: -30 }, x: 450, y: 470 }] {
+        return Err("Fingers moving together not recognized as a scroll");
+    }
+    // Once scrolling, a change in spread does not turn into a pinch
+    let scroll = feed(&mut screen, &mut recognizer, 6100, &[(1, true, 390, 450), (2, true, 530, 450)])?;
+    if only(&scroll) != Some(Gesture::Scroll { delta_x: 10, delta_y: -20 }) {
+        return Err("Scroll deltas not relative to the previous event");
+    }
+    if !feed(&mut screen, &mut recognizer, 6150, &[])?.is_empty() {
+        return Err("Ending a scroll reported a gesture");
+    }
+
+    feed(&mut screen, &mut recognizer, 7000, &[(1, true, 100, 400)])?;
+    feed(&mut screen, &mut recognizer, 7080, &[(1, true, 250, 405)])?;
+    feed(&mut screen, &mut recognizer, 7150, &[(1, true, 400, 410)])?;
+    let swipe = feed(&mut screen, &mut recognizer, 7160, &[(1, false, 400, 410)])?;
+    if only(&swipe) != Some(Gesture::Swipe { direction: SwipeDirection::Right, fingers: 1 }) {
+        return Err("Fling not recognized as a swipe");
+    }
+    feed(&mut screen, &mut recognizer, 8000, &[(1, true, 200, 600), (2, true, 260, 600), (3, true, 320, 600)])?;
+    feed(&mut screen, &mut recognizer, 8200, &[(1, true, 200, 400), (2, true, 260, 400), (3, true, 320, 400)])?;
+    if only(&feed(&mut screen, &mut recognizer, 8250, &[])?) != Some(Gesture::Swipe { direction: SwipeDirection::Up, fingers: 3 }) {
+        return Err("Three-finger swipe not recognized");
+    }
+    // The same path traced slowly is a drag, not a swipe
+    feed(&mut screen, &mut recognizer, 9000, &[(1, true, 100, 400)])?;
+    feed(&mut screen, &mut recognizer, 9600, &[(1, true, 400, 410)])?;
+    if !feed(&mut screen, &mut recognizer, 9700, &[])?.is_empty() {
+        return Err("Slow drag recognized as a gesture");
+    }
+    _print(format_args!("[Gesture Test] ✓ Pinch, scroll and swipe told apart with their parameters\n"));
+
+    // Test 4: Gestures reach the focused window in its own coordinates
+    _print(format_args!("[Gesture Test] Test 4: Delivering gestures to the focused window...\n"));
+    let mut wm = WindowManager::new(1024, 768);
+    let photos = wm.create_window(String::from("Photos"), Rect::new(200, 100, 600, 500), 1);
+    let other = wm.create_window(String::from("Other"), Rect::new(0, 0, 100, 100), 2);
+    wm.focus_window(photos);
+    feed(&mut screen, &mut recognizer, 10000, &[(1, true, 400, 300), (2, true, 500, 300)])?;
+    for recognized in feed(&mut screen, &mut recognizer, 10050, &[(1, true, 350, 300), (2, true, 550, 300)])? {
+        wm.dispatch_gesture_event(recognized.gesture, recognized.x, recognized.y);
+    }
+    feed(&mut screen, &mut recognizer, 10100, &[])?;
+    let delivered: Vec<_> = wm
+        .get_window(photos)
+        .map(|window| {
+            window
+                .pending_events
+                .iter()
+                .filter_map(|event| match event {
+                    WindowEvent::Gesture(event) => Some((event.gesture, event.x, event.y)),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if delivered != [(Gesture::Pinch { scale: 2.0 }, 250, 200)] {
+        return Err("Gesture not delivered to the focused window");
+    }
+    if wm.get_window(other).is_none_or(|window| !window.pending_events.is_empty()) {
+        return Err("Gesture delivered to an unfocused window");
+    }
+    _print(format_args!("[Gesture Test] ✓ Gestures delivered to the focused window\n"));
+
+    _print(format_args!("[Gesture Test] ✓ All gesture recognition tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for gesture recognition
+pub fn test_gestures() {
+    _print(format_args!("[Gesture Test] ===========================================\n"));
+    _print(format_args!("[Gesture Test]          GESTURE RECOGNITION TESTS\n"));
+    _print(format_args!("[Gesture Test] ===========================================\n"));
+
+    match run_gesture_tests() {
+        Ok(_) => _print(format_args!("[Gesture Test] ✓ All gesture recognition tests PASSED\n")),
+        Err(e) => _print(format_args!("[Gesture Test] ✗ Gesture recognition tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Gesture Test] ===========================================\n"));
+}
@@ -0,0 +1,158 @@
+//! Software GLES Renderer Test
+//! Renders textured, depth-tested and perspective-projected triangles with the software
+//! OpenGL ES subset and checks the resulting pixels and depth values
+
+use crate::graphics::gles::{GlContext, GlError, Mat4, Vertex};
+use crate::graphics::{self, Color, GraphicsBuffer};
+use crate::serial::_print;
+
+const BLACK: u32 = 0xFF00_0000;
+const RED: u32 = 0xFFFF_0000;
+const GREEN: u32 = 0xFF00_FF00;
+const BLUE: u32 = 0xFF00_00FF;
+const WHITE: u32 = 0xFFFF_FFFF;
+
+fn pixel(target: &GraphicsBuffer, x: u32, y: u32) -> u32 {
+    target.pixels[(y * target.width + x) as usize]
+}
+
+fn triangle(corners: [[f32; 3]; 3], color: Color) -> [Vertex; 3] {
+    corners.map(|position| Vertex::new(position, color, [0.0, 0.0]))
+}
+
+/// A triangle covering the whole viewport at depth `z`
+fn backdrop(z: f32, color: Color) -> [Vertex; 3] {
+    triangle([[-1.0, -1.0, z], [3.0, -1.0, z], [-1.0, 3.0, z]], color)
+}
+
+fn gl(result: Result<(), GlError>) -> Result<(), &'static str> {
+    result.map_err(|_| "Draw call failed")
+}
+
+pub fn run_gles_tests() -> Result<(), &'static str> {
+    _print(format_args!("[GLES Test] Starting software GLES renderer tests...\n"));
+
+    // Test 1: A textured triangle samples the texel under each pixel
+    _print(format_args!("[GLES Test] Test 1: Textured triangle...\n"));
+    let mut ctx = GlContext::new(64, 64).map_err(|_| "Context not created")?;
+    let mut target = GraphicsBuffer::new(64, 64);
+    // Vertex positions in pixels, y down
+    ctx.set_transform(Mat4::ortho(0.0, 64.0, 64.0, 0.0, -1.0, 1.0));
+    let texture = ctx.create_texture(2, 2, &[RED, GREEN, BLUE, WHITE]).map_err(|_| "Texture not created")?;
+    ctx.bind_texture(Some(texture)).map_err(|_| "Texture not bound")?;
+    let textured = |color: Color| {
+        [
+            Vertex::new([0.0, 0.0, 0.0], color, [0.0, 0.0]),
+            Vertex::new([64.0, 0.0, 0.0], color, [1.0, 0.0]),
+            Vertex::new([0.0, 64.0, 0.0], color, [0.0, 1.0]),
+        ]
+    };
+    gl(ctx.clear(&mut target))?;
+    gl(ctx.draw_triangles(&mut target, &textured(Color::WHITE)))?;
+    let samples = [pixel(&target, 8, 8), pixel(&target, 40, 8), pixel(&target, 8, 40), pixel(&target, 40, 40)];
+    if samples != [RED, GREEN, BLUE, BLACK] {
+        return Err("Textured triangle pixels wrong");
+    }
+    // The vertex color modulates the texture, and blends by its alpha when blending is on
+    gl(ctx.clear(&mut target))?;
+    gl(ctx.draw_triangles(&mut target, &textured(Color::rgb(128, 128, 128))))?;
+    if pixel(&target, 8, 8) != 0xFF80_0000 {
+        return Err("Vertex color did not modulate the texture");
+    }
+    ctx.set_clear_color(Color::WHITE);
+    ctx.set_blending(true);
+    gl(ctx.clear(&mut target))?;
+    gl(ctx.draw_triangles(&mut target, &textured(Color::new(255, 255, 255, 128))))?;
+    if pixel(&target, 8, 8) != 0xFFFF_7F7F || pixel(&target, 40, 40) != WHITE {
+        return Err("Translucent triangle not blended over the target");
+    }
+    _print(format_args!("[GLES Test] ✓ Texels sampled, modulated and blended\n"));
+
+    // Test 2: Where triangles overlap, the nearer one wins regardless of drawing order
+    _print(format_args!("[GLES Test] Test 2: Depth-tested overlap...\n"));
+    let mut ctx = GlContext::new(32, 32).map_err(|_| "Context not created")?;
+    let mut target = GraphicsBuffer::new(32, 32);
+    ctx.set_depth_test(true);
+    let far = backdrop(0.5, Color::RED);
+    // Covers the top-left quarter of the target
+    let near = triangle([[-1.0, 1.0, -0.5], [0.0, 1.0, -0.5], [-1.0, 0.0, -0.5]], Color::BLUE);
+    for order in [[&near, &far], [&far, &near]] {
+        gl(ctx.clear(&mut target))?;
+        for vertices in order {
+            gl(ctx.draw_triangles(&mut target, vertices))?;
+        }
+        if pixel(&target, 2, 2) != BLUE || pixel(&target, 28, 28) != RED {
+            return Err("Nearer triangle lost the depth test");
+        }
+        let depth_near = |x: u32, y: u32, expected: f32| ctx.depth_at(x, y).is_some_and(|depth| (depth - expected).abs() < 1e-5);
+        if !depth_near(2, 2, 0.25) || !depth_near(28, 28, 0.75) {
+            return Err("Depth buffer does not hold the nearest depth");
+        }
+    }
+    ctx.set_depth_test(false);
+    gl(ctx.clear(&mut target))?;
+    gl(ctx.draw_triangles(&mut target, &near))?;
+    gl(ctx.draw_triangles(&mut target, &far))?;
+    if pixel(&target, 2, 2) != RED {
+        return Err("Depth test applied while disabled");
+    }
+    // A triangle sloping through another is in front on its left and behind on its right
+    ctx.set_depth_test(true);
+    gl(ctx.clear(&mut target))?;
+    gl(ctx.draw_triangles(&mut target, &backdrop(0.0, Color::GREEN)))?;
+    let sloped = triangle([[-1.0, -1.0, -0.5], [3.0, -1.0, 1.5], [-1.0, 3.0, -0.5]], Color::BLUE);
+    gl(ctx.draw_triangles(&mut target, &sloped))?;
+    if pixel(&target, 4, 16) != BLUE || pixel(&target, 28, 16) != GREEN {
+        return Err("Intersecting triangles not resolved per pixel");
+    }
+    _print(format_args!("[GLES Test] ✓ Nearer triangle wins the depth test\n"));
+
+    // Test 3: A perspective scene rendered into a window, clipped at the near plane
+    _print(format_args!("[GLES Test] Test 3: Perspective rendering into a window...\n"));
+    let window = graphics::create_window("GLES", 0, 0, 64, 48, 1);
+    let mut ctx = GlContext::new(64, 48).map_err(|_| "Context not created")?;
+    ctx.set_transform(Mat4::frustum(-1.0, 1.0, -1.0, 1.0, 1.0, 10.0));
+    let rendered = graphics::with_window_buffer(window, |buffer| -> Result<[u32; 2], GlError> {
+        ctx.clear(buffer)?;
+        // One corner behind the eye: only the part in front of the near plane is drawn
+        ctx.draw_triangles(buffer, &triangle([[-1.0, -1.0, -2.0], [1.0, -1.0, -2.0], [0.0, 1.0, 1.0]], Color::WHITE))?;
+        // Entirely behind the eye
+        ctx.draw_triangles(buffer, &triangle([[-2.0, -2.0, 2.0], [2.0, -2.0, 2.0], [0.0, 2.0, 2.0]], Color::RED))?;
+        Ok([pixel(buffer, 32, 33), pixel(buffer, 32, 24)])
+    });
+    graphics::destroy_window(window);
+    if rendered != Some(Ok([WHITE, BLACK])) {
+        return Err("Perspective triangle not clipped at the near plane");
+    }
+    _print(format_args!("[GLES Test] ✓ Window buffer rendered with near-plane clipping\n"));
+
+    // Test 4: Invalid draw calls are rejected without drawing
+    _print(format_args!("[GLES Test] Test 4: Invalid calls...\n"));
+    let mut small = GraphicsBuffer::new(16, 16);
+    let vertices = backdrop(0.0, Color::WHITE);
+    if ctx.draw_triangles(&mut small, &vertices) != Err(GlError::InvalidFramebuffer)
+        || ctx.bind_texture(Some(99)) != Err(GlError::InvalidTexture)
+        || ctx.create_texture(2, 2, &[WHITE]) != Err(GlError::InvalidValue)
+        || ctx.draw_elements(&mut GraphicsBuffer::new(64, 48), &vertices[..2], &[0, 1, 2]) != Err(GlError::InvalidValue)
+    {
+        return Err("Invalid call accepted");
+    }
+    _print(format_args!("[GLES Test] ✓ Invalid calls rejected\n"));
+
+    _print(format_args!("[GLES Test] ✓ All software GLES renderer tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for the software GLES renderer
+pub fn test_gles() {
+    _print(format_args!("[GLES Test] ===========================================\n"));
+    _print(format_args!("[GLES Test]         SOFTWARE GLES RENDERER TESTS\n"));
+    _print(format_args!("[GLES Test] ===========================================\n"));
+
+    match run_gles_tests() {
+        Ok(_) => _print(format_args!("[GLES Test] ✓ All software GLES renderer tests PASSED\n")),
+        Err(e) => _print(format_args!("[GLES Test] ✗ Software GLES renderer tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[GLES Test] ===========================================\n"));
+}
@@ -3,13 +3,25 @@
 
 use alloc::vec;
 use alloc::vec::Vec;
+use alloc::boxed::Box;
 use alloc::string::{String, ToString};
 use alloc::collections::BTreeMap;
 use x86_64::VirtAddr;
 use spin::Mutex;
 use lazy_static::lazy_static;
 use bitflags::bitflags;
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use crate::input::ime::{Composition, ImeResponse, InputMethod};
+use crate::ui::accessibility::{self, AccessibilityEvent};
+
+pub mod font;
+pub mod gles;
+pub mod recording;
+pub mod renderer;
+pub mod shaping;
+
+pub use recording::{start_recording, stop_recording, RecordingTarget};
+pub use renderer::{register_hardware_probe, Renderer, RendererError, SoftwareRenderer};
 
 // Key modifier flags
 bitflags! {
@@ -25,6 +37,9 @@ bitflags! {
 // Global timestamp counter for events
 static EVENT_TIMESTAMP: AtomicU64 = AtomicU64::new(0);
 
+// Whether the compositor may render on hardware; mirrors the graphics service configuration
+static HARDWARE_ACCELERATION: AtomicBool = AtomicBool::new(true);
+
 // Global modifier key state
 static MODIFIER_STATE: Mutex<KeyModifiers> = Mutex::new(KeyModifiers::empty());
 
@@ -102,12 +117,48 @@ pub struct KeyboardEvent {
     pub timestamp: u64,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
 
+/// A touch gesture recognized by the input handler
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    Tap,
+    DoubleTap,
+    LongPress,
+    /// Two fingers spreading or closing; `scale` is their spread relative to when the pinch began
+    Pinch { scale: f32 },
+    /// Two fingers moving together, by this much since the previous scroll event
+    Scroll { delta_x: i32, delta_y: i32 },
+    /// A quick fling of `fingers` fingers, reported once they lift
+    Swipe { direction: SwipeDirection, fingers: u8 },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GestureEvent {
+    pub gesture: Gesture,
+    /// Where the gesture happened: the tap point, or the centre of the fingers
+    pub x: i32,
+    pub y: i32,
+    pub timestamp: u64,
+}
 
 #[derive(Debug, Clone)]
 pub enum WindowEvent {
     Mouse(MouseEvent),
+    /// The pointer moved to `x`, `y`, relative to the window
+    MouseMove { x: i32, y: i32 },
     Keyboard(KeyboardEvent),
+    Gesture(GestureEvent),
+    /// The input method's composition changed; an empty `text` means it ended
+    Preedit { text: String, cursor: usize },
+    /// The input method committed composed text to the focused widget
+    TextCommit(String),
     Resize { width: u32, height: u32 },
     Close,
 }
@@ -287,6 +338,9 @@ pub enum WindowState {
     Fullscreen,
 }
 
+/// Bit of the `CreateWindow` syscall's flags that opts the window into input method composition
+pub const CREATE_WINDOW_IME: u64 = 1 << 0;
+
 /// Window flags
 #[derive(Debug, Clone)]
 pub struct WindowFlags {
@@ -295,6 +349,8 @@ pub struct WindowFlags {
     pub always_on_top: bool,
     pub transparent: bool,
     pub blur_behind: bool,
+    /// Key presses go through the input method before reaching the window
+    pub ime_enabled: bool,
 }
 
 impl Default for WindowFlags {
@@ -305,10 +361,14 @@ impl Default for WindowFlags {
             always_on_top: false,
             transparent: false,
             blur_behind: false,
+            ime_enabled: false,
         }
     }
 }
 
+/// Gap between a widget's edge and the text drawn inside it
+const TEXT_INSET: i32 = 4;
+
 /// Window structure
 #[derive(Debug, Clone)]
 pub struct Window {
@@ -333,6 +393,11 @@ pub struct Widget {
     pub bounds: Rect,
     pub widget_type: WidgetType,
     pub focused: bool,
+    /// Disabled widgets take neither focus nor input
+    pub enabled: bool,
+    /// Name for assistive services when the widget shows no text of its own, such as the
+    /// label of a text input
+    pub accessible_name: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -344,22 +409,23 @@ pub enum WidgetType {
 }
 
 impl Widget {
+    pub fn new(id: u32, widget_type: WidgetType, bounds: Rect) -> Self {
+        Widget { id, bounds, widget_type, focused: false, enabled: true, accessible_name: None }
+    }
+    
     pub fn handle_mouse_event(&mut self, _event: MouseEvent) {
-        // Basic widget mouse handling
-        match &mut self.widget_type {
-            WidgetType::Button { .. } => {
-                // Handle button click
-            }
-            WidgetType::TextInput { .. } => {
-                // Handle text input focus
-                self.focused = true;
-            }
-            _ => {}
+        if !self.enabled {
+            return;
+        }
+        
+        // Basic widget mouse handling; clicked text inputs are focused by their window
+        if let WidgetType::Button { .. } = &mut self.widget_type {
+            // Handle button click
         }
     }
     
     pub fn handle_keyboard_event(&mut self, event: KeyboardEvent) {
-        if !self.focused {
+        if !self.focused || !self.enabled {
             return;
         }
         
@@ -368,15 +434,15 @@ impl Widget {
                 if event.pressed {
                     match event.key_code {
                         8 => { // Backspace
-                            if *cursor_pos > 0 {
-                                text.remove(*cursor_pos - 1);
-                                *cursor_pos -= 1;
+                            let previous = text.get(..*cursor_pos).and_then(|before| before.chars().next_back());
+                            if let Some(ch) = previous {
+                                *cursor_pos -= ch.len_utf8();
+                                text.remove(*cursor_pos);
                             }
                         }
                         32..=126 => { // Printable ASCII
-                            let ch = event.key_code as u8 as char;
-                            text.insert(*cursor_pos, ch);
-                            *cursor_pos += 1;
+                            let mut buf = [0; 4];
+                            self.insert_text((event.key_code as u8 as char).encode_utf8(&mut buf));
                         }
                         _ => {}
                     }
@@ -385,6 +451,16 @@ impl Widget {
             _ => {}
         }
     }
+    
+    /// Insert text at the caret of a text input
+    pub fn insert_text(&mut self, insert: &str) {
+        if let WidgetType::TextInput { text, cursor_pos } = &mut self.widget_type {
+            if text.is_char_boundary(*cursor_pos) {
+                text.insert_str(*cursor_pos, insert);
+                *cursor_pos += insert.len();
+            }
+        }
+    }
 }
 
 impl Rect {
@@ -417,6 +493,7 @@ impl Window {
         self.rect.width = width;
         self.rect.height = height;
         self.buffer = Some(GraphicsBuffer::new(width, height));
+        self.pending_events.push(WindowEvent::Resize { width, height });
     }
     
     pub fn move_to(&mut self, x: i32, y: i32) {
@@ -424,26 +501,42 @@ impl Window {
         self.rect.y = y;
     }
     
-    pub fn focus_next_widget(&mut self) {
-        if self.widgets.is_empty() {
-            return;
+    /// Add a widget, returning its ID within this window
+    pub fn add_widget(&mut self, widget_type: WidgetType, bounds: Rect) -> u32 {
+        let id = self.widgets.iter().map(|widget| widget.id).max().unwrap_or(0) + 1;
+        self.widgets.push(Widget::new(id, widget_type, bounds));
+        id
+    }
+    
+    /// Move keyboard focus to the widget at `index`, announcing the change to assistive
+    /// services. Disabled widgets cannot take focus
+    pub fn focus_widget(&mut self, index: usize) -> bool {
+        if !self.widgets.get(index).is_some_and(|widget| widget.enabled) {
+            return false;
+        }
+        if self.focused_widget == Some(index) {
+            return true;
         }
         
         // Clear current focus
-        if let Some(current) = self.focused_widget {
-            if current < self.widgets.len() {
-                self.widgets[current].focused = false;
-            }
+        if let Some(current) = self.focused_widget.and_then(|current| self.widgets.get_mut(current)) {
+            current.focused = false;
         }
         
-        // Move to next widget
-        let next = match self.focused_widget {
-            Some(current) => (current + 1) % self.widgets.len(),
-            None => 0,
-        };
+        self.focused_widget = Some(index);
+        self.widgets[index].focused = true;
+        accessibility::notify(AccessibilityEvent::FocusChanged { window: self.id, widget: Some(self.widgets[index].id) });
+        true
+    }
+    
+    pub fn focus_next_widget(&mut self) {
+        let count = self.widgets.len();
         
-        self.focused_widget = Some(next);
-        self.widgets[next].focused = true;
+        // Move to the next enabled widget, wrapping around
+        let start = self.focused_widget.map_or(0, |current| current + 1);
+        if let Some(next) = (0..count).map(|step| (start + step) % count).find(|&index| self.widgets[index].enabled) {
+            self.focus_widget(next);
+        }
     }
     
     pub fn handle_escape(&mut self) {
@@ -451,12 +544,50 @@ impl Window {
         if let Some(focused) = self.focused_widget {
             self.widgets[focused].focused = false;
             self.focused_widget = None;
+            accessibility::notify(AccessibilityEvent::FocusChanged { window: self.id, widget: None });
         } else {
             // Add close event
             self.pending_events.push(WindowEvent::Close);
         }
     }
     
+    /// Screen position of the focused text input's caret, where the compositor draws the
+    /// input method's preedit; the window's corner when no text input is focused
+    pub fn caret_position(&self) -> Point {
+        let focused = self.focused_widget.and_then(|index| self.widgets.get(index));
+        match focused.map(|widget| (widget.bounds, &widget.widget_type)) {
+            Some((bounds, WidgetType::TextInput { text, cursor_pos })) => {
+                let before = text.get(..*cursor_pos).unwrap_or(text);
+                Point::new(
+                    self.rect.x + bounds.x + TEXT_INSET + get_text_width(before) as i32,
+                    self.rect.y + bounds.y + TEXT_INSET,
+                )
+            }
+            Some((bounds, _)) => Point::new(self.rect.x + bounds.x + TEXT_INSET, self.rect.y + bounds.y + TEXT_INSET),
+            None => Point::new(self.rect.x + TEXT_INSET, self.rect.y + TEXT_INSET),
+        }
+    }
+    
+    /// Insert committed text into the focused widget and tell the window about it
+    pub fn commit_text(&mut self, text: String) {
+        self.update_focused_widget(|widget| widget.insert_text(&text));
+        self.pending_events.push(WindowEvent::TextCommit(text));
+    }
+    
+    /// Apply `update` to the focused widget, announcing a change of its value to assistive
+    /// services
+    fn update_focused_widget(&mut self, update: impl FnOnce(&mut Widget)) {
+        let window_id = self.id;
+        let Some(widget) = self.get_focused_widget_mut() else {
+            return;
+        };
+        let before = accessibility::widget_value(widget);
+        update(widget);
+        if let Some(value) = accessibility::widget_value(widget).filter(|value| Some(value) != before.as_ref()) {
+            accessibility::notify(AccessibilityEvent::ValueChanged { window: window_id, widget: widget.id, value });
+        }
+    }
+    
     pub fn get_focused_widget_mut(&mut self) -> Option<&mut Widget> {
         if let Some(index) = self.focused_widget {
             self.widgets.get_mut(index)
@@ -496,17 +627,33 @@ impl Default for RaeTheme {
 
 // (removed duplicate RaeUI Widget types; using earlier Widget/WidgetType with bounds)
 
+/// Pointer position and visibility, as the compositor draws the cursor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursorState {
+    pub x: i32,
+    pub y: i32,
+    pub visible: bool,
+}
+
 /// Window Manager
 pub struct WindowManager {
     windows: BTreeMap<WindowId, Window>,
     next_window_id: WindowId,
     focused_window: Option<WindowId>,
+    /// Window receiving all input regardless of focus and pointer position
+    input_grab: Option<WindowId>,
+    /// Window a mouse button was pressed in, which keeps the pointer until the release so
+    /// drags can leave it
+    pointer_owner: Option<WindowId>,
+    cursor: CursorState,
     window_order: Vec<WindowId>,
     _screen_width: u32,
     _screen_height: u32,
     theme: RaeTheme,
     widgets: BTreeMap<u32, Widget>,
     next_widget_id: u32,
+    /// Composes text for windows that enable it
+    input_method: Option<Box<dyn InputMethod>>,
 }
 
 impl WindowManager {
@@ -515,12 +662,16 @@ impl WindowManager {
             windows: BTreeMap::new(),
             next_window_id: 1,
             focused_window: None,
+            input_grab: None,
+            pointer_owner: None,
+            cursor: CursorState { x: 0, y: 0, visible: true },
             window_order: Vec::new(),
             _screen_width: screen_width,
             _screen_height: screen_height,
             theme: RaeTheme::default(),
             widgets: BTreeMap::new(),
             next_widget_id: 1,
+            input_method: None,
         }
     }
     
@@ -546,7 +697,18 @@ impl WindowManager {
         if self.windows.remove(&window_id).is_some() {
             self.window_order.retain(|&id| id != window_id);
             
+            // A grab never outlives its window
+            if self.input_grab == Some(window_id) {
+                self.release_input();
+            }
+            if self.pointer_owner == Some(window_id) {
+                self.pointer_owner = None;
+            }
+            
             if self.focused_window == Some(window_id) {
+                if let Some(input_method) = self.input_method.as_mut() {
+                    input_method.reset();
+                }
                 self.focused_window = self.window_order.last().copied();
                 if let Some(new_focused) = self.focused_window {
                     if let Some(window) = self.windows.get_mut(&new_focused) {
@@ -561,7 +723,16 @@ impl WindowManager {
     }
     
     pub fn focus_window(&mut self, window_id: WindowId) -> bool {
+        // Focus stays with a window that grabbed the input
+        if self.input_grab.is_some_and(|holder| holder != window_id) {
+            return false;
+        }
         if self.windows.contains_key(&window_id) {
+            // A composition belongs to the window it was typed into
+            if self.focused_window != Some(window_id) {
+                self.cancel_composition();
+            }
+            
             // Unfocus current window
             if let Some(current_focused) = self.focused_window {
                 if let Some(window) = self.windows.get_mut(&current_focused) {
@@ -589,6 +760,230 @@ impl WindowManager {
         self.windows.get(&window_id)
     }
     
+    /// Send all keyboard and mouse input to a window, regardless of focus and pointer
+    /// position, until `release_input` or the window's destruction. The window is focused
+    /// and raised, and the cursor is hidden and confined to it
+    pub fn grab_input(&mut self, window_id: WindowId) -> Result<(), &'static str> {
+        if !self.windows.contains_key(&window_id) {
+            return Err("Window not found");
+        }
+        if self.input_grab.is_some_and(|holder| holder != window_id) {
+            return Err("Input is grabbed by another window");
+        }
+        self.focus_window(window_id);
+        self.input_grab = Some(window_id);
+        self.cursor.visible = false;
+        self.move_cursor(self.cursor.x, self.cursor.y);
+        Ok(())
+    }
+    
+    /// End the input grab, returning the window that held it
+    pub fn release_input(&mut self) -> Option<WindowId> {
+        self.cursor.visible = true;
+        self.input_grab.take()
+    }
+    
+    pub fn get_input_grab(&self) -> Option<WindowId> {
+        self.input_grab
+    }
+    
+    pub fn get_cursor(&self) -> CursorState {
+        self.cursor
+    }
+    
+    /// A point clamped into the grabbing window, or unchanged without a grab
+    fn confine_point(&self, x: i32, y: i32) -> (i32, i32) {
+        match self.input_grab.and_then(|holder| self.windows.get(&holder)) {
+            Some(window) => {
+                let rect = window.rect;
+                let right = rect.x + rect.width.max(1) as i32 - 1;
+                let bottom = rect.y + rect.height.max(1) as i32 - 1;
+                (x.clamp(rect.x, right), y.clamp(rect.y, bottom))
+            }
+            None => (x, y),
+        }
+    }
+    
+    pub fn move_cursor(&mut self, x: i32, y: i32) {
+        let (x, y) = self.confine_point(x, y);
+        self.cursor.x = x;
+        self.cursor.y = y;
+    }
+    
+    /// Deliver a mouse button event to the grabbing window, or else the window holding the
+    /// pointer for a drag, or else the topmost window under the pointer, which a press also
+    /// focuses
+    pub fn dispatch_mouse_event(&mut self, x: i32, y: i32, button: u8, pressed: bool) {
+        let (x, y) = self.confine_point(x, y);
+        let target = match self.input_grab.or(self.pointer_owner) {
+            Some(holder) => Some(holder),
+            None => {
+                let target = self.get_window_at_point(Point::new(x, y));
+                if let Some(window_id) = target.filter(|_| pressed) {
+                    self.focus_window(window_id);
+                }
+                target
+            }
+        };
+        self.pointer_owner = if pressed { target } else { None };
+        
+        // Send mouse event to window/widget
+        if let Some(window) = target.and_then(|window_id| self.windows.get_mut(&window_id)) {
+            // Create mouse event structure
+            let mouse_event = MouseEvent {
+                x: x - window.rect.x,
+                y: y - window.rect.y,
+                button: if button == 0 { MouseButton::Left } else if button == 1 { MouseButton::Right } else { MouseButton::Middle },
+                pressed,
+                timestamp: get_timestamp(),
+            };
+            
+            // Add to window's event queue
+            window.pending_events.push(WindowEvent::Mouse(mouse_event));
+            
+            // Check if click is on any widgets
+            let local_point = Point::new(mouse_event.x, mouse_event.y);
+            let clicked_input = window.widgets.iter().position(|widget| {
+                matches!(widget.widget_type, WidgetType::TextInput { .. }) && widget.bounds.contains_point(local_point)
+            });
+            if let Some(index) = clicked_input.filter(|_| pressed) {
+                window.focus_widget(index);
+            }
+            for widget in &mut window.widgets {
+                if widget.bounds.contains_point(local_point) {
+                    widget.handle_mouse_event(mouse_event);
+                }
+            }
+        }
+    }
+    
+    /// Tell the window holding the pointer, or else the one under it, where the pointer moved
+    pub fn dispatch_mouse_motion(&mut self, x: i32, y: i32) {
+        let (x, y) = self.confine_point(x, y);
+        let target = self.input_grab.or(self.pointer_owner).or_else(|| self.get_window_at_point(Point::new(x, y)));
+        if let Some(window) = target.and_then(|window_id| self.windows.get_mut(&window_id)) {
+            window.pending_events.push(WindowEvent::MouseMove { x: x - window.rect.x, y: y - window.rect.y });
+        }
+    }
+    
+    /// Deliver a touch gesture to the grabbing window, or else the focused one, with its
+    /// position made relative to that window
+    pub fn dispatch_gesture_event(&mut self, gesture: Gesture, x: i32, y: i32) {
+        let target = self.input_grab.or(self.focused_window);
+        if let Some(window) = target.and_then(|window_id| self.windows.get_mut(&window_id)) {
+            window.pending_events.push(WindowEvent::Gesture(GestureEvent {
+                gesture,
+                x: x - window.rect.x,
+                y: y - window.rect.y,
+                timestamp: get_timestamp(),
+            }));
+        }
+    }
+    
+    /// Replace the input method, abandoning any composition of the old one
+    pub fn set_input_method(&mut self, input_method: Option<Box<dyn InputMethod>>) {
+        self.cancel_composition();
+        self.input_method = input_method;
+    }
+    
+    /// Opt a window in or out of input method composition
+    pub fn set_ime_enabled(&mut self, window_id: WindowId, enabled: bool) -> Result<(), &'static str> {
+        let window = self.windows.get_mut(&window_id).ok_or("Window not found")?;
+        window.flags.ime_enabled = enabled;
+        if !enabled && self.focused_window == Some(window_id) {
+            self.cancel_composition();
+        }
+        Ok(())
+    }
+    
+    /// The input method's composition in progress, if any
+    pub fn composition(&self) -> Option<Composition> {
+        self.input_method.as_ref()?.composition()
+    }
+    
+    /// Abandon the composition in progress, clearing the focused window's preedit
+    fn cancel_composition(&mut self) {
+        let Some(input_method) = self.input_method.as_mut() else {
+            return;
+        };
+        if input_method.composition().is_some() {
+            input_method.reset();
+            let target = self.input_grab.or(self.focused_window);
+            if let Some(window) = target.and_then(|window_id| self.windows.get_mut(&window_id)) {
+                window.pending_events.push(WindowEvent::Preedit { text: String::new(), cursor: 0 });
+            }
+        }
+    }
+    
+    /// Offer a key press to the input method when `window_id` opted in, returning whether
+    /// the method consumed it
+    fn compose_key(&mut self, window_id: WindowId, key_code: u32) -> bool {
+        let Some(window) = self.windows.get_mut(&window_id).filter(|window| window.flags.ime_enabled) else {
+            return false;
+        };
+        let Some(input_method) = self.input_method.as_mut() else {
+            return false;
+        };
+        match input_method.process_key(key_code) {
+            ImeResponse::PassThrough => return false,
+            ImeResponse::Composing => {
+                let composition = input_method.composition().unwrap_or_default();
+                window.pending_events.push(WindowEvent::Preedit { text: composition.preedit, cursor: composition.cursor });
+            }
+            ImeResponse::Commit(text) => {
+                window.pending_events.push(WindowEvent::Preedit { text: String::new(), cursor: 0 });
+                window.commit_text(text);
+            }
+            ImeResponse::Cancel => {
+                window.pending_events.push(WindowEvent::Preedit { text: String::new(), cursor: 0 });
+            }
+        }
+        true
+    }
+    
+    /// Deliver a keyboard event to the grabbing window, or else the focused one. Presses
+    /// go through the input method first when the window enables it
+    pub fn dispatch_keyboard_event(&mut self, key_code: u32, pressed: bool) {
+        let target = self.input_grab.or(self.focused_window);
+        // Update modifier state and get current modifiers
+        let current_modifiers = update_modifiers(key_code, pressed);
+        if pressed && target.is_some_and(|window_id| self.compose_key(window_id, key_code)) {
+            return;
+        }
+        if let Some(window) = target.and_then(|window_id| self.windows.get_mut(&window_id)) {
+            // Create keyboard event structure
+            let keyboard_event = KeyboardEvent {
+                key_code,
+                pressed,
+                modifiers: current_modifiers,
+                timestamp: get_timestamp(),
+            };
+            
+            // Add to window's event queue
+            window.pending_events.push(WindowEvent::Keyboard(keyboard_event));
+            
+            // Handle special keys
+            match key_code {
+                9 => { // Tab key
+                    if pressed {
+                        // Focus next widget in window
+                        window.focus_next_widget();
+                    }
+                }
+                27 => { // Escape key
+                    if pressed {
+                        // Close window or cancel operation
+                        window.handle_escape();
+                    }
+                }
+                _ => {
+                    // Send to focused widget if any
+                    window.update_focused_widget(|widget| widget.handle_keyboard_event(keyboard_event));
+                }
+            }
+        }
+    }
+    
     pub fn get_window_mut(&mut self, window_id: WindowId) -> Option<&mut Window> {
         self.windows.get_mut(&window_id)
     }
@@ -608,7 +1003,7 @@ impl WindowManager {
         let id = self.next_widget_id;
         self.next_widget_id += 1;
         
-        let widget = Widget { id, bounds: rect, widget_type, focused: false };
+        let widget = Widget::new(id, widget_type, rect);
         self.widgets.insert(id, widget);
         
         id
@@ -737,12 +1132,55 @@ impl WindowManager {
                 }
             }
         }
+        
+        self.render_composition(main_buffer);
+    }
+    
+    /// Draw the input method's preedit underlined at the caret of the window receiving
+    /// keys, with the candidate window below it
+    fn render_composition(&self, main_buffer: &mut GraphicsBuffer) {
+        let target = self.input_grab.or(self.focused_window);
+        let Some(window) = target.and_then(|window_id| self.windows.get(&window_id)) else {
+            return;
+        };
+        if !window.visible || !window.flags.ime_enabled {
+            return;
+        }
+        let Some(composition) = self.composition() else {
+            return;
+        };
+        
+        let caret = window.caret_position();
+        let line_height = get_text_height() as i32;
+        let preedit_width = get_text_width(&composition.preedit);
+        main_buffer.draw_rect(Rect::new(caret.x, caret.y, preedit_width, line_height as u32), self.theme.background_color);
+        let end_x = draw_glyphs(main_buffer, caret.x, caret.y, &composition.preedit, self.theme.text_color);
+        main_buffer.draw_line(
+            Point::new(caret.x, caret.y + line_height),
+            Point::new((end_x - 1).max(caret.x), caret.y + line_height),
+            self.theme.accent_color,
+        );
+        
+        let Some(widest) = composition.candidates.iter().map(|candidate| get_text_width(candidate)).max() else {
+            return;
+        };
+        let left = caret.x;
+        let top = caret.y + line_height + 2;
+        let width = widest + 2 * TEXT_INSET as u32;
+        for (index, candidate) in composition.candidates.iter().enumerate() {
+            let row = Rect::new(left, top + index as i32 * line_height, width, line_height as u32);
+            let background = if index == composition.selected { self.theme.accent_color } else { self.theme.background_color };
+            main_buffer.draw_rect(row, background);
+            draw_glyphs(main_buffer, row.x + TEXT_INSET, row.y, candidate, self.theme.text_color);
+        }
     }
     
     // Additional window management methods
     pub fn set_focus(&mut self, window_id: u32) -> Result<(), &'static str> {
         if self.focus_window(window_id) {
             Ok(())
+        } else if self.windows.contains_key(&window_id) {
+            Err("Input is grabbed by another window")
         } else {
             Err("Window not found")
         }
@@ -754,12 +1192,7 @@ impl WindowManager {
     
     pub fn resize_window(&mut self, window_id: u32, width: u32, height: u32) -> Result<(), &'static str> {
         if let Some(window) = self.windows.get_mut(&window_id) {
-            window.rect.width = width;
-            window.rect.height = height;
-            
-            // Recreate window buffer with new size
-            window.buffer = Some(GraphicsBuffer::new(width, height));
-            
+            window.resize(width, height);
             Ok(())
         } else {
             Err("Window not found")
@@ -948,9 +1381,18 @@ impl GpuAccelerator {
     }
 }
 
+/// Display hardware that must be told when framebuffer memory changes
+/// (e.g. virtio-gpu, where the guest framebuffer is only a backing store)
+pub trait FramebufferBackend: Send {
+    fn name(&self) -> &str;
+    fn flush(&mut self, rect: Rect);
+}
+
 /// Framebuffer compositor for hardware framebuffer access
 pub struct FramebufferCompositor {
     framebuffer_addr: VirtAddr,
+    backend: Option<Box<dyn FramebufferBackend>>,
+    renderer: Box<dyn Renderer>,
     width: u32,
     height: u32,
     pitch: u32,
@@ -967,6 +1409,8 @@ impl FramebufferCompositor {
     pub fn new(framebuffer_addr: VirtAddr, width: u32, height: u32, pitch: u32, bpp: u32) -> Self {
         Self {
             framebuffer_addr,
+            backend: None,
+            renderer: Box::new(SoftwareRenderer),
             width,
             height,
             pitch,
@@ -985,62 +1429,28 @@ impl FramebufferCompositor {
         self.dirty_regions.push(rect);
     }
     
-    /// Composite all windows to the back buffer
+    /// Composite all windows to the back buffer. If the renderer fails mid-frame the compositor
+    /// drops to the software renderer and redraws the frame with it
     pub fn composite(&mut self, window_manager: &WindowManager) {
-        // Clear back buffer
-        self.back_buffer.clear(window_manager.theme.background_color);
-        
-        // Render windows in z-order
-        for &window_id in &window_manager.window_order {
-            if let Some(window) = window_manager.windows.get(&window_id) {
-                if window.visible {
-                    self.composite_window(window);
-                }
-            }
+        if let Err(e) = renderer::composite_scene(self.renderer.as_mut(), &mut self.back_buffer, window_manager) {
+            crate::serial::_print(format_args!(
+                "[Graphics] {} renderer failed ({:?}), falling back to software\n",
+                self.renderer.name(),
+                e
+            ));
+            self.renderer = Box::new(SoftwareRenderer);
+            let _ = renderer::composite_scene(self.renderer.as_mut(), &mut self.back_buffer, window_manager);
         }
     }
-    
-    /// Composite a single window to the back buffer
-    fn composite_window(&mut self, window: &Window) {
-        if let Some(window_buffer) = &window.buffer {
-            // Blit window buffer to back buffer with clipping
-            let src_rect = Rect::new(0, 0, window_buffer.width, window_buffer.height);
-            let dst_rect = window.rect;
-            
-            self.blit_with_clipping(window_buffer, src_rect, dst_rect);
-        }
+
+    /// Composite with `renderer` from the next frame on
+    pub fn set_renderer(&mut self, renderer: Box<dyn Renderer>) {
+        crate::serial::_print(format_args!("[Graphics] Compositor rendering with {}\n", renderer.name()));
+        self.renderer = renderer;
     }
-    
-    /// Blit with clipping support
-    fn blit_with_clipping(&mut self, src: &GraphicsBuffer, _src_rect: Rect, dst_rect: Rect) {
-        let clip_rect = Rect::new(0, 0, self.width, self.height);
-        
-        // Calculate intersection
-        let x_start = core::cmp::max(dst_rect.x, clip_rect.x);
-        let y_start = core::cmp::max(dst_rect.y, clip_rect.y);
-        let x_end = core::cmp::min(dst_rect.x + dst_rect.width as i32, clip_rect.x + clip_rect.width as i32);
-        let y_end = core::cmp::min(dst_rect.y + dst_rect.height as i32, clip_rect.y + clip_rect.height as i32);
-        
-        if x_start >= x_end || y_start >= y_end {
-            return; // No intersection
-        }
-        
-        // Copy pixels
-        for y in y_start..y_end {
-            for x in x_start..x_end {
-                let src_x = (x - dst_rect.x) as u32;
-                let src_y = (y - dst_rect.y) as u32;
-                
-                if src_x < src.width && src_y < src.height {
-                    let src_index = (src_y * src.width + src_x) as usize;
-                    let dst_index = (y as u32 * self.width + x as u32) as usize;
-                    
-                    if src_index < src.pixels.len() && dst_index < self.back_buffer.pixels.len() {
-                        self.back_buffer.pixels[dst_index] = src.pixels[src_index];
-                    }
-                }
-            }
-        }
+
+    pub fn renderer_name(&self) -> &str {
+        self.renderer.name()
     }
     
     /// Present the back buffer to the hardware framebuffer
@@ -1069,6 +1479,17 @@ impl FramebufferCompositor {
             self.present_partial();
         }
         
+        // Let the display backend scan out what changed
+        if let Some(backend) = self.backend.as_mut() {
+            if self.dirty_regions.is_empty() {
+                backend.flush(Rect::new(0, 0, self.width, self.height));
+            } else {
+                for rect in &self.dirty_regions {
+                    backend.flush(*rect);
+                }
+            }
+        }
+        
         self.dirty_regions.clear();
         self.frame_count += 1;
         self.last_present_time = get_timestamp();
@@ -1170,6 +1591,7 @@ lazy_static! {
 pub fn init_graphics(screen_width: u32, screen_height: u32) -> Result<(), &'static str> {
     let mut wm = WINDOW_MANAGER.lock();
     *wm = WindowManager::new(screen_width, screen_height);
+    wm.set_input_method(Some(Box::new(crate::input::ime::DeadKeyIme::new())));
     
     let mut gpu = GPU_ACCELERATOR.lock();
     gpu.initialize()?;
@@ -1188,8 +1610,9 @@ pub fn init_framebuffer_compositor(
     pitch: u32, 
     bpp: u32
 ) -> Result<(), &'static str> {
-    let mut compositor = FRAMEBUFFER_COMPOSITOR.lock();
-    *compositor = Some(FramebufferCompositor::new(framebuffer_addr, width, height, pitch, bpp));
+    let mut compositor = FramebufferCompositor::new(framebuffer_addr, width, height, pitch, bpp);
+    compositor.set_renderer(renderer::select_registered_renderer(HARDWARE_ACCELERATION.load(Ordering::Relaxed)));
+    *FRAMEBUFFER_COMPOSITOR.lock() = Some(compositor);
     
     // Also update window manager and main buffer to match framebuffer
     let mut wm = WINDOW_MANAGER.lock();
@@ -1201,12 +1624,83 @@ pub fn init_framebuffer_compositor(
     Ok(())
 }
 
+/// Initialize the framebuffer compositor on memory owned by a display backend
+/// that needs an explicit flush after each present
+pub fn init_framebuffer_compositor_with_backend(
+    framebuffer_addr: VirtAddr,
+    width: u32,
+    height: u32,
+    pitch: u32,
+    bpp: u32,
+    backend: Box<dyn FramebufferBackend>,
+) -> Result<(), &'static str> {
+    init_framebuffer_compositor(framebuffer_addr, width, height, pitch, bpp)?;
+    
+    if let Some(compositor) = FRAMEBUFFER_COMPOSITOR.lock().as_mut() {
+        crate::serial::_print(format_args!("[Graphics] Compositor presenting through {}\n", backend.name()));
+        compositor.backend = Some(backend);
+    }
+    
+    Ok(())
+}
+
+/// Offer a hardware renderer to the compositor, switching to it now if it is the first
+/// available one and hardware acceleration is enabled
+pub fn register_hardware_renderer(probe: renderer::HardwareProbe) {
+    register_hardware_probe(probe);
+    reselect_renderer();
+}
+
+/// Allow or forbid compositing on hardware; the compositor switches renderer right away
+pub fn set_hardware_acceleration(enabled: bool) {
+    HARDWARE_ACCELERATION.store(enabled, Ordering::Relaxed);
+    reselect_renderer();
+}
+
+/// The name of the renderer the compositor draws with, if the compositor is running
+pub fn compositor_renderer() -> Option<String> {
+    FRAMEBUFFER_COMPOSITOR.lock().as_ref().map(|compositor| compositor.renderer_name().to_string())
+}
+
+fn reselect_renderer() {
+    if let Some(compositor) = FRAMEBUFFER_COMPOSITOR.lock().as_mut() {
+        let selected = renderer::select_registered_renderer(HARDWARE_ACCELERATION.load(Ordering::Relaxed));
+        if selected.name() != compositor.renderer_name() {
+            compositor.set_renderer(selected);
+        }
+    }
+}
+
 pub fn create_window(title: &str, x: i32, y: i32, width: u32, height: u32, process_id: u32) -> WindowId {
     let mut wm = WINDOW_MANAGER.lock();
     let rect = Rect::new(x, y, width, height);
     wm.create_window(title.into(), rect, process_id)
 }
 
+/// Run `f` on a window of the window manager, or return `None` when it does not exist
+pub fn with_window<F, R>(window_id: WindowId, f: F) -> Option<R>
+where
+    F: FnOnce(&Window) -> R,
+{
+    let wm = WINDOW_MANAGER.lock();
+    wm.get_window(window_id).map(f)
+}
+
+/// Remove and return the events queued for a window, or `None` when it does not exist
+pub fn take_window_events(window_id: WindowId) -> Option<Vec<WindowEvent>> {
+    let mut wm = WINDOW_MANAGER.lock();
+    wm.get_window_mut(window_id).map(|window| core::mem::take(&mut window.pending_events))
+}
+
+/// Run `f` on the pixel buffer of a window, e.g. to render into it with a `gles::GlContext`
+pub fn with_window_buffer<F, R>(window_id: WindowId, f: F) -> Option<R>
+where
+    F: FnOnce(&mut GraphicsBuffer) -> R,
+{
+    let mut wm = WINDOW_MANAGER.lock();
+    wm.get_window_mut(window_id)?.buffer.as_mut().map(f)
+}
+
 pub fn destroy_window(window_id: WindowId) -> bool {
     let mut wm = WINDOW_MANAGER.lock();
     wm.destroy_window(window_id)
@@ -1278,11 +1772,13 @@ pub fn render_frame() {
     if let Some(compositor) = compositor_opt.as_mut() {
         // Use hardware framebuffer compositor
         compositor.composite(&wm);
+        recording::capture_frame(compositor.get_back_buffer(), crate::time::get_timestamp_ns());
         compositor.present();
     } else {
         // Fallback to software rendering
         let mut buffer = MAIN_BUFFER.lock();
         wm.render(&mut buffer);
+        recording::capture_frame(&buffer, crate::time::get_timestamp_ns());
         
         // Present buffer to VGA text buffer region as a coarse preview
         // Map RGBA to ASCII shade for now (very rough fallback display)
@@ -1431,46 +1927,12 @@ pub fn create_boot_window() -> Result<u32, &'static str> {
     Ok(window_id)
 }
 
-/// Create a shell terminal window
+/// Create the window a shell terminal draws into, focused
 pub fn create_shell_window() -> Result<u32, &'static str> {
     let mut wm = WINDOW_MANAGER.lock();
     
     let rect = Rect::new(50, 50, 800, 600);
     let window_id = wm.create_window("RaeShell Terminal".to_string(), rect, 1);
-    
-    // Get the window and draw terminal content
-    if let Some(window) = wm.get_window_mut(window_id) {
-        if let Some(buffer) = &mut window.buffer {
-            // Fill with dark terminal background
-            let bg_color = Color::new(30, 30, 30, 255);
-            buffer.clear(bg_color);
-            
-            // Draw terminal prompt area
-            let prompt_color = Color::new(0, 122, 204, 255);
-            let prompt_rect = Rect::new(10, 550, 780, 30);
-            
-            for y in prompt_rect.y..(prompt_rect.y + prompt_rect.height as i32) {
-                for x in prompt_rect.x..(prompt_rect.x + prompt_rect.width as i32) {
-                    if x >= 0 && y >= 0 && (x as u32) < buffer.width && (y as u32) < buffer.height {
-                        buffer.set_pixel(x as u32, y as u32, prompt_color);
-                    }
-                }
-            }
-            
-            // Draw welcome text area
-            let text_color = Color::new(255, 255, 255, 255);
-            let text_rect = Rect::new(10, 10, 300, 30);
-            
-            for y in text_rect.y..(text_rect.y + text_rect.height as i32) {
-                for x in text_rect.x..(text_rect.x + text_rect.width as i32) {
-                    if x >= 0 && y >= 0 && (x as u32) < buffer.width && (y as u32) < buffer.height {
-                        buffer.set_pixel(x as u32, y as u32, text_color);
-                    }
-                }
-            }
-        }
-    }
-    
     wm.focus_window(window_id);
     Ok(window_id)
 }
@@ -1495,79 +1957,51 @@ pub fn get_screen_buffer() -> &'static Mutex<GraphicsBuffer> {
 
 pub fn handle_mouse_event(x: i32, y: i32, button: u8, pressed: bool) {
     let mut wm = WINDOW_MANAGER.lock();
-    let point = Point::new(x, y);
-    
-    if let Some(window_id) = wm.get_window_at_point(point) {
-        if pressed {
-            wm.focus_window(window_id);
-        }
-        // Send mouse event to window/widget
-        if let Some(window) = wm.get_window_mut(window_id) {
-            // Create mouse event structure
-            let mouse_event = MouseEvent {
-                x: x as i32 - window.rect.x,
-                y: y as i32 - window.rect.y,
-                button: if button == 0 { MouseButton::Left } else if button == 1 { MouseButton::Right } else { MouseButton::Middle },
-                pressed,
-                timestamp: get_timestamp(),
-            };
-            
-            // Add to window's event queue
-            window.pending_events.push(WindowEvent::Mouse(mouse_event));
-            
-            // Check if click is on any widgets
-            let local_point = Point::new(mouse_event.x, mouse_event.y);
-            for widget in &mut window.widgets {
-                if widget.bounds.contains_point(local_point) {
-                    widget.handle_mouse_event(mouse_event);
-                }
-            }
-        }
-    }
+    wm.dispatch_mouse_event(x, y, button, pressed);
 }
 
 pub fn handle_keyboard_event(key_code: u32, pressed: bool) {
     let mut wm = WINDOW_MANAGER.lock();
-    
-    if let Some(focused_id) = wm.focused_window {
-        if let Some(window) = wm.get_window_mut(focused_id) {
-            // Update modifier state and get current modifiers
-            let current_modifiers = update_modifiers(key_code, pressed);
-            
-            // Create keyboard event structure
-            let keyboard_event = KeyboardEvent {
-                key_code,
-                pressed,
-                modifiers: current_modifiers,
-                timestamp: get_timestamp(),
-            };
-            
-            // Add to window's event queue
-            window.pending_events.push(WindowEvent::Keyboard(keyboard_event));
-            
-            // Handle special keys
-            match key_code {
-                9 => { // Tab key
-                    if pressed {
-                        // Focus next widget in window
-                        window.focus_next_widget();
-                    }
-                }
-                27 => { // Escape key
-                    if pressed {
-                        // Close window or cancel operation
-                        window.handle_escape();
-                    }
-                }
-                _ => {
-                    // Send to focused widget if any
-                    if let Some(focused_widget) = window.get_focused_widget_mut() {
-                        focused_widget.handle_keyboard_event(keyboard_event);
-                    }
-                }
-            }
-        }
-    }
+    wm.dispatch_keyboard_event(key_code, pressed);
+}
+
+pub fn handle_gesture_event(gesture: Gesture, x: i32, y: i32) {
+    let mut wm = WINDOW_MANAGER.lock();
+    wm.dispatch_gesture_event(gesture, x, y);
+}
+
+/// Install the input method that composes text for windows enabling it, or remove it
+pub fn set_input_method(input_method: Option<Box<dyn InputMethod>>) {
+    let mut wm = WINDOW_MANAGER.lock();
+    wm.set_input_method(input_method);
+}
+
+pub fn set_ime_enabled(window_id: WindowId, enabled: bool) -> Result<(), &'static str> {
+    let mut wm = WINDOW_MANAGER.lock();
+    wm.set_ime_enabled(window_id, enabled)
+}
+
+/// Route all keyboard and mouse input to a window until `release_input`, hiding and
+/// confining the cursor to it
+pub fn grab_input(window_id: WindowId) -> Result<(), &'static str> {
+    let mut wm = WINDOW_MANAGER.lock();
+    wm.grab_input(window_id)
+}
+
+/// End the input grab, returning the window that held it
+pub fn release_input() -> Option<WindowId> {
+    let mut wm = WINDOW_MANAGER.lock();
+    wm.release_input()
+}
+
+pub fn get_input_grab() -> Option<WindowId> {
+    let wm = WINDOW_MANAGER.lock();
+    wm.get_input_grab()
+}
+
+pub fn get_cursor() -> CursorState {
+    let wm = WINDOW_MANAGER.lock();
+    wm.get_cursor()
 }
 
 pub fn resize_window(window_id: WindowId, width: u32, height: u32) -> Result<(), &'static str> {
@@ -1610,325 +2044,31 @@ pub fn get_focused_window() -> Option<WindowId> {
     wm.focused_window
 }
 
-// Font data structure
-#[derive(Debug, Clone)]
-struct FontGlyph {
-    width: u8,
-    height: u8,
-    bitmap: Vec<u8>,
-}
-
-// Basic 8x16 bitmap font
-struct BitmapFont {
-    _glyph_width: u8,
-    glyph_height: u8,
-    glyphs: BTreeMap<char, FontGlyph>,
-}
-
-impl BitmapFont {
-    fn new() -> Self {
-        let mut font = BitmapFont {
-            _glyph_width: 8,
-            glyph_height: 16,
-            glyphs: BTreeMap::new(),
-        };
-        
-        // Add basic ASCII characters (simplified bitmap data)
-        // In a real implementation, this would be loaded from font files
-        font.add_basic_glyphs();
-        font
-    }
-    
-    fn add_basic_glyphs(&mut self) {
-        // Add space character
-        self.glyphs.insert(' ', FontGlyph {
-            width: 8,
-            height: 16,
-            bitmap: vec![0; 16], // Empty bitmap for space
-        });
-        
-        // Add basic letters and numbers with simple patterns
-        // This is a simplified implementation - real fonts would have proper bitmaps
-        for ch in 'A'..='Z' {
-            self.glyphs.insert(ch, self.create_letter_glyph(ch));
-        }
-        
-        for ch in 'a'..='z' {
-            self.glyphs.insert(ch, self.create_letter_glyph(ch));
-        }
-        
-        for ch in '0'..='9' {
-            self.glyphs.insert(ch, self.create_digit_glyph(ch));
-        }
-        
-        // Add common punctuation
-        let punctuation = ['.', ',', '!', '?', ':', ';', '(', ')', '[', ']', '{', '}', '-', '_', '+', '='];
-        for &ch in &punctuation {
-            self.glyphs.insert(ch, self.create_punctuation_glyph(ch));
-        }
-    }
-    
-    fn create_letter_glyph(&self, ch: char) -> FontGlyph {
-        // Create a simple pattern for letters
-        let mut bitmap = vec![0u8; 16];
-        
-        // Simple pattern based on character
-        let pattern = match ch.to_ascii_uppercase() {
-            'A' => [
-                0b00111000,
-                0b01000100,
-                0b10000010,
-                0b10000010,
-                0b11111110,
-                0b10000010,
-                0b10000010,
-                0b10000010,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-            ],
-            'B' => [
-                0b11111100,
-                0b10000010,
-                0b10000010,
-                0b11111100,
-                0b11111100,
-                0b10000010,
-                0b10000010,
-                0b11111100,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-            ],
-            _ => {
-                // Default pattern for other letters
-                let base = (ch as u8) % 8;
-                [
-                    0b11111110,
-                    0b10000010 | base,
-                    0b10000010,
-                    0b10000010,
-                    0b11111110,
-                    0b10000010,
-                    0b10000010,
-                    0b10000010,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                ]
-            }
-        };
-        
-        bitmap.copy_from_slice(&pattern);
-        
-        FontGlyph {
-            width: 8,
-            height: 16,
-            bitmap,
-        }
+/// Draw `text` into `buffer` with its top-left corner at `x`, `y`, returning the x just past it
+pub(crate) fn draw_glyphs(buffer: &mut GraphicsBuffer, x: i32, y: i32, text: &str, color: Color) -> i32 {
+    if let Some(end_x) = font::draw_text(buffer, x, y, text, color) {
+        return end_x;
     }
-    
-    fn create_digit_glyph(&self, ch: char) -> FontGlyph {
-        let mut bitmap = vec![0u8; 16];
-        
-        let pattern = match ch {
-            '0' => [
-                0b01111100,
-                0b10000010,
-                0b10000110,
-                0b10001010,
-                0b10010010,
-                0b10100010,
-                0b11000010,
-                0b01111100,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-            ],
-            '1' => [
-                0b00011000,
-                0b00111000,
-                0b00011000,
-                0b00011000,
-                0b00011000,
-                0b00011000,
-                0b00011000,
-                0b01111110,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-            ],
-            _ => {
-                // Default pattern for other digits
-                let base = (ch as u8 - b'0') * 16;
-                [
-                    0b01111100 | (base & 0x0F),
-                    0b10000010,
-                    0b10000010,
-                    0b10000010,
-                    0b10000010,
-                    0b10000010,
-                    0b10000010,
-                    0b01111100,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                ]
-            }
-        };
-        
-        bitmap.copy_from_slice(&pattern);
-        
-        FontGlyph {
-            width: 8,
-            height: 16,
-            bitmap,
-        }
-    }
-    
-    fn create_punctuation_glyph(&self, ch: char) -> FontGlyph {
-        let mut bitmap = vec![0u8; 16];
-        
-        let pattern = match ch {
-            '.' => [
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00011000,
-                0b00011000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-            ],
-            '!' => [
-                0b00011000,
-                0b00011000,
-                0b00011000,
-                0b00011000,
-                0b00011000,
-                0b00000000,
-                0b00011000,
-                0b00011000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-                0b00000000,
-            ],
-            _ => {
-                // Default pattern for other punctuation
-                [
-                    0b00000000,
-                    0b00000000,
-                    0b00111100,
-                    0b00111100,
-                    0b00111100,
-                    0b00111100,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                    0b00000000,
-                ]
+    let mut current_x = x;
+    for ch in text.chars() {
+        for row in 0..font::bitmap::GLYPH_HEIGHT {
+            for col in 0..font::bitmap::GLYPH_WIDTH {
+                let (pixel_x, pixel_y) = (current_x + col as i32, y + row as i32);
+                if font::bitmap::is_lit(ch, col, row) && pixel_x >= 0 && pixel_y >= 0 {
+                    buffer.set_pixel(pixel_x as u32, pixel_y as u32, color);
+                }
             }
-        };
-        
-        bitmap.copy_from_slice(&pattern);
-        
-        FontGlyph {
-            width: 8,
-            height: 16,
-            bitmap,
         }
+        current_x += font::bitmap::GLYPH_WIDTH as i32;
     }
-    
-    fn get_glyph(&self, ch: char) -> Option<&FontGlyph> {
-        self.glyphs.get(&ch)
-    }
-}
-
-lazy_static! {
-    static ref DEFAULT_FONT: BitmapFont = BitmapFont::new();
+    current_x
 }
 
 pub fn draw_text(window_id: WindowId, x: i32, y: i32, text: &str, color: Color) -> Result<(), &'static str> {
     let mut wm = WINDOW_MANAGER.lock();
     if let Some(window) = wm.get_window_mut(window_id) {
         if let Some(buffer) = &mut window.buffer {
-            let mut current_x = x;
-            
-            for ch in text.chars() {
-                if let Some(glyph) = DEFAULT_FONT.get_glyph(ch) {
-                    // Draw the glyph bitmap
-                    for row in 0..glyph.height {
-                        let bitmap_row = glyph.bitmap[row as usize];
-                        for col in 0..glyph.width {
-                            if (bitmap_row >> (7 - col)) & 1 != 0 {
-                                let pixel_x = current_x + col as i32;
-                                let pixel_y = y + row as i32;
-                                
-                                // Check bounds
-                                if pixel_x >= 0 && pixel_y >= 0 && 
-                                   pixel_x < buffer.width as i32 && pixel_y < buffer.height as i32 {
-                                    buffer.set_pixel(pixel_x as u32, pixel_y as u32, color);
-                                }
-                            }
-                        }
-                    }
-                    current_x += glyph.width as i32;
-                } else {
-                    // Unknown character - draw a placeholder rectangle
-                    let char_rect = Rect::new(current_x, y, 8, 16);
-                    buffer.draw_rect(char_rect, Color::new(128, 128, 128, 255));
-                    current_x += 8;
-                }
-            }
+            draw_glyphs(buffer, x, y, text, color);
             Ok(())
         } else {
             Err("Window has no buffer")
@@ -1940,19 +2080,14 @@ pub fn draw_text(window_id: WindowId, x: i32, y: i32, text: &str, color: Color)
 
 // Additional text rendering functions
 pub fn get_text_width(text: &str) -> u32 {
-    let mut width = 0;
-    for ch in text.chars() {
-        if let Some(glyph) = DEFAULT_FONT.get_glyph(ch) {
-            width += glyph.width as u32;
-        } else {
-            width += 8; // Default character width
-        }
+    if let Some(width) = font::text_width(text) {
+        return width;
     }
-    width
+    text.chars().count() as u32 * font::bitmap::GLYPH_WIDTH
 }
 
 pub fn get_text_height() -> u32 {
-    DEFAULT_FONT.glyph_height as u32
+    font::line_height().unwrap_or(font::bitmap::GLYPH_HEIGHT)
 }
 
 pub fn draw_text_centered(window_id: WindowId, rect: Rect, text: &str, color: Color) -> Result<(), &'static str> {
@@ -2213,11 +2348,9 @@ pub fn cancel_current_operation() {
 }
 
 pub fn update_cursor_position(x: i32, y: i32) {
-    // Update global cursor position
-    static mut CURSOR_POS: (i32, i32) = (0, 0);
-    unsafe {
-        CURSOR_POS = (x, y);
-    }
+    // Update global cursor position, confined while input is grabbed
+    let mut wm = WINDOW_MANAGER.lock();
+    wm.move_cursor(x, y);
 }
 
 pub fn handle_window_drag(x: i32, y: i32, _delta_x: i32, _delta_y: i32) {
@@ -2232,12 +2365,8 @@ pub fn handle_window_drag(x: i32, y: i32, _delta_x: i32, _delta_y: i32) {
 }
 
 pub fn handle_mouse_hover(x: i32, y: i32) {
-    // Handle mouse hover effects
-    let wm = WINDOW_MANAGER.lock();
-    if let Some(_window_id) = wm.get_window_at_point(Point::new(x, y)) {
-        // Update hover state for window
-        // In a real implementation, this would update visual feedback
-    }
+    let mut wm = WINDOW_MANAGER.lock();
+    wm.dispatch_mouse_motion(x, y);
 }
 
 pub fn get_window_at_point(x: i32, y: i32) -> Option<u32> {
@@ -2247,6 +2376,9 @@ pub fn get_window_at_point(x: i32, y: i32) -> Option<u32> {
 
 pub fn start_window_drag_if_title_bar(x: i32, y: i32) {
     let wm = WINDOW_MANAGER.lock();
+    if wm.input_grab.is_some() {
+        return;
+    }
     if let Some(window_id) = wm.get_window_at_point(Point::new(x, y)) {
         if let Some(window) = wm.get_window(window_id) {
             // Check if click is in title bar area
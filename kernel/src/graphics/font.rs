@@ -0,0 +1,767 @@
+//! TrueType fonts
+//! Loads TrueType-flavoured OpenType fonts from the VFS and rasterizes their glyphs at any
+//! pixel size with anti-aliasing: outlines are flattened to lines and drawn into a coverage
+//! accumulation buffer, so every pixel gets the exact fraction of its area the glyph covers.
+//! Rasterized glyphs are cached per codepoint and size. Metrics (advances, `kern` table
+//! kerning, line height) are scaled to the same pixel size for layout.
+//!
+//! A font loaded with `load_system_font` replaces the built-in bitmap font in `draw_text`
+//! and the other text helpers of the graphics module, which lay text out with `shaping`.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::gles::floor;
+use super::shaping;
+use super::{Color, GraphicsBuffer};
+
+pub mod bitmap;
+
+/// Largest pixel size glyphs are rasterized at
+pub const MAX_SIZE: u32 = 512;
+/// The glyph cache is emptied when it grows past this many glyphs
+const MAX_CACHED_GLYPHS: usize = 1024;
+/// Composite glyphs nested deeper than this are rejected
+const MAX_COMPONENT_DEPTH: u8 = 8;
+
+static SYSTEM_FONT: Mutex<Option<(Font, u32)>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontError {
+    /// The file could not be read from the VFS
+    Unreadable,
+    /// Not a font, or one with CFF outlines
+    UnsupportedFormat,
+    /// A table the rasterizer needs is absent
+    MissingTable(&'static str),
+    /// A table or glyph extends past the end of its data
+    Truncated,
+    /// Inconsistent table contents
+    Corrupt,
+    /// A pixel size of zero or above `MAX_SIZE`
+    InvalidSize,
+}
+
+/// Vertical metrics in pixels at one size; `descent` is negative below the baseline
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineMetrics {
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_gap: f32,
+    /// Baseline-to-baseline distance
+    pub line_height: f32,
+}
+
+/// A rasterized glyph: one coverage byte per pixel, row by row from the top
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glyph {
+    pub width: u32,
+    pub height: u32,
+    /// Offset of the left column from the pen position
+    pub left: i32,
+    /// Offset of the top row above the baseline
+    pub top: i32,
+    /// Distance to move the pen for the next glyph
+    pub advance: f32,
+    pub coverage: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Table {
+    offset: usize,
+    length: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Point {
+    x: f32,
+    y: f32,
+    on_curve: bool,
+}
+
+impl Point {
+    fn midpoint(self, other: Point) -> Point {
+        Point { x: (self.x + other.x) * 0.5, y: (self.y + other.y) * 0.5, on_curve: true }
+    }
+}
+
+type Contour = Vec<Point>;
+
+fn read_u8(data: &[u8], offset: usize) -> Result<u8, FontError> {
+    data.get(offset).copied().ok_or(FontError::Truncated)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, FontError> {
+    data.get(offset..offset + 2).map(|b| u16::from_be_bytes([b[0], b[1]])).ok_or(FontError::Truncated)
+}
+
+fn read_i16(data: &[u8], offset: usize) -> Result<i16, FontError> {
+    read_u16(data, offset).map(|value| value as i16)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, FontError> {
+    data.get(offset..offset + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]])).ok_or(FontError::Truncated)
+}
+
+/// A 2.14 fixed-point number
+fn read_f2dot14(data: &[u8], offset: usize) -> Result<f32, FontError> {
+    read_i16(data, offset).map(|value| value as f32 / 16384.0)
+}
+
+fn ceil(value: f32) -> f32 {
+    -floor(-value)
+}
+
+pub struct Font {
+    data: Vec<u8>,
+    units_per_em: u16,
+    num_glyphs: u16,
+    long_loca: bool,
+    ascender: i16,
+    descender: i16,
+    line_gap: i16,
+    num_h_metrics: u16,
+    /// The chosen character-to-glyph subtable and its format (4 or 12)
+    cmap: (usize, u16),
+    hmtx: Table,
+    loca: Table,
+    glyf: Table,
+    kerning: BTreeMap<(u16, u16), i16>,
+    cache: BTreeMap<(char, u32), Arc<Glyph>>,
+}
+
+impl Font {
+    /// Read a font file from the VFS
+    pub fn load(path: &str) -> Result<Font, FontError> {
+        let data = crate::filesystem::read_file(path).map_err(|_| FontError::Unreadable)?;
+        Font::from_bytes(data)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Font, FontError> {
+        match read_u32(&data, 0)? {
+            0x0001_0000 | 0x7472_7565 => {}
+            _ => return Err(FontError::UnsupportedFormat),
+        }
+        let mut tables = BTreeMap::new();
+        for i in 0..read_u16(&data, 4)? as usize {
+            let record = 12 + i * 16;
+            let tag = read_u32(&data, record)?.to_be_bytes();
+            let offset = read_u32(&data, record + 8)? as usize;
+            let length = read_u32(&data, record + 12)? as usize;
+            if offset.checked_add(length).is_none_or(|end| end > data.len()) {
+                return Err(FontError::Truncated);
+            }
+            tables.insert(tag, Table { offset, length });
+        }
+        let table = |tag: &'static str| -> Result<Table, FontError> {
+            let key: [u8; 4] = tag.as_bytes().try_into().map_err(|_| FontError::MissingTable(tag))?;
+            tables.get(&key).copied().ok_or(FontError::MissingTable(tag))
+        };
+
+        let head = table("head")?;
+        if head.length < 54 || read_u32(&data, head.offset + 12)? != 0x5F0F_3CF5 {
+            return Err(FontError::Corrupt);
+        }
+        let units_per_em = read_u16(&data, head.offset + 18)?;
+        if !(16..=16384).contains(&units_per_em) {
+            return Err(FontError::Corrupt);
+        }
+        let long_loca = read_i16(&data, head.offset + 50)? != 0;
+
+        let num_glyphs = read_u16(&data, table("maxp")?.offset + 4)?;
+        let hhea = table("hhea")?;
+        if hhea.length < 36 {
+            return Err(FontError::Truncated);
+        }
+        let num_h_metrics = read_u16(&data, hhea.offset + 34)?;
+        let hmtx = table("hmtx")?;
+        let loca = table("loca")?;
+        let loca_entry = if long_loca { 4 } else { 2 };
+        if num_h_metrics == 0 || num_h_metrics > num_glyphs || num_glyphs == 0 {
+            return Err(FontError::Corrupt);
+        }
+        if hmtx.length < num_h_metrics as usize * 4 || loca.length < (num_glyphs as usize + 1) * loca_entry {
+            return Err(FontError::Truncated);
+        }
+
+        let cmap = Self::find_cmap(&data, table("cmap")?)?;
+        let kerning = match table("kern") {
+            Ok(kern) => Self::read_kerning(&data, kern)?,
+            Err(_) => BTreeMap::new(),
+        };
+        Ok(Font {
+            units_per_em,
+            num_glyphs,
+            long_loca,
+            ascender: read_i16(&data, hhea.offset + 4)?,
+            descender: read_i16(&data, hhea.offset + 6)?,
+            line_gap: read_i16(&data, hhea.offset + 8)?,
+            num_h_metrics,
+            cmap,
+            hmtx,
+            loca,
+            glyf: table("glyf")?,
+            kerning,
+            cache: BTreeMap::new(),
+            data,
+        })
+    }
+
+    /// The Unicode subtable of `cmap`, preferring the full-repertoire format 12 over format 4
+    fn find_cmap(data: &[u8], cmap: Table) -> Result<(usize, u16), FontError> {
+        let mut best: Option<(usize, u16)> = None;
+        for i in 0..read_u16(data, cmap.offset + 2)? as usize {
+            let record = cmap.offset + 4 + i * 8;
+            let platform = read_u16(data, record)?;
+            let encoding = read_u16(data, record + 2)?;
+            let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+            let subtable = cmap.offset + read_u32(data, record + 4)? as usize;
+            if !unicode || subtable >= cmap.offset + cmap.length {
+                continue;
+            }
+            let format = read_u16(data, subtable)?;
+            if (format == 4 || format == 12) && best.is_none_or(|(_, chosen)| format > chosen) {
+                best = Some((subtable, format));
+            }
+        }
+        let (subtable, format) = best.ok_or(FontError::MissingTable("cmap"))?;
+        // The fixed part of the subtable must be present for lookups
+        read_u16(data, subtable + if format == 4 { 14 } else { 16 })?;
+        Ok((subtable, format))
+    }
+
+    /// The pairs of the horizontal format 0 subtables of `kern`
+    fn read_kerning(data: &[u8], kern: Table) -> Result<BTreeMap<(u16, u16), i16>, FontError> {
+        let mut pairs = BTreeMap::new();
+        if read_u16(data, kern.offset)? != 0 {
+            // Only the Windows version of the table is understood
+            return Ok(pairs);
+        }
+        let mut subtable = kern.offset + 4;
+        for _ in 0..read_u16(data, kern.offset + 2)? {
+            let length = read_u16(data, subtable + 2)? as usize;
+            let coverage = read_u16(data, subtable + 4)?;
+            if length < 6 {
+                return Err(FontError::Corrupt);
+            }
+            // Horizontal, not minimum values or cross-stream, format 0
+            if coverage & 0xFF07 == 0x0001 {
+                for pair in 0..read_u16(data, subtable + 6)? as usize {
+                    let entry = subtable + 14 + pair * 6;
+                    pairs.insert((read_u16(data, entry)?, read_u16(data, entry + 2)?), read_i16(data, entry + 4)?);
+                }
+            }
+            subtable += length;
+        }
+        Ok(pairs)
+    }
+
+    pub fn units_per_em(&self) -> u16 {
+        self.units_per_em
+    }
+
+    pub fn num_glyphs(&self) -> u16 {
+        self.num_glyphs
+    }
+
+    /// `units` in pixels at `size` pixels per em, exact when the result is representable
+    pub fn to_pixels(&self, units: f32, size: u32) -> f32 {
+        units * size as f32 / self.units_per_em as f32
+    }
+
+    /// The glyph for `ch`, or 0 (the missing-glyph box) when the font has none
+    pub fn glyph_index(&self, ch: char) -> u16 {
+        let index = match self.cmap.1 {
+            4 => self.cmap_format4(ch as u32),
+            _ => self.cmap_format12(ch as u32),
+        };
+        index.ok().filter(|&index| index < self.num_glyphs).unwrap_or(0)
+    }
+
+    fn cmap_format4(&self, codepoint: u32) -> Result<u16, FontError> {
+        let Ok(c) = u16::try_from(codepoint) else { return Ok(0) };
+        let data = &self.data;
+        let base = self.cmap.0;
+        let seg_x2 = read_u16(data, base + 6)? as usize;
+        for segment in (0..seg_x2).step_by(2) {
+            if read_u16(data, base + 14 + segment)? < c {
+                continue;
+            }
+            let start = read_u16(data, base + 16 + seg_x2 + segment)?;
+            if start > c {
+                return Ok(0);
+            }
+            let delta = read_u16(data, base + 16 + 2 * seg_x2 + segment)?;
+            let range_at = base + 16 + 3 * seg_x2 + segment;
+            let range = read_u16(data, range_at)? as usize;
+            if range == 0 {
+                return Ok(c.wrapping_add(delta));
+            }
+            let index = read_u16(data, range_at + range + 2 * (c - start) as usize)?;
+            return Ok(if index == 0 { 0 } else { index.wrapping_add(delta) });
+        }
+        Ok(0)
+    }
+
+    fn cmap_format12(&self, codepoint: u32) -> Result<u16, FontError> {
+        let base = self.cmap.0;
+        for group in 0..read_u32(&self.data, base + 12)? as usize {
+            let entry = base + 16 + group * 12;
+            let start = read_u32(&self.data, entry)?;
+            if (start..=read_u32(&self.data, entry + 4)?).contains(&codepoint) {
+                let index = read_u32(&self.data, entry + 8)? + (codepoint - start);
+                return Ok(u16::try_from(index).unwrap_or(0));
+            }
+        }
+        Ok(0)
+    }
+
+    fn advance_units(&self, glyph: u16) -> u16 {
+        let entry = glyph.min(self.num_h_metrics - 1) as usize;
+        read_u16(&self.data, self.hmtx.offset + entry * 4).unwrap_or(0)
+    }
+
+    pub fn line_metrics(&self, size: u32) -> LineMetrics {
+        let (ascent, descent, line_gap) = (
+            self.to_pixels(self.ascender as f32, size),
+            self.to_pixels(self.descender as f32, size),
+            self.to_pixels(self.line_gap as f32, size),
+        );
+        LineMetrics { ascent, descent, line_gap, line_height: ascent - descent + line_gap }
+    }
+
+    /// How far the pen moves after `ch` at `size` pixels per em
+    pub fn advance(&self, ch: char, size: u32) -> f32 {
+        self.to_pixels(self.advance_units(self.glyph_index(ch)) as f32, size)
+    }
+
+    /// The adjustment to the pen between `left` and `right`, negative to move them closer
+    pub fn kerning(&self, left: char, right: char, size: u32) -> f32 {
+        let pair = (self.glyph_index(left), self.glyph_index(right));
+        self.kerning.get(&pair).map_or(0.0, |&value| self.to_pixels(value as f32, size))
+    }
+
+    /// The width of `text` laid out on one line
+    pub fn text_width(&self, text: &str, size: u32) -> f32 {
+        shaping::shape(self, text, size, None).width
+    }
+
+    /// The outline's bounding box of `ch` in font units as x min, y min, x max, y max, or
+    /// `None` for a glyph without an outline
+    pub fn glyph_bounds(&self, ch: char) -> Option<[i16; 4]> {
+        let offset = self.glyph_data(self.glyph_index(ch)).ok()??;
+        let mut bounds = [0i16; 4];
+        for (i, bound) in bounds.iter_mut().enumerate() {
+            *bound = read_i16(&self.data, offset + 2 + i * 2).ok()?;
+        }
+        Some(bounds)
+    }
+
+    /// `ch` rasterized at `size` pixels per em, from the cache when it was rasterized before
+    pub fn glyph(&mut self, ch: char, size: u32) -> Result<Arc<Glyph>, FontError> {
+        if size == 0 || size > MAX_SIZE {
+            return Err(FontError::InvalidSize);
+        }
+        if let Some(glyph) = self.cache.get(&(ch, size)) {
+            return Ok(glyph.clone());
+        }
+        let index = self.glyph_index(ch);
+        let contours = self.outline(index, 0)?;
+        let mut glyph = rasterize(&contours, |units| self.to_pixels(units, size), size)?;
+        glyph.advance = self.to_pixels(self.advance_units(index) as f32, size);
+        let glyph = Arc::new(glyph);
+        if self.cache.len() >= MAX_CACHED_GLYPHS {
+            self.cache.clear();
+        }
+        self.cache.insert((ch, size), glyph.clone());
+        Ok(glyph)
+    }
+
+    pub fn cached_glyphs(&self) -> usize {
+        self.cache.len()
+    }
+
+    fn glyph_data(&self, glyph: u16) -> Result<Option<usize>, FontError> {
+        let loca = |index: usize| -> Result<usize, FontError> {
+            Ok(if self.long_loca {
+                read_u32(&self.data, self.loca.offset + index * 4)? as usize
+            } else {
+                read_u16(&self.data, self.loca.offset + index * 2)? as usize * 2
+            })
+        };
+        let (start, end) = (loca(glyph as usize)?, loca(glyph as usize + 1)?);
+        if start > end || end > self.glyf.length {
+            return Err(FontError::Corrupt);
+        }
+        // A glyph without data, such as a space, has no outline
+        Ok((end - start >= 10).then_some(self.glyf.offset + start))
+    }
+
+    /// The contours of `glyph` in font units
+    fn outline(&self, glyph: u16, depth: u8) -> Result<Vec<Contour>, FontError> {
+        let Some(offset) = self.glyph_data(glyph)? else { return Ok(Vec::new()) };
+        let contours = read_i16(&self.data, offset)?;
+        if contours >= 0 {
+            self.simple_outline(offset, contours as usize)
+        } else if depth < MAX_COMPONENT_DEPTH {
+            self.composite_outline(offset, depth)
+        } else {
+            Err(FontError::Corrupt)
+        }
+    }
+
+    fn simple_outline(&self, offset: usize, contours: usize) -> Result<Vec<Contour>, FontError> {
+        let data = &self.data;
+        let mut ends = Vec::with_capacity(contours);
+        for i in 0..contours {
+            let end = read_u16(data, offset + 10 + i * 2)? as usize;
+            if ends.last().is_some_and(|&last| end < last) {
+                return Err(FontError::Corrupt);
+            }
+            ends.push(end);
+        }
+        let points = ends.last().map_or(0, |&last| last + 1);
+        let mut at = offset + 12 + contours * 2 + read_u16(data, offset + 10 + contours * 2)? as usize;
+
+        let mut flags = Vec::with_capacity(points);
+        while flags.len() < points {
+            let flag = read_u8(data, at)?;
+            at += 1;
+            let repeats = if flag & 0x08 != 0 {
+                at += 1;
+                read_u8(data, at - 1)? as usize
+            } else {
+                0
+            };
+            for _ in 0..=repeats.min(points - flags.len() - 1) {
+                flags.push(flag);
+            }
+        }
+
+        // x coordinates, then y coordinates, as deltas: a byte whose sign is in the flags, the
+        // previous value repeated, or a signed word
+        let mut coordinates = [vec![0i32; points], vec![0i32; points]];
+        for (axis, (short, same)) in [(0x02u8, 0x10u8), (0x04, 0x20)].into_iter().enumerate() {
+            let mut value = 0i32;
+            for (i, &flag) in flags.iter().enumerate() {
+                if flag & short != 0 {
+                    let delta = read_u8(data, at)? as i32;
+                    at += 1;
+                    value += if flag & same != 0 { delta } else { -delta };
+                } else if flag & same == 0 {
+                    value += read_i16(data, at)? as i32;
+                    at += 2;
+                }
+                coordinates[axis][i] = value;
+            }
+        }
+
+        let mut outline = Vec::with_capacity(contours);
+        let mut start = 0;
+        for end in ends {
+            outline.push(
+                (start..=end)
+                    .map(|i| Point { x: coordinates[0][i] as f32, y: coordinates[1][i] as f32, on_curve: flags[i] & 0x01 != 0 })
+                    .collect(),
+            );
+            start = end + 1;
+        }
+        Ok(outline)
+    }
+
+    fn composite_outline(&self, offset: usize, depth: u8) -> Result<Vec<Contour>, FontError> {
+        const ARGS_ARE_WORDS: u16 = 0x0001;
+        const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+        const HAVE_SCALE: u16 = 0x0008;
+        const MORE_COMPONENTS: u16 = 0x0020;
+        const HAVE_X_AND_Y_SCALE: u16 = 0x0040;
+        const HAVE_TWO_BY_TWO: u16 = 0x0080;
+
+        let data = &self.data;
+        let mut outline: Vec<Contour> = Vec::new();
+        let mut at = offset + 10;
+        loop {
+            let flags = read_u16(data, at)?;
+            let component = read_u16(data, at + 2)?;
+            at += 4;
+            let (arg1, arg2) = if flags & ARGS_ARE_WORDS != 0 {
+                at += 4;
+                (read_u16(data, at - 4)?, read_u16(data, at - 2)?)
+            } else {
+                at += 2;
+                (read_u8(data, at - 2)? as u16, read_u8(data, at - 1)? as u16)
+            };
+            // The 2×2 matrix [a c; b d] applied to the component's points
+            let (a, b, c, d) = if flags & HAVE_SCALE != 0 {
+                at += 2;
+                let scale = read_f2dot14(data, at - 2)?;
+                (scale, 0.0, 0.0, scale)
+            } else if flags & HAVE_X_AND_Y_SCALE != 0 {
+                at += 4;
+                (read_f2dot14(data, at - 4)?, 0.0, 0.0, read_f2dot14(data, at - 2)?)
+            } else if flags & HAVE_TWO_BY_TWO != 0 {
+                at += 8;
+                (read_f2dot14(data, at - 8)?, read_f2dot14(data, at - 6)?, read_f2dot14(data, at - 4)?, read_f2dot14(data, at - 2)?)
+            } else {
+                (1.0, 0.0, 0.0, 1.0)
+            };
+
+            let mut parts = self.outline(component, depth + 1)?;
+            for point in parts.iter_mut().flatten() {
+                (point.x, point.y) = (a * point.x + c * point.y, b * point.x + d * point.y);
+            }
+            let (dx, dy) = if flags & ARGS_ARE_XY_VALUES != 0 {
+                if flags & ARGS_ARE_WORDS != 0 {
+                    (arg1 as i16 as f32, arg2 as i16 as f32)
+                } else {
+                    (arg1 as u8 as i8 as f32, arg2 as u8 as i8 as f32)
+                }
+            } else {
+                // Move the component so its point `arg2` lands on point `arg1` of the glyph so far
+                let anchor = outline.iter().flatten().nth(arg1 as usize).ok_or(FontError::Corrupt)?;
+                let point = parts.iter().flatten().nth(arg2 as usize).ok_or(FontError::Corrupt)?;
+                (anchor.x - point.x, anchor.y - point.y)
+            };
+            for point in parts.iter_mut().flatten() {
+                point.x += dx;
+                point.y += dy;
+            }
+            outline.append(&mut parts);
+
+            if flags & MORE_COMPONENTS == 0 {
+                return Ok(outline);
+            }
+        }
+    }
+}
+
+/// Coverage accumulation: each line adds the signed area it covers to the cells it crosses,
+/// and a running sum along each row then gives every pixel's coverage
+struct Rasterizer {
+    width: usize,
+    height: usize,
+    accumulation: Vec<f32>,
+}
+
+impl Rasterizer {
+    fn new(width: usize, height: usize) -> Self {
+        // One spare cell for lines touching the right edge of the last row
+        Rasterizer { width, height, accumulation: vec![0.0; width * height + 1] }
+    }
+
+    fn add(&mut self, index: isize, value: f32) {
+        if let Some(cell) = usize::try_from(index).ok().and_then(|index| self.accumulation.get_mut(index)) {
+            *cell += value;
+        }
+    }
+
+    fn line(&mut self, from: (f32, f32), to: (f32, f32)) {
+        if from.1 == to.1 {
+            return;
+        }
+        let (direction, from, to) = if from.1 < to.1 { (1.0, from, to) } else { (-1.0, to, from) };
+        let dxdy = (to.0 - from.0) / (to.1 - from.1);
+        let mut x = from.0;
+        let end_row = (ceil(to.1) as usize).min(self.height);
+        for y in from.1 as usize..end_row {
+            let row = (y * self.width) as isize;
+            let dy = to.1.min(y as f32 + 1.0) - from.1.max(y as f32);
+            let x_next = x + dxdy * dy;
+            let d = dy * direction;
+            let (x0, x1) = if x < x_next { (x, x_next) } else { (x_next, x) };
+            let x0_floor = floor(x0);
+            let x0i = x0_floor as isize;
+            let x1_ceil = ceil(x1);
+            let x1i = x1_ceil as isize;
+            if x1i <= x0i + 1 {
+                // Within one cell: split by where the line crosses it on average
+                let mid = 0.5 * (x + x_next) - x0_floor;
+                self.add(row + x0i, d - d * mid);
+                self.add(row + x0i + 1, d * mid);
+            } else {
+                let s = 1.0 / (x1 - x0);
+                let x0f = x0 - x0_floor;
+                let a0 = 0.5 * s * (1.0 - x0f) * (1.0 - x0f);
+                let x1f = x1 - x1_ceil + 1.0;
+                let am = 0.5 * s * x1f * x1f;
+                self.add(row + x0i, d * a0);
+                if x1i == x0i + 2 {
+                    self.add(row + x0i + 1, d * (1.0 - a0 - am));
+                } else {
+                    let a1 = s * (1.5 - x0f);
+                    self.add(row + x0i + 1, d * (a1 - a0));
+                    for xi in x0i + 2..x1i - 1 {
+                        self.add(row + xi, d * s);
+                    }
+                    let a2 = a1 + (x1i - x0i - 3) as f32 * s;
+                    self.add(row + x1i - 1, d * (1.0 - a2 - am));
+                }
+                self.add(row + x1i, d * am);
+            }
+            x = x_next;
+        }
+    }
+
+    fn quadratic(&mut self, from: (f32, f32), control: (f32, f32), to: (f32, f32)) {
+        // Split into enough lines that none strays more than a fraction of a pixel
+        let (ddx, ddy) = (from.0 - 2.0 * control.0 + to.0, from.1 - 2.0 * control.1 + to.1);
+        let deviation = (ddx * ddx + ddy * ddy) as u64;
+        let steps = (1 + (deviation * 3).isqrt().isqrt()).min(64) as usize;
+        let mut previous = from;
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            let u = 1.0 - t;
+            let point = (
+                u * u * from.0 + 2.0 * u * t * control.0 + t * t * to.0,
+                u * u * from.1 + 2.0 * u * t * control.1 + t * t * to.1,
+            );
+            self.line(previous, point);
+            previous = point;
+        }
+    }
+
+    fn coverage(self) -> Vec<u8> {
+        let mut sum = 0.0f32;
+        self.accumulation[..self.width * self.height]
+            .iter()
+            .map(|&cell| {
+                sum += cell;
+                (sum.abs().min(1.0) * 255.0 + 0.5) as u8
+            })
+            .collect()
+    }
+}
+
+/// Draw `contours` (in font units) at `size` pixels per em, with `to_pixels` converting units
+fn rasterize(contours: &[Contour], to_pixels: impl Fn(f32) -> f32, size: u32) -> Result<Glyph, FontError> {
+    let mut points = contours.iter().flatten();
+    let Some(first) = points.next() else {
+        return Ok(Glyph { width: 0, height: 0, left: 0, top: 0, advance: 0.0, coverage: Vec::new() });
+    };
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (first.x, first.y, first.x, first.y);
+    for point in points {
+        (min_x, max_x) = (min_x.min(point.x), max_x.max(point.x));
+        (min_y, max_y) = (min_y.min(point.y), max_y.max(point.y));
+    }
+    // Control points bound the curves, so the box holds the whole outline
+    let left = floor(to_pixels(min_x));
+    let top = ceil(to_pixels(max_y));
+    let width = ceil(to_pixels(max_x)) - left;
+    let height = top - floor(to_pixels(min_y));
+    let limit = (4 * size) as f32;
+    if width > limit || height > limit {
+        return Err(FontError::Corrupt);
+    }
+    let (width, height) = (width as usize, height as usize);
+
+    // Pixel coordinates, y down from the top of the bitmap
+    let pixel = |point: Point| {
+        ((to_pixels(point.x) - left).clamp(0.0, width as f32), (top - to_pixels(point.y)).clamp(0.0, height as f32))
+    };
+    let mut rasterizer = Rasterizer::new(width, height);
+    for contour in contours.iter().filter(|contour| !contour.is_empty()) {
+        // Start on an on-curve point, or midway between two off-curve ones
+        let (start, rest) = match (contour[0], contour[contour.len() - 1]) {
+            (first, _) if first.on_curve => (first, &contour[1..]),
+            (_, last) if last.on_curve => (last, &contour[..contour.len() - 1]),
+            (first, last) => (first.midpoint(last), &contour[..]),
+        };
+        let mut previous = start;
+        let mut control: Option<Point> = None;
+        for &point in rest.iter().chain(core::iter::once(&start)) {
+            if point.on_curve {
+                match control.take() {
+                    Some(c) => rasterizer.quadratic(pixel(previous), pixel(c), pixel(point)),
+                    None => rasterizer.line(pixel(previous), pixel(point)),
+                }
+                previous = point;
+            } else {
+                // Two off-curve points in a row imply an on-curve point between them
+                if let Some(c) = control {
+                    let middle = c.midpoint(point);
+                    rasterizer.quadratic(pixel(previous), pixel(c), pixel(middle));
+                    previous = middle;
+                }
+                control = Some(point);
+            }
+        }
+    }
+
+    Ok(Glyph {
+        width: width as u32,
+        height: height as u32,
+        left: left as i32,
+        top: top as i32,
+        advance: 0.0,
+        coverage: rasterizer.coverage(),
+    })
+}
+
+/// Use the font at `path` at `size` pixels per em for the text of the graphics module
+pub fn load_system_font(path: &str, size: u32) -> Result<(), FontError> {
+    if size == 0 || size > MAX_SIZE {
+        return Err(FontError::InvalidSize);
+    }
+    let font = Font::load(path)?;
+    *SYSTEM_FONT.lock() = Some((font, size));
+    Ok(())
+}
+
+/// Go back to the built-in bitmap font
+pub fn unload_system_font() {
+    *SYSTEM_FONT.lock() = None;
+}
+
+/// Blend `color` over `pixel` at `coverage` / 255 of its alpha
+fn blend(pixel: u32, color: Color, coverage: u8) -> u32 {
+    let alpha = coverage as u32 * color.a as u32 / 255;
+    let mix = |source: u32, shift: u32| (source * alpha + ((pixel >> shift) & 0xFF) * (255 - alpha) + 127) / 255;
+    let a = alpha + ((pixel >> 24) & 0xFF) * (255 - alpha) / 255;
+    (a << 24) | (mix(color.r as u32, 16) << 16) | (mix(color.g as u32, 8) << 8) | mix(color.b as u32, 0)
+}
+
+/// Draw `text` with the system font, its line's top at `y`, returning the x just past it,
+/// or `None` without a system font
+pub(super) fn draw_text(buffer: &mut GraphicsBuffer, x: i32, y: i32, text: &str, color: Color) -> Option<i32> {
+    let mut system_font = SYSTEM_FONT.lock();
+    let (font, size) = system_font.as_mut()?;
+    let size = *size;
+    let baseline = y + floor(font.line_metrics(size).ascent + 0.5) as i32;
+    let run = shaping::shape(font, text, size, None);
+    for positioned in &run.glyphs {
+        let Ok(glyph) = font.glyph(positioned.ch, size) else { continue };
+        let origin_x = floor(x as f32 + positioned.x + 0.5) as i32 + glyph.left;
+        let origin_y = baseline - floor(positioned.y + 0.5) as i32 - glyph.top;
+        for (row, line) in glyph.coverage.chunks(glyph.width.max(1) as usize).enumerate() {
+            let py = origin_y + row as i32;
+            for (column, &coverage) in line.iter().enumerate() {
+                let px = origin_x + column as i32;
+                if coverage == 0 || px < 0 || py < 0 || px >= buffer.width as i32 || py >= buffer.height as i32 {
+                    continue;
+                }
+                let index = (py as u32 * buffer.width + px as u32) as usize;
+                if let Some(pixel) = buffer.pixels.get_mut(index) {
+                    *pixel = blend(*pixel, color, coverage);
+                }
+            }
+        }
+    }
+    Some(x + ceil(run.width) as i32)
+}
+
+/// The width of `text` in the system font, or `None` without one
+pub(super) fn text_width(text: &str) -> Option<u32> {
+    let system_font = SYSTEM_FONT.lock();
+    let (font, size) = system_font.as_ref()?;
+    Some(ceil(font.text_width(text, *size)) as u32)
+}
+
+/// The line height of the system font, or `None` without one
+pub(super) fn line_height() -> Option<u32> {
+    let system_font = SYSTEM_FONT.lock();
+    let (font, size) = system_font.as_ref()?;
+    Some(ceil(font.line_metrics(*size).line_height) as u32)
+}
@@ -0,0 +1,526 @@
+//! Software OpenGL ES subset
+//! A fixed-function renderer for apps that want 3D without a GPU. Triangles are transformed
+//! by a model-view-projection matrix, clipped against the near plane, rasterized with
+//! perspective-correct interpolation and depth tested, then shaded with their interpolated
+//! vertex color, modulated by the bound texture if there is one. A context renders into any
+//! `GraphicsBuffer` of its size, such as a window's buffer through
+//! `graphics::with_window_buffer`.
+//!
+//! Coordinates follow OpenGL: after the transform, x and y run from -1 (left, bottom) to 1
+//! (right, top) across the viewport and z from -1 (near) to 1 (far).
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::Mul;
+
+use super::{Color, GraphicsBuffer, Rect};
+
+pub type TextureId = u32;
+
+/// Triangles with a vertex this close to the eye plane are not drawn
+const MIN_W: f32 = 1e-6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlError {
+    /// A size, count or index out of range
+    InvalidValue,
+    InvalidTexture,
+    /// The target buffer is not the size of the context
+    InvalidFramebuffer,
+    OutOfMemory,
+}
+
+/// A 4×4 matrix applied to column vectors, stored row by row
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4(pub [[f32; 4]; 4]);
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+
+    pub fn translation(x: f32, y: f32, z: f32) -> Self {
+        Mat4([
+            [1.0, 0.0, 0.0, x],
+            [0.0, 1.0, 0.0, y],
+            [0.0, 0.0, 1.0, z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scale(x: f32, y: f32, z: f32) -> Self {
+        Mat4([
+            [x, 0.0, 0.0, 0.0],
+            [0.0, y, 0.0, 0.0],
+            [0.0, 0.0, z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Parallel projection of the given box, as `glOrtho`
+    pub fn ortho(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Self {
+        let (width, height, depth) = (right - left, top - bottom, far - near);
+        Mat4([
+            [2.0 / width, 0.0, 0.0, -(right + left) / width],
+            [0.0, 2.0 / height, 0.0, -(top + bottom) / height],
+            [0.0, 0.0, -2.0 / depth, -(far + near) / depth],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Perspective projection of the given view frustum, as `glFrustum`
+    pub fn frustum(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Self {
+        let (width, height, depth) = (right - left, top - bottom, far - near);
+        Mat4([
+            [2.0 * near / width, 0.0, (right + left) / width, 0.0],
+            [0.0, 2.0 * near / height, (top + bottom) / height, 0.0],
+            [0.0, 0.0, -(far + near) / depth, -2.0 * far * near / depth],
+            [0.0, 0.0, -1.0, 0.0],
+        ])
+    }
+
+    pub fn transform(&self, v: [f32; 4]) -> [f32; 4] {
+        self.0.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2] + row[3] * v[3])
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut product = [[0.0; 4]; 4];
+        for (row, out) in self.0.iter().zip(product.iter_mut()) {
+            for (column, cell) in out.iter_mut().enumerate() {
+                *cell = (0..4).map(|k| row[k] * other.0[k][column]).sum();
+            }
+        }
+        Mat4(product)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub color: Color,
+    /// (0, 0) is the texture's first texel and (1, 1) the far corner of its last
+    pub tex_coord: [f32; 2],
+}
+
+impl Vertex {
+    pub fn new(position: [f32; 3], color: Color, tex_coord: [f32; 2]) -> Self {
+        Self { position, color, tex_coord }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthFunc {
+    Less,
+    LessEqual,
+    Always,
+}
+
+impl DepthFunc {
+    fn passes(self, depth: f32, stored: f32) -> bool {
+        match self {
+            DepthFunc::Less => depth < stored,
+            DepthFunc::LessEqual => depth <= stored,
+            DepthFunc::Always => true,
+        }
+    }
+}
+
+/// How texture coordinates outside 0..1 are resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureWrap {
+    Repeat,
+    ClampToEdge,
+}
+
+/// Sampled nearest-texel; texels use the `GraphicsBuffer` pixel format
+struct Texture {
+    width: u32,
+    height: u32,
+    texels: Vec<u32>,
+    wrap: TextureWrap,
+}
+
+pub(super) fn floor(value: f32) -> f32 {
+    let truncated = value as i64 as f32;
+    if truncated > value { truncated - 1.0 } else { truncated }
+}
+
+impl Texture {
+    fn texel_index(&self, coord: f32, size: u32) -> i64 {
+        let index = floor(coord * size as f32) as i64;
+        match self.wrap {
+            TextureWrap::Repeat => index.rem_euclid(i64::from(size)),
+            TextureWrap::ClampToEdge => index.clamp(0, i64::from(size) - 1),
+        }
+    }
+
+    fn sample(&self, u: f32, v: f32) -> [f32; 4] {
+        let x = self.texel_index(u, self.width);
+        let y = self.texel_index(v, self.height);
+        unpack(self.texels[(y * i64::from(self.width) + x) as usize])
+    }
+}
+
+fn unpack(pixel: u32) -> [f32; 4] {
+    let channel = |shift: u32| ((pixel >> shift) & 0xFF) as f32 / 255.0;
+    [channel(16), channel(8), channel(0), channel(24)]
+}
+
+fn pack(rgba: [f32; 4]) -> u32 {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0 + 0.5) as u32;
+    (channel(rgba[3]) << 24) | (channel(rgba[0]) << 16) | (channel(rgba[1]) << 8) | channel(rgba[2])
+}
+
+fn color_to_rgba(color: Color) -> [f32; 4] {
+    [color.r, color.g, color.b, color.a].map(|channel| f32::from(channel) / 255.0)
+}
+
+/// A vertex after the transform, in clip coordinates
+#[derive(Clone, Copy)]
+struct ClipVertex {
+    position: [f32; 4],
+    color: [f32; 4],
+    tex_coord: [f32; 2],
+}
+
+impl ClipVertex {
+    /// Signed distance inside the near plane, where z = -w
+    fn near_distance(&self) -> f32 {
+        self.position[2] + self.position[3]
+    }
+
+    fn lerp(&self, other: &ClipVertex, t: f32) -> ClipVertex {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        ClipVertex {
+            position: core::array::from_fn(|i| mix(self.position[i], other.position[i])),
+            color: core::array::from_fn(|i| mix(self.color[i], other.color[i])),
+            tex_coord: core::array::from_fn(|i| mix(self.tex_coord[i], other.tex_coord[i])),
+        }
+    }
+}
+
+/// The part of a triangle in front of the near plane, as a convex polygon of up to four
+/// vertices
+fn clip_near(triangle: [ClipVertex; 3]) -> ([ClipVertex; 4], usize) {
+    let mut polygon = [triangle[0]; 4];
+    let mut len = 0;
+    for (i, current) in triangle.iter().enumerate() {
+        let next = &triangle[(i + 1) % 3];
+        let (current_distance, next_distance) = (current.near_distance(), next.near_distance());
+        if current_distance >= 0.0 {
+            polygon[len] = *current;
+            len += 1;
+        }
+        if (current_distance >= 0.0) != (next_distance >= 0.0) {
+            polygon[len] = current.lerp(next, current_distance / (current_distance - next_distance));
+            len += 1;
+        }
+    }
+    (polygon, len)
+}
+
+/// A vertex in window coordinates, with its attributes divided by w for perspective-correct
+/// interpolation
+#[derive(Clone, Copy)]
+struct ScreenVertex {
+    x: f32,
+    y: f32,
+    depth: f32,
+    inv_w: f32,
+    color: [f32; 4],
+    tex_coord: [f32; 2],
+}
+
+/// Twice the signed area of (a, b, p); positive when p lies clockwise of a→b on screen
+fn edge(a: &ScreenVertex, b: &ScreenVertex, x: f32, y: f32) -> f32 {
+    (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
+
+/// The pixels from the first to past the last that the coordinates touch, limited to the
+/// viewport span starting at `start` and to the target's `limit`
+fn pixel_span(coords: [f32; 3], start: i32, size: u32, limit: u32) -> (u32, u32) {
+    let low = coords.iter().copied().fold(f32::INFINITY, f32::min);
+    let high = coords.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let first = (floor(low) as i64).max(i64::from(start)).max(0);
+    let end = (floor(high) as i64 + 1).min(i64::from(start) + i64::from(size)).min(i64::from(limit));
+    (first as u32, end.max(first) as u32)
+}
+
+/// Pixels centred exactly on an edge belong to the triangle only if the edge is a top or
+/// left one, so triangles sharing an edge never both draw it
+fn is_top_left(a: &ScreenVertex, b: &ScreenVertex) -> bool {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    dy < 0.0 || (dy == 0.0 && dx > 0.0)
+}
+
+/// Rendering state in the manner of an OpenGL ES context. It owns the depth buffer and
+/// textures; the color buffer is whichever target is passed to the draw calls
+pub struct GlContext {
+    width: u32,
+    height: u32,
+    depth: Vec<f32>,
+    viewport: Rect,
+    transform: Mat4,
+    depth_test: bool,
+    depth_func: DepthFunc,
+    depth_write: bool,
+    blending: bool,
+    clear_color: Color,
+    textures: BTreeMap<TextureId, Texture>,
+    next_texture: TextureId,
+    bound_texture: Option<TextureId>,
+}
+
+impl GlContext {
+    pub fn new(width: u32, height: u32) -> Result<Self, GlError> {
+        let len = (width as usize).checked_mul(height as usize).filter(|&len| len > 0).ok_or(GlError::InvalidValue)?;
+        let mut depth = Vec::new();
+        depth.try_reserve_exact(len).map_err(|_| GlError::OutOfMemory)?;
+        depth.resize(len, 1.0);
+        Ok(Self {
+            width,
+            height,
+            depth,
+            viewport: Rect::new(0, 0, width, height),
+            transform: Mat4::IDENTITY,
+            depth_test: false,
+            depth_func: DepthFunc::Less,
+            depth_write: true,
+            blending: false,
+            clear_color: Color::BLACK,
+            textures: BTreeMap::new(),
+            next_texture: 1,
+            bound_texture: None,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The area of the target that clip space maps onto, as `glViewport` but measured from
+    /// the top-left corner like the rest of the graphics subsystem
+    pub fn set_viewport(&mut self, viewport: Rect) {
+        self.viewport = viewport;
+    }
+
+    /// The model-view-projection matrix applied to every vertex
+    pub fn set_transform(&mut self, transform: Mat4) {
+        self.transform = transform;
+    }
+
+    pub fn set_depth_test(&mut self, enabled: bool) {
+        self.depth_test = enabled;
+    }
+
+    pub fn set_depth_func(&mut self, func: DepthFunc) {
+        self.depth_func = func;
+    }
+
+    /// Whether fragments that pass the depth test update the depth buffer, as `glDepthMask`
+    pub fn set_depth_write(&mut self, enabled: bool) {
+        self.depth_write = enabled;
+    }
+
+    /// Blend fragments over the target by their alpha instead of replacing it
+    pub fn set_blending(&mut self, enabled: bool) {
+        self.blending = enabled;
+    }
+
+    pub fn set_clear_color(&mut self, color: Color) {
+        self.clear_color = color;
+    }
+
+    /// Fill `target` with the clear color and reset the depth buffer to the far plane
+    pub fn clear(&mut self, target: &mut GraphicsBuffer) -> Result<(), GlError> {
+        self.check_target(target)?;
+        target.clear(self.clear_color);
+        self.clear_depth();
+        Ok(())
+    }
+
+    pub fn clear_depth(&mut self) {
+        self.depth.fill(1.0);
+    }
+
+    /// Depth stored for a pixel, from 0 at the near plane to 1 at the far plane
+    pub fn depth_at(&self, x: u32, y: u32) -> Option<f32> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.depth.get((y * self.width + x) as usize).copied()
+    }
+
+    /// Create a texture from `width` × `height` texels in row-major order, in the
+    /// `GraphicsBuffer` pixel format
+    pub fn create_texture(&mut self, width: u32, height: u32, texels: &[u32]) -> Result<TextureId, GlError> {
+        let len = (width as usize).checked_mul(height as usize).filter(|&len| len > 0).ok_or(GlError::InvalidValue)?;
+        if texels.len() != len {
+            return Err(GlError::InvalidValue);
+        }
+        let mut copy = Vec::new();
+        copy.try_reserve_exact(len).map_err(|_| GlError::OutOfMemory)?;
+        copy.extend_from_slice(texels);
+        let id = self.next_texture;
+        self.next_texture += 1;
+        self.textures.insert(id, Texture { width, height, texels: copy, wrap: TextureWrap::Repeat });
+        Ok(id)
+    }
+
+    pub fn set_texture_wrap(&mut self, texture: TextureId, wrap: TextureWrap) -> Result<(), GlError> {
+        self.textures.get_mut(&texture).ok_or(GlError::InvalidTexture)?.wrap = wrap;
+        Ok(())
+    }
+
+    pub fn delete_texture(&mut self, texture: TextureId) -> Result<(), GlError> {
+        self.textures.remove(&texture).ok_or(GlError::InvalidTexture)?;
+        if self.bound_texture == Some(texture) {
+            self.bound_texture = None;
+        }
+        Ok(())
+    }
+
+    /// Texture modulating the color of everything drawn after, or `None` for vertex colors only
+    pub fn bind_texture(&mut self, texture: Option<TextureId>) -> Result<(), GlError> {
+        if texture.is_some_and(|texture| !self.textures.contains_key(&texture)) {
+            return Err(GlError::InvalidTexture);
+        }
+        self.bound_texture = texture;
+        Ok(())
+    }
+
+    /// Draw each consecutive three vertices as a triangle, as `glDrawArrays(GL_TRIANGLES)`
+    pub fn draw_triangles(&mut self, target: &mut GraphicsBuffer, vertices: &[Vertex]) -> Result<(), GlError> {
+        self.check_target(target)?;
+        if vertices.len() % 3 != 0 {
+            return Err(GlError::InvalidValue);
+        }
+        for triangle in vertices.chunks_exact(3) {
+            self.draw_triangle(target, [&triangle[0], &triangle[1], &triangle[2]]);
+        }
+        Ok(())
+    }
+
+    /// Draw each consecutive three indices into `vertices` as a triangle, as
+    /// `glDrawElements(GL_TRIANGLES)`
+    pub fn draw_elements(&mut self, target: &mut GraphicsBuffer, vertices: &[Vertex], indices: &[u16]) -> Result<(), GlError> {
+        self.check_target(target)?;
+        if indices.len() % 3 != 0 || indices.iter().any(|&index| usize::from(index) >= vertices.len()) {
+            return Err(GlError::InvalidValue);
+        }
+        for triangle in indices.chunks_exact(3) {
+            let corner = |i: usize| &vertices[usize::from(triangle[i])];
+            self.draw_triangle(target, [corner(0), corner(1), corner(2)]);
+        }
+        Ok(())
+    }
+
+    fn check_target(&self, target: &GraphicsBuffer) -> Result<(), GlError> {
+        if target.width != self.width || target.height != self.height || target.pixels.len() != self.depth.len() {
+            return Err(GlError::InvalidFramebuffer);
+        }
+        Ok(())
+    }
+
+    fn draw_triangle(&mut self, target: &mut GraphicsBuffer, corners: [&Vertex; 3]) {
+        let clipped = corners.map(|vertex| ClipVertex {
+            position: self.transform.transform([vertex.position[0], vertex.position[1], vertex.position[2], 1.0]),
+            color: color_to_rgba(vertex.color),
+            tex_coord: vertex.tex_coord,
+        });
+        let (polygon, len) = clip_near(clipped);
+        let projected = polygon.map(|vertex| self.to_screen(&vertex));
+        for pair in projected[1..len.max(1)].windows(2) {
+            if let (Some(first), Some(second), Some(third)) = (projected[0], pair[0], pair[1]) {
+                self.rasterize(target, [first, second, third]);
+            }
+        }
+    }
+
+    fn to_screen(&self, vertex: &ClipVertex) -> Option<ScreenVertex> {
+        let [x, y, z, w] = vertex.position;
+        if w < MIN_W || !(x.is_finite() && y.is_finite() && z.is_finite()) {
+            return None;
+        }
+        let inv_w = 1.0 / w;
+        Some(ScreenVertex {
+            x: self.viewport.x as f32 + (x * inv_w + 1.0) * 0.5 * self.viewport.width as f32,
+            y: self.viewport.y as f32 + (1.0 - y * inv_w) * 0.5 * self.viewport.height as f32,
+            depth: (z * inv_w + 1.0) * 0.5,
+            inv_w,
+            color: vertex.color.map(|channel| channel * inv_w),
+            tex_coord: vertex.tex_coord.map(|coord| coord * inv_w),
+        })
+    }
+
+    fn rasterize(&mut self, target: &mut GraphicsBuffer, mut v: [ScreenVertex; 3]) {
+        let mut area = edge(&v[0], &v[1], v[2].x, v[2].y);
+        if area < 0.0 {
+            v.swap(1, 2);
+            area = -area;
+        }
+        if area <= 0.0 || !area.is_finite() {
+            return;
+        }
+
+        // Pixels within both the triangle's bounds and the viewport
+        let (x_start, x_end) = pixel_span(v.map(|p| p.x), self.viewport.x, self.viewport.width, self.width);
+        let (y_start, y_end) = pixel_span(v.map(|p| p.y), self.viewport.y, self.viewport.height, self.height);
+
+        let texture = self.bound_texture.and_then(|id| self.textures.get(&id));
+        let edges = [(1, 2), (2, 0), (0, 1)].map(|(a, b)| is_top_left(&v[a], &v[b]));
+        for y in y_start..y_end {
+            let py = y as f32 + 0.5;
+            for x in x_start..x_end {
+                let px = x as f32 + 0.5;
+                let weights = [edge(&v[1], &v[2], px, py), edge(&v[2], &v[0], px, py), edge(&v[0], &v[1], px, py)];
+                if weights.iter().zip(edges).any(|(&weight, top_left)| weight < 0.0 || (weight == 0.0 && !top_left)) {
+                    continue;
+                }
+                let [l0, l1, l2] = weights.map(|weight| weight / area);
+                let depth = l0 * v[0].depth + l1 * v[1].depth + l2 * v[2].depth;
+                if !(0.0..=1.0).contains(&depth) {
+                    continue;
+                }
+                let index = (y * self.width + x) as usize;
+                if self.depth_test {
+                    if !self.depth_func.passes(depth, self.depth[index]) {
+                        continue;
+                    }
+                    if self.depth_write {
+                        self.depth[index] = depth;
+                    }
+                }
+
+                let w = 1.0 / (l0 * v[0].inv_w + l1 * v[1].inv_w + l2 * v[2].inv_w);
+                let interpolate = |a: f32, b: f32, c: f32| (l0 * a + l1 * b + l2 * c) * w;
+                let mut rgba: [f32; 4] = core::array::from_fn(|i| interpolate(v[0].color[i], v[1].color[i], v[2].color[i]));
+                if let Some(texture) = texture {
+                    let [u, t] = core::array::from_fn(|i| interpolate(v[0].tex_coord[i], v[1].tex_coord[i], v[2].tex_coord[i]));
+                    let texel = texture.sample(u, t);
+                    rgba = core::array::from_fn(|i| rgba[i] * texel[i]);
+                }
+                if self.blending {
+                    let destination = unpack(target.pixels[index]);
+                    let alpha = rgba[3];
+                    rgba = core::array::from_fn(|i| match i {
+                        3 => alpha + destination[3] * (1.0 - alpha),
+                        _ => rgba[i] * alpha + destination[i] * (1.0 - alpha),
+                    });
+                }
+                target.pixels[index] = pack(rgba);
+            }
+        }
+    }
+}
@@ -0,0 +1,401 @@
+//! Screen recording
+//! The compositor hands every composited frame to `capture_frame`. While a recording runs,
+//! frames are sampled at the requested rate and encoded into a bounded buffer that a
+//! background writer drains to the recording's file. Capturing never waits on the writer:
+//! a frame that cannot be taken because the recorder is busy, its buffer is full or the
+//! screen changed size is counted as dropped instead.
+//!
+//! Recordings use a simple lossless format, all integers little-endian:
+//! - header: magic `RREC`, version (u16), width, height and frame rate (u32 each)
+//! - per frame: capture time in nanoseconds (u64), payload length (u32), payload
+//!
+//! Each frame payload run-length encodes the frame's pixels on its own, as (count u16,
+//! pixel u32) pairs in row-major order. The header is written with the first captured
+//! frame, so a recording that captured nothing is empty.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use super::GraphicsBuffer;
+use crate::filesystem::{self, FileSystemError};
+
+pub const MAGIC: [u8; 4] = *b"RREC";
+pub const VERSION: u16 = 1;
+pub const MAX_FPS: u32 = 120;
+
+const HEADER_LEN: usize = 18;
+const FRAME_HEADER_LEN: usize = 12;
+const RUN_LEN: usize = 6;
+
+/// Encoded bytes held between writer drains; frames that do not fit are dropped. Memory
+/// recordings are never drained, so this also caps their total size
+pub const MAX_BUFFERED_BYTES: usize = 2 * 1024 * 1024;
+
+/// Time between background drains of the capture buffer to the recording's file
+const FLUSH_INTERVAL_MS: u64 = 100;
+
+/// Set while a recording runs, so the compositor skips capturing without taking a lock
+static RECORDING: AtomicBool = AtomicBool::new(false);
+static WRITER_RUNNING: AtomicBool = AtomicBool::new(false);
+/// Where the recording goes; locked before `CAPTURE` when both are needed
+static SINK: Mutex<Option<Sink>> = Mutex::new(None);
+static CAPTURE: Mutex<Option<Recorder>> = Mutex::new(None);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordingTarget {
+    /// A new file in the VFS
+    File(String),
+    /// Kept in memory and handed back by `stop_recording`, e.g. to place on the clipboard
+    Memory,
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    AlreadyRecording,
+    NotRecording,
+    InvalidFrameRate,
+    OutOfMemory,
+    Filesystem(FileSystemError),
+    /// The background writer thread could not be started
+    WriterUnavailable,
+    /// Not a recording, or one written by an unknown version
+    UnsupportedFormat,
+    /// Ends in the middle of the header or of a frame
+    Truncated,
+    /// A frame does not decode to exactly width × height pixels
+    Corrupt,
+}
+
+impl From<FileSystemError> for RecordingError {
+    fn from(error: FileSystemError) -> Self {
+        RecordingError::Filesystem(error)
+    }
+}
+
+/// What a finished recording captured
+#[derive(Debug)]
+pub struct RecordingSummary {
+    pub frames_captured: u64,
+    pub frames_dropped: u64,
+    /// Frame size, unknown if no frame was captured
+    pub dimensions: Option<(u32, u32)>,
+    pub bytes: u64,
+    /// The encoded recording, for `RecordingTarget::Memory`
+    pub data: Option<Vec<u8>>,
+}
+
+enum Sink {
+    /// `spare` is swapped with the capture buffer on every drain, so neither reallocates
+    File { fd: u64, spare: Vec<u8> },
+    Memory,
+}
+
+fn reserve_buffer() -> Result<Vec<u8>, RecordingError> {
+    let mut buffer = Vec::new();
+    buffer.try_reserve_exact(MAX_BUFFERED_BYTES).map_err(|_| RecordingError::OutOfMemory)?;
+    Ok(buffer)
+}
+
+/// Samples frames at a fixed rate and encodes them into a bounded buffer
+pub struct Recorder {
+    fps: u32,
+    interval_ns: u64,
+    next_due_ns: Option<u64>,
+    /// Fixed by the first captured frame
+    dimensions: Option<(u32, u32)>,
+    buffer: Vec<u8>,
+    frames_captured: u64,
+    frames_dropped: u64,
+    bytes: u64,
+}
+
+impl Recorder {
+    pub fn new(fps: u32) -> Result<Self, RecordingError> {
+        if fps == 0 || fps > MAX_FPS {
+            return Err(RecordingError::InvalidFrameRate);
+        }
+        Ok(Self {
+            fps,
+            interval_ns: 1_000_000_000 / u64::from(fps),
+            next_due_ns: None,
+            dimensions: None,
+            buffer: reserve_buffer()?,
+            frames_captured: 0,
+            frames_dropped: 0,
+            bytes: 0,
+        })
+    }
+
+    pub fn frames_captured(&self) -> u64 {
+        self.frames_captured
+    }
+
+    /// Frames that were due but not recorded
+    pub fn frames_dropped(&self) -> u64 {
+        self.frames_dropped
+    }
+
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        self.dimensions
+    }
+
+    /// Encoded bytes not yet drained
+    pub fn encoded(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Offer a composited frame; it is recorded only if a frame is due at `now_ns`. Frame
+    /// intervals that passed without any frame being offered count as dropped
+    pub fn capture(&mut self, frame: &GraphicsBuffer, now_ns: u64) {
+        let due = self.next_due_ns.unwrap_or(now_ns);
+        if now_ns < due {
+            return;
+        }
+        let missed = (now_ns - due) / self.interval_ns;
+        self.frames_dropped += missed;
+        self.next_due_ns = Some(due + (missed + 1) * self.interval_ns);
+        if self.encode(frame, now_ns) {
+            self.frames_captured += 1;
+        } else {
+            self.frames_dropped += 1;
+        }
+    }
+
+    /// Append `frame` to the buffer, or leave the buffer untouched and return false if the
+    /// frame does not fit or differs in size from the recording
+    fn encode(&mut self, frame: &GraphicsBuffer, timestamp_ns: u64) -> bool {
+        let dimensions = (frame.width, frame.height);
+        if self.dimensions.is_some_and(|recorded| recorded != dimensions) {
+            return false;
+        }
+        let start = self.buffer.len();
+        let header_len = if self.dimensions.is_none() { HEADER_LEN } else { 0 };
+        if start + header_len + FRAME_HEADER_LEN > MAX_BUFFERED_BYTES {
+            return false;
+        }
+        if self.dimensions.is_none() {
+            self.buffer.extend_from_slice(&MAGIC);
+            self.buffer.extend_from_slice(&VERSION.to_le_bytes());
+            self.buffer.extend_from_slice(&frame.width.to_le_bytes());
+            self.buffer.extend_from_slice(&frame.height.to_le_bytes());
+            self.buffer.extend_from_slice(&self.fps.to_le_bytes());
+        }
+        self.buffer.extend_from_slice(&timestamp_ns.to_le_bytes());
+        let length_at = self.buffer.len();
+        self.buffer.extend_from_slice(&0u32.to_le_bytes());
+
+        let mut pixels = frame.pixels.iter().copied().peekable();
+        while let Some(pixel) = pixels.next() {
+            let mut count = 1u16;
+            while count < u16::MAX && pixels.next_if_eq(&pixel).is_some() {
+                count += 1;
+            }
+            if self.buffer.len() + RUN_LEN > MAX_BUFFERED_BYTES {
+                self.buffer.truncate(start);
+                return false;
+            }
+            self.buffer.extend_from_slice(&count.to_le_bytes());
+            self.buffer.extend_from_slice(&pixel.to_le_bytes());
+        }
+
+        let payload_len = (self.buffer.len() - length_at - 4) as u32;
+        self.buffer[length_at..length_at + 4].copy_from_slice(&payload_len.to_le_bytes());
+        self.bytes += (self.buffer.len() - start) as u64;
+        self.dimensions = Some(dimensions);
+        true
+    }
+
+    /// Hand the encoded bytes over in exchange for an empty buffer
+    fn swap_buffer(&mut self, spare: &mut Vec<u8>) {
+        spare.clear();
+        core::mem::swap(&mut self.buffer, spare);
+    }
+
+    fn into_summary(self, keep_data: bool) -> RecordingSummary {
+        RecordingSummary {
+            frames_captured: self.frames_captured,
+            frames_dropped: self.frames_dropped,
+            dimensions: self.dimensions,
+            bytes: self.bytes,
+            data: keep_data.then_some(self.buffer),
+        }
+    }
+}
+
+/// Start recording the screen to `target` at `fps` frames per second
+pub fn start_recording(target: RecordingTarget, fps: u32) -> Result<(), RecordingError> {
+    let mut sink = SINK.lock();
+    if sink.is_some() {
+        return Err(RecordingError::AlreadyRecording);
+    }
+    let recorder = Recorder::new(fps)?;
+    let is_file = matches!(target, RecordingTarget::File(_));
+    *sink = Some(match target {
+        RecordingTarget::File(path) => {
+            let spare = reserve_buffer()?;
+            filesystem::create_file(&path)?;
+            Sink::File { fd: filesystem::open(&path, 0)?, spare }
+        }
+        RecordingTarget::Memory => Sink::Memory,
+    });
+    *CAPTURE.lock() = Some(recorder);
+    RECORDING.store(true, Ordering::Release);
+    drop(sink);
+
+    // A writer that finds no file to drain exits, clearing the flag while it holds the sink
+    if is_file && !WRITER_RUNNING.swap(true, Ordering::AcqRel) {
+        if crate::process::spawn_kernel_thread("screen-recorder", recording_writer_main).is_err() {
+            WRITER_RUNNING.store(false, Ordering::Release);
+            let _ = stop_recording();
+            return Err(RecordingError::WriterUnavailable);
+        }
+    }
+    Ok(())
+}
+
+/// Stop the recording, writing out everything captured so far
+pub fn stop_recording() -> Result<RecordingSummary, RecordingError> {
+    let mut sink = SINK.lock();
+    let target = sink.take().ok_or(RecordingError::NotRecording)?;
+    RECORDING.store(false, Ordering::Release);
+    let recorder = CAPTURE.lock().take().ok_or(RecordingError::NotRecording)?;
+    drop(sink);
+    match target {
+        Sink::File { fd, .. } => {
+            let written = write_all(fd, recorder.encoded());
+            let closed = filesystem::close(fd);
+            written?;
+            closed?;
+            Ok(recorder.into_summary(false))
+        }
+        Sink::Memory => Ok(recorder.into_summary(true)),
+    }
+}
+
+/// Offer a composited frame to the running recording, if any. Never blocks: if the writer
+/// holds the recorder, the frame is skipped and counted as dropped by the next capture
+pub fn capture_frame(frame: &GraphicsBuffer, now_ns: u64) {
+    if !RECORDING.load(Ordering::Acquire) {
+        return;
+    }
+    if let Some(mut capture) = CAPTURE.try_lock() {
+        if let Some(recorder) = capture.as_mut() {
+            recorder.capture(frame, now_ns);
+        }
+    }
+}
+
+fn write_all(fd: u64, mut data: &[u8]) -> Result<(), RecordingError> {
+    while !data.is_empty() {
+        match filesystem::write(fd, data)? {
+            0 => return Err(RecordingError::Filesystem(FileSystemError::IoError)),
+            written => data = &data[written..],
+        }
+    }
+    Ok(())
+}
+
+extern "C" fn recording_writer_main() -> ! {
+    loop {
+        crate::process::sleep_current(FLUSH_INTERVAL_MS);
+        let mut sink = SINK.lock();
+        let Some(Sink::File { fd, spare }) = sink.as_mut() else {
+            WRITER_RUNNING.store(false, Ordering::Release);
+            drop(sink);
+            crate::process::exit_process(0);
+        };
+        if let Some(recorder) = CAPTURE.lock().as_mut() {
+            recorder.swap_buffer(spare);
+        }
+        if let Err(e) = write_all(*fd, spare) {
+            crate::serial::_print(format_args!("[Graphics] Screen recording write failed: {:?}\n", e));
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedFrame {
+    pub timestamp_ns: u64,
+    pub pixels: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedRecording {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub frames: Vec<DecodedFrame>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], RecordingError> {
+        if self.data.len() < len {
+            return Err(RecordingError::Truncated);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], RecordingError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u16(&mut self) -> Result<u16, RecordingError> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, RecordingError> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, RecordingError> {
+        self.array().map(u64::from_le_bytes)
+    }
+}
+
+fn decode_frame(payload: &[u8], pixel_count: usize) -> Result<Vec<u32>, RecordingError> {
+    if payload.len() % RUN_LEN != 0 {
+        return Err(RecordingError::Corrupt);
+    }
+    let mut pixels = Vec::new();
+    pixels.try_reserve_exact(pixel_count).map_err(|_| RecordingError::OutOfMemory)?;
+    for run in payload.chunks_exact(RUN_LEN) {
+        let count = usize::from(u16::from_le_bytes([run[0], run[1]]));
+        let pixel = u32::from_le_bytes([run[2], run[3], run[4], run[5]]);
+        if count == 0 || pixels.len() + count > pixel_count {
+            return Err(RecordingError::Corrupt);
+        }
+        pixels.resize(pixels.len() + count, pixel);
+    }
+    if pixels.len() != pixel_count {
+        return Err(RecordingError::Corrupt);
+    }
+    Ok(pixels)
+}
+
+/// Decode a recording back into its frames' pixels
+pub fn decode(data: &[u8]) -> Result<DecodedRecording, RecordingError> {
+    let mut reader = Reader { data };
+    if reader.array::<4>()? != MAGIC || reader.u16()? != VERSION {
+        return Err(RecordingError::UnsupportedFormat);
+    }
+    let (width, height, fps) = (reader.u32()?, reader.u32()?, reader.u32()?);
+    let pixel_count = (width as usize).checked_mul(height as usize).ok_or(RecordingError::Corrupt)?;
+    let mut frames = Vec::new();
+    while !reader.data.is_empty() {
+        let timestamp_ns = reader.u64()?;
+        let payload_len = reader.u32()? as usize;
+        let pixels = decode_frame(reader.take(payload_len)?, pixel_count)?;
+        frames.push(DecodedFrame { timestamp_ns, pixels });
+    }
+    Ok(DecodedRecording { width, height, fps, frames })
+}
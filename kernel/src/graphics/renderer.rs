@@ -0,0 +1,141 @@
+//! Compositor rendering backends
+//! The compositor draws each frame through a `Renderer`: the software renderer on the CPU,
+//! or a hardware renderer that a GPU driver offers by registering a probe. At selection the
+//! probes are tried in registration order when hardware acceleration is enabled, and the
+//! first whose device is present and initializes wins; otherwise, or if the hardware fails
+//! later, the compositor falls back to software. Every backend must produce exactly the
+//! pixels the software renderer does.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::{Color, GraphicsBuffer, Point, Rect, WindowManager};
+
+/// Looks for a device able to render, returning its renderer if one is present
+pub type HardwareProbe = fn() -> Option<Box<dyn Renderer>>;
+
+static HARDWARE_PROBES: Mutex<Vec<HardwareProbe>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RendererError {
+    /// The device could not be brought up for rendering
+    InitFailed,
+    /// The device stopped responding mid-frame
+    DeviceLost,
+}
+
+pub trait Renderer: Send {
+    fn name(&self) -> &str;
+
+    fn is_hardware(&self) -> bool {
+        false
+    }
+
+    /// Prepare the device; called once, before the renderer is first used
+    fn init(&mut self) -> Result<(), RendererError> {
+        Ok(())
+    }
+
+    /// Fill the part of `rect` inside `target` with `color`
+    fn fill(&mut self, target: &mut GraphicsBuffer, rect: Rect, color: Color) -> Result<(), RendererError>;
+
+    /// Copy `source` into `target` with its top-left corner at `position`, clipped to `target`
+    fn blit(&mut self, target: &mut GraphicsBuffer, source: &GraphicsBuffer, position: Point) -> Result<(), RendererError>;
+}
+
+/// The rows and columns of a `width` × `height` area at `position` that lie inside `target`,
+/// as target ranges
+pub fn clip(target: &GraphicsBuffer, position: Point, width: u32, height: u32) -> Option<(core::ops::Range<u32>, core::ops::Range<u32>)> {
+    let span = |start: i32, size: u32, limit: u32| {
+        let first = i64::from(start).clamp(0, i64::from(limit));
+        let end = (i64::from(start) + i64::from(size)).clamp(0, i64::from(limit));
+        (first < end).then_some(first as u32..end as u32)
+    };
+    Some((span(position.x, width, target.width)?, span(position.y, height, target.height)?))
+}
+
+/// Renders on the CPU, row by row
+pub struct SoftwareRenderer;
+
+impl Renderer for SoftwareRenderer {
+    fn name(&self) -> &str {
+        "software"
+    }
+
+    fn fill(&mut self, target: &mut GraphicsBuffer, rect: Rect, color: Color) -> Result<(), RendererError> {
+        let Some((columns, rows)) = clip(target, Point::new(rect.x, rect.y), rect.width, rect.height) else {
+            return Ok(());
+        };
+        let pixel = ((color.a as u32) << 24) | ((color.r as u32) << 16) | ((color.g as u32) << 8) | (color.b as u32);
+        for y in rows {
+            let row = (y * target.width) as usize;
+            if let Some(span) = target.pixels.get_mut(row + columns.start as usize..row + columns.end as usize) {
+                span.fill(pixel);
+            }
+        }
+        Ok(())
+    }
+
+    fn blit(&mut self, target: &mut GraphicsBuffer, source: &GraphicsBuffer, position: Point) -> Result<(), RendererError> {
+        let Some((columns, rows)) = clip(target, position, source.width, source.height) else {
+            return Ok(());
+        };
+        // Offsets into the source of the first visible column and row
+        let (src_x, src_y) = ((columns.start as i32 - position.x) as u32, (rows.start as i32 - position.y) as u32);
+        let width = (columns.end - columns.start) as usize;
+        for (i, y) in rows.enumerate() {
+            let src = ((src_y + i as u32) * source.width + src_x) as usize;
+            let dst = (y * target.width + columns.start) as usize;
+            if let (Some(from), Some(to)) = (source.pixels.get(src..src + width), target.pixels.get_mut(dst..dst + width)) {
+                to.copy_from_slice(from);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Draw the visible windows of `wm` over its background, bottom to top
+pub fn composite_scene(renderer: &mut dyn Renderer, target: &mut GraphicsBuffer, wm: &WindowManager) -> Result<(), RendererError> {
+    renderer.fill(target, Rect::new(0, 0, target.width, target.height), wm.theme.background_color)?;
+    for window_id in &wm.window_order {
+        let Some(window) = wm.windows.get(window_id).filter(|window| window.visible) else {
+            continue;
+        };
+        if let Some(buffer) = &window.buffer {
+            renderer.blit(target, buffer, Point::new(window.rect.x, window.rect.y))?;
+        }
+    }
+    Ok(())
+}
+
+/// Offer a hardware renderer to the compositor; `probe` runs at every renderer selection
+pub fn register_hardware_probe(probe: HardwareProbe) {
+    HARDWARE_PROBES.lock().push(probe);
+}
+
+/// The renderer to composite with: the first hardware renderer from `probes` whose device is
+/// present and initializes, if `hardware_acceleration` is set, and the software renderer
+/// otherwise
+pub fn select_renderer(hardware_acceleration: bool, probes: &[HardwareProbe]) -> Box<dyn Renderer> {
+    if hardware_acceleration {
+        for probe in probes {
+            let Some(mut renderer) = probe() else { continue };
+            match renderer.init() {
+                Ok(()) => return renderer,
+                Err(e) => crate::serial::_print(format_args!(
+                    "[Graphics] {} renderer unavailable ({:?}), trying the next\n",
+                    renderer.name(),
+                    e
+                )),
+            }
+        }
+    }
+    Box::new(SoftwareRenderer)
+}
+
+/// `select_renderer` over the registered hardware probes
+pub fn select_registered_renderer(hardware_acceleration: bool) -> Box<dyn Renderer> {
+    let probes = HARDWARE_PROBES.lock().clone();
+    select_renderer(hardware_acceleration, &probes)
+}
@@ -0,0 +1,389 @@
+//! Text shaping and bidirectional layout
+//! Turns a string into a run of positioned glyphs for one line of text:
+//!
+//! 1. Every character gets an embedding level from the Unicode Bidirectional Algorithm
+//!    (UAX #9) without explicit embeddings or isolates, which covers mixed Latin, Hebrew and
+//!    Arabic text with numbers and punctuation.
+//! 2. Combining marks join the character before them into a cluster, which is composed into
+//!    a precomposed character when the font has one (e + ◌́ → é).
+//! 3. Ligatures (fi, fl, ff, ffi, ffl and Arabic lam-alef) replace their letters when the font
+//!    has the ligature glyph, and paired punctuation is mirrored in right-to-left text.
+//! 4. Clusters are reordered into visual order and laid out left to right, kerned, with any
+//!    remaining marks centered over their base.
+//!
+//! Glyphs are identified by character, so a run is drawn with `Font::glyph` like plain text.
+
+use alloc::vec::Vec;
+
+use super::font::Font;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Ltr,
+    Rtl,
+}
+
+/// Bidirectional character types of UAX #9, less the explicit formatting ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BidiClass {
+    /// L
+    LeftToRight,
+    /// R, such as Hebrew letters
+    RightToLeft,
+    /// AL
+    ArabicLetter,
+    /// EN
+    EuropeanNumber,
+    /// ES, plus and minus
+    EuropeanSeparator,
+    /// ET, such as currency and percent signs
+    EuropeanTerminator,
+    /// AN
+    ArabicNumber,
+    /// CS, such as commas and colons
+    CommonSeparator,
+    /// NSM, combining marks
+    NonspacingMark,
+    /// B
+    ParagraphSeparator,
+    /// S, tabs
+    SegmentSeparator,
+    /// WS
+    Whitespace,
+    /// ON
+    OtherNeutral,
+}
+
+pub fn bidi_class(ch: char) -> BidiClass {
+    use BidiClass::*;
+    match ch as u32 {
+        0x0A | 0x0D | 0x1C..=0x1E | 0x85 | 0x2029 => ParagraphSeparator,
+        0x09 | 0x0B | 0x1F => SegmentSeparator,
+        0x0C | 0x20 | 0x1680 | 0x2000..=0x200A | 0x2028 | 0x205F | 0x3000 => Whitespace,
+        0x200E => LeftToRight,
+        0x200F => RightToLeft,
+        0x30..=0x39 | 0xB2 | 0xB3 | 0xB9 | 0x06F0..=0x06F9 | 0x2070 | 0x2074..=0x2079 | 0xFF10..=0xFF19 => EuropeanNumber,
+        0x2B | 0x2D | 0x207A | 0x207B | 0x2212 => EuropeanSeparator,
+        0x23..=0x25 | 0xA2..=0xA5 | 0xB0 | 0xB1 | 0x066A | 0x2030..=0x2034 | 0x20A0..=0x20CF => EuropeanTerminator,
+        0x2C | 0x2E | 0x2F | 0x3A | 0xA0 | 0x060C | 0x202F | 0x2044 => CommonSeparator,
+        0x0600..=0x0605 | 0x0660..=0x0669 | 0x066B | 0x066C | 0x06DD => ArabicNumber,
+        0x0300..=0x036F
+        | 0x0483..=0x0489
+        | 0x0591..=0x05BD
+        | 0x05BF
+        | 0x05C1
+        | 0x05C2
+        | 0x05C4
+        | 0x05C5
+        | 0x05C7
+        | 0x0610..=0x061A
+        | 0x064B..=0x065F
+        | 0x0670
+        | 0x06D6..=0x06DC
+        | 0x06DF..=0x06E4
+        | 0x06E7
+        | 0x06E8
+        | 0x06EA..=0x06ED
+        | 0x1AB0..=0x1AFF
+        | 0x1DC0..=0x1DFF
+        | 0x20D0..=0x20FF
+        | 0xFE20..=0xFE2F => NonspacingMark,
+        0x0590..=0x05FF | 0x07C0..=0x085F | 0xFB1D..=0xFB4F => RightToLeft,
+        0x0600..=0x07BF | 0x0860..=0x08FF | 0xFB50..=0xFDFF | 0xFE70..=0xFEFF => ArabicLetter,
+        _ if ch.is_alphanumeric() => LeftToRight,
+        _ => OtherNeutral,
+    }
+}
+
+/// The paragraph level and the embedding level of each character of a line with bidi
+/// classes `classes`; `direction` forces the paragraph direction instead of taking it from
+/// the first strong character
+pub fn resolve_levels(classes: &[BidiClass], direction: Option<Direction>) -> (u8, Vec<u8>) {
+    use BidiClass::*;
+    let paragraph = match direction {
+        Some(Direction::Ltr) => 0,
+        Some(Direction::Rtl) => 1,
+        None => classes
+            .iter()
+            .find_map(|class| match class {
+                LeftToRight => Some(0),
+                RightToLeft | ArabicLetter => Some(1),
+                _ => None,
+            })
+            .unwrap_or(0),
+    };
+    // Without explicit embeddings the whole line is one run at the paragraph level
+    let edge = if paragraph % 2 == 0 { LeftToRight } else { RightToLeft };
+    let mut types = classes.to_vec();
+    let n = types.len();
+
+    // W1: marks take the type of the character before them
+    let mut previous = edge;
+    for class in types.iter_mut() {
+        if *class == NonspacingMark {
+            *class = previous;
+        }
+        previous = *class;
+    }
+    // W2: European numbers after Arabic letters are Arabic numbers; W3: AL becomes R
+    let mut strong = edge;
+    for class in types.iter_mut() {
+        match *class {
+            LeftToRight | RightToLeft | ArabicLetter => strong = *class,
+            EuropeanNumber if strong == ArabicLetter => *class = ArabicNumber,
+            _ => {}
+        }
+    }
+    for class in types.iter_mut().filter(|class| **class == ArabicLetter) {
+        *class = RightToLeft;
+    }
+    // W4: a single separator between two numbers of the same kind joins them
+    for i in 1..n.saturating_sub(1) {
+        let (before, after) = (types[i - 1], types[i + 1]);
+        if before == after && ((before == EuropeanNumber && matches!(types[i], EuropeanSeparator | CommonSeparator)) || (before == ArabicNumber && types[i] == CommonSeparator)) {
+            types[i] = before;
+        }
+    }
+    // W5: terminators next to European numbers are part of them
+    let mut i = 0;
+    while i < n {
+        if types[i] != EuropeanTerminator {
+            i += 1;
+            continue;
+        }
+        let end = (i..n).find(|&j| types[j] != EuropeanTerminator).unwrap_or(n);
+        if (i > 0 && types[i - 1] == EuropeanNumber) || (end < n && types[end] == EuropeanNumber) {
+            types[i..end].fill(EuropeanNumber);
+        }
+        i = end;
+    }
+    // W6: remaining separators and terminators are neutral
+    for class in types.iter_mut().filter(|class| matches!(class, EuropeanSeparator | EuropeanTerminator | CommonSeparator)) {
+        *class = OtherNeutral;
+    }
+    // W7: European numbers in left-to-right context are left-to-right
+    let mut strong = edge;
+    for class in types.iter_mut() {
+        match *class {
+            LeftToRight | RightToLeft => strong = *class,
+            EuropeanNumber if strong == LeftToRight => *class = LeftToRight,
+            _ => {}
+        }
+    }
+    // N1, N2: neutrals between characters of one direction take it, others the paragraph's
+    let direction_of = |class: BidiClass| match class {
+        LeftToRight => LeftToRight,
+        _ => RightToLeft,
+    };
+    let neutral = |class: BidiClass| matches!(class, OtherNeutral | Whitespace | SegmentSeparator | ParagraphSeparator);
+    let mut i = 0;
+    while i < n {
+        if !neutral(types[i]) {
+            i += 1;
+            continue;
+        }
+        let end = (i..n).find(|&j| !neutral(types[j])).unwrap_or(n);
+        let before = if i == 0 { edge } else { direction_of(types[i - 1]) };
+        let after = if end == n { edge } else { direction_of(types[end]) };
+        types[i..end].fill(if before == after { before } else { edge });
+        i = end;
+    }
+    // I1, I2: levels from the resolved types
+    let mut levels: Vec<u8> = types
+        .iter()
+        .map(|class| match (paragraph % 2, class) {
+            (0, RightToLeft) => paragraph + 1,
+            (0, ArabicNumber | EuropeanNumber) => paragraph + 2,
+            (1, LeftToRight | EuropeanNumber | ArabicNumber) => paragraph + 1,
+            _ => paragraph,
+        })
+        .collect();
+    // L1: separators, and whitespace before them or at the end of the line, go back to the
+    // paragraph level
+    let mut trailing = true;
+    for i in (0..n).rev() {
+        match classes[i] {
+            SegmentSeparator | ParagraphSeparator => {
+                levels[i] = paragraph;
+                trailing = true;
+            }
+            Whitespace if trailing => levels[i] = paragraph,
+            _ => trailing = false,
+        }
+    }
+    (paragraph, levels)
+}
+
+/// The logical indices of characters with embedding levels `levels` in visual order (L2)
+pub fn visual_order(levels: &[u8]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..levels.len()).collect();
+    let highest = levels.iter().copied().max().unwrap_or(0);
+    let lowest_odd = levels.iter().copied().min().unwrap_or(0) | 1;
+    for level in (lowest_odd..=highest).rev() {
+        let mut i = 0;
+        while i < order.len() {
+            if levels[order[i]] < level {
+                i += 1;
+                continue;
+            }
+            let end = (i..order.len()).find(|&j| levels[order[j]] < level).unwrap_or(order.len());
+            order[i..end].reverse();
+            i = end;
+        }
+    }
+    order
+}
+
+/// A glyph of a run, placed relative to the start of the run on the baseline
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionedGlyph {
+    pub ch: char,
+    pub x: f32,
+    /// Distance above the baseline
+    pub y: f32,
+    /// Byte offset in the text of the first character the glyph stands for
+    pub cluster: usize,
+}
+
+/// One line of shaped text, its glyphs in visual order from left to right
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphRun {
+    pub glyphs: Vec<PositionedGlyph>,
+    pub width: f32,
+    pub direction: Direction,
+}
+
+/// A base character and the marks attached to it
+struct Cluster {
+    base: char,
+    marks: Vec<char>,
+    start: usize,
+    level: u8,
+}
+
+/// Precomposed Latin letters by base and combining mark
+const COMPOSITIONS: &[(char, char, char)] = &[
+    ('A', '\u{300}', 'À'), ('E', '\u{300}', 'È'), ('I', '\u{300}', 'Ì'), ('O', '\u{300}', 'Ò'), ('U', '\u{300}', 'Ù'),
+    ('a', '\u{300}', 'à'), ('e', '\u{300}', 'è'), ('i', '\u{300}', 'ì'), ('o', '\u{300}', 'ò'), ('u', '\u{300}', 'ù'),
+    ('A', '\u{301}', 'Á'), ('E', '\u{301}', 'É'), ('I', '\u{301}', 'Í'), ('O', '\u{301}', 'Ó'), ('U', '\u{301}', 'Ú'),
+    ('Y', '\u{301}', 'Ý'), ('a', '\u{301}', 'á'), ('e', '\u{301}', 'é'), ('i', '\u{301}', 'í'), ('o', '\u{301}', 'ó'),
+    ('u', '\u{301}', 'ú'), ('y', '\u{301}', 'ý'), ('A', '\u{302}', 'Â'), ('E', '\u{302}', 'Ê'), ('I', '\u{302}', 'Î'),
+    ('O', '\u{302}', 'Ô'), ('U', '\u{302}', 'Û'), ('a', '\u{302}', 'â'), ('e', '\u{302}', 'ê'), ('i', '\u{302}', 'î'),
+    ('o', '\u{302}', 'ô'), ('u', '\u{302}', 'û'), ('A', '\u{303}', 'Ã'), ('N', '\u{303}', 'Ñ'), ('O', '\u{303}', 'Õ'),
+    ('a', '\u{303}', 'ã'), ('n', '\u{303}', 'ñ'), ('o', '\u{303}', 'õ'), ('A', '\u{308}', 'Ä'), ('E', '\u{308}', 'Ë'),
+    ('I', '\u{308}', 'Ï'), ('O', '\u{308}', 'Ö'), ('U', '\u{308}', 'Ü'), ('a', '\u{308}', 'ä'), ('e', '\u{308}', 'ë'),
+    ('i', '\u{308}', 'ï'), ('o', '\u{308}', 'ö'), ('u', '\u{308}', 'ü'), ('y', '\u{308}', 'ÿ'), ('A', '\u{30A}', 'Å'),
+    ('a', '\u{30A}', 'å'), ('C', '\u{327}', 'Ç'), ('c', '\u{327}', 'ç'),
+];
+
+/// Ligatures and the letters they replace, longest first
+const LIGATURES: &[(&[char], char)] = &[
+    (&['f', 'f', 'i'], '\u{FB03}'),
+    (&['f', 'f', 'l'], '\u{FB04}'),
+    (&['f', 'f'], '\u{FB00}'),
+    (&['f', 'i'], '\u{FB01}'),
+    (&['f', 'l'], '\u{FB02}'),
+    (&['\u{644}', '\u{622}'], '\u{FEF5}'),
+    (&['\u{644}', '\u{623}'], '\u{FEF7}'),
+    (&['\u{644}', '\u{625}'], '\u{FEF9}'),
+    (&['\u{644}', '\u{627}'], '\u{FEFB}'),
+];
+
+/// Punctuation drawn mirrored in right-to-left text
+const MIRRORED: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}'), ('<', '>'), ('«', '»'), ('‹', '›')];
+
+fn mirror(ch: char) -> char {
+    MIRRORED
+        .iter()
+        .find_map(|&(open, close)| match ch {
+            _ if ch == open => Some(close),
+            _ if ch == close => Some(open),
+            _ => None,
+        })
+        .unwrap_or(ch)
+}
+
+fn has_glyph(font: &Font, ch: char) -> bool {
+    font.glyph_index(ch) != 0
+}
+
+/// Lay out `text` as one line in `font` at `size` pixels per em; `direction` forces the
+/// paragraph direction, which otherwise follows the first strong character
+pub fn shape(font: &Font, text: &str, size: u32, direction: Option<Direction>) -> GlyphRun {
+    let characters: Vec<(usize, char)> = text.char_indices().collect();
+    let classes: Vec<BidiClass> = characters.iter().map(|&(_, ch)| bidi_class(ch)).collect();
+    let (paragraph, levels) = resolve_levels(&classes, direction);
+
+    let mut clusters: Vec<Cluster> = Vec::new();
+    for (i, &(start, ch)) in characters.iter().enumerate() {
+        if let Some(cluster) = clusters.last_mut().filter(|_| classes[i] == BidiClass::NonspacingMark) {
+            cluster.marks.push(ch);
+            continue;
+        }
+        clusters.push(Cluster { base: ch, marks: Vec::new(), start, level: levels[i] });
+    }
+
+    for cluster in clusters.iter_mut() {
+        while let Some(&mark) = cluster.marks.first() {
+            let composed = COMPOSITIONS.iter().find(|&&(base, with, _)| base == cluster.base && with == mark);
+            let Some(&(_, _, composed)) = composed.filter(|&&(_, _, composed)| has_glyph(font, composed)) else { break };
+            cluster.base = composed;
+            cluster.marks.remove(0);
+        }
+    }
+
+    let mut i = 0;
+    while i < clusters.len() {
+        let ligature = LIGATURES.iter().find(|(letters, ligature)| {
+            clusters.get(i..i + letters.len()).is_some_and(|parts| {
+                parts.iter().zip(letters.iter()).all(|(part, &letter)| {
+                    part.base == letter && part.marks.is_empty() && part.level == clusters[i].level
+                })
+            }) && has_glyph(font, *ligature)
+        });
+        if let Some(&(letters, ligature)) = ligature {
+            clusters[i].base = ligature;
+            clusters.drain(i + 1..i + letters.len());
+        }
+        i += 1;
+    }
+
+    for cluster in clusters.iter_mut().filter(|cluster| cluster.level % 2 == 1) {
+        cluster.base = mirror(cluster.base);
+    }
+
+    let cluster_levels: Vec<u8> = clusters.iter().map(|cluster| cluster.level).collect();
+    let pixels = |units: i32| font.to_pixels(units as f32, size);
+    // Keeps stacked marks apart from their base and each other
+    let gap = (font.units_per_em() / 20) as i32;
+    let mut glyphs = Vec::with_capacity(characters.len());
+    let mut pen = 0.0;
+    let mut previous: Option<char> = None;
+    for index in visual_order(&cluster_levels) {
+        let cluster = &clusters[index];
+        if let Some(previous) = previous {
+            pen += font.kerning(previous, cluster.base, size);
+        }
+        previous = Some(cluster.base);
+        glyphs.push(PositionedGlyph { ch: cluster.base, x: pen, y: 0.0, cluster: cluster.start });
+        let advance = font.advance(cluster.base, size);
+        let base = font.glyph_bounds(cluster.base);
+        let mut top = base.map_or(0, |bounds| bounds[3] as i32);
+        for &mark in &cluster.marks {
+            let (x, y) = match (base, font.glyph_bounds(mark)) {
+                (Some(base), Some(bounds)) => {
+                    // Center the mark over the base, and lift marks above the base clear of it
+                    let center = |bounds: [i16; 4]| (bounds[0] as i32 + bounds[2] as i32) / 2;
+                    let lift = if bounds[1] >= 0 { (top + gap - bounds[1] as i32).max(0) } else { 0 };
+                    top = top.max(bounds[3] as i32 + lift);
+                    (pen + pixels(center(base) - center(bounds)), pixels(lift))
+                }
+                _ => (pen + (advance - font.advance(mark, size)) / 2.0, 0.0),
+            };
+            glyphs.push(PositionedGlyph { ch: mark, x, y, cluster: cluster.start });
+        }
+        pen += advance;
+    }
+
+    GlyphRun { glyphs, width: pen, direction: if paragraph % 2 == 0 { Direction::Ltr } else { Direction::Rtl } }
+}
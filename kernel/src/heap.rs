@@ -1,15 +1,130 @@
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
 use linked_list_allocator::LockedHeap;
-use x86_64::structures::paging::{Mapper, Page, PageTableFlags, Size4KiB};
+use spin::Mutex;
+use x86_64::structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB};
 use x86_64::{VirtAddr};
 use alloc::vec::Vec;
 
+pub mod slab;
+
 // Place heap well above kernel code/data mapping to avoid overlaps
 pub const HEAP_START: usize = 0x_4444_0000_0000;
 pub const HEAP_SIZE: usize = 16 * 1024 * 1024; // 16 MiB
+/// Size the heap may grow to as allocations outgrow `HEAP_SIZE`
+pub const HEAP_MAX_SIZE: usize = 64 * 1024 * 1024; // 64 MiB
+/// Least the heap grows by at once
+const HEAP_GROW_STEP: usize = 1024 * 1024;
 
 #[global_allocator]
-static ALLOCATOR: LockedHeap = LockedHeap::empty();
+static ALLOCATOR: GrowableHeap = GrowableHeap {
+    heap: LockedHeap::empty(),
+    growing: Mutex::new(()),
+};
+
+/// The kernel heap. An allocation that does not fit maps more pages past the top of the heap
+/// and is tried again; when no frame is left for them, the OOM killer is asked for some
+struct GrowableHeap {
+    heap: LockedHeap,
+    growing: Mutex<()>,
+}
+
+unsafe impl GlobalAlloc for GrowableHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        loop {
+            if let Ok(allocation) = self.heap.lock().allocate_first_fit(layout) {
+                return allocation.as_ptr();
+            }
+            if !self.grow(layout) {
+                return ptr::null_mut();
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: GlobalAlloc callers only free pointers this allocator returned, never null
+        self.heap.lock().deallocate(NonNull::new_unchecked(ptr), layout);
+    }
+}
+
+impl GrowableHeap {
+    /// Map pages past the top of the heap, enough for `layout`, and add them to it. Returns
+    /// whether the heap grew. Allocations made while growing, by the frame allocator or the
+    /// OOM killer, must fit the heap as it is, so they do not grow it again
+    fn grow(&self, layout: Layout) -> bool {
+        let Some(_growing) = self.growing.try_lock() else {
+            return false;
+        };
+        let (top, size) = {
+            let heap = self.heap.lock();
+            (heap.top() as u64, heap.size())
+        };
+        let wanted = (layout.size() + layout.align()).max(HEAP_GROW_STEP).next_multiple_of(4096);
+        if size == 0 || size + wanted > HEAP_MAX_SIZE {
+            return false;
+        }
+
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let start_page = Page::<Size4KiB>::containing_address(VirtAddr::new(top));
+        let mut grown = 0;
+        crate::memory::with_mapper(|mapper| {
+            for page in Page::range(start_page, start_page + (wanted / 4096) as u64) {
+                let Some(frame) = GrowFrames.allocate_frame() else {
+                    break;
+                };
+                // SAFETY: The page lies past the top of the heap, where nothing is mapped,
+                // and the frame was just allocated
+                match unsafe { mapper.map_to(page, frame, flags, &mut GrowFrames) } {
+                    Ok(flush) => flush.flush(),
+                    Err(_) => {
+                        crate::memory::deallocate_frame(frame);
+                        break;
+                    }
+                }
+                grown += 4096;
+            }
+        });
+        if grown == 0 {
+            return false;
+        }
+        // SAFETY: The `grown` bytes from the old top were just mapped writable, and only the
+        // heap uses them
+        unsafe { self.heap.lock().extend(grown) };
+        true
+    }
+}
+
+/// Frames for growing the heap. When none is free the OOM killer frees some; a frame
+/// allocator that is busy, because its own bookkeeping is what needs the heap, gives none
+struct GrowFrames;
+
+unsafe impl FrameAllocator<Size4KiB> for GrowFrames {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        loop {
+            let mut frame_allocator = crate::memory::FRAME_ALLOC.try_lock()?;
+            if let Some(frame) = frame_allocator.as_mut()?.allocate_frame() {
+                return Some(frame);
+            }
+            drop(frame_allocator);
+            if !crate::oom::reclaim() {
+                return None;
+            }
+        }
+    }
+}
+
+/// Bytes the kernel heap spans, counting what it has grown by
+pub fn heap_size() -> usize {
+    ALLOCATOR.heap.lock().size()
+}
+
+/// Bytes of the kernel heap allocated
+pub fn heap_used() -> usize {
+    ALLOCATOR.heap.lock().used()
+}
 
+/// Map the initial `HEAP_SIZE` bytes of the kernel heap. Beyond them the heap grows on demand
+/// up to `HEAP_MAX_SIZE`, with frames from the global frame allocator
 pub fn init_heap<M, F>(
     mapper: &mut M,
     frame_allocator: &mut F,
@@ -71,7 +186,7 @@ where
     // - The memory region [HEAP_START, HEAP_START + HEAP_SIZE) must be exclusively owned by the allocator
     // - This must only be called once during system initialization
     // - All pages in the heap range have been successfully mapped above
-    unsafe { ALLOCATOR.lock().init(HEAP_START as *mut u8, HEAP_SIZE) }
+    unsafe { ALLOCATOR.heap.lock().init(HEAP_START as *mut u8, HEAP_SIZE) }
     Ok(())
 }
 
@@ -0,0 +1,256 @@
+//! Slab caches for fixed-size kernel objects
+//! A `SlabCache<T>` carves slabs out of the kernel heap and hands out their slots, sized to the
+//! power of two that holds a `T`. Freed slots go on the cache's freelist and are handed out
+//! again before another slab is taken, so objects that come and go, such as processes and
+//! memory areas, reuse the same memory instead of scattering small holes across the heap
+
+use alloc::alloc::{alloc, handle_alloc_error, Layout};
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+/// Smallest slot, enough for the link a free slot holds
+const MIN_SLOT_SIZE: usize = size_of::<FreeSlot>();
+/// Smallest slab taken from the heap
+const MIN_SLAB_SIZE: usize = 4096;
+/// Fewest objects a slab holds, for objects too large for `MIN_SLAB_SIZE` to fit many
+const MIN_SLAB_OBJECTS: usize = 8;
+
+/// Every cache that has taken a slab, for `slab_stats`
+static CACHES: Mutex<Vec<&'static dyn CacheStats>> = Mutex::new(Vec::new());
+
+/// A free slot, linking to the next one
+struct FreeSlot {
+    next: Option<NonNull<FreeSlot>>,
+}
+
+/// Usage of one cache, as `slab_stats` reports it
+#[derive(Debug, Clone)]
+pub struct SlabStats {
+    pub name: &'static str,
+    /// Size of the objects, before rounding up to the slot size
+    pub object_size: usize,
+    pub slot_size: usize,
+    pub objects_in_use: usize,
+    pub total_slabs: usize,
+    /// Objects the slabs hold in all
+    pub capacity: usize,
+    /// Share of the slab memory not holding a live object, from 0 to 1: free slots and the
+    /// rounding of each object up to its slot
+    pub fragmentation: f32,
+}
+
+trait CacheStats: Sync {
+    fn stats(&self) -> SlabStats;
+}
+
+struct SlabState {
+    free: Option<NonNull<FreeSlot>>,
+    in_use: usize,
+    slabs: usize,
+}
+
+// SAFETY: The freelist points into slabs the cache owns, reached only under its lock
+unsafe impl Send for SlabState {}
+
+/// A cache of slots for objects of type `T`. Slabs are kept once taken, for the objects
+/// allocated next
+pub struct SlabCache<T> {
+    name: &'static str,
+    state: Mutex<SlabState>,
+    registered: AtomicBool,
+    _objects: PhantomData<fn() -> T>,
+}
+
+impl<T: 'static> SlabCache<T> {
+    /// Size of each slot: the next power of two holding a `T`
+    pub const SLOT_SIZE: usize = if size_of::<T>() > MIN_SLOT_SIZE {
+        size_of::<T>().next_power_of_two()
+    } else {
+        MIN_SLOT_SIZE
+    };
+    const SLAB_SIZE: usize = if Self::SLOT_SIZE * MIN_SLAB_OBJECTS > MIN_SLAB_SIZE {
+        Self::SLOT_SIZE * MIN_SLAB_OBJECTS
+    } else {
+        MIN_SLAB_SIZE
+    };
+    /// Objects each slab holds
+    pub const SLAB_OBJECTS: usize = Self::SLAB_SIZE / Self::SLOT_SIZE;
+
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            state: Mutex::new(SlabState { free: None, in_use: 0, slabs: 0 }),
+            registered: AtomicBool::new(false),
+            _objects: PhantomData,
+        }
+    }
+
+    fn slab_layout() -> Option<Layout> {
+        let align = align_of::<T>().max(align_of::<FreeSlot>());
+        // A power of two holding a `T` is a multiple of its alignment, so every slot is aligned
+        Layout::from_size_align(Self::SLAB_SIZE, align).ok()
+    }
+
+    /// A slot for one `T`, uninitialised. `None` when the heap has no room for another slab
+    pub fn alloc(&'static self) -> Option<NonNull<T>> {
+        loop {
+            {
+                let mut state = self.state.lock();
+                if let Some(slot) = state.free {
+                    // SAFETY: Slots on the freelist hold a `FreeSlot` written by `free` or `grow`
+                    state.free = unsafe { slot.as_ref().next };
+                    state.in_use += 1;
+                    return Some(slot.cast());
+                }
+            }
+            // The lock is not held while the heap is asked for a slab: growing the heap may
+            // run the OOM killer, which frees objects back to this cache
+            self.grow()?;
+        }
+    }
+
+    /// Give back a slot from `alloc`
+    ///
+    /// # Safety
+    /// `ptr` must come from `alloc` on this cache, and the object in it must have been dropped
+    /// or never written
+    pub unsafe fn free(&self, ptr: NonNull<T>) {
+        let slot = ptr.cast::<FreeSlot>();
+        let mut state = self.state.lock();
+        slot.as_ptr().write(FreeSlot { next: state.free });
+        state.free = Some(slot);
+        state.in_use -= 1;
+    }
+
+    /// Take a slab from the heap and put its slots on the freelist
+    fn grow(&'static self) -> Option<()> {
+        if !self.registered.swap(true, Ordering::SeqCst) {
+            CACHES.lock().push(self);
+        }
+        let layout = Self::slab_layout()?;
+        // SAFETY: The layout has a non-zero size
+        let slab = NonNull::new(unsafe { alloc(layout) })?;
+
+        let mut state = self.state.lock();
+        for index in (0..Self::SLAB_OBJECTS).rev() {
+            // SAFETY: Slot `index` lies within the slab just allocated, aligned for a `FreeSlot`
+            let slot = unsafe {
+                let slot = slab.as_ptr().add(index * Self::SLOT_SIZE).cast::<FreeSlot>();
+                slot.write(FreeSlot { next: state.free });
+                NonNull::new_unchecked(slot)
+            };
+            state.free = Some(slot);
+        }
+        state.slabs += 1;
+        Some(())
+    }
+
+    pub fn stats(&self) -> SlabStats {
+        let (objects_in_use, total_slabs) = {
+            let state = self.state.lock();
+            (state.in_use, state.slabs)
+        };
+        let slab_bytes = total_slabs * Self::SLAB_SIZE;
+        let fragmentation = match slab_bytes {
+            0 => 0.0,
+            _ => 1.0 - (objects_in_use * size_of::<T>()) as f32 / slab_bytes as f32,
+        };
+        SlabStats {
+            name: self.name,
+            object_size: size_of::<T>(),
+            slot_size: Self::SLOT_SIZE,
+            objects_in_use,
+            total_slabs,
+            capacity: total_slabs * Self::SLAB_OBJECTS,
+            fragmentation,
+        }
+    }
+}
+
+impl<T: 'static> CacheStats for SlabCache<T> {
+    fn stats(&self) -> SlabStats {
+        SlabCache::stats(self)
+    }
+}
+
+/// Statistics for every cache that has allocated
+pub fn slab_stats() -> Vec<SlabStats> {
+    CACHES.lock().iter().map(|cache| cache.stats()).collect()
+}
+
+/// An object in a slot of a `SlabCache`, given back to the cache when dropped
+pub struct SlabBox<T: 'static> {
+    ptr: NonNull<T>,
+    cache: &'static SlabCache<T>,
+}
+
+// SAFETY: A `SlabBox` owns its object like a `Box` does, and the cache is shared under a lock
+unsafe impl<T: Send + 'static> Send for SlabBox<T> {}
+unsafe impl<T: Sync + 'static> Sync for SlabBox<T> {}
+
+impl<T: 'static> SlabBox<T> {
+    /// Move `value` into a slot of `cache`. Running out of memory is handled as for `Box::new`
+    pub fn new(cache: &'static SlabCache<T>, value: T) -> Self {
+        let Some(ptr) = cache.alloc() else {
+            handle_alloc_error(Layout::new::<T>());
+        };
+        // SAFETY: The slot is free, and sized and aligned for a `T`
+        unsafe { ptr.as_ptr().write(value) };
+        Self { ptr, cache }
+    }
+
+    /// Move the object out, giving its slot back
+    pub fn into_inner(boxed: Self) -> T {
+        let boxed = core::mem::ManuallyDrop::new(boxed);
+        // SAFETY: The object is read out once, and the slot is not used again after it is freed
+        unsafe {
+            let value = boxed.ptr.as_ptr().read();
+            boxed.cache.free(boxed.ptr);
+            value
+        }
+    }
+}
+
+impl<T: 'static> Deref for SlabBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: The slot holds a live `T` for as long as the box exists
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: 'static> DerefMut for SlabBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: As for `deref`, and the box is borrowed mutably
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T: 'static> Drop for SlabBox<T> {
+    fn drop(&mut self) {
+        // SAFETY: The object is dropped once, then its slot given back to the cache it came from
+        unsafe {
+            ptr::drop_in_place(self.ptr.as_ptr());
+            self.cache.free(self.ptr);
+        }
+    }
+}
+
+impl<T: Clone + 'static> Clone for SlabBox<T> {
+    fn clone(&self) -> Self {
+        SlabBox::new(self.cache, T::clone(self))
+    }
+}
+
+impl<T: fmt::Debug + 'static> fmt::Debug for SlabBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
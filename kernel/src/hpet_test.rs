@@ -0,0 +1,86 @@
+//! HPET Timer Test
+//! Checks that the HPET main counter is monotonic and that a comparator interrupt
+//! fires close to its programmed deadline
+
+use crate::serial::_print;
+use crate::time::hpet;
+
+const MONOTONIC_SAMPLES: usize = 10_000;
+
+/// One-shot deadline and how late its interrupt may arrive
+const COMPARATOR_DELAY_NS: u64 = 10_000_000;
+const COMPARATOR_TOLERANCE_NS: u64 = 2_000_000;
+const COMPARATOR_TIMEOUT_US: u64 = 100_000;
+
+/// Test the HPET counter and comparators against the QEMU HPET
+pub fn run_hpet_tests() -> Result<(), &'static str> {
+    _print(format_args!("[HPET Test] Starting HPET timer tests...\n"));
+    
+    // Test 1: HPET was found through ACPI
+    _print(format_args!("[HPET Test] Test 1: Checking HPET presence...\n"));
+    if !hpet::is_available() {
+        return Err("HPET not initialized");
+    }
+    _print(format_args!("[HPET Test] ✓ HPET at {} Hz with {} timers\n", hpet::frequency(), hpet::num_timers()));
+    
+    // Test 2: Main counter never goes backwards and tracks elapsed time
+    _print(format_args!("[HPET Test] Test 2: Counter monotonicity...\n"));
+    let mut previous = hpet::read_counter();
+    for _ in 0..MONOTONIC_SAMPLES {
+        let current = hpet::read_counter();
+        if current < previous {
+            return Err("HPET counter went backwards");
+        }
+        previous = current;
+    }
+    let start = hpet::read_counter();
+    crate::time::sleep_ms(5);
+    let elapsed_ns = hpet::ticks_to_ns(hpet::read_counter() - start);
+    if elapsed_ns < 4_000_000 {
+        return Err("HPET counter did not advance with time");
+    }
+    _print(format_args!("[HPET Test] ✓ Counter monotonic, {} ns over a 5 ms sleep\n", elapsed_ns));
+    
+    // Test 3: One-shot comparator interrupt on the last timer, clear of the system tick
+    _print(format_args!("[HPET Test] Test 3: Comparator interrupt...\n"));
+    let timer = hpet::num_timers() - 1;
+    if timer == hpet::SYSTEM_TIMER {
+        return Err("HPET has no spare comparator");
+    }
+    let fired_before = hpet::fire_count(timer);
+    let armed_at = hpet::read_counter();
+    hpet::arm_timer(timer, hpet::TimerMode::OneShot, COMPARATOR_DELAY_NS)?;
+    
+    let deadline = armed_at + hpet::ns_to_ticks(COMPARATOR_TIMEOUT_US * 1000);
+    while hpet::fire_count(timer) == fired_before && hpet::read_counter() < deadline {
+        core::hint::spin_loop();
+    }
+    hpet::disarm_timer(timer);
+    
+    if hpet::fire_count(timer) == fired_before {
+        return Err("HPET comparator interrupt did not fire");
+    }
+    let latency_ns = hpet::ticks_to_ns(hpet::last_fire_counter(timer) - armed_at);
+    if latency_ns < COMPARATOR_DELAY_NS || latency_ns > COMPARATOR_DELAY_NS + COMPARATOR_TOLERANCE_NS {
+        _print(format_args!("[HPET Test] Comparator fired after {} ns\n", latency_ns));
+        return Err("HPET comparator fired outside the expected window");
+    }
+    _print(format_args!("[HPET Test] ✓ Comparator fired after {} ns\n", latency_ns));
+    
+    _print(format_args!("[HPET Test] ✓ All HPET timer tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for HPET
+pub fn test_hpet() {
+    _print(format_args!("[HPET Test] ===========================================\n"));
+    _print(format_args!("[HPET Test]             HPET TIMER TESTS\n"));
+    _print(format_args!("[HPET Test] ===========================================\n"));
+    
+    match run_hpet_tests() {
+        Ok(_) => _print(format_args!("[HPET Test] ✓ All HPET tests PASSED\n")),
+        Err(e) => _print(format_args!("[HPET Test] ✗ HPET tests FAILED: {}\n", e)),
+    }
+    
+    _print(format_args!("[HPET Test] ===========================================\n"));
+}
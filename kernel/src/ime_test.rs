@@ -0,0 +1,198 @@
+//! Input Method Test
+//! Composes accented text with the dead-key input method, both on its own and through the
+//! window manager, and checks the preedit, the committed text and the compositor overlay
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::graphics::{
+    get_text_height, Color, GraphicsBuffer, RaeTheme, Rect, WidgetType, WindowEvent, WindowId,
+    WindowManager,
+};
+use crate::input::ime::{DeadKeyIme, ImeResponse, InputMethod, KEY_BACKSPACE, KEY_ENTER, KEY_SPACE, KEY_TAB};
+use crate::serial::_print;
+
+fn key(c: char) -> u32 {
+    c as u32
+}
+
+/// Press and release each key of `keys` in turn
+fn type_keys(wm: &mut WindowManager, keys: &[u32]) {
+    for &key_code in keys {
+        wm.dispatch_keyboard_event(key_code, true);
+        wm.dispatch_keyboard_event(key_code, false);
+    }
+}
+
+fn text_input(wm: &WindowManager, window_id: WindowId) -> Option<String> {
+    let window = wm.get_window(window_id)?;
+    match &window.widgets.get(window.focused_widget?)?.widget_type {
+        WidgetType::TextInput { text, .. } => Some(text.clone()),
+        _ => None,
+    }
+}
+
+/// A window holding one focused, empty text input
+fn editor(wm: &mut WindowManager, title: &str, rect: Rect) -> WindowId {
+    let window_id = wm.create_window(String::from(title), rect, 1);
+    if let Some(window) = wm.get_window_mut(window_id) {
+        window.add_widget(WidgetType::TextInput { text: String::new(), cursor_pos: 0 }, Rect::new(10, 10, 200, 24));
+        window.focus_next_widget();
+    }
+    window_id
+}
+
+/// Input method events queued for a window, drained
+fn take_ime_events(wm: &mut WindowManager, window_id: WindowId) -> Vec<WindowEvent> {
+    let Some(window) = wm.get_window_mut(window_id) else {
+        return Vec::new();
+    };
+    let events = core::mem::take(&mut window.pending_events);
+    events
+        .into_iter()
+        .filter(|event| matches!(event, WindowEvent::Preedit { .. } | WindowEvent::TextCommit(_)))
+        .collect()
+}
+
+fn is_preedit(event: Option<&WindowEvent>, expected: &str) -> bool {
+    matches!(event, Some(WindowEvent::Preedit { text, .. }) if text == expected)
+}
+
+pub fn run_ime_tests() -> Result<(), &'static str> {
+    _print(format_args!("[IME Test] Starting input method tests...\n"));
+
+    // Test 1: Dead-key composition on its own
+    _print(format_args!("[IME Test] Test 1: Dead-key composition...\n"));
+    let mut ime = DeadKeyIme::new();
+    if ime.process_key(key('e')) != ImeResponse::PassThrough || ime.composition().is_some() {
+        return Err("Plain key did not pass through");
+    }
+    if ime.process_key(key('\'')) != ImeResponse::Composing {
+        return Err("Accent key did not start a composition");
+    }
+    let composition = ime.composition().ok_or("No composition after the accent key")?;
+    if composition.preedit != "'" || composition.cursor != 1 || composition.candidates.first().map(String::as_str) != Some("á") {
+        return Err("Accent preedit or candidates wrong");
+    }
+    let composed: [(char, u32, &str); 5] = [
+        ('\'', key('e'), "é"),
+        ('~', key('n'), "ñ"),
+        ('"', key('U'), "Ü"),
+        ('\'', key('x'), "'x"),
+        ('^', KEY_SPACE, "^"),
+    ];
+    for (accent, base, expected) in composed {
+        ime.process_key(key(accent));
+        if ime.process_key(base) != ImeResponse::Commit(String::from(expected)) || ime.composition().is_some() {
+            return Err("Accent and key composed wrongly");
+        }
+    }
+    ime.process_key(key('`'));
+    if ime.process_key(KEY_BACKSPACE) != ImeResponse::Cancel || ime.composition().is_some() {
+        return Err("Backspace did not cancel the composition");
+    }
+    ime.process_key(key('\''));
+    if ime.process_key(KEY_TAB) != ImeResponse::Composing || ime.composition().map(|c| c.selected) != Some(1) {
+        return Err("Tab did not move the candidate highlight");
+    }
+    if ime.process_key(KEY_ENTER) != ImeResponse::Commit(String::from("é")) {
+        return Err("Enter did not commit the highlighted candidate");
+    }
+    _print(format_args!("[IME Test] ✓ Accents compose, fall back and cancel correctly\n"));
+
+    // Test 2: An opted-in window gets the preedit while composing and the composed text after
+    _print(format_args!("[IME Test] Test 2: Composing into a text input...\n"));
+    let mut wm = WindowManager::new(640, 480);
+    wm.set_input_method(Some(Box::new(DeadKeyIme::new())));
+    let notes = editor(&mut wm, "Notes", Rect::new(100, 100, 300, 200));
+    wm.set_ime_enabled(notes, true)?;
+    wm.focus_window(notes);
+    type_keys(&mut wm, &[key('c'), key('a'), key('f')]);
+    take_ime_events(&mut wm, notes);
+    type_keys(&mut wm, &[key('\'')]);
+    let composing = take_ime_events(&mut wm, notes);
+    if !is_preedit(composing.first(), "'") || composing.len() != 1 || wm.composition().is_none() {
+        return Err("Preedit not updated during composition");
+    }
+    if text_input(&wm, notes).as_deref() != Some("caf") {
+        return Err("Accent key reached the text input");
+    }
+    type_keys(&mut wm, &[key('e')]);
+    let committed = take_ime_events(&mut wm, notes);
+    if !is_preedit(committed.first(), "") || !matches!(committed.get(1), Some(WindowEvent::TextCommit(text)) if text == "é") {
+        return Err("Composed text not committed");
+    }
+    if text_input(&wm, notes).as_deref() != Some("café") || wm.composition().is_some() {
+        return Err("Composed character not inserted into the text input");
+    }
+    type_keys(&mut wm, &[KEY_BACKSPACE, key('e')]);
+    if text_input(&wm, notes).as_deref() != Some("cafe") {
+        return Err("Backspace did not remove the composed character");
+    }
+    _print(format_args!("[IME Test] ✓ Preedit tracked and \"café\" composed\n"));
+
+    // Test 3: Without opting in, keys pass through unchanged
+    _print(format_args!("[IME Test] Test 3: Windows without the IME flag...\n"));
+    let plain = editor(&mut wm, "Plain", Rect::new(420, 100, 200, 100));
+    wm.focus_window(plain);
+    type_keys(&mut wm, &[key('\''), key('e')]);
+    if text_input(&wm, plain).as_deref() != Some("'e") || wm.composition().is_some() {
+        return Err("Keys composed in a window without the IME flag");
+    }
+    let keys = wm
+        .get_window(plain)
+        .map_or(0, |window| window.pending_events.iter().filter(|event| matches!(event, WindowEvent::Keyboard(_))).count());
+    if keys != 4 || !take_ime_events(&mut wm, plain).is_empty() {
+        return Err("Key events changed without the IME flag");
+    }
+    _print(format_args!("[IME Test] ✓ Keys pass through unchanged\n"));
+
+    // Test 4: The compositor draws the preedit underline and the candidate window
+    _print(format_args!("[IME Test] Test 4: Preedit and candidate window rendering...\n"));
+    wm.focus_window(notes);
+    type_keys(&mut wm, &[key('"'), KEY_TAB]);
+    let caret = wm.get_window(notes).map(|window| window.caret_position()).ok_or("Window vanished")?;
+    let theme = RaeTheme::default();
+    let line_height = get_text_height() as i32;
+    let mut screen = GraphicsBuffer::new(640, 480);
+    wm.render(&mut screen);
+    let pixel = |screen: &GraphicsBuffer, x: i32, y: i32| -> Color { screen.get_pixel(x as u32, y as u32) };
+    let candidates_top = caret.y + line_height + 2;
+    if pixel(&screen, caret.x, caret.y + line_height) != theme.accent_color {
+        return Err("Preedit underline not drawn");
+    }
+    if pixel(&screen, caret.x + 1, candidates_top) != theme.background_color
+        || pixel(&screen, caret.x + 1, candidates_top + line_height) != theme.accent_color
+    {
+        return Err("Candidate window not drawn with the highlight on the selected candidate");
+    }
+    // Moving focus abandons the composition and clears the overlay
+    take_ime_events(&mut wm, notes);
+    wm.focus_window(plain);
+    if wm.composition().is_some() || !is_preedit(take_ime_events(&mut wm, notes).first(), "") {
+        return Err("Focus change did not cancel the composition");
+    }
+    let mut screen = GraphicsBuffer::new(640, 480);
+    wm.render(&mut screen);
+    if pixel(&screen, caret.x, caret.y + line_height) == theme.accent_color {
+        return Err("Preedit drawn after the composition ended");
+    }
+    _print(format_args!("[IME Test] ✓ Overlay drawn during composition and cleared after\n"));
+
+    _print(format_args!("[IME Test] ✓ All input method tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for the input method framework
+pub fn test_ime() {
+    _print(format_args!("[IME Test] ===========================================\n"));
+    _print(format_args!("[IME Test]            INPUT METHOD TESTS\n"));
+    _print(format_args!("[IME Test] ===========================================\n"));
+
+    match run_ime_tests() {
+        Ok(_) => _print(format_args!("[IME Test] ✓ All input method tests PASSED\n")),
+        Err(e) => _print(format_args!("[IME Test] ✗ Input method tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[IME Test] ===========================================\n"));
+}
@@ -0,0 +1,159 @@
+//! Inotify Test
+//! Watches a directory on a mounted in-memory filesystem through an inotify descriptor and
+//! checks that creating, writing and removing a file inside it read back as `IN_CREATE`,
+//! `IN_MODIFY` and `IN_DELETE` records named after the file, that a rename pairs
+//! `IN_MOVED_FROM` and `IN_MOVED_TO` by cookie, that removing a watched file ends its watch
+//! with `IN_DELETE_SELF` and `IN_IGNORED`, and that a recursive watch reports changes in
+//! subdirectories that a plain one does not
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::filesystem::inotify::{self, InotifyEvent, IN_NONBLOCK, IN_RECURSIVE};
+use crate::filesystem::inotify::{IN_ALL_EVENTS, IN_CREATE, IN_DELETE, IN_DELETE_SELF, IN_IGNORED, IN_MODIFY, IN_MOVED_FROM, IN_MOVED_TO};
+use crate::filesystem::{self, FileSystemError, MemoryFileSystem, O_CREAT, O_WRONLY};
+use crate::serial::_print;
+
+const MOUNT: &str = "/mnt/inotify-test";
+
+fn path(name: &str) -> String {
+    format!("{}/{}", MOUNT, name)
+}
+
+/// Write `data` to `name` in two writes, creating it if needed
+fn write(name: &str, data: &[u8]) -> Result<(), &'static str> {
+    let fd = filesystem::open(&path(name), O_WRONLY | O_CREAT).map_err(|_| "Failed to open for writing")?;
+    let (head, tail) = data.split_at(data.len() / 2);
+    let written = filesystem::write(fd, head).and_then(|_| filesystem::write(fd, tail));
+    let _ = filesystem::close(fd);
+    written.map(|_| ()).map_err(|_| "Failed to write")
+}
+
+/// The kind and name of each record queued on `fd`, without waiting
+fn read_events(fd: u64) -> Result<Vec<(u32, String)>, &'static str> {
+    let mut buffer = [0; 1024];
+    match filesystem::read(fd, &mut buffer) {
+        Ok(read) => Ok(InotifyEvent::parse(&buffer[..read]).into_iter().map(|event| (event.mask, event.name)).collect()),
+        Err(FileSystemError::WouldBlock) => Ok(Vec::new()),
+        Err(_) => Err("Failed to read the inotify descriptor"),
+    }
+}
+
+fn named(events: &[(u32, &str)]) -> Vec<(u32, String)> {
+    events.iter().map(|&(mask, name)| (mask, String::from(name))).collect()
+}
+
+fn run_event_tests(fds: &mut Vec<u64>) -> Result<(), &'static str> {
+    filesystem::create_directory(&path("docs")).map_err(|_| "Failed to create a directory")?;
+    filesystem::create_directory(&path("docs/sub")).map_err(|_| "Failed to create a directory")?;
+    filesystem::create_directory(&path("docs/sub/deep")).map_err(|_| "Failed to create a directory")?;
+
+    // Test 1: Creating, writing and removing a file read back as its three events
+    _print(format_args!("[Inotify Test] Test 1: Create, modify and delete...\n"));
+    let fd = inotify::create(IN_NONBLOCK).map_err(|_| "Failed to create an inotify descriptor")?;
+    fds.push(fd);
+    let docs = inotify::add_watch(fd, &path("docs"), IN_ALL_EVENTS).map_err(|_| "Failed to watch a directory")?;
+    if !read_events(fd)?.is_empty() {
+        return Err("Events before any change");
+    }
+    write("docs/notes.txt", b"first draft")?;
+    filesystem::remove(&path("docs/notes.txt")).map_err(|_| "Failed to remove a file")?;
+    let mut buffer = [0; InotifyEvent::HEADER_SIZE];
+    if filesystem::read(fd, &mut buffer) != Err(FileSystemError::InvalidOperation) {
+        return Err("Read too small for a record accepted");
+    }
+    let mut raw = [0; 1024];
+    let read = filesystem::read(fd, &mut raw).map_err(|_| "Failed to read the inotify descriptor")?;
+    let records = InotifyEvent::parse(&raw[..read]);
+    let expected = named(&[(IN_CREATE, "notes.txt"), (IN_MODIFY, "notes.txt"), (IN_DELETE, "notes.txt")]);
+    if records.iter().map(|event| (event.mask, event.name.clone())).collect::<Vec<_>>() != expected {
+        return Err("Create, modify and delete not read back in order");
+    }
+    if records.iter().any(|event| event.wd != docs as i32) || read % InotifyEvent::HEADER_SIZE != 0 {
+        return Err("Records carry the wrong watch descriptor or are unpadded");
+    }
+    _print(format_args!("[Inotify Test] ✓ {} records for watch {}\n", records.len(), docs));
+
+    // Test 2: A rename pairs its halves, and removing a watched file ends its watch
+    _print(format_args!("[Inotify Test] Test 2: Rename and a watched file's removal...\n"));
+    write("docs/draft.txt", b"x")?;
+    let draft = inotify::add_watch(fd, &path("docs/draft.txt"), IN_DELETE_SELF).map_err(|_| "Failed to watch a file")?;
+    read_events(fd)?;
+    filesystem::rename(&path("docs/draft.txt"), &path("docs/final.txt")).map_err(|_| "Failed to rename")?;
+    let mut raw = [0; 1024];
+    let read = filesystem::read(fd, &mut raw).map_err(|_| "Failed to read the inotify descriptor")?;
+    let moves = InotifyEvent::parse(&raw[..read]);
+    let paired = match &moves[..] {
+        [from, to] => from.mask == IN_MOVED_FROM && to.mask == IN_MOVED_TO && from.cookie != 0 && from.cookie == to.cookie,
+        _ => false,
+    };
+    if !paired || moves[0].name != "draft.txt" || moves[1].name != "final.txt" {
+        return Err("Rename not read back as a paired move");
+    }
+    filesystem::remove(&path("docs/final.txt")).map_err(|_| "Failed to remove a file")?;
+    let mut raw = [0; 1024];
+    let read = filesystem::read(fd, &mut raw).map_err(|_| "Failed to read the inotify descriptor")?;
+    let ended: Vec<(i32, u32)> = InotifyEvent::parse(&raw[..read]).iter().map(|event| (event.wd, event.mask)).collect();
+    let draft_wd = draft as i32;
+    if ended != [(docs as i32, IN_DELETE), (draft_wd, IN_DELETE_SELF), (draft_wd, IN_IGNORED)] {
+        return Err("Removed file's watch not ended by IN_DELETE_SELF and IN_IGNORED");
+    }
+    if inotify::rm_watch(fd, draft).is_ok() {
+        return Err("Ended watch could still be removed");
+    }
+    inotify::rm_watch(fd, docs).map_err(|_| "Failed to remove a watch")?;
+    if read_events(fd)? != named(&[(IN_IGNORED, "")]) {
+        return Err("Removed watch not acknowledged with IN_IGNORED");
+    }
+    _print(format_args!("[Inotify Test] ✓ Move cookie {} shared, watch {} ended\n", moves[0].cookie, draft));
+
+    // Test 3: A recursive watch sees changes in subdirectories, a plain one only its entries
+    _print(format_args!("[Inotify Test] Test 3: Recursive watch...\n"));
+    let recursive = inotify::create(IN_NONBLOCK).map_err(|_| "Failed to create an inotify descriptor")?;
+    fds.push(recursive);
+    let plain = inotify::create(IN_NONBLOCK).map_err(|_| "Failed to create an inotify descriptor")?;
+    fds.push(plain);
+    inotify::add_watch(recursive, &path("docs"), IN_CREATE | IN_DELETE | IN_RECURSIVE).map_err(|_| "Failed to watch a tree")?;
+    inotify::add_watch(plain, &path("docs"), IN_CREATE | IN_DELETE).map_err(|_| "Failed to watch a directory")?;
+    write("docs/sub/deep/index.db", b"entries")?;
+    write("docs/top.txt", b"top")?;
+    filesystem::remove(&path("docs/sub/deep/index.db")).map_err(|_| "Failed to remove a file")?;
+    let expected = named(&[(IN_CREATE, "sub/deep/index.db"), (IN_CREATE, "top.txt"), (IN_DELETE, "sub/deep/index.db")]);
+    if read_events(recursive)? != expected {
+        return Err("Recursive watch missed changes in a subdirectory");
+    }
+    if read_events(plain)? != named(&[(IN_CREATE, "top.txt")]) {
+        return Err("Plain watch reported changes below a subdirectory");
+    }
+    _print(format_args!("[Inotify Test] ✓ Recursive watch on {} saw docs/sub/deep\n", path("docs")));
+
+    _print(format_args!("[Inotify Test] ✓ All inotify tests completed successfully!\n"));
+    Ok(())
+}
+
+pub fn run_inotify_tests() -> Result<(), &'static str> {
+    let memory_fs = MemoryFileSystem::new(String::from("inotify-test"));
+    filesystem::mount_filesystem(Box::new(memory_fs), MOUNT).map_err(|_| "Failed to mount the test filesystem")?;
+    let mut fds = Vec::new();
+    let result = run_event_tests(&mut fds);
+    for fd in fds {
+        let _ = filesystem::close(fd);
+    }
+    let _ = filesystem::unmount_filesystem(MOUNT);
+    result
+}
+
+/// Main test runner for inotify descriptors
+pub fn test_inotify() {
+    _print(format_args!("[Inotify Test] ===========================================\n"));
+    _print(format_args!("[Inotify Test]               INOTIFY TESTS\n"));
+    _print(format_args!("[Inotify Test] ===========================================\n"));
+
+    match run_inotify_tests() {
+        Ok(_) => _print(format_args!("[Inotify Test] ✓ All inotify tests PASSED\n")),
+        Err(e) => _print(format_args!("[Inotify Test] ✗ Inotify tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Inotify Test] ===========================================\n"));
+}
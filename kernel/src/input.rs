@@ -2,7 +2,9 @@
 //! Provides real-time input processing for keyboard, mouse, and other input devices
 
 use crate::drivers;
+use crate::drivers::touchscreen::{TouchFrame, TouchPoint};
 use crate::graphics;
+use crate::graphics::{Gesture, SwipeDirection};
 use crate::slo::{with_slo_harness, SloCategory};
 use crate::slo_measure;
 use alloc::collections::BTreeMap;
@@ -11,12 +13,278 @@ use alloc::string::ToString;
 use spin::Mutex;
 use lazy_static::lazy_static;
 
+pub mod ime;
+
 // Input latency tracking for SLO measurements
 lazy_static! {
     static ref INPUT_TIMESTAMPS: Mutex<BTreeMap<u8, u64>> = Mutex::new(BTreeMap::new());
     static ref LATENCY_SAMPLES: Mutex<Vec<f64>> = Mutex::new(Vec::new());
 }
 
+/// Farthest a finger may wander and still tap or long-press, in pixels
+const TAP_SLOP: u64 = 10;
+const TAP_MAX_NS: u64 = 300_000_000;
+/// Longest gap between the first tap lifting and the second starting
+const DOUBLE_TAP_NS: u64 = 300_000_000;
+const DOUBLE_TAP_SLOP: u64 = 30;
+const LONG_PRESS_NS: u64 = 500_000_000;
+/// Change in two-finger spread that makes a pinch, in pixels
+const PINCH_THRESHOLD: u64 = 20;
+/// Two-finger travel that makes a scroll, in pixels
+const SCROLL_THRESHOLD: u64 = 10;
+const SWIPE_MIN_DISTANCE: i32 = 80;
+const SWIPE_MAX_NS: u64 = 500_000_000;
+
+static GESTURE_RECOGNIZER: Mutex<GestureRecognizer> = Mutex::new(GestureRecognizer::new());
+
+/// A gesture and where on screen it happened
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecognizedGesture {
+    pub gesture: Gesture,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// A finger followed from the frame it landed in
+#[derive(Debug, Clone, Copy)]
+struct TrackedTouch {
+    start: TouchPoint,
+    current: TouchPoint,
+}
+
+impl TrackedTouch {
+    fn travel(&self) -> u64 {
+        distance((self.start.x, self.start.y), (self.current.x, self.current.y))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GestureMode {
+    /// Nothing recognized yet; taps and swipes are decided when the fingers lift
+    Undecided,
+    /// A lone finger moved past the tap slop and can only end in a swipe
+    Moving,
+    LongPress,
+    Pinch { start_spread: u64, last_spread: u64 },
+    Scroll { last_x: i32, last_y: i32 },
+    /// A third finger joined a pinch or scroll; nothing more is recognized until all lift
+    Ignored,
+}
+
+fn distance(a: (i32, i32), b: (i32, i32)) -> u64 {
+    let dx = i64::from(a.0) - i64::from(b.0);
+    let dy = i64::from(a.1) - i64::from(b.1);
+    ((dx * dx + dy * dy) as u64).isqrt()
+}
+
+/// Turns the touch frames of a multi-touch screen into gestures. A sequence runs from the
+/// first finger landing to the last one lifting; continuous gestures (long press, pinch,
+/// scroll) are reported while it runs and discrete ones (tap, double tap, swipe) when it ends
+pub struct GestureRecognizer {
+    touches: BTreeMap<u8, TrackedTouch>,
+    /// Fingers of the current sequence that have already lifted
+    lifted: Vec<TrackedTouch>,
+    mode: GestureMode,
+    /// Most fingers down at once during the current sequence
+    max_fingers: usize,
+    sequence_start_ns: u64,
+    /// Centre and spread of two fingers when the second one landed
+    two_finger_start: Option<((i32, i32), u64)>,
+    /// When and where the last single tap lifted
+    last_tap: Option<(u64, i32, i32)>,
+}
+
+impl GestureRecognizer {
+    pub const fn new() -> Self {
+        Self {
+            touches: BTreeMap::new(),
+            lifted: Vec::new(),
+            mode: GestureMode::Undecided,
+            max_fingers: 0,
+            sequence_start_ns: 0,
+            two_finger_start: None,
+            last_tap: None,
+        }
+    }
+    
+    /// Fingers currently on the screen, by contact ID
+    pub fn touch_points(&self) -> Vec<TouchPoint> {
+        self.touches.values().map(|touch| touch.current).collect()
+    }
+    
+    /// Track the contacts of `frame` and return the gestures they complete
+    pub fn process_frame(&mut self, frame: &TouchFrame) -> Vec<RecognizedGesture> {
+        let mut gestures = Vec::new();
+        if self.touches.is_empty() && self.lifted.is_empty() {
+            if frame.contacts.is_empty() {
+                return gestures;
+            }
+            self.sequence_start_ns = frame.timestamp_ns;
+        }
+        
+        let gone: Vec<u8> = self
+            .touches
+            .keys()
+            .copied()
+            .filter(|id| !frame.contacts.iter().any(|contact| contact.id == *id))
+            .collect();
+        for id in gone {
+            if let Some(touch) = self.touches.remove(&id) {
+                self.lifted.push(touch);
+            }
+        }
+        for &contact in &frame.contacts {
+            self.touches
+                .entry(contact.id)
+                .and_modify(|touch| touch.current = contact)
+                .or_insert(TrackedTouch { start: contact, current: contact });
+        }
+        self.max_fingers = self.max_fingers.max(self.touches.len());
+        
+        if self.max_fingers > 2 && matches!(self.mode, GestureMode::Pinch { .. } | GestureMode::Scroll { .. }) {
+            self.mode = GestureMode::Ignored;
+        }
+        if self.touches.len() == 2 {
+            self.track_two_fingers(&mut gestures);
+        } else {
+            self.two_finger_start = None;
+        }
+        if self.mode == GestureMode::Undecided
+            && self.max_fingers == 1
+            && self.touches.values().any(|touch| touch.travel() > TAP_SLOP)
+        {
+            self.mode = GestureMode::Moving;
+        }
+        self.check_long_press(frame.timestamp_ns, &mut gestures);
+        
+        if self.touches.is_empty() {
+            self.finish_sequence(frame.timestamp_ns, &mut gestures);
+        }
+        gestures
+    }
+    
+    /// Recognize a long press while the screen reports no changes
+    pub fn tick(&mut self, now_ns: u64) -> Vec<RecognizedGesture> {
+        let mut gestures = Vec::new();
+        self.check_long_press(now_ns, &mut gestures);
+        gestures
+    }
+    
+    fn check_long_press(&mut self, now_ns: u64, gestures: &mut Vec<RecognizedGesture>) {
+        if self.mode != GestureMode::Undecided || self.max_fingers != 1 {
+            return;
+        }
+        let Some(touch) = self.touches.values().next() else {
+            return;
+        };
+        if now_ns.saturating_sub(self.sequence_start_ns) >= LONG_PRESS_NS {
+            gestures.push(RecognizedGesture { gesture: Gesture::LongPress, x: touch.current.x, y: touch.current.y });
+            self.mode = GestureMode::LongPress;
+        }
+    }
+    
+    fn track_two_fingers(&mut self, gestures: &mut Vec<RecognizedGesture>) {
+        let mut fingers = self.touches.values().map(|touch| (touch.current.x, touch.current.y));
+        let (Some(a), Some(b)) = (fingers.next(), fingers.next()) else {
+            return;
+        };
+        let center = ((a.0 + b.0) / 2, (a.1 + b.1) / 2);
+        let spread = distance(a, b);
+        let Some((start_center, start_spread)) = self.two_finger_start else {
+            self.two_finger_start = Some((center, spread));
+            return;
+        };
+        
+        match self.mode {
+            GestureMode::Undecided if self.max_fingers == 2 => {
+                let spread_change = spread.abs_diff(start_spread);
+                let travel = distance(center, start_center);
+                if spread_change >= PINCH_THRESHOLD && spread_change > travel {
+                    self.mode = GestureMode::Pinch { start_spread: start_spread.max(1), last_spread: start_spread };
+                } else if travel >= SCROLL_THRESHOLD {
+                    self.mode = GestureMode::Scroll { last_x: start_center.0, last_y: start_center.1 };
+                } else {
+                    return;
+                }
+                self.track_two_fingers(gestures);
+            }
+            GestureMode::Pinch { start_spread, last_spread } if spread != last_spread => {
+                let scale = spread as f32 / start_spread as f32;
+                gestures.push(RecognizedGesture { gesture: Gesture::Pinch { scale }, x: center.0, y: center.1 });
+                self.mode = GestureMode::Pinch { start_spread, last_spread: spread };
+            }
+            GestureMode::Scroll { last_x, last_y } if center != (last_x, last_y) => {
+                let gesture = Gesture::Scroll { delta_x: center.0 - last_x, delta_y: center.1 - last_y };
+                gestures.push(RecognizedGesture { gesture, x: center.0, y: center.1 });
+                self.mode = GestureMode::Scroll { last_x: center.0, last_y: center.1 };
+            }
+            _ => {}
+        }
+    }
+    
+    fn finish_sequence(&mut self, now_ns: u64, gestures: &mut Vec<RecognizedGesture>) {
+        let duration = now_ns.saturating_sub(self.sequence_start_ns);
+        let lifted = core::mem::take(&mut self.lifted);
+        let count = lifted.len().max(1) as i32;
+        let x = lifted.iter().map(|touch| touch.current.x).sum::<i32>() / count;
+        let y = lifted.iter().map(|touch| touch.current.y).sum::<i32>() / count;
+        
+        match self.mode {
+            GestureMode::Undecided if self.max_fingers == 1 && duration <= TAP_MAX_NS => {
+                let sequence_start_ns = self.sequence_start_ns;
+                let double = self.last_tap.is_some_and(|(tap_ns, tap_x, tap_y)| {
+                    sequence_start_ns.saturating_sub(tap_ns) <= DOUBLE_TAP_NS
+                        && distance((x, y), (tap_x, tap_y)) <= DOUBLE_TAP_SLOP
+                });
+                if double {
+                    gestures.push(RecognizedGesture { gesture: Gesture::DoubleTap, x, y });
+                    self.last_tap = None;
+                } else {
+                    gestures.push(RecognizedGesture { gesture: Gesture::Tap, x, y });
+                    self.last_tap = Some((now_ns, x, y));
+                }
+            }
+            GestureMode::Undecided | GestureMode::Moving if duration <= SWIPE_MAX_NS => {
+                let delta_x = lifted.iter().map(|touch| touch.current.x - touch.start.x).sum::<i32>() / count;
+                let delta_y = lifted.iter().map(|touch| touch.current.y - touch.start.y).sum::<i32>() / count;
+                let direction = if delta_x.abs() >= delta_y.abs() {
+                    if delta_x < 0 { SwipeDirection::Left } else { SwipeDirection::Right }
+                } else if delta_y < 0 {
+                    SwipeDirection::Up
+                } else {
+                    SwipeDirection::Down
+                };
+                if delta_x.abs().max(delta_y.abs()) >= SWIPE_MIN_DISTANCE {
+                    let gesture = Gesture::Swipe { direction, fingers: self.max_fingers as u8 };
+                    gestures.push(RecognizedGesture { gesture, x, y });
+                }
+            }
+            _ => {}
+        }
+        
+        self.mode = GestureMode::Undecided;
+        self.max_fingers = 0;
+        self.two_finger_start = None;
+    }
+}
+
+/// Feed queued touchscreen frames through the gesture recognizer and deliver the gestures
+/// to the focused window
+fn process_touch_events() {
+    let gestures = {
+        let mut recognizer = GESTURE_RECOGNIZER.lock();
+        let mut gestures = Vec::new();
+        while let Some(frame) = drivers::touchscreen::poll_frame() {
+            gestures.extend(recognizer.process_frame(&frame));
+        }
+        gestures.extend(recognizer.tick(crate::time::get_timestamp_ns()));
+        gestures
+    };
+    for recognized in gestures {
+        graphics::handle_gesture_event(recognized.gesture, recognized.x, recognized.y);
+    }
+}
+
 /// Record timestamp when keyboard interrupt occurs
 pub fn record_input_interrupt_timestamp(scancode: u8, timestamp_ns: u64) {
     let mut timestamps = INPUT_TIMESTAMPS.lock();
@@ -95,6 +363,8 @@ pub fn process_input_events() {
             LAST_MOUSE_STATE = (x, y, buttons);
         }
     }
+    
+    process_touch_events();
 }
 
 /// Enhanced keyboard event routing with focus management
@@ -0,0 +1,155 @@
+//! Input method framework
+//! An input method turns key presses into composed text. While it composes, the keys build
+//! a preedit string that the compositor draws underlined at the caret of the focused widget,
+//! with a candidate window when the method offers choices; once finished, the method commits
+//! the text to the widget. Only windows with `WindowFlags::ime_enabled` go through the input
+//! method, all others receive their key events unchanged.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+pub const KEY_BACKSPACE: u32 = 8;
+pub const KEY_TAB: u32 = 9;
+pub const KEY_ENTER: u32 = 13;
+pub const KEY_ESCAPE: u32 = 27;
+pub const KEY_SPACE: u32 = 32;
+
+/// Text an input method is in the middle of composing
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Composition {
+    pub preedit: String,
+    /// Caret position within the preedit, in characters
+    pub cursor: usize,
+    /// Choices shown in the candidate window; empty hides the window
+    pub candidates: Vec<String>,
+    /// Index of the highlighted candidate
+    pub selected: usize,
+}
+
+/// What an input method did with a key press
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImeResponse {
+    /// The key is not part of a composition and goes to the window unchanged
+    PassThrough,
+    /// The key started or changed the composition
+    Composing,
+    /// The composition finished with this text
+    Commit(String),
+    /// The composition was abandoned without committing anything
+    Cancel,
+}
+
+pub trait InputMethod: Send {
+    fn name(&self) -> &str;
+
+    /// Handle a key press, given as the character code the window would otherwise receive
+    fn process_key(&mut self, key_code: u32) -> ImeResponse;
+
+    /// The composition in progress, if any
+    fn composition(&self) -> Option<Composition>;
+
+    /// Drop the composition in progress without committing it
+    fn reset(&mut self);
+}
+
+/// An accent key and the characters it composes with, pairwise
+struct DeadKey {
+    accent: char,
+    bases: &'static str,
+    composed: &'static str,
+}
+
+static DEAD_KEYS: [DeadKey; 5] = [
+    DeadKey { accent: '\'', bases: "aeiouyAEIOUY", composed: "áéíóúýÁÉÍÓÚÝ" },
+    DeadKey { accent: '`', bases: "aeiouAEIOU", composed: "àèìòùÀÈÌÒÙ" },
+    DeadKey { accent: '^', bases: "aeiouAEIOU", composed: "âêîôûÂÊÎÔÛ" },
+    DeadKey { accent: '"', bases: "aeiouyAEIOUY", composed: "äëïöüÿÄËÏÖÜŸ" },
+    DeadKey { accent: '~', bases: "anoANO", composed: "ãñõÃÑÕ" },
+];
+
+impl DeadKey {
+    fn compose(&self, base: char) -> Option<char> {
+        self.bases.chars().zip(self.composed.chars()).find(|&(b, _)| b == base).map(|(_, composed)| composed)
+    }
+
+    fn candidates(&self) -> Vec<String> {
+        self.composed.chars().filter(|c| c.is_lowercase()).map(String::from).collect()
+    }
+}
+
+/// Accent composition in the style of a dead-key keyboard layout: an accent key starts a
+/// composition showing the accent as preedit, and the next letter commits the accented
+/// letter. The candidate window lists the letters the accent composes with; Tab moves the
+/// highlight and Enter commits the highlighted one. Space, or a letter the accent does not
+/// combine with, commits the accent as typed
+pub struct DeadKeyIme {
+    pending: Option<&'static DeadKey>,
+    selected: usize,
+}
+
+impl DeadKeyIme {
+    pub const fn new() -> Self {
+        Self { pending: None, selected: 0 }
+    }
+}
+
+impl InputMethod for DeadKeyIme {
+    fn name(&self) -> &str {
+        "dead-keys"
+    }
+
+    fn process_key(&mut self, key_code: u32) -> ImeResponse {
+        let typed = (32..=126).contains(&key_code).then(|| key_code as u8 as char);
+        let Some(dead_key) = self.pending else {
+            return match typed.and_then(|c| DEAD_KEYS.iter().find(|dead_key| dead_key.accent == c)) {
+                Some(dead_key) => {
+                    self.pending = Some(dead_key);
+                    self.selected = 0;
+                    ImeResponse::Composing
+                }
+                None => ImeResponse::PassThrough,
+            };
+        };
+
+        let response = match key_code {
+            KEY_BACKSPACE | KEY_ESCAPE => ImeResponse::Cancel,
+            KEY_TAB => {
+                self.selected = (self.selected + 1) % dead_key.candidates().len().max(1);
+                return ImeResponse::Composing;
+            }
+            KEY_ENTER => {
+                let candidates = dead_key.candidates();
+                ImeResponse::Commit(candidates.get(self.selected).cloned().unwrap_or_else(|| String::from(dead_key.accent)))
+            }
+            _ => {
+                let mut text = String::new();
+                match typed.and_then(|c| dead_key.compose(c)) {
+                    Some(composed) => text.push(composed),
+                    None => {
+                        text.push(dead_key.accent);
+                        if let Some(c) = typed.filter(|&c| key_code != KEY_SPACE && c != dead_key.accent) {
+                            text.push(c);
+                        }
+                    }
+                }
+                ImeResponse::Commit(text)
+            }
+        };
+        self.reset();
+        response
+    }
+
+    fn composition(&self) -> Option<Composition> {
+        self.pending.map(|dead_key| Composition {
+            preedit: String::from(dead_key.accent),
+            cursor: 1,
+            candidates: dead_key.candidates(),
+            selected: self.selected,
+        })
+    }
+
+    fn reset(&mut self) {
+        self.pending = None;
+        self.selected = 0;
+    }
+}
@@ -0,0 +1,118 @@
+//! Input Grab Test
+//! Routes keyboard and mouse events through a window manager while a window grabs the input,
+//! and checks that destroying the grabbing window gives input back to the focused window
+
+use alloc::string::String;
+use crate::graphics::{Rect, WindowEvent, WindowId, WindowManager};
+use crate::serial::_print;
+
+const KEY_A: u32 = 30;
+
+/// Keyboard and mouse events queued for a window
+fn input_counts(wm: &WindowManager, window_id: WindowId) -> (usize, usize) {
+    wm.get_window(window_id).map_or((0, 0), |window| {
+        let keys = window.pending_events.iter().filter(|event| matches!(event, WindowEvent::Keyboard(_))).count();
+        let clicks = window.pending_events.iter().filter(|event| matches!(event, WindowEvent::Mouse(_))).count();
+        (keys, clicks)
+    })
+}
+
+fn clear_events(wm: &mut WindowManager) {
+    for window_id in wm.get_window_list() {
+        if let Some(window) = wm.get_window_mut(window_id) {
+            window.pending_events.clear();
+        }
+    }
+}
+
+pub fn run_input_grab_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Grab Test] Starting input grab tests...\n"));
+    let mut wm = WindowManager::new(640, 480);
+    let editor = wm.create_window(String::from("Editor"), Rect::new(0, 0, 200, 200), 1);
+    let game = wm.create_window(String::from("Game"), Rect::new(300, 100, 100, 80), 2);
+    wm.focus_window(editor);
+
+    // Test 1: Without a grab, keys go to the focused window and clicks to the one under the pointer
+    _print(format_args!("[Grab Test] Test 1: Routing without a grab...\n"));
+    wm.dispatch_keyboard_event(KEY_A, true);
+    wm.dispatch_mouse_event(350, 120, 0, false);
+    if input_counts(&wm, editor) != (1, 0) || input_counts(&wm, game) != (0, 1) {
+        return Err("Input misrouted without a grab");
+    }
+    clear_events(&mut wm);
+    _print(format_args!("[Grab Test] ✓ Focus and position decide routing\n"));
+
+    // Test 2: During a grab, every event reaches the grabbing window
+    _print(format_args!("[Grab Test] Test 2: Routing during a grab...\n"));
+    wm.grab_input(game)?;
+    wm.dispatch_keyboard_event(KEY_A, true);
+    wm.dispatch_keyboard_event(KEY_A, false);
+    // A press over another window neither reaches it nor takes focus
+    wm.dispatch_mouse_event(50, 50, 0, true);
+    wm.dispatch_mouse_event(50, 50, 0, false);
+    if input_counts(&wm, game) != (2, 2) || input_counts(&wm, editor) != (0, 0) {
+        return Err("Grabbed input reached another window");
+    }
+    if wm.focus_window(editor) || wm.set_focus(editor).is_ok() || wm.grab_input(editor).is_ok() {
+        return Err("Another window took focus or the grab during a grab");
+    }
+    let click = wm.get_window(game).and_then(|window| {
+        window.pending_events.iter().find_map(|event| match event {
+            WindowEvent::Mouse(event) => Some((event.x, event.y)),
+            _ => None,
+        })
+    });
+    if click != Some((0, 0)) {
+        return Err("Grabbed click was not confined to the grabbing window");
+    }
+    _print(format_args!("[Grab Test] ✓ Keyboard and mouse events all reach the grabbing window\n"));
+
+    // Test 3: The cursor is hidden and confined while grabbed
+    _print(format_args!("[Grab Test] Test 3: Cursor confinement...\n"));
+    wm.move_cursor(10, 470);
+    let cursor = wm.get_cursor();
+    if cursor.visible || (cursor.x, cursor.y) != (300, 179) {
+        return Err("Cursor not hidden and confined during a grab");
+    }
+    wm.release_input();
+    wm.move_cursor(10, 470);
+    let cursor = wm.get_cursor();
+    if !cursor.visible || (cursor.x, cursor.y) != (10, 470) || wm.get_input_grab().is_some() {
+        return Err("Releasing the grab did not free the cursor");
+    }
+    _print(format_args!("[Grab Test] ✓ Cursor hidden and confined until release\n"));
+
+    // Test 4: Destroying the grabbing window releases the grab
+    _print(format_args!("[Grab Test] Test 4: Destroying the grabbing window...\n"));
+    wm.grab_input(game)?;
+    clear_events(&mut wm);
+    if !wm.destroy_window(game) {
+        return Err("Failed to destroy the grabbing window");
+    }
+    if wm.get_input_grab().is_some() || !wm.get_cursor().visible {
+        return Err("Grab outlived its window");
+    }
+    wm.dispatch_keyboard_event(KEY_A, true);
+    wm.dispatch_mouse_event(50, 50, 0, true);
+    if input_counts(&wm, editor) != (1, 1) || !wm.focus_window(editor) {
+        return Err("Input did not return to the remaining window");
+    }
+    _print(format_args!("[Grab Test] ✓ Grab released with its window\n"));
+
+    _print(format_args!("[Grab Test] ✓ All input grab tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for input grabs
+pub fn test_input_grab() {
+    _print(format_args!("[Grab Test] ===========================================\n"));
+    _print(format_args!("[Grab Test]             INPUT GRAB TESTS\n"));
+    _print(format_args!("[Grab Test] ===========================================\n"));
+
+    match run_input_grab_tests() {
+        Ok(_) => _print(format_args!("[Grab Test] ✓ All input grab tests PASSED\n")),
+        Err(e) => _print(format_args!("[Grab Test] ✗ Input grab tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Grab Test] ===========================================\n"));
+}
@@ -6,6 +6,10 @@ use pic8259::ChainedPics;
 pub const PIC_1_OFFSET: u8 = 32;
 pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
+/// Vectors handed out to devices' MSI-X table entries
+pub const MSI_VECTOR_BASE: u8 = 0x50;
+pub const MSI_VECTOR_COUNT: u8 = 32;
+
 // SAFETY: This is unsafe because:
 // - ChainedPics::new accesses hardware I/O ports for PIC configuration
 // - PIC_1_OFFSET and PIC_2_OFFSET must be valid interrupt vector offsets
@@ -26,6 +30,12 @@ lazy_static! {
         idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
         idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
         idt[InterruptIndex::Mouse.as_usize()].set_handler_fn(mouse_interrupt_handler);
+        idt[InterruptIndex::Rtc.as_usize()].set_handler_fn(rtc_interrupt_handler);
+        idt[InterruptIndex::Hpet.as_usize()].set_handler_fn(hpet_interrupt_handler);
+        idt[InterruptIndex::PciHotplug.as_usize()].set_handler_fn(pci_hotplug_interrupt_handler);
+        for vector in MSI_VECTOR_BASE..MSI_VECTOR_BASE + MSI_VECTOR_COUNT {
+            idt[usize::from(vector)].set_handler_fn(msi_interrupt_handler);
+        }
         idt
     };
 }
@@ -36,10 +46,16 @@ pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     Keyboard,
     Mouse,
+    /// CMOS RTC on ISA IRQ 8
+    Rtc = PIC_2_OFFSET,
+    /// HPET comparators routed through the I/O APIC
+    Hpet = PIC_2_OFFSET + 8,
+    /// PCIe hotplug slot events, from the ports' INTx pins through the I/O APIC
+    PciHotplug,
 }
 
 impl InterruptIndex {
-    fn as_u8(self) -> u8 { self as u8 }
+    pub fn as_u8(self) -> u8 { self as u8 }
     fn as_usize(self) -> usize { usize::from(self.as_u8()) }
 }
 
@@ -132,15 +148,17 @@ use alloc::format;
     
     let fault_addr = Cr2::read();
     
-    // Check if this is a stack expansion request
-      if !error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
-          // Page not present - might be stack expansion
+    // Check if this is a stack expansion or copy-on-write request
+      if !error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
+          || error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+          // Page not present - might be stack expansion or demand allocation; a write to a
+          // present page might be to one shared copy-on-write, which gets its own frame
           let current_pid = get_current_process_id();
           let expansion_result = crate::vmm::handle_page_fault(fault_addr, error_code.bits());
           
           match expansion_result {
               Ok(()) => {
-                  // Stack expansion successful, return to continue execution
+                  // Fault resolved, return to retry the access
                   return;
               }
               Err(VmError::StackOverflow) => {
@@ -153,6 +171,7 @@ use alloc::format;
                   // Report stack overflow to crash handler
                   let _ = crate::observability::with_observability_mut(|obs| {
                        obs.crash_handler.handle_crash(
+                           &obs.flight_recorder,
                            crate::observability::crash_handler::CrashType::StackOverflow,
                            crate::observability::crash_handler::CrashSeverity::Critical,
                           Some(crate::observability::Subsystem::Memory),
@@ -182,6 +201,7 @@ use alloc::format;
     // Report general page fault to crash handler
     let _ = crate::observability::with_observability_mut(|obs| {
          obs.crash_handler.handle_crash(
+             &obs.flight_recorder,
              crate::observability::crash_handler::CrashType::PageFault,
              crate::observability::crash_handler::CrashSeverity::Error,
             Some(crate::observability::Subsystem::Memory),
@@ -252,4 +272,35 @@ extern "x86-interrupt" fn mouse_interrupt_handler(_stack_frame: InterruptStackFr
     }
 }
 
+extern "x86-interrupt" fn rtc_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    crate::drivers::rtc::handle_interrupt();
+    
+    if crate::apic::is_apic_enabled() {
+        crate::apic::send_eoi();
+    } else {
+        // SAFETY: This is unsafe because:
+        // - Sends EOI to both PICs via I/O ports (IRQ 8 is on the secondary PIC)
+        // - Must only be called from within the corresponding interrupt handler
+        unsafe {
+            PICS.lock().notify_end_of_interrupt(InterruptIndex::Rtc.as_u8());
+        }
+    }
+}
+
+extern "x86-interrupt" fn hpet_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    // Only reachable through the I/O APIC; legacy-routed HPET ticks arrive on the timer vector
+    crate::time::hpet::handle_interrupt();
+    crate::apic::send_eoi();
+}
+
+extern "x86-interrupt" fn pci_hotplug_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    // Only routed through the I/O APIC; the rescan runs later from the main loop
+    crate::pci::handle_hotplug_interrupt();
+    crate::apic::send_eoi();
+}
 
+extern "x86-interrupt" fn msi_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    // Drivers poll their devices from the main loop; the message only ends the CPU's halt
+    // so that they do so promptly
+    crate::apic::send_eoi();
+}
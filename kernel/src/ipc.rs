@@ -704,6 +704,8 @@ struct IpcSystem {
     objects: BTreeMap<u32, IpcObject>,
     file_descriptors: BTreeMap<u32, FileDescriptor>,
     handle_tables: BTreeMap<u32, ProcessHandleTable>, // process_id -> handle table
+    /// Message queues bound to a service name, for clients to connect to
+    endpoint_names: BTreeMap<String, u32>,
     next_ipc_id: u32,
     _next_fd_id: u32,
     audit_log: AuditLog,
@@ -715,6 +717,7 @@ impl IpcSystem {
             objects: BTreeMap::new(),
             file_descriptors: BTreeMap::new(),
             handle_tables: BTreeMap::new(),
+            endpoint_names: BTreeMap::new(),
             next_ipc_id: 1,
             _next_fd_id: 1,
             audit_log: AuditLog::new(1000, 100), // 1000 entry capacity, 100 ops/sec rate limit
@@ -1440,6 +1443,107 @@ pub fn create_capability_endpoint(
     Ok(handle_id)
 }
 
+/// Messages a named endpoint holds
+const ENDPOINT_QUEUE_LEN: usize = 100;
+const ENDPOINT_MESSAGE_SIZE: usize = 4096;
+
+impl IpcSystem {
+    /// The message queue behind `process_id`'s `handle_id`, which must carry `required_rights`
+    fn endpoint_queue(&mut self, process_id: u32, handle_id: u32, required_rights: IpcRights) -> Result<&mut MessageQueue, IpcError> {
+        self.validate_handle_rights(process_id, handle_id, required_rights)?;
+        let object_id = self.handle_tables.get(&process_id)
+            .and_then(|table| table.get_handle(handle_id))
+            .map(|handle| handle.object_id)
+            .ok_or(IpcError::InvalidHandle)?;
+        match self.objects.get_mut(&object_id) {
+            Some(IpcObject::MessageQueue(queue)) => Ok(queue),
+            Some(_) => Err(IpcError::InvalidHandle),
+            None => Err(IpcError::ObjectNotFound),
+        }
+    }
+}
+
+/// Whether `process_id` may use named endpoints as `capability_type` allows. Init, which
+/// binds the endpoints of the services it activates, always may
+fn may_use_endpoints(process_id: u32, capability_type: CapabilityType) -> bool {
+    let process_id = ProcessId::from(process_id);
+    process_id == crate::process::init_pid() || crate::capabilities::holds_capability(process_id, capability_type)
+}
+
+/// Bind a message queue to `name` for `process_id`, which may receive on it and delegate
+/// it. The endpoint outlives the handles to it, keeping messages for whoever receives next.
+/// Binding takes an IpcCreate capability, so no process can squat on a service's name
+pub fn bind_endpoint(process_id: u32, name: &str) -> Result<u32, IpcError> {
+    if !may_use_endpoints(process_id, CapabilityType::IpcCreate) {
+        return Err(IpcError::CapabilityRequired);
+    }
+    let mut ipc = IPC_SYSTEM.lock();
+    if ipc.endpoint_names.contains_key(name) {
+        return Err(IpcError::CreationFailed);
+    }
+    let queue_id = ipc.next_ipc_id;
+    ipc.next_ipc_id += 1;
+    ipc.objects.insert(queue_id, IpcObject::MessageQueue(MessageQueue::new(ENDPOINT_QUEUE_LEN, ENDPOINT_MESSAGE_SIZE)));
+    ipc.endpoint_names.insert(name.to_string(), queue_id);
+
+    let rights = IpcRights { send: true, recv: true, delegate: true, inspect: true, ..IpcRights::NONE };
+    let handle_id = ipc.get_or_create_handle_table(process_id)
+        .allocate_handle(queue_id, IpcObjectType::MessageQueue, rights, None, Some(name.to_string()));
+    let _ = ipc.audit_log.log(
+        process_id,
+        "bind_endpoint".to_string(),
+        queue_id,
+        "success".to_string(),
+        format!("name={}, handle={}", name, handle_id),
+    );
+    Ok(handle_id)
+}
+
+/// A send-only handle for `process_id` to the endpoint bound to `name`, which takes an
+/// IpcConnect capability
+pub fn connect_endpoint(process_id: u32, name: &str) -> Result<u32, IpcError> {
+    if !may_use_endpoints(process_id, CapabilityType::IpcConnect) {
+        return Err(IpcError::CapabilityRequired);
+    }
+    let mut ipc = IPC_SYSTEM.lock();
+    let queue_id = *ipc.endpoint_names.get(name).ok_or(IpcError::ObjectNotFound)?;
+    let rights = IpcRights { send: true, ..IpcRights::NONE };
+    Ok(ipc.get_or_create_handle_table(process_id)
+        .allocate_handle(queue_id, IpcObjectType::MessageQueue, rights, None, Some(name.to_string())))
+}
+
+/// The handle to endpoint `name` that was handed to `process_id` to receive on
+pub fn activated_endpoint(process_id: u32, name: &str) -> Result<u32, IpcError> {
+    let ipc = IPC_SYSTEM.lock();
+    let queue_id = *ipc.endpoint_names.get(name).ok_or(IpcError::ObjectNotFound)?;
+    ipc.handle_tables.get(&process_id)
+        .and_then(|table| table.handles.iter().find(|(_, handle)| handle.object_id == queue_id && handle.rights.recv))
+        .map(|(&handle_id, _)| handle_id)
+        .ok_or(IpcError::InvalidHandle)
+}
+
+pub fn send_endpoint_message(process_id: u32, handle_id: u32, message: &[u8]) -> Result<(), IpcError> {
+    let mut ipc = IPC_SYSTEM.lock();
+    let queue = ipc.endpoint_queue(process_id, handle_id, IpcRights { send: true, ..IpcRights::NONE })?;
+    if message.len() > queue.max_message_size {
+        return Err(IpcError::InvalidSize);
+    }
+    queue.send(message).map_err(|_| IpcError::BufferFull)
+}
+
+pub fn receive_endpoint_message(process_id: u32, handle_id: u32) -> Result<Vec<u8>, IpcError> {
+    let mut ipc = IPC_SYSTEM.lock();
+    ipc.endpoint_queue(process_id, handle_id, IpcRights { recv: true, ..IpcRights::NONE })?
+        .receive()
+        .map_err(|_| IpcError::BufferEmpty)
+}
+
+/// Messages waiting on an endpoint `process_id` may receive on, without taking them
+pub fn pending_endpoint_messages(process_id: u32, handle_id: u32) -> Result<usize, IpcError> {
+    let mut ipc = IPC_SYSTEM.lock();
+    Ok(ipc.endpoint_queue(process_id, handle_id, IpcRights { recv: true, ..IpcRights::NONE })?.messages.len())
+}
+
 /// Get capability endpoint by handle
 pub fn get_capability_endpoint(process_id: u32, handle_id: u32) -> Result<CapabilityEndpoint, IpcError> {
     let ipc = IPC_SYSTEM.lock();
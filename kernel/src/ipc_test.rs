@@ -3,9 +3,19 @@
 
 use alloc::vec;
 use alloc::string::ToString;
+use crate::capabilities::{self, CapabilityType};
 use crate::ipc::*;
 use crate::serial::_print;
 
+/// Give `process_id` a capability of `capability_type`
+fn grant(process_id: u32, capability_type: CapabilityType) -> Result<(), &'static str> {
+    let process_id = u64::from(process_id);
+    let capability = capabilities::create_capability(capability_type, process_id, None, 0x01, false, false)
+        .map_err(|_| "Failed to create capability")?;
+    capabilities::grant_capability(process_id, capability).map_err(|_| "Failed to grant capability")?;
+    Ok(())
+}
+
 /// Test IPC message passing between services
 pub fn run_ipc_tests() -> Result<(), &'static str> {
     _print(format_args!("[IPC Test] Starting IPC message passing tests...\n"));
@@ -62,6 +72,43 @@ pub fn run_ipc_tests() -> Result<(), &'static str> {
         Ok(_) => return Err("Invalid handle should have been rejected"),
     }
     
+    // Test 6: Named endpoint handed from its binder to a receiver
+    _print(format_args!("[IPC Test] Test 6: Testing named endpoint hand-off...\n"));
+    let (binder, client, receiver) = (200, 201, 202);
+    if bind_endpoint(binder, "ipc_test.activation") != Err(IpcError::CapabilityRequired) {
+        return Err("Endpoint bound without an IpcCreate capability");
+    }
+    grant(binder, CapabilityType::IpcCreate)?;
+    let bound = bind_endpoint(binder, "ipc_test.activation").map_err(|_| "Failed to bind endpoint")?;
+    if bind_endpoint(binder, "ipc_test.activation").is_ok() {
+        return Err("Endpoint name bound twice");
+    }
+    if connect_endpoint(client, "ipc_test.activation") != Err(IpcError::CapabilityRequired) {
+        return Err("Endpoint connected to without an IpcConnect capability");
+    }
+    grant(client, CapabilityType::IpcConnect)?;
+    let sender = connect_endpoint(client, "ipc_test.activation").map_err(|_| "Failed to connect to endpoint")?;
+    send_endpoint_message(client, sender, b"first contact").map_err(|_| "Failed to send to endpoint")?;
+    if receive_endpoint_message(client, sender).is_ok() {
+        return Err("Send-only handle received a message");
+    }
+    if pending_endpoint_messages(binder, bound) != Ok(1) {
+        return Err("Queued message not seen by the binder");
+    }
+    let rights = IpcRights { send: true, recv: true, ..IpcRights::NONE };
+    delegate_handle(binder, receiver, bound, rights, None, Some("ipc_test.activation".to_string()))
+        .map_err(|_| "Failed to hand off endpoint")?;
+    let handed = activated_endpoint(receiver, "ipc_test.activation").map_err(|_| "Handed-off endpoint not found")?;
+    if receive_endpoint_message(receiver, handed).as_deref() != Ok(&b"first contact"[..]) {
+        return Err("Receiver did not get the queued message");
+    }
+    cleanup_process_ipc(receiver);
+    send_endpoint_message(client, sender, b"after exit").map_err(|_| "Endpoint closed with its receiver")?;
+    if pending_endpoint_messages(binder, bound) != Ok(1) {
+        return Err("Message for an exited receiver not kept");
+    }
+    _print(format_args!("[IPC Test] ✓ Endpoint kept its messages across receivers, bound and connected to by capability\n"));
+
     // Test statistics (simplified)
     _print(format_args!("[IPC Test] IPC Statistics:\n"));
     _print(format_args!("  - Capability endpoint created successfully\n"));
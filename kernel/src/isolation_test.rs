@@ -0,0 +1,136 @@
+//! CPU Isolation Test
+//! On a scheduler for four single-threaded cores, isolating a CPU moves general work off it
+//! and keeps new work and the load balancer away, while a real-time thread pinned to it is
+//! placed there and is the only thing it runs
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use x86_64::VirtAddr;
+use crate::arch::topology::CpuTopology;
+use crate::process::{CpuAffinity, Priority, Process, RtClass, SmpScheduler};
+use crate::serial::_print;
+use crate::vmm;
+
+const CPUS: u32 = 4;
+const ISOLATED: u32 = 3;
+
+/// Four cores of one thread each, with no caches shared
+fn four_cores() -> SmpScheduler {
+    let mut topology = CpuTopology::from_cpuid(&|_, _| (0, 0, 0, 0));
+    for cpu in 0..CPUS {
+        topology.add_cpu(cpu, cpu);
+    }
+    SmpScheduler::with_topology(topology)
+}
+
+/// Queue a process on `scheduler`, pinned to `pin` if given, and note its address space for cleanup
+fn spawn(scheduler: &mut SmpScheduler, rt_class: RtClass, pin: Option<u32>, address_spaces: &mut Vec<u64>) -> Result<u64, &'static str> {
+    let mut process = Process::new(String::from("isolation-test"), VirtAddr::new(0x400000), Priority::Normal).map_err(|_| "Failed to create process")?;
+    address_spaces.extend(process.address_space_id);
+    process.rt_params.class = rt_class;
+    if let Some(cpu) = pin {
+        process.cpu_affinity = CpuAffinity::single_cpu(cpu);
+    }
+    Ok(scheduler.add_process(process))
+}
+
+fn isolation(address_spaces: &mut Vec<u64>) -> Result<(), &'static str> {
+    // Test 1: Isolating a CPU moves its general work off and keeps new work away
+    _print(format_args!("[Isolation Test] Test 1: Placement avoids an isolated CPU...\n"));
+    let mut scheduler = four_cores();
+    for _ in 0..8 {
+        spawn(&mut scheduler, RtClass::BestEffort, None, address_spaces)?;
+    }
+    if scheduler.run_queue_lengths() != [2, 2, 2, 2] {
+        return Err("Processes not spread over every CPU");
+    }
+    scheduler.isolate_cpu(ISOLATED, true)?;
+    for _ in 0..3 {
+        spawn(&mut scheduler, RtClass::BestEffort, None, address_spaces)?;
+    }
+    let lengths = scheduler.run_queue_lengths();
+    if lengths[ISOLATED as usize] != 0 || lengths.iter().sum::<usize>() != 11 {
+        return Err("General work left on or placed on the isolated CPU");
+    }
+    if scheduler.isolate_cpu(CPUS, true).is_ok() {
+        return Err("Isolated a CPU that does not exist");
+    }
+    _print(format_args!("[Isolation Test] ✓ CPU {} emptied, queues {:?}\n", ISOLATED, lengths));
+
+    // Test 2: The load balancer spreads work over the pool but never onto the isolated CPU
+    _print(format_args!("[Isolation Test] Test 2: Load balancing skips it...\n"));
+    let mut scheduler = four_cores();
+    for cpu in 1..CPUS {
+        scheduler.isolate_cpu(cpu, true)?;
+    }
+    if scheduler.isolate_cpu(0, true).is_ok() {
+        return Err("Isolated every CPU");
+    }
+    for _ in 0..9 {
+        spawn(&mut scheduler, RtClass::BestEffort, None, address_spaces)?;
+    }
+    if scheduler.run_queue_lengths() != [9, 0, 0, 0] {
+        return Err("General work placed outside the pool");
+    }
+    for cpu in 1..ISOLATED {
+        scheduler.isolate_cpu(cpu, false)?;
+    }
+    for _ in 0..8 {
+        scheduler.balance_load();
+    }
+    let lengths = scheduler.run_queue_lengths();
+    if lengths[ISOLATED as usize] != 0 || lengths[0] == 9 || lengths[1] == 0 || lengths[2] == 0 {
+        return Err("Load balancer ignored the pool or used the isolated CPU");
+    }
+    _print(format_args!("[Isolation Test] ✓ Balanced to {:?}\n", lengths));
+
+    // Test 3: A real-time thread pinned to the isolated CPU runs there and nowhere else
+    _print(format_args!("[Isolation Test] Test 3: Pinned real-time thread...\n"));
+    let rt = spawn(&mut scheduler, RtClass::Edf, Some(ISOLATED), address_spaces)?;
+    let lengths = scheduler.run_queue_lengths();
+    if lengths[ISOLATED as usize] != 1 {
+        return Err("Pinned thread not placed on the isolated CPU");
+    }
+    scheduler.balance_load();
+    let picks: Vec<Option<u64>> = (0..4).map(|_| scheduler.schedule_on_cpu(ISOLATED)).collect();
+    if picks.iter().any(|&pick| pick != Some(rt)) {
+        return Err("Isolated CPU ran something besides the pinned thread");
+    }
+    if (0..ISOLATED).flat_map(|cpu| (0..12).map(move |_| cpu)).any(|cpu| scheduler.schedule_on_cpu(cpu) == Some(rt)) {
+        return Err("Pinned thread ran outside the isolated CPU");
+    }
+    // Back in the pool, the least loaded CPU takes general work again
+    scheduler.isolate_cpu(ISOLATED, false)?;
+    spawn(&mut scheduler, RtClass::BestEffort, None, address_spaces)?;
+    if scheduler.run_queue_lengths()[ISOLATED as usize] != 2 {
+        return Err("CPU returned to the pool is still passed over");
+    }
+    _print(format_args!("[Isolation Test] ✓ PID {} alone on CPU {}\n", rt, ISOLATED));
+    Ok(())
+}
+
+pub fn run_isolation_tests() -> Result<(), &'static str> {
+    let mut address_spaces = Vec::new();
+    let result = isolation(&mut address_spaces);
+    for id in address_spaces {
+        let _ = vmm::destroy_address_space(id);
+    }
+    result?;
+
+    _print(format_args!("[Isolation Test] ✓ All CPU isolation tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for CPU isolation
+pub fn test_isolation() {
+    _print(format_args!("[Isolation Test] ===========================================\n"));
+    _print(format_args!("[Isolation Test]           CPU ISOLATION TESTS\n"));
+    _print(format_args!("[Isolation Test] ===========================================\n"));
+
+    match run_isolation_tests() {
+        Ok(_) => _print(format_args!("[Isolation Test] ✓ All CPU isolation tests PASSED\n")),
+        Err(e) => _print(format_args!("[Isolation Test] ✗ CPU isolation tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Isolation Test] ===========================================\n"));
+}
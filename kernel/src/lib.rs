@@ -1,55 +1,211 @@
 #![no_std]
-#![feature(abi_x86_interrupt)]
+#![cfg_attr(target_arch = "x86_64", feature(abi_x86_interrupt))]
 
 pub mod serial;
+pub mod cmdline;
+#[cfg(target_arch = "aarch64")]
+#[path = "arch_aarch64.rs"]
+pub mod arch;
+
+// Everything below only builds for x86_64; the aarch64 port currently covers the serial
+// console and command line parsing. Anything allocating stays x86_64-only, since the only
+// heap is x86_64's
+#[cfg(target_arch = "x86_64")]
+pub mod security;
+#[cfg(target_arch = "x86_64")]
 pub mod gdt;
+#[cfg(target_arch = "x86_64")]
 pub mod memory;
+#[cfg(target_arch = "x86_64")]
 pub mod heap;
+#[cfg(target_arch = "x86_64")]
+pub mod oom;
+#[cfg(target_arch = "x86_64")]
 pub mod interrupts;
+#[cfg(target_arch = "x86_64")]
 pub mod vmm;
+#[cfg(target_arch = "x86_64")]
 pub mod arch;
+#[cfg(target_arch = "x86_64")]
 pub mod apic;
+#[cfg(target_arch = "x86_64")]
 pub mod uefi;
+#[cfg(target_arch = "x86_64")]
+pub mod acpi;
+#[cfg(target_arch = "x86_64")]
 pub mod pci;
+#[cfg(target_arch = "x86_64")]
 pub mod percpu;
+#[cfg(target_arch = "x86_64")]
 pub mod time;
+#[cfg(target_arch = "x86_64")]
 pub mod process;
+#[cfg(target_arch = "x86_64")]
+pub mod futex;
+#[cfg(target_arch = "x86_64")]
 pub mod syscall;
+#[cfg(target_arch = "x86_64")]
 pub mod elf;
+#[cfg(target_arch = "x86_64")]
 pub mod filesystem;
+#[cfg(target_arch = "x86_64")]
 pub mod tarfs;
+#[cfg(target_arch = "x86_64")]
 pub use filesystem as fs;
 // pub mod drivers;
 // pub mod network;
 // pub mod ipc;
+#[cfg(target_arch = "x86_64")]
 pub mod graphics;
+#[cfg(target_arch = "x86_64")]
 pub mod vesa;
+#[cfg(target_arch = "x86_64")]
 pub mod drivers;
+#[cfg(target_arch = "x86_64")]
 pub mod network;
+#[cfg(target_arch = "x86_64")]
 pub mod ipc;
+#[cfg(target_arch = "x86_64")]
+pub mod shm_ring;
+#[cfg(target_arch = "x86_64")]
 pub mod ui;
+#[cfg(target_arch = "x86_64")]
 pub mod userspace_test;
 // mod filesystem_test; // Temporarily disabled due to serde dependency conflicts
+#[cfg(target_arch = "x86_64")]
 pub mod sound;
+#[cfg(target_arch = "x86_64")]
 pub mod input;
-pub mod security;
+#[cfg(target_arch = "x86_64")]
 pub mod capabilities;
+#[cfg(target_arch = "x86_64")]
 pub mod rae_assistant;
+#[cfg(target_arch = "x86_64")]
 pub mod raeshell;
+#[cfg(target_arch = "x86_64")]
 pub mod raepkg;
+#[cfg(target_arch = "x86_64")]
 pub mod raede;
+#[cfg(target_arch = "x86_64")]
 pub mod raekit;
+#[cfg(target_arch = "x86_64")]
 pub mod slo;
+#[cfg(target_arch = "x86_64")]
 pub mod slo_tests;
+#[cfg(target_arch = "x86_64")]
 pub mod nvme_perf_tests;
+#[cfg(target_arch = "x86_64")]
 pub mod ipc_test;
+#[cfg(target_arch = "x86_64")]
+pub mod pci_hotplug_test;
+#[cfg(target_arch = "x86_64")]
+pub mod virtio_test;
+#[cfg(target_arch = "x86_64")]
+pub mod hpet_test;
+#[cfg(target_arch = "x86_64")]
+pub mod rtc_test;
+#[cfg(target_arch = "x86_64")]
+pub mod timezone_test;
+#[cfg(target_arch = "x86_64")]
+pub mod observability_test;
+#[cfg(target_arch = "x86_64")]
+pub mod metrics;
+#[cfg(target_arch = "x86_64")]
+pub mod metrics_test;
+#[cfg(target_arch = "x86_64")]
+pub mod input_grab_test;
+#[cfg(target_arch = "x86_64")]
+pub mod gesture_test;
+#[cfg(target_arch = "x86_64")]
+pub mod ime_test;
+#[cfg(target_arch = "x86_64")]
+pub mod accessibility_test;
+#[cfg(target_arch = "x86_64")]
+pub mod recording_test;
+#[cfg(target_arch = "x86_64")]
+pub mod gles_test;
+#[cfg(target_arch = "x86_64")]
+pub mod renderer_test;
+#[cfg(target_arch = "x86_64")]
+pub mod font_test;
+#[cfg(target_arch = "x86_64")]
+pub mod shaping_test;
+#[cfg(target_arch = "x86_64")]
+pub mod terminal_test;
+#[cfg(target_arch = "x86_64")]
+pub mod editor_test;
+#[cfg(target_arch = "x86_64")]
+pub mod script_test;
+#[cfg(target_arch = "x86_64")]
+pub mod oom_test;
+#[cfg(target_arch = "x86_64")]
+pub mod slab_test;
+#[cfg(target_arch = "x86_64")]
+pub mod monitor_test;
+#[cfg(target_arch = "x86_64")]
+pub mod netconfig_test;
+#[cfg(target_arch = "x86_64")]
+pub mod dns_test;
+#[cfg(target_arch = "x86_64")]
+pub mod power;
+#[cfg(target_arch = "x86_64")]
+pub mod power_test;
+#[cfg(target_arch = "x86_64")]
+pub mod topology_test;
+#[cfg(target_arch = "x86_64")]
+pub mod tarfs_test;
+#[cfg(target_arch = "x86_64")]
+pub mod aging_test;
+#[cfg(target_arch = "x86_64")]
+pub mod isolation_test;
+#[cfg(target_arch = "x86_64")]
+pub mod symlink_test;
+#[cfg(target_arch = "x86_64")]
+pub mod watch_test;
+#[cfg(target_arch = "x86_64")]
+pub mod sched_test;
+#[cfg(target_arch = "x86_64")]
+pub mod test_threads;
+#[cfg(target_arch = "x86_64")]
+pub mod flock_test;
+#[cfg(target_arch = "x86_64")]
+pub mod tls_test;
+#[cfg(target_arch = "x86_64")]
+pub mod sleep_test;
+#[cfg(target_arch = "x86_64")]
+pub mod clone_test;
+#[cfg(target_arch = "x86_64")]
+pub mod thread_group_test;
+#[cfg(target_arch = "x86_64")]
+pub mod balance_test;
+#[cfg(target_arch = "x86_64")]
+pub mod futex_test;
+#[cfg(target_arch = "x86_64")]
+pub mod eventfd_test;
+#[cfg(target_arch = "x86_64")]
+pub mod timerfd_test;
+#[cfg(target_arch = "x86_64")]
+pub mod inotify_test;
+#[cfg(target_arch = "x86_64")]
+pub mod shm_ring_test;
+#[cfg(target_arch = "x86_64")]
+pub mod packet_socket_test;
+#[cfg(target_arch = "x86_64")]
+pub mod mmap_test;
+#[cfg(target_arch = "x86_64")]
+pub mod cow_test;
+#[cfg(target_arch = "x86_64")]
 pub mod microkernel;
+#[cfg(target_arch = "x86_64")]
 pub mod secure_boot;
+#[cfg(target_arch = "x86_64")]
 pub mod observability;
 
 extern crate alloc;
+#[cfg(target_arch = "x86_64")]
 use alloc::string::ToString;
 
+#[cfg(target_arch = "x86_64")]
 pub fn init(boot_info: &'static bootloader::BootInfo) {
     serial::init();
     gdt::init();
@@ -99,11 +255,24 @@ pub fn init(boot_info: &'static bootloader::BootInfo) {
         time::init_with_apic(); // Initialize timer with APIC and TSC deadline support
     }
     
+    // Route the CMOS RTC interrupt for periodic/alarm events
+    if let Err(e) = drivers::rtc::init() {
+        crate::serial::_print(format_args!("[RTC] Failed to initialize: {}\n", e));
+    }
+    
     // Initialize PCI subsystem with MSI-X support
     if let Err(e) = pci::init() {
         crate::serial::_print(format_args!("[PCI] Failed to initialize: {}\n", e));
     }
     
+    // Register bus drivers and bind them to the devices found at boot
+    drivers::virtio::register_drivers();
+    drivers::virtio_gpu::register_driver();
+    pci::rescan();
+    
+    // Copy the frames interfaces receive to packet sockets
+    network::packet::init();
+    
     vmm::init();
     
     // Test VMM functionality (address space isolation and memory protection)
@@ -111,13 +280,35 @@ pub fn init(boot_info: &'static bootloader::BootInfo) {
         crate::serial::_print(format_args!("[VMM] Tests failed: {}\n", e));
     }
     
+    // Flight recorder, and the page user-space probes check before tracing
+    if let Err(e) = observability::init_observability() {
+        crate::serial::_print(format_args!("[Observability] Failed to initialize: {:?}\n", e));
+    }
+    if let Err(e) = observability::user_probes::init() {
+        crate::serial::_print(format_args!("[Observability] User probes unavailable: {:?}\n", e));
+    }
+
     process::init();
     
     // Initialize threading system
     let idle_pid = process::init_idle_thread().expect("Failed to initialize idle thread");
     let demo_pid = process::spawn_demo_thread().expect("Failed to spawn demo thread");
     crate::serial::_print(format_args!("Initialized idle thread (PID: {}) and demo thread (PID: {})\n", idle_pid, demo_pid));
-    
+
+    // Persist the flight recorder when `flightlog=` names a disk region
+    match observability::start_flight_export() {
+        Ok(Some(pid)) => crate::serial::_print(format_args!("[Observability] Flight recorder export running (PID: {})\n", pid)),
+        Ok(None) => {}
+        Err(e) => crate::serial::_print(format_args!("[Observability] Flight recorder export not started: {:?}\n", e)),
+    }
+
+    // Serve Prometheus metrics when `metrics=` names a port
+    match metrics::start_metrics_server() {
+        Ok(Some(pid)) => crate::serial::_print(format_args!("[Metrics] Metrics server running (PID: {})\n", pid)),
+        Ok(None) => {}
+        Err(e) => crate::serial::_print(format_args!("[Metrics] Metrics server not started: {}\n", e)),
+    }
+
     // Demonstrate process spawning capabilities
     demonstrate_process_spawning();
     // Scheduler preemption demo marker
@@ -128,8 +319,19 @@ pub fn init(boot_info: &'static bootloader::BootInfo) {
     
     syscall::init();
     
-    // Initialize graphics with UEFI GOP
-    let graphics_initialized = if let Some(framebuffer_info) = uefi::get_framebuffer_info() {
+    // Prefer a virtio-gpu scanout, then UEFI GOP
+    let graphics_initialized = if drivers::virtio_gpu::framebuffer().is_some() {
+        match drivers::virtio_gpu::attach_compositor() {
+            Ok(_) => {
+                crate::serial::_print(format_args!("[Graphics] virtio-gpu framebuffer compositor initialized\n"));
+                true
+            }
+            Err(e) => {
+                crate::serial::_print(format_args!("[Graphics] Failed to initialize virtio-gpu compositor: {}\n", e));
+                false
+            }
+        }
+    } else if let Some(framebuffer_info) = uefi::get_framebuffer_info() {
         crate::serial::_print(format_args!("[UEFI] Using GOP framebuffer {}x{} at {:?}\n", 
             framebuffer_info.width, framebuffer_info.height, framebuffer_info.base_addr));
         
@@ -209,6 +411,9 @@ pub fn init(boot_info: &'static bootloader::BootInfo) {
         crate::serial::_print(format_args!("[FS] Failed to initialize filesystem: {}\n", e));
     }
     crate::serial::_print(format_args!("[Filesystem] VFS initialized with root filesystem\n"));
+    if arch::topology::write_cpuinfo().is_err() {
+        crate::serial::_print(format_args!("[Topology] Failed to write /proc/cpuinfo\n"));
+    }
     
     // Test filesystem functionality
     // filesystem_test::run_all_filesystem_tests(); // Temporarily disabled due to serde dependency conflicts
@@ -247,6 +452,7 @@ pub fn init(boot_info: &'static bootloader::BootInfo) {
 }
 
 /// Demonstrate process spawning capabilities
+#[cfg(target_arch = "x86_64")]
 fn demonstrate_process_spawning() {
     crate::serial::_print(format_args!("\n[Process Demo] Starting process spawning demonstration...\n"));
     
@@ -331,6 +537,7 @@ fn demonstrate_process_spawning() {
 }
 
 /// Worker thread function for demonstration
+#[cfg(target_arch = "x86_64")]
 extern "C" fn worker_thread_main() -> ! {
     let mut counter = 0u64;
     loop {
@@ -347,6 +554,7 @@ extern "C" fn worker_thread_main() -> ! {
 }
 
 /// Real-time worker thread function for demonstration
+#[cfg(target_arch = "x86_64")]
 extern "C" fn rt_worker_thread_main() -> ! {
     let mut rt_counter = 0u64;
     loop {
@@ -365,6 +573,7 @@ extern "C" fn rt_worker_thread_main() -> ! {
 }
 
 /// Demonstrates shell functionality
+#[cfg(target_arch = "x86_64")]
 fn demonstrate_shell_functionality() {
     crate::serial::_print(format_args!("\n=== RaeShell Demonstration ===\n"));
     
@@ -480,6 +689,7 @@ fn demonstrate_shell_functionality() {
 
 /// Demonstrates graphics rendering capabilities
 #[allow(dead_code)]
+#[cfg(target_arch = "x86_64")]
 fn demonstrate_graphics_rendering() {
     crate::serial::_print(format_args!("\n=== Graphics Rendering Demonstration ===\n"));
     
@@ -533,8 +743,10 @@ fn demonstrate_graphics_rendering() {
     // Test text rendering
     crate::serial::_print(format_args!("\nTesting text rendering...\n"));
     for &window_id in &created_windows {
-        // Note: draw_text function may not be available in current API
-        crate::serial::_print(format_args!("Text rendering would be drawn in window {} (API pending)\n", window_id));
+        match graphics::draw_text(window_id, 10, 10, "RaeenOS", graphics::Color::new(255, 255, 255, 255)) {
+            Ok(_) => crate::serial::_print(format_args!("Drew text in window {}\n", window_id)),
+            Err(e) => crate::serial::_print(format_args!("Failed to draw text in window {}: {}\n", window_id, e)),
+        }
     }
     
     // Test window focus and management
@@ -602,6 +814,7 @@ fn demonstrate_graphics_rendering() {
 }
 
 /// Launch the desktop environment and start the main system loop
+#[cfg(target_arch = "x86_64")]
 pub fn launch_desktop_environment() -> ! {
     crate::serial::_print(format_args!("[Desktop] Starting RaeenOS Desktop Environment...\n"));
     
@@ -616,6 +829,144 @@ pub fn launch_desktop_environment() -> ! {
         // Run IPC message passing tests
         crate::serial::_print(format_args!("[Desktop] Running IPC tests...\n"));
         crate::ipc_test::test_ipc_functionality();
+        
+        // Run PCI hotplug binding tests
+        crate::pci_hotplug_test::test_pci_hotplug();
+        
+        // Run VirtIO block/net driver tests
+        crate::virtio_test::test_virtio_drivers();
+        
+        // Run HPET counter/comparator tests
+        crate::hpet_test::test_hpet();
+        
+        // Run CMOS RTC decoding tests
+        crate::rtc_test::test_rtc();
+        
+        // Run timezone conversion tests
+        crate::timezone_test::test_timezones();
+        
+        // Run flight recorder compression tests
+        crate::observability_test::test_observability();
+        
+        // Run Prometheus metrics endpoint tests
+        crate::metrics_test::test_metrics();
+        
+        // Run input grab routing tests
+        crate::input_grab_test::test_input_grab();
+        
+        // Run multi-touch gesture recognition tests
+        crate::gesture_test::test_gestures();
+        
+        // Run input method composition tests
+        crate::ime_test::test_ime();
+        
+        // Run accessibility tree tests
+        crate::accessibility_test::test_accessibility();
+        
+        // Run screen recording tests
+        crate::recording_test::test_recording();
+        
+        // Run software GLES renderer tests
+        crate::gles_test::test_gles();
+        
+        // Run compositor renderer tests
+        crate::renderer_test::test_renderer();
+        
+        // Run TrueType font tests
+        crate::font_test::test_font();
+        
+        // Run text shaping tests
+        crate::shaping_test::test_shaping();
+        
+        // Run terminal widget tests
+        crate::terminal_test::test_terminal();
+        
+        // Run line editor tests
+        crate::editor_test::test_editor();
+
+        // Run script interpreter tests
+        crate::script_test::test_script();
+
+        // Run OOM killer stress tests
+        crate::oom_test::test_oom();
+
+        // Run slab allocator tests
+        crate::slab_test::test_slab();
+
+        // Run resource monitor tests
+        crate::monitor_test::test_monitor();
+
+        // Run network configuration tests
+        crate::netconfig_test::test_netconfig();
+
+        // Run DNS lookup tests
+        crate::dns_test::test_dns();
+
+        // Run battery and power status tests
+        crate::power_test::test_power();
+
+        // Run CPU topology tests
+        crate::topology_test::test_topology();
+
+        // Run TAR filesystem overlay tests
+        crate::tarfs_test::test_tarfs();
+
+        // Run priority aging tests
+        crate::aging_test::test_aging();
+
+        // Run CPU isolation tests
+        crate::isolation_test::test_isolation();
+
+        // Run symbolic link tests
+        crate::symlink_test::test_symlink();
+
+        // Run filesystem watch tests
+        crate::watch_test::test_watch();
+
+        // Run scheduler statistics tests
+        crate::sched_test::test_sched();
+
+        // Run advisory file lock tests
+        crate::flock_test::test_flock();
+
+        // Run thread-local storage tests
+        crate::tls_test::test_tls();
+
+        // Run nanosleep tests
+        crate::sleep_test::test_sleep();
+
+        // Run clone tests
+        crate::clone_test::test_clone();
+
+        // Run thread group tests
+        crate::thread_group_test::test_thread_group();
+
+        // Run load balancing tests
+        crate::balance_test::test_balance();
+
+        // Run futex tests
+        crate::futex_test::test_futex();
+
+        // Run eventfd and signalfd tests
+        crate::eventfd_test::test_eventfd();
+
+        // Run timerfd and epoll tests
+        crate::timerfd_test::test_timerfd();
+
+        // Run inotify tests
+        crate::inotify_test::test_inotify();
+
+        // Run shared-memory ring tests
+        crate::shm_ring_test::test_shm_ring();
+
+        // Run packet socket and capture tests
+        crate::packet_socket_test::test_packet_sockets();
+
+        // Run mmap placement tests
+        crate::mmap_test::test_mmap();
+
+        // Run copy-on-write tests for kernel writes to user memory
+        crate::cow_test::test_cow();
     }
     #[cfg(not(feature = "test-mode"))]
     crate::serial::_print(format_args!("[Desktop] IPC tests disabled\n"));
@@ -636,6 +987,7 @@ pub fn launch_desktop_environment() -> ! {
 
 /// Show boot animation during startup
 #[allow(dead_code)]
+#[cfg(target_arch = "x86_64")]
 fn show_boot_animation() {
     crate::serial::_print(format_args!("[Boot] Displaying boot animation...\n"));
     
@@ -660,21 +1012,21 @@ fn show_boot_animation() {
 }
 
 /// Initialize input system for keyboard and mouse
+#[cfg(target_arch = "x86_64")]
 fn init_input_system() -> Result<(), &'static str> {
     crate::serial::_print(format_args!("[Input] Initializing input system...\n"));
     
     // Initialize keyboard driver
     drivers::keyboard::init();
     
-    // Initialize PS/2 mouse driver
-    let mouse = drivers::Ps2MouseDriver::new();
-    match drivers::register_device(alloc::boxed::Box::new(mouse)) {
-        Ok(mouse_id) => {
-            crate::serial::_print(format_args!("[Input] Registered PS/2 mouse (ID: {})
-", mouse_id));
+    // Attach the PS/2 mouse through the driver model
+    drivers::register_driver(alloc::boxed::Box::new(drivers::Ps2MouseDriver::new()));
+    match drivers::device_added(&drivers::BusDevice::Platform("i8042-aux")) {
+        Ok(Some(name)) => {
+            crate::serial::_print(format_args!("[Input] Attached {} driver\n", name));
         }
-        Err(_) => {
-            return Err("Failed to register PS/2 mouse");
+        Ok(None) | Err(_) => {
+            return Err("Failed to attach PS/2 mouse");
         }
     }
     
@@ -682,6 +1034,7 @@ fn init_input_system() -> Result<(), &'static str> {
 }
 
 /// Launch the RaeShell in a new process
+#[cfg(target_arch = "x86_64")]
 fn launch_shell() -> Result<(), &'static str> {
     crate::serial::_print(format_args!("[Desktop] Launching RaeShell...\n"));
     
@@ -689,15 +1042,15 @@ fn launch_shell() -> Result<(), &'static str> {
     let session_id = raeshell::create_shell_session().map_err(|_| "Failed to create shell session")?;
     crate::serial::_print(format_args!("[Desktop] Created shell session (ID: {})\n", session_id));
     
-    // Create shell window
-    if let Ok(window_id) = graphics::create_shell_window() {
-        crate::serial::_print(format_args!("[Desktop] Created shell window (ID: {})\n", window_id));
-    }
+    // Open a terminal window on the session
+    let window_id = raekit::terminal::open_shell_terminal(session_id)?;
+    crate::serial::_print(format_args!("[Desktop] Created shell window (ID: {})\n", window_id));
     
     Ok(())
 }
 
 /// Main desktop event loop - handles input and window management
+#[cfg(target_arch = "x86_64")]
 fn desktop_main_loop() -> ! {
     crate::serial::_print(format_args!("[Desktop] Starting main event loop...\n"));
     
@@ -705,23 +1058,43 @@ fn desktop_main_loop() -> ! {
         // Process input events
         process_input_events();
         
+        // Bind/unbind drivers for PCI devices added or removed at runtime
+        pci::process_hotplug_events();
+        
+        // Take in the frames network interfaces received
+        drivers::virtio::poll_net();
+        
+        // Move work between CPUs when the timer tick marked a balancing round due
+        process::run_pending_balance();
+        
+        // Refresh /proc/loadavg after the scheduler samples the run queues
+        let _ = process::publish_load_average();
+        
+        // Refresh /proc/sched with every CPU's queues
+        let _ = process::publish_sched_stats();
+        
+        // Follow display resizes reported by the virtio-gpu host
+        drivers::virtio_gpu::process_display_events();
+        
+        // Feed input to shell terminals and redraw them
+        raekit::terminal::process_terminal_events();
+        
+        // Read the batteries, warning when low and suspending when critical
+        power::poll();
+        
         // Update window manager
         graphics::update_window_manager();
         
         // Render frame if needed
         graphics::render_frame();
         
-        // Yield to other processes
-        process::yield_current();
-        
-        // Small delay to prevent busy waiting
-        for _ in 0..1000 {
-            core::hint::spin_loop();
-        }
+        // Sleep a millisecond before the next pass, leaving the CPU to other processes
+        process::nanosleep(1_000_000);
     }
 }
 
 /// Process keyboard and mouse input events with enhanced routing
+#[cfg(target_arch = "x86_64")]
 fn process_input_events() {
     // Process keyboard events with improved handling
     while let Some(key) = drivers::keyboard::get_key() {
@@ -763,6 +1136,7 @@ fn process_input_events() {
 }
 
 /// Enhanced keyboard event routing with focus management
+#[cfg(target_arch = "x86_64")]
 fn route_keyboard_event(key_code: u32, pressed: bool) {
     // Handle global hotkeys first
     if pressed {
@@ -794,6 +1168,7 @@ fn route_keyboard_event(key_code: u32, pressed: bool) {
 }
 
 /// Enhanced mouse movement event routing
+#[cfg(target_arch = "x86_64")]
 fn route_mouse_move_event(x: i32, y: i32, last_x: i32, last_y: i32) {
     // Calculate movement delta
     let delta_x = x - last_x;
@@ -810,6 +1185,7 @@ fn route_mouse_move_event(x: i32, y: i32, last_x: i32, last_y: i32) {
 }
 
 /// Enhanced mouse button event routing
+#[cfg(target_arch = "x86_64")]
 fn route_mouse_button_event(x: i32, y: i32, button: u8, pressed: bool) {
     if pressed {
         // Handle window focus on click
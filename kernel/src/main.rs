@@ -1,19 +1,29 @@
 #![no_std]
 #![no_main]
 
+#[cfg(target_arch = "x86_64")]
 use core::fmt::Write;
 use core::panic::PanicInfo;
+#[cfg(target_arch = "x86_64")]
 use spin::Mutex;
+#[cfg(target_arch = "x86_64")]
 use lazy_static::lazy_static;
+#[cfg(target_arch = "x86_64")]
 use x86_64::instructions::hlt as cpu_halt;
 use kernel as k;
+#[cfg(target_arch = "x86_64")]
 use bootloader::{entry_point, BootInfo};
 extern crate alloc;
 
+#[cfg(target_arch = "aarch64")]
+mod boot_aarch64;
+
+#[cfg(target_arch = "x86_64")]
 lazy_static! {
     static ref VGA_WRITER: Mutex<VgaWriter> = Mutex::new(VgaWriter::new());
 }
 
+#[cfg(target_arch = "x86_64")]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     let mut writer = VGA_WRITER.lock();
@@ -21,8 +31,17 @@ fn panic(info: &PanicInfo) -> ! {
     loop { cpu_halt(); }
 }
 
+#[cfg(target_arch = "aarch64")]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    k::serial::_print(format_args!("PANIC: {}\n", info));
+    k::arch::halt();
+}
+
+#[cfg(target_arch = "x86_64")]
 entry_point!(kernel_main);
 
+#[cfg(target_arch = "x86_64")]
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
     k::init(boot_info);
     {
@@ -36,17 +55,22 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     k::launch_desktop_environment();
 }
 
+#[cfg(target_arch = "x86_64")]
 const BUFFER_HEIGHT: usize = 25;
+#[cfg(target_arch = "x86_64")]
 const BUFFER_WIDTH: usize = 80;
 
+#[cfg(target_arch = "x86_64")]
 struct VgaWriter {
     buffer_ptr: *mut VgaChar,
     column_position: usize,
     color: u8,
 }
 
+#[cfg(target_arch = "x86_64")]
 unsafe impl Send for VgaWriter {}
 
+#[cfg(target_arch = "x86_64")]
 #[repr(C)]
 #[derive(Copy, Clone)]
 struct VgaChar {
@@ -54,6 +78,7 @@ struct VgaChar {
     color_code: u8,
 }
 
+#[cfg(target_arch = "x86_64")]
 impl VgaWriter {
     fn new() -> Self {
         Self {
@@ -120,6 +145,7 @@ impl VgaWriter {
     }
 }
 
+#[cfg(target_arch = "x86_64")]
 impl Write for VgaWriter {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
         for byte in s.bytes() {
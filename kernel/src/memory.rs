@@ -3,7 +3,7 @@ use x86_64::structures::paging::{OffsetPageTable, PageTable, PhysFrame, FrameAll
 use spin::Mutex;
 use core::sync::atomic::{AtomicU64, Ordering};
 use alloc::vec::Vec;
-use alloc::collections::BTreeSet;
+use alloc::collections::{BTreeMap, BTreeSet};
 
 /// Memory region types for frame allocation
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -47,6 +47,9 @@ pub struct BootInfoFrameAllocator {
     memory_map: &'static bootloader::bootinfo::MemoryMap,
     allocated_frames: BTreeSet<u64>,
     free_frames: Vec<PhysFrame>,
+    /// Reference counts of frames mapped by more than one owner, such as pages shared
+    /// copy-on-write after a fork. Frames missing here have a single owner
+    shared_frames: BTreeMap<u64, usize>,
 }
 
 impl BootInfoFrameAllocator {
@@ -61,6 +64,7 @@ impl BootInfoFrameAllocator {
             memory_map,
             allocated_frames: BTreeSet::new(),
             free_frames: Vec::new(),
+            shared_frames: BTreeMap::new(),
         };
         
         // Pre-populate free frames list
@@ -90,9 +94,16 @@ unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
 }
 
 impl BootInfoFrameAllocator {
-    /// Deallocate a frame and return it to the free list
+    /// Drop one reference to a frame, returning it to the free list with the last
     pub fn deallocate_frame(&mut self, frame: PhysFrame) {
         let frame_addr = frame.start_address().as_u64();
+        if let Some(count) = self.shared_frames.get_mut(&frame_addr) {
+            *count -= 1;
+            if *count == 1 {
+                self.shared_frames.remove(&frame_addr);
+            }
+            return;
+        }
         if self.allocated_frames.remove(&frame_addr) {
             self.free_frames.push(frame);
             update_allocated_frames(-1);
@@ -113,6 +124,49 @@ impl BootInfoFrameAllocator {
     pub fn is_allocated(&self, frame: PhysFrame) -> bool {
         self.allocated_frames.contains(&frame.start_address().as_u64())
     }
+    
+    /// Add a reference to an allocated frame, so it outlives one deallocation per reference
+    pub fn share_frame(&mut self, frame: PhysFrame) {
+        let frame_addr = frame.start_address().as_u64();
+        if self.allocated_frames.contains(&frame_addr) {
+            *self.shared_frames.entry(frame_addr).or_insert(1) += 1;
+        }
+    }
+    
+    /// Number of references to a frame, 0 once it is free
+    pub fn ref_count(&self, frame: PhysFrame) -> usize {
+        let frame_addr = frame.start_address().as_u64();
+        match self.shared_frames.get(&frame_addr) {
+            Some(&count) => count,
+            None => usize::from(self.allocated_frames.contains(&frame_addr)),
+        }
+    }
+    
+    /// Allocate `count` physically contiguous frames, returning the first.
+    /// Searches ascending runs in the free list, which is built in address order.
+    pub fn allocate_contiguous(&mut self, count: usize) -> Option<PhysFrame> {
+        if count == 0 {
+            return None;
+        }
+        
+        let mut run_start = 0;
+        for i in 0..self.free_frames.len() {
+            let addr = self.free_frames[i].start_address().as_u64();
+            if i > run_start && addr != self.free_frames[i - 1].start_address().as_u64() + 4096 {
+                run_start = i;
+            }
+            if i + 1 - run_start == count {
+                let first = self.free_frames[run_start];
+                for frame in self.free_frames.drain(run_start..=i) {
+                    self.allocated_frames.insert(frame.start_address().as_u64());
+                }
+                update_allocated_frames(count as i64);
+                return Some(first);
+            }
+        }
+        
+        None
+    }
 }
 
 // ---------- Global accessors for mapper/frame allocator ----------
@@ -164,10 +218,30 @@ where
     f(&mut mapper)
 }
 
+/// Run `f` with a mapper over the page tables rooted at `pml4_frame`, active or not
+pub fn with_page_table_mapper<F, R>(pml4_frame: PhysFrame, f: F) -> R
+where
+    F: FnOnce(&mut OffsetPageTable<'static>) -> R,
+{
+    let offset = VirtAddr::new(active_physical_offset());
+    // SAFETY: Safe because:
+    // 1. pml4_frame is the PML4 of an address space, reachable through the physical memory offset
+    // 2. Callers hold the VMM lock, so no other mapper modifies these tables concurrently
+    let pml4 = unsafe { &mut *(offset + pml4_frame.start_address().as_u64()).as_mut_ptr::<PageTable>() };
+    // SAFETY: The offset maps all physical memory, as OffsetPageTable requires
+    let mut mapper = unsafe { OffsetPageTable::new(pml4, offset) };
+    f(&mut mapper)
+}
+
 pub fn allocate_frame() -> Option<PhysFrame> {
     FRAME_ALLOC.lock().as_mut().and_then(|a| a.allocate_frame())
 }
 
+/// Allocate physically contiguous frames for DMA buffers
+pub fn allocate_contiguous_frames(count: usize) -> Option<PhysFrame> {
+    FRAME_ALLOC.lock().as_mut().and_then(|a| a.allocate_contiguous(count))
+}
+
 /// Allocate a frame with guard pages on both sides
 pub fn allocate_frame_with_guards() -> Option<(PhysFrame, PhysFrame, PhysFrame)> {
     let mut frame_alloc = FRAME_ALLOC.lock();
@@ -485,13 +559,25 @@ where
     f(allocator)
 }
 
-/// Deallocate a frame globally
+/// Drop a reference to a frame globally, freeing it with the last
 pub fn deallocate_frame(frame: PhysFrame) {
     with_frame_allocator(|allocator| {
         allocator.deallocate_frame(frame);
     });
 }
 
+/// Add a reference to a frame mapped by a further owner
+pub fn share_frame(frame: PhysFrame) {
+    if let Some(allocator) = FRAME_ALLOC.lock().as_mut() {
+        allocator.share_frame(frame);
+    }
+}
+
+/// Number of references to a frame, 0 once it is free
+pub fn frame_ref_count(frame: PhysFrame) -> usize {
+    FRAME_ALLOC.lock().as_ref().map_or(0, |allocator| allocator.ref_count(frame))
+}
+
 /// Get memory statistics
 pub fn get_memory_stats() -> (usize, usize, u64) {
     with_frame_allocator(|allocator| {
@@ -513,25 +599,15 @@ use lazy_static::lazy_static;
 
 // Memory statistics tracking
 struct MemoryStats {
-    total_memory: u64,
-    allocated_frames: u64,
     heap_break: VirtAddr,
 }
 
 lazy_static! {
     static ref MEMORY_STATS: Mutex<MemoryStats> = Mutex::new(MemoryStats {
-        total_memory: 0,
-        allocated_frames: 0,
         heap_break: VirtAddr::new(0x400000000), // Start heap at 16GB virtual address
     });
 }
 
-// Initialize memory statistics from bootloader info
-pub fn init_memory_stats(total_memory: u64) {
-    let mut stats = MEMORY_STATS.lock();
-    stats.total_memory = total_memory;
-}
-
 // Memory management functions for syscalls
 pub fn set_program_break(addr: VirtAddr) -> Result<VirtAddr, ()> {
     let mut stats = MEMORY_STATS.lock();
@@ -638,7 +714,6 @@ pub fn set_program_break(addr: VirtAddr) -> Result<VirtAddr, ()> {
         }).map_err(|_| ())?;
         
         stats.heap_break = addr;
-        stats.allocated_frames += pages_to_map;
         Ok(addr)
     } else {
         // No change
@@ -646,33 +721,43 @@ pub fn set_program_break(addr: VirtAddr) -> Result<VirtAddr, ()> {
     }
 }
 
+/// Free and allocated frames, none before the frame allocator is set up
+fn frame_counts() -> (u64, u64) {
+    FRAME_ALLOC
+        .lock()
+        .as_ref()
+        .map_or((0, 0), |allocator| (allocator.free_count() as u64, allocator.allocated_count() as u64))
+}
+
+/// Bytes of physical memory the frame allocator manages, free or allocated
 pub fn get_total_memory() -> u64 {
-    let stats = MEMORY_STATS.lock();
-    if stats.total_memory == 0 {
-        // Fallback: try to detect memory from bootloader or use conservative estimate
-        256 * 1024 * 1024 // 256MB fallback
-    } else {
-        stats.total_memory
-    }
+    let (free, allocated) = frame_counts();
+    (free + allocated) * 4096
 }
 
+/// Bytes of physical memory in free frames
 pub fn get_free_memory() -> u64 {
-    let stats = MEMORY_STATS.lock();
-    let total = if stats.total_memory == 0 {
-        256 * 1024 * 1024 // 256MB fallback
-    } else {
-        stats.total_memory
-    };
-    
-    let used = stats.allocated_frames * 4096;
-    
-    // Reserve some memory for kernel operations
-    let kernel_reserved = 32 * 1024 * 1024; // 32MB for kernel
-    
-    if total > used + kernel_reserved {
-        total - used - kernel_reserved
-    } else {
-        0
+    frame_counts().0 * 4096
+}
+
+/// Use of physical memory, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub total: u64,
+    pub used: u64,
+    pub free: u64,
+    /// Part of `used` holding pages read from files for file mappings
+    pub cached: u64,
+}
+
+/// How physical memory is used now
+pub fn memory_usage() -> MemoryUsage {
+    let (free, allocated) = frame_counts();
+    MemoryUsage {
+        total: (free + allocated) * 4096,
+        used: allocated * 4096,
+        free: free * 4096,
+        cached: crate::vmm::file_backed_pages() as u64 * 4096,
     }
 }
 
@@ -0,0 +1,356 @@
+//! Live metrics for RaeenOS in the Prometheus text exposition format
+//!
+//! A `MetricsSnapshot` gathers the kernel's running statistics: requests delivered to each
+//! microkernel service, scheduler run-queue lengths, memory and frame usage, compositor frame
+//! rate, socket traffic and syscall latencies from the flight recorder. `render` writes a
+//! snapshot as Prometheus text.
+//!
+//! The metrics server answers `GET /metrics` over the network on the port named by
+//! `metrics=<port>` on the kernel command line. Scrapers authenticate with a handle to a
+//! `SystemMetrics` capability, sent as `Authorization: Bearer <handle>`; the handle is
+//! resolved in the handle table of the process on the other end of the connection.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Display, Write};
+use core::sync::atomic::{AtomicU16, Ordering};
+use spin::Mutex;
+use crate::capabilities::{CapabilityType, Handle};
+use crate::microkernel::ServiceType;
+use crate::network::{NetworkError, NetworkResult, NetworkStats};
+use crate::observability::query::{self, EventFilter, LatencySummary};
+use crate::process::ProcessId;
+
+/// Path the metrics are served at
+pub const METRICS_PATH: &str = "/metrics";
+
+/// Content type of the Prometheus text format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Permission a `SystemMetrics` capability needs to read the metrics
+pub const METRICS_READ: u64 = 0x01;
+
+/// Longest request the server reads before answering
+pub const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Connections waiting to be accepted by the server
+const LISTEN_BACKLOG: u32 = 16;
+
+/// Pause between polls of an idle listener or a slow client
+const POLL_INTERVAL_MS: u64 = 10;
+
+/// How long a client has to finish sending its request
+const REQUEST_TIMEOUT_MS: u64 = 1_000;
+
+/// Port the server thread listens on, set before it starts
+static METRICS_PORT: AtomicU16 = AtomicU16::new(0);
+
+/// Compositor frame count and time of the previous snapshot, for the frame rate
+static LAST_FRAME_SAMPLE: Mutex<Option<(u64, u64)>> = Mutex::new(None);
+
+/// Statistics gathered at one point in time
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsSnapshot {
+    /// Requests delivered to each registered service
+    pub service_requests: Vec<(ServiceType, u64)>,
+    /// Processes waiting to run, indexed by CPU ID
+    pub run_queue_lengths: Vec<usize>,
+    pub process_count: u64,
+    pub memory_total_bytes: u64,
+    pub memory_free_bytes: u64,
+    pub frames_allocated: u64,
+    pub frames_free: u64,
+    /// Frames presented by the compositor since boot
+    pub compositor_frames: u64,
+    /// Frames per second presented since the previous snapshot
+    pub compositor_fps: f64,
+    pub network: NetworkStats,
+    /// Syscall latencies over the flight recorder's history
+    pub syscall_latency: Option<LatencySummary>,
+    /// Sum of those latencies, in nanoseconds
+    pub syscall_latency_sum_ns: u64,
+}
+
+impl MetricsSnapshot {
+    /// Gather the current statistics
+    pub fn collect() -> Self {
+        let service_requests = crate::microkernel::with_service_registry(|registry| registry.request_counts())
+            .unwrap_or_default();
+        let (frames_free, _, frames_allocated) = crate::memory::get_memory_stats();
+        // The compositor is absent until the desktop starts
+        let compositor_frames = crate::graphics::get_frame_stats().map_or(0, |(frames, _)| frames);
+        let now_ns = crate::time::get_timestamp_ns();
+        let compositor_fps = {
+            let mut last = LAST_FRAME_SAMPLE.lock();
+            let fps = frame_rate(*last, compositor_frames, now_ns);
+            *last = Some((compositor_frames, now_ns));
+            fps
+        };
+
+        let latencies = query::syscall_latencies(&crate::observability::query_entries(&EventFilter::default()));
+        let syscall_latency_sum_ns = latencies.iter().sum();
+
+        Self {
+            service_requests,
+            run_queue_lengths: crate::process::get_run_queue_lengths(),
+            process_count: crate::process::get_process_count(),
+            memory_total_bytes: crate::memory::get_total_memory(),
+            memory_free_bytes: crate::memory::get_free_memory(),
+            frames_allocated,
+            frames_free: frames_free as u64,
+            compositor_frames,
+            compositor_fps,
+            network: crate::network::get_network_stats(),
+            syscall_latency: LatencySummary::from_samples(latencies),
+            syscall_latency_sum_ns,
+        }
+    }
+}
+
+/// Frames per second between a previous `(frames, timestamp_ns)` sample and now; 0 without
+/// a previous sample or when no time has passed
+pub fn frame_rate(previous: Option<(u64, u64)>, frames: u64, now_ns: u64) -> f64 {
+    match previous {
+        Some((last_frames, last_ns)) if now_ns > last_ns => {
+            frames.saturating_sub(last_frames) as f64 * 1_000_000_000.0 / (now_ns - last_ns) as f64
+        }
+        _ => 0.0,
+    }
+}
+
+/// Builds Prometheus text one metric family at a time
+struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels.iter().map(|(key, value)| format!("{}=\"{}\"", key, value)).collect();
+            let _ = write!(self.text, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.text, " {}", value);
+    }
+}
+
+/// Nanoseconds as decimal seconds, without going through floating point
+fn seconds(ns: u64) -> String {
+    format!("{}.{:09}", ns / 1_000_000_000, ns % 1_000_000_000)
+}
+
+/// A snapshot in the Prometheus text exposition format
+pub fn render(snapshot: &MetricsSnapshot) -> String {
+    let mut out = Exposition { text: String::new() };
+
+    out.family("raeen_service_requests_total", "counter", "Requests delivered to each microkernel service.");
+    for (service, count) in &snapshot.service_requests {
+        out.sample("raeen_service_requests_total", &[("service", &format!("{}", service))], count);
+    }
+
+    out.family("raeen_scheduler_run_queue_length", "gauge", "Processes waiting to run on each CPU.");
+    for (cpu, length) in snapshot.run_queue_lengths.iter().enumerate() {
+        out.sample("raeen_scheduler_run_queue_length", &[("cpu", &format!("{}", cpu))], length);
+    }
+
+    out.family("raeen_processes", "gauge", "Processes known to the scheduler.");
+    out.sample("raeen_processes", &[], snapshot.process_count);
+
+    out.family("raeen_memory_total_bytes", "gauge", "Physical memory managed by the kernel.");
+    out.sample("raeen_memory_total_bytes", &[], snapshot.memory_total_bytes);
+    out.family("raeen_memory_free_bytes", "gauge", "Physical memory not in use.");
+    out.sample("raeen_memory_free_bytes", &[], snapshot.memory_free_bytes);
+
+    out.family("raeen_memory_frames", "gauge", "Physical frames by allocation state.");
+    out.sample("raeen_memory_frames", &[("state", "allocated")], snapshot.frames_allocated);
+    out.sample("raeen_memory_frames", &[("state", "free")], snapshot.frames_free);
+
+    out.family("raeen_compositor_frames_total", "counter", "Frames presented by the compositor.");
+    out.sample("raeen_compositor_frames_total", &[], snapshot.compositor_frames);
+    out.family("raeen_compositor_fps", "gauge", "Compositor frames per second since the previous scrape.");
+    out.sample("raeen_compositor_fps", &[], format!("{:.2}", snapshot.compositor_fps));
+
+    out.family("raeen_network_bytes_total", "counter", "Bytes moved through sockets.");
+    out.sample("raeen_network_bytes_total", &[("direction", "sent")], snapshot.network.bytes_sent);
+    out.sample("raeen_network_bytes_total", &[("direction", "received")], snapshot.network.bytes_received);
+
+    out.family("raeen_syscall_latency_seconds", "summary", "Syscall entry to exit time over the flight recorder's history.");
+    if let Some(summary) = &snapshot.syscall_latency {
+        for (quantile, ns) in [("0.5", summary.p50), ("0.9", summary.p90), ("0.99", summary.p99)] {
+            out.sample("raeen_syscall_latency_seconds", &[("quantile", quantile)], seconds(ns));
+        }
+    }
+    out.sample("raeen_syscall_latency_seconds_sum", &[], seconds(snapshot.syscall_latency_sum_ns));
+    out.sample("raeen_syscall_latency_seconds_count", &[], snapshot.syscall_latency.map_or(0, |summary| summary.count));
+
+    out.text
+}
+
+/// An HTTP response from the metrics server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl HttpResponse {
+    fn text(status: u16, body: &str) -> Self {
+        Self { status, content_type: "text/plain; charset=utf-8", body: format!("{}\n", body) }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Internal Server Error",
+        }
+    }
+
+    /// The response as written to the connection
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.status, self.reason(), self.content_type, self.body.len()
+        );
+        if self.status == 401 {
+            head.push_str("WWW-Authenticate: Bearer\r\n");
+        }
+        head.push_str("\r\n");
+        let mut bytes = head.into_bytes();
+        bytes.extend_from_slice(self.body.as_bytes());
+        bytes
+    }
+}
+
+/// Capability handle in an `Authorization: Bearer <handle>` header
+fn bearer_handle<'a>(mut headers: impl Iterator<Item = &'a str>) -> Option<Handle> {
+    let value = headers.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim().eq_ignore_ascii_case("authorization").then_some(value.trim())
+    })?;
+    value.strip_prefix("Bearer ")?.trim().parse().ok()
+}
+
+/// Answer one HTTP request sent by `peer_pid`
+pub fn handle_request(request: &[u8], peer_pid: ProcessId) -> HttpResponse {
+    let Some((head, _body)) = core::str::from_utf8(request).ok().and_then(|text| text.split_once("\r\n\r\n")) else {
+        return HttpResponse::text(400, "malformed request");
+    };
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (method, target, version) = (request_line.next(), request_line.next(), request_line.next());
+    let (Some(method), Some(target), Some(version)) = (method, target, version) else {
+        return HttpResponse::text(400, "malformed request line");
+    };
+    if !version.starts_with("HTTP/1.") {
+        return HttpResponse::text(400, "unsupported HTTP version");
+    }
+    if method != "GET" {
+        return HttpResponse::text(405, "only GET is supported");
+    }
+    if target.split('?').next() != Some(METRICS_PATH) {
+        return HttpResponse::text(404, "not found");
+    }
+
+    let Some(handle) = bearer_handle(lines) else {
+        return HttpResponse::text(401, "a SystemMetrics capability handle is required");
+    };
+    if crate::capabilities::check_capability(peer_pid, handle, CapabilityType::SystemMetrics, METRICS_READ) != Ok(true) {
+        return HttpResponse::text(403, "handle does not grant SystemMetrics");
+    }
+
+    HttpResponse { status: 200, content_type: CONTENT_TYPE, body: render(&MetricsSnapshot::collect()) }
+}
+
+/// Open a listening stream socket on `port`
+pub fn listen(port: u16) -> NetworkResult<u32> {
+    let socket = crate::network::create_socket(crate::network::AF_INET, crate::network::SOCK_STREAM, 0)?;
+    let [high, low] = port.to_be_bytes();
+    let bound = crate::network::bind_socket(socket, &[0, 0, 0, 0, high, low])
+        .and_then(|()| crate::network::listen_socket(socket, LISTEN_BACKLOG));
+    if let Err(e) = bound {
+        let _ = crate::network::close_socket(socket);
+        return Err(e);
+    }
+    Ok(socket)
+}
+
+/// Read a request head from `connection`, waiting up to `REQUEST_TIMEOUT_MS` for the rest
+fn read_request(connection: u32) -> NetworkResult<Vec<u8>> {
+    let mut request = Vec::new();
+    let mut waited_ms = 0;
+    while request.len() < MAX_REQUEST_SIZE && !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        match crate::network::receive_data(connection, MAX_REQUEST_SIZE - request.len(), 0) {
+            Ok(data) => request.extend_from_slice(&data),
+            Err(NetworkError::WouldBlock) if waited_ms < REQUEST_TIMEOUT_MS => {
+                crate::process::sleep_current(POLL_INTERVAL_MS);
+                waited_ms += POLL_INTERVAL_MS;
+            }
+            Err(NetworkError::WouldBlock) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(request)
+}
+
+/// Answer the next connection waiting on `listener`. Returns false when none was waiting
+pub fn serve_next(listener: u32) -> NetworkResult<bool> {
+    let connection = match crate::network::accept_connection(listener) {
+        Ok(connection) => connection,
+        Err(NetworkError::WouldBlock) => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let served = crate::network::get_peer_process_id(connection).and_then(|peer_pid| {
+        let request = read_request(connection)?;
+        let response = handle_request(&request, peer_pid as ProcessId);
+        crate::network::send_data(connection, &response.to_bytes(), 0).map(|_| ())
+    });
+    let closed = crate::network::close_socket(connection);
+    served.and(closed).map(|()| true)
+}
+
+/// Serve metrics in the background when `metrics=<port>` is on the kernel command line.
+/// Returns the server thread's PID
+pub fn start_metrics_server() -> Result<Option<u64>, &'static str> {
+    let Some(port) = crate::cmdline::get("metrics") else { return Ok(None) };
+    let port: u16 = port.parse().ok().filter(|&port| port != 0).ok_or("invalid metrics port")?;
+    METRICS_PORT.store(port, Ordering::Release);
+    crate::process::spawn_kernel_thread("metrics-server", metrics_server_main)
+        .map(Some)
+        .map_err(|_| "failed to spawn metrics server")
+}
+
+extern "C" fn metrics_server_main() -> ! {
+    let port = METRICS_PORT.load(Ordering::Acquire);
+    // The server is part of the kernel and needs network access for its socket
+    let pid = crate::process::get_current_process_id();
+    let listener = match crate::security::init_process_security(pid as u32, None)
+        .map_err(|()| NetworkError::PermissionDenied)
+        .and_then(|()| listen(port))
+    {
+        Ok(listener) => listener,
+        Err(e) => {
+            crate::serial::_print(format_args!("[Metrics] Cannot listen on port {}: {:?}\n", port, e));
+            crate::process::exit_process(1);
+        }
+    };
+    crate::serial::_print(format_args!("[Metrics] Serving {} on port {}\n", METRICS_PATH, port));
+    loop {
+        match serve_next(listener) {
+            Ok(true) => {}
+            Ok(false) => crate::process::sleep_current(POLL_INTERVAL_MS),
+            Err(e) => crate::serial::_print(format_args!("[Metrics] Request failed: {:?}\n", e)),
+        }
+    }
+}
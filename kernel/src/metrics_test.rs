@@ -0,0 +1,301 @@
+//! Metrics Test
+//! Checks the metrics endpoint: well-formed Prometheus output carrying every expected metric
+//! family, values that follow the live statistics, and the capability guard, scraped over a
+//! local network connection like an external scraper would
+
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::capabilities::{self, CapabilityType};
+use crate::metrics::{self, MetricsSnapshot, CONTENT_TYPE, METRICS_READ};
+use crate::microkernel::{ServiceRegistry, ServiceType};
+use crate::network::{self, NetworkStats};
+use crate::observability::query::LatencySummary;
+use crate::serial::_print;
+
+/// Port the test server listens on
+const TEST_PORT: u16 = 19100;
+
+/// Metric families every scrape must carry
+const EXPECTED_FAMILIES: [&str; 10] = [
+    "raeen_service_requests_total",
+    "raeen_scheduler_run_queue_length",
+    "raeen_processes",
+    "raeen_memory_total_bytes",
+    "raeen_memory_free_bytes",
+    "raeen_memory_frames",
+    "raeen_compositor_frames_total",
+    "raeen_compositor_fps",
+    "raeen_network_bytes_total",
+    "raeen_syscall_latency_seconds",
+];
+
+/// Validate Prometheus text: every family has HELP then TYPE, every sample belongs to the
+/// family declared above it, labels are quoted and values are numbers. Returns the families
+fn check_exposition(text: &str) -> Result<BTreeSet<String>, &'static str> {
+    let mut families = BTreeSet::new();
+    let mut help: Option<&str> = None;
+    let mut current: Option<(&str, &str)> = None;
+    for line in text.lines() {
+        if let Some(rest) = line.strip_prefix("# HELP ") {
+            let (name, _) = rest.split_once(' ').ok_or("HELP line without text")?;
+            help = Some(name);
+        } else if let Some(rest) = line.strip_prefix("# TYPE ") {
+            let (name, kind) = rest.split_once(' ').ok_or("TYPE line without a type")?;
+            if help != Some(name) {
+                return Err("TYPE line not preceded by HELP for the same metric");
+            }
+            if !["counter", "gauge", "summary"].contains(&kind) {
+                return Err("Unknown metric type");
+            }
+            if !families.insert(String::from(name)) {
+                return Err("Metric family declared twice");
+            }
+            current = Some((name, kind));
+        } else {
+            let (series, value) = line.rsplit_once(' ').ok_or("Sample without a value")?;
+            value.parse::<f64>().map_err(|_| "Sample value is not a number")?;
+            let (name, labels) = match series.split_once('{') {
+                Some((name, labels)) => (name, Some(labels.strip_suffix('}').ok_or("Unterminated label set")?)),
+                None => (series, None),
+            };
+            for label in labels.into_iter().flat_map(|labels| labels.split(',')) {
+                let (key, value) = label.split_once('=').ok_or("Label without a value")?;
+                if key.is_empty() || value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+                    return Err("Malformed label");
+                }
+            }
+            let (family, kind) = current.ok_or("Sample before any TYPE line")?;
+            let base = match kind {
+                "summary" => name.strip_suffix("_sum").or_else(|| name.strip_suffix("_count")).unwrap_or(name),
+                _ => name,
+            };
+            if base != family {
+                return Err("Sample outside its metric family");
+            }
+        }
+    }
+    Ok(families)
+}
+
+/// Value of the sample with exactly this series name and labels
+fn sample(text: &str, series: &str) -> Option<f64> {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .find_map(|line| line.rsplit_once(' ').filter(|(name, _)| *name == series))
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+/// Status, headers and body of an HTTP response
+fn parse_response(response: &[u8]) -> Result<(u16, String, String), &'static str> {
+    let text = core::str::from_utf8(response).map_err(|_| "Response is not UTF-8")?;
+    let (head, body) = text.split_once("\r\n\r\n").ok_or("Response without a header terminator")?;
+    let (status_line, headers) = head.split_once("\r\n").unwrap_or((head, ""));
+    let status = status_line
+        .strip_prefix("HTTP/1.1 ")
+        .and_then(|rest| rest.get(..3))
+        .and_then(|code| code.parse().ok())
+        .ok_or("Malformed status line")?;
+    let length = headers
+        .lines()
+        .find_map(|line| line.strip_prefix("Content-Length: "))
+        .and_then(|length| length.parse::<usize>().ok())
+        .ok_or("Response without Content-Length")?;
+    if length != body.len() {
+        return Err("Content-Length does not match the body");
+    }
+    Ok((status, String::from(headers), String::from(body)))
+}
+
+/// Connect to `listener` on the test port, send `request`, let the server answer it and read
+/// the response. Returns the response's raw bytes
+fn scrape(listener: u32, request: &str) -> Result<Vec<u8>, &'static str> {
+    let client = network::create_socket(network::AF_INET, network::SOCK_STREAM, 0).map_err(|_| "Failed to create client socket")?;
+    let [high, low] = TEST_PORT.to_be_bytes();
+    let exchange = network::connect_socket(client, &[127, 0, 0, 1, high, low])
+        .and_then(|()| network::send_data(client, request.as_bytes(), 0))
+        .map_err(|_| "Failed to send request")
+        .and_then(|_| match metrics::serve_next(listener) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err("Server saw no connection"),
+            Err(_) => Err("Server failed to answer"),
+        })
+        .and_then(|()| network::receive_data(client, 64 * 1024, 0).map_err(|_| "No response received"));
+    let _ = network::close_socket(client);
+    exchange
+}
+
+fn get(handle: Option<u32>, path: &str) -> String {
+    let authorization = handle.map(|handle| format!("Authorization: Bearer {}\r\n", handle)).unwrap_or_default();
+    format!("GET {} HTTP/1.1\r\nHost: raeenos\r\n{}\r\n", path, authorization)
+}
+
+/// A capability of `capability_type` granted to `pid`, returning the handle and its ID
+fn grant(pid: u64, capability_type: CapabilityType) -> Result<(u32, u64), &'static str> {
+    let id = capabilities::create_capability(capability_type, pid, None, METRICS_READ, false, false)?;
+    Ok((capabilities::grant_capability(pid, id)?, id))
+}
+
+/// A snapshot with fixed values, for checking how each statistic is rendered
+fn fixed_snapshot(service_requests: Vec<(ServiceType, u64)>) -> MetricsSnapshot {
+    MetricsSnapshot {
+        service_requests,
+        run_queue_lengths: alloc::vec![3, 0],
+        process_count: 7,
+        memory_total_bytes: 512 * 1024 * 1024,
+        memory_free_bytes: 256 * 1024 * 1024,
+        frames_allocated: 1000,
+        frames_free: 65000,
+        compositor_frames: 160,
+        compositor_fps: metrics::frame_rate(Some((100, 0)), 160, 1_000_000_000),
+        network: NetworkStats { bytes_sent: 42, bytes_received: 24 },
+        syscall_latency: LatencySummary::from_samples(alloc::vec![1_000, 2_000, 3_000]),
+        syscall_latency_sum_ns: 6_000,
+    }
+}
+
+pub fn run_metrics_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Metrics Test] Starting metrics tests...\n"));
+
+    let pid = crate::process::get_current_process_id();
+    // Kernel contexts without a security context get one so they may open sockets
+    if crate::security::request_permission(pid as u32, "network.access").is_err() {
+        crate::security::init_process_security(pid as u32, None).map_err(|_| "Failed to set up test security context")?;
+    }
+    let listener = metrics::listen(TEST_PORT).map_err(|_| "Failed to listen on the test port")?;
+    let result = run_with_listener(pid, listener);
+    let _ = network::close_socket(listener);
+    result?;
+
+    _print(format_args!("[Metrics Test] ✓ All metrics tests completed successfully!\n"));
+    Ok(())
+}
+
+fn run_with_listener(pid: u64, listener: u32) -> Result<(), &'static str> {
+    let (handle, capability_id) = grant(pid, CapabilityType::SystemMetrics)?;
+
+    // Test 1: An authorized scrape returns well-formed Prometheus text with every family
+    _print(format_args!("[Metrics Test] Test 1: Authorized scrape...\n"));
+    let (status, headers, body) = parse_response(&scrape(listener, &get(Some(handle), "/metrics"))?)?;
+    if status != 200 {
+        return Err("Authorized scrape was refused");
+    }
+    if !headers.lines().any(|line| line == format!("Content-Type: {}", CONTENT_TYPE)) {
+        return Err("Scrape is not served as Prometheus text");
+    }
+    let families = check_exposition(&body)?;
+    if let Some(missing) = EXPECTED_FAMILIES.iter().find(|name| !families.contains(**name)) {
+        _print(format_args!("[Metrics Test] Missing metric family {}\n", missing));
+        return Err("Scrape lacks an expected metric family");
+    }
+    _print(format_args!("[Metrics Test] ✓ {} well-formed metric families\n", families.len()));
+
+    // Test 2: Scraped values follow the live statistics
+    _print(format_args!("[Metrics Test] Test 2: Live values...\n"));
+    let cpus = crate::process::get_run_queue_lengths().len();
+    if (0..cpus).any(|cpu| sample(&body, &format!("raeen_scheduler_run_queue_length{{cpu=\"{}\"}}", cpu)).is_none()) {
+        return Err("Run queue length missing for a CPU");
+    }
+    if sample(&body, "raeen_memory_total_bytes") != Some(crate::memory::get_total_memory() as f64) {
+        return Err("Total memory does not match the memory manager");
+    }
+    // Everything after the first scrape is traffic the next scrape must count
+    let before = network::get_network_stats();
+    let request = get(Some(handle), "/metrics");
+    let response = scrape(listener, &request)?;
+    let (_, _, body) = parse_response(&response)?;
+    let sent = sample(&body, "raeen_network_bytes_total{direction=\"sent\"}").ok_or("No sent bytes")?;
+    let received = sample(&body, "raeen_network_bytes_total{direction=\"received\"}").ok_or("No received bytes")?;
+    // The body is rendered after the request went through but before the response did
+    if sent != (before.bytes_sent + request.len() as u64) as f64 || received != (before.bytes_received + request.len() as u64) as f64 {
+        return Err("Network byte counters do not reflect the scrape's own traffic");
+    }
+    let after = network::get_network_stats();
+    if after.bytes_sent != before.bytes_sent + (request.len() + response.len()) as u64 {
+        return Err("Socket layer missed sent bytes");
+    }
+    let live = MetricsSnapshot::collect();
+    if live.network != after || live.process_count != crate::process::get_process_count() {
+        return Err("Snapshot differs from the live statistics");
+    }
+    _print(format_args!("[Metrics Test] ✓ Byte counters include the scrape's own {} request bytes\n", request.len()));
+
+    // Test 3: Each statistic lands in its own series
+    _print(format_args!("[Metrics Test] Test 3: Rendering of each statistic...\n"));
+    let mut registry = ServiceRegistry::new();
+    registry.register_service(ServiceType::Network, pid, ServiceType::Network.default_capabilities())?;
+    registry.register_service(ServiceType::Compositor, pid, ServiceType::Compositor.default_capabilities())?;
+    for _ in 0..3 {
+        registry.record_request(ServiceType::Network);
+    }
+    registry.record_request(ServiceType::Audio);
+    let text = metrics::render(&fixed_snapshot(registry.request_counts()));
+    check_exposition(&text)?;
+    let expected = [
+        ("raeen_service_requests_total{service=\"rae-netd\"}", 3.0),
+        ("raeen_service_requests_total{service=\"rae-compositord\"}", 0.0),
+        ("raeen_scheduler_run_queue_length{cpu=\"0\"}", 3.0),
+        ("raeen_scheduler_run_queue_length{cpu=\"1\"}", 0.0),
+        ("raeen_processes", 7.0),
+        ("raeen_memory_frames{state=\"allocated\"}", 1000.0),
+        ("raeen_memory_frames{state=\"free\"}", 65000.0),
+        ("raeen_compositor_frames_total", 160.0),
+        ("raeen_compositor_fps", 60.0),
+        ("raeen_network_bytes_total{direction=\"sent\"}", 42.0),
+        ("raeen_syscall_latency_seconds{quantile=\"0.5\"}", 0.000002),
+        ("raeen_syscall_latency_seconds{quantile=\"0.99\"}", 0.000003),
+        ("raeen_syscall_latency_seconds_sum", 0.000006),
+        ("raeen_syscall_latency_seconds_count", 3.0),
+    ];
+    for (series, value) in expected {
+        if sample(&text, series) != Some(value) {
+            _print(format_args!("[Metrics Test] Wrong value for {}\n", series));
+            return Err("Statistic rendered with the wrong value");
+        }
+    }
+    if sample(&text, "raeen_service_requests_total{service=\"rae-audiod\"}").is_some() {
+        return Err("Unregistered service reported");
+    }
+    _print(format_args!("[Metrics Test] ✓ {} series carry their statistics\n", expected.len()));
+
+    // Test 4: The endpoint is only reachable with a SystemMetrics capability
+    _print(format_args!("[Metrics Test] Test 4: Capability guard...\n"));
+    let (file_handle, _) = grant(pid, CapabilityType::FileRead)?;
+    let cases = [
+        (get(None, "/metrics"), 401),
+        (get(Some(file_handle), "/metrics"), 403),
+        (get(Some(u32::MAX), "/metrics"), 403),
+        (get(Some(handle), "/other"), 404),
+        (format!("POST /metrics HTTP/1.1\r\nAuthorization: Bearer {}\r\n\r\n", handle), 405),
+        (String::from("GARBAGE\r\n\r\n"), 400),
+    ];
+    for (request, expected_status) in &cases {
+        let (status, _, body) = parse_response(&scrape(listener, request)?)?;
+        if status != *expected_status || body.contains("raeen_") {
+            return Err("Unauthorized or invalid request was not refused");
+        }
+    }
+    capabilities::revoke_capability(capability_id)?;
+    let (status, _, _) = parse_response(&scrape(listener, &get(Some(handle), "/metrics"))?)?;
+    if status != 403 {
+        return Err("Revoked capability still reaches the metrics");
+    }
+    _print(format_args!("[Metrics Test] ✓ {} refused requests, revocation honored\n", cases.len() + 1));
+
+    Ok(())
+}
+
+/// Main test runner for metrics
+pub fn test_metrics() {
+    _print(format_args!("[Metrics Test] ===========================================\n"));
+    _print(format_args!("[Metrics Test]              METRICS TESTS\n"));
+    _print(format_args!("[Metrics Test] ===========================================\n"));
+
+    match run_metrics_tests() {
+        Ok(_) => _print(format_args!("[Metrics Test] ✓ All metrics tests PASSED\n")),
+        Err(e) => _print(format_args!("[Metrics Test] ✗ Metrics tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Metrics Test] ===========================================\n"));
+}
@@ -235,6 +235,7 @@ struct ServiceInfo {
     start_time: u64,
     restart_count: u32,
     last_health_check: u64,
+    request_count: u64,
 }
 
 impl ServiceRegistry {
@@ -264,6 +265,7 @@ impl ServiceRegistry {
             start_time: crate::time::get_timestamp_ns(),
             restart_count: 0,
             last_health_check: 0,
+            request_count: 0,
         };
         
         self.services.insert(service_type, service_info);
@@ -336,6 +338,21 @@ impl ServiceRegistry {
         }
     }
     
+    /// Count a request delivered to a service
+    pub fn record_request(&mut self, service_type: ServiceType) {
+        if let Some(service_info) = self.services.get_mut(&service_type) {
+            service_info.request_count += 1;
+        }
+    }
+    
+    /// Requests delivered to each registered service
+    pub fn request_counts(&self) -> Vec<(ServiceType, u64)> {
+        self.services
+            .iter()
+            .map(|(service_type, info)| (*service_type, info.request_count))
+            .collect()
+    }
+    
     /// Get all running services
     pub fn get_running_services(&self) -> Vec<ServiceType> {
         self.services
@@ -598,6 +615,7 @@ pub fn send_service_message(
     send_ipc_message_to_service(endpoint_id, &serialized)
         .map_err(|_| "Failed to send IPC message")?;
     
+    with_service_registry(|registry| registry.record_request(target_service));
     Ok(())
 }
 
@@ -0,0 +1,86 @@
+//! Mmap Placement Test
+//! Maps anonymous memory into a fresh address space with hints and checks where it lands: at
+//! a free hint, elsewhere when the hint is taken or runs past user space, and nowhere for a
+//! fixed mapping that cannot go at its hint. Hints near the top of user space, in the upper
+//! half and sizes too large for any region are refused rather than overflow
+
+use x86_64::VirtAddr;
+use crate::serial::_print;
+use crate::vmm::{self, MappingSource, VmError, VmPermissions};
+
+const PAGE: u64 = 4096;
+const USER_SPACE_END: u64 = 0x8000_0000_0000;
+/// The last page of user space
+const TOP_PAGE: u64 = USER_SPACE_END - PAGE;
+/// The first address of the kernel's upper half
+const UPPER_HALF: u64 = 0xFFFF_8000_0000_0000;
+const FREE_HINT: u64 = 0x5000_0000_0000;
+
+fn map(address_space_id: u64, hint: Option<u64>, size: u64, fixed: bool) -> Result<VirtAddr, VmError> {
+    let permissions = VmPermissions::READ | VmPermissions::WRITE | VmPermissions::USER;
+    vmm::map_memory(address_space_id, hint.map(VirtAddr::new), size, permissions, fixed, MappingSource::Anonymous { shared: false })
+}
+
+fn placement(address_space_id: u64) -> Result<(), &'static str> {
+    // Test 1: A free hint is taken, and a taken one moves the mapping unless it is fixed
+    _print(format_args!("[Mmap Test] Test 1: Hints in user space...\n"));
+    let placed = map(address_space_id, Some(FREE_HINT), 2 * PAGE, false).map_err(|_| "Failed to map at a free hint")?;
+    if placed.as_u64() != FREE_HINT {
+        return Err("Free hint not taken");
+    }
+    let moved = map(address_space_id, Some(FREE_HINT + PAGE), PAGE, false).map_err(|_| "Failed to map past a taken hint")?;
+    if moved.as_u64() >= FREE_HINT && moved.as_u64() < FREE_HINT + 2 * PAGE {
+        return Err("Mapping placed over another");
+    }
+    if !matches!(map(address_space_id, Some(FREE_HINT + PAGE), PAGE, true), Err(VmError::AddressInUse)) {
+        return Err("Fixed mapping over another not refused");
+    }
+    _print(format_args!("[Mmap Test] ✓ Hints taken when free\n"));
+
+    // Test 2: Hints whose range leaves user space fall back to the mmap region
+    _print(format_args!("[Mmap Test] Test 2: Hints outside user space...\n"));
+    for hint in [TOP_PAGE, UPPER_HALF] {
+        let placed = map(address_space_id, Some(hint), 2 * PAGE, false).map_err(|_| "Failed to map past an unusable hint")?;
+        if placed.as_u64() + 2 * PAGE > USER_SPACE_END {
+            return Err("Mapping placed outside user space");
+        }
+        if !matches!(map(address_space_id, Some(hint), 2 * PAGE, true), Err(VmError::InvalidOperation)) {
+            return Err("Fixed mapping outside user space not refused");
+        }
+    }
+    _print(format_args!("[Mmap Test] ✓ Unusable hints fell back\n"));
+
+    // Test 3: Sizes no region can hold fail, with or without a hint
+    _print(format_args!("[Mmap Test] Test 3: Oversized mappings...\n"));
+    for hint in [None, Some(FREE_HINT), Some(TOP_PAGE)] {
+        if map(address_space_id, hint, 1 << 62, false).is_ok() || map(address_space_id, hint, u64::MAX - PAGE, false).is_ok() {
+            return Err("Oversized mapping placed");
+        }
+    }
+    _print(format_args!("[Mmap Test] ✓ Oversized mappings refused\n"));
+    Ok(())
+}
+
+pub fn run_mmap_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Mmap Test] Starting mmap placement tests...\n"));
+    let address_space_id = vmm::create_address_space().map_err(|_| "Failed to create address space")?;
+    let result = placement(address_space_id);
+    let _ = vmm::destroy_address_space(address_space_id);
+    result?;
+    _print(format_args!("[Mmap Test] ✓ All mmap placement tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for mmap placement
+pub fn test_mmap() {
+    _print(format_args!("[Mmap Test] ===========================================\n"));
+    _print(format_args!("[Mmap Test]          MMAP PLACEMENT TESTS\n"));
+    _print(format_args!("[Mmap Test] ===========================================\n"));
+
+    match run_mmap_tests() {
+        Ok(_) => _print(format_args!("[Mmap Test] ✓ All mmap placement tests PASSED\n")),
+        Err(e) => _print(format_args!("[Mmap Test] ✗ Mmap placement tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Mmap Test] ===========================================\n"));
+}
@@ -0,0 +1,207 @@
+//! Resource Monitor Test
+//! Checks that `free` reports the frames actually allocated, that `uptime` agrees with the
+//! timer and the boot time, and that the load averages follow how many processes occupy the
+//! run queues, sampled on the uptime clock and published as `/proc/loadavg`
+
+use alloc::vec::Vec;
+use x86_64::instructions::interrupts;
+use crate::process::{self, LoadAverages, LoadSampler};
+use crate::raeshell::monitor;
+use crate::serial::_print;
+
+const FRAMES: u64 = 64;
+
+/// The used column of the `Mem:` row of `free`, in KiB
+fn free_used_kib() -> Option<u64> {
+    let report = monitor::free_report();
+    let row = report.lines().find(|line| line.starts_with("Mem:"))?;
+    row.split_whitespace().nth(2)?.parse().ok()
+}
+
+extern "C" fn spinning_thread() -> ! {
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+fn runnable_count() -> usize {
+    process::get_smp_scheduler().lock().runnable_count()
+}
+
+/// Whether each average is within 0.05 of `expected`
+fn near(load: (f32, f32, f32), expected: (f32, f32, f32)) -> bool {
+    [(load.0, expected.0), (load.1, expected.1), (load.2, expected.2)].iter().all(|(average, expected)| (average - expected).abs() < 0.05)
+}
+
+/// Step a clock from `from_ms` to `to_ms` in 100ms timer ticks with `runnable` processes
+/// occupying the run queues throughout
+fn run_clock(sampler: &mut LoadSampler, from_ms: u64, to_ms: u64, runnable: usize) {
+    let mut now = from_ms;
+    while now < to_ms {
+        now += 100;
+        sampler.tick(now, runnable);
+    }
+}
+
+pub fn run_monitor_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Monitor Test] Starting resource monitor tests...\n"));
+
+    // Test 1: Frames allocated show up as used memory in `free`, and come off it when freed
+    _print(format_args!("[Monitor Test] Test 1: free tracks frame allocations...\n"));
+    let measured = interrupts::without_interrupts(|| {
+        let before = free_used_kib();
+        let frames: Vec<_> = (0..FRAMES).map_while(|_| crate::memory::allocate_frame()).collect();
+        let during = free_used_kib();
+        let usage = crate::memory::memory_usage();
+        let allocated = frames.len() as u64;
+        for frame in frames {
+            crate::memory::deallocate_frame(frame);
+        }
+        Some((before?, during?, free_used_kib()?, usage, allocated))
+    });
+    let (before, during, after, usage, allocated) = measured.ok_or("free report has no Mem row")?;
+    if allocated != FRAMES {
+        return Err("Failed to allocate frames");
+    }
+    if during != before + FRAMES * 4 || after != before {
+        return Err("free used memory does not follow frame allocations");
+    }
+    if usage.used + usage.free != usage.total || usage.used / 1024 != during {
+        return Err("free totals inconsistent");
+    }
+    _print(format_args!("[Monitor Test] ✓ {} KiB used, {} KiB while {} frames were held\n", before, during, FRAMES));
+
+    // Test 2: `uptime` reads the timer, and the boot time plus the uptime is the time now
+    _print(format_args!("[Monitor Test] Test 2: uptime and boot time...\n"));
+    if monitor::format_uptime(59) != "0:00:59" || monitor::format_uptime(90_061) != "1 day, 1:01:01" {
+        return Err("Uptime misformatted");
+    }
+    let seconds = crate::time::get_uptime_seconds();
+    let report = monitor::uptime_report();
+    let reported = crate::time::get_uptime_seconds();
+    if !(report.starts_with(&alloc::format!("up {},", monitor::format_uptime(seconds)))
+        || report.starts_with(&alloc::format!("up {},", monitor::format_uptime(reported))))
+    {
+        return Err("uptime does not report the timer's uptime");
+    }
+    let booted = crate::time::boot_time() + crate::time::get_uptime_seconds();
+    if booted.abs_diff(crate::time::get_timestamp()) > 1 {
+        return Err("Boot time plus uptime is not the time now");
+    }
+    crate::time::sleep_ms(1100);
+    if crate::time::get_uptime_seconds() <= seconds {
+        return Err("Uptime did not advance");
+    }
+    _print(format_args!("[Monitor Test] ✓ Booted at {}, {}\n", monitor::boot_time_report(), monitor::uptime_report()));
+
+    // Test 3: Load averages rise towards a steady run-queue occupancy and decay once it ends
+    _print(format_args!("[Monitor Test] Test 3: Load averages...\n"));
+    let mut load = LoadAverages::new();
+    let samples_per_minute = (60_000 / process::LOAD_SAMPLE_INTERVAL_MS) as usize;
+    for _ in 0..samples_per_minute {
+        load.sample(4);
+    }
+    let [rising, five, fifteen] = load.hundredths();
+    // After one minute the 1 minute average has come 1 - 1/e of the way
+    if !(240..=265).contains(&rising) || five >= rising || fifteen >= five {
+        return Err("Load averages did not rise at their rates");
+    }
+    for _ in 0..15 * samples_per_minute * 10 {
+        load.sample(4);
+    }
+    if load.hundredths() != [400; 3] {
+        return Err("Load averages did not settle at the run-queue occupancy");
+    }
+    for _ in 0..samples_per_minute {
+        load.sample(0);
+    }
+    let [one, five, fifteen] = load.hundredths();
+    if !(135..=160).contains(&one) || five <= one || fifteen <= five {
+        return Err("Load averages did not decay at their rates");
+    }
+    _print(format_args!("[Monitor Test] ✓ 4 runnable for a minute gives {}, idle again gives {}\n", rising, one));
+
+    // Test 4: The sampled occupancy counts the processes ready to run
+    _print(format_args!("[Monitor Test] Test 4: Run-queue occupancy...\n"));
+    let (before, spawned, during) = interrupts::without_interrupts(|| {
+        let before = runnable_count();
+        let spawned: Vec<u64> = (0..3)
+            .filter_map(|_| process::spawn_kernel_thread("load-spinner", spinning_thread).ok())
+            .collect();
+        (before, spawned, runnable_count())
+    });
+    for &pid in &spawned {
+        process::terminate_process(pid);
+    }
+    let after = interrupts::without_interrupts(runnable_count);
+    if spawned.len() != 3 {
+        return Err("Failed to spawn threads");
+    }
+    if during != before + 3 || after != before {
+        return Err("Runnable count does not follow the run queues");
+    }
+    _print(format_args!("[Monitor Test] ✓ {} runnable, {} with three more threads\n", before, during));
+
+    // Test 5: On a clock, each average comes 1 - 1/e of the way to a steady occupancy over its window
+    _print(format_args!("[Monitor Test] Test 5: Load averages on a clock...\n"));
+    let mut sampler = LoadSampler::new();
+    if sampler.tick(process::LOAD_SAMPLE_INTERVAL_MS - 1, 3) != 0 || sampler.averages != LoadAverages::new() {
+        return Err("Load sampled before the first interval ended");
+    }
+    // 3 * (1 - 1/e), and 3 * (1 - e^(-1/5)) and 3 * (1 - e^(-1/15)) for the longer windows
+    let window = 1.896;
+    run_clock(&mut sampler, 0, 60_000, 3);
+    if !near(sampler.averages.values(), (window, 0.544, 0.193)) {
+        return Err("Averages wrong after a minute");
+    }
+    run_clock(&mut sampler, 60_000, 300_000, 3);
+    let five_minutes = sampler.averages.values();
+    if !near(five_minutes, (3.0, window, 0.850)) {
+        return Err("Averages wrong after five minutes");
+    }
+    run_clock(&mut sampler, 300_000, 900_000, 3);
+    if !near(sampler.averages.values(), (3.0, 2.851, window)) {
+        return Err("Averages wrong after fifteen minutes");
+    }
+    // Intervals whose ticks were lost are made up when the clock is next seen
+    let mut stalled = LoadSampler::new();
+    if stalled.tick(300_000, 3) != 60 || stalled.averages.values() != five_minutes {
+        return Err("Missed intervals not made up");
+    }
+    _print(format_args!("[Monitor Test] ✓ 3 runnable reaches {:.2} within each window\n", sampler.averages.values().2));
+
+    // Test 6: /proc/loadavg
+    _print(format_args!("[Monitor Test] Test 6: /proc/loadavg...\n"));
+    if sampler.averages.loadavg(2, 40, 97) != "3.00 2.87 1.89 2/40 97\n" {
+        return Err("loadavg line wrong");
+    }
+    let published = process::publish_load_average()
+        .and_then(|_| crate::filesystem::read_file(process::LOADAVG_PATH))
+        .map_err(|_| "Failed to publish /proc/loadavg")?;
+    let published = alloc::string::String::from_utf8(published).map_err(|_| "/proc/loadavg is not text")?;
+    let fields: Vec<&str> = published.split_whitespace().collect();
+    let well_formed = fields.len() == 5
+        && fields[..3].iter().all(|field| field.parse::<f32>().is_ok())
+        && fields[3].split_once('/').is_some_and(|(runnable, total)| runnable.parse::<u64>().is_ok() && total.parse::<u64>().is_ok());
+    if !well_formed {
+        return Err("/proc/loadavg malformed");
+    }
+    _print(format_args!("[Monitor Test] ✓ /proc/loadavg: {}", published));
+
+    _print(format_args!("[Monitor Test] ✓ All resource monitor tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for the resource monitor
+pub fn test_monitor() {
+    _print(format_args!("[Monitor Test] ===========================================\n"));
+    _print(format_args!("[Monitor Test]        RESOURCE MONITOR TESTS\n"));
+    _print(format_args!("[Monitor Test] ===========================================\n"));
+
+    match run_monitor_tests() {
+        Ok(_) => _print(format_args!("[Monitor Test] ✓ All resource monitor tests PASSED\n")),
+        Err(e) => _print(format_args!("[Monitor Test] ✗ Resource monitor tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Monitor Test] ===========================================\n"));
+}
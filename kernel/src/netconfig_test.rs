@@ -0,0 +1,157 @@
+//! Network Configuration Test
+//! Runs `ifconfig` and `ip` against the interfaces the kernel has: the listing shows loopback
+//! and each virtio-net device with its hardware and IPv4 addresses, bringing an interface down
+//! shows in its state and takes its routes away, and a static address is assigned and routed
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use core::net::Ipv4Addr;
+use crate::drivers::virtio;
+use crate::network::interface::{self, InterfaceKind, Ipv4Cidr};
+use crate::raeshell::netconfig::{cmd_ifconfig, cmd_ip};
+use crate::raeshell::ShellResult;
+use crate::serial::_print;
+
+/// Output of a command line, failing when the command does
+fn run(line: &str) -> Result<String, &'static str> {
+    let args: alloc::vec::Vec<&str> = line.split_whitespace().collect();
+    let result = match args.first() {
+        Some(&"ip") => cmd_ip(&args),
+        _ => cmd_ifconfig(&args),
+    };
+    match result {
+        ShellResult::Success(output) => Ok(output),
+        _ => Err("Network command failed"),
+    }
+}
+
+fn mac_text(mac: [u8; 6]) -> String {
+    format!("{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", mac[0], mac[1], mac[2], mac[3], mac[4], mac[5])
+}
+
+pub fn run_netconfig_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Netconfig Test] Starting network configuration tests...\n"));
+
+    // Test 1: Loopback and every virtio-net device are listed with their addresses
+    _print(format_args!("[Netconfig Test] Test 1: Interface listing...\n"));
+    let interfaces = interface::list_interfaces();
+    let loopback = interfaces.iter().find(|i| i.kind == InterfaceKind::Loopback).ok_or("No loopback interface")?;
+    if loopback.name != "lo" || !loopback.up || loopback.addresses != [Ipv4Cidr::new(Ipv4Addr::LOCALHOST, 8).map_err(|_| "Bad address")?] {
+        return Err("Loopback not up at 127.0.0.1/8");
+    }
+    let devices = virtio::net_devices();
+    let ethernet: alloc::vec::Vec<_> = interfaces.iter().filter(|i| i.kind == InterfaceKind::Ethernet).collect();
+    if ethernet.len() != devices.len()
+        || !devices.iter().all(|device| ethernet.iter().any(|i| i.mac_address == device.lock().mac_address()))
+    {
+        return Err("Ethernet interfaces do not match the virtio-net devices");
+    }
+    let listing = run("ip addr")?;
+    if !listing.contains("lo: <LOOPBACK,UP>") || !listing.contains("inet 127.0.0.1/8 scope host lo") {
+        return Err("ip addr does not show loopback");
+    }
+    for nic in &ethernet {
+        if !listing.contains(&format!("{}: {}:", nic.index, nic.name)) || !listing.contains(&format!("link/ether {}", mac_text(nic.mac_address))) {
+            return Err("ip addr does not show a NIC");
+        }
+        if !nic.addresses.iter().all(|address| listing.contains(&format!("inet {} ", address))) {
+            return Err("ip addr does not show a NIC's addresses");
+        }
+    }
+    if !run("ifconfig")?.contains("lo: flags=<UP,LOOPBACK,RUNNING>  mtu 65536\n        inet 127.0.0.1  netmask 255.0.0.0") {
+        return Err("ifconfig does not show loopback");
+    }
+    _print(format_args!("[Netconfig Test] ✓ {} interfaces listed, {} of them NICs\n", interfaces.len(), ethernet.len()));
+
+    // The rest run on the first NIC, or on loopback when there is none
+    let name = ethernet.first().map_or(String::from("lo"), |nic| nic.name.clone());
+    let was_up = interface::interface_info(&name).map_err(|_| "Interface vanished")?.up;
+
+    // Test 2: An interface brought down reports it, loses its routes, and comes back up
+    _print(format_args!("[Netconfig Test] Test 2: Interface state on {}...\n", name));
+    run(&format!("ip link set {} up", name))?;
+    run(&format!("ip addr add 10.77.0.15/24 dev {}", name))?;
+    let route = format!("10.77.0.0/24 dev {} proto kernel scope link src 10.77.0.15", name);
+    if !run("ip route")?.contains(&route) {
+        return Err("Address of an interface up has no route");
+    }
+    run(&format!("ip link set {} down", name))?;
+    let info = interface::interface_info(&name).map_err(|_| "Interface vanished")?;
+    let down = !info.up
+        && run(&format!("ip link show {}", name))?.contains("state DOWN")
+        && !run(&format!("ifconfig {}", name))?.contains("UP")
+        && !run("ifconfig")?.contains(&format!("{}: flags", name));
+    let routed_down = run("ip route")?.contains(&route) || interface::route_to(Ipv4Addr::new(10, 77, 0, 2)).is_ok();
+    run(&format!("ifconfig {} up", name))?;
+    if !down {
+        return Err("Interface brought down still reported up");
+    }
+    if routed_down {
+        return Err("Interface down still routed");
+    }
+    if !interface::interface_info(&name).map_err(|_| "Interface vanished")?.up || !run(&format!("ip link show {}", name))?.contains("state UP") {
+        return Err("Interface not brought back up");
+    }
+    _print(format_args!("[Netconfig Test] ✓ {} reported down and unrouted, then up again\n", name));
+
+    // Test 3: Static addresses are assigned, replaced and removed, and traffic follows them
+    _print(format_args!("[Netconfig Test] Test 3: Static addresses on {}...\n", name));
+    let added = Ipv4Cidr::new(Ipv4Addr::new(10, 77, 0, 15), 24).map_err(|_| "Bad address")?;
+    if !interface::interface_info(&name).map_err(|_| "Interface vanished")?.addresses.contains(&added) {
+        return Err("Static address not assigned");
+    }
+    if run(&format!("ip addr add 10.77.0.15/24 dev {}", name)).is_ok() || run("ip addr add 10.77.0.16/24 dev nosuch0").is_ok() {
+        return Err("Duplicate address or missing interface accepted");
+    }
+    if run("ip route get 10.77.0.2")? != format!("10.77.0.2 dev {} src 10.77.0.15", name) {
+        return Err("Traffic to the subnet not routed from the address");
+    }
+    run("ip route add 172.30.0.0/16 via 10.77.0.1")?;
+    let via_gateway = run("ip route get 172.30.4.5")? == format!("172.30.4.5 via 10.77.0.1 dev {} src 10.77.0.15", name);
+    run("ip route del 172.30.0.0/16")?;
+    if !via_gateway {
+        return Err("Static route not followed");
+    }
+    if run("ip route add 172.31.0.0/16 via 192.0.2.1").is_ok() {
+        return Err("Route through an unreachable gateway accepted");
+    }
+    run(&format!("ip addr del 10.77.0.15/24 dev {}", name))?;
+    if run("ip route")?.contains("10.77.0.0/24") || run("ip route get 10.77.0.2").is_ok() {
+        return Err("Removed address still routed");
+    }
+    // ifconfig puts its address first, in place of the first one there
+    let before = interface::interface_info(&name).map_err(|_| "Interface vanished")?.addresses;
+    run(&format!("ifconfig {} 10.78.1.7 netmask 255.255.0.0", name))?;
+    let replaced = interface::interface_info(&name).map_err(|_| "Interface vanished")?.addresses;
+    let shown = run(&format!("ifconfig {}", name))?.contains("inet 10.78.1.7  netmask 255.255.0.0");
+    for address in replaced.iter().filter(|address| !before.contains(address)) {
+        let _ = interface::remove_address(&name, *address);
+    }
+    if let Some(first) = before.first() {
+        let _ = interface::add_address(&name, *first);
+    }
+    if replaced.first().map(|address| address.to_string()) != Some(String::from("10.78.1.7/16")) || replaced.len() != before.len().max(1) || !shown {
+        return Err("ifconfig did not assign the address");
+    }
+    if !was_up {
+        run(&format!("ip link set {} down", name))?;
+    }
+    _print(format_args!("[Netconfig Test] ✓ Static addresses assigned, routed and removed\n"));
+
+    _print(format_args!("[Netconfig Test] ✓ All network configuration tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for network configuration
+pub fn test_netconfig() {
+    _print(format_args!("[Netconfig Test] ===========================================\n"));
+    _print(format_args!("[Netconfig Test]      NETWORK CONFIGURATION TESTS\n"));
+    _print(format_args!("[Netconfig Test] ===========================================\n"));
+
+    match run_netconfig_tests() {
+        Ok(_) => _print(format_args!("[Netconfig Test] ✓ All network configuration tests PASSED\n")),
+        Err(e) => _print(format_args!("[Netconfig Test] ✗ Network configuration tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Netconfig Test] ===========================================\n"));
+}
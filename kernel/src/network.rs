@@ -1,5 +1,12 @@
 //! Network subsystem for RaeenOS
 
+pub mod bpf;
+pub mod dns;
+pub mod interface;
+pub mod packet;
+pub mod pcap;
+pub mod udp;
+
 use alloc::vec::Vec;
 
 #[derive(Debug)]
@@ -15,6 +22,15 @@ pub enum NetworkError {
     AddressFamilyNotSupported,
     ProtocolNotSupported,
     SocketTypeNotSupported,
+    InterfaceNotFound,
+    AddressExists,
+    AddressNotFound,
+    RouteExists,
+    RouteNotFound,
+    NetworkUnreachable,
+    HostUnreachable,
+    InvalidFrame,
+    InvalidFilter,
 }
 
 impl From<NetworkError> for crate::syscall::SyscallError {
@@ -31,6 +47,15 @@ impl From<NetworkError> for crate::syscall::SyscallError {
             NetworkError::AddressFamilyNotSupported => crate::syscall::SyscallError::InvalidArgument,
             NetworkError::ProtocolNotSupported => crate::syscall::SyscallError::InvalidArgument,
             NetworkError::SocketTypeNotSupported => crate::syscall::SyscallError::InvalidArgument,
+            NetworkError::InterfaceNotFound => crate::syscall::SyscallError::ResourceNotFound,
+            NetworkError::AddressExists => crate::syscall::SyscallError::ResourceBusy,
+            NetworkError::AddressNotFound => crate::syscall::SyscallError::ResourceNotFound,
+            NetworkError::RouteExists => crate::syscall::SyscallError::ResourceBusy,
+            NetworkError::RouteNotFound => crate::syscall::SyscallError::ResourceNotFound,
+            NetworkError::NetworkUnreachable => crate::syscall::SyscallError::NetworkError,
+            NetworkError::HostUnreachable => crate::syscall::SyscallError::NetworkError,
+            NetworkError::InvalidFrame => crate::syscall::SyscallError::InvalidArgument,
+            NetworkError::InvalidFilter => crate::syscall::SyscallError::InvalidArgument,
         }
     }
 }
@@ -42,12 +67,14 @@ use spin::Mutex;
 use lazy_static::lazy_static;
 
 // Socket domains
-const AF_INET: u32 = 2;  // IPv4
+pub const AF_INET: u32 = 2;  // IPv4
 const AF_INET6: u32 = 10; // IPv6
+pub const AF_PACKET: u32 = 17; // Whole link-layer frames
 
 // Socket types
-const SOCK_STREAM: u32 = 1; // TCP
+pub const SOCK_STREAM: u32 = 1; // TCP
 const SOCK_DGRAM: u32 = 2;  // UDP
+pub const SOCK_RAW: u32 = 3; // Frames as they are
 
 // Protocols
 const IPPROTO_TCP: u32 = 6;
@@ -97,6 +124,10 @@ struct Socket {
     receive_buffer: Vec<u8>,
     send_buffer: Vec<u8>,
     process_id: u32,
+    /// Other end of a local stream connection, which receives what this socket sends
+    peer: Option<u32>,
+    /// For a packet socket, the frames it sees and holds
+    packet: Option<packet::PacketSocket>,
 }
 
 impl Socket {
@@ -113,6 +144,8 @@ impl Socket {
             receive_buffer: Vec::new(),
             send_buffer: Vec::new(),
             process_id,
+            peer: None,
+            packet: None,
         }
     }
 }
@@ -122,6 +155,14 @@ struct NetworkSystem {
     sockets: BTreeMap<u32, Socket>,
     next_socket_fd: u32,
     port_allocations: BTreeMap<u16, u32>, // port -> socket_fd
+    stats: NetworkStats,
+}
+
+/// Bytes moved through sockets since boot
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetworkStats {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
 }
 
 lazy_static! {
@@ -129,6 +170,7 @@ lazy_static! {
         sockets: BTreeMap::new(),
         next_socket_fd: 1,
         port_allocations: BTreeMap::new(),
+        stats: NetworkStats::default(),
     });
 }
 
@@ -144,11 +186,14 @@ pub fn create_socket(domain: u32, socket_type: u32, protocol: u32) -> NetworkRes
     // Validate parameters
     match domain {
         AF_INET => {}, // IPv4 supported
+        AF_PACKET => {},
         AF_INET6 => return Err(NetworkError::AddressFamilyNotSupported), // IPv6 not implemented
         _ => return Err(NetworkError::AddressFamilyNotSupported),
     }
     
     match socket_type {
+        SOCK_RAW if domain == AF_PACKET => {}
+        _ if domain == AF_PACKET => return Err(NetworkError::SocketTypeNotSupported),
         SOCK_STREAM => {
             if protocol != 0 && protocol != IPPROTO_TCP {
                 return Err(NetworkError::ProtocolNotSupported);
@@ -162,10 +207,17 @@ pub fn create_socket(domain: u32, socket_type: u32, protocol: u32) -> NetworkRes
         _ => return Err(NetworkError::SocketTypeNotSupported),
     }
     
+    // Packet sockets take a NetworkRaw capability, and see frames of the ethertype given
+    let packet = match domain {
+        AF_PACKET => Some(packet::PacketSocket::open(current_pid as u32, protocol)?),
+        _ => None,
+    };
+    
     let socket_fd = network.next_socket_fd;
     network.next_socket_fd += 1;
     
-    let socket = Socket::new(domain, socket_type, protocol, current_pid as u32);
+    let mut socket = Socket::new(domain, socket_type, protocol, current_pid as u32);
+    socket.packet = packet;
     network.sockets.insert(socket_fd, socket);
     
     Ok(socket_fd)
@@ -175,6 +227,18 @@ pub fn bind_socket(socket_fd: u32, addr: &[u8]) -> NetworkResult<()> {
     let mut network = NETWORK_SYSTEM.lock();
     let current_pid = crate::process::get_current_process_id();
     
+    // Packet sockets bind to an interface rather than a port, and may bind again
+    if let Some(socket) = network.sockets.get_mut(&socket_fd) {
+        if let Some(packet) = socket.packet.as_mut() {
+            if socket.process_id != current_pid as u32 {
+                return Err(NetworkError::PermissionDenied);
+            }
+            packet.bind(addr)?;
+            socket.state = SocketState::Bound;
+            return Ok(());
+        }
+    }
+    
     // First, validate the socket and parse address
     let socket_addr = {
         let socket = network.sockets.get(&socket_fd)
@@ -265,7 +329,7 @@ pub fn accept_connection(socket_fd: u32) -> NetworkResult<u32> {
     };
     
     // Accept the first pending connection
-    let _client_fd = {
+    let client_fd = {
         let socket = network.sockets.get_mut(&socket_fd)
             .ok_or(NetworkError::InvalidSocket)?;
         socket.pending_connections.remove(0)
@@ -279,6 +343,12 @@ pub fn accept_connection(socket_fd: u32) -> NetworkResult<u32> {
     client_socket.state = SocketState::Connected;
     client_socket.local_addr = local_addr;
     
+    // Pair the two ends, unless the client closed while it waited in the backlog
+    if let Some(client) = network.sockets.get_mut(&client_fd) {
+        client.peer = Some(new_socket_fd);
+        client_socket.peer = Some(client_fd);
+    }
+    
     network.sockets.insert(new_socket_fd, client_socket);
     
     Ok(new_socket_fd)
@@ -351,6 +421,12 @@ pub fn send_data(socket_fd: u32, data: &[u8], _flags: u32) -> NetworkResult<usiz
         return Err(NetworkError::PermissionDenied);
     }
     
+    // Packet sockets send whole frames out of their interface
+    if socket.packet.is_some() {
+        drop(network);
+        return packet::send(socket_fd, data);
+    }
+    
     // Check state
     match socket.socket_type {
         SOCK_STREAM => {
@@ -366,16 +442,27 @@ pub fn send_data(socket_fd: u32, data: &[u8], _flags: u32) -> NetworkResult<usiz
         _ => return Err(NetworkError::ProtocolNotSupported),
     }
     
-    // Add data to send buffer (simplified)
-    socket.send_buffer.extend_from_slice(data);
-    
-    // In a real implementation, we would:
-    // 1. Fragment data into packets
-    // 2. Add TCP/UDP headers
-    // 3. Add IP headers
-    // 4. Send via network interface
+    // Local stream connections deliver straight to the peer's receive buffer
+    let peer = socket.peer;
+    match peer {
+        Some(peer_fd) => {
+            let peer_socket = network.sockets.get_mut(&peer_fd)
+                .ok_or(NetworkError::NotConnected)?;
+            peer_socket.receive_buffer.extend_from_slice(data);
+        }
+        None => {
+            // Add data to send buffer (simplified)
+            socket.send_buffer.extend_from_slice(data);
+            
+            // In a real implementation, we would:
+            // 1. Fragment data into packets
+            // 2. Add TCP/UDP headers
+            // 3. Add IP headers
+            // 4. Send via network interface
+        }
+    }
     
-    // For now, simulate successful send
+    network.stats.bytes_sent += data.len() as u64;
     Ok(data.len())
 }
 
@@ -391,6 +478,12 @@ pub fn receive_data(socket_fd: u32, length: usize, _flags: u32) -> NetworkResult
             return Err(NetworkError::PermissionDenied);
         }
     
+    // Packet sockets read whole frames, or pcap records of them
+    if socket.packet.is_some() {
+        drop(network);
+        return packet::receive(socket_fd, length);
+    }
+    
     // Check state
     match socket.socket_type {
         SOCK_STREAM => {
@@ -413,8 +506,9 @@ pub fn receive_data(socket_fd: u32, length: usize, _flags: u32) -> NetworkResult
     
     // Read data from receive buffer
     let to_read = core::cmp::min(length, socket.receive_buffer.len());
-    let data = socket.receive_buffer.drain(0..to_read).collect();
+    let data: Vec<u8> = socket.receive_buffer.drain(0..to_read).collect();
     
+    network.stats.bytes_received += data.len() as u64;
     Ok(data)
 }
 
@@ -463,6 +557,30 @@ pub fn get_socket_info(socket_fd: u32) -> NetworkResult<(SocketState, Option<Soc
     Ok((socket.state, socket.local_addr.clone(), socket.remote_addr.clone()))
 }
 
+// Get the process on the other end of a local stream connection
+pub fn get_peer_process_id(socket_fd: u32) -> NetworkResult<u32> {
+    let network = NETWORK_SYSTEM.lock();
+    let current_pid = crate::process::get_current_process_id();
+    
+    let socket = network.sockets.get(&socket_fd)
+        .ok_or(NetworkError::InvalidSocket)?;
+    
+    // Check ownership
+    if socket.process_id != current_pid as u32 {
+        return Err(NetworkError::PermissionDenied);
+    }
+    
+    socket.peer
+        .and_then(|peer_fd| network.sockets.get(&peer_fd))
+        .map(|peer| peer.process_id)
+        .ok_or(NetworkError::NotConnected)
+}
+
+// Get the byte counters of the socket layer
+pub fn get_network_stats() -> NetworkStats {
+    NETWORK_SYSTEM.lock().stats
+}
+
 // Clean up network resources for a process
 pub fn cleanup_process_network(process_id: u32) {
     let sockets_to_close: Vec<u32> = {
@@ -0,0 +1,416 @@
+//! Classic BPF
+//! Filters of the classic Berkeley Packet Filter machine run over whole Ethernet frames: a
+//! program is checked once when it is attached, so running it can neither loop nor touch
+//! memory outside the frame and its scratch words, and its return value is how much of the
+//! frame to keep, none when it is zero. Expressions in a small part of the tcpdump language
+//! compile to such programs.
+
+use alloc::vec::Vec;
+use core::net::Ipv4Addr;
+use super::{NetworkError, NetworkResult};
+
+/// Most instructions a program may have
+pub const MAX_INSTRUCTIONS: usize = 4096;
+/// Scratch words a program may store to and load from
+const MEMORY_WORDS: usize = 16;
+/// What a compiled filter keeps of a frame it passes: all of it
+const ACCEPT_ALL: u32 = 0x0004_0000;
+
+// Instruction classes
+const LD: u16 = 0x00;
+const LDX: u16 = 0x01;
+const ST: u16 = 0x02;
+const STX: u16 = 0x03;
+const ALU: u16 = 0x04;
+const JMP: u16 = 0x05;
+const RET: u16 = 0x06;
+const MISC: u16 = 0x07;
+
+// Load sizes
+const W: u16 = 0x00;
+const H: u16 = 0x08;
+const B: u16 = 0x10;
+
+// Load modes
+const IMM: u16 = 0x00;
+const ABS: u16 = 0x20;
+const IND: u16 = 0x40;
+const MEM: u16 = 0x60;
+const LEN: u16 = 0x80;
+const MSH: u16 = 0xa0;
+
+// ALU operations
+const ADD: u16 = 0x00;
+const SUB: u16 = 0x10;
+const MUL: u16 = 0x20;
+const DIV: u16 = 0x30;
+const OR: u16 = 0x40;
+const AND: u16 = 0x50;
+const LSH: u16 = 0x60;
+const RSH: u16 = 0x70;
+const NEG: u16 = 0x80;
+const MOD: u16 = 0x90;
+const XOR: u16 = 0xa0;
+
+// Jumps
+const JA: u16 = 0x00;
+const JEQ: u16 = 0x10;
+const JGT: u16 = 0x20;
+const JGE: u16 = 0x30;
+const JSET: u16 = 0x40;
+
+// Operand of an ALU operation or jump, and value returned
+const K: u16 = 0x00;
+const X: u16 = 0x08;
+const RET_A: u16 = 0x10;
+
+// Register transfers
+const TAX: u16 = 0x00;
+const TXA: u16 = 0x80;
+
+const ETHERTYPE_IPV4: u32 = 0x0800;
+const ETHERTYPE_ARP: u32 = 0x0806;
+const IPPROTO_ICMP: u32 = 1;
+const IPPROTO_TCP: u32 = 6;
+const IPPROTO_UDP: u32 = 17;
+/// Offsets into a frame of the fields expressions test
+const ETHERTYPE_OFFSET: u32 = 12;
+const IPV4_OFFSET: u32 = 14;
+const PROTOCOL_OFFSET: u32 = IPV4_OFFSET + 9;
+const FRAGMENT_OFFSET: u32 = IPV4_OFFSET + 6;
+const SOURCE_OFFSET: u32 = IPV4_OFFSET + 12;
+const DESTINATION_OFFSET: u32 = IPV4_OFFSET + 16;
+
+/// One instruction, laid out as a `struct sock_filter`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub code: u16,
+    /// Instructions skipped when a conditional jump is taken
+    pub jt: u8,
+    /// Instructions skipped when it is not
+    pub jf: u8,
+    pub k: u32,
+}
+
+impl Instruction {
+    pub const LEN: usize = 8;
+
+    pub const fn new(code: u16, jt: u8, jf: u8, k: u32) -> Self {
+        Self { code, jt, jf, k }
+    }
+
+    const fn statement(code: u16, k: u32) -> Self {
+        Self::new(code, 0, 0, k)
+    }
+}
+
+/// A program from the bytes of an array of `struct sock_filter`
+pub fn decode(bytes: &[u8]) -> NetworkResult<Vec<Instruction>> {
+    if bytes.len() % Instruction::LEN != 0 {
+        return Err(NetworkError::InvalidFilter);
+    }
+    Ok(bytes
+        .chunks_exact(Instruction::LEN)
+        .map(|raw| Instruction {
+            code: u16::from_le_bytes([raw[0], raw[1]]),
+            jt: raw[2],
+            jf: raw[3],
+            k: u32::from_le_bytes([raw[4], raw[5], raw[6], raw[7]]),
+        })
+        .collect())
+}
+
+/// Check that a program can be run: every instruction known, scratch words in range, no
+/// division by a zero constant, every jump forward to an instruction, and a return last
+pub fn validate(program: &[Instruction]) -> NetworkResult<()> {
+    if program.is_empty() || program.len() > MAX_INSTRUCTIONS {
+        return Err(NetworkError::InvalidFilter);
+    }
+    for (pc, instruction) in program.iter().enumerate() {
+        let code = instruction.code;
+        let k = instruction.k as usize;
+        // Instructions left after this one, which a jump may skip past but not leave
+        let remaining = program.len() - pc - 1;
+        let valid = code <= 0xff && match code & 0x07 {
+            LD => match code & 0xe0 {
+                IMM | LEN => code & 0x18 == W,
+                ABS | IND => matches!(code & 0x18, W | H | B),
+                MEM => code & 0x18 == W && k < MEMORY_WORDS,
+                _ => false,
+            },
+            LDX => match code & 0xe0 {
+                IMM | LEN => code & 0x18 == W,
+                MEM => code & 0x18 == W && k < MEMORY_WORDS,
+                MSH => code & 0x18 == B,
+                _ => false,
+            },
+            ST | STX => code & !0x07 == 0 && k < MEMORY_WORDS,
+            ALU => match code & 0xf0 {
+                NEG => code & 0x08 == K,
+                DIV | MOD => code & 0x08 == X || instruction.k != 0,
+                ADD | SUB | MUL | OR | AND | LSH | RSH | XOR => true,
+                _ => false,
+            },
+            JMP => match code & 0xf0 {
+                JA => code & 0x08 == K && k < remaining,
+                JEQ | JGT | JGE | JSET => usize::from(instruction.jt) < remaining && usize::from(instruction.jf) < remaining,
+                _ => false,
+            },
+            RET => matches!(code & 0x18, K | X | RET_A) && code & 0xe0 == 0,
+            MISC => matches!(code & 0xf8, TAX | TXA),
+            _ => false,
+        };
+        if !valid {
+            return Err(NetworkError::InvalidFilter);
+        }
+    }
+    match program.last() {
+        Some(last) if last.code & 0x07 == RET => Ok(()),
+        _ => Err(NetworkError::InvalidFilter),
+    }
+}
+
+/// The big-endian word, half-word or byte at `offset` in `packet`
+fn fetch(packet: &[u8], offset: u32, size: u16) -> Option<u32> {
+    let offset = usize::try_from(offset).ok()?;
+    let len = match size {
+        W => 4,
+        H => 2,
+        _ => 1,
+    };
+    let bytes = packet.get(offset..offset.checked_add(len)?)?;
+    Some(bytes.iter().fold(0u32, |value, &byte| (value << 8) | u32::from(byte)))
+}
+
+/// Run a validated program over a packet, returning how many of its bytes to keep. A load
+/// past the end of the packet rejects it, as does a division by a zero index register
+pub fn run(program: &[Instruction], packet: &[u8]) -> u32 {
+    let (mut a, mut x) = (0u32, 0u32);
+    let mut memory = [0u32; MEMORY_WORDS];
+    let mut pc = 0;
+    while let Some(instruction) = program.get(pc) {
+        pc += 1;
+        let code = instruction.code;
+        let k = instruction.k;
+        let operand = if code & 0x08 == X { x } else { k };
+        match code & 0x07 {
+            LD => {
+                let loaded = match code & 0xe0 {
+                    IMM => Some(k),
+                    ABS => fetch(packet, k, code & 0x18),
+                    IND => fetch(packet, x.wrapping_add(k), code & 0x18),
+                    MEM => memory.get(k as usize).copied(),
+                    LEN => Some(packet.len() as u32),
+                    _ => None,
+                };
+                match loaded {
+                    Some(value) => a = value,
+                    None => return 0,
+                }
+            }
+            LDX => {
+                let loaded = match code & 0xe0 {
+                    IMM => Some(k),
+                    MEM => memory.get(k as usize).copied(),
+                    LEN => Some(packet.len() as u32),
+                    MSH => fetch(packet, k, B).map(|byte| (byte & 0x0f) * 4),
+                    _ => None,
+                };
+                match loaded {
+                    Some(value) => x = value,
+                    None => return 0,
+                }
+            }
+            ST | STX => {
+                let value = if code & 0x07 == ST { a } else { x };
+                match memory.get_mut(k as usize) {
+                    Some(word) => *word = value,
+                    None => return 0,
+                }
+            }
+            ALU => {
+                a = match code & 0xf0 {
+                    ADD => a.wrapping_add(operand),
+                    SUB => a.wrapping_sub(operand),
+                    MUL => a.wrapping_mul(operand),
+                    DIV | MOD if operand == 0 => return 0,
+                    DIV => a / operand,
+                    MOD => a % operand,
+                    OR => a | operand,
+                    AND => a & operand,
+                    LSH => a.checked_shl(operand).unwrap_or(0),
+                    RSH => a.checked_shr(operand).unwrap_or(0),
+                    NEG => a.wrapping_neg(),
+                    XOR => a ^ operand,
+                    _ => return 0,
+                };
+            }
+            JMP => {
+                let taken = match code & 0xf0 {
+                    JA => {
+                        pc += k as usize;
+                        continue;
+                    }
+                    JEQ => a == operand,
+                    JGT => a > operand,
+                    JGE => a >= operand,
+                    JSET => a & operand != 0,
+                    _ => return 0,
+                };
+                pc += usize::from(if taken { instruction.jt } else { instruction.jf });
+            }
+            RET => {
+                return match code & 0x18 {
+                    K => k,
+                    X => x,
+                    _ => a,
+                };
+            }
+            MISC => match code & 0xf8 {
+                TAX => x = a,
+                _ => a = x,
+            },
+            _ => return 0,
+        }
+    }
+    0
+}
+
+/// Marks the jump offsets that lead to the rejecting return, until it is placed
+const REJECT: u8 = u8::MAX;
+
+/// A program under construction, whose failed tests all jump to one rejecting return
+struct Compiler {
+    program: Vec<Instruction>,
+}
+
+impl Compiler {
+    fn push(&mut self, instruction: Instruction) {
+        self.program.push(instruction);
+    }
+
+    /// Go on when the accumulator equals `value`, reject the frame when not
+    fn require(&mut self, value: u32) {
+        self.push(Instruction::new(JMP | JEQ | K, 0, REJECT, value));
+    }
+
+    fn ethertype(&mut self, ethertype: u32) {
+        self.push(Instruction::statement(LD | H | ABS, ETHERTYPE_OFFSET));
+        self.require(ethertype);
+    }
+
+    fn protocol(&mut self, protocol: u32) {
+        self.ethertype(ETHERTYPE_IPV4);
+        self.push(Instruction::statement(LD | B | ABS, PROTOCOL_OFFSET));
+        self.require(protocol);
+    }
+
+    /// Pass frames whose IPv4 source, destination or either is `address`
+    fn host(&mut self, direction: Option<Direction>, address: Ipv4Addr) {
+        self.ethertype(ETHERTYPE_IPV4);
+        let load = |compiler: &mut Self, offset| compiler.push(Instruction::statement(LD | W | ABS, offset));
+        self.either(direction, u32::from(address), load, SOURCE_OFFSET, DESTINATION_OFFSET);
+    }
+
+    /// Pass unfragmented TCP and UDP frames whose source port, destination port or either is `port`
+    fn port(&mut self, direction: Option<Direction>, port: u16) {
+        self.ethertype(ETHERTYPE_IPV4);
+        self.push(Instruction::statement(LD | B | ABS, PROTOCOL_OFFSET));
+        self.push(Instruction::new(JMP | JEQ | K, 1, 0, IPPROTO_TCP));
+        self.require(IPPROTO_UDP);
+        // Later fragments carry no ports
+        self.push(Instruction::statement(LD | H | ABS, FRAGMENT_OFFSET));
+        self.push(Instruction::new(JMP | JSET | K, REJECT, 0, 0x1fff));
+        self.push(Instruction::statement(LDX | B | MSH, IPV4_OFFSET));
+        // Ports lead the TCP or UDP header, past the IPv4 header the index register now spans
+        let load = |compiler: &mut Self, offset| compiler.push(Instruction::statement(LD | H | IND, offset));
+        self.either(direction, u32::from(port), load, IPV4_OFFSET, IPV4_OFFSET + 2);
+    }
+
+    /// Require the field `load` brings from `source` or `destination`, or from either, to be `value`
+    fn either(&mut self, direction: Option<Direction>, value: u32, load: fn(&mut Self, u32), source: u32, destination: u32) {
+        match direction {
+            Some(Direction::Source) => {
+                load(self, source);
+                self.require(value);
+            }
+            Some(Direction::Destination) => {
+                load(self, destination);
+                self.require(value);
+            }
+            None => {
+                load(self, source);
+                // A match skips testing the destination
+                self.push(Instruction::new(JMP | JEQ | K, 2, 0, value));
+                load(self, destination);
+                self.require(value);
+            }
+        }
+    }
+
+    /// End the program with its returns, pointing every failed test at the rejecting one
+    fn finish(mut self) -> NetworkResult<Vec<Instruction>> {
+        self.push(Instruction::statement(RET | K, ACCEPT_ALL));
+        self.push(Instruction::statement(RET | K, 0));
+        let reject = self.program.len() - 1;
+        for (pc, instruction) in self.program.iter_mut().enumerate() {
+            if instruction.code & 0x07 != JMP {
+                continue;
+            }
+            for target in [&mut instruction.jt, &mut instruction.jf] {
+                if *target == REJECT {
+                    *target = u8::try_from(reject - pc - 1).map_err(|_| NetworkError::InvalidFilter)?;
+                }
+            }
+        }
+        validate(&self.program)?;
+        Ok(self.program)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Source,
+    Destination,
+}
+
+/// Compile a filter expression: primitives joined by `and`, each of `arp`, `ip`, `tcp`,
+/// `udp`, `icmp`, `[src|dst] host ADDR` or `[src|dst] port PORT`. An empty expression
+/// passes every frame
+pub fn compile(expression: &str) -> NetworkResult<Vec<Instruction>> {
+    let mut compiler = Compiler { program: Vec::new() };
+    let mut tokens = expression.split_whitespace().peekable();
+    while tokens.peek().is_some() {
+        let direction = match tokens.peek() {
+            Some(&"src") => Some(Direction::Source),
+            Some(&"dst") => Some(Direction::Destination),
+            _ => None,
+        };
+        if direction.is_some() {
+            tokens.next();
+        }
+        match (tokens.next(), direction) {
+            (Some("arp"), None) => compiler.ethertype(ETHERTYPE_ARP),
+            (Some("ip"), None) => compiler.ethertype(ETHERTYPE_IPV4),
+            (Some("tcp"), None) => compiler.protocol(IPPROTO_TCP),
+            (Some("udp"), None) => compiler.protocol(IPPROTO_UDP),
+            (Some("icmp"), None) => compiler.protocol(IPPROTO_ICMP),
+            (Some("host"), direction) => {
+                let address = tokens.next().and_then(|address| address.parse().ok()).ok_or(NetworkError::InvalidFilter)?;
+                compiler.host(direction, address);
+            }
+            (Some("port"), direction) => {
+                let port = tokens.next().and_then(|port| port.parse().ok()).ok_or(NetworkError::InvalidFilter)?;
+                compiler.port(direction, port);
+            }
+            _ => return Err(NetworkError::InvalidFilter),
+        }
+        match tokens.next() {
+            None => break,
+            Some("and" | "&&") if tokens.peek().is_some() => {}
+            Some(_) => return Err(NetworkError::InvalidFilter),
+        }
+    }
+    compiler.finish()
+}
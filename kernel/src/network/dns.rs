@@ -0,0 +1,591 @@
+//! DNS resolution
+//! Queries for A, AAAA, CNAME and PTR records, sent over UDP to the name servers listed in
+//! `/etc/resolv.conf` (or to QEMU's user-network server when it lists none) and answered
+//! with their TTLs. Address lookups follow CNAME chains, asking again for the target when a
+//! server gives only the alias; reverse lookups ask for the PTR record of an address's
+//! `in-addr.arpa` or `ip6.arpa` name. Messages are encoded and decoded here in full, name
+//! compression included.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddrV4};
+use core::str::FromStr;
+use core::sync::atomic::{AtomicU16, Ordering};
+use super::{udp, NetworkError, NetworkResult};
+
+pub const RESOLV_CONF: &str = "/etc/resolv.conf";
+/// The resolver QEMU's user networking provides
+const DEFAULT_NAME_SERVER: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 3);
+pub const DNS_PORT: u16 = 53;
+const QUERY_TIMEOUT_MS: u64 = 2000;
+/// Aliases followed before a lookup gives up on a chain
+const MAX_CNAME_HOPS: usize = 8;
+const CLASS_IN: u16 = 1;
+const MAX_NAME_LEN: usize = 253;
+const MAX_LABEL_LEN: usize = 63;
+/// Compression pointers followed in one name, more than a well-formed message needs
+const MAX_POINTERS: usize = 32;
+
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_AUTHORITATIVE: u16 = 0x0400;
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+const FLAG_RECURSION_AVAILABLE: u16 = 0x0080;
+
+pub const RCODE_NO_ERROR: u8 = 0;
+pub const RCODE_SERVER_FAILURE: u8 = 2;
+pub const RCODE_NAME_ERROR: u8 = 3;
+pub const RCODE_REFUSED: u8 = 5;
+
+static NEXT_ID: AtomicU16 = AtomicU16::new(0);
+
+/// Record types looked up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordType {
+    A,
+    Aaaa,
+    Cname,
+    Ptr,
+}
+
+impl RecordType {
+    fn code(self) -> u16 {
+        match self {
+            Self::A => 1,
+            Self::Cname => 5,
+            Self::Ptr => 12,
+            Self::Aaaa => 28,
+        }
+    }
+
+    fn from_code(code: u16) -> Option<Self> {
+        match code {
+            1 => Some(Self::A),
+            5 => Some(Self::Cname),
+            12 => Some(Self::Ptr),
+            28 => Some(Self::Aaaa),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::A => "A",
+            Self::Aaaa => "AAAA",
+            Self::Cname => "CNAME",
+            Self::Ptr => "PTR",
+        }
+    }
+}
+
+impl FromStr for RecordType {
+    type Err = DnsError;
+
+    fn from_str(text: &str) -> Result<Self, DnsError> {
+        match text.to_ascii_uppercase().as_str() {
+            "A" => Ok(Self::A),
+            "AAAA" => Ok(Self::Aaaa),
+            "CNAME" => Ok(Self::Cname),
+            "PTR" => Ok(Self::Ptr),
+            _ => Err(DnsError::UnsupportedType),
+        }
+    }
+}
+
+/// What a record holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Cname(String),
+    Ptr(String),
+}
+
+impl RecordData {
+    pub fn record_type(&self) -> RecordType {
+        match self {
+            Self::A(_) => RecordType::A,
+            Self::Aaaa(_) => RecordType::Aaaa,
+            Self::Cname(_) => RecordType::Cname,
+            Self::Ptr(_) => RecordType::Ptr,
+        }
+    }
+}
+
+/// A resource record. Names are kept without the root's trailing dot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub name: String,
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Question {
+    pub name: String,
+    pub record_type: RecordType,
+}
+
+/// A DNS message, as much of it as lookups use: the header, the questions and the answer
+/// section. Records of other types are skipped when decoding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub id: u16,
+    pub response: bool,
+    pub authoritative: bool,
+    pub recursion_desired: bool,
+    pub recursion_available: bool,
+    pub rcode: u8,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+}
+
+#[derive(Debug)]
+pub enum DnsError {
+    /// No server could be reached
+    Network(NetworkError),
+    /// The reply was cut short, or does not answer the query
+    Malformed,
+    /// The name does not exist (NXDOMAIN)
+    NameError,
+    /// The server failed, or refused, with this response code
+    ServerError(u8),
+    InvalidName,
+    UnsupportedType,
+    /// An alias chain loops or runs too long
+    CnameLoop,
+}
+
+impl From<NetworkError> for DnsError {
+    fn from(error: NetworkError) -> Self {
+        Self::Network(error)
+    }
+}
+
+/// Whether two names are the same, which DNS compares without regard to case
+fn same_name(a: &str, b: &str) -> bool {
+    a.trim_end_matches('.').eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
+
+fn check_name(name: &str) -> Result<(), DnsError> {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    if name.len() > MAX_NAME_LEN || (!name.is_empty() && name.split('.').any(|label| label.is_empty() || label.len() > MAX_LABEL_LEN)) {
+        return Err(DnsError::InvalidName);
+    }
+    Ok(())
+}
+
+/// Writes messages, pointing repeated names back at their first occurrence
+struct Encoder {
+    bytes: Vec<u8>,
+    names: BTreeMap<String, u16>,
+}
+
+impl Encoder {
+    fn name(&mut self, name: &str) {
+        let name = name.strip_suffix('.').unwrap_or(name);
+        let mut rest = name;
+        while !rest.is_empty() {
+            let key = rest.to_ascii_lowercase();
+            if let Some(&offset) = self.names.get(&key) {
+                self.bytes.extend_from_slice(&(0xc000 | offset).to_be_bytes());
+                return;
+            }
+            if self.bytes.len() < 0x3fff {
+                self.names.insert(key, self.bytes.len() as u16);
+            }
+            let (label, tail) = rest.split_once('.').unwrap_or((rest, ""));
+            self.bytes.push(label.len() as u8);
+            self.bytes.extend_from_slice(label.as_bytes());
+            rest = tail;
+        }
+        self.bytes.push(0);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], DnsError> {
+        let end = self.position.checked_add(count).filter(|&end| end <= self.bytes.len()).ok_or(DnsError::Malformed)?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, DnsError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, DnsError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// A name, following compression pointers to wherever its labels are
+    fn name(&mut self) -> Result<String, DnsError> {
+        let mut name = String::new();
+        let mut position = self.position;
+        let mut resume = None;
+        let mut pointers = 0;
+        loop {
+            let length = usize::from(*self.bytes.get(position).ok_or(DnsError::Malformed)?);
+            match length {
+                0 => {
+                    position += 1;
+                    break;
+                }
+                _ if length & 0xc0 == 0xc0 => {
+                    let low = usize::from(*self.bytes.get(position + 1).ok_or(DnsError::Malformed)?);
+                    pointers += 1;
+                    if pointers > MAX_POINTERS {
+                        return Err(DnsError::Malformed);
+                    }
+                    resume.get_or_insert(position + 2);
+                    position = ((length & 0x3f) << 8) | low;
+                }
+                _ if length <= MAX_LABEL_LEN => {
+                    let label = self.bytes.get(position + 1..position + 1 + length).ok_or(DnsError::Malformed)?;
+                    if !name.is_empty() {
+                        name.push('.');
+                    }
+                    name.push_str(core::str::from_utf8(label).map_err(|_| DnsError::Malformed)?);
+                    if name.len() > MAX_NAME_LEN {
+                        return Err(DnsError::Malformed);
+                    }
+                    position += 1 + length;
+                }
+                _ => return Err(DnsError::Malformed),
+            }
+        }
+        self.position = resume.unwrap_or(position);
+        Ok(name)
+    }
+}
+
+impl Message {
+    /// A recursive query for one name and type
+    pub fn query(id: u16, name: &str, record_type: RecordType) -> Self {
+        Self {
+            id,
+            response: false,
+            authoritative: false,
+            recursion_desired: true,
+            recursion_available: false,
+            rcode: RCODE_NO_ERROR,
+            questions: alloc::vec![Question { name: name.trim_end_matches('.').to_string(), record_type }],
+            answers: Vec::new(),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder { bytes: Vec::with_capacity(512), names: BTreeMap::new() };
+        let mut flags = u16::from(self.rcode & 0x0f);
+        for (set, flag) in [
+            (self.response, FLAG_RESPONSE),
+            (self.authoritative, FLAG_AUTHORITATIVE),
+            (self.recursion_desired, FLAG_RECURSION_DESIRED),
+            (self.recursion_available, FLAG_RECURSION_AVAILABLE),
+        ] {
+            if set {
+                flags |= flag;
+            }
+        }
+        encoder.u16(self.id);
+        encoder.u16(flags);
+        encoder.u16(self.questions.len() as u16);
+        encoder.u16(self.answers.len() as u16);
+        encoder.u16(0);
+        encoder.u16(0);
+        for question in &self.questions {
+            encoder.name(&question.name);
+            encoder.u16(question.record_type.code());
+            encoder.u16(CLASS_IN);
+        }
+        for record in &self.answers {
+            encoder.name(&record.name);
+            encoder.u16(record.data.record_type().code());
+            encoder.u16(CLASS_IN);
+            encoder.bytes.extend_from_slice(&record.ttl.to_be_bytes());
+            let length_at = encoder.bytes.len();
+            encoder.u16(0);
+            match &record.data {
+                RecordData::A(address) => encoder.bytes.extend_from_slice(&address.octets()),
+                RecordData::Aaaa(address) => encoder.bytes.extend_from_slice(&address.octets()),
+                RecordData::Cname(name) | RecordData::Ptr(name) => encoder.name(name),
+            }
+            let length = (encoder.bytes.len() - length_at - 2) as u16;
+            encoder.bytes[length_at..length_at + 2].copy_from_slice(&length.to_be_bytes());
+        }
+        encoder.bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, DnsError> {
+        let mut decoder = Decoder { bytes, position: 0 };
+        let id = decoder.u16()?;
+        let flags = decoder.u16()?;
+        let question_count = decoder.u16()?;
+        let answer_count = decoder.u16()?;
+        decoder.take(4)?;
+
+        let mut questions = Vec::new();
+        for _ in 0..question_count {
+            let name = decoder.name()?;
+            let record_type = decoder.u16()?;
+            decoder.u16()?;
+            // A question of another type is kept out, though it still had to be read past
+            if let Some(record_type) = RecordType::from_code(record_type) {
+                questions.push(Question { name, record_type });
+            }
+        }
+
+        let mut answers = Vec::new();
+        for _ in 0..answer_count {
+            let name = decoder.name()?;
+            let record_type = decoder.u16()?;
+            let class = decoder.u16()?;
+            let ttl = decoder.u32()?;
+            let length = usize::from(decoder.u16()?);
+            let end = decoder.position + length;
+            let data = match RecordType::from_code(record_type).filter(|_| class == CLASS_IN) {
+                Some(RecordType::A) if length == 4 => {
+                    let octets = decoder.take(4)?;
+                    Some(RecordData::A(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3])))
+                }
+                Some(RecordType::Aaaa) if length == 16 => {
+                    let mut octets = [0u8; 16];
+                    octets.copy_from_slice(decoder.take(16)?);
+                    Some(RecordData::Aaaa(Ipv6Addr::from(octets)))
+                }
+                Some(RecordType::Cname) => Some(RecordData::Cname(decoder.name()?)),
+                Some(RecordType::Ptr) => Some(RecordData::Ptr(decoder.name()?)),
+                _ => None,
+            };
+            if decoder.position > end || end > bytes.len() {
+                return Err(DnsError::Malformed);
+            }
+            decoder.position = end;
+            // The top bit of a TTL is reserved: such a TTL is read as zero
+            let ttl = if ttl > i32::MAX as u32 { 0 } else { ttl };
+            if let Some(data) = data {
+                answers.push(Record { name, ttl, data });
+            }
+        }
+
+        Ok(Self {
+            id,
+            response: flags & FLAG_RESPONSE != 0,
+            authoritative: flags & FLAG_AUTHORITATIVE != 0,
+            recursion_desired: flags & FLAG_RECURSION_DESIRED != 0,
+            recursion_available: flags & FLAG_RECURSION_AVAILABLE != 0,
+            rcode: (flags & 0x0f) as u8,
+            questions,
+            answers,
+        })
+    }
+}
+
+/// The `in-addr.arpa` or `ip6.arpa` name an address's PTR record has
+pub fn reverse_name(address: IpAddr) -> String {
+    match address {
+        IpAddr::V4(address) => {
+            let [a, b, c, d] = address.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a)
+        }
+        IpAddr::V6(address) => {
+            let mut name = String::new();
+            for byte in address.octets().iter().rev() {
+                let _ = write!(name, "{:x}.{:x}.", byte & 0x0f, byte >> 4);
+            }
+            name.push_str("ip6.arpa");
+            name
+        }
+    }
+}
+
+/// How queries reach a name server
+pub trait Transport {
+    /// Send `query` to `server` and return its reply
+    fn exchange(&self, server: SocketAddrV4, query: &[u8], timeout_ms: u64) -> NetworkResult<Vec<u8>>;
+}
+
+impl<T: Transport> Transport for &T {
+    fn exchange(&self, server: SocketAddrV4, query: &[u8], timeout_ms: u64) -> NetworkResult<Vec<u8>> {
+        (**self).exchange(server, query, timeout_ms)
+    }
+}
+
+/// Queries sent in UDP datagrams through the kernel's interfaces
+pub struct UdpTransport;
+
+impl Transport for UdpTransport {
+    fn exchange(&self, server: SocketAddrV4, query: &[u8], timeout_ms: u64) -> NetworkResult<Vec<u8>> {
+        udp::exchange(server, query, timeout_ms)
+    }
+}
+
+/// The answer to a lookup
+#[derive(Debug, Clone)]
+pub struct Answer {
+    /// The server that answered
+    pub server: SocketAddrV4,
+    /// Whether it is the authority for the name rather than answering from its cache
+    pub authoritative: bool,
+    /// The alias chain from the name asked for, then the records of the type asked for
+    pub records: Vec<Record>,
+    /// Time from the first query going out to the last answer arriving
+    pub elapsed_ms: u64,
+}
+
+/// The name servers of `/etc/resolv.conf`, one `nameserver ADDRESS` line each
+pub fn configured_servers() -> Vec<SocketAddrV4> {
+    let config = crate::filesystem::read_file(RESOLV_CONF).ok().and_then(|data| String::from_utf8(data).ok());
+    let servers: Vec<SocketAddrV4> = config
+        .iter()
+        .flat_map(|config| config.lines())
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("nameserver"), Some(address)) => address.parse().ok(),
+                _ => None,
+            }
+        })
+        .map(|address| SocketAddrV4::new(address, DNS_PORT))
+        .collect();
+    if servers.is_empty() {
+        alloc::vec![SocketAddrV4::new(DEFAULT_NAME_SERVER, DNS_PORT)]
+    } else {
+        servers
+    }
+}
+
+/// Asks name servers, in order, until one answers
+pub struct Resolver<T: Transport> {
+    servers: Vec<SocketAddrV4>,
+    transport: T,
+}
+
+impl Resolver<UdpTransport> {
+    /// A resolver for the configured name servers
+    pub fn system() -> Self {
+        Self::new(configured_servers(), UdpTransport)
+    }
+}
+
+impl<T: Transport> Resolver<T> {
+    pub fn new(servers: Vec<SocketAddrV4>, transport: T) -> Self {
+        Self { servers, transport }
+    }
+
+    pub fn servers(&self) -> &[SocketAddrV4] {
+        &self.servers
+    }
+
+    /// A resolver asking `servers` the same way
+    pub fn with_servers(&self, servers: Vec<SocketAddrV4>) -> Resolver<&T> {
+        Resolver { servers, transport: &self.transport }
+    }
+
+    /// Send one query, to each server in turn until one replies. A reply is taken as final
+    /// unless the server failed or refused, which the next server may not
+    pub fn query(&self, name: &str, record_type: RecordType) -> Result<(SocketAddrV4, Message), DnsError> {
+        check_name(name)?;
+        let mut last_error = DnsError::Network(NetworkError::NetworkUnreachable);
+        for &server in &self.servers {
+            // Counter and clock mixed, so that IDs do not repeat from one boot to the next
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed) ^ (crate::time::get_uptime_ms() as u16).rotate_left(7);
+            let query = Message::query(id, name, record_type);
+            let reply = match self.transport.exchange(server, &query.encode(), QUERY_TIMEOUT_MS) {
+                Ok(reply) => reply,
+                Err(error) => {
+                    last_error = DnsError::Network(error);
+                    continue;
+                }
+            };
+            let message = match Message::decode(&reply) {
+                Ok(message) => message,
+                Err(error) => {
+                    last_error = error;
+                    continue;
+                }
+            };
+            let answers_query = message.response
+                && message.id == id
+                && message.questions.len() == 1
+                && same_name(&message.questions[0].name, name)
+                && message.questions[0].record_type == record_type;
+            if !answers_query {
+                last_error = DnsError::Malformed;
+                continue;
+            }
+            match message.rcode {
+                RCODE_NO_ERROR => return Ok((server, message)),
+                RCODE_NAME_ERROR => return Err(DnsError::NameError),
+                rcode => last_error = DnsError::ServerError(rcode),
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Records of `record_type` for `name`. Aliases met on the way come first in the answer,
+    /// in the order they lead from one to the next
+    pub fn lookup(&self, name: &str, record_type: RecordType) -> Result<Answer, DnsError> {
+        let started = crate::time::get_uptime_ms();
+        let mut records = Vec::new();
+        let mut current = name.trim_end_matches('.').to_string();
+        let mut hops = 0;
+        loop {
+            let asked = current.clone();
+            let (server, message) = self.query(&asked, record_type)?;
+            let authoritative = message.authoritative;
+            // Walk the chain as far as this answer goes
+            loop {
+                let found: Vec<Record> = message
+                    .answers
+                    .iter()
+                    .filter(|record| same_name(&record.name, &current) && record.data.record_type() == record_type)
+                    .cloned()
+                    .collect();
+                if !found.is_empty() || record_type == RecordType::Cname {
+                    records.extend(found);
+                    return Ok(Answer { server, authoritative, records, elapsed_ms: crate::time::get_uptime_ms() - started });
+                }
+                let alias = message.answers.iter().find_map(|record| match &record.data {
+                    RecordData::Cname(target) if same_name(&record.name, &current) => Some((record, target)),
+                    _ => None,
+                });
+                match alias {
+                    Some((record, target)) => {
+                        hops += 1;
+                        if hops > MAX_CNAME_HOPS || records.iter().any(|seen: &Record| same_name(&seen.name, target)) {
+                            return Err(DnsError::CnameLoop);
+                        }
+                        records.push(record.clone());
+                        current = target.clone();
+                    }
+                    None => break,
+                }
+            }
+            // The name asked about has no records of the type. When the server gave an alias
+            // but not what it stands for, the target is asked about next
+            if same_name(&current, &asked) {
+                return Ok(Answer { server, authoritative, records, elapsed_ms: crate::time::get_uptime_ms() - started });
+            }
+        }
+    }
+
+    /// The names an address's PTR records give
+    pub fn reverse(&self, address: IpAddr) -> Result<Answer, DnsError> {
+        self.lookup(&reverse_name(address), RecordType::Ptr)
+    }
+}
@@ -0,0 +1,373 @@
+//! Network interfaces and routes
+//! The loopback interface and an Ethernet interface for each attached virtio-net device, with
+//! their state and IPv4 addresses, and the routing table: a route to the subnet of each address
+//! on an interface that is up, and the static routes added through them. Interfaces follow the
+//! devices as they attach and detach, taking the next free `ethN` name.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::net::Ipv4Addr;
+use core::str::FromStr;
+use spin::Mutex;
+use super::{NetworkError, NetworkResult};
+use crate::drivers::virtio::{self, NetStatistics, VirtioNet};
+
+pub const LOOPBACK_NAME: &str = "lo";
+const LOOPBACK_MTU: u32 = 65536;
+const ETHERNET_MTU: u32 = 1500;
+
+/// An IPv4 address and the length of its network prefix, `10.0.2.15/24`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Ipv4Cidr {
+    pub address: Ipv4Addr,
+    pub prefix_len: u8,
+}
+
+impl Ipv4Cidr {
+    pub fn new(address: Ipv4Addr, prefix_len: u8) -> NetworkResult<Self> {
+        if prefix_len > 32 {
+            return Err(NetworkError::InvalidAddress);
+        }
+        Ok(Self { address, prefix_len })
+    }
+
+    /// The prefix length of a netmask such as `255.255.255.0`, whose ones must lead
+    pub fn prefix_of_netmask(netmask: Ipv4Addr) -> NetworkResult<u8> {
+        let mask = u32::from(netmask);
+        let prefix_len = mask.leading_ones();
+        if mask.checked_shl(prefix_len).unwrap_or(0) != 0 {
+            return Err(NetworkError::InvalidAddress);
+        }
+        Ok(prefix_len as u8)
+    }
+
+    fn mask(&self) -> u32 {
+        u32::MAX.checked_shl(32 - u32::from(self.prefix_len)).unwrap_or(0)
+    }
+
+    pub fn netmask(&self) -> Ipv4Addr {
+        Ipv4Addr::from(self.mask())
+    }
+
+    /// The network itself, with the host bits cleared
+    pub fn network(&self) -> Self {
+        Self { address: Ipv4Addr::from(u32::from(self.address) & self.mask()), prefix_len: self.prefix_len }
+    }
+
+    pub fn broadcast(&self) -> Ipv4Addr {
+        Ipv4Addr::from(u32::from(self.address) | !self.mask())
+    }
+
+    pub fn contains(&self, address: Ipv4Addr) -> bool {
+        (u32::from(address) ^ u32::from(self.address)) & self.mask() == 0
+    }
+}
+
+impl fmt::Display for Ipv4Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+/// `10.0.2.15/24`, or a bare address as a `/32`
+impl FromStr for Ipv4Cidr {
+    type Err = NetworkError;
+
+    fn from_str(text: &str) -> NetworkResult<Self> {
+        let (address, prefix_len) = match text.split_once('/') {
+            Some((address, prefix_len)) => (address, prefix_len.parse().map_err(|_| NetworkError::InvalidAddress)?),
+            None => (text, 32),
+        };
+        Self::new(address.parse().map_err(|_| NetworkError::InvalidAddress)?, prefix_len)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterfaceKind {
+    Loopback,
+    Ethernet,
+}
+
+/// An interface as it stands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interface {
+    /// Number of the interface, never reused for another
+    pub index: u32,
+    pub name: String,
+    pub kind: InterfaceKind,
+    pub mac_address: [u8; 6],
+    pub mtu: u32,
+    /// Brought up, to carry traffic and routes
+    pub up: bool,
+    pub addresses: Vec<Ipv4Cidr>,
+    /// Traffic counted by the device; loopback has no device to count it
+    pub statistics: Option<NetStatistics>,
+}
+
+/// A route to `destination`, through `gateway` when it is not on the link of `interface`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub destination: Ipv4Cidr,
+    pub gateway: Option<Ipv4Addr>,
+    pub interface: String,
+    /// For the route to the subnet of an address, that address, as the source of traffic
+    pub source: Option<Ipv4Addr>,
+    pub metric: u32,
+}
+
+struct InterfaceEntry {
+    interface: Interface,
+    device: Option<Arc<Mutex<VirtioNet>>>,
+}
+
+struct InterfaceTable {
+    interfaces: Vec<InterfaceEntry>,
+    static_routes: Vec<Route>,
+    next_index: u32,
+}
+
+static INTERFACES: Mutex<InterfaceTable> = Mutex::new(InterfaceTable {
+    interfaces: Vec::new(),
+    static_routes: Vec::new(),
+    next_index: 1,
+});
+
+impl InterfaceTable {
+    fn add(&mut self, name: String, kind: InterfaceKind, mac_address: [u8; 6], mtu: u32, device: Option<Arc<Mutex<VirtioNet>>>) -> &mut InterfaceEntry {
+        let interface = Interface {
+            index: self.next_index,
+            name,
+            kind,
+            mac_address,
+            mtu,
+            up: kind == InterfaceKind::Loopback,
+            addresses: Vec::new(),
+            statistics: None,
+        };
+        self.next_index += 1;
+        self.interfaces.push(InterfaceEntry { interface, device });
+        let index = self.interfaces.len() - 1;
+        &mut self.interfaces[index]
+    }
+
+    /// Bring the interfaces in line with the devices attached: loopback first, an interface for
+    /// each new device, and none for a device gone along with the routes through it
+    fn sync(&mut self) {
+        if self.interfaces.is_empty() {
+            let loopback = self.add(LOOPBACK_NAME.into(), InterfaceKind::Loopback, [0; 6], LOOPBACK_MTU, None);
+            loopback.interface.addresses.push(Ipv4Cidr { address: Ipv4Addr::LOCALHOST, prefix_len: 8 });
+        }
+
+        let devices = virtio::net_devices();
+        let mut removed = Vec::new();
+        self.interfaces.retain(|entry| match &entry.device {
+            Some(device) if !devices.iter().any(|attached| Arc::ptr_eq(attached, device)) => {
+                removed.push(entry.interface.name.clone());
+                false
+            }
+            _ => true,
+        });
+        self.static_routes.retain(|route| !removed.contains(&route.interface));
+
+        for device in devices {
+            let known = self.interfaces.iter().any(|entry| entry.device.as_ref().is_some_and(|known| Arc::ptr_eq(known, &device)));
+            if !known {
+                let name = (0..)
+                    .map(|number| format!("eth{}", number))
+                    .find(|name| self.find(name).is_none())
+                    .unwrap_or_default();
+                let mac_address = device.lock().mac_address();
+                self.add(name, InterfaceKind::Ethernet, mac_address, ETHERNET_MTU, Some(device));
+            }
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<&InterfaceEntry> {
+        self.interfaces.iter().find(|entry| entry.interface.name == name)
+    }
+
+    fn find_mut(&mut self, name: &str) -> NetworkResult<&mut Interface> {
+        self.interfaces
+            .iter_mut()
+            .find(|entry| entry.interface.name == name)
+            .map(|entry| &mut entry.interface)
+            .ok_or(NetworkError::InterfaceNotFound)
+    }
+
+    fn snapshot(entry: &InterfaceEntry) -> Interface {
+        let mut interface = entry.interface.clone();
+        interface.statistics = entry.device.as_ref().map(|device| device.lock().statistics());
+        interface
+    }
+
+    fn is_up(&self, name: &str) -> bool {
+        self.find(name).is_some_and(|entry| entry.interface.up)
+    }
+
+    /// Routes to the subnets of the addresses of the interfaces that are up
+    fn connected_routes(&self) -> impl Iterator<Item = Route> + '_ {
+        self.interfaces
+            .iter()
+            .filter(|entry| entry.interface.up)
+            .flat_map(|entry| {
+                entry.interface.addresses.iter().map(|address| Route {
+                    destination: address.network(),
+                    gateway: None,
+                    interface: entry.interface.name.clone(),
+                    source: Some(address.address),
+                    metric: 0,
+                })
+            })
+    }
+
+    fn routes(&self) -> Vec<Route> {
+        let mut routes: Vec<Route> = self
+            .connected_routes()
+            .chain(self.static_routes.iter().filter(|route| self.is_up(&route.interface)).cloned())
+            .collect();
+        routes.sort_by_key(|route| (route.destination.prefix_len != 0, route.destination, route.metric));
+        routes
+    }
+}
+
+/// Every interface, in the order they appeared
+pub fn list_interfaces() -> Vec<Interface> {
+    let mut table = INTERFACES.lock();
+    table.sync();
+    table.interfaces.iter().map(InterfaceTable::snapshot).collect()
+}
+
+pub fn interface_info(name: &str) -> NetworkResult<Interface> {
+    let mut table = INTERFACES.lock();
+    table.sync();
+    table.find(name).map(InterfaceTable::snapshot).ok_or(NetworkError::InterfaceNotFound)
+}
+
+/// Bring an interface up or down. Down, its addresses stay but it carries no routes
+pub fn set_interface_state(name: &str, up: bool) -> NetworkResult<()> {
+    let mut table = INTERFACES.lock();
+    table.sync();
+    table.find_mut(name)?.up = up;
+    Ok(())
+}
+
+pub fn add_address(name: &str, address: Ipv4Cidr) -> NetworkResult<()> {
+    let mut table = INTERFACES.lock();
+    table.sync();
+    let interface = table.find_mut(name)?;
+    if interface.addresses.iter().any(|existing| existing.address == address.address) {
+        return Err(NetworkError::AddressExists);
+    }
+    interface.addresses.push(address);
+    Ok(())
+}
+
+/// Give an interface `address` as its first address, in place of the one it had
+pub fn set_address(name: &str, address: Ipv4Cidr) -> NetworkResult<()> {
+    let mut table = INTERFACES.lock();
+    table.sync();
+    let interface = table.find_mut(name)?;
+    interface.addresses.retain(|existing| existing.address != address.address);
+    match interface.addresses.first_mut() {
+        Some(first) => *first = address,
+        None => interface.addresses.push(address),
+    }
+    Ok(())
+}
+
+pub fn remove_address(name: &str, address: Ipv4Cidr) -> NetworkResult<()> {
+    let mut table = INTERFACES.lock();
+    table.sync();
+    let interface = table.find_mut(name)?;
+    let count = interface.addresses.len();
+    interface.addresses.retain(|existing| *existing != address);
+    if interface.addresses.len() == count {
+        return Err(NetworkError::AddressNotFound);
+    }
+    Ok(())
+}
+
+/// Add a static route to `destination`. Through a gateway it goes out of the interface whose
+/// subnet holds the gateway, which must be `interface` when that is given; without one it
+/// goes straight out of `interface`
+pub fn add_route(destination: Ipv4Cidr, gateway: Option<Ipv4Addr>, interface: Option<&str>, metric: u32) -> NetworkResult<()> {
+    let mut table = INTERFACES.lock();
+    table.sync();
+    if let Some(name) = interface {
+        table.find(name).ok_or(NetworkError::InterfaceNotFound)?;
+    }
+    let interface = match gateway {
+        Some(gateway) => table
+            .connected_routes()
+            .find(|route| route.destination.contains(gateway) && interface.is_none_or(|name| route.interface == name))
+            .map(|route| route.interface)
+            .ok_or(NetworkError::NetworkUnreachable)?,
+        None => String::from(interface.ok_or(NetworkError::NetworkUnreachable)?),
+    };
+    let destination = destination.network();
+    if table.static_routes.iter().any(|route| route.destination == destination && route.metric == metric) {
+        return Err(NetworkError::RouteExists);
+    }
+    table.static_routes.push(Route { destination, gateway, interface, source: None, metric });
+    Ok(())
+}
+
+pub fn remove_route(destination: Ipv4Cidr) -> NetworkResult<()> {
+    let mut table = INTERFACES.lock();
+    let destination = destination.network();
+    let index = table
+        .static_routes
+        .iter()
+        .position(|route| route.destination == destination)
+        .ok_or(NetworkError::RouteNotFound)?;
+    table.static_routes.remove(index);
+    Ok(())
+}
+
+/// The routing table: the default route first, then by destination
+pub fn routes() -> Vec<Route> {
+    let mut table = INTERFACES.lock();
+    table.sync();
+    table.routes()
+}
+
+/// The virtio-net device behind an Ethernet interface
+pub fn device(name: &str) -> NetworkResult<Arc<Mutex<VirtioNet>>> {
+    let mut table = INTERFACES.lock();
+    table.sync();
+    table.find(name).ok_or(NetworkError::InterfaceNotFound)?.device.clone().ok_or(NetworkError::InterfaceNotFound)
+}
+
+/// The index of the interface on a virtio-net device, while the interface is up
+pub fn up_index_of(device: &Arc<Mutex<VirtioNet>>) -> Option<u32> {
+    let mut table = INTERFACES.lock();
+    table.sync();
+    table
+        .interfaces
+        .iter()
+        .find(|entry| entry.interface.up && entry.device.as_ref().is_some_and(|known| Arc::ptr_eq(known, device)))
+        .map(|entry| entry.interface.index)
+}
+
+/// The route traffic to `address` takes: the most specific one holding it, with the source
+/// address of its interface filled in
+pub fn route_to(address: Ipv4Addr) -> NetworkResult<Route> {
+    let mut table = INTERFACES.lock();
+    table.sync();
+    let mut route = table
+        .routes()
+        .into_iter()
+        .filter(|route| route.destination.contains(address))
+        .min_by_key(|route| (u8::MAX - route.destination.prefix_len, route.metric))
+        .ok_or(NetworkError::NetworkUnreachable)?;
+    if route.source.is_none() {
+        route.source = table
+            .find(&route.interface)
+            .and_then(|entry| entry.interface.addresses.iter().find(|address| route.gateway.is_none_or(|gateway| address.contains(gateway))))
+            .map(|address| address.address);
+    }
+    Ok(route)
+}
@@ -0,0 +1,244 @@
+//! Packet sockets
+//! AF_PACKET raw sockets see whole Ethernet frames, headers and all, as interfaces receive
+//! them, and send frames of their own unchanged. Opening one takes a NetworkRaw capability.
+//! A socket in capture mode also sees the frames interfaces send, and reads each frame as a
+//! pcap record stamped with when it crossed. A classic BPF filter attached to a socket picks
+//! the frames it sees and how much of each it keeps.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+use super::bpf::{self, Instruction};
+use super::interface::{self, Interface, InterfaceKind};
+use super::{pcap, NetworkError, NetworkResult, NETWORK_SYSTEM};
+use crate::capabilities::{self, CapabilityType};
+use crate::drivers::virtio::{self, FrameDirection, VirtioNet};
+
+/// Protocol of a packet socket that sees frames of every ethertype
+pub const ETH_P_ALL: u16 = 0x0003;
+/// Bound to no interface, a packet socket sees frames on all of them
+pub const ANY_INTERFACE: u32 = 0;
+/// Frames a socket holds unread before later ones are dropped
+const QUEUE_LIMIT: usize = 256;
+const ETHERNET_HEADER_LEN: usize = 14;
+
+/// Which way a frame crossed its interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+/// Frames a packet socket has queued, and those it had no room for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PacketStatistics {
+    pub queued: u64,
+    pub dropped: u64,
+}
+
+/// What a packet socket sees and holds
+#[derive(Debug)]
+pub(super) struct PacketSocket {
+    /// The ethertype it sees, or ETH_P_ALL
+    protocol: u16,
+    /// Index of the interface bound to; bound to none it sees them all but cannot send
+    interface: u32,
+    filter: Option<Vec<Instruction>>,
+    /// In capture mode, the most of a frame a record holds
+    snaplen: Option<u32>,
+    /// Frames, or pcap records of them in capture mode, oldest first
+    frames: VecDeque<Vec<u8>>,
+    statistics: PacketStatistics,
+}
+
+impl PacketSocket {
+    /// A socket for process `process_id` seeing frames of ethertype `protocol`, 0 or
+    /// ETH_P_ALL for every one
+    pub(super) fn open(process_id: u32, protocol: u32) -> NetworkResult<Self> {
+        if !capabilities::holds_capability(u64::from(process_id), CapabilityType::NetworkRaw) {
+            return Err(NetworkError::PermissionDenied);
+        }
+        let protocol = match u16::try_from(protocol) {
+            Ok(0) => ETH_P_ALL,
+            Ok(protocol) => protocol,
+            Err(_) => return Err(NetworkError::ProtocolNotSupported),
+        };
+        Ok(Self {
+            protocol,
+            interface: ANY_INTERFACE,
+            filter: None,
+            snaplen: None,
+            frames: VecDeque::new(),
+            statistics: PacketStatistics::default(),
+        })
+    }
+
+    /// Bind to the interface whose index is the big-endian word of `address`, or to none
+    pub(super) fn bind(&mut self, address: &[u8]) -> NetworkResult<()> {
+        let index: [u8; 4] = address.get(..4).and_then(|index| index.try_into().ok()).ok_or(NetworkError::InvalidAddress)?;
+        let index = u32::from_be_bytes(index);
+        if index != ANY_INTERFACE && find_interface(index).is_none() {
+            return Err(NetworkError::InterfaceNotFound);
+        }
+        self.interface = index;
+        Ok(())
+    }
+
+    /// Queue a frame that crossed interface `index`, when the socket wants it
+    fn offer(&mut self, index: u32, direction: Direction, frame: &[u8], timestamp_us: u64) {
+        if self.interface != ANY_INTERFACE && self.interface != index {
+            return;
+        }
+        // Only a capture sees what is sent
+        if direction == Direction::Outgoing && self.snaplen.is_none() {
+            return;
+        }
+        if self.protocol != ETH_P_ALL && u16::from_be_bytes([frame[12], frame[13]]) != self.protocol {
+            return;
+        }
+        let mut kept = frame.len();
+        if let Some(filter) = &self.filter {
+            kept = kept.min(bpf::run(filter, frame) as usize);
+        }
+        if kept == 0 {
+            return;
+        }
+        if self.frames.len() >= QUEUE_LIMIT {
+            self.statistics.dropped += 1;
+            return;
+        }
+        let queued = match self.snaplen {
+            Some(snaplen) => pcap::record(timestamp_us, &frame[..kept.min(snaplen as usize)], frame.len()),
+            None => frame[..kept].to_vec(),
+        };
+        self.frames.push_back(queued);
+        self.statistics.queued += 1;
+    }
+}
+
+fn find_interface(index: u32) -> Option<Interface> {
+    interface::list_interfaces().into_iter().find(|interface| interface.index == index)
+}
+
+/// Unix time now, in microseconds
+fn timestamp_us() -> u64 {
+    crate::time::boot_time() * 1_000_000 + crate::time::get_uptime_ms() * 1000
+}
+
+/// Hand a frame that crossed interface `index` to the packet sockets that want it
+pub fn tap(index: u32, direction: Direction, frame: &[u8]) {
+    if frame.len() < ETHERNET_HEADER_LEN {
+        return;
+    }
+    let timestamp_us = timestamp_us();
+    let mut network = NETWORK_SYSTEM.lock();
+    for socket in network.sockets.values_mut() {
+        if let Some(packet) = socket.packet.as_mut() {
+            packet.offer(index, direction, frame, timestamp_us);
+        }
+    }
+}
+
+/// Send a whole Ethernet frame out of interface `index`. Loopback hands it straight back as
+/// received
+pub fn transmit(index: u32, frame: &[u8]) -> NetworkResult<()> {
+    let interface = find_interface(index).ok_or(NetworkError::InterfaceNotFound)?;
+    if !interface.up {
+        return Err(NetworkError::NetworkUnreachable);
+    }
+    if frame.len() < ETHERNET_HEADER_LEN || frame.len() > ETHERNET_HEADER_LEN + interface.mtu as usize {
+        return Err(NetworkError::InvalidFrame);
+    }
+    match interface.kind {
+        InterfaceKind::Loopback => {
+            tap(index, Direction::Outgoing, frame);
+            tap(index, Direction::Incoming, frame);
+        }
+        InterfaceKind::Ethernet => {
+            let device = interface::device(&interface.name)?;
+            // The driver hands the capture its copy of the frame
+            device.lock().send_frame(frame).map_err(|_| NetworkError::NetworkUnreachable)?;
+        }
+    }
+    Ok(())
+}
+
+/// Hand the packet sockets a copy of a frame an Ethernet interface received or sent, leaving
+/// the frame itself to the rest of the stack
+fn frame_crossed(device: &Arc<Mutex<VirtioNet>>, direction: FrameDirection, frame: &[u8]) {
+    let direction = match direction {
+        FrameDirection::Received => Direction::Incoming,
+        FrameDirection::Sent => Direction::Outgoing,
+    };
+    if let Some(index) = interface::up_index_of(device) {
+        tap(index, direction, frame);
+    }
+}
+
+/// Have the virtio-net driver copy the frames it receives and sends to the packet sockets,
+/// whichever part of the stack moved them
+pub fn init() {
+    virtio::set_tap_handler(frame_crossed);
+}
+
+/// Send a frame from packet socket `socket_fd` out of the interface it is bound to
+pub(super) fn send(socket_fd: u32, frame: &[u8]) -> NetworkResult<usize> {
+    let index = {
+        let network = NETWORK_SYSTEM.lock();
+        let packet = network.sockets.get(&socket_fd).and_then(|socket| socket.packet.as_ref()).ok_or(NetworkError::InvalidSocket)?;
+        packet.interface
+    };
+    if index == ANY_INTERFACE {
+        return Err(NetworkError::NotConnected);
+    }
+    transmit(index, frame)?;
+    NETWORK_SYSTEM.lock().stats.bytes_sent += frame.len() as u64;
+    Ok(frame.len())
+}
+
+/// The oldest frame queued on packet socket `socket_fd`, cut to `length` bytes
+pub(super) fn receive(socket_fd: u32, length: usize) -> NetworkResult<Vec<u8>> {
+    virtio::poll_net();
+    let mut network = NETWORK_SYSTEM.lock();
+    let packet = network.sockets.get_mut(&socket_fd).and_then(|socket| socket.packet.as_mut()).ok_or(NetworkError::InvalidSocket)?;
+    let mut frame = packet.frames.pop_front().ok_or(NetworkError::WouldBlock)?;
+    frame.truncate(length);
+    network.stats.bytes_received += frame.len() as u64;
+    Ok(frame)
+}
+
+/// Run `change` on packet socket `socket_fd`, when the caller owns it
+fn with_packet_socket<T>(socket_fd: u32, change: impl FnOnce(&mut PacketSocket) -> T) -> NetworkResult<T> {
+    let mut network = NETWORK_SYSTEM.lock();
+    let current_pid = crate::process::get_current_process_id();
+    let socket = network.sockets.get_mut(&socket_fd).ok_or(NetworkError::InvalidSocket)?;
+    if socket.process_id != current_pid as u32 {
+        return Err(NetworkError::PermissionDenied);
+    }
+    let packet = socket.packet.as_mut().ok_or(NetworkError::ProtocolNotSupported)?;
+    Ok(change(packet))
+}
+
+/// Attach a BPF program to packet socket `socket_fd`, or detach the one it has
+pub fn set_filter(socket_fd: u32, filter: Option<Vec<Instruction>>) -> NetworkResult<()> {
+    if let Some(program) = &filter {
+        bpf::validate(program)?;
+    }
+    with_packet_socket(socket_fd, |packet| packet.filter = filter)
+}
+
+/// Put packet socket `socket_fd` in capture mode, keeping up to `snaplen` bytes of each
+/// frame, or take it out of it. Frames queued in the mode left are dropped
+pub fn set_capture(socket_fd: u32, snaplen: Option<u32>) -> NetworkResult<()> {
+    with_packet_socket(socket_fd, |packet| {
+        if packet.snaplen != snaplen {
+            packet.frames.clear();
+        }
+        packet.snaplen = snaplen;
+    })
+}
+
+pub fn statistics(socket_fd: u32) -> NetworkResult<PacketStatistics> {
+    with_packet_socket(socket_fd, |packet| packet.statistics)
+}
@@ -0,0 +1,90 @@
+//! pcap capture files
+//! The classic libpcap format: a file header naming Ethernet as the link type and the most of
+//! a frame any record holds, then a record for each frame, stamped to the microsecond. Files
+//! are written little-endian; files of either byte order are read.
+
+use alloc::vec::Vec;
+
+pub const MAGIC: u32 = 0xa1b2_c3d4;
+pub const VERSION_MAJOR: u16 = 2;
+pub const VERSION_MINOR: u16 = 4;
+pub const LINKTYPE_ETHERNET: u32 = 1;
+pub const FILE_HEADER_LEN: usize = 24;
+pub const RECORD_HEADER_LEN: usize = 16;
+
+/// One captured frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<'a> {
+    /// Unix time the frame crossed its interface, in microseconds
+    pub timestamp_us: u64,
+    /// Length of the frame on the wire, of which `data` may hold only the start
+    pub original_len: u32,
+    pub data: &'a [u8],
+}
+
+/// The header a file of Ethernet frames cut to `snaplen` bytes starts with
+pub fn file_header(snaplen: u32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(FILE_HEADER_LEN);
+    header.extend_from_slice(&MAGIC.to_le_bytes());
+    header.extend_from_slice(&VERSION_MAJOR.to_le_bytes());
+    header.extend_from_slice(&VERSION_MINOR.to_le_bytes());
+    // Timestamps are UTC, and claim no particular accuracy
+    header.extend_from_slice(&0i32.to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.extend_from_slice(&snaplen.to_le_bytes());
+    header.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+    header
+}
+
+/// A record of `data`, the start of a frame `original_len` bytes long
+pub fn record(timestamp_us: u64, data: &[u8], original_len: usize) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN + data.len());
+    record.extend_from_slice(&((timestamp_us / 1_000_000) as u32).to_le_bytes());
+    record.extend_from_slice(&((timestamp_us % 1_000_000) as u32).to_le_bytes());
+    record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    record.extend_from_slice(&(original_len as u32).to_le_bytes());
+    record.extend_from_slice(data);
+    record
+}
+
+fn read_u32(bytes: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let field: [u8; 4] = bytes.get(offset..offset + 4)?.try_into().ok()?;
+    Some(if big_endian { u32::from_be_bytes(field) } else { u32::from_le_bytes(field) })
+}
+
+/// The record at the start of `bytes` and the bytes after it, when it is whole
+pub fn parse_record(bytes: &[u8], big_endian: bool) -> Option<(Record<'_>, &[u8])> {
+    let seconds = read_u32(bytes, 0, big_endian)?;
+    let micros = read_u32(bytes, 4, big_endian)?;
+    let included_len = read_u32(bytes, 8, big_endian)? as usize;
+    let original_len = read_u32(bytes, 12, big_endian)?;
+    if micros >= 1_000_000 || included_len > original_len as usize {
+        return None;
+    }
+    let data = bytes.get(RECORD_HEADER_LEN..RECORD_HEADER_LEN.checked_add(included_len)?)?;
+    let record = Record { timestamp_us: u64::from(seconds) * 1_000_000 + u64::from(micros), original_len, data };
+    Some((record, &bytes[RECORD_HEADER_LEN + included_len..]))
+}
+
+/// The snapshot length and records of a capture of Ethernet frames, when `file` is one
+pub fn parse_file(file: &[u8]) -> Option<(u32, Vec<Record<'_>>)> {
+    let big_endian = match read_u32(file, 0, false)? {
+        MAGIC => false,
+        magic if magic == MAGIC.swap_bytes() => true,
+        _ => return None,
+    };
+    let version = read_u32(file, 4, big_endian)?;
+    let (major, minor) = if big_endian { ((version >> 16) as u16, version as u16) } else { (version as u16, (version >> 16) as u16) };
+    if (major, minor) != (VERSION_MAJOR, VERSION_MINOR) || read_u32(file, 20, big_endian)? != LINKTYPE_ETHERNET {
+        return None;
+    }
+    let snaplen = read_u32(file, 16, big_endian)?;
+    let mut rest = file.get(FILE_HEADER_LEN..)?;
+    let mut records = Vec::new();
+    while !rest.is_empty() {
+        let (record, after) = parse_record(rest, big_endian)?;
+        records.push(record);
+        rest = after;
+    }
+    Some((snaplen, records))
+}
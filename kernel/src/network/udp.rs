@@ -0,0 +1,243 @@
+//! UDP over Ethernet
+//! Exchanges of one datagram and its reply with a host beyond a virtio-net interface: the
+//! route picks the interface, the source address and the next hop, ARP finds the next hop's
+//! hardware address, and the reply is the first datagram back from the host to the port the
+//! request left from. ARP requests for the interface's address are answered while waiting.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::net::{Ipv4Addr, SocketAddrV4};
+use core::sync::atomic::{AtomicU16, Ordering};
+use spin::Mutex;
+use super::interface::{self, LOOPBACK_NAME};
+use super::{NetworkError, NetworkResult};
+use crate::drivers::virtio::VirtioNet;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV4_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+const ARP_PACKET_LEN: usize = 28;
+const IPPROTO_UDP: u8 = 17;
+const DEFAULT_TTL: u8 = 64;
+const BROADCAST: [u8; 6] = [0xff; 6];
+/// How long an ARP request is waited on before it is sent again
+const ARP_RETRY_MS: u64 = 250;
+const EPHEMERAL_FIRST: u16 = 49152;
+
+static ARP_CACHE: Mutex<BTreeMap<Ipv4Addr, [u8; 6]>> = Mutex::new(BTreeMap::new());
+static NEXT_PORT: AtomicU16 = AtomicU16::new(EPHEMERAL_FIRST);
+static NEXT_ID: AtomicU16 = AtomicU16::new(1);
+
+/// One's-complement sum of `parts` taken as one run of big-endian 16-bit words
+fn checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum = 0u32;
+    let mut high: Option<u8> = None;
+    for byte in parts.iter().flat_map(|part| part.iter().copied()) {
+        match high.take() {
+            Some(high) => sum += u32::from(u16::from_be_bytes([high, byte])),
+            None => high = Some(byte),
+        }
+    }
+    if let Some(high) = high {
+        sum += u32::from(u16::from_be_bytes([high, 0]));
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn ethernet_frame(destination: [u8; 6], source: [u8; 6], ethertype: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(ETHERNET_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&destination);
+    frame.extend_from_slice(&source);
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn arp_packet(operation: u16, sender: ([u8; 6], Ipv4Addr), target: ([u8; 6], Ipv4Addr)) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(ARP_PACKET_LEN);
+    // Ethernet hardware, IPv4 protocol, their address lengths
+    packet.extend_from_slice(&[0, 1, 0x08, 0x00, 6, 4]);
+    packet.extend_from_slice(&operation.to_be_bytes());
+    packet.extend_from_slice(&sender.0);
+    packet.extend_from_slice(&sender.1.octets());
+    packet.extend_from_slice(&target.0);
+    packet.extend_from_slice(&target.1.octets());
+    packet
+}
+
+/// An IPv4 packet carrying one UDP datagram
+fn udp_packet(source: SocketAddrV4, destination: SocketAddrV4, payload: &[u8]) -> Vec<u8> {
+    let udp_len = (UDP_HEADER_LEN + payload.len()) as u16;
+    let mut udp = Vec::with_capacity(usize::from(udp_len));
+    udp.extend_from_slice(&source.port().to_be_bytes());
+    udp.extend_from_slice(&destination.port().to_be_bytes());
+    udp.extend_from_slice(&udp_len.to_be_bytes());
+    udp.extend_from_slice(&[0, 0]);
+    udp.extend_from_slice(payload);
+    let mut pseudo = [0u8; 12];
+    pseudo[0..4].copy_from_slice(&source.ip().octets());
+    pseudo[4..8].copy_from_slice(&destination.ip().octets());
+    pseudo[9] = IPPROTO_UDP;
+    pseudo[10..12].copy_from_slice(&udp_len.to_be_bytes());
+    // A sum of zero goes out as all ones: zero means none was computed
+    let sum = match checksum(&[&pseudo, &udp]) {
+        0 => 0xffff,
+        sum => sum,
+    };
+    udp[6..8].copy_from_slice(&sum.to_be_bytes());
+
+    let mut packet = Vec::with_capacity(IPV4_HEADER_LEN + udp.len());
+    packet.push(0x45);
+    packet.push(0);
+    packet.extend_from_slice(&((IPV4_HEADER_LEN + udp.len()) as u16).to_be_bytes());
+    packet.extend_from_slice(&NEXT_ID.fetch_add(1, Ordering::Relaxed).to_be_bytes());
+    // Don't fragment
+    packet.extend_from_slice(&[0x40, 0]);
+    packet.push(DEFAULT_TTL);
+    packet.push(IPPROTO_UDP);
+    packet.extend_from_slice(&[0, 0]);
+    packet.extend_from_slice(&source.ip().octets());
+    packet.extend_from_slice(&destination.ip().octets());
+    let sum = checksum(&[&packet]);
+    packet[10..12].copy_from_slice(&sum.to_be_bytes());
+    packet.extend_from_slice(&udp);
+    packet
+}
+
+/// The operation and sender of an ARP packet, and the address it asks about
+fn parse_arp(frame: &[u8]) -> Option<(u16, [u8; 6], Ipv4Addr, Ipv4Addr)> {
+    let packet = frame.get(ETHERNET_HEADER_LEN..ETHERNET_HEADER_LEN + ARP_PACKET_LEN)?;
+    if u16::from_be_bytes([frame[12], frame[13]]) != ETHERTYPE_ARP || packet[..6] != [0, 1, 0x08, 0x00, 6, 4] {
+        return None;
+    }
+    let mut sender_mac = [0u8; 6];
+    sender_mac.copy_from_slice(&packet[8..14]);
+    let sender_ip = Ipv4Addr::new(packet[14], packet[15], packet[16], packet[17]);
+    let target_ip = Ipv4Addr::new(packet[24], packet[25], packet[26], packet[27]);
+    Some((u16::from_be_bytes([packet[6], packet[7]]), sender_mac, sender_ip, target_ip))
+}
+
+/// The payload of a UDP datagram in a frame, when it comes from `from` to `to`
+fn parse_udp(frame: &[u8], from: SocketAddrV4, to: SocketAddrV4) -> Option<Vec<u8>> {
+    if frame.len() < ETHERNET_HEADER_LEN + IPV4_HEADER_LEN || u16::from_be_bytes([frame[12], frame[13]]) != ETHERTYPE_IPV4 {
+        return None;
+    }
+    let packet = &frame[ETHERNET_HEADER_LEN..];
+    let header_len = usize::from(packet[0] & 0x0f) * 4;
+    let total_len = usize::from(u16::from_be_bytes([packet[2], packet[3]]));
+    if packet[0] >> 4 != 4 || header_len < IPV4_HEADER_LEN || total_len < header_len + UDP_HEADER_LEN || total_len > packet.len() {
+        return None;
+    }
+    // Fragments are not reassembled
+    if packet[9] != IPPROTO_UDP || checksum(&[&packet[..header_len]]) != 0 || u16::from_be_bytes([packet[6], packet[7]]) & 0x3fff != 0 {
+        return None;
+    }
+    if packet[12..16] != from.ip().octets() || packet[16..20] != to.ip().octets() {
+        return None;
+    }
+    let udp = &packet[header_len..total_len];
+    let udp_len = usize::from(u16::from_be_bytes([udp[4], udp[5]]));
+    if u16::from_be_bytes([udp[0], udp[1]]) != from.port() || u16::from_be_bytes([udp[2], udp[3]]) != to.port() {
+        return None;
+    }
+    if udp_len < UDP_HEADER_LEN || udp_len > udp.len() {
+        return None;
+    }
+    if udp[6..8] != [0, 0] {
+        let mut pseudo = [0u8; 12];
+        pseudo[0..4].copy_from_slice(&from.ip().octets());
+        pseudo[4..8].copy_from_slice(&to.ip().octets());
+        pseudo[9] = IPPROTO_UDP;
+        pseudo[10..12].copy_from_slice(&(udp_len as u16).to_be_bytes());
+        if checksum(&[&pseudo, &udp[..udp_len]]) != 0 {
+            return None;
+        }
+    }
+    Some(udp[UDP_HEADER_LEN..udp_len].to_vec())
+}
+
+/// One end of the link: the interface's device and its hardware and IPv4 addresses
+struct Link {
+    device: Arc<Mutex<VirtioNet>>,
+    mac: [u8; 6],
+    ip: Ipv4Addr,
+}
+
+impl Link {
+    fn send(&self, destination: [u8; 6], ethertype: u16, payload: &[u8]) -> NetworkResult<()> {
+        let frame = ethernet_frame(destination, self.mac, ethertype, payload);
+        self.device.lock().send_frame(&frame).map_err(|_| NetworkError::NetworkUnreachable)
+    }
+
+    /// Take received frames until `found` picks one out or `deadline` passes. ARP traffic
+    /// on the way teaches the cache, and requests for our address are answered
+    fn receive<T>(&self, deadline: u64, mut found: impl FnMut(&[u8]) -> Option<T>) -> NetworkResult<T> {
+        loop {
+            let frame = self.device.lock().receive_frame();
+            match frame {
+                Some(frame) => {
+                    if let Some((operation, sender_mac, sender_ip, target_ip)) = parse_arp(&frame) {
+                        ARP_CACHE.lock().insert(sender_ip, sender_mac);
+                        if operation == 1 && target_ip == self.ip {
+                            let reply = arp_packet(2, (self.mac, self.ip), (sender_mac, sender_ip));
+                            let _ = self.send(sender_mac, ETHERTYPE_ARP, &reply);
+                        }
+                    }
+                    if let Some(value) = found(&frame) {
+                        return Ok(value);
+                    }
+                }
+                None if crate::time::get_uptime_ms() >= deadline => return Err(NetworkError::Timeout),
+                None => core::hint::spin_loop(),
+            }
+        }
+    }
+
+    /// The hardware address of a host on the link, asking for it when it is not known
+    fn resolve(&self, address: Ipv4Addr, deadline: u64) -> NetworkResult<[u8; 6]> {
+        loop {
+            if let Some(mac) = ARP_CACHE.lock().get(&address) {
+                return Ok(*mac);
+            }
+            let request = arp_packet(1, (self.mac, self.ip), ([0; 6], address));
+            self.send(BROADCAST, ETHERTYPE_ARP, &request)?;
+            let retry = (crate::time::get_uptime_ms() + ARP_RETRY_MS).min(deadline);
+            let answered = self.receive(retry, |frame| {
+                parse_arp(frame).filter(|&(operation, _, sender, _)| operation == 2 && sender == address).map(|(_, mac, _, _)| mac)
+            });
+            match answered {
+                Ok(mac) => return Ok(mac),
+                Err(NetworkError::Timeout) if crate::time::get_uptime_ms() < deadline => continue,
+                Err(NetworkError::Timeout) => return Err(NetworkError::HostUnreachable),
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+/// Send `payload` in a datagram to `destination` and wait up to `timeout_ms` for its reply
+pub fn exchange(destination: SocketAddrV4, payload: &[u8], timeout_ms: u64) -> NetworkResult<Vec<u8>> {
+    let route = interface::route_to(*destination.ip())?;
+    // No service in the kernel listens on loopback
+    if route.interface == LOOPBACK_NAME {
+        return Err(NetworkError::ConnectionRefused);
+    }
+    let device = interface::device(&route.interface)?;
+    let mac = device.lock().mac_address();
+    let link = Link { device, mac, ip: route.source.ok_or(NetworkError::NetworkUnreachable)? };
+    let deadline = crate::time::get_uptime_ms() + timeout_ms;
+
+    let next_hop = link.resolve(route.gateway.unwrap_or(*destination.ip()), deadline)?;
+    let port = NEXT_PORT
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |port| Some(port.checked_add(1).unwrap_or(EPHEMERAL_FIRST)))
+        .unwrap_or(EPHEMERAL_FIRST);
+    let source = SocketAddrV4::new(link.ip, port);
+    link.send(next_hop, ETHERTYPE_IPV4, &udp_packet(source, destination, payload))?;
+    link.receive(deadline, |frame| parse_udp(frame, destination, source))
+}
@@ -9,6 +9,7 @@ use alloc::collections::BTreeMap;
 use alloc::format;
 use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
 use spin::RwLock;
+use super::flight_recorder::FlightRecorder;
 use super::{ObservabilityError, Subsystem};
 
 /// Maximum crash context entries
@@ -230,9 +231,11 @@ impl CrashHandler {
         }
     }
 
-    /// Handle a crash event
+    /// Handle a crash event, recording it into `recorder`. Callers already hold the
+    /// observability system, so this never goes through the global one
     pub fn handle_crash(
         &self,
+        recorder: &FlightRecorder,
         crash_type: CrashType,
         severity: CrashSeverity,
         subsystem: Option<Subsystem>,
@@ -307,7 +310,7 @@ impl CrashHandler {
         
         // Record in flight recorder if enabled
         if self.config.read().dump_to_flight_recorder {
-            super::record_event(super::ObservabilityEvent::Crash {
+            recorder.record_event(super::ObservabilityEvent::Crash {
                 crash_type: format!("{:?}", crash_type),
                 severity: format!("{:?}", severity),
                 subsystem,
@@ -656,6 +659,7 @@ macro_rules! report_crash {
     ($crash_type:expr, $severity:expr, $message:expr) => {
         $crate::observability::with_observability_mut(|obs| {
             let _ = obs.crash_handler.handle_crash(
+                &obs.flight_recorder,
                 $crash_type,
                 $severity,
                 None,
@@ -670,6 +674,7 @@ macro_rules! report_crash {
     ($crash_type:expr, $severity:expr, $subsystem:expr, $message:expr) => {
         $crate::observability::with_observability_mut(|obs| {
             let _ = obs.crash_handler.handle_crash(
+                &obs.flight_recorder,
                 $crash_type,
                 $severity,
                 Some($subsystem),
@@ -0,0 +1,597 @@
+//! Event Codec - Compact binary encoding of flight recorder entries
+//!
+//! Each entry becomes a record: a flags byte; the timestamp, sequence ID and span ID as
+//! varint deltas from the previous record; then everything else (the "body") in fixed-width
+//! little-endian form. The body is compared byte by byte with a template - the last body
+//! recorded for the same event kind and PID - and stored as alternating runs copied from the
+//! template and literal bytes, so a repeated syscall from the same process costs a handful of
+//! bytes instead of a full entry.
+//!
+//! Records depend on the records before them, so a stream is only decodable from a point
+//! where encoder and decoder state were both reset (the start of a flight recorder block).
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use super::flight_recorder::FlightRecorderEntry;
+use super::{
+    ContextSwitchReason, MemoryOperation, ObservabilityError, ObservabilityEvent, ServiceOperation,
+    ServiceResult, Severity, Subsystem, WatchdogAction,
+};
+
+/// Number of templates kept per stream; a record names the slot it was diffed against
+pub const TEMPLATE_SLOTS: usize = 8;
+
+/// Largest header a record can have: flags, three 10-byte varints and the body length
+const MAX_RECORD_HEADER: usize = 1 + 3 * 10 + 5;
+
+/// Shortest run worth a copy op; shorter matches stay inside the literal
+const MIN_COPY: usize = 3;
+
+const FLAG_SLOT_MASK: u8 = 0x07;
+const FLAG_DELTA: u8 = 0x08;
+
+pub(super) const SEVERITIES: [Severity; 6] = [
+    Severity::Trace, Severity::Debug, Severity::Info, Severity::Warn, Severity::Error, Severity::Fatal,
+];
+
+pub(super) const SUBSYSTEMS: [Subsystem; 24] = [
+    Subsystem::Kernel, Subsystem::Memory, Subsystem::Scheduler, Subsystem::Filesystem,
+    Subsystem::Network, Subsystem::Graphics, Subsystem::Audio, Subsystem::Input, Subsystem::Ipc,
+    Subsystem::Power, Subsystem::Security, Subsystem::Storage, Subsystem::Usb, Subsystem::Pci,
+    Subsystem::Acpi, Subsystem::Timer, Subsystem::Interrupt, Subsystem::Smp,
+    Subsystem::Virtualization, Subsystem::Unknown, Subsystem::Ai, Subsystem::Compositor,
+    Subsystem::PackageManager, Subsystem::ServiceManager,
+];
+
+const MEMORY_OPERATIONS: [MemoryOperation; 5] = [
+    MemoryOperation::Allocate, MemoryOperation::Deallocate, MemoryOperation::Map,
+    MemoryOperation::Unmap, MemoryOperation::Protect,
+];
+
+const SWITCH_REASONS: [ContextSwitchReason; 5] = [
+    ContextSwitchReason::Preemption, ContextSwitchReason::Yield, ContextSwitchReason::Block,
+    ContextSwitchReason::Exit, ContextSwitchReason::Signal,
+];
+
+const SERVICE_OPERATIONS: [ServiceOperation; 5] = [
+    ServiceOperation::Start, ServiceOperation::Stop, ServiceOperation::Restart,
+    ServiceOperation::HealthCheck, ServiceOperation::Crash,
+];
+
+const SERVICE_RESULTS: [ServiceResult; 4] = [
+    ServiceResult::Success, ServiceResult::Failure, ServiceResult::Timeout, ServiceResult::Killed,
+];
+
+const WATCHDOG_ACTIONS: [WatchdogAction; 4] = [
+    WatchdogAction::Warning, WatchdogAction::Restart, WatchdogAction::Panic, WatchdogAction::Ignore,
+];
+
+/// State shared by encoder and decoder; both sides update it identically after each record
+#[derive(Debug, Clone, Default)]
+struct StreamState {
+    prev_timestamp: u64,
+    prev_sequence: u64,
+    prev_span: u64,
+    templates: [Vec<u8>; TEMPLATE_SLOTS],
+}
+
+impl StreamState {
+    fn advance(&mut self, timestamp: u64, sequence: u64, span: u64) {
+        self.prev_timestamp = timestamp;
+        self.prev_sequence = sequence;
+        self.prev_span = span;
+    }
+}
+
+/// Encodes entries into records; buffers are reused so the record path does not allocate
+/// once warmed up
+#[derive(Debug, Default)]
+pub struct Encoder {
+    state: StreamState,
+    /// Template key of each slot, `None` while unused
+    keys: [Option<u64>; TEMPLATE_SLOTS],
+    next_victim: usize,
+    body: Vec<u8>,
+    pending: Option<(u64, u64, u64, u64)>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget all history; the next record starts a new decodable stream
+    pub fn reset(&mut self) {
+        self.state.prev_timestamp = 0;
+        self.state.prev_sequence = 0;
+        self.state.prev_span = 0;
+        for template in self.state.templates.iter_mut() {
+            template.clear();
+        }
+        self.keys = [None; TEMPLATE_SLOTS];
+        self.next_victim = 0;
+    }
+
+    /// Serialize `entry` for the following `commit`, returning the most bytes its record
+    /// can take
+    pub fn prepare(&mut self, entry: &FlightRecorderEntry) -> usize {
+        self.body.clear();
+        write_body(&mut self.body, entry);
+        self.pending = Some((entry.timestamp_ns, entry.sequence_id, entry.span_id, template_key(&entry.event)));
+        MAX_RECORD_HEADER + self.body.len()
+    }
+
+    /// Append the record of the last prepared entry to `out`
+    pub fn commit(&mut self, out: &mut Vec<u8>) {
+        let Some((timestamp, sequence, span, key)) = self.pending.take() else { return };
+
+        let (slot, hit) = match self.keys.iter().position(|k| *k == Some(key)) {
+            Some(slot) => (slot, true),
+            None => {
+                let slot = self.next_victim;
+                self.next_victim = (self.next_victim + 1) % TEMPLATE_SLOTS;
+                self.keys[slot] = Some(key);
+                (slot, false)
+            }
+        };
+
+        let flags_at = out.len();
+        out.push(slot as u8);
+        put_varint(out, zigzag(timestamp.wrapping_sub(self.state.prev_timestamp) as i64));
+        put_varint(out, zigzag(sequence.wrapping_sub(self.state.prev_sequence).wrapping_sub(1) as i64));
+        put_varint(out, zigzag(span.wrapping_sub(self.state.prev_span).wrapping_sub(1) as i64));
+        put_varint(out, self.body.len() as u64);
+
+        let body_at = out.len();
+        let template = &self.state.templates[slot];
+        if hit && write_delta(out, &self.body, template, self.body.len()) {
+            out[flags_at] |= FLAG_DELTA;
+        } else {
+            out.truncate(body_at);
+            out.extend_from_slice(&self.body);
+        }
+
+        let template = &mut self.state.templates[slot];
+        template.clear();
+        template.extend_from_slice(&self.body);
+        self.state.advance(timestamp, sequence, span);
+    }
+}
+
+/// Decodes a stream of records produced by one `Encoder` since its last reset
+pub struct Decoder<'a> {
+    reader: Reader<'a>,
+    state: StreamState,
+    body: Vec<u8>,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { reader: Reader { data, pos: 0 }, state: StreamState::default(), body: Vec::new() }
+    }
+
+    /// The next entry, or `None` at the end of the stream
+    pub fn next_entry(&mut self) -> Result<Option<FlightRecorderEntry>, ObservabilityError> {
+        if self.reader.pos == self.reader.data.len() {
+            return Ok(None);
+        }
+        let flags = self.reader.u8()?;
+        if flags & !(FLAG_SLOT_MASK | FLAG_DELTA) != 0 {
+            return Err(ObservabilityError::CorruptData);
+        }
+        let slot = (flags & FLAG_SLOT_MASK) as usize;
+        let timestamp = self.state.prev_timestamp.wrapping_add(unzigzag(self.reader.varint()?) as u64);
+        let sequence = self.state.prev_sequence.wrapping_add(unzigzag(self.reader.varint()?) as u64).wrapping_add(1);
+        let span = self.state.prev_span.wrapping_add(unzigzag(self.reader.varint()?) as u64).wrapping_add(1);
+        let body_len = self.reader.varint()? as usize;
+
+        self.body.clear();
+        if flags & FLAG_DELTA != 0 {
+            let template = &self.state.templates[slot];
+            while self.body.len() < body_len {
+                let copy = self.reader.varint()? as usize;
+                let start = self.body.len();
+                let end = start.checked_add(copy).ok_or(ObservabilityError::CorruptData)?;
+                let copied = template.get(start..end).ok_or(ObservabilityError::CorruptData)?;
+                self.body.extend_from_slice(copied);
+                let literal = self.reader.varint()? as usize;
+                self.body.extend_from_slice(self.reader.bytes(literal)?);
+            }
+            if self.body.len() != body_len {
+                return Err(ObservabilityError::CorruptData);
+            }
+        } else {
+            self.body.extend_from_slice(self.reader.bytes(body_len)?);
+        }
+
+        let entry = read_body(&self.body, timestamp, sequence, span)?;
+        let template = &mut self.state.templates[slot];
+        template.clear();
+        template.extend_from_slice(&self.body);
+        self.state.advance(timestamp, sequence, span);
+        Ok(Some(entry))
+    }
+}
+
+/// Decode every record of a stream
+pub fn decode_all(data: &[u8]) -> Result<Vec<FlightRecorderEntry>, ObservabilityError> {
+    let mut decoder = Decoder::new(data);
+    let mut entries = Vec::new();
+    while let Some(entry) = decoder.next_entry()? {
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Events of the same kind from the same source share a template. Syscalls are further split
+/// by number and direction, since an entry and its exit differ in most fields.
+fn template_key(event: &ObservabilityEvent) -> u64 {
+    let (source, variant) = match event {
+        ObservabilityEvent::Syscall { pid, syscall_id, entry, .. } => (*pid, (*syscall_id << 1) | *entry as u32),
+        ObservabilityEvent::Memory { pid, .. }
+        | ObservabilityEvent::PageFault { pid, .. }
+        | ObservabilityEvent::ProcessCreated { pid, .. }
+        | ObservabilityEvent::ProcessTerminated { pid, .. } => (*pid, 0),
+        ObservabilityEvent::Ipc { from_pid, .. } | ObservabilityEvent::ContextSwitch { from_pid, .. } => (*from_pid, 0),
+        ObservabilityEvent::Service { service_id, .. } => (*service_id, 0),
+        ObservabilityEvent::Interrupt { vector, .. } => (*vector as u32, 0),
+        ObservabilityEvent::Watchdog { subsystem, .. } | ObservabilityEvent::Tracepoint { subsystem, .. } => (*subsystem as u32, 0),
+        _ => (0, 0),
+    };
+    ((event_tag(event) as u64) << 56) ^ ((variant as u64) << 32) ^ source as u64
+}
+
+/// Append the copy/literal runs turning `template` into `body`; false if they would not be
+/// smaller than `limit` bytes
+fn write_delta(out: &mut Vec<u8>, body: &[u8], template: &[u8], limit: usize) -> bool {
+    let start = out.len();
+    let matches = |i: usize, cap: usize| {
+        body[i..].iter().zip(template.get(i..).unwrap_or_default()).take(cap).take_while(|(a, b)| a == b).count()
+    };
+    let mut i = 0;
+    while i < body.len() {
+        let copy = matches(i, usize::MAX);
+        i += copy;
+        let literal_start = i;
+        while i < body.len() && matches(i, MIN_COPY) < MIN_COPY.min(body.len() - i) {
+            i += 1;
+        }
+        put_varint(out, copy as u64);
+        put_varint(out, (i - literal_start) as u64);
+        out.extend_from_slice(&body[literal_start..i]);
+        if out.len() - start >= limit {
+            return false;
+        }
+    }
+    true
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+/// Optional values keep a fixed width so that later fields stay aligned with the template
+fn put_opt_u32(out: &mut Vec<u8>, value: Option<u32>) {
+    out.push(value.is_some() as u8);
+    out.extend_from_slice(&value.unwrap_or(0).to_le_bytes());
+}
+
+fn event_tag(event: &ObservabilityEvent) -> u8 {
+    match event {
+        ObservabilityEvent::Syscall { .. } => 0,
+        ObservabilityEvent::Ipc { .. } => 1,
+        ObservabilityEvent::Memory { .. } => 2,
+        ObservabilityEvent::ContextSwitch { .. } => 3,
+        ObservabilityEvent::Interrupt { .. } => 4,
+        ObservabilityEvent::PageFault { .. } => 5,
+        ObservabilityEvent::Service { .. } => 6,
+        ObservabilityEvent::Watchdog { .. } => 7,
+        ObservabilityEvent::Tracepoint { .. } => 8,
+        ObservabilityEvent::SystemBoot { .. } => 9,
+        ObservabilityEvent::ProcessCreated { .. } => 10,
+        ObservabilityEvent::ProcessTerminated { .. } => 11,
+        ObservabilityEvent::Crash { .. } => 12,
+        ObservabilityEvent::TraceCompleted { .. } => 13,
+    }
+}
+
+fn write_body(out: &mut Vec<u8>, entry: &FlightRecorderEntry) {
+    out.extend_from_slice(&entry.thread_id.to_le_bytes());
+    out.push(entry.cpu_id);
+    out.push(entry.severity as u8);
+    out.push(entry.subsystem as u8);
+    out.push(entry.trace_id.is_some() as u8);
+    out.extend_from_slice(&entry.trace_id.unwrap_or(0).to_le_bytes());
+    out.push(entry.parent_span_id.is_some() as u8);
+    out.extend_from_slice(&entry.parent_span_id.unwrap_or(0).to_le_bytes());
+
+    out.push(event_tag(&entry.event));
+    match &entry.event {
+        ObservabilityEvent::Syscall { syscall_id, pid, entry, args, result } => {
+            out.extend_from_slice(&syscall_id.to_le_bytes());
+            out.extend_from_slice(&pid.to_le_bytes());
+            out.push(*entry as u8);
+            for arg in args {
+                out.extend_from_slice(&arg.to_le_bytes());
+            }
+            out.push(result.is_some() as u8);
+            out.extend_from_slice(&result.unwrap_or(0).to_le_bytes());
+        }
+        ObservabilityEvent::Ipc { from_pid, to_pid, message_type, size, trace_id } => {
+            out.extend_from_slice(&from_pid.to_le_bytes());
+            out.extend_from_slice(&to_pid.to_le_bytes());
+            out.extend_from_slice(&message_type.to_le_bytes());
+            out.extend_from_slice(&size.to_le_bytes());
+            out.extend_from_slice(&trace_id.to_le_bytes());
+        }
+        ObservabilityEvent::Memory { operation, address, size, pid } => {
+            out.push(*operation as u8);
+            out.extend_from_slice(&address.to_le_bytes());
+            out.extend_from_slice(&size.to_le_bytes());
+            out.extend_from_slice(&pid.to_le_bytes());
+        }
+        ObservabilityEvent::ContextSwitch { from_pid, to_pid, reason } => {
+            out.extend_from_slice(&from_pid.to_le_bytes());
+            out.extend_from_slice(&to_pid.to_le_bytes());
+            out.push(*reason as u8);
+        }
+        ObservabilityEvent::Interrupt { vector, duration_ns, nested } => {
+            out.push(*vector);
+            out.extend_from_slice(&duration_ns.to_le_bytes());
+            out.push(*nested as u8);
+        }
+        ObservabilityEvent::PageFault { address, error_code, pid, resolved, duration_ns } => {
+            out.extend_from_slice(&address.to_le_bytes());
+            out.extend_from_slice(&error_code.to_le_bytes());
+            out.extend_from_slice(&pid.to_le_bytes());
+            out.push(*resolved as u8);
+            out.extend_from_slice(&duration_ns.to_le_bytes());
+        }
+        ObservabilityEvent::Service { service_id, operation, result } => {
+            out.extend_from_slice(&service_id.to_le_bytes());
+            out.push(*operation as u8);
+            out.push(*result as u8);
+        }
+        ObservabilityEvent::Watchdog { subsystem, timeout_ms, action } => {
+            out.push(*subsystem as u8);
+            out.extend_from_slice(&timeout_ms.to_le_bytes());
+            out.push(*action as u8);
+        }
+        ObservabilityEvent::Tracepoint { name, subsystem, data } => {
+            out.push(*subsystem as u8);
+            put_bytes(out, name.as_bytes());
+            put_bytes(out, data);
+        }
+        ObservabilityEvent::SystemBoot { timestamp, boot_stage } => {
+            out.extend_from_slice(&timestamp.to_le_bytes());
+            put_bytes(out, boot_stage.as_bytes());
+        }
+        ObservabilityEvent::ProcessCreated { pid, name, parent_pid } => {
+            out.extend_from_slice(&pid.to_le_bytes());
+            put_opt_u32(out, *parent_pid);
+            put_bytes(out, name.as_bytes());
+        }
+        ObservabilityEvent::ProcessTerminated { pid, exit_code, signal } => {
+            out.extend_from_slice(&pid.to_le_bytes());
+            out.extend_from_slice(&exit_code.to_le_bytes());
+            put_opt_u32(out, *signal);
+        }
+        ObservabilityEvent::Crash { crash_type, severity, subsystem, message, recovery_action } => {
+            out.push(subsystem.is_some() as u8);
+            out.push(subsystem.map_or(0, |s| s as u8));
+            put_bytes(out, crash_type.as_bytes());
+            put_bytes(out, severity.as_bytes());
+            put_bytes(out, message.as_bytes());
+            put_bytes(out, recovery_action.as_bytes());
+        }
+        ObservabilityEvent::TraceCompleted { trace_id, correlation_id, duration_ms, span_count, error_count } => {
+            out.extend_from_slice(&trace_id.to_le_bytes());
+            out.extend_from_slice(&correlation_id.to_le_bytes());
+            out.extend_from_slice(&duration_ms.to_le_bytes());
+            out.extend_from_slice(&span_count.to_le_bytes());
+            out.extend_from_slice(&error_count.to_le_bytes());
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ObservabilityError> {
+        let end = self.pos.checked_add(len).ok_or(ObservabilityError::CorruptData)?;
+        let bytes = self.data.get(self.pos..end).ok_or(ObservabilityError::CorruptData)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ObservabilityError> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, ObservabilityError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, ObservabilityError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ObservabilityError::CorruptData),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, ObservabilityError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, ObservabilityError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn u128(&mut self) -> Result<u128, ObservabilityError> {
+        Ok(u128::from_le_bytes(self.array()?))
+    }
+
+    fn varint(&mut self) -> Result<u64, ObservabilityError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ObservabilityError::CorruptData)
+    }
+
+    fn opt_u32(&mut self) -> Result<Option<u32>, ObservabilityError> {
+        let present = self.bool()?;
+        let value = self.u32()?;
+        Ok(present.then_some(value))
+    }
+
+    fn string(&mut self) -> Result<String, ObservabilityError> {
+        let len = self.u32()? as usize;
+        let bytes = self.bytes(len)?;
+        core::str::from_utf8(bytes).map(String::from).map_err(|_| ObservabilityError::CorruptData)
+    }
+
+    fn byte_vec(&mut self) -> Result<Vec<u8>, ObservabilityError> {
+        let len = self.u32()? as usize;
+        Ok(self.bytes(len)?.to_vec())
+    }
+
+    fn variant<T: Copy, const N: usize>(&mut self, table: &[T; N]) -> Result<T, ObservabilityError> {
+        table.get(self.u8()? as usize).copied().ok_or(ObservabilityError::CorruptData)
+    }
+}
+
+fn read_body(body: &[u8], timestamp_ns: u64, sequence_id: u64, span_id: u64) -> Result<FlightRecorderEntry, ObservabilityError> {
+    let mut r = Reader { data: body, pos: 0 };
+    let thread_id = r.u32()?;
+    let cpu_id = r.u8()?;
+    let severity = r.variant(&SEVERITIES)?;
+    let subsystem = r.variant(&SUBSYSTEMS)?;
+    let has_trace = r.bool()?;
+    let trace_id = r.u128()?;
+    let has_parent = r.bool()?;
+    let parent_span_id = r.u64()?;
+
+    let event = match r.u8()? {
+        0 => ObservabilityEvent::Syscall {
+            syscall_id: r.u32()?,
+            pid: r.u32()?,
+            entry: r.bool()?,
+            args: [r.u64()?, r.u64()?, r.u64()?, r.u64()?, r.u64()?, r.u64()?],
+            result: {
+                let present = r.bool()?;
+                let value = r.u64()? as i64;
+                present.then_some(value)
+            },
+        },
+        1 => ObservabilityEvent::Ipc {
+            from_pid: r.u32()?,
+            to_pid: r.u32()?,
+            message_type: r.u32()?,
+            size: r.u32()?,
+            trace_id: r.u128()?,
+        },
+        2 => ObservabilityEvent::Memory {
+            operation: r.variant(&MEMORY_OPERATIONS)?,
+            address: r.u64()?,
+            size: r.u64()?,
+            pid: r.u32()?,
+        },
+        3 => ObservabilityEvent::ContextSwitch {
+            from_pid: r.u32()?,
+            to_pid: r.u32()?,
+            reason: r.variant(&SWITCH_REASONS)?,
+        },
+        4 => ObservabilityEvent::Interrupt { vector: r.u8()?, duration_ns: r.u64()?, nested: r.bool()? },
+        5 => ObservabilityEvent::PageFault {
+            address: r.u64()?,
+            error_code: r.u32()?,
+            pid: r.u32()?,
+            resolved: r.bool()?,
+            duration_ns: r.u64()?,
+        },
+        6 => ObservabilityEvent::Service {
+            service_id: r.u32()?,
+            operation: r.variant(&SERVICE_OPERATIONS)?,
+            result: r.variant(&SERVICE_RESULTS)?,
+        },
+        7 => ObservabilityEvent::Watchdog {
+            subsystem: r.variant(&SUBSYSTEMS)?,
+            timeout_ms: r.u32()?,
+            action: r.variant(&WATCHDOG_ACTIONS)?,
+        },
+        8 => {
+            let subsystem = r.variant(&SUBSYSTEMS)?;
+            ObservabilityEvent::Tracepoint { subsystem, name: r.string()?, data: r.byte_vec()? }
+        }
+        9 => ObservabilityEvent::SystemBoot { timestamp: r.u64()?, boot_stage: r.string()? },
+        10 => {
+            let pid = r.u32()?;
+            let parent_pid = r.opt_u32()?;
+            ObservabilityEvent::ProcessCreated { pid, name: r.string()?, parent_pid }
+        }
+        11 => ObservabilityEvent::ProcessTerminated { pid: r.u32()?, exit_code: r.u32()? as i32, signal: r.opt_u32()? },
+        12 => {
+            let has_subsystem = r.bool()?;
+            let subsystem = r.variant(&SUBSYSTEMS)?;
+            ObservabilityEvent::Crash {
+                crash_type: r.string()?,
+                severity: r.string()?,
+                subsystem: has_subsystem.then_some(subsystem),
+                message: r.string()?,
+                recovery_action: r.string()?,
+            }
+        }
+        13 => ObservabilityEvent::TraceCompleted {
+            trace_id: r.u128()?,
+            correlation_id: r.u64()?,
+            duration_ms: r.u64()?,
+            span_count: r.u32()?,
+            error_count: r.u32()?,
+        },
+        _ => return Err(ObservabilityError::CorruptData),
+    };
+    if r.pos != body.len() {
+        return Err(ObservabilityError::CorruptData);
+    }
+
+    Ok(FlightRecorderEntry {
+        timestamp_ns,
+        sequence_id,
+        thread_id,
+        cpu_id,
+        severity,
+        subsystem,
+        event,
+        trace_id: has_trace.then_some(trace_id),
+        parent_span_id: has_parent.then_some(parent_span_id),
+        span_id,
+    })
+}
@@ -3,12 +3,21 @@
 //! The flight recorder maintains a circular buffer of system events that can be
 //! dumped on crash or analyzed for performance debugging. It's designed to be
 //! always-on with minimal performance impact.
+//!
+//! With compression enabled, entries are delta-encoded (see `event_codec`) into a ring of
+//! fixed-size blocks bounded by bytes rather than entry count, so the same memory holds
+//! far more history when events repeat.
+//!
+//! Entries leave the recorder through `drain`, oldest first, either copied or consumed, and
+//! `encode_dump` packs them into a self-describing file for analysis after an incident.
 
 use alloc::vec::Vec;
 use alloc::string::String;
-use alloc::collections::VecDeque;
+use alloc::collections::{BTreeSet, VecDeque};
 use core::sync::atomic::{AtomicU64, Ordering};
 use spin::{Mutex, RwLock};
+use super::event_codec::{self, Encoder};
+use super::query::EventFilter;
 use super::{ObservabilityEvent, ObservabilityError, Severity, Subsystem};
 
 /// Maximum number of events in the flight recorder buffer
@@ -17,6 +26,24 @@ const MAX_FLIGHT_RECORDER_EVENTS: usize = 65536; // 64K events
 /// Maximum size of event data in bytes
 const MAX_EVENT_DATA_SIZE: usize = 512;
 
+/// Size of a block of the compressed ring. Each block decodes on its own, so blocks are
+/// also the unit of eviction.
+const COMPRESSED_BLOCK_SIZE: usize = 4096;
+
+const DUMP_MAGIC: &[u8; 8] = b"RAEFDUMP";
+const DUMP_VERSION: u32 = 1;
+/// Magic, version and entry count, ahead of the entries as one `event_codec` stream
+const DUMP_HEADER_SIZE: usize = 16;
+
+/// Whether a drain leaves what it returns in the recorder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrainMode {
+    /// Return copies, leaving the recorder as it was
+    Copy,
+    /// Remove what is returned, so the next drain picks up after it
+    Consume,
+}
+
 /// Flight recorder configuration
 #[derive(Debug, Clone)]
 pub struct FlightRecorderConfig {
@@ -34,7 +61,7 @@ impl Default for FlightRecorderConfig {
         Self {
             max_events: MAX_FLIGHT_RECORDER_EVENTS,
             max_event_size: MAX_EVENT_DATA_SIZE,
-            enable_compression: false, // Disabled for performance
+            enable_compression: false, // Raw entries are cheapest to record
             redaction_enabled: true,
             storage_path: None, // In-memory only by default
             auto_dump_on_crash: true,
@@ -44,7 +71,7 @@ impl Default for FlightRecorderConfig {
 }
 
 /// Flight recorder event with metadata
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FlightRecorderEntry {
     pub timestamp_ns: u64,
     pub sequence_id: u64,
@@ -70,10 +97,143 @@ pub struct FlightRecorderStats {
     pub redacted_events: u64,
 }
 
+/// Delta-encoded entries in blocks, bounded by `budget` bytes
+struct CompressedRing {
+    /// Full blocks with their entry counts, oldest first
+    sealed: VecDeque<(Vec<u8>, usize)>,
+    sealed_bytes: usize,
+    open: Vec<u8>,
+    open_count: usize,
+    count: usize,
+    encoder: Encoder,
+    budget: usize,
+}
+
+impl CompressedRing {
+    fn new(budget: usize) -> Self {
+        Self {
+            sealed: VecDeque::new(),
+            sealed_bytes: 0,
+            open: Vec::with_capacity(COMPRESSED_BLOCK_SIZE),
+            open_count: 0,
+            count: 0,
+            encoder: Encoder::new(),
+            budget,
+        }
+    }
+
+    fn bytes(&self) -> usize {
+        self.sealed_bytes + self.open.len()
+    }
+
+    /// Append an entry, returning how many of the oldest entries were evicted for it
+    fn push(&mut self, entry: &FlightRecorderEntry) -> usize {
+        let bound = self.encoder.prepare(entry);
+        if self.open_count > 0 && self.open.len() + bound > COMPRESSED_BLOCK_SIZE {
+            let mut block = core::mem::replace(&mut self.open, Vec::with_capacity(COMPRESSED_BLOCK_SIZE));
+            block.shrink_to_fit();
+            self.sealed_bytes += block.len();
+            self.sealed.push_back((block, self.open_count));
+            self.open_count = 0;
+            self.encoder.reset();
+        }
+        self.encoder.commit(&mut self.open);
+        self.open_count += 1;
+        self.count += 1;
+
+        let mut evicted = 0;
+        while self.bytes() > self.budget {
+            let Some((block, count)) = self.sealed.pop_front() else { break };
+            self.sealed_bytes -= block.len();
+            self.count -= count;
+            evicted += count;
+        }
+        evicted
+    }
+
+    fn decode(&self) -> Vec<FlightRecorderEntry> {
+        let mut entries = Vec::with_capacity(self.count);
+        let blocks = self.sealed.iter().map(|(block, _)| block.as_slice());
+        for block in blocks.chain(core::iter::once(self.open.as_slice())) {
+            // Blocks are only ever written by our own encoder; should one fail to decode,
+            // keep what precedes the damage
+            let mut decoder = event_codec::Decoder::new(block);
+            while let Ok(Some(entry)) = decoder.next_entry() {
+                entries.push(entry);
+            }
+        }
+        entries
+    }
+}
+
+/// Storage behind the flight recorder
+enum RecorderBuffer {
+    /// Entries as recorded, bounded by count
+    Entries(VecDeque<FlightRecorderEntry>),
+    /// Entries delta-encoded, bounded by the bytes `max_events` raw entries would take
+    Compressed(CompressedRing),
+}
+
+impl RecorderBuffer {
+    fn for_config(config: &FlightRecorderConfig) -> Self {
+        if config.enable_compression {
+            let budget = config.max_events.saturating_mul(core::mem::size_of::<FlightRecorderEntry>());
+            RecorderBuffer::Compressed(CompressedRing::new(budget))
+        } else {
+            RecorderBuffer::Entries(VecDeque::with_capacity(config.max_events.min(MAX_FLIGHT_RECORDER_EVENTS)))
+        }
+    }
+
+    /// Store an entry, returning how many old entries were dropped to make room
+    fn push(&mut self, entry: FlightRecorderEntry, max_events: usize) -> usize {
+        match self {
+            RecorderBuffer::Entries(entries) => {
+                let mut dropped = 0;
+                // If buffer is full, remove oldest entry
+                while entries.len() >= max_events {
+                    entries.pop_front();
+                    dropped += 1;
+                }
+                entries.push_back(entry);
+                dropped
+            }
+            RecorderBuffer::Compressed(ring) => ring.push(&entry),
+        }
+    }
+
+    /// Drop the entries not matching `keep`. Compressed entries are re-encoded, since
+    /// blocks only decode whole
+    fn retain(&mut self, config: &FlightRecorderConfig, mut keep: impl FnMut(&FlightRecorderEntry) -> bool) {
+        let entries = match self {
+            RecorderBuffer::Entries(entries) => {
+                entries.retain(|e| keep(e));
+                return;
+            }
+            RecorderBuffer::Compressed(ring) => ring.decode(),
+        };
+        *self = RecorderBuffer::for_config(config);
+        for entry in entries.into_iter().filter(|e| keep(e)) {
+            self.push(entry, config.max_events);
+        }
+    }
+
+    /// Entries matching `keep`, oldest first
+    fn collect(&self, mut keep: impl FnMut(&FlightRecorderEntry) -> bool) -> Vec<FlightRecorderEntry> {
+        match self {
+            RecorderBuffer::Entries(entries) => entries.iter().filter(|e| keep(e)).cloned().collect(),
+            RecorderBuffer::Compressed(ring) => {
+                let mut entries = ring.decode();
+                entries.retain(|e| keep(e));
+                entries
+            }
+        }
+    }
+}
+
 /// Flight recorder implementation
 pub struct FlightRecorder {
     config: FlightRecorderConfig,
-    buffer: Mutex<VecDeque<FlightRecorderEntry>>,
+    buffer: Mutex<RecorderBuffer>,
     sequence_counter: AtomicU64,
     span_counter: AtomicU64,
     stats: RwLock<FlightRecorderStats>,
@@ -93,8 +253,8 @@ impl FlightRecorder {
         }
 
         Ok(Self {
+            buffer: Mutex::new(RecorderBuffer::for_config(&config)),
             config,
-            buffer: Mutex::new(VecDeque::with_capacity(MAX_FLIGHT_RECORDER_EVENTS)),
             sequence_counter: AtomicU64::new(1),
             span_counter: AtomicU64::new(1),
             stats: RwLock::new(FlightRecorderStats::default()),
@@ -151,31 +311,105 @@ impl FlightRecorder {
         self.add_entry(entry);
     }
 
+    /// Record an event raised on behalf of user space, attributed to `thread_id` with the
+    /// severity its sender declared
+    pub fn record_event_from(
+        &self,
+        event: ObservabilityEvent,
+        severity: Severity,
+        thread_id: u32,
+        trace_id: Option<u128>,
+        parent_span_id: Option<u64>,
+    ) {
+        if self.enabled.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        let entry = FlightRecorderEntry {
+            timestamp_ns: crate::time::get_timestamp_ns(),
+            sequence_id: self.sequence_counter.fetch_add(1, Ordering::SeqCst),
+            thread_id,
+            cpu_id: self.get_current_cpu_id(),
+            severity,
+            subsystem: self.determine_subsystem(&event),
+            event: self.maybe_redact_event(event),
+            trace_id,
+            parent_span_id,
+            span_id: self.span_counter.fetch_add(1, Ordering::SeqCst),
+        };
+
+        self.add_entry(entry);
+    }
+
     /// Add an entry to the circular buffer
-    fn add_entry(&self, entry: FlightRecorderEntry) {
+    pub(crate) fn add_entry(&self, entry: FlightRecorderEntry) {
         let mut buffer = self.buffer.lock();
+        let dropped = buffer.push(entry, self.config.max_events);
         
-        // If buffer is full, remove oldest entry
-        if buffer.len() >= self.config.max_events {
-            buffer.pop_front();
-            self.stats.write().events_dropped += 1;
+        // Update statistics
+        let mut stats = self.stats.write();
+        stats.total_events_recorded += 1;
+        stats.events_dropped += dropped as u64;
+        self.update_buffer_stats(&buffer, &mut stats);
+    }
+
+    fn update_buffer_stats(&self, buffer: &RecorderBuffer, stats: &mut FlightRecorderStats) {
+        match buffer {
+            RecorderBuffer::Entries(entries) => {
+                stats.buffer_utilization_percent = ((entries.len() * 100) / self.config.max_events) as u8;
+            }
+            RecorderBuffer::Compressed(ring) => {
+                let bytes = ring.bytes().max(1);
+                stats.buffer_utilization_percent = ((bytes * 100) / ring.budget.max(1)).min(100) as u8;
+                stats.average_event_size_bytes = (bytes / ring.count.max(1)) as u32;
+                stats.compression_ratio =
+                    (ring.count * core::mem::size_of::<FlightRecorderEntry>()) as f32 / bytes as f32;
+            }
         }
+    }
 
-        buffer.push_back(entry);
-        
-        // Update statistics
-        {
-            let mut stats = self.stats.write();
-            stats.total_events_recorded += 1;
-            stats.buffer_utilization_percent = 
-                ((buffer.len() * 100) / self.config.max_events) as u8;
+    /// Every entry in the recorder, oldest first; compressed entries are decoded
+    pub fn snapshot(&self) -> Vec<FlightRecorderEntry> {
+        self.buffer.lock().collect(|_| true)
+    }
+
+    /// Up to `max` entries, oldest first, for export elsewhere. They are taken in one go, so
+    /// recording on other CPUs meanwhile lands wholly before or after them, and with
+    /// `DrainMode::Consume` they are also removed
+    pub fn drain(&self, max: usize, mode: DrainMode) -> Vec<FlightRecorderEntry> {
+        let mut buffer = self.buffer.lock();
+        let mut wanted = max;
+        let entries = buffer.collect(|_| {
+            let take = wanted > 0;
+            wanted = wanted.saturating_sub(1);
+            take
+        });
+        if mode == DrainMode::Consume && !entries.is_empty() {
+            let mut taken = entries.len();
+            buffer.retain(&self.config, |_| {
+                let keep = taken == 0;
+                taken = taken.saturating_sub(1);
+                keep
+            });
+            self.update_buffer_stats(&buffer, &mut self.stats.write());
         }
+        entries
+    }
+
+    /// Remove `exported` entries, which an earlier copying drain returned, keeping whatever
+    /// was recorded since
+    pub fn discard(&self, exported: &[FlightRecorderEntry]) {
+        let sequences: BTreeSet<u64> = exported.iter().map(|e| e.sequence_id).collect();
+        let mut buffer = self.buffer.lock();
+        buffer.retain(&self.config, |entry| !sequences.contains(&entry.sequence_id));
+        let mut stats = self.stats.write();
+        stats.last_dump_timestamp = crate::time::get_timestamp();
+        self.update_buffer_stats(&buffer, &mut stats);
     }
 
     /// Dump the flight recorder buffer for crash analysis
     pub fn dump_on_crash(&self) -> Vec<FlightRecorderEntry> {
-        let buffer = self.buffer.lock();
-        let mut entries: Vec<_> = buffer.iter().cloned().collect();
+        let mut entries = self.snapshot();
         
         // Sort by sequence ID to ensure chronological order
         entries.sort_by_key(|e| e.sequence_id);
@@ -189,31 +423,31 @@ impl FlightRecorder {
     /// Get recent events for debugging
     pub fn get_recent_events(&self, count: usize) -> Vec<FlightRecorderEntry> {
         let buffer = self.buffer.lock();
-        let start_idx = if buffer.len() > count {
-            buffer.len() - count
-        } else {
-            0
-        };
-        
-        buffer.range(start_idx..).cloned().collect()
+        match &*buffer {
+            RecorderBuffer::Entries(entries) => {
+                let start_idx = entries.len().saturating_sub(count);
+                entries.range(start_idx..).cloned().collect()
+            }
+            RecorderBuffer::Compressed(ring) => {
+                let mut entries = ring.decode();
+                entries.split_off(entries.len().saturating_sub(count))
+            }
+        }
     }
 
     /// Get events by subsystem
     pub fn get_events_by_subsystem(&self, subsystem: Subsystem) -> Vec<FlightRecorderEntry> {
-        let buffer = self.buffer.lock();
-        buffer.iter()
-            .filter(|entry| entry.subsystem == subsystem)
-            .cloned()
-            .collect()
+        self.buffer.lock().collect(|entry| entry.subsystem == subsystem)
     }
 
     /// Get events by trace ID
     pub fn get_events_by_trace_id(&self, trace_id: u128) -> Vec<FlightRecorderEntry> {
-        let buffer = self.buffer.lock();
-        buffer.iter()
-            .filter(|entry| entry.trace_id == Some(trace_id))
-            .cloned()
-            .collect()
+        self.buffer.lock().collect(|entry| entry.trace_id == Some(trace_id))
+    }
+
+    /// Entries selected by `filter`, oldest first
+    pub fn query(&self, filter: &EventFilter) -> Vec<FlightRecorderEntry> {
+        self.buffer.lock().collect(|entry| filter.matches(entry))
     }
 
     /// Get flight recorder statistics
@@ -234,7 +468,7 @@ impl FlightRecorder {
     /// Clear the flight recorder buffer
     pub fn clear(&self) {
         let mut buffer = self.buffer.lock();
-        buffer.clear();
+        *buffer = RecorderBuffer::for_config(&self.config);
         
         // Reset stats
         let mut stats = self.stats.write();
@@ -247,7 +481,17 @@ impl FlightRecorder {
             return Err(ObservabilityError::InvalidConfiguration);
         }
         
+        // Re-store what was recorded in the storage the new configuration calls for
+        let entries = self.snapshot();
         self.config = config;
+        let mut buffer = RecorderBuffer::for_config(&self.config);
+        let mut stats = self.stats.write();
+        for entry in entries {
+            stats.events_dropped += buffer.push(entry, self.config.max_events) as u64;
+        }
+        self.update_buffer_stats(&buffer, &mut stats);
+        drop(stats);
+        *self.buffer.get_mut() = buffer;
         Ok(())
     }
 
@@ -273,7 +517,7 @@ impl FlightRecorder {
         None
     }
 
-    fn determine_severity(&self, event: &ObservabilityEvent) -> Severity {
+    pub(super) fn determine_severity(&self, event: &ObservabilityEvent) -> Severity {
         match event {
             ObservabilityEvent::Service { result: super::ServiceResult::Failure, .. } => Severity::Error,
             ObservabilityEvent::Service { operation: super::ServiceOperation::Crash, .. } => Severity::Fatal,
@@ -291,7 +535,7 @@ impl FlightRecorder {
         }
     }
 
-    fn determine_subsystem(&self, event: &ObservabilityEvent) -> Subsystem {
+    pub(super) fn determine_subsystem(&self, event: &ObservabilityEvent) -> Subsystem {
         match event {
             ObservabilityEvent::Syscall { .. } => Subsystem::Kernel,
             ObservabilityEvent::Ipc { .. } => Subsystem::Ipc,
@@ -348,6 +592,47 @@ impl FlightRecorder {
     }
 }
 
+/// Up to `max` of the recorded events, oldest first, removed from the recorder unless `mode`
+/// is `DrainMode::Copy`. Empty before observability is initialized
+pub fn drain(max: usize, mode: DrainMode) -> Vec<ObservabilityEvent> {
+    super::with_observability(|obs| obs.flight_recorder.drain(max, mode))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|entry| entry.event)
+        .collect()
+}
+
+/// Pack `entries` into the dump file format: a header naming the format and the entry count,
+/// then the entries delta-encoded as a single `event_codec` stream
+pub fn encode_dump(entries: &[FlightRecorderEntry]) -> Vec<u8> {
+    let mut dump = Vec::with_capacity(DUMP_HEADER_SIZE + entries.len() * 16);
+    dump.extend_from_slice(DUMP_MAGIC);
+    dump.extend_from_slice(&DUMP_VERSION.to_le_bytes());
+    dump.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    let mut encoder = Encoder::new();
+    for entry in entries {
+        encoder.prepare(entry);
+        encoder.commit(&mut dump);
+    }
+    dump
+}
+
+/// Entries of a dump written by `encode_dump`, oldest first
+pub fn decode_dump(dump: &[u8]) -> Result<Vec<FlightRecorderEntry>, ObservabilityError> {
+    if dump.len() < DUMP_HEADER_SIZE || &dump[..8] != DUMP_MAGIC {
+        return Err(ObservabilityError::CorruptData);
+    }
+    let field = |at: usize| u32::from_le_bytes([dump[at], dump[at + 1], dump[at + 2], dump[at + 3]]);
+    if field(8) != DUMP_VERSION {
+        return Err(ObservabilityError::CorruptData);
+    }
+    let entries = event_codec::decode_all(&dump[DUMP_HEADER_SIZE..])?;
+    if entries.len() != field(12) as usize {
+        return Err(ObservabilityError::CorruptData);
+    }
+    Ok(entries)
+}
+
 /// Format flight recorder entries for human consumption
 pub fn format_flight_recorder_dump(entries: &[FlightRecorderEntry]) -> String {
     use alloc::format;
@@ -0,0 +1,131 @@
+//! Hang Dump - Focused capture of a subsystem the watchdog is about to restart
+//!
+//! A restart wipes the state that explains a hang, so before restarting a subsystem the
+//! watchdog saves its threads' scheduling states and stacks along with the subsystem's most
+//! recent flight recorder events.
+//!
+//! Stacks are unwound along the saved frame-pointer chain. Only kernel thread stacks are
+//! readable from any address space; user threads get their saved top frame alone.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+use crate::process::ProcessState;
+use super::crash_handler::StackFrame;
+use super::flight_recorder::{FlightRecorder, FlightRecorderEntry};
+use super::query::EventFilter;
+use super::Subsystem;
+
+/// Most flight recorder events kept in one dump
+pub const MAX_DUMP_EVENTS: usize = 256;
+
+/// Deepest stack unwound per thread
+pub const MAX_UNWIND_DEPTH: usize = 32;
+
+/// A thread of the hung subsystem as it was when the watchdog fired
+#[derive(Debug, Clone)]
+pub struct ThreadDump {
+    pub pid: u64,
+    pub name: String,
+    pub state: ProcessState,
+    /// Innermost frame first, starting at the saved instruction pointer
+    pub stack: Vec<StackFrame>,
+}
+
+/// Everything saved about a hung subsystem before its restart
+#[derive(Debug, Clone)]
+pub struct HangDump {
+    pub subsystem: Subsystem,
+    pub watchdog: String,
+    pub timestamp_ns: u64,
+    pub last_heartbeat: u64,
+    pub failure_count: u64,
+    /// Threads registered with the watchdog; those that no longer exist are left out
+    pub threads: Vec<ThreadDump>,
+    /// The subsystem's most recent flight recorder events, oldest first
+    pub events: Vec<FlightRecorderEntry>,
+}
+
+fn frame(instruction_pointer: u64, stack_pointer: u64, frame_pointer: u64) -> StackFrame {
+    StackFrame {
+        instruction_pointer,
+        stack_pointer,
+        frame_pointer,
+        symbol_name: None,
+        module_name: None,
+        offset: 0,
+    }
+}
+
+/// Walk the frame-pointer chain from a saved `rip`/`rsp`/`rbp`, reading words through
+/// `read`. Only frames wholly inside `stack` are followed, and each caller frame must lie
+/// above its callee, so a corrupt chain ends the walk instead of looping
+pub fn unwind(rip: u64, rsp: u64, rbp: u64, stack: Range<u64>, read: impl Fn(u64) -> Option<u64>) -> Vec<StackFrame> {
+    let mut frames = vec![frame(rip, rsp, rbp)];
+    let mut frame_pointer = rbp;
+    while frames.len() < MAX_UNWIND_DEPTH {
+        if frame_pointer % 8 != 0 || frame_pointer < stack.start || frame_pointer.saturating_add(16) > stack.end {
+            break;
+        }
+        let (Some(caller_frame_pointer), Some(return_address)) = (read(frame_pointer), read(frame_pointer + 8)) else {
+            break;
+        };
+        if return_address == 0 {
+            break;
+        }
+        frames.push(frame(return_address, frame_pointer + 16, caller_frame_pointer));
+        if caller_frame_pointer <= frame_pointer {
+            break;
+        }
+        frame_pointer = caller_frame_pointer;
+    }
+    frames
+}
+
+/// State and stack of thread `pid`, or `None` when it does not exist
+pub fn capture_thread(pid: u64) -> Option<ThreadDump> {
+    crate::process::with_process(pid, |process| {
+        let stack = match process.kernel_stack_ptr {
+            Some(_) => {
+                let base = process.stack_base.as_u64();
+                base..base + process.stack_size as u64
+            }
+            None => 0..0,
+        };
+        let readable = stack.start..stack.end.saturating_sub(7);
+        let context = &process.context;
+        let frames = unwind(context.rip, context.rsp, context.rbp, stack, |address| {
+            (address % 8 == 0 && readable.contains(&address)).then(|| {
+                // SAFETY: `address` is an aligned word inside the kernel thread's stack, which
+                // the process keeps allocated while it exists, and the scheduler lock held by
+                // `with_process` keeps it from being reaped meanwhile. A thread running on
+                // another CPU may rewrite the word, which at worst yields a wrong frame.
+                unsafe { core::ptr::read_volatile(address as *const u64) }
+            })
+        });
+        ThreadDump { pid, name: process.name.clone(), state: process.state, stack: frames }
+    })
+}
+
+/// Capture a dump of `subsystem` from `recorder` and the threads in `threads`
+pub fn capture(
+    recorder: &FlightRecorder,
+    subsystem: Subsystem,
+    watchdog: &str,
+    last_heartbeat: u64,
+    failure_count: u64,
+    threads: &[u64],
+) -> HangDump {
+    let mut events = recorder.query(&EventFilter { subsystem: Some(subsystem), ..EventFilter::default() });
+    events.drain(..events.len().saturating_sub(MAX_DUMP_EVENTS));
+    HangDump {
+        subsystem,
+        watchdog: String::from(watchdog),
+        timestamp_ns: crate::time::get_timestamp_ns(),
+        last_heartbeat,
+        failure_count,
+        threads: threads.iter().filter_map(|&pid| capture_thread(pid)).collect(),
+        events,
+    }
+}
@@ -8,11 +8,17 @@
 //! - Unified trace correlation across IPC boundaries
 
 pub mod flight_recorder;
+pub mod event_codec;
+pub mod ring_file;
+pub mod query;
 pub mod tracepoints;
+pub mod user_probes;
 pub mod watchdog;
+pub mod hang_dump;
 pub mod crash_handler;
 pub mod trace_correlation;
 
+use alloc::boxed::Box;
 use alloc::vec::Vec;
 use alloc::string::String;
 use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
@@ -47,11 +53,16 @@ impl ObservabilitySystem {
         Ok(())
     }
 
-    /// Record an observability event
+    /// Record an observability event, unless the tracepoint filter drops its subsystem at
+    /// its severity
     pub fn record_event(&self, event: ObservabilityEvent) {
         if self.enabled.load(Ordering::Relaxed) == 0 {
             return;
         }
+        let subsystem = self.flight_recorder.determine_subsystem(&event);
+        if !self.tracepoints.allows(subsystem, self.flight_recorder.determine_severity(&event)) {
+            return;
+        }
         
         self.flight_recorder.record_event(event);
     }
@@ -74,7 +85,7 @@ impl ObservabilitySystem {
     /// Periodic maintenance - should be called regularly
     pub fn periodic_maintenance(&self) {
         // Check watchdogs
-        self.watchdog.check_watchdogs();
+        self.watchdog.check_watchdogs(crate::time::get_uptime_ms(), &self.flight_recorder, &self.crash_handler);
         
         // Clean up expired traces
         self.trace_correlation.cleanup_expired_traces();
@@ -152,6 +163,7 @@ pub enum ObservabilityError {
     NotEnabled,
     TraceNotFound,
     SpanNotFound,
+    CorruptData,
 }
 
 /// Severity levels for observability events
@@ -195,7 +207,7 @@ pub enum Subsystem {
 }
 
 /// Event types for the flight recorder
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ObservabilityEvent {
     /// System call entry/exit
     Syscall {
@@ -338,9 +350,127 @@ pub enum WatchdogAction {
 
 /// Record an observability event
 pub fn record_event(event: ObservabilityEvent) {
-    let _ = with_observability_mut(|obs| {
-        obs.flight_recorder.record_event(event);
-    });
+    let _ = with_observability(|obs| obs.record_event(event));
+}
+
+/// Whether events of `subsystem` at `severity` are recorded, so callers can skip building
+/// them
+pub fn is_recorded(subsystem: Subsystem, severity: Severity) -> bool {
+    with_observability(|obs| obs.is_enabled() && obs.tracepoints.allows(subsystem, severity)).unwrap_or(false)
+}
+
+/// Every event in the flight recorder, oldest first
+pub fn snapshot() -> Vec<flight_recorder::FlightRecorderEntry> {
+    with_observability(|obs| obs.flight_recorder.snapshot()).unwrap_or_default()
+}
+
+/// Events selected by `filter`, oldest first
+pub fn query(filter: &query::EventFilter) -> Vec<ObservabilityEvent> {
+    query_entries(filter).into_iter().map(|entry| entry.event).collect()
+}
+
+/// Entries selected by `filter`, oldest first, with their timestamps and trace context
+pub fn query_entries(filter: &query::EventFilter) -> Vec<flight_recorder::FlightRecorderEntry> {
+    with_observability(|obs| obs.flight_recorder.query(filter)).unwrap_or_default()
+}
+
+/// Hang dumps the watchdogs saved before restarting subsystems, oldest first
+pub fn hang_dumps() -> Vec<hang_dump::HangDump> {
+    with_observability(|obs| obs.watchdog.get_hang_dumps()).unwrap_or_default()
+}
+
+/// Time between background drains of the flight recorder to its ring file
+const FLIGHT_EXPORT_INTERVAL_MS: u64 = 5_000;
+
+/// Ring file the background export drains into, once started
+static FLIGHT_LOG: Mutex<Option<ring_file::RingFile>> = Mutex::new(None);
+
+/// The ring region named by `flightlog=<start_lba>:<slots>` on the first virtio disk
+fn configured_flight_log() -> Result<Option<(Box<dyn crate::drivers::BlockDevice>, u64, u64)>, ObservabilityError> {
+    let Some(spec) = crate::cmdline::get("flightlog") else { return Ok(None) };
+    let (start_lba, slots) = spec
+        .split_once(':')
+        .and_then(|(start, slots)| Some((start.parse().ok()?, slots.parse().ok()?)))
+        .ok_or(ObservabilityError::InvalidConfiguration)?;
+    let disk = crate::drivers::virtio::block_devices()
+        .into_iter()
+        .next()
+        .ok_or(ObservabilityError::NotEnabled)?;
+    Ok(Some((Box::new(disk), start_lba, slots)))
+}
+
+/// Start draining the flight recorder to its on-disk ring in the background; does nothing
+/// unless the kernel command line configures `flightlog=`. Returns the export thread's PID
+pub fn start_flight_export() -> Result<Option<u64>, ObservabilityError> {
+    let Some((disk, start_lba, slots)) = configured_flight_log()? else { return Ok(None) };
+    match init_observability() {
+        Ok(()) | Err(ObservabilityError::AlreadyInitialized) => {}
+        Err(e) => return Err(e),
+    }
+    *FLIGHT_LOG.lock() = Some(ring_file::RingFile::open(disk, start_lba, slots)?);
+    crate::process::spawn_kernel_thread("flight-export", flight_export_main)
+        .map(Some)
+        .map_err(|_| ObservabilityError::ResourceExhausted)
+}
+
+extern "C" fn flight_export_main() -> ! {
+    loop {
+        crate::process::sleep_current(FLIGHT_EXPORT_INTERVAL_MS);
+        if let Err(e) = export_flight_recorder() {
+            crate::serial::_print(format_args!("[Observability] Flight recorder export failed: {:?}\n", e));
+        }
+    }
+}
+
+/// Drain the flight recorder into the ring file now, returning how many entries were written
+pub fn export_flight_recorder() -> Result<usize, ObservabilityError> {
+    let mut log = FLIGHT_LOG.lock();
+    let ring = log.as_mut().ok_or(ObservabilityError::NotEnabled)?;
+    let entries = with_observability(|obs| obs.flight_recorder.drain(usize::MAX, flight_recorder::DrainMode::Consume))?;
+    ring.append(&entries)
+}
+
+/// Write everything in the flight recorder to the file at `path` in the dump format of
+/// `flight_recorder::encode_dump`, replacing it, and return how many entries were written.
+/// With `DrainMode::Consume` they leave the recorder, but only once the file is written
+pub fn dump_flight_recorder(path: &str, mode: flight_recorder::DrainMode) -> Result<usize, ObservabilityError> {
+    use crate::filesystem::{self, O_CREAT, O_TRUNC, O_WRONLY};
+
+    let entries = with_observability(|obs| obs.flight_recorder.drain(usize::MAX, flight_recorder::DrainMode::Copy))?;
+    let dump = flight_recorder::encode_dump(&entries);
+    let fd = filesystem::open(path, O_WRONLY | O_CREAT | O_TRUNC).map_err(|_| ObservabilityError::StorageFull)?;
+    let mut unwritten = dump.as_slice();
+    let written = loop {
+        match filesystem::write(fd, unwritten) {
+            Ok(0) | Err(_) => break false,
+            Ok(n) if n >= unwritten.len() => break true,
+            Ok(n) => unwritten = &unwritten[n..],
+        }
+    };
+    let closed = filesystem::close(fd).is_ok();
+    if !written || !closed {
+        return Err(ObservabilityError::StorageFull);
+    }
+    if mode == flight_recorder::DrainMode::Consume {
+        with_observability(|obs| obs.flight_recorder.discard(&entries))?;
+    }
+    Ok(entries.len())
+}
+
+/// Entries of a dump file written by `dump_flight_recorder`, oldest first
+pub fn read_flight_dump(path: &str) -> Result<Vec<flight_recorder::FlightRecorderEntry>, ObservabilityError> {
+    let dump = crate::filesystem::read_file(path).map_err(|_| ObservabilityError::StorageFull)?;
+    flight_recorder::decode_dump(&dump)
+}
+
+/// Events in the on-disk ring, oldest first. Before export starts this is the previous
+/// boot's history, read without modifying the disk
+pub fn read_flight_log() -> Result<Vec<flight_recorder::FlightRecorderEntry>, ObservabilityError> {
+    if let Some(ring) = FLIGHT_LOG.lock().as_mut() {
+        return ring.read_entries();
+    }
+    let (disk, start_lba, slots) = configured_flight_log()?.ok_or(ObservabilityError::NotEnabled)?;
+    ring_file::RingFile::open_existing(disk, start_lba, slots)?.read_entries()
 }
 
 /// Access observability system (read-only)
@@ -363,22 +493,26 @@ pub fn periodic_maintenance() {
 macro_rules! trace_event {
     ($subsystem:expr, $name:expr, $($arg:expr),*) => {
         {
-            use alloc::vec;
-            let mut data = vec![];
-            $(
-                // Serialize arguments - simplified for now
-                let arg_bytes = format!("{:?}", $arg).into_bytes();
-                data.extend_from_slice(&arg_bytes);
-                data.push(b'|'); // separator
-            )*
-            
-            $crate::observability::record_event(
-                $crate::observability::ObservabilityEvent::Tracepoint {
-                    name: $name.into(),
-                    subsystem: $subsystem,
-                    data,
-                }
-            );
+            let subsystem = $subsystem;
+            // Filtered events are dropped before their data is built
+            if $crate::observability::is_recorded(subsystem, $crate::observability::Severity::Debug) {
+                use alloc::vec;
+                let mut data = vec![];
+                $(
+                    // Serialize arguments - simplified for now
+                    let arg_bytes = format!("{:?}", $arg).into_bytes();
+                    data.extend_from_slice(&arg_bytes);
+                    data.push(b'|'); // separator
+                )*
+
+                $crate::observability::record_event(
+                    $crate::observability::ObservabilityEvent::Tracepoint {
+                        name: $name.into(),
+                        subsystem,
+                        data,
+                    }
+                );
+            }
         }
     };
 }
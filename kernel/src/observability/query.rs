@@ -0,0 +1,161 @@
+//! Query - Filtering and aggregation over recorded observability events
+//!
+//! An `EventFilter` selects flight recorder entries by subsystem, minimum severity, PID,
+//! trace ID and time range; unset criteria match everything. The aggregation helpers work on
+//! the selected entries: event counts by kind, and latency summaries built by pairing syscall
+//! entry/exit records and IPC request/reply messages.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use super::flight_recorder::FlightRecorderEntry;
+use super::{ObservabilityEvent, Severity, Subsystem};
+
+/// Criteria an entry must meet to be selected; `Default` selects everything
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventFilter {
+    pub subsystem: Option<Subsystem>,
+    /// Lowest severity selected
+    pub min_severity: Option<Severity>,
+    /// Selects events that involve this process on either side
+    pub pid: Option<u32>,
+    /// Selects events recorded in the trace, and IPC messages carrying its ID
+    pub trace_id: Option<u128>,
+    /// Start of the time range, inclusive
+    pub since_ns: Option<u64>,
+    /// End of the time range, exclusive
+    pub until_ns: Option<u64>,
+}
+
+impl EventFilter {
+    /// Whether `entry` meets every criterion that is set
+    pub fn matches(&self, entry: &FlightRecorderEntry) -> bool {
+        self.subsystem.map_or(true, |subsystem| entry.subsystem == subsystem)
+            && self.min_severity.map_or(true, |severity| entry.severity >= severity)
+            && self.pid.map_or(true, |pid| event_pids(&entry.event).contains(&Some(pid)))
+            && self.trace_id.map_or(true, |trace_id| in_trace(entry, trace_id))
+            && self.since_ns.map_or(true, |since| entry.timestamp_ns >= since)
+            && self.until_ns.map_or(true, |until| entry.timestamp_ns < until)
+    }
+}
+
+/// Processes an event involves
+fn event_pids(event: &ObservabilityEvent) -> [Option<u32>; 2] {
+    match *event {
+        ObservabilityEvent::Syscall { pid, .. }
+        | ObservabilityEvent::Memory { pid, .. }
+        | ObservabilityEvent::PageFault { pid, .. }
+        | ObservabilityEvent::ProcessTerminated { pid, .. } => [Some(pid), None],
+        ObservabilityEvent::ProcessCreated { pid, parent_pid, .. } => [Some(pid), parent_pid],
+        ObservabilityEvent::Ipc { from_pid, to_pid, .. }
+        | ObservabilityEvent::ContextSwitch { from_pid, to_pid, .. } => [Some(from_pid), Some(to_pid)],
+        _ => [None, None],
+    }
+}
+
+fn in_trace(entry: &FlightRecorderEntry, trace_id: u128) -> bool {
+    entry.trace_id == Some(trace_id)
+        || matches!(entry.event,
+            ObservabilityEvent::Ipc { trace_id: id, .. } | ObservabilityEvent::TraceCompleted { trace_id: id, .. } if id == trace_id)
+}
+
+/// Short name of an event's kind, as used by `count_by_kind`
+pub fn event_kind(event: &ObservabilityEvent) -> &'static str {
+    match event {
+        ObservabilityEvent::Syscall { .. } => "syscall",
+        ObservabilityEvent::Ipc { .. } => "ipc",
+        ObservabilityEvent::Memory { .. } => "memory",
+        ObservabilityEvent::ContextSwitch { .. } => "context_switch",
+        ObservabilityEvent::Interrupt { .. } => "interrupt",
+        ObservabilityEvent::PageFault { .. } => "page_fault",
+        ObservabilityEvent::Service { .. } => "service",
+        ObservabilityEvent::Watchdog { .. } => "watchdog",
+        ObservabilityEvent::Tracepoint { .. } => "tracepoint",
+        ObservabilityEvent::SystemBoot { .. } => "system_boot",
+        ObservabilityEvent::ProcessCreated { .. } => "process_created",
+        ObservabilityEvent::ProcessTerminated { .. } => "process_terminated",
+        ObservabilityEvent::Crash { .. } => "crash",
+        ObservabilityEvent::TraceCompleted { .. } => "trace_completed",
+    }
+}
+
+/// Number of entries of each event kind
+pub fn count_by_kind(entries: &[FlightRecorderEntry]) -> BTreeMap<&'static str, usize> {
+    let mut counts = BTreeMap::new();
+    for entry in entries {
+        *counts.entry(event_kind(&entry.event)).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Nanoseconds from each syscall entry to the matching exit of the same process and syscall,
+/// in exit order; calls without both records in `entries` are left out
+pub fn syscall_latencies(entries: &[FlightRecorderEntry]) -> Vec<u64> {
+    let mut pending: BTreeMap<(u32, u32), Vec<u64>> = BTreeMap::new();
+    let mut latencies = Vec::new();
+    for entry in entries {
+        if let ObservabilityEvent::Syscall { syscall_id, pid, entry: entering, .. } = entry.event {
+            let starts = pending.entry((pid, syscall_id)).or_default();
+            if entering {
+                starts.push(entry.timestamp_ns);
+            } else if let Some(start) = starts.pop() {
+                latencies.push(entry.timestamp_ns.saturating_sub(start));
+            }
+        }
+    }
+    latencies
+}
+
+/// Nanoseconds from each IPC request to the reply in the same trace, which travels the
+/// opposite way between the same two processes
+pub fn ipc_latencies(entries: &[FlightRecorderEntry]) -> Vec<u64> {
+    let mut pending: BTreeMap<(u128, u32, u32), u64> = BTreeMap::new();
+    let mut latencies = Vec::new();
+    for entry in entries {
+        if let ObservabilityEvent::Ipc { from_pid, to_pid, trace_id, .. } = entry.event {
+            match pending.remove(&(trace_id, to_pid, from_pid)) {
+                Some(sent) => latencies.push(entry.timestamp_ns.saturating_sub(sent)),
+                None => {
+                    pending.entry((trace_id, from_pid, to_pid)).or_insert(entry.timestamp_ns);
+                }
+            }
+        }
+    }
+    latencies
+}
+
+/// Distribution of a set of latencies, in nanoseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub min: u64,
+    pub max: u64,
+    pub mean: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+}
+
+impl LatencySummary {
+    /// Summarize `samples`; `None` when there are none
+    pub fn from_samples(mut samples: Vec<u64>) -> Option<Self> {
+        samples.sort_unstable();
+        let (&min, &max) = (samples.first()?, samples.last()?);
+        let total: u128 = samples.iter().map(|&sample| sample as u128).sum();
+        Some(Self {
+            count: samples.len(),
+            min,
+            max,
+            mean: (total / samples.len() as u128) as u64,
+            p50: percentile(&samples, 50),
+            p90: percentile(&samples, 90),
+            p99: percentile(&samples, 99),
+        })
+    }
+}
+
+/// Nearest-rank percentile of sorted, non-empty `samples`
+fn percentile(samples: &[u64], percent: u32) -> u64 {
+    let n = samples.len();
+    let rank = (percent.min(100) as usize * n).div_ceil(100).max(1);
+    samples[rank.min(n) - 1]
+}
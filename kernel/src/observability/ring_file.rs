@@ -0,0 +1,242 @@
+//! Ring File - Flight recorder history persisted on a block device
+//!
+//! The ring occupies a fixed region of a disk: a header slot followed by `slot_count` data
+//! slots of `SLOT_SIZE` bytes. Entries are encoded with `event_codec` into the slot being
+//! filled, which is rewritten whole on every export; once full, the next slot is started and
+//! after the last one the oldest is overwritten. Writes are always whole, aligned slots, so the
+//! region maps directly onto the block cache.
+//!
+//! Each slot carries a generation number and a checksum of its payload. Readers order valid
+//! slots by generation, so events come back in recording order, and a slot torn by a crash
+//! mid-write fails its checksum and is skipped.
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::drivers::BlockDevice;
+use super::event_codec::{self, Encoder};
+use super::flight_recorder::FlightRecorderEntry;
+use super::ObservabilityError;
+
+/// Size of the header and of each data slot, in bytes
+pub const SLOT_SIZE: usize = 4096;
+
+const RING_MAGIC: &[u8; 8] = b"RAEFRING";
+const RING_VERSION: u32 = 1;
+const SLOT_MAGIC: u32 = 0x544f_4c53; // "SLOT"
+const SLOT_HEADER_SIZE: usize = 24;
+
+/// Encoded bytes one slot holds
+pub const SLOT_PAYLOAD: usize = SLOT_SIZE - SLOT_HEADER_SIZE;
+
+/// A slot read back from disk
+struct Slot {
+    generation: u64,
+    payload: Vec<u8>,
+}
+
+/// Fixed-size ring of flight recorder entries on a block device
+pub struct RingFile {
+    device: Box<dyn BlockDevice>,
+    start_lba: u64,
+    slot_count: u64,
+    blocks_per_slot: u64,
+    /// Generation of the slot being filled; it lives at `generation % slot_count`
+    generation: u64,
+    open: Vec<u8>,
+    open_count: u32,
+    encoder: Encoder,
+    skipped: u64,
+}
+
+impl RingFile {
+    /// Open the ring at `start_lba`, formatting the region unless it already holds a ring of
+    /// `slot_count` slots; existing history is kept and appending starts a fresh slot after it
+    pub fn open(device: Box<dyn BlockDevice>, start_lba: u64, slot_count: u64) -> Result<Self, ObservabilityError> {
+        let mut ring = Self::attach(device, start_lba, slot_count)?;
+        if ring.has_valid_header()? {
+            ring.generation = ring.read_slots()?.iter().map(|slot| slot.generation + 1).max().unwrap_or(0);
+        } else {
+            ring.format()?;
+        }
+        Ok(ring)
+    }
+
+    /// Open an existing ring without modifying the disk, for reading a previous boot's history
+    pub fn open_existing(device: Box<dyn BlockDevice>, start_lba: u64, slot_count: u64) -> Result<Self, ObservabilityError> {
+        let mut ring = Self::attach(device, start_lba, slot_count)?;
+        if !ring.has_valid_header()? {
+            return Err(ObservabilityError::CorruptData);
+        }
+        Ok(ring)
+    }
+
+    fn attach(device: Box<dyn BlockDevice>, start_lba: u64, slot_count: u64) -> Result<Self, ObservabilityError> {
+        let block_size = device.block_size();
+        if slot_count == 0 || block_size == 0 || SLOT_SIZE % block_size != 0 {
+            return Err(ObservabilityError::InvalidConfiguration);
+        }
+        let blocks_per_slot = (SLOT_SIZE / block_size) as u64;
+        let end = slot_count
+            .checked_add(1)
+            .and_then(|slots| slots.checked_mul(blocks_per_slot))
+            .and_then(|blocks| blocks.checked_add(start_lba))
+            .ok_or(ObservabilityError::InvalidConfiguration)?;
+        if end > device.block_count() {
+            return Err(ObservabilityError::StorageFull);
+        }
+        Ok(Self {
+            device,
+            start_lba,
+            slot_count,
+            blocks_per_slot,
+            generation: 0,
+            open: Vec::with_capacity(SLOT_PAYLOAD),
+            open_count: 0,
+            encoder: Encoder::new(),
+            skipped: 0,
+        })
+    }
+
+    /// Number of data slots in the ring
+    pub fn slot_count(&self) -> u64 {
+        self.slot_count
+    }
+
+    /// Entries too large for a slot, which were not written
+    pub fn skipped_entries(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Append entries, oldest first, writing every slot they touch; returns how many were stored
+    pub fn append(&mut self, entries: &[FlightRecorderEntry]) -> Result<usize, ObservabilityError> {
+        let mut stored = 0;
+        for entry in entries {
+            let bound = self.encoder.prepare(entry);
+            if bound > SLOT_PAYLOAD {
+                self.skipped += 1;
+                continue;
+            }
+            if self.open.len() + bound > SLOT_PAYLOAD {
+                self.write_open_slot()?;
+                self.generation += 1;
+                self.open.clear();
+                self.open_count = 0;
+                // Every slot is decodable on its own
+                self.encoder.reset();
+            }
+            self.encoder.commit(&mut self.open);
+            self.open_count += 1;
+            stored += 1;
+        }
+        if stored > 0 {
+            self.write_open_slot()?;
+        }
+        Ok(stored)
+    }
+
+    /// Every entry in the ring, oldest first
+    pub fn read_entries(&mut self) -> Result<Vec<FlightRecorderEntry>, ObservabilityError> {
+        let mut slots = self.read_slots()?;
+        slots.sort_unstable_by_key(|slot| slot.generation);
+        let mut entries = Vec::new();
+        for slot in slots {
+            // A slot that fails to decode is dropped; the others are still usable
+            if let Ok(decoded) = event_codec::decode_all(&slot.payload) {
+                entries.extend(decoded);
+            }
+        }
+        Ok(entries)
+    }
+
+    fn slot_lba(&self, index: u64) -> u64 {
+        self.start_lba + index * self.blocks_per_slot
+    }
+
+    fn read_raw(&mut self, index: u64) -> Result<Vec<u8>, ObservabilityError> {
+        let mut buffer = vec![0u8; SLOT_SIZE];
+        let lba = self.slot_lba(index);
+        self.device.read_blocks(lba, &mut buffer).map_err(|_| ObservabilityError::StorageFull)?;
+        Ok(buffer)
+    }
+
+    fn write_raw(&mut self, index: u64, buffer: &[u8]) -> Result<(), ObservabilityError> {
+        let lba = self.slot_lba(index);
+        self.device.write_blocks(lba, buffer).map_err(|_| ObservabilityError::StorageFull)
+    }
+
+    fn has_valid_header(&mut self) -> Result<bool, ObservabilityError> {
+        let header = self.read_raw(0)?;
+        Ok(&header[..8] == RING_MAGIC && read_u32(&header, 8) == RING_VERSION && read_u64(&header, 12) == self.slot_count)
+    }
+
+    /// Write a fresh header and clear every slot, so stale data is never taken for history
+    fn format(&mut self) -> Result<(), ObservabilityError> {
+        let empty = vec![0u8; SLOT_SIZE];
+        for index in 1..=self.slot_count {
+            self.write_raw(index, &empty)?;
+        }
+        let mut header = vec![0u8; SLOT_SIZE];
+        header[..8].copy_from_slice(RING_MAGIC);
+        header[8..12].copy_from_slice(&RING_VERSION.to_le_bytes());
+        header[12..20].copy_from_slice(&self.slot_count.to_le_bytes());
+        self.write_raw(0, &header)?;
+        self.generation = 0;
+        Ok(())
+    }
+
+    fn write_open_slot(&mut self) -> Result<(), ObservabilityError> {
+        let mut buffer = vec![0u8; SLOT_SIZE];
+        buffer[0..4].copy_from_slice(&SLOT_MAGIC.to_le_bytes());
+        buffer[4..12].copy_from_slice(&self.generation.to_le_bytes());
+        buffer[12..16].copy_from_slice(&self.open_count.to_le_bytes());
+        buffer[16..20].copy_from_slice(&(self.open.len() as u32).to_le_bytes());
+        buffer[20..24].copy_from_slice(&checksum(self.generation, &self.open).to_le_bytes());
+        buffer[SLOT_HEADER_SIZE..SLOT_HEADER_SIZE + self.open.len()].copy_from_slice(&self.open);
+        let index = 1 + self.generation % self.slot_count;
+        self.write_raw(index, &buffer)
+    }
+
+    /// Slots holding intact data, in disk order
+    fn read_slots(&mut self) -> Result<Vec<Slot>, ObservabilityError> {
+        let mut slots = Vec::new();
+        for index in 0..self.slot_count {
+            let raw = self.read_raw(1 + index)?;
+            if read_u32(&raw, 0) != SLOT_MAGIC {
+                continue;
+            }
+            let generation = read_u64(&raw, 4);
+            let len = read_u32(&raw, 16) as usize;
+            if generation % self.slot_count != index || len > SLOT_PAYLOAD {
+                continue;
+            }
+            let payload = &raw[SLOT_HEADER_SIZE..SLOT_HEADER_SIZE + len];
+            if checksum(generation, payload) != read_u32(&raw, 20) {
+                continue;
+            }
+            slots.push(Slot { generation, payload: payload.to_vec() });
+        }
+        Ok(slots)
+    }
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&buffer[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn read_u64(buffer: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&buffer[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+/// FNV-1a over the generation and payload
+fn checksum(generation: u64, payload: &[u8]) -> u32 {
+    generation
+        .to_le_bytes()
+        .iter()
+        .chain(payload)
+        .fold(0x811c_9dc5u32, |hash, &byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+}
@@ -2,19 +2,27 @@
 //!
 //! This module provides a tracepoint system similar to USDT (Userland Statically Defined Tracing)
 //! that allows dynamic enabling/disabling of instrumentation points throughout the kernel.
+//!
+//! The registry also holds the severity filter every recorded event passes: a minimum
+//! severity per subsystem, and a default for subsystems without one of their own. It is
+//! read on every event, so it is kept in atomics and can be changed at any time.
 
 use alloc::vec::Vec;
 use alloc::string::{String, ToString};
 use alloc::collections::BTreeMap;
 use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
 use spin::RwLock;
-use super::{ObservabilityError, Subsystem};
+use super::event_codec::{SEVERITIES, SUBSYSTEMS};
+use super::{ObservabilityError, Severity, Subsystem};
 
 /// Maximum number of tracepoints
 const MAX_TRACEPOINTS: usize = 4096;
 
 /// Maximum size of tracepoint data
-const MAX_TRACEPOINT_DATA_SIZE: usize = 1024;
+pub(super) const MAX_TRACEPOINT_DATA_SIZE: usize = 1024;
+
+/// Filter slot of a subsystem that follows the default minimum severity
+const FOLLOW_DEFAULT: u8 = 0xff;
 
 /// Tracepoint definition
 #[derive(Debug)]
@@ -60,6 +68,9 @@ pub struct TracepointRegistry {
     next_id: AtomicU64,
     next_probe_id: AtomicU64,
     global_enabled: AtomicU8,
+    /// Lowest severity recorded per subsystem, indexed by `Subsystem as usize`
+    min_severity: [AtomicU8; SUBSYSTEMS.len()],
+    default_min_severity: AtomicU8,
     stats: RwLock<TracepointStats>,
 }
 
@@ -92,6 +103,8 @@ impl TracepointRegistry {
             next_id: AtomicU64::new(1),
             next_probe_id: AtomicU64::new(1),
             global_enabled: AtomicU8::new(1), // Enabled by default
+            min_severity: [const { AtomicU8::new(FOLLOW_DEFAULT) }; SUBSYSTEMS.len()],
+            default_min_severity: AtomicU8::new(Severity::Trace as u8), // Everything recorded
             stats: RwLock::new(TracepointStats::default()),
         }
     }
@@ -224,6 +237,44 @@ impl TracepointRegistry {
         Ok(disabled_count)
     }
 
+    /// Record events of `subsystem` at `min_severity` and above, whatever the default.
+    /// `Subsystem::Unknown` always follows the default
+    pub fn set_filter(&self, subsystem: Subsystem, min_severity: Severity) -> Result<(), ObservabilityError> {
+        if subsystem == Subsystem::Unknown {
+            return Err(ObservabilityError::InvalidConfiguration);
+        }
+        self.min_severity[subsystem as usize].store(min_severity as u8, Ordering::Release);
+        Ok(())
+    }
+
+    /// Return `subsystem` to the default minimum severity
+    pub fn clear_filter(&self, subsystem: Subsystem) {
+        self.min_severity[subsystem as usize].store(FOLLOW_DEFAULT, Ordering::Release);
+    }
+
+    /// Minimum severity of subsystems without a filter of their own
+    pub fn set_default_filter(&self, min_severity: Severity) {
+        self.default_min_severity.store(min_severity as u8, Ordering::Release);
+    }
+
+    /// The lowest severity recorded for `subsystem`
+    pub fn min_severity(&self, subsystem: Subsystem) -> Severity {
+        let level = self.min_severity_level(subsystem);
+        SEVERITIES.get(level as usize).copied().unwrap_or(Severity::Trace)
+    }
+
+    fn min_severity_level(&self, subsystem: Subsystem) -> u8 {
+        match self.min_severity.get(subsystem as usize).map(|level| level.load(Ordering::Relaxed)) {
+            Some(level) if level != FOLLOW_DEFAULT => level,
+            _ => self.default_min_severity.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Whether an event of `subsystem` at `severity` is recorded
+    pub fn allows(&self, subsystem: Subsystem, severity: Severity) -> bool {
+        severity as u8 >= self.min_severity_level(subsystem)
+    }
+
     /// Fire a tracepoint
     pub fn fire_tracepoint(&self, id: u32, args: &[u64], data: &[u8]) {
         // Quick check if globally disabled
@@ -233,8 +284,8 @@ impl TracepointRegistry {
         
         let tracepoints = self.tracepoints.read();
         if let Some(tracepoint) = tracepoints.get(&id) {
-            // Quick check if tracepoint is enabled
-            if tracepoint.enabled.load(Ordering::Relaxed) == 0 {
+            // Quick check if tracepoint is enabled, and its events wanted
+            if tracepoint.enabled.load(Ordering::Relaxed) == 0 || !self.allows(tracepoint.subsystem, Severity::Debug) {
                 return;
             }
             
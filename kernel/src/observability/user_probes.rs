@@ -0,0 +1,206 @@
+//! User Probes - USDT-style tracepoints fired from user space
+//!
+//! User programs emit probes with the `TraceProbe` syscall; they land in the flight recorder
+//! as `Tracepoint` events attributed to the calling process, inside its current trace.
+//!
+//! Whether a probe is wanted is decided per subsystem by a minimum severity, published in a
+//! read-only page mapped into every process at `PROBE_PAGE_ADDR`. `UserProbe::fire` checks
+//! that page before trapping, so a disabled probe costs two loads and no syscall. The kernel
+//! repeats the check, so processes that skip it cannot record disabled probes.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use x86_64::{PhysAddr, VirtAddr};
+use crate::vmm::{VmArea, VmAreaType, VmError, VmPermissions};
+use super::event_codec::{SEVERITIES, SUBSYSTEMS};
+use super::flight_recorder::FlightRecorder;
+use super::trace_correlation::AttributeValue;
+use super::tracepoints::MAX_TRACEPOINT_DATA_SIZE;
+use super::{ObservabilityError, ObservabilityEvent, Severity, Subsystem};
+
+/// Where the probe page is mapped in user address spaces, just below the stack region
+pub const PROBE_PAGE_ADDR: u64 = 0x6fff_ffff_f000;
+
+/// Longest probe name accepted
+pub const MAX_PROBE_NAME: usize = 64;
+
+/// Most data one probe carries
+pub const MAX_PROBE_DATA: usize = MAX_TRACEPOINT_DATA_SIZE;
+
+/// `min_severity` value of a subsystem whose probes are all disabled
+pub const PROBES_OFF: u8 = 0xff;
+
+/// Physical address of the kernel's probe page, 0 until `init` allocates it
+static PROBE_PAGE_PHYS: AtomicU64 = AtomicU64::new(0);
+
+/// Probe enablement shared with user space
+#[repr(C, align(4096))]
+pub struct ProbeEnablePage {
+    /// Bumped on every change, so user space can cache its decisions
+    pub generation: AtomicU64,
+    /// Lowest severity recorded per subsystem, indexed by `Subsystem as usize`
+    pub min_severity: [AtomicU8; SUBSYSTEMS.len()],
+}
+
+const _: () = assert!(core::mem::size_of::<ProbeEnablePage>() == 4096);
+
+impl ProbeEnablePage {
+    /// A page with every subsystem's probes disabled
+    pub const fn new() -> Self {
+        Self {
+            generation: AtomicU64::new(0),
+            min_severity: [const { AtomicU8::new(PROBES_OFF) }; SUBSYSTEMS.len()],
+        }
+    }
+
+    /// Record probes of `subsystem` at `min_severity` and above, or none
+    pub fn set(&self, subsystem: Subsystem, min_severity: Option<Severity>) {
+        let level = min_severity.map_or(PROBES_OFF, |severity| severity as u8);
+        self.min_severity[subsystem as usize].store(level, Ordering::Release);
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    /// Whether a probe of the raw `subsystem` and `severity` values would be recorded
+    pub fn is_enabled(&self, subsystem: u8, severity: u8) -> bool {
+        self.min_severity
+            .get(subsystem as usize)
+            .is_some_and(|level| severity >= level.load(Ordering::Relaxed))
+    }
+}
+
+impl Default for ProbeEnablePage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A probe as user space declares it
+#[derive(Debug, Clone, Copy)]
+pub struct UserProbe<'a> {
+    pub name: &'a str,
+    pub subsystem: Subsystem,
+    pub severity: Severity,
+}
+
+impl UserProbe<'_> {
+    /// Arguments of the `TraceProbe` syscall for this probe carrying `data`
+    pub fn syscall_args(&self, data: &[u8]) -> [u64; 6] {
+        [
+            self.name.as_ptr() as u64,
+            self.name.len() as u64,
+            self.subsystem as u64,
+            self.severity as u64,
+            data.as_ptr() as u64,
+            data.len() as u64,
+        ]
+    }
+
+    /// The user-space side of a probe: consult `page` and only enter the kernel through
+    /// `syscall` when the probe is enabled. Returns the syscall's result, or `None` when
+    /// the probe was suppressed
+    pub fn fire(&self, page: &ProbeEnablePage, data: &[u8], syscall: impl FnOnce(u64, [u64; 6]) -> i64) -> Option<i64> {
+        if !page.is_enabled(self.subsystem as u8, self.severity as u8) {
+            return None;
+        }
+        Some(syscall(crate::syscall::SyscallNumber::TraceProbe as u64, self.syscall_args(data)))
+    }
+}
+
+/// Allocate and publish the probe page; every subsystem starts disabled
+pub fn init() -> Result<(), ObservabilityError> {
+    if PROBE_PAGE_PHYS.load(Ordering::Acquire) != 0 {
+        return Err(ObservabilityError::AlreadyInitialized);
+    }
+    let frame = crate::memory::allocate_frame().ok_or(ObservabilityError::ResourceExhausted)?;
+    let page = crate::memory::phys_to_virt(frame.start_address()).as_mut_ptr::<ProbeEnablePage>();
+    // SAFETY: The frame was just allocated for the probe page and is reachable through the
+    // physical memory mapping; it is 4 KiB aligned like `ProbeEnablePage` and nothing else
+    // refers to it yet.
+    unsafe { page.write(ProbeEnablePage::new()) };
+    PROBE_PAGE_PHYS.store(frame.start_address().as_u64(), Ordering::Release);
+    Ok(())
+}
+
+/// The kernel's view of the probe page, once `init` has run
+pub fn probe_page() -> Option<&'static ProbeEnablePage> {
+    let phys = PROBE_PAGE_PHYS.load(Ordering::Acquire);
+    if phys == 0 {
+        return None;
+    }
+    let page = crate::memory::phys_to_virt(PhysAddr::new(phys)).as_ptr::<ProbeEnablePage>();
+    // SAFETY: `init` initialized the page and it is never freed; all fields are atomics, so
+    // shared references are sound.
+    Some(unsafe { &*page })
+}
+
+/// Record probes of `subsystem` at `min_severity` and above, or none
+pub fn set_probe_level(subsystem: Subsystem, min_severity: Option<Severity>) -> Result<(), ObservabilityError> {
+    probe_page().ok_or(ObservabilityError::NotInitialized)?.set(subsystem, min_severity);
+    Ok(())
+}
+
+/// Map the probe page read-only at `PROBE_PAGE_ADDR` in an address space
+pub fn map_probe_page(address_space_id: u64) -> Result<(), ObservabilityError> {
+    let phys = PROBE_PAGE_PHYS.load(Ordering::Acquire);
+    if phys == 0 {
+        return Err(ObservabilityError::NotInitialized);
+    }
+    let start = VirtAddr::new(PROBE_PAGE_ADDR);
+    let permissions = VmPermissions::READ | VmPermissions::USER;
+    crate::vmm::with_vmm(|vmm| -> Result<(), VmError> {
+        let address_space = vmm.get_address_space_mut(address_space_id).ok_or(VmError::InvalidAddressSpace)?;
+        address_space.map_area(&VmArea::new(start, start + 4096u64, VmAreaType::Shared, permissions))?;
+        vmm.map_page(address_space_id, start, PhysAddr::new(phys), permissions.to_page_table_flags())
+    })
+    .map_err(|_| ObservabilityError::ResourceExhausted)
+}
+
+/// Record a probe fired by `pid` into `recorder` unless `page` has it disabled. Returns
+/// whether it was recorded
+pub fn record_probe(
+    recorder: &FlightRecorder,
+    page: &ProbeEnablePage,
+    pid: u32,
+    probe: &UserProbe,
+    data: &[u8],
+    trace: Option<(u128, Option<u64>)>,
+) -> Result<bool, ObservabilityError> {
+    if probe.name.is_empty() || probe.name.len() > MAX_PROBE_NAME || data.len() > MAX_PROBE_DATA {
+        return Err(ObservabilityError::InvalidTracepoint);
+    }
+    if !page.is_enabled(probe.subsystem as u8, probe.severity as u8) {
+        return Ok(false);
+    }
+    let event = ObservabilityEvent::Tracepoint {
+        name: probe.name.to_string(),
+        subsystem: probe.subsystem,
+        data: data.to_vec(),
+    };
+    let (trace_id, parent_span_id) = trace.map_or((None, None), |(trace_id, span)| (Some(trace_id), span));
+    recorder.record_event_from(event, probe.severity, pid, trace_id, parent_span_id);
+    Ok(true)
+}
+
+/// Kernel side of the `TraceProbe` syscall, after the arguments were copied in: validate
+/// them, record the probe and add it to the current span of the active trace
+pub fn handle_probe(pid: u32, name: &str, subsystem: u64, severity: u64, data: &[u8]) -> Result<bool, ObservabilityError> {
+    let subsystem = *SUBSYSTEMS.get(subsystem as usize).ok_or(ObservabilityError::InvalidTracepoint)?;
+    let severity = *SEVERITIES.get(severity as usize).ok_or(ObservabilityError::InvalidTracepoint)?;
+    let page = probe_page().ok_or(ObservabilityError::NotInitialized)?;
+    let probe = UserProbe { name, subsystem, severity };
+
+    super::with_observability(|obs| {
+        let context = obs.trace_correlation.get_current_context();
+        let trace = context.as_ref().map(|context| (context.trace_id, context.current_span_id));
+        let recorded = record_probe(&obs.flight_recorder, page, pid, &probe, data, trace)?;
+        if let Some((trace_id, Some(span_id))) = trace.filter(|_| recorded) {
+            let mut attributes = BTreeMap::new();
+            attributes.insert(String::from("pid"), AttributeValue::Int(pid as i64));
+            attributes.insert(String::from("data"), AttributeValue::Bytes(data.to_vec()));
+            // A trace that ended or filled up keeps the flight recorder entry regardless
+            let _ = obs.trace_correlation.add_span_event(trace_id, span_id, name, attributes);
+        }
+        Ok(recorded)
+    })?
+}
@@ -1,20 +1,48 @@
 //! Watchdog Manager - Per-subsystem monitoring and micro-restarts
 //!
 //! This module provides watchdog functionality for monitoring subsystem health
-//! and performing automatic recovery actions including micro-restarts.
+//! and performing automatic recovery actions including micro-restarts. Before a
+//! restart, the hung subsystem's threads and recent events are saved as a hang dump.
+//!
+//! A subsystem pets its watchdog before each deadline, which falls `timeout_ms` after the
+//! last pet; petting exactly at the deadline is in time. A missed deadline fires the
+//! watchdog's action once, and the next deadline falls a whole timeout later, so a
+//! subsystem that stays silent misses again and again and escalates. Times are
+//! milliseconds of uptime.
+//!
+//! A restart goes to the subsystem's own restart handler. Subsystems without one are
+//! restarted through the default handler, which the service manager installs when it
+//! starts; before then, or for subsystems no service runs, the restart fails.
 
+use alloc::format;
 use alloc::string::{String, ToString};
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
 use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
 use spin::RwLock;
+use super::crash_handler::{CrashHandler, CrashSeverity, CrashType};
+use super::flight_recorder::FlightRecorder;
+use super::hang_dump::{self, HangDump};
 use super::{Subsystem, WatchdogAction};
 
 /// Maximum number of watchdogs
 const MAX_WATCHDOGS: usize = 256;
 
+/// Hang dumps kept for analysis; the oldest is dropped first
+const MAX_HANG_DUMPS: usize = 8;
+
 /// Default watchdog timeout in milliseconds
 const DEFAULT_TIMEOUT_MS: u32 = 30000; // 30 seconds
 
+/// Restarts the subsystems whose watchdogs have no restart handler of their own
+static DEFAULT_RESTART_HANDLER: RwLock<Option<RestartHandler>> = RwLock::new(None);
+
+/// Have `handler` restart every subsystem whose watchdog has no restart handler of its own.
+/// It runs with the observability system locked, so it must not record events
+pub fn set_default_restart_handler(handler: RestartHandler) {
+    *DEFAULT_RESTART_HANDLER.write() = Some(handler);
+}
+
 /// Watchdog configuration
 #[derive(Debug, Clone)]
 pub struct WatchdogConfig {
@@ -50,6 +78,8 @@ pub enum EscalationPolicy {
     Restart,
     /// Restart then panic if restart fails
     RestartThenPanic,
+    /// Warn, then restart once more than `max_failures` deadlines in a row were missed
+    WarnThenRestart,
     /// Immediate panic
     Panic,
     /// Custom handler
@@ -75,12 +105,17 @@ pub struct Watchdog {
     pub config: WatchdogConfig,
     pub state: AtomicU8, // WatchdogState as u8
     pub last_heartbeat: AtomicU64,
+    /// When the watchdog fires unless petted first
+    pub deadline: AtomicU64,
+    /// Deadlines missed since the last pet
     pub failure_count: AtomicU64,
     pub restart_count: AtomicU64,
     pub last_restart_time: AtomicU64,
     pub restart_handler: Option<RestartHandler>,
     pub health_check_handler: Option<HealthCheckHandler>,
     pub custom_escalation_handler: Option<EscalationHandler>,
+    /// Threads doing the subsystem's work, captured in its hang dumps
+    pub threads: Vec<u64>,
 }
 
 /// Restart handler function type
@@ -111,6 +146,7 @@ pub struct WatchdogStats {
     pub total_timeouts: u64,
     pub total_restarts: u64,
     pub total_panics: u64,
+    pub total_hang_dumps: u64,
     pub average_heartbeat_interval_ms: u32,
     pub last_timeout_timestamp: u64,
 }
@@ -123,6 +159,7 @@ pub struct WatchdogManager {
     global_enabled: AtomicU8,
     stats: RwLock<WatchdogStats>,
     monitor_thread_active: AtomicU8,
+    hang_dumps: RwLock<VecDeque<HangDump>>,
 }
 
 impl WatchdogManager {
@@ -135,16 +172,42 @@ impl WatchdogManager {
             global_enabled: AtomicU8::new(1),
             stats: RwLock::new(WatchdogStats::default()),
             monitor_thread_active: AtomicU8::new(0),
+            hang_dumps: RwLock::new(VecDeque::new()),
         }
     }
 
-    /// Register a new watchdog for a subsystem
+    /// Register a watchdog for a subsystem that takes `action` when the subsystem goes
+    /// `timeout_ms` without petting it. Repeated misses escalate a `Warning` to a restart.
+    /// The watchdog is started
     pub fn register_watchdog(
+        &self,
+        subsystem: Subsystem,
+        timeout_ms: u32,
+        action: WatchdogAction,
+    ) -> Result<u32, WatchdogError> {
+        let escalation_policy = match action {
+            WatchdogAction::Warning => EscalationPolicy::WarnThenRestart,
+            WatchdogAction::Restart => EscalationPolicy::Restart,
+            WatchdogAction::Panic => EscalationPolicy::Panic,
+            WatchdogAction::Ignore => return Err(WatchdogError::InvalidConfiguration),
+        };
+        let config = WatchdogConfig { timeout_ms, escalation_policy, ..WatchdogConfig::default() };
+        let id = self.register_watchdog_with_config(subsystem, &format!("{:?}", subsystem), config)?;
+        self.start_watchdog(subsystem)?;
+        Ok(id)
+    }
+
+    /// Register a new watchdog for a subsystem
+    pub fn register_watchdog_with_config(
         &self,
         subsystem: Subsystem,
         name: &str,
         config: WatchdogConfig,
     ) -> Result<u32, WatchdogError> {
+        if config.timeout_ms == 0 {
+            return Err(WatchdogError::InvalidConfiguration);
+        }
+
         let mut watchdogs = self.watchdogs.write();
         let mut subsystem_to_id = self.subsystem_to_id.write();
         
@@ -159,20 +222,23 @@ impl WatchdogManager {
         }
         
         let id = self.next_id.fetch_add(1, Ordering::SeqCst) as u32;
+        let now = crate::time::get_uptime_ms();
         
         let watchdog = Watchdog {
             id,
             subsystem,
             name: name.to_string(),
-            config,
             state: AtomicU8::new(WatchdogState::Inactive as u8),
-            last_heartbeat: AtomicU64::new(crate::time::get_timestamp()),
+            last_heartbeat: AtomicU64::new(now),
+            deadline: AtomicU64::new(now + config.timeout_ms as u64),
+            config,
             failure_count: AtomicU64::new(0),
             restart_count: AtomicU64::new(0),
             last_restart_time: AtomicU64::new(0),
             restart_handler: None,
             health_check_handler: None,
             custom_escalation_handler: None,
+            threads: Vec::new(),
         };
         
         watchdogs.insert(id, watchdog);
@@ -226,6 +292,25 @@ impl WatchdogManager {
         }
     }
 
+    /// Associate a thread with a subsystem, so its hang dumps include the thread's stack
+    pub fn add_thread(&self, subsystem: Subsystem, pid: u64) -> Result<(), WatchdogError> {
+        let subsystem_to_id = self.subsystem_to_id.read();
+        if let Some(&id) = subsystem_to_id.get(&subsystem) {
+            drop(subsystem_to_id);
+            let mut watchdogs = self.watchdogs.write();
+            if let Some(watchdog) = watchdogs.get_mut(&id) {
+                if !watchdog.threads.contains(&pid) {
+                    watchdog.threads.push(pid);
+                }
+                Ok(())
+            } else {
+                Err(WatchdogError::SubsystemNotFound)
+            }
+        } else {
+            Err(WatchdogError::SubsystemNotFound)
+        }
+    }
+
     /// Start monitoring a subsystem
     pub fn start_watchdog(&self, subsystem: Subsystem) -> Result<(), WatchdogError> {
         let subsystem_to_id = self.subsystem_to_id.read();
@@ -233,11 +318,13 @@ impl WatchdogManager {
             drop(subsystem_to_id);
             let watchdogs = self.watchdogs.read();
             if let Some(watchdog) = watchdogs.get(&id) {
-                watchdog.state.store(WatchdogState::Active as u8, Ordering::SeqCst);
-                watchdog.last_heartbeat.store(crate::time::get_timestamp(), Ordering::SeqCst);
+                let was_active = watchdog.state.swap(WatchdogState::Active as u8, Ordering::SeqCst) == WatchdogState::Active as u8;
+                watchdog.rearm(crate::time::get_uptime_ms());
                 
                 // Update stats
-                self.stats.write().active_watchdogs += 1;
+                if !was_active {
+                    self.stats.write().active_watchdogs += 1;
+                }
                 
                 // Start monitor thread if not already active
                 self.ensure_monitor_thread_active();
@@ -273,14 +360,16 @@ impl WatchdogManager {
         }
     }
 
-    /// Send heartbeat for a subsystem
-    pub fn heartbeat(&self, subsystem: Subsystem) -> Result<(), WatchdogError> {
+    /// Pet a subsystem's watchdog, moving its deadline a timeout on from now and ending
+    /// any run of missed deadlines
+    pub fn pet(&self, subsystem: Subsystem) -> Result<(), WatchdogError> {
         let subsystem_to_id = self.subsystem_to_id.read();
         if let Some(&id) = subsystem_to_id.get(&subsystem) {
             drop(subsystem_to_id);
             let watchdogs = self.watchdogs.read();
             if let Some(watchdog) = watchdogs.get(&id) {
-                watchdog.last_heartbeat.store(crate::time::get_timestamp(), Ordering::SeqCst);
+                watchdog.rearm(crate::time::get_uptime_ms());
+                watchdog.failure_count.store(0, Ordering::SeqCst);
                 
                 // Reset state to active if it was triggered
                 let current_state = watchdog.state.load(Ordering::Relaxed);
@@ -297,40 +386,48 @@ impl WatchdogManager {
         }
     }
 
-    /// Check all watchdogs for timeouts
-    pub fn check_watchdogs(&self) {
+    /// Fire the watchdogs whose deadlines passed by `current_time`, recording into and
+    /// dumping from `recorder` and reporting panics to `crashes`
+    pub fn check_watchdogs(&self, current_time: u64, recorder: &FlightRecorder, crashes: &CrashHandler) {
         if self.global_enabled.load(Ordering::Relaxed) == 0 {
             return;
         }
         
-        let current_time = crate::time::get_timestamp();
         let watchdogs = self.watchdogs.read();
         
         for watchdog in watchdogs.values() {
             let state = watchdog.state.load(Ordering::Relaxed);
-            if state != WatchdogState::Active as u8 {
+            if state != WatchdogState::Active as u8 && state != WatchdogState::Triggered as u8 {
                 continue;
             }
             
-            let last_heartbeat = watchdog.last_heartbeat.load(Ordering::Relaxed);
-            let timeout_ms = watchdog.config.timeout_ms as u64;
-            
-            if current_time - last_heartbeat > timeout_ms {
-                self.handle_watchdog_timeout(watchdog, current_time);
+            if current_time > watchdog.deadline.load(Ordering::Relaxed) {
+                // The next miss is a whole timeout away, so this one fires once
+                watchdog.deadline.store(current_time + watchdog.config.timeout_ms as u64, Ordering::SeqCst);
+                self.handle_watchdog_timeout(watchdog, current_time, recorder, crashes);
             } else if watchdog.config.health_check_interval_ms > 0 {
                 // Perform periodic health check
-                let last_check_time = last_heartbeat;
+                let last_check_time = watchdog.last_heartbeat.load(Ordering::Relaxed);
                 let check_interval = watchdog.config.health_check_interval_ms as u64;
                 
-                if current_time - last_check_time > check_interval {
-                    self.perform_health_check(watchdog);
+                if current_time.saturating_sub(last_check_time) > check_interval {
+                    self.perform_health_check(watchdog, recorder, crashes);
                 }
             }
         }
     }
 
+    /// Act on a timeout of `subsystem`'s watchdog now, as if its heartbeat had lapsed.
+    /// Returns the action taken
+    pub fn simulate_timeout(&self, subsystem: Subsystem, recorder: &FlightRecorder, crashes: &CrashHandler) -> Result<WatchdogAction, WatchdogError> {
+        let id = *self.subsystem_to_id.read().get(&subsystem).ok_or(WatchdogError::SubsystemNotFound)?;
+        let watchdogs = self.watchdogs.read();
+        let watchdog = watchdogs.get(&id).ok_or(WatchdogError::SubsystemNotFound)?;
+        Ok(self.handle_watchdog_timeout(watchdog, crate::time::get_uptime_ms(), recorder, crashes))
+    }
+
     /// Handle watchdog timeout
-    fn handle_watchdog_timeout(&self, watchdog: &Watchdog, current_time: u64) {
+    fn handle_watchdog_timeout(&self, watchdog: &Watchdog, current_time: u64, recorder: &FlightRecorder, crashes: &CrashHandler) -> WatchdogAction {
         // Mark as triggered
         watchdog.state.store(WatchdogState::Triggered as u8, Ordering::SeqCst);
         
@@ -360,6 +457,13 @@ impl WatchdogManager {
                     WatchdogAction::Panic
                 }
             },
+            EscalationPolicy::WarnThenRestart => {
+                if failure_count <= watchdog.config.max_failures as u64 {
+                    WatchdogAction::Warning
+                } else {
+                    WatchdogAction::Restart
+                }
+            },
             EscalationPolicy::Panic => WatchdogAction::Panic,
             EscalationPolicy::Custom => {
                 if let Some(handler) = watchdog.custom_escalation_handler {
@@ -371,7 +475,7 @@ impl WatchdogManager {
         };
         
         // Record watchdog event
-        super::record_event(super::ObservabilityEvent::Watchdog {
+        recorder.record_event(super::ObservabilityEvent::Watchdog {
             subsystem: watchdog.subsystem,
             timeout_ms: watchdog.config.timeout_ms,
             action,
@@ -383,28 +487,64 @@ impl WatchdogManager {
                 // Just log the warning - already recorded above
             },
             WatchdogAction::Restart => {
-                self.attempt_restart(watchdog);
+                // Save the hung state before the restart discards it
+                self.save_hang_dump(hang_dump::capture(
+                    recorder,
+                    watchdog.subsystem,
+                    &watchdog.name,
+                    watchdog.last_heartbeat.load(Ordering::Relaxed),
+                    failure_count,
+                    &watchdog.threads,
+                ));
+                self.attempt_restart(watchdog, current_time, recorder, crashes);
             },
             WatchdogAction::Panic => {
-                self.stats.write().total_panics += 1;
-                crate::serial::_print(format_args!("[WATCHDOG] CRITICAL: Timeout for subsystem {:?} - system would panic in production\n", watchdog.subsystem));
-                // In production, this would be a panic. For now, mark as failed.
-                watchdog.state.store(WatchdogState::Failed as u8, Ordering::SeqCst);
+                self.panic(watchdog, recorder, crashes, "missed its deadline");
             },
             WatchdogAction::Ignore => {
                 // Do nothing
             },
         }
+        
+        action
     }
 
-    /// Attempt to restart a subsystem
-    fn attempt_restart(&self, watchdog: &Watchdog) {
-        let current_time = crate::time::get_timestamp();
+    /// Keep a hang dump, dropping the oldest beyond `MAX_HANG_DUMPS`
+    fn save_hang_dump(&self, dump: HangDump) {
+        let mut dumps = self.hang_dumps.write();
+        if dumps.len() >= MAX_HANG_DUMPS {
+            dumps.pop_front();
+        }
+        dumps.push_back(dump);
+        self.stats.write().total_hang_dumps += 1;
+    }
+
+    /// Saved hang dumps, oldest first
+    pub fn get_hang_dumps(&self) -> Vec<HangDump> {
+        self.hang_dumps.read().iter().cloned().collect()
+    }
+
+    /// The most recent hang dump of a subsystem
+    pub fn latest_hang_dump(&self, subsystem: Subsystem) -> Option<HangDump> {
+        self.hang_dumps.read().iter().rev().find(|dump| dump.subsystem == subsystem).cloned()
+    }
+
+    /// Report a subsystem's hang to the crash handler as fatal, which halts the system
+    /// unless configured not to, and give up on its watchdog
+    fn panic(&self, watchdog: &Watchdog, recorder: &FlightRecorder, crashes: &CrashHandler, reason: &str) {
+        self.stats.write().total_panics += 1;
+        watchdog.state.store(WatchdogState::Failed as u8, Ordering::SeqCst);
+        let message = format!("Watchdog of {} {}", watchdog.name, reason);
+        let _ = crashes.handle_crash(recorder, CrashType::Timeout, CrashSeverity::Fatal, Some(watchdog.subsystem), None, None, None, None, &message);
+    }
+
+    /// Attempt to restart a subsystem through its restart handler, or the default one
+    fn attempt_restart(&self, watchdog: &Watchdog, current_time: u64, recorder: &FlightRecorder, crashes: &CrashHandler) {
         let last_restart = watchdog.last_restart_time.load(Ordering::Relaxed);
         
         // Check restart rate limiting
         let hour_ms = 3600000; // 1 hour in milliseconds
-        if current_time - last_restart < hour_ms {
+        if current_time.saturating_sub(last_restart) < hour_ms {
             let restart_count = watchdog.restart_count.load(Ordering::Relaxed);
             if restart_count >= watchdog.config.max_restarts_per_hour as u64 {
                 // Too many restarts, mark as failed instead of panic
@@ -418,12 +558,11 @@ impl WatchdogManager {
         // Mark as recovering
         watchdog.state.store(WatchdogState::Recovering as u8, Ordering::SeqCst);
         
-        // Attempt restart
-        let restart_result = if let Some(handler) = watchdog.restart_handler {
-            handler(watchdog.subsystem)
-        } else {
-            // Default restart behavior
-            self.default_restart_handler(watchdog.subsystem)
+        // Whoever owns the subsystem knows how to restart it; the rest are the service
+        // manager's to restart
+        let restart_result = match watchdog.restart_handler.or(*DEFAULT_RESTART_HANDLER.read()) {
+            Some(handler) => handler(watchdog.subsystem),
+            None => Err(WatchdogError::RestartFailed),
         };
         
         match restart_result {
@@ -432,7 +571,7 @@ impl WatchdogManager {
                 watchdog.restart_count.fetch_add(1, Ordering::SeqCst);
                 watchdog.last_restart_time.store(current_time, Ordering::SeqCst);
                 watchdog.state.store(WatchdogState::Active as u8, Ordering::SeqCst);
-                watchdog.last_heartbeat.store(current_time, Ordering::SeqCst);
+                watchdog.rearm(current_time);
                 
                 self.stats.write().total_restarts += 1;
             },
@@ -442,61 +581,34 @@ impl WatchdogManager {
                 
                 // Escalate based on policy
                 if watchdog.config.escalation_policy == EscalationPolicy::RestartThenPanic {
-                    self.stats.write().total_panics += 1;
-                    crate::serial::_print(format_args!("[WATCHDOG] CRITICAL: Failed to restart subsystem {:?} - would panic in production\n", watchdog.subsystem));
-                    // Keep as failed instead of panicking
+                    self.panic(watchdog, recorder, crashes, "could not restart its subsystem");
                 }
             },
         }
     }
 
     /// Perform health check for a watchdog
-    fn perform_health_check(&self, watchdog: &Watchdog) {
+    fn perform_health_check(&self, watchdog: &Watchdog, recorder: &FlightRecorder, crashes: &CrashHandler) {
         if let Some(handler) = watchdog.health_check_handler {
+            let current_time = crate::time::get_uptime_ms();
             match handler(watchdog.subsystem) {
                 Ok(healthy) => {
                     if healthy {
-                        // Update heartbeat on successful health check
-                        watchdog.last_heartbeat.store(crate::time::get_timestamp(), Ordering::SeqCst);
+                        // A healthy subsystem counts as having petted its watchdog
+                        watchdog.rearm(current_time);
                     } else {
                         // Health check failed, treat as timeout
-                        self.handle_watchdog_timeout(watchdog, crate::time::get_timestamp());
+                        self.handle_watchdog_timeout(watchdog, current_time, recorder, crashes);
                     }
                 },
                 Err(_) => {
                     // Health check error, treat as timeout
-                    self.handle_watchdog_timeout(watchdog, crate::time::get_timestamp());
+                    self.handle_watchdog_timeout(watchdog, current_time, recorder, crashes);
                 },
             }
         }
     }
 
-    /// Default restart handler
-    fn default_restart_handler(&self, subsystem: Subsystem) -> Result<(), WatchdogError> {
-        // Default implementation - would need to be customized per subsystem
-        match subsystem {
-            Subsystem::ServiceManager => {
-                // Restart service manager
-                // TODO: Implement actual restart logic
-                Ok(())
-            },
-            Subsystem::Network => {
-                // Restart network stack
-                // TODO: Implement actual restart logic
-                Ok(())
-            },
-            Subsystem::Graphics => {
-                // Restart graphics subsystem
-                // TODO: Implement actual restart logic
-                Ok(())
-            },
-            _ => {
-                // Generic restart - just reset state
-                Ok(())
-            },
-        }
-    }
-
     /// Ensure monitor thread is active
     fn ensure_monitor_thread_active(&self) {
         if self.monitor_thread_active.swap(1, Ordering::SeqCst) == 0 {
@@ -555,6 +667,14 @@ impl WatchdogManager {
     }
 }
 
+impl Watchdog {
+    /// Start a new timeout from `now`
+    fn rearm(&self, now: u64) {
+        self.last_heartbeat.store(now, Ordering::SeqCst);
+        self.deadline.store(now + self.config.timeout_ms as u64, Ordering::SeqCst);
+    }
+}
+
 /// Watchdog status information
 #[derive(Debug, Clone)]
 pub struct WatchdogStatus {
@@ -573,7 +693,7 @@ pub struct WatchdogStatus {
 macro_rules! watchdog_heartbeat {
     ($subsystem:expr) => {
         let _ = $crate::observability::with_observability(|obs| {
-            let _ = obs.watchdog.heartbeat($subsystem);
+            let _ = obs.watchdog.pet($subsystem);
         });
     };
 }
@@ -583,7 +703,7 @@ macro_rules! watchdog_heartbeat {
 macro_rules! register_watchdog {
     ($subsystem:expr, $name:expr) => {
         $crate::observability::with_observability_mut(|obs| {
-            obs.watchdog.register_watchdog(
+            obs.watchdog.register_watchdog_with_config(
                 $subsystem,
                 $name,
                 $crate::observability::watchdog::WatchdogConfig::default()
@@ -592,7 +712,7 @@ macro_rules! register_watchdog {
     };
     ($subsystem:expr, $name:expr, $config:expr) => {
         $crate::observability::with_observability_mut(|obs| {
-            obs.watchdog.register_watchdog($subsystem, $name, $config)
+            obs.watchdog.register_watchdog_with_config($subsystem, $name, $config)
         })
     };
 }
\ No newline at end of file
@@ -0,0 +1,709 @@
+//! Observability Test
+//! Exercises the flight recorder's compressed storage: compression ratio on repetitive
+//! traces and bit-exact decoding of every event kind; its export to an on-disk ring; and
+//! queries and latency aggregation over what it recorded; user-space probes; the hang
+//! dump a watchdog saves before restarting a subsystem; drains and dump files of a full
+//! recorder; the per-subsystem severity filter; and watchdog deadlines firing and escalating
+//! their actions
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use spin::{Mutex, Once};
+use crate::drivers::{BlockDevice, DeviceError, DeviceResult};
+use crate::observability::crash_handler::{CrashHandler, CrashHandlerConfig, CrashType};
+use crate::observability::event_codec;
+use crate::observability::hang_dump;
+use crate::observability::query::{self, EventFilter, LatencySummary};
+use crate::observability::tracepoints::TracepointRegistry;
+use crate::observability::ring_file::{RingFile, SLOT_PAYLOAD};
+use crate::observability::user_probes::{self, ProbeEnablePage, UserProbe};
+use crate::observability::watchdog::{EscalationPolicy, WatchdogConfig, WatchdogManager};
+use crate::syscall::SyscallNumber;
+use crate::observability::flight_recorder::{self, DrainMode, FlightRecorder, FlightRecorderConfig, FlightRecorderEntry};
+use crate::observability::*;
+use crate::serial::_print;
+
+/// Raw capacity of the recorders under test, in entries
+const TEST_MAX_EVENTS: usize = 1024;
+
+fn recorder(enable_compression: bool) -> Result<FlightRecorder, &'static str> {
+    FlightRecorder::with_config(FlightRecorderConfig {
+        max_events: TEST_MAX_EVENTS,
+        enable_compression,
+        redaction_enabled: false,
+        ..FlightRecorderConfig::default()
+    })
+    .map_err(|_| "Failed to create flight recorder")
+}
+
+fn entry(i: u64, subsystem: Subsystem, event: ObservabilityEvent) -> FlightRecorderEntry {
+    FlightRecorderEntry {
+        timestamp_ns: 5_000_000_000 + i * 850,
+        sequence_id: i + 1,
+        thread_id: 7,
+        cpu_id: (i % 2) as u8,
+        severity: Severity::Trace,
+        subsystem,
+        event,
+        trace_id: None,
+        parent_span_id: None,
+        span_id: i + 1,
+    }
+}
+
+/// Disk in memory, shared so a test can reopen what it wrote
+struct RamDisk {
+    blocks: Vec<u8>,
+}
+
+const RAM_BLOCK_SIZE: usize = 512;
+
+impl RamDisk {
+    fn shared(block_count: usize) -> Arc<Mutex<RamDisk>> {
+        Arc::new(Mutex::new(RamDisk { blocks: vec![0; block_count * RAM_BLOCK_SIZE] }))
+    }
+
+    fn range(&self, lba: u64, len: usize) -> DeviceResult<core::ops::Range<usize>> {
+        let start = lba as usize * RAM_BLOCK_SIZE;
+        if len % RAM_BLOCK_SIZE != 0 || start + len > self.blocks.len() {
+            return Err(DeviceError::InvalidParameter);
+        }
+        Ok(start..start + len)
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn block_size(&self) -> usize {
+        RAM_BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        (self.blocks.len() / RAM_BLOCK_SIZE) as u64
+    }
+
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> DeviceResult<()> {
+        let range = self.range(lba, buffer.len())?;
+        buffer.copy_from_slice(&self.blocks[range]);
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buffer: &[u8]) -> DeviceResult<()> {
+        let range = self.range(lba, buffer.len())?;
+        self.blocks[range].copy_from_slice(buffer);
+        Ok(())
+    }
+}
+
+/// A read/write loop of one process, with the occasional context switch
+fn repetitive_trace(count: u64) -> Vec<FlightRecorderEntry> {
+    (0..count)
+        .map(|i| {
+            if i % 64 == 63 {
+                let event = ObservabilityEvent::ContextSwitch { from_pid: 42, to_pid: 1, reason: ContextSwitchReason::Preemption };
+                return entry(i, Subsystem::Scheduler, event);
+            }
+            let entering = i % 2 == 0;
+            let event = ObservabilityEvent::Syscall {
+                syscall_id: ((i / 2) % 2) as u32,
+                pid: 42,
+                entry: entering,
+                args: if entering { [3, 0x7f00_0000 + (i % 16) * 64, 4096, 0, 0, 0] } else { [0; 6] },
+                result: if entering { None } else { Some(4096) },
+            };
+            entry(i, Subsystem::Kernel, event)
+        })
+        .collect()
+}
+
+/// One entry of every event kind, with optional fields both set and unset
+fn every_event_kind() -> Vec<FlightRecorderEntry> {
+    let events = vec![
+        (Subsystem::Kernel, ObservabilityEvent::Syscall { syscall_id: 60, pid: 9, entry: false, args: [u64::MAX, 1, 2, 3, 4, 5], result: Some(-22) }),
+        (Subsystem::Ipc, ObservabilityEvent::Ipc { from_pid: 1, to_pid: 2, message_type: 3, size: 4, trace_id: u128::MAX - 1 }),
+        (Subsystem::Memory, ObservabilityEvent::Memory { operation: MemoryOperation::Protect, address: 0xffff_8000_0000_0000, size: 8192, pid: 3 }),
+        (Subsystem::Scheduler, ObservabilityEvent::ContextSwitch { from_pid: 3, to_pid: 4, reason: ContextSwitchReason::Signal }),
+        (Subsystem::Interrupt, ObservabilityEvent::Interrupt { vector: 0x20, duration_ns: 1_200, nested: true }),
+        (Subsystem::Memory, ObservabilityEvent::PageFault { address: 0xdead_b000, error_code: 7, pid: 5, resolved: false, duration_ns: 40_000 }),
+        (Subsystem::ServiceManager, ObservabilityEvent::Service { service_id: 12, operation: ServiceOperation::HealthCheck, result: ServiceResult::Timeout }),
+        (Subsystem::Storage, ObservabilityEvent::Watchdog { subsystem: Subsystem::Storage, timeout_ms: 5_000, action: WatchdogAction::Restart }),
+        (Subsystem::Compositor, ObservabilityEvent::Tracepoint { name: String::from("frame_present"), subsystem: Subsystem::Compositor, data: vec![0, 1, 2, 255] }),
+        (Subsystem::Kernel, ObservabilityEvent::SystemBoot { timestamp: 17, boot_stage: String::from("smp-online") }),
+        (Subsystem::Scheduler, ObservabilityEvent::ProcessCreated { pid: 77, name: String::from("raeshell"), parent_pid: Some(1) }),
+        (Subsystem::Scheduler, ObservabilityEvent::ProcessCreated { pid: 1, name: String::new(), parent_pid: None }),
+        (Subsystem::Scheduler, ObservabilityEvent::ProcessTerminated { pid: 77, exit_code: -1, signal: Some(9) }),
+        (Subsystem::Scheduler, ObservabilityEvent::ProcessTerminated { pid: 78, exit_code: 0, signal: None }),
+        (Subsystem::Unknown, ObservabilityEvent::Crash {
+            crash_type: String::from("panic"),
+            severity: String::from("fatal"),
+            subsystem: None,
+            message: String::from("überlauf"),
+            recovery_action: String::from("restart"),
+        }),
+        (Subsystem::Kernel, ObservabilityEvent::TraceCompleted { trace_id: 1 << 100, correlation_id: 3, duration_ms: 12, span_count: 4, error_count: 1 }),
+    ];
+    events
+        .into_iter()
+        .enumerate()
+        .map(|(i, (subsystem, event))| {
+            let mut entry = entry(i as u64 * 3, subsystem, event);
+            if i % 2 == 0 {
+                entry.trace_id = Some(0x1234_5678_9abc_def0_u128 << 32);
+                entry.parent_span_id = Some(i as u64);
+            }
+            entry
+        })
+        .collect()
+}
+
+/// An entry at an explicit time, recorded inside `trace_id` when given
+fn timed(timestamp_ns: u64, subsystem: Subsystem, event: ObservabilityEvent, trace_id: Option<u128>) -> FlightRecorderEntry {
+    let mut entry = entry(timestamp_ns, subsystem, event);
+    entry.timestamp_ns = timestamp_ns;
+    entry.trace_id = trace_id;
+    entry
+}
+
+fn syscall(pid: u32, syscall_id: u32, entering: bool) -> ObservabilityEvent {
+    ObservabilityEvent::Syscall {
+        syscall_id,
+        pid,
+        entry: entering,
+        args: [0; 6],
+        result: if entering { None } else { Some(0) },
+    }
+}
+
+fn ipc(from_pid: u32, to_pid: u32, trace_id: u128) -> ObservabilityEvent {
+    ObservabilityEvent::Ipc { from_pid, to_pid, message_type: 1, size: 64, trace_id }
+}
+
+/// Watchdogs of the hang dump test, reachable from its restart handler
+static HANG_WATCHDOGS: Once<WatchdogManager> = Once::new();
+
+/// Whether the hang dump test's restart handler found the dump already saved
+static DUMPED_BEFORE_RESTART: AtomicBool = AtomicBool::new(false);
+
+fn restart_hung_storage(subsystem: Subsystem) -> Result<(), watchdog::WatchdogError> {
+    let dumped = HANG_WATCHDOGS.get().and_then(|watchdogs| watchdogs.latest_hang_dump(subsystem)).is_some();
+    DUMPED_BEFORE_RESTART.store(dumped, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Restarts of the subsystem the watchdog deadline test never pets
+static SILENT_RESTARTS: AtomicU32 = AtomicU32::new(0);
+
+fn restart_silent_audio(_subsystem: Subsystem) -> Result<(), watchdog::WatchdogError> {
+    SILENT_RESTARTS.fetch_add(1, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Actions of the watchdog events `recorder` holds for `subsystem`, oldest first
+fn watchdog_actions(recorder: &FlightRecorder, subsystem: Subsystem) -> Vec<WatchdogAction> {
+    recorder
+        .snapshot()
+        .iter()
+        .filter_map(|entry| match entry.event {
+            ObservabilityEvent::Watchdog { subsystem: fired, action, .. } if fired == subsystem => Some(action),
+            _ => None,
+        })
+        .collect()
+}
+
+extern "C" fn hung_storage_thread() -> ! {
+    loop {
+        crate::process::block_current();
+    }
+}
+
+/// Test compressed flight recorder storage
+pub fn run_observability_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Obs Test] Starting observability tests...\n"));
+
+    // Test 1: Repetitive events pack far more history into the raw ring's bytes
+    _print(format_args!("[Obs Test] Test 1: Compression of a repetitive trace...\n"));
+    let trace = repetitive_trace(20_000);
+    let compressed = recorder(true)?;
+    for entry in &trace {
+        compressed.add_entry(entry.clone());
+    }
+    let stats = compressed.get_stats();
+    let snapshot = compressed.snapshot();
+    _print(format_args!(
+        "[Obs Test] {} of {} events retained, compression ratio {:.1}x, {} bytes/event\n",
+        snapshot.len(), trace.len(), stats.compression_ratio, stats.average_event_size_bytes
+    ));
+    if stats.compression_ratio < 6.0 {
+        return Err("Compression ratio below 6x on a repetitive trace");
+    }
+    if snapshot.len() < 6 * TEST_MAX_EVENTS {
+        return Err("Compressed ring holds too little history");
+    }
+    if stats.events_dropped as usize + snapshot.len() != trace.len() {
+        return Err("Dropped and retained events do not add up");
+    }
+    _print(format_args!("[Obs Test] ✓ Ratio above 6x\n"));
+
+    // Test 2: What is retained decodes to exactly what was recorded
+    _print(format_args!("[Obs Test] Test 2: Bit-exact snapshot...\n"));
+    if snapshot[..] != trace[trace.len() - snapshot.len()..] {
+        return Err("Snapshot differs from the recorded events");
+    }
+    let recent = compressed.get_recent_events(10);
+    if recent[..] != trace[trace.len() - 10..] {
+        return Err("Recent events differ from the recorded events");
+    }
+    if compressed.get_events_by_subsystem(Subsystem::Scheduler).len() != snapshot.iter().filter(|e| e.subsystem == Subsystem::Scheduler).count() {
+        return Err("Subsystem filter over compressed storage is wrong");
+    }
+    _print(format_args!("[Obs Test] ✓ Newest {} events decode bit-exactly\n", snapshot.len()));
+
+    // Test 3: Every event kind round-trips, including optional fields
+    _print(format_args!("[Obs Test] Test 3: Every event kind...\n"));
+    let kinds = every_event_kind();
+    let small = recorder(true)?;
+    for entry in &kinds {
+        small.add_entry(entry.clone());
+    }
+    if small.snapshot() != kinds {
+        return Err("An event kind did not survive encoding");
+    }
+    _print(format_args!("[Obs Test] ✓ {} event kinds round-trip\n", kinds.len()));
+
+    // Test 4: Uncompressed storage keeps its entry-count bound
+    _print(format_args!("[Obs Test] Test 4: Uncompressed ring...\n"));
+    let raw = recorder(false)?;
+    for entry in &trace {
+        raw.add_entry(entry.clone());
+    }
+    if raw.snapshot()[..] != trace[trace.len() - TEST_MAX_EVENTS..] {
+        return Err("Uncompressed ring does not hold the newest max_events entries");
+    }
+    _print(format_args!("[Obs Test] ✓ Holds the newest {} entries\n", TEST_MAX_EVENTS));
+
+    // Test 5: Damaged records are rejected rather than misdecoded
+    _print(format_args!("[Obs Test] Test 5: Corrupt data...\n"));
+    if event_codec::decode_all(&[0xff, 0x00]) != Err(ObservabilityError::CorruptData) {
+        return Err("Corrupt record was accepted");
+    }
+    _print(format_args!("[Obs Test] ✓ Corrupt record rejected\n"));
+
+    // Test 6: A drain moves recorded events into the on-disk ring
+    _print(format_args!("[Obs Test] Test 6: Drain to the ring file...\n"));
+    let disk = RamDisk::shared(256);
+    let mut ring = RingFile::open(Box::new(disk.clone()), 8, 16).map_err(|_| "Failed to open ring file")?;
+    let source = recorder(true)?;
+    for entry in &kinds {
+        source.add_entry(entry.clone());
+    }
+    let drained = source.drain(usize::MAX, DrainMode::Consume);
+    if drained != kinds || !source.snapshot().is_empty() {
+        return Err("Drain did not empty the recorder in order");
+    }
+    if ring.append(&drained) != Ok(kinds.len()) {
+        return Err("Ring file did not store the drained events");
+    }
+    if ring.read_entries() != Ok(kinds.clone()) {
+        return Err("Drained events are missing from the ring file");
+    }
+    _print(format_args!("[Obs Test] ✓ {} drained events on disk\n", kinds.len()));
+
+    // Test 7: The ring wraps at its size cap, keeping the newest slots
+    _print(format_args!("[Obs Test] Test 7: Ring wraps at its cap...\n"));
+    for chunk in trace.chunks(500) {
+        if ring.append(chunk) != Ok(chunk.len()) {
+            return Err("Ring file rejected events");
+        }
+    }
+    let on_disk = ring.read_entries().map_err(|_| "Failed to read ring file")?;
+    let capacity_bound = ring.slot_count() as usize * SLOT_PAYLOAD;
+    if on_disk.is_empty() || on_disk.len() >= trace.len() || on_disk.len() > capacity_bound {
+        return Err("Ring file did not wrap at its size cap");
+    }
+    if on_disk[..] != trace[trace.len() - on_disk.len()..] {
+        return Err("Wrapped ring does not hold the newest events");
+    }
+    _print(format_args!("[Obs Test] ✓ Newest {} of {} events kept in {} slots\n", on_disk.len(), trace.len(), ring.slot_count()));
+
+    // Test 8: A reader of the previous boot's ring reconstructs events in order
+    _print(format_args!("[Obs Test] Test 8: Reading a prior ring...\n"));
+    drop(ring);
+    let mut prior = RingFile::open_existing(Box::new(disk.clone()), 8, 16).map_err(|_| "Failed to reopen ring file")?;
+    if prior.read_entries() != Ok(on_disk.clone()) {
+        return Err("Reopened ring file lost or reordered events");
+    }
+    let mut resumed = RingFile::open(Box::new(disk.clone()), 8, 16).map_err(|_| "Failed to resume ring file")?;
+    if resumed.append(&kinds[..1]) != Ok(1) {
+        return Err("Resumed ring file rejected an event");
+    }
+    let after = resumed.read_entries().map_err(|_| "Failed to read resumed ring file")?;
+    if after.last() != kinds.first()
+        || after.len() > on_disk.len() + 1
+        || after[..after.len() - 1] != on_disk[on_disk.len() + 1 - after.len()..]
+    {
+        return Err("Resumed ring file did not append after the prior history");
+    }
+    if RingFile::open_existing(Box::new(RamDisk::shared(256)), 8, 16).is_ok() {
+        return Err("Blank disk was taken for a ring file");
+    }
+    _print(format_args!("[Obs Test] ✓ Prior history read back in order\n"));
+
+    // Test 9: Filtering by subsystem returns only that subsystem's events
+    _print(format_args!("[Obs Test] Test 9: Query by subsystem...\n"));
+    const REQUEST: u128 = 0xabc0_0000_0000_0001;
+    const OTHER: u128 = 0xdef0_0000_0000_0002;
+    let queried = recorder(false)?;
+    let request = [
+        timed(1_000, Subsystem::Ipc, ipc(10, 20, REQUEST), Some(REQUEST)),
+        timed(1_500, Subsystem::Kernel, syscall(20, 3, true), Some(REQUEST)),
+        timed(4_500, Subsystem::Kernel, syscall(20, 3, false), Some(REQUEST)),
+        timed(5_000, Subsystem::Ipc, ipc(20, 10, REQUEST), Some(REQUEST)),
+        timed(5_200, Subsystem::Kernel, ObservabilityEvent::TraceCompleted { trace_id: REQUEST, correlation_id: 1, duration_ms: 0, span_count: 4, error_count: 0 }, None),
+    ];
+    let noise = [
+        timed(1_200, Subsystem::Ipc, ipc(30, 40, OTHER), Some(OTHER)),
+        timed(2_000, Subsystem::Memory, ObservabilityEvent::Memory { operation: MemoryOperation::Allocate, address: 0x1000, size: 4096, pid: 30 }, None),
+        timed(3_000, Subsystem::Scheduler, ObservabilityEvent::ContextSwitch { from_pid: 20, to_pid: 30, reason: ContextSwitchReason::Yield }, None),
+        timed(3_500, Subsystem::Ipc, ipc(40, 30, OTHER), Some(OTHER)),
+    ];
+    let mut recorded: Vec<FlightRecorderEntry> = request.iter().chain(noise.iter()).cloned().collect();
+    recorded.sort_by_key(|entry| entry.timestamp_ns);
+    for entry in &recorded {
+        queried.add_entry(entry.clone());
+    }
+    let ipc_only = queried.query(&EventFilter { subsystem: Some(Subsystem::Ipc), ..EventFilter::default() });
+    if ipc_only.len() != 4 || ipc_only.iter().any(|entry| entry.subsystem != Subsystem::Ipc) {
+        return Err("Subsystem filter returned other subsystems' events");
+    }
+    let window = queried.query(&EventFilter { pid: Some(30), since_ns: Some(1_200), until_ns: Some(3_500), ..EventFilter::default() });
+    if window.iter().map(|entry| entry.timestamp_ns).collect::<Vec<_>>() != [1_200, 2_000, 3_000] {
+        return Err("PID and time range filter selected the wrong events");
+    }
+    if queried.query(&EventFilter { min_severity: Some(Severity::Trace), ..EventFilter::default() }).len() != recorded.len()
+        || !queried.query(&EventFilter { min_severity: Some(Severity::Debug), ..EventFilter::default() }).is_empty()
+    {
+        return Err("Severity filter selected the wrong events");
+    }
+    _print(format_args!("[Obs Test] ✓ {} IPC events of {}\n", ipc_only.len(), recorded.len()));
+
+    // Test 10: Filtering by trace ID reconstructs the whole request
+    _print(format_args!("[Obs Test] Test 10: Query by trace ID...\n"));
+    if queried.query(&EventFilter { trace_id: Some(REQUEST), ..EventFilter::default() }) != request {
+        return Err("Trace filter did not reconstruct the request");
+    }
+    let counts = query::count_by_kind(&request);
+    if counts.get("ipc") != Some(&2) || counts.get("syscall") != Some(&2) || counts.get("trace_completed") != Some(&1) {
+        return Err("Counts by kind are wrong");
+    }
+    if query::ipc_latencies(&recorded) != [2_300, 4_000] {
+        return Err("IPC round trips were paired wrongly");
+    }
+    _print(format_args!("[Obs Test] ✓ Request trace of {} events reconstructed\n", request.len()));
+
+    // Test 11: Latency percentiles come from the recorded durations
+    _print(format_args!("[Obs Test] Test 11: Syscall latency percentiles...\n"));
+    let latency = recorder(false)?;
+    for i in 0..100u64 {
+        // Calls of 1..=100 us, interleaved with another process's syscalls
+        let start = i * 1_000_000;
+        latency.add_entry(timed(start, Subsystem::Kernel, syscall(42, 1, true), None));
+        latency.add_entry(timed(start + 10, Subsystem::Kernel, syscall(43, 1, true), None));
+        latency.add_entry(timed(start + 20, Subsystem::Kernel, syscall(43, 1, false), None));
+        latency.add_entry(timed(start + (i + 1) * 1_000, Subsystem::Kernel, syscall(42, 1, false), None));
+    }
+    let calls = latency.query(&EventFilter { pid: Some(42), ..EventFilter::default() });
+    let summary = LatencySummary::from_samples(query::syscall_latencies(&calls)).ok_or("No syscall latencies found")?;
+    let expected = LatencySummary { count: 100, min: 1_000, max: 100_000, mean: 50_500, p50: 50_000, p90: 90_000, p99: 99_000 };
+    if summary != expected {
+        return Err("Syscall latency percentiles are wrong");
+    }
+    if LatencySummary::from_samples(Vec::new()).is_some() {
+        return Err("Empty latency set produced a summary");
+    }
+    _print(format_args!("[Obs Test] ✓ p50 {} ns, p90 {} ns, p99 {} ns\n", summary.p50, summary.p90, summary.p99));
+
+    // Test 12: An enabled user probe reaches the recorder attributed to its process
+    _print(format_args!("[Obs Test] Test 12: Enabled user probe...\n"));
+    let probes = ProbeEnablePage::new();
+    probes.set(Subsystem::Compositor, Some(Severity::Info));
+    let traced = recorder(false)?;
+    let probe = UserProbe { name: "frame_drop", subsystem: Subsystem::Compositor, severity: Severity::Warn };
+    let mut syscalls = 0;
+    let fired = probe.fire(&probes, b"late", |number, args| {
+        syscalls += 1;
+        if number != SyscallNumber::TraceProbe as u64 || args[1] != 10 || args[2] != Subsystem::Compositor as u64 || args[5] != 4 {
+            return -1;
+        }
+        match user_probes::record_probe(&traced, &probes, 314, &probe, b"late", Some((REQUEST, Some(7)))) {
+            Ok(true) => 1,
+            _ => -1,
+        }
+    });
+    if fired != Some(1) || syscalls != 1 {
+        return Err("Enabled user probe did not reach the kernel");
+    }
+    let probed = traced.snapshot();
+    let expected_event = ObservabilityEvent::Tracepoint { name: String::from("frame_drop"), subsystem: Subsystem::Compositor, data: b"late".to_vec() };
+    match probed.as_slice() {
+        [entry] if entry.event == expected_event
+            && entry.thread_id == 314
+            && entry.severity == Severity::Warn
+            && entry.subsystem == Subsystem::Compositor
+            && entry.trace_id == Some(REQUEST)
+            && entry.parent_span_id == Some(7) => {}
+        _ => return Err("User probe was recorded with the wrong attribution"),
+    }
+    _print(format_args!("[Obs Test] ✓ Probe recorded for PID 314 in its trace\n"));
+
+    // Test 13: A disabled probe is suppressed before making the syscall
+    _print(format_args!("[Obs Test] Test 13: Disabled user probes...\n"));
+    let below_level = UserProbe { name: "frame_ok", subsystem: Subsystem::Compositor, severity: Severity::Debug };
+    let disabled_subsystem = UserProbe { name: "rx", subsystem: Subsystem::Network, severity: Severity::Fatal };
+    for quiet in [below_level, disabled_subsystem] {
+        if quiet.fire(&probes, b"", |_, _| { syscalls += 1; 0 }).is_some() {
+            return Err("Disabled user probe fired");
+        }
+    }
+    if syscalls != 1 {
+        return Err("Disabled user probe made a syscall");
+    }
+    // Processes that skip the page check are still filtered by the kernel
+    if user_probes::record_probe(&traced, &probes, 314, &below_level, b"", None) != Ok(false) || traced.snapshot().len() != 1 {
+        return Err("Kernel recorded a disabled user probe");
+    }
+    let generation = probes.generation.load(Ordering::Acquire);
+    probes.set(Subsystem::Compositor, None);
+    if probes.generation.load(Ordering::Acquire) == generation || probe.fire(&probes, b"", |_, _| 0).is_some() {
+        return Err("Disabling a subsystem did not reach user space");
+    }
+    _print(format_args!("[Obs Test] ✓ Disabled probes never enter the kernel\n"));
+
+    // Test 14: Stacks unwind along the frame-pointer chain and stop at a corrupt link
+    _print(format_args!("[Obs Test] Test 14: Stack unwinding...\n"));
+    const STACK_BASE: u64 = 0x1000;
+    let mut words = [0u64; 32];
+    // Three frames, each saving its caller's frame pointer and return address
+    for (frame_pointer, caller, return_address) in [(0x1040, 0x1080, 0xaaa1), (0x1080, 0x10c0, 0xaaa2), (0x10c0, 0, 0xaaa3)] {
+        words[((frame_pointer - STACK_BASE) / 8) as usize] = caller;
+        words[((frame_pointer - STACK_BASE) / 8) as usize + 1] = return_address;
+    }
+    let stack = STACK_BASE..STACK_BASE + (words.len() * 8) as u64;
+    let read = |address: u64| words.get(((address - STACK_BASE) / 8) as usize).copied();
+    let frames = hang_dump::unwind(0xbbb0, 0x1010, 0x1040, stack.clone(), read);
+    let addresses: Vec<u64> = frames.iter().map(|frame| frame.instruction_pointer).collect();
+    if addresses != [0xbbb0, 0xaaa1, 0xaaa2, 0xaaa3] || frames[1].frame_pointer != 0x1080 {
+        return Err("Unwound stack does not follow the frame-pointer chain");
+    }
+    // A frame pointing at itself, and one outside the stack, each end the walk
+    words[(0x1080 - STACK_BASE) as usize / 8] = 0x1080;
+    let read = |address: u64| words.get(((address - STACK_BASE) / 8) as usize).copied();
+    if hang_dump::unwind(0xbbb0, 0x1010, 0x1040, stack.clone(), read).len() != 3
+        || hang_dump::unwind(0xbbb0, 0x1010, 0x9000, stack, |_| Some(0xaaa1)).len() != 1
+    {
+        return Err("Corrupt frame chain was followed");
+    }
+    _print(format_args!("[Obs Test] ✓ {} frames unwound, corrupt chains cut short\n", addresses.len()));
+
+    // Test 15: A watchdog timeout saves a dump of the hung subsystem before restarting it
+    _print(format_args!("[Obs Test] Test 15: Hang dump on watchdog timeout...\n"));
+    let watchdogs = HANG_WATCHDOGS.call_once(WatchdogManager::new);
+    let hung = recorder(false)?;
+    for i in 0..5 {
+        let event = ObservabilityEvent::Service { service_id: 4, operation: ServiceOperation::HealthCheck, result: ServiceResult::Timeout };
+        hung.add_entry(entry(i, Subsystem::Storage, event));
+    }
+    hung.add_entry(entry(5, Subsystem::Network, ObservabilityEvent::Interrupt { vector: 0x2b, duration_ns: 900, nested: false }));
+    let config = WatchdogConfig { escalation_policy: EscalationPolicy::Restart, ..WatchdogConfig::default() };
+    watchdogs.register_watchdog_with_config(Subsystem::Storage, "rae-storaged", config).map_err(|_| "Failed to register watchdog")?;
+    watchdogs.set_restart_handler(Subsystem::Storage, restart_hung_storage).map_err(|_| "Failed to set restart handler")?;
+    let pid = crate::process::spawn_kernel_thread("hung-storage", hung_storage_thread).map_err(|_| "Failed to spawn hung thread")?;
+    let action = watchdogs
+        .add_thread(Subsystem::Storage, pid)
+        .and_then(|()| watchdogs.start_watchdog(Subsystem::Storage))
+        .and_then(|()| watchdogs.simulate_timeout(Subsystem::Storage, &hung, &CrashHandler::new()));
+    crate::process::terminate_process(pid);
+    if action != Ok(WatchdogAction::Restart) {
+        return Err("Watchdog timeout did not restart the subsystem");
+    }
+    if !DUMPED_BEFORE_RESTART.load(Ordering::SeqCst) {
+        return Err("Subsystem restarted before its hang dump was saved");
+    }
+    let dump = watchdogs.latest_hang_dump(Subsystem::Storage).ok_or("No hang dump saved")?;
+    if dump.events.len() != 6 || dump.events.iter().any(|entry| entry.subsystem != Subsystem::Storage) {
+        return Err("Hang dump does not hold exactly the subsystem's recent events");
+    }
+    if !matches!(dump.events.last().map(|entry| &entry.event), Some(ObservabilityEvent::Watchdog { action: WatchdogAction::Restart, .. })) {
+        return Err("Hang dump lacks the watchdog timeout itself");
+    }
+    let [thread] = &dump.threads[..] else {
+        return Err("Hang dump does not hold the subsystem's thread");
+    };
+    if thread.pid != pid || thread.name != "hung-storage" || thread.stack.first().map_or(true, |frame| frame.instruction_pointer == 0) {
+        return Err("Hang dump lacks the thread's stack");
+    }
+    let restarts = watchdogs.get_watchdog_status(Subsystem::Storage).map_or(0, |status| status.restart_count);
+    if restarts != 1 || watchdogs.get_stats().total_hang_dumps != 1 {
+        return Err("Restart or dump count is wrong");
+    }
+    _print(format_args!(
+        "[Obs Test] ✓ Dump of {} events and {} stack frames saved before the restart\n",
+        dump.events.len(), thread.stack.len()
+    ));
+
+    // Test 16: Draining a recorder that overflowed returns only its newest entries, copied
+    // or consumed, and they survive a dump file
+    _print(format_args!("[Obs Test] Test 16: Drain and dump of an overflowed recorder...\n"));
+    let overflowed = recorder(false)?;
+    if !overflowed.drain(usize::MAX, DrainMode::Consume).is_empty() {
+        return Err("Empty recorder drained entries");
+    }
+    let recorded: Vec<FlightRecorderEntry> = (0..TEST_MAX_EVENTS as u64 + 300)
+        .map(|i| entry(i, Subsystem::Kernel, syscall(9, (i % 40) as u32, i % 2 == 0)))
+        .collect();
+    for entry in &recorded {
+        overflowed.add_entry(entry.clone());
+    }
+    let newest = &recorded[recorded.len() - TEST_MAX_EVENTS..];
+    if overflowed.drain(usize::MAX, DrainMode::Copy)[..] != newest[..] || overflowed.snapshot()[..] != newest[..] {
+        return Err("Copying drain did not return just the newest entries, or consumed them");
+    }
+    let first = overflowed.drain(100, DrainMode::Consume);
+    if first[..] != newest[..100] || overflowed.snapshot()[..] != newest[100..] {
+        return Err("Consuming drain did not take the oldest entries alone");
+    }
+    let dump = flight_recorder::encode_dump(&first);
+    if flight_recorder::decode_dump(&dump).as_deref() != Ok(&first[..]) {
+        return Err("Dump file did not round-trip");
+    }
+    if flight_recorder::decode_dump(&flight_recorder::encode_dump(&[])) != Ok(Vec::new())
+        || flight_recorder::decode_dump(&dump[..dump.len() - 1]).is_ok()
+    {
+        return Err("Empty dump rejected, or truncated dump accepted");
+    }
+    let squeezed = recorder(true)?;
+    for entry in &recorded {
+        squeezed.add_entry(entry.clone());
+    }
+    let kept = squeezed.snapshot();
+    let taken = squeezed.drain(10, DrainMode::Consume);
+    if taken[..] != kept[..10] || squeezed.snapshot()[..] != kept[10..] {
+        return Err("Consuming drain of compressed entries lost or kept the wrong ones");
+    }
+    _print(format_args!("[Obs Test] ✓ Newest {} of {} entries drained, dump of {} bytes\n", newest.len(), recorded.len(), dump.len()));
+
+    // Test 17: Raising Network's minimum severity drops its Debug events but keeps Errors
+    _print(format_args!("[Obs Test] Test 17: Severity filter...\n"));
+    let registry = TracepointRegistry::new();
+    registry.set_filter(Subsystem::Network, Severity::Warn).map_err(|_| "Failed to set a filter")?;
+    if registry.allows(Subsystem::Network, Severity::Debug) || !registry.allows(Subsystem::Network, Severity::Error) {
+        return Err("Network filter did not split Debug from Error");
+    }
+    if !registry.allows(Subsystem::Storage, Severity::Debug) {
+        return Err("Filter on Network applied to Storage");
+    }
+    registry.set_default_filter(Severity::Info);
+    let follows_default = !registry.allows(Subsystem::Storage, Severity::Debug) && registry.min_severity(Subsystem::Unknown) == Severity::Info;
+    if !follows_default || registry.set_filter(Subsystem::Unknown, Severity::Fatal).is_ok() {
+        return Err("Unfiltered and unknown subsystems did not follow the default");
+    }
+    registry.clear_filter(Subsystem::Network);
+    if registry.min_severity(Subsystem::Network) != Severity::Info {
+        return Err("Cleared filter did not return to the default");
+    }
+    match init_observability() {
+        Ok(()) | Err(ObservabilityError::AlreadyInitialized) => {}
+        Err(_) => return Err("Failed to initialize observability"),
+    }
+    with_observability(|obs| obs.tracepoints.set_filter(Subsystem::Network, Severity::Error))
+        .map_err(|_| "Failed to set the global filter")?
+        .map_err(|_| "Failed to set the global filter")?;
+    const FILTER_TEST_TIMEOUT_MS: u32 = 0x5eed;
+    record_event(ObservabilityEvent::Tracepoint { name: String::from("filter_test_debug"), subsystem: Subsystem::Network, data: Vec::new() });
+    record_event(ObservabilityEvent::Watchdog { subsystem: Subsystem::Network, timeout_ms: FILTER_TEST_TIMEOUT_MS, action: WatchdogAction::Restart });
+    let filtered_out = is_recorded(Subsystem::Network, Severity::Debug);
+    let _ = with_observability(|obs| obs.tracepoints.clear_filter(Subsystem::Network));
+    let recorded = crate::observability::snapshot();
+    let debug_kept = recorded.iter().any(|entry| matches!(&entry.event, ObservabilityEvent::Tracepoint { name, .. } if name == "filter_test_debug"));
+    let error_kept = recorded.iter().any(|entry| matches!(entry.event, ObservabilityEvent::Watchdog { timeout_ms: FILTER_TEST_TIMEOUT_MS, .. }));
+    if debug_kept || filtered_out || !error_kept {
+        return Err("Global filter did not drop Network Debug events while keeping Errors");
+    }
+    _print(format_args!("[Obs Test] ✓ Network Debug dropped, Error kept\n"));
+
+    // Test 18: A watchdog never petted fires its action once per missed deadline, repeated
+    // warnings escalate to a restart, and a panic goes to the crash handler
+    _print(format_args!("[Obs Test] Test 18: Watchdog deadlines...\n"));
+    const SHORT_TIMEOUT_MS: u64 = 20;
+    let crashes = CrashHandler::new();
+    crashes.update_config(CrashHandlerConfig { halt_on_fatal: false, enable_auto_recovery: false, ..CrashHandlerConfig::default() });
+    let watchdogs = WatchdogManager::new();
+    let missed = recorder(false)?;
+    watchdogs.register_watchdog(Subsystem::Audio, SHORT_TIMEOUT_MS as u32, WatchdogAction::Warning).map_err(|_| "Failed to register watchdog")?;
+    watchdogs.set_restart_handler(Subsystem::Audio, restart_silent_audio).map_err(|_| "Failed to set restart handler")?;
+    if watchdogs.register_watchdog(Subsystem::Usb, SHORT_TIMEOUT_MS as u32, WatchdogAction::Ignore).is_ok() {
+        return Err("Watchdog registered to do nothing");
+    }
+    let start = watchdogs.get_watchdog_status(Subsystem::Audio).ok_or("Watchdog not registered")?.last_heartbeat;
+    watchdogs.check_watchdogs(start + SHORT_TIMEOUT_MS, &missed, &crashes);
+    if !watchdog_actions(&missed, Subsystem::Audio).is_empty() {
+        return Err("Watchdog fired at its deadline rather than after it");
+    }
+    for now in start + SHORT_TIMEOUT_MS + 1..start + 2 * SHORT_TIMEOUT_MS {
+        watchdogs.check_watchdogs(now, &missed, &crashes);
+    }
+    if watchdog_actions(&missed, Subsystem::Audio) != [WatchdogAction::Warning] {
+        return Err("Missed deadline did not fire its action exactly once");
+    }
+    // Each further timeout without a pet is another miss, the fourth in a row a restart
+    for now in start + 2 * SHORT_TIMEOUT_MS..=start + 5 * SHORT_TIMEOUT_MS {
+        watchdogs.check_watchdogs(now, &missed, &crashes);
+    }
+    let escalated = [WatchdogAction::Warning, WatchdogAction::Warning, WatchdogAction::Warning, WatchdogAction::Restart];
+    if watchdog_actions(&missed, Subsystem::Audio) != escalated || SILENT_RESTARTS.load(Ordering::SeqCst) != 1 {
+        return Err("Repeated misses did not escalate to one restart");
+    }
+    watchdogs.pet(Subsystem::Audio).map_err(|_| "Failed to pet watchdog")?;
+    let petted = watchdogs.get_watchdog_status(Subsystem::Audio).ok_or("Watchdog lost")?.last_heartbeat;
+    watchdogs.check_watchdogs(petted + SHORT_TIMEOUT_MS, &missed, &crashes);
+    watchdogs.check_watchdogs(petted + SHORT_TIMEOUT_MS + 1, &missed, &crashes);
+    if watchdog_actions(&missed, Subsystem::Audio)[escalated.len()..] != [WatchdogAction::Warning] {
+        return Err("Pet did not move the deadline or end the run of misses");
+    }
+    let panicking = WatchdogManager::new();
+    panicking.register_watchdog(Subsystem::Power, SHORT_TIMEOUT_MS as u32, WatchdogAction::Panic).map_err(|_| "Failed to register watchdog")?;
+    let start = panicking.get_watchdog_status(Subsystem::Power).ok_or("Watchdog not registered")?.last_heartbeat;
+    for now in [start + SHORT_TIMEOUT_MS + 1, start + SHORT_TIMEOUT_MS + 2, start + 10 * SHORT_TIMEOUT_MS] {
+        panicking.check_watchdogs(now, &missed, &crashes);
+    }
+    let reported = crashes.get_recent_crashes(usize::MAX);
+    let [crash] = &reported[..] else {
+        return Err("Panic did not reach the crash handler exactly once");
+    };
+    if crash.crash_type != CrashType::Timeout || crash.subsystem != Some(Subsystem::Power) {
+        return Err("Crash handler got the wrong hang");
+    }
+    if panicking.get_watchdog_status(Subsystem::Power).map(|status| status.state) != Some(watchdog::WatchdogState::Failed) {
+        return Err("Panicked watchdog still armed");
+    }
+    _print(format_args!("[Obs Test] ✓ Missed deadlines fired once each, escalated to a restart, and panicked\n"));
+
+    _print(format_args!("[Obs Test] ✓ All observability tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for observability
+pub fn test_observability() {
+    _print(format_args!("[Obs Test] ===========================================\n"));
+    _print(format_args!("[Obs Test]            OBSERVABILITY TESTS\n"));
+    _print(format_args!("[Obs Test] ===========================================\n"));
+
+    match run_observability_tests() {
+        Ok(_) => _print(format_args!("[Obs Test] ✓ All observability tests PASSED\n")),
+        Err(e) => _print(format_args!("[Obs Test] ✗ Observability tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Obs Test] ===========================================\n"));
+}
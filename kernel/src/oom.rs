@@ -0,0 +1,104 @@
+//! Out-of-memory killer
+//!
+//! When the frame allocator runs dry, `reclaim` ends a process to win frames back: the one
+//! `select_victim` scores highest, by the memory it holds weighted by its priority. Kernel
+//! processes and the idle thread share the kernel address space and are never chosen.
+//! Ending a process destroys its address space, which returns the frames of its `VmArea`s
+//! to the allocator. The kernel heap's grow path and the page fault path call `reclaim`
+//! when they get no frame, then try again.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::process::{self, Priority, Process, ProcessState};
+
+/// Set while a victim is being ended, so allocations made meanwhile do not start another
+static RECLAIMING: AtomicBool = AtomicBool::new(false);
+
+/// Weight of each priority in a victim's score: low priority work goes first, and the
+/// foreground game and high priority services last
+fn priority_weight(priority: Priority) -> u64 {
+    match priority {
+        Priority::Low => 4,
+        Priority::Normal => 2,
+        Priority::High | Priority::Gaming => 1,
+    }
+}
+
+/// Whether `process` may be ended to free memory: a live process with its own address space
+fn is_killable(process: &Process, idle_pid: u64) -> bool {
+    process.pid != idle_pid
+        && process.address_space_id.is_some()
+        && !matches!(process.state, ProcessState::Terminated | ProcessState::Zombie(_))
+}
+
+/// Frames the global allocator has free
+fn free_frames() -> usize {
+    crate::memory::FRAME_ALLOC.lock().as_ref().map_or(0, |allocator| allocator.free_count())
+}
+
+/// Choose the process to end to free memory: the killable one whose score, the larger of
+/// its recorded `memory_usage` and its resident pages, times its priority weight, is
+/// highest. `None` when no process holds any memory, or when the scheduler or VMM lock is
+/// taken, as ending a process needs both
+pub fn select_victim() -> Option<u64> {
+    let idle_pid = process::idle_thread_pid();
+    let candidates: Vec<(u64, u64, usize, Priority)> = process::try_with_processes(|processes| {
+        processes
+            .iter()
+            .flatten()
+            .filter(|process| is_killable(process, idle_pid))
+            .filter_map(|process| Some((process.pid, process.address_space_id?, process.memory_usage, process.priority)))
+            .collect()
+    })?;
+
+    let mut victim = None;
+    let mut highest = 0;
+    for (pid, address_space_id, memory_usage, priority) in candidates {
+        let resident = crate::vmm::resident_pages(address_space_id)?;
+        let pages = (memory_usage / 4096).max(resident) as u64;
+        let score = pages * priority_weight(priority);
+        if score > highest {
+            highest = score;
+            victim = Some(pid);
+        }
+    }
+    victim
+}
+
+/// End process `pid` along with the threads sharing its address space, freeing its frames
+fn kill(pid: u64) {
+    let idle_pid = process::idle_thread_pid();
+    let Some((name, address_space_id)) = process::with_process(pid, |process| (process.name.clone(), process.address_space_id)) else {
+        return;
+    };
+    let group: Vec<u64> = process::try_with_processes(|processes| {
+        processes
+            .iter()
+            .flatten()
+            .filter(|process| process.address_space_id == address_space_id && is_killable(process, idle_pid))
+            .map(|process| process.pid)
+            .collect()
+    })
+    .unwrap_or_else(|| alloc::vec![pid]);
+
+    let free_before = free_frames();
+    for pid in group {
+        process::terminate_process(pid);
+    }
+    let freed = free_frames().saturating_sub(free_before);
+    crate::serial_println!("[OOM] Out of memory: killed process {} ({}), freeing {} KiB", pid, name, freed * 4);
+}
+
+/// End the process `select_victim` chooses. Returns whether one was ended, so that the
+/// caller should try its allocation again
+pub fn reclaim() -> bool {
+    if RECLAIMING.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+    let victim = select_victim();
+    if let Some(pid) = victim {
+        kill(pid);
+    }
+    RECLAIMING.store(false, Ordering::SeqCst);
+    victim.is_some()
+}
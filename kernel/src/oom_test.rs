@@ -0,0 +1,143 @@
+//! OOM Killer Test
+//! Exhausts physical memory while a low priority process holds frames, then checks that
+//! the page fault and kernel heap paths get memory back by ending it, that kernel processes
+//! are never chosen, and that frames can be allocated again afterwards
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use x86_64::structures::paging::PhysFrame;
+use x86_64::VirtAddr;
+use crate::memory;
+use crate::oom::select_victim;
+use crate::process::{self, Priority, Process, ProcessState};
+use crate::serial::_print;
+use crate::vmm::{self, MappingSource, VmPermissions};
+
+/// Start a blocked, low priority user process holding `pages` resident pages. Returns its
+/// PID and address space
+fn spawn_hog(pages: u64) -> Result<(u64, u64), &'static str> {
+    let mut hog = Process::user_process(String::from("oom-hog"), VirtAddr::new(0x400000))
+        .map_err(|_| "Failed to create hog process")?;
+    hog.priority = Priority::Low;
+    let address_space_id = hog.address_space_id.ok_or("Hog has no address space")?;
+    // Shared anonymous mappings are backed as they are made
+    let permissions = VmPermissions::READ | VmPermissions::WRITE | VmPermissions::USER;
+    vmm::map_memory(address_space_id, None, pages * 4096, permissions, false, MappingSource::Anonymous { shared: true })
+        .map_err(|_| "Failed to back hog memory")?;
+
+    let mut scheduler = process::get_smp_scheduler().lock();
+    let pid = scheduler.add_process(hog);
+    // The hog has no code; it only has to hold memory
+    scheduler.block_process(pid);
+    Ok((pid, address_space_id))
+}
+
+/// Take every free frame, so the next allocation finds none
+fn exhaust_memory() -> Vec<PhysFrame> {
+    let (free, _, _) = memory::get_memory_stats();
+    let mut held = Vec::with_capacity(free);
+    while let Some(frame) = memory::allocate_frame() {
+        held.push(frame);
+    }
+    held
+}
+
+fn release(held: Vec<PhysFrame>) {
+    for frame in held {
+        memory::deallocate_frame(frame);
+    }
+}
+
+/// Whether process `pid` has been ended
+fn is_ended(pid: u64) -> bool {
+    process::with_process(pid, |process| matches!(process.state, ProcessState::Terminated | ProcessState::Zombie(_)))
+        .unwrap_or(true)
+}
+
+pub fn run_oom_tests() -> Result<(), &'static str> {
+    _print(format_args!("[OOM Test] Starting OOM killer tests...\n"));
+
+    // Test 1: The process holding the most memory is chosen, never a kernel process
+    _print(format_args!("[OOM Test] Test 1: Victim selection...\n"));
+    let (hog, hog_address_space) = spawn_hog(256)?;
+    if vmm::resident_pages(hog_address_space) != Some(256) {
+        return Err("Hog memory not resident");
+    }
+    if select_victim() != Some(hog) {
+        return Err("Process holding the most memory not chosen");
+    }
+    _print(format_args!("[OOM Test] ✓ Hog chosen as victim\n"));
+
+    // Test 2: A demand-zero fault with no frame left ends the hog and is served
+    _print(format_args!("[OOM Test] Test 2: Page fault under memory exhaustion...\n"));
+    let address_space_id = vmm::create_address_space().map_err(|_| "Failed to create address space")?;
+    let permissions = VmPermissions::READ | VmPermissions::WRITE;
+    let start = vmm::map_memory(address_space_id, None, 4096, permissions, false, MappingSource::Anonymous { shared: false })
+        .map_err(|_| "Failed to map test page")?;
+    let held = exhaust_memory();
+    if memory::allocate_frame().is_some() {
+        release(held);
+        return Err("Memory not exhausted");
+    }
+    let faulted = vmm::handle_fault_in(address_space_id, start, 0x2);
+    let resident = vmm::resident_pages(address_space_id);
+    release(held);
+    let _ = vmm::destroy_address_space(address_space_id);
+    if faulted.is_err() || resident != Some(1) {
+        return Err("Fault not served after OOM kill");
+    }
+    if !is_ended(hog) || vmm::resident_pages(hog_address_space) != Some(0) {
+        return Err("Victim not ended with its frames freed");
+    }
+    _print(format_args!("[OOM Test] ✓ Victim killed and fault served\n"));
+
+    // Test 3: The kernel heap grows past its initial size by ending a process for frames
+    _print(format_args!("[OOM Test] Test 3: Heap growth under memory exhaustion...\n"));
+    let (hog, _) = spawn_hog(5120)?;
+    let heap_before = crate::heap::heap_size();
+    let held = exhaust_memory();
+    // The heap cannot hold as much as its whole size without growing
+    let mut buffer = Vec::<u8>::new();
+    let reserved = buffer.try_reserve_exact(heap_before);
+    let heap_after = crate::heap::heap_size();
+    drop(buffer);
+    release(held);
+    if reserved.is_err() || heap_after <= heap_before {
+        return Err("Heap did not grow after OOM kill");
+    }
+    if !is_ended(hog) {
+        return Err("Heap growth did not end the victim");
+    }
+    _print(format_args!("[OOM Test] ✓ Heap grew from {} to {} KiB\n", heap_before / 1024, heap_after / 1024));
+
+    // Test 4: The system recovers, and kernel processes are still not chosen
+    _print(format_args!("[OOM Test] Test 4: Recovery...\n"));
+    match memory::allocate_frame() {
+        Some(frame) => memory::deallocate_frame(frame),
+        None => return Err("Frames not allocatable after recovery"),
+    }
+    if let Some(pid) = select_victim() {
+        let kernel = process::with_process(pid, |process| process.address_space_id.is_none()).unwrap_or(true);
+        if kernel || pid == process::idle_thread_pid() {
+            return Err("Kernel process chosen as victim");
+        }
+    }
+    _print(format_args!("[OOM Test] ✓ Memory allocatable again\n"));
+
+    _print(format_args!("[OOM Test] ✓ All OOM killer tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for the OOM killer
+pub fn test_oom() {
+    _print(format_args!("[OOM Test] ===========================================\n"));
+    _print(format_args!("[OOM Test]            OOM KILLER TESTS\n"));
+    _print(format_args!("[OOM Test] ===========================================\n"));
+
+    match run_oom_tests() {
+        Ok(_) => _print(format_args!("[OOM Test] ✓ All OOM killer tests PASSED\n")),
+        Err(e) => _print(format_args!("[OOM Test] ✗ OOM killer tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[OOM Test] ===========================================\n"));
+}
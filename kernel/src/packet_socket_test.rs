@@ -0,0 +1,219 @@
+//! Packet Socket Test
+//! Checks over loopback that opening a packet socket takes a NetworkRaw capability, that a
+//! frame one raw socket sends reaches the interface and another raw socket receives it whole,
+//! Ethernet header and all, and that frames a capture keeps, filtered by a compiled BPF
+//! expression and cut to its snapshot length, serialize to valid pcap records. On Ethernet,
+//! a capture sees the frames the driver sends
+
+use alloc::vec::Vec;
+use crate::capabilities::{self, CapabilityType};
+use crate::network::bpf::{self, Instruction};
+use crate::network::interface::{self, LOOPBACK_NAME};
+use crate::network::packet::{self, ETH_P_ALL};
+use crate::network::pcap;
+use crate::network::{self, NetworkError, AF_PACKET, SOCK_RAW};
+use crate::serial::_print;
+
+const DESTINATION_MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+const SOURCE_MAC: [u8; 6] = [0x52, 0x55, 0x0a, 0x00, 0x02, 0x02];
+/// Local experimental ethertype, which nothing else on loopback carries
+const ETHERTYPE_TEST: u16 = 0x88b5;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const SNAPLEN: u32 = 64;
+const RECEIVE_LEN: usize = 2048;
+
+fn ethernet_frame(ethertype: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::new();
+    frame.extend_from_slice(&DESTINATION_MAC);
+    frame.extend_from_slice(&SOURCE_MAC);
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// A frame of a UDP datagram from 10.0.2.15:49152 to 10.0.2.2:`port` with `payload_len` bytes
+fn udp_frame(port: u16, payload_len: usize) -> Vec<u8> {
+    let udp_len = (8 + payload_len) as u16;
+    let mut packet = Vec::new();
+    packet.extend_from_slice(&[0x45, 0]);
+    packet.extend_from_slice(&(20 + udp_len).to_be_bytes());
+    packet.extend_from_slice(&[0, 1, 0x40, 0, 64, 17, 0, 0, 10, 0, 2, 15, 10, 0, 2, 2]);
+    packet.extend_from_slice(&49152u16.to_be_bytes());
+    packet.extend_from_slice(&port.to_be_bytes());
+    packet.extend_from_slice(&udp_len.to_be_bytes());
+    packet.extend_from_slice(&[0, 0]);
+    packet.extend((0..payload_len).map(|i| i as u8));
+    ethernet_frame(ETHERTYPE_IPV4, &packet)
+}
+
+/// An ARP request asking for 10.0.2.2
+fn arp_frame() -> Vec<u8> {
+    let mut packet = alloc::vec![0, 1, 0x08, 0x00, 6, 4, 0, 1];
+    packet.extend_from_slice(&SOURCE_MAC);
+    packet.extend_from_slice(&[10, 0, 2, 15, 0, 0, 0, 0, 0, 0, 10, 0, 2, 2]);
+    ethernet_frame(ETHERTYPE_ARP, &packet)
+}
+
+/// A raw socket seeing frames of `protocol`, bound to interface `index`
+fn raw_socket(protocol: u16, index: u32) -> Result<u32, &'static str> {
+    let socket = network::create_socket(AF_PACKET, SOCK_RAW, u32::from(protocol)).map_err(|_| "Packet socket not opened")?;
+    network::bind_socket(socket, &index.to_be_bytes()).map_err(|_| "Packet socket not bound")?;
+    Ok(socket)
+}
+
+fn run_with_capability(loopback: u32) -> Result<(), &'static str> {
+    // Test 1: A raw socket receives a frame another sent, headers and all
+    _print(format_args!("[Packet Test] Test 1: Raw frames over loopback...\n"));
+    let sender = raw_socket(ETH_P_ALL, loopback)?;
+    let receiver = raw_socket(ETHERTYPE_TEST, loopback)?;
+    let ipv4_only = raw_socket(ETHERTYPE_IPV4, loopback)?;
+    let frame = ethernet_frame(ETHERTYPE_TEST, b"raw frame over loopback");
+    if network::send_data(sender, &frame, 0).map_err(|_| "Raw frame not sent")? != frame.len() {
+        return Err("Raw frame sent short");
+    }
+    if network::receive_data(receiver, RECEIVE_LEN, 0).map_err(|_| "Raw frame not received")? != frame {
+        return Err("Frame received differs from the one sent");
+    }
+    if !matches!(network::receive_data(ipv4_only, RECEIVE_LEN, 0), Err(NetworkError::WouldBlock)) {
+        return Err("Socket saw a frame of another ethertype");
+    }
+    if network::receive_data(receiver, 6, 0).is_ok() || network::receive_data(sender, 6, 0).ok().as_deref() != Some(&frame[..6]) {
+        return Err("Frames not read one at a time, cut to the length asked");
+    }
+    _print(format_args!("[Packet Test] ✓ {}-byte frame received whole, Ethernet header included\n", frame.len()));
+
+    // Test 2: A frame sent raw reaches the interface, and only bound sockets may send
+    _print(format_args!("[Packet Test] Test 2: Raw sends reach the interface...\n"));
+    let capture = raw_socket(ETH_P_ALL, loopback)?;
+    packet::set_capture(capture, Some(RECEIVE_LEN as u32)).map_err(|_| "Capture mode not set")?;
+    network::send_data(sender, &frame, 0).map_err(|_| "Raw frame not sent")?;
+    // Loopback sends the frame, then receives it
+    for _ in 0..2 {
+        let record = network::receive_data(capture, RECEIVE_LEN, 0).map_err(|_| "Frame not seen on the interface")?;
+        match pcap::parse_record(&record, false) {
+            Some((record, rest)) if record.data == frame.as_slice() && rest.is_empty() => {}
+            _ => return Err("Captured record does not hold the frame sent"),
+        }
+    }
+    let unbound = network::create_socket(AF_PACKET, SOCK_RAW, u32::from(ETH_P_ALL)).map_err(|_| "Packet socket not opened")?;
+    if !matches!(network::send_data(unbound, &frame, 0), Err(NetworkError::NotConnected)) {
+        return Err("Unbound socket sent a frame");
+    }
+    if !matches!(network::send_data(sender, &frame[..10], 0), Err(NetworkError::InvalidFrame)) {
+        return Err("Frame shorter than its Ethernet header sent");
+    }
+    let ethernet = interface::list_interfaces().into_iter().find(|interface| interface.up && interface.statistics.is_some());
+    if let Some(ethernet) = ethernet {
+        let tx_before = ethernet.statistics.map_or(0, |statistics| statistics.tx_packets);
+        let wire = raw_socket(ETH_P_ALL, ethernet.index)?;
+        let watch = raw_socket(ETH_P_ALL, ethernet.index)?;
+        packet::set_capture(watch, Some(RECEIVE_LEN as u32)).map_err(|_| "Capture mode not set")?;
+        network::send_data(wire, &frame, 0).map_err(|_| "Raw frame not sent on Ethernet")?;
+        let tx_after = interface::interface_info(&ethernet.name).ok().and_then(|interface| interface.statistics).map_or(0, |statistics| statistics.tx_packets);
+        // The driver tees the frame it sent, among whatever else crossed the wire meanwhile
+        let mut captured = false;
+        while let Ok(record) = network::receive_data(watch, RECEIVE_LEN, 0) {
+            captured |= pcap::parse_record(&record, false).is_some_and(|(record, _)| record.data == frame.as_slice());
+        }
+        let _ = network::close_socket(wire);
+        let _ = network::close_socket(watch);
+        if tx_after != tx_before + 1 {
+            return Err("Raw frame did not reach the Ethernet device");
+        }
+        if !captured {
+            return Err("Frame sent on Ethernet not captured by the driver's tee");
+        }
+        _print(format_args!("[Packet Test] ✓ Raw frame transmitted by {} and captured leaving\n", ethernet.name));
+    }
+    _print(format_args!("[Packet Test] ✓ Sent frame seen leaving and arriving on {}\n", LOOPBACK_NAME));
+
+    // Test 3: Filtered captures serialize to a valid pcap file
+    _print(format_args!("[Packet Test] Test 3: Filtered capture to pcap...\n"));
+    let dns_query = udp_frame(53, 100);
+    let filter = bpf::compile("udp and dst port 53").map_err(|_| "Filter not compiled")?;
+    if bpf::run(&filter, &dns_query) == 0 || bpf::run(&filter, &udp_frame(123, 100)) != 0 || bpf::run(&filter, &arp_frame()) != 0 {
+        return Err("Compiled filter passed the wrong frames");
+    }
+    let arp = bpf::compile("arp").map_err(|_| "Filter not compiled")?;
+    if bpf::run(&arp, &arp_frame()) == 0 || bpf::run(&arp, &dns_query) != 0 || bpf::compile("port").is_ok() {
+        return Err("Expressions not compiled as written");
+    }
+    let out_of_range = [Instruction::new(0x15, 5, 0, 1), Instruction::new(0x06, 0, 0, 0)];
+    let divide_by_zero = [Instruction::new(0x34, 0, 0, 0), Instruction::new(0x06, 0, 0, 0)];
+    if bpf::validate(&out_of_range).is_ok() || bpf::validate(&divide_by_zero).is_ok() || bpf::validate(&[]).is_ok() {
+        return Err("Unsafe program passed validation");
+    }
+
+    packet::set_capture(capture, Some(SNAPLEN)).map_err(|_| "Capture mode not set")?;
+    packet::set_filter(capture, Some(filter)).map_err(|_| "Filter not attached")?;
+    for frame in [arp_frame(), dns_query.clone(), udp_frame(123, 100)] {
+        network::send_data(sender, &frame, 0).map_err(|_| "Frame not sent")?;
+    }
+    let mut file = pcap::file_header(SNAPLEN);
+    while let Ok(record) = network::receive_data(capture, RECEIVE_LEN, 0) {
+        file.extend_from_slice(&record);
+    }
+    let header_valid = file[..4] == [0xd4, 0xc3, 0xb2, 0xa1]
+        && file[4..8] == [2, 0, 4, 0]
+        && u32::from_le_bytes([file[16], file[17], file[18], file[19]]) == SNAPLEN
+        && u32::from_le_bytes([file[20], file[21], file[22], file[23]]) == pcap::LINKTYPE_ETHERNET;
+    if !header_valid {
+        return Err("pcap file header malformed");
+    }
+    let (snaplen, records) = pcap::parse_file(&file).ok_or("pcap file does not parse")?;
+    if snaplen != SNAPLEN || records.len() != 2 {
+        return Err("Capture kept other frames than the filter passes");
+    }
+    for record in &records {
+        if record.data != &dns_query[..SNAPLEN as usize] || record.original_len as usize != dns_query.len() {
+            return Err("Record not cut to the snapshot length");
+        }
+    }
+    let expected_len = pcap::FILE_HEADER_LEN + 2 * (pcap::RECORD_HEADER_LEN + SNAPLEN as usize);
+    if file.len() != expected_len {
+        return Err("pcap file holds more than its records");
+    }
+    let statistics = packet::statistics(capture).map_err(|_| "No capture statistics")?;
+    _print(format_args!("[Packet Test] ✓ {} of 6 frames captured into a {}-byte pcap file, {} dropped\n", records.len(), file.len(), statistics.dropped));
+
+    for socket in [sender, receiver, ipv4_only, capture, unbound] {
+        network::close_socket(socket).map_err(|_| "Packet socket not closed")?;
+    }
+    Ok(())
+}
+
+pub fn run_packet_socket_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Packet Test] Starting packet socket tests...\n"));
+    let pid = crate::process::get_current_process_id();
+    let loopback = interface::interface_info(LOOPBACK_NAME).map_err(|_| "No loopback interface")?.index;
+
+    if !matches!(network::create_socket(AF_PACKET, SOCK_RAW, u32::from(ETH_P_ALL)), Err(NetworkError::PermissionDenied)) {
+        return Err("Packet socket opened without a NetworkRaw capability");
+    }
+    let capability = capabilities::create_capability(CapabilityType::NetworkRaw, pid, None, 0, false, false)?;
+    capabilities::grant_capability(pid, capability)?;
+    let result = run_with_capability(loopback);
+    capabilities::revoke_capability(capability)?;
+    result?;
+    if network::create_socket(AF_PACKET, SOCK_RAW, u32::from(ETH_P_ALL)).is_ok() {
+        return Err("Packet socket opened after the capability was revoked");
+    }
+
+    _print(format_args!("[Packet Test] ✓ All packet socket tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for packet sockets and capture
+pub fn test_packet_sockets() {
+    _print(format_args!("[Packet Test] ===========================================\n"));
+    _print(format_args!("[Packet Test]           PACKET SOCKET TESTS\n"));
+    _print(format_args!("[Packet Test] ===========================================\n"));
+
+    match run_packet_socket_tests() {
+        Ok(_) => _print(format_args!("[Packet Test] ✓ All packet socket tests PASSED\n")),
+        Err(e) => _print(format_args!("[Packet Test] ✗ Packet socket tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Packet Test] ===========================================\n"));
+}
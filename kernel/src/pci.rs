@@ -1,13 +1,17 @@
 //! PCI (Peripheral Component Interconnect) subsystem for RaeenOS
-//! Implements PCI device enumeration, configuration space access, and MSI-X interrupt support
+//! Implements PCI device enumeration, configuration space access, MSI-X interrupt support,
+//! and hotplug rescans with driver binding
 
 use lazy_static::lazy_static;
 use spin::Mutex;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
 use x86_64::instructions::port::Port;
 use x86_64::{PhysAddr, VirtAddr};
 
 use crate::apic;
+use crate::drivers::BusDevice;
+use crate::interrupts::{InterruptIndex, MSI_VECTOR_BASE, MSI_VECTOR_COUNT};
 
 /// PCI Configuration Space Access Ports
 const PCI_CONFIG_ADDRESS: u16 = 0xCF8;
@@ -26,6 +30,27 @@ const PCI_CAPABILITIES_PTR: u8 = 0x34;
 const PCI_INTERRUPT_LINE: u8 = 0x3C;
 const PCI_INTERRUPT_PIN: u8 = 0x3D;
 
+/// PCI Command Register Bits
+const PCI_COMMAND_INTX_DISABLE: u16 = 1 << 10;
+
+/// PCI Express Capability Structure (hotplug-related registers)
+const PCIE_CAPABILITY_ID: u8 = 0x10;
+const PCIE_CAP_FLAGS: u8 = 0x02;
+const PCIE_SLOT_CONTROL: u8 = 0x18;
+const PCIE_SLOT_STATUS: u8 = 0x1A;
+
+/// PCIe Capabilities Register Bits
+const PCIE_FLAGS_SLOT_IMPLEMENTED: u16 = 1 << 8;
+
+/// PCIe Slot Control Register Bits
+const PCIE_SLOT_CTRL_PDCE: u16 = 1 << 3;   // Presence Detect Changed Enable
+const PCIE_SLOT_CTRL_HPIE: u16 = 1 << 5;   // Hot-Plug Interrupt Enable
+const PCIE_SLOT_CTRL_DLLSCE: u16 = 1 << 12; // Data Link Layer State Changed Enable
+
+/// PCIe Slot Status Register Bits (write 1 to clear)
+const PCIE_SLOT_STATUS_PDC: u16 = 1 << 3;   // Presence Detect Changed
+const PCIE_SLOT_STATUS_DLLSC: u16 = 1 << 8; // Data Link Layer State Changed
+
 /// MSI-X Capability Structure
 const MSIX_CAPABILITY_ID: u8 = 0x11;
 const MSIX_MESSAGE_CONTROL: u8 = 0x02;
@@ -34,7 +59,6 @@ const MSIX_PBA_OFFSET: u8 = 0x08;
 
 /// MSI-X Message Control Register Bits
 const MSIX_ENABLE: u16 = 1 << 15;
-#[allow(dead_code)]
 const MSIX_FUNCTION_MASK: u16 = 1 << 14;
 const MSIX_TABLE_SIZE_MASK: u16 = 0x7FF;
 
@@ -69,6 +93,25 @@ pub struct PciDevice {
     pub msix_info: Option<MsixInfo>,
 }
 
+impl PciDevice {
+    /// Physical base of a memory BAR, handling 64-bit BARs
+    pub fn memory_bar(&self, bar: u8) -> Option<u64> {
+        let index = bar as usize;
+        if index >= 6 {
+            return None;
+        }
+        let low = self.bars[index];
+        if (low & 1) != 0 {
+            return None; // I/O BAR
+        }
+        let mut base = (low & !0xF) as u64;
+        if ((low >> 1) & 0x3) == 0x2 && index + 1 < 6 {
+            base |= (self.bars[index + 1] as u64) << 32;
+        }
+        if base == 0 { None } else { Some(base) }
+    }
+}
+
 /// PCI Capability Information
 #[derive(Debug, Clone)]
 pub struct PciCapability {
@@ -90,6 +133,18 @@ pub struct MsixInfo {
     pub pba_base: Option<VirtAddr>,
 }
 
+/// Summary of a bus rescan
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RescanReport {
+    pub added: usize,
+    pub removed: usize,
+    pub bound: usize,
+}
+
+/// Set from interrupt context when a hotplug slot signals a presence change;
+/// the rescan itself runs later from `process_hotplug_events`.
+static HOTPLUG_PENDING: AtomicBool = AtomicBool::new(false);
+
 /// Interrupt Vector Allocation
 #[derive(Debug, Copy, Clone)]
 struct InterruptVector {
@@ -102,6 +157,7 @@ struct InterruptVector {
 #[derive(Debug)]
 pub struct PciManager {
     devices: Vec<PciDevice>,
+    hotplug_slots: Vec<(u8, u8, u8, u8)>, // bus, device, function, PCIe capability offset
     interrupt_vectors: [InterruptVector; 256],
     next_vector: u8,
 }
@@ -125,8 +181,9 @@ impl PciManager {
         
         Self {
             devices: Vec::new(),
+            hotplug_slots: Vec::new(),
             interrupt_vectors,
-            next_vector: 32, // Start after legacy interrupts
+            next_vector: MSI_VECTOR_BASE,
         }
     }
     
@@ -134,18 +191,28 @@ impl PciManager {
     pub fn enumerate_devices(&mut self) {
         crate::serial::_print(format_args!("[PCI] Starting device enumeration...\n"));
         
+        self.devices = self.scan_bus();
+        for pci_device in &self.devices {
+            crate::serial::_print(format_args!(
+                "[PCI] Found device {:02X}:{:02X}.{} - {:04X}:{:04X} (Class: {:02X})\n",
+                pci_device.bus, pci_device.device, pci_device.function,
+                pci_device.vendor_id, pci_device.device_id,
+                pci_device.class_code
+            ));
+        }
+        
+        crate::serial::_print(format_args!("[PCI] Enumeration complete. Found {} devices\n", self.devices.len()));
+    }
+    
+    /// Walk every bus/device/function and return the devices present
+    fn scan_bus(&mut self) -> Vec<PciDevice> {
+        let mut found = Vec::new();
+        
         for bus in 0..=255 {
             for device in 0..32 {
                 for function in 0..8 {
                     if let Some(pci_device) = self.probe_device(bus, device, function) {
-                        crate::serial::_print(format_args!(
-                            "[PCI] Found device {:02X}:{:02X}.{} - {:04X}:{:04X} (Class: {:02X})\n",
-                            bus, device, function,
-                            pci_device.vendor_id, pci_device.device_id,
-                            pci_device.class_code
-                        ));
-                        
-                        self.devices.push(pci_device);
+                        found.push(pci_device);
                     }
                     
                     // Only check function 0 for single-function devices
@@ -159,7 +226,7 @@ impl PciManager {
             }
         }
         
-        crate::serial::_print(format_args!("[PCI] Enumeration complete. Found {} devices\n", self.devices.len()));
+        found
     }
     
     /// Probe a specific PCI device
@@ -276,9 +343,9 @@ impl PciManager {
         None
     }
     
-    /// Allocate an interrupt vector
+    /// Allocate an interrupt vector from those the IDT reserves for MSI-X
     pub fn allocate_interrupt_vector(&mut self, bus: u8, device: u8, function: u8) -> Option<u8> {
-        for vector in self.next_vector..=255 {
+        for vector in self.next_vector..MSI_VECTOR_BASE + MSI_VECTOR_COUNT {
             if !self.interrupt_vectors[vector as usize].allocated {
                 self.interrupt_vectors[vector as usize].allocated = true;
                 self.interrupt_vectors[vector as usize].device_info = Some((bus, device, function));
@@ -294,14 +361,14 @@ impl PciManager {
         None
     }
     
-    /// Configure MSI-X for a device
+    /// Configure MSI-X for a device: allocate `vectors_needed` vectors and point that many
+    /// table entries at them on this CPU's local APIC
     pub fn configure_msix(&mut self, bus: u8, device: u8, function: u8, vectors_needed: u16) -> Result<Vec<u8>, &'static str> {
         let device_index = self.devices.iter().position(|d| {
             d.bus == bus && d.device == device && d.function == function
         }).ok_or("Device not found")?;
         
-        // Extract needed information first to avoid borrowing conflicts
-        let (table_bar_addr, pba_bar_addr, table_offset, pba_offset, _table_size, capability_offset) = {
+        let (table_phys, pba_phys, capability_offset) = {
             let pci_device = &self.devices[device_index];
             let msix_info = pci_device.msix_info.as_ref().ok_or("Device does not support MSI-X")?;
             
@@ -309,27 +376,20 @@ impl PciManager {
                 return Err("Requested more vectors than supported");
             }
             
-            let table_bar_addr = pci_device.bars[msix_info.table_bar as usize] & !0xF;
-            let pba_bar_addr = pci_device.bars[msix_info.pba_bar as usize] & !0xF;
-            
-            if table_bar_addr == 0 || pba_bar_addr == 0 {
-                return Err("Invalid BAR addresses for MSI-X");
-            }
-            
-            (table_bar_addr, pba_bar_addr, msix_info.table_offset, msix_info.pba_offset, 
-              msix_info.table_size, msix_info.capability_offset)
+            let table_bar_addr = pci_device.memory_bar(msix_info.table_bar).ok_or("Invalid BAR addresses for MSI-X")?;
+            let pba_bar_addr = pci_device.memory_bar(msix_info.pba_bar).ok_or("Invalid BAR addresses for MSI-X")?;
+            (
+                PhysAddr::new(table_bar_addr + msix_info.table_offset as u64),
+                PhysAddr::new(pba_bar_addr + msix_info.pba_offset as u64),
+                msix_info.capability_offset,
+            )
         };
         
-        // Map the MSI-X table (simplified - would need proper memory mapping)
-        let table_phys = PhysAddr::new(table_bar_addr as u64 + table_offset as u64);
-        let pba_phys = PhysAddr::new(pba_bar_addr as u64 + pba_offset as u64);
-        
-        // Update the device's MSI-X info
-        {
-            let pci_device = &mut self.devices[device_index];
-            let msix_info = pci_device.msix_info.as_mut().unwrap();
-            msix_info.table_base = Some(VirtAddr::new(table_phys.as_u64()));
-            msix_info.pba_base = Some(VirtAddr::new(pba_phys.as_u64()));
+        // The table lives in device memory, reached through the physical memory map
+        let table_base = crate::memory::phys_to_virt(table_phys);
+        if let Some(msix_info) = self.devices[device_index].msix_info.as_mut() {
+            msix_info.table_base = Some(table_base);
+            msix_info.pba_base = Some(crate::memory::phys_to_virt(pba_phys));
         }
         
         // Allocate interrupt vectors
@@ -342,16 +402,14 @@ impl PciManager {
             }
         }
         
-        // Configure MSI-X table entries
+        // Mask the whole function while its entries change, then enable MSI-X
+        let message_control = read_config_word(bus, device, function, capability_offset + MSIX_MESSAGE_CONTROL);
+        write_config_word(bus, device, function, capability_offset + MSIX_MESSAGE_CONTROL, message_control | MSIX_FUNCTION_MASK);
         for (i, &vector) in allocated_vectors.iter().enumerate() {
-            let msix_info = &self.devices[device_index].msix_info.as_ref().unwrap();
-            self.configure_msix_entry(msix_info, i as u16, vector)?;
+            write_msix_entry(table_base, i, vector);
         }
-        
-        // Enable MSI-X
-        let mut message_control = read_config_word(bus, device, function, capability_offset + MSIX_MESSAGE_CONTROL);
-        message_control |= MSIX_ENABLE;
-        write_config_word(bus, device, function, capability_offset + MSIX_MESSAGE_CONTROL, message_control);
+        write_config_word(bus, device, function, capability_offset + MSIX_MESSAGE_CONTROL,
+            (message_control | MSIX_ENABLE) & !MSIX_FUNCTION_MASK);
         
         crate::serial::_print(format_args!(
             "[PCI] Configured MSI-X for device {:02X}:{:02X}.{} with {} vectors\n",
@@ -361,27 +419,52 @@ impl PciManager {
         Ok(allocated_vectors)
     }
     
-    /// Configure a single MSI-X table entry
-    fn configure_msix_entry(&self, msix_info: &MsixInfo, entry_index: u16, vector: u8) -> Result<(), &'static str> {
-        if entry_index >= msix_info.table_size {
-            return Err("Entry index out of bounds");
-        }
-        
-        // Calculate Local APIC address for MSI-X
-        let apic_id = apic::get_apic_id();
-        let message_addr = 0xFEE00000u32 | ((apic_id & 0xFF) << 12);
+    /// Locate PCIe downstream ports with a hotplug-capable slot and enable
+    /// presence-detect interrupts on them
+    fn enable_hotplug_slots(&mut self) {
+        self.hotplug_slots.clear();
         
-        // Message data contains the interrupt vector
-        let message_data = vector as u32;
+        for dev in &self.devices {
+            let cap = match dev.capabilities.iter().find(|c| c.id == PCIE_CAPABILITY_ID) {
+                Some(cap) => cap,
+                None => continue,
+            };
+            
+            let flags = read_config_word(dev.bus, dev.device, dev.function, cap.offset + PCIE_CAP_FLAGS);
+            if (flags & PCIE_FLAGS_SLOT_IMPLEMENTED) == 0 {
+                continue;
+            }
+            
+            // Clear stale change bits before enabling the interrupt
+            write_config_word(dev.bus, dev.device, dev.function, cap.offset + PCIE_SLOT_STATUS,
+                PCIE_SLOT_STATUS_PDC | PCIE_SLOT_STATUS_DLLSC);
+            
+            let mut control = read_config_word(dev.bus, dev.device, dev.function, cap.offset + PCIE_SLOT_CONTROL);
+            control |= PCIE_SLOT_CTRL_PDCE | PCIE_SLOT_CTRL_DLLSCE | PCIE_SLOT_CTRL_HPIE;
+            write_config_word(dev.bus, dev.device, dev.function, cap.offset + PCIE_SLOT_CONTROL, control);
+            route_hotplug_interrupt(dev);
+            
+            self.hotplug_slots.push((dev.bus, dev.device, dev.function, cap.offset));
+        }
         
-        // In a real implementation, we would write to the mapped MSI-X table
-        // For now, we just log the configuration
         crate::serial::_print(format_args!(
-            "[PCI] MSI-X Entry {}: addr=0x{:08X}, data=0x{:08X}, vector={}\n",
-            entry_index, message_addr, message_data, vector
+            "[PCI] Hotplug enabled on {} slot(s)\n", self.hotplug_slots.len()
         ));
-        
-        Ok(())
+    }
+    
+    /// Acknowledge presence/link change events on all hotplug slots.
+    /// Returns true if any slot reported a change.
+    fn ack_hotplug_slots(&self) -> bool {
+        let mut changed = false;
+        for &(bus, device, function, offset) in &self.hotplug_slots {
+            let status = read_config_word(bus, device, function, offset + PCIE_SLOT_STATUS);
+            let events = status & (PCIE_SLOT_STATUS_PDC | PCIE_SLOT_STATUS_DLLSC);
+            if events != 0 {
+                write_config_word(bus, device, function, offset + PCIE_SLOT_STATUS, events);
+                changed = true;
+            }
+        }
+        changed
     }
     
     /// Get all PCI devices
@@ -400,6 +483,46 @@ impl PciManager {
     }
 }
 
+/// Point MSI-X table entry `index` at `vector` on this CPU's local APIC and unmask it. The
+/// table only takes dword accesses
+fn write_msix_entry(table_base: VirtAddr, index: usize, vector: u8) {
+    let entry = (table_base.as_u64() as usize + index * core::mem::size_of::<MsixTableEntry>()) as *mut u32;
+    let message_addr = 0xFEE0_0000u32 | ((apic::get_apic_id() & 0xFF) << 12);
+    // SAFETY: the entry lies inside the table the device's capability describes, mapped as
+    // device memory; each field is written as one aligned dword
+    unsafe {
+        core::ptr::write_volatile(entry, message_addr);
+        core::ptr::write_volatile(entry.add(1), 0);
+        core::ptr::write_volatile(entry.add(2), u32::from(vector));
+        core::ptr::write_volatile(entry.add(3), 0);
+    }
+}
+
+/// Deliver a hotplug port's slot events on the hotplug vector through its INTx pin. MSI-X is
+/// turned off on the port so that the pin is what signals
+fn route_hotplug_interrupt(dev: &PciDevice) {
+    if let Some(msix) = &dev.msix_info {
+        let control = read_config_word(dev.bus, dev.device, dev.function, msix.capability_offset + MSIX_MESSAGE_CONTROL);
+        write_config_word(dev.bus, dev.device, dev.function, msix.capability_offset + MSIX_MESSAGE_CONTROL, control & !MSIX_ENABLE);
+    }
+    let command = read_config_word(dev.bus, dev.device, dev.function, PCI_COMMAND);
+    write_config_word(dev.bus, dev.device, dev.function, PCI_COMMAND, command & !PCI_COMMAND_INTX_DISABLE);
+    
+    if dev.interrupt_pin == 0 || !apic::is_apic_enabled() {
+        crate::serial::_print(format_args!(
+            "[PCI] No hotplug interrupt for {:02X}:{:02X}.{}, it is rescanned on request only\n",
+            dev.bus, dev.device, dev.function
+        ));
+        return;
+    }
+    if let Err(e) = apic::route_irq(dev.interrupt_line, InterruptIndex::PciHotplug.as_u8()) {
+        crate::serial::_print(format_args!(
+            "[PCI] Failed to route hotplug interrupt for {:02X}:{:02X}.{}: {}\n",
+            dev.bus, dev.device, dev.function, e
+        ));
+    }
+}
+
 /// Read a byte from PCI configuration space
 fn read_config_byte(bus: u8, device: u8, function: u8, offset: u8) -> u8 {
     let address = 0x80000000u32
@@ -495,10 +618,89 @@ pub fn init() -> Result<(), &'static str> {
         }
     }
     
+    manager.enable_hotplug_slots();
+    drop(manager);
+    
+    // Attach drivers registered before init to the devices found at boot
+    rescan();
+    
     crate::serial::_print(format_args!("[PCI] PCI subsystem initialized\n"));
     Ok(())
 }
 
+/// Re-enumerate the bus, offering new devices, and those no driver has
+/// claimed yet, to the typed driver model and detaching the drivers of
+/// devices that have disappeared.
+pub fn rescan() -> RescanReport {
+    let present = PCI_MANAGER.lock().scan_bus();
+    apply_scan(present)
+}
+
+/// Bring the driver model in line with `present`, the devices a scan of the bus found, as
+/// `rescan` does after scanning. Tests pass a scan with devices added or taken away to plug
+/// and pull them deterministically.
+///
+/// Drivers attach and detach without the PCI manager lock held so they are
+/// free to call back into this module.
+pub fn apply_scan(present: Vec<PciDevice>) -> RescanReport {
+    let mut report = RescanReport::default();
+    
+    let removed_devices = {
+        let mut manager = PCI_MANAGER.lock();
+        let same = |a: &PciDevice, b: &PciDevice| {
+            a.bus == b.bus && a.device == b.device && a.function == b.function
+                && a.vendor_id == b.vendor_id && a.device_id == b.device_id
+        };
+        
+        report.added = present.iter()
+            .filter(|p| !manager.devices.iter().any(|d| same(d, p)))
+            .count();
+        let removed_devices: Vec<PciDevice> = manager.devices.iter()
+            .filter(|d| !present.iter().any(|p| same(d, p)))
+            .cloned()
+            .collect();
+        report.removed = removed_devices.len();
+        manager.devices = present.clone();
+        removed_devices
+    };
+    
+    for device in removed_devices {
+        crate::drivers::device_removed(&BusDevice::Pci(device));
+    }
+    
+    // Devices already bound are passed over by the registry
+    for device in present {
+        match crate::drivers::device_added(&BusDevice::Pci(device)) {
+            Ok(Some(_)) => report.bound += 1,
+            Ok(None) => {}
+            Err(e) => crate::serial::_print(format_args!("[PCI] Driver attach failed: {}\n", e)),
+        }
+    }
+    
+    report
+}
+
+/// Hotplug interrupt entry point. Only marks the rescan due; the slot is
+/// acknowledged and the bus rescanned from `process_hotplug_events`.
+pub fn handle_hotplug_interrupt() {
+    HOTPLUG_PENDING.store(true, Ordering::Release);
+}
+
+/// Request a rescan without a hotplug interrupt (e.g. from the shell)
+pub fn request_rescan() {
+    HOTPLUG_PENDING.store(true, Ordering::Release);
+}
+
+/// Run a deferred rescan if a hotplug event or rescan request is pending
+pub fn process_hotplug_events() -> Option<RescanReport> {
+    if !HOTPLUG_PENDING.swap(false, Ordering::AcqRel) {
+        return None;
+    }
+    
+    PCI_MANAGER.lock().ack_hotplug_slots();
+    Some(rescan())
+}
+
 /// Get PCI manager instance
 pub fn get_manager() -> &'static Mutex<PciManager> {
     &PCI_MANAGER
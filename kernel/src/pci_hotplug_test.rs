@@ -0,0 +1,166 @@
+//! PCI Hotplug Test
+//! Verifies that a rescan attaches a registered driver to the e1000 present in the QEMU test
+//! config, the typed driver registry's attach/detach lifecycle, and that a device plugged
+//! into and pulled from a scan is bound and then unbound
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::drivers::{self, BusDevice, DeviceResult, Driver, DriverRegistry};
+use crate::pci::{self, PciDevice};
+use crate::serial::_print;
+
+const E1000_VENDOR: u16 = 0x8086;
+const E1000_DEVICE: u16 = 0x100E;
+/// IDs no real device has, for the device plugged in by Test 4
+const HOTPLUG_VENDOR: u16 = 0xFFFE;
+const HOTPLUG_DEVICE: u16 = 0x0001;
+
+static ATTACH_COUNT: AtomicUsize = AtomicUsize::new(0);
+static DETACH_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Minimal typed driver that claims a single vendor/device pair
+struct MockDriver {
+    name: &'static str,
+    vendor_id: u16,
+    device_id: u16,
+}
+
+impl Driver for MockDriver {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn probe(&self, device: &BusDevice) -> bool {
+        matches!(device, BusDevice::Pci(d) if d.vendor_id == self.vendor_id && d.device_id == self.device_id)
+    }
+
+    fn attach(&mut self, _device: &BusDevice) -> DeviceResult<()> {
+        ATTACH_COUNT.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn detach(&mut self) {
+        DETACH_COUNT.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn fake_device(vendor_id: u16, device_id: u16, class_code: u8) -> PciDevice {
+    PciDevice {
+        bus: 0,
+        device: 3,
+        function: 0,
+        vendor_id,
+        device_id,
+        class_code,
+        subclass: 0,
+        prog_if: 0,
+        revision_id: 0,
+        header_type: 0,
+        bars: [0; 6],
+        interrupt_line: 0,
+        interrupt_pin: 0,
+        capabilities: Vec::new(),
+        msix_info: None,
+    }
+}
+
+/// Test PCI driver binding and hotplug
+pub fn run_pci_hotplug_tests() -> Result<(), &'static str> {
+    _print(format_args!("[PCI Test] Starting PCI hotplug tests...\n"));
+
+    // Test 1: Rescan attaches a newly registered driver to the e1000 from the QEMU test
+    // configuration, which no driver had claimed
+    _print(format_args!("[PCI Test] Test 1: Rescan binds e1000...\n"));
+    drivers::register_driver(Box::new(MockDriver { name: "e1000-test", vendor_id: E1000_VENDOR, device_id: E1000_DEVICE }));
+
+    let report = pci::rescan();
+    let present = pci::get_manager().lock().find_device_by_id(E1000_VENDOR, E1000_DEVICE).cloned();
+
+    match present {
+        Some(device) => {
+            let (bus, slot, function) = (device.bus, device.device, device.function);
+            if drivers::bound_driver(&BusDevice::Pci(device)).as_deref() != Some("e1000-test") {
+                return Err("e1000 present but not bound after rescan");
+            }
+            if ATTACH_COUNT.load(Ordering::SeqCst) != 1 || report.bound != 1 {
+                return Err("Unexpected bind count after rescan");
+            }
+            _print(format_args!("[PCI Test] ✓ e1000 bound at {:02X}:{:02X}.{}\n", bus, slot, function));
+        }
+        None => _print(format_args!("[PCI Test] ! No e1000 present, skipping bind check\n")),
+    }
+
+    // Test 2: A second rescan with no bus change is idempotent
+    _print(format_args!("[PCI Test] Test 2: Idempotent rescan...\n"));
+    let report = pci::rescan();
+    if report.bound != 0 || report.removed != 0 || DETACH_COUNT.load(Ordering::SeqCst) != 0 {
+        return Err("Rescan without bus changes modified bindings");
+    }
+    _print(format_args!("[PCI Test] ✓ Rescan without changes left bindings intact\n"));
+
+    // Test 3: Typed driver registry attaches the matching driver and detaches on removal
+    _print(format_args!("[PCI Test] Test 3: Driver registry lifecycle...\n"));
+    let mut registry = DriverRegistry::new();
+    registry.register(Box::new(MockDriver { name: "mock-virtio", vendor_id: 0x1AF4, device_id: 0x1000 }));
+    registry.register(Box::new(MockDriver { name: "mock-e1000", vendor_id: E1000_VENDOR, device_id: E1000_DEVICE }));
+
+    let detaches = DETACH_COUNT.load(Ordering::SeqCst);
+    let device = BusDevice::Pci(fake_device(E1000_VENDOR, E1000_DEVICE, 0x02));
+    match registry.attach_device(&device) {
+        Ok(Some(ref name)) if name == "mock-e1000" => {}
+        _ => return Err("Registry did not attach the matching driver"),
+    }
+    if registry.detach_device(&device).as_deref() != Some("mock-e1000")
+        || DETACH_COUNT.load(Ordering::SeqCst) != detaches + 1
+    {
+        return Err("Registry did not detach driver on removal");
+    }
+    _print(format_args!("[PCI Test] ✓ Matching driver attached and detached\n"));
+
+    // Test 4: A device appearing in a scan is bound, and unbound once it is gone again
+    _print(format_args!("[PCI Test] Test 4: Plugging and pulling a device...\n"));
+    drivers::register_driver(Box::new(MockDriver { name: "hotplug-test", vendor_id: HOTPLUG_VENDOR, device_id: HOTPLUG_DEVICE }));
+    let mut plugged = fake_device(HOTPLUG_VENDOR, HOTPLUG_DEVICE, 0xFF);
+    plugged.bus = 0xFE;
+    let before = pci::get_manager().lock().get_devices().to_vec();
+    let attaches = ATTACH_COUNT.load(Ordering::SeqCst);
+    let detaches = DETACH_COUNT.load(Ordering::SeqCst);
+
+    let plugged_device = BusDevice::Pci(plugged.clone());
+    let mut with_device = before.clone();
+    with_device.push(plugged);
+    let report = pci::apply_scan(with_device);
+    if report.added != 1 || report.bound != 1 || ATTACH_COUNT.load(Ordering::SeqCst) != attaches + 1 {
+        return Err("Plugged device not bound");
+    }
+    if drivers::bound_driver(&plugged_device).as_deref() != Some("hotplug-test") {
+        return Err("Plugged device bound to the wrong driver");
+    }
+
+    let report = pci::apply_scan(before);
+    if report.removed != 1 || DETACH_COUNT.load(Ordering::SeqCst) != detaches + 1 {
+        return Err("Pulled device not unbound");
+    }
+    if drivers::bound_driver(&plugged_device).is_some() {
+        return Err("Pulled device still bound");
+    }
+    _print(format_args!("[PCI Test] ✓ Plugged device bound, then unbound once pulled\n"));
+
+    _print(format_args!("[PCI Test] ✓ All PCI hotplug tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for PCI hotplug
+pub fn test_pci_hotplug() {
+    _print(format_args!("[PCI Test] ===========================================\n"));
+    _print(format_args!("[PCI Test]          PCI HOTPLUG TESTS\n"));
+    _print(format_args!("[PCI Test] ===========================================\n"));
+
+    match run_pci_hotplug_tests() {
+        Ok(_) => _print(format_args!("[PCI Test] ✓ All PCI hotplug tests PASSED\n")),
+        Err(e) => _print(format_args!("[PCI Test] ✗ PCI hotplug tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[PCI Test] ===========================================\n"));
+}
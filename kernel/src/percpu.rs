@@ -398,6 +398,7 @@ pub fn init() -> Result<(), &'static str> {
     
     CPU_COUNT.store(1, Ordering::SeqCst);
     PERCPU_INITIALIZED.store(true, Ordering::SeqCst);
+    arch::topology::add_cpu(0, bsp_apic_id);
     
     crate::serial::_print(format_args!(
         "[PerCPU] Initialized BSP (CPU 0, APIC ID {}) with features: TSC={}, TSC-deadline={}, x2APIC={}\n",
@@ -438,6 +439,7 @@ pub fn add_cpu(cpu_id: u32, apic_id: u32) -> Result<(), &'static str> {
     }
     
     CPU_COUNT.fetch_add(1, Ordering::SeqCst);
+    arch::topology::add_cpu(cpu_id, apic_id);
     
     crate::serial::_print(format_args!(
         "[PerCPU] Added CPU {} (APIC ID {}) with GS base\n",
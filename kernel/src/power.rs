@@ -0,0 +1,492 @@
+//! Power service
+//! Battery and AC adapter status read from ACPI: every battery device (`PNP0C0A`) reports its
+//! capacities in `_BIF` and its state, rate and remaining charge in `_BST`, and every AC
+//! adapter (`ACPI0003`) whether it is plugged in through `_PSR`. From these come the charge
+//! percentage, the charging state and time-to-empty and time-to-full estimates.
+//!
+//! Polling holds the combined charge against the power policy while running on battery:
+//! falling to the low level posts a notification, and reaching the critical level, or a
+//! battery flagging itself critical, posts another and suspends the system. Each happens once
+//! until the charge climbs back above its level or an adapter is plugged in.
+//!
+//! The namespace is evaluated through an `AcpiNamespace` the platform's AML interpreter
+//! attaches; until one is attached the system has no battery and no adapter.
+//!
+//! Shutting down detaches the drivers and enters the S5 sleep state through the FADT's PM1
+//! control blocks, with the sleep types `\_S5` gives.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+/// `_HID` of a control method battery
+pub const BATTERY_HID: &str = "PNP0C0A";
+/// `_HID` of an AC adapter
+pub const AC_ADAPTER_HID: &str = "ACPI0003";
+/// How often `poll` rereads the batteries
+pub const POLL_INTERVAL_MS: u64 = 5_000;
+
+/// A battery field whose value the firmware does not know
+const UNKNOWN: u64 = 0xFFFF_FFFF;
+/// `_STA` bit set while a battery is inserted
+const STA_BATTERY_PRESENT: u64 = 1 << 4;
+const BST_DISCHARGING: u64 = 1 << 0;
+const BST_CHARGING: u64 = 1 << 1;
+const BST_CRITICAL: u64 = 1 << 2;
+/// Elements of a `_BIF` package
+const BIF_LENGTH: usize = 13;
+/// Elements of a `_BST` package
+const BST_LENGTH: usize = 4;
+/// Offsets in the FADT of the PM1a and PM1b control block ports
+const FADT_PM1A_CNT_BLK: u64 = 64;
+const FADT_PM1B_CNT_BLK: u64 = 68;
+/// PM1 control register sleep type field and the bit that enters it
+const PM1_SLP_TYP_SHIFT: u32 = 10;
+const PM1_SLP_EN: u16 = 1 << 13;
+
+/// A value an ACPI object evaluates to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AcpiObject {
+    Integer(u64),
+    String(String),
+    Buffer(Vec<u8>),
+    Package(Vec<AcpiObject>),
+}
+
+impl AcpiObject {
+    fn text(&self) -> String {
+        match self {
+            AcpiObject::String(text) => text.clone(),
+            AcpiObject::Buffer(bytes) => String::from_utf8_lossy(bytes).trim_end_matches('\0').into(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// The part of the ACPI namespace the power service reads
+pub trait AcpiNamespace {
+    /// Paths of the devices whose `_HID` is `hid`, such as `\_SB.BAT0`
+    fn devices(&self, hid: &str) -> Vec<String>;
+    /// Evaluate the object or control method at `path`, such as `\_SB.BAT0._BST`
+    fn evaluate(&self, path: &str) -> Option<AcpiObject>;
+}
+
+impl<N: AcpiNamespace + ?Sized> AcpiNamespace for Box<N> {
+    fn devices(&self, hid: &str) -> Vec<String> {
+        (**self).devices(hid)
+    }
+
+    fn evaluate(&self, path: &str) -> Option<AcpiObject> {
+        (**self).evaluate(path)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PowerError {
+    /// The object at this path is missing or not shaped as the ACPI specification defines it
+    BadObject(String),
+}
+
+/// Whether a battery's figures are energy or charge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerUnit {
+    /// Capacities in mWh, rates in mW
+    MilliwattHours,
+    /// Capacities in mAh, rates in mA
+    MilliampHours,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChargingState {
+    Charging,
+    Discharging,
+    /// Neither charging nor discharging with the battery full
+    Full,
+    /// Neither charging nor discharging short of full, as when the charge is held on AC
+    NotCharging,
+}
+
+/// One battery, as `_BIF` and `_BST` describe it; figures the firmware does not know are `None`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Battery {
+    pub path: String,
+    pub unit: PowerUnit,
+    pub design_capacity: Option<u64>,
+    /// The capacity at the last full charge, lower than the design capacity as a battery wears
+    pub full_capacity: Option<u64>,
+    pub remaining: Option<u64>,
+    pub rate: Option<u64>,
+    /// Design voltage in mV
+    pub design_voltage: Option<u64>,
+    pub state: ChargingState,
+    /// Set by the firmware when the battery is about to run out
+    pub critical: bool,
+    pub model: String,
+    pub serial: String,
+    /// Chemistry, such as `LION`
+    pub battery_type: String,
+}
+
+impl Battery {
+    /// The capacity the charge is measured against: the last full charge, else the design
+    fn capacity(&self) -> Option<u64> {
+        self.full_capacity.or(self.design_capacity).filter(|&capacity| capacity > 0)
+    }
+
+    /// Remaining charge as a percentage of the last full charge, to the nearest percent
+    pub fn percent(&self) -> Option<u8> {
+        let (remaining, capacity) = (self.remaining?, self.capacity()?);
+        Some(((remaining.min(capacity) * 100 + capacity / 2) / capacity) as u8)
+    }
+
+    /// Minutes until the battery runs out at the present rate, while discharging
+    pub fn time_to_empty(&self) -> Option<u64> {
+        minutes(self.state, ChargingState::Discharging, self.remaining?, self.rate?)
+    }
+
+    /// Minutes until the battery is full at the present rate, while charging
+    pub fn time_to_full(&self) -> Option<u64> {
+        let missing = self.capacity()?.saturating_sub(self.remaining?);
+        minutes(self.state, ChargingState::Charging, missing, self.rate?)
+    }
+
+    /// `value` in this battery's unit as energy in mWh, so batteries reporting in
+    /// different units can be added up; charge without a design voltage stays as it is
+    fn energy(&self, value: u64) -> u64 {
+        match (self.unit, self.design_voltage) {
+            (PowerUnit::MilliampHours, Some(millivolts)) => value * millivolts / 1000,
+            _ => value,
+        }
+    }
+}
+
+/// Minutes to move `amount` at `rate` per hour, when `state` is `wanted`
+fn minutes(state: ChargingState, wanted: ChargingState, amount: u64, rate: u64) -> Option<u64> {
+    (state == wanted && rate > 0).then(|| amount * 60 / rate)
+}
+
+/// Every battery and adapter in the system
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PowerStatus {
+    pub batteries: Vec<Battery>,
+    /// Whether each adapter is plugged in
+    pub adapters: Vec<bool>,
+}
+
+impl PowerStatus {
+    pub fn on_ac(&self) -> bool {
+        self.adapters.iter().any(|&online| online)
+    }
+
+    /// Running on battery power: no adapter plugged in and a battery discharging
+    pub fn on_battery(&self) -> bool {
+        !self.on_ac() && self.batteries.iter().any(|battery| battery.state == ChargingState::Discharging)
+    }
+
+    /// Sum of `value` over the batteries as energy, unknown if any battery does not report it
+    fn total(&self, value: impl Fn(&Battery) -> Option<u64>) -> Option<u64> {
+        self.batteries.iter().map(|battery| Some(battery.energy(value(battery)?))).sum()
+    }
+
+    /// The combined charge of all batteries, to the nearest percent
+    pub fn percent(&self) -> Option<u8> {
+        let capacity = self.total(Battery::capacity).filter(|&capacity| capacity > 0)?;
+        let remaining = self.total(|battery| battery.remaining)?.min(capacity);
+        Some(((remaining * 100 + capacity / 2) / capacity) as u8)
+    }
+
+    /// The batteries' combined state: charging or discharging if any is, full if all are
+    pub fn state(&self) -> Option<ChargingState> {
+        let states = || self.batteries.iter().map(|battery| battery.state);
+        if self.batteries.is_empty() {
+            None
+        } else if states().any(|state| state == ChargingState::Charging) {
+            Some(ChargingState::Charging)
+        } else if states().any(|state| state == ChargingState::Discharging) {
+            Some(ChargingState::Discharging)
+        } else if states().all(|state| state == ChargingState::Full) {
+            Some(ChargingState::Full)
+        } else {
+            Some(ChargingState::NotCharging)
+        }
+    }
+
+    /// Minutes until every battery has run out at the combined rate
+    pub fn time_to_empty(&self) -> Option<u64> {
+        let remaining = self.total(|battery| battery.remaining)?;
+        minutes(self.state()?, ChargingState::Discharging, remaining, self.total(|battery| battery.rate)?)
+    }
+
+    /// Minutes until every battery is full at the combined rate
+    pub fn time_to_full(&self) -> Option<u64> {
+        let missing = self.total(Battery::capacity)?.saturating_sub(self.total(|battery| battery.remaining)?);
+        minutes(self.state()?, ChargingState::Charging, missing, self.total(|battery| battery.rate)?)
+    }
+}
+
+/// Charge levels, in percent, at which the power service acts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerPolicy {
+    /// Notify that the battery is low
+    pub low_percent: u8,
+    /// Notify and suspend
+    pub critical_percent: u8,
+}
+
+impl Default for PowerPolicy {
+    fn default() -> Self {
+        Self { low_percent: 10, critical_percent: 5 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerNotification {
+    BatteryLow { percent: u8, minutes_left: Option<u64> },
+    /// Sent just before the system suspends
+    BatteryCritical { percent: u8 },
+}
+
+impl fmt::Display for PowerNotification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PowerNotification::BatteryLow { percent, minutes_left: Some(minutes) } => {
+                write!(f, "Battery low: {}% ({}:{:02} remaining)", percent, minutes / 60, minutes % 60)
+            }
+            PowerNotification::BatteryLow { percent, minutes_left: None } => write!(f, "Battery low: {}%", percent),
+            PowerNotification::BatteryCritical { percent } => {
+                write!(f, "Battery critical: {}%, suspending", percent)
+            }
+        }
+    }
+}
+
+/// What the power service does about a draining battery
+pub trait PowerActions {
+    fn notify(&mut self, notification: &PowerNotification);
+    fn suspend(&mut self);
+}
+
+/// Reads the batteries and adapters and acts on the charge
+pub struct PowerService<N: AcpiNamespace> {
+    namespace: N,
+    policy: PowerPolicy,
+    low_notified: bool,
+    critical_handled: bool,
+}
+
+impl<N: AcpiNamespace> PowerService<N> {
+    pub fn new(namespace: N, policy: PowerPolicy) -> Self {
+        Self { namespace, policy, low_notified: false, critical_handled: false }
+    }
+
+    /// Every present battery and every adapter, freshly evaluated
+    pub fn status(&self) -> Result<PowerStatus, PowerError> {
+        let mut status = PowerStatus::default();
+        for path in self.namespace.devices(BATTERY_HID) {
+            if let Some(battery) = self.read_battery(&path)? {
+                status.batteries.push(battery);
+            }
+        }
+        for path in self.namespace.devices(AC_ADAPTER_HID) {
+            let psr = format!("{}._PSR", path);
+            match self.namespace.evaluate(&psr) {
+                Some(AcpiObject::Integer(online)) => status.adapters.push(online != 0),
+                _ => return Err(PowerError::BadObject(psr)),
+            }
+        }
+        Ok(status)
+    }
+
+    /// Read the status and act on it: notify when the charge falls to the low level on
+    /// battery, and notify and suspend when it reaches the critical level
+    pub fn poll(&mut self, actions: &mut dyn PowerActions) -> Result<PowerStatus, PowerError> {
+        let status = self.status()?;
+        let percent = match status.percent() {
+            Some(percent) if status.on_battery() => percent,
+            _ => {
+                self.low_notified = false;
+                self.critical_handled = false;
+                return Ok(status);
+            }
+        };
+
+        let critical = percent <= self.policy.critical_percent || status.batteries.iter().any(|battery| battery.critical);
+        if !critical {
+            self.critical_handled = false;
+        } else if !self.critical_handled {
+            self.critical_handled = true;
+            self.low_notified = true;
+            actions.notify(&PowerNotification::BatteryCritical { percent });
+            actions.suspend();
+        }
+
+        if percent > self.policy.low_percent {
+            self.low_notified = false;
+        } else if !self.low_notified {
+            self.low_notified = true;
+            actions.notify(&PowerNotification::BatteryLow { percent, minutes_left: status.time_to_empty() });
+        }
+        Ok(status)
+    }
+
+    /// The battery at `path`, or `None` if `_STA` says it is not inserted
+    fn read_battery(&self, path: &str) -> Result<Option<Battery>, PowerError> {
+        // A device without _STA is present
+        if let Some(AcpiObject::Integer(sta)) = self.namespace.evaluate(&format!("{}._STA", path)) {
+            if sta & STA_BATTERY_PRESENT == 0 {
+                return Ok(None);
+            }
+        }
+        let info = self.package(path, "_BIF", BIF_LENGTH)?;
+        let status = self.package(path, "_BST", BST_LENGTH)?;
+        let field = |package: &[AcpiObject], method: &str, index: usize| match package[index] {
+            AcpiObject::Integer(UNKNOWN) => Ok(None),
+            AcpiObject::Integer(value) => Ok(Some(value)),
+            _ => Err(PowerError::BadObject(format!("{}.{}", path, method))),
+        };
+
+        let unit = match field(&info, "_BIF", 0)? {
+            Some(0) => PowerUnit::MilliwattHours,
+            Some(1) => PowerUnit::MilliampHours,
+            _ => return Err(PowerError::BadObject(format!("{}._BIF", path))),
+        };
+        let bits = field(&status, "_BST", 0)?.unwrap_or(0);
+        let full_capacity = field(&info, "_BIF", 2)?;
+        let remaining = field(&status, "_BST", 2)?;
+        let state = if bits & BST_CHARGING != 0 {
+            ChargingState::Charging
+        } else if bits & BST_DISCHARGING != 0 {
+            ChargingState::Discharging
+        } else if matches!((remaining, full_capacity), (Some(remaining), Some(full)) if remaining >= full) {
+            ChargingState::Full
+        } else {
+            ChargingState::NotCharging
+        };
+
+        Ok(Some(Battery {
+            path: path.into(),
+            unit,
+            design_capacity: field(&info, "_BIF", 1)?,
+            full_capacity,
+            remaining,
+            rate: field(&status, "_BST", 1)?,
+            design_voltage: field(&info, "_BIF", 4)?,
+            state,
+            critical: bits & BST_CRITICAL != 0,
+            model: info[9].text(),
+            serial: info[10].text(),
+            battery_type: info[11].text(),
+        }))
+    }
+
+    /// The package `method` of `path` evaluates to, which must have `length` elements
+    fn package(&self, path: &str, method: &str, length: usize) -> Result<Vec<AcpiObject>, PowerError> {
+        let full_path = format!("{}.{}", path, method);
+        match self.namespace.evaluate(&full_path) {
+            Some(AcpiObject::Package(elements)) if elements.len() >= length => Ok(elements),
+            _ => Err(PowerError::BadObject(full_path)),
+        }
+    }
+}
+
+/// Logs notifications to the console and suspends to idle
+struct SystemActions;
+
+impl PowerActions for SystemActions {
+    fn notify(&mut self, notification: &PowerNotification) {
+        crate::serial::_print(format_args!("[Power] {}\n", notification));
+    }
+
+    /// Detach the drivers and halt until a key press wakes the system; the key only wakes it
+    /// and is not delivered
+    fn suspend(&mut self) {
+        crate::serial::_print(format_args!("[Power] Suspending to idle\n"));
+        crate::drivers::suspend_drivers();
+        while crate::drivers::keyboard::get_key().is_none() {
+            x86_64::instructions::hlt();
+        }
+        crate::drivers::resume_drivers();
+        crate::serial::_print(format_args!("[Power] Resumed\n"));
+    }
+}
+
+static POWER: Mutex<Option<PowerService<Box<dyn AcpiNamespace + Send>>>> = Mutex::new(None);
+static LAST_POLL_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Read batteries and adapters from `namespace` from now on
+pub fn attach(namespace: Box<dyn AcpiNamespace + Send>) {
+    *POWER.lock() = Some(PowerService::new(namespace, PowerPolicy::default()));
+    crate::serial::_print(format_args!("[Power] ACPI namespace attached\n"));
+}
+
+/// The system's batteries and adapters; none before a namespace is attached
+pub fn status() -> Result<PowerStatus, PowerError> {
+    POWER.lock().as_ref().map_or(Ok(PowerStatus::default()), PowerService::status)
+}
+
+/// Poll the batteries if `POLL_INTERVAL_MS` has passed since the last poll; called from the
+/// main loop
+pub fn poll() {
+    let now = crate::time::get_uptime_ms();
+    let last = LAST_POLL_MS.load(Ordering::Relaxed);
+    if now.saturating_sub(last) < POLL_INTERVAL_MS && last != 0 {
+        return;
+    }
+    LAST_POLL_MS.store(now.max(1), Ordering::Relaxed);
+    if let Some(service) = POWER.lock().as_mut() {
+        if let Err(e) = service.poll(&mut SystemActions) {
+            crate::serial::_print(format_args!("[Power] Poll failed: {:?}\n", e));
+        }
+    }
+}
+
+/// SLP_TYPa and SLP_TYPb of the S5 state from `\_S5`, or 0 for both, QEMU's values, without
+/// a namespace to read them from
+fn s5_sleep_types() -> (u64, u64) {
+    let package = POWER.lock().as_ref().and_then(|service| service.namespace.evaluate("\\_S5"));
+    match package {
+        Some(AcpiObject::Package(elements)) => match elements.as_slice() {
+            [AcpiObject::Integer(a), AcpiObject::Integer(b), ..] => (*a, *b),
+            [AcpiObject::Integer(a)] => (*a, *a),
+            _ => (0, 0),
+        },
+        _ => (0, 0),
+    }
+}
+
+/// Detach every driver and power the machine off through the S5 sleep state, halting if
+/// the firmware leaves it running
+pub fn shutdown() -> ! {
+    crate::serial::_print(format_args!("[Power] Shutting down\n"));
+    crate::drivers::shutdown_drivers();
+
+    let (type_a, type_b) = s5_sleep_types();
+    let fadt = crate::acpi::find_table(b"FACP")
+        .filter(|&fadt| crate::acpi::read_header(fadt).length as u64 >= FADT_PM1B_CNT_BLK + 4);
+    if let Some(fadt) = fadt {
+        for (offset, sleep_type) in [(FADT_PM1A_CNT_BLK, type_a), (FADT_PM1B_CNT_BLK, type_b)] {
+            // SAFETY: the FADT lies in memory the firmware reserved, reached through the
+            // physical memory map, and the field is an aligned dword
+            let port = unsafe { core::ptr::read_volatile(crate::memory::phys_to_virt(fadt + offset).as_ptr::<u32>()) };
+            let Ok(port) = u16::try_from(port) else {
+                continue;
+            };
+            if port == 0 {
+                continue;
+            }
+            let value = ((sleep_type as u16 & 0x7) << PM1_SLP_TYP_SHIFT) | PM1_SLP_EN;
+            // SAFETY: the port is the PM1 control block the FADT names
+            unsafe { x86_64::instructions::port::Port::<u16>::new(port).write(value) };
+        }
+    }
+
+    crate::serial::_print(format_args!("[Power] Power-off failed, halting\n"));
+    x86_64::instructions::interrupts::disable();
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
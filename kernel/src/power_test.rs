@@ -0,0 +1,235 @@
+//! Power Service Test
+//! Reads emulated ACPI battery and AC adapter objects: the reported percentage, charging state
+//! and time estimates match their `_BIF`, `_BST` and `_PSR` values, batteries in different
+//! units add up, and draining past the low and critical levels notifies once and suspends once
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+use crate::power::{
+    AcpiNamespace, AcpiObject, ChargingState, PowerActions, PowerError, PowerNotification, PowerPolicy, PowerService,
+    AC_ADAPTER_HID, BATTERY_HID,
+};
+use crate::raeshell::power::{acpi_report, battery_report};
+use crate::serial::_print;
+
+const BAT0: &str = "\\_SB.BAT0";
+const BAT1: &str = "\\_SB.BAT1";
+const ADAPTER: &str = "\\_SB.AC";
+/// _BST state bits
+const DISCHARGING: u64 = 1;
+const CHARGING: u64 = 2;
+const CRITICAL: u64 = 4;
+
+/// An ACPI namespace of fixed objects the test changes as it goes
+#[derive(Default)]
+struct FakeAcpi {
+    devices: Mutex<Vec<(&'static str, String)>>,
+    objects: Mutex<BTreeMap<String, AcpiObject>>,
+}
+
+impl FakeAcpi {
+    fn add_device(&self, hid: &'static str, path: &str) {
+        self.devices.lock().push((hid, path.to_string()));
+    }
+
+    fn set(&self, path: &str, method: &str, object: AcpiObject) {
+        self.objects.lock().insert(alloc::format!("{}.{}", path, method), object);
+    }
+
+    /// `_BIF` of a battery measured in `unit` (0 mWh, 1 mAh)
+    fn set_info(&self, path: &str, unit: u64, design: u64, full: u64, voltage: u64) {
+        let integers = [unit, design, full, 1, voltage, design / 10, design / 20, 1, 1];
+        let mut info: Vec<AcpiObject> = integers.iter().map(|&value| AcpiObject::Integer(value)).collect();
+        info.push(AcpiObject::String("RAE-45".to_string()));
+        info.push(AcpiObject::Buffer(b"0042\0".to_vec()));
+        info.push(AcpiObject::String("LION".to_string()));
+        info.push(AcpiObject::String("Raeen".to_string()));
+        self.set(path, "_BIF", AcpiObject::Package(info));
+    }
+
+    /// `_BST` of a battery
+    fn set_status(&self, path: &str, state: u64, rate: u64, remaining: u64) {
+        let status = [state, rate, remaining, 11_400].iter().map(|&value| AcpiObject::Integer(value)).collect();
+        self.set(path, "_BST", AcpiObject::Package(status));
+    }
+
+    fn set_adapter(&self, online: bool) {
+        self.set(ADAPTER, "_PSR", AcpiObject::Integer(online as u64));
+    }
+}
+
+impl AcpiNamespace for &FakeAcpi {
+    fn devices(&self, hid: &str) -> Vec<String> {
+        self.devices.lock().iter().filter(|(device_hid, _)| *device_hid == hid).map(|(_, path)| path.clone()).collect()
+    }
+
+    fn evaluate(&self, path: &str) -> Option<AcpiObject> {
+        self.objects.lock().get(path).cloned()
+    }
+}
+
+/// Records what the power service asks for
+#[derive(Default)]
+struct FakeActions {
+    notifications: Vec<PowerNotification>,
+    suspends: u32,
+}
+
+impl PowerActions for FakeActions {
+    fn notify(&mut self, notification: &PowerNotification) {
+        self.notifications.push(*notification);
+    }
+
+    fn suspend(&mut self) {
+        self.suspends += 1;
+    }
+}
+
+/// A laptop with one 45 Wh battery, 50 Wh when new, and an AC adapter
+fn laptop() -> FakeAcpi {
+    let acpi = FakeAcpi::default();
+    acpi.add_device(BATTERY_HID, BAT0);
+    acpi.add_device(AC_ADAPTER_HID, ADAPTER);
+    acpi.set(BAT0, "_STA", AcpiObject::Integer(0x1F));
+    acpi.set_info(BAT0, 0, 50_000, 45_000, 11_100);
+    acpi.set_status(BAT0, DISCHARGING, 9_000, 18_000);
+    acpi.set_adapter(false);
+    acpi
+}
+
+/// Drain the laptop's battery to `percent` and poll
+fn drain(service: &mut PowerService<&FakeAcpi>, acpi: &FakeAcpi, actions: &mut FakeActions, percent: u64) -> Result<(), &'static str> {
+    acpi.set_status(BAT0, DISCHARGING, 9_000, 45_000 * percent / 100);
+    service.poll(actions).map(|_| ()).map_err(|_| "Poll failed")
+}
+
+pub fn run_power_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Power Test] Starting power service tests...\n"));
+
+    // Test 1: percentage, state and estimates match the ACPI values
+    _print(format_args!("[Power Test] Test 1: Reading _BIF, _BST and _PSR...\n"));
+    let acpi = laptop();
+    let service = PowerService::new(&acpi, PowerPolicy::default());
+    let status = service.status().map_err(|_| "Battery not read")?;
+    let battery = status.batteries.first().ok_or("Battery missing")?;
+    // 18000 of 45000 mWh left, drawing 9000 mW
+    if battery.percent() != Some(40) || battery.state != ChargingState::Discharging || status.on_ac() {
+        return Err("Discharging battery misreported");
+    }
+    if status.time_to_empty() != Some(120) || status.time_to_full().is_some() || battery.model != "RAE-45" || battery.serial != "0042" {
+        return Err("Discharging estimate or battery details wrong");
+    }
+    if battery_report(&status) != "Battery: 40%, discharging, 2:00 remaining\nAC adapter: off-line" {
+        return Err("battery output wrong");
+    }
+    let listing = acpi_report(&status, true, true, true);
+    let expected = "Battery 0: Discharging, 40%, 02:00:00 remaining\n\
+                    Battery 0: design capacity 50000 mWh, last full capacity 45000 mWh = 90%\n\
+                    Adapter 0: off-line";
+    if listing != expected {
+        return Err("acpi output wrong");
+    }
+    acpi.set_status(BAT0, CHARGING, 13_500, 36_000);
+    acpi.set_adapter(true);
+    let status = service.status().map_err(|_| "Battery not read")?;
+    // 9000 mWh to go at 13500 mW
+    if status.percent() != Some(80) || status.state() != Some(ChargingState::Charging) || status.time_to_full() != Some(40) {
+        return Err("Charging battery misreported");
+    }
+    if acpi_report(&status, true, true, false) != "Battery 0: Charging, 80%, 00:40:00 until charged\nAdapter 0: on-line" {
+        return Err("acpi output wrong while charging");
+    }
+    acpi.set_status(BAT0, 0, 0xFFFF_FFFF, 45_000);
+    let status = service.status().map_err(|_| "Battery not read")?;
+    if status.state() != Some(ChargingState::Full) || status.percent() != Some(100) || status.batteries[0].rate.is_some() {
+        return Err("Full battery or unknown rate misreported");
+    }
+    _print(format_args!("[Power Test] ✓ 40% discharging, 80% charging and full read as reported\n"));
+
+    // Test 2: batteries add up across units, absent ones are skipped, bad objects rejected
+    _print(format_args!("[Power Test] Test 2: Several batteries...\n"));
+    let acpi = laptop();
+    acpi.add_device(BATTERY_HID, BAT1);
+    acpi.set(BAT1, "_STA", AcpiObject::Integer(0x0F));
+    let service = PowerService::new(&acpi, PowerPolicy::default());
+    if service.status().map(|status| status.batteries.len()) != Ok(1) {
+        return Err("Removed battery reported");
+    }
+    // 1000 of 4000 mAh at 10 V: 10000 of 40000 mWh, with 18000 of 45000 mWh in the first
+    acpi.set(BAT1, "_STA", AcpiObject::Integer(0x1F));
+    acpi.set_info(BAT1, 1, 4_000, 4_000, 10_000);
+    acpi.set_status(BAT1, DISCHARGING, 100, 1_000);
+    let status = service.status().map_err(|_| "Batteries not read")?;
+    if status.batteries.len() != 2 || status.batteries[1].percent() != Some(25) || status.percent() != Some(33) {
+        return Err("Batteries in mWh and mAh not combined");
+    }
+    acpi.set(BAT1, "_BST", AcpiObject::Package(vec![AcpiObject::Integer(DISCHARGING)]));
+    if service.status() != Err(PowerError::BadObject("\\_SB.BAT1._BST".to_string())) {
+        return Err("Short _BST package accepted");
+    }
+    _print(format_args!("[Power Test] ✓ Two batteries read as 33% together\n"));
+
+    // Test 3: the low level notifies once, the critical level notifies and suspends once
+    _print(format_args!("[Power Test] Test 3: Low and critical thresholds...\n"));
+    let acpi = laptop();
+    let mut service = PowerService::new(&acpi, PowerPolicy::default());
+    let mut actions = FakeActions::default();
+    for percent in [40, 11] {
+        drain(&mut service, &acpi, &mut actions, percent)?;
+    }
+    if !actions.notifications.is_empty() || actions.suspends != 0 {
+        return Err("Acted above the low level");
+    }
+    for percent in [10, 9, 8] {
+        drain(&mut service, &acpi, &mut actions, percent)?;
+    }
+    if actions.notifications != [PowerNotification::BatteryLow { percent: 10, minutes_left: Some(30) }] || actions.suspends != 0 {
+        return Err("Low battery not notified exactly once");
+    }
+    for percent in [6, 5, 4] {
+        drain(&mut service, &acpi, &mut actions, percent)?;
+    }
+    if actions.suspends != 1 || actions.notifications.last() != Some(&PowerNotification::BatteryCritical { percent: 5 }) {
+        return Err("Critical level did not suspend exactly once");
+    }
+    acpi.set_adapter(true);
+    acpi.set_status(BAT0, CHARGING, 9_000, 1_800);
+    service.poll(&mut actions).map_err(|_| "Poll failed")?;
+    acpi.set_adapter(false);
+    drain(&mut service, &acpi, &mut actions, 4)?;
+    if actions.suspends != 2 || actions.notifications.len() != 3 {
+        return Err("Unplugging at the critical level did not suspend again");
+    }
+    let acpi = laptop();
+    let mut service = PowerService::new(&acpi, PowerPolicy::default());
+    let mut actions = FakeActions::default();
+    acpi.set_status(BAT0, DISCHARGING | CRITICAL, 9_000, 13_500);
+    service.poll(&mut actions).map_err(|_| "Poll failed")?;
+    acpi.set_adapter(true);
+    acpi.set_status(BAT0, CHARGING | CRITICAL, 9_000, 13_500);
+    service.poll(&mut actions).map_err(|_| "Poll failed")?;
+    if actions.suspends != 1 || actions.notifications != [PowerNotification::BatteryCritical { percent: 30 }] {
+        return Err("Battery flagged critical did not suspend once on battery power");
+    }
+    _print(format_args!("[Power Test] ✓ Notified at 10%, suspended at 5% and again after unplugging\n"));
+
+    _print(format_args!("[Power Test] ✓ All power service tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for the power service
+pub fn test_power() {
+    _print(format_args!("[Power Test] ===========================================\n"));
+    _print(format_args!("[Power Test]           POWER SERVICE TESTS\n"));
+    _print(format_args!("[Power Test] ===========================================\n"));
+
+    match run_power_tests() {
+        Ok(_) => _print(format_args!("[Power Test] ✓ All power service tests PASSED\n")),
+        Err(e) => _print(format_args!("[Power Test] ✗ Power service tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Power Test] ===========================================\n"));
+}
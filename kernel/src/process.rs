@@ -1,18 +1,32 @@
 use alloc::collections::VecDeque;
 use alloc::vec::Vec;
 use alloc::string::ToString;
+use bitflags::bitflags;
 use core::ops::DerefMut;
-use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use spin::{Mutex, Once};
 use x86_64::{VirtAddr, PhysAddr};
+use x86_64::instructions::interrupts;
 use crate::arch::{get_cpu_count, get_current_cpu_id};
+use crate::arch::topology::CpuTopology;
+use crate::heap::slab::{SlabBox, SlabCache};
 
 static NEXT_PID: AtomicU64 = AtomicU64::new(1);
 static SMP_SCHEDULER: Once<Mutex<SmpScheduler>> = Once::new();
 static _LEGACY_SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler::new());
 static IDLE_THREAD_PID: AtomicU64 = AtomicU64::new(0);
-static SLEEPERS: Mutex<alloc::vec::Vec<(u64, u64)>> = Mutex::new(alloc::vec::Vec::new()); // (wake_ms, pid)
+/// The process that adopts orphans, PID 1 until init registers itself
+static INIT_PID: AtomicU64 = AtomicU64::new(1);
+/// Slots for the process table, reused as processes come and go
+static PROCESS_CACHE: SlabCache<Process> = SlabCache::new("process");
+static SLEEPERS: Mutex<SleepQueue> = Mutex::new(SleepQueue::new());
 pub static JOIN_WAITERS: Mutex<alloc::collections::BTreeMap<u64, alloc::vec::Vec<u64>>> = Mutex::new(alloc::collections::BTreeMap::new()); // target_pid -> waiters
+static CHILD_WAITERS: Mutex<alloc::collections::BTreeSet<u64>> = Mutex::new(alloc::collections::BTreeSet::new()); // parents blocked in wait_pid
+/// Child exits parents have been told of, counted so `wait_pid` sees one land between looking
+/// for exited children and blocking
+static CHILD_EXITS: AtomicU64 = AtomicU64::new(0);
+/// A load balancing round is due, marked by the timer tick for the main loop to run
+static BALANCE_PENDING: AtomicBool = AtomicBool::new(false);
 
 pub type ProcessId = u64;
 
@@ -22,6 +36,41 @@ pub enum ProcessState {
     Running,
     Blocked,
     Terminated,
+    /// Exited with the code, kept until the parent waits on it
+    Zombie(i32),
+}
+
+/// Errors from starting and waiting on child processes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessError {
+    /// No process is running on this CPU
+    NoCurrentProcess,
+    /// The caller has no child matching the wait
+    NoChildren,
+    /// The program is missing or not an ELF executable
+    NotExecutable,
+    /// No memory for the new process
+    OutOfMemory,
+    /// The clone flags or stack do not make a thread that can run
+    InvalidClone,
+    /// A nice value outside `NICE_MIN` to `NICE_MAX`
+    InvalidNice,
+    /// No process has the PID given
+    NoSuchProcess,
+}
+
+bitflags! {
+    /// What a process made by `clone_process` shares with the one that made it, taking a
+    /// copy of the rest. The values are Linux's
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct CloneFlags: u64 {
+        /// Run in the same address space
+        const VM = 0x100;
+        /// Share the descriptor table
+        const FILES = 0x400;
+        /// Join the same thread group, so as to have the same PID
+        const THREAD = 0x10000;
+    }
 }
 
 /// Basic signal types for process management
@@ -31,6 +80,7 @@ pub enum Signal {
     SIGKILL = 9,   // Force kill
     SIGSTOP = 19,  // Stop process
     SIGCONT = 18,  // Continue process
+    SIGCHLD = 17,  // Child exited
     SIGUSR1 = 10,  // User-defined signal 1
     SIGUSR2 = 12,  // User-defined signal 2
 }
@@ -42,7 +92,8 @@ pub type SignalHandler = fn(Signal);
 fn default_signal_handler(signal: Signal) {
     match signal {
         Signal::SIGTERM | Signal::SIGKILL => {
-            exit_process(-1); // Exit with error code
+            // Fatal signals end the whole thread group
+            exit_group(-1); // Exit with error code
         }
         Signal::SIGSTOP => {
             // Block the current process
@@ -67,6 +118,23 @@ pub enum Priority {
     Gaming = 3, // Special priority for gaming mode
 }
 
+/// Nice values run from `NICE_MIN`, the most favoured, to `NICE_MAX`, as on Linux
+pub const NICE_MIN: i32 = -20;
+pub const NICE_MAX: i32 = 19;
+
+impl Priority {
+    /// The priority a nice value maps to: High below 0, Low from 10, as `nice` gives by
+    /// default, and Normal between. `None` out of range
+    pub fn from_nice(nice: i32) -> Option<Priority> {
+        match nice {
+            NICE_MIN..=-1 => Some(Priority::High),
+            0..=9 => Some(Priority::Normal),
+            10..=NICE_MAX => Some(Priority::Low),
+            _ => None,
+        }
+    }
+}
+
 /// Real-time scheduling classes
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RtClass {
@@ -218,9 +286,18 @@ pub struct Process {
     pub heap_base: VirtAddr,
     pub heap_size: usize,
     pub name: alloc::string::String,
+    /// Microseconds spent running, counted a timer tick at a time
     pub cpu_time: u64,
     pub memory_usage: usize,
-    pub open_files: Vec<u64>, // File descriptor IDs
+    /// Uptime in milliseconds when the process was created
+    pub start_time: u64,
+    pub exit_code: i32,
+    /// Thread group the process belongs to, reported as its PID: its own PID unless it is a
+    /// thread made with `CloneFlags::THREAD`
+    pub thread_group: u64,
+    /// Descriptors opened through syscalls, shared by threads made with `CloneFlags::FILES`
+    /// and closed when the last process sharing them exits
+    pub open_files: alloc::sync::Arc<Mutex<Vec<u64>>>,
     pub permissions: ProcessPermissions,
     pub pending_signals: u64, // Bitmask of pending signals
     pub signal_handlers: [Option<SignalHandler>; 32], // Signal handler table
@@ -229,6 +306,12 @@ pub struct Process {
     pub cpu_affinity: CpuAffinity, // CPU affinity for SMP scheduling
     pub rt_params: RtParams,
     pub numa_node: Option<NumaNode>, // Real-time scheduling parameters
+    /// Thread pointer loaded into the FS base while the process runs, 0 without TLS
+    pub fs_base: u64,
+    /// TLS template of the program, from which each of its threads gets a block
+    pub tls_template: Option<alloc::sync::Arc<crate::elf::TlsTemplate>>,
+    /// Head of the thread's robust futex list in user memory, 0 until it sets one
+    pub robust_list: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -288,7 +371,10 @@ impl Process {
             name,
             cpu_time: 0,
             memory_usage: 0,
-            open_files: Vec::new(),
+            start_time: crate::time::get_uptime_ms(),
+            exit_code: 0,
+            thread_group: pid,
+            open_files: alloc::sync::Arc::new(Mutex::new(Vec::new())),
             permissions: ProcessPermissions::default(),
             pending_signals: 0,
             signal_handlers: [None; 32],
@@ -296,6 +382,9 @@ impl Process {
             cpu_affinity: CpuAffinity::ANY,
             rt_params: RtParams::default(),
             numa_node: None,
+            fs_base: 0,
+            tls_template: None,
+            robust_list: 0,
         })
     }
     
@@ -313,6 +402,11 @@ impl Process {
         Ok(process)
     }
 
+    /// Real-time, high-priority and gaming processes, which suffer most from sharing a core
+    pub fn is_latency_sensitive(&self) -> bool {
+        self.rt_params.class != RtClass::BestEffort || matches!(self.priority, Priority::High | Priority::Gaming)
+    }
+
     pub fn with_kernel_stack(mut self, stack_ptr: *mut u8, stack_size: usize) -> Self {
         self.kernel_stack_ptr = Some(stack_ptr as usize);
         self.stack_base = VirtAddr::new(stack_ptr as u64);
@@ -320,6 +414,15 @@ impl Process {
         self.context.rsp = (stack_ptr as u64) + stack_size as u64;
         self
     }
+
+    /// Give the process a TLS block made from `template` in its address space and point its
+    /// FS base at it
+    pub fn setup_tls(&mut self, template: alloc::sync::Arc<crate::elf::TlsTemplate>) -> Result<(), crate::vmm::VmError> {
+        let address_space_id = self.address_space_id.ok_or(crate::vmm::VmError::InvalidAddressSpace)?;
+        self.fs_base = template.allocate_block(address_space_id)?.as_u64();
+        self.tls_template = Some(template);
+        Ok(())
+    }
     
     pub fn user_process(name: alloc::string::String, entry_point: VirtAddr) -> Result<Self, crate::vmm::VmError> {
         let pid = NEXT_PID.fetch_add(1, Ordering::SeqCst);
@@ -366,7 +469,10 @@ impl Process {
             name,
             cpu_time: 0,
             memory_usage: 0,
-            open_files: Vec::new(),
+            start_time: crate::time::get_uptime_ms(),
+            exit_code: 0,
+            thread_group: pid,
+            open_files: alloc::sync::Arc::new(Mutex::new(Vec::new())),
             pending_signals: 0,
             signal_handlers: [None; 32],
             permissions: ProcessPermissions {
@@ -380,6 +486,9 @@ impl Process {
             cpu_affinity: CpuAffinity::ANY,
             rt_params: RtParams::default(),
             numa_node: None,
+            fs_base: 0,
+            tls_template: None,
+            robust_list: 0,
         })
      }
 }
@@ -409,6 +518,12 @@ impl CpuAffinity {
         (self.mask & (1u64 << cpu_id)) != 0
     }
     
+    /// Whether the affinity was narrowed from every CPU, which is what lets a process onto
+    /// an isolated CPU
+    pub fn is_restricted(&self) -> bool {
+        self.mask != u64::MAX
+    }
+    
     pub fn set_cpu(&mut self, cpu_id: u32, allowed: bool) {
         if allowed {
             self.mask |= 1u64 << cpu_id;
@@ -417,6 +532,11 @@ impl CpuAffinity {
         }
     }
     
+    /// Bitmask of the CPUs allowed
+    pub fn mask(&self) -> u64 {
+        self.mask
+    }
+    
     pub fn first_allowed_cpu(&self) -> Option<u32> {
         if self.mask == 0 {
             return None;
@@ -425,10 +545,88 @@ impl CpuAffinity {
     }
 }
 
+/// A process in a real-time queue, as the scheduler statistics show it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtQueueEntry {
+    pub pid: u64,
+    /// Absolute deadline, in microseconds
+    pub deadline_us: u64,
+    /// Budget left in the current period, the CBS server's for CBS processes
+    pub budget_us: u64,
+}
+
+/// One CPU's scheduler state at a moment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuSchedStats {
+    pub cpu_id: u32,
+    pub current: Option<u64>,
+    /// Ready queue lengths, indexed by `Priority`
+    pub queue_lengths: [usize; 4],
+    /// The EDF queue in order, then the CBS queue
+    pub edf_queue: Vec<RtQueueEntry>,
+    pub cbs_queue: Vec<RtQueueEntry>,
+    pub context_switches: u64,
+    pub load: u32,
+    pub isolated: bool,
+}
+
+impl CpuSchedStats {
+    /// Words in the record `to_words` makes
+    pub const WORDS: usize = 11;
+    
+    /// The statistics as `sys_sched_stats` hands them to user space: CPU ID, current process
+    /// or 0, load, context switches, the high, normal, low and gaming queue lengths, the EDF
+    /// and CBS queue depths, and 1 if the CPU is isolated
+    pub fn to_words(&self) -> [u64; Self::WORDS] {
+        let [high, normal, low, gaming] = self.queue_lengths.map(|length| length as u64);
+        [
+            self.cpu_id as u64,
+            self.current.unwrap_or(0),
+            self.load as u64,
+            self.context_switches,
+            high,
+            normal,
+            low,
+            gaming,
+            self.edf_queue.len() as u64,
+            self.cbs_queue.len() as u64,
+            self.isolated as u64,
+        ]
+    }
+}
+
+/// A process as `ps` lists it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessSchedStats {
+    pub pid: u64,
+    pub name: alloc::string::String,
+    pub state: ProcessState,
+    pub priority: Priority,
+    /// Microseconds spent running
+    pub cpu_time: u64,
+    pub affinity: CpuAffinity,
+}
+
+/// A process waiting in a ready queue, with what priority aging knows of it
+#[derive(Debug, Clone, Copy)]
+struct ReadyEntry {
+    pid: u64,
+    /// Uptime (ms) it last ran or moved up, once aging has seen it queued
+    waiting_since: Option<u64>,
+    /// Its own queue, when aged into a higher one
+    aged_from: Option<usize>,
+}
+
+impl ReadyEntry {
+    fn new(pid: u64) -> Self {
+        Self { pid, waiting_since: None, aged_from: None }
+    }
+}
+
 /// Per-CPU scheduler data
 pub struct CpuScheduler {
-    _cpu_id: u32,
-    ready_queues: [VecDeque<u64>; 4], // One queue per priority level
+    cpu_id: u32,
+    ready_queues: [VecDeque<ReadyEntry>; 4], // One queue per priority level
     rt_edf_queue: VecDeque<u64>,      // EDF real-time queue (sorted by deadline)
     rt_cbs_queue: VecDeque<u64>,      // CBS real-time queue
     current_process: Option<u64>,
@@ -437,16 +635,20 @@ pub struct CpuScheduler {
     idle_thread_pid: Option<u64>,
     load: AtomicU32, // Current load (number of ready processes)
     _last_balance_time: u64,
-    rt_isolated: bool, // Whether this CPU is isolated for RT tasks
+    rt_isolated: bool, // Whether this CPU only runs processes pinned to it
     numa_node: Option<NumaNode>, // NUMA node this CPU belongs to
     cbs_budget_tracker: alloc::collections::BTreeMap<u64, u64>, // Track CBS budget usage
     priority_inheritance_chains: alloc::collections::BTreeMap<u64, Vec<u64>>, // PI chains
+    context_switches: u64, // Times a pick replaced the running process with another
 }
 
+/// How long a Normal or Low process may wait in its ready queue before it moves up one
+pub const PRIORITY_AGING_MS: u64 = 100;
+
 impl CpuScheduler {
     pub fn new(cpu_id: u32) -> Self {
         Self {
-            _cpu_id: cpu_id,
+            cpu_id,
             ready_queues: [
                 VecDeque::new(), VecDeque::new(),
                 VecDeque::new(), VecDeque::new()
@@ -463,26 +665,27 @@ impl CpuScheduler {
             numa_node: None,
             cbs_budget_tracker: alloc::collections::BTreeMap::new(),
             priority_inheritance_chains: alloc::collections::BTreeMap::new(),
+            context_switches: 0,
         }
     }
     
     pub fn add_process(&mut self, pid: u64, priority: Priority) {
         let priority_idx = priority as usize;
-        self.ready_queues[priority_idx].push_back(pid);
+        self.ready_queues[priority_idx].push_back(ReadyEntry::new(pid));
         self.load.fetch_add(1, Ordering::Relaxed);
     }
     
     /// Add a real-time process to the appropriate RT queue
-    pub fn add_rt_process(&mut self, pid: u64, rt_class: RtClass, processes: &[Option<Process>]) {
+    pub fn add_rt_process(&mut self, pid: u64, rt_class: RtClass, processes: &[Option<SlabBox<Process>>]) {
         match rt_class {
             RtClass::Edf => {
                 // Insert in deadline order (EDF)
-                if let Some(process) = processes.get(pid as usize).and_then(|p| p.as_ref()) {
+                if let Some(process) = processes.get(pid as usize).and_then(|p| p.as_deref()) {
                     let deadline = process.rt_params.next_deadline;
                     let mut inserted = false;
                     
                     for (i, &existing_pid) in self.rt_edf_queue.iter().enumerate() {
-                        if let Some(existing_process) = processes.get(existing_pid as usize).and_then(|p| p.as_ref()) {
+                        if let Some(existing_process) = processes.get(existing_pid as usize).and_then(|p| p.as_deref()) {
                             if deadline < existing_process.rt_params.next_deadline {
                                 self.rt_edf_queue.insert(i, pid);
                                 inserted = true;
@@ -501,7 +704,7 @@ impl CpuScheduler {
             },
             RtClass::BestEffort => {
                 // Fall back to normal priority scheduling
-                if let Some(process) = processes.get(pid as usize).and_then(|p| p.as_ref()) {
+                if let Some(process) = processes.get(pid as usize).and_then(|p| p.as_deref()) {
                     self.add_process(pid, process.priority);
                 }
             }
@@ -514,7 +717,7 @@ impl CpuScheduler {
         
         // Remove from regular queues
         for queue in &mut self.ready_queues {
-            if let Some(pos) = queue.iter().position(|&p| p == pid) {
+            if let Some(pos) = queue.iter().position(|entry| entry.pid == pid) {
                 queue.remove(pos);
                 self.load.fetch_sub(1, Ordering::Relaxed);
                 found = true;
@@ -538,7 +741,45 @@ impl CpuScheduler {
         }
     }
     
-    pub fn schedule(&mut self, gaming_mode: bool, processes: &[Option<Process>]) -> Option<u64> {
+    /// Move Normal and Low processes that have waited `PRIORITY_AGING_MS` since they last ran
+    /// up one queue, so a busy higher queue cannot starve them. Real-time and gaming
+    /// processes are left alone. A process is only timed from when aging first sees it queued,
+    /// and what aging knows of it lives in its queue entry, so leaves the queues with it
+    pub fn age(&mut self, now_ms: u64) {
+        // High takes from Normal before Normal takes from Low, so nothing moves up twice at once
+        for to in [Priority::High as usize, Priority::Normal as usize] {
+            let from = to + 1;
+            let mut index = 0;
+            while let Some(entry) = self.ready_queues[from].get_mut(index) {
+                let since = *entry.waiting_since.get_or_insert(now_ms);
+                if Some(entry.pid) == self.current_process || now_ms.saturating_sub(since) < PRIORITY_AGING_MS {
+                    index += 1;
+                    continue;
+                }
+                let aged_from = entry.aged_from.or(Some(from));
+                let pid = entry.pid;
+                self.ready_queues[from].remove(index);
+                self.ready_queues[to].push_back(ReadyEntry { pid, waiting_since: Some(now_ms), aged_from });
+            }
+        }
+    }
+    
+    pub fn schedule(&mut self, gaming_mode: bool, processes: &[Option<SlabBox<Process>>]) -> Option<u64> {
+        self.schedule_at(crate::time::get_uptime_ms(), gaming_mode, processes)
+    }
+    
+    /// Pick the next process at uptime `now_ms`, the clock priority aging runs on
+    pub fn schedule_at(&mut self, now_ms: u64, gaming_mode: bool, processes: &[Option<SlabBox<Process>>]) -> Option<u64> {
+        let previous = self.current_process;
+        let next = self.pick_next(now_ms, gaming_mode, processes);
+        if next.is_some() && next != previous {
+            self.context_switches += 1;
+        }
+        next
+    }
+    
+    fn pick_next(&mut self, now_ms: u64, gaming_mode: bool, processes: &[Option<SlabBox<Process>>]) -> Option<u64> {
+        self.age(now_ms);
         let current_time = crate::time::get_precise_time_ns() / 1000; // Use precise TSC time in microseconds
         
         // 1. Real-time EDF scheduling (highest priority)
@@ -548,7 +789,7 @@ impl CpuScheduler {
         let mut edf_index = None;
         
         for (i, &pid) in self.rt_edf_queue.iter().enumerate() {
-            if let Some(process) = processes.get(pid as usize).and_then(|p| p.as_ref()) {
+            if let Some(process) = processes.get(pid as usize).and_then(|p| p.as_deref()) {
                 // Only schedule if process has remaining budget and hasn't missed deadline
                 if self.can_schedule_rt_process(processes, pid) && 
                    process.rt_params.next_deadline > current_time &&
@@ -566,7 +807,7 @@ impl CpuScheduler {
             self.current_process = Some(pid);
             // Calculate time slice based on remaining budget and deadline urgency
             let remaining_budget = processes.get(pid as usize)
-                .and_then(|p| p.as_ref())
+                .and_then(|p| p.as_deref())
                 .map(|p| p.rt_params.remaining_budget)
                 .unwrap_or(100);
             let time_to_deadline = earliest_deadline.saturating_sub(current_time);
@@ -586,7 +827,7 @@ impl CpuScheduler {
         // CBS processes get bandwidth-controlled execution
         let mut cbs_candidates = Vec::new();
         for (i, &pid) in self.rt_cbs_queue.iter().enumerate() {
-            if let Some(process) = processes.get(pid as usize).and_then(|p| p.as_ref()) {
+            if let Some(process) = processes.get(pid as usize).and_then(|p| p.as_deref()) {
                 // Check if CBS process has budget available
                 if process.rt_params.remaining_budget > 0 {
                     cbs_candidates.push((i, pid, process.rt_params.remaining_budget));
@@ -606,7 +847,7 @@ impl CpuScheduler {
         
         // 3. Gaming mode prioritization (if no RT tasks)
         if gaming_mode {
-            if let Some(pid) = self.ready_queues[Priority::Gaming as usize].pop_front() {
+            if let Some(ReadyEntry { pid, .. }) = self.ready_queues[Priority::Gaming as usize].pop_front() {
                 self.current_process = Some(pid);
                 self.current_time_slice_remaining = self.time_slice;
                 return Some(pid);
@@ -614,13 +855,14 @@ impl CpuScheduler {
         }
         
         // 4. Regular priority-based round-robin scheduling
-        for (_priority, queue) in self.ready_queues.iter_mut().enumerate() {
-            if let Some(pid) = queue.pop_front() {
-                // Re-add to end of queue for round-robin
-                queue.push_back(pid);
+        for priority in 0..self.ready_queues.len() {
+            if let Some(ReadyEntry { pid, aged_from, .. }) = self.ready_queues[priority].pop_front() {
+                // Re-add to end of queue for round-robin, back at its own priority if it was aged
+                let base = aged_from.unwrap_or(priority);
+                self.ready_queues[base].push_back(ReadyEntry { pid, waiting_since: Some(now_ms), aged_from: None });
                 self.current_process = Some(pid);
                 self.current_time_slice_remaining = self.time_slice;
-                self.load.fetch_sub(1, Ordering::Relaxed);
+                // Still queued, so the load is unchanged
                 return Some(pid);
             }
         }
@@ -636,7 +878,7 @@ impl CpuScheduler {
     }
     
     /// Update RT process deadlines and budgets
-    pub fn update_rt_timing(&mut self, processes: &mut [Option<Process>]) {
+    pub fn update_rt_timing(&mut self, processes: &mut [Option<SlabBox<Process>>]) {
         let current_time = crate::time::get_uptime_ms() * 1000; // Convert to microseconds
         
         // Update EDF processes
@@ -644,7 +886,7 @@ impl CpuScheduler {
         let mut budget_exhausted_pids = Vec::new();
         
         for &pid in &self.rt_edf_queue {
-            if let Some(process) = processes.get_mut(pid as usize).and_then(|p| p.as_mut()) {
+            if let Some(process) = processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()) {
                 // Check for deadline miss
                 if current_time > process.rt_params.next_deadline {
                     deadline_missed_pids.push(pid);
@@ -675,7 +917,7 @@ impl CpuScheduler {
         
         // Re-add deadline-missed processes (they get new deadlines)
         for pid in deadline_missed_pids {
-            if let Some(process) = processes.get(pid as usize).and_then(|p| p.as_ref()) {
+            if let Some(process) = processes.get(pid as usize).and_then(|p| p.as_deref()) {
                 self.add_rt_process(pid, process.rt_params.class, processes);
             }
         }
@@ -685,7 +927,7 @@ impl CpuScheduler {
         let mut cbs_exhausted_pids = Vec::new();
         
         for &pid in &self.rt_cbs_queue {
-            if let Some(process) = processes.get_mut(pid as usize).and_then(|p| p.as_mut()) {
+            if let Some(process) = processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()) {
                 // Replenish budget if period elapsed
                 if current_time >= process.rt_params.next_deadline {
                     cbs_replenish_pids.push(pid);
@@ -716,8 +958,8 @@ impl CpuScheduler {
     }
     
     /// Check if a process has remaining RT budget
-    pub fn has_rt_budget(&self, pid: u64, processes: &[Option<Process>]) -> bool {
-        if let Some(process) = processes.get(pid as usize).and_then(|p| p.as_ref()) {
+    pub fn has_rt_budget(&self, pid: u64, processes: &[Option<SlabBox<Process>>]) -> bool {
+        if let Some(process) = processes.get(pid as usize).and_then(|p| p.as_deref()) {
             process.rt_params.remaining_budget > 0
         } else {
             false
@@ -728,7 +970,50 @@ impl CpuScheduler {
         self.load.load(Ordering::Relaxed)
     }
     
-    pub fn tick_time_slice(&mut self, processes: &mut [Option<Process>]) -> bool {
+    /// Processes waiting to run on this CPU, across the priority and real-time queues
+    pub fn run_queue_length(&self) -> usize {
+        self.ready_queues.iter().map(VecDeque::len).sum::<usize>()
+            + self.rt_edf_queue.len()
+            + self.rt_cbs_queue.len()
+    }
+    
+    /// What this CPU is running and has queued, with the real-time queues' deadlines and budgets
+    pub fn stats(&self, processes: &[Option<SlabBox<Process>>]) -> CpuSchedStats {
+        let rt_entries = |queue: &VecDeque<u64>| -> Vec<RtQueueEntry> {
+            queue
+                .iter()
+                .map(|&pid| {
+                    let params = processes.get(pid as usize).and_then(|p| p.as_deref()).map(|process| process.rt_params).unwrap_or_default();
+                    let budget_us = params.cbs_params.map_or(params.remaining_budget, |cbs| cbs.remaining_budget);
+                    RtQueueEntry { pid, deadline_us: params.next_deadline, budget_us }
+                })
+                .collect()
+        };
+        CpuSchedStats {
+            cpu_id: self.cpu_id,
+            current: self.current_process,
+            queue_lengths: [0, 1, 2, 3].map(|priority| self.ready_queues[priority].len()),
+            edf_queue: rt_entries(&self.rt_edf_queue),
+            cbs_queue: rt_entries(&self.rt_cbs_queue),
+            context_switches: self.context_switches,
+            load: self.get_load(),
+            isolated: self.rt_isolated,
+        }
+    }
+    
+    /// Processes queued on this CPU or running on it, leaving out the idle thread
+    fn occupants(&self) -> impl Iterator<Item = u64> + '_ {
+        self.ready_queues
+            .iter()
+            .flatten()
+            .map(|entry| entry.pid)
+            .chain(self.rt_edf_queue.iter().copied())
+            .chain(self.rt_cbs_queue.iter().copied())
+            .chain(self.current_process)
+            .filter(move |&pid| Some(pid) != self.idle_thread_pid)
+    }
+    
+    pub fn tick_time_slice(&mut self, processes: &mut [Option<SlabBox<Process>>]) -> bool {
         if self.current_time_slice_remaining > 0 {
             self.current_time_slice_remaining -= 1;
             
@@ -752,10 +1037,10 @@ impl CpuScheduler {
     }
     
     /// Update real-time process deadlines and budget tracking
-    pub fn update_rt_deadlines(&mut self, processes: &mut [Option<Process>], current_time_us: u64) {
+    pub fn update_rt_deadlines(&mut self, processes: &mut [Option<SlabBox<Process>>], current_time_us: u64) {
         // Update EDF queue deadlines
         for &pid in &self.rt_edf_queue {
-            if let Some(process) = processes.get_mut(pid as usize).and_then(|p| p.as_mut()) {
+            if let Some(process) = processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()) {
                 if current_time_us >= process.rt_params.next_deadline {
                     // Deadline missed, update to next period
                     process.rt_params.next_deadline += process.rt_params.period_us;
@@ -767,7 +1052,7 @@ impl CpuScheduler {
         
         // Update CBS queue budgets with precise tracking
         for &pid in &self.rt_cbs_queue {
-            if let Some(process) = processes.get_mut(pid as usize).and_then(|p| p.as_mut()) {
+            if let Some(process) = processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()) {
                 // Check if budget replenishment is needed
                 if current_time_us >= process.rt_params.next_deadline {
                     // Replenish budget for next period
@@ -788,11 +1073,11 @@ impl CpuScheduler {
         // Re-sort EDF queue by deadline
         self.rt_edf_queue.make_contiguous().sort_by(|&a, &b| {
             let deadline_a = processes.get(a as usize)
-                .and_then(|p| p.as_ref())
+                .and_then(|p| p.as_deref())
                 .map(|p| p.rt_params.next_deadline)
                 .unwrap_or(u64::MAX);
             let deadline_b = processes.get(b as usize)
-                .and_then(|p| p.as_ref())
+                .and_then(|p| p.as_deref())
                 .map(|p| p.rt_params.next_deadline)
                 .unwrap_or(u64::MAX);
             deadline_a.cmp(&deadline_b)
@@ -800,8 +1085,8 @@ impl CpuScheduler {
     }
     
     /// Consume budget for a running real-time process
-    pub fn consume_rt_budget(&mut self, processes: &mut [Option<Process>], pid: u64, consumed_us: u64) {
-        if let Some(process) = processes.get_mut(pid as usize).and_then(|p| p.as_mut()) {
+    pub fn consume_rt_budget(&mut self, processes: &mut [Option<SlabBox<Process>>], pid: u64, consumed_us: u64) {
+        if let Some(process) = processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()) {
             if process.rt_params.remaining_budget >= consumed_us {
                 process.rt_params.remaining_budget -= consumed_us;
             } else {
@@ -815,8 +1100,8 @@ impl CpuScheduler {
     }
     
     /// Check if a real-time process can be scheduled (has budget)
-    pub fn can_schedule_rt_process(&self, processes: &[Option<Process>], pid: u64) -> bool {
-        if let Some(process) = processes.get(pid as usize).and_then(|p| p.as_ref()) {
+    pub fn can_schedule_rt_process(&self, processes: &[Option<SlabBox<Process>>], pid: u64) -> bool {
+        if let Some(process) = processes.get(pid as usize).and_then(|p| p.as_deref()) {
             process.rt_params.remaining_budget > 0 && 
             process.state == ProcessState::Ready
         } else {
@@ -838,11 +1123,13 @@ impl CpuScheduler {
     }
     
     pub fn yield_current(&mut self) {
-        if let Some(pid) = self.current_process {
-            // Add current process back to ready queue
-            // We'll assume it was running at priority 1 (normal)
-            self.ready_queues[1].push_back(pid);
-            self.current_process = None;
+        if let Some(pid) = self.current_process.take() {
+            // A pick leaves the process queued for its next turn. One that was taken off the
+            // queues meanwhile goes back; we'll assume it was running at priority 1 (normal)
+            let queued = self.ready_queue_of(pid).is_some() || [&self.rt_edf_queue, &self.rt_cbs_queue].iter().any(|queue| queue.contains(&pid));
+            if !queued && Some(pid) != self.idle_thread_pid {
+                self.ready_queues[1].push_back(ReadyEntry::new(pid));
+            }
         }
     }
     
@@ -850,23 +1137,23 @@ impl CpuScheduler {
         if let Some(pid) = self.current_process {
             // Remove from ready queues
             for queue in &mut self.ready_queues {
-                queue.retain(|&p| p != pid);
+                queue.retain(|entry| entry.pid != pid);
             }
             self.current_process = None;
         }
     }
 
     // Priority inheritance methods
-    pub fn inherit_priority(&mut self, pid: u64, from_pid: u64, processes: &mut [Option<Process>]) {
+    pub fn inherit_priority(&mut self, pid: u64, from_pid: u64, processes: &mut [Option<SlabBox<Process>>]) {
         // First, get the priority from the source process
-        let from_priority = if let Some(from_process) = processes.get(from_pid as usize).and_then(|p| p.as_ref()) {
+        let from_priority = if let Some(from_process) = processes.get(from_pid as usize).and_then(|p| p.as_deref()) {
             from_process.priority
         } else {
             return;
         };
         
         // Then modify the target process
-        if let Some(process) = processes.get_mut(pid as usize).and_then(|p| p.as_mut()) {
+        if let Some(process) = processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()) {
             match process.rt_params.priority_inheritance {
                 PriorityInheritanceState::None => {
                     process.rt_params.priority_inheritance = PriorityInheritanceState::Inherited {
@@ -877,17 +1164,19 @@ impl CpuScheduler {
                     
                     // Track inheritance chain
                     self.priority_inheritance_chains.entry(from_pid).or_insert_with(Vec::new).push(pid);
+                    self.requeue(pid, from_priority);
                 }
                 _ => {} // Already inheriting, don't override
             }
         }
     }
 
-    pub fn restore_priority(&mut self, pid: u64, processes: &mut [Option<Process>]) {
-        if let Some(process) = processes.get_mut(pid as usize).and_then(|p| p.as_mut()) {
+    pub fn restore_priority(&mut self, pid: u64, processes: &mut [Option<SlabBox<Process>>]) {
+        if let Some(process) = processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()) {
             if let PriorityInheritanceState::Inherited { original_priority, inherited_from } = process.rt_params.priority_inheritance {
                 process.priority = original_priority;
                 process.rt_params.priority_inheritance = PriorityInheritanceState::None;
+                self.requeue(pid, original_priority);
                 
                 // Remove from inheritance chain
                 if let Some(chain) = self.priority_inheritance_chains.get_mut(&inherited_from) {
@@ -900,9 +1189,30 @@ impl CpuScheduler {
         }
     }
 
+    /// Move `pid` to the ready queue of `priority` if it waits in another. Any aging is
+    /// dropped, as `priority` is its own from now on
+    fn requeue(&mut self, pid: u64, priority: Priority) {
+        let Some((queue, index)) = self.ready_queue_of(pid) else {
+            return;
+        };
+        if queue == priority as usize {
+            self.ready_queues[queue][index].aged_from = None;
+        } else if let Some(entry) = self.ready_queues[queue].remove(index) {
+            self.ready_queues[priority as usize].push_back(ReadyEntry { aged_from: None, ..entry });
+        }
+    }
+
+    /// The ready queue `pid` waits in, and where in it
+    fn ready_queue_of(&self, pid: u64) -> Option<(usize, usize)> {
+        self.ready_queues
+            .iter()
+            .enumerate()
+            .find_map(|(queue, entries)| entries.iter().position(|entry| entry.pid == pid).map(|index| (queue, index)))
+    }
+
     // CBS throttling methods
-    pub fn update_cbs_budget(&mut self, pid: u64, consumed_us: u64, processes: &mut [Option<Process>]) {
-        if let Some(process) = processes.get_mut(pid as usize).and_then(|p| p.as_mut()) {
+    pub fn update_cbs_budget(&mut self, pid: u64, consumed_us: u64, processes: &mut [Option<SlabBox<Process>>]) {
+        if let Some(process) = processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()) {
             if let Some(ref mut cbs_params) = process.rt_params.cbs_params {
                 if cbs_params.remaining_budget >= consumed_us {
                     cbs_params.remaining_budget -= consumed_us;
@@ -917,7 +1227,7 @@ impl CpuScheduler {
         }
     }
 
-    pub fn replenish_cbs_budget(&mut self, current_time_us: u64, processes: &mut [Option<Process>]) {
+    pub fn replenish_cbs_budget(&mut self, current_time_us: u64, processes: &mut [Option<SlabBox<Process>>]) {
         for process_opt in processes.iter_mut() {
             if let Some(process) = process_opt {
                 if let Some(ref mut cbs_params) = process.rt_params.cbs_params {
@@ -951,15 +1261,54 @@ impl CpuScheduler {
 /// Global SMP-aware scheduler
 pub struct SmpScheduler {
     cpu_schedulers: Vec<Mutex<CpuScheduler>>,
-    processes: Vec<Option<Process>>,
+    processes: Vec<Option<SlabBox<Process>>>,
     gaming_mode: bool,
     num_cpus: u32,
     _current_cpu: AtomicU32,
+    /// Fixed CPU layout to place processes by instead of the one detected from CPUID
+    topology: Option<CpuTopology>,
+    /// Milliseconds between load balancing rounds, marked due from the timer tick
+    balance_interval_ms: u64,
+    /// Most processes one balancing round moves
+    max_migrations_per_balance: usize,
+    /// Uptime (ms) the next balancing round is due
+    next_balance_ms: u64,
+}
+
+/// Default milliseconds between load balancing rounds
+pub const LOAD_BALANCE_INTERVAL_MS: u64 = 100;
+
+/// Default cap on the processes one load balancing round moves, so a burst of work does not
+/// bounce between CPUs
+pub const MAX_MIGRATIONS_PER_BALANCE: usize = 4;
+
+/// How much busier one CPU has to be than another before work leaves its NUMA node
+const REMOTE_NUMA_IMBALANCE: u32 = 4;
+
+/// What runs on the SMT siblings of a CPU
+#[derive(Debug, Default, Clone, Copy)]
+struct SiblingWork {
+    /// Siblings with any process queued or running
+    busy: u32,
+    /// Siblings with a latency-sensitive process
+    latency_sensitive: u32,
+    /// Siblings with a gaming process, whose core is meant to be its own
+    gaming: u32,
 }
 
 impl SmpScheduler {
     pub fn new() -> Self {
-        let num_cpus = get_cpu_count();
+        Self::with_cpus(get_cpu_count(), None)
+    }
+    
+    /// A scheduler for the CPUs of `topology`, placing processes by it rather than by the
+    /// detected layout
+    pub fn with_topology(topology: CpuTopology) -> Self {
+        let num_cpus = topology.threads().map(|thread| thread.cpu + 1).max().unwrap_or(1);
+        Self::with_cpus(num_cpus, Some(topology))
+    }
+    
+    fn with_cpus(num_cpus: u32, topology: Option<CpuTopology>) -> Self {
         let mut cpu_schedulers = Vec::with_capacity(num_cpus as usize);
         
         for cpu_id in 0..num_cpus {
@@ -972,6 +1321,26 @@ impl SmpScheduler {
             gaming_mode: false,
             num_cpus,
             _current_cpu: AtomicU32::new(0),
+            topology,
+            balance_interval_ms: LOAD_BALANCE_INTERVAL_MS,
+            max_migrations_per_balance: MAX_MIGRATIONS_PER_BALANCE,
+            next_balance_ms: LOAD_BALANCE_INTERVAL_MS,
+        }
+    }
+    
+    /// The SMT siblings of `cpu_id` as a CPU mask, in the fixed layout or the detected one
+    fn sibling_mask(&self, cpu_id: u32) -> u64 {
+        match &self.topology {
+            Some(topology) => topology.smt_sibling_mask(cpu_id),
+            None => crate::arch::topology::smt_sibling_mask(cpu_id),
+        }
+    }
+    
+    /// The lowest cache level two CPUs share in the fixed layout or the detected one
+    fn shared_cache_level(&self, cpu_a: u32, cpu_b: u32) -> Option<u8> {
+        match &self.topology {
+            Some(topology) => topology.shared_cache_level(cpu_a, cpu_b),
+            None => crate::arch::topology::shared_cache_level(cpu_a, cpu_b),
         }
     }
     
@@ -990,18 +1359,19 @@ impl SmpScheduler {
         
         // Find the least loaded CPU that can run this process
         let target_cpu = self.find_best_cpu_for_process(&process);
+        let priority = process.priority;
         
-        self.processes[pid as usize] = Some(process.clone());
+        self.processes[pid as usize] = Some(SlabBox::new(&PROCESS_CACHE, process));
         
         if let Some(cpu_id) = target_cpu {
-            self.cpu_schedulers[cpu_id as usize].lock().add_process(pid, process.priority);
+            self.cpu_schedulers[cpu_id as usize].lock().add_process(pid, priority);
         }
         
         pid
     }
     
     pub fn remove_process(&mut self, pid: u64) {
-        if let Some(process) = self.processes.get_mut(pid as usize).and_then(|p| p.as_mut()) {
+        if let Some(process) = self.processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()) {
             process.state = ProcessState::Terminated;
             
             // Remove from all CPU schedulers
@@ -1019,49 +1389,174 @@ impl SmpScheduler {
         self.cpu_schedulers[cpu_id as usize].lock().schedule(self.gaming_mode, &self.processes)
     }
     
+    /// What the SMT siblings of `cpu_id` are running, not counting `skip`
+    fn sibling_work(&self, cpu_id: u32, skip: Option<u64>) -> SiblingWork {
+        let mut work = SiblingWork::default();
+        let mut siblings = self.sibling_mask(cpu_id);
+        while siblings != 0 {
+            let sibling = siblings.trailing_zeros();
+            siblings &= siblings - 1;
+            let Some(scheduler) = self.cpu_schedulers.get(sibling as usize) else {
+                continue;
+            };
+            let scheduler = scheduler.lock();
+            let occupants = scheduler
+                .occupants()
+                .filter(|&occupant| Some(occupant) != skip)
+                .filter_map(|occupant| self.processes.get(occupant as usize).and_then(|p| p.as_deref()));
+            let (mut busy, mut latency_sensitive, mut gaming) = (false, false, false);
+            for process in occupants {
+                busy = true;
+                latency_sensitive |= process.is_latency_sensitive();
+                gaming |= process.priority == Priority::Gaming;
+            }
+            work.busy += busy as u32;
+            work.latency_sensitive += latency_sensitive as u32;
+            work.gaming += gaming as u32;
+        }
+        work
+    }
+    
+    /// Whether `process` may be placed on `cpu_id`: its affinity allows it, and if the CPU is
+    /// isolated the process was pinned to a set of CPUs that includes it
+    fn may_run_on(&self, process: &Process, cpu_id: u32) -> bool {
+        process.cpu_affinity.can_run_on(cpu_id) && (process.cpu_affinity.is_restricted() || !self.is_rt_isolated(cpu_id))
+    }
+    
+    /// The CPU the process should run on, never an isolated one it is not pinned to. A gaming process takes the least loaded CPU of a
+    /// core with nothing on its siblings, so it has the physical core to itself. Other
+    /// latency-sensitive processes keep off cores running another one, so two of them do
+    /// not share execution units while an idle core is free. Everything else goes to the
+    /// least loaded CPU, keeping off gaming cores when there is room elsewhere
     pub fn find_best_cpu_for_process(&self, process: &Process) -> Option<u32> {
-        let mut best_cpu = None;
-        let mut min_load = u32::MAX;
+        let gaming = process.priority == Priority::Gaming;
+        let latency_sensitive = process.is_latency_sensitive();
         
-        for cpu_id in 0..self.num_cpus {
-            if process.cpu_affinity.can_run_on(cpu_id) {
+        (0..self.num_cpus)
+            .filter(|&cpu_id| self.may_run_on(process, cpu_id))
+            .min_by_key(|&cpu_id| {
+                let siblings = self.sibling_work(cpu_id, Some(process.pid));
                 let load = self.cpu_schedulers[cpu_id as usize].lock().get_load();
-                if load < min_load {
-                    min_load = load;
-                    best_cpu = Some(cpu_id);
+                if gaming {
+                    (siblings.busy, 0, load, 0)
+                } else if latency_sensitive {
+                    (siblings.gaming, siblings.latency_sensitive, load, siblings.busy)
+                } else {
+                    (siblings.gaming, 0, load, 0)
                 }
-            }
+            })
+    }
+    
+    /// Where to move work from `from_cpu` out of `(cpu_id, load)` candidates: a CPU more than
+    /// two processes lighter and not sharing a core with a gaming process, preferring the one
+    /// sharing the closest cache with `from_cpu` so the migrated process finds its working
+    /// set warm, then the least loaded
+    fn migration_target(&self, from_cpu: u32, from_load: u32, candidates: &[(u32, u32)]) -> Option<u32> {
+        candidates
+            .iter()
+            .filter(|&&(cpu_id, load)| cpu_id != from_cpu && from_load > load + 2)
+            .min_by_key(|&&(cpu_id, load)| {
+                let gaming = self.sibling_work(cpu_id, None).gaming;
+                (gaming, self.shared_cache_level(from_cpu, cpu_id).unwrap_or(u8::MAX), load)
+            })
+            .map(|&(cpu_id, _)| cpu_id)
+    }
+    
+    /// Every CPU's scheduler state, indexed by CPU ID. All CPUs are locked together so the
+    /// snapshot is of a single moment
+    pub fn sched_stats(&self) -> Vec<CpuSchedStats> {
+        let cpu_schedulers: Vec<_> = self.cpu_schedulers.iter().map(|cpu_scheduler| cpu_scheduler.lock()).collect();
+        cpu_schedulers.iter().map(|cpu_scheduler| cpu_scheduler.stats(&self.processes)).collect()
+    }
+    
+    /// Every process in the table, by PID
+    pub fn process_stats(&self) -> Vec<ProcessSchedStats> {
+        self.processes
+            .iter()
+            .flatten()
+            .map(|process| ProcessSchedStats {
+                pid: process.pid,
+                name: process.name.clone(),
+                state: process.state,
+                priority: process.priority,
+                cpu_time: process.cpu_time,
+                affinity: process.cpu_affinity,
+            })
+            .collect()
+    }
+    
+    /// Run queue length of each CPU, indexed by CPU ID
+    pub fn run_queue_lengths(&self) -> Vec<usize> {
+        self.cpu_schedulers
+            .iter()
+            .map(|cpu_scheduler| cpu_scheduler.lock().run_queue_length())
+            .collect()
+    }
+    
+    /// Processes ready to run or running on any CPU, not counting the idle thread: the
+    /// run-queue occupancy the load averages are sampled from. The timer interrupt samples
+    /// it, so each process is counted once by looking it up in the queues, without allocating
+    pub fn runnable_count(&self) -> usize {
+        self.processes
+            .iter()
+            .filter_map(|p| p.as_deref())
+            .filter(|process| matches!(process.state, ProcessState::Ready | ProcessState::Running))
+            .filter(|process| {
+                self.cpu_schedulers
+                    .iter()
+                    .any(|cpu_scheduler| cpu_scheduler.lock().occupants().any(|pid| pid == process.pid))
+            })
+            .count()
+    }
+    
+    /// Run a balancing round every `interval_ms` from the timer tick, moving at most
+    /// `max_migrations` processes each round
+    pub fn set_balance_policy(&mut self, interval_ms: u64, max_migrations: usize) {
+        self.balance_interval_ms = interval_ms.max(1);
+        self.max_migrations_per_balance = max_migrations;
+    }
+    
+    /// Whether a balancing round is due at uptime `now_ms`, starting the next interval if so
+    fn balance_due(&mut self, now_ms: u64) -> bool {
+        if now_ms < self.next_balance_ms {
+            return false;
         }
-        
-        best_cpu
+        self.next_balance_ms = now_ms + self.balance_interval_ms;
+        true
     }
     
-    pub fn balance_load(&mut self) {
-        // Simple load balancing: move processes from heavily loaded CPUs to lightly loaded ones
-        let mut loads: Vec<(u32, u32)> = Vec::new(); // (cpu_id, load)
-        
-        for cpu_id in 0..self.num_cpus {
-            let load = self.cpu_schedulers[cpu_id as usize].lock().get_load();
-            loads.push((cpu_id, load));
+    /// Run a balancing round if one is due at uptime `now_ms`, returning the processes moved
+    pub fn balance_tick(&mut self, now_ms: u64) -> usize {
+        if !self.balance_due(now_ms) {
+            return 0;
         }
-        
-        loads.sort_by_key(|&(_, load)| load);
-        
-        // If the difference between max and min load is significant, balance
-        if loads.len() >= 2 {
-            let min_load = loads[0].1;
-            let max_load = loads[loads.len() - 1].1;
-            
-            if max_load > min_load + 2 {
-                // Move one process from the most loaded CPU to the least loaded CPU
-                let _src_cpu = loads[loads.len() - 1].0;
-                let _dst_cpu = loads[0].0;
-                
-                // This is a simplified implementation - in practice, we'd need more
-                // sophisticated logic to migrate processes safely
-                // For now, we just note that load balancing is needed
+        self.balance_load()
+    }
+    
+    /// One balancing round: move ready processes from the most loaded CPU to lightly loaded
+    /// ones, cache-local first, until no CPU is more than two processes busier than another
+    /// or the round's migrations are used up. Isolated CPUs are left out either way. Returns
+    /// the processes moved
+    pub fn balance_load(&mut self) -> usize {
+        let mut migrations = 0;
+        while migrations < self.max_migrations_per_balance {
+            let loads: Vec<(u32, u32)> = (0..self.num_cpus)
+                .filter(|&cpu_id| !self.is_rt_isolated(cpu_id))
+                .map(|cpu_id| (cpu_id, self.cpu_schedulers[cpu_id as usize].lock().get_load()))
+                .collect();
+            let Some(&(src_cpu, max_load)) = loads.iter().max_by_key(|&&(_, load)| load) else {
+                break;
+            };
+            let Some(dst_cpu) = self.migration_target(src_cpu, max_load, &loads) else {
+                break;
+            };
+            let dst_load = loads.iter().find(|&&(cpu_id, _)| cpu_id == dst_cpu).map_or(0, |&(_, load)| load);
+            if !self.migrate_process_between_cpus(src_cpu, dst_cpu, max_load > dst_load + REMOTE_NUMA_IMBALANCE) {
+                break;
             }
+            migrations += 1;
         }
+        migrations
     }
     
     pub fn set_gaming_mode(&mut self, enabled: bool) {
@@ -1079,11 +1574,11 @@ impl SmpScheduler {
         let scheduler = self.cpu_schedulers[cpu_id as usize].lock();
         scheduler.current_process
             .and_then(|pid| self.processes.get(pid as usize))
-            .and_then(|p| p.as_ref())
+            .and_then(|p| p.as_deref())
     }
     
     pub fn set_process_affinity(&mut self, pid: u64, affinity: CpuAffinity) -> bool {
-        if let Some(process) = self.processes.get_mut(pid as usize).and_then(|p| p.as_mut()) {
+        if let Some(process) = self.processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()) {
             let _old_affinity = process.cpu_affinity;
             process.cpu_affinity = affinity;
             
@@ -1153,7 +1648,7 @@ impl SmpScheduler {
             
             // Check EDF queue for earliest deadline (only processes with budget)
             for &pid in &scheduler.rt_edf_queue {
-                if let Some(process) = self.processes.get(pid as usize).and_then(|p| p.as_ref()) {
+                if let Some(process) = self.processes.get(pid as usize).and_then(|p| p.as_deref()) {
                     // Only consider processes with remaining budget
                     if process.rt_params.remaining_budget > 0 && 
                        process.rt_params.next_deadline < earliest_deadline {
@@ -1181,12 +1676,6 @@ impl SmpScheduler {
         }
     }
     
-    /// Check if a CPU core should be isolated for RT threads
-    pub fn is_rt_core(&self, cpu_id: u8) -> bool {
-        // Cores 2 and 3 are reserved for RT threads (input, audio, compositor)
-        cpu_id >= 2 && cpu_id <= 3
-    }
-    
     /// Get the preferred CPU core for an RT thread type
     pub fn get_rt_cpu_affinity(&self, rt_class: RtClass) -> Option<u8> {
         match rt_class {
@@ -1234,51 +1723,13 @@ impl SmpScheduler {
         }
     }
     
-    /// Enforce RT core isolation by moving non-RT processes away from RT cores
-    pub fn enforce_rt_core_isolation(&mut self) {
-        for (cpu_id, cpu_scheduler) in self.cpu_schedulers.iter().enumerate() {
-            if self.is_rt_core(cpu_id as u8) {
-                let mut scheduler = cpu_scheduler.lock();
-                let mut non_rt_processes = Vec::new();
-                
-                // Find non-RT processes on RT cores
-                for priority_queue in &mut scheduler.ready_queues {
-                    while let Some(pid) = priority_queue.pop_front() {
-                        if let Some(process) = self.processes.get(pid as usize).and_then(|p| p.as_ref()) {
-                            if matches!(process.rt_params.class, RtClass::BestEffort) {
-                                non_rt_processes.push(pid);
-                            } else {
-                                priority_queue.push_back(pid); // Keep RT processes
-                            }
-                        }
-                    }
-                }
-                
-                drop(scheduler);
-                
-                // Migrate non-RT processes to non-RT cores
-                for pid in non_rt_processes {
-                    // Find a non-RT core with least load
-                    let mut target_cpu = 0;
-                    let mut min_load = u32::MAX;
-                    
-                    for (other_cpu_id, other_scheduler) in self.cpu_schedulers.iter().enumerate() {
-                        if !self.is_rt_core(other_cpu_id as u8) {
-                            let load = other_scheduler.lock().get_load();
-                            if load < min_load {
-                                min_load = load;
-                                target_cpu = other_cpu_id;
-                            }
-                        }
-                    }
-                    
-                    // Add to target CPU's ready queue
-                    if let Some(process) = self.processes.get(pid as usize).and_then(|p| p.as_ref()) {
-                        let priority = process.priority as usize;
-                        if priority < self.cpu_schedulers[target_cpu].lock().ready_queues.len() {
-                            self.cpu_schedulers[target_cpu].lock().ready_queues[priority].push_back(pid);
-                        }
-                    }
+    /// Take a ready process off the CPUs until `unblock_process` wakes it
+    pub fn block_process(&mut self, pid: u64) {
+        if let Some(process) = self.processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()) {
+            if process.state == ProcessState::Ready {
+                process.state = ProcessState::Blocked;
+                for cpu_scheduler in &self.cpu_schedulers {
+                    cpu_scheduler.lock().remove_process(pid);
                 }
             }
         }
@@ -1286,7 +1737,7 @@ impl SmpScheduler {
 
     pub fn unblock_process(&mut self, pid: u64) {
         // First, check if the process exists and is blocked, and get its priority
-        let (should_unblock, priority) = if let Some(process) = self.processes.get_mut(pid as usize).and_then(|p| p.as_mut()) {
+        let (should_unblock, priority) = if let Some(process) = self.processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()) {
             if process.state == ProcessState::Blocked {
                 process.state = ProcessState::Ready;
                 (true, process.priority)
@@ -1299,7 +1750,7 @@ impl SmpScheduler {
         
         if should_unblock {
             // Now find the best CPU for this process (without borrowing self.processes)
-            if let Some(process) = self.processes.get(pid as usize).and_then(|p| p.as_ref()) {
+            if let Some(process) = self.processes.get(pid as usize).and_then(|p| p.as_deref()) {
                 if let Some(cpu_id) = self.find_best_cpu_for_process(process) {
                     self.cpu_schedulers[cpu_id as usize].lock().add_process(pid, priority);
                 }
@@ -1316,7 +1767,7 @@ impl SmpScheduler {
     
     /// Add a real-time process to the best available CPU
     pub fn add_rt_process(&mut self, pid: u64, rt_class: RtClass) {
-        if let Some(process) = self.processes.get(pid as usize).and_then(|p| p.as_ref()) {
+        if let Some(process) = self.processes.get(pid as usize).and_then(|p| p.as_deref()) {
             let best_cpu = self.find_best_cpu_for_process(process).unwrap_or(0);
             
             if let Some(cpu_scheduler) = self.cpu_schedulers.get(best_cpu as usize) {
@@ -1332,14 +1783,51 @@ impl SmpScheduler {
         }
     }
     
-    /// Set CPU isolation for real-time tasks
-    pub fn set_rt_isolation(&mut self, cpu_id: u32, isolated: bool) {
-        if let Some(cpu_scheduler) = self.cpu_schedulers.get(cpu_id as usize) {
-            cpu_scheduler.lock().rt_isolated = isolated;
+    /// Take `cpu_id` out of the general pool, or return it. An isolated CPU only runs
+    /// processes whose affinity was narrowed to include it: placement and load balancing
+    /// pass it over, and whatever else is queued there moves off when it is isolated. It
+    /// keeps taking its own timer interrupts and IPIs. At least one CPU stays in the pool
+    pub fn isolate_cpu(&mut self, cpu_id: u32, isolated: bool) -> Result<(), &'static str> {
+        let cpu_scheduler = self.cpu_schedulers.get(cpu_id as usize).ok_or("No such CPU")?;
+        if isolated && (0..self.num_cpus).all(|other| other == cpu_id || self.is_rt_isolated(other)) {
+            return Err("Cannot isolate every CPU");
+        }
+        cpu_scheduler.lock().rt_isolated = isolated;
+        if isolated {
+            self.evict_unpinned(cpu_id);
+        }
+        Ok(())
+    }
+    
+    /// Move the processes queued on isolated `cpu_id` that are not pinned to it to the best
+    /// CPU in the general pool
+    fn evict_unpinned(&mut self, cpu_id: u32) {
+        let evicted: Vec<u64> = self.cpu_schedulers[cpu_id as usize]
+            .lock()
+            .occupants()
+            .filter(|&pid| {
+                self.processes
+                    .get(pid as usize)
+                    .and_then(|p| p.as_deref())
+                    .is_some_and(|process| !process.cpu_affinity.is_restricted() || !process.cpu_affinity.can_run_on(cpu_id))
+            })
+            .collect();
+        
+        for pid in evicted {
+            self.cpu_schedulers[cpu_id as usize].lock().remove_process(pid);
+            let Some(process) = self.processes.get(pid as usize).and_then(|p| p.as_deref()) else {
+                continue;
+            };
+            let rt_class = process.rt_params.class;
+            if rt_class != RtClass::BestEffort {
+                self.add_rt_process(pid, rt_class);
+            } else if let Some(target) = self.find_best_cpu_for_process(process) {
+                self.cpu_schedulers[target as usize].lock().add_process(pid, process.priority);
+            }
         }
     }
     
-    /// Get RT isolation status for a CPU
+    /// Whether `cpu_id` is isolated from the general pool
     pub fn is_rt_isolated(&self, cpu_id: u32) -> bool {
         if let Some(cpu_scheduler) = self.cpu_schedulers.get(cpu_id as usize) {
             cpu_scheduler.lock().rt_isolated
@@ -1388,28 +1876,16 @@ impl SmpScheduler {
             return;
         }
 
-        // Find the most and least loaded CPUs within this NUMA node
-        let mut max_load = 0;
-        let mut min_load = u32::MAX;
-        let mut max_cpu = 0;
-        let mut min_cpu = 0;
+        let loads: Vec<(u32, u32)> = cpu_list.iter()
+            .map(|&cpu_id| (cpu_id as u32, self.cpu_schedulers[cpu_id].lock().get_load()))
+            .collect();
 
-        for &cpu_id in cpu_list {
-            let load = self.cpu_schedulers[cpu_id].lock().get_load();
-            if load > max_load {
-                max_load = load;
-                max_cpu = cpu_id;
-            }
-            if load < min_load {
-                min_load = load;
-                min_cpu = cpu_id;
+        // Migrate from the most loaded CPU if the imbalance is significant
+        if let Some(&(max_cpu, max_load)) = loads.iter().max_by_key(|&&(_, load)| load) {
+            if let Some(min_cpu) = self.migration_target(max_cpu, max_load, &loads) {
+                self.migrate_process_between_cpus(max_cpu, min_cpu, false);
             }
         }
-
-        // Migrate processes if load imbalance is significant
-        if max_load > min_load + 2 {
-            self.migrate_process_between_cpus(max_cpu as u32, min_cpu as u32);
-        }
     }
 
     fn balance_load_across_numa_nodes(&mut self, numa_groups: &alloc::collections::BTreeMap<u8, Vec<usize>>) {
@@ -1432,83 +1908,99 @@ impl SmpScheduler {
             let (low_numa, low_load) = numa_loads[0];
             let (high_numa, high_load) = numa_loads[numa_loads.len() - 1];
             
-            if high_load > low_load + 4 {
+            if high_load > low_load + REMOTE_NUMA_IMBALANCE {
                 // Find representative CPUs from each NUMA node
                 if let (Some(low_cpus), Some(high_cpus)) = (numa_groups.get(&low_numa), numa_groups.get(&high_numa)) {
                     if let (Some(&low_cpu), Some(&high_cpu)) = (low_cpus.first(), high_cpus.first()) {
-                        self.migrate_process_between_cpus(high_cpu as u32, low_cpu as u32);
+                        self.migrate_process_between_cpus(high_cpu as u32, low_cpu as u32, true);
                     }
                 }
             }
         }
     }
 
-    fn migrate_process_between_cpus(&mut self, from_cpu: u32, to_cpu: u32) {
-        // Find a suitable process to migrate
-        let mut process_to_migrate: Option<u64> = None;
+    /// Move one ready process queued on `from_cpu` to `to_cpu`, returning whether one moved.
+    /// The process running on `from_cpu`, real-time and gaming processes, and processes
+    /// whose affinity keeps them off `to_cpu` stay. Processes local to `to_cpu`'s NUMA node
+    /// go first, and others only if `allow_remote`
+    fn migrate_process_between_cpus(&mut self, from_cpu: u32, to_cpu: u32, allow_remote: bool) -> bool {
+        if from_cpu == to_cpu {
+            return false;
+        }
         
-        {
+        // Look for a process in the lowest priority queue; gaming processes keep their core
+        let queued: Vec<u64> = {
             let from_scheduler = self.cpu_schedulers[from_cpu as usize].lock();
-            
-            // Look for a non-RT process in the lowest priority queue
-            for priority in (0..4).rev() {
-                if let Some(&pid) = from_scheduler.ready_queues[priority].front() {
-                    // Check if process can run on target CPU
-                    if let Some(process) = self.processes.get(pid as usize).and_then(|p| p.as_ref()) {
-                        if process.cpu_affinity.can_run_on(to_cpu) {
-                            process_to_migrate = Some(pid);
-                            break;
-                        }
-                    }
-                }
-            }
-        }
+            [Priority::Low, Priority::Normal, Priority::High]
+                .iter()
+                .flat_map(|&priority| from_scheduler.ready_queues[priority as usize].iter().map(|entry| entry.pid))
+                .filter(|&pid| Some(pid) != from_scheduler.current_process)
+                .collect()
+        };
+        let candidates: Vec<&Process> = queued
+            .iter()
+            .filter_map(|&pid| self.processes.get(pid as usize).and_then(|p| p.as_deref()))
+            .filter(|process| process.state == ProcessState::Ready && self.may_run_on(process, to_cpu))
+            .collect();
+        let process_to_migrate = {
+            let to_scheduler = self.cpu_schedulers[to_cpu as usize].lock();
+            candidates
+                .iter()
+                .find(|process| to_scheduler.is_numa_local(process))
+                .or_else(|| candidates.first().filter(|_| allow_remote))
+                .map(|process| (process.pid, process.priority))
+        };
+        
+        let Some((pid, priority)) = process_to_migrate else {
+            return false;
+        };
+        self.cpu_schedulers[from_cpu as usize].lock().remove_process(pid);
+        self.cpu_schedulers[to_cpu as usize].lock().add_process(pid, priority);
+        true
+    }
 
-        // Perform the migration
-        if let Some(pid) = process_to_migrate {
-            {
-                let mut from_scheduler = self.cpu_schedulers[from_cpu as usize].lock();
-                from_scheduler.remove_process(pid);
-            }
-            
-            if let Some(process) = self.processes.get(pid as usize).and_then(|p| p.as_ref()) {
-                let mut to_scheduler = self.cpu_schedulers[to_cpu as usize].lock();
-                to_scheduler.add_process(pid, process.priority);
-            }
-        }
+    /// The CPU `pid` is queued or running on
+    fn cpu_of(&self, pid: u64) -> Option<u32> {
+        self.cpu_schedulers
+            .iter()
+            .position(|cpu_scheduler| cpu_scheduler.lock().occupants().any(|occupant| occupant == pid))
+            .map(|cpu_id| cpu_id as u32)
     }
 
-    // Priority inheritance across CPUs
+    // Priority inheritance across CPUs. A process that is off the CPUs, blocked on something
+    // else, keeps its inheritance in CPU 0's books and is queued at the lent priority when
+    // it wakes
     pub fn inherit_priority_across_cpus(&mut self, pid: u64, from_pid: u64) {
-        // Find which CPUs these processes are on
-        let mut pid_cpu: Option<u32> = None;
-        
-        for (cpu_id, scheduler_mutex) in self.cpu_schedulers.iter().enumerate() {
-            let scheduler = scheduler_mutex.lock();
-            if scheduler.current_process == Some(pid) {
-                pid_cpu = Some(cpu_id as u32);
-                break;
-            }
+        let cpu_id = self.cpu_of(pid).unwrap_or(0);
+        if let Some(cpu_scheduler) = self.cpu_schedulers.get(cpu_id as usize) {
+            cpu_scheduler.lock().inherit_priority(pid, from_pid, &mut self.processes);
         }
+    }
 
-        // Apply priority inheritance
-        if let Some(cpu_id) = pid_cpu {
-            let mut scheduler = self.cpu_schedulers[cpu_id as usize].lock();
-            scheduler.inherit_priority(pid, from_pid, &mut self.processes);
+    pub fn restore_priority_across_cpus(&mut self, pid: u64) {
+        let cpu_id = self.cpu_of(pid).unwrap_or(0);
+        if let Some(cpu_scheduler) = self.cpu_schedulers.get(cpu_id as usize) {
+            cpu_scheduler.lock().restore_priority(pid, &mut self.processes);
         }
     }
 
-    pub fn restore_priority_across_cpus(&mut self, pid: u64) {
-        // Find which CPU this process is on
-        for (cpu_id, scheduler_mutex) in self.cpu_schedulers.iter().enumerate() {
-            let scheduler = scheduler_mutex.lock();
-            if scheduler.current_process == Some(pid) {
-                drop(scheduler);
-                let mut scheduler = self.cpu_schedulers[cpu_id].lock();
-                scheduler.restore_priority(pid, &mut self.processes);
-                break;
-            }
+    /// Give process `pid` the base priority `priority`, moving it to that ready queue. A
+    /// process holding a lent priority keeps it, taking up `priority` once it is given back.
+    /// Returns whether the process exists
+    pub fn set_process_priority(&mut self, pid: u64, priority: Priority) -> bool {
+        let cpu_id = self.cpu_of(pid);
+        let Some(process) = self.processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()) else {
+            return false;
+        };
+        if let PriorityInheritanceState::Inherited { original_priority, .. } = &mut process.rt_params.priority_inheritance {
+            *original_priority = priority;
+            return true;
+        }
+        process.priority = priority;
+        if let Some(cpu_scheduler) = cpu_id.and_then(|cpu_id| self.cpu_schedulers.get(cpu_id as usize)) {
+            cpu_scheduler.lock().requeue(pid, priority);
         }
+        true
     }
 
     // CBS throttling across all CPUs
@@ -1684,20 +2176,54 @@ pub fn init() {
     let _ = get_smp_scheduler();
 }
 
+/// Processes sleeping until a deadline on the monotonic clock, in nanoseconds
+#[derive(Debug, Default)]
+pub struct SleepQueue {
+    sleepers: Vec<(u64, u64)>, // (wake_ns, pid)
+}
+
+impl SleepQueue {
+    pub const fn new() -> Self {
+        Self { sleepers: Vec::new() }
+    }
+
+    pub fn sleep(&mut self, pid: u64, wake_ns: u64) {
+        self.sleepers.retain(|&(_, sleeper)| sleeper != pid);
+        self.sleepers.push((wake_ns, pid));
+    }
+
+    /// Take one sleeper due by `now_ns`
+    pub fn take_due(&mut self, now_ns: u64) -> Option<u64> {
+        let index = self.sleepers.iter().position(|&(wake_ns, _)| now_ns >= wake_ns)?;
+        Some(self.sleepers.swap_remove(index).1)
+    }
+
+    /// Take `pid` out before it is due, returning when it was
+    pub fn cancel(&mut self, pid: u64) -> Option<u64> {
+        let index = self.sleepers.iter().position(|&(_, sleeper)| sleeper == pid)?;
+        Some(self.sleepers.swap_remove(index).0)
+    }
+
+    /// When the next sleeper is due
+    pub fn next_wake(&self) -> Option<u64> {
+        self.sleepers.iter().map(|&(wake_ns, _)| wake_ns).min()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sleepers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sleepers.is_empty()
+    }
+}
+
 /// Wake any sleeping processes whose deadline has passed
 fn wake_due_sleepers() {
     // No heap allocations in ISR path: wake sleepers one-by-one
     loop {
-        let now_ms = crate::time::get_uptime_ms();
-        let maybe_pid = {
-            let mut sleepers = SLEEPERS.lock();
-            if let Some((idx, _)) = sleepers.iter().enumerate().find(|(_, (wake_ms, _))| now_ms >= *wake_ms) {
-                let (_, pid) = sleepers.swap_remove(idx);
-                Some(pid)
-            } else {
-                None
-            }
-        };
+        let now_ns = crate::time::get_precise_time_ns();
+        let maybe_pid = SLEEPERS.lock().take_due(now_ns);
         if let Some(pid) = maybe_pid {
             get_smp_scheduler().lock().unblock_process(pid);
         } else {
@@ -1706,13 +2232,100 @@ fn wake_due_sleepers() {
     }
 }
 
+/// Wake `pid` early if it sleeps, as a signal does
+fn interrupt_sleep(pid: u64) {
+    let cancelled = interrupts::without_interrupts(|| SLEEPERS.lock().cancel(pid));
+    if cancelled.is_some() {
+        get_smp_scheduler().lock().unblock_process(pid);
+    }
+}
+
 /// Put the current process to sleep for the specified milliseconds
 pub fn sleep_current(milliseconds: u64) {
-    let wake_ms = crate::time::get_uptime_ms().saturating_add(milliseconds);
-    let pid = get_current_process_id();
-    SLEEPERS.lock().push((wake_ms, pid));
+    nanosleep(milliseconds.saturating_mul(1_000_000));
+}
+
+/// Sleep for `nanoseconds` of monotonic time. The current process is blocked so the CPU runs
+/// other work meanwhile, and the CPU's timer is armed for the wake-up: with TSC deadline
+/// mode exactly, so sleeps well under a millisecond keep their length, and otherwise at the
+/// first periodic tick past it. With no process to block, early in boot, the CPU halts
+/// between interrupts until the deadline instead. A signal ends the sleep early. Returns the
+/// nanoseconds left unslept, 0 once the whole sleep elapsed. Sleeping for 0 only yields
+pub fn nanosleep(nanoseconds: u64) -> u64 {
+    if nanoseconds == 0 {
+        yield_current();
+        return 0;
+    }
+    let wake_ns = crate::time::get_precise_time_ns().saturating_add(nanoseconds);
+    if !block_current_until(Some(wake_ns), |_| true) {
+        while crate::time::get_precise_time_ns() < wake_ns {
+            if interrupts::are_enabled() {
+                x86_64::instructions::hlt();
+            } else {
+                core::hint::spin_loop();
+            }
+        }
+    }
+    wake_ns.saturating_sub(crate::time::get_precise_time_ns())
+}
+
+/// Block the current process until `unblock_process` wakes it, if `register`, called with
+/// it first, notes something for it to wait for and returns true. Registering and blocking
+/// happen together under the scheduler lock with interrupts held off, so the wake-up cannot
+/// come before the block it undoes. Returns the PID if the process blocked
+pub fn block_current_if(register: impl FnOnce(&Process) -> bool) -> Option<u64> {
     let cpu_id = get_current_cpu_id();
-    get_smp_scheduler().lock().block_current_on_cpu(cpu_id);
+    interrupts::without_interrupts(|| {
+        let mut scheduler = get_smp_scheduler().lock();
+        let pid = scheduler.get_current_process_id(cpu_id)?;
+        let process = scheduler.processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut())?;
+        if !register(process) {
+            return None;
+        }
+        process.state = ProcessState::Blocked;
+        scheduler.block_current_on_cpu(cpu_id);
+        Some(pid)
+    })
+}
+
+/// Wait until process `pid`, blocked by `block_current_if`, is woken
+pub fn wait_until_unblocked(pid: u64) {
+    while with_process(pid, |process| process.state == ProcessState::Blocked).unwrap_or(false) {
+        core::hint::spin_loop();
+    }
+}
+
+/// Block the current process as `block_current_if` does and wait until it is woken, which
+/// also happens once `deadline_ns` on the monotonic clock passes if there is one. Returns
+/// whether the process blocked
+pub fn block_current_until(deadline_ns: Option<u64>, register: impl FnOnce(&Process) -> bool) -> bool {
+    let pid = block_current_if(|process| {
+        if !register(process) {
+            return false;
+        }
+        if let Some(wake_ns) = deadline_ns {
+            SLEEPERS.lock().sleep(process.pid, wake_ns);
+        }
+        true
+    });
+    if deadline_ns.is_some() {
+        crate::time::schedule_next_timer_deadline();
+    }
+    let Some(pid) = pid else {
+        return false;
+    };
+    wait_until_unblocked(pid);
+    // Woken before the deadline, which must not wake it again later
+    interrupts::without_interrupts(|| SLEEPERS.lock().cancel(pid));
+    true
+}
+
+/// Microseconds until the earliest sleeper or timerfd is due, at least 1, if there is one
+fn next_sleeper_wake_us() -> Option<u64> {
+    let sleeper_ns = SLEEPERS.lock().next_wake();
+    let wake_ns = sleeper_ns.into_iter().chain(crate::filesystem::timerfd::next_deadline_ns()).min()?;
+    let left_ns = wake_ns.saturating_sub(crate::time::get_precise_time_ns());
+    Some(left_ns.div_ceil(1000).max(1))
 }
 
 pub fn create_process(name: alloc::string::String, entry_point: VirtAddr, priority: Priority) -> Result<u64, crate::vmm::VmError> {
@@ -1822,49 +2435,277 @@ pub fn unblock_process(pid: u64) {
 }
 
 pub fn terminate_process(pid: u64) {
-    // Perform comprehensive cleanup before removing process
-    cleanup_process_resources(pid as u32);
-    get_smp_scheduler().lock().remove_process(pid);
+    end_process(pid, -1);
 }
 
-/// Comprehensive cleanup of all process resources
-fn cleanup_process_resources(process_id: u32) {
-    // Clean up security context
-    crate::security::cleanup_process_security(process_id);
-    
-    // Clean up capabilities
-    crate::capabilities::cleanup_process_capabilities(process_id as u64);
-    
-    // Clean up IPC resources
-    crate::ipc::cleanup_process_ipc(process_id);
-    
-    // Clean up network resources
-    crate::network::cleanup_process_network(process_id);
-    
-    // Clean up RaeKit applications
-    crate::raekit::cleanup_process_raekit(process_id);
-    
-    // Clean up assistant sessions
-    crate::rae_assistant::cleanup_process_assistant(process_id);
-    
-    // Clean up shell sessions
-    crate::raeshell::cleanup_process_shell(process_id);
-    
-    // Clean up RaeDE sessions
-    crate::raede::cleanup_process_raede(process_id);
-    
-    // Clean up address space if it exists
-    let scheduler = get_smp_scheduler().lock();
-    if let Some(process) = scheduler.processes.get(process_id as usize).and_then(|p| p.as_ref()) {
-        if let Some(address_space_id) = process.address_space_id {
-            drop(scheduler); // Release lock before VMM operations
-            let _ = crate::vmm::destroy_address_space(address_space_id);
+/// Free what process `pid` holds and retire it with `exit_code`, waking the threads joined
+/// on it
+fn end_process(pid: u64, exit_code: i32) {
+    // Perform comprehensive cleanup before removing process
+    cleanup_process_resources(pid as u32);
+    retire_process(pid, exit_code);
+    if let Some(waiters) = JOIN_WAITERS.lock().remove(&pid) {
+        let mut scheduler = get_smp_scheduler().lock();
+        for waiter in waiters {
+            scheduler.unblock_process(waiter);
         }
     }
 }
 
-// Idle thread function - runs when no other processes are ready
-extern "C" fn idle_thread_main() -> ! {
+/// End every thread of thread group `thread_group` with `exit_code`, but `except`
+pub fn end_thread_group(thread_group: u64, except: Option<u64>, exit_code: i32) {
+    loop {
+        let next = {
+            let scheduler = get_smp_scheduler().lock();
+            scheduler.processes.iter().flatten()
+                .find(|thread| thread.thread_group == thread_group && Some(thread.pid) != except && is_live(&scheduler.processes, thread.pid))
+                .map(|thread| thread.pid)
+        };
+        let Some(pid) = next else {
+            break;
+        };
+        end_process(pid, exit_code);
+    }
+}
+
+/// Whether process `pid` exists and has not exited
+fn is_live(processes: &[Option<SlabBox<Process>>], pid: u64) -> bool {
+    processes
+        .get(pid as usize)
+        .and_then(|slot| slot.as_deref())
+        .is_some_and(|process| !matches!(process.state, ProcessState::Terminated | ProcessState::Zombie(_)))
+}
+
+/// A thread of thread group `thread_group` that has not exited, its leader while that runs
+fn live_thread_of(processes: &[Option<SlabBox<Process>>], thread_group: u64) -> Option<u64> {
+    if is_live(processes, thread_group) {
+        return Some(thread_group);
+    }
+    processes.iter().flatten()
+        .find(|thread| thread.thread_group == thread_group && is_live(processes, thread.pid))
+        .map(|thread| thread.pid)
+}
+
+/// Mark SIGCHLD pending for `parent`, waking it if it is blocked in `wait_pid`
+fn notify_parent(scheduler: &mut SmpScheduler, parent: u64) {
+    if let Some(process) = scheduler.processes.get_mut(parent as usize).and_then(|p| p.as_deref_mut()) {
+        process.pending_signals |= 1 << (Signal::SIGCHLD as u8);
+    }
+    CHILD_EXITS.fetch_add(1, Ordering::SeqCst);
+    if CHILD_WAITERS.lock().remove(&parent) {
+        scheduler.unblock_process(parent);
+    }
+}
+
+/// Take process `pid` off the CPUs after it ended with `exit_code`. Its children are adopted
+/// by another thread of its group while one runs, else by init while it lives, else by the
+/// idle thread; threads of its own group are left as they are. A thread group leader whose
+/// parent can wait on it becomes a zombie holding the exit code, and the parent is sent
+/// SIGCHLD once the last thread of the group has exited; other threads, those sharing their
+/// parent's address space, and processes without a parent are marked terminated. An
+/// address space or descriptor table no live process shares any more is freed
+fn retire_process(pid: u64, exit_code: i32) {
+    let mut scheduler = get_smp_scheduler().lock();
+    if !is_live(&scheduler.processes, pid) {
+        return;
+    }
+    scheduler.remove_process(pid);
+    let Some(thread_group) = scheduler.processes.get(pid as usize).and_then(|p| p.as_deref()).map(|p| p.thread_group) else {
+        return;
+    };
+    let survivor = live_thread_of(&scheduler.processes, thread_group);
+
+    let idle_pid = IDLE_THREAD_PID.load(Ordering::SeqCst);
+    let init_pid = INIT_PID.load(Ordering::SeqCst);
+    let reaper = match survivor {
+        Some(thread) => thread,
+        None if pid != init_pid && is_live(&scheduler.processes, init_pid) => init_pid,
+        None => idle_pid,
+    };
+    let mut adopted_zombie = false;
+    for slot in scheduler.processes.iter_mut() {
+        let Some(child) = slot.as_deref_mut().filter(|child| child.parent_pid == Some(pid) && child.thread_group != thread_group) else {
+            continue;
+        };
+        child.parent_pid = Some(reaper);
+        if matches!(child.state, ProcessState::Zombie(_)) {
+            // The idle thread never waits, so zombies it would adopt are freed now
+            if reaper == idle_pid {
+                *slot = None;
+            } else {
+                adopted_zombie = true;
+            }
+        }
+    }
+    if adopted_zombie {
+        notify_parent(&mut scheduler, reaper);
+    }
+
+    let Some(process) = scheduler.processes.get(pid as usize).and_then(|p| p.as_deref()) else {
+        return;
+    };
+    let waiting_parent = process.parent_pid.filter(|&parent| {
+        let shares_address_space = scheduler.processes.get(parent as usize).and_then(|p| p.as_deref()).is_some_and(|parent| {
+            process.address_space_id.is_some() && parent.address_space_id == process.address_space_id
+        });
+        pid == thread_group && parent != idle_pid && !shares_address_space && is_live(&scheduler.processes, parent)
+    });
+    if let Some(process) = scheduler.processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()) {
+        process.set_exit_code(exit_code);
+        process.state = match waiting_parent {
+            Some(_) => ProcessState::Zombie(exit_code),
+            None => ProcessState::Terminated,
+        };
+    }
+    let group_parent = if pid == thread_group {
+        waiting_parent
+    } else {
+        scheduler.processes.get(thread_group as usize)
+            .and_then(|p| p.as_deref())
+            .filter(|leader| matches!(leader.state, ProcessState::Zombie(_)))
+            .and_then(|leader| leader.parent_pid)
+    };
+    if let Some(parent) = group_parent.filter(|_| survivor.is_none()) {
+        notify_parent(&mut scheduler, parent);
+    }
+
+    // The last process out of an address space or a descriptor table frees it
+    let Some(process) = scheduler.processes.get(pid as usize).and_then(|p| p.as_deref()) else {
+        return;
+    };
+    let (address_space_id, open_files) = (process.address_space_id, process.open_files.clone());
+    let sharers = || scheduler.processes.iter().flatten().filter(|other| is_live(&scheduler.processes, other.pid));
+    let space_shared = address_space_id.is_some_and(|id| sharers().any(|other| other.address_space_id == Some(id)));
+    let files_shared = sharers().any(|other| alloc::sync::Arc::ptr_eq(&other.open_files, &open_files));
+    drop(scheduler);
+    if let Some(address_space_id) = address_space_id.filter(|_| !space_shared) {
+        let _ = crate::vmm::destroy_address_space(address_space_id);
+    }
+    if !files_shared {
+        let fds = core::mem::take(&mut *open_files.lock());
+        for fd in fds {
+            let _ = crate::filesystem::close(fd);
+        }
+    }
+}
+
+/// Free a child of `parent` that exited, `pid` or any for `None`, and return its PID and exit
+/// code. `None` while the matching children are all still running
+fn reap_child(scheduler: &mut SmpScheduler, parent: ProcessId, pid: Option<ProcessId>) -> Result<Option<(ProcessId, i32)>, ProcessError> {
+    let mut running_children = false;
+    let mut exited = None;
+    for child in scheduler.processes.iter().flatten() {
+        if child.parent_pid != Some(parent) || pid.is_some_and(|pid| pid != child.pid) {
+            continue;
+        }
+        match child.state {
+            // A leader is reaped with the last thread of its group
+            ProcessState::Zombie(exit_code) if live_thread_of(&scheduler.processes, child.thread_group).is_none() => {
+                exited = Some((child.pid, exit_code));
+            }
+            ProcessState::Terminated => exited = Some((child.pid, child.get_exit_code())),
+            _ => running_children = true,
+        }
+        if exited.is_some() {
+            break;
+        }
+    }
+    if let Some((child_pid, _)) = exited {
+        if let Some(slot) = scheduler.processes.get_mut(child_pid as usize) {
+            *slot = None;
+        }
+        return Ok(exited);
+    }
+    if !running_children {
+        return Err(ProcessError::NoChildren);
+    }
+    Ok(None)
+}
+
+/// Wait for a child of the current process to exit: child `pid`, or any child for `None`.
+/// A child that already exited is reaped at once; otherwise the caller blocks until one
+/// does. Returns the child's PID and exit code, and frees the child
+pub fn wait_pid(pid: Option<ProcessId>) -> Result<(ProcessId, i32), ProcessError> {
+    loop {
+        let exits = CHILD_EXITS.load(Ordering::SeqCst);
+        if let Some(exited) = try_wait_pid(pid)? {
+            return Ok(exited);
+        }
+
+        // Block until a child's exit wakes this process, then look again. An exit since the
+        // look above is noticed under the scheduler lock, and looked for again instead
+        let blocked = block_current_if(|process| {
+            if CHILD_EXITS.load(Ordering::SeqCst) != exits {
+                return false;
+            }
+            CHILD_WAITERS.lock().insert(process.pid);
+            true
+        });
+        if let Some(current_pid) = blocked {
+            wait_until_unblocked(current_pid);
+        }
+    }
+}
+
+/// Like `wait_pid`, but returns `None` at once instead of blocking while the children are
+/// still running
+pub fn try_wait_pid(pid: Option<ProcessId>) -> Result<Option<(ProcessId, i32)>, ProcessError> {
+    let cpu_id = get_current_cpu_id();
+    interrupts::without_interrupts(|| {
+        let mut scheduler = get_smp_scheduler().lock();
+        let current_pid = scheduler.get_current_process_id(cpu_id).ok_or(ProcessError::NoCurrentProcess)?;
+        reap_child(&mut scheduler, current_pid, pid)
+    })
+}
+
+/// Make the current process the one that adopts orphans and reaps them, as init does
+pub fn register_init() -> Result<ProcessId, ProcessError> {
+    let cpu_id = get_current_cpu_id();
+    let current_pid = get_smp_scheduler().lock().get_current_process_id(cpu_id).ok_or(ProcessError::NoCurrentProcess)?;
+    INIT_PID.store(current_pid, Ordering::SeqCst);
+    Ok(current_pid)
+}
+
+/// The process that adopts orphans and reaps them
+pub fn init_pid() -> ProcessId {
+    INIT_PID.load(Ordering::SeqCst)
+}
+
+/// Comprehensive cleanup of all process resources
+fn cleanup_process_resources(process_id: u32) {
+    // Clean up security context
+    crate::security::cleanup_process_security(process_id);
+    
+    // Clean up capabilities
+    crate::capabilities::cleanup_process_capabilities(process_id as u64);
+    
+    // Clean up IPC resources
+    crate::ipc::cleanup_process_ipc(process_id);
+    
+    // Clean up network resources
+    crate::network::cleanup_process_network(process_id);
+    
+    // Clean up RaeKit applications
+    crate::raekit::cleanup_process_raekit(process_id);
+    
+    // Clean up assistant sessions
+    crate::rae_assistant::cleanup_process_assistant(process_id);
+    
+    // Clean up shell sessions
+    crate::raeshell::cleanup_process_shell(process_id);
+    
+    // Clean up RaeDE sessions
+    crate::raede::cleanup_process_raede(process_id);
+    
+    // Release file locks
+    crate::filesystem::lock::release_process_locks(process_id as u64);
+    
+    // Recover the robust futexes it holds and leave futex queues
+    crate::futex::exit_thread(process_id as u64);
+}
+
+// Idle thread function - runs when no other processes are ready
+extern "C" fn idle_thread_main() -> ! {
     loop {
         // Halt CPU until next interrupt to save power
         unsafe {
@@ -1951,10 +2792,11 @@ pub fn init_rt_threads() -> Result<(), crate::vmm::VmError> {
         return Err(crate::vmm::VmError::OutOfMemory); // Use a VmError variant
     }
     
-    let mut scheduler = get_smp_scheduler().lock();
-    
-    // Enforce RT core isolation before creating RT threads
-    scheduler.enforce_rt_core_isolation();
+    // Keep everything not pinned to cores 2 and 3 off them. CPUs 0 and 1 stay in the
+    // general pool, so isolation cannot fail
+    for cpu_id in [2, 3] {
+        let _ = isolate_cpu(cpu_id, true);
+    }
     
     // Input RT thread - EDF with 1ms period, 200μs budget on core 2
     let input_affinity = CpuAffinity::single_cpu(2);
@@ -1967,9 +2809,6 @@ pub fn init_rt_threads() -> Result<(), crate::vmm::VmError> {
         Some(input_affinity)
     )?;
     
-    // Migrate input thread to its dedicated core
-    scheduler.migrate_rt_process(input_pid, 2);
-    
     // Audio RT thread - CBS with 2.67ms period, 500μs budget on core 3
     let audio_affinity = CpuAffinity::single_cpu(3);
     let audio_pid = spawn_rt_kernel_thread(
@@ -1981,9 +2820,6 @@ pub fn init_rt_threads() -> Result<(), crate::vmm::VmError> {
         Some(audio_affinity)
     )?;
     
-    // Migrate audio thread to its dedicated core
-    scheduler.migrate_rt_process(audio_pid, 3);
-    
     // Compositor RT thread - CBS with 8.33ms period, 2ms budget on core 3 (shared with audio)
     let compositor_affinity = CpuAffinity::single_cpu(3);
     let compositor_pid = spawn_rt_kernel_thread(
@@ -1995,11 +2831,6 @@ pub fn init_rt_threads() -> Result<(), crate::vmm::VmError> {
         Some(compositor_affinity)
     )?;
     
-    // Migrate compositor thread to core 3 (shared with audio via CBS)
-    scheduler.migrate_rt_process(compositor_pid, 3);
-    
-    drop(scheduler);
-    
     crate::serial_println!("[RT] Initialized RT threads with core isolation:");
     crate::serial_println!("[RT] Input (PID {}): EDF 1ms/200μs on CPU 2", input_pid);
     crate::serial_println!("[RT] Audio (PID {}): CBS 2.67ms/500μs on CPU 3", audio_pid);
@@ -2013,6 +2844,12 @@ pub fn set_gaming_mode(enabled: bool) {
     get_smp_scheduler().lock().set_gaming_mode(enabled);
 }
 
+/// Dedicate `cpu_id` to the processes pinned to it, or return it to the general pool.
+/// See `SmpScheduler::isolate_cpu`
+pub fn isolate_cpu(cpu_id: u32, isolated: bool) -> Result<(), &'static str> {
+    get_smp_scheduler().lock().isolate_cpu(cpu_id, isolated)
+}
+
 pub fn get_current_process_info() -> Option<(u64, alloc::string::String, ProcessState)> {
     let cpu_id = get_current_cpu_id();
     let scheduler = get_smp_scheduler().lock();
@@ -2031,41 +2868,63 @@ pub fn get_current_process_parent_id() -> Option<u64> {
     scheduler.get_current_process(cpu_id).and_then(|p| p.parent_pid)
 }
 
-/// Iterate over processes under the scheduler lock and call a visitor
-pub fn for_each_process<F>(mut f: F) -> Result<(), ()>
+/// Thread group of the current process, the PID it reports
+pub fn get_current_thread_group() -> Option<u64> {
+    let cpu_id = get_current_cpu_id();
+    let scheduler = get_smp_scheduler().lock();
+    scheduler.get_current_process(cpu_id).map(|p| p.thread_group)
+}
+
+/// Enter `fd` in the descriptor table of the current process
+pub fn add_open_file(fd: u64) {
+    let cpu_id = get_current_cpu_id();
+    let scheduler = get_smp_scheduler().lock();
+    if let Some(process) = scheduler.get_current_process(cpu_id) {
+        process.open_files.lock().push(fd);
+    }
+}
+
+/// Take `fd` out of the descriptor table of the current process once it is closed
+pub fn remove_open_file(fd: u64) {
+    let cpu_id = get_current_cpu_id();
+    let scheduler = get_smp_scheduler().lock();
+    if let Some(process) = scheduler.get_current_process(cpu_id) {
+        process.open_files.lock().retain(|&open| open != fd);
+    }
+}
+
+/// Call `f` with process `pid` under the scheduler lock; `None` when it does not exist
+pub fn with_process<F, R>(pid: u64, f: F) -> Option<R>
 where
-    F: FnMut(u64, &alloc::string::String, ProcessState, Priority),
+    F: FnOnce(&Process) -> R,
 {
     let scheduler = get_smp_scheduler().lock();
-    for (idx, slot) in scheduler.processes.iter().enumerate() {
-        if let Some(p) = slot.as_ref() {
-            f(idx as u64, &p.name, p.state, p.priority);
-        }
-    }
-    Ok(())
+    scheduler.processes.get(pid as usize).and_then(|slot| slot.as_deref()).map(f)
+}
+
+/// Call `f` with the process table unless the scheduler lock is already taken, for callers
+/// such as the OOM killer that may run while it is held
+pub fn try_with_processes<F, R>(f: F) -> Option<R>
+where
+    F: FnOnce(&[Option<SlabBox<Process>>]) -> R,
+{
+    let scheduler = get_smp_scheduler().try_lock()?;
+    Some(f(&scheduler.processes))
+}
+
+/// PID of the idle thread, 0 before it is started
+pub fn idle_thread_pid() -> u64 {
+    IDLE_THREAD_PID.load(Ordering::SeqCst)
 }
 
 /// Create a thread within the current user's address space
 pub fn spawn_user_thread(entry_point: VirtAddr, stack_size: usize) -> Result<u64, crate::vmm::VmError> {
     // Identify parent process and its address space
     let cpu_id = get_current_cpu_id();
-    let scheduler = get_smp_scheduler().lock();
-    let parent_pid = scheduler.get_current_process_id(cpu_id).ok_or(crate::vmm::VmError::InvalidAddressSpace)?;
-    let (parent_as, parent_priority, parent_heap_base, parent_heap_size, parent_permissions, parent_numa, parent_name) = {
-        let pref = scheduler.processes.get(parent_pid as usize)
-            .and_then(|p| p.as_ref())
-            .ok_or(crate::vmm::VmError::InvalidAddressSpace)?;
-        (
-            pref.address_space_id.ok_or(crate::vmm::VmError::InvalidAddressSpace)?,
-            pref.priority,
-            pref.heap_base,
-            pref.heap_size,
-            pref.permissions.clone(),
-            pref.numa_node,
-            pref.name.clone(),
-        )
-    };
-    drop(scheduler);
+    let parent_pid = get_smp_scheduler().lock().get_current_process_id(cpu_id).ok_or(crate::vmm::VmError::InvalidAddressSpace)?;
+    let parent_as = with_process(parent_pid, |parent| parent.address_space_id)
+        .flatten()
+        .ok_or(crate::vmm::VmError::InvalidAddressSpace)?;
     // Allocate stack in parent's address space
     let user_stack_top = VirtAddr::new(0x7FFF_FFFF_0000);
     let user_stack_base = user_stack_top - stack_size as u64;
@@ -2082,42 +2941,109 @@ pub fn spawn_user_thread(entry_point: VirtAddr, stack_size: usize) -> Result<u64
         Ok(())
     })?;
 
-    // Build thread context sharing parent's address space
-    let mut ctx = ProcessContext::new_user_context(entry_point, user_stack_top);
-    ctx.rflags = 0x202;
+    let mut sched = get_smp_scheduler().lock();
+    let parent = sched.processes.get(parent_pid as usize)
+        .and_then(|p| p.as_deref())
+        .ok_or(crate::vmm::VmError::InvalidAddressSpace)?;
+    let mut thread = clone_process(parent, CloneFlags::VM | CloneFlags::FILES | CloneFlags::THREAD, entry_point, user_stack_top, 0)
+        .map_err(|_| crate::vmm::VmError::OutOfMemory)?;
+    thread.stack_base = user_stack_base;
+    thread.stack_size = stack_size;
+    Ok(sched.add_process(thread))
+}
 
-    // Allocate PID/TID
-    let pid = NEXT_PID.fetch_add(1, Ordering::SeqCst);
+/// Make a new process from `parent` that starts at `entry` with its stack pointer at
+/// `stack`, sharing with `parent` what `flags` say. Without `CloneFlags::VM` it gets a
+/// copy-on-write copy of the address space, and without `CloneFlags::FILES` a descriptor
+/// table of its own, which starts empty. Its FS base is `tls`, or for 0 a fresh block of
+/// the program's thread-locals if it has any. A thread group needs a shared address space,
+/// and a thread in one a stack of its own. The process is returned for the caller to
+/// schedule
+pub fn clone_process(parent: &Process, flags: CloneFlags, entry: VirtAddr, stack: VirtAddr, tls: u64) -> Result<Process, ProcessError> {
+    if flags.contains(CloneFlags::THREAD) && !flags.contains(CloneFlags::VM) {
+        return Err(ProcessError::InvalidClone);
+    }
+    if flags.contains(CloneFlags::VM) && stack.is_null() {
+        return Err(ProcessError::InvalidClone);
+    }
+
+    let address_space_id = match parent.address_space_id {
+        Some(space) if !flags.contains(CloneFlags::VM) => {
+            Some(crate::vmm::copy_address_space(space).map_err(|_| ProcessError::OutOfMemory)?)
+        }
+        shared => shared,
+    };
+    let fs_base = match (&parent.tls_template, address_space_id) {
+        _ if tls != 0 => Ok(tls),
+        // The copy keeps the TLS block where it was
+        _ if !flags.contains(CloneFlags::VM) => Ok(parent.fs_base),
+        (Some(template), Some(space)) => template.allocate_block(space).map(|block| block.as_u64()),
+        _ => Ok(0),
+    };
+    let fs_base = match fs_base {
+        Ok(fs_base) => fs_base,
+        Err(_) => {
+            if let Some(copy) = address_space_id.filter(|_| !flags.contains(CloneFlags::VM)) {
+                let _ = crate::vmm::destroy_address_space(copy);
+            }
+            return Err(ProcessError::OutOfMemory);
+        }
+    };
 
-    let thread = Process {
+    let mut context = ProcessContext::new_user_context(entry, stack);
+    // Like fork, the new process sees the call return 0
+    context.rax = 0;
+    let pid = NEXT_PID.fetch_add(1, Ordering::SeqCst);
+    Ok(Process {
         pid,
-        parent_pid: Some(parent_pid),
+        parent_pid: Some(parent.pid),
         state: ProcessState::Ready,
-        priority: parent_priority,
-        context: ctx,
+        priority: parent.priority,
+        context,
         page_table: None,
-        address_space_id: Some(parent_as),
-        stack_base: user_stack_base,
-        stack_size,
-        heap_base: parent_heap_base,
-        heap_size: parent_heap_size,
-        name: alloc::format!("{}_t{}", parent_name, pid),
+        address_space_id,
+        stack_base: parent.stack_base,
+        stack_size: parent.stack_size,
+        heap_base: parent.heap_base,
+        heap_size: parent.heap_size,
+        name: alloc::format!("{}_t{}", parent.name, pid),
         cpu_time: 0,
         memory_usage: 0,
-        open_files: alloc::vec::Vec::new(),
-        permissions: parent_permissions,
+        start_time: crate::time::get_uptime_ms(),
+        exit_code: 0,
+        thread_group: if flags.contains(CloneFlags::THREAD) { parent.thread_group } else { pid },
+        open_files: if flags.contains(CloneFlags::FILES) {
+            parent.open_files.clone()
+        } else {
+            alloc::sync::Arc::new(Mutex::new(Vec::new()))
+        },
+        permissions: parent.permissions.clone(),
         pending_signals: 0,
-        signal_handlers: [None; 32],
+        signal_handlers: parent.signal_handlers,
         kernel_stack_ptr: None,
         cpu_affinity: CpuAffinity::ANY,
         rt_params: RtParams::default(),
-        numa_node: parent_numa,
-    };
+        numa_node: parent.numa_node,
+        fs_base,
+        tls_template: parent.tls_template.clone(),
+        robust_list: 0,
+    })
+}
 
-    // Register the new thread with the scheduler
-    let mut sched = get_smp_scheduler().lock();
-    let _ = sched.add_process(thread);
-    Ok(pid)
+/// Clone the current process as `clone_process` does and schedule the new process,
+/// returning its PID
+pub fn clone_current(flags: CloneFlags, entry: VirtAddr, stack: VirtAddr, tls: u64) -> Result<ProcessId, ProcessError> {
+    let cpu_id = get_current_cpu_id();
+    let mut scheduler = get_smp_scheduler().lock();
+    let current_pid = scheduler.get_current_process_id(cpu_id).ok_or(ProcessError::NoCurrentProcess)?;
+    let parent = scheduler.processes.get(current_pid as usize)
+        .and_then(|p| p.as_deref())
+        .ok_or(ProcessError::NoCurrentProcess)?;
+    let child = clone_process(parent, flags, entry, stack, tls)?;
+    let child_pid = child.pid;
+    let _ = crate::security::init_process_security(child_pid as u32, Some(current_pid as u32));
+    scheduler.add_process(child);
+    Ok(child_pid)
 }
 
 /// Set the current process/thread priority
@@ -2125,8 +3051,45 @@ pub fn set_current_priority(priority: Priority) {
     let cpu_id = get_current_cpu_id();
     let mut scheduler = get_smp_scheduler().lock();
     if let Some(pid) = scheduler.get_current_process_id(cpu_id) {
-        if let Some(proc_ref) = scheduler.processes.get_mut(pid as usize).and_then(|p| p.as_mut()) {
-            proc_ref.priority = priority;
+        scheduler.set_process_priority(pid, priority);
+    }
+}
+
+/// Set the base priority of process `pid` from `nice`
+pub fn renice(pid: u64, nice: i32) -> Result<(), ProcessError> {
+    let priority = Priority::from_nice(nice).ok_or(ProcessError::InvalidNice)?;
+    if !get_smp_scheduler().lock().set_process_priority(pid, priority) {
+        return Err(ProcessError::NoSuchProcess);
+    }
+    Ok(())
+}
+
+/// Whether the current process may renice `pid`: one of its own threads or a child
+pub fn may_renice(pid: u64) -> bool {
+    let Some(thread_group) = get_current_thread_group() else {
+        return false;
+    };
+    with_process(pid, |process| process.thread_group == thread_group || process.parent_pid == Some(thread_group))
+        .unwrap_or(false)
+}
+
+/// Lend process `pid` the priority of `from_pid`, which waits on something `pid` holds
+pub fn inherit_priority(pid: u64, from_pid: u64) {
+    get_smp_scheduler().lock().inherit_priority_across_cpus(pid, from_pid);
+}
+
+/// Take back the priority process `pid` was lent
+pub fn restore_priority(pid: u64) {
+    get_smp_scheduler().lock().restore_priority_across_cpus(pid);
+}
+
+/// Record `head` as the robust futex list of the current thread
+pub fn set_robust_list(head: u64) {
+    let cpu_id = get_current_cpu_id();
+    let mut scheduler = get_smp_scheduler().lock();
+    if let Some(pid) = scheduler.get_current_process_id(cpu_id) {
+        if let Some(process) = scheduler.processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()) {
+            process.robust_list = head;
         }
     }
 }
@@ -2137,7 +3100,7 @@ pub fn is_process_alive(process_id: u64) -> bool {
     if let Some(Some(process)) = scheduler.processes.get(process_id as usize) {
         match process.state {
             ProcessState::Running | ProcessState::Ready | ProcessState::Blocked => true,
-            ProcessState::Terminated => false,
+            ProcessState::Terminated | ProcessState::Zombie(_) => false,
         }
     } else {
         false
@@ -2226,6 +3189,12 @@ pub fn switch_context(old_context: *mut ProcessContext, new_context: *const Proc
     }
 }
 
+/// Point the FS base at `base`, the thread pointer of the process about to run
+pub fn load_fs_base(base: u64) {
+    // The kernel reaches its own per-CPU data through GS and never uses FS
+    x86_64::registers::model_specific::FsBase::write(VirtAddr::new_truncate(base));
+}
+
 pub fn context_switch(old_pid: Option<u64>, new_pid: u64) {
     // Capture required state under the scheduler lock, but avoid holding borrows across drops
     let (old_ctx_ptr, old_as_id, new_as_id) = {
@@ -2233,17 +3202,17 @@ pub fn context_switch(old_pid: Option<u64>, new_pid: u64) {
         let mut old_ctx_ptr: *mut ProcessContext = core::ptr::null_mut();
         let mut old_as_id: Option<u64> = None;
         if let Some(opid) = old_pid {
-            if let Some(old_proc) = sched.processes.get(opid as usize).and_then(|p| p.as_ref()) {
+            if let Some(old_proc) = sched.processes.get(opid as usize).and_then(|p| p.as_deref()) {
                 old_as_id = old_proc.address_space_id;
             }
-            if let Some(old_proc_mut) = sched.processes.get_mut(opid as usize).and_then(|p| p.as_mut()) {
+            if let Some(old_proc_mut) = sched.processes.get_mut(opid as usize).and_then(|p| p.as_deref_mut()) {
                 old_ctx_ptr = &mut old_proc_mut.context as *mut ProcessContext;
             }
         }
         let new_as_id = sched
             .processes
             .get(new_pid as usize)
-            .and_then(|p| p.as_ref())
+            .and_then(|p| p.as_deref())
             .and_then(|p| p.address_space_id);
         (old_ctx_ptr, old_as_id, new_as_id)
     };
@@ -2260,16 +3229,17 @@ pub fn context_switch(old_pid: Option<u64>, new_pid: u64) {
     
     // Save FPU state from old process if it exists
     if let Some(opid) = old_pid {
-        if let Some(_old_process) = sched2.processes.get_mut(opid as usize).and_then(|p| p.as_mut()) {
+        if let Some(_old_process) = sched2.processes.get_mut(opid as usize).and_then(|p| p.as_deref_mut()) {
             // FPU state saving would be handled by hardware context switching
         }
     }
     
-    if let Some(new_process) = sched2.processes.get_mut(new_pid as usize).and_then(|p| p.as_mut()) {
+    if let Some(new_process) = sched2.processes.get_mut(new_pid as usize).and_then(|p| p.as_deref_mut()) {
         new_process.state = ProcessState::Running;
         
         // FPU state restoration would be handled by hardware context switching
         
+        load_fs_base(new_process.fs_base);
         let new_ctx_ptr = &new_process.context as *const ProcessContext;
         switch_context(old_ctx_ptr, new_ctx_ptr);
     }
@@ -2278,8 +3248,9 @@ pub fn context_switch(old_pid: Option<u64>, new_pid: u64) {
 pub fn schedule_tick() {
     // Process signals for current process first
     process_signals();
-    // Wake sleepers that reached their deadline
+    // Wake sleepers that reached their deadline, and the waiters of timers that expired
     wake_due_sleepers();
+    crate::filesystem::timerfd::expire_due(crate::time::get_precise_time_ns());
     
     let cpu_id = get_current_cpu_id();
     let mut smp_scheduler = get_smp_scheduler().lock();
@@ -2288,9 +3259,20 @@ pub fn schedule_tick() {
     let time_slice_expired = smp_scheduler.tick_time_slice_on_cpu(cpu_id);
     let current = smp_scheduler.get_current_process_id(cpu_id);
     
+    // Charge the tick to the process it interrupted
+    let tick_us = 1_000_000 / crate::time::timer_frequency().max(1);
+    if let Some(process) = current.and_then(|pid| smp_scheduler.processes.get_mut(pid as usize)).and_then(|p| p.as_deref_mut()) {
+        process.cpu_time += tick_us;
+    }
+    sample_load(&smp_scheduler);
+    // A round allocates, so the interrupt only marks it due for the main loop
+    if smp_scheduler.balance_due(crate::time::get_uptime_ms()) {
+        BALANCE_PENDING.store(true, Ordering::Release);
+    }
+    
     // Only preempt if time slice expired or current process is not running
     let should_schedule = if let Some(pid) = current {
-        if let Some(proc_ref) = smp_scheduler.processes.get_mut(pid as usize).and_then(|p| p.as_mut()) {
+        if let Some(proc_ref) = smp_scheduler.processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()) {
             // Check if process was terminated by signal handling
             if proc_ref.state == ProcessState::Terminated {
                 true
@@ -2324,11 +3306,21 @@ pub fn schedule_tick() {
     }
 }
 
+/// Run the balancing round the timer tick last marked due, returning the processes moved.
+/// Interrupts stay off while the scheduler is locked, so the tick cannot wait on this CPU
+pub fn run_pending_balance() -> usize {
+    if !BALANCE_PENDING.swap(false, Ordering::AcqRel) {
+        return 0;
+    }
+    interrupts::without_interrupts(|| get_smp_scheduler().lock().balance_load())
+}
+
 /// Get the next scheduler deadline in microseconds for tickless operation
 pub fn get_next_scheduler_deadline_us() -> u64 {
     // Use SMP scheduler's optimized deadline calculation
-    let smp_scheduler = get_smp_scheduler().lock();
-    smp_scheduler.get_earliest_deadline_us()
+    let deadline_us = get_smp_scheduler().lock().get_earliest_deadline_us();
+    // Sleepers and timerfds are woken on time however soon they are due
+    next_sleeper_wake_us().map_or(deadline_us, |wake_us| deadline_us.min(wake_us))
 }
 
 // Process management functions for syscalls
@@ -2339,22 +3331,44 @@ pub fn fork_process() -> Result<ProcessId, crate::vmm::VmError> {
     
     // Get the current process
     let parent_process = scheduler.processes.get(current_pid as usize)
-        .and_then(|p| p.as_ref())
+        .and_then(|p| p.as_deref())
         .ok_or(crate::vmm::VmError::InvalidAddressSpace)?;
     
-    // Create a new process ID
-    let child_pid = NEXT_PID.fetch_add(1, Ordering::SeqCst);
-    
     // Clone the parent process (simplified - just create new with same properties)
     let mut child_process = Process::new(
         parent_process.name.clone(),
         VirtAddr::new(parent_process.context.rip),
         parent_process.priority
     )?;
+    let child_pid = child_process.pid;
     child_process.parent_pid = Some(current_pid);
     child_process.state = ProcessState::Ready;
     child_process.permissions = parent_process.permissions.clone();
     
+    // The child gets a copy-on-write image of the parent's memory in place of the empty
+    // address space it was created with
+    if let Some(parent_space) = parent_process.address_space_id {
+        let child_space = match crate::vmm::copy_address_space(parent_space) {
+            Ok(space) => space,
+            Err(error) => {
+                if let Some(empty_space) = child_process.address_space_id {
+                    let _ = crate::vmm::destroy_address_space(empty_space);
+                }
+                return Err(error);
+            }
+        };
+        if let Some(empty_space) = child_process.address_space_id.replace(child_space) {
+            let _ = crate::vmm::destroy_address_space(empty_space);
+        }
+        child_process.stack_base = parent_process.stack_base;
+        child_process.stack_size = parent_process.stack_size;
+        child_process.heap_base = parent_process.heap_base;
+        child_process.heap_size = parent_process.heap_size;
+        // The copy keeps the TLS block where it was
+        child_process.fs_base = parent_process.fs_base;
+        child_process.tls_template = parent_process.tls_template.clone();
+    }
+    
     // Initialize security context for child process
     let _ = crate::security::init_process_security(child_pid as u32, Some(current_pid as u32));
     
@@ -2375,7 +3389,7 @@ pub fn exec_process(path: &str, args: &[&str]) -> Result<(), ()> {
     
     // Get the current process
     let process = scheduler.processes.get_mut(current_pid as usize)
-        .and_then(|p| p.as_mut())
+        .and_then(|p| p.as_deref_mut())
         .ok_or(())?;
     
     // Check if we have permission to execute files
@@ -2405,8 +3419,14 @@ pub fn exec_process(path: &str, args: &[&str]) -> Result<(), ()> {
         Ok::<(), ()>(())
     }).map_err(|_| ())?;
     
-    // Load the ELF into the process's address space
-    crate::elf::load_elf(file_data, address_space_id).map_err(|_| ())?;
+    // Load the ELF into the process's address space, with a fresh TLS block if it has thread-locals
+    let (_, tls_template) = crate::elf::load_elf_with_tls(file_data, address_space_id).map_err(|_| ())?;
+    process.fs_base = 0;
+    process.tls_template = None;
+    if let Some(template) = tls_template {
+        process.setup_tls(alloc::sync::Arc::new(template)).map_err(|_| ())?;
+    }
+    load_fs_base(process.fs_base);
     
     // Set up user stack (8MB stack starting at high address)
     let stack_size = 8 * 1024 * 1024u64; // 8MB
@@ -2429,6 +3449,9 @@ pub fn exec_process(path: &str, args: &[&str]) -> Result<(), ()> {
         }
         Ok::<(), ()>(())
     }).map_err(|_| ())?;
+
+    // User probes check the probe page before paying for a syscall
+    crate::observability::user_probes::map_probe_page(address_space_id).map_err(|_| ())?;
     
     // Set up command line arguments on the stack
     let mut argv: Vec<String> = Vec::new();
@@ -2541,118 +3564,373 @@ pub fn spawn_user_process(name: &str, entry_point: VirtAddr) -> Result<u32, crat
     Ok(pid as u32)
 }
 
-pub fn wait_for_process(pid: ProcessId) -> Result<i32, ()> {
+/// Start the ELF executable at `path` in a new user process, a child of the current one
+pub fn spawn_program(path: &str) -> Result<ProcessId, ProcessError> {
+    let data = crate::filesystem::read_file(path).map_err(|_| ProcessError::NotExecutable)?;
+    let entry_point = crate::elf::validate_elf(&data).map_err(|_| ProcessError::NotExecutable)?;
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let mut process = Process::user_process(name.to_string(), entry_point).map_err(|_| ProcessError::OutOfMemory)?;
+    let address_space_id = process.address_space_id.ok_or(ProcessError::OutOfMemory)?;
+    let loaded = match crate::elf::load_elf_with_tls(data, address_space_id) {
+        Ok((_, Some(template))) => process.setup_tls(alloc::sync::Arc::new(template)).map_err(|_| ProcessError::OutOfMemory),
+        Ok((_, None)) => Ok(()),
+        Err(_) => Err(ProcessError::NotExecutable),
+    };
+    if let Err(error) = loaded {
+        let _ = crate::vmm::destroy_address_space(address_space_id);
+        return Err(error);
+    }
+
     let cpu_id = get_current_cpu_id();
     let mut scheduler = get_smp_scheduler().lock();
-    let current_pid = scheduler.get_current_process_id(cpu_id).ok_or(())?;
-    
-    // Check if the target process exists
-    let target_exists = scheduler.processes.get(pid as usize)
-        .and_then(|p| p.as_ref())
-        .is_some();
-    
-    if !target_exists {
-        return Err(()); // Process doesn't exist
-    }
-    
-    // Check if current process is parent of target process
-    let is_parent = scheduler.processes.get(pid as usize)
-        .and_then(|p| p.as_ref())
-        .map(|p| p.parent_pid == Some(current_pid))
-        .unwrap_or(false);
-    
-    if !is_parent {
-        return Err(()); // Can only wait for child processes
+    let parent_pid = scheduler.get_current_process_id(cpu_id);
+    process.parent_pid = parent_pid;
+    let _ = crate::security::init_process_security(process.pid as u32, parent_pid.map(|pid| pid as u32));
+    Ok(scheduler.add_process(process))
+}
+
+/// Wait for child `pid` to exit and return its exit code
+pub fn wait_for_process(pid: ProcessId) -> Result<i32, ()> {
+    wait_pid(Some(pid)).map(|(_, exit_code)| exit_code).map_err(|_| ())
+}
+
+pub fn get_process_count() -> u64 {
+    let scheduler = get_smp_scheduler().lock();
+    scheduler.processes.iter().filter(|p| p.is_some()).count() as u64
+}
+
+pub fn get_run_queue_lengths() -> Vec<usize> {
+    get_smp_scheduler().lock().run_queue_lengths()
+}
+
+/// Fixed-point shift of the load averages: 1.0 is `1 << LOAD_SHIFT`
+const LOAD_SHIFT: u32 = 11;
+const LOAD_ONE: u64 = 1 << LOAD_SHIFT;
+/// Time between samples of the run queues for the load averages
+pub const LOAD_SAMPLE_INTERVAL_MS: u64 = 5_000;
+/// Share of the 1, 5 and 15 minute averages kept at each sample, e^(-5s / period) in fixed point
+const LOAD_DECAY: [u64; 3] = [1884, 2014, 2037];
+/// Most missed samples made up at once: two and a half hours, by which time even the 15
+/// minute average has settled on the occupancy
+const LOAD_CATCH_UP_SAMPLES: u64 = 1_800;
+pub const LOADAVG_PATH: &str = "/proc/loadavg";
+
+/// The load averages, in fixed point, and the uptime at which the run queues are next sampled
+static LOAD_AVERAGES: [AtomicU64; 3] = [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+static NEXT_LOAD_SAMPLE_MS: AtomicU64 = AtomicU64::new(LOAD_SAMPLE_INTERVAL_MS);
+/// Set when the averages have moved since `/proc/loadavg` was last written
+static LOADAVG_STALE: AtomicBool = AtomicBool::new(true);
+
+/// Runnable processes averaged over the last 1, 5 and 15 minutes. Each sample of the run
+/// queues decays the averages exponentially towards it, as on Unix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LoadAverages {
+    averages: [u64; 3],
+}
+
+impl LoadAverages {
+    pub const fn new() -> Self {
+        Self { averages: [0; 3] }
     }
-    
-    // Check if process is already terminated
-    let target_state = scheduler.processes.get(pid as usize)
-        .and_then(|p| p.as_ref())
-        .map(|p| p.state)
-        .unwrap_or(ProcessState::Terminated);
-    
-    match target_state {
-        ProcessState::Terminated => {
-            // Process already terminated, get the actual exit code
-            let exit_code = scheduler.processes.get(pid as usize)
-                .and_then(|p| p.as_ref())
-                .map(|p| p.get_exit_code())
-                .unwrap_or(0);
-            
-            // Clean up the terminated process
-            if let Some(slot) = scheduler.processes.get_mut(pid as usize) {
-                *slot = None;
+
+    /// Fold in a sample of `runnable` processes
+    pub fn sample(&mut self, runnable: usize) {
+        let active = runnable as u64 * LOAD_ONE;
+        for (average, decay) in self.averages.iter_mut().zip(LOAD_DECAY) {
+            let mut next = *average * decay + active * (LOAD_ONE - decay);
+            // Round up while rising, so a steady load is reached rather than approached
+            if active >= *average {
+                next += LOAD_ONE - 1;
             }
-            drop(scheduler); // Release lock before cleanup
-            crate::security::cleanup_process_security(pid as u32);
-            
-            Ok(exit_code)
+            *average = next >> LOAD_SHIFT;
         }
-        _ => {
-            // Process still running, block current process until it terminates
-            if let Some(current_process) = scheduler.processes.get_mut(current_pid as usize)
-                .and_then(|p| p.as_mut()) {
-                current_process.state = ProcessState::Blocked;
-            }
-            
-            // Remove current process from all CPU ready queues
-            for cpu_scheduler in &scheduler.cpu_schedulers {
-                cpu_scheduler.lock().remove_process(current_pid);
+    }
+
+    /// The 1, 5 and 15 minute averages in hundredths
+    pub fn hundredths(&self) -> [u64; 3] {
+        self.averages.map(|average| (average * 100 + LOAD_ONE / 2) >> LOAD_SHIFT)
+    }
+
+    /// The 1, 5 and 15 minute averages
+    pub fn values(&self) -> (f32, f32, f32) {
+        let [one, five, fifteen] = self.averages.map(|average| average as f32 / LOAD_ONE as f32);
+        (one, five, fifteen)
+    }
+
+    /// The `/proc/loadavg` line: the averages, then `runnable/total` processes and the most
+    /// recently created PID
+    pub fn loadavg(&self, runnable: usize, total: u64, last_pid: u64) -> alloc::string::String {
+        let [one, five, fifteen] = self.hundredths();
+        alloc::format!(
+            "{}.{:02} {}.{:02} {}.{:02} {}/{} {}\n",
+            one / 100, one % 100, five / 100, five % 100, fifteen / 100, fifteen % 100, runnable, total, last_pid
+        )
+    }
+}
+
+/// The load averages together with when the run queues are next due to be sampled, stepped
+/// along by the uptime clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadSampler {
+    pub averages: LoadAverages,
+    next_sample_ms: u64,
+}
+
+impl Default for LoadSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoadSampler {
+    /// No load yet, with the first sample due one interval after boot
+    pub const fn new() -> Self {
+        Self { averages: LoadAverages::new(), next_sample_ms: LOAD_SAMPLE_INTERVAL_MS }
+    }
+
+    pub fn is_due(&self, now_ms: u64) -> bool {
+        now_ms >= self.next_sample_ms
+    }
+
+    /// Fold `runnable` into the averages once for every sample interval that has ended by
+    /// `now_ms`, so intervals whose ticks were lost still count. Returns the intervals that ended
+    pub fn tick(&mut self, now_ms: u64, runnable: usize) -> u64 {
+        if !self.is_due(now_ms) {
+            return 0;
+        }
+        let intervals = (now_ms - self.next_sample_ms) / LOAD_SAMPLE_INTERVAL_MS + 1;
+        for _ in 0..intervals.min(LOAD_CATCH_UP_SAMPLES) {
+            self.averages.sample(runnable);
+        }
+        self.next_sample_ms += intervals * LOAD_SAMPLE_INTERVAL_MS;
+        intervals
+    }
+}
+
+/// The system load averages
+pub fn load_averages() -> LoadAverages {
+    LoadAverages {
+        averages: [0, 1, 2].map(|index| LOAD_AVERAGES[index].load(Ordering::Relaxed)),
+    }
+}
+
+/// The system load averaged over the last 1, 5 and 15 minutes
+pub fn load_average() -> (f32, f32, f32) {
+    load_averages().values()
+}
+
+/// Sample the run queues into the load averages when `LOAD_SAMPLE_INTERVAL_MS` has passed
+fn sample_load(scheduler: &SmpScheduler) {
+    let now = crate::time::get_uptime_ms();
+    let mut sampler = LoadSampler { averages: load_averages(), next_sample_ms: NEXT_LOAD_SAMPLE_MS.load(Ordering::Relaxed) };
+    // Counting the occupancy takes every CPU's lock, so only do it when a sample is due
+    if !sampler.is_due(now) {
+        return;
+    }
+    sampler.tick(now, scheduler.runnable_count());
+    NEXT_LOAD_SAMPLE_MS.store(sampler.next_sample_ms, Ordering::Relaxed);
+    for (stored, average) in LOAD_AVERAGES.iter().zip(sampler.averages.averages) {
+        stored.store(average, Ordering::Relaxed);
+    }
+    LOADAVG_STALE.store(true, Ordering::Release);
+}
+
+/// Rewrite `/proc/loadavg` if the load averages have been sampled since it was last written.
+/// Sampling happens in the timer interrupt, so the file is written later from the main loop
+pub fn publish_load_average() -> Result<(), ()> {
+    if !LOADAVG_STALE.swap(false, Ordering::AcqRel) {
+        return Ok(());
+    }
+    let (runnable, total) = {
+        let scheduler = get_smp_scheduler().lock();
+        (scheduler.runnable_count(), scheduler.processes.iter().filter(|p| p.is_some()).count() as u64)
+    };
+    let text = load_averages().loadavg(runnable, total, NEXT_PID.load(Ordering::Relaxed).saturating_sub(1));
+    write_proc_file(LOADAVG_PATH, &text)
+}
+
+pub const SCHED_STATS_PATH: &str = "/proc/sched";
+/// Time between rewrites of `/proc/sched`
+pub const SCHED_STATS_INTERVAL_MS: u64 = 1_000;
+static NEXT_SCHED_STATS_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Every CPU's scheduler state, indexed by CPU ID and taken at a single moment
+pub fn sched_stats() -> Vec<CpuSchedStats> {
+    get_smp_scheduler().lock().sched_stats()
+}
+
+/// The `/proc/sched` text: a line per CPU, each followed by a line per process in its EDF
+/// and CBS queues
+pub fn sched_report(stats: &[CpuSchedStats]) -> alloc::string::String {
+    use core::fmt::Write;
+    let mut report = alloc::string::String::new();
+    for cpu in stats {
+        let current = cpu.current.map_or(alloc::string::String::from("-"), |pid| alloc::format!("{}", pid));
+        let [high, normal, low, gaming] = cpu.queue_lengths;
+        let _ = writeln!(
+            report,
+            "cpu{} current={} load={} switches={} isolated={} high={} normal={} low={} gaming={}",
+            cpu.cpu_id, current, cpu.load, cpu.context_switches, cpu.isolated as u8, high, normal, low, gaming
+        );
+        for (class, queue) in [("edf", &cpu.edf_queue), ("cbs", &cpu.cbs_queue)] {
+            for entry in queue {
+                let _ = writeln!(report, "  {} pid={} deadline_us={} budget_us={}", class, entry.pid, entry.deadline_us, entry.budget_us);
             }
-            
-            // In a real implementation, this would yield to scheduler
-            // For now, return a placeholder exit code
-            Ok(0)
         }
     }
+    report
 }
 
-pub fn get_process_count() -> u64 {
-    let scheduler = get_smp_scheduler().lock();
-    scheduler.processes.iter().filter(|p| p.is_some()).count() as u64
+/// Every process with its state, priority, CPU time and affinity, by PID
+pub fn process_stats() -> Vec<ProcessSchedStats> {
+    get_smp_scheduler().lock().process_stats()
+}
+
+/// The process table `ps` prints: a header, then a line per process
+pub fn process_report(processes: &[ProcessSchedStats]) -> alloc::string::String {
+    use core::fmt::Write;
+    let mut report = alloc::string::String::from("  PID STATE   PRIO     CPU_MS AFFINITY         NAME\n");
+    for process in processes {
+        let state = match process.state {
+            ProcessState::Ready => "ready",
+            ProcessState::Running => "running",
+            ProcessState::Blocked => "blocked",
+            ProcessState::Terminated => "exited",
+            ProcessState::Zombie(_) => "zombie",
+        };
+        let priority = match process.priority {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+            Priority::Gaming => "gaming",
+        };
+        let _ = writeln!(
+            report,
+            "{:>5} {:<7} {:<6} {:>8} {:016x} {}",
+            process.pid, state, priority, process.cpu_time / 1000, process.affinity.mask(), process.name
+        );
+    }
+    report
+}
+
+/// Print every CPU's scheduler state and the process table to the serial console, both
+/// taken at the same moment
+pub fn dump_scheduler() {
+    let (cpus, processes) = {
+        let scheduler = get_smp_scheduler().lock();
+        (scheduler.sched_stats(), scheduler.process_stats())
+    };
+    crate::serial::_print(format_args!("{}{}", sched_report(&cpus), process_report(&processes)));
+}
+
+/// Rewrite `/proc/sched` once `SCHED_STATS_INTERVAL_MS` has passed since it was last
+/// written. Called from the main loop
+pub fn publish_sched_stats() -> Result<(), ()> {
+    let now = crate::time::get_uptime_ms();
+    if now < NEXT_SCHED_STATS_MS.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    NEXT_SCHED_STATS_MS.store(now + SCHED_STATS_INTERVAL_MS, Ordering::Relaxed);
+    write_proc_file(SCHED_STATS_PATH, &sched_report(&sched_stats()))
+}
+
+/// Replace the file at `path` with `text`
+fn write_proc_file(path: &str, text: &str) -> Result<(), ()> {
+    use crate::filesystem::{O_CREAT, O_TRUNC, O_WRONLY};
+    let fd = crate::filesystem::open(path, O_WRONLY | O_CREAT | O_TRUNC).map_err(|_| ())?;
+    let written = crate::filesystem::write(fd, text.as_bytes());
+    let _ = crate::filesystem::close(fd);
+    written.map(|_| ()).map_err(|_| ())
 }
 
-/// Add exit code support to Process struct
 impl Process {
     pub fn set_exit_code(&mut self, code: i32) {
-        // Store exit code in unused field or add new field
-        // For now, we'll use a simple approach
-        self.cpu_time = code as u64; // Repurpose this field temporarily
+        self.exit_code = code;
     }
     
     pub fn get_exit_code(&self) -> i32 {
-        self.cpu_time as i32
+        self.exit_code
     }
 }
 
-/// Send a signal to a process
+/// Send a signal to the process `pid` belongs to, as `kill` does: it is left pending on the
+/// thread group leader, or on another of its threads once the leader has exited
 pub fn send_signal(pid: ProcessId, signal: Signal) -> Result<(), &'static str> {
-    let mut scheduler = get_smp_scheduler().lock();
-    
-    if let Some(process) = scheduler.processes.get_mut(pid as usize).and_then(|p| p.as_mut()) {
+    let thread_group = with_process(pid, |process| process.thread_group).ok_or("Process not found")?;
+    if signal == Signal::SIGKILL {
+        return kill_thread_group(thread_group);
+    }
+    let target = live_thread_of(&get_smp_scheduler().lock().processes, thread_group).unwrap_or(pid);
+    mark_signal_pending(target, signal)
+}
+
+/// Send a signal to thread `tid` of thread group `thread_group` alone, as `tgkill` does.
+/// SIGKILL still ends the whole group
+pub fn send_thread_signal(thread_group: u64, tid: u64, signal: Signal) -> Result<(), &'static str> {
+    if with_process(tid, |thread| thread.thread_group) != Some(thread_group) {
+        return Err("Thread not found");
+    }
+    if signal == Signal::SIGKILL {
+        return kill_thread_group(thread_group);
+    }
+    mark_signal_pending(tid, signal)
+}
+
+fn mark_signal_pending(pid: ProcessId, signal: Signal) -> Result<(), &'static str> {
+    let thread_group = {
+        let mut scheduler = get_smp_scheduler().lock();
+        let process = scheduler.processes.get_mut(pid as usize).and_then(|p| p.as_deref_mut()).ok_or("Process not found")?;
         // Set the signal bit in pending_signals
         process.pending_signals |= 1 << (signal as u8);
-        
-        // If it's SIGKILL, force terminate immediately
-        if signal == Signal::SIGKILL {
-            process.state = ProcessState::Terminated;
-            process.set_exit_code(-9); // SIGKILL exit code
-        }
-        
-        Ok(())
-    } else {
-        Err("Process not found")
+        process.thread_group
+    };
+    interrupt_sleep(pid);
+    crate::filesystem::signalfd::notify(thread_group, signal);
+    Ok(())
+}
+
+/// The signals in `mask` pending on any thread of `thread_group`
+pub fn pending_signals_of(thread_group: u64, mask: u64) -> u64 {
+    let scheduler = get_smp_scheduler().lock();
+    let pending = scheduler.processes.iter().flatten()
+        .filter(|thread| thread.thread_group == thread_group)
+        .fold(0, |pending, thread| pending | thread.pending_signals);
+    pending & mask
+}
+
+/// Take the lowest numbered signal in `mask` pending on a thread of `thread_group`, as
+/// delivered rather than handled
+pub fn take_pending_signal(thread_group: u64, mask: u64) -> Option<Signal> {
+    let mut scheduler = get_smp_scheduler().lock();
+    let thread = scheduler.processes.iter_mut().flatten()
+        .filter(|thread| thread.thread_group == thread_group && thread.pending_signals & mask != 0)
+        .min_by_key(|thread| (thread.pending_signals & mask).trailing_zeros())?;
+    let signal = (thread.pending_signals & mask).trailing_zeros();
+    thread.pending_signals &= !(1 << signal);
+    Signal::try_from(signal as u8).ok()
+}
+
+/// Force every thread of `thread_group` to exit, the calling thread last if it is one of them
+fn kill_thread_group(thread_group: u64) -> Result<(), &'static str> {
+    let current_pid = get_current_process_id();
+    let caller_in_group = with_process(current_pid, |process| process.thread_group) == Some(thread_group);
+    end_thread_group(thread_group, caller_in_group.then_some(current_pid), -9); // SIGKILL exit code
+    if caller_in_group {
+        exit_process(-9);
     }
+    Ok(())
 }
 
-/// Process pending signals for the current process
+/// Process pending signals for the current process, leaving those its signalfds take
 pub fn process_signals() {
     let current_pid = get_current_process_id();
+    let routed = with_process(current_pid, |process| process.thread_group)
+        .map_or(0, crate::filesystem::signalfd::routed_signals);
     let mut scheduler = get_smp_scheduler().lock();
     
-    if let Some(process) = scheduler.processes.get_mut(current_pid as usize).and_then(|p| p.as_mut()) {
-        let pending = process.pending_signals;
-        process.pending_signals = 0; // Clear pending signals
+    if let Some(process) = scheduler.processes.get_mut(current_pid as usize).and_then(|p| p.as_deref_mut()) {
+        let pending = process.pending_signals & !routed;
+        process.pending_signals &= routed; // Clear the signals handled here
         
         // Release scheduler lock before handling signals
         let handlers = process.signal_handlers;
@@ -2679,7 +3957,7 @@ pub fn set_signal_handler(signal: Signal, handler: Option<SignalHandler>) -> Res
     let current_pid = get_current_process_id();
     let mut scheduler = get_smp_scheduler().lock();
     
-    if let Some(process) = scheduler.processes.get_mut(current_pid as usize).and_then(|p| p.as_mut()) {
+    if let Some(process) = scheduler.processes.get_mut(current_pid as usize).and_then(|p| p.as_deref_mut()) {
         process.signal_handlers[signal as usize] = handler;
         Ok(())
     } else {
@@ -2689,12 +3967,13 @@ pub fn set_signal_handler(signal: Signal, handler: Option<SignalHandler>) -> Res
 
 /// Convert signal number to Signal enum
 impl Signal {
-    fn try_from(value: u8) -> Result<Self, &'static str> {
+    pub fn try_from(value: u8) -> Result<Self, &'static str> {
         match value {
             9 => Ok(Signal::SIGKILL),
             10 => Ok(Signal::SIGUSR1),
             12 => Ok(Signal::SIGUSR2),
             15 => Ok(Signal::SIGTERM),
+            17 => Ok(Signal::SIGCHLD),
             18 => Ok(Signal::SIGCONT),
             19 => Ok(Signal::SIGSTOP),
             _ => Err("Unknown signal"),
@@ -2703,31 +3982,13 @@ impl Signal {
 }
 
 /// Enhanced process termination with exit code
+/// Ends the calling thread alone; the rest of its thread group runs on
 pub fn exit_process(exit_code: i32) -> ! {
     let current_pid = get_current_process_id();
     
-    // Set exit code before cleanup
-    {
-        let mut scheduler = get_smp_scheduler().lock();
-        if let Some(process) = scheduler.processes.get_mut(current_pid as usize).and_then(|p| p.as_mut()) {
-            process.set_exit_code(exit_code);
-            process.state = ProcessState::Terminated;
-        }
-    }
-    
-    // Perform cleanup
-    cleanup_process_resources(current_pid as u32);
-    
-    // Remove from scheduler
-    get_smp_scheduler().lock().remove_process(current_pid);
-    // Wake any join waiters
-    {
-        let mut waiters = JOIN_WAITERS.lock();
-        if let Some(list) = waiters.remove(&current_pid) {
-            let mut sched = get_smp_scheduler().lock();
-            for w in list { sched.unblock_process(w); }
-        }
-    }
+    // Leave the exit code for the parent, take the process off the CPUs and wake any join
+    // waiters
+    end_process(current_pid, exit_code);
     
     // Force context switch to next process
     if let Some(next_pid) = schedule() {
@@ -2742,6 +4003,15 @@ pub fn exit_process(exit_code: i32) -> ! {
     }
 }
 
+/// End every thread of the calling thread's group with `exit_code`, the caller last
+pub fn exit_group(exit_code: i32) -> ! {
+    let current_pid = get_current_process_id();
+    if let Some(thread_group) = with_process(current_pid, |process| process.thread_group) {
+        end_thread_group(thread_group, Some(current_pid), exit_code);
+    }
+    exit_process(exit_code)
+}
+
 /// Terminate the current process due to a fatal error (e.g., stack overflow)
 pub fn terminate_current_process() -> ! {
     exit_process(-1); // Exit with error code -1
@@ -8,6 +8,8 @@ use alloc::collections::BTreeMap;
 use spin::Mutex;
 use lazy_static::lazy_static;
 
+pub mod terminal;
+
 // Application metadata
 #[derive(Debug, Clone)]
 pub struct AppInfo {
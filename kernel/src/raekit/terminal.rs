@@ -0,0 +1,1610 @@
+//! Terminal widget
+//! A character-cell terminal for the shell window. Output is kept as logical lines, wrapped
+//! to the current width only when shown, so a resize reflows the screen and the scrollback
+//! alike. The bottom `rows` display rows form the screen that cursor movement and erasing
+//! address; above it up to `SCROLLBACK_ROWS` rows of history stay reachable by scrolling.
+//! The escape sequences understood are those of VT100 and xterm that full-screen programs
+//! rely on: SGR colors (16, 256 and RGB) and attributes, cursor movement, positioning and
+//! saving, screen, line and character erasing, inserting and deleting lines and characters,
+//! scroll regions, and the alternate screen. Rows a scroll region or line insertion moves
+//! are split into logical lines of their own, as on a fixed grid. Other sequences, control
+//! strings and character set designations are consumed and ignored.
+//! A selection is held in the same logical coordinates, so it stays on its text through
+//! scrolling and reflow. The shell terminal selects with the mouse, copying what was selected
+//! to the clipboard, and pastes the clipboard into the command line. Keys reach the shell's
+//! line editor as the bytes a terminal would send for them.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use core::ops::Range;
+use spin::Mutex;
+
+use crate::graphics::{self, Color, Gesture, GestureEvent, GraphicsBuffer, KeyModifiers, MouseButton, MouseEvent, Rect, WindowEvent, WindowId};
+use crate::raeshell::editor::{EditResult, LineEditor};
+use crate::raeshell::monitor::{self, TopSampler};
+use crate::raeshell::{self, script, ShellResult};
+use crate::ui::clipboard;
+
+/// Display rows kept above the screen before the oldest lines are dropped
+pub const SCROLLBACK_ROWS: usize = 1000;
+
+pub const DEFAULT_FOREGROUND: Color = Color { r: 204, g: 204, b: 204, a: 255 };
+pub const DEFAULT_BACKGROUND: Color = Color { r: 30, g: 30, b: 30, a: 255 };
+
+/// Navigation keys have no character code and arrive as their set-1 scancode behind the
+/// 0xE0 extended-key prefix
+pub const KEY_UP: u32 = 0xE048;
+pub const KEY_DOWN: u32 = 0xE050;
+pub const KEY_PAGE_UP: u32 = 0xE049;
+pub const KEY_PAGE_DOWN: u32 = 0xE051;
+pub const KEY_LEFT: u32 = 0xE04B;
+pub const KEY_RIGHT: u32 = 0xE04D;
+pub const KEY_HOME: u32 = 0xE047;
+pub const KEY_END: u32 = 0xE04F;
+pub const KEY_DELETE: u32 = 0xE053;
+
+const TAB_WIDTH: usize = 8;
+
+/// Longest pause between the clicks of a double or triple click
+const MULTI_CLICK_MS: u64 = 400;
+
+/// Parameters kept for one control sequence; further ones are ignored
+const MAX_PARAMS: usize = 16;
+
+/// Black, red, green, yellow, blue, magenta, cyan and white, then their bright forms
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 49, 49),
+    (13, 188, 121),
+    (229, 229, 16),
+    (36, 114, 200),
+    (188, 63, 188),
+    (17, 168, 205),
+    (229, 229, 229),
+    (102, 102, 102),
+    (241, 76, 76),
+    (35, 209, 139),
+    (245, 245, 67),
+    (59, 142, 234),
+    (214, 112, 214),
+    (41, 184, 219),
+    (255, 255, 255),
+];
+
+bitflags! {
+    /// How a cell's character is drawn besides its colors. Dimming is already blended into
+    /// the cell's foreground
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct CellAttributes: u8 {
+        const BOLD          = 1 << 0;
+        const DIM           = 1 << 1;
+        const UNDERLINE     = 1 << 2;
+        const STRIKETHROUGH = 1 << 3;
+        const HIDDEN        = 1 << 4;
+    }
+}
+
+/// One character cell as shown
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cell {
+    pub ch: char,
+    pub foreground: Color,
+    pub background: Color,
+    pub attributes: CellAttributes,
+}
+
+impl Cell {
+    const BLANK: Cell = Cell { ch: ' ', foreground: DEFAULT_FOREGROUND, background: DEFAULT_BACKGROUND, attributes: CellAttributes::empty() };
+}
+
+/// Color of the 256-color palette at `index`: the 16 ANSI colors, a 6x6x6 cube and a gray ramp
+pub fn indexed_color(index: u8) -> Color {
+    match index {
+        0..=15 => {
+            let (r, g, b) = PALETTE[usize::from(index)];
+            Color::rgb(r, g, b)
+        }
+        16..=231 => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let cube = index - 16;
+            Color::rgb(level(cube / 36), level(cube / 6 % 6), level(cube % 6))
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            Color::rgb(gray, gray, gray)
+        }
+    }
+}
+
+/// A color as SGR selected it, resolved when a cell is written
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Ink {
+    Default,
+    Indexed(u8),
+    Rgb(Color),
+}
+
+/// Attributes given to the characters written next
+#[derive(Debug, Clone, Copy)]
+struct Pen {
+    foreground: Ink,
+    background: Ink,
+    attributes: CellAttributes,
+    inverse: bool,
+}
+
+impl Pen {
+    const DEFAULT: Pen = Pen { foreground: Ink::Default, background: Ink::Default, attributes: CellAttributes::empty(), inverse: false };
+
+    fn cell(&self, ch: char) -> Cell {
+        let mut foreground = match self.foreground {
+            Ink::Default => DEFAULT_FOREGROUND,
+            // Bold brightens the eight basic colors
+            Ink::Indexed(index) if self.attributes.contains(CellAttributes::BOLD) && index < 8 => indexed_color(index + 8),
+            Ink::Indexed(index) => indexed_color(index),
+            Ink::Rgb(color) => color,
+        };
+        let mut background = match self.background {
+            Ink::Default => DEFAULT_BACKGROUND,
+            Ink::Indexed(index) => indexed_color(index),
+            Ink::Rgb(color) => color,
+        };
+        if self.inverse {
+            core::mem::swap(&mut foreground, &mut background);
+        }
+        if self.attributes.contains(CellAttributes::DIM) {
+            foreground = halfway(foreground, background);
+        }
+        Cell { ch, foreground, background, attributes: self.attributes }
+    }
+
+    /// The cell erasing leaves: a blank in the background color, without attributes
+    fn blank(&self) -> Cell {
+        Cell { attributes: CellAttributes::empty(), ..self.cell(' ') }
+    }
+}
+
+/// The color halfway between `from` and `to`
+fn halfway(from: Color, to: Color) -> Color {
+    let mix = |a: u8, b: u8| ((u16::from(a) + u16::from(b)) / 2) as u8;
+    Color::rgb(mix(from.r, to.r), mix(from.g, to.g), mix(from.b, to.b))
+}
+
+/// What a selection grows by as the pointer drags it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionUnit {
+    Character,
+    Word,
+    Line,
+}
+
+/// A cell of a logical line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct TextPosition {
+    line: usize,
+    offset: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Selection {
+    /// Where the selection started
+    anchor: TextPosition,
+    /// Where the pointer is now
+    head: TextPosition,
+    unit: SelectionUnit,
+}
+
+/// The main screen, kept while the alternate screen is shown
+struct SavedScreen {
+    lines: VecDeque<Vec<Cell>>,
+    cursor_line: usize,
+    cursor_offset: usize,
+    wrap_pending: bool,
+    pen: Pen,
+}
+
+/// Cursor position and pen kept by `ESC 7` or `CSI s`
+#[derive(Debug, Clone, Copy)]
+struct SavedCursor {
+    row: usize,
+    column: usize,
+    pen: Pen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParseState {
+    Ground,
+    Escape,
+    /// After `ESC` and an intermediate byte, as in a character set designation, until the
+    /// final byte
+    EscapeIntermediate,
+    /// Inside `ESC [`, collecting parameters until the final byte
+    Csi,
+    /// Inside an operating system command or a device control, privacy or application
+    /// string, which runs to BEL or `ESC \`
+    ControlString,
+    ControlStringEscape,
+}
+
+pub struct Terminal {
+    columns: usize,
+    rows: usize,
+    /// Logical lines, oldest first, each as long as what was written to it
+    lines: VecDeque<Vec<Cell>>,
+    cursor_line: usize,
+    /// Cell of the cursor within its logical line
+    cursor_offset: usize,
+    /// The last written character filled its row; the cursor stays on it and the next
+    /// character starts the following row
+    wrap_pending: bool,
+    /// Display rows the view is scrolled back from the screen
+    scroll_offset: usize,
+    selection: Option<Selection>,
+    pen: Pen,
+    saved_cursor: Option<SavedCursor>,
+    /// Top and bottom screen rows that line feeds scroll, when not the whole screen
+    scroll_region: Option<(usize, usize)>,
+    /// The main screen while the alternate one is shown; the alternate screen keeps no
+    /// scrollback
+    primary: Option<SavedScreen>,
+    cursor_visible: bool,
+    /// Characters past the last column wrap to the next row rather than overwrite it
+    autowrap: bool,
+    state: ParseState,
+    params: Vec<u16>,
+    /// Private marker of the control sequence, such as the `?` of the DEC modes
+    marker: Option<char>,
+    /// The control sequence has intermediate bytes and is ignored
+    intermediate: bool,
+}
+
+impl Terminal {
+    pub fn new(columns: usize, rows: usize) -> Self {
+        let mut lines = VecDeque::new();
+        lines.push_back(Vec::new());
+        Terminal {
+            columns: columns.max(1),
+            rows: rows.max(1),
+            lines,
+            cursor_line: 0,
+            cursor_offset: 0,
+            wrap_pending: false,
+            scroll_offset: 0,
+            selection: None,
+            pen: Pen::DEFAULT,
+            saved_cursor: None,
+            scroll_region: None,
+            primary: None,
+            cursor_visible: true,
+            autowrap: true,
+            state: ParseState::Ground,
+            params: Vec::new(),
+            marker: None,
+            intermediate: false,
+        }
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Rows of history above the screen
+    pub fn scrollback_rows(&self) -> usize {
+        self.total_rows().saturating_sub(self.rows)
+    }
+
+    /// How far the view is scrolled back into the history, in rows
+    pub fn scroll_offset(&self) -> usize {
+        self.scroll_offset
+    }
+
+    /// Scroll the view `rows` rows back into the history, or forward when negative
+    pub fn scroll(&mut self, rows: isize) {
+        let offset = if rows >= 0 {
+            self.scroll_offset.saturating_add(rows.unsigned_abs())
+        } else {
+            self.scroll_offset.saturating_sub(rows.unsigned_abs())
+        };
+        self.scroll_offset = offset.min(self.scrollback_rows());
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll_offset = 0;
+    }
+
+    /// Change the size in cells, rewrapping every line to the new width
+    pub fn resize(&mut self, columns: usize, rows: usize) {
+        self.columns = columns.max(1);
+        self.rows = rows.max(1);
+        self.scroll_region = None;
+        self.settle_wrap();
+        self.materialize_cursor();
+        self.trim_scrollback();
+        self.scroll_offset = self.scroll_offset.min(self.scrollback_rows());
+    }
+
+    /// Feed output to the terminal. A view scrolled back into the history stays on the rows
+    /// it shows
+    pub fn write(&mut self, text: &str) {
+        let before = self.total_rows();
+        for ch in text.chars() {
+            self.process(ch);
+        }
+        if self.scroll_offset > 0 {
+            self.scroll_offset += self.total_rows().saturating_sub(before);
+        }
+        self.trim_scrollback();
+        self.scroll_offset = self.scroll_offset.min(self.scrollback_rows());
+    }
+
+    /// The cell shown at `row`, `column` of the view, or `None` outside it
+    pub fn cell(&self, row: usize, column: usize) -> Option<Cell> {
+        if column >= self.columns {
+            return None;
+        }
+        let cells = self.view_row(row)?;
+        Some(cells.get(column).copied().unwrap_or(Cell::BLANK))
+    }
+
+    /// Characters shown on `row` of the view, without trailing blanks
+    pub fn row_text(&self, row: usize) -> String {
+        let cells = self.view_row(row).unwrap_or(&[]);
+        let text: String = cells.iter().map(|cell| cell.ch).collect();
+        String::from(text.trim_end())
+    }
+
+    /// Row and column of the cursor in the view, if it is in view
+    pub fn cursor(&self) -> Option<(usize, usize)> {
+        let row = self.cursor_row().checked_sub(self.view_top())?;
+        (row < self.rows).then_some((row, self.cursor_offset % self.columns))
+    }
+
+    pub fn is_cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    /// Whether the alternate screen of full-screen programs is shown
+    pub fn is_alternate_screen(&self) -> bool {
+        self.primary.is_some()
+    }
+
+    /// Whether the next character would start a row
+    pub fn at_line_start(&self) -> bool {
+        !self.wrap_pending && self.cursor_offset.is_multiple_of(self.columns)
+    }
+
+    /// Begin a selection at `row`, `column` of the view, dropping any previous one. A
+    /// character selection is empty until it is extended to another cell
+    pub fn start_selection(&mut self, row: usize, column: usize, unit: SelectionUnit) {
+        let position = self.position_at(row, column);
+        self.selection = Some(Selection { anchor: position, head: position, unit });
+    }
+
+    /// Move the end of the selection to `row`, `column` of the view
+    pub fn extend_selection(&mut self, row: usize, column: usize) {
+        let position = self.position_at(row, column);
+        if let Some(selection) = self.selection.as_mut() {
+            selection.head = position;
+        }
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection = None;
+    }
+
+    /// The selected text, its logical lines joined by newlines and without trailing blanks
+    pub fn selected_text(&self) -> Option<String> {
+        let (start, end) = self.selection_bounds()?;
+        let mut text = String::new();
+        for line in start.line..=end.line {
+            let cells = self.lines.get(line).map_or(&[][..], |cells| cells.as_slice());
+            let from = if line == start.line { start.offset } else { 0 };
+            let to = if line == end.line { end.offset.min(cells.len()) } else { cells.len() };
+            let selected: String = cells.get(from..to).unwrap_or(&[]).iter().map(|cell| cell.ch).collect();
+            if line != start.line {
+                text.push('\n');
+            }
+            text.push_str(selected.trim_end());
+        }
+        Some(text)
+    }
+
+    /// Whether the cell at `row`, `column` of the view is selected
+    pub fn is_selected(&self, row: usize, column: usize) -> bool {
+        let Some((line, start)) = self.view_location(row) else {
+            return false;
+        };
+        let position = TextPosition { line, offset: start + column };
+        self.selection_bounds().is_some_and(|(first, end)| first <= position && position < end)
+    }
+
+    /// Draw the view into `buffer` with cells of `cell_width` by `cell_height` pixels, the
+    /// cursor and the selection as inverted cells
+    pub fn render(&self, buffer: &mut GraphicsBuffer, cell_width: u32, cell_height: u32) {
+        buffer.clear(DEFAULT_BACKGROUND);
+        let cursor = self.cursor().filter(|_| self.cursor_visible);
+        let bounds = self.selection_bounds();
+        let mut utf8 = [0; 4];
+        for row in 0..self.rows {
+            let location = self.view_location(row);
+            let cells = self.view_row(row).unwrap_or(&[]);
+            let y = row as i32 * cell_height as i32;
+            for column in 0..self.columns {
+                let mut cell = cells.get(column).copied().unwrap_or(Cell::BLANK);
+                let position = location.map(|(line, start)| TextPosition { line, offset: start + column });
+                let selected = position.zip(bounds).is_some_and(|(position, (first, end))| first <= position && position < end);
+                if selected != (cursor == Some((row, column))) {
+                    core::mem::swap(&mut cell.foreground, &mut cell.background);
+                }
+                let x = column as i32 * cell_width as i32;
+                if cell.background != DEFAULT_BACKGROUND {
+                    buffer.draw_rect(Rect::new(x, y, cell_width, cell_height), cell.background);
+                }
+                if cell.ch != ' ' && !cell.attributes.contains(CellAttributes::HIDDEN) {
+                    let glyph = cell.ch.encode_utf8(&mut utf8);
+                    graphics::draw_glyphs(buffer, x, y, glyph, cell.foreground);
+                    // Bold thickens the glyph by drawing it again a pixel over
+                    if cell.attributes.contains(CellAttributes::BOLD) {
+                        graphics::draw_glyphs(buffer, x + 1, y, glyph, cell.foreground);
+                    }
+                }
+                if cell.attributes.contains(CellAttributes::UNDERLINE) {
+                    buffer.draw_rect(Rect::new(x, y + cell_height as i32 - 2, cell_width, 1), cell.foreground);
+                }
+                if cell.attributes.contains(CellAttributes::STRIKETHROUGH) {
+                    buffer.draw_rect(Rect::new(x, y + cell_height as i32 / 2, cell_width, 1), cell.foreground);
+                }
+            }
+        }
+    }
+
+    fn line_rows(&self, line: &[Cell]) -> usize {
+        line.len().div_ceil(self.columns).max(1)
+    }
+
+    fn total_rows(&self) -> usize {
+        self.lines.iter().map(|line| self.line_rows(line)).sum()
+    }
+
+    /// Logical line and starting cell of display row `row`, counted from the oldest
+    fn locate(&self, row: usize) -> Option<(usize, usize)> {
+        let mut first = 0;
+        for (index, line) in self.lines.iter().enumerate() {
+            let rows = self.line_rows(line);
+            if row < first + rows {
+                return Some((index, (row - first) * self.columns));
+            }
+            first += rows;
+        }
+        None
+    }
+
+    fn cursor_row(&self) -> usize {
+        let above: usize = self.lines.iter().take(self.cursor_line).map(|line| self.line_rows(line)).sum();
+        above + self.cursor_offset / self.columns
+    }
+
+    fn screen_top(&self) -> usize {
+        self.scrollback_rows()
+    }
+
+    fn view_top(&self) -> usize {
+        self.screen_top().saturating_sub(self.scroll_offset)
+    }
+
+    /// Logical line and starting cell of `row` of the view, if text reaches that row
+    fn view_location(&self, row: usize) -> Option<(usize, usize)> {
+        if row >= self.rows {
+            return None;
+        }
+        self.locate(self.view_top() + row)
+    }
+
+    /// Cells of `row` of the view; shorter than the width when the rest is blank
+    fn view_row(&self, row: usize) -> Option<&[Cell]> {
+        if row >= self.rows {
+            return None;
+        }
+        let Some((line, start)) = self.view_location(row) else {
+            return Some(&[]);
+        };
+        let cells = self.lines.get(line)?;
+        let end = (start + self.columns).min(cells.len());
+        Some(cells.get(start..end).unwrap_or(&[]))
+    }
+
+    /// Text position under `row`, `column` of the view; positions past the last row fall on it
+    fn position_at(&self, row: usize, column: usize) -> TextPosition {
+        let row = (self.view_top() + row.min(self.rows - 1)).min(self.total_rows().saturating_sub(1));
+        let (line, start) = self.locate(row).unwrap_or((0, 0));
+        TextPosition { line, offset: start + column.min(self.columns - 1) }
+    }
+
+    /// First selected cell and the cell just past the last, with words and lines completed
+    /// for those units
+    fn selection_bounds(&self) -> Option<(TextPosition, TextPosition)> {
+        let selection = self.selection?;
+        let first = selection.anchor.min(selection.head);
+        let last = selection.anchor.max(selection.head);
+        match selection.unit {
+            SelectionUnit::Character if first == last => None,
+            SelectionUnit::Character => Some((first, TextPosition { offset: last.offset + 1, ..last })),
+            SelectionUnit::Word => {
+                let (start, _) = self.word_span(first);
+                let (_, end) = self.word_span(last);
+                Some((TextPosition { offset: start, ..first }, TextPosition { offset: end, ..last }))
+            }
+            SelectionUnit::Line => {
+                let end = self.lines.get(last.line).map_or(0, |cells| cells.len());
+                Some((TextPosition { offset: 0, ..first }, TextPosition { offset: end, ..last }))
+            }
+        }
+    }
+
+    /// Cells of the word around `position`: a run of word characters, a run of blanks, or
+    /// else the single character there
+    fn word_span(&self, position: TextPosition) -> (usize, usize) {
+        let cells = self.lines.get(position.line).map_or(&[][..], |cells| cells.as_slice());
+        let class = |offset: usize| {
+            let ch = cells.get(offset).map_or(' ', |cell| cell.ch);
+            if ch.is_whitespace() {
+                0
+            } else if ch.is_alphanumeric() || "_-./~".contains(ch) {
+                1
+            } else {
+                2
+            }
+        };
+        let target = class(position.offset);
+        if target == 2 {
+            return (position.offset, position.offset + 1);
+        }
+        let mut start = position.offset;
+        while start > 0 && class(start - 1) == target {
+            start -= 1;
+        }
+        let mut end = position.offset + 1;
+        while end < cells.len() && class(end) == target {
+            end += 1;
+        }
+        (start, end)
+    }
+
+    /// Drop the oldest line, and the selection when it started there
+    fn drop_oldest_line(&mut self) -> Option<Vec<Cell>> {
+        let line = self.lines.pop_front()?;
+        self.cursor_line = self.cursor_line.saturating_sub(1);
+        self.selection = self.selection.filter(|selection| selection.anchor.line > 0 && selection.head.line > 0).map(|mut selection| {
+            selection.anchor.line -= 1;
+            selection.head.line -= 1;
+            selection
+        });
+        Some(line)
+    }
+
+    /// Start the next row now if the row a pending wrap waits on is no longer full, after
+    /// the width changed
+    fn settle_wrap(&mut self) {
+        if self.wrap_pending && !(self.cursor_offset + 1).is_multiple_of(self.columns) {
+            self.cursor_offset += 1;
+            self.wrap_pending = false;
+        }
+    }
+
+    /// Pad the cursor's line so the row the cursor rests on exists
+    fn materialize_cursor(&mut self) {
+        let offset = self.cursor_offset;
+        let needed = if offset > 0 && offset.is_multiple_of(self.columns) { offset + 1 } else { offset };
+        if let Some(line) = self.lines.get_mut(self.cursor_line) {
+            if line.len() < needed {
+                line.resize(needed, Cell::BLANK);
+            }
+        }
+    }
+
+    /// Put the cursor on display row `row`, adding empty lines to reach it
+    fn move_to_row(&mut self, row: usize, column: usize) {
+        for _ in self.total_rows()..=row {
+            self.lines.push_back(Vec::new());
+        }
+        if let Some((line, start)) = self.locate(row) {
+            self.cursor_line = line;
+            self.cursor_offset = start + column.min(self.columns - 1);
+            self.wrap_pending = false;
+            self.materialize_cursor();
+        }
+    }
+
+    /// Cursor position on the screen
+    fn screen_position(&self) -> (usize, usize) {
+        let row = self.cursor_row().saturating_sub(self.screen_top()).min(self.rows - 1);
+        (row, self.cursor_offset % self.columns)
+    }
+
+    fn set_screen_position(&mut self, row: usize, column: usize) {
+        self.move_to_row(self.screen_top() + row.min(self.rows - 1), column);
+    }
+
+    /// Top and bottom rows of the scroll region
+    fn region(&self) -> (usize, usize) {
+        self.scroll_region.unwrap_or((0, self.rows - 1))
+    }
+
+    /// Add empty lines until there are rows enough to fill the screen
+    fn fill_screen(&mut self) {
+        for _ in self.total_rows()..self.rows {
+            self.lines.push_back(Vec::new());
+        }
+    }
+
+    /// Give each row of the screen a logical line of its own, so rows can be moved and
+    /// replaced as on a fixed grid. A line wrapping from the scrollback onto the screen is cut
+    /// at the screen's top
+    fn isolate_screen(&mut self) {
+        self.fill_screen();
+        let (row, column) = self.screen_position();
+        let top = self.screen_top();
+        let columns = self.columns;
+        let mut lines = VecDeque::with_capacity(self.lines.len() + self.rows);
+        let mut first = 0;
+        for mut line in self.lines.drain(..) {
+            let rows = line.len().div_ceil(columns).max(1);
+            // Rows of the line above the screen stay together
+            let kept = top.saturating_sub(first);
+            first += rows;
+            if kept >= rows {
+                lines.push_back(line);
+                continue;
+            }
+            let mut rest = line.split_off(kept * columns);
+            if kept > 0 {
+                lines.push_back(line);
+            }
+            while rest.len() > columns {
+                let tail = rest.split_off(columns);
+                lines.push_back(rest);
+                rest = tail;
+            }
+            lines.push_back(rest);
+        }
+        self.lines = lines;
+        self.cursor_line = self.lines.len() - self.rows + row;
+        self.cursor_offset = column;
+        self.selection = None;
+        self.materialize_cursor();
+    }
+
+    /// Remove `count` screen rows at `top`, pulling the rows below up to `bottom` up and
+    /// blank rows in above `bottom`
+    fn delete_rows(&mut self, top: usize, bottom: usize, count: usize) {
+        self.isolate_screen();
+        let base = self.lines.len() - self.rows;
+        for _ in 0..count.min(bottom + 1 - top) {
+            self.lines.remove(base + top);
+            self.lines.insert(base + bottom, Vec::new());
+        }
+        self.wrap_pending = false;
+        self.materialize_cursor();
+    }
+
+    /// Insert `count` blank screen rows at `top`, pushing the rows below it down and those
+    /// past `bottom` out
+    fn insert_rows(&mut self, top: usize, bottom: usize, count: usize) {
+        self.isolate_screen();
+        let base = self.lines.len() - self.rows;
+        for _ in 0..count.min(bottom + 1 - top) {
+            self.lines.remove(base + bottom);
+            self.lines.insert(base + top, Vec::new());
+        }
+        self.wrap_pending = false;
+        self.materialize_cursor();
+    }
+
+    /// Scroll the scroll region up `count` rows. Rows leaving the top of the whole screen go
+    /// into the scrollback
+    fn scroll_up(&mut self, count: usize) {
+        match self.scroll_region {
+            Some((top, bottom)) => self.delete_rows(top, bottom, count),
+            None => {
+                let (row, column) = self.screen_position();
+                self.fill_screen();
+                for _ in 0..count.min(self.rows) {
+                    self.lines.push_back(Vec::new());
+                }
+                self.set_screen_position(row, column);
+            }
+        }
+    }
+
+    /// Scroll the scroll region down `count` rows, dropping the rows leaving its bottom
+    fn scroll_down(&mut self, count: usize) {
+        let (top, bottom) = self.region();
+        self.insert_rows(top, bottom, count);
+    }
+
+    /// Drop the oldest lines beyond the scrollback limit
+    fn trim_scrollback(&mut self) {
+        let limit = if self.primary.is_some() { 0 } else { SCROLLBACK_ROWS };
+        let mut excess = self.total_rows().saturating_sub(self.rows + limit);
+        while excess > 0 && self.cursor_line > 0 {
+            let Some(line) = self.drop_oldest_line() else {
+                break;
+            };
+            excess = excess.saturating_sub(self.line_rows(&line));
+        }
+    }
+
+    fn process(&mut self, ch: char) {
+        match self.state {
+            ParseState::Ground => match ch {
+                '\x1b' => self.state = ParseState::Escape,
+                // Vertical tab and form feed move down like line feed
+                '\n' | '\x0b' | '\x0c' => self.newline(),
+                '\r' => self.carriage_return(),
+                '\x08' => self.backspace(),
+                '\t' => self.tab(),
+                ch if ch.is_control() => {}
+                ch => self.print(ch),
+            },
+            ParseState::Escape => {
+                self.state = ParseState::Ground;
+                match ch {
+                    '[' => {
+                        self.params.clear();
+                        self.marker = None;
+                        self.intermediate = false;
+                        self.state = ParseState::Csi;
+                    }
+                    ']' | 'P' | 'X' | '^' | '_' => self.state = ParseState::ControlString,
+                    ' '..='/' => self.state = ParseState::EscapeIntermediate,
+                    'D' => self.index(),
+                    'E' => self.newline(),
+                    'M' => self.reverse_index(),
+                    '7' => self.save_cursor(),
+                    '8' => self.restore_cursor(),
+                    'c' => self.reset(),
+                    _ => {}
+                }
+            }
+            ParseState::EscapeIntermediate => {
+                if !matches!(ch, ' '..='/') {
+                    self.state = ParseState::Ground;
+                }
+            }
+            ParseState::Csi => match ch {
+                '0'..='9' => {
+                    if self.params.is_empty() {
+                        self.params.push(0);
+                    }
+                    if let Some(param) = self.params.last_mut() {
+                        let digit = ch as u16 - u16::from(b'0');
+                        *param = param.saturating_mul(10).saturating_add(digit);
+                    }
+                }
+                // Colon-separated subparameters are read as parameters of their own
+                ';' | ':' => {
+                    if self.params.is_empty() {
+                        self.params.push(0);
+                    }
+                    if self.params.len() < MAX_PARAMS {
+                        self.params.push(0);
+                    }
+                }
+                '<'..='?' => self.marker = Some(ch),
+                ' '..='/' => self.intermediate = true,
+                '@'..='~' => {
+                    self.state = ParseState::Ground;
+                    match (self.marker, self.intermediate) {
+                        (None, false) => self.control(ch),
+                        (Some('?'), false) if ch == 'h' || ch == 'l' => self.set_private_modes(ch == 'h'),
+                        // Other private and intermediate sequences change nothing shown
+                        _ => {}
+                    }
+                }
+                '\x1b' => self.state = ParseState::Escape,
+                _ => self.state = ParseState::Ground,
+            },
+            ParseState::ControlString => match ch {
+                '\x07' => self.state = ParseState::Ground,
+                '\x1b' => self.state = ParseState::ControlStringEscape,
+                _ => {}
+            },
+            ParseState::ControlStringEscape => self.state = ParseState::Ground,
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Terminal::new(self.columns, self.rows);
+    }
+
+    fn print(&mut self, ch: char) {
+        if self.wrap_pending {
+            self.wrap();
+        }
+        let cell = self.pen.cell(ch);
+        let offset = self.cursor_offset;
+        let Some(line) = self.lines.get_mut(self.cursor_line) else {
+            return;
+        };
+        if let Some(existing) = line.get_mut(offset) {
+            *existing = cell;
+        } else {
+            line.resize(offset, Cell::BLANK);
+            line.push(cell);
+        }
+        if !(offset + 1).is_multiple_of(self.columns) {
+            self.cursor_offset += 1;
+        } else if self.autowrap {
+            self.wrap_pending = true;
+        }
+    }
+
+    /// Carry the cursor onto the next row after it filled one. When only empty rows follow,
+    /// the next row joins the cursor's logical line, keeping the line whole for reflow;
+    /// wrapping onto a row of other text, or past the scroll region, moves as on a fixed grid
+    fn wrap(&mut self) {
+        self.wrap_pending = false;
+        let continued = self.lines.get(self.cursor_line).is_some_and(|line| line.len() > self.cursor_offset + 1);
+        let at_region_bottom = self.scroll_region.is_some_and(|(_, bottom)| self.screen_position().0 == bottom);
+        if continued {
+            self.cursor_offset += 1;
+        } else if at_region_bottom {
+            self.newline();
+        } else {
+            match self.lines.get(self.cursor_line + 1) {
+                None => self.cursor_offset += 1,
+                Some(next) if next.is_empty() => {
+                    self.lines.remove(self.cursor_line + 1);
+                    self.cursor_offset += 1;
+                }
+                Some(_) => self.newline(),
+            }
+        }
+    }
+
+    fn carriage_return(&mut self) {
+        self.wrap_pending = false;
+        self.cursor_offset -= self.cursor_offset % self.columns;
+    }
+
+    /// Move to the start of the next row. Line feed implies carriage return, as shell output
+    /// ends lines with `\n` alone
+    fn newline(&mut self) {
+        self.carriage_return();
+        self.index();
+    }
+
+    /// Move down a row in the same column. At the bottom of the scroll region the region
+    /// scrolls; past the bottom of the screen without one, output goes on to a new row
+    fn index(&mut self) {
+        let (row, column) = self.screen_position();
+        if let Some((_, bottom)) = self.scroll_region {
+            if row == bottom {
+                self.scroll_up(1);
+                return;
+            }
+            if row + 1 == self.rows {
+                return;
+            }
+        }
+        let next = self.cursor_row() + 1;
+        match self.locate(next) {
+            Some((line, start)) => {
+                self.cursor_line = line;
+                self.cursor_offset = start + column;
+            }
+            None => {
+                self.lines.push_back(Vec::new());
+                self.cursor_line = self.lines.len() - 1;
+                self.cursor_offset = column;
+            }
+        }
+        self.wrap_pending = false;
+        self.materialize_cursor();
+    }
+
+    /// Move up a row in the same column, scrolling the region down at its top
+    fn reverse_index(&mut self) {
+        let (row, column) = self.screen_position();
+        if row == self.region().0 {
+            self.scroll_down(1);
+        } else if row > 0 {
+            self.set_screen_position(row - 1, column);
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        let (row, column) = self.screen_position();
+        self.saved_cursor = Some(SavedCursor { row, column, pen: self.pen });
+    }
+
+    /// Return to the saved cursor position and pen, or the home position and default pen
+    fn restore_cursor(&mut self) {
+        let saved = self.saved_cursor.unwrap_or(SavedCursor { row: 0, column: 0, pen: Pen::DEFAULT });
+        self.pen = saved.pen;
+        self.set_screen_position(saved.row, saved.column);
+    }
+
+    /// Show an empty alternate screen, keeping the main screen with its cursor and pen
+    fn enter_alternate_screen(&mut self) {
+        if self.primary.is_some() {
+            return;
+        }
+        let (row, column) = self.screen_position();
+        let mut lines = VecDeque::new();
+        lines.push_back(Vec::new());
+        self.primary = Some(SavedScreen {
+            lines: core::mem::replace(&mut self.lines, lines),
+            cursor_line: self.cursor_line,
+            cursor_offset: self.cursor_offset,
+            wrap_pending: self.wrap_pending,
+            pen: self.pen,
+        });
+        self.cursor_line = 0;
+        self.cursor_offset = 0;
+        self.scroll_offset = 0;
+        self.selection = None;
+        self.set_screen_position(row, column);
+    }
+
+    /// Discard the alternate screen and show the main one as it was left, reflowed to the
+    /// current width
+    fn leave_alternate_screen(&mut self) {
+        let Some(primary) = self.primary.take() else {
+            return;
+        };
+        self.lines = primary.lines;
+        self.cursor_line = primary.cursor_line;
+        self.cursor_offset = primary.cursor_offset;
+        self.wrap_pending = primary.wrap_pending;
+        self.pen = primary.pen;
+        self.scroll_offset = 0;
+        self.selection = None;
+        self.settle_wrap();
+        self.materialize_cursor();
+    }
+
+    /// Apply the DEC private modes of the control sequence, set or reset
+    fn set_private_modes(&mut self, enable: bool) {
+        let modes = core::mem::take(&mut self.params);
+        for mode in modes {
+            match mode {
+                7 => self.autowrap = enable,
+                25 => self.cursor_visible = enable,
+                47 | 1047 | 1049 if enable => self.enter_alternate_screen(),
+                47 | 1047 | 1049 => self.leave_alternate_screen(),
+                _ => {}
+            }
+        }
+    }
+
+    /// Step back one cell, into the previous row when the line wrapped there, so erasing
+    /// echoed input works across wrapped rows
+    fn backspace(&mut self) {
+        if self.wrap_pending {
+            self.wrap_pending = false;
+        } else if self.cursor_offset > 0 {
+            self.cursor_offset -= 1;
+        }
+    }
+
+    fn tab(&mut self) {
+        if self.wrap_pending {
+            return;
+        }
+        let column = self.cursor_offset % self.columns;
+        let stop = ((column / TAB_WIDTH + 1) * TAB_WIDTH).min(self.columns - 1);
+        self.cursor_offset += stop - column;
+        self.materialize_cursor();
+    }
+
+    /// Parameter `index` of the control sequence, with 0 or a missing one read as 1
+    fn count(&self, index: usize) -> usize {
+        self.params.get(index).copied().filter(|&param| param != 0).map_or(1, usize::from)
+    }
+
+    fn control(&mut self, action: char) {
+        let (row, column) = self.screen_position();
+        let mode = self.params.first().copied().unwrap_or(0);
+        match action {
+            'A' => self.set_screen_position(row.saturating_sub(self.count(0)), column),
+            'B' => self.set_screen_position(row.saturating_add(self.count(0)), column),
+            'C' => self.set_screen_position(row, column.saturating_add(self.count(0))),
+            'D' => self.set_screen_position(row, column.saturating_sub(self.count(0))),
+            'E' => self.set_screen_position(row.saturating_add(self.count(0)), 0),
+            'F' => self.set_screen_position(row.saturating_sub(self.count(0)), 0),
+            'G' => self.set_screen_position(row, self.count(0) - 1),
+            'H' | 'f' => self.set_screen_position(self.count(0) - 1, self.count(1) - 1),
+            'd' => self.set_screen_position(self.count(0) - 1, column),
+            '`' => self.set_screen_position(row, self.count(0) - 1),
+            'J' => self.erase_display(mode),
+            'K' => self.erase_line(mode),
+            '@' => self.insert_cells(self.count(0)),
+            'P' => self.delete_cells(self.count(0)),
+            'X' => {
+                let offset = self.cursor_offset;
+                let end = (offset + self.count(0)).min(offset - column + self.columns);
+                self.blank_cells(self.cursor_line, offset..end);
+            }
+            'L' | 'M' => {
+                let (top, bottom) = self.region();
+                if (top..=bottom).contains(&row) {
+                    if action == 'L' {
+                        self.insert_rows(row, bottom, self.count(0));
+                    } else {
+                        self.delete_rows(row, bottom, self.count(0));
+                    }
+                    self.carriage_return();
+                }
+            }
+            'S' => self.scroll_up(self.count(0)),
+            'T' => self.scroll_down(self.count(0)),
+            'r' => self.set_scroll_region(),
+            's' => self.save_cursor(),
+            'u' => self.restore_cursor(),
+            'm' => self.select_graphic_rendition(),
+            _ => {}
+        }
+    }
+
+    /// Limit scrolling to the rows between the two parameters, the whole screen when they
+    /// are missing, and home the cursor
+    fn set_scroll_region(&mut self) {
+        let top = self.count(0) - 1;
+        let bottom = self.params.get(1).copied().filter(|&param| param != 0).map_or(self.rows, usize::from).min(self.rows) - 1;
+        if top < bottom {
+            self.scroll_region = (top > 0 || bottom + 1 < self.rows).then_some((top, bottom));
+            self.set_screen_position(0, 0);
+        }
+    }
+
+    /// Insert `count` blanks at the cursor, shifting the rest of its row right; cells pushed
+    /// past the last column are lost
+    fn insert_cells(&mut self, count: usize) {
+        let offset = self.cursor_offset;
+        let end = offset - offset % self.columns + self.columns;
+        let blank = self.pen.blank();
+        let Some(line) = self.lines.get_mut(self.cursor_line) else {
+            return;
+        };
+        if line.len() <= offset {
+            return;
+        }
+        let row_end = end.min(line.len() + count);
+        if line.len() < row_end {
+            line.resize(row_end, Cell::BLANK);
+        }
+        if let Some(cells) = line.get_mut(offset..row_end) {
+            let count = count.min(cells.len());
+            cells.rotate_right(count);
+            cells.iter_mut().take(count).for_each(|cell| *cell = blank);
+        }
+    }
+
+    /// Delete `count` cells at the cursor, shifting the rest of its row left and blanks in at
+    /// its end
+    fn delete_cells(&mut self, count: usize) {
+        let offset = self.cursor_offset;
+        let end = offset - offset % self.columns + self.columns;
+        let blank = self.pen.blank();
+        let Some(line) = self.lines.get_mut(self.cursor_line) else {
+            return;
+        };
+        let row_end = end.min(line.len());
+        if let Some(cells) = line.get_mut(offset..row_end) {
+            let count = count.min(cells.len());
+            cells.rotate_left(count);
+            cells.iter_mut().rev().take(count).for_each(|cell| *cell = blank);
+        }
+    }
+
+    fn blank_cells(&mut self, line: usize, range: Range<usize>) {
+        let blank = self.pen.blank();
+        if let Some(cells) = self.lines.get_mut(line) {
+            let end = range.end.min(cells.len());
+            for cell in cells.iter_mut().take(end).skip(range.start) {
+                *cell = blank;
+            }
+        }
+    }
+
+    fn erase_line(&mut self, mode: u16) {
+        let offset = self.cursor_offset;
+        let start = offset - offset % self.columns;
+        let range = match mode {
+            0 => offset..start + self.columns,
+            1 => start..offset + 1,
+            2 => start..start + self.columns,
+            _ => return,
+        };
+        self.blank_cells(self.cursor_line, range);
+    }
+
+    fn erase_display(&mut self, mode: u16) {
+        match mode {
+            // Cursor to the end of the screen
+            0 => {
+                self.blank_cells(self.cursor_line, self.cursor_offset..usize::MAX);
+                for line in self.cursor_line + 1..self.lines.len() {
+                    self.blank_cells(line, 0..usize::MAX);
+                }
+            }
+            // Top of the screen to the cursor
+            1 => {
+                for row in self.screen_top()..self.cursor_row() {
+                    if let Some((line, start)) = self.locate(row) {
+                        self.blank_cells(line, start..start + self.columns);
+                    }
+                }
+                self.erase_line(1);
+            }
+            // The whole screen, whose rows move into the scrollback
+            2 => {
+                let (row, column) = self.screen_position();
+                while self.lines.len() > self.cursor_line + 1 && self.lines.back().is_some_and(|line| line.is_empty()) {
+                    self.lines.pop_back();
+                }
+                for _ in 0..self.rows {
+                    self.lines.push_back(Vec::new());
+                }
+                self.set_screen_position(row, column);
+            }
+            // The scrollback
+            3 => {
+                if let Some((first, _)) = self.locate(self.screen_top()) {
+                    for _ in 0..first.min(self.cursor_line) {
+                        self.drop_oldest_line();
+                    }
+                }
+                self.scroll_offset = 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn select_graphic_rendition(&mut self) {
+        let params = core::mem::take(&mut self.params);
+        if params.is_empty() {
+            self.pen = Pen::DEFAULT;
+        }
+        let mut index = 0;
+        while let Some(&code) = params.get(index) {
+            match code {
+                0 => self.pen = Pen::DEFAULT,
+                1 => self.pen.attributes.insert(CellAttributes::BOLD),
+                2 => self.pen.attributes.insert(CellAttributes::DIM),
+                4 | 21 => self.pen.attributes.insert(CellAttributes::UNDERLINE),
+                8 => self.pen.attributes.insert(CellAttributes::HIDDEN),
+                9 => self.pen.attributes.insert(CellAttributes::STRIKETHROUGH),
+                22 => self.pen.attributes.remove(CellAttributes::BOLD | CellAttributes::DIM),
+                24 => self.pen.attributes.remove(CellAttributes::UNDERLINE),
+                28 => self.pen.attributes.remove(CellAttributes::HIDDEN),
+                29 => self.pen.attributes.remove(CellAttributes::STRIKETHROUGH),
+                7 => self.pen.inverse = true,
+                27 => self.pen.inverse = false,
+                30..=37 => self.pen.foreground = Ink::Indexed((code - 30) as u8),
+                39 => self.pen.foreground = Ink::Default,
+                40..=47 => self.pen.background = Ink::Indexed((code - 40) as u8),
+                49 => self.pen.background = Ink::Default,
+                90..=97 => self.pen.foreground = Ink::Indexed((code - 90 + 8) as u8),
+                100..=107 => self.pen.background = Ink::Indexed((code - 100 + 8) as u8),
+                38 | 48 => {
+                    let (ink, used) = extended_color(params.get(index + 1..).unwrap_or(&[]));
+                    if let Some(ink) = ink {
+                        if code == 38 {
+                            self.pen.foreground = ink;
+                        } else {
+                            self.pen.background = ink;
+                        }
+                    }
+                    index += used;
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+    }
+}
+
+/// Read the color of a `38` or `48` SGR code from the parameters after it, `5;n` for the
+/// palette or `2;r;g;b`, returning it and the parameters it used
+fn extended_color(params: &[u16]) -> (Option<Ink>, usize) {
+    let channel = |value: u16| u8::try_from(value).unwrap_or(u8::MAX);
+    match params {
+        [5, index, ..] => (Some(Ink::Indexed(channel(*index))), 2),
+        [2, r, g, b, ..] => (Some(Ink::Rgb(Color::rgb(channel(*r), channel(*g), channel(*b)))), 4),
+        [5] => (None, 1),
+        [2, rest @ ..] => (None, 1 + rest.len()),
+        _ => (None, 0),
+    }
+}
+
+/// A terminal attached to a shell session, fed the events of its window
+pub struct ShellTerminal {
+    session: u32,
+    terminal: Terminal,
+    /// Edits the command line being typed
+    editor: LineEditor,
+    /// Lines of a command still missing its end, such as an `if` awaiting its `fi`
+    script: String,
+    /// Scroll gesture travel not yet amounting to a row, in pixels
+    scroll_pixels: i32,
+    /// The left button is down and dragging a selection
+    selecting: bool,
+    /// Time and cell of the last left click, for counting double and triple clicks
+    last_click: Option<(u64, usize, usize)>,
+    clicks: u8,
+    /// `top` running live on the alternate screen, in place of the prompt
+    live_top: Option<LiveTop>,
+}
+
+/// A live `top`, redrawn each interval until `q` or Ctrl-C
+struct LiveTop {
+    sampler: TopSampler,
+    interval_ms: u64,
+    /// Uptime in milliseconds at which to redraw next
+    next_refresh_ms: u64,
+}
+
+static SHELL_TERMINALS: Mutex<BTreeMap<WindowId, ShellTerminal>> = Mutex::new(BTreeMap::new());
+
+/// Size of a terminal cell, from the advance and line height of the current font
+pub fn cell_size() -> (u32, u32) {
+    (graphics::get_text_width("M").max(1), graphics::get_text_height().max(1))
+}
+
+/// Cell under window position `x`, `y`
+fn cell_at(x: i32, y: i32) -> (usize, usize) {
+    let (cell_width, cell_height) = cell_size();
+    ((y.max(0) as u32 / cell_height) as usize, (x.max(0) as u32 / cell_width) as usize)
+}
+
+/// Whether a key is Ctrl-Shift with `letter`, given as its capital
+fn is_shortcut(key_code: u32, modifiers: KeyModifiers, letter: u8) -> bool {
+    modifiers.contains(KeyModifiers::CTRL | KeyModifiers::SHIFT)
+        && u8::try_from(key_code).is_ok_and(|key| [letter, letter.to_ascii_lowercase(), letter - b'@'].contains(&key))
+}
+
+impl ShellTerminal {
+    pub fn new(session: u32, columns: usize, rows: usize) -> Self {
+        let mut editor = LineEditor::new(columns);
+        editor.set_completer(raeshell::complete_command);
+        ShellTerminal {
+            session,
+            terminal: Terminal::new(columns, rows),
+            editor,
+            script: String::new(),
+            scroll_pixels: 0,
+            selecting: false,
+            last_click: None,
+            clicks: 0,
+            live_top: None,
+        }
+    }
+
+    pub fn terminal(&self) -> &Terminal {
+        &self.terminal
+    }
+
+    /// The command line typed so far
+    pub fn input(&self) -> String {
+        self.editor.line()
+    }
+
+    /// Start a new command line, with the session's history to browse
+    fn write_prompt(&mut self) {
+        let prompt = raeshell::get_shell_prompt(self.session).unwrap_or_else(|_| String::from("$ "));
+        if let Ok(history) = raeshell::get_command_history(self.session) {
+            self.editor.set_history(history);
+        }
+        self.editor.start(&prompt);
+        self.show_editing();
+    }
+
+    /// Write what the line editor drew since last shown
+    fn show_editing(&mut self) {
+        let output = self.editor.take_output();
+        if !output.is_empty() {
+            self.terminal.scroll_to_bottom();
+            self.terminal.write(&output);
+        }
+    }
+
+    fn render(&self, window: WindowId) {
+        let (cell_width, cell_height) = cell_size();
+        graphics::with_window_buffer(window, |buffer| self.terminal.render(buffer, cell_width, cell_height));
+    }
+
+    /// Handle one event of the terminal's window, returning false once the session ended
+    pub fn handle_event(&mut self, event: WindowEvent) -> bool {
+        match event {
+            WindowEvent::Keyboard(key) if key.pressed => return self.handle_key(key.key_code, key.modifiers),
+            WindowEvent::TextCommit(text) => return self.send_input(text.as_bytes()),
+            WindowEvent::Mouse(mouse) => return self.handle_mouse(mouse),
+            WindowEvent::MouseMove { x, y } if self.selecting => {
+                let (row, column) = cell_at(x, y);
+                self.terminal.extend_selection(row, column);
+            }
+            WindowEvent::Gesture(GestureEvent { gesture: Gesture::Scroll { delta_y, .. }, .. }) => {
+                let (_, cell_height) = cell_size();
+                self.scroll_pixels += delta_y;
+                let rows = self.scroll_pixels / cell_height as i32;
+                self.scroll_pixels -= rows * cell_height as i32;
+                self.terminal.scroll(rows as isize);
+            }
+            WindowEvent::Resize { width, height } => {
+                let (cell_width, cell_height) = cell_size();
+                let columns = (width / cell_width) as usize;
+                self.terminal.resize(columns, (height / cell_height) as usize);
+                self.editor.set_columns(columns);
+                if let Some(top) = &mut self.live_top {
+                    top.next_refresh_ms = 0;
+                    self.refresh_top();
+                }
+            }
+            WindowEvent::Close => return false,
+            _ => {}
+        }
+        true
+    }
+
+    /// Left drags select, by word after a double click and by line after a triple click,
+    /// and copy on release; a middle click pastes
+    fn handle_mouse(&mut self, event: MouseEvent) -> bool {
+        let (row, column) = cell_at(event.x, event.y);
+        match (event.button, event.pressed) {
+            (MouseButton::Left, true) => {
+                let now = crate::time::get_uptime_ms();
+                let repeated = self.last_click.is_some_and(|(time, last_row, last_column)| {
+                    now.saturating_sub(time) <= MULTI_CLICK_MS && (last_row, last_column) == (row, column)
+                });
+                self.clicks = if repeated { self.clicks % 3 + 1 } else { 1 };
+                self.last_click = Some((now, row, column));
+                let unit = match self.clicks {
+                    1 => SelectionUnit::Character,
+                    2 => SelectionUnit::Word,
+                    _ => SelectionUnit::Line,
+                };
+                self.terminal.start_selection(row, column, unit);
+                self.selecting = true;
+            }
+            (MouseButton::Left, false) if self.selecting => {
+                self.selecting = false;
+                self.terminal.extend_selection(row, column);
+                self.copy_selection();
+            }
+            (MouseButton::Middle, true) => return self.paste(),
+            _ => {}
+        }
+        true
+    }
+
+    fn handle_key(&mut self, key_code: u32, modifiers: KeyModifiers) -> bool {
+        let page = self.terminal.rows() as isize;
+        match key_code {
+            KEY_PAGE_UP => self.terminal.scroll(page),
+            KEY_PAGE_DOWN => self.terminal.scroll(-page),
+            KEY_UP if modifiers.contains(KeyModifiers::SHIFT) => self.terminal.scroll(1),
+            KEY_DOWN if modifiers.contains(KeyModifiers::SHIFT) => self.terminal.scroll(-1),
+            _ if is_shortcut(key_code, modifiers, b'C') => self.copy_selection(),
+            _ if is_shortcut(key_code, modifiers, b'V') => return self.paste(),
+            _ => {
+                let mut buffer = [0; 5];
+                if let Some(input) = key_input(key_code, modifiers, &mut buffer) {
+                    return self.send_input(input);
+                }
+            }
+        }
+        true
+    }
+
+    fn copy_selection(&mut self) {
+        if let Some(text) = self.terminal.selected_text() {
+            clipboard::set_text(text);
+        }
+    }
+
+    /// Insert the clipboard text into the command line, running a command at each line
+    /// break. Returns false when a pasted command ended the session
+    fn paste(&mut self) -> bool {
+        let Some(text) = clipboard::text() else {
+            return true;
+        };
+        for (index, line) in text.split('\n').enumerate() {
+            if index > 0 {
+                let command = self.editor.submit();
+                self.show_editing();
+                if !self.accept_line(&command) {
+                    return false;
+                }
+            }
+            self.editor.insert_text(line);
+            self.show_editing();
+        }
+        true
+    }
+
+    /// Pass terminal input to the line editor, running each line it accepts. Returns false
+    /// once the session ended
+    fn send_input(&mut self, input: &[u8]) -> bool {
+        if self.live_top.is_some() {
+            if input.iter().any(|&byte| byte == b'q' || byte == 0x03) {
+                self.stop_top();
+            }
+            return true;
+        }
+        for &byte in input {
+            let result = self.editor.feed_byte(byte);
+            self.show_editing();
+            let running = match result {
+                Some(EditResult::Line(line)) => self.accept_line(&line),
+                Some(EditResult::Interrupted) => {
+                    self.script.clear();
+                    self.write_prompt();
+                    true
+                }
+                Some(EditResult::EndOfInput) => false,
+                None => true,
+            };
+            if !running {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Take a line from the editor: run the command it completes, or prompt for the next
+    /// line of one still open. Returns false when the command ended the session
+    fn accept_line(&mut self, line: &str) -> bool {
+        self.script.push_str(line);
+        if !script::is_complete(&self.script) {
+            self.script.push('\n');
+            self.editor.start("> ");
+            self.show_editing();
+            return true;
+        }
+        let command = core::mem::take(&mut self.script);
+        self.run_command(&command)
+    }
+
+    /// Run a command line and show its output, returning false when it ended the session
+    fn run_command(&mut self, line: &str) -> bool {
+        self.terminal.scroll_to_bottom();
+        if let Some(interval_ms) = monitor::live_top_interval(line) {
+            let _ = raeshell::add_command_history(self.session, line);
+            self.start_top(interval_ms);
+            return true;
+        }
+        match raeshell::execute_command(self.session, line) {
+            Ok(ShellResult::Success(output)) => self.terminal.write(&output),
+            Ok(ShellResult::Error(message)) => {
+                self.terminal.write("\x1b[31m");
+                self.terminal.write(&message);
+                self.terminal.write("\x1b[0m");
+            }
+            Ok(ShellResult::Exit) | Err(()) => return false,
+        }
+        if !self.terminal.at_line_start() {
+            self.terminal.write("\n");
+        }
+        self.write_prompt();
+        true
+    }
+
+    /// Show `top` on the alternate screen, with the cursor hidden, until quit
+    fn start_top(&mut self, interval_ms: u64) {
+        self.terminal.write("\x1b[?1049h\x1b[?25l");
+        self.live_top = Some(LiveTop { sampler: TopSampler::new(), interval_ms, next_refresh_ms: 0 });
+        self.refresh_top();
+    }
+
+    /// Leave `top` for the screen and prompt it replaced
+    fn stop_top(&mut self) {
+        self.live_top = None;
+        self.terminal.write("\x1b[?25h\x1b[?1049l");
+        self.write_prompt();
+    }
+
+    /// Redraw a live `top` once its interval has passed, returning whether it was redrawn
+    pub fn refresh_top(&mut self) -> bool {
+        let now = crate::time::get_uptime_ms();
+        let rows = self.terminal.rows();
+        let Some(top) = self.live_top.as_mut().filter(|top| now >= top.next_refresh_ms) else {
+            return false;
+        };
+        top.next_refresh_ms = now + top.interval_ms;
+        let screen = monitor::top_screen(&top.sampler.sample(), Some(rows));
+        self.terminal.write("\x1b[H\x1b[2J");
+        self.terminal.write(&screen);
+        true
+    }
+}
+
+/// The bytes a terminal sends for a key, as a program reading it in raw mode receives them,
+/// encoded into `buffer` where they are not fixed
+fn key_input(key_code: u32, modifiers: KeyModifiers, buffer: &mut [u8; 5]) -> Option<&[u8]> {
+    let word = modifiers.intersects(KeyModifiers::CTRL | KeyModifiers::ALT);
+    let input: &[u8] = match key_code {
+        KEY_UP => b"\x1b[A",
+        KEY_DOWN => b"\x1b[B",
+        KEY_RIGHT if word => b"\x1b[1;5C",
+        KEY_LEFT if word => b"\x1b[1;5D",
+        KEY_RIGHT => b"\x1b[C",
+        KEY_LEFT => b"\x1b[D",
+        KEY_HOME => b"\x1b[H",
+        KEY_END => b"\x1b[F",
+        KEY_DELETE => b"\x1b[3~",
+        // Backspace sends DEL and Enter a carriage return
+        8 => b"\x7f",
+        10 | 13 => b"\r",
+        // Other extended keys send nothing
+        _ if key_code >> 8 == 0xE0 => return None,
+        _ => {
+            let ch = char::from_u32(key_code)?;
+            let ch = match ch {
+                'a'..='z' | 'A'..='Z' if modifiers.contains(KeyModifiers::CTRL) => char::from(ch as u8 & 0x1f),
+                _ => ch,
+            };
+            // Alt sends the key behind ESC
+            let start = if modifiers.contains(KeyModifiers::ALT) && !ch.is_control() {
+                buffer[0] = 0x1b;
+                1
+            } else {
+                0
+            };
+            let length = ch.encode_utf8(&mut buffer[start..]).len();
+            return Some(&buffer[..start + length]);
+        }
+    };
+    Some(input)
+}
+
+/// Open a terminal window on shell session `session` and show its prompt
+pub fn open_shell_terminal(session: u32) -> Result<WindowId, &'static str> {
+    let window = graphics::create_shell_window()?;
+    let size = graphics::with_window(window, |window| window.buffer.as_ref().map(|buffer| (buffer.width, buffer.height)));
+    let (width, height) = size.flatten().ok_or("Shell window has no buffer")?;
+    let (cell_width, cell_height) = cell_size();
+    let mut shell = ShellTerminal::new(session, (width / cell_width) as usize, (height / cell_height) as usize);
+    shell.write_prompt();
+    shell.render(window);
+    SHELL_TERMINALS.lock().insert(window, shell);
+    Ok(window)
+}
+
+/// Feed the input queued for each shell terminal window to its session and redraw it,
+/// along with any live `top` due a refresh. Terminals whose window closed or whose session exited are removed
+pub fn process_terminal_events() {
+    let mut terminals = SHELL_TERMINALS.lock();
+    let mut ended = Vec::new();
+    for (&window, shell) in terminals.iter_mut() {
+        let Some(events) = graphics::take_window_events(window) else {
+            ended.push(window);
+            continue;
+        };
+        let refreshed = shell.refresh_top();
+        if events.is_empty() {
+            if refreshed {
+                shell.render(window);
+            }
+            continue;
+        }
+        if events.into_iter().all(|event| shell.handle_event(event)) {
+            shell.render(window);
+        } else {
+            graphics::destroy_window(window);
+            ended.push(window);
+        }
+    }
+    for window in ended {
+        if let Some(shell) = terminals.remove(&window) {
+            let _ = raeshell::close_shell_session(shell.session);
+        }
+    }
+}
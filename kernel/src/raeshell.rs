@@ -7,6 +7,16 @@ use alloc::collections::BTreeMap;
 use spin::Mutex;
 use lazy_static::lazy_static;
 
+pub mod editor;
+pub mod monitor;
+pub mod netconfig;
+pub mod dnsutils;
+pub mod power;
+pub mod script;
+pub mod tcpdump;
+
+use script::{Interpreter, ScriptError};
+
 // Shell command result
 #[derive(Debug, Clone)]
 pub enum ShellResult {
@@ -18,6 +28,17 @@ pub enum ShellResult {
 // Built-in command function type
 type BuiltinCommand = fn(&[&str]) -> ShellResult;
 
+// Built-in command that reads and writes its standard streams
+type StreamCommand = fn(&[&str], &mut Streams) -> ShellResult;
+
+/// A command's standard streams: what it reads, when anything was piped to it, and what it
+/// writes, passed on to the next stage of a pipeline or to a redirected file
+#[derive(Debug, Clone, Default)]
+pub struct Streams {
+    pub stdin: Option<String>,
+    pub stdout: String,
+}
+
 // Shell session state
 #[derive(Debug, Clone)]
 struct ShellSession {
@@ -27,6 +48,11 @@ struct ShellSession {
     command_history: Vec<String>,
     process_id: u32,
     prompt: String,
+    /// Exit status of the last command line, `$?` in the next
+    last_status: i32,
+    aliases: BTreeMap<String, String>,
+    /// Function names and the source of their bodies
+    functions: BTreeMap<String, String>,
 }
 
 impl ShellSession {
@@ -44,6 +70,9 @@ impl ShellSession {
             command_history: Vec::new(),
             process_id,
             prompt: "raeshell> ".to_string(),
+            last_status: 0,
+            aliases: BTreeMap::new(),
+            functions: BTreeMap::new(),
         }
     }
     
@@ -63,13 +92,23 @@ impl ShellSession {
     fn set_env(&mut self, key: String, value: String) {
         self.environment.insert(key, value);
     }
+    
+    // The rc file run when a session starts, holding the user's aliases and functions
+    fn rc_path(&self) -> String {
+        let home = self.get_env("HOME").map_or("", String::as_str);
+        format!("{}/{}", home.trim_end_matches('/'), RC_FILE_NAME)
+    }
 }
 
+// Name of the rc file in the home directory
+const RC_FILE_NAME: &str = ".raeshrc";
+
 // Shell system state
 struct ShellSystem {
     sessions: BTreeMap<u32, ShellSession>,
     next_session_id: u32,
     builtin_commands: BTreeMap<String, BuiltinCommand>,
+    stream_commands: BTreeMap<String, StreamCommand>,
 }
 
 lazy_static! {
@@ -78,21 +117,20 @@ lazy_static! {
             sessions: BTreeMap::new(),
             next_session_id: 1,
             builtin_commands: BTreeMap::new(),
+            stream_commands: BTreeMap::new(),
         };
         
         // Register built-in commands
         system.builtin_commands.insert("help".to_string(), cmd_help);
-        system.builtin_commands.insert("ls".to_string(), cmd_ls);
         system.builtin_commands.insert("cd".to_string(), cmd_cd);
         system.builtin_commands.insert("pwd".to_string(), cmd_pwd);
-        system.builtin_commands.insert("echo".to_string(), cmd_echo);
         system.builtin_commands.insert("env".to_string(), cmd_env);
         system.builtin_commands.insert("export".to_string(), cmd_export);
         system.builtin_commands.insert("history".to_string(), cmd_history);
         system.builtin_commands.insert("clear".to_string(), cmd_clear);
         system.builtin_commands.insert("ps".to_string(), cmd_ps);
         system.builtin_commands.insert("kill".to_string(), cmd_kill);
-        system.builtin_commands.insert("cat".to_string(), cmd_cat);
+        system.builtin_commands.insert("renice".to_string(), cmd_renice);
         system.builtin_commands.insert("touch".to_string(), cmd_touch);
         system.builtin_commands.insert("rm".to_string(), cmd_rm);
         system.builtin_commands.insert("mkdir".to_string(), cmd_mkdir);
@@ -105,7 +143,27 @@ lazy_static! {
         system.builtin_commands.insert("date".to_string(), cmd_date);
         system.builtin_commands.insert("uptime".to_string(), cmd_uptime);
         system.builtin_commands.insert("free".to_string(), cmd_free);
+        system.builtin_commands.insert("top".to_string(), cmd_top);
+        system.builtin_commands.insert("lspci".to_string(), cmd_lspci);
+        system.builtin_commands.insert("ifconfig".to_string(), netconfig::cmd_ifconfig);
+        system.builtin_commands.insert("ip".to_string(), netconfig::cmd_ip);
+        system.builtin_commands.insert("host".to_string(), dnsutils::cmd_host);
+        system.builtin_commands.insert("nslookup".to_string(), dnsutils::cmd_nslookup);
+        system.builtin_commands.insert("tcpdump".to_string(), tcpdump::cmd_tcpdump);
+        system.builtin_commands.insert("battery".to_string(), power::cmd_battery);
+        system.builtin_commands.insert("acpi".to_string(), power::cmd_acpi);
+        system.builtin_commands.insert("poweroff".to_string(), power::cmd_poweroff);
+        system.builtin_commands.insert("flightlog".to_string(), cmd_flightlog);
+        system.builtin_commands.insert("trace".to_string(), cmd_trace);
         system.builtin_commands.insert("thread_stress".to_string(), cmd_thread_stress);
+        system.builtin_commands.insert("true".to_string(), cmd_true);
+        system.builtin_commands.insert("false".to_string(), cmd_false);
+        system.builtin_commands.insert("test".to_string(), cmd_test);
+        system.builtin_commands.insert("[".to_string(), cmd_test);
+        system.builtin_commands.insert("sh".to_string(), cmd_sh);
+        system.stream_commands.insert("ls".to_string(), cmd_ls);
+        system.stream_commands.insert("echo".to_string(), cmd_echo);
+        system.stream_commands.insert("cat".to_string(), cmd_cat);
         
         Mutex::new(system)
     };
@@ -113,23 +171,22 @@ lazy_static! {
 
 // Built-in command implementations
 fn cmd_help(_args: &[&str]) -> ShellResult {
-    let help_text = "RaeShell - Built-in Commands:\n  help        - Show this help message\n  ls [path]   - List directory contents\n  cd <path>   - Change directory\n  pwd         - Print working directory\n  echo <text> - Print text to output\n  env         - Show environment variables\n  export K=V  - Set environment variable\n  history     - Show command history\n  clear       - Clear screen\n  ps          - List running processes\n  kill <pid>  - Terminate process\n  cat <file>  - Display file contents\n  touch <file>- Create empty file\n  rm <file>   - Remove file\n  mkdir <dir> - Create directory\n  rmdir <dir> - Remove directory\n  cp <src> <dst> - Copy file\n  mv <src> <dst> - Move/rename file\n  exit        - Exit shell\n  uname       - System information\n  whoami      - Current user\n  date        - Current date/time\n  uptime      - System uptime\n  free        - Memory usage";
+    let help_text = "RaeShell - Built-in Commands:\n  help        - Show this help message\n  ls [path]   - List directory contents\n  cd <path>   - Change directory\n  pwd         - Print working directory\n  echo <text> - Print text to output\n  env         - Show environment variables\n  export K=V  - Set environment variable\n  history     - Show command history\n  clear       - Clear screen\n  ps          - List running processes\n  kill <pid>  - Terminate process\n  renice NICE PID - Set a process's priority from a nice value, -20 to 19\n  cat [file...] - Display files, or standard input\n  touch <file>- Create empty file\n  rm <file>   - Remove file\n  mkdir <dir> - Create directory\n  rmdir <dir> - Remove directory\n  cp <src> <dst> - Copy file\n  mv <src> <dst> - Move/rename file\n  exit        - Exit shell\n  uname       - System information\n  whoami      - Current user\n  date [-u] [-I] - Current local date/time\n  uptime [-s] - System uptime and load averages, or the boot time\n  free        - Memory usage in KiB\n  top [-d SECS] [-n COUNT] - Processes by CPU use, live until q, or COUNT screens\n  lspci [-r]  - PCI devices and their drivers, or rescan the bus for hotplugged ones\n  ifconfig [-a] [IFACE [ADDR[/PREFIX]] [up|down]] - Show or configure interfaces\n  ip link|addr|route [COMMAND] - Show or change interfaces, addresses and routes\n  host [-t TYPE] NAME|ADDR [SERVER] - Look up addresses, aliases or names, with TTLs\n  nslookup [-type=TYPE] NAME|ADDR [SERVER] - Query the name servers of /etc/resolv.conf\n  tcpdump [-i IFACE] [-c COUNT] [-w FILE | -r FILE] [EXPR] - Capture frames, or print a capture\n  battery     - Battery charge, state and time left, and the AC adapter\n  acpi [-b] [-a] [-i] [-V] - Each battery, its capacities and the AC adapters\n  poweroff    - Detach the drivers and turn the machine off\n  flightlog [-n N] - Flight recorder events saved on disk\n  trace [-p PID] [-t TRACE] [-n N] [--stats] - Query recorded events\n  trace dump [--consume] PATH - Save recorded events to a file\n  true / false - Succeed / fail\n  test EXPR, [ EXPR ] - Check strings, numbers and files\n  sh FILE [ARGS] - Run a script file\n  sh -c SCRIPT [NAME [ARGS]] - Run a script given inline\n  alias [NAME[=TEXT]] - Define or show aliases\n  unalias [-a] NAME - Remove aliases\n\nCommand lines are scripts: ;  &&  ||  !  if/elif/else/fi  for/while/until ... do ... done\n  case ... in PATTERN) ... ;; esac  break  continue  $VAR  $(command)  $((arithmetic))\n  NAME() { ... } defines a function, left early with return\n  CMD | CMD pipes output to input; > FILE writes output to FILE, >> FILE appends\n~/.raeshrc runs when a session starts";
     
     ShellResult::Success(help_text.to_string())
 }
 
-fn cmd_ls(args: &[&str]) -> ShellResult {
+fn cmd_ls(args: &[&str], streams: &mut Streams) -> ShellResult {
     let path = if args.len() > 1 { args[1] } else { "." };
     
     // Use VFS to list directory
     match crate::filesystem::list_directory(path) {
         Ok(entries) => {
-            let mut output = String::new();
             for entry in entries {
-                output.push_str(&entry);
-                output.push('\n');
+                streams.stdout.push_str(&entry);
+                streams.stdout.push('\n');
             }
-            ShellResult::Success(output)
+            ShellResult::Success(String::new())
         }
         Err(_) => ShellResult::Error(format!("ls: cannot access '{}': No such file or directory", path)),
     }
@@ -162,13 +219,10 @@ fn cmd_pwd(_args: &[&str]) -> ShellResult {
     ShellResult::Success("/".to_string())
 }
 
-fn cmd_echo(args: &[&str]) -> ShellResult {
-    if args.len() < 2 {
-        ShellResult::Success(String::new())
-    } else {
-        let output = args[1..].join(" ");
-        ShellResult::Success(output)
-    }
+fn cmd_echo(args: &[&str], streams: &mut Streams) -> ShellResult {
+    streams.stdout.push_str(&args[1..].join(" "));
+    streams.stdout.push('\n');
+    ShellResult::Success(String::new())
 }
 
 fn cmd_env(_args: &[&str]) -> ShellResult {
@@ -207,9 +261,7 @@ fn cmd_clear(_args: &[&str]) -> ShellResult {
 }
 
 fn cmd_ps(_args: &[&str]) -> ShellResult {
-    // Use kernel-provided dump for now (prints to serial)
-    let _ = crate::syscall::handle_syscall(352, 0, 0, 0, 0, 0, 0);
-    ShellResult::Success("(ps) see serial for detailed list".to_string())
+    ShellResult::Success(crate::process::process_report(&crate::process::process_stats()))
 }
 
 fn cmd_kill(args: &[&str]) -> ShellResult {
@@ -225,31 +277,45 @@ fn cmd_kill(args: &[&str]) -> ShellResult {
     }
 }
 
-fn cmd_cat(args: &[&str]) -> ShellResult {
-    if args.len() < 2 {
+fn cmd_renice(args: &[&str]) -> ShellResult {
+    let (Some(nice), Some(pid)) = (args.get(1), args.get(2)) else {
+        return ShellResult::Error("renice: usage: renice NICE PID".to_string());
+    };
+    let (Ok(nice), Ok(pid)) = (nice.parse::<i32>(), pid.parse::<u64>()) else {
+        return ShellResult::Error("renice: invalid nice value or process ID".to_string());
+    };
+    let Some(priority) = crate::process::Priority::from_nice(nice) else {
+        return ShellResult::Error(format!("renice: nice value {} out of range", nice));
+    };
+    match crate::process::renice(pid, nice) {
+        Ok(()) => ShellResult::Success(format!("{}: priority now {:?}", pid, priority)),
+        Err(_) => ShellResult::Error(format!("renice: {}: no such process", pid)),
+    }
+}
+
+// Write each file in turn, standard input in place of `-` or of no file at all
+fn cmd_cat(args: &[&str], streams: &mut Streams) -> ShellResult {
+    let files = if args.len() < 2 { &["-"][..] } else { &args[1..] };
+    if files == ["-"] && streams.stdin.is_none() {
         return ShellResult::Error("cat: missing argument".to_string());
     }
     
-    let filename = args[1];
-    
-    match crate::filesystem::open_file(filename) {
-        Ok(fd) => {
-            match crate::filesystem::read_file_fd(fd, 4096) {
-                Ok(data) => {
-                    let _ = crate::filesystem::close_file(fd);
-                    match String::from_utf8(data) {
-                        Ok(content) => ShellResult::Success(content),
-                        Err(_) => ShellResult::Error("cat: file contains binary data".to_string()),
-                    }
-                }
-                Err(_) => {
-                    let _ = crate::filesystem::close_file(fd);
-                    ShellResult::Error(format!("cat: cannot read '{}'", filename))
-                }
+    for &filename in files {
+        if filename == "-" {
+            if let Some(input) = streams.stdin.take() {
+                streams.stdout.push_str(&input);
             }
+            continue;
+        }
+        let Ok(data) = crate::filesystem::read_file(filename) else {
+            return ShellResult::Error(format!("cat: '{}': No such file or directory", filename));
+        };
+        match String::from_utf8(data) {
+            Ok(content) => streams.stdout.push_str(&content),
+            Err(_) => return ShellResult::Error("cat: file contains binary data".to_string()),
         }
-        Err(_) => ShellResult::Error(format!("cat: '{}': No such file or directory", filename)),
     }
+    ShellResult::Success(String::new())
 }
 
 fn cmd_touch(args: &[&str]) -> ShellResult {
@@ -383,36 +449,190 @@ fn cmd_whoami(_args: &[&str]) -> ShellResult {
     ShellResult::Success("user".to_string())
 }
 
-fn cmd_date(_args: &[&str]) -> ShellResult {
-    let uptime = crate::time::get_system_uptime();
-    ShellResult::Success(format!("System uptime: {} ticks", uptime))
+fn cmd_date(args: &[&str]) -> ShellResult {
+    let utc = crate::time::get_timestamp() as i64;
+    let mut local = crate::time::local_now();
+    let mut iso = false;
+    
+    for arg in args {
+        match *arg {
+            "-u" | "--utc" => local = crate::time::timezone::TimeZone::utc().to_local(utc),
+            "-I" | "--iso-8601" => iso = true,
+            _ => return ShellResult::Error(format!("date: unknown option '{}'", arg)),
+        }
+    }
+    
+    if iso {
+        ShellResult::Success(local.format_iso8601())
+    } else {
+        ShellResult::Success(local.format())
+    }
 }
 
-fn cmd_uptime(_args: &[&str]) -> ShellResult {
-    let uptime = crate::time::get_system_uptime();
-    let seconds = uptime / 1000; // Assuming ticks are milliseconds
-    let minutes = seconds / 60;
-    let hours = minutes / 60;
-    
-    ShellResult::Success(format!("up {}:{:02}:{:02}", hours, minutes % 60, seconds % 60))
+fn cmd_uptime(args: &[&str]) -> ShellResult {
+    match args {
+        [_] => ShellResult::Success(monitor::uptime_report()),
+        [_, "-s"] => ShellResult::Success(monitor::boot_time_report()),
+        _ => ShellResult::Error("usage: uptime [-s]".to_string()),
+    }
 }
 
-fn cmd_free(_args: &[&str]) -> ShellResult {
-    let total = crate::memory::get_total_memory();
-    let free = crate::memory::get_free_memory();
-    let used = total - free;
-    
-    let output = format!(
-        "              total        used        free\nMem:    {:10} {:10} {:10}\n\nMemory usage: {:.1}%",
-        total / 1024,
-        used / 1024,
-        free / 1024,
-        (used as f32 / total as f32) * 100.0
-    );
-    
+fn cmd_free(args: &[&str]) -> ShellResult {
+    if args.len() > 1 {
+        return ShellResult::Error("usage: free".to_string());
+    }
+    ShellResult::Success(monitor::free_report())
+}
+
+/// `lspci` lists the PCI functions and the driver bound to each; `lspci -r` has the main
+/// loop rescan the bus, binding and unbinding drivers as devices came and went
+fn cmd_lspci(args: &[&str]) -> ShellResult {
+    match args {
+        [_] => {
+            let devices = crate::pci::get_manager().lock().get_devices().to_vec();
+            let lines: Vec<String> = devices.into_iter()
+                .map(|device| {
+                    let line = format!(
+                        "{:02x}:{:02x}.{} {:04x}:{:04x} class {:02x}",
+                        device.bus, device.device, device.function, device.vendor_id, device.device_id, device.class_code
+                    );
+                    let driver = crate::drivers::bound_driver(&crate::drivers::BusDevice::Pci(device));
+                    format!("{} {}", line, driver.as_deref().unwrap_or("-"))
+                })
+                .collect();
+            ShellResult::Success(lines.join("\n"))
+        }
+        [_, "-r"] => {
+            crate::pci::request_rescan();
+            ShellResult::Success("lspci: rescan requested".to_string())
+        }
+        _ => ShellResult::Error("usage: lspci [-r]".to_string()),
+    }
+}
+
+/// Print `top` screens: COUNT of them, each after waiting the interval. A terminal shows
+/// `top` without `-n` live instead
+fn cmd_top(args: &[&str]) -> ShellResult {
+    let mut interval_ms = monitor::DEFAULT_TOP_INTERVAL_MS;
+    let mut count = 1;
+    let mut options = args[1..].iter();
+    while let Some(&option) = options.next() {
+        let value = options.next();
+        match (option, value) {
+            ("-d", Some(seconds)) => match monitor::parse_interval(seconds) {
+                Some(interval) => interval_ms = interval,
+                None => return ShellResult::Error(format!("top: invalid interval '{}'", seconds)),
+            },
+            ("-n", Some(n)) => match n.parse::<usize>() {
+                Ok(n) if n > 0 => count = n,
+                _ => return ShellResult::Error(format!("top: invalid count '{}'", n)),
+            },
+            _ => return ShellResult::Error("usage: top [-d SECONDS] [-n COUNT]".to_string()),
+        }
+    }
+
+    let mut sampler = monitor::TopSampler::new();
+    let mut screens = Vec::with_capacity(count);
+    for index in 0..count {
+        if index > 0 {
+            crate::time::sleep_ms(interval_ms);
+        }
+        screens.push(monitor::top_screen(&sampler.sample(), None));
+    }
+    ShellResult::Success(screens.join("\n\n"))
+}
+
+fn cmd_flightlog(args: &[&str]) -> ShellResult {
+    let count = match args {
+        [_] => None,
+        [_, "-n", n] => match n.parse::<usize>() {
+            Ok(n) => Some(n),
+            Err(_) => return ShellResult::Error(format!("flightlog: invalid count '{}'", n)),
+        },
+        _ => return ShellResult::Error("usage: flightlog [-n N]".to_string()),
+    };
+
+    match crate::observability::read_flight_log() {
+        Ok(entries) => {
+            let start = count.map_or(0, |n| entries.len().saturating_sub(n));
+            ShellResult::Success(crate::observability::flight_recorder::format_flight_recorder_dump(&entries[start..]))
+        }
+        Err(crate::observability::ObservabilityError::NotEnabled) => {
+            ShellResult::Error("flightlog: no flight log configured (boot with flightlog=<lba>:<slots>)".to_string())
+        }
+        Err(e) => ShellResult::Error(format!("flightlog: cannot read flight log: {:?}", e)),
+    }
+}
+
+fn cmd_trace(args: &[&str]) -> ShellResult {
+    use crate::observability::query::{self, EventFilter, LatencySummary};
+
+    if args.get(1) == Some(&"dump") {
+        return trace_dump(&args[2..]);
+    }
+
+    let mut filter = EventFilter::default();
+    let mut count = None;
+    let mut stats = false;
+    let mut rest = args.iter().skip(1);
+    while let Some(&arg) = rest.next() {
+        if arg == "--stats" {
+            stats = true;
+            continue;
+        }
+        match (arg, rest.next()) {
+            ("-p", Some(pid)) => match pid.parse() {
+                Ok(pid) => filter.pid = Some(pid),
+                Err(_) => return ShellResult::Error(format!("trace: invalid PID '{}'", pid)),
+            },
+            ("-t", Some(id)) => match u128::from_str_radix(id.trim_start_matches("0x"), 16) {
+                Ok(id) => filter.trace_id = Some(id),
+                Err(_) => return ShellResult::Error(format!("trace: invalid trace ID '{}'", id)),
+            },
+            ("-n", Some(n)) => match n.parse::<usize>() {
+                Ok(n) => count = Some(n),
+                Err(_) => return ShellResult::Error(format!("trace: invalid count '{}'", n)),
+            },
+            _ => return ShellResult::Error("usage: trace [-p PID] [-t TRACE_ID] [-n N] [--stats]".to_string()),
+        }
+    }
+
+    let entries = crate::observability::query_entries(&filter);
+    if !stats {
+        let start = count.map_or(0, |n| entries.len().saturating_sub(n));
+        return ShellResult::Success(crate::observability::flight_recorder::format_flight_recorder_dump(&entries[start..]));
+    }
+
+    let mut output = format!("{} events\n", entries.len());
+    for (kind, n) in query::count_by_kind(&entries) {
+        output.push_str(&format!("  {:<20} {}\n", kind, n));
+    }
+    let latencies = [("syscall", query::syscall_latencies(&entries)), ("ipc", query::ipc_latencies(&entries))];
+    for (kind, samples) in latencies {
+        if let Some(summary) = LatencySummary::from_samples(samples) {
+            output.push_str(&format!(
+                "{} latency (ns): n={} min={} p50={} p90={} p99={} max={}\n",
+                kind, summary.count, summary.min, summary.p50, summary.p90, summary.p99, summary.max
+            ));
+        }
+    }
     ShellResult::Success(output)
 }
 
+fn trace_dump(args: &[&str]) -> ShellResult {
+    use crate::observability::flight_recorder::DrainMode;
+
+    let (mode, path) = match args {
+        [path] => (DrainMode::Copy, *path),
+        ["--consume", path] => (DrainMode::Consume, *path),
+        _ => return ShellResult::Error("usage: trace dump [--consume] PATH".to_string()),
+    };
+    match crate::observability::dump_flight_recorder(path, mode) {
+        Ok(count) => ShellResult::Success(format!("{} events written to {}", count, path)),
+        Err(e) => ShellResult::Error(format!("trace: cannot dump to {}: {:?}", path, e)),
+    }
+}
+
 fn cmd_thread_stress(args: &[&str]) -> ShellResult {
     // placeholder trigger to run userspace-thread-stress once available
     let threads = if args.len() > 1 { args[1].parse::<u64>().unwrap_or(4) } else { 4 };
@@ -420,65 +640,242 @@ fn cmd_thread_stress(args: &[&str]) -> ShellResult {
     ShellResult::Success("thread_stress: userspace binary will perform measurement".to_string())
 }
 
-// Parse command line into tokens
-fn parse_command_line(input: &str) -> Vec<&str> {
-    input.trim().split_whitespace().collect()
+fn cmd_true(_args: &[&str]) -> ShellResult {
+    ShellResult::Success(String::new())
+}
+
+fn cmd_false(_args: &[&str]) -> ShellResult {
+    ShellResult::Error(String::new())
+}
+
+fn cmd_test(args: &[&str]) -> ShellResult {
+    let mut operands = args.get(1..).unwrap_or(&[]);
+    if args.first() == Some(&"[") {
+        match operands.split_last() {
+            Some((&"]", rest)) => operands = rest,
+            _ => return ShellResult::Error("[: missing ']'".to_string()),
+        }
+    }
+
+    match test_expression(operands) {
+        Ok(true) => ShellResult::Success(String::new()),
+        Ok(false) => ShellResult::Error(String::new()),
+        Err(message) => ShellResult::Error(format!("test: {}", message)),
+    }
 }
 
-// Create a new shell session
+// Evaluate the operands of `test`: a leading `!` negates the rest, and one, two or three
+// operands make a string, unary or binary test
+fn test_expression(operands: &[&str]) -> Result<bool, String> {
+    let is_type = |path: &str, file_type: crate::filesystem::FileType| {
+        crate::filesystem::metadata(path).is_ok_and(|metadata| metadata.file_type == file_type)
+    };
+    match operands {
+        [] => Ok(false),
+        ["!", rest @ ..] => test_expression(rest).map(|result| !result),
+        [text] => Ok(!text.is_empty()),
+        [operator, operand] => match *operator {
+            "-z" => Ok(operand.is_empty()),
+            "-n" => Ok(!operand.is_empty()),
+            "-e" => Ok(crate::filesystem::metadata(operand).is_ok()),
+            "-f" => Ok(is_type(operand, crate::filesystem::FileType::Regular)),
+            "-d" => Ok(is_type(operand, crate::filesystem::FileType::Directory)),
+            _ => Err(format!("unknown operator '{}'", operator)),
+        },
+        [left, operator, right] => match *operator {
+            "=" | "==" => Ok(left == right),
+            "!=" => Ok(left != right),
+            "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge" => {
+                let number = |text: &str| text.parse::<i64>().map_err(|_| format!("integer expected, got '{}'", text));
+                let (left, right) = (number(left)?, number(right)?);
+                Ok(match *operator {
+                    "-eq" => left == right,
+                    "-ne" => left != right,
+                    "-lt" => left < right,
+                    "-le" => left <= right,
+                    "-gt" => left > right,
+                    _ => left >= right,
+                })
+            }
+            _ => Err(format!("unknown operator '{}'", operator)),
+        },
+        _ => Err("too many arguments".to_string()),
+    }
+}
+
+fn cmd_sh(args: &[&str]) -> ShellResult {
+    // The script's $0 onwards: its file name and arguments, or for -c the given name
+    let (source, arguments) = match args {
+        [_, "-c", source, rest @ ..] => (source.to_string(), if rest.is_empty() { &["sh"][..] } else { rest }),
+        [_, path, ..] => {
+            let source = crate::filesystem::read_file(path).ok().and_then(|data| String::from_utf8(data).ok());
+            match source {
+                Some(source) => (source, &args[1..]),
+                None => return ShellResult::Error(format!("sh: cannot read '{}'", path)),
+            }
+        }
+        _ => return ShellResult::Error("usage: sh FILE [ARGS...] | sh -c SCRIPT [NAME [ARGS...]]".to_string()),
+    };
+
+    let mut interpreter = Interpreter::new(run_command);
+    interpreter.set_arguments(arguments.iter().map(|arg| arg.to_string()).collect());
+    let result = interpreter.run(&source);
+    script_result(&mut interpreter, result)
+}
+
+// Create a new shell session, running the rc file in it when there is one
 pub fn create_shell_session() -> Result<u32, ()> {
-    let mut shell = SHELL_SYSTEM.lock();
-    let current_pid = crate::process::get_current_process_id();
+    let (session_id, rc_path) = {
+        let mut shell = SHELL_SYSTEM.lock();
+        let current_pid = crate::process::get_current_process_id();
+        
+        // Check permission
+        if !crate::security::request_permission(current_pid as u32, "shell.access").unwrap_or(false) {
+            return Err(());
+        }
+        
+        let session_id = shell.next_session_id;
+        shell.next_session_id += 1;
+        
+        let session = ShellSession::new(session_id, current_pid as u32);
+        let rc_path = session.rc_path();
+        shell.sessions.insert(session_id, session);
+        (session_id, rc_path)
+    };
     
-    // Check permission
-    if !crate::security::request_permission(current_pid as u32, "shell.access").unwrap_or(false) {
-        return Err(());
+    // What the rc file prints is not shown; the aliases and functions it defines stay
+    let rc_source = crate::filesystem::read_file(&rc_path).ok().and_then(|data| String::from_utf8(data).ok());
+    if let Some(source) = rc_source {
+        let _ = run_in_session(session_id, &source);
     }
     
-    let session_id = shell.next_session_id;
-    shell.next_session_id += 1;
-    
-    let session = ShellSession::new(session_id, current_pid as u32);
-    shell.sessions.insert(session_id, session);
-    
     Ok(session_id)
 }
 
-// Execute a command in a shell session
-pub fn execute_command(session_id: u32, command_line: &str) -> Result<ShellResult, ()> {
-    let mut shell = SHELL_SYSTEM.lock();
-    let current_pid = crate::process::get_current_process_id();
-    
-    let session = shell.sessions.get_mut(&session_id)
-        .ok_or(())?;
+// Write the session's aliases and functions to its rc file, so new sessions start with them
+pub fn save_shell_rc(session_id: u32) -> Result<(), ()> {
+    let (rc_path, definitions) = {
+        let shell = SHELL_SYSTEM.lock();
+        let current_pid = crate::process::get_current_process_id();
+        
+        let session = shell.sessions.get(&session_id)
+            .ok_or(())?;
+        
+        // Check ownership
+        if u64::from(session.process_id) != current_pid {
+            return Err(());
+        }
+        
+        let mut interpreter = Interpreter::new(run_command);
+        interpreter.set_aliases(session.aliases.clone());
+        interpreter.set_functions(session.functions.clone());
+        (session.rc_path(), interpreter.definitions())
+    };
     
-    // Check ownership
-    if u64::from(session.process_id) != current_pid {
-        return Err(());
+    let _ = crate::filesystem::remove(&rc_path);
+    crate::filesystem::create_file(&rc_path).map_err(|_| ())?;
+    let fd = crate::filesystem::open_file(&rc_path)?;
+    let written = crate::filesystem::write_file(fd, definitions.as_bytes());
+    let _ = crate::filesystem::close_file(fd);
+    written.map(|_| ())
+}
+
+// Execute a command line in a shell session. The line is run as a script, with the
+// session's variables and the status of its previous line
+pub fn execute_command(session_id: u32, command_line: &str) -> Result<ShellResult, ()> {
+    {
+        let mut shell = SHELL_SYSTEM.lock();
+        let current_pid = crate::process::get_current_process_id();
+
+        let session = shell.sessions.get_mut(&session_id)
+            .ok_or(())?;
+
+        // Check ownership
+        if u64::from(session.process_id) != current_pid {
+            return Err(());
+        }
+
+        let command_line = command_line.trim();
+        if command_line.is_empty() {
+            return Ok(ShellResult::Success(String::new()));
+        }
+
+        // Add to history, a script of several lines as one
+        session.add_to_history(command_line.replace('\n', "; "));
     }
-    
-    // Parse command
-    let tokens = parse_command_line(command_line);
-    if tokens.is_empty() {
-        return Ok(ShellResult::Success(String::new()));
+    run_in_session(session_id, command_line)
+}
+
+// Run a script in a shell session: with its variables, aliases and functions and the status
+// of its previous line, keeping what the script changes of them
+fn run_in_session(session_id: u32, source: &str) -> Result<ShellResult, ()> {
+    let mut interpreter = Interpreter::new(run_command);
+    {
+        let shell = SHELL_SYSTEM.lock();
+        let session = shell.sessions.get(&session_id).ok_or(())?;
+        interpreter.set_variables(session.environment.clone());
+        interpreter.set_aliases(session.aliases.clone());
+        interpreter.set_functions(session.functions.clone());
+        interpreter.set_status(session.last_status);
     }
-    
-    let command = tokens[0];
-    
-    // Add to history
-    session.add_to_history(command_line.to_string());
-    
-    // Check for built-in command
-    if let Some(&builtin_fn) = shell.builtin_commands.get(command) {
-        Ok(builtin_fn(&tokens))
+
+    // Commands run without the shell lock held, as built-ins such as `sh` take it themselves
+    let result = interpreter.run(source);
+
+    if let Some(session) = SHELL_SYSTEM.lock().sessions.get_mut(&session_id) {
+        session.environment = interpreter.variables().clone();
+        session.aliases = interpreter.aliases().clone();
+        session.functions = interpreter.functions().clone();
+        session.last_status = interpreter.status();
+    }
+
+    if result.is_ok() && interpreter.has_exited() {
+        return Ok(ShellResult::Exit);
+    }
+    Ok(script_result(&mut interpreter, result))
+}
+
+// Run one simple command with its standard streams: a built-in, or else an external program
+pub fn run_command(args: &[&str], streams: &mut Streams) -> ShellResult {
+    let Some(&command) = args.first() else {
+        return ShellResult::Success(String::new());
+    };
+
+    // Look the built-in up, then release the lock before it runs
+    let (stream_command, builtin) = {
+        let shell = SHELL_SYSTEM.lock();
+        (shell.stream_commands.get(command).copied(), shell.builtin_commands.get(command).copied())
+    };
+    if let Some(stream_fn) = stream_command {
+        stream_fn(args, streams)
+    } else if let Some(builtin_fn) = builtin {
+        builtin_fn(args)
     } else {
         // Try to execute as external program
-        match crate::process::exec_process(command, &tokens[1..]) {
+        match crate::process::exec_process(command, &args[1..]) {
             Ok(()) => {
                 // exec replaces current process, so this shouldn't normally return
-                Ok(ShellResult::Success(String::new()))
+                ShellResult::Success(String::new())
+            }
+            Err(_) => ShellResult::Error(format!("{}: command not found", command)),
+        }
+    }
+}
+
+// Turn what a script printed and how it ended into a command result
+fn script_result(interpreter: &mut Interpreter, result: Result<i32, ScriptError>) -> ShellResult {
+    let mut output = interpreter.take_output();
+    let output_len = output.trim_end_matches('\n').len();
+    output.truncate(output_len);
+    match result {
+        Ok(0) => ShellResult::Success(output),
+        Ok(_) => ShellResult::Error(output),
+        Err(error) => {
+            if !output.is_empty() {
+                output.push('\n');
             }
-            Err(_) => Ok(ShellResult::Error(format!("{}: command not found", command))),
+            output.push_str(&format!("raeshell: {}", error));
+            ShellResult::Error(output)
         }
     }
 }
@@ -581,6 +978,23 @@ pub fn get_command_history(session_id: u32) -> Result<Vec<String>, ()> {
     Ok(session.command_history.clone())
 }
 
+// Record a command line run outside `execute_command`, such as one a terminal handles itself
+pub fn add_command_history(session_id: u32, command_line: &str) -> Result<(), ()> {
+    let mut shell = SHELL_SYSTEM.lock();
+    let current_pid = crate::process::get_current_process_id();
+
+    let session = shell.sessions.get_mut(&session_id)
+        .ok_or(())?;
+
+    // Check ownership
+    if u64::from(session.process_id) != current_pid {
+        return Err(());
+    }
+
+    session.add_to_history(command_line.trim().to_string());
+    Ok(())
+}
+
 // Close shell session
 pub fn close_shell_session(session_id: u32) -> Result<(), ()> {
     let mut shell = SHELL_SYSTEM.lock();
@@ -619,6 +1033,16 @@ pub fn get_builtin_commands() -> Vec<String> {
     shell.builtin_commands.keys().cloned().collect()
 }
 
+// Complete the command being typed: built-in command names starting with the word so far
+pub fn complete_command(line: &str) -> Vec<String> {
+    let word = line.trim_start();
+    if word.contains(char::is_whitespace) {
+        return Vec::new();
+    }
+    let shell = SHELL_SYSTEM.lock();
+    shell.builtin_commands.keys().filter(|name| name.starts_with(word)).cloned().collect()
+}
+
 // Check if command is built-in
 pub fn is_builtin_command(command: &str) -> bool {
     let shell = SHELL_SYSTEM.lock();
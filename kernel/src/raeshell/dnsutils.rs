@@ -0,0 +1,214 @@
+//! Name lookup commands
+//! `host` and `nslookup` over the kernel's DNS resolver: the addresses of a name with the
+//! aliases leading to them, or the names of an address, each with its TTL, followed by the
+//! server that answered and how long it took.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::net::{IpAddr, Ipv4Addr, SocketAddrV4};
+use crate::network::dns::{self, Answer, DnsError, Record, RecordData, RecordType, Resolver, Transport};
+use crate::network::NetworkError;
+use super::ShellResult;
+
+/// What to look up
+enum Query {
+    /// The names an address has
+    Reverse(IpAddr),
+    /// Records of these types for a name
+    Forward(String, Vec<RecordType>),
+}
+
+impl Query {
+    fn new(target: &str, record_type: Option<RecordType>) -> Self {
+        match (target.parse::<IpAddr>(), record_type) {
+            (Ok(address), None | Some(RecordType::Ptr)) => Self::Reverse(address),
+            (_, Some(record_type)) => Self::Forward(String::from(target), alloc::vec![record_type]),
+            (Err(_), None) => Self::Forward(String::from(target), alloc::vec![RecordType::A, RecordType::Aaaa]),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Self::Reverse(address) => dns::reverse_name(*address),
+            Self::Forward(name, _) => name.clone(),
+        }
+    }
+}
+
+/// Answers to a query, one for each type asked, or why there are none
+fn run<T: Transport>(resolver: &Resolver<T>, query: &Query) -> Result<Vec<Answer>, DnsError> {
+    match query {
+        Query::Reverse(address) => Ok(alloc::vec![resolver.reverse(*address)?]),
+        Query::Forward(name, types) => types.iter().map(|&record_type| resolver.lookup(name, record_type)).collect(),
+    }
+}
+
+/// The records of the answers, the aliases leading to them given once
+fn records(answers: &[Answer]) -> Vec<&Record> {
+    let mut records: Vec<&Record> = Vec::new();
+    for record in answers.iter().flat_map(|answer| &answer.records) {
+        if !records.contains(&record) {
+            records.push(record);
+        }
+    }
+    records
+}
+
+fn rcode_name(rcode: u8) -> &'static str {
+    match rcode {
+        dns::RCODE_SERVER_FAILURE => "SERVFAIL",
+        dns::RCODE_NAME_ERROR => "NXDOMAIN",
+        dns::RCODE_REFUSED => "REFUSED",
+        _ => "FORMERR",
+    }
+}
+
+fn describe(error: &DnsError) -> String {
+    match error {
+        DnsError::Network(NetworkError::Timeout | NetworkError::HostUnreachable) => {
+            String::from("connection timed out; no servers could be reached")
+        }
+        DnsError::Network(NetworkError::NetworkUnreachable) => String::from("network unreachable"),
+        DnsError::Network(NetworkError::ConnectionRefused) => String::from("connection refused"),
+        DnsError::Network(_) => String::from("no servers could be reached"),
+        DnsError::Malformed => String::from("reply from server malformed"),
+        DnsError::NameError => String::from("NXDOMAIN"),
+        DnsError::ServerError(rcode) => String::from(rcode_name(*rcode)),
+        DnsError::InvalidName => String::from("invalid name"),
+        DnsError::UnsupportedType => String::from("unsupported query type"),
+        DnsError::CnameLoop => String::from("CNAME loop"),
+    }
+}
+
+/// A resolver asking `server` in place of the configured ones, when one is given
+fn resolver_for<'a, T: Transport>(resolver: &'a Resolver<T>, server: Option<&str>) -> Result<Resolver<&'a T>, String> {
+    let servers = match server {
+        Some(server) => {
+            let address: Ipv4Addr = server.parse().map_err(|_| format!("invalid server address '{}'", server))?;
+            alloc::vec![SocketAddrV4::new(address, dns::DNS_PORT)]
+        }
+        None => resolver.servers().to_vec(),
+    };
+    Ok(resolver.with_servers(servers))
+}
+
+fn elapsed_ms(answers: &[Answer]) -> u64 {
+    answers.iter().map(|answer| answer.elapsed_ms).sum()
+}
+
+/// `host [-t TYPE] NAME|ADDRESS [SERVER]` with the given resolver
+pub fn host<T: Transport>(resolver: &Resolver<T>, args: &[&str]) -> ShellResult {
+    let (record_type, rest) = match args.get(1..).unwrap_or(&[]) {
+        ["-t", record_type, rest @ ..] => match record_type.parse::<RecordType>() {
+            Ok(record_type) => (Some(record_type), rest),
+            Err(_) => return ShellResult::Error(format!("host: invalid type: {}", record_type)),
+        },
+        rest => (None, rest),
+    };
+    let (target, server) = match rest {
+        [target] => (*target, None),
+        [target, server] => (*target, Some(*server)),
+        _ => return ShellResult::Error(String::from("usage: host [-t A|AAAA|CNAME|PTR] NAME|ADDRESS [SERVER]")),
+    };
+    let resolver = match resolver_for(resolver, server) {
+        Ok(resolver) => resolver,
+        Err(message) => return ShellResult::Error(format!("host: {}", message)),
+    };
+    let query = Query::new(target, record_type);
+
+    let answers = match run(&resolver, &query) {
+        Ok(answers) => answers,
+        Err(DnsError::NameError) => return ShellResult::Error(format!("Host {} not found: 3(NXDOMAIN)", target)),
+        Err(DnsError::ServerError(rcode)) => {
+            return ShellResult::Error(format!("Host {} not found: {}({})", target, rcode, rcode_name(rcode)));
+        }
+        Err(error) => return ShellResult::Error(format!(";; {}", describe(&error))),
+    };
+
+    let mut output = String::new();
+    for record in records(&answers) {
+        let _ = match &record.data {
+            RecordData::A(address) => write!(output, "{} has address {}", record.name, address),
+            RecordData::Aaaa(address) => write!(output, "{} has IPv6 address {}", record.name, address),
+            RecordData::Cname(target) => write!(output, "{} is an alias for {}.", record.name, target),
+            RecordData::Ptr(target) => write!(output, "{} domain name pointer {}.", record.name, target),
+        };
+        let _ = writeln!(output, " (TTL {})", record.ttl);
+    }
+    // A type asked for by name that the name has none of is said so
+    if let (Query::Forward(name, _), Some(record_type)) = (&query, record_type) {
+        if !answers.iter().flat_map(|answer| &answer.records).any(|record| record.data.record_type() == record_type) {
+            let _ = writeln!(output, "{} has no {} record", name, record_type.name());
+        }
+    }
+    if let Some(first) = answers.first() {
+        let _ = write!(output, "Received from {}#{} in {} ms", first.server.ip(), first.server.port(), elapsed_ms(&answers));
+    }
+    ShellResult::Success(output)
+}
+
+/// `nslookup [-type=TYPE] NAME|ADDRESS [SERVER]` with the given resolver
+pub fn nslookup<T: Transport>(resolver: &Resolver<T>, args: &[&str]) -> ShellResult {
+    let mut record_type = None;
+    let mut operands = Vec::new();
+    for &arg in args.get(1..).unwrap_or(&[]) {
+        let option = ["-type=", "-query=", "-q=", "-t="].iter().find_map(|prefix| arg.strip_prefix(prefix));
+        match option {
+            Some(value) => match value.parse::<RecordType>() {
+                Ok(value) => record_type = Some(value),
+                Err(_) => return ShellResult::Error(format!("nslookup: unknown query type: {}", value)),
+            },
+            None => operands.push(arg),
+        }
+    }
+    let (target, server) = match operands.as_slice() {
+        [target] => (*target, None),
+        [target, server] => (*target, Some(*server)),
+        _ => return ShellResult::Error(String::from("usage: nslookup [-type=A|AAAA|CNAME|PTR] NAME|ADDRESS [SERVER]")),
+    };
+    let resolver = match resolver_for(resolver, server) {
+        Ok(resolver) => resolver,
+        Err(message) => return ShellResult::Error(format!("nslookup: {}", message)),
+    };
+    let query = Query::new(target, record_type);
+
+    let answers = match run(&resolver, &query) {
+        Ok(answers) => answers,
+        Err(error @ (DnsError::NameError | DnsError::ServerError(_))) => {
+            return ShellResult::Error(format!("** server can't find {}: {}", query.name(), describe(&error)));
+        }
+        Err(error) => return ShellResult::Error(format!(";; {}", describe(&error))),
+    };
+
+    let mut output = String::new();
+    if let Some(first) = answers.first() {
+        let _ = write!(output, "Server:\t\t{}\nAddress:\t{}#{}\n\n", first.server.ip(), first.server.ip(), first.server.port());
+    }
+    let records = records(&answers);
+    let only_aliases = records.iter().all(|record| matches!(record.data, RecordData::Cname(_)));
+    if records.is_empty() || (only_aliases && record_type != Some(RecordType::Cname)) {
+        let _ = writeln!(output, "*** Can't find {}: No answer", query.name());
+    } else if !answers.iter().all(|answer| answer.authoritative) {
+        output.push_str("Non-authoritative answer:\n");
+    }
+    for record in records {
+        let _ = match &record.data {
+            RecordData::A(address) => writeln!(output, "Name:\t{}\nAddress: {}\tttl = {}", record.name, address, record.ttl),
+            RecordData::Aaaa(address) => writeln!(output, "Name:\t{}\nAddress: {}\tttl = {}", record.name, address, record.ttl),
+            RecordData::Cname(target) => writeln!(output, "{}\tcanonical name = {}.\tttl = {}", record.name, target, record.ttl),
+            RecordData::Ptr(target) => writeln!(output, "{}\tname = {}.\tttl = {}", record.name, target, record.ttl),
+        };
+    }
+    let _ = write!(output, "\nQuery time: {} msec", elapsed_ms(&answers));
+    ShellResult::Success(output)
+}
+
+pub fn cmd_host(args: &[&str]) -> ShellResult {
+    host(&Resolver::system(), args)
+}
+
+pub fn cmd_nslookup(args: &[&str]) -> ShellResult {
+    nslookup(&Resolver::system(), args)
+}
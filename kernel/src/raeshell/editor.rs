@@ -0,0 +1,606 @@
+//! Line editor
+//! Readline-style editing of a command line on a terminal in raw mode. Input arrives as the
+//! bytes a terminal sends, UTF-8 text and escape sequences for the editing keys; each edit is
+//! answered with output that redraws the line from the prompt's row, clears what is below it
+//! and places the cursor. Characters rather than bytes are the unit of editing. Besides moving
+//! by character and by word, the editor browses and incrementally searches the history, kills
+//! and yanks text, and completes the word before the cursor through a hook.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Completions for the last word of the line before the cursor, each a whole word to replace it
+pub type Completer = fn(&str) -> Vec<String>;
+
+/// What a key ended the edit with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditResult {
+    /// Enter accepted the line
+    Line(String),
+    /// Ctrl-C abandoned the line
+    Interrupted,
+    /// Ctrl-D on an empty line
+    EndOfInput,
+}
+
+/// A key decoded from the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    /// A control character, by its byte
+    Control(u8),
+    /// A character typed with Alt, which arrives behind ESC
+    Meta(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    Delete,
+    /// Left or right with Ctrl or Alt
+    WordLeft,
+    WordRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InputState {
+    Ground,
+    Escape,
+    /// Inside `ESC [` or `ESC O`, collecting parameters until the final byte
+    Sequence,
+    /// Inside a UTF-8 character, with this many bytes still to come
+    Utf8(usize),
+}
+
+/// Parameters kept for one escape sequence; further ones are ignored
+const MAX_PARAMS: usize = 4;
+
+const CTRL_A: u8 = 0x01;
+const CTRL_B: u8 = 0x02;
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const CTRL_E: u8 = 0x05;
+const CTRL_F: u8 = 0x06;
+const CTRL_G: u8 = 0x07;
+const CTRL_H: u8 = 0x08;
+const TAB: u8 = 0x09;
+const LINE_FEED: u8 = 0x0a;
+const CTRL_K: u8 = 0x0b;
+const CTRL_L: u8 = 0x0c;
+const CARRIAGE_RETURN: u8 = 0x0d;
+const CTRL_N: u8 = 0x0e;
+const CTRL_P: u8 = 0x10;
+const CTRL_R: u8 = 0x12;
+const CTRL_U: u8 = 0x15;
+const CTRL_W: u8 = 0x17;
+const CTRL_Y: u8 = 0x19;
+const DELETE: u8 = 0x7f;
+
+/// An incremental search back through the history
+struct Search {
+    query: String,
+    /// History entry of the last match
+    found: Option<usize>,
+    /// The query matches no entry older than the shown one
+    failed: bool,
+    /// Line and cursor before the search, restored when it is aborted
+    original: (Vec<char>, usize),
+}
+
+pub struct LineEditor {
+    prompt: String,
+    line: Vec<char>,
+    /// Character of the line the cursor is on
+    cursor: usize,
+    history: Vec<String>,
+    /// History entry shown while browsing, and the line typed before browsing began
+    browsing: Option<(usize, Vec<char>)>,
+    kill_buffer: Vec<char>,
+    /// The previous key killed text, so a further kill adds to the kill buffer
+    killing: bool,
+    /// The previous key was a Tab that left the word ambiguous, so another lists the choices
+    completion_ambiguous: bool,
+    search: Option<Search>,
+    completer: Option<Completer>,
+    state: InputState,
+    params: Vec<u16>,
+    utf8: Vec<u8>,
+    /// Output for the terminal not yet taken
+    output: String,
+    columns: usize,
+    /// Row of the cursor below the prompt's first row, as last drawn
+    cursor_row: usize,
+}
+
+impl LineEditor {
+    /// An editor for a terminal `columns` cells wide, with an empty prompt
+    pub fn new(columns: usize) -> Self {
+        LineEditor {
+            prompt: String::new(),
+            line: Vec::new(),
+            cursor: 0,
+            history: Vec::new(),
+            browsing: None,
+            kill_buffer: Vec::new(),
+            killing: false,
+            completion_ambiguous: false,
+            search: None,
+            completer: None,
+            state: InputState::Ground,
+            params: Vec::new(),
+            utf8: Vec::new(),
+            output: String::new(),
+            columns: columns.max(1),
+            cursor_row: 0,
+        }
+    }
+
+    /// Follow a change of the terminal's width, which rewraps the drawn line
+    pub fn set_columns(&mut self, columns: usize) {
+        self.columns = columns.max(1);
+        self.cursor_row = self.cursor_position() / self.columns;
+    }
+
+    pub fn set_completer(&mut self, completer: Completer) {
+        self.completer = Some(completer);
+    }
+
+    /// Replace the history that Up, Down and Ctrl-R browse, oldest entry first
+    pub fn set_history(&mut self, history: Vec<String>) {
+        self.history = history;
+        self.browsing = None;
+    }
+
+    /// Begin a new line at the start of a terminal row, showing `prompt`
+    pub fn start(&mut self, prompt: &str) {
+        self.prompt = String::from(prompt);
+        self.line.clear();
+        self.cursor = 0;
+        self.browsing = None;
+        self.search = None;
+        self.killing = false;
+        self.completion_ambiguous = false;
+        self.cursor_row = 0;
+        self.output.push_str(prompt);
+    }
+
+    /// The line as typed so far
+    pub fn line(&self) -> String {
+        self.line.iter().collect()
+    }
+
+    /// Character of the line the cursor is on
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Output to write to the terminal for the edits made since last taken
+    pub fn take_output(&mut self) -> String {
+        core::mem::take(&mut self.output)
+    }
+
+    /// Insert text at the cursor, leaving out control characters
+    pub fn insert_text(&mut self, text: &str) {
+        let count = self.line.len();
+        let chars = text.chars().filter(|ch| !ch.is_control());
+        self.line.splice(self.cursor..self.cursor, chars);
+        self.cursor += self.line.len() - count;
+        self.redraw();
+    }
+
+    /// Accept the line as Enter does, leaving the terminal on the row below it
+    pub fn submit(&mut self) -> String {
+        self.search = None;
+        self.cursor = self.line.len();
+        self.redraw();
+        self.output.push('\n');
+        self.cursor_row = 0;
+        self.cursor = 0;
+        self.browsing = None;
+        core::mem::take(&mut self.line).into_iter().collect()
+    }
+
+    /// Feed one byte of terminal input, returning what ended the edit if it did
+    pub fn feed_byte(&mut self, byte: u8) -> Option<EditResult> {
+        let key = self.decode(byte)?;
+        let key = match self.search {
+            Some(_) => self.search_key(key)?,
+            None => key,
+        };
+        self.handle_key(key)
+    }
+
+    fn decode(&mut self, byte: u8) -> Option<Key> {
+        match self.state {
+            InputState::Ground => match byte {
+                0x1b => {
+                    self.state = InputState::Escape;
+                    None
+                }
+                0x00..=0x1f | DELETE => Some(Key::Control(byte)),
+                0x20..=0x7e => Some(Key::Char(char::from(byte))),
+                0xc2..=0xf4 => {
+                    self.utf8.clear();
+                    self.utf8.push(byte);
+                    self.state = InputState::Utf8(match byte {
+                        0xc2..=0xdf => 1,
+                        0xe0..=0xef => 2,
+                        _ => 3,
+                    });
+                    None
+                }
+                // A stray continuation byte or one never valid in UTF-8
+                _ => None,
+            },
+            InputState::Utf8(remaining) => {
+                if byte & 0xc0 != 0x80 {
+                    // The character was cut short; the byte starts something new
+                    self.state = InputState::Ground;
+                    return self.decode(byte);
+                }
+                self.utf8.push(byte);
+                if remaining > 1 {
+                    self.state = InputState::Utf8(remaining - 1);
+                    return None;
+                }
+                self.state = InputState::Ground;
+                core::str::from_utf8(&self.utf8).ok().and_then(|text| text.chars().next()).map(Key::Char)
+            }
+            InputState::Escape => {
+                self.state = InputState::Ground;
+                match byte {
+                    b'[' | b'O' => {
+                        self.params.clear();
+                        self.state = InputState::Sequence;
+                        None
+                    }
+                    0x08 | 0x20..=0x7f => Some(Key::Meta(char::from(byte))),
+                    _ => None,
+                }
+            }
+            InputState::Sequence => match byte {
+                b'0'..=b'9' => {
+                    if self.params.is_empty() {
+                        self.params.push(0);
+                    }
+                    if let Some(param) = self.params.last_mut() {
+                        *param = param.saturating_mul(10).saturating_add(u16::from(byte - b'0'));
+                    }
+                    None
+                }
+                b';' => {
+                    if self.params.is_empty() {
+                        self.params.push(0);
+                    }
+                    if self.params.len() < MAX_PARAMS {
+                        self.params.push(0);
+                    }
+                    None
+                }
+                0x40..=0x7e => {
+                    self.state = InputState::Ground;
+                    // The second parameter gives the modifiers, 1 meaning none
+                    let modified = self.params.get(1).is_some_and(|&modifiers| modifiers > 1);
+                    match (byte, self.params.first().copied().unwrap_or(0)) {
+                        (b'A', _) => Some(Key::Up),
+                        (b'B', _) => Some(Key::Down),
+                        (b'C', _) if modified => Some(Key::WordRight),
+                        (b'D', _) if modified => Some(Key::WordLeft),
+                        (b'C', _) => Some(Key::Right),
+                        (b'D', _) => Some(Key::Left),
+                        (b'H', _) | (b'~', 1 | 7) => Some(Key::Home),
+                        (b'F', _) | (b'~', 4 | 8) => Some(Key::End),
+                        (b'~', 3) => Some(Key::Delete),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            },
+        }
+    }
+
+    fn handle_key(&mut self, key: Key) -> Option<EditResult> {
+        let killing = core::mem::take(&mut self.killing);
+        let completion_ambiguous = core::mem::take(&mut self.completion_ambiguous);
+        let end = self.line.len();
+        match key {
+            Key::Char(ch) => {
+                self.line.insert(self.cursor, ch);
+                self.cursor += 1;
+                self.redraw();
+            }
+            Key::Control(CARRIAGE_RETURN | LINE_FEED) => return Some(EditResult::Line(self.submit())),
+            Key::Control(CTRL_A) | Key::Home => self.move_to(0),
+            Key::Control(CTRL_E) | Key::End => self.move_to(end),
+            Key::Control(CTRL_B) | Key::Left => self.move_to(self.cursor.saturating_sub(1)),
+            Key::Control(CTRL_F) | Key::Right => self.move_to((self.cursor + 1).min(end)),
+            Key::Meta('b') | Key::WordLeft => self.move_to(self.word_start()),
+            Key::Meta('f') | Key::WordRight => self.move_to(self.word_end()),
+            Key::Control(CTRL_H | DELETE) => self.delete(self.cursor.saturating_sub(1)..self.cursor),
+            Key::Control(CTRL_D) if self.line.is_empty() => return Some(EditResult::EndOfInput),
+            Key::Control(CTRL_D) | Key::Delete => self.delete(self.cursor..(self.cursor + 1).min(end)),
+            Key::Control(CTRL_K) => self.kill(self.cursor..end, killing),
+            Key::Control(CTRL_U) => self.kill(0..self.cursor, killing),
+            Key::Control(CTRL_W) => self.kill(self.whitespace_word_start()..self.cursor, killing),
+            Key::Meta('d') => self.kill(self.cursor..self.word_end(), killing),
+            Key::Meta('\x7f' | '\x08') => self.kill(self.word_start()..self.cursor, killing),
+            Key::Control(CTRL_Y) => {
+                let cursor = self.cursor;
+                self.line.splice(cursor..cursor, self.kill_buffer.iter().copied());
+                self.cursor += self.kill_buffer.len();
+                self.redraw();
+            }
+            Key::Control(CTRL_P) | Key::Up => self.history_previous(),
+            Key::Control(CTRL_N) | Key::Down => self.history_next(),
+            Key::Control(CTRL_R) => {
+                self.search = Some(Search { query: String::new(), found: None, failed: false, original: (self.line.clone(), self.cursor) });
+                self.redraw();
+            }
+            Key::Control(CTRL_C) => {
+                self.cursor = end;
+                self.redraw();
+                self.output.push_str("^C\n");
+                self.line.clear();
+                self.cursor = 0;
+                self.cursor_row = 0;
+                return Some(EditResult::Interrupted);
+            }
+            Key::Control(CTRL_L) => {
+                self.output.push_str("\x1b[H\x1b[2J");
+                self.cursor_row = 0;
+                self.redraw();
+            }
+            Key::Control(TAB) => self.complete(completion_ambiguous),
+            _ => {}
+        }
+        None
+    }
+
+    /// Handle a key during an incremental search, returning it when it ends the search and
+    /// should then act on the line found
+    fn search_key(&mut self, key: Key) -> Option<Key> {
+        let search = self.search.as_mut()?;
+        match key {
+            Key::Char(ch) => {
+                search.query.push(ch);
+                // A longer query can still match the entry shown
+                let from = search.found.map_or(self.history.len(), |found| found + 1);
+                self.search_history(from);
+            }
+            Key::Control(CTRL_R) => {
+                let from = search.found.unwrap_or(self.history.len());
+                self.search_history(from);
+            }
+            Key::Control(CTRL_H | DELETE) => {
+                search.query.pop();
+                self.search_history(self.history.len());
+            }
+            Key::Control(CTRL_G) => {
+                if let Some(Search { original: (line, cursor), .. }) = self.search.take() {
+                    self.line = line;
+                    self.cursor = cursor;
+                }
+                self.redraw();
+            }
+            key => {
+                self.search = None;
+                self.redraw();
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    /// Show the newest history entry older than `before` that holds the search query, with
+    /// the cursor on the match
+    fn search_history(&mut self, before: usize) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        let candidates = self.history.get(..before).unwrap_or(&self.history);
+        match candidates.iter().rposition(|entry| entry.contains(search.query.as_str())) {
+            Some(index) => {
+                let entry = &self.history[index];
+                let offset = entry.find(search.query.as_str()).unwrap_or(0);
+                self.cursor = entry[..offset].chars().count();
+                self.line = entry.chars().collect();
+                search.found = Some(index);
+                search.failed = false;
+            }
+            None => search.failed = true,
+        }
+        self.redraw();
+    }
+
+    fn history_previous(&mut self) {
+        let index = match &self.browsing {
+            Some((index, _)) => index.checked_sub(1),
+            None => self.history.len().checked_sub(1),
+        };
+        let Some(index) = index else {
+            return;
+        };
+        let typed = match self.browsing.take() {
+            Some((_, typed)) => typed,
+            None => self.line.clone(),
+        };
+        self.browsing = Some((index, typed));
+        self.show_entry(index);
+    }
+
+    fn history_next(&mut self) {
+        let Some((index, typed)) = self.browsing.take() else {
+            return;
+        };
+        if index + 1 < self.history.len() {
+            self.browsing = Some((index + 1, typed));
+            self.show_entry(index + 1);
+        } else {
+            self.cursor = typed.len();
+            self.line = typed;
+            self.redraw();
+        }
+    }
+
+    fn show_entry(&mut self, index: usize) {
+        self.line = self.history.get(index).map(|entry| entry.chars().collect()).unwrap_or_default();
+        self.cursor = self.line.len();
+        self.redraw();
+    }
+
+    /// Complete the word before the cursor: a single completion replaces it, several are
+    /// narrowed to the prefix they share, and a second Tab without progress lists them
+    fn complete(&mut self, list: bool) {
+        let Some(completer) = self.completer else {
+            return;
+        };
+        let before: String = self.line[..self.cursor].iter().collect();
+        let start = self.line[..self.cursor].iter().rposition(|ch| ch.is_whitespace()).map_or(0, |index| index + 1);
+        let completions = completer(&before);
+        let replacement = match completions.as_slice() {
+            [] => return,
+            [only] => format!("{} ", only),
+            [first, rest @ ..] => {
+                let shared = rest.iter().fold(first.chars().count(), |shared, other| {
+                    first.chars().zip(other.chars()).take(shared).take_while(|(a, b)| a == b).count()
+                });
+                if shared > self.cursor - start {
+                    first.chars().take(shared).collect()
+                } else {
+                    if list {
+                        self.list_completions(&completions);
+                    } else {
+                        self.completion_ambiguous = true;
+                    }
+                    return;
+                }
+            }
+        };
+        let count = self.line.len();
+        self.line.splice(start..self.cursor, replacement.chars());
+        self.cursor = self.cursor + self.line.len() - count;
+        self.redraw();
+    }
+
+    /// Show the completions on the rows below the line, then the line again
+    fn list_completions(&mut self, completions: &[String]) {
+        let cursor = self.cursor;
+        self.cursor = self.line.len();
+        self.redraw();
+        self.output.push('\n');
+        self.output.push_str(&completions.join("  "));
+        self.output.push('\n');
+        self.cursor_row = 0;
+        self.cursor = cursor;
+        self.redraw();
+    }
+
+    fn move_to(&mut self, cursor: usize) {
+        if cursor != self.cursor {
+            self.cursor = cursor;
+            self.redraw();
+        }
+    }
+
+    fn delete(&mut self, range: Range<usize>) {
+        if !range.is_empty() {
+            self.cursor = range.start;
+            self.line.drain(range);
+            self.redraw();
+        }
+    }
+
+    /// Cut `range` into the kill buffer, adding to what the previous key killed when `append`
+    fn kill(&mut self, range: Range<usize>, append: bool) {
+        let killed: Vec<char> = self.line.drain(range.clone()).collect();
+        if !append {
+            self.kill_buffer = killed;
+        } else if range.start < self.cursor {
+            // Killed backwards, so it came before the text killed already
+            self.kill_buffer.splice(0..0, killed);
+        } else {
+            self.kill_buffer.extend(killed);
+        }
+        self.killing = true;
+        self.cursor = range.start;
+        self.redraw();
+    }
+
+    /// Start of the word before the cursor, words being runs of letters and digits
+    fn word_start(&self) -> usize {
+        let mut index = self.cursor;
+        while index > 0 && !self.line[index - 1].is_alphanumeric() {
+            index -= 1;
+        }
+        while index > 0 && self.line[index - 1].is_alphanumeric() {
+            index -= 1;
+        }
+        index
+    }
+
+    /// End of the word at or after the cursor
+    fn word_end(&self) -> usize {
+        let mut index = self.cursor;
+        while index < self.line.len() && !self.line[index].is_alphanumeric() {
+            index += 1;
+        }
+        while index < self.line.len() && self.line[index].is_alphanumeric() {
+            index += 1;
+        }
+        index
+    }
+
+    /// Start of the whitespace-delimited word before the cursor
+    fn whitespace_word_start(&self) -> usize {
+        let mut index = self.cursor;
+        while index > 0 && self.line[index - 1].is_whitespace() {
+            index -= 1;
+        }
+        while index > 0 && !self.line[index - 1].is_whitespace() {
+            index -= 1;
+        }
+        index
+    }
+
+    /// The prompt shown before the line: the search state while searching
+    fn shown_prompt(&self) -> String {
+        match &self.search {
+            Some(search) => format!("({}reverse-i-search)`{}': ", if search.failed { "failed " } else { "" }, search.query),
+            None => self.prompt.clone(),
+        }
+    }
+
+    /// Cell of the cursor counted from the start of the prompt
+    fn cursor_position(&self) -> usize {
+        self.shown_prompt().chars().count() + self.cursor
+    }
+
+    /// Rewrite the prompt and line from the prompt's first row, clear the rest of the screen
+    /// and put the cursor back on its character
+    fn redraw(&mut self) {
+        let prompt = self.shown_prompt();
+        if self.cursor_row > 0 {
+            self.output.push_str(&format!("\x1b[{}A", self.cursor_row));
+        }
+        self.output.push('\r');
+        self.output.push_str(&prompt);
+        self.output.extend(self.line.iter());
+        let prompt_length = prompt.chars().count();
+        let end = prompt_length + self.line.len();
+        if end > 0 && end.is_multiple_of(self.columns) {
+            // Wrap now, so the cursor is on the row below the full one and not held at its end
+            self.output.push_str(" \r");
+        }
+        self.output.push_str("\x1b[J");
+        let position = prompt_length + self.cursor;
+        let (end_row, cursor_row) = (end / self.columns, position / self.columns);
+        if end_row > cursor_row {
+            self.output.push_str(&format!("\x1b[{}A", end_row - cursor_row));
+        }
+        self.output.push_str(&format!("\x1b[{}G", position % self.columns + 1));
+        self.cursor_row = cursor_row;
+    }
+}
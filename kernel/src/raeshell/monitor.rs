@@ -0,0 +1,212 @@
+//! Resource monitor
+//! The reports behind `free`, `uptime` and `top`. Memory comes from the frame allocator and
+//! the kernel heap, uptime from the timer with the boot time read from the RTC, load averages
+//! from the scheduler's run queues, and each process's CPU use from the CPU time it gained
+//! between two samples.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use crate::process::{self, Priority, ProcessState};
+
+/// Refresh interval of `top` when none is given
+pub const DEFAULT_TOP_INTERVAL_MS: u64 = 1_000;
+
+/// Memory in KiB, as `free` shows it: physical memory by frame, then the kernel heap, whose
+/// cached column is the free slots of its slab caches
+pub fn free_report() -> String {
+    let memory = crate::memory::memory_usage();
+    let heap_size = crate::heap::heap_size() as u64;
+    let heap_used = crate::heap::heap_used() as u64;
+    let slab_free: u64 = crate::heap::slab::slab_stats()
+        .iter()
+        .map(|cache| ((cache.capacity - cache.objects_in_use) * cache.slot_size) as u64)
+        .sum();
+    let row = |name: &str, [total, used, free, cached]: [u64; 4]| {
+        format!("{:<6}{:>12}{:>12}{:>12}{:>12}", name, total / 1024, used / 1024, free / 1024, cached / 1024)
+    };
+    format!(
+        "{:<6}{:>12}{:>12}{:>12}{:>12}\n{}\n{}",
+        "", "total", "used", "free", "cached",
+        row("Mem:", [memory.total, memory.used, memory.free, memory.cached]),
+        row("Heap:", [heap_size, heap_used, heap_size.saturating_sub(heap_used), slab_free]),
+    )
+}
+
+/// `H:MM:SS`, led by the days when there are any
+pub fn format_uptime(seconds: u64) -> String {
+    let clock = format!("{}:{:02}:{:02}", seconds / 3600 % 24, seconds / 60 % 60, seconds % 60);
+    match seconds / 86_400 {
+        0 => clock,
+        1 => format!("1 day, {}", clock),
+        days => format!("{} days, {}", days, clock),
+    }
+}
+
+/// `0.52, 0.31, 0.12`
+pub fn format_load_averages() -> String {
+    let [one, five, fifteen] = process::load_averages().hundredths();
+    format!("{}.{:02}, {}.{:02}, {}.{:02}", one / 100, one % 100, five / 100, five % 100, fifteen / 100, fifteen % 100)
+}
+
+/// The boot time in local time, as `uptime -s` shows it
+pub fn boot_time_report() -> String {
+    let booted = crate::time::timezone::to_local(crate::time::boot_time() as i64).datetime;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        booted.year, booted.month, booted.day, booted.hour, booted.minute, booted.second
+    )
+}
+
+/// `up 1:02:03, 12 processes, load average: 0.52, 0.31, 0.12`
+pub fn uptime_report() -> String {
+    format!(
+        "up {}, {} processes, load average: {}",
+        format_uptime(crate::time::get_uptime_seconds()),
+        process::get_process_count(),
+        format_load_averages()
+    )
+}
+
+/// One process in `top`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopEntry {
+    pub pid: u64,
+    pub name: String,
+    pub state: ProcessState,
+    pub priority: Priority,
+    /// CPU time over the sampled interval, in tenths of a percent of one CPU
+    pub cpu_tenths: u64,
+    /// Resident memory of the process's address space, in KiB; 0 for kernel threads
+    pub resident_kib: u64,
+}
+
+/// Samples the process list, measuring CPU use from one sample to the next
+pub struct TopSampler {
+    /// CPU time of each process at the previous sample
+    previous: BTreeMap<u64, u64>,
+    /// Uptime in milliseconds at the previous sample
+    previous_ms: u64,
+}
+
+impl TopSampler {
+    /// A sampler whose first sample measures CPU use since boot
+    pub fn new() -> Self {
+        Self { previous: BTreeMap::new(), previous_ms: 0 }
+    }
+
+    /// The processes, busiest first, with their CPU use since the previous sample
+    pub fn sample(&mut self) -> Vec<TopEntry> {
+        let now = crate::time::get_uptime_ms();
+        let elapsed_ms = now.saturating_sub(self.previous_ms).max(1);
+        let processes = process::try_with_processes(|processes| {
+            processes
+                .iter()
+                .flatten()
+                .map(|process| (process.pid, process.name.clone(), process.state, process.priority, process.cpu_time, process.address_space_id))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+        let mut cpu_times = BTreeMap::new();
+        let mut entries: Vec<TopEntry> = processes
+            .into_iter()
+            .map(|(pid, name, state, priority, cpu_time, address_space_id)| {
+                cpu_times.insert(pid, cpu_time);
+                let used_us = cpu_time.saturating_sub(self.previous.get(&pid).copied().unwrap_or(0));
+                let resident_pages = address_space_id.and_then(crate::vmm::resident_pages).unwrap_or(0);
+                TopEntry {
+                    pid,
+                    name,
+                    state,
+                    priority,
+                    // Microseconds per millisecond is tenths of a percent
+                    cpu_tenths: used_us / elapsed_ms,
+                    resident_kib: resident_pages as u64 * 4,
+                }
+            })
+            .collect();
+        entries.sort_by(|a, b| b.cpu_tenths.cmp(&a.cpu_tenths).then(a.pid.cmp(&b.pid)));
+
+        self.previous = cpu_times;
+        self.previous_ms = now;
+        entries
+    }
+}
+
+fn state_name(state: ProcessState) -> &'static str {
+    match state {
+        ProcessState::Running => "running",
+        ProcessState::Ready => "ready",
+        ProcessState::Blocked => "blocked",
+        ProcessState::Terminated => "exited",
+        ProcessState::Zombie(_) => "zombie",
+    }
+}
+
+fn priority_name(priority: Priority) -> &'static str {
+    match priority {
+        Priority::Low => "low",
+        Priority::Normal => "normal",
+        Priority::High => "high",
+        Priority::Gaming => "gaming",
+    }
+}
+
+/// A screen of `top`: uptime and load, task and memory totals, then the processes in
+/// `entries`, as many as fit in `rows` rows when given
+pub fn top_screen(entries: &[TopEntry], rows: Option<usize>) -> String {
+    let runnable = entries.iter().filter(|entry| matches!(entry.state, ProcessState::Running | ProcessState::Ready)).count();
+    let blocked = entries.iter().filter(|entry| entry.state == ProcessState::Blocked).count();
+    let memory = crate::memory::memory_usage();
+
+    let mut screen = String::new();
+    let _ = writeln!(screen, "top - {}", uptime_report());
+    let _ = writeln!(
+        screen,
+        "Tasks: {} total, {} runnable, {} blocked, {} exited",
+        entries.len(), runnable, blocked, entries.len() - runnable - blocked
+    );
+    let _ = writeln!(
+        screen,
+        "KiB Mem: {} total, {} used, {} free, {} cached",
+        memory.total / 1024, memory.used / 1024, memory.free / 1024, memory.cached / 1024
+    );
+    let _ = write!(screen, "\n{:>6} {:>6} {:>9} {:<8} {:<7} {}", "PID", "%CPU", "RES KiB", "STATE", "PRI", "NAME");
+
+    let shown = rows.map_or(entries.len(), |rows| rows.saturating_sub(5));
+    for entry in entries.iter().take(shown) {
+        let cpu = format!("{}.{}", entry.cpu_tenths / 10, entry.cpu_tenths % 10);
+        let _ = write!(
+            screen,
+            "\n{:>6} {:>6} {:>9} {:<8} {:<7} {}",
+            entry.pid, cpu, entry.resident_kib, state_name(entry.state), priority_name(entry.priority), entry.name
+        );
+    }
+    screen
+}
+
+/// The refresh interval of a `top` command line to show live, updating in place until
+/// quit: `top`, or `top -d SECONDS`. `None` for other lines, including `top -n COUNT`,
+/// which prints its screens and ends
+pub fn live_top_interval(line: &str) -> Option<u64> {
+    match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["top"] => Some(DEFAULT_TOP_INTERVAL_MS),
+        ["top", "-d", seconds] => parse_interval(seconds),
+        _ => None,
+    }
+}
+
+/// Seconds given to `top -d`, as milliseconds; whole or with a fraction, and positive
+pub fn parse_interval(seconds: &str) -> Option<u64> {
+    let (whole, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    if fraction.len() > 3 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let whole: u64 = if whole.is_empty() { 0 } else { whole.parse().ok()? };
+    let fraction: u64 = format!("{:0<3}", fraction).parse().ok()?;
+    let interval = whole.checked_mul(1000)?.checked_add(fraction)?;
+    (interval > 0).then_some(interval)
+}
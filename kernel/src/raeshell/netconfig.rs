@@ -0,0 +1,313 @@
+//! Network configuration commands
+//! `ifconfig` and `ip` over the kernel's network interfaces: listing them with their state and
+//! addresses, bringing them up and down, assigning static addresses, and showing and changing
+//! the routing table.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::net::Ipv4Addr;
+use crate::network::interface::{self, Interface, InterfaceKind, Ipv4Cidr, Route};
+use crate::network::NetworkError;
+use super::ShellResult;
+
+fn describe(error: NetworkError) -> &'static str {
+    match error {
+        NetworkError::InterfaceNotFound => "no such interface",
+        NetworkError::InvalidAddress => "invalid address",
+        NetworkError::AddressExists => "address already assigned",
+        NetworkError::AddressNotFound => "address not assigned",
+        NetworkError::RouteExists => "route already exists",
+        NetworkError::RouteNotFound => "no such route",
+        NetworkError::NetworkUnreachable => "network unreachable",
+        _ => "request failed",
+    }
+}
+
+fn format_mac(mac: &[u8; 6]) -> String {
+    format!("{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", mac[0], mac[1], mac[2], mac[3], mac[4], mac[5])
+}
+
+fn flags(interface: &Interface) -> String {
+    let mut flags = Vec::new();
+    if interface.up {
+        flags.push("UP");
+    }
+    flags.push(match interface.kind {
+        InterfaceKind::Loopback => "LOOPBACK",
+        InterfaceKind::Ethernet => "BROADCAST",
+    });
+    if interface.up {
+        flags.push("RUNNING");
+    }
+    flags.join(",")
+}
+
+/// An interface as `ifconfig` shows it
+fn ifconfig_entry(interface: &Interface) -> String {
+    let mut entry = format!("{}: flags=<{}>  mtu {}", interface.name, flags(interface), interface.mtu);
+    for address in &interface.addresses {
+        let _ = write!(entry, "\n        inet {}  netmask {}", address.address, address.netmask());
+        if interface.kind == InterfaceKind::Ethernet && address.prefix_len < 31 {
+            let _ = write!(entry, "  broadcast {}", address.broadcast());
+        }
+    }
+    match interface.kind {
+        InterfaceKind::Loopback => entry.push_str("\n        loop"),
+        InterfaceKind::Ethernet => {
+            let _ = write!(entry, "\n        ether {}", format_mac(&interface.mac_address));
+        }
+    }
+    if let Some(statistics) = interface.statistics {
+        let _ = write!(
+            entry,
+            "\n        RX packets {}  bytes {}\n        TX packets {}  bytes {}",
+            statistics.rx_packets, statistics.rx_bytes, statistics.tx_packets, statistics.tx_bytes
+        );
+    }
+    entry
+}
+
+/// `ADDR[/PREFIX] [netmask MASK]`, the address `ifconfig` assigns
+fn parse_ifconfig_address(args: &[&str]) -> Result<Ipv4Cidr, NetworkError> {
+    match args {
+        [address] => address.parse(),
+        [address, "netmask", netmask] if !address.contains('/') => {
+            let netmask = netmask.parse().map_err(|_| NetworkError::InvalidAddress)?;
+            Ipv4Cidr::new(address.parse().map_err(|_| NetworkError::InvalidAddress)?, Ipv4Cidr::prefix_of_netmask(netmask)?)
+        }
+        _ => Err(NetworkError::InvalidAddress),
+    }
+}
+
+/// `ifconfig [-a]` lists the interfaces that are up, or all of them; `ifconfig IFACE` shows
+/// one; `ifconfig IFACE up|down` brings it up or down; `ifconfig IFACE ADDR[/PREFIX]
+/// [netmask MASK] [up|down]` assigns its address
+pub fn cmd_ifconfig(args: &[&str]) -> ShellResult {
+    const USAGE: &str = "usage: ifconfig [-a] [IFACE [ADDR[/PREFIX] [netmask MASK]] [up|down]]";
+    let error = |error: NetworkError| ShellResult::Error(format!("ifconfig: {}", describe(error)));
+    let args = &args[1..];
+    match args {
+        [] | ["-a"] => {
+            let all = !args.is_empty();
+            let entries: Vec<String> = interface::list_interfaces()
+                .iter()
+                .filter(|interface| all || interface.up)
+                .map(ifconfig_entry)
+                .collect();
+            ShellResult::Success(entries.join("\n\n"))
+        }
+        [name] => match interface::interface_info(name) {
+            Ok(interface) => ShellResult::Success(ifconfig_entry(&interface)),
+            Err(e) => error(e),
+        },
+        [name, rest @ ..] => {
+            let (address, state) = match rest {
+                [address @ .., "up"] => (address, Some(true)),
+                [address @ .., "down"] => (address, Some(false)),
+                address => (address, None),
+            };
+            if !address.is_empty() {
+                let result = parse_ifconfig_address(address).and_then(|address| interface::set_address(name, address));
+                match result {
+                    Err(NetworkError::InvalidAddress) => return ShellResult::Error(USAGE.to_string()),
+                    Err(e) => return error(e),
+                    Ok(()) => {}
+                }
+            }
+            match state.map_or(Ok(()), |up| interface::set_interface_state(name, up)) {
+                Ok(()) => ShellResult::Success(String::new()),
+                Err(e) => error(e),
+            }
+        }
+    }
+}
+
+/// The first line of an interface as `ip link` and `ip addr` show it
+fn ip_link_entry(interface: &Interface) -> String {
+    let mut flags = Vec::new();
+    flags.push(match interface.kind {
+        InterfaceKind::Loopback => "LOOPBACK",
+        InterfaceKind::Ethernet => "BROADCAST",
+    });
+    if interface.up {
+        flags.push("UP");
+    }
+    let (link, broadcast) = match interface.kind {
+        InterfaceKind::Loopback => ("loopback", [0; 6]),
+        InterfaceKind::Ethernet => ("ether", [0xff; 6]),
+    };
+    format!(
+        "{}: {}: <{}> mtu {} state {}\n    link/{} {} brd {}",
+        interface.index,
+        interface.name,
+        flags.join(","),
+        interface.mtu,
+        if interface.up { "UP" } else { "DOWN" },
+        link,
+        format_mac(&interface.mac_address),
+        format_mac(&broadcast)
+    )
+}
+
+fn ip_addr_entry(interface: &Interface) -> String {
+    let mut entry = ip_link_entry(interface);
+    let scope = match interface.kind {
+        InterfaceKind::Loopback => "host",
+        InterfaceKind::Ethernet => "global",
+    };
+    for address in &interface.addresses {
+        let _ = write!(entry, "\n    inet {}", address);
+        if interface.kind == InterfaceKind::Ethernet && address.prefix_len < 31 {
+            let _ = write!(entry, " brd {}", address.broadcast());
+        }
+        let _ = write!(entry, " scope {} {}", scope, interface.name);
+    }
+    entry
+}
+
+fn route_entry(route: &Route) -> String {
+    let mut entry = if route.destination.prefix_len == 0 {
+        String::from("default")
+    } else {
+        route.destination.to_string()
+    };
+    if let Some(gateway) = route.gateway {
+        let _ = write!(entry, " via {}", gateway);
+    }
+    let _ = write!(entry, " dev {}", route.interface);
+    if route.gateway.is_none() {
+        if let Some(source) = route.source {
+            let _ = write!(entry, " proto kernel scope link src {}", source);
+        }
+    }
+    if route.metric != 0 {
+        let _ = write!(entry, " metric {}", route.metric);
+    }
+    entry
+}
+
+/// Interfaces shown by `ip link` and `ip addr`: all of them, or the one named
+fn shown_interfaces(args: &[&str]) -> Result<Vec<Interface>, String> {
+    match args {
+        [] | ["show"] => Ok(interface::list_interfaces()),
+        ["show", name] | ["show", "dev", name] => interface::interface_info(name)
+            .map(|interface| alloc::vec![interface])
+            .map_err(|e| format!("ip: {} \"{}\"", describe(e), name)),
+        _ => Err(String::from("ip: unknown arguments")),
+    }
+}
+
+/// `NAME VALUE` pairs after an `ip` subcommand, such as `dev eth0 via 10.0.2.2`
+fn ip_options<'a>(args: &[&'a str], names: &[&str]) -> Result<Vec<(&'a str, &'a str)>, String> {
+    args.chunks(2)
+        .map(|pair| match pair {
+            [name, value] if names.contains(name) => Ok((*name, *value)),
+            _ => Err(format!("ip: unexpected \"{}\"", pair[0])),
+        })
+        .collect()
+}
+
+fn ip_option<'a>(options: &[(&str, &'a str)], name: &str) -> Option<&'a str> {
+    options.iter().find(|(option, _)| *option == name).map(|(_, value)| *value)
+}
+
+fn parse_destination(destination: &str) -> Result<Ipv4Cidr, NetworkError> {
+    match destination {
+        "default" => Ipv4Cidr::new(Ipv4Addr::UNSPECIFIED, 0),
+        destination => destination.parse(),
+    }
+}
+
+fn ip_link(args: &[&str]) -> Result<String, String> {
+    match args {
+        ["set", name, state] | ["set", "dev", name, state] => {
+            let up = match *state {
+                "up" => true,
+                "down" => false,
+                _ => return Err(format!("ip: unknown link state \"{}\"", state)),
+            };
+            interface::set_interface_state(name, up).map_err(|e| format!("ip: {} \"{}\"", describe(e), name))?;
+            Ok(String::new())
+        }
+        args => Ok(shown_interfaces(args)?.iter().map(ip_link_entry).collect::<Vec<_>>().join("\n")),
+    }
+}
+
+fn ip_addr(args: &[&str]) -> Result<String, String> {
+    match args {
+        [action @ ("add" | "del"), address, rest @ ..] => {
+            let options = ip_options(rest, &["dev"])?;
+            let name = ip_option(&options, "dev").ok_or("ip: no device given with \"dev\"")?;
+            let address: Ipv4Cidr = address.parse().map_err(|e| format!("ip: {} \"{}\"", describe(e), address))?;
+            let result = match *action {
+                "add" => interface::add_address(name, address),
+                _ => interface::remove_address(name, address),
+            };
+            result.map_err(|e| format!("ip: {}", describe(e)))?;
+            Ok(String::new())
+        }
+        args => Ok(shown_interfaces(args)?.iter().map(ip_addr_entry).collect::<Vec<_>>().join("\n")),
+    }
+}
+
+fn ip_route(args: &[&str]) -> Result<String, String> {
+    match args {
+        [] | ["show"] => Ok(interface::routes().iter().map(route_entry).collect::<Vec<_>>().join("\n")),
+        ["get", address] => {
+            let address: Ipv4Addr = address.parse().map_err(|_| format!("ip: invalid address \"{}\"", address))?;
+            let route = interface::route_to(address).map_err(|e| format!("ip: {}", describe(e)))?;
+            let mut entry = address.to_string();
+            if let Some(gateway) = route.gateway {
+                let _ = write!(entry, " via {}", gateway);
+            }
+            let _ = write!(entry, " dev {}", route.interface);
+            if let Some(source) = route.source {
+                let _ = write!(entry, " src {}", source);
+            }
+            Ok(entry)
+        }
+        ["add", destination, rest @ ..] => {
+            let options = ip_options(rest, &["via", "dev", "metric"])?;
+            let destination = parse_destination(destination).map_err(|e| format!("ip: {} \"{}\"", describe(e), destination))?;
+            let gateway = ip_option(&options, "via")
+                .map(|gateway| gateway.parse().map_err(|_| format!("ip: invalid gateway \"{}\"", gateway)))
+                .transpose()?;
+            let metric = ip_option(&options, "metric")
+                .map(|metric| metric.parse().map_err(|_| format!("ip: invalid metric \"{}\"", metric)))
+                .transpose()?
+                .unwrap_or(0);
+            if gateway.is_none() && ip_option(&options, "dev").is_none() {
+                return Err(String::from("ip: a route needs \"via\" or \"dev\""));
+            }
+            interface::add_route(destination, gateway, ip_option(&options, "dev"), metric).map_err(|e| format!("ip: {}", describe(e)))?;
+            Ok(String::new())
+        }
+        ["del", destination] => {
+            let destination = parse_destination(destination).map_err(|e| format!("ip: {} \"{}\"", describe(e), destination))?;
+            interface::remove_route(destination).map_err(|e| format!("ip: {}", describe(e)))?;
+            Ok(String::new())
+        }
+        _ => Err(String::from("ip: unknown arguments")),
+    }
+}
+
+/// `ip link [show [IFACE]]`, `ip link set IFACE up|down`, `ip addr [show [IFACE]]`,
+/// `ip addr add|del ADDR/PREFIX dev IFACE`, `ip route [show]`, `ip route get ADDR`,
+/// `ip route add DEST|default [via GATEWAY] [dev IFACE] [metric N]`, `ip route del DEST|default`
+pub fn cmd_ip(args: &[&str]) -> ShellResult {
+    let result = match args.get(1..).unwrap_or(&[]) {
+        [object, rest @ ..] => match *object {
+            "l" | "link" => ip_link(rest),
+            "a" | "addr" | "address" => ip_addr(rest),
+            "r" | "route" => ip_route(rest),
+            _ => Err(format!("ip: unknown object \"{}\"", object)),
+        },
+        [] => Err(String::from("usage: ip link|addr|route [COMMAND]")),
+    };
+    match result {
+        Ok(output) => ShellResult::Success(output),
+        Err(message) => ShellResult::Error(message),
+    }
+}
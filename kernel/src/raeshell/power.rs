@@ -0,0 +1,148 @@
+//! Power commands
+//! `battery` and `acpi` over the power service: the combined charge with its state and time
+//! estimate, each battery on its own with its capacities, and whether the AC adapters are
+//! plugged in.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use crate::power::{self, Battery, ChargingState, PowerError, PowerStatus, PowerUnit};
+use super::ShellResult;
+
+fn state_name(state: ChargingState) -> &'static str {
+    match state {
+        ChargingState::Charging => "Charging",
+        ChargingState::Discharging => "Discharging",
+        ChargingState::Full => "Full",
+        ChargingState::NotCharging => "Not charging",
+    }
+}
+
+fn adapter_state(online: bool) -> &'static str {
+    if online { "on-line" } else { "off-line" }
+}
+
+/// `H:MM`
+fn format_minutes(minutes: u64) -> String {
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+/// `42%, discharging, 1:23 remaining` for the batteries together, then the adapter
+pub fn battery_report(status: &PowerStatus) -> String {
+    let mut lines = Vec::new();
+    match (status.percent(), status.state()) {
+        (Some(percent), Some(state)) => {
+            let mut line = format!("Battery: {}%, {}", percent, state_name(state).to_lowercase());
+            if let Some(minutes) = status.time_to_empty() {
+                line.push_str(&format!(", {} remaining", format_minutes(minutes)));
+            } else if let Some(minutes) = status.time_to_full() {
+                line.push_str(&format!(", {} until charged", format_minutes(minutes)));
+            }
+            lines.push(line);
+        }
+        (None, Some(state)) => lines.push(format!("Battery: {}", state_name(state).to_lowercase())),
+        _ => lines.push("No battery".to_string()),
+    }
+    if !status.adapters.is_empty() {
+        lines.push(format!("AC adapter: {}", adapter_state(status.on_ac())));
+    }
+    lines.join("\n")
+}
+
+/// `Battery 0: Discharging, 42%, 01:23:00 remaining`, as `acpi -b` shows it
+fn battery_line(index: usize, battery: &Battery) -> String {
+    let mut line = format!("Battery {}: {}", index, state_name(battery.state));
+    if let Some(percent) = battery.percent() {
+        line.push_str(&format!(", {}%", percent));
+    }
+    if let Some(minutes) = battery.time_to_empty() {
+        line.push_str(&format!(", {:02}:{:02}:00 remaining", minutes / 60, minutes % 60));
+    } else if let Some(minutes) = battery.time_to_full() {
+        line.push_str(&format!(", {:02}:{:02}:00 until charged", minutes / 60, minutes % 60));
+    }
+    line
+}
+
+/// `Battery 0: design capacity 50000 mWh, last full capacity 45000 mWh = 90%`, as `acpi -i`
+/// shows it
+fn battery_info_line(index: usize, battery: &Battery) -> String {
+    let unit = match battery.unit {
+        PowerUnit::MilliwattHours => "mWh",
+        PowerUnit::MilliampHours => "mAh",
+    };
+    let capacity = |value: Option<u64>| value.map_or("unknown".to_string(), |value| format!("{} {}", value, unit));
+    let mut line = format!(
+        "Battery {}: design capacity {}, last full capacity {}",
+        index, capacity(battery.design_capacity), capacity(battery.full_capacity)
+    );
+    if let (Some(design), Some(full)) = (battery.design_capacity.filter(|&design| design > 0), battery.full_capacity) {
+        line.push_str(&format!(" = {}%", (full * 100 + design / 2) / design));
+    }
+    line
+}
+
+/// The `acpi` listing: batteries, their capacities when `info` is set, then adapters
+pub fn acpi_report(status: &PowerStatus, batteries: bool, adapters: bool, info: bool) -> String {
+    let mut lines = Vec::new();
+    if batteries {
+        if status.batteries.is_empty() {
+            lines.push("No battery".to_string());
+        }
+        for (index, battery) in status.batteries.iter().enumerate() {
+            lines.push(battery_line(index, battery));
+            if info {
+                lines.push(battery_info_line(index, battery));
+            }
+        }
+    }
+    if adapters {
+        if status.adapters.is_empty() {
+            lines.push("No AC adapter".to_string());
+        }
+        for (index, &online) in status.adapters.iter().enumerate() {
+            lines.push(format!("Adapter {}: {}", index, adapter_state(online)));
+        }
+    }
+    lines.join("\n")
+}
+
+/// `battery` shows the combined charge, its state and time estimate, and the adapter
+pub fn cmd_battery(args: &[&str]) -> ShellResult {
+    if args.len() > 1 {
+        return ShellResult::Error("usage: battery".to_string());
+    }
+    match power::status() {
+        Ok(status) => ShellResult::Success(battery_report(&status)),
+        Err(PowerError::BadObject(path)) => ShellResult::Error(format!("battery: cannot read {}", path)),
+    }
+}
+
+/// `poweroff` detaches the drivers and turns the machine off
+pub fn cmd_poweroff(args: &[&str]) -> ShellResult {
+    if args.len() > 1 {
+        return ShellResult::Error("usage: poweroff".to_string());
+    }
+    power::shutdown()
+}
+
+/// `acpi [-b] [-a] [-i] [-V]` lists the batteries (the default), the adapters, the batteries'
+/// capacities, or everything
+pub fn cmd_acpi(args: &[&str]) -> ShellResult {
+    let (mut batteries, mut adapters, mut info) = (false, false, false);
+    for arg in &args[1..] {
+        match *arg {
+            "-b" | "--battery" => batteries = true,
+            "-a" | "--ac-adapter" => adapters = true,
+            "-i" | "--details" => info = true,
+            "-V" | "--everything" => (batteries, adapters, info) = (true, true, true),
+            _ => return ShellResult::Error("usage: acpi [-b] [-a] [-i] [-V]".to_string()),
+        }
+    }
+    if !adapters {
+        batteries = true;
+    }
+    match power::status() {
+        Ok(status) => ShellResult::Success(acpi_report(&status, batteries, adapters, info)),
+        Err(PowerError::BadObject(path)) => ShellResult::Error(format!("acpi: cannot read {}", path)),
+    }
+}
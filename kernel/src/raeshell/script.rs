@@ -0,0 +1,1644 @@
+//! Script interpreter
+//! Runs shell scripts: commands joined by `;`, newlines, `&&` and `||` and negated with `!`,
+//! in `if`/`elif`/`else`/`fi` conditionals, `for`, `while` and `until` loops with `break` and
+//! `continue`, and `case` statements matching glob patterns, ended early by `exit`. Functions
+//! defined with `name() { ... }` run in the caller's context and leave with `return`, and
+//! aliases from `alias name=text` are replaced by their text in command position. Before a
+//! command runs its words are expanded: `$name` and `${name}` variables, `$(...)` and backquote
+//! command substitution, and `$((...))` integer arithmetic. Unquoted expansions are split into
+//! fields at whitespace. Commands joined by `|` form a pipeline, each stage reading what the
+//! one before it wrote, and `>` and `>>` send a stage's output to a file instead, emptied first
+//! or appended to. Simple commands go to a runner with their standard streams, and a command's
+//! exit status is 0 when it succeeds and 1 when it fails.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::filesystem::{self, FileType};
+use super::{ShellResult, Streams};
+
+/// Runs one simple command, given its name followed by its arguments and its standard streams
+pub type CommandRunner = fn(&[&str], &mut Streams) -> ShellResult;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptError {
+    /// The script ends inside a quote, substitution or compound command, so more input is needed
+    Incomplete,
+    /// A token where the grammar does not allow one
+    Syntax(String),
+    /// An arithmetic expression that cannot be evaluated
+    Arithmetic(String),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::Incomplete => write!(f, "unexpected end of script"),
+            ScriptError::Syntax(message) => write!(f, "syntax error: {}", message),
+            ScriptError::Arithmetic(message) => write!(f, "arithmetic error: {}", message),
+        }
+    }
+}
+
+/// A piece of a word, with whether it was quoted, which keeps it from field splitting and
+/// pattern matching
+#[derive(Debug, Clone, PartialEq)]
+enum WordPart {
+    Literal(String, bool),
+    /// `$name` or `${name}`
+    Variable(String, bool),
+    /// `$(...)` or backquotes, holding the script to run
+    Command(String, bool),
+    /// `$((...))`, holding the expression
+    Arithmetic(String, bool),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Word {
+    parts: Vec<WordPart>,
+}
+
+impl Word {
+    /// The word's text if it is plain unquoted text, as reserved words and names are
+    fn literal(&self) -> Option<&str> {
+        match self.parts.as_slice() {
+            [WordPart::Literal(text, false)] => Some(text),
+            _ => None,
+        }
+    }
+
+    /// The name and value of a `name=value` word
+    fn assignment(&self) -> Option<(String, Word)> {
+        let Some(WordPart::Literal(text, false)) = self.parts.first() else {
+            return None;
+        };
+        let (name, value) = text.split_once('=')?;
+        if !is_name(name) {
+            return None;
+        }
+        let mut parts = self.parts.clone();
+        if value.is_empty() {
+            parts.remove(0);
+        } else if let Some(first) = parts.first_mut() {
+            *first = WordPart::Literal(value.to_string(), false);
+        }
+        Some((name.to_string(), Word { parts }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(Word),
+    And,
+    Or,
+    Semicolon,
+    DoubleSemicolon,
+    Pipe,
+    /// `>`, output to a file emptied first
+    Great,
+    /// `>>`, output appended to a file
+    DoubleGreat,
+    OpenParen,
+    CloseParen,
+    Newline,
+}
+
+fn describe(token: &Token) -> String {
+    let text = match token {
+        Token::Word(word) => return word.literal().map_or_else(|| String::from("word"), |text| format!("`{}`", text)),
+        Token::And => "&&",
+        Token::Or => "||",
+        Token::Semicolon => ";",
+        Token::DoubleSemicolon => ";;",
+        Token::Pipe => "|",
+        Token::Great => ">",
+        Token::DoubleGreat => ">>",
+        Token::OpenParen => "(",
+        Token::CloseParen => ")",
+        Token::Newline => "newline",
+    };
+    format!("`{}`", text)
+}
+
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars.next().is_some_and(|first| first == '_' || first.is_ascii_alphabetic())
+        && chars.all(|ch| ch == '_' || ch.is_ascii_alphanumeric())
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Lexer {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.position += 1;
+        Some(ch)
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        let found = self.peek() == Some(ch);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn text(&self, start: usize, end: usize) -> String {
+        self.chars[start..end].iter().collect()
+    }
+
+    /// Skip blanks, line continuations and comments up to the next token
+    fn skip_blanks(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\r') => self.position += 1,
+                Some('\\') if self.chars.get(self.position + 1) == Some(&'\n') => self.position += 2,
+                Some('#') => {
+                    while self.peek().is_some_and(|ch| ch != '\n') {
+                        self.position += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn token(&mut self) -> Result<Option<Token>, ScriptError> {
+        let Some(ch) = self.peek() else {
+            return Ok(None);
+        };
+        if !matches!(ch, '\n' | ';' | '&' | '|' | '>' | '(' | ')') {
+            return self.word().map(|word| Some(Token::Word(word)));
+        }
+        self.position += 1;
+        let token = match ch {
+            '\n' => Token::Newline,
+            ';' if self.eat(';') => Token::DoubleSemicolon,
+            ';' => Token::Semicolon,
+            '&' if self.eat('&') => Token::And,
+            '&' => return Err(ScriptError::Syntax(String::from("background commands are not supported"))),
+            '|' if self.eat('|') => Token::Or,
+            '|' => Token::Pipe,
+            '>' if self.eat('>') => Token::DoubleGreat,
+            '>' => Token::Great,
+            '(' => Token::OpenParen,
+            _ => Token::CloseParen,
+        };
+        Ok(Some(token))
+    }
+
+    fn word(&mut self) -> Result<Word, ScriptError> {
+        let mut word = Word::default();
+        let mut text = String::new();
+        while let Some(ch) = self.peek() {
+            if matches!(ch, ' ' | '\t' | '\r' | '\n' | ';' | '&' | '|' | '>' | '(' | ')') {
+                break;
+            }
+            self.position += 1;
+            if !matches!(ch, '\\' | '\'' | '"' | '$' | '`') {
+                text.push(ch);
+                continue;
+            }
+            if !text.is_empty() {
+                word.parts.push(WordPart::Literal(core::mem::take(&mut text), false));
+            }
+            match ch {
+                '\\' => match self.bump() {
+                    Some('\n') => {}
+                    Some(escaped) => word.parts.push(WordPart::Literal(escaped.to_string(), true)),
+                    None => return Err(ScriptError::Incomplete),
+                },
+                '\'' => {
+                    let start = self.position;
+                    while self.bump().ok_or(ScriptError::Incomplete)? != '\'' {}
+                    word.parts.push(WordPart::Literal(self.text(start, self.position - 1), true));
+                }
+                '"' => self.double_quoted(&mut word)?,
+                '$' => word.parts.push(self.dollar(false)?),
+                _ => word.parts.push(WordPart::Command(self.backquoted()?, false)),
+            }
+        }
+        if !text.is_empty() {
+            word.parts.push(WordPart::Literal(text, false));
+        }
+        Ok(word)
+    }
+
+    /// The rest of a double-quoted string, where only `$`, backquotes and some escapes act
+    fn double_quoted(&mut self, word: &mut Word) -> Result<(), ScriptError> {
+        let mut text = String::new();
+        loop {
+            match self.bump().ok_or(ScriptError::Incomplete)? {
+                '"' => break,
+                '\\' => match self.bump().ok_or(ScriptError::Incomplete)? {
+                    '\n' => {}
+                    escaped @ ('$' | '`' | '"' | '\\') => text.push(escaped),
+                    other => {
+                        text.push('\\');
+                        text.push(other);
+                    }
+                },
+                ch @ ('$' | '`') => {
+                    if !text.is_empty() {
+                        word.parts.push(WordPart::Literal(core::mem::take(&mut text), true));
+                    }
+                    let part = if ch == '$' { self.dollar(true)? } else { WordPart::Command(self.backquoted()?, true) };
+                    word.parts.push(part);
+                }
+                ch => text.push(ch),
+            }
+        }
+        // Kept even when empty, so `""` is still an argument
+        word.parts.push(WordPart::Literal(text, true));
+        Ok(())
+    }
+
+    /// The expansion after a `$`, or the `$` itself when nothing expandable follows
+    fn dollar(&mut self, quoted: bool) -> Result<WordPart, ScriptError> {
+        let part = match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                if self.eat('(') {
+                    WordPart::Arithmetic(self.arithmetic_source()?, quoted)
+                } else {
+                    WordPart::Command(self.command_source()?, quoted)
+                }
+            }
+            Some('{') => {
+                self.position += 1;
+                let start = self.position;
+                while self.bump().ok_or(ScriptError::Incomplete)? != '}' {}
+                WordPart::Variable(self.text(start, self.position - 1), quoted)
+            }
+            Some(ch) if ch.is_ascii_digit() || matches!(ch, '?' | '#' | '@' | '*' | '$') => {
+                self.position += 1;
+                WordPart::Variable(ch.to_string(), quoted)
+            }
+            Some(ch) if ch == '_' || ch.is_ascii_alphabetic() => {
+                let start = self.position;
+                while self.peek().is_some_and(|ch| ch == '_' || ch.is_ascii_alphanumeric()) {
+                    self.position += 1;
+                }
+                WordPart::Variable(self.text(start, self.position), quoted)
+            }
+            _ => WordPart::Literal(String::from("$"), quoted),
+        };
+        Ok(part)
+    }
+
+    /// The script inside `$(...)`, up to the parenthesis closing it
+    fn command_source(&mut self) -> Result<String, ScriptError> {
+        let start = self.position;
+        let mut depth = 0usize;
+        loop {
+            match self.bump().ok_or(ScriptError::Incomplete)? {
+                '\\' => {
+                    self.bump().ok_or(ScriptError::Incomplete)?;
+                }
+                '\'' => while self.bump().ok_or(ScriptError::Incomplete)? != '\'' {},
+                '"' => loop {
+                    match self.bump().ok_or(ScriptError::Incomplete)? {
+                        '"' => break,
+                        '\\' => {
+                            self.bump().ok_or(ScriptError::Incomplete)?;
+                        }
+                        _ => {}
+                    }
+                },
+                '(' => depth += 1,
+                ')' if depth == 0 => return Ok(self.text(start, self.position - 1)),
+                ')' => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    /// The expression inside `$((...))`, up to the `))` closing it
+    fn arithmetic_source(&mut self) -> Result<String, ScriptError> {
+        let start = self.position;
+        let mut depth = 0usize;
+        loop {
+            match self.bump().ok_or(ScriptError::Incomplete)? {
+                '(' => depth += 1,
+                ')' if depth == 0 => {
+                    if !self.eat(')') {
+                        return Err(ScriptError::Syntax(String::from("expected `))`")));
+                    }
+                    return Ok(self.text(start, self.position - 2));
+                }
+                ')' => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    /// The script between backquotes, where `\`` stands for a backquote
+    fn backquoted(&mut self) -> Result<String, ScriptError> {
+        let mut source = String::new();
+        loop {
+            match self.bump().ok_or(ScriptError::Incomplete)? {
+                '`' => return Ok(source),
+                '\\' => match self.bump().ok_or(ScriptError::Incomplete)? {
+                    escaped @ ('`' | '$' | '\\') => source.push(escaped),
+                    other => {
+                        source.push('\\');
+                        source.push(other);
+                    }
+                },
+                ch => source.push(ch),
+            }
+        }
+    }
+}
+
+/// Where a token lies in its script, as the character positions it starts and ends at
+type Span = (usize, usize);
+
+fn tokenize(source: &str) -> Result<(Vec<Token>, Vec<Span>), ScriptError> {
+    let mut lexer = Lexer { chars: source.chars().collect(), position: 0 };
+    let mut tokens = Vec::new();
+    let mut spans = Vec::new();
+    loop {
+        lexer.skip_blanks();
+        let start = lexer.position;
+        let Some(token) = lexer.token()? else {
+            break;
+        };
+        tokens.push(token);
+        spans.push((start, lexer.position));
+    }
+    Ok((tokens, spans))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Connector {
+    And,
+    Or,
+}
+
+/// Commands joined by `&&` and `||`, each run or skipped by the status before it
+#[derive(Debug)]
+struct AndOr {
+    first: Pipeline,
+    rest: Vec<(Connector, Pipeline)>,
+}
+
+/// Commands joined by `|`, each reading the output of the one before
+#[derive(Debug)]
+struct Pipeline {
+    negated: bool,
+    stages: Vec<Stage>,
+}
+
+/// A command of a pipeline, with where its output goes instead of the next stage
+#[derive(Debug)]
+struct Stage {
+    command: Command,
+    redirections: Vec<Redirection>,
+}
+
+/// `> target`, or `>> target` when `append`
+#[derive(Debug)]
+struct Redirection {
+    target: Word,
+    append: bool,
+}
+
+type List = Vec<AndOr>;
+
+#[derive(Debug)]
+enum Command {
+    Simple { assignments: Vec<(String, Word)>, words: Vec<Word> },
+    If { branches: Vec<(List, List)>, otherwise: Option<List> },
+    /// Without items the loop runs over the script's arguments
+    For { variable: String, items: Option<Vec<Word>>, body: List },
+    While { condition: List, body: List, until: bool },
+    Case { subject: Word, arms: Vec<(Vec<Word>, List)> },
+    /// `name() { body }`, holding the body's source
+    Function { name: String, body: String },
+}
+
+struct Parser<'a> {
+    source: Vec<char>,
+    tokens: Vec<Token>,
+    spans: Vec<Span>,
+    position: usize,
+    aliases: &'a BTreeMap<String, String>,
+    /// Aliases being expanded, with the position their values end at; an alias is not
+    /// expanded again within its own value
+    expanding: Vec<(String, usize)>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek_literal(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Word(word)) => word.literal(),
+            _ => None,
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(&Token::Newline) {
+            self.position += 1;
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while matches!(self.peek(), Some(Token::Newline | Token::Semicolon)) {
+            self.position += 1;
+        }
+    }
+
+    fn unexpected<T>(&self) -> Result<T, ScriptError> {
+        match self.peek() {
+            Some(token) => Err(ScriptError::Syntax(format!("unexpected {}", describe(token)))),
+            None => Err(ScriptError::Incomplete),
+        }
+    }
+
+    fn expect(&mut self, keyword: &str) -> Result<(), ScriptError> {
+        match self.peek() {
+            None => Err(ScriptError::Incomplete),
+            _ if self.peek_literal() == Some(keyword) => {
+                self.position += 1;
+                Ok(())
+            }
+            Some(token) => Err(ScriptError::Syntax(format!("expected `{}` before {}", keyword, describe(token)))),
+        }
+    }
+
+    fn word(&mut self) -> Result<Word, ScriptError> {
+        match self.peek() {
+            Some(Token::Word(word)) => {
+                let word = word.clone();
+                self.position += 1;
+                Ok(word)
+            }
+            _ => self.unexpected(),
+        }
+    }
+
+    /// Commands up to the end of input, a `;;` or `)`, or one of the reserved `terminators`
+    /// in command position
+    fn list(&mut self, terminators: &[&str]) -> Result<List, ScriptError> {
+        let mut list = Vec::new();
+        loop {
+            self.skip_separators();
+            match self.peek() {
+                None | Some(Token::DoubleSemicolon | Token::CloseParen) => break,
+                _ if self.peek_literal().is_some_and(|word| terminators.contains(&word)) => break,
+                _ => {}
+            }
+            list.push(self.and_or()?);
+            if !matches!(self.peek(), Some(Token::Semicolon | Token::Newline)) {
+                break;
+            }
+        }
+        Ok(list)
+    }
+
+    fn and_or(&mut self) -> Result<AndOr, ScriptError> {
+        let first = self.pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let connector = match self.peek() {
+                Some(Token::And) => Connector::And,
+                Some(Token::Or) => Connector::Or,
+                _ => break,
+            };
+            self.position += 1;
+            self.skip_newlines();
+            rest.push((connector, self.pipeline()?));
+        }
+        Ok(AndOr { first, rest })
+    }
+
+    fn pipeline(&mut self) -> Result<Pipeline, ScriptError> {
+        let negated = self.peek_literal() == Some("!");
+        if negated {
+            self.position += 1;
+        }
+        let mut stages = alloc::vec![self.stage()?];
+        while self.peek() == Some(&Token::Pipe) {
+            self.position += 1;
+            self.skip_newlines();
+            stages.push(self.stage()?);
+        }
+        Ok(Pipeline { negated, stages })
+    }
+
+    /// A command and its redirections: among the words of a simple command, or after a
+    /// compound one
+    fn stage(&mut self) -> Result<Stage, ScriptError> {
+        self.expand_aliases()?;
+        let mut redirections = Vec::new();
+        let command = match self.peek_literal() {
+            Some("if") => self.if_clause()?,
+            Some("for") => self.for_clause()?,
+            Some("while") => self.while_clause(false)?,
+            Some("until") => self.while_clause(true)?,
+            Some("case") => self.case_clause()?,
+            Some("then" | "elif" | "else" | "fi" | "do" | "done" | "esac" | "}") => return self.unexpected(),
+            Some(name) if is_name(name) && self.tokens.get(self.position + 1) == Some(&Token::OpenParen) => {
+                return Ok(Stage { command: self.function_definition()?, redirections });
+            }
+            _ => return self.simple_command(),
+        };
+        while let Some(redirection) = self.redirection()? {
+            redirections.push(redirection);
+        }
+        Ok(Stage { command, redirections })
+    }
+
+    /// A `>` or `>>` and its target, when one comes next
+    fn redirection(&mut self) -> Result<Option<Redirection>, ScriptError> {
+        let append = match self.peek() {
+            Some(Token::Great) => false,
+            Some(Token::DoubleGreat) => true,
+            _ => return Ok(None),
+        };
+        self.position += 1;
+        match self.next_token() {
+            Some(Token::Word(target)) => Ok(Some(Redirection { target, append })),
+            _ => Err(ScriptError::Syntax(format!("expected a file name after `{}`", if append { ">>" } else { ">" }))),
+        }
+    }
+
+    /// Replace an alias name in command position with the tokens of its value, and again
+    /// while the value starts with another alias
+    fn expand_aliases(&mut self) -> Result<(), ScriptError> {
+        loop {
+            let position = self.position;
+            self.expanding.retain(|&(_, end)| end > position);
+            let Some(name) = self.peek_literal() else {
+                return Ok(());
+            };
+            if self.expanding.iter().any(|(expanding, _)| expanding == name) {
+                return Ok(());
+            }
+            let Some(value) = self.aliases.get(name) else {
+                return Ok(());
+            };
+            let name = name.to_string();
+            let (tokens, _) = tokenize(value).map_err(|_| ScriptError::Syntax(format!("bad alias `{}`", name)))?;
+            let span = self.spans.get(position).copied().unwrap_or_default();
+            let count = tokens.len();
+            self.tokens.splice(position..=position, tokens);
+            self.spans.splice(position..=position, core::iter::repeat(span).take(count));
+            for (_, end) in self.expanding.iter_mut() {
+                *end = (*end + count).saturating_sub(1);
+            }
+            self.expanding.push((name, position + count));
+        }
+    }
+
+    fn simple_command(&mut self) -> Result<Stage, ScriptError> {
+        let mut assignments = Vec::new();
+        let mut words = Vec::new();
+        let mut redirections = Vec::new();
+        loop {
+            if let Some(redirection) = self.redirection()? {
+                redirections.push(redirection);
+                continue;
+            }
+            let Some(Token::Word(word)) = self.peek() else {
+                break;
+            };
+            match word.assignment() {
+                Some(assignment) if words.is_empty() => assignments.push(assignment),
+                _ => words.push(word.clone()),
+            }
+            self.position += 1;
+        }
+        if assignments.is_empty() && words.is_empty() && redirections.is_empty() {
+            return self.unexpected();
+        }
+        Ok(Stage { command: Command::Simple { assignments, words }, redirections })
+    }
+
+    fn if_clause(&mut self) -> Result<Command, ScriptError> {
+        self.position += 1;
+        let mut branches = Vec::new();
+        loop {
+            let condition = self.list(&["then"])?;
+            self.expect("then")?;
+            let body = self.list(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+            if self.peek_literal() != Some("elif") {
+                break;
+            }
+            self.position += 1;
+        }
+        let otherwise = if self.peek_literal() == Some("else") {
+            self.position += 1;
+            Some(self.list(&["fi"])?)
+        } else {
+            None
+        };
+        self.expect("fi")?;
+        Ok(Command::If { branches, otherwise })
+    }
+
+    fn for_clause(&mut self) -> Result<Command, ScriptError> {
+        self.position += 1;
+        let variable = match self.word()?.literal() {
+            Some(name) if is_name(name) => name.to_string(),
+            _ => return Err(ScriptError::Syntax(String::from("bad for loop variable"))),
+        };
+        self.skip_newlines();
+        let items = if self.peek_literal() == Some("in") {
+            self.position += 1;
+            let mut items = Vec::new();
+            while let Some(Token::Word(word)) = self.peek() {
+                items.push(word.clone());
+                self.position += 1;
+            }
+            Some(items)
+        } else {
+            None
+        };
+        self.skip_separators();
+        self.expect("do")?;
+        let body = self.list(&["done"])?;
+        self.expect("done")?;
+        Ok(Command::For { variable, items, body })
+    }
+
+    fn while_clause(&mut self, until: bool) -> Result<Command, ScriptError> {
+        self.position += 1;
+        let condition = self.list(&["do"])?;
+        self.expect("do")?;
+        let body = self.list(&["done"])?;
+        self.expect("done")?;
+        Ok(Command::While { condition, body, until })
+    }
+
+    fn function_definition(&mut self) -> Result<Command, ScriptError> {
+        let name = self.word()?.literal().unwrap_or_default().to_string();
+        self.position += 1;
+        match self.next_token() {
+            Some(Token::CloseParen) => {}
+            None => return Err(ScriptError::Incomplete),
+            Some(token) => return Err(ScriptError::Syntax(format!("expected `)` before {}", describe(&token)))),
+        }
+        self.skip_newlines();
+        self.expect("{")?;
+        let start = self.previous_span().1;
+        self.list(&["}"])?;
+        self.expect("}")?;
+        let end = self.previous_span().0;
+        let body = self.source.get(start..end).unwrap_or_default().iter().collect();
+        Ok(Command::Function { name, body })
+    }
+
+    fn previous_span(&self) -> Span {
+        self.position.checked_sub(1).and_then(|index| self.spans.get(index)).copied().unwrap_or_default()
+    }
+
+    fn case_clause(&mut self) -> Result<Command, ScriptError> {
+        self.position += 1;
+        let subject = self.word()?;
+        self.skip_newlines();
+        self.expect("in")?;
+        let mut arms = Vec::new();
+        loop {
+            self.skip_separators();
+            if self.peek_literal() == Some("esac") {
+                self.position += 1;
+                break;
+            }
+            if self.peek() == Some(&Token::OpenParen) {
+                self.position += 1;
+            }
+            let mut patterns = Vec::new();
+            loop {
+                patterns.push(self.word()?);
+                match self.next_token() {
+                    Some(Token::Pipe) => {}
+                    Some(Token::CloseParen) => break,
+                    None => return Err(ScriptError::Incomplete),
+                    Some(token) => return Err(ScriptError::Syntax(format!("expected `)` before {}", describe(&token)))),
+                }
+            }
+            let body = self.list(&["esac"])?;
+            arms.push((patterns, body));
+            match self.peek() {
+                Some(Token::DoubleSemicolon) => self.position += 1,
+                _ if self.peek_literal() == Some("esac") => {}
+                _ => return self.unexpected(),
+            }
+        }
+        Ok(Command::Case { subject, arms })
+    }
+}
+
+/// Parse a script, expanding `aliases` in command position
+fn parse(source: &str, aliases: &BTreeMap<String, String>) -> Result<List, ScriptError> {
+    let (tokens, spans) = tokenize(source)?;
+    let mut parser = Parser {
+        source: source.chars().collect(),
+        tokens,
+        spans,
+        position: 0,
+        aliases,
+        expanding: Vec::new(),
+    };
+    let list = parser.list(&[])?;
+    if parser.peek().is_some() {
+        return parser.unexpected();
+    }
+    Ok(list)
+}
+
+/// Whether `source` is a whole script rather than the start of one still missing its end,
+/// such as an `if` without its `fi` or an unclosed quote
+pub fn is_complete(source: &str) -> bool {
+    !matches!(parse(source, &BTreeMap::new()), Err(ScriptError::Incomplete))
+}
+
+/// How a command left the commands around it to go on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow {
+    Normal,
+    /// Leave this many enclosing loops
+    Break(usize),
+    /// Leave this many enclosing loops less one, and start the next pass of the last
+    Continue(usize),
+    /// Leave the function being run
+    Return,
+    Exit,
+}
+
+/// Deepest functions may call one another, to bound the kernel stack a script can use
+const MAX_FUNCTION_DEPTH: usize = 16;
+
+pub struct Interpreter {
+    runner: CommandRunner,
+    variables: BTreeMap<String, String>,
+    /// The script's name and arguments, `$0` onwards
+    arguments: Vec<String>,
+    status: i32,
+    exited: bool,
+    output: String,
+    /// Command substitutions being captured; their error messages wait in `errors` and
+    /// follow the output when the outermost one ends
+    capturing: usize,
+    errors: String,
+    loop_depth: usize,
+    /// Alias names and the text they stand for in command position
+    aliases: BTreeMap<String, String>,
+    /// Function names and the source of their bodies
+    functions: BTreeMap<String, String>,
+    function_depth: usize,
+    /// Standard input of the commands running: what the pipeline stage before them wrote,
+    /// until a command reads it
+    stdin: Option<String>,
+}
+
+impl Interpreter {
+    pub fn new(runner: CommandRunner) -> Self {
+        Interpreter {
+            runner,
+            variables: BTreeMap::new(),
+            arguments: alloc::vec![String::from("raeshell")],
+            status: 0,
+            exited: false,
+            output: String::new(),
+            capturing: 0,
+            errors: String::new(),
+            loop_depth: 0,
+            aliases: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            function_depth: 0,
+            stdin: None,
+        }
+    }
+
+    pub fn variables(&self) -> &BTreeMap<String, String> {
+        &self.variables
+    }
+
+    pub fn set_variables(&mut self, variables: BTreeMap<String, String>) {
+        self.variables = variables;
+    }
+
+    pub fn aliases(&self) -> &BTreeMap<String, String> {
+        &self.aliases
+    }
+
+    pub fn set_aliases(&mut self, aliases: BTreeMap<String, String>) {
+        self.aliases = aliases;
+    }
+
+    /// Defined functions, each name with the source of its body
+    pub fn functions(&self) -> &BTreeMap<String, String> {
+        &self.functions
+    }
+
+    pub fn set_functions(&mut self, functions: BTreeMap<String, String>) {
+        self.functions = functions;
+    }
+
+    /// A script that defines the current aliases and functions again
+    pub fn definitions(&self) -> String {
+        let mut script = String::new();
+        for (name, value) in &self.aliases {
+            script.push_str(&format!("alias {}={}\n", name, quote(value)));
+        }
+        for (name, body) in &self.functions {
+            script.push_str(&format!("{}() {{{}}}\n", name, body));
+        }
+        script
+    }
+
+    /// Set the script's name and arguments, `$0` onwards
+    pub fn set_arguments(&mut self, arguments: Vec<String>) {
+        self.arguments = arguments;
+    }
+
+    /// Exit status of the last command, `$?`
+    pub fn status(&self) -> i32 {
+        self.status
+    }
+
+    pub fn set_status(&mut self, status: i32) {
+        self.status = status;
+    }
+
+    /// Whether the last script ended with `exit`
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// What the scripts printed since last taken, command output and error messages in order
+    pub fn take_output(&mut self) -> String {
+        core::mem::take(&mut self.output)
+    }
+
+    /// Run a script, returning its exit status
+    pub fn run(&mut self, source: &str) -> Result<i32, ScriptError> {
+        self.exited = false;
+        let list = parse(source, &self.aliases)?;
+        self.run_list(&list)?;
+        Ok(self.status)
+    }
+
+    fn write_output(&mut self, text: &str) {
+        if !text.is_empty() {
+            self.output.push_str(text);
+            if !text.ends_with('\n') {
+                self.output.push('\n');
+            }
+        }
+    }
+
+    fn write_error(&mut self, message: &str) {
+        if message.is_empty() {
+            return;
+        }
+        let target = if self.capturing > 0 { &mut self.errors } else { &mut self.output };
+        target.push_str(message);
+        if !message.ends_with('\n') {
+            target.push('\n');
+        }
+    }
+
+    fn run_list(&mut self, list: &List) -> Result<Flow, ScriptError> {
+        for and_or in list {
+            let flow = self.run_and_or(and_or)?;
+            if flow != Flow::Normal {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn run_and_or(&mut self, and_or: &AndOr) -> Result<Flow, ScriptError> {
+        let mut flow = self.run_pipeline(&and_or.first)?;
+        for (connector, pipeline) in &and_or.rest {
+            if flow != Flow::Normal {
+                break;
+            }
+            if (*connector == Connector::And) == (self.status == 0) {
+                flow = self.run_pipeline(pipeline)?;
+            }
+        }
+        Ok(flow)
+    }
+
+    fn run_pipeline(&mut self, pipeline: &Pipeline) -> Result<Flow, ScriptError> {
+        let flow = self.run_stages(&pipeline.stages)?;
+        if pipeline.negated && flow == Flow::Normal {
+            self.status = i32::from(self.status == 0);
+        }
+        Ok(flow)
+    }
+
+    /// Run the stages of a pipeline in order, each reading what the one before wrote and the
+    /// first the pipeline's own input. All but the last run as in a subshell, and the status
+    /// is that of the last
+    fn run_stages(&mut self, stages: &[Stage]) -> Result<Flow, ScriptError> {
+        let Some((last, leading)) = stages.split_last() else {
+            return Ok(Flow::Normal);
+        };
+        // What the first stage left of the pipeline's input, for the commands after it
+        let mut outer = None;
+        for (index, stage) in leading.iter().enumerate() {
+            let (flow, output) = self.subshell(|interpreter| interpreter.run_stage(stage));
+            let unread = self.stdin.replace(output);
+            if index == 0 {
+                outer = unread;
+            }
+            if let Err(error) = flow {
+                self.stdin = outer;
+                return Err(error);
+            }
+        }
+        let flow = self.run_stage(last);
+        if !leading.is_empty() {
+            self.stdin = outer;
+        }
+        flow
+    }
+
+    /// Run a stage's command with its output sent to the files it is redirected to, each
+    /// made ready in turn and the output written to the last. A file that cannot be written
+    /// fails the stage without running the command
+    fn run_stage(&mut self, stage: &Stage) -> Result<Flow, ScriptError> {
+        let mut target = None;
+        for redirection in &stage.redirections {
+            let path = self.expand_string(&redirection.target)?;
+            if let Err(message) = prepare_target(&path, redirection.append) {
+                self.write_error(&message);
+                self.status = 1;
+                return Ok(Flow::Normal);
+            }
+            target = Some(path);
+        }
+        let Some(path) = target else {
+            return self.run_command(&stage.command);
+        };
+        let (flow, output) = self.capture(|interpreter| interpreter.run_command(&stage.command));
+        if let Err(message) = append_target(&path, &output) {
+            self.write_error(&message);
+            self.status = 1;
+        }
+        flow
+    }
+
+    fn run_command(&mut self, command: &Command) -> Result<Flow, ScriptError> {
+        match command {
+            Command::Simple { assignments, words } => self.run_simple(assignments, words),
+            Command::If { branches, otherwise } => {
+                for (condition, body) in branches {
+                    let flow = self.run_list(condition)?;
+                    if flow != Flow::Normal {
+                        return Ok(flow);
+                    }
+                    if self.status == 0 {
+                        return self.run_body(body);
+                    }
+                }
+                match otherwise {
+                    Some(body) => self.run_body(body),
+                    None => {
+                        self.status = 0;
+                        Ok(Flow::Normal)
+                    }
+                }
+            }
+            Command::For { variable, items, body } => {
+                let items = match items {
+                    Some(words) => {
+                        let mut fields = Vec::new();
+                        for word in words {
+                            fields.extend(self.expand_fields(word)?);
+                        }
+                        fields
+                    }
+                    None => self.arguments.iter().skip(1).cloned().collect(),
+                };
+                let mut status = 0;
+                for item in items {
+                    self.variables.insert(variable.clone(), item);
+                    let stop = self.run_loop_body(body)?;
+                    status = self.status;
+                    if let Some(flow) = stop {
+                        return Ok(flow);
+                    }
+                }
+                self.status = status;
+                Ok(Flow::Normal)
+            }
+            Command::While { condition, body, until } => {
+                let mut status = 0;
+                loop {
+                    let flow = self.run_list(condition)?;
+                    if flow != Flow::Normal {
+                        return Ok(flow);
+                    }
+                    if (self.status == 0) == *until {
+                        break;
+                    }
+                    let stop = self.run_loop_body(body)?;
+                    status = self.status;
+                    if let Some(flow) = stop {
+                        return Ok(flow);
+                    }
+                }
+                self.status = status;
+                Ok(Flow::Normal)
+            }
+            Command::Function { name, body } => {
+                self.functions.insert(name.clone(), body.clone());
+                self.status = 0;
+                Ok(Flow::Normal)
+            }
+            Command::Case { subject, arms } => {
+                let subject: Vec<char> = self.expand_string(subject)?.chars().collect();
+                for (patterns, body) in arms {
+                    for pattern in patterns {
+                        if glob_match(&self.expand_pattern(pattern)?, &subject) {
+                            return self.run_body(body);
+                        }
+                    }
+                }
+                self.status = 0;
+                Ok(Flow::Normal)
+            }
+        }
+    }
+
+    /// Run the body of a compound command; an empty one succeeds
+    fn run_body(&mut self, body: &List) -> Result<Flow, ScriptError> {
+        self.status = 0;
+        self.run_list(body)
+    }
+
+    /// Run one pass of a loop body, returning how to leave the loop if it should end
+    fn run_loop_body(&mut self, body: &List) -> Result<Option<Flow>, ScriptError> {
+        self.loop_depth += 1;
+        let flow = self.run_body(body);
+        self.loop_depth -= 1;
+        Ok(match flow? {
+            Flow::Normal | Flow::Continue(1) => None,
+            Flow::Break(1) => Some(Flow::Normal),
+            Flow::Break(count) => Some(Flow::Break(count - 1)),
+            Flow::Continue(count) => Some(Flow::Continue(count - 1)),
+            flow @ (Flow::Return | Flow::Exit) => Some(flow),
+        })
+    }
+
+    fn run_simple(&mut self, assignments: &[(String, Word)], words: &[Word]) -> Result<Flow, ScriptError> {
+        // Without a command, the status is that of the last command substitution, if any
+        if words.is_empty() {
+            self.status = 0;
+        }
+        for (name, value) in assignments {
+            let value = self.expand_string(value)?;
+            self.variables.insert(name.clone(), value);
+        }
+        let mut fields = Vec::new();
+        for word in words {
+            fields.extend(self.expand_fields(word)?);
+        }
+        let Some(name) = fields.first() else {
+            return Ok(Flow::Normal);
+        };
+
+        match name.as_str() {
+            "break" | "continue" => {
+                let count = match fields.get(1) {
+                    Some(count) => count.parse::<usize>().ok().filter(|&count| count > 0),
+                    None => Some(1),
+                };
+                let message = match count {
+                    _ if self.loop_depth == 0 => format!("{}: only meaningful in a loop", name),
+                    None => format!("{}: loop count must be a positive number", name),
+                    Some(count) => {
+                        self.status = 0;
+                        let count = count.min(self.loop_depth);
+                        return Ok(if name == "break" { Flow::Break(count) } else { Flow::Continue(count) });
+                    }
+                };
+                self.write_error(&message);
+                self.status = 1;
+                return Ok(Flow::Normal);
+            }
+            "exit" => {
+                match fields.get(1).map(|code| code.parse::<i32>()) {
+                    Some(Ok(code)) => self.status = code,
+                    Some(Err(_)) => {
+                        self.write_error("exit: numeric argument required");
+                        self.status = 2;
+                    }
+                    None => {}
+                }
+                self.exited = true;
+                return Ok(Flow::Exit);
+            }
+            "return" => {
+                if self.function_depth == 0 {
+                    self.write_error("return: can only be used in a function");
+                    self.status = 1;
+                    return Ok(Flow::Normal);
+                }
+                match fields.get(1).map(|code| code.parse::<i32>()) {
+                    Some(Ok(code)) => self.status = code,
+                    Some(Err(_)) => {
+                        self.write_error("return: numeric argument required");
+                        self.status = 2;
+                    }
+                    None => {}
+                }
+                return Ok(Flow::Return);
+            }
+            "alias" => {
+                self.alias(&fields[1..]);
+                return Ok(Flow::Normal);
+            }
+            "unalias" => {
+                self.unalias(&fields[1..]);
+                return Ok(Flow::Normal);
+            }
+            _ => {}
+        }
+        if let Some(body) = self.functions.get(name).cloned() {
+            return self.call_function(name, &body, &fields[1..]);
+        }
+
+        let args: Vec<&str> = fields.iter().map(String::as_str).collect();
+        let mut streams = Streams { stdin: self.stdin.take(), stdout: String::new() };
+        let result = (self.runner)(&args, &mut streams);
+        // What the command left unread, the next command reads
+        self.stdin = streams.stdin;
+        self.output.push_str(&streams.stdout);
+        match result {
+            ShellResult::Success(output) => {
+                self.write_output(&output);
+                self.status = 0;
+            }
+            ShellResult::Error(message) => {
+                self.write_error(&message);
+                self.status = 1;
+            }
+            ShellResult::Exit => {
+                self.exited = true;
+                return Ok(Flow::Exit);
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// Run the body of function `name` with `args` as `$1` onwards. It runs among the caller's
+    /// variables, so its assignments last
+    fn call_function(&mut self, name: &str, body: &str, args: &[String]) -> Result<Flow, ScriptError> {
+        if self.function_depth >= MAX_FUNCTION_DEPTH {
+            self.write_error(&format!("{}: maximum function nesting exceeded", name));
+            self.status = 1;
+            return Ok(Flow::Normal);
+        }
+        let list = parse(body, &self.aliases)?;
+        let script_name = self.arguments.first().cloned().unwrap_or_default();
+        let arguments = core::iter::once(script_name).chain(args.iter().cloned()).collect();
+        let arguments = core::mem::replace(&mut self.arguments, arguments);
+        let loop_depth = core::mem::replace(&mut self.loop_depth, 0);
+        self.function_depth += 1;
+        let flow = self.run_body(&list);
+        self.function_depth -= 1;
+        self.loop_depth = loop_depth;
+        self.arguments = arguments;
+        Ok(match flow? {
+            Flow::Exit => Flow::Exit,
+            _ => Flow::Normal,
+        })
+    }
+
+    /// `alias`: list every alias, or for each argument define `name=value` or show `name`
+    fn alias(&mut self, args: &[String]) {
+        self.status = 0;
+        if args.is_empty() {
+            let listing: String = self.aliases.iter().map(|(name, value)| format!("alias {}={}\n", name, quote(value))).collect();
+            self.write_output(&listing);
+            return;
+        }
+        for arg in args {
+            match arg.split_once('=') {
+                Some((name, value)) if is_name(name) => {
+                    self.aliases.insert(name.to_string(), value.to_string());
+                }
+                Some((name, _)) => {
+                    self.write_error(&format!("alias: `{}`: invalid alias name", name));
+                    self.status = 1;
+                }
+                None => match self.aliases.get(arg.as_str()) {
+                    Some(value) => {
+                        let line = format!("alias {}={}", arg, quote(value));
+                        self.write_output(&line);
+                    }
+                    None => {
+                        self.write_error(&format!("alias: {}: not found", arg));
+                        self.status = 1;
+                    }
+                },
+            }
+        }
+    }
+
+    /// `unalias`: remove the named aliases, or all of them with `-a`
+    fn unalias(&mut self, args: &[String]) {
+        self.status = 0;
+        if args.is_empty() {
+            self.write_error("usage: unalias [-a] NAME...");
+            self.status = 2;
+            return;
+        }
+        for arg in args {
+            if arg == "-a" {
+                self.aliases.clear();
+            } else if self.aliases.remove(arg.as_str()).is_none() {
+                self.write_error(&format!("unalias: {}: not found", arg));
+                self.status = 1;
+            }
+        }
+    }
+
+    fn variable_value(&self, name: &str) -> String {
+        match name {
+            "?" => self.status.to_string(),
+            "#" => self.arguments.len().saturating_sub(1).to_string(),
+            "@" | "*" => self.arguments.get(1..).map(|arguments| arguments.join(" ")).unwrap_or_default(),
+            "$" => crate::process::get_current_process_id().to_string(),
+            _ => match name.parse::<usize>() {
+                Ok(index) => self.arguments.get(index).cloned().unwrap_or_default(),
+                Err(_) => self.variables.get(name).cloned().unwrap_or_default(),
+            },
+        }
+    }
+
+    /// Text of an expansion, with whether it was quoted
+    fn expand_part(&mut self, part: &WordPart) -> Result<(String, bool), ScriptError> {
+        Ok(match part {
+            WordPart::Literal(text, quoted) => (text.clone(), *quoted),
+            WordPart::Variable(name, quoted) => (self.variable_value(name), *quoted),
+            WordPart::Command(source, quoted) => (self.substitute(source)?, *quoted),
+            WordPart::Arithmetic(expression, quoted) => {
+                let value = evaluate(expression, &|name| self.variable_value(name))?;
+                (value.to_string(), *quoted)
+            }
+        })
+    }
+
+    /// Expand a word into fields, splitting the results of unquoted expansions at whitespace
+    fn expand_fields(&mut self, word: &Word) -> Result<Vec<String>, ScriptError> {
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut started = false;
+        for part in &word.parts {
+            let (text, quoted) = self.expand_part(part)?;
+            if quoted || matches!(part, WordPart::Literal(..)) {
+                field.push_str(&text);
+                started = true;
+                continue;
+            }
+            for ch in text.chars() {
+                if ch.is_whitespace() {
+                    if started {
+                        fields.push(core::mem::take(&mut field));
+                        started = false;
+                    }
+                } else {
+                    field.push(ch);
+                    started = true;
+                }
+            }
+        }
+        if started {
+            fields.push(field);
+        }
+        Ok(fields)
+    }
+
+    /// Expand a word into one string, without field splitting
+    fn expand_string(&mut self, word: &Word) -> Result<String, ScriptError> {
+        let mut text = String::new();
+        for part in &word.parts {
+            text.push_str(&self.expand_part(part)?.0);
+        }
+        Ok(text)
+    }
+
+    /// Expand a word into a glob pattern, where quoted text only matches itself
+    fn expand_pattern(&mut self, word: &Word) -> Result<Vec<char>, ScriptError> {
+        let mut pattern = Vec::new();
+        for part in &word.parts {
+            let (text, quoted) = self.expand_part(part)?;
+            for ch in text.chars() {
+                if quoted && matches!(ch, '*' | '?' | '[' | '\\') {
+                    pattern.push('\\');
+                }
+                pattern.push(ch);
+            }
+        }
+        Ok(pattern)
+    }
+
+    /// Run a command substitution, returning its output less trailing newlines
+    fn substitute(&mut self, source: &str) -> Result<String, ScriptError> {
+        let list = parse(source, &self.aliases)?;
+        let (flow, captured) = self.subshell(|interpreter| interpreter.run_list(&list));
+        flow?;
+        Ok(captured.trim_end_matches('\n').to_string())
+    }
+
+    /// Run `run` capturing its output, as `capture` does, on a copy of the variables, aliases
+    /// and functions, so definitions inside it do not last and `exit` only ends it
+    fn subshell<T>(&mut self, run: impl FnOnce(&mut Self) -> T) -> (T, String) {
+        let variables = self.variables.clone();
+        let functions = self.functions.clone();
+        let aliases = self.aliases.clone();
+        let exited = self.exited;
+        let captured = self.capture(run);
+        self.variables = variables;
+        self.functions = functions;
+        self.aliases = aliases;
+        self.exited = exited;
+        captured
+    }
+
+    /// Run `run`, returning what it printed instead of printing it. Error messages are still
+    /// printed, after the output of the outermost capture
+    fn capture<T>(&mut self, run: impl FnOnce(&mut Self) -> T) -> (T, String) {
+        let outer = core::mem::take(&mut self.output);
+        self.capturing += 1;
+        let result = run(self);
+        self.capturing -= 1;
+        let captured = core::mem::replace(&mut self.output, outer);
+        if self.capturing == 0 {
+            let errors = core::mem::take(&mut self.errors);
+            self.output.push_str(&errors);
+        }
+        (result, captured)
+    }
+}
+
+/// Make the file `path` ready for a redirection: emptied, or for `append` made when missing.
+/// Its directory must already exist
+fn prepare_target(path: &str, append: bool) -> Result<(), String> {
+    let directory = match path.rfind('/') {
+        Some(0) => "/",
+        Some(end) => &path[..end],
+        None => ".",
+    };
+    match filesystem::metadata(directory) {
+        Ok(metadata) if metadata.file_type == FileType::Directory => {}
+        Ok(_) => return Err(format!("{}: Not a directory", path)),
+        Err(_) => return Err(format!("{}: No such file or directory", path)),
+    }
+    match filesystem::metadata(path) {
+        Ok(metadata) if metadata.file_type == FileType::Directory => return Err(format!("{}: Is a directory", path)),
+        Ok(_) if append => return Ok(()),
+        Ok(_) => {
+            let _ = filesystem::remove(path);
+        }
+        Err(_) => {}
+    }
+    filesystem::create_file(path).map_err(|_| format!("{}: cannot create file", path))
+}
+
+/// Add `text` to the end of the file `path`
+fn append_target(path: &str, text: &str) -> Result<(), String> {
+    if text.is_empty() {
+        return Ok(());
+    }
+    let mut contents = filesystem::read_file(path).unwrap_or_default();
+    contents.extend_from_slice(text.as_bytes());
+    let _ = filesystem::remove(path);
+    let written = filesystem::create_file(path).ok().and_then(|_| filesystem::open_file(path).ok()).and_then(|fd| {
+        let written = filesystem::write_file(fd, &contents);
+        let _ = filesystem::close_file(fd);
+        written.ok()
+    });
+    written.map(|_| ()).ok_or_else(|| format!("{}: write error", path))
+}
+
+/// `text` single-quoted, so that it reads back as itself
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Whether `text` matches the glob `pattern`: `*` matches any run of characters, `?` any one,
+/// `[...]` one from a set with ranges, negated by a leading `!` or `^`, and `\` escapes
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|skip| glob_match(rest, &text[skip..])),
+        Some(('?', rest)) => text.split_first().is_some_and(|(_, remaining)| glob_match(rest, remaining)),
+        Some(('[', rest)) if rest.contains(&']') => {
+            let close = rest.iter().position(|&ch| ch == ']').unwrap_or(rest.len());
+            let (set, after) = (&rest[..close], &rest[close + 1..]);
+            let (negated, set) = match set.split_first() {
+                Some(('!' | '^', set)) => (true, set),
+                _ => (false, set),
+            };
+            text.split_first().is_some_and(|(&ch, remaining)| {
+                let mut members = set.iter().peekable();
+                let mut found = false;
+                while let Some(&low) = members.next() {
+                    let high = if members.peek() == Some(&&'-') {
+                        members.next();
+                        members.next().copied().unwrap_or('-')
+                    } else {
+                        low
+                    };
+                    found |= (low..=high).contains(&ch);
+                }
+                found != negated && glob_match(after, remaining)
+            })
+        }
+        Some(('\\', rest)) if !rest.is_empty() => {
+            text.first() == rest.first() && glob_match(&rest[1..], &text[1..])
+        }
+        Some((ch, rest)) => text.first() == Some(ch) && glob_match(rest, &text[1..]),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ArithmeticToken {
+    Number(i64),
+    /// A variable, named bare or after `$`
+    Name(String),
+    Operator(&'static str),
+    Open,
+    Close,
+}
+
+/// Binary operators from the loosest binding to the tightest
+const PRECEDENCE: [&[&str]; 6] = [&["||"], &["&&"], &["==", "!="], &["<", "<=", ">", ">="], &["+", "-"], &["*", "/", "%"]];
+
+const OPERATORS: [&str; 14] = ["||", "&&", "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "!"];
+
+fn arithmetic_tokens(expression: &str) -> Result<Vec<ArithmeticToken>, ScriptError> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while let Some(&ch) = chars.get(index) {
+        let rest: String = chars[index..].iter().take(2).collect();
+        if ch.is_whitespace() {
+            index += 1;
+        } else if ch.is_ascii_digit() {
+            let start = index;
+            while chars.get(index).is_some_and(|ch| ch.is_ascii_alphanumeric()) {
+                index += 1;
+            }
+            let digits: String = chars[start..index].iter().collect();
+            let number = match digits.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16),
+                None => digits.parse(),
+            };
+            tokens.push(ArithmeticToken::Number(number.map_err(|_| ScriptError::Arithmetic(format!("bad number `{}`", digits)))?));
+        } else if ch == '$' || ch == '_' || ch.is_ascii_alphabetic() {
+            if ch == '$' {
+                index += 1;
+            }
+            let braced = chars.get(index) == Some(&'{');
+            if braced {
+                index += 1;
+            }
+            let start = index;
+            match chars.get(index) {
+                Some(&special) if special.is_ascii_digit() || matches!(special, '?' | '#') => index += 1,
+                _ => {
+                    while chars.get(index).is_some_and(|&ch| ch == '_' || ch.is_ascii_alphanumeric()) {
+                        index += 1;
+                    }
+                }
+            }
+            let name: String = chars[start..index].iter().collect();
+            if braced && chars.get(index) == Some(&'}') {
+                index += 1;
+            }
+            if name.is_empty() {
+                return Err(ScriptError::Arithmetic(String::from("expected a variable name after `$`")));
+            }
+            tokens.push(ArithmeticToken::Name(name));
+        } else if ch == '(' || ch == ')' {
+            tokens.push(if ch == '(' { ArithmeticToken::Open } else { ArithmeticToken::Close });
+            index += 1;
+        } else if let Some(operator) = OPERATORS.iter().find(|operator| rest.starts_with(**operator)) {
+            tokens.push(ArithmeticToken::Operator(operator));
+            index += operator.len();
+        } else {
+            return Err(ScriptError::Arithmetic(format!("unexpected `{}`", ch)));
+        }
+    }
+    Ok(tokens)
+}
+
+struct ArithmeticParser<'a> {
+    tokens: Vec<ArithmeticToken>,
+    position: usize,
+    lookup: &'a dyn Fn(&str) -> String,
+}
+
+impl ArithmeticParser<'_> {
+    fn peek_operator(&self) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some(ArithmeticToken::Operator(operator)) => Some(operator),
+            _ => None,
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<i64, ScriptError> {
+        let Some(operators) = PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+        let mut value = self.binary(level + 1)?;
+        while let Some(operator) = self.peek_operator().filter(|operator| operators.contains(operator)) {
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            value = match operator {
+                "||" => i64::from(value != 0 || right != 0),
+                "&&" => i64::from(value != 0 && right != 0),
+                "==" => i64::from(value == right),
+                "!=" => i64::from(value != right),
+                "<" => i64::from(value < right),
+                "<=" => i64::from(value <= right),
+                ">" => i64::from(value > right),
+                ">=" => i64::from(value >= right),
+                "+" => value.wrapping_add(right),
+                "-" => value.wrapping_sub(right),
+                "*" => value.wrapping_mul(right),
+                _ if right == 0 => return Err(ScriptError::Arithmetic(String::from("division by zero"))),
+                "/" => value.wrapping_div(right),
+                _ => value.wrapping_rem(right),
+            };
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<i64, ScriptError> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        match token {
+            Some(ArithmeticToken::Operator("-")) => Ok(self.unary()?.wrapping_neg()),
+            Some(ArithmeticToken::Operator("+")) => self.unary(),
+            Some(ArithmeticToken::Operator("!")) => Ok(i64::from(self.unary()? == 0)),
+            Some(ArithmeticToken::Number(value)) => Ok(value),
+            Some(ArithmeticToken::Name(name)) => {
+                // Unset and empty variables count as zero
+                let value = (self.lookup)(&name);
+                let value = value.trim();
+                if value.is_empty() {
+                    return Ok(0);
+                }
+                value.parse().map_err(|_| ScriptError::Arithmetic(format!("{}: `{}` is not a number", name, value)))
+            }
+            Some(ArithmeticToken::Open) => {
+                let value = self.binary(0)?;
+                match self.tokens.get(self.position) {
+                    Some(ArithmeticToken::Close) => {
+                        self.position += 1;
+                        Ok(value)
+                    }
+                    _ => Err(ScriptError::Arithmetic(String::from("expected `)`"))),
+                }
+            }
+            Some(token) => Err(ScriptError::Arithmetic(format!("unexpected {:?}", token))),
+            None => Err(ScriptError::Arithmetic(String::from("expression ends early"))),
+        }
+    }
+}
+
+/// Evaluate an integer expression, looking variables up through `lookup`
+fn evaluate(expression: &str, lookup: &dyn Fn(&str) -> String) -> Result<i64, ScriptError> {
+    let mut parser = ArithmeticParser { tokens: arithmetic_tokens(expression)?, position: 0, lookup };
+    if parser.tokens.is_empty() {
+        return Ok(0);
+    }
+    let value = parser.binary(0)?;
+    match parser.tokens.get(parser.position) {
+        None => Ok(value),
+        Some(token) => Err(ScriptError::Arithmetic(format!("unexpected {:?}", token))),
+    }
+}
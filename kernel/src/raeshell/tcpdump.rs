@@ -0,0 +1,262 @@
+//! Packet capture command
+//! `tcpdump` over a packet socket in capture mode: a line for each frame an interface sends
+//! or receives that the filter expression passes, until enough frames are seen or the time is
+//! up, or a pcap file of them with `-w`. `-r` prints the frames of a saved capture instead.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::net::Ipv4Addr;
+use crate::network::bpf::{self, Instruction};
+use crate::network::packet::{self, ANY_INTERFACE, ETH_P_ALL};
+use crate::network::pcap::{self, Record};
+use crate::network::{self, interface, NetworkError, AF_PACKET, SOCK_RAW};
+use super::ShellResult;
+
+const USAGE: &str = "usage: tcpdump [-i IFACE] [-c COUNT] [-s SNAPLEN] [-t SECS] [-w FILE | -r FILE] [EXPRESSION]";
+const DEFAULT_COUNT: usize = 10;
+const DEFAULT_SECONDS: u64 = 10;
+/// What tcpdump keeps of each frame when not told: all of it
+const DEFAULT_SNAPLEN: u32 = 262_144;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const ETHERNET_HEADER_LEN: usize = 14;
+const UDP_HEADER_LEN: usize = 8;
+const IPPROTO_ICMP: u8 = 1;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+/// TCP flags in the order tcpdump shows them
+const TCP_FLAGS: [(u8, char); 6] = [(0x01, 'F'), (0x02, 'S'), (0x04, 'R'), (0x08, 'P'), (0x10, '.'), (0x20, 'U')];
+
+struct Options<'a> {
+    interface: Option<&'a str>,
+    count: usize,
+    seconds: u64,
+    snaplen: u32,
+    write: Option<&'a str>,
+    read: Option<&'a str>,
+    expression: Vec<&'a str>,
+}
+
+impl<'a> Options<'a> {
+    fn parse(args: &[&'a str]) -> Option<Self> {
+        let mut options = Options {
+            interface: None,
+            count: DEFAULT_COUNT,
+            seconds: DEFAULT_SECONDS,
+            snaplen: DEFAULT_SNAPLEN,
+            write: None,
+            read: None,
+            expression: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(&arg) = args.next() {
+            match arg {
+                "-i" => options.interface = Some(*args.next()?),
+                "-c" => options.count = args.next()?.parse().ok().filter(|&count| count > 0)?,
+                // 0 keeps whole frames, as in tcpdump
+                "-s" => options.snaplen = args.next()?.parse().ok().map(|snaplen| if snaplen == 0 { DEFAULT_SNAPLEN } else { snaplen })?,
+                "-t" => options.seconds = args.next()?.parse().ok()?,
+                "-w" => options.write = Some(*args.next()?),
+                "-r" => options.read = Some(*args.next()?),
+                _ if arg.starts_with('-') => return None,
+                word => options.expression.push(word),
+            }
+        }
+        if options.read.is_some() && options.write.is_some() {
+            return None;
+        }
+        Some(options)
+    }
+}
+
+fn describe(error: NetworkError) -> &'static str {
+    match error {
+        NetworkError::PermissionDenied => "permission denied: capturing takes a NetworkRaw capability",
+        NetworkError::InterfaceNotFound => "no such interface",
+        NetworkError::InvalidFilter => "invalid filter expression",
+        _ => "capture failed",
+    }
+}
+
+fn format_mac(mac: &[u8]) -> String {
+    mac.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<String>>().join(":")
+}
+
+fn ipv4_at(bytes: &[u8], offset: usize) -> Option<Ipv4Addr> {
+    let octets: [u8; 4] = bytes.get(offset..offset + 4)?.try_into().ok()?;
+    Some(Ipv4Addr::from(octets))
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*bytes.get(offset)?, *bytes.get(offset + 1)?]))
+}
+
+fn describe_arp(packet: &[u8]) -> Option<String> {
+    let sender_ip = ipv4_at(packet, 14)?;
+    let target_ip = ipv4_at(packet, 24)?;
+    match u16_at(packet, 6)? {
+        1 => Some(format!("ARP, Request who-has {} tell {}, length {}", target_ip, sender_ip, packet.len())),
+        2 => Some(format!("ARP, Reply {} is-at {}, length {}", sender_ip, format_mac(packet.get(8..14)?), packet.len())),
+        operation => Some(format!("ARP, operation {}, length {}", operation, packet.len())),
+    }
+}
+
+fn describe_ipv4(packet: &[u8]) -> Option<String> {
+    let header_len = usize::from(packet.first()? & 0x0f) * 4;
+    let payload_len = usize::from(u16_at(packet, 2)?).saturating_sub(header_len);
+    let protocol = *packet.get(9)?;
+    let source = ipv4_at(packet, 12)?;
+    let destination = ipv4_at(packet, 16)?;
+    // Of the transport header, what the capture kept
+    let transport = packet.get(header_len..).unwrap_or_default();
+    let ports = u16_at(transport, 0).zip(u16_at(transport, 2));
+    let line = match (protocol, ports) {
+        (IPPROTO_TCP, Some((source_port, destination_port))) => {
+            let flags: String = match transport.get(13) {
+                Some(&bits) if bits & 0x3f != 0 => TCP_FLAGS.iter().filter(|&&(flag, _)| bits & flag != 0).map(|&(_, name)| name).collect(),
+                _ => String::from("none"),
+            };
+            let data_offset = transport.get(12).map_or(0, |&offset| usize::from(offset >> 4) * 4);
+            format!(
+                "IP {}.{} > {}.{}: Flags [{}], length {}",
+                source, source_port, destination, destination_port, flags, payload_len.saturating_sub(data_offset)
+            )
+        }
+        (IPPROTO_UDP, Some((source_port, destination_port))) => format!(
+            "IP {}.{} > {}.{}: UDP, length {}",
+            source, source_port, destination, destination_port, payload_len.saturating_sub(UDP_HEADER_LEN)
+        ),
+        (IPPROTO_ICMP, _) => {
+            let kind = match transport.first() {
+                Some(0) => String::from("echo reply"),
+                Some(3) => String::from("destination unreachable"),
+                Some(8) => String::from("echo request"),
+                Some(11) => String::from("time exceeded"),
+                Some(kind) => format!("type {}", kind),
+                None => String::from("truncated"),
+            };
+            format!("IP {} > {}: ICMP {}, length {}", source, destination, kind, payload_len)
+        }
+        (protocol, _) => format!("IP {} > {}: ip-proto-{}, length {}", source, destination, protocol, payload_len),
+    };
+    Some(line)
+}
+
+/// A frame as one line, after the local time it crossed its interface
+fn summary(record: &Record) -> String {
+    let time = crate::time::timezone::to_local((record.timestamp_us / 1_000_000) as i64).datetime;
+    let mut line = format!("{:02}:{:02}:{:02}.{:06} ", time.hour, time.minute, time.second, record.timestamp_us % 1_000_000);
+    let frame = record.data;
+    let ethertype = u16_at(frame, 12).unwrap_or_default();
+    let payload = frame.get(ETHERNET_HEADER_LEN..).unwrap_or_default();
+    let described = match ethertype {
+        ETHERTYPE_ARP => describe_arp(payload),
+        ETHERTYPE_IPV4 => describe_ipv4(payload),
+        _ => None,
+    };
+    match described {
+        Some(described) => line.push_str(&described),
+        None => {
+            let _ = write!(line, "ethertype 0x{:04x}, length {}", ethertype, record.original_len);
+        }
+    }
+    line
+}
+
+/// Capture with a socket already open: the pcap records of the frames seen, and how many
+/// more there was no room for
+fn capture(socket: u32, index: u32, filter: Vec<Instruction>, options: &Options) -> Result<(Vec<Vec<u8>>, u64), NetworkError> {
+    network::bind_socket(socket, &index.to_be_bytes())?;
+    packet::set_filter(socket, Some(filter))?;
+    packet::set_capture(socket, Some(options.snaplen))?;
+    let deadline = crate::time::get_uptime_ms() + options.seconds * 1000;
+    let mut records = Vec::new();
+    while records.len() < options.count {
+        match network::receive_data(socket, pcap::RECORD_HEADER_LEN + options.snaplen as usize, 0) {
+            Ok(record) => records.push(record),
+            Err(NetworkError::WouldBlock) if crate::time::get_uptime_ms() < deadline => core::hint::spin_loop(),
+            Err(NetworkError::WouldBlock) => break,
+            Err(error) => return Err(error),
+        }
+    }
+    Ok((records, packet::statistics(socket)?.dropped))
+}
+
+fn write_file(path: &str, data: &[u8]) -> Result<(), ()> {
+    let _ = crate::filesystem::remove(path);
+    crate::filesystem::create_file(path).map_err(|_| ())?;
+    let fd = crate::filesystem::open_file(path)?;
+    let written = crate::filesystem::write_file(fd, data);
+    let _ = crate::filesystem::close_file(fd);
+    written.map(|_| ())
+}
+
+/// Print the frames of a saved capture that the filter passes
+fn read_capture(path: &str, filter: &[Instruction]) -> ShellResult {
+    let Ok(file) = crate::filesystem::read_file(path) else {
+        return ShellResult::Error(format!("tcpdump: {}: No such file or directory", path));
+    };
+    let Some((snaplen, records)) = pcap::parse_file(&file) else {
+        return ShellResult::Error(format!("tcpdump: {}: not an Ethernet pcap capture", path));
+    };
+    let mut output = format!("reading from file {}, link-type EN10MB (Ethernet), snapshot length {}", path, snaplen);
+    for record in records.iter().filter(|record| bpf::run(filter, record.data) != 0) {
+        output.push('\n');
+        output.push_str(&summary(record));
+    }
+    ShellResult::Success(output)
+}
+
+/// `tcpdump [-i IFACE] [-c COUNT] [-s SNAPLEN] [-t SECS] [-w FILE | -r FILE] [EXPRESSION]`
+pub fn cmd_tcpdump(args: &[&str]) -> ShellResult {
+    let Some(options) = Options::parse(args.get(1..).unwrap_or(&[])) else {
+        return ShellResult::Error(String::from(USAGE));
+    };
+    let filter = match bpf::compile(&options.expression.join(" ")) {
+        Ok(filter) => filter,
+        Err(error) => return ShellResult::Error(format!("tcpdump: {}", describe(error))),
+    };
+    if let Some(path) = options.read {
+        return read_capture(path, &filter);
+    }
+    let index = match options.interface {
+        Some(name) => match interface::interface_info(name) {
+            Ok(interface) => interface.index,
+            Err(error) => return ShellResult::Error(format!("tcpdump: {}: {}", name, describe(error))),
+        },
+        None => ANY_INTERFACE,
+    };
+
+    let socket = match network::create_socket(AF_PACKET, SOCK_RAW, u32::from(ETH_P_ALL)) {
+        Ok(socket) => socket,
+        Err(error) => return ShellResult::Error(format!("tcpdump: {}", describe(error))),
+    };
+    let captured = capture(socket, index, filter, &options);
+    let _ = network::close_socket(socket);
+    let (records, dropped) = match captured {
+        Ok(captured) => captured,
+        Err(error) => return ShellResult::Error(format!("tcpdump: {}", describe(error))),
+    };
+
+    let mut output = String::new();
+    match options.write {
+        Some(path) => {
+            let mut file = pcap::file_header(options.snaplen);
+            records.iter().for_each(|record| file.extend_from_slice(record));
+            if write_file(path, &file).is_err() {
+                return ShellResult::Error(format!("tcpdump: cannot write '{}'", path));
+            }
+        }
+        None => {
+            for (record, _) in records.iter().filter_map(|record| pcap::parse_record(record, false)) {
+                output.push_str(&summary(&record));
+                output.push('\n');
+            }
+        }
+    }
+    let _ = write!(output, "{} packets captured\n{} packets dropped by kernel", records.len(), dropped);
+    ShellResult::Success(output)
+}
@@ -0,0 +1,148 @@
+//! Screen Recording Test
+//! Records synthetic composited frames to a VFS file and to memory, decodes the recordings
+//! back, and checks frame sampling and the counting of dropped frames
+
+use alloc::vec::Vec;
+use crate::filesystem;
+use crate::graphics::recording::{self, decode, RecordingError, RecordingTarget, Recorder};
+use crate::graphics::GraphicsBuffer;
+use crate::serial::_print;
+
+const MS: u64 = 1_000_000;
+const RECORDING_PATH: &str = "/tmp/recording_test.rrec";
+
+fn frame(width: u32, height: u32, pixel: impl Fn(u32, u32) -> u32) -> GraphicsBuffer {
+    let mut frame = GraphicsBuffer::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            frame.pixels[(y * width + x) as usize] = pixel(x, y);
+        }
+    }
+    frame
+}
+
+pub fn run_recording_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Recording Test] Starting screen recording tests...\n"));
+
+    // Test 1: A short recording to a file holds the sampled frames at their size
+    _print(format_args!("[Recording Test] Test 1: Recording to a file...\n"));
+    let frames = [
+        frame(16, 8, |_, _| 0xFF20_4060),
+        frame(16, 8, |x, _| if x < 8 { 0xFFFF_0000 } else { 0xFF00_00FF }),
+        frame(16, 8, |x, y| 0xFF00_0000 | (x << 16) | (y << 8)),
+    ];
+    let _ = filesystem::remove(RECORDING_PATH);
+    recording::start_recording(RecordingTarget::File(RECORDING_PATH.into()), 10).map_err(|_| "Recording did not start")?;
+    let start = crate::time::get_timestamp_ns();
+    // At 10 fps the frame 40 ms in is not due yet and is skipped
+    let offered = [(&frames[0], 0), (&frames[1], 40 * MS), (&frames[1], 100 * MS), (&frames[2], 200 * MS)];
+    for (offered, offset) in offered {
+        recording::capture_frame(offered, start + offset);
+    }
+    let summary = recording::stop_recording().map_err(|_| "Recording did not stop")?;
+    if summary.frames_captured != 3 || summary.frames_dropped != 0 || summary.dimensions != Some((16, 8)) {
+        return Err("Recording summary wrong");
+    }
+    let data = filesystem::read_file(RECORDING_PATH).map_err(|_| "Recording file missing")?;
+    if data.len() as u64 != summary.bytes || summary.data.is_some() {
+        return Err("Recording file does not hold the encoded frames");
+    }
+    let decoded = decode(&data).map_err(|_| "Recording file does not decode")?;
+    if (decoded.width, decoded.height, decoded.fps, decoded.frames.len()) != (16, 8, 10, 3) {
+        return Err("Recording header or frame count wrong");
+    }
+    let timestamps: Vec<u64> = decoded.frames.iter().map(|frame| frame.timestamp_ns - start).collect();
+    if timestamps != [0, 100 * MS, 200 * MS] {
+        return Err("Frames not sampled at the frame rate");
+    }
+    if decoded.frames.iter().zip(&frames).any(|(decoded, captured)| decoded.pixels != captured.pixels) {
+        return Err("Decoded frames differ from the captured ones");
+    }
+    let _ = filesystem::remove(RECORDING_PATH);
+    _print(format_args!("[Recording Test] ✓ 3 frames of 16x8 written and decoded\n"));
+
+    // Test 2: Frames decode back losslessly, including runs longer than one run record holds
+    _print(format_args!("[Recording Test] Test 2: Lossless round trip...\n"));
+    let solid = frame(300, 300, |_, _| 0xFF80_8080);
+    let gradient = frame(300, 300, |x, y| 0xFF00_0000 | ((x * 255 / 299) << 16) | ((y * 255 / 299) << 8) | ((x ^ y) & 0xFF));
+    recording::start_recording(RecordingTarget::Memory, 30).map_err(|_| "Memory recording did not start")?;
+    let start = crate::time::get_timestamp_ns();
+    recording::capture_frame(&solid, start);
+    recording::capture_frame(&gradient, start + 34 * MS);
+    let summary = recording::stop_recording().map_err(|_| "Memory recording did not stop")?;
+    let decoded = decode(summary.data.as_deref().ok_or("Memory recording returned no data")?)
+        .map_err(|_| "Memory recording does not decode")?;
+    if decoded.frames.len() != 2 || decoded.frames[0].pixels != solid.pixels || decoded.frames[1].pixels != gradient.pixels {
+        return Err("Frames did not decode back to the captured content");
+    }
+    _print(format_args!("[Recording Test] ✓ Solid and gradient frames decode unchanged\n"));
+
+    // Test 3: Frames the recorder cannot take are counted as dropped
+    _print(format_args!("[Recording Test] Test 3: Dropped frames...\n"));
+    let mut recorder = Recorder::new(10).map_err(|_| "Recorder not created")?;
+    recorder.capture(&frames[0], 0);
+    // Offered 350 ms later, the frames due at 100 and 200 ms never arrived
+    recorder.capture(&frames[1], 350 * MS);
+    if (recorder.frames_captured(), recorder.frames_dropped()) != (2, 2) {
+        return Err("Late frames not counted as dropped");
+    }
+    recorder.capture(&frame(8, 8, |_, _| 0), 400 * MS);
+    if (recorder.frames_captured(), recorder.frames_dropped()) != (2, 3) || recorder.dimensions() != Some((16, 8)) {
+        return Err("Frame of another size not dropped");
+    }
+    drop(recorder);
+    // Every pixel differs from the last, so the frame encodes larger than the buffer
+    let noise = GraphicsBuffer { width: 600, height: 600, pixels: (0..600 * 600).collect() };
+    let mut fresh = Recorder::new(10).map_err(|_| "Recorder not created")?;
+    fresh.capture(&noise, 0);
+    if (fresh.frames_captured(), fresh.frames_dropped()) != (0, 1) || !fresh.encoded().is_empty() {
+        return Err("Frame larger than the buffer not dropped");
+    }
+    _print(format_args!("[Recording Test] ✓ Late, resized and oversized frames counted as dropped\n"));
+
+    // Test 4: Misuse is reported and damaged recordings are rejected
+    _print(format_args!("[Recording Test] Test 4: Errors...\n"));
+    if !matches!(recording::stop_recording(), Err(RecordingError::NotRecording))
+        || !matches!(recording::start_recording(RecordingTarget::Memory, 0), Err(RecordingError::InvalidFrameRate))
+    {
+        return Err("Invalid recording calls accepted");
+    }
+    recording::start_recording(RecordingTarget::Memory, 10).map_err(|_| "Recording did not start")?;
+    let second = recording::start_recording(RecordingTarget::Memory, 10);
+    let data = recording::stop_recording().map_err(|_| "Recording did not stop")?.data.unwrap_or_default();
+    if !matches!(second, Err(RecordingError::AlreadyRecording)) || !data.is_empty() {
+        return Err("Second recording started, or a frameless recording is not empty");
+    }
+    let mut recorder = Recorder::new(10).map_err(|_| "Recorder not created")?;
+    recorder.capture(&frames[2], 0);
+    let encoded = recorder.encoded();
+    let mut foreign = encoded.to_vec();
+    foreign[..4].copy_from_slice(b"RIFF");
+    let mut corrupt = encoded.to_vec();
+    // Lengthen the first run so the runs cover more pixels than the frame has
+    corrupt[30] = corrupt[30].wrapping_add(1);
+    if !matches!(decode(&foreign), Err(RecordingError::UnsupportedFormat))
+        || !matches!(decode(&encoded[..encoded.len() - 1]), Err(RecordingError::Truncated))
+        || !matches!(decode(&corrupt), Err(RecordingError::Corrupt))
+    {
+        return Err("Damaged recording accepted");
+    }
+    _print(format_args!("[Recording Test] ✓ Misuse and damaged recordings rejected\n"));
+
+    _print(format_args!("[Recording Test] ✓ All screen recording tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for screen recording
+pub fn test_recording() {
+    _print(format_args!("[Recording Test] ===========================================\n"));
+    _print(format_args!("[Recording Test]           SCREEN RECORDING TESTS\n"));
+    _print(format_args!("[Recording Test] ===========================================\n"));
+
+    match run_recording_tests() {
+        Ok(_) => _print(format_args!("[Recording Test] ✓ All screen recording tests PASSED\n")),
+        Err(e) => _print(format_args!("[Recording Test] ✗ Screen recording tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Recording Test] ===========================================\n"));
+}
@@ -0,0 +1,199 @@
+//! Compositor Renderer Test
+//! Composites the same scene with the software renderer and with a mock hardware renderer,
+//! checks the output is identical, and checks renderer selection and the fallback to software
+//! when hardware fails at init or mid-frame
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use x86_64::VirtAddr;
+use crate::graphics::renderer::{composite_scene, select_renderer, HardwareProbe};
+use crate::graphics::{Color, FramebufferCompositor, GraphicsBuffer, Point, RaeTheme, Rect, Renderer, RendererError, SoftwareRenderer, WindowManager};
+use crate::serial::_print;
+
+const WIDTH: u32 = 96;
+const HEIGHT: u32 = 64;
+
+/// Stands in for a GPU: draws pixel by pixel, counts its commands and can be told to fail
+struct MockHardwareRenderer {
+    fail_init: bool,
+    /// Blits accepted before the device is lost
+    blits_left: Option<u32>,
+    commands: u32,
+}
+
+impl MockHardwareRenderer {
+    fn new() -> Self {
+        Self { fail_init: false, blits_left: None, commands: 0 }
+    }
+
+    fn pixel_mut(target: &mut GraphicsBuffer, x: i64, y: i64) -> Option<&mut u32> {
+        if x < 0 || y < 0 || x >= i64::from(target.width) || y >= i64::from(target.height) {
+            return None;
+        }
+        target.pixels.get_mut((y * i64::from(target.width) + x) as usize)
+    }
+}
+
+impl Renderer for MockHardwareRenderer {
+    fn name(&self) -> &str {
+        "mock-gpu"
+    }
+
+    fn is_hardware(&self) -> bool {
+        true
+    }
+
+    fn init(&mut self) -> Result<(), RendererError> {
+        if self.fail_init { Err(RendererError::InitFailed) } else { Ok(()) }
+    }
+
+    fn fill(&mut self, target: &mut GraphicsBuffer, rect: Rect, color: Color) -> Result<(), RendererError> {
+        self.commands += 1;
+        let value = u32::from_be_bytes([color.a, color.r, color.g, color.b]);
+        for y in 0..i64::from(rect.height) {
+            for x in 0..i64::from(rect.width) {
+                if let Some(pixel) = Self::pixel_mut(target, i64::from(rect.x) + x, i64::from(rect.y) + y) {
+                    *pixel = value;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn blit(&mut self, target: &mut GraphicsBuffer, source: &GraphicsBuffer, position: Point) -> Result<(), RendererError> {
+        if let Some(left) = self.blits_left.as_mut() {
+            if *left == 0 {
+                return Err(RendererError::DeviceLost);
+            }
+            *left -= 1;
+        }
+        self.commands += 1;
+        for (index, &value) in source.pixels.iter().enumerate() {
+            let (x, y) = ((index as u32 % source.width) as i64, (index as u32 / source.width) as i64);
+            if let Some(pixel) = Self::pixel_mut(target, i64::from(position.x) + x, i64::from(position.y) + y) {
+                *pixel = value;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn no_device() -> Option<Box<dyn Renderer>> {
+    None
+}
+
+fn broken_device() -> Option<Box<dyn Renderer>> {
+    Some(Box::new(MockHardwareRenderer { fail_init: true, ..MockHardwareRenderer::new() }))
+}
+
+fn working_device() -> Option<Box<dyn Renderer>> {
+    Some(Box::new(MockHardwareRenderer::new()))
+}
+
+/// Overlapping windows, one hanging off each edge of the screen and one hidden
+fn scene() -> WindowManager {
+    let mut wm = WindowManager::new(WIDTH, HEIGHT);
+    let windows = [
+        (Rect::new(8, 8, 40, 30), true),
+        (Rect::new(30, 20, 40, 30), true),
+        (Rect::new(-10, -6, 24, 20), true),
+        (Rect::new(80, 50, 30, 30), true),
+        (Rect::new(40, 4, 20, 20), false),
+    ];
+    for (i, (rect, visible)) in windows.into_iter().enumerate() {
+        let id = wm.create_window(String::from("Scene"), rect, 1);
+        if let Some(window) = wm.get_window_mut(id) {
+            window.visible = visible;
+            if let Some(buffer) = window.buffer.as_mut() {
+                let width = buffer.width;
+                for (index, pixel) in buffer.pixels.iter_mut().enumerate() {
+                    let (x, y) = (index as u32 % width, index as u32 / width);
+                    *pixel = 0xFF00_0000 | ((i as u32 * 50) << 16) | ((x * 6) << 8) | (y * 8);
+                }
+            }
+        }
+    }
+    wm
+}
+
+fn render(renderer: &mut dyn Renderer, wm: &WindowManager) -> Result<GraphicsBuffer, &'static str> {
+    let mut target = GraphicsBuffer::new(WIDTH, HEIGHT);
+    composite_scene(renderer, &mut target, wm).map_err(|_| "Scene not composited")?;
+    Ok(target)
+}
+
+pub fn run_renderer_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Renderer Test] Starting compositor renderer tests...\n"));
+    let wm = scene();
+
+    // Test 1: Software and hardware renderers composite the same scene identically
+    _print(format_args!("[Renderer Test] Test 1: Identical output across backends...\n"));
+    let software = render(&mut SoftwareRenderer, &wm)?;
+    let mut hardware = MockHardwareRenderer::new();
+    let accelerated = render(&mut hardware, &wm)?;
+    // One fill for the background and one blit per visible window
+    if hardware.commands != 5 {
+        return Err("Hardware renderer not given the scene");
+    }
+    if software.pixels != accelerated.pixels {
+        return Err("Hardware output differs from software");
+    }
+    let theme = RaeTheme::default().background_color;
+    let background = u32::from_be_bytes([theme.a, theme.r, theme.g, theme.b]);
+    // Uncovered, inside the hidden window, and the clipped top-left window's pixel (10, 6)
+    if software.pixels[(60 * WIDTH + 2) as usize] != background
+        || software.pixels[(6 * WIDTH + 50) as usize] != background
+        || software.pixels[0] != 0xFF64_3C30
+    {
+        return Err("Composited scene wrong");
+    }
+    _print(format_args!("[Renderer Test] ✓ Software and hardware output identical\n"));
+
+    // Test 2: Hardware is chosen only when enabled and its device initializes
+    _print(format_args!("[Renderer Test] Test 2: Renderer selection...\n"));
+    let probes: [HardwareProbe; 3] = [no_device, broken_device, working_device];
+    if select_renderer(true, &probes).name() != "mock-gpu" || !select_renderer(true, &probes).is_hardware() {
+        return Err("Available hardware renderer not selected");
+    }
+    if select_renderer(false, &probes).name() != "software" {
+        return Err("Hardware selected with acceleration disabled");
+    }
+    if select_renderer(true, &[no_device, broken_device]).name() != "software" || select_renderer(true, &[]).name() != "software" {
+        return Err("No software fallback without usable hardware");
+    }
+    _print(format_args!("[Renderer Test] ✓ Hardware selected when available, software otherwise\n"));
+
+    // Test 3: A device lost mid-frame falls back to software without a visible difference
+    _print(format_args!("[Renderer Test] Test 3: Mid-frame fallback...\n"));
+    let mut compositor = FramebufferCompositor::new(VirtAddr::new(0), WIDTH, HEIGHT, WIDTH * 4, 32);
+    compositor.set_renderer(Box::new(MockHardwareRenderer { blits_left: Some(2), ..MockHardwareRenderer::new() }));
+    compositor.composite(&wm);
+    if compositor.renderer_name() != "software" {
+        return Err("Compositor kept the failed renderer");
+    }
+    if compositor.get_back_buffer().pixels != software.pixels {
+        return Err("Frame after fallback differs from software");
+    }
+    compositor.composite(&wm);
+    if compositor.get_back_buffer().pixels != software.pixels {
+        return Err("Frames after fallback differ from software");
+    }
+    _print(format_args!("[Renderer Test] ✓ Lost device replaced by software in the same frame\n"));
+
+    _print(format_args!("[Renderer Test] ✓ All compositor renderer tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for compositor renderers
+pub fn test_renderer() {
+    _print(format_args!("[Renderer Test] ===========================================\n"));
+    _print(format_args!("[Renderer Test]        COMPOSITOR RENDERER TESTS\n"));
+    _print(format_args!("[Renderer Test] ===========================================\n"));
+
+    match run_renderer_tests() {
+        Ok(_) => _print(format_args!("[Renderer Test] ✓ All compositor renderer tests PASSED\n")),
+        Err(e) => _print(format_args!("[Renderer Test] ✗ Compositor renderer tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Renderer Test] ===========================================\n"));
+}
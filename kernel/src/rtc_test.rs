@@ -0,0 +1,95 @@
+//! RTC Driver Test
+//! Decodes canned CMOS register values in every encoding and sanity-checks the live clock
+
+use crate::drivers::rtc::{self, RtcRegisters};
+use crate::serial::_print;
+use crate::time::DateTime;
+
+/// 2024-08-15 12:30:45 UTC
+const EXPECTED: DateTime = DateTime { year: 2024, month: 8, day: 15, hour: 12, minute: 30, second: 45 };
+const EXPECTED_TIMESTAMP: u64 = 1_723_725_045;
+
+/// 2020-01-01 and 2100-01-01; anything outside is a misread clock
+const PLAUSIBLE_MIN: u64 = 1_577_836_800;
+const PLAUSIBLE_MAX: u64 = 4_102_444_800;
+
+/// Status B values: bit 1 selects 24-hour mode, bit 2 binary encoding
+const BCD_24H: u8 = 0x02;
+const BINARY_24H: u8 = 0x06;
+const BCD_12H: u8 = 0x00;
+const BINARY_12H: u8 = 0x04;
+
+/// Build a snapshot from [second, minute, hour, day, month, year]
+fn registers(fields: [u8; 6], century: u8, status_b: u8) -> RtcRegisters {
+    let [second, minute, hour, day, month, year] = fields;
+    RtcRegisters { second, minute, hour, day, month, year, century, status_b }
+}
+
+fn hour_of(hour: u8, status_b: u8) -> u8 {
+    rtc::decode(&registers([0, 0, hour, 1, 1, 0], 0, status_b)).hour
+}
+
+/// Test RTC decoding and the live clock
+pub fn run_rtc_tests() -> Result<(), &'static str> {
+    _print(format_args!("[RTC Test] Starting RTC driver tests...\n"));
+    
+    // Test 1: BCD and binary encodings decode to the same time
+    _print(format_args!("[RTC Test] Test 1: BCD and binary decoding...\n"));
+    let bcd = rtc::decode(&registers([0x45, 0x30, 0x12, 0x15, 0x08, 0x24], 0x20, BCD_24H));
+    let binary = rtc::decode(&registers([45, 30, 12, 15, 8, 24], 20, BINARY_24H));
+    if bcd != EXPECTED || binary != EXPECTED {
+        return Err("BCD/binary register decoding mismatch");
+    }
+    if EXPECTED.to_timestamp() != EXPECTED_TIMESTAMP {
+        return Err("Unix timestamp conversion mismatch");
+    }
+    // Without a century register the 21st century is assumed
+    if rtc::decode(&registers([0x45, 0x30, 0x12, 0x15, 0x08, 0x24], 0, BCD_24H)) != EXPECTED {
+        return Err("Default century not applied");
+    }
+    _print(format_args!("[RTC Test] ✓ Both encodings decode to {}\n", EXPECTED_TIMESTAMP));
+    
+    // Test 2: 12-hour mode, including the midnight and noon edge cases
+    _print(format_args!("[RTC Test] Test 2: 12-hour decoding...\n"));
+    let cases = [
+        (0x12, BCD_12H, 0),         // 12 AM
+        (0x80 | 0x12, BCD_12H, 12), // 12 PM
+        (0x80 | 0x01, BCD_12H, 13),
+        (0x11, BCD_12H, 11),
+        (0x80 | 11, BINARY_12H, 23),
+        (12, BINARY_12H, 0),
+    ];
+    for (raw, status_b, expected) in cases {
+        if hour_of(raw, status_b) != expected {
+            return Err("12-hour register decoding mismatch");
+        }
+    }
+    _print(format_args!("[RTC Test] ✓ 12-hour AM/PM hours decoded\n"));
+    
+    // Test 3: The live RTC reads a plausible time
+    _print(format_args!("[RTC Test] Test 3: Reading the live RTC...\n"));
+    let now = rtc::read();
+    let timestamp = now.to_timestamp();
+    if timestamp < PLAUSIBLE_MIN || timestamp > PLAUSIBLE_MAX {
+        return Err("RTC timestamp is implausible");
+    }
+    _print(format_args!("[RTC Test] ✓ {:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC ({})\n",
+        now.year, now.month, now.day, now.hour, now.minute, now.second, timestamp));
+    
+    _print(format_args!("[RTC Test] ✓ All RTC driver tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for the RTC driver
+pub fn test_rtc() {
+    _print(format_args!("[RTC Test] ===========================================\n"));
+    _print(format_args!("[RTC Test]              RTC DRIVER TESTS\n"));
+    _print(format_args!("[RTC Test] ===========================================\n"));
+    
+    match run_rtc_tests() {
+        Ok(_) => _print(format_args!("[RTC Test] ✓ All RTC tests PASSED\n")),
+        Err(e) => _print(format_args!("[RTC Test] ✗ RTC tests FAILED: {}\n", e)),
+    }
+    
+    _print(format_args!("[RTC Test] ===========================================\n"));
+}
@@ -0,0 +1,185 @@
+//! Scheduler Statistics Test
+//! Queues processes pinned to known CPUs of a two-core scheduler and checks that the per-CPU
+//! statistics report the ready queue lengths, load and context switches, that EDF and CBS
+//! threads show up in their queues with their deadlines and budgets, that `/proc/sched` is
+//! published with a line per CPU, and that a yield passes the CPU on without queueing the
+//! process twice
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use x86_64::VirtAddr;
+use crate::arch::topology::CpuTopology;
+use crate::process::{self, CbsParams, CpuAffinity, Priority, Process, RtClass, RtQueueEntry, SmpScheduler};
+use crate::serial::_print;
+use crate::vmm;
+
+/// Two cores of one thread each
+fn two_cores() -> SmpScheduler {
+    let mut topology = CpuTopology::from_cpuid(&|_, _| (0, 0, 0, 0));
+    for cpu in 0..2 {
+        topology.add_cpu(cpu, cpu);
+    }
+    SmpScheduler::with_topology(topology)
+}
+
+/// Queue a process pinned to `cpu`, noting its address space for cleanup
+fn spawn(scheduler: &mut SmpScheduler, priority: Priority, cpu: u32, address_spaces: &mut Vec<u64>) -> Result<u64, &'static str> {
+    spawn_with(scheduler, priority, cpu, address_spaces, |_| {})
+}
+
+fn spawn_with(
+    scheduler: &mut SmpScheduler,
+    priority: Priority,
+    cpu: u32,
+    address_spaces: &mut Vec<u64>,
+    configure: impl FnOnce(&mut Process),
+) -> Result<u64, &'static str> {
+    let mut process = Process::new(String::from("sched-test"), VirtAddr::new(0x400000), priority).map_err(|_| "Failed to create process")?;
+    address_spaces.extend(process.address_space_id);
+    process.cpu_affinity = CpuAffinity::single_cpu(cpu);
+    configure(&mut process);
+    Ok(scheduler.add_process(process))
+}
+
+fn sched_stats(address_spaces: &mut Vec<u64>) -> Result<(), &'static str> {
+    // Test 1: Queue lengths by priority, load and context switches per CPU
+    _print(format_args!("[Sched Test] Test 1: Per-CPU queue lengths...\n"));
+    let mut scheduler = two_cores();
+    let high = spawn(&mut scheduler, Priority::High, 0, address_spaces)?;
+    spawn(&mut scheduler, Priority::Normal, 0, address_spaces)?;
+    spawn(&mut scheduler, Priority::Normal, 0, address_spaces)?;
+    spawn(&mut scheduler, Priority::Low, 1, address_spaces)?;
+    spawn(&mut scheduler, Priority::Gaming, 1, address_spaces)?;
+    let stats = scheduler.sched_stats();
+    if stats.len() != 2 || stats[0].queue_lengths != [1, 2, 0, 0] || stats[1].queue_lengths != [0, 0, 1, 1] {
+        return Err("Queue lengths wrong");
+    }
+    if stats.iter().map(|cpu| (cpu.load, cpu.current, cpu.context_switches)).ne([(3, None, 0), (2, None, 0)]) {
+        return Err("Load, current process or switches wrong before scheduling");
+    }
+    // The High process stays picked, so only the first pick is a switch
+    scheduler.schedule_on_cpu(0);
+    scheduler.schedule_on_cpu(0);
+    let stats = scheduler.sched_stats();
+    let cpu0 = &stats[0];
+    if cpu0.current != Some(high) || cpu0.context_switches != 1 {
+        return Err("Current process or context switches wrong after scheduling");
+    }
+    let line = alloc::format!("cpu0 current={} load=3 switches=1 isolated=0 high=1 normal=2 low=0 gaming=0\n", high);
+    if process::sched_report(&[cpu0.clone()]) != line {
+        return Err("CPU line of the report wrong");
+    }
+    _print(format_args!("[Sched Test] ✓ {}", line));
+
+    // Test 2: Real-time threads are listed in their queues with deadlines and budgets
+    _print(format_args!("[Sched Test] Test 2: EDF and CBS queues...\n"));
+    let rt = |class: RtClass, deadline_us: u64, budget_us: u64| {
+        move |process: &mut Process| {
+            process.rt_params.class = class;
+            process.rt_params.next_deadline = deadline_us;
+            process.rt_params.remaining_budget = budget_us;
+            if class == RtClass::Cbs {
+                process.rt_params.cbs_params = Some(CbsParams {
+                    server_budget_us: 800,
+                    server_period_us: 10_000,
+                    remaining_budget: 300,
+                    next_replenishment: deadline_us,
+                    throttled: false,
+                });
+            }
+        }
+    };
+    let late = spawn_with(&mut scheduler, Priority::High, 1, address_spaces, rt(RtClass::Edf, 9_000, 400))?;
+    let early = spawn_with(&mut scheduler, Priority::High, 1, address_spaces, rt(RtClass::Edf, 5_000, 200))?;
+    let server = spawn_with(&mut scheduler, Priority::Normal, 1, address_spaces, rt(RtClass::Cbs, 7_000, 900))?;
+    for (pid, class) in [(late, RtClass::Edf), (early, RtClass::Edf), (server, RtClass::Cbs)] {
+        scheduler.add_rt_process(pid, class);
+    }
+    let stats = scheduler.sched_stats();
+    let cpu1 = &stats[1];
+    let edf = [
+        RtQueueEntry { pid: early, deadline_us: 5_000, budget_us: 200 },
+        RtQueueEntry { pid: late, deadline_us: 9_000, budget_us: 400 },
+    ];
+    if cpu1.edf_queue != edf || cpu1.cbs_queue != [RtQueueEntry { pid: server, deadline_us: 7_000, budget_us: 300 }] {
+        return Err("Real-time queues wrong");
+    }
+    let report = process::sched_report(&[cpu1.clone()]);
+    let lines: Vec<&str> = report.lines().skip(1).collect();
+    let expected = [
+        alloc::format!("  edf pid={} deadline_us=5000 budget_us=200", early),
+        alloc::format!("  edf pid={} deadline_us=9000 budget_us=400", late),
+        alloc::format!("  cbs pid={} deadline_us=7000 budget_us=300", server),
+    ];
+    if lines != expected {
+        return Err("Real-time lines of the report wrong");
+    }
+    _print(format_args!("[Sched Test] ✓ EDF thread {} listed first, deadline 5000us\n", early));
+
+    // Test 3: /proc/sched has a line for every CPU of the running scheduler
+    _print(format_args!("[Sched Test] Test 3: /proc/sched...\n"));
+    process::publish_sched_stats().map_err(|_| "Failed to publish /proc/sched")?;
+    let published = crate::filesystem::read_file(process::SCHED_STATS_PATH).map_err(|_| "Failed to read /proc/sched")?;
+    let published = String::from_utf8(published).map_err(|_| "/proc/sched is not text")?;
+    let cpus = published.lines().filter(|line| line.starts_with("cpu")).count();
+    if !published.starts_with("cpu0 current=") || cpus != process::sched_stats().len() {
+        return Err("/proc/sched malformed");
+    }
+    _print(format_args!("[Sched Test] ✓ /proc/sched lists {} CPUs\n", cpus));
+
+    // Test 4: A yield keeps one queue entry per process, and `ps` and `sched_stats` see it
+    _print(format_args!("[Sched Test] Test 4: Yield and process statistics...\n"));
+    let mut scheduler = two_cores();
+    let first = spawn(&mut scheduler, Priority::Normal, 0, address_spaces)?;
+    let second = spawn(&mut scheduler, Priority::Normal, 0, address_spaces)?;
+    if scheduler.schedule_on_cpu(0) != Some(first) {
+        return Err("First process not picked first");
+    }
+    scheduler.yield_current_on_cpu(0);
+    let stats = scheduler.sched_stats();
+    if stats[0].queue_lengths[Priority::Normal as usize] != 2 || stats[0].current.is_some() {
+        return Err("Yield queued the process twice or kept it current");
+    }
+    if scheduler.schedule_on_cpu(0) != Some(second) {
+        return Err("Yield did not pass the CPU on");
+    }
+    let stats = scheduler.sched_stats();
+    if stats[0].to_words()[..4] != [0, second, 2, 2] {
+        return Err("Statistics words wrong");
+    }
+    let listed = scheduler.process_stats();
+    for pid in [first, second] {
+        let entry = listed.iter().find(|process| process.pid == pid).ok_or("Process missing from the list")?;
+        if entry.priority != Priority::Normal || entry.affinity != CpuAffinity::single_cpu(0) {
+            return Err("Process listed with the wrong priority or affinity");
+        }
+    }
+    _print(format_args!("[Sched Test] ✓ Yield passed CPU 0 from {} to {}\n", first, second));
+    Ok(())
+}
+
+pub fn run_sched_tests() -> Result<(), &'static str> {
+    let mut address_spaces = Vec::new();
+    let result = sched_stats(&mut address_spaces);
+    for id in address_spaces {
+        let _ = vmm::destroy_address_space(id);
+    }
+    result?;
+
+    _print(format_args!("[Sched Test] ✓ All scheduler statistics tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for scheduler statistics
+pub fn test_sched() {
+    _print(format_args!("[Sched Test] ===========================================\n"));
+    _print(format_args!("[Sched Test]       SCHEDULER STATISTICS TESTS\n"));
+    _print(format_args!("[Sched Test] ===========================================\n"));
+
+    match run_sched_tests() {
+        Ok(_) => _print(format_args!("[Sched Test] ✓ All scheduler statistics tests PASSED\n")),
+        Err(e) => _print(format_args!("[Sched Test] ✗ Scheduler statistics tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Sched Test] ===========================================\n"));
+}
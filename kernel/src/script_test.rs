@@ -0,0 +1,251 @@
+//! Script Interpreter Test
+//! Runs shell scripts through RaeShell's interpreter with its built-in commands and checks
+//! what they print: branching on exit status, loops over word lists, command substitution,
+//! arithmetic, `case` patterns, scripts cut short that need more input, aliases and
+//! functions, including those a new shell session loads from its rc file, and pipelines and
+//! output redirected to files
+
+use alloc::string::String;
+use crate::raeshell::{self, run_command, ShellResult};
+use crate::raeshell::script::{is_complete, Interpreter, ScriptError};
+use crate::serial::_print;
+
+/// Run `source` in a fresh interpreter, returning its exit status and output
+fn run(source: &str) -> Result<(i32, String), ScriptError> {
+    let mut interpreter = Interpreter::new(run_command);
+    let status = interpreter.run(source)?;
+    Ok((status, interpreter.take_output()))
+}
+
+/// Run `source` and check it succeeds printing `expected`
+fn check(source: &str, expected: &str, failure: &'static str) -> Result<(), &'static str> {
+    match run(source) {
+        Ok((0, output)) if output == expected => Ok(()),
+        _ => Err(failure),
+    }
+}
+
+pub fn run_script_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Script Test] Starting script interpreter tests...\n"));
+
+    // Test 1: if/elif/else and && and || choose by exit status
+    _print(format_args!("[Script Test] Test 1: Branching on exit status...\n"));
+    check(
+        "if false; then echo wrong; elif test 2 -gt 1; then echo elif; else echo never; fi\n\
+         if [ abc = abd ]; then echo equal; else echo differ; fi\n\
+         true && echo and || echo or\n\
+         false && echo and || echo or\n\
+         ! true; echo $?",
+        "elif\ndiffer\nand\nor\n1\n",
+        "Branch taken against the exit status",
+    )?;
+    if run("true; false").ok() != Some((1, String::new())) {
+        return Err("Script status not that of its last command");
+    }
+    _print(format_args!("[Script Test] ✓ Branches chosen by exit status\n"));
+
+    // Test 2: for loops over words, with break and continue
+    _print(format_args!("[Script Test] Test 2: For loops...\n"));
+    check(
+        "for name in alpha \"beta gamma\" delta; do echo \"<$name>\"; done\n\
+         words='x y  z'; for w in $words; do echo $w; done",
+        "<alpha>\n<beta gamma>\n<delta>\nx\ny\nz\n",
+        "Loop items not split as words",
+    )?;
+    check(
+        "for i in 1 2 3 4 5; do\n\
+           if test $i = 2; then continue; fi\n\
+           if test $i = 4; then break; fi\n\
+           echo $i\n\
+         done\n\
+         for a in 1 2; do for b in x y; do echo $a$b; break 2; done; done",
+        "1\n3\n1x\n",
+        "Loop not left by break and continue",
+    )?;
+    _print(format_args!("[Script Test] ✓ Lists iterated in order\n"));
+
+    // Test 3: Command substitution captures output, without its variables leaking out
+    _print(format_args!("[Script Test] Test 3: Command substitution...\n"));
+    check(
+        "greeting=$(echo hello   world); echo \"[$greeting]\"\n\
+         echo $(echo $(echo deep)) `echo tick`\n\
+         x=$(false); echo $?\n\
+         inner=outer; y=$(inner=changed; echo $inner); echo $y $inner",
+        "[hello world]\ndeep tick\n1\nchanged outer\n",
+        "Substituted output not captured",
+    )?;
+    check("sh -c 'echo nested $1 $#' name arg", "nested arg 1\n", "Inline script not run by sh")?;
+    _print(format_args!("[Script Test] ✓ Output captured into words\n"));
+
+    // Test 4: Arithmetic with precedence, variables and comparisons
+    _print(format_args!("[Script Test] Test 4: Arithmetic...\n"));
+    check(
+        "echo $((1 + 2 * 3)) $(( (1 + 2) * 3 )) $((7 / 2)) $((7 % 3)) $((-4 + 10))\n\
+         n=5; echo $((n * 2 + $n)) $((n > 3 && n != 5)) $((!0))\n\
+         i=0; total=0\n\
+         while [ $i -lt 4 ]; do i=$((i + 1)); total=$((total + i)); done; echo $total\n\
+         until test $i -eq 0; do i=$((i - 1)); done; echo $i",
+        "7 9 3 1 6\n15 0 1\n10\n0\n",
+        "Arithmetic evaluated wrong",
+    )?;
+    if !matches!(run("echo $((1 / 0))"), Err(ScriptError::Arithmetic(_))) {
+        return Err("Division by zero not reported");
+    }
+    _print(format_args!("[Script Test] ✓ Expressions evaluated\n"));
+
+    // Test 5: case matches glob patterns, exit ends the script, and unfinished scripts wait
+    _print(format_args!("[Script Test] Test 5: Case, exit and incomplete scripts...\n"));
+    check(
+        "for file in notes.txt run.sh image.PNG; do\n\
+           case $file in\n\
+             *.sh) echo \"$file: script\";;\n\
+             *.txt|*.md) echo \"$file: text\";;\n\
+             *.[Pp][Nn][Gg]) echo \"$file: image\";;\n\
+             *) echo \"$file: other\";;\n\
+           esac\n\
+         done\n\
+         case '*' in \"*\") echo star;; esac; case x in \"*\") echo star;; *) echo other;; esac",
+        "notes.txt: text\nrun.sh: script\nimage.PNG: image\nstar\nother\n",
+        "Case arm chosen wrong",
+    )?;
+    let mut interpreter = Interpreter::new(run_command);
+    if interpreter.run("echo before; exit 3; echo after") != Ok(3)
+        || !interpreter.has_exited()
+        || interpreter.take_output() != "before\n"
+    {
+        return Err("Exit did not end the script");
+    }
+    let unfinished = ["if true; then", "for x in a b; do echo $x", "echo 'open", "echo $(echo", "true &&"];
+    if unfinished.iter().any(|source| is_complete(source)) || !is_complete("if true; then echo; fi") {
+        return Err("Unfinished script taken as whole");
+    }
+    if !matches!(run("fi"), Err(ScriptError::Syntax(_))) {
+        return Err("Stray reserved word accepted");
+    }
+    _print(format_args!("[Script Test] ✓ Patterns matched and unfinished scripts detected\n"));
+
+    // Test 6: Aliases expand in command position only, and not again inside themselves
+    _print(format_args!("[Script Test] Test 6: Aliases...\n"));
+    let mut interpreter = Interpreter::new(run_command);
+    interpreter.run("alias say='echo said' echo='echo [echo]'; alias quiet=true")
+        .map_err(|_| "Alias definition failed")?;
+    interpreter.take_output();
+    if interpreter.run("say hi; echo say; if say in if; then quiet && say done; fi") != Ok(0)
+        || interpreter.take_output() != "[echo] said hi\n[echo] say\n[echo] said in if\n[echo] said done\n"
+    {
+        return Err("Alias not expanded on invocation");
+    }
+    // A line is parsed before it runs, so the removal shows from the next one
+    if interpreter.run("alias say; unalias say") != Ok(0)
+        || interpreter.run("say") != Ok(1)
+        || interpreter.take_output() != "alias say='echo said'\nsay: command not found\n"
+    {
+        return Err("Alias not shown and removed");
+    }
+    _print(format_args!("[Script Test] ✓ Aliases expanded\n"));
+
+    // Test 7: Functions run their bodies with arguments, in the caller's variables
+    _print(format_args!("[Script Test] Test 7: Functions...\n"));
+    check(
+        "greet() { echo \"hello $1 of $#\"; last=$1; }\n\
+         greet world extra; echo \"[$last]\"\n\
+         count() {\n\
+           if test $1 -eq 0; then return 3; fi\n\
+           echo $1; count $(($1 - 1))\n\
+         }\n\
+         count 2; echo status $?\n\
+         for x in a b; do found() { return 0; }; found; echo loop $x; done\n\
+         echo args $#",
+        "hello world of 2\n[world]\n2\n1\nstatus 3\nloop a\nloop b\nargs 0\n",
+        "Function body not run with its arguments",
+    )?;
+    if run("forever() { forever; }; forever").ok().map(|(status, _)| status) != Some(1) {
+        return Err("Runaway recursion not stopped");
+    }
+    if !matches!(run("broken() { echo; "), Err(ScriptError::Incomplete)) || run("return").ok().map(|(status, _)| status) != Some(1) {
+        return Err("Function definition misparsed");
+    }
+    _print(format_args!("[Script Test] ✓ Functions called with arguments\n"));
+
+    // Test 8: Aliases and functions saved to the rc file load into a new session
+    _print(format_args!("[Script Test] Test 8: Loading the rc file...\n"));
+    let session = raeshell::create_shell_session().map_err(|_| "Failed to create shell session")?;
+    let rc_path = "/home/user/.raeshrc";
+    let saved_rc = crate::filesystem::read_file(rc_path).ok();
+    let _ = crate::filesystem::create_directory("/home");
+    let _ = crate::filesystem::create_directory("/home/user");
+    let defined = raeshell::execute_command(session, "alias hi='echo hi there'\ntwice() { echo \"$1$1\"; }");
+    let saved = raeshell::save_shell_rc(session);
+    let fresh = raeshell::create_shell_session();
+    let loaded = match fresh {
+        Ok(fresh) => raeshell::execute_command(fresh, "hi; twice ab"),
+        Err(()) => Err(()),
+    };
+    let _ = raeshell::close_shell_session(session);
+    if let Ok(fresh) = fresh {
+        let _ = raeshell::close_shell_session(fresh);
+    }
+    let _ = crate::filesystem::remove(rc_path);
+    if let Some(data) = saved_rc {
+        let restored = crate::filesystem::create_file(rc_path).ok().and_then(|_| crate::filesystem::open_file(rc_path).ok());
+        if let Some(fd) = restored {
+            let _ = crate::filesystem::write_file(fd, &data);
+            let _ = crate::filesystem::close_file(fd);
+        }
+    }
+    if !matches!(defined, Ok(ShellResult::Success(_))) || saved.is_err() {
+        return Err("Definitions not saved to the rc file");
+    }
+    if !matches!(loaded, Ok(ShellResult::Success(output)) if output == "hi there\nabab") {
+        return Err("Definitions not loaded from the rc file");
+    }
+    _print(format_args!("[Script Test] ✓ Definitions loaded in a new session\n"));
+
+    // Test 9: Pipelines pass output along, and redirections send it to files
+    _print(format_args!("[Script Test] Test 9: Pipelines and redirection...\n"));
+    check(
+        "echo hi | cat\n\
+         echo one two | cat | cat -\n\
+         echo 'a|b' \"c>d\" a\\|b\n\
+         relay() { echo hello; cat; }; echo piped |\n  relay | cat",
+        "hi\none two\na|b c>d a|b\nhello\npiped\n",
+        "Output not passed along the pipeline",
+    )?;
+    let _ = crate::filesystem::create_directory("/tmp");
+    let redirected = check(
+        "echo hi > /tmp/x; cat /tmp/x\n\
+         echo more >>/tmp/x; cat /tmp/x | cat\n\
+         >/tmp/x echo replaced; cat /tmp/x",
+        "hi\nhi\nmore\nreplaced\n",
+        "Output not redirected to the file",
+    );
+    let _ = crate::filesystem::remove("/tmp/x");
+    redirected?;
+    let missing = run("echo lost > /nonexistent/dir/file");
+    if !matches!(missing, Ok((1, output)) if output == "/nonexistent/dir/file: No such file or directory\n")
+        || crate::filesystem::metadata("/nonexistent/dir/file").is_ok()
+    {
+        return Err("Redirection into a missing directory not refused");
+    }
+    if !matches!(run("echo hi >"), Err(ScriptError::Syntax(_))) {
+        return Err("Redirection without a file accepted");
+    }
+    _print(format_args!("[Script Test] ✓ Output piped and redirected\n"));
+
+    _print(format_args!("[Script Test] ✓ All script interpreter tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for the script interpreter
+pub fn test_script() {
+    _print(format_args!("[Script Test] ===========================================\n"));
+    _print(format_args!("[Script Test]          SCRIPT INTERPRETER TESTS\n"));
+    _print(format_args!("[Script Test] ===========================================\n"));
+
+    match run_script_tests() {
+        Ok(_) => _print(format_args!("[Script Test] ✓ All script interpreter tests PASSED\n")),
+        Err(e) => _print(format_args!("[Script Test] ✗ Script interpreter tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Script Test] ===========================================\n"));
+}
@@ -1,4 +1,5 @@
 use spin::Mutex;
+#[cfg(target_arch = "x86_64")]
 use uart_16550::SerialPort;
 use core::fmt::Write;
 
@@ -7,8 +8,74 @@ use core::fmt::Write;
 // - SerialPort::new requires unsafe because it accesses hardware I/O ports
 // - The port address must be valid and not conflict with other hardware
 // - Serial port access requires proper initialization before use
+#[cfg(target_arch = "x86_64")]
 pub static SERIAL1: Mutex<SerialPort> = Mutex::new(unsafe { SerialPort::new(0x3F8) });
 
+// SAFETY: 0x0900_0000 is the PL011 UART on the QEMU `virt` machine, identity mapped
+// while the MMU is off during early boot
+#[cfg(target_arch = "aarch64")]
+pub static SERIAL1: Mutex<Pl011> = Mutex::new(unsafe { Pl011::new(0x0900_0000) });
+
+/// ARM PrimeCell PL011 UART, the console on aarch64 virtual machines
+#[cfg(target_arch = "aarch64")]
+pub struct Pl011 {
+    base: usize,
+}
+
+#[cfg(target_arch = "aarch64")]
+impl Pl011 {
+    const DR: usize = 0x00;
+    const FR: usize = 0x18;
+    const LCRH: usize = 0x2C;
+    const CR: usize = 0x30;
+    const FR_TXFF: u32 = 1 << 5;
+    const LCRH_FEN: u32 = 1 << 4;
+    const LCRH_WLEN_8: u32 = 0b11 << 5;
+    const CR_UARTEN: u32 = 1 << 0;
+    const CR_TXE: u32 = 1 << 8;
+    const CR_RXE: u32 = 1 << 9;
+
+    /// # Safety
+    /// `base` must be the MMIO address of a PL011 that nothing else drives
+    pub const unsafe fn new(base: usize) -> Self {
+        Self { base }
+    }
+
+    fn read(&self, offset: usize) -> u32 {
+        // SAFETY: offset is one of the PL011 registers within the device's MMIO window
+        unsafe { core::ptr::read_volatile((self.base + offset) as *const u32) }
+    }
+
+    fn write(&mut self, offset: usize, value: u32) {
+        // SAFETY: as in `read`
+        unsafe { core::ptr::write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    /// 8N1 with FIFOs enabled; firmware has already programmed the baud rate
+    pub fn init(&mut self) {
+        self.write(Self::CR, 0);
+        self.write(Self::LCRH, Self::LCRH_WLEN_8 | Self::LCRH_FEN);
+        self.write(Self::CR, Self::CR_UARTEN | Self::CR_TXE | Self::CR_RXE);
+    }
+
+    pub fn send(&mut self, byte: u8) {
+        while self.read(Self::FR) & Self::FR_TXFF != 0 {
+            core::hint::spin_loop();
+        }
+        self.write(Self::DR, byte as u32);
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl Write for Pl011 {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for byte in s.bytes() {
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
+
 pub fn init() {
     let mut serial = SERIAL1.lock();
     serial.init();
@@ -0,0 +1,135 @@
+//! Text Shaping Test
+//! Shapes Hebrew, Arabic, Latin and mixed strings with a small TrueType font and checks the
+//! visual glyph order, mark attachment, ligatures and mirroring of the resulting glyph runs
+
+use alloc::vec::Vec;
+use crate::font_test::{assemble_font, simple_glyph, unicode_cmap};
+use crate::graphics::font::Font;
+use crate::graphics::shaping::{shape, Direction, GlyphRun};
+use crate::serial::_print;
+
+const SIZE: u32 = 20;
+
+/// Letters are 500-unit boxes, ﬁ and lam-alef wider ones, and the marks 100-unit bars
+/// hanging left of their zero advance
+fn build_font() -> Result<Font, &'static str> {
+    let bar = |top: i16| simple_glyph(&[(0, 0, true), (0, top, true), (400, top, true), (400, 0, true)]);
+    let glyphs = [
+        Vec::new(),
+        simple_glyph(&[(50, 0, true), (50, 500, true), (450, 500, true), (450, 0, true)]),
+        Vec::new(),
+        simple_glyph(&[(-300, 550, true), (-300, 700, true), (-200, 700, true), (-200, 550, true)]),
+        bar(700),
+        simple_glyph(&[(50, 0, true), (50, 500, true), (750, 500, true), (750, 0, true)]),
+        bar(600),
+    ];
+    let mut mapping = Vec::new();
+    for ch in ['a', 'b', 'e', 'f', 'i', '1', '2', '(', ')', 'א', 'ב', 'ג', 'ا', 'ب', 'ل'] {
+        mapping.push((ch, 1));
+    }
+    mapping.extend([(' ', 2), ('\u{301}', 3), ('\u{5B8}', 3), ('l', 4), ('á', 4), ('\u{FB01}', 5), ('\u{FEFB}', 6)]);
+    let cmap = unicode_cmap(&mapping);
+    Font::from_bytes(assemble_font(&glyphs, &[500, 500, 250, 0, 500, 800, 600], &cmap, &[])).map_err(|_| "Test font not loaded")
+}
+
+fn characters(run: &GlyphRun) -> Vec<char> {
+    run.glyphs.iter().map(|glyph| glyph.ch).collect()
+}
+
+pub fn run_shaping_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Shaping Test] Starting text shaping tests...\n"));
+    let font = build_font()?;
+
+    // Test 1: Right-to-left text is laid out in reverse of its logical order
+    _print(format_args!("[Shaping Test] Test 1: Right-to-left reordering...\n"));
+    let run = shape(&font, "אבג", SIZE, None);
+    if characters(&run) != ['ג', 'ב', 'א'] || run.direction != Direction::Rtl || run.width != 30.0 {
+        return Err("Hebrew not reordered to visual order");
+    }
+    let positions: Vec<(f32, usize)> = run.glyphs.iter().map(|glyph| (glyph.x, glyph.cluster)).collect();
+    if positions != [(0.0, 4), (10.0, 2), (20.0, 0)] {
+        return Err("Reordered glyphs placed wrong");
+    }
+    if characters(&shape(&font, "אבג", SIZE, Some(Direction::Ltr))) != ['ג', 'ב', 'א'] {
+        return Err("Hebrew not reversed in a left-to-right paragraph");
+    }
+    _print(format_args!("[Shaping Test] ✓ Hebrew laid out right to left\n"));
+
+    // Test 2: Combining marks attach to their base
+    _print(format_args!("[Shaping Test] Test 2: Combining marks...\n"));
+    let run = shape(&font, "e\u{301}", SIZE, None);
+    // The mark's bar, centered on -250, moves 500 units right onto the base centered on 250
+    let placed: Vec<(char, f32, f32, usize)> = run.glyphs.iter().map(|g| (g.ch, g.x, g.y, g.cluster)).collect();
+    if placed != [('e', 0.0, 0.0, 0), ('\u{301}', 10.0, 0.0, 0)] || run.width != 10.0 {
+        return Err("Accent not attached to its base");
+    }
+    // Over a taller base the mark is lifted clear of it: 700 + 50 - 550 units
+    let run = shape(&font, "l\u{301}", SIZE, None);
+    if run.glyphs.get(1).map(|mark| (mark.x, mark.y)) != Some((9.0, 4.0)) {
+        return Err("Accent not lifted over a tall base");
+    }
+    if characters(&shape(&font, "a\u{301}", SIZE, None)) != ['á'] {
+        return Err("Accent not composed with its base");
+    }
+    // A Hebrew point stays with its letter through reordering
+    let run = shape(&font, "אב\u{5B8}", SIZE, None);
+    let placed: Vec<(char, f32, usize)> = run.glyphs.iter().map(|g| (g.ch, g.x, g.cluster)).collect();
+    if placed != [('ב', 0.0, 2), ('\u{5B8}', 10.0, 2), ('א', 10.0, 0)] {
+        return Err("Mark separated from its base by reordering");
+    }
+    _print(format_args!("[Shaping Test] ✓ Marks attached, lifted and composed\n"));
+
+    // Test 3: Mixed-direction text follows the bidirectional algorithm
+    _print(format_args!("[Shaping Test] Test 3: Mixed directions...\n"));
+    // Numbers after Hebrew belong to the Hebrew but keep their own order
+    if characters(&shape(&font, "ab אבג 12", SIZE, None)) != ['a', 'b', ' ', '1', '2', ' ', 'ג', 'ב', 'א'] {
+        return Err("Hebrew and numbers in Latin text ordered wrong");
+    }
+    let run = shape(&font, "אב ab", SIZE, None);
+    if characters(&run) != ['a', 'b', ' ', 'ב', 'א'] || run.direction != Direction::Rtl {
+        return Err("Latin in Hebrew text ordered wrong");
+    }
+    if characters(&shape(&font, "ب12", SIZE, None)) != ['1', '2', 'ب'] {
+        return Err("Numbers after Arabic ordered wrong");
+    }
+    // Parentheses are mirrored so they still enclose the text
+    if characters(&shape(&font, "(אב)", SIZE, None)) != ['(', 'ב', 'א', ')'] {
+        return Err("Parentheses in right-to-left text not mirrored");
+    }
+    _print(format_args!("[Shaping Test] ✓ Mixed Latin, Hebrew, Arabic and numbers in visual order\n"));
+
+    // Test 4: Ligatures replace their letters when the font has them
+    _print(format_args!("[Shaping Test] Test 4: Ligatures...\n"));
+    let run = shape(&font, "fi", SIZE, None);
+    if characters(&run) != ['\u{FB01}'] || run.width != 16.0 || font.text_width("fi", SIZE) != 16.0 {
+        return Err("fi ligature not formed");
+    }
+    // Without an ffi glyph the fi ligature still applies to the last two letters
+    if characters(&shape(&font, "ffi", SIZE, None)) != ['f', '\u{FB01}'] {
+        return Err("Available ligature not used");
+    }
+    if characters(&shape(&font, "f\u{301}i", SIZE, None)) != ['f', '\u{301}', 'i'] {
+        return Err("Ligature formed across a mark");
+    }
+    if characters(&shape(&font, "\u{644}\u{627}", SIZE, None)) != ['\u{FEFB}'] {
+        return Err("Lam-alef ligature not formed");
+    }
+    _print(format_args!("[Shaping Test] ✓ Latin and Arabic ligatures formed\n"));
+
+    _print(format_args!("[Shaping Test] ✓ All text shaping tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for text shaping
+pub fn test_shaping() {
+    _print(format_args!("[Shaping Test] ===========================================\n"));
+    _print(format_args!("[Shaping Test]             TEXT SHAPING TESTS\n"));
+    _print(format_args!("[Shaping Test] ===========================================\n"));
+
+    match run_shaping_tests() {
+        Ok(_) => _print(format_args!("[Shaping Test] ✓ All text shaping tests PASSED\n")),
+        Err(e) => _print(format_args!("[Shaping Test] ✗ Text shaping tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Shaping Test] ===========================================\n"));
+}
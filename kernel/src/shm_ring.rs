@@ -0,0 +1,239 @@
+//! Shared-memory rings
+//!
+//! A ring carries fixed-size records from one producer to one consumer through a named
+//! shared region that both map, each into its own address space. The region starts with a
+//! header holding the record size and capacity, the count of records ever pushed (the head)
+//! and popped (the tail), and a futex word the consumer sets before waiting on an empty
+//! ring; the record slots follow it. Only the producer writes the head and only the
+//! consumer the tail, so neither takes a lock: a record is written before the head counting
+//! it is published, and read after that head is seen. A push that finds the consumer
+//! waiting clears the word and wakes it through the futex, keyed by the memory it lies in
+//! so that the two address spaces meet on it.
+
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use x86_64::VirtAddr;
+use crate::futex::{self, FutexError};
+use crate::vmm::{self, VmError, VmPermissions};
+
+/// First word of every ring header, "RRNG"
+pub const RING_MAGIC: u32 = 0x474e_5252;
+/// Bytes of the header before the first record. The head, tail and futex word each get a
+/// cache line of their own, so the two sides do not contend on one
+pub const HEADER_SIZE: usize = 256;
+/// Largest ring, header and records, that may be created
+pub const MAX_RING_SIZE: usize = 16 * 1024 * 1024;
+
+/// Offsets of the header fields
+const MAGIC: usize = 0;
+const RECORD_SIZE: usize = 4;
+const CAPACITY: usize = 8;
+const HEAD: usize = 64;
+const TAIL: usize = 128;
+const WAITING: usize = 192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RingError {
+    InvalidArgument,
+    AlreadyExists,
+    NotFound,
+    OutOfMemory,
+    /// The mapping holds no ring header
+    NotARing,
+    /// No slot is free for a push
+    Full,
+    /// No record is there to pop
+    Empty,
+    /// The futex word could not be reached
+    Fault,
+}
+
+pub type RingResult<T> = Result<T, RingError>;
+
+impl From<VmError> for RingError {
+    fn from(error: VmError) -> Self {
+        match error {
+            VmError::NotFound => RingError::NotFound,
+            VmError::AddressInUse => RingError::AlreadyExists,
+            VmError::OutOfMemory => RingError::OutOfMemory,
+            _ => RingError::InvalidArgument,
+        }
+    }
+}
+
+/// Create ring `name` of `capacity` records of `record_size` bytes, in a shared region
+/// mapped with `permissions`
+pub fn create(name: &str, record_size: usize, capacity: usize, permissions: VmPermissions) -> RingResult<()> {
+    let size = record_size.checked_mul(capacity)
+        .and_then(|records| records.checked_add(HEADER_SIZE))
+        .filter(|&size| record_size > 0 && capacity > 0 && size <= MAX_RING_SIZE)
+        .ok_or(RingError::InvalidArgument)?;
+    let (Ok(record_size), Ok(capacity)) = (u32::try_from(record_size), u32::try_from(capacity)) else {
+        return Err(RingError::InvalidArgument);
+    };
+    vmm::create_shared_memory(name.into(), size as u64, permissions)?;
+    let Some(frame) = vmm::shared_memory_frame(name, 0) else {
+        let _ = vmm::destroy_shared_memory(name);
+        return Err(RingError::NotFound);
+    };
+    let header = crate::memory::phys_to_virt(frame.start_address()).as_mut_ptr::<u8>();
+    // SAFETY: Safe because:
+    // 1. The header lies in the region's first frame, reached through the physical memory
+    //    offset, and the fields are aligned to their size
+    // 2. The region was just created, so nothing maps it yet and the zeroed head, tail and
+    //    futex word already read as an empty ring nobody waits on
+    unsafe {
+        header.add(RECORD_SIZE).cast::<u32>().write_volatile(record_size);
+        header.add(CAPACITY).cast::<u32>().write_volatile(capacity);
+        header.add(MAGIC).cast::<u32>().write_volatile(RING_MAGIC);
+    }
+    Ok(())
+}
+
+/// Map ring `name` into address space `as_id`, returning where it went
+pub fn map(as_id: u64, name: &str) -> RingResult<VirtAddr> {
+    Ok(vmm::map_shared_memory(as_id, name, None)?)
+}
+
+/// Remove ring `name`; it lives on in the address spaces that map it until they unmap it
+pub fn destroy(name: &str) -> RingResult<()> {
+    Ok(vmm::destroy_shared_memory(name)?)
+}
+
+/// One side's view of a ring mapped into the active address space
+#[derive(Debug, Clone, Copy)]
+pub struct SpscRing {
+    base: u64,
+    record_size: usize,
+    capacity: u64,
+}
+
+impl SpscRing {
+    /// The ring mapped at `base`
+    ///
+    /// # Safety
+    /// `base` must be where a ring is mapped in the active address space, and the ring must
+    /// stay mapped, and that address space active, for as long as the view is used. At most
+    /// one view pushes and one pops at a time
+    pub unsafe fn attach(base: VirtAddr) -> RingResult<Self> {
+        // The caller vouches for the mapping, and the header fields are aligned words
+        let read = |offset: usize| (base.as_u64() as usize + offset) as *const u32;
+        if read(MAGIC).read_volatile() != RING_MAGIC {
+            return Err(RingError::NotARing);
+        }
+        let record_size = read(RECORD_SIZE).read_volatile() as usize;
+        let capacity = read(CAPACITY).read_volatile() as u64;
+        if record_size == 0 || capacity == 0 {
+            return Err(RingError::NotARing);
+        }
+        Ok(Self { base: base.as_u64(), record_size, capacity })
+    }
+
+    pub fn record_size(&self) -> usize {
+        self.record_size
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity as usize
+    }
+
+    fn head(&self) -> &AtomicU64 {
+        // SAFETY: `attach` requires the ring to stay mapped, and the head is aligned
+        unsafe { &*((self.base as usize + HEAD) as *const AtomicU64) }
+    }
+
+    fn tail(&self) -> &AtomicU64 {
+        // SAFETY: As for the head
+        unsafe { &*((self.base as usize + TAIL) as *const AtomicU64) }
+    }
+
+    fn waiting(&self) -> &AtomicU32 {
+        // SAFETY: As for the head
+        unsafe { &*((self.base as usize + WAITING) as *const AtomicU32) }
+    }
+
+    fn slot(&self, index: u64) -> *mut u8 {
+        let offset = HEADER_SIZE + (index % self.capacity) as usize * self.record_size;
+        (self.base as usize + offset) as *mut u8
+    }
+
+    /// Records pushed and not yet popped
+    pub fn len(&self) -> usize {
+        self.head().load(Ordering::Acquire).wrapping_sub(self.tail().load(Ordering::Acquire)) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add `record`, exactly a record long, behind the others, waking the consumer if it is
+    /// waiting. Producer side only
+    pub fn push(&self, record: &[u8]) -> RingResult<()> {
+        if record.len() != self.record_size {
+            return Err(RingError::InvalidArgument);
+        }
+        let head = self.head().load(Ordering::Relaxed);
+        if head.wrapping_sub(self.tail().load(Ordering::Acquire)) >= self.capacity {
+            return Err(RingError::Full);
+        }
+        // SAFETY: The slot lies in the mapping, and the consumer does not read it until the
+        // head below counts it
+        unsafe { core::ptr::copy_nonoverlapping(record.as_ptr(), self.slot(head), self.record_size) };
+        // Sequentially consistent with the consumer flagging itself waiting, so that either it
+        // sees this record or this push sees it waiting
+        self.head().store(head.wrapping_add(1), Ordering::SeqCst);
+        if self.waiting().swap(0, Ordering::SeqCst) != 0 {
+            futex::wake(self.waiting_address(), 1).map_err(|_| RingError::Fault)?;
+        }
+        Ok(())
+    }
+
+    /// Take the oldest record into `record`, exactly a record long. Consumer side only
+    pub fn pop(&self, record: &mut [u8]) -> RingResult<()> {
+        if record.len() != self.record_size {
+            return Err(RingError::InvalidArgument);
+        }
+        let tail = self.tail().load(Ordering::Relaxed);
+        if self.head().load(Ordering::Acquire) == tail {
+            return Err(RingError::Empty);
+        }
+        // SAFETY: The slot lies in the mapping, and the producer does not reuse it until the
+        // tail below frees it
+        unsafe { core::ptr::copy_nonoverlapping(self.slot(tail), record.as_mut_ptr(), self.record_size) };
+        self.tail().store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    fn waiting_address(&self) -> u64 {
+        self.base + WAITING as u64
+    }
+
+    /// Queue consumer thread `pid` to be woken by the next push, returning false instead
+    /// when there are records to pop
+    pub fn request_wait(&self, pid: u64) -> RingResult<bool> {
+        self.waiting().store(1, Ordering::SeqCst);
+        if self.head().load(Ordering::SeqCst) != self.tail().load(Ordering::Relaxed) {
+            self.waiting().store(0, Ordering::Relaxed);
+            return Ok(false);
+        }
+        match futex::request_wait(self.waiting_address(), pid, 1) {
+            Ok(()) => Ok(true),
+            // A push came in between and cleared the word
+            Err(FutexError::WouldBlock) => Ok(false),
+            Err(_) => Err(RingError::Fault),
+        }
+    }
+
+    /// Take the oldest record into `record`, waiting for one while the ring is empty.
+    /// Consumer side only
+    pub fn pop_wait(&self, record: &mut [u8]) -> RingResult<()> {
+        loop {
+            match self.pop(record) {
+                Err(RingError::Empty) => {}
+                result => return result,
+            }
+            if self.request_wait(crate::process::get_current_process_id())? {
+                futex::block_while_queued();
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! Shared-Memory Ring Test
+//! Checks that a ring mapped into two address spaces at different addresses carries records
+//! from a producer in one to a consumer in the other in order, with none lost or torn across
+//! many wraps, that a push to a full ring fails, and that a consumer finding the ring empty
+//! waits on its futex until the producer's next push wakes it
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use x86_64::VirtAddr;
+use crate::futex;
+use crate::process::{self, Process};
+use crate::serial::_print;
+use crate::test_threads::parked_thread;
+use crate::shm_ring::{self, RingError, SpscRing};
+use crate::vmm::{self, VmPermissions};
+
+const ENTRY: u64 = 0x40_0000;
+const RING: &str = "shm-ring-test";
+/// Records odd-sized, and enough of them to span several pages
+const RECORD_SIZE: usize = 40;
+const CAPACITY: usize = 200;
+const RECORDS: u64 = 5000;
+
+/// A user process, scheduled already blocked so it never runs
+fn blocked_process(name: &str) -> Result<u64, &'static str> {
+    let process = Process::user_process(String::from(name), VirtAddr::new(ENTRY)).map_err(|_| "Failed to create process")?;
+    let mut scheduler = process::get_smp_scheduler().lock();
+    let pid = scheduler.add_process(process);
+    scheduler.block_process(pid);
+    Ok(pid)
+}
+
+fn address_space_of(pid: u64) -> Result<u64, &'static str> {
+    process::with_process(pid, |process| process.address_space_id)
+        .flatten()
+        .ok_or("Process has no address space")
+}
+
+/// Run `f` in the address space of process `pid`
+fn in_space_of<T>(pid: u64, f: impl FnOnce() -> T) -> Result<T, &'static str> {
+    let address_space_id = address_space_of(pid)?;
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let previous = vmm::current_address_space().ok_or("No address space active")?;
+        vmm::switch_address_space(address_space_id).map_err(|_| "Failed to switch address space")?;
+        let result = f();
+        vmm::switch_address_space(previous).map_err(|_| "Failed to switch back")?;
+        Ok(result)
+    })
+}
+
+/// Record `sequence`: its number, then every byte derived from it, so a torn record shows
+fn record(sequence: u64) -> [u8; RECORD_SIZE] {
+    let mut record = [0; RECORD_SIZE];
+    record[..8].copy_from_slice(&sequence.to_ne_bytes());
+    for (i, byte) in record[8..].iter_mut().enumerate() {
+        *byte = sequence.wrapping_mul(31).wrapping_add(i as u64) as u8;
+    }
+    record
+}
+
+fn stream(pids: &mut Vec<u64>) -> Result<(), &'static str> {
+    // Test 1: Both sides see one ring at different addresses
+    _print(format_args!("[Shm Ring Test] Test 1: Mapping into two address spaces...\n"));
+    // Mapped without USER so the test can touch it
+    shm_ring::create(RING, RECORD_SIZE, CAPACITY, VmPermissions::READ | VmPermissions::WRITE)
+        .map_err(|_| "Failed to create ring")?;
+    if shm_ring::create(RING, RECORD_SIZE, CAPACITY, VmPermissions::READ | VmPermissions::WRITE) != Err(RingError::AlreadyExists) {
+        return Err("Ring created twice under one name");
+    }
+    let producer = blocked_process("shm-ring-producer")?;
+    pids.push(producer);
+    let consumer = blocked_process("shm-ring-consumer")?;
+    pids.push(consumer);
+    // Something mapped first in the consumer puts the ring at another address there
+    vmm::map_memory(address_space_of(consumer)?, None, 4096, VmPermissions::READ, false, vmm::MappingSource::Anonymous { shared: false })
+        .map_err(|_| "Failed to map memory")?;
+    let push_base = shm_ring::map(address_space_of(producer)?, RING).map_err(|_| "Failed to map ring into the producer")?;
+    let pop_base = shm_ring::map(address_space_of(consumer)?, RING).map_err(|_| "Failed to map ring into the consumer")?;
+    shm_ring::destroy(RING).map_err(|_| "Failed to destroy ring")?;
+    if push_base == pop_base {
+        return Err("Ring mapped at one address in both");
+    }
+    // SAFETY: Each view is only used inside `in_space_of` for the process it was mapped into,
+    // which keeps the ring mapped until it is terminated
+    let attach = |base| unsafe { SpscRing::attach(base) };
+    let pushing = in_space_of(producer, || attach(push_base))?.map_err(|_| "Producer found no ring")?;
+    let popping = in_space_of(consumer, || attach(pop_base))?.map_err(|_| "Consumer found no ring")?;
+    if (popping.record_size(), popping.capacity()) != (RECORD_SIZE, CAPACITY) {
+        return Err("Ring header not as created");
+    }
+    let shared = in_space_of(producer, || pushing.push(&record(0)))?.is_ok()
+        && in_space_of(consumer, || popping.len())? == 1;
+    if !shared {
+        return Err("Push not seen from the other address space");
+    }
+    let mut popped = [0; RECORD_SIZE];
+    if in_space_of(consumer, || popping.pop(&mut popped))? != Ok(()) || popped != record(0) {
+        return Err("Record changed on the way through");
+    }
+    _print(format_args!("[Shm Ring Test] ✓ Ring at {:#x} in the producer and {:#x} in the consumer\n", push_base.as_u64(), pop_base.as_u64()));
+
+    // Test 2: Records stream through many wraps with none lost or torn
+    _print(format_args!("[Shm Ring Test] Test 2: Streaming records...\n"));
+    let (mut next_push, mut next_pop) = (1, 1);
+    // Uneven batches on each side, so the head and tail pass the end at different places
+    let mut round = 0;
+    while next_pop < RECORDS {
+        round += 1;
+        let batch = (round * 37) % (CAPACITY as u64 + 20);
+        in_space_of(producer, || {
+            while next_push < RECORDS && next_push < next_pop + batch {
+                match pushing.push(&record(next_push)) {
+                    Ok(()) => next_push += 1,
+                    Err(_) => break,
+                }
+            }
+        })?;
+        let batch = (round * 53) % (CAPACITY as u64 + 20);
+        let taken = in_space_of(consumer, || {
+            let mut taken = Vec::new();
+            while (taken.len() as u64) < batch {
+                let mut record = [0; RECORD_SIZE];
+                match popping.pop(&mut record) {
+                    Ok(()) => taken.push(record),
+                    Err(_) => break,
+                }
+            }
+            taken
+        })?;
+        for taken in taken {
+            if taken != record(next_pop) {
+                return Err("Record lost, reordered or torn");
+            }
+            next_pop += 1;
+        }
+    }
+    if !in_space_of(consumer, || popping.is_empty())? {
+        return Err("Records left over after the stream");
+    }
+    _print(format_args!("[Shm Ring Test] ✓ {} records streamed intact in {} rounds\n", RECORDS, round));
+
+    // Test 3: A full ring refuses pushes, and a waiting consumer wakes on the next push
+    _print(format_args!("[Shm Ring Test] Test 3: Blocking and waking...\n"));
+    let filled = in_space_of(producer, || {
+        let pushed = (0..CAPACITY as u64).all(|sequence| pushing.push(&record(sequence)).is_ok());
+        (pushed, pushing.push(&record(0)))
+    })?;
+    if filled != (true, Err(RingError::Full)) {
+        return Err("Full ring not refusing pushes");
+    }
+    let waiter = process::spawn_kernel_thread("shm-ring-test", parked_thread).map_err(|_| "Failed to spawn a thread")?;
+    pids.push(waiter);
+    // A consumer with records to pop does not wait
+    if in_space_of(consumer, || popping.request_wait(waiter))? != Ok(false) || futex::is_waiting(waiter) {
+        return Err("Consumer waited on a ring with records");
+    }
+    let drained = in_space_of(consumer, || {
+        let mut record = [0; RECORD_SIZE];
+        let mut count = 0;
+        while popping.pop(&mut record).is_ok() {
+            count += 1;
+        }
+        (count, popping.pop(&mut record), popping.request_wait(waiter))
+    })?;
+    if drained != (CAPACITY, Err(RingError::Empty), Ok(true)) || !futex::is_waiting(waiter) {
+        return Err("Consumer not waiting on the emptied ring");
+    }
+    in_space_of(producer, || pushing.push(&record(RECORDS)))?.map_err(|_| "Failed to push")?;
+    if futex::is_waiting(waiter) {
+        return Err("Push did not wake the waiting consumer");
+    }
+    let woken = in_space_of(consumer, || popping.pop(&mut popped))?;
+    if woken != Ok(()) || popped != record(RECORDS) {
+        return Err("Woken consumer did not find the record");
+    }
+    // With nobody waiting, a push leaves the futex alone
+    in_space_of(producer, || pushing.push(&record(0)))?.map_err(|_| "Failed to push")?;
+    if futex::is_waiting(waiter) {
+        return Err("Waiter queued again without waiting");
+    }
+    _print(format_args!("[Shm Ring Test] ✓ Consumer {} woke when the emptied ring refilled\n", waiter));
+    Ok(())
+}
+
+pub fn run_shm_ring_tests() -> Result<(), &'static str> {
+    let mut pids = Vec::new();
+    let result = stream(&mut pids);
+    for pid in pids.into_iter().rev() {
+        process::terminate_process(pid);
+    }
+    let _ = shm_ring::destroy(RING);
+    result?;
+
+    _print(format_args!("[Shm Ring Test] ✓ All shared-memory ring tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for shared-memory rings
+pub fn test_shm_ring() {
+    _print(format_args!("[Shm Ring Test] ===========================================\n"));
+    _print(format_args!("[Shm Ring Test]          SHARED-MEMORY RING TESTS\n"));
+    _print(format_args!("[Shm Ring Test] ===========================================\n"));
+
+    match run_shm_ring_tests() {
+        Ok(_) => _print(format_args!("[Shm Ring Test] ✓ All shared-memory ring tests PASSED\n")),
+        Err(e) => _print(format_args!("[Shm Ring Test] ✗ Shared-memory ring tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Shm Ring Test] ===========================================\n"));
+}
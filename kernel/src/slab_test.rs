@@ -0,0 +1,143 @@
+//! Slab Allocator Test
+//! Allocates and frees ten thousand objects from a slab cache over several rounds and checks
+//! the cache reuses its slabs instead of taking more, that freed slots are handed out again,
+//! and that the process table and memory areas draw from caches that stay the same size as
+//! mappings come and go
+
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use core::ptr::NonNull;
+use crate::heap::slab::{slab_stats, SlabBox, SlabCache};
+use crate::serial::_print;
+use crate::vmm::{self, MappingSource, VmPermissions};
+
+/// An object of an awkward size, rounded up to a slot of 128 bytes
+struct TestObject {
+    id: u64,
+    payload: [u64; 11],
+}
+
+static TEST_CACHE: SlabCache<TestObject> = SlabCache::new("slab_test");
+
+const OBJECTS: usize = 10_000;
+
+/// Allocate `OBJECTS` objects at once, check each kept its contents, then free them all
+fn allocate_round() -> Result<(), &'static str> {
+    let mut objects = Vec::with_capacity(OBJECTS);
+    for id in 0..OBJECTS as u64 {
+        objects.push(SlabBox::new(&TEST_CACHE, TestObject { id, payload: [id; 11] }));
+    }
+    if TEST_CACHE.stats().objects_in_use != OBJECTS {
+        return Err("Objects in use miscounted");
+    }
+    if !objects.iter().enumerate().all(|(index, object)| object.id == index as u64 && object.payload[10] == index as u64) {
+        return Err("Object overwritten by another");
+    }
+    Ok(())
+}
+
+pub fn run_slab_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Slab Test] Starting slab allocator tests...\n"));
+
+    // Test 1: Ten thousand objects allocated and freed over and over reuse the first slabs
+    _print(format_args!("[Slab Test] Test 1: Reuse across {} objects...\n", OBJECTS));
+    allocate_round()?;
+    let first = TEST_CACHE.stats();
+    let slab_objects = SlabCache::<TestObject>::SLAB_OBJECTS;
+    if first.slot_size != 128 || first.capacity < OBJECTS || first.capacity >= OBJECTS + slab_objects {
+        return Err("Slabs not sized to the objects");
+    }
+    for _ in 0..4 {
+        allocate_round()?;
+    }
+    let last = TEST_CACHE.stats();
+    if last.total_slabs != first.total_slabs || last.objects_in_use != 0 {
+        return Err("Cache grew instead of reusing its slabs");
+    }
+    _print(format_args!("[Slab Test] ✓ {} slabs reused over 5 rounds\n", last.total_slabs));
+
+    // Test 2: A freed slot is handed out again, and half the objects freed make room for as many
+    _print(format_args!("[Slab Test] Test 2: Freed slots reused...\n"));
+    let slot = TEST_CACHE.alloc().ok_or("Slot not allocated")?;
+    // SAFETY: The slot came from this cache and was never written
+    unsafe { TEST_CACHE.free(slot) };
+    let again = TEST_CACHE.alloc().ok_or("Slot not allocated")?;
+    // SAFETY: As above
+    unsafe { TEST_CACHE.free(again) };
+    if again != slot {
+        return Err("Freed slot not handed out next");
+    }
+    let mut objects: Vec<Option<SlabBox<TestObject>>> = (0..OBJECTS as u64)
+        .map(|id| Some(SlabBox::new(&TEST_CACHE, TestObject { id, payload: [0; 11] })))
+        .collect();
+    let freed: BTreeSet<NonNull<TestObject>> = objects.iter_mut().step_by(2).filter_map(|object| {
+        let object = object.take()?;
+        let address = NonNull::from(&*object);
+        drop(object);
+        Some(address)
+    }).collect();
+    let refilled: Vec<SlabBox<TestObject>> = (0..freed.len() as u64)
+        .map(|id| SlabBox::new(&TEST_CACHE, TestObject { id, payload: [0; 11] }))
+        .collect();
+    let reused = refilled.iter().all(|object| freed.contains(&NonNull::from(&**object)));
+    let stats = TEST_CACHE.stats();
+    drop(refilled);
+    drop(objects);
+    if !reused || stats.total_slabs != last.total_slabs {
+        return Err("Freed slots not reused");
+    }
+    _print(format_args!("[Slab Test] ✓ Freed slots handed out again\n"));
+
+    // Test 3: Mappings made, split and removed over and over keep the memory area cache the
+    // same size, and every cache in use is reported
+    _print(format_args!("[Slab Test] Test 3: Memory area cache...\n"));
+    let address_space_id = vmm::create_address_space().map_err(|_| "Failed to create address space")?;
+    let permissions = VmPermissions::READ | VmPermissions::WRITE;
+    let area_slabs = || slab_stats().into_iter().find(|stats| stats.name == "vm_area").map(|stats| stats.total_slabs);
+    let mut first_slabs = None;
+    let mut cycled = Ok(());
+    for _ in 0..1000 {
+        // Unmapping the second page splits the mapping in two
+        cycled = vmm::map_memory(address_space_id, None, 4 * 4096, permissions, false, MappingSource::Anonymous { shared: false })
+            .and_then(|start| {
+                vmm::unmap_memory(address_space_id, start + 4096u64, 4096)?;
+                vmm::unmap_memory(address_space_id, start, 4 * 4096)
+            });
+        if cycled.is_err() {
+            break;
+        }
+        first_slabs = first_slabs.or_else(area_slabs);
+    }
+    let _ = vmm::destroy_address_space(address_space_id);
+    if cycled.is_err() {
+        return Err("Failed to map and unmap memory");
+    }
+    if first_slabs.is_none() || area_slabs() != first_slabs {
+        return Err("Memory area cache grew across mappings");
+    }
+    let stats = slab_stats();
+    let reported = ["process", "vm_area", "slab_test"]
+        .iter()
+        .all(|name| stats.iter().any(|stats| stats.name == *name));
+    if !reported || stats.iter().any(|stats| !(0.0..=1.0).contains(&stats.fragmentation)) {
+        return Err("Cache statistics not reported");
+    }
+    _print(format_args!("[Slab Test] ✓ Memory area cache held at {} slabs\n", first_slabs.unwrap_or(0)));
+
+    _print(format_args!("[Slab Test] ✓ All slab allocator tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for the slab allocator
+pub fn test_slab() {
+    _print(format_args!("[Slab Test] ===========================================\n"));
+    _print(format_args!("[Slab Test]          SLAB ALLOCATOR TESTS\n"));
+    _print(format_args!("[Slab Test] ===========================================\n"));
+
+    match run_slab_tests() {
+        Ok(_) => _print(format_args!("[Slab Test] ✓ All slab allocator tests PASSED\n")),
+        Err(e) => _print(format_args!("[Slab Test] ✗ Slab allocator tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Slab Test] ===========================================\n"));
+}
@@ -0,0 +1,81 @@
+//! Sleep Test
+//! Checks that `nanosleep` sleeps at least as long as asked on the monotonic clock, down to
+//! sleeps under a millisecond, that a zero sleep returns at once, and that the sleep queue
+//! wakes sleepers as they fall due and lets a signal take one out early with time left
+
+use crate::process::{self, SleepQueue};
+use crate::serial::_print;
+use crate::time::get_precise_time_ns;
+
+/// How far past its deadline a sleep may end
+const OVERSLEEP_TOLERANCE_NS: u64 = 20_000_000;
+
+/// Sleep for `nanoseconds`, returning the monotonic time that passed
+fn timed_sleep(nanoseconds: u64) -> Result<u64, &'static str> {
+    let start = get_precise_time_ns();
+    if process::nanosleep(nanoseconds) != 0 {
+        return Err("Sleep ended early");
+    }
+    Ok(get_precise_time_ns() - start)
+}
+
+pub fn run_sleep_tests() -> Result<(), &'static str> {
+    // Test 1: A 5ms sleep lasts at least 5ms of monotonic time
+    _print(format_args!("[Sleep Test] Test 1: 5ms sleep...\n"));
+    let elapsed = timed_sleep(5_000_000)?;
+    if elapsed < 5_000_000 {
+        return Err("5ms sleep ended before 5ms passed");
+    }
+    if elapsed > 5_000_000 + OVERSLEEP_TOLERANCE_NS {
+        return Err("5ms sleep overslept");
+    }
+    _print(format_args!("[Sleep Test] ✓ Slept {} ns\n", elapsed));
+
+    // Test 2: Sleeps under a millisecond keep their length, and a zero sleep only yields
+    _print(format_args!("[Sleep Test] Test 2: Sub-millisecond and zero sleeps...\n"));
+    let elapsed = timed_sleep(200_000)?;
+    if elapsed < 200_000 {
+        return Err("200us sleep ended before 200us passed");
+    }
+    let start = get_precise_time_ns();
+    if process::nanosleep(0) != 0 || get_precise_time_ns() - start >= 1_000_000 {
+        return Err("Zero sleep did not return at once");
+    }
+    _print(format_args!("[Sleep Test] ✓ 200us sleep took {} ns\n", elapsed));
+
+    // Test 3: Sleepers wake as they fall due, and a signal takes one out early
+    _print(format_args!("[Sleep Test] Test 3: Sleep queue...\n"));
+    let mut queue = SleepQueue::new();
+    queue.sleep(1, 10_000);
+    queue.sleep(2, 4_000);
+    if queue.next_wake() != Some(4_000) || queue.take_due(3_999).is_some() {
+        return Err("Sleeper woken before its deadline");
+    }
+    if queue.take_due(4_000) != Some(2) || queue.next_wake() != Some(10_000) {
+        return Err("Due sleeper not woken");
+    }
+    // Sleeping again moves the deadline rather than adding a second entry
+    queue.sleep(1, 12_000);
+    let remaining = queue.cancel(1).map(|wake_ns| wake_ns - 6_000);
+    if remaining != Some(6_000) || queue.cancel(1).is_some() || !queue.is_empty() {
+        return Err("Interrupted sleeper not taken out with its time left");
+    }
+    _print(format_args!("[Sleep Test] ✓ Signalled sleeper had 6000 ns left\n"));
+
+    _print(format_args!("[Sleep Test] ✓ All sleep tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for nanosleep
+pub fn test_sleep() {
+    _print(format_args!("[Sleep Test] ===========================================\n"));
+    _print(format_args!("[Sleep Test]               SLEEP TESTS\n"));
+    _print(format_args!("[Sleep Test] ===========================================\n"));
+
+    match run_sleep_tests() {
+        Ok(_) => _print(format_args!("[Sleep Test] ✓ All sleep tests PASSED\n")),
+        Err(e) => _print(format_args!("[Sleep Test] ✗ Sleep tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Sleep Test] ===========================================\n"));
+}
@@ -0,0 +1,156 @@
+//! Symbolic Link Test
+//! Looks up paths through symbolic links on a mounted in-memory filesystem: absolute and
+//! relative targets, links to directories, dangling links, and loops, which fail with
+//! `TooManyLinks` once `MAX_SYMLINK_HOPS` links have been followed. `symlink_metadata`
+//! and `readlink` see the link itself, while everything else sees its target
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::filesystem::{self, FileSystemError, FileType, MemoryFileSystem, MAX_SYMLINK_HOPS, O_CREAT, O_RDONLY, O_WRONLY};
+use crate::serial::_print;
+
+const MOUNT: &str = "/mnt/symlink-test";
+
+fn path(name: &str) -> String {
+    format!("{}/{}", MOUNT, name)
+}
+
+/// Write `data` to `name`, creating it if needed
+fn write(name: &str, data: &[u8]) -> Result<(), &'static str> {
+    let fd = filesystem::open(&path(name), O_WRONLY | O_CREAT).map_err(|_| "Failed to open for writing")?;
+    let written = filesystem::write(fd, data);
+    let _ = filesystem::close(fd);
+    match written {
+        Ok(count) if count == data.len() => Ok(()),
+        _ => Err("Failed to write"),
+    }
+}
+
+/// Everything in `name`, read through a fresh read-only descriptor
+fn read(name: &str) -> Result<Vec<u8>, FileSystemError> {
+    let fd = filesystem::open(&path(name), O_RDONLY)?;
+    let mut contents = Vec::new();
+    let mut buffer = [0u8; 16];
+    let result = loop {
+        match filesystem::read(fd, &mut buffer) {
+            Ok(0) => break Ok(contents),
+            Ok(count) => contents.extend_from_slice(&buffer[..count]),
+            Err(e) => break Err(e),
+        }
+    };
+    let _ = filesystem::close(fd);
+    result
+}
+
+fn link(target: &str, name: &str) -> Result<(), &'static str> {
+    filesystem::symlink(target, &path(name)).map_err(|_| "Failed to create a link")
+}
+
+fn run_link_tests() -> Result<(), &'static str> {
+    filesystem::create_directory(&path("data")).map_err(|_| "Failed to create a directory")?;
+    filesystem::create_directory(&path("other")).map_err(|_| "Failed to create a directory")?;
+    write("data/file.txt", b"contents")?;
+
+    // Test 1: An absolute link reads as its target, but lstat and readlink see the link
+    _print(format_args!("[Symlink Test] Test 1: Absolute links...\n"));
+    let target = path("data/file.txt");
+    link(&target, "file-link")?;
+    link(&path("data"), "dir-link")?;
+    if read("file-link").ok().as_deref() != Some(b"contents") || read("dir-link/file.txt").ok().as_deref() != Some(b"contents") {
+        return Err("Link did not read as its target");
+    }
+    let followed = filesystem::metadata(&path("file-link")).map_err(|_| "No metadata through the link")?;
+    let own = filesystem::symlink_metadata(&path("file-link")).map_err(|_| "No metadata for the link")?;
+    if followed.file_type != FileType::Regular || followed.size != 8 || own.file_type != FileType::SymbolicLink || own.size != target.len() as u64 {
+        return Err("Link metadata wrong");
+    }
+    let listing = filesystem::list_directory(&path("dir-link")).map_err(|_| "Failed to list through the link")?;
+    if filesystem::readlink(&path("file-link")).ok() != Some(target) || listing != ["file.txt"] {
+        return Err("readlink or listing through the link wrong");
+    }
+    if !matches!(filesystem::readlink(&path("data/file.txt")), Err(FileSystemError::InvalidOperation)) {
+        return Err("readlink of a regular file succeeded");
+    }
+    _print(format_args!("[Symlink Test] ✓ file-link reads data/file.txt, dir-link lists {:?}\n", listing));
+
+    // Test 2: Relative targets start from the link's directory, and dangling links resolve
+    // to their missing target
+    _print(format_args!("[Symlink Test] Test 2: Relative and dangling links...\n"));
+    link("file.txt", "data/sibling")?;
+    link("../data/file.txt", "other/up")?;
+    link("../dir-link/sibling", "other/chain")?;
+    for name in ["data/sibling", "other/up", "other/chain", "other/../data/./sibling"] {
+        if read(name).ok().as_deref() != Some(b"contents") {
+            return Err("Relative link did not resolve from its directory");
+        }
+    }
+    if filesystem::readlink(&path("other/up")).ok().as_deref() != Some("../data/file.txt") {
+        return Err("readlink rewrote a relative target");
+    }
+    link("created.txt", "other/dangling")?;
+    if !matches!(filesystem::metadata(&path("other/dangling")), Err(FileSystemError::NotFound))
+        || !matches!(read("other/dangling"), Err(FileSystemError::NotFound))
+        || filesystem::symlink_metadata(&path("other/dangling")).is_err()
+    {
+        return Err("Dangling link not handled");
+    }
+    // Creating through a dangling link creates its target, and removing a link leaves it
+    write("other/dangling", b"made")?;
+    filesystem::remove(&path("other/dangling")).map_err(|_| "Failed to remove a link")?;
+    if read("other/created.txt").ok().as_deref() != Some(b"made") || filesystem::symlink_metadata(&path("other/dangling")).is_ok() {
+        return Err("Link removal or creation through a link wrong");
+    }
+    _print(format_args!("[Symlink Test] ✓ Relative links resolved, dangling link created its target\n"));
+
+    // Test 3: Loops fail with TooManyLinks, and so do chains longer than the limit
+    _print(format_args!("[Symlink Test] Test 3: Loops...\n"));
+    link("loop", "loop")?;
+    link("pong", "ping")?;
+    link("ping", "pong")?;
+    for name in ["loop", "ping", "loop/file.txt"] {
+        if !matches!(read(name), Err(FileSystemError::TooManyLinks)) || !matches!(filesystem::metadata(&path(name)), Err(FileSystemError::TooManyLinks)) {
+            return Err("Loop not detected");
+        }
+    }
+    if filesystem::readlink(&path("loop")).ok().as_deref() != Some("loop") {
+        return Err("Looping link unreadable");
+    }
+    // chain-N is N links from the file
+    link("data/file.txt", "chain-1")?;
+    for hop in 2..=MAX_SYMLINK_HOPS + 1 {
+        link(&format!("chain-{}", hop - 1), &format!("chain-{}", hop))?;
+    }
+    if read(&format!("chain-{}", MAX_SYMLINK_HOPS)).ok().as_deref() != Some(b"contents")
+        || !matches!(read(&format!("chain-{}", MAX_SYMLINK_HOPS + 1)), Err(FileSystemError::TooManyLinks))
+    {
+        return Err("Link limit not at MAX_SYMLINK_HOPS");
+    }
+    _print(format_args!("[Symlink Test] ✓ Loops refused, {} links followed\n", MAX_SYMLINK_HOPS));
+
+    _print(format_args!("[Symlink Test] ✓ All symbolic link tests completed successfully!\n"));
+    Ok(())
+}
+
+pub fn run_symlink_tests() -> Result<(), &'static str> {
+    let memory_fs = MemoryFileSystem::new(String::from("symlink-test"));
+    filesystem::mount_filesystem(Box::new(memory_fs), MOUNT).map_err(|_| "Failed to mount the test filesystem")?;
+    let result = run_link_tests();
+    let _ = filesystem::unmount_filesystem(MOUNT);
+    result
+}
+
+/// Main test runner for symbolic links
+pub fn test_symlink() {
+    _print(format_args!("[Symlink Test] ===========================================\n"));
+    _print(format_args!("[Symlink Test]          SYMBOLIC LINK TESTS\n"));
+    _print(format_args!("[Symlink Test] ===========================================\n"));
+
+    match run_symlink_tests() {
+        Ok(_) => _print(format_args!("[Symlink Test] ✓ All symbolic link tests PASSED\n")),
+        Err(e) => _print(format_args!("[Symlink Test] ✗ Symbolic link tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Symlink Test] ===========================================\n"));
+}
@@ -1,26 +1,8 @@
+/// Print the scheduler state and process table to the serial console, returning the
+/// number of processes
 fn sys_dump_process_list() -> SyscallResult {
-    // For now, print to serial; userspace can capture via serial log or later via IPC
-    let mut count = 0;
-    // Use public helper to iterate: add a minimal export in process.rs
-    match crate::process::for_each_process(|pid, name, state, prio| {
-        let state_s = match state {
-            crate::process::ProcessState::Ready => "Ready",
-            crate::process::ProcessState::Running => "Running",
-            crate::process::ProcessState::Blocked => "Blocked",
-            crate::process::ProcessState::Terminated => "Terminated",
-        };
-        let prio_s = match prio {
-            crate::process::Priority::High => "High",
-            crate::process::Priority::Normal => "Normal",
-            crate::process::Priority::Low => "Low",
-            crate::process::Priority::Gaming => "Gaming",
-        };
-        crate::serial_println!("[ps] pid={} name={} state={} prio={}", pid, name, state_s, prio_s);
-        count += 1;
-    }) {
-        Ok(()) => SyscallResult::success(count),
-        Err(_) => SyscallResult::error(SyscallError::ResourceBusy),
-    }
+    crate::process::dump_scheduler();
+    SyscallResult::success(crate::process::process_stats().len() as i64)
 }
 use alloc::vec::Vec;
 use alloc::vec;
@@ -41,10 +23,37 @@ pub enum SyscallNumber {
     GetPpid = 6,
     Sleep = 7,
     Yield = 8,
+    WaitPid = 9,
     // Threads (append-only, new IDs at end of table)
     ThreadCreate = 350,
     SetPriority = 351,
     DumpProcessList = 352,
+    NanoSleep = 353,
+    Clone = 354,
+    GetTid = 355,
+    ExitGroup = 356,
+    TgKill = 357,
+    SchedStats = 358,
+    Futex = 359,
+    SetRobustList = 360,
+    EventFd = 361,
+    SignalFd = 362,
+    Poll = 363,
+    Renice = 364,
+    TimerfdCreate = 365,
+    TimerfdSettime = 366,
+    TimerfdGettime = 367,
+    EpollCreate = 368,
+    EpollCtl = 369,
+    EpollWait = 370,
+    InotifyInit = 371,
+    InotifyAddWatch = 372,
+    InotifyRmWatch = 373,
+    RingCreate = 374,
+    RingMap = 375,
+    RingDestroy = 376,
+    PacketFilter = 377,
+    PacketCapture = 378,
     
     // File operations
     Open = 10,
@@ -115,6 +124,9 @@ pub enum SyscallNumber {
     AiQuery = 300,
     AiGenerate = 301,
     AiAnalyze = 302,
+
+    // Tracing
+    TraceProbe = 400,
 }
 
 #[derive(Debug)]
@@ -176,9 +188,36 @@ pub fn handle_syscall(
         6 => sys_getppid(),
         7 => sys_sleep(arg1),
         8 => sys_yield(),
+        9 => sys_waitpid(arg1, arg2),
         350 => sys_thread_create(arg1, arg2),
         351 => sys_set_priority(arg1),
         352 => sys_dump_process_list(),
+        353 => sys_nanosleep(arg1),
+        354 => sys_clone(arg1, arg2, arg3),
+        355 => sys_gettid(),
+        356 => sys_exit_group(arg1 as i32),
+        357 => sys_tgkill(arg1, arg2, arg3 as i32),
+        358 => sys_sched_stats(arg1, arg2),
+        359 => sys_futex(arg1, arg2 as u32, arg3),
+        360 => sys_set_robust_list(arg1, arg2),
+        361 => sys_eventfd(arg1, arg2 as u32),
+        362 => sys_signalfd(arg1),
+        363 => sys_poll(arg1, arg2, arg3 as i64),
+        364 => sys_setpriority(arg1, arg2 as i64),
+        365 => sys_timerfd_create(arg1 as u32, arg2 as u32),
+        366 => sys_timerfd_settime(arg1, arg2 as u32, arg3, arg4),
+        367 => sys_timerfd_gettime(arg1, arg2),
+        368 => sys_epoll_create(),
+        369 => sys_epoll_ctl(arg1, arg2 as u32, arg3, arg4),
+        370 => sys_epoll_wait(arg1, arg2, arg3, arg4 as i64),
+        371 => sys_inotify_init(arg1 as u32),
+        372 => sys_inotify_add_watch(arg1, arg2, arg3 as u32),
+        373 => sys_inotify_rm_watch(arg1, arg2),
+        374 => sys_ring_create(arg1, arg2, arg3),
+        375 => sys_ring_map(arg1),
+        376 => sys_ring_destroy(arg1),
+        377 => sys_packet_filter(arg1, arg2, arg3),
+        378 => sys_packet_capture(arg1, arg2),
         
         // File operations
         10 => sys_open(arg1, arg2, arg3),
@@ -249,17 +288,26 @@ pub fn handle_syscall(
         300 => sys_ai_query(arg1, arg2, arg3),
         301 => sys_ai_generate(arg1, arg2, arg3),
         302 => sys_ai_analyze(arg1, arg2, arg3),
+
+        // Tracing
+        400 => sys_trace_probe(arg1, arg2, arg3, arg4, arg5, arg6),
         
         _ => SyscallResult::error(SyscallError::InvalidSyscall),
     }
 }
 
 // Process management syscalls
+/// End the calling thread, leaving the rest of its thread group running
 fn sys_exit(exit_code: i32) -> SyscallResult {
     crate::process::exit_process(exit_code);
     // This line is never reached since exit_process never returns
 }
 
+/// End every thread of the caller's thread group
+fn sys_exit_group(exit_code: i32) -> SyscallResult {
+    crate::process::exit_group(exit_code);
+}
+
 fn sys_fork() -> SyscallResult {
     match crate::process::fork_process() {
         Ok(child_pid) => {
@@ -314,6 +362,21 @@ fn sys_wait(pid: u64) -> SyscallResult {
     }
 }
 
+/// Wait for child `pid` to exit, or for any child when `pid` is zero or negative. Returns the
+/// child's PID and stores its exit code at `status` unless that is null
+fn sys_waitpid(pid: u64, status: u64) -> SyscallResult {
+    let target = if (pid as i64) > 0 { Some(pid) } else { None };
+    match crate::process::wait_pid(target) {
+        Ok((child_pid, exit_code)) => {
+            if status != 0 && copy_to_user(status, &exit_code.to_ne_bytes()).is_err() {
+                return SyscallResult::error(SyscallError::InvalidArgument);
+            }
+            SyscallResult::success(child_pid as i64)
+        }
+        Err(_) => SyscallResult::error(SyscallError::ResourceNotFound),
+    }
+}
+
 fn sys_kill(pid: u64, signal: i32) -> SyscallResult {
     // Convert signal number to Signal enum
     let signal_enum = match signal {
@@ -323,6 +386,7 @@ fn sys_kill(pid: u64, signal: i32) -> SyscallResult {
         18 => crate::process::Signal::SIGCONT,
         10 => crate::process::Signal::SIGUSR1,
         12 => crate::process::Signal::SIGUSR2,
+        17 => crate::process::Signal::SIGCHLD,
         _ => return SyscallResult::error(SyscallError::InvalidArgument),
     };
     match crate::process::send_signal(pid, signal_enum) {
@@ -339,9 +403,125 @@ fn sys_kill(pid: u64, signal: i32) -> SyscallResult {
     }
 }
 
+/// Send `signal` to thread `tid` of thread group `thread_group` rather than to whichever
+/// thread of the group takes it
+fn sys_tgkill(thread_group: u64, tid: u64, signal: i32) -> SyscallResult {
+    let Some(signal) = u8::try_from(signal).ok().and_then(|number| crate::process::Signal::try_from(number).ok()) else {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    };
+    match crate::process::send_thread_signal(thread_group, tid, signal) {
+        Ok(()) => SyscallResult::success(0),
+        Err(_) => SyscallResult::error(SyscallError::ResourceNotFound),
+    }
+}
+
+/// Copy the statistics of up to `max_cpus` CPUs to `buffer`, `CpuSchedStats::WORDS` words
+/// per CPU, returning the number of CPUs copied
+fn sys_sched_stats(buffer: u64, max_cpus: u64) -> SyscallResult {
+    let stats = crate::process::sched_stats();
+    let count = core::cmp::min(stats.len(), max_cpus as usize);
+    let bytes: Vec<u8> = stats[..count].iter().flat_map(|cpu| cpu.to_words()).flat_map(u64::to_ne_bytes).collect();
+    if copy_to_user(buffer, &bytes).is_err() {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    }
+    SyscallResult::success(count as i64)
+}
+
+/// Wait on or wake the futex at `address` (`FUTEX_WAIT`, `FUTEX_WAKE`), or take or release
+/// it as a priority-inheritance lock (`FUTEX_LOCK_PI`, `FUTEX_TRYLOCK_PI`, `FUTEX_UNLOCK_PI`).
+/// `value` is the word expected by a wait and the most threads a wake wakes
+fn sys_futex(address: u64, operation: u32, value: u64) -> SyscallResult {
+    use crate::futex::{self, FutexError};
+    let result = match operation & !futex::FUTEX_PRIVATE_FLAG {
+        futex::FUTEX_WAIT => futex::wait(address, value as u32).map(|()| 0),
+        futex::FUTEX_WAKE => futex::wake(address, value as usize),
+        futex::FUTEX_LOCK_PI => futex::lock_pi(address, false).map(|()| 0),
+        futex::FUTEX_TRYLOCK_PI => futex::lock_pi(address, true).map(|()| 0),
+        futex::FUTEX_UNLOCK_PI => futex::unlock_pi(address, crate::process::get_current_process_id()).map(|_| 0),
+        _ => Err(FutexError::InvalidArgument),
+    };
+    match result {
+        Ok(value) => SyscallResult::success(value as i64),
+        Err(FutexError::WouldBlock | FutexError::Deadlock) => SyscallResult::error(SyscallError::ResourceBusy),
+        Err(FutexError::NotOwner) => SyscallResult::error(SyscallError::PermissionDenied),
+        Err(FutexError::InvalidArgument | FutexError::Fault) => SyscallResult::error(SyscallError::InvalidArgument),
+    }
+}
+
+/// Set the robust futex list of the calling thread, recovered when it exits
+fn sys_set_robust_list(head: u64, length: u64) -> SyscallResult {
+    if length != crate::futex::ROBUST_LIST_HEAD_SIZE {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    }
+    crate::process::set_robust_list(head);
+    SyscallResult::success(0)
+}
+
+/// Open an eventfd with its counter at `initval`, taking `EFD_SEMAPHORE` and `EFD_NONBLOCK`
+/// in `flags`
+fn sys_eventfd(initval: u64, flags: u32) -> SyscallResult {
+    match crate::filesystem::eventfd::create(initval, flags) {
+        Ok(fd) => {
+            crate::process::add_open_file(fd);
+            SyscallResult::success(fd as i64)
+        }
+        Err(_) => SyscallResult::error(SyscallError::InvalidArgument),
+    }
+}
+
+/// Open a signalfd on which the signals in `mask`, bit `1 << signal` each, pending for the
+/// caller's thread group are read instead of handled
+fn sys_signalfd(mask: u64) -> SyscallResult {
+    let Some(thread_group) = crate::process::get_current_thread_group() else {
+        return SyscallResult::error(SyscallError::ResourceNotFound);
+    };
+    match crate::filesystem::signalfd::create(thread_group, mask) {
+        Ok(fd) => {
+            crate::process::add_open_file(fd);
+            SyscallResult::success(fd as i64)
+        }
+        Err(_) => SyscallResult::error(SyscallError::InvalidArgument),
+    }
+}
+
+/// Most entries one `poll` takes
+const MAX_POLL_FDS: u64 = 1024;
+
+/// Wait until one of the `count` `struct pollfd` entries at `fds` is ready or `timeout_ms`
+/// passes, waiting for ever when it is negative, and return how many are
+fn sys_poll(fds: u64, count: u64, timeout_ms: i64) -> SyscallResult {
+    use crate::filesystem::poll::{self, PollFd};
+    if count > MAX_POLL_FDS {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    }
+    let Ok(bytes) = slice_from_user(fds, count as usize * PollFd::SIZE) else {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    };
+    let mut entries: Vec<PollFd> = bytes.chunks_exact(PollFd::SIZE)
+        .filter_map(|entry| entry.try_into().ok())
+        .map(PollFd::from_bytes)
+        .collect();
+    let timeout_ns = u64::try_from(timeout_ms).ok().map(|ms| ms.saturating_mul(1_000_000));
+    let ready = poll::poll(&mut entries, timeout_ns);
+    let bytes: Vec<u8> = entries.into_iter().flat_map(PollFd::to_bytes).collect();
+    if copy_to_user(fds, &bytes).is_err() {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    }
+    SyscallResult::success(ready as i64)
+}
+
+/// PID of the caller, which threads of one group share
 fn sys_getpid() -> SyscallResult {
+    match crate::process::get_current_thread_group() {
+        Some(pid) => SyscallResult::success(pid as i64),
+        None => SyscallResult::error(SyscallError::ResourceNotFound)
+    }
+}
+
+/// ID of the calling thread, its own even within a thread group
+fn sys_gettid() -> SyscallResult {
     match crate::process::get_current_process_info() {
-        Some((pid, _, _)) => SyscallResult::success(pid as i64),
+        Some((tid, _, _)) => SyscallResult::success(tid as i64),
         None => SyscallResult::error(SyscallError::ResourceNotFound)
     }
 }
@@ -359,6 +539,12 @@ fn sys_sleep(_milliseconds: u64) -> SyscallResult {
     SyscallResult::success(0)
 }
 
+/// Sleep for `nanoseconds`, returning the nanoseconds left if a signal woke the caller early
+fn sys_nanosleep(nanoseconds: u64) -> SyscallResult {
+    let remaining = crate::process::nanosleep(nanoseconds);
+    SyscallResult::success(remaining.min(i64::MAX as u64) as i64)
+}
+
 fn sys_yield() -> SyscallResult {
     crate::process::yield_current();
     SyscallResult::success(0)
@@ -374,6 +560,29 @@ fn sys_thread_create(entry: u64, stack_size: u64) -> SyscallResult {
     }
 }
 
+/// Make a thread or process that returns from this call with 0 on `stack`, sharing with
+/// the caller what `flags` say, with its FS base at `tls` unless that is 0. Without
+/// `CloneFlags::VM` a zero `stack` keeps the caller's stack pointer. Returns the new ID
+fn sys_clone(flags: u64, stack: u64, tls: u64) -> SyscallResult {
+    use crate::process::{CloneFlags, ProcessError};
+    let Some(flags) = CloneFlags::from_bits(flags) else {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    };
+    let Some((return_address, user_stack)) = user_return_frame() else {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    };
+    let stack = if stack == 0 && !flags.contains(CloneFlags::VM) { user_stack } else { stack };
+    let (Ok(entry), Ok(stack)) = (VirtAddr::try_new(return_address), VirtAddr::try_new(stack)) else {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    };
+    match crate::process::clone_current(flags, entry, stack, tls) {
+        Ok(tid) => SyscallResult::success(tid as i64),
+        Err(ProcessError::InvalidClone) => SyscallResult::error(SyscallError::InvalidArgument),
+        Err(ProcessError::NoCurrentProcess) => SyscallResult::error(SyscallError::ResourceNotFound),
+        Err(_) => SyscallResult::error(SyscallError::OutOfMemory),
+    }
+}
+
 #[allow(dead_code)]
 fn sys_thread_join(tid: u64) -> SyscallResult {
     // If target already terminated, return immediately
@@ -407,6 +616,248 @@ fn sys_set_priority(level: u64) -> SyscallResult {
     SyscallResult::success(0)
 }
 
+/// Open a disarmed timerfd on `clock`, taking `TFD_NONBLOCK` in `flags`
+fn sys_timerfd_create(clock: u32, flags: u32) -> SyscallResult {
+    match crate::filesystem::timerfd::create(clock, flags) {
+        Ok(fd) => {
+            crate::process::add_open_file(fd);
+            SyscallResult::success(fd as i64)
+        }
+        Err(_) => SyscallResult::error(SyscallError::InvalidArgument),
+    }
+}
+
+/// Size of a `struct itimerspec`: the interval, then the expiry, as seconds and nanoseconds
+const ITIMERSPEC_SIZE: usize = 32;
+
+fn timer_spec_from_user(ptr: u64) -> Option<crate::filesystem::timerfd::TimerSpec> {
+    let bytes = slice_from_user(ptr, ITIMERSPEC_SIZE).ok()?;
+    let mut fields = bytes.chunks_exact(8).filter_map(|field| field.try_into().ok()).map(i64::from_ne_bytes);
+    let mut nanoseconds = || -> Option<u64> {
+        let (seconds, nanoseconds) = (u64::try_from(fields.next()?).ok()?, u64::try_from(fields.next()?).ok()?);
+        if nanoseconds >= 1_000_000_000 {
+            return None;
+        }
+        seconds.checked_mul(1_000_000_000)?.checked_add(nanoseconds)
+    };
+    let interval_ns = nanoseconds()?;
+    Some(crate::filesystem::timerfd::TimerSpec { value_ns: nanoseconds()?, interval_ns })
+}
+
+fn timer_spec_to_user(ptr: u64, spec: crate::filesystem::timerfd::TimerSpec) -> bool {
+    let bytes: Vec<u8> = [spec.interval_ns, spec.value_ns].into_iter()
+        .flat_map(|ns| [ns / 1_000_000_000, ns % 1_000_000_000])
+        .flat_map(u64::to_ne_bytes)
+        .collect();
+    copy_to_user(ptr, &bytes).is_ok()
+}
+
+/// Arm or disarm timerfd `fd` as the `struct itimerspec` at `new_value` says, relative to
+/// now unless `flags` has `TFD_TIMER_ABSTIME`, leaving the old setting at `old_value` unless
+/// that is 0
+fn sys_timerfd_settime(fd: u64, flags: u32, new_value: u64, old_value: u64) -> SyscallResult {
+    let Some(spec) = timer_spec_from_user(new_value) else {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    };
+    match crate::filesystem::timerfd::settime(fd, flags, spec) {
+        Ok(previous) if old_value == 0 || timer_spec_to_user(old_value, previous) => SyscallResult::success(0),
+        Ok(_) => SyscallResult::error(SyscallError::InvalidArgument),
+        Err(crate::filesystem::FileSystemError::NotFound) => SyscallResult::error(SyscallError::ResourceNotFound),
+        Err(_) => SyscallResult::error(SyscallError::InvalidArgument),
+    }
+}
+
+/// Leave how long until timerfd `fd` expires, and its interval, at `current_value`
+fn sys_timerfd_gettime(fd: u64, current_value: u64) -> SyscallResult {
+    match crate::filesystem::timerfd::gettime(fd) {
+        Ok(spec) if timer_spec_to_user(current_value, spec) => SyscallResult::success(0),
+        Ok(_) => SyscallResult::error(SyscallError::InvalidArgument),
+        Err(_) => SyscallResult::error(SyscallError::ResourceNotFound),
+    }
+}
+
+fn sys_epoll_create() -> SyscallResult {
+    match crate::filesystem::epoll::create() {
+        Ok(fd) => {
+            crate::process::add_open_file(fd);
+            SyscallResult::success(fd as i64)
+        }
+        Err(_) => SyscallResult::error(SyscallError::OutOfMemory),
+    }
+}
+
+/// Add, change or remove `fd` in the interest list of `epfd` as `op` says, with the
+/// `struct epoll_event` at `event`, which `EPOLL_CTL_DEL` ignores
+fn sys_epoll_ctl(epfd: u64, op: u32, fd: u64, event: u64) -> SyscallResult {
+    use crate::filesystem::epoll::{self, EpollEvent};
+    use crate::filesystem::FileSystemError;
+    let event = if op == epoll::EPOLL_CTL_DEL {
+        EpollEvent { events: 0, data: 0 }
+    } else {
+        let bytes = slice_from_user(event, EpollEvent::SIZE).ok().and_then(|bytes| bytes.try_into().ok());
+        let Some(bytes) = bytes else {
+            return SyscallResult::error(SyscallError::InvalidArgument);
+        };
+        EpollEvent::from_bytes(bytes)
+    };
+    match epoll::ctl(epfd, op, fd, event) {
+        Ok(()) => SyscallResult::success(0),
+        Err(FileSystemError::NotFound) => SyscallResult::error(SyscallError::ResourceNotFound),
+        Err(FileSystemError::AlreadyExists) => SyscallResult::error(SyscallError::ResourceBusy),
+        Err(_) => SyscallResult::error(SyscallError::InvalidArgument),
+    }
+}
+
+/// Wait until a descriptor in the interest list of `epfd` is ready or `timeout_ms` passes,
+/// for ever when it is negative, leaving up to `max_events` `struct epoll_event`s at
+/// `events`. Returns how many
+fn sys_epoll_wait(epfd: u64, events: u64, max_events: u64, timeout_ms: i64) -> SyscallResult {
+    use crate::filesystem::epoll;
+    if max_events == 0 || max_events > MAX_POLL_FDS {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    }
+    let timeout_ns = u64::try_from(timeout_ms).ok().map(|ms| ms.saturating_mul(1_000_000));
+    let ready = match epoll::wait(epfd, max_events as usize, timeout_ns) {
+        Ok(ready) => ready,
+        Err(_) => return SyscallResult::error(SyscallError::ResourceNotFound),
+    };
+    let bytes: Vec<u8> = ready.iter().flat_map(|event| event.to_bytes()).collect();
+    if copy_to_user(events, &bytes).is_err() {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    }
+    SyscallResult::success(ready.len() as i64)
+}
+
+/// Open an inotify descriptor, taking `IN_NONBLOCK` in `flags`
+fn sys_inotify_init(flags: u32) -> SyscallResult {
+    match crate::filesystem::inotify::create(flags) {
+        Ok(fd) => {
+            crate::process::add_open_file(fd);
+            SyscallResult::success(fd as i64)
+        }
+        Err(_) => SyscallResult::error(SyscallError::InvalidArgument),
+    }
+}
+
+/// Watch the path at `path` for the `IN_*` events in `mask` on inotify descriptor `fd`,
+/// returning the watch descriptor
+fn sys_inotify_add_watch(fd: u64, path: u64, mask: u32) -> SyscallResult {
+    let Ok(path) = c_str_from_user(path) else {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    };
+    match crate::filesystem::inotify::add_watch(fd, &path, mask) {
+        Ok(wd) => SyscallResult::success(wd as i64),
+        Err(crate::filesystem::FileSystemError::NotFound) => SyscallResult::error(SyscallError::ResourceNotFound),
+        Err(crate::filesystem::FileSystemError::PermissionDenied) => SyscallResult::error(SyscallError::PermissionDenied),
+        Err(_) => SyscallResult::error(SyscallError::InvalidArgument),
+    }
+}
+
+fn sys_inotify_rm_watch(fd: u64, wd: u64) -> SyscallResult {
+    match crate::filesystem::inotify::rm_watch(fd, wd) {
+        Ok(()) => SyscallResult::success(0),
+        Err(_) => SyscallResult::error(SyscallError::InvalidArgument),
+    }
+}
+
+fn ring_error(error: crate::shm_ring::RingError) -> SyscallResult {
+    use crate::shm_ring::RingError;
+    match error {
+        RingError::NotFound => SyscallResult::error(SyscallError::ResourceNotFound),
+        RingError::AlreadyExists => SyscallResult::error(SyscallError::ResourceBusy),
+        RingError::OutOfMemory => SyscallResult::error(SyscallError::OutOfMemory),
+        _ => SyscallResult::error(SyscallError::InvalidArgument),
+    }
+}
+
+/// Create the shared-memory ring named by the c-string at `name`, of `capacity` records of
+/// `record_size` bytes
+fn sys_ring_create(name: u64, record_size: u64, capacity: u64) -> SyscallResult {
+    let Ok(name) = c_str_from_user(name) else {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    };
+    let permissions = crate::vmm::VmPermissions::READ | crate::vmm::VmPermissions::WRITE | crate::vmm::VmPermissions::USER;
+    match crate::shm_ring::create(&name, record_size as usize, capacity as usize, permissions) {
+        Ok(()) => SyscallResult::success(0),
+        Err(error) => ring_error(error),
+    }
+}
+
+/// Map the ring named by the c-string at `name` into the caller, returning its address
+fn sys_ring_map(name: u64) -> SyscallResult {
+    let Ok(name) = c_str_from_user(name) else {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    };
+    match crate::shm_ring::map(get_current_process_address_space(), &name) {
+        Ok(addr) => SyscallResult::success(addr.as_u64() as i64),
+        Err(error) => ring_error(error),
+    }
+}
+
+fn sys_ring_destroy(name: u64) -> SyscallResult {
+    let Ok(name) = c_str_from_user(name) else {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    };
+    match crate::shm_ring::destroy(&name) {
+        Ok(()) => SyscallResult::success(0),
+        Err(error) => ring_error(error),
+    }
+}
+
+/// Attach the `count` classic BPF instructions at `program` to packet socket `socket_fd`,
+/// or detach its filter when `count` is 0
+fn sys_packet_filter(socket_fd: u64, program: u64, count: u64) -> SyscallResult {
+    use crate::network::bpf;
+    let filter = match count as usize {
+        0 => None,
+        count if count > bpf::MAX_INSTRUCTIONS => return SyscallResult::error(SyscallError::InvalidArgument),
+        count => {
+            let Ok(bytes) = slice_from_user(program, count * bpf::Instruction::LEN) else {
+                return SyscallResult::error(SyscallError::InvalidArgument);
+            };
+            match bpf::decode(&bytes) {
+                Ok(program) => Some(program),
+                Err(error) => return SyscallResult::error(error.into()),
+            }
+        }
+    };
+    match crate::network::packet::set_filter(socket_fd as u32, filter) {
+        Ok(()) => SyscallResult::success(0),
+        Err(error) => SyscallResult::error(error.into()),
+    }
+}
+
+/// Put packet socket `socket_fd` in capture mode, reading pcap records of up to `snaplen`
+/// bytes of each frame sent or received, or take it out of it when `snaplen` is 0
+fn sys_packet_capture(socket_fd: u64, snaplen: u64) -> SyscallResult {
+    let snaplen = match snaplen {
+        0 => None,
+        snaplen => Some(u32::try_from(snaplen).unwrap_or(u32::MAX)),
+    };
+    match crate::network::packet::set_capture(socket_fd as u32, snaplen) {
+        Ok(()) => SyscallResult::success(0),
+        Err(error) => SyscallResult::error(error.into()),
+    }
+}
+
+/// Set the base priority of process `pid`, the caller when 0, from nice value `nice`,
+/// -20 to 19. The caller may renice its own threads and its children
+fn sys_setpriority(pid: u64, nice: i64) -> SyscallResult {
+    use crate::process::ProcessError;
+    let pid = if pid == 0 { crate::process::get_current_process_id() } else { pid };
+    if !crate::process::may_renice(pid) {
+        return SyscallResult::error(SyscallError::PermissionDenied);
+    }
+    let Ok(nice) = i32::try_from(nice) else {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    };
+    match crate::process::renice(pid, nice) {
+        Ok(()) => SyscallResult::success(0),
+        Err(ProcessError::NoSuchProcess) => SyscallResult::error(SyscallError::ResourceNotFound),
+        Err(_) => SyscallResult::error(SyscallError::InvalidArgument),
+    }
+}
+
 // File system syscalls
 fn sys_open(path: u64, flags: u64, _mode: u64) -> SyscallResult {
     let path_str = match c_str_from_user(path) {
@@ -414,14 +865,20 @@ fn sys_open(path: u64, flags: u64, _mode: u64) -> SyscallResult {
         Err(_) => return SyscallResult::error(SyscallError::InvalidArgument)
     };
     match crate::filesystem::open(&path_str, flags as u32) {
-        Ok(fd) => SyscallResult::success(fd as i64),
+        Ok(fd) => {
+            crate::process::add_open_file(fd);
+            SyscallResult::success(fd as i64)
+        }
         Err(_) => SyscallResult::error(SyscallError::ResourceNotFound)
     }
 }
 
 fn sys_close(fd: u64) -> SyscallResult {
     match crate::filesystem::close(fd) {
-        Ok(()) => SyscallResult::success(0),
+        Ok(()) => {
+            crate::process::remove_open_file(fd);
+            SyscallResult::success(0)
+        }
         Err(_) => SyscallResult::error(SyscallError::InvalidArgument)
     }
 }
@@ -433,6 +890,7 @@ fn sys_read(fd: u64, buffer: u64, count: u64) -> SyscallResult {
             let _ = copy_to_user(buffer, &buf[..bytes_read]);
             SyscallResult::success(bytes_read as i64)
         }
+        Err(crate::filesystem::FileSystemError::WouldBlock) => SyscallResult::error(SyscallError::ResourceBusy),
         Err(_) => SyscallResult::error(SyscallError::IoError)
     }
 }
@@ -444,6 +902,7 @@ fn sys_write(fd: u64, buffer: u64, count: u64) -> SyscallResult {
     };
     match crate::filesystem::write(fd as u64, &data) {
         Ok(bytes_written) => SyscallResult::success(bytes_written as i64),
+        Err(crate::filesystem::FileSystemError::WouldBlock) => SyscallResult::error(SyscallError::ResourceBusy),
         Err(_) => SyscallResult::error(SyscallError::IoError)
     }
 }
@@ -528,38 +987,93 @@ fn sys_unlink(path: u64) -> SyscallResult {
 }
 
 // Memory management syscalls
-fn sys_mmap(_addr: u64, length: u64, prot: u64, _flags: u64, _fd: u64, _offset: i64) -> SyscallResult {
-    // Validate allocation length
-    if length == 0 || length > 0x40000000 { // 1GB limit
+
+// mmap protection and mapping flags, with their Linux values
+const PROT_READ: u64 = 0x1;
+const PROT_WRITE: u64 = 0x2;
+const PROT_EXEC: u64 = 0x4;
+const MAP_SHARED: u64 = 0x01;
+const MAP_PRIVATE: u64 = 0x02;
+const MAP_FIXED: u64 = 0x10;
+const MAP_ANONYMOUS: u64 = 0x20;
+
+// Parse POSIX protection flags, or None for unknown bits
+fn prot_to_permissions(prot: u64) -> Option<crate::vmm::VmPermissions> {
+    if prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+        return None;
+    }
+    let mut permissions = crate::vmm::VmPermissions::empty();
+    if prot & PROT_READ != 0 { permissions |= crate::vmm::VmPermissions::READ; }
+    if prot & PROT_WRITE != 0 { permissions |= crate::vmm::VmPermissions::WRITE; }
+    if prot & PROT_EXEC != 0 { permissions |= crate::vmm::VmPermissions::EXECUTE; }
+    Some(permissions)
+}
+
+fn sys_mmap(addr: u64, length: u64, prot: u64, flags: u64, fd: u64, offset: i64) -> SyscallResult {
+    // Validate allocation length, and the page alignment of the offset
+    if length == 0 || length > 0x40000000 || offset < 0 || offset as u64 % 4096 != 0 { // 1GB limit
         return SyscallResult::error(SyscallError::InvalidArgument);
     }
     
-    // Parse POSIX protection flags
-    let mut permissions = crate::vmm::VmPermissions::empty();
-    if prot & 0x1 != 0 { permissions |= crate::vmm::VmPermissions::READ; }
-    if prot & 0x2 != 0 { permissions |= crate::vmm::VmPermissions::WRITE; }
-    if prot & 0x4 != 0 { permissions |= crate::vmm::VmPermissions::EXECUTE; }
+    let Some(permissions) = prot_to_permissions(prot) else {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    };
     
     // Enforce W^X policy
-    if let Err(_) = permissions.validate_wx_policy() {
+    if permissions.validate_wx_policy().is_err() {
         return SyscallResult::error(SyscallError::PermissionDenied);
     }
     
+    let shared = match flags & (MAP_SHARED | MAP_PRIVATE) {
+        MAP_SHARED => true,
+        MAP_PRIVATE => false,
+        _ => return SyscallResult::error(SyscallError::InvalidArgument),
+    };
+    let source = if flags & MAP_ANONYMOUS != 0 {
+        crate::vmm::MappingSource::Anonymous { shared }
+    } else if shared {
+        // Writes through a shared file mapping would have to reach the file
+        return SyscallResult::error(SyscallError::NotImplemented);
+    } else {
+        match crate::filesystem::file_path(fd) {
+            Some(path) => crate::vmm::MappingSource::File { path, offset: offset as u64 },
+            None => return SyscallResult::error(SyscallError::InvalidArgument),
+        }
+    };
+    
+    // A zero address leaves the placement to the kernel
+    let hint = match addr {
+        0 => None,
+        _ => match VirtAddr::try_new(addr) {
+            Ok(hint) => Some(hint),
+            Err(_) => return SyscallResult::error(SyscallError::InvalidArgument),
+        },
+    };
+    
     let current_as = get_current_process_address_space();
-    match crate::vmm::allocate_area(
+    match crate::vmm::map_memory(
         current_as,
+        hint,
         length,
-        crate::vmm::VmAreaType::Heap,
-        permissions
+        permissions | crate::vmm::VmPermissions::USER,
+        flags & MAP_FIXED != 0,
+        source
     ) {
         Ok(addr) => SyscallResult::success(addr.as_u64() as i64),
+        Err(crate::vmm::VmError::AddressInUse) => SyscallResult::error(SyscallError::ResourceBusy),
+        Err(crate::vmm::VmError::InvalidAlignment | crate::vmm::VmError::InvalidOperation) => {
+            SyscallResult::error(SyscallError::InvalidArgument)
+        }
         Err(_) => SyscallResult::error(SyscallError::OutOfMemory)
     }
 }
 
-fn sys_munmap(addr: u64, _length: u64) -> SyscallResult {
+fn sys_munmap(addr: u64, length: u64) -> SyscallResult {
+    let Ok(start) = VirtAddr::try_new(addr) else {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    };
     let current_as = get_current_process_address_space();
-    match crate::vmm::deallocate_area(current_as, x86_64::VirtAddr::new(addr)) {
+    match crate::vmm::unmap_memory(current_as, start, length) {
         Ok(()) => SyscallResult::success(0),
         Err(_) => SyscallResult::error(SyscallError::InvalidArgument)
     }
@@ -571,11 +1085,9 @@ fn sys_mprotect(addr: u64, length: u64, prot: u64) -> SyscallResult {
         return SyscallResult::error(SyscallError::InvalidArgument);
     }
     
-    // Parse POSIX protection flags
-    let mut permissions = crate::vmm::VmPermissions::empty();
-    if prot & 0x1 != 0 { permissions |= crate::vmm::VmPermissions::READ; }
-    if prot & 0x2 != 0 { permissions |= crate::vmm::VmPermissions::WRITE; }
-    if prot & 0x4 != 0 { permissions |= crate::vmm::VmPermissions::EXECUTE; }
+    let Some(permissions) = prot_to_permissions(prot) else {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    };
     
     // Enforce W^X policy
     if let Err(_) = permissions.validate_wx_policy() {
@@ -724,8 +1236,12 @@ fn sys_set_theme(theme_id: u64, options: u64) -> SyscallResult {
     }
 }
 
-fn sys_create_window(x: u64, y: u64, width: u64, height: u64, _flags: u64) -> SyscallResult {
+fn sys_create_window(x: u64, y: u64, width: u64, height: u64, flags: u64) -> SyscallResult {
     let window_id = crate::graphics::create_window("Window", x as i32, y as i32, width as u32, height as u32, 0);
+    let ime_enabled = flags & crate::graphics::CREATE_WINDOW_IME != 0;
+    if ime_enabled && crate::graphics::set_ime_enabled(window_id, true).is_err() {
+        return SyscallResult::error(SyscallError::ResourceNotFound);
+    }
     SyscallResult::success(window_id as i64)
 }
 
@@ -1109,6 +1625,30 @@ fn sys_ai_analyze(data: u64, analysis_buffer: u64, buffer_size: u64) -> SyscallR
     }
 }
 
+// Tracing syscalls
+fn sys_trace_probe(name: u64, name_len: u64, subsystem: u64, severity: u64, data: u64, data_len: u64) -> SyscallResult {
+    use crate::observability::{user_probes, ObservabilityError};
+
+    if name_len == 0 || name_len as usize > user_probes::MAX_PROBE_NAME || data_len as usize > user_probes::MAX_PROBE_DATA {
+        return SyscallResult::error(SyscallError::InvalidArgument);
+    }
+    let name = match slice_from_user(name, name_len as usize).map(alloc::string::String::from_utf8) {
+        Ok(Ok(name)) => name,
+        _ => return SyscallResult::error(SyscallError::InvalidArgument),
+    };
+    let data = match slice_from_user(data, data_len as usize) {
+        Ok(data) => data,
+        Err(_) => return SyscallResult::error(SyscallError::InvalidArgument),
+    };
+
+    let pid = crate::process::get_current_process_id() as u32;
+    match user_probes::handle_probe(pid, &name, subsystem, severity, &data) {
+        Ok(recorded) => SyscallResult::success(recorded as i64),
+        Err(ObservabilityError::InvalidTracepoint) => SyscallResult::error(SyscallError::InvalidArgument),
+        Err(_) => SyscallResult::error(SyscallError::ResourceNotFound),
+    }
+}
+
 // Assembly syscall entry point
 #[no_mangle]
 pub extern "C" fn syscall_handler(
@@ -1267,6 +1807,20 @@ fn get_current_process_address_space() -> u64 {
     0
 }
 
+/// Where the syscall being served returns to in user space and the user stack pointer,
+/// as `syscall_entry` saved them
+fn user_return_frame() -> Option<(u64, u64)> {
+    let cpu = crate::percpu::current_cpu_data()?;
+    let kernel_stack = cpu.get_kernel_stack();
+    if kernel_stack == 0 {
+        return None;
+    }
+    // SAFETY: `syscall_entry` switched to the kernel stack whose top is `kernel_stack` and
+    // pushed the user RIP first, and that frame stays in place until the syscall returns
+    let return_address = unsafe { ((kernel_stack - 8) as *const u64).read() };
+    Some((return_address, cpu.get_user_stack()))
+}
+
 // ------- Safe user access helper functions using new uaccess API -------
 fn c_str_from_user(ptr: u64) -> Result<alloc::string::String, crate::arch::uaccess::UAccessError> {
     crate::arch::uaccess::read_cstr_from_user(ptr, 4096)
@@ -1306,16 +1860,6 @@ unsafe fn slice_from_user_unsafe(ptr: u64, len: usize) -> Vec<u8> {
     v
 }
 
-#[allow(dead_code)]
-unsafe fn copy_to_user_unsafe(dst: u64, src: &[u8]) {
-    // - The memory region [dst, dst + src.len()) must be valid and writable
-    // - src.len() must not cause integer overflow when added to dst
-    // - The source and destination must not overlap (nonoverlapping requirement)
-    // - User pointer validation should occur before calling this function
-    // - The user process must not have been deallocated during the syscall
-    core::ptr::copy_nonoverlapping(src.as_ptr(), dst as *mut u8, src.len());
-}
-
 unsafe fn unsafe_any_as_bytes<T: Sized>(t: &T) -> &[u8] {
     // SAFETY: This is unsafe because:
     // - t must be a valid reference to a properly initialized value of type T
@@ -1,18 +1,23 @@
 //! TAR filesystem implementation for RaeenOS
 //! 
-//! This module provides a simple read-only TAR filesystem that can be used
-//! to load embedded archives or preloaded binaries into the VFS.
+//! This module provides a TAR filesystem that can be used to load embedded
+//! archives or preloaded binaries into the VFS. The archive itself is read-only;
+//! files created or written are kept in a RAM overlay that shadows the archive.
+//! Opening an archive file for writing first copies it into the overlay, and
+//! removing one hides it behind a whiteout.
 
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::string::{String, ToString};
 use alloc::borrow::ToOwned;
 use alloc::vec::Vec;
 use alloc::boxed::Box;
 use alloc::format;
+use alloc::sync::Arc;
 use core::str;
+use spin::Mutex;
 
 use crate::filesystem::{
-    FileSystem, File, FileType, FileMetadata, FileSystemError, FileSystemResult
+    FileSystem, File, FileType, FileMetadata, FileSystemError, FileSystemResult, O_ACCMODE, O_RDONLY
 };
 
 /// TAR header structure (POSIX.1-1988 format)
@@ -55,11 +60,29 @@ struct TarEntry {
     metadata: FileMetadata,
 }
 
+/// A file or directory in the RAM overlay, shadowing any archive entry at its path
+#[derive(Debug, Clone)]
+struct OverlayNode {
+    metadata: FileMetadata,
+    /// Shared with every open file of the node so that writes through a descriptor persist
+    data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl OverlayNode {
+    fn new(file_type: FileType, data: Vec<u8>) -> Self {
+        let metadata = FileMetadata { file_type, ..FileMetadata::default() };
+        Self { metadata, data: Arc::new(Mutex::new(data)) }
+    }
+}
+
 /// TAR filesystem implementation
 pub struct TarFileSystem {
     name: String,
     data: Vec<u8>,
     entries: BTreeMap<String, TarEntry>,
+    overlay: BTreeMap<String, OverlayNode>,
+    /// Removed archive paths, hiding the entries at and below them
+    whiteouts: BTreeSet<String>,
 }
 
 impl TarFileSystem {
@@ -69,6 +92,8 @@ impl TarFileSystem {
             name,
             data,
             entries: BTreeMap::new(),
+            overlay: BTreeMap::new(),
+            whiteouts: BTreeSet::new(),
         };
         
         tarfs.parse_archive()?;
@@ -207,6 +232,55 @@ impl TarFileSystem {
             path.to_owned()
         }
     }
+    
+    /// Whether the archive entry at `path` has been removed
+    fn is_whited_out(&self, path: &str) -> bool {
+        self.whiteouts.iter().any(|whiteout| is_at_or_below(path, whiteout))
+    }
+    
+    /// The archive entry at `path`, unless it has been removed
+    fn archive_entry(&self, path: &str) -> Option<&TarEntry> {
+        self.entries.get(path).filter(|_| !self.is_whited_out(path))
+    }
+    
+    /// Every visible path, overlay and archive alike
+    fn visible_paths(&self) -> impl Iterator<Item = &String> {
+        self.overlay.keys().chain(self.entries.keys().filter(|name| !self.is_whited_out(name)))
+    }
+    
+    /// The type of whatever is at `path`. Directories need no entry of their own: the
+    /// archive may only hold the files inside them
+    fn file_type(&self, path: &str) -> Option<FileType> {
+        if path == "." {
+            return Some(FileType::Directory);
+        }
+        if let Some(node) = self.overlay.get(path) {
+            return Some(node.metadata.file_type);
+        }
+        if let Some(entry) = self.archive_entry(path) {
+            return Some(entry.file_type);
+        }
+        self.visible_paths()
+            .any(|name| name.len() > path.len() && is_at_or_below(name, path))
+            .then_some(FileType::Directory)
+    }
+    
+    /// The archive contents of the file `entry`
+    fn archive_data(&self, entry: &TarEntry) -> Vec<u8> {
+        let start = entry.data_offset;
+        let end = start + entry.size as usize;
+        self.data.get(start..end).map(<[u8]>::to_vec).unwrap_or_default()
+    }
+}
+
+/// Whether `path` is `ancestor` or lies inside it
+fn is_at_or_below(path: &str, ancestor: &str) -> bool {
+    path.strip_prefix(ancestor).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// The directory holding `path`, `.` for the root
+fn parent_of(path: &str) -> &str {
+    path.rsplit_once('/').map_or(".", |(parent, _)| parent)
 }
 
 impl FileSystem for TarFileSystem {
@@ -214,130 +288,208 @@ impl FileSystem for TarFileSystem {
         &self.name
     }
     
-    fn open(&mut self, path: &str, _flags: u32) -> FileSystemResult<Box<dyn File>> {
+    /// Open a file, from the overlay if it is there. Opening an archive file for anything
+    /// but reading copies it into the overlay first
+    fn open(&mut self, path: &str, flags: u32) -> FileSystemResult<Box<dyn File>> {
         let normalized_path = self.normalize_path(path);
         
-        if let Some(entry) = self.entries.get(&normalized_path) {
+        if !self.overlay.contains_key(&normalized_path) {
+            let entry = self.archive_entry(&normalized_path).ok_or_else(|| match self.file_type(&normalized_path) {
+                Some(_) => FileSystemError::IsADirectory,
+                None => FileSystemError::NotFound,
+            })?;
             if entry.file_type == FileType::Directory {
                 return Err(FileSystemError::IsADirectory);
             }
             
-            let file_data = if entry.size > 0 {
-                let start = entry.data_offset;
-                let end = start + entry.size as usize;
-                if end <= self.data.len() {
-                    self.data[start..end].to_vec()
-                } else {
-                    Vec::new()
-                }
-            } else {
-                Vec::new()
-            };
+            let data = self.archive_data(entry);
+            let metadata = entry.metadata.clone();
+            if flags & O_ACCMODE == O_RDONLY {
+                return Ok(Box::new(TarFile {
+                    data: Arc::new(Mutex::new(data)),
+                    position: 0,
+                    metadata,
+                    writable: false,
+                }));
+            }
             
-            Ok(Box::new(TarFile {
-                data: file_data,
-                position: 0,
-                metadata: entry.metadata.clone(),
-            }))
-        } else {
-            Err(FileSystemError::NotFound)
+            let mut node = OverlayNode::new(FileType::Regular, data);
+            node.metadata.permissions = metadata.permissions;
+            self.overlay.insert(normalized_path.clone(), node);
         }
+        
+        let node = self.overlay.get(&normalized_path).ok_or(FileSystemError::NotFound)?;
+        if node.metadata.file_type == FileType::Directory {
+            return Err(FileSystemError::IsADirectory);
+        }
+        Ok(Box::new(TarFile {
+            data: Arc::clone(&node.data),
+            position: 0,
+            metadata: node.metadata.clone(),
+            writable: true,
+        }))
     }
     
-    fn create(&mut self, _path: &str, _file_type: FileType) -> FileSystemResult<()> {
-        Err(FileSystemError::ReadOnly)
+    fn create(&mut self, path: &str, file_type: FileType) -> FileSystemResult<()> {
+        let normalized_path = self.normalize_path(path);
+        
+        if self.file_type(&normalized_path).is_some() {
+            return Err(FileSystemError::AlreadyExists);
+        }
+        match self.file_type(parent_of(&normalized_path)) {
+            Some(FileType::Directory) => {}
+            Some(_) => return Err(FileSystemError::NotADirectory),
+            None => return Err(FileSystemError::NotFound),
+        }
+        
+        self.overlay.insert(normalized_path, OverlayNode::new(file_type, Vec::new()));
+        Ok(())
     }
     
-    fn remove(&mut self, _path: &str) -> FileSystemResult<()> {
-        Err(FileSystemError::ReadOnly)
+    fn remove(&mut self, path: &str) -> FileSystemResult<()> {
+        let normalized_path = self.normalize_path(path);
+        
+        if normalized_path == "." {
+            return Err(FileSystemError::InvalidOperation);
+        }
+        if self.file_type(&normalized_path).is_none() {
+            return Err(FileSystemError::NotFound);
+        }
+        
+        self.overlay.retain(|name, _| !is_at_or_below(name, &normalized_path));
+        if self.entries.keys().any(|name| is_at_or_below(name, &normalized_path)) {
+            self.whiteouts.insert(normalized_path);
+        }
+        Ok(())
     }
     
     fn metadata(&self, path: &str) -> FileSystemResult<FileMetadata> {
         let normalized_path = self.normalize_path(path);
         
-        if let Some(entry) = self.entries.get(&normalized_path) {
-            Ok(entry.metadata.clone())
-        } else {
-            Err(FileSystemError::NotFound)
+        if let Some(node) = self.overlay.get(&normalized_path) {
+            let mut metadata = node.metadata.clone();
+            metadata.size = node.data.lock().len() as u64;
+            return Ok(metadata);
+        }
+        if let Some(entry) = self.archive_entry(&normalized_path) {
+            return Ok(entry.metadata.clone());
+        }
+        match self.file_type(&normalized_path) {
+            Some(file_type) => Ok(FileMetadata { file_type, permissions: 0o755, ..FileMetadata::default() }),
+            None => Err(FileSystemError::NotFound),
         }
     }
     
     fn list_directory(&self, path: &str) -> FileSystemResult<Vec<String>> {
         let normalized_path = self.normalize_path(path);
         
-        // Check if the path is a directory
-        if let Some(entry) = self.entries.get(&normalized_path) {
-            if entry.file_type != FileType::Directory {
-                return Err(FileSystemError::NotADirectory);
-            }
-        } else if normalized_path != "." {
-            return Err(FileSystemError::NotFound);
+        match self.file_type(&normalized_path) {
+            Some(FileType::Directory) => {}
+            Some(_) => return Err(FileSystemError::NotADirectory),
+            None => return Err(FileSystemError::NotFound),
         }
         
-        let mut children = Vec::new();
         let prefix = if normalized_path == "." {
             String::new()
         } else {
             format!("{}/", normalized_path)
         };
         
-        for name in self.entries.keys() {
-            if name.starts_with(&prefix) {
-                let relative = &name[prefix.len()..];
-                if !relative.is_empty() && !relative.contains('/') {
-                    children.push(relative.to_owned());
-                }
-            }
-        }
+        // Directories the archive only implies show up through the paths inside them
+        let children: BTreeSet<&str> = self.visible_paths()
+            .filter_map(|name| name.strip_prefix(prefix.as_str()))
+            .filter_map(|relative| relative.split('/').next())
+            .filter(|child| !child.is_empty())
+            .collect();
         
-        Ok(children)
+        Ok(children.into_iter().map(str::to_owned).collect())
     }
     
-    fn rename(&mut self, _old_path: &str, _new_path: &str) -> FileSystemResult<()> {
-        Err(FileSystemError::ReadOnly)
+    fn rename(&mut self, old_path: &str, new_path: &str) -> FileSystemResult<()> {
+        let old_path = self.normalize_path(old_path);
+        let new_path = self.normalize_path(new_path);
+        
+        match self.file_type(&old_path) {
+            Some(FileType::Regular) => {}
+            Some(_) => return Err(FileSystemError::InvalidOperation),
+            None => return Err(FileSystemError::NotFound),
+        }
+        
+        let node = match self.overlay.get(&old_path) {
+            Some(node) => node.clone(),
+            None => {
+                let entry = self.archive_entry(&old_path).ok_or(FileSystemError::NotFound)?;
+                let mut node = OverlayNode::new(FileType::Regular, self.archive_data(entry));
+                node.metadata.permissions = entry.metadata.permissions;
+                node
+            }
+        };
+        self.create(&new_path, FileType::Regular)?;
+        self.overlay.insert(new_path, node);
+        self.remove(&old_path)
     }
     
     fn sync(&mut self) -> FileSystemResult<()> {
-        Ok(()) // Read-only filesystem, nothing to sync
+        Ok(()) // The archive is read-only and the overlay lives in memory
     }
 }
 
 /// TAR file implementation
 struct TarFile {
-    data: Vec<u8>,
+    data: Arc<Mutex<Vec<u8>>>,
     position: usize,
     metadata: FileMetadata,
+    /// False for archive files opened read-only, which are not copied into the overlay
+    writable: bool,
 }
 
 impl File for TarFile {
     fn read(&mut self, buffer: &mut [u8]) -> FileSystemResult<usize> {
-        if self.position >= self.data.len() {
+        let data = self.data.lock();
+        if self.position >= data.len() {
             return Ok(0);
         }
         
-        let available = self.data.len() - self.position;
+        let available = data.len() - self.position;
         let to_read = buffer.len().min(available);
         
-        buffer[..to_read].copy_from_slice(&self.data[self.position..self.position + to_read]);
+        buffer[..to_read].copy_from_slice(&data[self.position..self.position + to_read]);
         self.position += to_read;
         
         Ok(to_read)
     }
     
-    fn write(&mut self, _buffer: &[u8]) -> FileSystemResult<usize> {
-        Err(FileSystemError::ReadOnly)
+    fn write(&mut self, buffer: &[u8]) -> FileSystemResult<usize> {
+        if !self.writable {
+            return Err(FileSystemError::ReadOnly);
+        }
+        
+        let mut data = self.data.lock();
+        let end = self.position + buffer.len();
+        // Writing past the end zero-fills the gap
+        if end > data.len() {
+            data.resize(end, 0);
+        }
+        
+        data[self.position..end].copy_from_slice(buffer);
+        self.position = end;
+        self.metadata.size = data.len() as u64;
+        self.metadata.modified = crate::time::get_timestamp();
+        
+        Ok(buffer.len())
     }
     
     fn seek(&mut self, pos: crate::filesystem::SeekFrom) -> FileSystemResult<u64> {
         use crate::filesystem::SeekFrom;
         
+        let len = self.data.lock().len();
         let new_pos = match pos {
             SeekFrom::Start(offset) => offset as usize,
             SeekFrom::End(offset) => {
                 if offset >= 0 {
-                    self.data.len() + offset as usize
+                    len + offset as usize
                 } else {
-                    self.data.len().saturating_sub((-offset) as usize)
+                    len.saturating_sub((-offset) as usize)
                 }
             }
             SeekFrom::Current(offset) => {
@@ -349,20 +501,39 @@ impl File for TarFile {
             }
         };
         
-        self.position = new_pos.min(self.data.len());
+        // Only a writable file can be extended by writing beyond its end
+        self.position = if self.writable { new_pos } else { new_pos.min(len) };
         Ok(self.position as u64)
     }
     
+    fn truncate(&mut self, size: u64) -> FileSystemResult<()> {
+        if !self.writable {
+            return Err(FileSystemError::ReadOnly);
+        }
+        
+        self.data.lock().resize(size as usize, 0);
+        self.metadata.size = size;
+        self.metadata.modified = crate::time::get_timestamp();
+        Ok(())
+    }
+    
     fn metadata(&self) -> FileSystemResult<FileMetadata> {
-        Ok(self.metadata.clone())
+        let mut metadata = self.metadata.clone();
+        metadata.size = self.data.lock().len() as u64;
+        Ok(metadata)
     }
     
     fn flush(&mut self) -> FileSystemResult<()> {
-        Ok(()) // Read-only file, nothing to flush
+        Ok(()) // Writes go straight to the overlay
     }
     
-    fn set_permissions(&mut self, _permissions: u32) -> FileSystemResult<()> {
-        Err(FileSystemError::ReadOnly)
+    fn set_permissions(&mut self, permissions: u32) -> FileSystemResult<()> {
+        if !self.writable {
+            return Err(FileSystemError::ReadOnly);
+        }
+        
+        self.metadata.permissions = permissions;
+        Ok(())
     }
 }
 
@@ -374,7 +545,11 @@ pub fn create_tar_filesystem(name: String, data: Vec<u8>) -> Result<Box<dyn File
 
 /// Load a simple embedded TAR archive for testing
 pub fn create_test_tar_filesystem() -> Result<Box<dyn FileSystem>, FileSystemError> {
-    // Create a minimal TAR archive with some test files
+    create_tar_filesystem("testfs".to_owned(), test_archive())
+}
+
+/// A minimal TAR archive with some test files
+pub fn test_archive() -> Vec<u8> {
     let mut tar_data = Vec::new();
     
     // Add a simple test file "hello.txt"
@@ -383,8 +558,7 @@ pub fn create_test_tar_filesystem() -> Result<Box<dyn FileSystem>, FileSystemErr
     
     // Add end-of-archive marker (two zero blocks)
     tar_data.resize(tar_data.len() + TAR_BLOCK_SIZE * 2, 0);
-    
-    create_tar_filesystem("testfs".to_owned(), tar_data)
+    tar_data
 }
 
 /// Helper function to add a file to TAR data
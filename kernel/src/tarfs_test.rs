@@ -0,0 +1,154 @@
+//! TAR Filesystem Overlay Test
+//! Writes through the VFS to a mounted TAR archive: new files are created in the RAM overlay
+//! and read back after reopening, written archive files shadow their archive contents,
+//! writes past the end zero-fill the gap, and `O_CREAT`, `O_TRUNC` and `O_APPEND` behave
+//! as on Unix, with creation failing when the parent directory does not exist
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::filesystem::{self, FileSystemError, FileType, SeekFrom, O_APPEND, O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY};
+use crate::serial::_print;
+use crate::tarfs;
+
+const MOUNT: &str = "/mnt/overlay-test";
+
+fn path(name: &str) -> String {
+    alloc::format!("{}/{}", MOUNT, name)
+}
+
+/// Write `data` to `name`, opened with `flags`
+fn write(name: &str, flags: u32, data: &[u8]) -> Result<(), &'static str> {
+    let fd = filesystem::open(&path(name), flags).map_err(|_| "Failed to open for writing")?;
+    let written = filesystem::write(fd, data);
+    let _ = filesystem::close(fd);
+    match written {
+        Ok(count) if count == data.len() => Ok(()),
+        _ => Err("Failed to write"),
+    }
+}
+
+/// Everything in `name`, read through a fresh read-only descriptor
+fn read(name: &str) -> Result<Vec<u8>, &'static str> {
+    let fd = filesystem::open(&path(name), O_RDONLY).map_err(|_| "Failed to open for reading")?;
+    let mut contents = Vec::new();
+    let mut buffer = [0u8; 16];
+    let result = loop {
+        match filesystem::read(fd, &mut buffer) {
+            Ok(0) => break Ok(contents),
+            Ok(count) => contents.extend_from_slice(&buffer[..count]),
+            Err(_) => break Err("Failed to read"),
+        }
+    };
+    let _ = filesystem::close(fd);
+    result
+}
+
+fn run_overlay_tests() -> Result<(), &'static str> {
+    // Test 1: A created file keeps what was written once reopened
+    _print(format_args!("[TarFS Test] Test 1: Create, write and read back...\n"));
+    write("notes.txt", O_WRONLY | O_CREAT, b"hello")?;
+    if read("notes.txt")? != b"hello" {
+        return Err("Created file did not read back");
+    }
+    let size = filesystem::metadata(&path("notes.txt")).map_err(|_| "No metadata for created file")?.size;
+    let listing = filesystem::list_directory(MOUNT).map_err(|_| "Failed to list the root")?;
+    if size != 5 || listing != ["bin", "hello.txt", "notes.txt"] {
+        return Err("Created file missing from metadata or listing");
+    }
+    _print(format_args!("[TarFS Test] ✓ notes.txt reads back \"hello\" beside {:?}\n", listing));
+
+    // Test 2: Written archive files shadow the archive, which read-only opens cannot change
+    _print(format_args!("[TarFS Test] Test 2: Overlay over archive files...\n"));
+    if read("hello.txt")? != b"Hello, RaeenOS!\n" {
+        return Err("Archive file unreadable");
+    }
+    let fd = filesystem::open(&path("bin/test"), O_RDONLY).map_err(|_| "Failed to open archive file")?;
+    let refused = matches!(filesystem::write(fd, b"x"), Err(FileSystemError::ReadOnly));
+    let _ = filesystem::close(fd);
+    if !refused {
+        return Err("Read-only open of an archive file was writable");
+    }
+    write("hello.txt", O_WRONLY | O_TRUNC, b"changed")?;
+    if read("hello.txt")? != b"changed" {
+        return Err("Overlay did not shadow the archive file");
+    }
+    write("hello.txt", O_WRONLY, b"CH")?;
+    if read("hello.txt")? != b"CHanged" {
+        return Err("Overwrite without O_TRUNC lost the rest of the file");
+    }
+    _print(format_args!("[TarFS Test] ✓ hello.txt copied up and shadowed\n"));
+
+    // Test 3: Writing past the end zero-fills, O_APPEND writes at the end and truncation cuts
+    _print(format_args!("[TarFS Test] Test 3: Past EOF, append and truncate...\n"));
+    let fd = filesystem::open(&path("notes.txt"), O_RDWR).map_err(|_| "Failed to reopen notes.txt")?;
+    let gap = filesystem::seek(fd, SeekFrom::Start(8)).and_then(|_| filesystem::write(fd, b"!"));
+    let _ = filesystem::close(fd);
+    if gap.is_err() || read("notes.txt")? != b"hello\0\0\0!" {
+        return Err("Write past the end did not zero-fill");
+    }
+    let fd = filesystem::open(&path("notes.txt"), O_WRONLY | O_APPEND).map_err(|_| "Failed to open for append")?;
+    let appended = filesystem::seek(fd, SeekFrom::Start(0)).and_then(|_| filesystem::write(fd, b"?"));
+    let cut = filesystem::truncate(fd, 2);
+    let _ = filesystem::close(fd);
+    if appended.is_err() || cut.is_err() {
+        return Err("Append or truncate failed");
+    }
+    write("notes.txt", O_WRONLY | O_APPEND, b"y")?;
+    if read("notes.txt")? != b"hey" {
+        return Err("Append or truncate wrote in the wrong place");
+    }
+    _print(format_args!("[TarFS Test] ✓ Gap zero-filled, appends land at the end\n"));
+
+    // Test 4: Files are only created inside directories that exist
+    _print(format_args!("[TarFS Test] Test 4: Parent directories...\n"));
+    if !matches!(filesystem::open(&path("missing/new.txt"), O_WRONLY | O_CREAT), Err(FileSystemError::NotFound)) {
+        return Err("Created a file in a missing directory");
+    }
+    if !matches!(filesystem::create_file(&path("hello.txt/new.txt")), Err(FileSystemError::NotADirectory)) {
+        return Err("Created a file inside a file");
+    }
+    if filesystem::open(&path("docs/new.txt"), O_RDONLY).is_ok() {
+        return Err("Opened a file that does not exist");
+    }
+    filesystem::create_directory(&path("docs")).map_err(|_| "Failed to create a directory")?;
+    write("docs/new.txt", O_WRONLY | O_CREAT, b"nested")?;
+    write("bin/tool", O_WRONLY | O_CREAT, b"implied")?;
+    if read("docs/new.txt")? != b"nested" || read("bin/tool")? != b"implied" {
+        return Err("Files in new or archive-implied directories unreadable");
+    }
+    filesystem::remove(&path("hello.txt")).map_err(|_| "Failed to remove an archive file")?;
+    if filesystem::metadata(&path("hello.txt")).is_ok() {
+        return Err("Removed archive file still visible");
+    }
+    let bin = filesystem::list_directory(&path("bin")).map_err(|_| "Failed to list bin")?;
+    if bin != ["test", "tool"] || filesystem::metadata(&path("bin")).map(|m| m.file_type).ok() != Some(FileType::Directory) {
+        return Err("Implied directory listed wrong");
+    }
+    _print(format_args!("[TarFS Test] ✓ Missing parents refused, bin holds {:?}\n", bin));
+
+    _print(format_args!("[TarFS Test] ✓ All TAR filesystem tests completed successfully!\n"));
+    Ok(())
+}
+
+pub fn run_tarfs_tests() -> Result<(), &'static str> {
+    let tar_fs = tarfs::create_tar_filesystem(String::from("overlay-test"), tarfs::test_archive())
+        .map_err(|_| "Failed to parse the test archive")?;
+    filesystem::mount_filesystem(tar_fs, MOUNT).map_err(|_| "Failed to mount the test archive")?;
+    let result = run_overlay_tests();
+    let _ = filesystem::unmount_filesystem(MOUNT);
+    result
+}
+
+/// Main test runner for the TAR filesystem overlay
+pub fn test_tarfs() {
+    _print(format_args!("[TarFS Test] ===========================================\n"));
+    _print(format_args!("[TarFS Test]        TAR FILESYSTEM OVERLAY TESTS\n"));
+    _print(format_args!("[TarFS Test] ===========================================\n"));
+
+    match run_tarfs_tests() {
+        Ok(_) => _print(format_args!("[TarFS Test] ✓ All TAR filesystem tests PASSED\n")),
+        Err(e) => _print(format_args!("[TarFS Test] ✗ TAR filesystem tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[TarFS Test] ===========================================\n"));
+}
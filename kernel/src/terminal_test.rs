@@ -0,0 +1,350 @@
+//! Terminal Widget Test
+//! Feeds ANSI output to the shell window's terminal and checks the colors of the resulting
+//! cells, that output past the screen stays reachable in the scrollback, and that resizing
+//! reflows wrapped lines. Then drives a shell terminal with mouse and key events to check
+//! selection, copying to the clipboard and pasting into the command line. Last, sequences of
+//! the VT100 and xterm escape codes full-screen programs use are checked against the cell
+//! grid they should leave
+
+use alloc::format;
+use alloc::string::String;
+use crate::graphics::{Color, GraphicsBuffer, KeyModifiers, KeyboardEvent, MouseButton, MouseEvent, WindowEvent};
+use crate::raekit::terminal::{cell_size, indexed_color, CellAttributes, SelectionUnit, ShellTerminal, Terminal, DEFAULT_BACKGROUND, DEFAULT_FOREGROUND};
+use crate::serial::_print;
+use crate::ui::clipboard;
+
+/// Press or release of `button` over the cell at `row`, `column`
+fn click(row: usize, column: usize, button: MouseButton, pressed: bool) -> WindowEvent {
+    let (x, y) = pointer(row, column);
+    WindowEvent::Mouse(MouseEvent { x, y, button, pressed, timestamp: 0 })
+}
+
+/// Window position inside the cell at `row`, `column`
+fn pointer(row: usize, column: usize) -> (i32, i32) {
+    let (cell_width, cell_height) = cell_size();
+    ((column as u32 * cell_width + 1) as i32, (row as u32 * cell_height + 1) as i32)
+}
+
+fn ctrl_shift(key_code: u8) -> WindowEvent {
+    let modifiers = KeyModifiers::CTRL | KeyModifiers::SHIFT;
+    WindowEvent::Keyboard(KeyboardEvent { key_code: u32::from(key_code), pressed: true, modifiers, timestamp: 0 })
+}
+
+fn feed(shell: &mut ShellTerminal, events: impl IntoIterator<Item = WindowEvent>) -> Result<(), &'static str> {
+    for event in events {
+        if !shell.handle_event(event) {
+            return Err("Shell terminal closed by an event");
+        }
+    }
+    Ok(())
+}
+
+pub fn run_terminal_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Terminal Test] Starting terminal widget tests...\n"));
+
+    // Test 1: SGR codes color the cells written after them
+    _print(format_args!("[Terminal Test] Test 1: ANSI colors...\n"));
+    let mut terminal = Terminal::new(20, 4);
+    terminal.write("\x1b[31mR\x1b[1;32mG\x1b[0m.\x1b[44;97mB\x1b[38;5;196mX\x1b[38;2;1;2;3;48;5;232mY\x1b[7mI\x1b[m");
+    let cells: [(char, _, _); 7] = [
+        ('R', indexed_color(1), DEFAULT_BACKGROUND),
+        // Bold brightens green
+        ('G', indexed_color(10), DEFAULT_BACKGROUND),
+        ('.', DEFAULT_FOREGROUND, DEFAULT_BACKGROUND),
+        ('B', indexed_color(15), indexed_color(4)),
+        ('X', indexed_color(196), indexed_color(4)),
+        ('Y', Color::rgb(1, 2, 3), indexed_color(232)),
+        ('I', indexed_color(232), Color::rgb(1, 2, 3)),
+    ];
+    for (column, (ch, foreground, background)) in cells.into_iter().enumerate() {
+        let cell = terminal.cell(0, column).ok_or("Cell outside the view")?;
+        if cell.ch != ch || cell.foreground != foreground || cell.background != background {
+            return Err("SGR colors not applied to the cell");
+        }
+    }
+    if indexed_color(196) != Color::rgb(255, 0, 0) || indexed_color(244) != Color::rgb(128, 128, 128) {
+        return Err("256-color palette wrong");
+    }
+    // Erasing and cursor movement keep to the cells they address
+    terminal.write("\x1b[2;5Hab\x1b[2D\x1b[41m\x1b[1K\x1b[0m");
+    let erased = terminal.cell(1, 3).ok_or("Cell outside the view")?;
+    if terminal.row_text(1) != "     b" || erased.background != indexed_color(1) || terminal.cursor() != Some((1, 4)) {
+        return Err("Cursor movement or line erase wrong");
+    }
+    // The colored background reaches the window's pixels
+    let mut buffer = GraphicsBuffer::new(20 * 8, 4 * 16);
+    terminal.render(&mut buffer, 8, 16);
+    if buffer.get_pixel(3 * 8, 16) != indexed_color(1) || buffer.get_pixel(12 * 8, 3 * 16) != DEFAULT_BACKGROUND {
+        return Err("Cell backgrounds not rendered");
+    }
+    _print(format_args!("[Terminal Test] ✓ Foreground and background colors set per cell\n"));
+
+    // Test 2: Output past the bottom scrolls into the scrollback
+    _print(format_args!("[Terminal Test] Test 2: Scrollback...\n"));
+    let mut terminal = Terminal::new(10, 5);
+    for line in 0..20 {
+        terminal.write(&format!("line {}\n", line));
+    }
+    // Twenty lines and the empty one the cursor is on, five of them on screen
+    if terminal.scrollback_rows() != 16 || terminal.row_text(0) != "line 16" || terminal.cursor() != Some((4, 0)) {
+        return Err("Screen not following the output");
+    }
+    terminal.scroll(16);
+    if terminal.row_text(0) != "line 0" || terminal.row_text(4) != "line 4" || terminal.cursor().is_some() {
+        return Err("Oldest output not reachable by scrolling");
+    }
+    // Scrolling stops at the oldest row, and new output leaves the scrolled view in place
+    terminal.scroll(5);
+    terminal.write("line 20\n");
+    if terminal.scroll_offset() != 17 || terminal.row_text(0) != "line 0" {
+        return Err("Scrolled view moved by output");
+    }
+    terminal.scroll(-3);
+    if terminal.row_text(0) != "line 3" {
+        return Err("View not scrolled forward");
+    }
+    terminal.scroll_to_bottom();
+    if terminal.row_text(3) != "line 20" {
+        return Err("View not returned to the screen");
+    }
+    // Clearing the screen keeps what it showed in the scrollback
+    terminal.write("\x1b[2J\x1b[Hfresh");
+    if terminal.row_text(0) != "fresh" || !terminal.row_text(1).is_empty() {
+        return Err("Screen not cleared");
+    }
+    terminal.scroll(5);
+    if terminal.row_text(3) != "line 20" {
+        return Err("Cleared screen not kept in the scrollback");
+    }
+    _print(format_args!("[Terminal Test] ✓ Output beyond the screen reachable by scrolling\n"));
+
+    // Test 3: Resizing rewraps long lines
+    _print(format_args!("[Terminal Test] Test 3: Reflow on resize...\n"));
+    let mut terminal = Terminal::new(10, 6);
+    terminal.write("abcdefghijklmnopqrstuvwxy\nshort\n$ ");
+    if terminal.row_text(0) != "abcdefghij" || terminal.row_text(2) != "uvwxy" || terminal.row_text(3) != "short" {
+        return Err("Long line not wrapped");
+    }
+    terminal.resize(20, 6);
+    if terminal.row_text(0) != "abcdefghijklmnopqrst" || terminal.row_text(1) != "uvwxy" || terminal.row_text(2) != "short" {
+        return Err("Wrapped line not joined when widened");
+    }
+    if terminal.cursor() != Some((3, 2)) {
+        return Err("Cursor not moved with its line");
+    }
+    terminal.resize(4, 6);
+    if terminal.row_text(0) != "qrst" || terminal.row_text(2) != "y" || terminal.row_text(3) != "shor" || terminal.row_text(4) != "t" {
+        return Err("Line not rewrapped when narrowed");
+    }
+    // The wrapped rows that no longer fit went into the scrollback
+    terminal.scroll(4);
+    if terminal.row_text(0) != "abcd" {
+        return Err("Reflowed rows missing from the scrollback");
+    }
+    _print(format_args!("[Terminal Test] ✓ Wrapped lines reflowed to the new width\n"));
+
+    // Test 4: Dragging selects the cells between press and release
+    _print(format_args!("[Terminal Test] Test 4: Mouse selection...\n"));
+    let mut shell = ShellTerminal::new(0, 20, 4);
+    let text = "echo hello world.txt and more";
+    feed(&mut shell, [WindowEvent::TextCommit(String::from(text))])?;
+    // Backwards from row 1 column 5 to row 0 column 15, across the wrap
+    let (x, y) = pointer(0, 15);
+    feed(&mut shell, [click(1, 5, MouseButton::Left, true), WindowEvent::MouseMove { x, y }, click(0, 15, MouseButton::Left, false)])?;
+    if shell.terminal().selected_text().as_deref() != Some("d.txt and m") {
+        return Err("Drag selected the wrong characters");
+    }
+    if !shell.terminal().is_selected(0, 15) || !shell.terminal().is_selected(1, 5) || shell.terminal().is_selected(1, 6) {
+        return Err("Selected cells not highlighted");
+    }
+    // The selection keeps to its text when the view scrolls
+    let mut terminal = Terminal::new(10, 3);
+    for line in 0..6 {
+        terminal.write(&format!("row {}\n", line));
+    }
+    terminal.start_selection(0, 0, SelectionUnit::Line);
+    terminal.scroll(2);
+    if terminal.selected_text().as_deref() != Some("row 4") || !terminal.is_selected(2, 0) || terminal.is_selected(0, 0) {
+        return Err("Selection lost by scrolling");
+    }
+    _print(format_args!("[Terminal Test] ✓ Drag selection covers the right text through scrolling\n"));
+
+    // Test 5: Selections are copied to the clipboard
+    _print(format_args!("[Terminal Test] Test 5: Copying...\n"));
+    if clipboard::text().as_deref() != Some("d.txt and m") {
+        return Err("Finished selection not copied");
+    }
+    // A double click selects the word, a third click its whole line
+    clipboard::clear();
+    feed(&mut shell, [click(0, 12, MouseButton::Left, true), click(0, 12, MouseButton::Left, false)])?;
+    feed(&mut shell, [click(0, 12, MouseButton::Left, true)])?;
+    feed(&mut shell, [click(0, 12, MouseButton::Left, false)])?;
+    if clipboard::text().as_deref() != Some("world.txt") {
+        return Err("Double click did not copy the word");
+    }
+    clipboard::clear();
+    feed(&mut shell, [ctrl_shift(b'C')])?;
+    if clipboard::text().as_deref() != Some("world.txt") {
+        return Err("Ctrl-Shift-C did not copy the selection");
+    }
+    feed(&mut shell, [click(0, 12, MouseButton::Left, true), click(0, 12, MouseButton::Left, false)])?;
+    if clipboard::text().as_deref() != Some(text) {
+        return Err("Triple click did not copy the line");
+    }
+    _print(format_args!("[Terminal Test] ✓ Drag, word and line selections placed on the clipboard\n"));
+
+    // Test 6: Pasting types the clipboard into the command line
+    _print(format_args!("[Terminal Test] Test 6: Pasting...\n"));
+    clipboard::set_text(String::from(" | wc"));
+    feed(&mut shell, [click(3, 0, MouseButton::Middle, true), click(3, 0, MouseButton::Middle, false)])?;
+    if shell.input() != format!("{} | wc", text) || shell.terminal().row_text(1) != " and more | wc" {
+        return Err("Middle click did not paste into the input");
+    }
+    clipboard::set_text(String::from(" -l\t"));
+    feed(&mut shell, [ctrl_shift(b'v')])?;
+    if shell.input() != format!("{} | wc -l", text) {
+        return Err("Ctrl-Shift-V did not paste into the input");
+    }
+    clipboard::clear();
+    _print(format_args!("[Terminal Test] ✓ Clipboard text injected into the input\n"));
+
+    // Test 7: Cursor positioning and character editing address the right cells
+    _print(format_args!("[Terminal Test] Test 7: Cursor positioning and editing...\n"));
+    let mut terminal = Terminal::new(10, 4);
+    // Delete two characters, insert two blanks on the wrapped row, then erase three cells
+    terminal.write("0123456789abc\x1b[3;3HX\x1b[1;5H\x1b[2P\x1b[2;2H\x1b[2@\x1b[1;2H\x1b[3X");
+    if terminal.row_text(0) != "0   6789" || terminal.row_text(1) != "a  bc" || terminal.row_text(2) != "  X" {
+        return Err("Characters not inserted, deleted or erased in place");
+    }
+    if terminal.cursor() != Some((0, 1)) {
+        return Err("Editing moved the cursor");
+    }
+    // Line and column set on their own
+    terminal.write("\x1b[4d\x1b[7`Z\x1b[2;4H\x1b[K");
+    if terminal.row_text(3) != "      Z" || terminal.row_text(1) != "a" || terminal.cursor() != Some((1, 3)) {
+        return Err("Row or column positioning wrong");
+    }
+    _print(format_args!("[Terminal Test] ✓ Cursor placed and cells edited where addressed\n"));
+
+    // Test 8: SGR attributes reach the cells and their pixels
+    _print(format_args!("[Terminal Test] Test 8: SGR attributes...\n"));
+    let mut terminal = Terminal::new(20, 2);
+    terminal.write("\x1b[1;4mB\x1b[22mU \x1b[24;9mS \x1b[0;2;31mD\x1b[0;8mH\x1b[28;38:2:10:20:30;48;5;21mC\x1b[m");
+    let underline = CellAttributes::UNDERLINE;
+    let strikethrough = CellAttributes::STRIKETHROUGH;
+    let cells = [
+        ('B', CellAttributes::BOLD | underline, DEFAULT_FOREGROUND, DEFAULT_BACKGROUND),
+        ('U', underline, DEFAULT_FOREGROUND, DEFAULT_BACKGROUND),
+        (' ', underline, DEFAULT_FOREGROUND, DEFAULT_BACKGROUND),
+        ('S', strikethrough, DEFAULT_FOREGROUND, DEFAULT_BACKGROUND),
+        (' ', strikethrough, DEFAULT_FOREGROUND, DEFAULT_BACKGROUND),
+        // Dim red is blended halfway into the background
+        ('D', CellAttributes::DIM, Color::rgb(117, 39, 39), DEFAULT_BACKGROUND),
+        ('H', CellAttributes::HIDDEN, DEFAULT_FOREGROUND, DEFAULT_BACKGROUND),
+        ('C', CellAttributes::empty(), Color::rgb(10, 20, 30), Color::rgb(0, 0, 255)),
+    ];
+    for (column, (ch, attributes, foreground, background)) in cells.into_iter().enumerate() {
+        let cell = terminal.cell(0, column).ok_or("Cell outside the view")?;
+        if cell.ch != ch || cell.attributes != attributes || cell.foreground != foreground || cell.background != background {
+            return Err("SGR attributes not applied to the cell");
+        }
+    }
+    // Underline is drawn along the bottom of the cell, strikethrough across its middle
+    let mut buffer = GraphicsBuffer::new(20 * 8, 2 * 16);
+    terminal.render(&mut buffer, 8, 16);
+    if buffer.get_pixel(2 * 8 + 4, 14) != DEFAULT_FOREGROUND || buffer.get_pixel(2 * 8 + 4, 8) != DEFAULT_BACKGROUND {
+        return Err("Underline not rendered");
+    }
+    if buffer.get_pixel(4 * 8 + 4, 8) != DEFAULT_FOREGROUND || buffer.get_pixel(4 * 8 + 4, 14) != DEFAULT_BACKGROUND {
+        return Err("Strikethrough not rendered");
+    }
+    _print(format_args!("[Terminal Test] ✓ Bold, dim, underline, strikethrough and hidden cells\n"));
+
+    // Test 9: A scroll region scrolls only its rows
+    _print(format_args!("[Terminal Test] Test 9: Scroll regions...\n"));
+    let mut terminal = Terminal::new(10, 5);
+    terminal.write("a\nb\nc\nd\ne\x1b[2;4r\x1b[4;1H\nf");
+    let rows = |terminal: &Terminal| (0..terminal.rows()).map(|row| terminal.row_text(row)).collect::<alloc::vec::Vec<String>>();
+    if rows(&terminal) != ["a", "c", "d", "f", "e"] || terminal.cursor() != Some((3, 1)) {
+        return Err("Line feed at the region's bottom did not scroll the region");
+    }
+    // Reverse index at the region's top scrolls it down, dropping its bottom row
+    terminal.write("\x1b[2;1H\x1bMg");
+    if rows(&terminal) != ["a", "g", "c", "d", "e"] {
+        return Err("Reverse index did not scroll the region down");
+    }
+    // Deleting and inserting lines keeps to the region, and below it line feed stays put
+    terminal.write("\x1b[3;1H\x1b[M\x1b[L\x1b[5;3H\nz");
+    if rows(&terminal) != ["a", "g", "", "d", "z"] || terminal.scrollback_rows() != 0 {
+        return Err("Line insertion or deletion escaped the region");
+    }
+    // Without a region, scrolling up moves the top row into the scrollback
+    terminal.write("\x1b[r\x1b[S\x1b[T");
+    if rows(&terminal) != ["", "g", "", "d", "z"] || terminal.scrollback_rows() != 1 {
+        return Err("Whole screen not scrolled");
+    }
+    terminal.scroll(1);
+    if terminal.row_text(0) != "a" {
+        return Err("Scrolled-off row missing from the scrollback");
+    }
+    _print(format_args!("[Terminal Test] ✓ Region scrolling, line insertion and deletion\n"));
+
+    // Test 10: The alternate screen leaves the main screen as it was
+    _print(format_args!("[Terminal Test] Test 10: Alternate screen...\n"));
+    let mut terminal = Terminal::new(10, 3);
+    terminal.write("one\ntwo\n\x1b[32m$ \x1b[?1049h\x1b[m\x1b[?25l");
+    if !terminal.is_alternate_screen() || !terminal.row_text(0).is_empty() || terminal.cursor() != Some((2, 2)) || terminal.is_cursor_visible() {
+        return Err("Alternate screen not entered empty");
+    }
+    // Full-screen output leaves no scrollback behind
+    terminal.write("\x1b[H\x1b[7mvim\x1b[m\x1b[3;1H~\n~");
+    terminal.scroll(5);
+    if rows(&terminal) != ["", "~", "~"] || terminal.scrollback_rows() != 0 || terminal.scroll_offset() != 0 {
+        return Err("Alternate screen kept a scrollback");
+    }
+    terminal.write("\x1b[?1049l\x1b[?25hls");
+    if terminal.is_alternate_screen() || rows(&terminal) != ["one", "two", "$ ls"] || !terminal.is_cursor_visible() {
+        return Err("Main screen not restored");
+    }
+    if terminal.cell(2, 3).map(|cell| cell.foreground) != Some(indexed_color(2)) {
+        return Err("Pen not restored with the main screen");
+    }
+    _print(format_args!("[Terminal Test] ✓ Alternate screen entered and left\n"));
+
+    // Test 11: Unknown sequences and control strings are consumed
+    _print(format_args!("[Terminal Test] Test 11: Unknown sequences...\n"));
+    let mut terminal = Terminal::new(20, 2);
+    terminal.write("a\x1b]0;title\x07b\x1b]2;x\x1b\\c\x1bP1$r0m\x1b\\d\x1b(Be\x1b#8f\x1b[>1;2mg\x1b[2 qh\x1b[?1000hi");
+    terminal.write("\x1b[=5uj\x1b=k\x1b[5;12;44;99;999zl\x1b_app\x1b\\m\x0en\x1b[1\x1b[32mo");
+    if terminal.row_text(0) != "abcdefghijklmno" || !terminal.row_text(1).is_empty() || terminal.cursor() != Some((0, 15)) {
+        return Err("Unknown sequence corrupted the text");
+    }
+    for column in 0..14 {
+        let cell = terminal.cell(0, column).ok_or("Cell outside the view")?;
+        if cell.foreground != DEFAULT_FOREGROUND || cell.background != DEFAULT_BACKGROUND || !cell.attributes.is_empty() {
+            return Err("Unknown sequence changed the pen");
+        }
+    }
+    // An escape inside a control sequence abandons it for the next
+    if terminal.cell(0, 14).map(|cell| cell.foreground) != Some(indexed_color(2)) {
+        return Err("Interrupted sequence not abandoned");
+    }
+    _print(format_args!("[Terminal Test] ✓ Unknown sequences consumed without effect\n"));
+
+    _print(format_args!("[Terminal Test] ✓ All terminal widget tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for the terminal widget
+pub fn test_terminal() {
+    _print(format_args!("[Terminal Test] ===========================================\n"));
+    _print(format_args!("[Terminal Test]          TERMINAL WIDGET TESTS\n"));
+    _print(format_args!("[Terminal Test] ===========================================\n"));
+
+    match run_terminal_tests() {
+        Ok(_) => _print(format_args!("[Terminal Test] ✓ All terminal widget tests PASSED\n")),
+        Err(e) => _print(format_args!("[Terminal Test] ✗ Terminal widget tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Terminal Test] ===========================================\n"));
+}
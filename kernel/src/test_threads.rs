@@ -0,0 +1,19 @@
+//! Test Threads
+//! Processes the kernel tests schedule only to hold state, parked so they never run
+
+use crate::process::{self, Process};
+
+/// Entry point of a kernel thread that blocks whenever it is run
+pub extern "C" fn parked_thread() -> ! {
+    loop {
+        process::block_current();
+    }
+}
+
+/// Schedule `process` already blocked, so it never runs
+pub fn add_blocked(process: Process) -> u64 {
+    let mut scheduler = process::get_smp_scheduler().lock();
+    let pid = scheduler.add_process(process);
+    scheduler.block_process(pid);
+    pid
+}
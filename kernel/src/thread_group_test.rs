@@ -0,0 +1,133 @@
+//! Thread Group Test
+//! Builds thread groups of a leader and two threads under a parent process and checks that
+//! SIGKILL to any of them ends the group, that one thread exiting leaves the others running
+//! and its signals to the rest, that the parent only hears of the group once its last
+//! thread is gone, and that exiting the group ends every thread
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use x86_64::VirtAddr;
+use crate::process::{self, CloneFlags, Process, ProcessState, Signal};
+use crate::serial::_print;
+use crate::test_threads::add_blocked;
+
+const ENTRY: u64 = 0x40_0000;
+const THREAD_STACK: u64 = 0x7FFF_FFFE_0000;
+
+/// A parked user process, a child of `parent` if given
+fn user_process(parent: Option<u64>, pids: &mut Vec<u64>) -> Result<u64, &'static str> {
+    let mut process = Process::user_process(String::from("thread-group-test"), VirtAddr::new(ENTRY))
+        .map_err(|_| "Failed to create process")?;
+    process.parent_pid = parent;
+    let pid = add_blocked(process);
+    pids.push(pid);
+    Ok(pid)
+}
+
+/// A parent process, a leader under it and two threads of the leader's group, all parked
+fn thread_group(pids: &mut Vec<u64>) -> Result<(u64, [u64; 3]), &'static str> {
+    let parent = user_process(None, pids)?;
+    let leader = user_process(Some(parent), pids)?;
+    let mut group = [leader; 3];
+    for thread in &mut group[1..] {
+        let clone = process::with_process(leader, |leader| {
+            process::clone_process(leader, CloneFlags::VM | CloneFlags::FILES | CloneFlags::THREAD, VirtAddr::new(ENTRY), VirtAddr::new(THREAD_STACK), 0)
+        });
+        *thread = add_blocked(clone.ok_or("Leader gone")?.map_err(|_| "Failed to clone a thread")?);
+        pids.push(*thread);
+    }
+    Ok((parent, group))
+}
+
+fn state(pid: u64) -> Option<ProcessState> {
+    process::with_process(pid, |process| process.state)
+}
+
+fn pending(pid: u64, signal: Signal) -> bool {
+    process::with_process(pid, |process| process.pending_signals & (1 << (signal as u8)) != 0).unwrap_or(false)
+}
+
+fn run_groups(pids: &mut Vec<u64>) -> Result<(), &'static str> {
+    // Test 1: SIGKILL to any thread ends the whole group
+    _print(format_args!("[Thread Group Test] Test 1: SIGKILL to the group...\n"));
+    let (parent, [leader, first, second]) = thread_group(pids)?;
+    if process::with_process(second, |thread| thread.thread_group) != Some(leader) {
+        return Err("Thread not in its leader's group");
+    }
+    process::send_signal(second, Signal::SIGKILL).map_err(|_| "Failed to send SIGKILL")?;
+    if [leader, first, second].iter().any(|&pid| process::is_process_alive(pid)) {
+        return Err("Thread survived SIGKILL to its group");
+    }
+    if state(leader) != Some(ProcessState::Zombie(-9)) || !pending(parent, Signal::SIGCHLD) {
+        return Err("Parent not told the group was killed");
+    }
+    _print(format_args!("[Thread Group Test] ✓ Group {} killed through thread {}\n", leader, second));
+
+    // Test 2: One thread exiting leaves the rest running, and the parent hears of the group
+    // only once its last thread has exited
+    _print(format_args!("[Thread Group Test] Test 2: Exit of single threads...\n"));
+    let (parent, [leader, first, second]) = thread_group(pids)?;
+    process::terminate_process(first);
+    if !process::is_process_alive(leader) || !process::is_process_alive(second) {
+        return Err("Thread exit ended other threads");
+    }
+    process::send_signal(first, Signal::SIGUSR1).map_err(|_| "Failed to signal the group")?;
+    process::send_thread_signal(leader, second, Signal::SIGUSR2).map_err(|_| "Failed to signal a thread")?;
+    if !pending(leader, Signal::SIGUSR1) || pending(leader, Signal::SIGUSR2) || !pending(second, Signal::SIGUSR2) {
+        return Err("Signal not delivered to the thread it was meant for");
+    }
+    if process::send_thread_signal(parent, second, Signal::SIGUSR1).is_ok() {
+        return Err("Thread signalled through another thread group");
+    }
+    process::terminate_process(leader);
+    let orphaned = process::with_process(second, |thread| thread.parent_pid != Some(leader));
+    if state(leader) != Some(ProcessState::Zombie(-1)) || orphaned != Some(false) || pending(parent, Signal::SIGCHLD) {
+        return Err("Leader's exit reported while a thread still runs");
+    }
+    process::send_signal(leader, Signal::SIGUSR1).map_err(|_| "Failed to signal the group")?;
+    if !pending(second, Signal::SIGUSR1) {
+        return Err("Signal to a group without its leader lost");
+    }
+    process::terminate_process(second);
+    if !pending(parent, Signal::SIGCHLD) {
+        return Err("Parent not told the last thread exited");
+    }
+    _print(format_args!("[Thread Group Test] ✓ Group {} reported once thread {} exited\n", leader, second));
+
+    // Test 3: Exiting the group ends every thread with its exit code
+    _print(format_args!("[Thread Group Test] Test 3: Group exit...\n"));
+    let (_, group) = thread_group(pids)?;
+    process::end_thread_group(group[0], None, 3);
+    let exit_codes = group.map(|pid| process::with_process(pid, |process| (process.state, process.get_exit_code())));
+    if exit_codes != [Some((ProcessState::Zombie(3), 3)), Some((ProcessState::Terminated, 3)), Some((ProcessState::Terminated, 3))] {
+        return Err("Group exit left a thread running or lost the exit code");
+    }
+    _print(format_args!("[Thread Group Test] ✓ All {} threads of group {} exited with 3\n", group.len(), group[0]));
+    Ok(())
+}
+
+pub fn run_thread_group_tests() -> Result<(), &'static str> {
+    let mut pids = Vec::new();
+    let result = run_groups(&mut pids);
+    for pid in pids.into_iter().filter(|&pid| process::is_process_alive(pid)) {
+        process::terminate_process(pid);
+    }
+    result?;
+
+    _print(format_args!("[Thread Group Test] ✓ All thread group tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for thread groups
+pub fn test_thread_group() {
+    _print(format_args!("[Thread Group Test] ===========================================\n"));
+    _print(format_args!("[Thread Group Test]            THREAD GROUP TESTS\n"));
+    _print(format_args!("[Thread Group Test] ===========================================\n"));
+
+    match run_thread_group_tests() {
+        Ok(_) => _print(format_args!("[Thread Group Test] ✓ All thread group tests PASSED\n")),
+        Err(e) => _print(format_args!("[Thread Group Test] ✗ Thread group tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Thread Group Test] ===========================================\n"));
+}
@@ -1,27 +1,29 @@
-use core::sync::atomic::{AtomicU64, AtomicBool, Ordering};
+use core::sync::atomic::{AtomicU64, AtomicU8, AtomicBool, Ordering};
 use x86_64::instructions::port::Port;
 use crate::arch::tsc;
 
+pub mod hpet;
+pub mod timezone;
+
 
 static SYSTEM_TIME: AtomicU64 = AtomicU64::new(0);
 static TIMER_FREQUENCY: AtomicU64 = AtomicU64::new(1000); // 1000 Hz default
 static UPTIME_TICKS: AtomicU64 = AtomicU64::new(0);
+/// Unix time the system booted at, from the RTC reading at startup
+static BOOT_TIME: AtomicU64 = AtomicU64::new(0);
 static TSC_DEADLINE_ENABLED: AtomicBool = AtomicBool::new(false);
+static CLOCK_SOURCE: AtomicU8 = AtomicU8::new(ClockSource::Pit as u8);
 
-// Real-time clock (RTC) ports
-const RTC_SECONDS: u16 = 0x00;
-const RTC_MINUTES: u16 = 0x02;
-const RTC_HOURS: u16 = 0x04;
-const RTC_DAY: u16 = 0x07;
-const RTC_MONTH: u16 = 0x08;
-const RTC_YEAR: u16 = 0x09;
-const RTC_STATUS_A: u16 = 0x0A;
-const RTC_STATUS_B: u16 = 0x0B;
-
-const CMOS_ADDRESS: u16 = 0x70;
-const CMOS_DATA: u16 = 0x71;
+/// Timer hardware driving the system tick
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ClockSource {
+    Pit,
+    TscDeadline,
+    Hpet,
+}
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct DateTime {
     pub year: u16,
     pub month: u8,
@@ -38,6 +40,41 @@ impl DateTime {
         let seconds_today = (self.hour as u64 * 3600) + (self.minute as u64 * 60) + (self.second as u64);
         (days_since_epoch * 86400) + seconds_today
     }
+    
+    /// Convert a Unix timestamp (seconds, may be negative) to a calendar date/time
+    pub fn from_timestamp(timestamp: i64) -> Self {
+        let days = timestamp.div_euclid(86400);
+        let seconds = timestamp.rem_euclid(86400) as u32;
+        let (year, month, day) = civil_from_days(days);
+        Self {
+            year: year as u16,
+            month,
+            day,
+            hour: (seconds / 3600) as u8,
+            minute: ((seconds / 60) % 60) as u8,
+            second: (seconds % 60) as u8,
+        }
+    }
+    
+    /// Day of the week, 0 = Sunday
+    pub fn weekday(&self) -> u8 {
+        // 1970-01-01 was a Thursday
+        ((days_since_epoch(self.year, self.month, self.day) + 4) % 7) as u8
+    }
+}
+
+/// Days since 1970-01-01 to (year, month, day), proleptic Gregorian
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u8;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
 }
 
 fn days_since_epoch(year: u16, month: u8, day: u8) -> u64 {
@@ -63,62 +100,26 @@ fn days_since_epoch(year: u16, month: u8, day: u8) -> u64 {
     }
     
     // Add remaining days
-    days + (day - 1) as u64
+    days + day.saturating_sub(1) as u64
 }
 
 fn is_leap_year(year: u16) -> bool {
     (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0)
 }
 
-fn bcd_to_binary(bcd: u8) -> u8 {
-    (bcd & 0x0F) + ((bcd >> 4) * 10)
-}
-
-fn read_rtc_register(reg: u16) -> u8 {
-    unsafe {
-        // SAFETY: This is unsafe because:
-        // - CMOS_ADDRESS (0x70) and CMOS_DATA (0x71) are standard x86 CMOS/RTC I/O ports
-        // - Port I/O requires privileged instructions that can affect system state
-        // - The two-step process (write address, read data) must be atomic
-        // - No other code should access CMOS ports concurrently
-        // - reg parameter must be a valid RTC register offset
-        let mut addr_port = Port::new(CMOS_ADDRESS);
-        let mut data_port = Port::new(CMOS_DATA);
-        
-        addr_port.write(reg as u8);
-        data_port.read()
+/// Number of days in a month (1-12)
+pub fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
     }
 }
 
-fn is_rtc_updating() -> bool {
-    read_rtc_register(RTC_STATUS_A) & 0x80 != 0
-}
-
+/// Read the wall-clock time from the CMOS RTC (UTC)
 pub fn read_rtc() -> DateTime {
-    // Wait for RTC to not be updating
-    while is_rtc_updating() {
-        core::hint::spin_loop();
-    }
-    
-    let second = read_rtc_register(RTC_SECONDS);
-    let minute = read_rtc_register(RTC_MINUTES);
-    let hour = read_rtc_register(RTC_HOURS);
-    let day = read_rtc_register(RTC_DAY);
-    let month = read_rtc_register(RTC_MONTH);
-    let year = read_rtc_register(RTC_YEAR);
-    
-    // Check if RTC is in BCD mode
-    let status_b = read_rtc_register(RTC_STATUS_B);
-    let is_bcd = (status_b & 0x04) == 0;
-    
-    DateTime {
-        year: if is_bcd { bcd_to_binary(year) as u16 + 2000 } else { year as u16 + 2000 },
-        month: if is_bcd { bcd_to_binary(month) } else { month },
-        day: if is_bcd { bcd_to_binary(day) } else { day },
-        hour: if is_bcd { bcd_to_binary(hour) } else { hour },
-        minute: if is_bcd { bcd_to_binary(minute) } else { minute },
-        second: if is_bcd { bcd_to_binary(second) } else { second },
-    }
+    crate::drivers::rtc::read()
 }
 
 pub fn init() {
@@ -126,24 +127,10 @@ pub fn init() {
     let rtc_time = read_rtc();
     let timestamp = rtc_time.to_timestamp();
     SYSTEM_TIME.store(timestamp, Ordering::SeqCst);
+    BOOT_TIME.store(timestamp.saturating_sub(get_uptime_seconds()), Ordering::SeqCst);
+    timezone::init();
     
-    // Initialize TSC subsystem first
-    if let Ok(()) = tsc::init() {
-        crate::serial::_print(format_args!("[Timer] TSC initialized with frequency: {} Hz\n", tsc::get_frequency()));
-        
-        // Try to enable TSC-deadline timer if supported
-        if crate::arch::has_cpu_feature(crate::arch::CpuFeature::TscDeadline) {
-            init_tsc_deadline_timer();
-        } else {
-            crate::serial::_print(format_args!("[Timer] TSC-deadline not supported, falling back to PIT\n"));
-            init_pit();
-        }
-    } else {
-        crate::serial::_print(format_args!("[Timer] TSC initialization failed, using PIT\n"));
-        init_pit();
-        // Fallback TSC calibration for performance counters
-        calibrate_tsc_fallback();
-    }
+    init_clock_source();
 }
 
 /// Initialize timer with APIC support
@@ -152,26 +139,84 @@ pub fn init_with_apic() {
     let rtc_time = read_rtc();
     let timestamp = rtc_time.to_timestamp();
     SYSTEM_TIME.store(timestamp, Ordering::SeqCst);
+    BOOT_TIME.store(timestamp.saturating_sub(get_uptime_seconds()), Ordering::SeqCst);
+    timezone::init();
     
-    // Initialize TSC subsystem first
-    if let Ok(()) = tsc::init() {
-        crate::serial::_print(format_args!("[Timer] TSC initialized with frequency: {} Hz\n", tsc::get_frequency()));
-        
-        // Enable TSC-deadline timer if supported
-        if crate::arch::has_cpu_feature(crate::arch::CpuFeature::TscDeadline) {
-            init_tsc_deadline_timer();
-        } else {
-            crate::serial::_print(format_args!("[Timer] TSC-deadline not supported, falling back to PIT\n"));
-            init_pit();
+    init_clock_source();
+}
+
+/// Bring up the HPET and TSC, then pick the tick source.
+/// `clocksource=tsc|hpet|pit` on the kernel command line overrides auto-detection.
+fn init_clock_source() {
+    // HPET first so TSC calibration doesn't need the PIT
+    if let Err(e) = hpet::init() {
+        crate::serial::_print(format_args!("[Timer] HPET not available: {}\n", e));
+    }
+    
+    // Initialize TSC subsystem
+    let tsc_initialized = match tsc::init() {
+        Ok(()) => {
+            crate::serial::_print(format_args!("[Timer] TSC initialized with frequency: {} Hz\n", tsc::get_frequency()));
+            true
         }
-    } else {
-        crate::serial::_print(format_args!("[Timer] TSC initialization failed, using PIT\n"));
-        init_pit();
+        Err(_) => {
+            crate::serial::_print(format_args!("[Timer] TSC initialization failed\n"));
+            false
+        }
+    };
+    
+    match crate::cmdline::get("clocksource") {
+        Some("pit") => init_pit(),
+        Some("hpet") => init_fallback_timer(),
+        requested => {
+            if let Some(other) = requested.filter(|&r| r != "tsc") {
+                crate::serial::_print(format_args!("[Timer] Unknown clocksource '{}', auto-detecting\n", other));
+            }
+            // Enable TSC-deadline timer if supported
+            if tsc_initialized && crate::arch::has_cpu_feature(crate::arch::CpuFeature::TscDeadline) {
+                init_tsc_deadline_timer();
+            } else {
+                crate::serial::_print(format_args!("[Timer] TSC-deadline not available\n"));
+                init_fallback_timer();
+            }
+        }
+    }
+    
+    if !tsc_initialized {
         // Fallback TSC calibration for performance counters
         calibrate_tsc_fallback();
     }
 }
 
+/// Tick from the HPET when present, otherwise the PIT
+fn init_fallback_timer() {
+    const TARGET_FREQUENCY: u64 = 1000; // 1000 Hz (1ms intervals)
+    
+    if hpet::is_available() {
+        match hpet::start_system_tick(TARGET_FREQUENCY) {
+            Ok(()) => {
+                TIMER_FREQUENCY.store(TARGET_FREQUENCY, Ordering::SeqCst);
+                CLOCK_SOURCE.store(ClockSource::Hpet as u8, Ordering::SeqCst);
+                crate::serial::_print(format_args!("[Timer] HPET timer initialized at {} Hz\n", TARGET_FREQUENCY));
+                return;
+            }
+            Err(e) => crate::serial::_print(format_args!("[Timer] HPET tick failed: {}\n", e)),
+        }
+    }
+    
+    crate::serial::_print(format_args!("[Timer] Using PIT\n"));
+    init_pit();
+}
+
+/// Timer hardware currently driving the system tick
+pub fn clock_source() -> ClockSource {
+    match CLOCK_SOURCE.load(Ordering::SeqCst) {
+        x if x == ClockSource::TscDeadline as u8 => ClockSource::TscDeadline,
+        x if x == ClockSource::Hpet as u8 => ClockSource::Hpet,
+        _ => ClockSource::Pit,
+    }
+}
+
 fn init_pit() {
     const PIT_FREQUENCY: u32 = 1193182; // PIT base frequency
     const TARGET_FREQUENCY: u32 = 1000; // 1000 Hz (1ms intervals)
@@ -191,6 +236,7 @@ fn init_pit() {
     }
     
     TIMER_FREQUENCY.store(TARGET_FREQUENCY as u64, Ordering::SeqCst);
+    CLOCK_SOURCE.store(ClockSource::Pit as u8, Ordering::SeqCst);
 }
 
 /// Initialize TSC-deadline timer
@@ -198,8 +244,8 @@ fn init_tsc_deadline_timer() {
     const TARGET_FREQUENCY: u64 = 1000; // 1000 Hz (1ms intervals)
     
     if !tsc::is_invariant_available() {
-        crate::serial::_print(format_args!("[Timer] Invariant TSC not available, falling back to PIT\n"));
-        init_pit();
+        crate::serial::_print(format_args!("[Timer] Invariant TSC not available\n"));
+        init_fallback_timer();
         return;
     }
     
@@ -214,10 +260,11 @@ fn init_tsc_deadline_timer() {
         
         TSC_DEADLINE_ENABLED.store(true, Ordering::SeqCst);
         TIMER_FREQUENCY.store(TARGET_FREQUENCY, Ordering::SeqCst);
+        CLOCK_SOURCE.store(ClockSource::TscDeadline as u8, Ordering::SeqCst);
         crate::serial::_print(format_args!("[Timer] TSC-deadline timer initialized at {} Hz\n", TARGET_FREQUENCY));
     } else {
-        crate::serial::_print(format_args!("[Timer] TSC not calibrated, falling back to PIT\n"));
-        init_pit();
+        crate::serial::_print(format_args!("[Timer] TSC not calibrated\n"));
+        init_fallback_timer();
     }
 }
 
@@ -320,6 +367,21 @@ pub fn get_system_uptime() -> u64 {
     get_uptime_seconds()
 }
 
+/// Unix time the system booted at
+pub fn boot_time() -> u64 {
+    BOOT_TIME.load(Ordering::SeqCst)
+}
+
+/// Timer interrupts per second
+pub fn timer_frequency() -> u64 {
+    TIMER_FREQUENCY.load(Ordering::SeqCst)
+}
+
+/// Current wall-clock time in the configured timezone
+pub fn local_now() -> timezone::LocalTime {
+    timezone::to_local(get_timestamp() as i64)
+}
+
 pub fn get_datetime() -> DateTime {
     // For now, just read from RTC each time
     // In a real implementation, we'd maintain this in memory
@@ -343,7 +405,13 @@ static TSC_FREQUENCY_FALLBACK: AtomicU64 = AtomicU64::new(0);
 
 /// Fallback TSC calibration for performance counters when arch TSC init fails
 pub fn calibrate_tsc_fallback() {
-    // Calibrate TSC frequency using PIT
+    if let Some(tsc_freq) = hpet::calibrate_tsc() {
+        TSC_FREQUENCY_FALLBACK.store(tsc_freq, Ordering::SeqCst);
+        crate::serial::_print(format_args!("[Timer] Fallback TSC calibrated against HPET at {} Hz\n", tsc_freq));
+        return;
+    }
+    
+    // Calibrate TSC frequency using the tick counter
     let start_tsc = tsc::read_tsc();
     let start_time = get_uptime_ms();
     
@@ -385,6 +453,8 @@ pub fn get_precise_time_ns() -> u64 {
         
         if freq > 0 {
             (current_tsc * 1_000_000_000) / freq
+        } else if hpet::is_available() {
+            hpet::now_ns()
         } else {
             // Fallback to millisecond precision
             get_uptime_ms() * 1_000_000
@@ -0,0 +1,351 @@
+//! HPET (High Precision Event Timer) support
+//! Maps the HPET block described by the ACPI HPET table and provides a monotonic counter,
+//! one-shot/periodic comparator interrupts, and TSC calibration without the PIT
+
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use x86_64::structures::paging::{Mapper, Page, PageTableFlags, PhysFrame, Size4KiB};
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::acpi::{GenericAddress, SdtHeader, ADDRESS_SPACE_SYSTEM_MEMORY};
+use crate::interrupts::InterruptIndex;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+struct HpetTable {
+    header: SdtHeader,
+    event_timer_block_id: u32,
+    base_address: GenericAddress,
+    hpet_number: u8,
+    minimum_tick: u16,
+    page_protection: u8,
+}
+
+/// General registers
+const REG_CAPABILITIES: u64 = 0x000;
+const REG_CONFIG: u64 = 0x010;
+const REG_INTERRUPT_STATUS: u64 = 0x020;
+const REG_MAIN_COUNTER: u64 = 0x0F0;
+
+/// Per-timer registers
+const fn timer_config_reg(timer: u8) -> u64 {
+    0x100 + 0x20 * timer as u64
+}
+
+const fn timer_comparator_reg(timer: u8) -> u64 {
+    0x108 + 0x20 * timer as u64
+}
+
+/// Capability and configuration bits
+const CAP_COUNTER_64BIT: u64 = 1 << 13;
+const CAP_LEGACY_ROUTE: u64 = 1 << 15;
+const CONFIG_ENABLE: u64 = 1 << 0;
+const CONFIG_LEGACY_ROUTE: u64 = 1 << 1;
+
+const TIMER_LEVEL_TRIGGERED: u64 = 1 << 1;
+const TIMER_INT_ENABLE: u64 = 1 << 2;
+const TIMER_PERIODIC: u64 = 1 << 3;
+const TIMER_PERIODIC_CAP: u64 = 1 << 4;
+const TIMER_VAL_SET: u64 = 1 << 6;
+const TIMER_ROUTE_SHIFT: u64 = 9;
+const TIMER_ROUTE_MASK: u64 = 0x1F << TIMER_ROUTE_SHIFT;
+const TIMER_FSB_ENABLE: u64 = 1 << 14;
+
+const FEMTOS_PER_NANO: u64 = 1_000_000;
+/// The spec caps the counter period at 100ns
+const MAX_PERIOD_FS: u64 = 100_000_000;
+const MAX_TIMERS: usize = 32;
+
+/// Comparator used for the system tick when HPET is the clock source
+pub const SYSTEM_TIMER: u8 = 0;
+
+/// Comparator operating modes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerMode {
+    OneShot,
+    Periodic,
+}
+
+static HPET_BASE: AtomicU64 = AtomicU64::new(0);
+static PERIOD_FS: AtomicU64 = AtomicU64::new(0);
+static NUM_TIMERS: AtomicU8 = AtomicU8::new(0);
+static COUNTER_64BIT: AtomicBool = AtomicBool::new(false);
+static LEGACY_ROUTE_CAPABLE: AtomicBool = AtomicBool::new(false);
+static SYSTEM_TICK: AtomicBool = AtomicBool::new(false);
+/// Highest counter value seen, used to extend 32-bit counters
+static LAST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+const ZERO: AtomicU64 = AtomicU64::new(0);
+static FIRE_COUNT: [AtomicU64; MAX_TIMERS] = [ZERO; MAX_TIMERS];
+static LAST_FIRE: [AtomicU64; MAX_TIMERS] = [ZERO; MAX_TIMERS];
+
+fn read_reg(offset: u64) -> u64 {
+    let base = HPET_BASE.load(Ordering::Acquire);
+    unsafe { core::ptr::read_volatile((base + offset) as *const u64) }
+}
+
+fn write_reg(offset: u64, value: u64) {
+    let base = HPET_BASE.load(Ordering::Acquire);
+    unsafe { core::ptr::write_volatile((base + offset) as *mut u64, value) }
+}
+
+/// Map the register block uncached, alongside the I/O APIC mapping
+fn map_registers(base: PhysAddr) -> Result<VirtAddr, &'static str> {
+    crate::memory::with_mapper(|mapper| {
+        let virt_addr = VirtAddr::new(0xFFFF_8000_0000_0000 + base.as_u64());
+        let page: Page<Size4KiB> = Page::containing_address(virt_addr);
+        let frame: PhysFrame<Size4KiB> = PhysFrame::containing_address(base);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+
+        crate::memory::with_frame_allocator(|frame_allocator| {
+            unsafe {
+                mapper.map_to(page, frame, flags, frame_allocator)
+                    .map_err(|_| "Failed to map HPET registers")?
+                    .flush();
+            }
+            Ok::<(), &str>(())
+        })?;
+        Ok(virt_addr)
+    })
+}
+
+/// Locate the HPET through ACPI, map it and start the main counter
+pub fn init() -> Result<(), &'static str> {
+    if is_available() {
+        return Ok(());
+    }
+
+    let table_addr = crate::acpi::find_table(b"HPET").ok_or("No ACPI HPET table")?;
+    let table: HpetTable = unsafe { crate::acpi::read_table(table_addr) };
+    let address = table.base_address;
+    let phys = address.address;
+    if address.address_space != ADDRESS_SPACE_SYSTEM_MEMORY || phys == 0 {
+        return Err("HPET is not memory mapped");
+    }
+
+    let base = map_registers(PhysAddr::new(phys))?;
+    HPET_BASE.store(base.as_u64(), Ordering::Release);
+
+    let capabilities = read_reg(REG_CAPABILITIES);
+    let period_fs = capabilities >> 32;
+    if period_fs == 0 || period_fs > MAX_PERIOD_FS {
+        HPET_BASE.store(0, Ordering::Release);
+        return Err("HPET reports an invalid counter period");
+    }
+    let num_timers = (((capabilities >> 8) & 0x1F) + 1) as u8;
+
+    PERIOD_FS.store(period_fs, Ordering::SeqCst);
+    NUM_TIMERS.store(num_timers, Ordering::SeqCst);
+    COUNTER_64BIT.store((capabilities & CAP_COUNTER_64BIT) != 0, Ordering::SeqCst);
+    LEGACY_ROUTE_CAPABLE.store((capabilities & CAP_LEGACY_ROUTE) != 0, Ordering::SeqCst);
+
+    // Quiesce every comparator before enabling the counter
+    for timer in 0..num_timers {
+        let config = read_reg(timer_config_reg(timer));
+        write_reg(timer_config_reg(timer), config & !(TIMER_INT_ENABLE | TIMER_PERIODIC | TIMER_FSB_ENABLE));
+    }
+    write_reg(REG_INTERRUPT_STATUS, u32::MAX as u64);
+    write_reg(REG_CONFIG, (read_reg(REG_CONFIG) & !CONFIG_LEGACY_ROUTE) | CONFIG_ENABLE);
+
+    crate::serial::_print(format_args!(
+        "[HPET] {} timers, {} Hz, {}-bit counter at {:#x}\n",
+        num_timers,
+        frequency(),
+        if COUNTER_64BIT.load(Ordering::SeqCst) { 64 } else { 32 },
+        phys
+    ));
+    Ok(())
+}
+
+/// Whether the HPET has been initialized
+pub fn is_available() -> bool {
+    HPET_BASE.load(Ordering::Acquire) != 0
+}
+
+/// Counter frequency in Hz
+pub fn frequency() -> u64 {
+    match PERIOD_FS.load(Ordering::SeqCst) {
+        0 => 0,
+        period => 1_000_000_000_000_000 / period,
+    }
+}
+
+/// Number of comparators
+pub fn num_timers() -> u8 {
+    NUM_TIMERS.load(Ordering::SeqCst)
+}
+
+/// Monotonic main counter value
+pub fn read_counter() -> u64 {
+    if !is_available() {
+        return 0;
+    }
+
+    let raw = read_reg(REG_MAIN_COUNTER);
+    if COUNTER_64BIT.load(Ordering::Relaxed) {
+        return raw;
+    }
+
+    // Extend the 32-bit counter; it must be sampled at least once per wrap
+    let raw = raw & 0xFFFF_FFFF;
+    let last = LAST_COUNTER.load(Ordering::Acquire);
+    let mut value = (last & !0xFFFF_FFFF) | raw;
+    if value < last {
+        value += 1 << 32;
+    }
+    LAST_COUNTER.fetch_max(value, Ordering::AcqRel).max(value)
+}
+
+/// Convert counter ticks to nanoseconds
+pub fn ticks_to_ns(ticks: u64) -> u64 {
+    ((ticks as u128 * PERIOD_FS.load(Ordering::Relaxed) as u128) / FEMTOS_PER_NANO as u128) as u64
+}
+
+/// Convert nanoseconds to counter ticks
+pub fn ns_to_ticks(ns: u64) -> u64 {
+    match PERIOD_FS.load(Ordering::Relaxed) {
+        0 => 0,
+        period => ((ns as u128 * FEMTOS_PER_NANO as u128) / period as u128) as u64,
+    }
+}
+
+/// Nanoseconds since the counter was enabled
+pub fn now_ns() -> u64 {
+    ticks_to_ns(read_counter())
+}
+
+/// Busy-wait on the main counter
+pub fn delay_us(us: u64) {
+    let target = read_counter() + ns_to_ticks(us * 1000);
+    while read_counter() < target {
+        core::hint::spin_loop();
+    }
+}
+
+/// Measure the TSC frequency against the HPET over 10ms
+pub fn calibrate_tsc() -> Option<u64> {
+    if !is_available() {
+        return None;
+    }
+
+    let start_counter = read_counter();
+    let start_tsc = crate::arch::tsc::read_tsc_serialized();
+    delay_us(10_000);
+    let end_tsc = crate::arch::tsc::read_tsc_serialized();
+    let elapsed_ns = ticks_to_ns(read_counter() - start_counter);
+
+    if elapsed_ns == 0 {
+        return None;
+    }
+    Some(((end_tsc - start_tsc) as u128 * 1_000_000_000 / elapsed_ns as u128) as u64)
+}
+
+/// Pick an I/O APIC input for a comparator, avoiding the ISA range when possible
+fn select_route(timer: u8) -> Option<u8> {
+    let route_cap = read_reg(timer_config_reg(timer)) >> 32;
+    if route_cap == 0 {
+        return None;
+    }
+    let above_isa = route_cap & !0xFFFF;
+    let candidates = if above_isa != 0 { above_isa } else { route_cap };
+    Some(candidates.trailing_zeros() as u8)
+}
+
+/// Program a comparator to interrupt after `interval_ns`, once or periodically.
+/// Interrupts are delivered through the I/O APIC; without it only the system timer
+/// can fire, via the legacy replacement route onto IRQ0.
+pub fn arm_timer(timer: u8, mode: TimerMode, interval_ns: u64) -> Result<(), &'static str> {
+    if !is_available() {
+        return Err("HPET not available");
+    }
+    if timer >= num_timers() {
+        return Err("Invalid HPET timer");
+    }
+
+    let mut config = read_reg(timer_config_reg(timer));
+    if mode == TimerMode::Periodic && (config & TIMER_PERIODIC_CAP) == 0 {
+        return Err("HPET timer does not support periodic mode");
+    }
+    let interval = ns_to_ticks(interval_ns).max(1);
+
+    config &= !(TIMER_PERIODIC | TIMER_FSB_ENABLE | TIMER_ROUTE_MASK | TIMER_INT_ENABLE);
+    write_reg(timer_config_reg(timer), config);
+
+    if crate::apic::is_apic_enabled() {
+        // Level-triggered so the status register says which comparator fired
+        let gsi = select_route(timer).ok_or("HPET timer has no usable interrupt route")?;
+        crate::apic::route_irq(gsi, InterruptIndex::Hpet.as_u8())?;
+        config |= TIMER_LEVEL_TRIGGERED | ((gsi as u64) << TIMER_ROUTE_SHIFT);
+    } else if timer == SYSTEM_TIMER && LEGACY_ROUTE_CAPABLE.load(Ordering::SeqCst) {
+        config &= !TIMER_LEVEL_TRIGGERED;
+        write_reg(REG_CONFIG, read_reg(REG_CONFIG) | CONFIG_LEGACY_ROUTE);
+    } else {
+        return Err("HPET timer interrupts require the I/O APIC");
+    }
+
+    let first = read_counter() + interval;
+    match mode {
+        TimerMode::OneShot => {
+            write_reg(timer_config_reg(timer), config | TIMER_INT_ENABLE);
+            write_reg(timer_comparator_reg(timer), first);
+        }
+        TimerMode::Periodic => {
+            // With VAL_SET the first write sets the comparator, the second the period
+            write_reg(timer_config_reg(timer), config | TIMER_INT_ENABLE | TIMER_PERIODIC | TIMER_VAL_SET);
+            write_reg(timer_comparator_reg(timer), first);
+            write_reg(timer_comparator_reg(timer), interval);
+        }
+    }
+    Ok(())
+}
+
+/// Stop a comparator from interrupting
+pub fn disarm_timer(timer: u8) {
+    if !is_available() || timer >= num_timers() {
+        return;
+    }
+    let config = read_reg(timer_config_reg(timer));
+    write_reg(timer_config_reg(timer), config & !(TIMER_INT_ENABLE | TIMER_PERIODIC));
+    write_reg(REG_INTERRUPT_STATUS, 1 << timer);
+}
+
+/// Drive the system tick from the HPET at `frequency_hz`
+pub fn start_system_tick(frequency_hz: u64) -> Result<(), &'static str> {
+    if frequency_hz == 0 {
+        return Err("Invalid tick frequency");
+    }
+    arm_timer(SYSTEM_TIMER, TimerMode::Periodic, 1_000_000_000 / frequency_hz)?;
+    SYSTEM_TICK.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Number of interrupts a comparator has delivered
+pub fn fire_count(timer: u8) -> u64 {
+    FIRE_COUNT.get(timer as usize).map_or(0, |count| count.load(Ordering::SeqCst))
+}
+
+/// Main counter value when a comparator last interrupted
+pub fn last_fire_counter(timer: u8) -> u64 {
+    LAST_FIRE.get(timer as usize).map_or(0, |at| at.load(Ordering::SeqCst))
+}
+
+/// Called from the HPET interrupt vector
+pub fn handle_interrupt() {
+    let now = read_counter();
+    let status = read_reg(REG_INTERRUPT_STATUS) & ((1u64 << num_timers()) - 1);
+    // Writing the set bits back clears them and deasserts the level-triggered line
+    write_reg(REG_INTERRUPT_STATUS, status);
+
+    for timer in 0..num_timers() {
+        if (status & (1 << timer)) == 0 {
+            continue;
+        }
+        FIRE_COUNT[timer as usize].fetch_add(1, Ordering::SeqCst);
+        LAST_FIRE[timer as usize].store(now, Ordering::SeqCst);
+
+        if timer == SYSTEM_TIMER && SYSTEM_TICK.load(Ordering::SeqCst) {
+            crate::time::tick();
+            crate::process::schedule_tick();
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Timezone support for RaeenOS
+//! Parses POSIX TZ rules (e.g. `EST5EDT,M3.2.0,M11.1.0`), ships a minimal tzdata subset
+//! keyed by zone name, and converts between UTC and local time across DST transitions
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use spin::RwLock;
+
+use super::{days_in_month, DateTime};
+
+/// Minimal tzdata subset: zone name to POSIX TZ rule
+const TZDATA: &[(&str, &str)] = &[
+    ("UTC", "UTC0"),
+    ("Etc/UTC", "UTC0"),
+    ("America/New_York", "EST5EDT,M3.2.0,M11.1.0"),
+    ("America/Chicago", "CST6CDT,M3.2.0,M11.1.0"),
+    ("America/Denver", "MST7MDT,M3.2.0,M11.1.0"),
+    ("America/Phoenix", "MST7"),
+    ("America/Los_Angeles", "PST8PDT,M3.2.0,M11.1.0"),
+    ("America/Anchorage", "AKST9AKDT,M3.2.0,M11.1.0"),
+    ("America/St_Johns", "NST3:30NDT,M3.2.0,M11.1.0"),
+    ("America/Sao_Paulo", "<-03>3"),
+    ("Pacific/Honolulu", "HST10"),
+    ("Europe/London", "GMT0BST,M3.5.0/1,M10.5.0"),
+    ("Europe/Berlin", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Paris", "CET-1CEST,M3.5.0,M10.5.0/3"),
+    ("Europe/Moscow", "MSK-3"),
+    ("Asia/Kolkata", "IST-5:30"),
+    ("Asia/Shanghai", "CST-8"),
+    ("Asia/Tokyo", "JST-9"),
+    ("Australia/Sydney", "AEST-10AEDT,M10.1.0,M4.1.0/3"),
+    ("Pacific/Auckland", "NZST-12NZDT,M9.5.0,M4.1.0/3"),
+];
+
+/// Default transition time (02:00 local) when a rule omits one
+const DEFAULT_TRANSITION_SECONDS: i32 = 2 * 3600;
+
+/// `Mm.w.d/time`: day `d` (0 = Sunday) of week `w` (5 = last) of month `m`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransitionRule {
+    pub month: u8,
+    pub week: u8,
+    pub weekday: u8,
+    /// Local wall-clock seconds after midnight
+    pub time: i32,
+}
+
+impl TransitionRule {
+    /// Day of the month this rule selects in `year`
+    fn day_in(&self, year: u16) -> u8 {
+        let first_weekday = DateTime { year, month: self.month, day: 1, hour: 0, minute: 0, second: 0 }.weekday();
+        let first = 1 + (self.weekday + 7 - first_weekday) % 7;
+        let mut day = first + (self.week - 1) * 7;
+        while day > days_in_month(year, self.month) {
+            day -= 7;
+        }
+        day
+    }
+
+    /// UTC instant of this transition in `year`, given the offset in effect before it
+    fn utc_instant(&self, year: u16, offset_before: i32) -> i64 {
+        let day = self.day_in(year);
+        let midnight = DateTime { year, month: self.month, day, hour: 0, minute: 0, second: 0 }.to_timestamp() as i64;
+        midnight + self.time as i64 - offset_before as i64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DstRule {
+    pub abbreviation: String,
+    /// Seconds east of UTC while DST is in effect
+    pub offset: i32,
+    pub start: TransitionRule,
+    pub end: TransitionRule,
+}
+
+/// A timezone: standard offset plus an optional DST rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeZone {
+    pub name: String,
+    pub abbreviation: String,
+    /// Seconds east of UTC (POSIX strings use the opposite sign)
+    pub offset: i32,
+    pub dst: Option<DstRule>,
+}
+
+/// A UTC instant expressed in a timezone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalTime {
+    pub datetime: DateTime,
+    /// Seconds east of UTC in effect at this instant
+    pub utc_offset: i32,
+    pub is_dst: bool,
+    pub abbreviation: String,
+}
+
+const WEEKDAY_NAMES: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+impl LocalTime {
+    /// `Thu 2024-08-15 08:30:45 EDT`
+    pub fn format(&self) -> String {
+        let dt = &self.datetime;
+        format!(
+            "{} {:04}-{:02}-{:02} {:02}:{:02}:{:02} {}",
+            WEEKDAY_NAMES[dt.weekday() as usize],
+            dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second,
+            self.abbreviation
+        )
+    }
+
+    /// `2024-08-15T08:30:45-04:00`
+    pub fn format_iso8601(&self) -> String {
+        let dt = &self.datetime;
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}",
+            dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second,
+            format_offset(self.utc_offset)
+        )
+    }
+}
+
+/// `+05:30` / `-03:30`
+pub fn format_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let abs = offset.unsigned_abs();
+    format!("{}{:02}:{:02}", sign, abs / 3600, (abs / 60) % 60)
+}
+
+impl TimeZone {
+    pub fn utc() -> Self {
+        Self { name: "UTC".to_string(), abbreviation: "UTC".to_string(), offset: 0, dst: None }
+    }
+
+    /// Look up a zone in the built-in tzdata subset, or parse a POSIX TZ string
+    pub fn load(spec: &str) -> Result<Self, &'static str> {
+        match TZDATA.iter().find(|(name, _)| *name == spec) {
+            Some((name, rule)) => {
+                let mut zone = Self::parse_posix(rule)?;
+                zone.name = name.to_string();
+                Ok(zone)
+            }
+            None => Self::parse_posix(spec),
+        }
+    }
+
+    /// Parse a POSIX TZ string such as `CET-1CEST,M3.5.0,M10.5.0/3`
+    pub fn parse_posix(spec: &str) -> Result<Self, &'static str> {
+        let mut parser = Parser { rest: spec };
+        let abbreviation = parser.abbreviation()?;
+        let offset = -parser.offset()?;
+
+        let dst = if parser.rest.is_empty() {
+            None
+        } else {
+            let dst_abbreviation = parser.abbreviation()?;
+            let dst_offset = if parser.rest.starts_with(',') { offset + 3600 } else { -parser.offset()? };
+            if !parser.eat(',') {
+                return Err("DST rule requires transition dates");
+            }
+            let start = parser.transition()?;
+            if !parser.eat(',') {
+                return Err("DST rule requires an end date");
+            }
+            let end = parser.transition()?;
+            Some(DstRule { abbreviation: dst_abbreviation, offset: dst_offset, start, end })
+        };
+
+        if !parser.rest.is_empty() {
+            return Err("Trailing characters in TZ string");
+        }
+
+        Ok(Self { name: spec.to_string(), abbreviation, offset, dst })
+    }
+
+    /// Whether DST is in effect at a UTC instant
+    pub fn is_dst(&self, utc: i64) -> bool {
+        let dst = match &self.dst {
+            Some(dst) => dst,
+            None => return false,
+        };
+        let year = DateTime::from_timestamp(utc + self.offset as i64).year;
+        let start = dst.start.utc_instant(year, self.offset);
+        let end = dst.end.utc_instant(year, dst.offset);
+        if start < end {
+            utc >= start && utc < end
+        } else {
+            // Southern hemisphere: DST spans the new year
+            utc >= start || utc < end
+        }
+    }
+
+    /// Offset from UTC in effect at a UTC instant
+    pub fn offset_at(&self, utc: i64) -> i32 {
+        match &self.dst {
+            Some(dst) if self.is_dst(utc) => dst.offset,
+            _ => self.offset,
+        }
+    }
+
+    /// Convert a UTC instant to local time
+    pub fn to_local(&self, utc: i64) -> LocalTime {
+        let is_dst = self.is_dst(utc);
+        let (utc_offset, abbreviation) = match &self.dst {
+            Some(dst) if is_dst => (dst.offset, dst.abbreviation.clone()),
+            _ => (self.offset, self.abbreviation.clone()),
+        };
+        LocalTime {
+            datetime: DateTime::from_timestamp(utc + utc_offset as i64),
+            utc_offset,
+            is_dst,
+            abbreviation,
+        }
+    }
+
+    /// Convert a local wall-clock time to UTC. Times repeated when DST ends resolve
+    /// to the earlier (DST) instant; times skipped when it starts are shifted forward.
+    pub fn to_utc(&self, local: &DateTime) -> i64 {
+        let wall = local.to_timestamp() as i64;
+        let dst_offset = self.dst.as_ref().map_or(self.offset, |dst| dst.offset);
+        let as_dst = wall - dst_offset as i64;
+        if self.is_dst(as_dst) {
+            return as_dst;
+        }
+        wall - self.offset as i64
+    }
+}
+
+/// Cursor over a POSIX TZ string
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn eat(&mut self, c: char) -> bool {
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> &'a str {
+        let end = self.rest.find(|c: char| !pred(c)).unwrap_or(self.rest.len());
+        let (taken, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        taken
+    }
+
+    /// `EST` or quoted `<-03>`
+    fn abbreviation(&mut self) -> Result<String, &'static str> {
+        let name = if self.eat('<') {
+            let name = self.take_while(|c| c != '>');
+            if !self.eat('>') {
+                return Err("Unterminated quoted TZ abbreviation");
+            }
+            name
+        } else {
+            self.take_while(|c| c.is_ascii_alphabetic())
+        };
+        if name.len() < 3 {
+            return Err("TZ abbreviation must be at least 3 characters");
+        }
+        Ok(name.to_string())
+    }
+
+    fn number(&mut self) -> Result<i32, &'static str> {
+        self.take_while(|c| c.is_ascii_digit()).parse().map_err(|_| "Expected a number in TZ string")
+    }
+
+    /// `[+-]hh[:mm[:ss]]` in seconds
+    fn offset(&mut self) -> Result<i32, &'static str> {
+        let sign = if self.eat('-') { -1 } else { self.eat('+'); 1 };
+        let mut seconds = self.number()? * 3600;
+        if self.eat(':') {
+            seconds += self.number()? * 60;
+            if self.eat(':') {
+                seconds += self.number()?;
+            }
+        }
+        Ok(sign * seconds)
+    }
+
+    /// `Mm.w.d[/time]`
+    fn transition(&mut self) -> Result<TransitionRule, &'static str> {
+        if !self.eat('M') {
+            return Err("Only Mm.w.d TZ transition rules are supported");
+        }
+        let month = self.number()?;
+        if !self.eat('.') {
+            return Err("Malformed TZ transition rule");
+        }
+        let week = self.number()?;
+        if !self.eat('.') {
+            return Err("Malformed TZ transition rule");
+        }
+        let weekday = self.number()?;
+        if !(1..=12).contains(&month) || !(1..=5).contains(&week) || !(0..=6).contains(&weekday) {
+            return Err("TZ transition rule out of range");
+        }
+        let time = if self.eat('/') { self.offset()? } else { DEFAULT_TRANSITION_SECONDS };
+        Ok(TransitionRule { month: month as u8, week: week as u8, weekday: weekday as u8, time })
+    }
+}
+
+static CURRENT_ZONE: RwLock<Option<TimeZone>> = RwLock::new(None);
+
+/// Load the zone named by the `tz=` kernel command line option, defaulting to UTC
+pub fn init() {
+    let zone = match crate::cmdline::get("tz") {
+        Some(spec) => TimeZone::load(spec).unwrap_or_else(|e| {
+            crate::serial::_print(format_args!("[Time] Invalid timezone '{}': {}, using UTC\n", spec, e));
+            TimeZone::utc()
+        }),
+        None => TimeZone::utc(),
+    };
+    crate::serial::_print(format_args!("[Time] Timezone: {}\n", zone.name));
+    *CURRENT_ZONE.write() = Some(zone);
+}
+
+/// Switch the system timezone by tzdata name or POSIX TZ string
+pub fn set_timezone(spec: &str) -> Result<(), &'static str> {
+    let zone = TimeZone::load(spec)?;
+    *CURRENT_ZONE.write() = Some(zone);
+    Ok(())
+}
+
+/// The system timezone
+pub fn current() -> TimeZone {
+    CURRENT_ZONE.read().clone().unwrap_or_else(TimeZone::utc)
+}
+
+/// Convert a UTC instant to the system timezone
+pub fn to_local(utc: i64) -> LocalTime {
+    match CURRENT_ZONE.read().as_ref() {
+        Some(zone) => zone.to_local(utc),
+        None => TimeZone::utc().to_local(utc),
+    }
+}
+
+/// Names in the built-in tzdata subset
+pub fn zone_names() -> impl Iterator<Item = &'static str> {
+    TZDATA.iter().map(|(name, _)| *name)
+}
@@ -0,0 +1,131 @@
+//! Timerfd and Epoll Test
+//! Checks that a one-shot timerfd turns readable at its deadline and reads back one expiry,
+//! that an interval timerfd counts every interval that passed since it was last read, and
+//! that epoll_wait reports a timerfd once it expires, with the word it was added with, while
+//! an idle eventfd in the same interest list stays quiet
+
+use alloc::vec::Vec;
+use crate::filesystem::epoll::{self, EpollEvent, EPOLLIN, EPOLL_CTL_ADD};
+use crate::filesystem::eventfd::{self, EFD_NONBLOCK};
+use crate::filesystem::poll::{self, PollFd, POLLIN};
+use crate::filesystem::timerfd::{self, TimerSpec, CLOCK_MONOTONIC, TFD_NONBLOCK, TFD_TIMER_ABSTIME};
+use crate::filesystem::{self, FileSystemError};
+use crate::serial::_print;
+use crate::time::get_precise_time_ns;
+
+const MS: u64 = 1_000_000;
+
+fn read_count(fd: u64) -> Result<u64, FileSystemError> {
+    let mut count = [0; 8];
+    filesystem::read(fd, &mut count)?;
+    Ok(u64::from_ne_bytes(count))
+}
+
+fn timers(fds: &mut Vec<u64>) -> Result<(), &'static str> {
+    // Test 1: A one-shot timer turns readable at its deadline, once
+    _print(format_args!("[Timerfd Test] Test 1: One-shot timer...\n"));
+    let fd = timerfd::create(CLOCK_MONOTONIC, TFD_NONBLOCK).map_err(|_| "Failed to create a timerfd")?;
+    fds.push(fd);
+    if read_count(fd) != Err(FileSystemError::WouldBlock) {
+        return Err("Disarmed timerfd readable");
+    }
+    let armed_at = get_precise_time_ns();
+    timerfd::settime(fd, 0, TimerSpec { value_ns: 5 * MS, interval_ns: 0 }).map_err(|_| "Failed to arm the timerfd")?;
+    let mut fds_polled = [PollFd::new(fd, POLLIN)];
+    if poll::poll(&mut fds_polled, Some(0)) != 0 {
+        return Err("Timerfd readable before its deadline");
+    }
+    if poll::poll(&mut fds_polled, Some(100 * MS)) != 1 || fds_polled[0].revents != POLLIN {
+        return Err("Timerfd not readable after its deadline");
+    }
+    let waited_ns = get_precise_time_ns() - armed_at;
+    if waited_ns < 5 * MS {
+        return Err("Timerfd readable early");
+    }
+    if read_count(fd) != Ok(1) || read_count(fd) != Err(FileSystemError::WouldBlock) {
+        return Err("One-shot timerfd did not expire exactly once");
+    }
+    if timerfd::gettime(fd) != Ok(TimerSpec::default()) {
+        return Err("Expired one-shot timerfd still armed");
+    }
+    _print(format_args!("[Timerfd Test] ✓ Timerfd {} readable after {} µs\n", fd, waited_ns / 1000));
+
+    // Test 2: An interval timer counts every interval passed
+    _print(format_args!("[Timerfd Test] Test 2: Interval timer...\n"));
+    let fd = timerfd::create(CLOCK_MONOTONIC, TFD_NONBLOCK).map_err(|_| "Failed to create a timerfd")?;
+    fds.push(fd);
+    let start_ns = get_precise_time_ns() + 10 * MS;
+    timerfd::settime(fd, TFD_TIMER_ABSTIME, TimerSpec { value_ns: start_ns, interval_ns: 10 * MS })
+        .map_err(|_| "Failed to arm the timerfd")?;
+    while get_precise_time_ns() < start_ns + 45 * MS {
+        core::hint::spin_loop();
+    }
+    let count = read_count(fd);
+    if count != Ok(5) {
+        return Err("Interval timerfd miscounted its expiries");
+    }
+    let remaining = timerfd::gettime(fd).map_err(|_| "Failed to read the timerfd setting")?;
+    if remaining.interval_ns != 10 * MS || remaining.value_ns == 0 || remaining.value_ns > 10 * MS {
+        return Err("Interval timerfd not rearmed");
+    }
+    let previous = timerfd::settime(fd, 0, TimerSpec::default()).map_err(|_| "Failed to disarm the timerfd")?;
+    if previous.interval_ns != 10 * MS || timerfd::gettime(fd) != Ok(TimerSpec::default()) {
+        return Err("Disarming did not return the old setting");
+    }
+    _print(format_args!("[Timerfd Test] ✓ Timerfd {} counted 5 expiries of 10 ms\n", fd));
+
+    // Test 3: Epoll reports the timer once it expires, and not the idle eventfd
+    _print(format_args!("[Timerfd Test] Test 3: Epoll...\n"));
+    let epfd = epoll::create().map_err(|_| "Failed to create an epoll instance")?;
+    fds.push(epfd);
+    let timer = timerfd::create(CLOCK_MONOTONIC, TFD_NONBLOCK).map_err(|_| "Failed to create a timerfd")?;
+    fds.push(timer);
+    let event = eventfd::create(0, EFD_NONBLOCK).map_err(|_| "Failed to create an eventfd")?;
+    fds.push(event);
+    epoll::ctl(epfd, EPOLL_CTL_ADD, timer, EpollEvent { events: EPOLLIN, data: 0x7131 })
+        .map_err(|_| "Failed to add the timerfd")?;
+    epoll::ctl(epfd, EPOLL_CTL_ADD, event, EpollEvent { events: EPOLLIN, data: 0xe7e1 })
+        .map_err(|_| "Failed to add the eventfd")?;
+    if epoll::ctl(epfd, EPOLL_CTL_ADD, timer, EpollEvent { events: EPOLLIN, data: 0 }).is_ok() {
+        return Err("Descriptor added twice");
+    }
+    if epoll::wait(epfd, 8, Some(0)) != Ok(Vec::new()) {
+        return Err("Epoll reported idle descriptors");
+    }
+    timerfd::settime(timer, 0, TimerSpec { value_ns: 5 * MS, interval_ns: 0 }).map_err(|_| "Failed to arm the timerfd")?;
+    let ready = epoll::wait(epfd, 8, Some(100 * MS)).map_err(|_| "Failed to wait on the epoll instance")?;
+    if ready != [EpollEvent { events: EPOLLIN, data: 0x7131 }] {
+        return Err("Epoll did not report the expired timerfd alone");
+    }
+    if read_count(timer) != Ok(1) || epoll::wait(epfd, 8, Some(0)) != Ok(Vec::new()) {
+        return Err("Epoll still reported the drained timerfd");
+    }
+    _print(format_args!("[Timerfd Test] ✓ Epoll {} reported timerfd {} with its data\n", epfd, timer));
+    Ok(())
+}
+
+pub fn run_timerfd_tests() -> Result<(), &'static str> {
+    let mut fds = Vec::new();
+    let result = timers(&mut fds);
+    for fd in fds.into_iter().rev() {
+        let _ = filesystem::close(fd);
+    }
+    result?;
+
+    _print(format_args!("[Timerfd Test] ✓ All timerfd and epoll tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for timerfds and epoll
+pub fn test_timerfd() {
+    _print(format_args!("[Timerfd Test] ===========================================\n"));
+    _print(format_args!("[Timerfd Test]           TIMERFD AND EPOLL TESTS\n"));
+    _print(format_args!("[Timerfd Test] ===========================================\n"));
+
+    match run_timerfd_tests() {
+        Ok(_) => _print(format_args!("[Timerfd Test] ✓ All timerfd and epoll tests PASSED\n")),
+        Err(e) => _print(format_args!("[Timerfd Test] ✗ Timerfd and epoll tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Timerfd Test] ===========================================\n"));
+}
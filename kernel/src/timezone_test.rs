@@ -0,0 +1,100 @@
+//! Timezone Test
+//! Converts known UTC instants across DST boundaries and checks local-time formatting
+
+use crate::serial::_print;
+use crate::time::timezone::TimeZone;
+use crate::time::DateTime;
+
+/// 2024-03-10 06:59:59 / 07:00:00 UTC, around the US spring-forward transition
+const NY_BEFORE_DST: i64 = 1_710_053_999;
+const NY_DST_START: i64 = 1_710_054_000;
+/// 2024-11-03 05:59:59 / 06:00:00 UTC, around the US fall-back transition
+const NY_BEFORE_STD: i64 = 1_730_613_599;
+const NY_DST_END: i64 = 1_730_613_600;
+/// 2024-01-15 12:00:00 UTC and 2024-07-01 00:00:00 UTC
+const MID_JANUARY: i64 = 1_705_320_000;
+const JULY_FIRST: i64 = 1_719_792_000;
+
+fn expect(zone: &TimeZone, utc: i64, formatted: &str, err: &'static str) -> Result<(), &'static str> {
+    let local = zone.to_local(utc);
+    if local.format() != formatted {
+        _print(format_args!("[TZ Test] Got '{}', expected '{}'\n", local.format(), formatted));
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Test UTC/local conversion and formatting
+pub fn run_timezone_tests() -> Result<(), &'static str> {
+    _print(format_args!("[TZ Test] Starting timezone tests...\n"));
+    
+    // Test 1: Spring forward in New York skips 02:00-03:00 local
+    _print(format_args!("[TZ Test] Test 1: DST start...\n"));
+    let new_york = TimeZone::load("America/New_York")?;
+    expect(&new_york, NY_BEFORE_DST, "Sun 2024-03-10 01:59:59 EST", "Wrong local time before DST start")?;
+    expect(&new_york, NY_DST_START, "Sun 2024-03-10 03:00:00 EDT", "Wrong local time at DST start")?;
+    if new_york.to_local(NY_DST_START).format_iso8601() != "2024-03-10T03:00:00-04:00" {
+        return Err("Wrong ISO 8601 formatting");
+    }
+    _print(format_args!("[TZ Test] ✓ 01:59:59 EST -> 03:00:00 EDT\n"));
+    
+    // Test 2: Fall back repeats 01:00-02:00 local
+    _print(format_args!("[TZ Test] Test 2: DST end...\n"));
+    expect(&new_york, NY_BEFORE_STD, "Sun 2024-11-03 01:59:59 EDT", "Wrong local time before DST end")?;
+    expect(&new_york, NY_DST_END, "Sun 2024-11-03 01:00:00 EST", "Wrong local time at DST end")?;
+    _print(format_args!("[TZ Test] ✓ 01:59:59 EDT -> 01:00:00 EST\n"));
+    
+    // Test 3: Local to UTC, including a repeated and a skipped wall-clock time
+    _print(format_args!("[TZ Test] Test 3: Local to UTC...\n"));
+    let at = |hour, minute, second, month, day| DateTime { year: 2024, month, day, hour, minute, second };
+    if new_york.to_utc(&at(3, 0, 0, 3, 10)) != NY_DST_START {
+        return Err("Local to UTC conversion mismatch");
+    }
+    if new_york.to_utc(&at(1, 59, 59, 11, 3)) != NY_BEFORE_STD {
+        return Err("Repeated local time should resolve to DST");
+    }
+    if new_york.to_utc(&at(2, 30, 0, 3, 10)) != NY_DST_START + 30 * 60 {
+        return Err("Skipped local time should shift forward");
+    }
+    _print(format_args!("[TZ Test] ✓ Local times map back to UTC\n"));
+    
+    // Test 4: Negative half-hour offset and southern-hemisphere DST
+    _print(format_args!("[TZ Test] Test 4: Negative and southern offsets...\n"));
+    let st_johns = TimeZone::load("America/St_Johns")?;
+    expect(&st_johns, MID_JANUARY, "Mon 2024-01-15 08:30:00 NST", "Wrong Newfoundland time")?;
+    if st_johns.to_local(MID_JANUARY).format_iso8601() != "2024-01-15T08:30:00-03:30" {
+        return Err("Wrong negative offset formatting");
+    }
+    let sydney = TimeZone::load("Australia/Sydney")?;
+    expect(&sydney, MID_JANUARY, "Mon 2024-01-15 23:00:00 AEDT", "Wrong Sydney summer time")?;
+    expect(&sydney, JULY_FIRST, "Mon 2024-07-01 10:00:00 AEST", "Wrong Sydney standard time")?;
+    let sao_paulo = TimeZone::load("America/Sao_Paulo")?;
+    expect(&sao_paulo, JULY_FIRST, "Sun 2024-06-30 21:00:00 -03", "Wrong quoted-abbreviation zone")?;
+    _print(format_args!("[TZ Test] ✓ -03:30, +10/+11 and quoted zones convert correctly\n"));
+    
+    // Test 5: Malformed TZ strings are rejected
+    _print(format_args!("[TZ Test] Test 5: Invalid TZ strings...\n"));
+    for spec in ["EST", "EST5EDT", "EST5EDT,J60,J300", "X5"] {
+        if TimeZone::parse_posix(spec).is_ok() {
+            return Err("Malformed TZ string accepted");
+        }
+    }
+    _print(format_args!("[TZ Test] ✓ Invalid TZ strings rejected\n"));
+    
+    _print(format_args!("[TZ Test] ✓ All timezone tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for timezone support
+pub fn test_timezones() {
+    _print(format_args!("[TZ Test] ===========================================\n"));
+    _print(format_args!("[TZ Test]              TIMEZONE TESTS\n"));
+    _print(format_args!("[TZ Test] ===========================================\n"));
+    
+    match run_timezone_tests() {
+        Ok(_) => _print(format_args!("[TZ Test] ✓ All timezone tests PASSED\n")),
+        Err(e) => _print(format_args!("[TZ Test] ✗ Timezone tests FAILED: {}\n", e)),
+    }
+    
+    _print(format_args!("[TZ Test] ===========================================\n"));
+}
@@ -0,0 +1,179 @@
+//! Thread-Local Storage Test
+//! Loads a program with a PT_TLS segment into a user process and checks that it gets a TLS
+//! block in the x86-64 variant II layout with its FS base at the thread pointer, that
+//! thread-locals read FS-relative, as compiled code reads them, hold their initial values,
+//! and that another thread's block is a separate copy
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use x86_64::registers::model_specific::FsBase;
+use x86_64::VirtAddr;
+use crate::arch::cr4;
+use crate::elf::{self, ElfError};
+use crate::process::{self, Process};
+use crate::serial::_print;
+use crate::vmm;
+
+const COUNTER_INIT: u64 = 0x1122_3344_5566_7788;
+const FLAGS_INIT: u32 = 0xCAFE_F00D;
+
+/// An executable whose only segment is a PT_TLS one: `init_image` as `.tdata`, zeroed up
+/// to `mem_size`
+fn elf_with_tls(init_image: &[u8], mem_size: u64, align: u64) -> Vec<u8> {
+    const HEADER_SIZE: u64 = 64;
+    const PROGRAM_HEADER_SIZE: u64 = 56;
+    let mut data = Vec::new();
+    data.extend_from_slice(b"\x7fELF\x02\x01\x01");
+    data.resize(16, 0);
+    data.extend_from_slice(&2u16.to_le_bytes()); // ET_EXEC
+    data.extend_from_slice(&62u16.to_le_bytes()); // EM_X86_64
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&0x40_0000u64.to_le_bytes()); // Entry point
+    data.extend_from_slice(&HEADER_SIZE.to_le_bytes()); // Program headers
+    data.extend_from_slice(&0u64.to_le_bytes()); // No section headers
+    data.extend_from_slice(&0u32.to_le_bytes());
+    for field in [HEADER_SIZE as u16, PROGRAM_HEADER_SIZE as u16, 1, 0, 0, 0] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(&7u32.to_le_bytes()); // PT_TLS
+    data.extend_from_slice(&4u32.to_le_bytes()); // PF_R
+    let image_offset = HEADER_SIZE + PROGRAM_HEADER_SIZE;
+    for field in [image_offset, 0x60_0000, 0x60_0000, init_image.len() as u64, mem_size, align] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(init_image);
+    data
+}
+
+/// Run `f` in `address_space_id` with the FS base at `fs_base`, as a thread of it would run
+fn in_thread<T>(address_space_id: u64, fs_base: u64, f: impl FnOnce() -> T) -> Result<T, &'static str> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let previous = vmm::current_address_space().ok_or("No address space active")?;
+        vmm::switch_address_space(address_space_id).map_err(|_| "Failed to switch address space")?;
+        let saved = FsBase::read();
+        process::load_fs_base(fs_base);
+        let result = f();
+        FsBase::write(saved);
+        vmm::switch_address_space(previous).map_err(|_| "Failed to switch back")?;
+        Ok(result)
+    })
+}
+
+/// Read the 8 bytes at `offset` from the FS base
+fn read_fs(offset: i64) -> u64 {
+    let value;
+    // SAFETY: `in_thread` has switched to the test address space with the FS base at a
+    // mapped TLS block covering `offset`, and SMAP is lifted only for the read
+    unsafe {
+        if cr4::has_bits(cr4::CR4_SMAP) {
+            core::arch::asm!("stac", options(nomem, nostack));
+        }
+        core::arch::asm!("mov {}, qword ptr fs:[{}]", out(reg) value, in(reg) offset, options(nostack, readonly, preserves_flags));
+        if cr4::has_bits(cr4::CR4_SMAP) {
+            core::arch::asm!("clac", options(nomem, nostack));
+        }
+    }
+    value
+}
+
+/// Write `value` to the 8 bytes at `offset` from the FS base
+fn write_fs(offset: i64, value: u64) {
+    // SAFETY: As for `read_fs`
+    unsafe {
+        if cr4::has_bits(cr4::CR4_SMAP) {
+            core::arch::asm!("stac", options(nomem, nostack));
+        }
+        core::arch::asm!("mov qword ptr fs:[{}], {}", in(reg) offset, in(reg) value, options(nostack, preserves_flags));
+        if cr4::has_bits(cr4::CR4_SMAP) {
+            core::arch::asm!("clac", options(nomem, nostack));
+        }
+    }
+}
+
+fn run_tls(process: &mut Process) -> Result<(), &'static str> {
+    let address_space_id = process.address_space_id.ok_or("Process has no address space")?;
+
+    // Test 1: The PT_TLS segment becomes a template and the process a block for it
+    _print(format_args!("[TLS Test] Test 1: Loading a PT_TLS segment...\n"));
+    let mut init_image = Vec::new();
+    init_image.extend_from_slice(&COUNTER_INIT.to_le_bytes());
+    init_image.extend_from_slice(&FLAGS_INIT.to_le_bytes());
+    let (_, template) = elf::load_elf_with_tls(elf_with_tls(&init_image, 32, 16), address_space_id)
+        .map_err(|_| "Failed to load the program")?;
+    let template = Arc::new(template.ok_or("PT_TLS segment not found")?);
+    if template.mem_size != 32 || template.align != 16 || template.thread_pointer_offset() != 32 {
+        return Err("TLS template wrong");
+    }
+    let malformed = elf::ElfLoader::new(elf_with_tls(&init_image, 8, 16)).map_err(|_| "Failed to parse")?;
+    let unaligned = elf::ElfLoader::new(elf_with_tls(&init_image, 32, 24)).map_err(|_| "Failed to parse")?;
+    for loader in [malformed, unaligned] {
+        if !matches!(loader.tls_template(), Err(ElfError::InvalidProgramHeader)) {
+            return Err("Malformed PT_TLS segment accepted");
+        }
+    }
+    process.setup_tls(template.clone()).map_err(|_| "Failed to allocate the TLS block")?;
+    if process.fs_base == 0 || process.fs_base % 16 != 0 {
+        return Err("FS base not at an aligned thread pointer");
+    }
+    _print(format_args!("[TLS Test] ✓ FS base at {:#x}\n", process.fs_base));
+
+    // Test 2: FS-relative reads find the initial values below the thread pointer
+    _print(format_args!("[TLS Test] Test 2: FS-relative thread-locals...\n"));
+    let fs_base = process.fs_base;
+    let values = in_thread(address_space_id, fs_base, || [read_fs(0), read_fs(-32), read_fs(-24), read_fs(-16), read_fs(-8)])?;
+    if values[0] != fs_base {
+        return Err("%fs:0 does not point to itself");
+    }
+    if values[1] != COUNTER_INIT || values[2] as u32 != FLAGS_INIT {
+        return Err("Initialized thread-locals wrong");
+    }
+    if values[2] >> 32 != 0 || values[3] != 0 || values[4] != 0 {
+        return Err(".tbss not zeroed");
+    }
+    _print(format_args!("[TLS Test] ✓ %fs:-32 holds {:#x}\n", values[1]));
+
+    // Test 3: A second thread's block is its own copy of the template
+    _print(format_args!("[TLS Test] Test 3: Separate blocks per thread...\n"));
+    let second = template.allocate_block(address_space_id).map_err(|_| "Failed to allocate a second block")?;
+    if second == VirtAddr::new(fs_base) {
+        return Err("Threads share a TLS block");
+    }
+    in_thread(address_space_id, fs_base, || write_fs(-32, 7))?;
+    let (first_value, second_value) = (
+        in_thread(address_space_id, fs_base, || read_fs(-32))?,
+        in_thread(address_space_id, second.as_u64(), || read_fs(-32))?,
+    );
+    if first_value != 7 || second_value != COUNTER_INIT {
+        return Err("Write to one thread's block seen by another");
+    }
+    _print(format_args!("[TLS Test] ✓ Second block at {:#x} kept its initial value\n", second.as_u64()));
+    Ok(())
+}
+
+pub fn run_tls_tests() -> Result<(), &'static str> {
+    let mut process = Process::user_process(String::from("tls-test"), VirtAddr::new(0x40_0000))
+        .map_err(|_| "Failed to create process")?;
+    let result = run_tls(&mut process);
+    if let Some(id) = process.address_space_id {
+        let _ = vmm::destroy_address_space(id);
+    }
+    result?;
+
+    _print(format_args!("[TLS Test] ✓ All thread-local storage tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for thread-local storage
+pub fn test_tls() {
+    _print(format_args!("[TLS Test] ===========================================\n"));
+    _print(format_args!("[TLS Test]        THREAD-LOCAL STORAGE TESTS\n"));
+    _print(format_args!("[TLS Test] ===========================================\n"));
+
+    match run_tls_tests() {
+        Ok(_) => _print(format_args!("[TLS Test] ✓ All thread-local storage tests PASSED\n")),
+        Err(e) => _print(format_args!("[TLS Test] ✗ Thread-local storage tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[TLS Test] ===========================================\n"));
+}
@@ -0,0 +1,263 @@
+//! CPU Topology Test
+//! Builds the topology from canned CPUID dumps: a 4-core, 8-thread CPU described by leaf 0x0B
+//! pairs SMT siblings and shares L1 and L2 per core and L3 per package, a two-package CPU with
+//! dies described by leaf 0x1F numbers its cores within each package, and a CPU with only the
+//! legacy leaves is still split into cores and listed in `/proc/cpuinfo` form. On a known
+//! two-package layout the scheduler puts two real-time processes on different cores, gives a
+//! gaming process a core of its own, and migrates work to a CPU sharing a cache over a colder,
+//! idler one
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::string::String;
+use alloc::vec::Vec;
+use x86_64::VirtAddr;
+use crate::arch::topology::{CacheKind, Core, CpuTopology, CpuidRegisters, Package, Thread};
+use crate::process::{CpuAffinity, Priority, Process, RtClass, SmpScheduler, LOAD_BALANCE_INTERVAL_MS};
+use crate::serial::_print;
+use crate::vmm;
+
+/// Extended topology level types
+const SMT: u32 = 1 << 8;
+const CORE: u32 = 2 << 8;
+const DIE: u32 = 5 << 8;
+
+/// CPUID answers for a fixed set of leaves and subleaves, zero everywhere else
+struct CannedCpuid(BTreeMap<(u32, u32), CpuidRegisters>);
+
+impl CannedCpuid {
+    fn new(leaves: &[((u32, u32), CpuidRegisters)]) -> Self {
+        Self(leaves.iter().copied().collect())
+    }
+
+    fn topology(&self) -> CpuTopology {
+        CpuTopology::from_cpuid(&|leaf, subleaf| self.0.get(&(leaf, subleaf)).copied().unwrap_or((0, 0, 0, 0)))
+    }
+}
+
+/// A leaf 4 cache descriptor: `(EAX, EBX, ECX)` for `ways` of 64-byte lines in `sets` sets,
+/// shared by `sharing` logical CPUs
+fn cache(kind: u32, level: u32, sharing: u32, ways: u32, sets: u32) -> CpuidRegisters {
+    (kind | level << 5 | (sharing - 1) << 14, 63 | (ways - 1) << 22, sets - 1, 0)
+}
+
+fn core(id: u32, threads: &[(u32, u32)]) -> Core {
+    Core { id, threads: threads.iter().map(|&(cpu, apic_id)| Thread { cpu, apic_id }).collect() }
+}
+
+fn add_cpus(topology: &mut CpuTopology, apic_ids: &[u32]) {
+    for (cpu, &apic_id) in apic_ids.iter().enumerate() {
+        topology.add_cpu(cpu as u32, apic_id);
+    }
+}
+
+/// Two packages of two cores of two threads; L1 and L2 per core, L3 per package. CPUs 0-3
+/// are the first thread of each core, 4-7 the second, so CPU n and n + 4 are siblings and
+/// CPUs 0, 1, 4 and 5 share package 0's L3
+fn two_packages() -> CpuTopology {
+    let mut topology = CannedCpuid::new(&[
+        ((0, 0), (0x16, 0, 0, 0)),
+        ((0x0B, 0), (1, 2, SMT, 0)),
+        ((0x0B, 1), (2, 4, CORE | 1, 0)),
+        ((4, 0), cache(1, 1, 2, 8, 64)),
+        ((4, 1), cache(3, 2, 2, 4, 1024)),
+        ((4, 2), cache(3, 3, 4, 16, 8192)),
+    ])
+    .topology();
+    add_cpus(&mut topology, &[0, 2, 4, 6, 1, 3, 5, 7]);
+    topology
+}
+
+/// Queue a process on `scheduler`, on `pin` if given, and note its address space for cleanup
+fn spawn(scheduler: &mut SmpScheduler, priority: Priority, rt_class: RtClass, pin: Option<u32>, address_spaces: &mut Vec<u64>) -> Result<u64, &'static str> {
+    let mut process = Process::new(String::from("smt-test"), VirtAddr::new(0x400000), priority).map_err(|_| "Failed to create process")?;
+    address_spaces.extend(process.address_space_id);
+    process.rt_params.class = rt_class;
+    if let Some(cpu) = pin {
+        process.cpu_affinity = CpuAffinity::single_cpu(cpu);
+    }
+    Ok(scheduler.add_process(process))
+}
+
+/// The CPUs with something queued, with how much
+fn occupied(scheduler: &SmpScheduler) -> Vec<(u32, usize)> {
+    scheduler.run_queue_lengths().into_iter().enumerate().filter(|&(_, length)| length > 0).map(|(cpu, length)| (cpu as u32, length)).collect()
+}
+
+fn smt_scheduling(address_spaces: &mut Vec<u64>) -> Result<(), &'static str> {
+    let topology = two_packages();
+    let mut scheduler = SmpScheduler::with_topology(topology.clone());
+    spawn(&mut scheduler, Priority::Normal, RtClass::Edf, None, address_spaces)?;
+    spawn(&mut scheduler, Priority::Normal, RtClass::Cbs, None, address_spaces)?;
+    let rt_cpus: Vec<u32> = occupied(&scheduler).into_iter().map(|(cpu, _)| cpu).collect();
+    if rt_cpus.len() != 2 || topology.smt_sibling_mask(rt_cpus[0]) & (1 << rt_cpus[1]) != 0 {
+        return Err("Real-time processes share a physical core");
+    }
+    spawn(&mut scheduler, Priority::Gaming, RtClass::BestEffort, None, address_spaces)?;
+    let gaming_cpu = occupied(&scheduler).into_iter().map(|(cpu, _)| cpu).find(|cpu| !rt_cpus.contains(cpu)).ok_or("Gaming process not queued")?;
+    if rt_cpus.iter().any(|&cpu| topology.smt_sibling_mask(gaming_cpu) & (1 << cpu) != 0) {
+        return Err("Gaming process placed beside a busy sibling");
+    }
+    for _ in 0..5 {
+        spawn(&mut scheduler, Priority::Normal, RtClass::BestEffort, None, address_spaces)?;
+    }
+    let lengths = scheduler.run_queue_lengths();
+    if lengths.iter().enumerate().any(|(sibling, &length)| topology.smt_sibling_mask(gaming_cpu) & (1 << sibling) != 0 && length != 0) {
+        return Err("Work placed on the gaming core while other CPUs had room");
+    }
+
+    // CPU 0 holds five movable processes; its sibling CPU 4 is too busy to take one, CPUs 1
+    // and 5 share its L3 but have work, package 1 is idle and cold
+    let mut scheduler = SmpScheduler::with_topology(topology);
+    let mut movable = Vec::new();
+    for _ in 0..5 {
+        movable.push(spawn(&mut scheduler, Priority::Normal, RtClass::BestEffort, Some(0), address_spaces)?);
+    }
+    for pid in movable {
+        scheduler.set_process_affinity(pid, CpuAffinity::all_cpus());
+    }
+    for cpu in [4, 4, 4, 1, 5] {
+        spawn(&mut scheduler, Priority::Normal, RtClass::BestEffort, Some(cpu), address_spaces)?;
+    }
+    // A single migration, to see where it goes
+    scheduler.set_balance_policy(LOAD_BALANCE_INTERVAL_MS, 1);
+    scheduler.balance_load();
+    if occupied(&scheduler) != [(0, 4), (1, 2), (4, 3), (5, 1)] {
+        return Err("Migration went to a cold CPU over one sharing the L3");
+    }
+    Ok(())
+}
+
+pub fn run_topology_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Topology Test] Starting CPU topology tests...\n"));
+
+    // Test 1: leaf 0x0B with 4 cores of 2 threads, each core with its own L1 and L2
+    _print(format_args!("[Topology Test] Test 1: 4 cores, 8 threads from leaf 0x0B...\n"));
+    let cpuid = CannedCpuid::new(&[
+        ((0, 0), (0x16, 0, 0, 0)),
+        ((0x0B, 0), (1, 2, SMT, 0)),
+        ((0x0B, 1), (4, 8, CORE | 1, 0)),
+        ((4, 0), cache(1, 1, 2, 8, 64)),
+        ((4, 1), cache(2, 1, 2, 8, 64)),
+        ((4, 2), cache(3, 2, 2, 4, 1024)),
+        ((4, 3), cache(3, 3, 16, 16, 8192)),
+    ]);
+    let mut topology = cpuid.topology();
+    // CPUs 0-3 are the first thread of each core, 4-7 the second
+    add_cpus(&mut topology, &[0, 2, 4, 6, 1, 3, 5, 7]);
+    let expected = vec![Package {
+        id: 0,
+        cores: vec![
+            core(0, &[(0, 0), (4, 1)]),
+            core(1, &[(1, 2), (5, 3)]),
+            core(2, &[(2, 4), (6, 5)]),
+            core(3, &[(3, 6), (7, 7)]),
+        ],
+    }];
+    if topology.packages != expected {
+        return Err("Threads not paired into their cores");
+    }
+    if topology.smt_sibling_mask(0) != 1 << 4 || topology.smt_sibling_mask(7) != 1 << 3 || topology.smt_sibling_mask(8) != 0 {
+        return Err("SMT siblings wrong");
+    }
+    let sizes: Vec<(u8, CacheKind, u32)> = topology.caches.iter().map(|cache| (cache.level, cache.kind, cache.size)).collect();
+    let expected_sizes = [
+        (1, CacheKind::Data, 32 * 1024),
+        (1, CacheKind::Instruction, 32 * 1024),
+        (2, CacheKind::Unified, 256 * 1024),
+        (3, CacheKind::Unified, 8 * 1024 * 1024),
+    ];
+    if sizes != expected_sizes {
+        return Err("Caches read wrong");
+    }
+    if topology.shared_cache_level(0, 4) != Some(1) || topology.shared_cache_level(0, 1) != Some(3) || topology.shared_cache_level(0, 8).is_some() {
+        return Err("Cache sharing wrong");
+    }
+    _print(format_args!("[Topology Test] ✓ 4 cores of 2 threads sharing L1/L2, one L3\n"));
+
+    // Test 2: leaf 0x1F wins over 0x0B, and die bits number the cores within a package
+    _print(format_args!("[Topology Test] Test 2: Two packages with dies from leaf 0x1F...\n"));
+    let cpuid = CannedCpuid::new(&[
+        ((0, 0), (0x1F, 0, 0, 0)),
+        ((0x0B, 0), (0, 1, SMT, 0)),
+        ((0x0B, 1), (1, 2, CORE | 1, 0)),
+        ((0x1F, 0), (1, 2, SMT, 0)),
+        ((0x1F, 1), (3, 8, CORE | 1, 0)),
+        ((0x1F, 2), (5, 32, DIE | 2, 0)),
+    ]);
+    let mut topology = cpuid.topology();
+    // Package 0 has cores 0 and 1 of die 0 and core 0 of die 1; package 1 has one core
+    add_cpus(&mut topology, &[0, 1, 2, 3, 32, 33, 8]);
+    let expected = vec![
+        Package { id: 0, cores: vec![core(0, &[(0, 0), (1, 1)]), core(1, &[(2, 2), (3, 3)]), core(4, &[(6, 8)])] },
+        Package { id: 1, cores: vec![core(0, &[(4, 32), (5, 33)])] },
+    ];
+    if topology.packages != expected {
+        return Err("Packages, dies or cores split wrong");
+    }
+    if topology.smt_sibling_mask(4) != 1 << 5 || topology.smt_sibling_mask(6) != 0 || topology.shared_cache_level(0, 1).is_some() {
+        return Err("Siblings or caches wrong without cache leaves");
+    }
+    topology.remove_cpu(6);
+    topology.add_cpu(5, 34);
+    if topology.packages[0].cores.len() != 2 || topology.packages[1].cores != [core(0, &[(4, 32)]), core(1, &[(5, 34)])] {
+        return Err("CPU not moved or emptied core kept");
+    }
+    _print(format_args!("[Topology Test] ✓ 2 packages, core 4 on the second die\n"));
+
+    // Test 3: legacy leaves 1 and 4 give the layout, written out as /proc/cpuinfo
+    _print(format_args!("[Topology Test] Test 3: Legacy leaves and /proc/cpuinfo...\n"));
+    // 4 logical CPUs per package; leaf 4 EAX[31:26] counts 2 cores
+    let (l1_eax, l1_ebx, l1_ecx, _) = cache(1, 1, 2, 8, 64);
+    let cpuid = CannedCpuid::new(&[
+        ((0, 0), (4, 0, 0, 0)),
+        ((1, 0), (0, 4 << 16, 0, 1 << 28)),
+        ((4, 0), (l1_eax | 1 << 26, l1_ebx, l1_ecx, 0)),
+        ((4, 1), cache(3, 2, 4, 8, 1024)),
+    ]);
+    let mut topology = cpuid.topology();
+    add_cpus(&mut topology, &[0, 1, 2, 3]);
+    if topology.packages != [Package { id: 0, cores: vec![core(0, &[(0, 0), (1, 1)]), core(1, &[(2, 2), (3, 3)])] }] {
+        return Err("Legacy counts not split into 2 cores of 2 threads");
+    }
+    let cpuinfo = topology.cpuinfo("GenuineIntel", "Rae CPU");
+    let last = "processor\t: 3\nvendor_id\t: GenuineIntel\nmodel name\t: Rae CPU\ncache size\t: 512 KB\n\
+                physical id\t: 0\nsiblings\t: 4\ncore id\t\t: 1\ncpu cores\t: 2\napicid\t\t: 3\n\
+                cache_alignment\t: 64\n\n";
+    if cpuinfo.matches("processor\t:").count() != 4 || !cpuinfo.starts_with("processor\t: 0\n") || !cpuinfo.ends_with(last) {
+        return Err("/proc/cpuinfo text wrong");
+    }
+    let mut topology = CannedCpuid::new(&[((0, 0), (1, 0, 0, 0))]).topology();
+    add_cpus(&mut topology, &[0, 1]);
+    if topology.packages.len() != 2 || topology.smt_sibling_mask(0) != 0 {
+        return Err("CPU without HTT not one core per package");
+    }
+    _print(format_args!("[Topology Test] ✓ Legacy layout read and listed\n"));
+
+    // Test 4: placement keeps latency-sensitive processes apart and migration keeps caches warm
+    _print(format_args!("[Topology Test] Test 4: SMT-aware placement and migration...\n"));
+    let mut address_spaces = Vec::new();
+    let result = smt_scheduling(&mut address_spaces);
+    for id in address_spaces {
+        let _ = vmm::destroy_address_space(id);
+    }
+    result?;
+    _print(format_args!("[Topology Test] ✓ RT and gaming processes on their own cores, migration within the L3\n"));
+
+    _print(format_args!("[Topology Test] ✓ All CPU topology tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for CPU topology
+pub fn test_topology() {
+    _print(format_args!("[Topology Test] ===========================================\n"));
+    _print(format_args!("[Topology Test]            CPU TOPOLOGY TESTS\n"));
+    _print(format_args!("[Topology Test] ===========================================\n"));
+
+    match run_topology_tests() {
+        Ok(_) => _print(format_args!("[Topology Test] ✓ All CPU topology tests PASSED\n")),
+        Err(e) => _print(format_args!("[Topology Test] ✗ CPU topology tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Topology Test] ===========================================\n"));
+}
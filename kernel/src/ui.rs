@@ -5,6 +5,9 @@ use alloc::collections::BTreeMap;
 use spin::Mutex;
 use lazy_static::lazy_static;
 
+pub mod accessibility;
+pub mod clipboard;
+
 // Color definitions
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Color {
@@ -0,0 +1,168 @@
+//! Accessibility tree for assistive services such as screen readers
+//! Each window is exposed as a tree of nodes, one per widget, giving its role, name, value
+//! and state. Focus and value changes are queued as notifications that an assistive service
+//! drains with `take_events`.
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use spin::Mutex;
+
+use crate::graphics::{Rect, Widget, WidgetType, Window, WindowId};
+
+/// Notifications kept for an assistive service that is slow to drain them; the oldest are dropped
+const MAX_PENDING_EVENTS: usize = 256;
+
+static EVENTS: Mutex<VecDeque<AccessibilityEvent>> = Mutex::new(VecDeque::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Window,
+    Button,
+    Label,
+    TextField,
+    Group,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Window => "window",
+            Role::Button => "button",
+            Role::Label => "label",
+            Role::TextField => "textfield",
+            Role::Group => "group",
+        }
+    }
+}
+
+/// One element of the accessibility tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessibleNode {
+    /// Widget ID within its window, or the window ID for the root
+    pub id: u32,
+    pub role: Role,
+    pub name: String,
+    /// Current contents of editable widgets
+    pub value: Option<String>,
+    pub focused: bool,
+    pub disabled: bool,
+    /// Position relative to the window, or on screen for the root
+    pub bounds: Rect,
+    pub children: Vec<AccessibleNode>,
+}
+
+impl AccessibleNode {
+    /// The first node in this subtree, depth first, that `predicate` accepts
+    pub fn find(&self, predicate: &impl Fn(&AccessibleNode) -> bool) -> Option<&AccessibleNode> {
+        if predicate(self) {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.find(predicate))
+    }
+
+    fn write_indented(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        write!(f, "{:indent$}{} \"{}\"", "", self.role.as_str(), self.name, indent = depth * 2)?;
+        if let Some(value) = &self.value {
+            write!(f, " = \"{}\"", value)?;
+        }
+        if self.focused {
+            write!(f, " [focused]")?;
+        }
+        if self.disabled {
+            write!(f, " [disabled]")?;
+        }
+        writeln!(f)?;
+        self.children.iter().try_for_each(|child| child.write_indented(f, depth + 1))
+    }
+}
+
+/// One line per node, children indented under their parent
+impl fmt::Display for AccessibleNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
+
+/// A change an assistive service should announce
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessibilityEvent {
+    /// Keyboard focus moved to `widget`, or off every widget of the window
+    FocusChanged { window: WindowId, widget: Option<u32> },
+    ValueChanged { window: WindowId, widget: u32, value: String },
+}
+
+pub fn widget_role(widget: &Widget) -> Role {
+    match widget.widget_type {
+        WidgetType::Button { .. } => Role::Button,
+        WidgetType::Label { .. } => Role::Label,
+        WidgetType::TextInput { .. } => Role::TextField,
+        WidgetType::Panel => Role::Group,
+    }
+}
+
+/// What an assistive service reads out for a widget: its accessible name if set, otherwise
+/// the text it shows
+pub fn widget_name(widget: &Widget) -> String {
+    if let Some(name) = &widget.accessible_name {
+        return name.clone();
+    }
+    match &widget.widget_type {
+        WidgetType::Button { text } | WidgetType::Label { text } => text.clone(),
+        WidgetType::TextInput { .. } | WidgetType::Panel => String::new(),
+    }
+}
+
+/// The editable contents of a widget
+pub fn widget_value(widget: &Widget) -> Option<String> {
+    match &widget.widget_type {
+        WidgetType::TextInput { text, .. } => Some(text.clone()),
+        _ => None,
+    }
+}
+
+fn widget_node(widget: &Widget) -> AccessibleNode {
+    AccessibleNode {
+        id: widget.id,
+        role: widget_role(widget),
+        name: widget_name(widget),
+        value: widget_value(widget),
+        focused: widget.focused,
+        disabled: !widget.enabled,
+        bounds: widget.bounds,
+        children: Vec::new(),
+    }
+}
+
+/// The accessibility tree of `window`: the window itself with its widgets as children
+pub fn build_tree(window: &Window) -> AccessibleNode {
+    AccessibleNode {
+        id: window.id,
+        role: Role::Window,
+        name: window.title.clone(),
+        value: None,
+        focused: window.focused,
+        disabled: false,
+        bounds: window.rect,
+        children: window.widgets.iter().map(widget_node).collect(),
+    }
+}
+
+/// The accessibility tree of a window of the window manager
+pub fn dump_tree(window: WindowId) -> Option<AccessibleNode> {
+    crate::graphics::with_window(window, build_tree)
+}
+
+pub fn notify(event: AccessibilityEvent) {
+    let mut events = EVENTS.lock();
+    if events.len() >= MAX_PENDING_EVENTS {
+        events.pop_front();
+    }
+    events.push_back(event);
+}
+
+/// Drain the notifications queued since the last call, oldest first
+pub fn take_events() -> Vec<AccessibilityEvent> {
+    EVENTS.lock().drain(..).collect()
+}
@@ -0,0 +1,22 @@
+//! System clipboard
+//! Holds the text most recently copied by any application until another copy replaces it,
+//! for any application to paste.
+
+use alloc::string::String;
+use spin::Mutex;
+
+static CLIPBOARD: Mutex<Option<String>> = Mutex::new(None);
+
+/// Replace the clipboard contents with `text`
+pub fn set_text(text: String) {
+    *CLIPBOARD.lock() = Some(text);
+}
+
+/// The text on the clipboard, if anything was copied
+pub fn text() -> Option<String> {
+    CLIPBOARD.lock().clone()
+}
+
+pub fn clear() {
+    *CLIPBOARD.lock() = None;
+}
@@ -0,0 +1,116 @@
+//! VirtIO Driver Test
+//! Reads the marker sector from the test disk, round-trips an ARP exchange over virtio-net
+//! and presents a test pattern through virtio-gpu
+
+use crate::drivers::virtio;
+use crate::drivers::virtio_gpu;
+use crate::graphics::Rect;
+use crate::drivers::BlockDevice;
+use crate::serial::_print;
+
+/// Marker written to sector 0 of the virtio-blk test image by raeen-test
+const VIRTIO_TEST_MAGIC: &[u8] = b"RAEENOS-VIRTIO-BLK-TEST";
+
+/// QEMU user-mode networking addresses
+const GUEST_IP: [u8; 4] = [10, 0, 2, 15];
+const GATEWAY_IP: [u8; 4] = [10, 0, 2, 2];
+
+const RECEIVE_ATTEMPTS: u32 = 1000;
+
+/// Scanout size requested with xres/yres in the QEMU test config
+const GPU_TEST_RESOLUTION: (u32, u32) = (800, 600);
+
+/// Build a broadcast ARP request asking for the gateway's MAC
+fn build_arp_request(mac: [u8; 6]) -> [u8; 42] {
+    let mut frame = [0u8; 42];
+    frame[0..6].copy_from_slice(&[0xFF; 6]);
+    frame[6..12].copy_from_slice(&mac);
+    frame[12..14].copy_from_slice(&[0x08, 0x06]); // EtherType: ARP
+    frame[14..22].copy_from_slice(&[0x00, 0x01, 0x08, 0x00, 6, 4, 0x00, 0x01]); // Ethernet/IPv4 request
+    frame[22..28].copy_from_slice(&mac);
+    frame[28..32].copy_from_slice(&GUEST_IP);
+    frame[38..42].copy_from_slice(&GATEWAY_IP);
+    frame
+}
+
+fn is_arp_reply_from_gateway(frame: &[u8]) -> bool {
+    frame.len() >= 42
+        && frame[12..14] == [0x08, 0x06]
+        && frame[20..22] == [0x00, 0x02]
+        && frame[28..32] == GATEWAY_IP
+}
+
+/// Test virtio-blk, virtio-net and virtio-gpu against the devices in the QEMU test config
+pub fn run_virtio_tests() -> Result<(), &'static str> {
+    _print(format_args!("[VirtIO Test] Starting VirtIO driver tests...\n"));
+    
+    // Test 1: virtio-blk reads the known sector
+    _print(format_args!("[VirtIO Test] Test 1: Reading marker sector...\n"));
+    let disk = virtio::block_devices().into_iter().next().ok_or("No virtio-blk device attached")?;
+    let mut sector = [0u8; 512];
+    disk.lock().read_blocks(0, &mut sector).map_err(|_| "virtio-blk read failed")?;
+    if &sector[..VIRTIO_TEST_MAGIC.len()] != VIRTIO_TEST_MAGIC {
+        return Err("Sector 0 does not contain the test marker");
+    }
+    _print(format_args!("[VirtIO Test] ✓ Sector 0 read correctly\n"));
+    
+    // Test 2: virtio-net sends an ARP request and receives the gateway's reply
+    _print(format_args!("[VirtIO Test] Test 2: ARP round trip...\n"));
+    let nic = virtio::net_devices().into_iter().next().ok_or("No virtio-net device attached")?;
+    let mut nic = nic.lock();
+    let request = build_arp_request(nic.mac_address());
+    nic.send_frame(&request).map_err(|_| "virtio-net send failed")?;
+    
+    let mut replied = false;
+    for _ in 0..RECEIVE_ATTEMPTS {
+        if let Some(frame) = nic.receive_frame() {
+            if is_arp_reply_from_gateway(&frame) {
+                replied = true;
+                break;
+            }
+        }
+        crate::time::sleep_ms(1);
+    }
+    if !replied {
+        return Err("No ARP reply received from gateway");
+    }
+    _print(format_args!("[VirtIO Test] ✓ Frame sent and reply received\n"));
+    drop(nic);
+    
+    // Test 3: virtio-gpu scans out at the host resolution and presents a pattern
+    _print(format_args!("[VirtIO Test] Test 3: virtio-gpu scanout...\n"));
+    let gpu = virtio_gpu::primary().ok_or("No virtio-gpu device attached")?;
+    let mut gpu = gpu.lock();
+    let reported = gpu.display_info().map_err(|_| "virtio-gpu display info failed")?;
+    if reported != Some(GPU_TEST_RESOLUTION) || gpu.resolution() != GPU_TEST_RESOLUTION {
+        return Err("virtio-gpu scanout does not match the host resolution");
+    }
+    let fb = gpu.framebuffer().ok_or("virtio-gpu has no framebuffer")?;
+    for y in 0..fb.height {
+        let row = (fb.address + (y * fb.pitch) as u64).as_mut_ptr::<u32>();
+        for x in 0..fb.width {
+            let color = ((x * 255 / fb.width) << 16) | ((y * 255 / fb.height) << 8) | 0x80;
+            unsafe { core::ptr::write_volatile(row.add(x as usize), color) };
+        }
+    }
+    gpu.flush(Rect { x: 0, y: 0, width: fb.width, height: fb.height })
+        .map_err(|_| "virtio-gpu flush failed")?;
+    _print(format_args!("[VirtIO Test] ✓ {}x{} pattern presented\n", fb.width, fb.height));
+    
+    _print(format_args!("[VirtIO Test] ✓ All VirtIO driver tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for VirtIO drivers
+pub fn test_virtio_drivers() {
+    _print(format_args!("[VirtIO Test] ===========================================\n"));
+    _print(format_args!("[VirtIO Test]           VIRTIO DRIVER TESTS\n"));
+    _print(format_args!("[VirtIO Test] ===========================================\n"));
+    
+    match run_virtio_tests() {
+        Ok(_) => _print(format_args!("[VirtIO Test] ✓ All VirtIO tests PASSED\n")),
+        Err(e) => _print(format_args!("[VirtIO Test] ✗ VirtIO tests FAILED: {}\n", e)),
+    }
+    
+    _print(format_args!("[VirtIO Test] ===========================================\n"));
+}
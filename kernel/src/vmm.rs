@@ -1,3 +1,13 @@
+//! Virtual memory manager
+//! Address spaces, the areas mapped in them and the page faults that back those areas.
+//!
+//! `init` sets CR0.WP, so read-only pages bind the kernel as they bind user code. Without it
+//! the kernel's writes into a page shared copy-on-write after a fork would land in the frame
+//! both processes still map, changing the other process's memory behind its back. With it,
+//! kernel writes to user memory go through `prepare_user_write`, which gives copy-on-write
+//! pages their own frames first and refuses read-only ones, and the ELF loader fills
+//! segments through the physical memory map, so a missed case faults rather than corrupts.
+
 use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 use alloc::boxed::Box;
@@ -6,15 +16,26 @@ use spin::{Mutex, RwLock};
 use bitflags::bitflags;
 use x86_64::{
     structures::paging::{
-        Page, PageTable, PageTableFlags, PhysFrame, Size4KiB,
-        Mapper, FrameAllocator,
+        Page, PageTable, PageTableFlags, PageTableIndex, PhysFrame, Size4KiB,
+        Mapper, FrameAllocator, Translate,
+        mapper::{MappedFrame, TranslateResult},
     },
     VirtAddr, PhysAddr,
 };
-use x86_64::registers::control::Cr3;
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr3};
+use crate::heap::slab::{SlabBox, SlabCache};
 use crate::memory;
 
 static VMM: RwLock<VirtualMemoryManager> = RwLock::new(VirtualMemoryManager::new());
+/// Slots for the areas of every address space, reused as mappings come and go
+static VM_AREA_CACHE: SlabCache<VmArea> = SlabCache::new("vm_area");
+
+/// Page table bit, free for OS use, marking a page whose frame is shared copy-on-write. Such
+/// pages are mapped read-only; the first write copies the frame if it is still shared
+pub const COPY_ON_WRITE: PageTableFlags = PageTableFlags::BIT_9;
+
+/// End of the lower half, the user part of every address space
+const USER_SPACE_END: u64 = 0x8000_0000_0000;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VmAreaType {
@@ -25,6 +46,8 @@ pub enum VmAreaType {
     Shared,
     Device,
     Guard,
+    /// Memory mapped with mmap, anonymous or from a file
+    Mapping,
 }
 
 bitflags! {
@@ -122,6 +145,8 @@ pub struct VmArea {
     pub permissions: VmPermissions,
     pub name: Option<alloc::string::String>,
     pub file_offset: Option<u64>,
+    /// File whose contents a file mapping's pages are read from, at `file_offset` onwards
+    pub file_path: Option<alloc::string::String>,
     pub is_shared: bool,
     pub is_anonymous: bool,
     pub ref_count: u32,
@@ -136,6 +161,7 @@ impl VmArea {
             permissions,
             name: None,
             file_offset: None,
+            file_path: None,
             is_shared: false,
             is_anonymous: true,
             ref_count: 1,
@@ -166,12 +192,13 @@ pub struct AddressSpace {
     pub id: u64,
     pub page_table: Box<PageTable>,
     pub pml4_frame: PhysFrame,  // Physical frame for this address space's PML4
-    pub areas: BTreeMap<VirtAddr, VmArea>,
+    pub areas: BTreeMap<VirtAddr, SlabBox<VmArea>>,
     pub heap_start: VirtAddr,
     pub heap_end: VirtAddr,
     pub stack_start: VirtAddr,
     pub stack_end: VirtAddr,
     pub mmap_start: VirtAddr,
+    pub mmap_end: VirtAddr,
     pub next_mmap: VirtAddr,
 }
 
@@ -189,6 +216,7 @@ impl AddressSpace {
         let stack_start = VirtAddr::new(0x7000_0000_0000); // 1792 TiB
         let stack_end = VirtAddr::new(0x8000_0000_0000);   // 2048 TiB
         let mmap_start = VirtAddr::new(0x3000_0000_0000);  // 768 TiB
+        let mmap_end = VirtAddr::new(0x4000_0000_0000);    // 1 PiB, where shared memory starts
         
         let mut address_space = Self {
             id,
@@ -200,6 +228,7 @@ impl AddressSpace {
             stack_start,
             stack_end,
             mmap_start,
+            mmap_end,
             next_mmap: mmap_start,
         };
         
@@ -233,6 +262,11 @@ impl AddressSpace {
             // 4. We only read from the current PML4, no modifications
             let current_pml4 = unsafe { &*(current_pml4_virt.as_ptr::<PageTable>()) };
             
+            // Start with no user mappings (entries 0-255), whatever the frame held before
+            for i in 0..256 {
+                new_pml4[i].set_unused();
+            }
+            
             // Copy kernel higher-half entries (entries 256-511 for kernel space)
             for i in 256..512 {
                 new_pml4[i] = current_pml4[i].clone();
@@ -248,12 +282,12 @@ impl AddressSpace {
             }
         }
         
-        self.areas.insert(area.start, area);
+        self.areas.insert(area.start, SlabBox::new(&VM_AREA_CACHE, area));
         Ok(())
     }
     
     pub fn remove_area(&mut self, start: VirtAddr) -> Option<VmArea> {
-        self.areas.remove(&start)
+        self.areas.remove(&start).map(SlabBox::into_inner)
     }
     
     pub fn find_area(&self, addr: VirtAddr) -> Option<&VmArea> {
@@ -312,22 +346,37 @@ impl AddressSpace {
         Ok(start_addr)
     }
     
-    fn find_free_space(&self, start: VirtAddr, end: VirtAddr, size: u64) -> Result<VirtAddr, VmError> {
-        let mut current = start;
-        
-        while current + size <= end {
-            let mut found_overlap = false;
-            
-            for area in self.areas.values() {
-                if current < area.end && current + size > area.start {
-                    current = area.end;
-                    found_overlap = true;
-                    break;
-                }
+    /// Place a mapping of `size` bytes at `hint` when the range there is free user space, or
+    /// else in the first free space of the mmap region. A `fixed` mapping only goes at `hint`
+    pub fn place_mapping(&self, hint: Option<VirtAddr>, size: u64, fixed: bool) -> Result<VirtAddr, VmError> {
+        if let Some(start) = hint {
+            // Only a range inside user space is compared against the areas, in raw addresses,
+            // as a hint near the top or in the upper half has no valid end address
+            let user_end = start.as_u64().checked_add(size).filter(|&end| end <= USER_SPACE_END);
+            match user_end {
+                Some(end) if !self.overlaps(start.as_u64(), end) => return Ok(start),
+                Some(_) if fixed => return Err(VmError::AddressInUse),
+                None if fixed => return Err(VmError::InvalidOperation),
+                _ => {}
             }
-            
-            if !found_overlap {
-                return Ok(current);
+        }
+        self.find_free_space(self.mmap_start, self.mmap_end, size)
+    }
+    
+    /// Whether any area overlaps the range from `start` to `end`
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.areas.values().any(|area| start < area.end.as_u64() && end > area.start.as_u64())
+    }
+    
+    fn find_free_space(&self, start: VirtAddr, end: VirtAddr, size: u64) -> Result<VirtAddr, VmError> {
+        let mut current = start.as_u64();
+        
+        // Sizes too large for the region end the search rather than overflow
+        while let Some(current_end) = current.checked_add(size).filter(|&current_end| current_end <= end.as_u64()) {
+            let overlap = self.areas.values().find(|area| current < area.end.as_u64() && current_end > area.start.as_u64());
+            match overlap {
+                Some(area) => current = area.end.as_u64(),
+                None => return VirtAddr::try_new(current).map_err(|_| VmError::OutOfMemory),
             }
         }
         
@@ -381,7 +430,7 @@ impl AddressSpace {
         }
         
         // Add the area to our tracking
-        self.areas.insert(area.start, area.clone());
+        self.areas.insert(area.start, SlabBox::new(&VM_AREA_CACHE, area.clone()));
         
         // For now, we don't need to actually map pages here
         // Page mapping will be done on-demand during page faults
@@ -397,7 +446,7 @@ pub struct VirtualMemoryManager {
     next_as_id: u64,
     current_as_id: Option<u64>,
     kernel_as_id: u64,
-    shared_areas: BTreeMap<alloc::string::String, VmArea>,
+    shared_areas: BTreeMap<alloc::string::String, SharedRegion>,
 }
 
 impl VirtualMemoryManager {
@@ -434,8 +483,9 @@ impl VirtualMemoryManager {
         
         // Get the address space before removing it
         if let Some(address_space) = self.address_spaces.get(&id) {
-            // Deallocate all mapped pages in all areas
-            memory::with_mapper(|mapper| {
+            // Deallocate all mapped pages in all areas; frames shared copy-on-write lose
+            // this address space's reference and stay with the other owners
+            memory::with_page_table_mapper(address_space.pml4_frame, |mapper| {
                 for area in address_space.areas.values() {
                     for page in area.pages() {
                         if let Ok(frame) = mapper.translate_page(page) {
@@ -557,9 +607,9 @@ impl VirtualMemoryManager {
     }
     
     pub fn map_page(&mut self, as_id: u64, virt_addr: VirtAddr, phys_addr: PhysAddr, flags: PageTableFlags) -> Result<(), VmError> {
-        let _address_space = self.get_address_space_mut(as_id)
+        let address_space = self.get_address_space_mut(as_id)
             .ok_or(VmError::InvalidAddressSpace)?;
-        memory::with_mapper(|mapper| {
+        memory::with_page_table_mapper(address_space.pml4_frame, |mapper| {
             let frame = PhysFrame::containing_address(phys_addr);
             let page: Page<Size4KiB> = Page::containing_address(virt_addr);
             let mut alloc = GlobalFrameAlloc;
@@ -578,14 +628,14 @@ impl VirtualMemoryManager {
     }
     
     pub fn unmap_page(&mut self, as_id: u64, virt_addr: VirtAddr) -> Result<(), VmError> {
-        let _address_space = self.get_address_space_mut(as_id)
+        let address_space = self.get_address_space_mut(as_id)
             .ok_or(VmError::InvalidAddressSpace)?;
-        memory::with_mapper(|mapper| {
+        memory::with_page_table_mapper(address_space.pml4_frame, |mapper| {
             let page: Page<Size4KiB> = Page::containing_address(virt_addr);
             match mapper.unmap(page) {
                 Ok((frame, flush)) => { 
                     flush.flush(); 
-                    // Deallocate the frame after unmapping
+                    // Drop this mapping's reference to the frame, freeing it if it was the last
                     memory::deallocate_frame(frame);
                     Ok(()) 
                 }
@@ -596,14 +646,24 @@ impl VirtualMemoryManager {
     
     pub fn handle_page_fault(&mut self, virt_addr: VirtAddr, error_code: u64) -> Result<(), VmError> {
         let current_as_id = self.current_as_id.ok_or(VmError::InvalidAddressSpace)?;
-        let address_space_ptr: *mut AddressSpace = self.get_address_space_mut(current_as_id)
+        self.handle_fault_in(current_as_id, virt_addr, error_code)
+    }
+    
+    /// Resolve a fault at `virt_addr` in address space `current_as_id`, active or not
+    pub fn handle_fault_in(&mut self, current_as_id: u64, virt_addr: VirtAddr, error_code: u64) -> Result<(), VmError> {
+        let address_space = self.get_address_space(current_as_id)
             .ok_or(VmError::InvalidAddressSpace)?;
-        let address_space = unsafe { &mut *address_space_ptr };
         
         // Find the VMA containing this address
         let area = address_space.find_area(virt_addr)
+            .cloned()
             .ok_or(VmError::SegmentationFault)?;
         
+        // Nothing at all may be done with a mapping made without access
+        if !(area.permissions.readable() || area.permissions.writable() || area.permissions.executable()) {
+            return Err(VmError::PermissionDenied);
+        }
+        
         // Check permissions
         let is_write = (error_code & 0x2) != 0;
         let is_user = (error_code & 0x4) != 0;
@@ -621,6 +681,14 @@ impl VirtualMemoryManager {
             return Err(VmError::PermissionDenied);
         }
         
+        // The page is present, so only a write to a copy-on-write page can be resolved
+        if (error_code & 0x1) != 0 {
+            if is_write {
+                return self.copy_on_write(current_as_id, virt_addr);
+            }
+            return Err(VmError::PermissionDenied);
+        }
+        
         // Handle different types of page faults
         match area.area_type {
             VmAreaType::Stack => {
@@ -628,20 +696,114 @@ impl VirtualMemoryManager {
                 if virt_addr < area.start {
                     self.expand_stack(current_as_id, virt_addr)?;
                 } else {
-                    self.allocate_page_on_demand(current_as_id, virt_addr, area.permissions)?;
+                    self.allocate_page_on_demand(current_as_id, virt_addr, &area)?;
                 }
             },
-            VmAreaType::Heap => {
-                self.allocate_page_on_demand(current_as_id, virt_addr, area.permissions)?;
-            },
             _ => {
-                self.allocate_page_on_demand(current_as_id, virt_addr, area.permissions)?;
+                self.allocate_page_on_demand(current_as_id, virt_addr, &area)?;
             }
         }
         
         Ok(())
     }
     
+    /// Resolve a write fault on a copy-on-write page. A frame still shared is copied into a
+    /// fresh one mapped writable, and this address space's reference to the original dropped;
+    /// the last owner of a frame gets it back writable without a copy
+    fn copy_on_write(&mut self, as_id: u64, virt_addr: VirtAddr) -> Result<(), VmError> {
+        let pml4_frame = self.get_address_space(as_id)
+            .ok_or(VmError::InvalidAddressSpace)?
+            .pml4_frame;
+        let page: Page<Size4KiB> = Page::containing_address(virt_addr);
+        memory::with_page_table_mapper(pml4_frame, |mapper| {
+            let (frame, flags) = match mapper.translate(page.start_address()) {
+                TranslateResult::Mapped { frame: MappedFrame::Size4KiB(frame), flags, .. } => (frame, flags),
+                _ => return Err(VmError::SegmentationFault),
+            };
+            if !flags.contains(COPY_ON_WRITE) {
+                return Err(VmError::PermissionDenied);
+            }
+            let flags = (flags - COPY_ON_WRITE) | PageTableFlags::WRITABLE;
+            
+            if memory::frame_ref_count(frame) <= 1 {
+                // SAFETY: The page stays mapped to the same frame; only its flags change
+                let flush = unsafe { mapper.update_flags(page, flags) }.map_err(|_| VmError::MapError)?;
+                flush.flush();
+                return Ok(());
+            }
+            
+            let copy = memory::allocate_frame().ok_or(VmError::OutOfMemory)?;
+            // SAFETY: Safe because:
+            // 1. Both frames are allocated, distinct and reachable through the physical memory offset
+            // 2. The shared frame is only read, and the new frame is not mapped anywhere yet
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    memory::phys_to_virt(frame.start_address()).as_ptr::<u8>(),
+                    memory::phys_to_virt(copy.start_address()).as_mut_ptr::<u8>(),
+                    4096,
+                );
+            }
+            let (_, flush) = mapper.unmap(page).map_err(|_| VmError::UnmapError)?;
+            flush.ignore(); // Flushed by the remapping below
+            let mut alloc = GlobalFrameAlloc;
+            // SAFETY: The page was unmapped just above and the copy is a fresh frame
+            match unsafe { mapper.map_to(page, copy, flags, &mut alloc) } {
+                Ok(mapping) => mapping.flush(),
+                Err(_) => {
+                    memory::deallocate_frame(copy);
+                    return Err(VmError::MapError);
+                }
+            }
+            
+            // Drop the reference the copy replaced
+            memory::deallocate_frame(frame);
+            Ok(())
+        })
+    }
+    
+    /// Make `len` bytes of user memory at `start` ready for the kernel to write. Each page must
+    /// be mapped writable for user code, or lie in a writable user area and be copy-on-write or
+    /// not yet backed; it ends up backed by a frame of its own, copied if it was shared
+    pub fn prepare_user_write(&mut self, as_id: u64, start: VirtAddr, len: u64) -> Result<(), VmError> {
+        if len == 0 {
+            return Ok(());
+        }
+        let last = start.as_u64().checked_add(len - 1)
+            .and_then(|last| VirtAddr::try_new(last).ok())
+            .ok_or(VmError::SegmentationFault)?;
+        let pml4_frame = self.get_address_space(as_id)
+            .ok_or(VmError::InvalidAddressSpace)?
+            .pml4_frame;
+        for page in Page::<Size4KiB>::range_inclusive(Page::containing_address(start), Page::containing_address(last)) {
+            let address = page.start_address();
+            let flags = memory::with_page_table_mapper(pml4_frame, |mapper| match mapper.translate(address) {
+                TranslateResult::Mapped { flags, .. } => Some(flags),
+                _ => None,
+            });
+            if let Some(flags) = flags {
+                if flags.contains(PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE) {
+                    continue;
+                }
+                if !flags.contains(COPY_ON_WRITE) {
+                    return Err(VmError::PermissionDenied);
+                }
+            }
+            let area = self.get_address_space(as_id)
+                .ok_or(VmError::InvalidAddressSpace)?
+                .find_area(address)
+                .cloned()
+                .ok_or(VmError::SegmentationFault)?;
+            if !area.permissions.writable() || !area.permissions.user_accessible() {
+                return Err(VmError::PermissionDenied);
+            }
+            match flags {
+                Some(_) => self.copy_on_write(as_id, address)?,
+                None => self.allocate_page_on_demand(as_id, address, &area)?,
+            }
+        }
+        Ok(())
+    }
+    
     fn expand_stack(&mut self, as_id: u64, fault_addr: VirtAddr) -> Result<(), VmError> {
         let as_ptr: *mut AddressSpace = self.get_address_space_mut(as_id)
             .ok_or(VmError::InvalidAddressSpace)?;
@@ -651,7 +813,7 @@ impl VirtualMemoryManager {
         let mut stack_area = None;
         for area in address_space.areas.values() {
             if area.area_type == VmAreaType::Stack {
-                stack_area = Some(area.clone());
+                stack_area = Some(VmArea::clone(area));
                 break;
             }
         }
@@ -669,23 +831,41 @@ impl VirtualMemoryManager {
         
         // Remove old area and add expanded one
         address_space.areas.retain(|_, area| area.area_type != VmAreaType::Stack);
-        address_space.add_area(stack_area)?;
+        address_space.add_area(stack_area.clone())?;
         
         // Allocate the page
-        let _ = self.allocate_page_on_demand(as_id, fault_addr, VmPermissions::READ);
+        let _ = self.allocate_page_on_demand(as_id, fault_addr, &stack_area);
         
         Ok(())
     }
     
-    fn allocate_page_on_demand(&mut self, _as_id: u64, virt_addr: VirtAddr, permissions: VmPermissions) -> Result<(), VmError> {
+    /// Back the page of `area` holding `virt_addr` with a fresh frame: zero-filled, or for a
+    /// file mapping filled from the file at the page's offset, zero past the file's end
+    fn allocate_page_on_demand(&mut self, as_id: u64, virt_addr: VirtAddr, area: &VmArea) -> Result<(), VmError> {
         // Enforce W^X policy or dual-mapping policy
-        permissions.validate_dual_mapping_policy()?;
+        area.permissions.validate_dual_mapping_policy()?;
         
-        let flags = permissions.to_page_table_flags();
-        memory::with_mapper(|mapper| {
-            let frame = memory::allocate_frame().ok_or(VmError::OutOfMemory)?;
-            let page: Page<Size4KiB> = Page::containing_address(virt_addr);
+        let pml4_frame = self.get_address_space(as_id)
+            .ok_or(VmError::InvalidAddressSpace)?
+            .pml4_frame;
+        let page: Page<Size4KiB> = Page::containing_address(virt_addr);
+        let frame = memory::allocate_frame().ok_or(VmError::OutOfMemory)?;
+        // SAFETY: Safe because:
+        // 1. The frame was just allocated, so nothing else refers to it
+        // 2. The physical memory offset maps the whole frame, and it is page aligned
+        let contents = unsafe { &mut *memory::phys_to_virt(frame.start_address()).as_mut_ptr::<[u8; 4096]>() };
+        contents.fill(0);
+        if let (Some(path), Some(offset)) = (&area.file_path, area.file_offset) {
+            if read_file_page(path, offset + (page.start_address() - area.start), contents).is_err() {
+                memory::deallocate_frame(frame);
+                return Err(VmError::IoError);
+            }
+        }
+        
+        let flags = area.permissions.to_page_table_flags();
+        memory::with_page_table_mapper(pml4_frame, |mapper| {
             let mut alloc = GlobalFrameAlloc;
+            // SAFETY: The page faulted as not present, and the frame is fresh
             match unsafe { mapper.map_to(page, frame, flags, &mut alloc) } {
                 Ok(res) => {
                     res.flush();
@@ -700,10 +880,116 @@ impl VirtualMemoryManager {
         })
     }
 
+    /// Add a mapping of `size` bytes, placed as `place_mapping` does. Its pages are backed when
+    /// first touched, the page fault filling them in from `source`, except those of a shared
+    /// anonymous mapping: they are backed at once, so that forked children share every page
+    pub fn map_memory(&mut self, as_id: u64, hint: Option<VirtAddr>, size: u64, permissions: VmPermissions, fixed: bool, source: MappingSource) -> Result<VirtAddr, VmError> {
+        permissions.validate_dual_mapping_policy()?;
+        if size == 0 || hint.is_some_and(|start| !start.is_aligned(4096u64)) {
+            return Err(VmError::InvalidAlignment);
+        }
+        let size = size.checked_add(0xFFF).ok_or(VmError::OutOfMemory)? & !0xFFF;
+        
+        let address_space = self.get_address_space_mut(as_id)
+            .ok_or(VmError::InvalidAddressSpace)?;
+        let start = address_space.place_mapping(hint, size, fixed)?;
+        let mut area = VmArea::new(start, start + size, VmAreaType::Mapping, permissions);
+        match source {
+            MappingSource::Anonymous { shared } => area.is_shared = shared,
+            MappingSource::File { path, offset } => {
+                area.is_anonymous = false;
+                area.file_path = Some(path);
+                area.file_offset = Some(offset);
+            }
+        }
+        let shared = area.is_shared;
+        address_space.add_area(area.clone())?;
+        if shared {
+            for page in area.pages() {
+                if let Err(error) = self.allocate_page_on_demand(as_id, page.start_address(), &area) {
+                    let _ = self.unmap_memory(as_id, start, size);
+                    return Err(error);
+                }
+            }
+        }
+        Ok(start)
+    }
+    
+    /// Remove the mappings in the `size` bytes from `start`, freeing their pages. Areas reaching
+    /// past the range keep their parts outside it, so unmapping from the middle of one splits it
+    pub fn unmap_memory(&mut self, as_id: u64, start: VirtAddr, size: u64) -> Result<(), VmError> {
+        if size == 0 || !start.is_aligned(4096u64) {
+            return Err(VmError::InvalidAlignment);
+        }
+        let end = start.as_u64().checked_add(size).ok_or(VmError::InvalidOperation)?;
+        let end = VirtAddr::new_truncate(end.checked_add(0xFFF).ok_or(VmError::InvalidOperation)? & !0xFFF);
+        if end.as_u64() > USER_SPACE_END {
+            return Err(VmError::InvalidOperation);
+        }
+        
+        let address_space = self.get_address_space_mut(as_id)
+            .ok_or(VmError::InvalidAddressSpace)?;
+        let overlapping: Vec<VmArea> = address_space.areas.values()
+            .filter(|area| area.start < end && start < area.end)
+            .map(|area| VmArea::clone(area))
+            .collect();
+        let mut freed = Vec::new();
+        for area in overlapping {
+            address_space.areas.remove(&area.start);
+            freed.push((area.start.max(start), area.end.min(end)));
+            if area.start < start {
+                let mut before = area.clone();
+                before.end = start;
+                address_space.areas.insert(before.start, SlabBox::new(&VM_AREA_CACHE, before));
+            }
+            if end < area.end {
+                let mut after = area.clone();
+                after.start = end;
+                after.file_offset = area.file_offset.map(|offset| offset + (end - area.start));
+                address_space.areas.insert(after.start, SlabBox::new(&VM_AREA_CACHE, after));
+            }
+        }
+        
+        memory::with_page_table_mapper(address_space.pml4_frame, |mapper| {
+            for (from, to) in freed {
+                for page in Page::<Size4KiB>::range(Page::containing_address(from), Page::containing_address(to)) {
+                    // Pages never touched have nothing to free
+                    if let Ok((frame, flush)) = mapper.unmap(page) {
+                        flush.flush();
+                        memory::deallocate_frame(frame);
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+    
+    /// Add the named region `name` of `size` bytes, rounded up to whole pages, backed by
+    /// zeroed frames of its own that every mapping of it shares
     pub fn create_shared_area(&mut self, name: alloc::string::String, size: u64, permissions: VmPermissions) -> Result<(), VmError> {
+        permissions.validate_dual_mapping_policy()?;
+        if size == 0 {
+            return Err(VmError::InvalidOperation);
+        }
+        if self.shared_areas.contains_key(&name) {
+            return Err(VmError::AddressInUse);
+        }
+        let size = size.checked_add(0xFFF).ok_or(VmError::OutOfMemory)? & !0xFFF;
         let start = VirtAddr::new(0x4000_0000_0000); // Shared memory region
         let end = start + size;
         
+        let mut frames = Vec::new();
+        for _ in 0..size / 4096 {
+            let Some(frame) = memory::allocate_frame() else {
+                frames.into_iter().for_each(memory::deallocate_frame);
+                return Err(VmError::OutOfMemory);
+            };
+            // SAFETY: The frame was just allocated, so nothing else refers to it, and the
+            // physical memory offset maps the whole of it
+            unsafe { memory::phys_to_virt(frame.start_address()).as_mut_ptr::<u8>().write_bytes(0, 4096) };
+            frames.push(frame);
+        }
+        
         let area = VmArea {
             start,
             end,
@@ -711,42 +997,98 @@ impl VirtualMemoryManager {
             permissions,
             name: Some(name.clone()),
             file_offset: None,
+            file_path: None,
             is_shared: true,
             is_anonymous: true,
             ref_count: 0,
         };
         
-        self.shared_areas.insert(name, area);
+        self.shared_areas.insert(name, SharedRegion { area, frames });
         Ok(())
     }
     
+    /// Map the whole of region `name` into address space `as_id`, at `virt_addr` when that
+    /// range is free and otherwise in the mmap region, and return where it went
     pub fn map_shared_area(&mut self, as_id: u64, name: &str, virt_addr: Option<VirtAddr>) -> Result<VirtAddr, VmError> {
-        let shared_area = self.shared_areas.get(name)
-            .cloned()
+        let region = self.shared_areas.get(name)
             .ok_or(VmError::NotFound)?;
+        let size = region.area.size();
+        let frames = region.frames.clone();
+        let mut area = region.area.clone();
         
         let address_space = self.get_address_space_mut(as_id)
             .ok_or(VmError::InvalidAddressSpace)?;
-        
-        let start_addr = if let Some(addr) = virt_addr {
-            addr
-        } else {
-            address_space.next_mmap
-        };
-        
-        let mut area = shared_area.clone();
+        let start_addr = address_space.place_mapping(virt_addr, size, false)?;
         area.start = start_addr;
-        area.end = start_addr + shared_area.size();
-        area.ref_count += 1;
-        
+        area.end = start_addr + size;
+        area.ref_count = 1;
+        let flags = area.permissions.to_page_table_flags();
+        let pml4_frame = address_space.pml4_frame;
         address_space.add_area(area)?;
         
-        if virt_addr.is_none() {
-            address_space.next_mmap += shared_area.size();
+        let mapped = memory::with_page_table_mapper(pml4_frame, |mapper| {
+            let mut alloc = GlobalFrameAlloc;
+            let first = Page::<Size4KiB>::containing_address(start_addr);
+            frames.iter().zip(Page::range(first, first + frames.len() as u64)).try_for_each(|(&frame, page)| {
+                // SAFETY: The area was free until just above, so the page is unmapped, and the
+                // frame is kept alive by the reference added for this mapping
+                unsafe { mapper.map_to(page, frame, flags, &mut alloc) }
+                    .map(|mapping| mapping.flush())
+                    .map_err(|_| VmError::MapError)?;
+                memory::share_frame(frame);
+                Ok(())
+            })
+        });
+        if let Err(error) = mapped {
+            let _ = self.unmap_memory(as_id, start_addr, size);
+            return Err(error);
         }
         
         Ok(start_addr)
     }
+    
+    /// Remove region `name`, so it can no longer be mapped. Its frames stay with the
+    /// mappings already made, and are freed with the last of them
+    pub fn destroy_shared_area(&mut self, name: &str) -> Result<(), VmError> {
+        let region = self.shared_areas.remove(name).ok_or(VmError::NotFound)?;
+        region.frames.into_iter().for_each(memory::deallocate_frame);
+        Ok(())
+    }
+}
+
+/// A named region of memory, with the frames backing it
+#[derive(Debug)]
+struct SharedRegion {
+    area: VmArea,
+    frames: Vec<PhysFrame>,
+}
+
+/// Where the pages of a new mapping come from
+#[derive(Debug, Clone)]
+pub enum MappingSource {
+    /// Zero-filled pages, also seen by forked children when `shared`
+    Anonymous { shared: bool },
+    /// Private copies of the pages of the file at `path`, from `offset` on
+    File { path: alloc::string::String, offset: u64 },
+}
+
+/// Read the page of the file at `path` that starts at `offset` into `page`, leaving what lies
+/// past the end of the file untouched
+fn read_file_page(path: &str, offset: u64, page: &mut [u8; 4096]) -> crate::filesystem::FileSystemResult<()> {
+    let fd = crate::filesystem::open(path, 0)?;
+    let result = crate::filesystem::seek(fd, crate::filesystem::SeekFrom::Start(offset)).and_then(|_| {
+        let mut filled = 0;
+        while filled < page.len() {
+            let read = crate::filesystem::read(fd, &mut page[filled..])?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+        }
+        Ok(())
+    });
+    let _ = crate::filesystem::close(fd);
+    result
 }
 
 struct GlobalFrameAlloc;
@@ -770,6 +1112,7 @@ pub enum VmError {
     TestFailed,
     WxViolation,
     JitNotAllowed,
+    IoError,
 }
 
 impl fmt::Display for VmError {
@@ -789,6 +1132,7 @@ impl fmt::Display for VmError {
             VmError::TestFailed => write!(f, "Test failed"),
             VmError::WxViolation => write!(f, "W^X policy violation"),
             VmError::JitNotAllowed => write!(f, "JIT compilation not allowed"),
+            VmError::IoError => write!(f, "I/O error reading mapped file"),
         }
     }
 }
@@ -837,8 +1181,43 @@ static MEMORY_STATS: Mutex<MemoryStats> = Mutex::new(MemoryStats {
     major_page_faults: 0,
 });
 
+/// Present 4 KiB user pages of the page tables rooted at `pml4_frame`, with their frames
+/// and flags
+fn user_mappings(pml4_frame: PhysFrame) -> Vec<(Page<Size4KiB>, PhysFrame, PageTableFlags)> {
+    // SAFETY: Safe because:
+    // 1. Every frame passed here is a page table of the hierarchy, taken from a present entry
+    // 2. The physical memory offset maps all page table frames
+    // 3. The tables are only read, under the VMM lock
+    let table = |frame: PhysFrame| unsafe { &*memory::phys_to_virt(frame.start_address()).as_ptr::<PageTable>() };
+    let index = |i: usize| PageTableIndex::new(i as u16);
+    
+    let mut mappings = Vec::new();
+    // User space is the lower half, entries 0-255 of the PML4
+    for (i4, entry4) in table(pml4_frame).iter().enumerate().take(256) {
+        let Ok(pdpt) = entry4.frame() else { continue };
+        for (i3, entry3) in table(pdpt).iter().enumerate() {
+            // Huge pages report no frame and are skipped
+            let Ok(directory) = entry3.frame() else { continue };
+            for (i2, entry2) in table(directory).iter().enumerate() {
+                let Ok(page_table) = entry2.frame() else { continue };
+                for (i1, entry1) in table(page_table).iter().enumerate() {
+                    if let Ok(frame) = entry1.frame() {
+                        let page = Page::from_page_table_indices(index(i4), index(i3), index(i2), index(i1));
+                        mappings.push((page, frame, entry1.flags()));
+                    }
+                }
+            }
+        }
+    }
+    mappings
+}
+
 // Public API functions
 pub fn init() {
+    // Make read-only pages binding on the kernel too, as the module documentation explains
+    // SAFETY: Setting WP only restricts supervisor writes to pages mapped writable
+    unsafe { Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT)) };
+    
     let mut vmm = VMM.write();
     
     // Create kernel address space
@@ -889,22 +1268,16 @@ pub fn switch_address_space(id: u64) -> VmResult<()> {
     VMM.write().switch_address_space(id)
 }
 
-pub fn allocate_area(as_id: u64, size: u64, area_type: VmAreaType, permissions: VmPermissions) -> VmResult<VirtAddr> {
-    let mut vmm = VMM.write();
-    let address_space = vmm.get_address_space_mut(as_id)
-        .ok_or(VmError::InvalidAddressSpace)?;
-    address_space.allocate_area(size, area_type, permissions)
+/// The address space last switched to
+pub fn current_address_space() -> Option<u64> {
+    VMM.read().current_as_id
 }
 
-pub fn deallocate_area(as_id: u64, start: VirtAddr) -> VmResult<()> {
+pub fn allocate_area(as_id: u64, size: u64, area_type: VmAreaType, permissions: VmPermissions) -> VmResult<VirtAddr> {
     let mut vmm = VMM.write();
     let address_space = vmm.get_address_space_mut(as_id)
         .ok_or(VmError::InvalidAddressSpace)?;
-    
-    address_space.remove_area(start)
-        .ok_or(VmError::NotFound)?;
-    
-    Ok(())
+    address_space.allocate_area(size, area_type, permissions)
 }
 
 pub fn map_page(as_id: u64, virt_addr: VirtAddr, phys_addr: PhysAddr, permissions: VmPermissions) -> VmResult<()> {
@@ -916,8 +1289,77 @@ pub fn unmap_page(as_id: u64, virt_addr: VirtAddr) -> VmResult<()> {
     VMM.write().unmap_page(as_id, virt_addr)
 }
 
+pub fn map_memory(as_id: u64, hint: Option<VirtAddr>, size: u64, permissions: VmPermissions, fixed: bool, source: MappingSource) -> VmResult<VirtAddr> {
+    VMM.write().map_memory(as_id, hint, size, permissions, fixed, source)
+}
+
+pub fn unmap_memory(as_id: u64, start: VirtAddr, size: u64) -> VmResult<()> {
+    VMM.write().unmap_memory(as_id, start, size)
+}
+
 pub fn handle_page_fault(virt_addr: VirtAddr, error_code: u64) -> VmResult<()> {
-    VMM.write().handle_page_fault(virt_addr, error_code)
+    let current_as_id = VMM.read().current_as_id.ok_or(VmError::InvalidAddressSpace)?;
+    handle_fault_in(current_as_id, virt_addr, error_code)
+}
+
+/// Ready `len` bytes of user memory at `start` in the active address space for the kernel to
+/// write, as `VirtualMemoryManager::prepare_user_write` does, reclaiming memory as faults do
+pub fn prepare_user_write(start: u64, len: u64) -> VmResult<()> {
+    let start = VirtAddr::try_new(start).map_err(|_| VmError::SegmentationFault)?;
+    loop {
+        let result = {
+            let mut vmm = VMM.write();
+            let as_id = vmm.current_as_id.ok_or(VmError::InvalidAddressSpace)?;
+            vmm.prepare_user_write(as_id, start, len)
+        };
+        match result {
+            Err(VmError::OutOfMemory) if crate::oom::reclaim() => continue,
+            result => return result,
+        }
+    }
+}
+
+/// Resolve a fault in address space `as_id`. When no frame is left to back the page, the
+/// OOM killer ends a process to free some and the fault is tried again
+pub fn handle_fault_in(as_id: u64, virt_addr: VirtAddr, error_code: u64) -> VmResult<()> {
+    loop {
+        let result = VMM.write().handle_fault_in(as_id, virt_addr, error_code);
+        match result {
+            Err(VmError::OutOfMemory) if crate::oom::reclaim() => continue,
+            result => return result,
+        }
+    }
+}
+
+/// Number of pages backed by frames in address space `as_id`, none when it does not exist.
+/// `None` when the VMM lock is taken
+pub fn resident_pages(as_id: u64) -> Option<usize> {
+    let vmm = VMM.try_read()?;
+    let Some(address_space) = vmm.get_address_space(as_id) else {
+        return Some(0);
+    };
+    Some(memory::with_page_table_mapper(address_space.pml4_frame, |mapper| {
+        address_space.areas.values()
+            .flat_map(|area| area.pages())
+            .filter(|&page| mapper.translate_page(page).is_ok())
+            .count()
+    }))
+}
+
+/// Frames holding pages of file mappings, across all address spaces. A frame shared by
+/// several, as after a fork, counts once
+pub fn file_backed_pages() -> usize {
+    let vmm = VMM.read();
+    let mut frames = alloc::collections::BTreeSet::new();
+    for address_space in vmm.address_spaces.values() {
+        memory::with_page_table_mapper(address_space.pml4_frame, |mapper| {
+            let pages = address_space.areas.values()
+                .filter(|area| !area.is_anonymous)
+                .flat_map(|area| area.pages());
+            frames.extend(pages.filter_map(|page| mapper.translate_page(page).ok()));
+        });
+    }
+    frames.len()
 }
 
 pub fn create_shared_memory(name: alloc::string::String, size: u64, permissions: VmPermissions) -> VmResult<()> {
@@ -928,6 +1370,26 @@ pub fn map_shared_memory(as_id: u64, name: &str, virt_addr: Option<VirtAddr>) ->
     VMM.write().map_shared_area(as_id, name, virt_addr)
 }
 
+pub fn destroy_shared_memory(name: &str) -> VmResult<()> {
+    VMM.write().destroy_shared_area(name)
+}
+
+/// The frame backing page `page` of region `name`
+pub fn shared_memory_frame(name: &str, page: usize) -> Option<PhysFrame> {
+    VMM.read().shared_areas.get(name)?.frames.get(page).copied()
+}
+
+/// The physical address behind `virt_addr` in address space `as_id` when it lies in a shared
+/// mapping, which other address spaces may map as well
+pub fn shared_phys_addr(as_id: u64, virt_addr: VirtAddr) -> Option<PhysAddr> {
+    let vmm = VMM.read();
+    let address_space = vmm.get_address_space(as_id)?;
+    if !address_space.find_area(virt_addr)?.is_shared {
+        return None;
+    }
+    memory::with_page_table_mapper(address_space.pml4_frame, |mapper| mapper.translate_addr(virt_addr))
+}
+
 pub fn get_memory_stats() -> MemoryStats {
     *MEMORY_STATS.lock()
 }
@@ -988,28 +1450,66 @@ pub fn protect_memory(as_id: u64, start: VirtAddr, _size: u64, permissions: VmPe
     }
 }
 
+/// Copy an address space for a forked process. The areas are duplicated and the mapped pages
+/// shared: frames of shared areas stay writable on both sides, while private frames are mapped
+/// read-only and copy-on-write in both, so the first write from either side copies the frame
 pub fn copy_address_space(src_id: u64) -> VmResult<u64> {
     let mut vmm = VMM.write();
     
     // Get source address space
-    let src_areas = {
+    let (src_areas, src_pml4) = {
         let src_as = vmm.get_address_space(src_id)
             .ok_or(VmError::InvalidAddressSpace)?;
-        src_as.areas.clone()
+        (src_as.areas.clone(), src_as.pml4_frame)
     };
     
     // Create new address space
     let new_id = vmm.create_address_space()?;
+    let new_pml4 = vmm.get_address_space(new_id)
+        .ok_or(VmError::InvalidAddressSpace)?
+        .pml4_frame;
     
     // Copy all areas
     if let Some(new_as) = vmm.get_address_space_mut(new_id) {
         for area in src_areas.values() {
-            let mut new_area = area.clone();
+            let mut new_area = VmArea::clone(area);
             new_area.ref_count = 1;
             let _ = new_as.add_area(new_area);
         }
     }
     
+    // Share every mapped page with the copy
+    let shared = user_mappings(src_pml4).into_iter().try_for_each(|(page, frame, flags)| {
+        let in_shared_area = src_areas.values()
+            .any(|area| area.is_shared && area.contains(page.start_address()));
+        // Only pages the source may write need copying on write; read-only ones stay as they are
+        let flags = if in_shared_area || !flags.intersects(PageTableFlags::WRITABLE | COPY_ON_WRITE) {
+            flags
+        } else {
+            let cow_flags = (flags - PageTableFlags::WRITABLE) | COPY_ON_WRITE;
+            memory::with_page_table_mapper(src_pml4, |mapper| {
+                // SAFETY: The page stays mapped to the same frame; it only loses write access
+                unsafe { mapper.update_flags(page, cow_flags) }.map(|flush| flush.ignore())
+            }).map_err(|_| VmError::MapError)?;
+            cow_flags
+        };
+        memory::with_page_table_mapper(new_pml4, |mapper| {
+            let mut alloc = GlobalFrameAlloc;
+            // SAFETY: The new address space has no user mappings besides those copied here,
+            // and the frame is kept alive by the reference added below
+            unsafe { mapper.map_to(page, frame, flags, &mut alloc) }.map(|mapping| mapping.ignore())
+        }).map_err(|_| VmError::MapError)?;
+        memory::share_frame(frame);
+        Ok(())
+    });
+    
+    // The source may be the active address space, holding writable translations
+    x86_64::instructions::tlb::flush_all();
+    
+    if let Err(error) = shared {
+        let _ = vmm.destroy_address_space(new_id);
+        return Err(error);
+    }
     Ok(new_id)
 }
 
@@ -1347,6 +1847,144 @@ pub fn test_memory_protection() -> VmResult<()> {
     Ok(())
 }
 
+/// Test copy-on-write sharing between an address space and its copy
+pub fn test_copy_on_write() -> VmResult<()> {
+    let parent_id = create_address_space()?;
+    let test_addr = allocate_area(parent_id, 4096, VmAreaType::Data,
+                                  VmPermissions::READ | VmPermissions::WRITE | VmPermissions::USER)?;
+    let frame = memory::allocate_frame().ok_or(VmError::OutOfMemory)?;
+    with_vmm(|vmm| {
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+        vmm.map_page(parent_id, test_addr, frame.start_address(), flags)
+    })?;
+    
+    switch_address_space(parent_id)?;
+    let ptr = test_addr.as_mut_ptr::<u64>();
+    // SAFETY: This is unsafe because:
+    // - test_addr is mapped in the active address space, at first writable and later
+    //   copy-on-write, where a write faults and the fault handler maps a writable frame
+    // - The address is page aligned, so aligned for u64 access
+    // - No other code accesses this test memory
+    let (read, write) = (|| unsafe { ptr.read_volatile() }, |value: u64| unsafe { ptr.write_volatile(value) });
+    write(0x1111);
+    
+    // Test 1: The copy shares the frame instead of duplicating it
+    let child_id = copy_address_space(parent_id)?;
+    if memory::frame_ref_count(frame) != 2 {
+        return Err(VmError::TestFailed);
+    }
+    
+    // Test 2: A write by the parent copies the frame, leaving the child's contents alone
+    write(0xAAAA);
+    if read() != 0xAAAA || memory::frame_ref_count(frame) != 1 {
+        return Err(VmError::TestFailed);
+    }
+    switch_address_space(child_id)?;
+    if read() != 0x1111 {
+        return Err(VmError::TestFailed);
+    }
+    
+    // Test 3: The child, now the frame's only owner, writes to it in place
+    write(0xBBBB);
+    if read() != 0xBBBB || memory::frame_ref_count(frame) != 1 {
+        return Err(VmError::TestFailed);
+    }
+    switch_address_space(parent_id)?;
+    if read() != 0xAAAA {
+        return Err(VmError::TestFailed);
+    }
+    
+    // Test 4: The frame is freed once the last side sharing it unmaps it
+    let grandchild_id = copy_address_space(child_id)?;
+    if memory::frame_ref_count(frame) != 2 {
+        return Err(VmError::TestFailed);
+    }
+    destroy_address_space(grandchild_id)?;
+    if memory::frame_ref_count(frame) != 1 {
+        return Err(VmError::TestFailed);
+    }
+    unmap_page(child_id, test_addr)?;
+    if memory::frame_ref_count(frame) != 0 {
+        return Err(VmError::TestFailed);
+    }
+    
+    // Clean up
+    destroy_address_space(child_id)?;
+    destroy_address_space(parent_id)?;
+    
+    Ok(())
+}
+
+/// Test a demand-zero anonymous mapping, and punching a hole in its middle
+pub fn test_anonymous_mapping() -> VmResult<()> {
+    let as_id = create_address_space()?;
+    let pml4_frame = VMM.read().get_address_space(as_id)
+        .ok_or(VmError::InvalidAddressSpace)?
+        .pml4_frame;
+    let mapped = |addr: VirtAddr| memory::with_page_table_mapper(pml4_frame, |mapper| {
+        mapper.translate_page(Page::<Size4KiB>::containing_address(addr)).is_ok()
+    });
+    let areas = || get_address_space_info(as_id)
+        .map(|(_, areas)| areas.into_iter().map(|(start, end, _, _)| (start, end)).collect::<Vec<_>>())
+        .unwrap_or_default();
+    
+    // The kernel touches the pages itself here, so they are mapped without USER
+    let permissions = VmPermissions::READ | VmPermissions::WRITE;
+    let anonymous = MappingSource::Anonymous { shared: false };
+    let start = map_memory(as_id, None, 3 * 4096, permissions, false, anonymous.clone())?;
+    let page = |index: u64| start + index * 4096;
+    
+    // Test 1: The mapping is one area, with no pages backed before they are touched
+    if areas() != [(page(0), page(3))] || (0..3).any(|index| mapped(page(index))) {
+        return Err(VmError::TestFailed);
+    }
+    
+    switch_address_space(as_id)?;
+    // SAFETY: This is unsafe because:
+    // - Every address used lies in a page of the mapping in the active address space, or of
+    //   the hole at the point it is mapped again, so the fault handler backs it on first access
+    // - The addresses are u64 aligned
+    // - No other code accesses this test memory
+    let read = |addr: VirtAddr| unsafe { addr.as_ptr::<u64>().read_volatile() };
+    let write = |addr: VirtAddr, value: u64| unsafe { addr.as_mut_ptr::<u64>().write_volatile(value) };
+    
+    // Test 2: Touching a page faults in a zeroed frame, which keeps what is written to it
+    for index in 0..3 {
+        if read(page(index) + 8u64) != 0 {
+            return Err(VmError::TestFailed);
+        }
+        write(page(index), 0x1000 + index);
+    }
+    if !(0..3).all(|index| mapped(page(index)) && read(page(index)) == 0x1000 + index) {
+        return Err(VmError::TestFailed);
+    }
+    
+    // Test 3: Unmapping the middle page splits the area around the hole and frees its page
+    unmap_memory(as_id, page(1), 4096)?;
+    if areas() != [(page(0), page(1)), (page(2), page(3))] || mapped(page(1)) {
+        return Err(VmError::TestFailed);
+    }
+    if read(page(0)) != 0x1000 || read(page(2)) != 0x1002 {
+        return Err(VmError::TestFailed);
+    }
+    
+    // Test 4: A fault in the hole is not served, and only the hole takes a fixed mapping
+    if !matches!(with_vmm(|vmm| vmm.handle_page_fault(page(1), 0)), Err(VmError::SegmentationFault)) {
+        return Err(VmError::TestFailed);
+    }
+    if !matches!(map_memory(as_id, Some(page(2)), 4096, permissions, true, anonymous.clone()), Err(VmError::AddressInUse)) {
+        return Err(VmError::TestFailed);
+    }
+    if map_memory(as_id, Some(page(1)), 4096, permissions, true, anonymous)? != page(1) || read(page(1)) != 0 {
+        return Err(VmError::TestFailed);
+    }
+    
+    // Clean up
+    destroy_address_space(as_id)?;
+    
+    Ok(())
+}
+
 /// Run all VMM tests
 pub fn run_vmm_tests() -> VmResult<()> {
     crate::serial::_print(format_args!("[VMM] Testing address space isolation..."));
@@ -1357,6 +1995,14 @@ pub fn run_vmm_tests() -> VmResult<()> {
     test_memory_protection()?;
     crate::serial::_print(format_args!(" PASS\n"));
     
+    crate::serial::_print(format_args!("[VMM] Testing copy-on-write..."));
+    test_copy_on_write()?;
+    crate::serial::_print(format_args!(" PASS\n"));
+    
+    crate::serial::_print(format_args!("[VMM] Testing anonymous mappings..."));
+    test_anonymous_mapping()?;
+    crate::serial::_print(format_args!(" PASS\n"));
+    
     crate::serial::_print(format_args!("[VMM] All tests passed!\n"));
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,113 @@
+//! Filesystem Watch Test
+//! Watches a directory and a file on a mounted in-memory filesystem and checks that creating,
+//! writing, renaming and removing through the VFS delivers the matching events: entries
+//! directly inside a watched directory are reported and deeper ones are not, a watch
+//! follows its path when renamed, and removing a watched path ends the watch with a final
+//! `Deleted`
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use crate::filesystem::watch::{self, WatchEvent, WATCH_ALL, WATCH_CREATED, WATCH_DELETED};
+use crate::filesystem::{self, FileSystemError, MemoryFileSystem, O_CREAT, O_WRONLY};
+use crate::serial::_print;
+
+const MOUNT: &str = "/mnt/watch-test";
+
+fn path(name: &str) -> String {
+    format!("{}/{}", MOUNT, name)
+}
+
+/// Write `data` to `name` in two writes, creating it if needed
+fn write(name: &str, data: &[u8]) -> Result<(), &'static str> {
+    let fd = filesystem::open(&path(name), O_WRONLY | O_CREAT).map_err(|_| "Failed to open for writing")?;
+    let (head, tail) = data.split_at(data.len() / 2);
+    let written = filesystem::write(fd, head).and_then(|_| filesystem::write(fd, tail));
+    let _ = filesystem::close(fd);
+    written.map(|_| ()).map_err(|_| "Failed to write")
+}
+
+fn run_event_tests() -> Result<(), &'static str> {
+    filesystem::create_directory(&path("docs")).map_err(|_| "Failed to create a directory")?;
+    filesystem::create_directory(&path("docs/deep")).map_err(|_| "Failed to create a directory")?;
+
+    // Test 1: Creating in a watched directory delivers Created, and writes Modified
+    _print(format_args!("[Watch Test] Test 1: Directory watch...\n"));
+    let docs = watch::add_watch(&path("docs"), WATCH_ALL).map_err(|_| "Failed to watch a directory")?;
+    if !watch::poll_events(docs).map_err(|_| "Failed to poll")?.is_empty() {
+        return Err("Events queued before any change");
+    }
+    write("docs/notes.txt", b"hello")?;
+    write("docs/deep/hidden.txt", b"too deep")?;
+    let events = watch::read_events(docs).map_err(|_| "Failed to read events")?;
+    let expected = [WatchEvent::Created(path("docs/notes.txt")), WatchEvent::Modified(path("docs/notes.txt"))];
+    if events != expected {
+        return Err("Created then one Modified not delivered");
+    }
+    _print(format_args!("[Watch Test] ✓ Got {:?}\n", events));
+
+    // Test 2: A file watch sees only its file, filtered by mask, and follows a rename
+    _print(format_args!("[Watch Test] Test 2: File watch and rename...\n"));
+    let notes = watch::add_watch(&path("docs/notes.txt"), WATCH_ALL).map_err(|_| "Failed to watch a file")?;
+    let creations = watch::add_watch(&path("docs"), WATCH_CREATED).map_err(|_| "Failed to watch a directory")?;
+    write("docs/other.txt", b"sibling")?;
+    filesystem::rename(&path("docs/notes.txt"), &path("notes.txt")).map_err(|_| "Failed to rename")?;
+    write("notes.txt", b"moved")?;
+    let renamed = WatchEvent::Renamed { from: path("docs/notes.txt"), to: path("notes.txt") };
+    if watch::poll_events(notes).ok() != Some(vec![renamed.clone(), WatchEvent::Modified(path("notes.txt"))]) {
+        return Err("File watch saw the wrong events or lost its file on rename");
+    }
+    if watch::poll_events(creations).ok() != Some(vec![WatchEvent::Created(path("docs/other.txt"))]) {
+        return Err("Mask not applied");
+    }
+    let events = watch::poll_events(docs).map_err(|_| "Failed to poll")?;
+    if events != [WatchEvent::Created(path("docs/other.txt")), WatchEvent::Modified(path("docs/other.txt")), renamed] {
+        return Err("Directory watch missed a rename out of it");
+    }
+    _print(format_args!("[Watch Test] ✓ notes.txt watch followed it out of docs\n"));
+
+    // Test 3: Removing a watched path ends its watch with a final Deleted
+    _print(format_args!("[Watch Test] Test 3: Removing watched paths...\n"));
+    let deletions = watch::add_watch(&path("docs"), WATCH_DELETED).map_err(|_| "Failed to watch a directory")?;
+    filesystem::remove(&path("notes.txt")).map_err(|_| "Failed to remove a file")?;
+    filesystem::remove(&path("docs")).map_err(|_| "Failed to remove a directory")?;
+    // Nothing new once the watched path is gone
+    let _ = filesystem::create_directory(&path("docs"));
+    let finals = [(notes, "notes.txt"), (docs, "docs"), (creations, "docs"), (deletions, "docs")];
+    for (id, name) in finals {
+        let last = watch::read_events(id).map_err(|_| "Removed watch unreadable")?.pop();
+        if last != Some(WatchEvent::Deleted(path(name))) || !matches!(watch::poll_events(id), Err(FileSystemError::NotFound)) {
+            return Err("Watch not ended by a final Deleted");
+        }
+    }
+    if watch::remove_watch(docs).is_ok() {
+        return Err("Ended watch could still be removed");
+    }
+    _print(format_args!("[Watch Test] ✓ {} watches ended by their final Deleted\n", finals.len()));
+
+    _print(format_args!("[Watch Test] ✓ All filesystem watch tests completed successfully!\n"));
+    Ok(())
+}
+
+pub fn run_watch_tests() -> Result<(), &'static str> {
+    let memory_fs = MemoryFileSystem::new(String::from("watch-test"));
+    filesystem::mount_filesystem(Box::new(memory_fs), MOUNT).map_err(|_| "Failed to mount the test filesystem")?;
+    let result = run_event_tests();
+    let _ = filesystem::unmount_filesystem(MOUNT);
+    result
+}
+
+/// Main test runner for filesystem watches
+pub fn test_watch() {
+    _print(format_args!("[Watch Test] ===========================================\n"));
+    _print(format_args!("[Watch Test]         FILESYSTEM WATCH TESTS\n"));
+    _print(format_args!("[Watch Test] ===========================================\n"));
+
+    match run_watch_tests() {
+        Ok(_) => _print(format_args!("[Watch Test] ✓ All filesystem watch tests PASSED\n")),
+        Err(e) => _print(format_args!("[Watch Test] ✗ Filesystem watch tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Watch Test] ===========================================\n"));
+}
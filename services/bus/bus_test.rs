@@ -0,0 +1,137 @@
+//! Message Bus Tests
+//! Connects several clients to a private bus: a method call and its reply make the round trip
+//! by well-known name, a broadcast signal reaches every subscriber and no one else, and a name
+//! already owned cannot be taken until its owner lets go
+
+use alloc::vec;
+
+use super::{BusError, MatchRule, Message, MessageBus, MessageKind, BUS_INTERFACE, ERROR_NO_REPLY};
+use crate::serial::_print;
+
+const NOTES: &str = "org.raeen.Notes";
+const NOTES_INTERFACE: &str = "org.raeen.Notes1";
+
+pub fn run_bus_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Bus Test] Starting message bus tests...\n"));
+
+    // Test 1: a method call reaches the owner of a well-known name and the reply comes back
+    _print(format_args!("[Bus Test] Test 1: Method call and reply...\n"));
+    let mut bus = MessageBus::new();
+    let (service, service_name) = bus.connect();
+    let (client, client_name) = bus.connect();
+    bus.request_name(service, NOTES).map_err(|_| "Free name not granted")?;
+    let call = Message::method_call(NOTES, "/org/raeen/Notes", NOTES_INTERFACE, "Count", vec![]);
+    let serial = bus.send(client, call).map_err(|_| "Method call not routed")?;
+    let received = bus.receive(service).map_err(|_| "Service not connected")?.ok_or("Call not delivered")?;
+    if received.kind != MessageKind::MethodCall || received.sender != client_name || received.serial != serial {
+        return Err("Call delivered without its sender and serial");
+    }
+    bus.send(service, Message::method_return(&received, vec![3])).map_err(|_| "Reply not routed")?;
+    let reply = bus.receive(client).map_err(|_| "Client not connected")?.ok_or("Reply not delivered")?;
+    if reply.kind != MessageKind::MethodReturn || reply.reply_serial != Some(serial) || reply.sender != service_name || reply.body != [3] {
+        return Err("Reply does not answer the call");
+    }
+    if bus.send(service, Message::method_return(&received, vec![4])) != Err(BusError::UnexpectedReply) {
+        return Err("Second reply to one call accepted");
+    }
+    let (intruder, _) = bus.connect();
+    let second = bus.send(client, Message::method_call(NOTES, "/org/raeen/Notes", NOTES_INTERFACE, "Count", vec![]))
+        .map_err(|_| "Method call not routed")?;
+    let pending = bus.receive(service).map_err(|_| "Service not connected")?.ok_or("Call not delivered")?;
+    if pending.serial != second || bus.send(intruder, Message::method_return(&pending, vec![])) != Err(BusError::UnexpectedReply) {
+        return Err("Reply from a connection that was not called accepted");
+    }
+    if !matches!(bus.send(client, Message::method_call("org.raeen.Nobody", "/", NOTES_INTERFACE, "Count", vec![])), Err(BusError::ServiceUnknown(_))) {
+        return Err("Call to an unowned name routed");
+    }
+    _print(format_args!("[Bus Test] ✓ {} answered {}'s call\n", NOTES, client_name));
+
+    // Test 2: a broadcast signal reaches every subscriber once, and only them
+    _print(format_args!("[Bus Test] Test 2: Broadcasting a signal...\n"));
+    let mut bus = MessageBus::new();
+    let (power, _) = bus.connect();
+    let (dock, _) = bus.connect();
+    let (panel, _) = bus.connect();
+    let (editor, _) = bus.connect();
+    bus.request_name(power, "org.raeen.Power").map_err(|_| "Free name not granted")?;
+    bus.add_match(dock, MatchRule::signal("org.raeen.Power1", Some("BatteryLow"))).map_err(|_| "Rule not added")?;
+    let mut from_power = MatchRule::signal("org.raeen.Power1", None);
+    from_power.sender = Some("org.raeen.Power".into());
+    bus.add_match(panel, from_power).map_err(|_| "Rule not added")?;
+    bus.add_match(editor, MatchRule::signal("org.raeen.Power1", Some("Charging"))).map_err(|_| "Rule not added")?;
+    bus.send(power, Message::signal("/org/raeen/Power", "org.raeen.Power1", "BatteryLow", vec![9]))
+        .map_err(|_| "Signal not sent")?;
+    for subscriber in [dock, panel] {
+        let signal = bus.receive(subscriber).map_err(|_| "Subscriber not connected")?.ok_or("Subscriber missed the signal")?;
+        if signal.kind != MessageKind::Signal || signal.member != "BatteryLow" || signal.body != [9] {
+            return Err("Subscriber got the wrong signal");
+        }
+        if bus.pending(subscriber) != Ok(0) {
+            return Err("Subscriber got the signal twice");
+        }
+    }
+    if bus.pending(editor) != Ok(0) || bus.pending(power) != Ok(0) {
+        return Err("Signal reached a connection that did not subscribe to it");
+    }
+    bus.send(editor, Message::signal("/org/raeen/Power", "org.raeen.Power1", "Charging", vec![]))
+        .map_err(|_| "Signal not sent")?;
+    if bus.pending(panel) != Ok(0) || bus.pending(editor) != Ok(1) {
+        return Err("Sender rule matched a signal from another connection");
+    }
+    _print(format_args!("[Bus Test] ✓ Signal reached both subscribers and no one else\n"));
+
+    // Test 3: a well-known name has one owner at a time
+    _print(format_args!("[Bus Test] Test 3: Enforcing name ownership...\n"));
+    let mut bus = MessageBus::new();
+    let (first, first_name) = bus.connect();
+    let (second, second_name) = bus.connect();
+    let (watcher, _) = bus.connect();
+    bus.add_match(watcher, MatchRule::signal(BUS_INTERFACE, Some("NameOwnerChanged"))).map_err(|_| "Rule not added")?;
+    bus.request_name(first, NOTES).map_err(|_| "Free name not granted")?;
+    if bus.request_name(second, NOTES) != Err(BusError::NameTaken(NOTES.into())) {
+        return Err("Owned name granted to a second connection");
+    }
+    if bus.request_name(first, NOTES).is_err() || bus.name_owner(NOTES) != Some(first_name.clone()) {
+        return Err("Owner lost its name by asking again");
+    }
+    if bus.release_name(second, NOTES) != Err(BusError::NameNotOwned(NOTES.into())) {
+        return Err("Name released by a connection that does not own it");
+    }
+    for bad in ["notes", "org..raeen", "org.raeen.1Notes", ":1.1", super::BUS_NAME] {
+        if !matches!(bus.request_name(second, bad), Err(BusError::InvalidName(_))) {
+            return Err("Invalid or reserved name granted");
+        }
+    }
+    let stranded = bus.send(second, Message::method_call(NOTES, "/", NOTES_INTERFACE, "Count", vec![]))
+        .map_err(|_| "Method call not routed")?;
+    bus.disconnect(first).map_err(|_| "Owner not disconnected")?;
+    let error = bus.receive(second).map_err(|_| "Caller not connected")?.ok_or("Caller not told its call failed")?;
+    if error.kind != MessageKind::Error || error.reply_serial != Some(stranded) || error.error_name.as_deref() != Some(ERROR_NO_REPLY) {
+        return Err("Stranded call not answered with an error");
+    }
+    bus.request_name(second, NOTES).map_err(|_| "Released name not granted")?;
+    if bus.name_owner(NOTES) != Some(second_name.clone()) || bus.list_names() != [(NOTES.into(), second_name)] {
+        return Err("Name not handed to its new owner");
+    }
+    if bus.pending(watcher) != Ok(3) {
+        return Err("Ownership changes not announced");
+    }
+    _print(format_args!("[Bus Test] ✓ Second owner rejected until the first disconnected\n"));
+
+    _print(format_args!("[Bus Test] ✓ All message bus tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for the message bus
+pub fn test_bus_service() {
+    _print(format_args!("[Bus Test] ===========================================\n"));
+    _print(format_args!("[Bus Test]            MESSAGE BUS TESTS\n"));
+    _print(format_args!("[Bus Test] ===========================================\n"));
+
+    match run_bus_tests() {
+        Ok(_) => _print(format_args!("[Bus Test] ✓ All message bus tests PASSED\n")),
+        Err(e) => _print(format_args!("[Bus Test] ✗ Message bus tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Bus Test] ===========================================\n"));
+}
@@ -0,0 +1,452 @@
+//! Message Bus Service (rae-busd)
+//! Routes messages between connected apps, in the manner of D-Bus. Every connection gets a
+//! unique name (`:1.N`) and may own well-known names such as `org.raeen.Notes`, one owner per
+//! name. Method calls go to a named destination and their replies go back only to the caller
+//! that is waiting for them; signals are broadcast to every connection whose match rules
+//! accept them. The bus stamps each message with its sender, so no app can speak for a name it
+//! does not own.
+
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use spin::Mutex;
+
+pub mod bus_test;
+
+/// The bus's own name, the sender of the signals it emits
+pub const BUS_NAME: &str = "org.raeen.Bus";
+/// Interface of the bus's own signals
+pub const BUS_INTERFACE: &str = "org.raeen.Bus";
+/// Error sent to callers whose destination disconnected before replying
+pub const ERROR_NO_REPLY: &str = "org.raeen.Error.NoReply";
+/// Messages a connection may have waiting before sends to it fail
+const MAX_QUEUED_MESSAGES: usize = 256;
+/// Longest well-known name, interface or member
+const MAX_NAME_LENGTH: usize = 255;
+
+/// A connection to the bus
+pub type ClientId = u64;
+
+/// Bus errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BusError {
+    /// The system bus has not been started
+    NotRunning,
+    UnknownConnection,
+    InvalidName(String),
+    /// Owned by another connection
+    NameTaken(String),
+    NameNotOwned(String),
+    /// No connection owns the destination
+    ServiceUnknown(String),
+    /// A method call or reply without a destination
+    MissingDestination,
+    /// A reply to no call the destination is waiting on from this sender
+    UnexpectedReply,
+    QueueFull,
+    MatchRuleNotFound,
+}
+
+/// What a message is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    MethodCall,
+    MethodReturn,
+    Error,
+    Signal,
+}
+
+/// One message on the bus
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub kind: MessageKind,
+    /// Set by the bus when sent, counting from 1 per connection
+    pub serial: u32,
+    /// The sender's unique name, set by the bus
+    pub sender: String,
+    /// Well-known or unique name; none for a broadcast signal
+    pub destination: Option<String>,
+    pub path: String,
+    pub interface: String,
+    pub member: String,
+    /// The call a method return or error answers
+    pub reply_serial: Option<u32>,
+    pub error_name: Option<String>,
+    pub body: Vec<u8>,
+}
+
+impl Message {
+    fn new(kind: MessageKind, path: &str, interface: &str, member: &str, body: Vec<u8>) -> Self {
+        Self {
+            kind,
+            serial: 0,
+            sender: String::new(),
+            destination: None,
+            path: path.to_string(),
+            interface: interface.to_string(),
+            member: member.to_string(),
+            reply_serial: None,
+            error_name: None,
+            body,
+        }
+    }
+
+    pub fn method_call(destination: &str, path: &str, interface: &str, member: &str, body: Vec<u8>) -> Self {
+        let mut message = Self::new(MessageKind::MethodCall, path, interface, member, body);
+        message.destination = Some(destination.to_string());
+        message
+    }
+
+    /// A signal for every connection whose match rules accept it
+    pub fn signal(path: &str, interface: &str, member: &str, body: Vec<u8>) -> Self {
+        Self::new(MessageKind::Signal, path, interface, member, body)
+    }
+
+    /// The answer to `call`, back to its sender
+    pub fn method_return(call: &Message, body: Vec<u8>) -> Self {
+        let mut message = Self::new(MessageKind::MethodReturn, &call.path, &call.interface, &call.member, body);
+        message.destination = Some(call.sender.clone());
+        message.reply_serial = Some(call.serial);
+        message
+    }
+
+    /// `call` failed with `error_name`; `text` says why
+    pub fn error(call: &Message, error_name: &str, text: &str) -> Self {
+        let mut message = Self::new(MessageKind::Error, &call.path, &call.interface, &call.member, text.as_bytes().to_vec());
+        message.destination = Some(call.sender.clone());
+        message.reply_serial = Some(call.serial);
+        message.error_name = Some(error_name.to_string());
+        message
+    }
+}
+
+/// Which broadcast signals a connection receives; an unset field matches anything
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MatchRule {
+    /// Well-known or unique name of the sender
+    pub sender: Option<String>,
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+}
+
+impl MatchRule {
+    /// Signals of `interface`, optionally only `member`
+    pub fn signal(interface: &str, member: Option<&str>) -> Self {
+        Self { interface: Some(interface.to_string()), member: member.map(str::to_string), ..Self::default() }
+    }
+}
+
+/// Whether `name` is a valid well-known name or interface: two or more dot-separated
+/// elements of ASCII letters, digits, `_` and `-`, none starting with a digit
+fn valid_dotted_name(name: &str) -> bool {
+    name.len() <= MAX_NAME_LENGTH
+        && name.split('.').count() >= 2
+        && name.split('.').all(|element| {
+            !element.is_empty()
+                && !element.starts_with(|c: char| c.is_ascii_digit())
+                && element.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        })
+}
+
+fn valid_member(member: &str) -> bool {
+    !member.is_empty()
+        && member.len() <= MAX_NAME_LENGTH
+        && !member.starts_with(|c: char| c.is_ascii_digit())
+        && member.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+struct Connection {
+    unique_name: String,
+    owned: BTreeSet<String>,
+    rules: Vec<MatchRule>,
+    queue: VecDeque<Message>,
+    next_serial: u32,
+}
+
+/// The bus: its connections, who owns which name, and the calls awaiting replies
+pub struct MessageBus {
+    connections: BTreeMap<ClientId, Connection>,
+    /// Well-known names and their owners
+    names: BTreeMap<String, ClientId>,
+    /// Calls not yet answered: (caller, serial) and the connection that must answer
+    pending_calls: BTreeMap<(ClientId, u32), ClientId>,
+    next_client: ClientId,
+    bus_serial: u32,
+}
+
+impl MessageBus {
+    pub fn new() -> Self {
+        Self {
+            connections: BTreeMap::new(),
+            names: BTreeMap::new(),
+            pending_calls: BTreeMap::new(),
+            next_client: 1,
+            bus_serial: 0,
+        }
+    }
+
+    /// A new connection, with its unique name
+    pub fn connect(&mut self) -> (ClientId, String) {
+        let client = self.next_client;
+        self.next_client += 1;
+        let unique_name = format!(":1.{}", client);
+        self.connections.insert(client, Connection {
+            unique_name: unique_name.clone(),
+            owned: BTreeSet::new(),
+            rules: Vec::new(),
+            queue: VecDeque::new(),
+            next_serial: 1,
+        });
+        (client, unique_name)
+    }
+
+    /// Close `client`: its names are released, and callers still waiting on it get a
+    /// `ERROR_NO_REPLY` error
+    pub fn disconnect(&mut self, client: ClientId) -> Result<(), BusError> {
+        let connection = self.connections.remove(&client).ok_or(BusError::UnknownConnection)?;
+        for name in &connection.owned {
+            self.names.remove(name);
+            self.name_owner_changed(name, &connection.unique_name, "");
+        }
+
+        let stranded: Vec<(ClientId, u32)> = self.pending_calls.iter()
+            .filter(|(&(caller, _), &callee)| caller == client || callee == client)
+            .map(|(&call, _)| call)
+            .collect();
+        for (caller, serial) in stranded {
+            self.pending_calls.remove(&(caller, serial));
+            if caller == client {
+                continue;
+            }
+            let Some(waiting) = self.connections.get(&caller) else { continue };
+            let mut error = Message::new(MessageKind::Error, "/", BUS_INTERFACE, "Disconnected", Vec::new());
+            error.sender = connection.unique_name.clone();
+            error.destination = Some(waiting.unique_name.clone());
+            error.reply_serial = Some(serial);
+            error.error_name = Some(ERROR_NO_REPLY.to_string());
+            self.deliver(caller, error);
+        }
+        Ok(())
+    }
+
+    /// Take ownership of the well-known `name`. Owning it already is not an error; another
+    /// connection owning it is
+    pub fn request_name(&mut self, client: ClientId, name: &str) -> Result<(), BusError> {
+        if !valid_dotted_name(name) || name == BUS_NAME {
+            return Err(BusError::InvalidName(name.to_string()));
+        }
+        let unique_name = self.connections.get(&client).ok_or(BusError::UnknownConnection)?.unique_name.clone();
+        match self.names.get(name) {
+            Some(&owner) if owner == client => return Ok(()),
+            Some(_) => return Err(BusError::NameTaken(name.to_string())),
+            None => {}
+        }
+        self.names.insert(name.to_string(), client);
+        if let Some(connection) = self.connections.get_mut(&client) {
+            connection.owned.insert(name.to_string());
+        }
+        self.name_owner_changed(name, "", &unique_name);
+        Ok(())
+    }
+
+    pub fn release_name(&mut self, client: ClientId, name: &str) -> Result<(), BusError> {
+        let connection = self.connections.get_mut(&client).ok_or(BusError::UnknownConnection)?;
+        if !connection.owned.remove(name) {
+            return Err(BusError::NameNotOwned(name.to_string()));
+        }
+        let unique_name = connection.unique_name.clone();
+        self.names.remove(name);
+        self.name_owner_changed(name, &unique_name, "");
+        Ok(())
+    }
+
+    /// The unique name of the connection that owns `name`, well-known or unique
+    pub fn name_owner(&self, name: &str) -> Option<String> {
+        self.resolve(name).and_then(|client| self.connections.get(&client)).map(|connection| connection.unique_name.clone())
+    }
+
+    /// Every well-known name and its owner's unique name
+    pub fn list_names(&self) -> Vec<(String, String)> {
+        self.names.iter()
+            .filter_map(|(name, client)| Some((name.clone(), self.connections.get(client)?.unique_name.clone())))
+            .collect()
+    }
+
+    /// Receive broadcast signals that `rule` accepts
+    pub fn add_match(&mut self, client: ClientId, rule: MatchRule) -> Result<(), BusError> {
+        self.connections.get_mut(&client).ok_or(BusError::UnknownConnection)?.rules.push(rule);
+        Ok(())
+    }
+
+    pub fn remove_match(&mut self, client: ClientId, rule: &MatchRule) -> Result<(), BusError> {
+        let connection = self.connections.get_mut(&client).ok_or(BusError::UnknownConnection)?;
+        let index = connection.rules.iter().position(|existing| existing == rule).ok_or(BusError::MatchRuleNotFound)?;
+        connection.rules.remove(index);
+        Ok(())
+    }
+
+    /// Route `message` from `client`, which the bus stamps as its sender; returns the
+    /// message's serial, which replies to a method call refer to
+    pub fn send(&mut self, client: ClientId, mut message: Message) -> Result<u32, BusError> {
+        if !valid_dotted_name(&message.interface) {
+            return Err(BusError::InvalidName(message.interface));
+        }
+        if !valid_member(&message.member) {
+            return Err(BusError::InvalidName(message.member));
+        }
+        let connection = self.connections.get(&client).ok_or(BusError::UnknownConnection)?;
+        message.sender = connection.unique_name.clone();
+        message.serial = connection.next_serial;
+
+        let destination = match &message.destination {
+            Some(name) => Some(self.resolve(name).ok_or_else(|| BusError::ServiceUnknown(name.clone()))?),
+            None if message.kind == MessageKind::Signal => None,
+            None => return Err(BusError::MissingDestination),
+        };
+        match (message.kind, destination) {
+            (MessageKind::MethodCall, Some(callee)) => {
+                self.check_room(callee)?;
+                self.pending_calls.insert((client, message.serial), callee);
+                self.deliver(callee, message.clone());
+            }
+            (MessageKind::MethodReturn | MessageKind::Error, Some(caller)) => {
+                let call = (caller, message.reply_serial.ok_or(BusError::UnexpectedReply)?);
+                if self.pending_calls.get(&call) != Some(&client) {
+                    return Err(BusError::UnexpectedReply);
+                }
+                self.check_room(caller)?;
+                self.pending_calls.remove(&call);
+                self.deliver(caller, message.clone());
+            }
+            (MessageKind::Signal, Some(target)) => {
+                self.check_room(target)?;
+                self.deliver(target, message.clone());
+            }
+            (MessageKind::Signal, None) => self.broadcast(&message),
+            (_, None) => return Err(BusError::MissingDestination),
+        }
+
+        if let Some(connection) = self.connections.get_mut(&client) {
+            connection.next_serial = connection.next_serial.wrapping_add(1).max(1);
+        }
+        Ok(message.serial)
+    }
+
+    /// The next message for `client`, oldest first
+    pub fn receive(&mut self, client: ClientId) -> Result<Option<Message>, BusError> {
+        Ok(self.connections.get_mut(&client).ok_or(BusError::UnknownConnection)?.queue.pop_front())
+    }
+
+    /// Messages waiting for `client`
+    pub fn pending(&self, client: ClientId) -> Result<usize, BusError> {
+        Ok(self.connections.get(&client).ok_or(BusError::UnknownConnection)?.queue.len())
+    }
+
+    /// The connection a well-known or unique name refers to
+    fn resolve(&self, name: &str) -> Option<ClientId> {
+        if name.starts_with(':') {
+            self.connections.iter().find(|(_, connection)| connection.unique_name == name).map(|(&client, _)| client)
+        } else {
+            self.names.get(name).copied()
+        }
+    }
+
+    fn check_room(&self, client: ClientId) -> Result<(), BusError> {
+        match self.connections.get(&client) {
+            Some(connection) if connection.queue.len() >= MAX_QUEUED_MESSAGES => Err(BusError::QueueFull),
+            Some(_) => Ok(()),
+            None => Err(BusError::UnknownConnection),
+        }
+    }
+
+    fn deliver(&mut self, client: ClientId, message: Message) {
+        if let Some(connection) = self.connections.get_mut(&client) {
+            connection.queue.push_back(message);
+        }
+    }
+
+    fn rule_matches(&self, rule: &MatchRule, message: &Message) -> bool {
+        let sender_matches = match &rule.sender {
+            None => true,
+            Some(name) if name.starts_with(':') || name == BUS_NAME => *name == message.sender,
+            Some(name) => self.name_owner(name).as_deref() == Some(message.sender.as_str()),
+        };
+        sender_matches
+            && rule.path.as_ref().is_none_or(|path| *path == message.path)
+            && rule.interface.as_ref().is_none_or(|interface| *interface == message.interface)
+            && rule.member.as_ref().is_none_or(|member| *member == message.member)
+    }
+
+    /// Queue `signal` once for every connection with a rule that accepts it; a full queue
+    /// misses the signal rather than failing the broadcast
+    fn broadcast(&mut self, signal: &Message) {
+        let subscribers: Vec<ClientId> = self.connections.iter()
+            .filter(|(_, connection)| connection.rules.iter().any(|rule| self.rule_matches(rule, signal)))
+            .map(|(&client, _)| client)
+            .collect();
+        for client in subscribers {
+            if self.check_room(client).is_ok() {
+                self.deliver(client, signal.clone());
+            }
+        }
+    }
+
+    /// Broadcast `NameOwnerChanged` with the name, old owner and new owner, NUL-separated;
+    /// an empty owner means none
+    fn name_owner_changed(&mut self, name: &str, old_owner: &str, new_owner: &str) {
+        self.bus_serial = self.bus_serial.wrapping_add(1).max(1);
+        let body = format!("{}\0{}\0{}", name, old_owner, new_owner).into_bytes();
+        let mut signal = Message::signal("/org/raeen/Bus", BUS_INTERFACE, "NameOwnerChanged", body);
+        signal.sender = BUS_NAME.to_string();
+        signal.serial = self.bus_serial;
+        self.broadcast(&signal);
+    }
+}
+
+/// The system bus
+static BUS: Mutex<Option<MessageBus>> = Mutex::new(None);
+
+/// Start the system bus
+pub fn init() {
+    let mut bus = BUS.lock();
+    if bus.is_none() {
+        *bus = Some(MessageBus::new());
+        crate::serial::_print(format_args!("[rae-busd] System bus ready\n"));
+    }
+}
+
+/// Run `f` on the system bus
+fn with_bus<T>(f: impl FnOnce(&mut MessageBus) -> Result<T, BusError>) -> Result<T, BusError> {
+    BUS.lock().as_mut().ok_or(BusError::NotRunning).and_then(f)
+}
+
+/// Connect to the system bus
+pub fn connect() -> Result<(ClientId, String), BusError> {
+    with_bus(|bus| Ok(bus.connect()))
+}
+
+pub fn disconnect(client: ClientId) -> Result<(), BusError> {
+    with_bus(|bus| bus.disconnect(client))
+}
+
+pub fn request_name(client: ClientId, name: &str) -> Result<(), BusError> {
+    with_bus(|bus| bus.request_name(client, name))
+}
+
+pub fn release_name(client: ClientId, name: &str) -> Result<(), BusError> {
+    with_bus(|bus| bus.release_name(client, name))
+}
+
+pub fn add_match(client: ClientId, rule: MatchRule) -> Result<(), BusError> {
+    with_bus(|bus| bus.add_match(client, rule))
+}
+
+pub fn send(client: ClientId, message: Message) -> Result<u32, BusError> {
+    with_bus(|bus| bus.send(client, message))
+}
+
+pub fn receive(client: ClientId) -> Result<Option<Message>, BusError> {
+    with_bus(|bus| bus.receive(client))
+}
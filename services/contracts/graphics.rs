@@ -15,6 +15,7 @@ pub enum GraphicsRequest {
     MoveWindow { window_id: u32, x: i32, y: i32 },
     ResizeWindow { window_id: u32, width: u32, height: u32 },
     SetWindowTitle { window_id: u32, title: String },
+    SetWindowOpacity { window_id: u32, opacity: u8 },
     FocusWindow { window_id: u32 },
     GetWindowList,
     GetWindowInfo { window_id: u32 },
@@ -31,6 +32,10 @@ pub enum GraphicsRequest {
     GetFramebufferInfo,
     SetVsync { enabled: bool },
     GetFrameStats,
+    /// ARGB pixels, row by row
+    SetCursorBitmap { width: u32, height: u32, pixels: Vec<u32> },
+    MoveCursor { x: i32, y: i32 },
+    SetCursorVisible { visible: bool },
     
     // Compositor operations
     SetCompositorMode { mode: CompositorMode },
@@ -62,6 +67,7 @@ pub enum GraphicsResponse {
     WindowMoved,
     WindowResized,
     WindowTitleSet,
+    WindowOpacitySet,
     WindowFocused,
     WindowList { windows: Vec<WindowInfo> },
     WindowInfo { info: WindowInfo },
@@ -76,6 +82,9 @@ pub enum GraphicsResponse {
     FramebufferInfo { info: FramebufferInfo },
     VsyncSet,
     FrameStats { stats: FrameStatistics },
+    CursorBitmapSet,
+    CursorMoved,
+    CursorVisibilitySet,
     
     CompositorModeSet,
     CompositorStats { stats: CompositorStatistics },
@@ -96,7 +105,7 @@ pub enum GraphicsResponse {
 }
 
 /// Window rectangle
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct WindowRect {
     pub x: i32,
     pub y: i32,
@@ -104,6 +113,56 @@ pub struct WindowRect {
     pub height: u32,
 }
 
+impl WindowRect {
+    pub const fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// One past the rightmost column
+    pub fn right(&self) -> i64 {
+        self.x as i64 + self.width as i64
+    }
+
+    /// One past the bottom row
+    pub fn bottom(&self) -> i64 {
+        self.y as i64 + self.height as i64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    /// Whether the two share at least one pixel
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// The pixels in both, if any
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if right <= x as i64 || bottom <= y as i64 {
+            return None;
+        }
+        Some(Self::new(x, y, (right - x as i64) as u32, (bottom - y as i64) as u32))
+    }
+
+    /// The smallest rectangle holding both
+    pub fn union(&self, other: &Self) -> Self {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+        Self::new(x, y, (right - x as i64) as u32, (bottom - y as i64) as u32)
+    }
+}
+
 /// Color representation
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Color {
@@ -132,6 +191,8 @@ pub struct WindowInfo {
     pub visible: bool,
     pub focused: bool,
     pub process_id: u32,
+    /// 0 is invisible, 255 fully opaque
+    pub opacity: u8,
 }
 
 /// Framebuffer information
@@ -38,6 +38,17 @@ pub enum NetworkRequest {
     StartDhcpClient { interface_name: String },
     StopDhcpClient { interface_name: String },
     GetDhcpLease { interface_name: String },
+    
+    // Egress traffic shaping
+    SetTrafficClass { class: TrafficClass },
+    RemoveTrafficClass { class_id: u32 },
+    AssignTrafficClass { socket_id: u32, class_id: u32 },
+    SetEgressRate { interface_name: String, rate_limit: Option<RateLimit> },
+    
+    // Connection tracking and source NAT
+    SetMasquerade { network: IpAddress, prefix_len: u8, interface_name: String },
+    RemoveMasquerade { network: IpAddress, prefix_len: u8 },
+    GetConnectionTable,
 }
 
 /// Network service responses
@@ -67,6 +78,15 @@ pub enum NetworkResponse {
     DhcpClientStarted,
     DhcpClientStopped,
     DhcpLease { lease: DhcpLeaseInfo },
+    
+    TrafficClassSet,
+    TrafficClassRemoved,
+    TrafficClassAssigned,
+    EgressRateSet,
+    
+    MasqueradeSet,
+    MasqueradeRemoved,
+    ConnectionTable { connections: Vec<TrackedConnection> },
 }
 
 /// Socket domains
@@ -166,6 +186,48 @@ pub struct DhcpLeaseInfo {
     pub rebinding_time: u32,
 }
 
+/// A token bucket: a steady rate, and how much may go at once after a quiet spell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub bytes_per_second: u64,
+    pub burst_bytes: u32,
+}
+
+/// A class of outgoing traffic. Packets of classes of higher priority leave first, and a
+/// class with a rate limit never sends faster than it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrafficClass {
+    pub class_id: u32,
+    /// 0 is the highest
+    pub priority: u8,
+    pub rate_limit: Option<RateLimit>,
+}
+
+/// A flow the network service tracks, as its first packet went
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackedConnection {
+    /// IP protocol number
+    pub protocol: u8,
+    pub source: SocketAddress,
+    pub destination: SocketAddress,
+    /// What the source is rewritten to when the flow is masqueraded
+    pub translated_source: Option<SocketAddress>,
+    pub state: ConnectionState,
+    pub expires_in_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectionState {
+    /// Packets have gone one way only
+    New,
+    /// Replies have come back
+    Established,
+    /// A FIN has been seen
+    Closing,
+    /// A RST has been seen
+    Closed,
+}
+
 /// Network service performance metrics
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkMetrics {
@@ -213,4 +275,7 @@ pub mod network_errors {
     pub const ROUTE_NOT_FOUND: u32 = error_codes::INTERNAL_ERROR + 8;
     pub const DNS_RESOLUTION_FAILED: u32 = error_codes::INTERNAL_ERROR + 9;
     pub const DHCP_FAILED: u32 = error_codes::INTERNAL_ERROR + 10;
+    pub const CONNECTION_RESET: u32 = error_codes::INTERNAL_ERROR + 11;
+    pub const NOT_CONNECTED: u32 = error_codes::INTERNAL_ERROR + 12;
+    pub const WOULD_BLOCK: u32 = error_codes::INTERNAL_ERROR + 13;
 }
\ No newline at end of file
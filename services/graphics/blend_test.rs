@@ -0,0 +1,121 @@
+//! Alpha Blending Tests
+//! Composites translucent drawing and windows into an off-screen framebuffer: a half-alpha
+//! red rect over blue gives purple whether it is drawn on the blue window or on a transparent
+//! one above it, window opacity lets what lies beneath show through, and blending rounds
+//! to the nearest value without overflowing a channel
+
+use alloc::string::ToString;
+
+use super::super::contracts::graphics::{Color, WindowRect};
+use super::framebuffer_manager::FramebufferManager;
+use super::window_manager::{blend, WindowManager};
+use crate::serial::_print;
+
+const SIZE: u32 = 16;
+/// Red at alpha 128 over opaque blue: 128/255 red and the remaining 127/255 blue
+const PURPLE: u32 = 0xFF80_007F;
+const HALF_RED: Color = Color { r: 255, g: 0, b: 0, a: 128 };
+
+/// The composited pixel at `x`, `y` once the windows' damage is recomposited
+fn composited(framebuffer: &FramebufferManager, windows: &WindowManager, x: u32, y: u32) -> Result<Option<u32>, &'static str> {
+    framebuffer.composite_frame(windows).map_err(|_| "Composite failed")?;
+    Ok(framebuffer.back_pixel(x, y))
+}
+
+/// An opaque blue window filling the screen
+fn blue_screen() -> Result<(FramebufferManager, WindowManager, u32), &'static str> {
+    let framebuffer = FramebufferManager::with_size(SIZE, SIZE);
+    let windows = WindowManager::new();
+    let blue = windows.create_window("blue".to_string(), WindowRect::new(0, 0, SIZE, SIZE), 0).map_err(|_| "Window not created")?;
+    windows.clear_window(blue, Color::BLUE).map_err(|_| "Clear failed")?;
+    Ok((framebuffer, windows, blue))
+}
+
+pub fn run_blend_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Blend Test] Starting alpha blending tests...\n"));
+
+    // Test 1: a 50%-alpha red rect drawn over blue gives purple
+    _print(format_args!("[Blend Test] Test 1: Half-alpha red over blue...\n"));
+    let (framebuffer, windows, blue) = blue_screen()?;
+    windows.draw_rect(blue, WindowRect::new(2, 2, 4, 4), HALF_RED, true).map_err(|_| "Draw failed")?;
+    if composited(&framebuffer, &windows, 3, 3)? != Some(PURPLE) {
+        return Err("Half-alpha red over blue is not purple");
+    }
+    if framebuffer.back_pixel(8, 8) != Some(0xFF00_00FF) {
+        return Err("Blue outside the rect changed");
+    }
+    windows.draw_rect(blue, WindowRect::new(8, 8, 4, 4), HALF_RED, false).map_err(|_| "Draw failed")?;
+    if composited(&framebuffer, &windows, 8, 8)? != Some(PURPLE) {
+        return Err("Outline corner blended more than once");
+    }
+    _print(format_args!("[Blend Test] ✓ 50% red over blue is {:#010X}\n", PURPLE));
+
+    // Test 2: drawn on a transparent window, the red keeps its straight color
+    _print(format_args!("[Blend Test] Test 2: Half-alpha red on a transparent window...\n"));
+    let (framebuffer, windows, _) = blue_screen()?;
+    let glass = windows.create_window("glass".to_string(), WindowRect::new(0, 0, SIZE, SIZE), 1).map_err(|_| "Window not created")?;
+    windows.draw_pixel(glass, 5, 5, HALF_RED).map_err(|_| "Draw failed")?;
+    if composited(&framebuffer, &windows, 5, 5)? != Some(PURPLE) {
+        return Err("Translucent pixel on a transparent window darkened or lost");
+    }
+    windows.draw_pixel(glass, 5, 5, HALF_RED).map_err(|_| "Draw failed")?;
+    // Alpha 128 twice covers 1 - (127/255)^2 of the pixel: alpha 192, still pure red
+    if composited(&framebuffer, &windows, 5, 5)? != Some(0xFF_C0_00_3F) {
+        return Err("Translucent pixels not accumulated source-over");
+    }
+    _print(format_args!("[Blend Test] ✓ Straight alpha kept on a transparent window\n"));
+
+    // Test 3: a window's opacity lets the windows beneath it show through
+    _print(format_args!("[Blend Test] Test 3: Translucent windows...\n"));
+    let (framebuffer, windows, _) = blue_screen()?;
+    let red = windows.create_window("red".to_string(), WindowRect::new(4, 4, 8, 8), 1).map_err(|_| "Window not created")?;
+    windows.clear_window(red, Color::RED).map_err(|_| "Clear failed")?;
+    if composited(&framebuffer, &windows, 6, 6)? != Some(0xFFFF_0000) {
+        return Err("Opaque window did not cover the one beneath");
+    }
+    windows.set_window_opacity(red, 128).map_err(|_| "Opacity not set")?;
+    if composited(&framebuffer, &windows, 6, 6)? != Some(PURPLE) {
+        return Err("Half-opaque window did not blend with the one beneath");
+    }
+    if windows.get_window_info(red).map(|info| info.opacity) != Ok(128) {
+        return Err("Opacity not reported");
+    }
+    windows.set_window_opacity(red, 0).map_err(|_| "Opacity not set")?;
+    if composited(&framebuffer, &windows, 6, 6)? != Some(0xFF00_00FF) {
+        return Err("Fully transparent window drawn");
+    }
+    if windows.set_window_opacity(999, 10).is_ok() {
+        return Err("Opacity set on a missing window");
+    }
+    _print(format_args!("[Blend Test] ✓ Opacity 255, 128 and 0 composite as expected\n"));
+
+    // Test 4: rounding goes to the nearest value and never overflows a channel
+    _print(format_args!("[Blend Test] Test 4: Rounding and clamping...\n"));
+    if blend(0xFFFF_FFFF, 0xFEFF_FFFF) != 0xFFFF_FFFF || blend(0xFFFF_FFFF, 0x01FF_FFFF) != 0xFFFF_FFFF {
+        return Err("White over white is not white");
+    }
+    if blend(0xFF00_0000, 0x0112_3456) != 0xFF00_0000 || blend(0xFF12_3456, 0x0000_0000) != 0xFF12_3456 {
+        return Err("Nearly or fully transparent source changed the destination");
+    }
+    if blend(0x0000_0000, 0x0100_FF00) != 0x0100_FF00 || blend(0x80FF_0000, 0xFF00_FF00) != 0xFF00_FF00 {
+        return Err("Source alpha or opaque source not kept");
+    }
+    _print(format_args!("[Blend Test] ✓ Blends rounded and clamped\n"));
+
+    _print(format_args!("[Blend Test] ✓ All alpha blending tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for alpha blending
+pub fn test_alpha_blending() {
+    _print(format_args!("[Blend Test] ===========================================\n"));
+    _print(format_args!("[Blend Test]          ALPHA BLENDING TESTS\n"));
+    _print(format_args!("[Blend Test] ===========================================\n"));
+
+    match run_blend_tests() {
+        Ok(_) => _print(format_args!("[Blend Test] ✓ All alpha blending tests PASSED\n")),
+        Err(e) => _print(format_args!("[Blend Test] ✗ Alpha blending tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Blend Test] ===========================================\n"));
+}
@@ -0,0 +1,166 @@
+//! Cursor Plane Tests
+//! Moves the cursor over an off-screen framebuffer: the pixels it leaves are restored exactly,
+//! moving it damages nothing, it is clipped at the screen edges, and a window redrawn under
+//! it in the same frame shows through once it moves away
+
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::super::contracts::graphics::{Color, WindowRect};
+use super::framebuffer_manager::{FramebufferManager, MAX_CURSOR_SIZE};
+use super::window_manager::WindowManager;
+use crate::serial::_print;
+
+const SCREEN_WIDTH: u32 = 64;
+const SCREEN_HEIGHT: u32 = 48;
+const CURSOR_SIZE: u32 = 8;
+const WHITE: u32 = 0xFFFF_FFFF;
+const RED: u32 = 0xFFFF_0000;
+
+/// A triangle: opaque below the diagonal, half transparent on it, clear above it
+fn arrow() -> Vec<u32> {
+    (0..CURSOR_SIZE * CURSOR_SIZE)
+        .map(|i| {
+            let (x, y) = (i % CURSOR_SIZE, i / CURSOR_SIZE);
+            match x.cmp(&y) {
+                core::cmp::Ordering::Less => WHITE,
+                core::cmp::Ordering::Equal => 0x80FF_FFFF,
+                core::cmp::Ordering::Greater => 0,
+            }
+        })
+        .collect()
+}
+
+/// Every presented pixel, row by row
+fn front(framebuffer: &FramebufferManager) -> Vec<Option<u32>> {
+    (0..SCREEN_HEIGHT).flat_map(|y| (0..SCREEN_WIDTH).map(move |x| framebuffer.front_pixel(x, y))).collect()
+}
+
+/// A presented frame with stripes of every shade of red, green and blue, so no two
+/// neighbouring pixels match
+fn striped_screen() -> Result<(FramebufferManager, WindowManager), &'static str> {
+    let framebuffer = FramebufferManager::with_size(SCREEN_WIDTH, SCREEN_HEIGHT);
+    let windows = WindowManager::new();
+    let window = windows.create_window("stripes".to_string(), WindowRect::new(0, 0, SCREEN_WIDTH, SCREEN_HEIGHT), 0)
+        .map_err(|_| "Window not created")?;
+    for y in 0..SCREEN_HEIGHT {
+        for x in 0..SCREEN_WIDTH {
+            let color = Color { r: (x * 4) as u8, g: (y * 5) as u8, b: ((x + y) * 3) as u8, a: 255 };
+            windows.draw_pixel(window, x, y, color).map_err(|_| "Draw failed")?;
+        }
+    }
+    framebuffer.composite_frame(&windows).map_err(|_| "Composite failed")?;
+    framebuffer.swap_buffers().map_err(|_| "Swap failed")?;
+    Ok((framebuffer, windows))
+}
+
+pub fn run_cursor_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Cursor Test] Starting cursor plane tests...\n"));
+
+    // Test 1: the background under the cursor is restored exactly wherever it goes
+    _print(format_args!("[Cursor Test] Test 1: Moving the cursor over a static background...\n"));
+    let (framebuffer, windows) = striped_screen()?;
+    let background = front(&framebuffer);
+    framebuffer.move_cursor(-100, -100).map_err(|_| "Move failed")?;
+    framebuffer.set_cursor_bitmap(CURSOR_SIZE, CURSOR_SIZE, arrow()).map_err(|_| "Bitmap rejected")?;
+    let mut moves = 0;
+    for step in 0..35 {
+        let (x, y) = (step * 2 - 6, step - 4);
+        framebuffer.move_cursor(x, y).map_err(|_| "Move failed")?;
+        let shown = front(&framebuffer);
+        let cursor = WindowRect::new(x, y, CURSOR_SIZE, CURSOR_SIZE);
+        for (i, (now, before)) in shown.iter().zip(&background).enumerate() {
+            let pixel = WindowRect::new(i as i32 % SCREEN_WIDTH as i32, i as i32 / SCREEN_WIDTH as i32, 1, 1);
+            if !cursor.intersects(&pixel) && now != before {
+                return Err("Pixel left by the cursor not restored");
+            }
+        }
+        if shown == background {
+            return Err("Cursor on screen not drawn");
+        }
+        moves += 1;
+    }
+    if !framebuffer.pending_damage().is_empty() || framebuffer.composite_frame(&windows) != Ok(false) {
+        return Err("Moving the cursor damaged the frame");
+    }
+    framebuffer.move_cursor(SCREEN_WIDTH as i32, SCREEN_HEIGHT as i32).map_err(|_| "Move failed")?;
+    if front(&framebuffer) != background {
+        return Err("Background not restored once the cursor left the screen");
+    }
+    framebuffer.move_cursor(10, 10).map_err(|_| "Move failed")?;
+    framebuffer.set_cursor_visible(false).map_err(|_| "Visibility not set")?;
+    if front(&framebuffer) != background {
+        return Err("Hidden cursor still drawn");
+    }
+    _print(format_args!("[Cursor Test] ✓ Background restored exactly after {} moves\n", moves));
+
+    // Test 2: the cursor is clipped at the screen edges
+    _print(format_args!("[Cursor Test] Test 2: Clipping at the screen edges...\n"));
+    let (framebuffer, _windows) = striped_screen()?;
+    let background = front(&framebuffer);
+    framebuffer.set_cursor_bitmap(CURSOR_SIZE, CURSOR_SIZE, vec![WHITE; (CURSOR_SIZE * CURSOR_SIZE) as usize])
+        .map_err(|_| "Bitmap rejected")?;
+    framebuffer.move_cursor(SCREEN_WIDTH as i32 - 2, SCREEN_HEIGHT as i32 - 3).map_err(|_| "Move failed")?;
+    let covered = front(&framebuffer).iter().filter(|&&pixel| pixel == Some(WHITE)).count();
+    if covered != 2 * 3 || framebuffer.front_pixel(SCREEN_WIDTH - 1, SCREEN_HEIGHT - 1) != Some(WHITE) {
+        return Err("Cursor at the bottom-right corner not clipped");
+    }
+    framebuffer.move_cursor(-5, -6).map_err(|_| "Move failed")?;
+    let covered = front(&framebuffer).iter().filter(|&&pixel| pixel == Some(WHITE)).count();
+    if covered != 3 * 2 || framebuffer.front_pixel(0, 0) != Some(WHITE) || framebuffer.front_pixel(3, 0) == Some(WHITE) {
+        return Err("Cursor at the top-left corner not clipped");
+    }
+    framebuffer.move_cursor(i32::MIN, i32::MAX).map_err(|_| "Move failed")?;
+    if front(&framebuffer) != background {
+        return Err("Cursor far off screen drawn");
+    }
+    let oversized = MAX_CURSOR_SIZE + 1;
+    if framebuffer.set_cursor_bitmap(oversized, 1, vec![WHITE; oversized as usize]).is_ok()
+        || framebuffer.set_cursor_bitmap(2, 2, vec![WHITE; 3]).is_ok()
+    {
+        return Err("Bad cursor bitmap accepted");
+    }
+    _print(format_args!("[Cursor Test] ✓ Cursor clipped at both corners\n"));
+
+    // Test 3: a window redrawn under the cursor in the same frame shows once it moves on
+    _print(format_args!("[Cursor Test] Test 3: Redrawing a window under the cursor...\n"));
+    let framebuffer = FramebufferManager::with_size(SCREEN_WIDTH, SCREEN_HEIGHT);
+    let windows = WindowManager::new();
+    framebuffer.composite_frame(&windows).map_err(|_| "Composite failed")?;
+    framebuffer.swap_buffers().map_err(|_| "Swap failed")?;
+    framebuffer.set_cursor_bitmap(CURSOR_SIZE, CURSOR_SIZE, arrow()).map_err(|_| "Bitmap rejected")?;
+    framebuffer.move_cursor(10, 10).map_err(|_| "Move failed")?;
+    let window = windows.create_window("under".to_string(), WindowRect::new(8, 8, 16, 16), 0).map_err(|_| "Window not created")?;
+    windows.clear_window(window, Color::RED).map_err(|_| "Clear failed")?;
+    framebuffer.composite_frame(&windows).map_err(|_| "Composite failed")?;
+    if framebuffer.back_pixel(11, 10) != Some(RED) {
+        return Err("Cursor composited into the frame");
+    }
+    framebuffer.swap_buffers().map_err(|_| "Swap failed")?;
+    if framebuffer.front_pixel(10, 11) != Some(WHITE) || framebuffer.front_pixel(11, 10) != Some(RED) {
+        return Err("Cursor not drawn over the window presented under it");
+    }
+    framebuffer.move_cursor(40, 40).map_err(|_| "Move failed")?;
+    if (8..24).any(|i| framebuffer.front_pixel(i, i) != Some(RED) || framebuffer.front_pixel(i, 8) != Some(RED)) {
+        return Err("Stale pixels restored over the redrawn window");
+    }
+    _print(format_args!("[Cursor Test] ✓ Window redrawn under the cursor shows after it moves\n"));
+
+    _print(format_args!("[Cursor Test] ✓ All cursor plane tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for the cursor plane
+pub fn test_cursor_plane() {
+    _print(format_args!("[Cursor Test] ===========================================\n"));
+    _print(format_args!("[Cursor Test]           CURSOR PLANE TESTS\n"));
+    _print(format_args!("[Cursor Test] ===========================================\n"));
+
+    match run_cursor_tests() {
+        Ok(_) => _print(format_args!("[Cursor Test] ✓ All cursor plane tests PASSED\n")),
+        Err(e) => _print(format_args!("[Cursor Test] ✗ Cursor plane tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Cursor Test] ===========================================\n"));
+}
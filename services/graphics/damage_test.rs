@@ -0,0 +1,160 @@
+//! Damage Tracking Tests
+//! Composites windows into an off-screen framebuffer: a draw damages no more than the part of
+//! its window on screen, overlapping damage is merged, a moved window damages where it was
+//! and where it is, frames with no damage composite nothing, and a swap clears the frame's damage
+
+use alloc::string::ToString;
+
+use super::super::contracts::graphics::{Color, CompositorMode, WindowRect};
+use super::framebuffer_manager::{DamageList, FramebufferManager, MAX_DAMAGE_REGIONS};
+use super::window_manager::WindowManager;
+use crate::serial::_print;
+
+const SCREEN_WIDTH: u32 = 64;
+const SCREEN_HEIGHT: u32 = 48;
+const BLACK: u32 = 0xFF00_0000;
+const RED: u32 = 0xFFFF_0000;
+const BLUE: u32 = 0xFF00_00FF;
+
+/// A framebuffer and window manager with the first, full-screen frame already presented
+fn screen() -> Result<(FramebufferManager, WindowManager), &'static str> {
+    let framebuffer = FramebufferManager::with_size(SCREEN_WIDTH, SCREEN_HEIGHT);
+    let windows = WindowManager::new();
+    if framebuffer.composite_frame(&windows) != Ok(true) {
+        return Err("First frame did not redraw the screen");
+    }
+    framebuffer.swap_buffers().map_err(|_| "Swap failed")?;
+    Ok((framebuffer, windows))
+}
+
+pub fn run_damage_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Damage Test] Starting damage tracking tests...\n"));
+
+    // Test 1: a 10x10 draw damages at most its window's part of the screen
+    _print(format_args!("[Damage Test] Test 1: Damage from a single draw...\n"));
+    let (framebuffer, windows) = screen()?;
+    let window_rect = WindowRect::new(-4, -4, 20, 20);
+    let window = windows.create_window("partly off screen".to_string(), window_rect, 0).map_err(|_| "Window not created")?;
+    if framebuffer.composite_frame(&windows) != Ok(false) {
+        return Err("Transparent new window recomposited the screen");
+    }
+    windows.draw_rect(window, WindowRect::new(0, 0, 10, 10), Color::RED, true).map_err(|_| "Draw failed")?;
+    if framebuffer.composite_frame(&windows) != Ok(true) {
+        return Err("Draw did not damage the screen");
+    }
+    let damage = framebuffer.frame_damage();
+    let clipped = window_rect.intersection(&WindowRect::new(0, 0, SCREEN_WIDTH, SCREEN_HEIGHT)).ok_or("Window off screen")?;
+    if damage.regions() != [WindowRect::new(0, 0, 6, 6)] {
+        return Err("Draw damaged more than it drew on screen");
+    }
+    if damage.area() > 100 || damage.bounds().and_then(|bounds| bounds.intersection(&clipped)) != damage.bounds() {
+        return Err("Damage larger than the window's clipped rect");
+    }
+    if framebuffer.back_pixel(5, 5) != Some(RED) || framebuffer.back_pixel(6, 6) != Some(BLACK) {
+        return Err("Damaged region not recomposited");
+    }
+    framebuffer.swap_buffers().map_err(|_| "Swap failed")?;
+    if !framebuffer.frame_damage().is_empty() || framebuffer.front_pixel(0, 0) != Some(RED) {
+        return Err("Swap did not present the damage and clear it");
+    }
+    windows.draw_pixel(window, 30, 30, Color::RED).map_err(|_| "Draw failed")?;
+    if !windows.take_damage().is_empty() {
+        return Err("Pixel outside the window damaged the screen");
+    }
+    _print(format_args!("[Damage Test] ✓ 10x10 draw damaged only its 6x6 on-screen part\n"));
+
+    // Test 2: overlapping damage is merged, disjoint damage kept apart up to a limit
+    _print(format_args!("[Damage Test] Test 2: Coalescing overlapping damage...\n"));
+    let mut damage = DamageList::new();
+    damage.add(WindowRect::new(0, 0, 10, 10));
+    damage.add(WindowRect::new(5, 5, 10, 10));
+    if damage.regions() != [WindowRect::new(0, 0, 15, 15)] {
+        return Err("Overlapping rectangles not merged");
+    }
+    damage.add(WindowRect::new(20, 0, 5, 5));
+    damage.add(WindowRect::new(0, 0, 0, 8));
+    if damage.regions().len() != 2 {
+        return Err("Disjoint or empty rectangles merged");
+    }
+    // Touches both regions, so all three merge
+    damage.add(WindowRect::new(8, 0, 14, 3));
+    if damage.regions() != [WindowRect::new(0, 0, 25, 15)] {
+        return Err("Rectangle bridging two regions not merged with both");
+    }
+    let mut scattered = DamageList::new();
+    for i in 0..=MAX_DAMAGE_REGIONS as i32 {
+        scattered.add(WindowRect::new(i * 4, 0, 2, 2));
+    }
+    if scattered.regions() != [WindowRect::new(0, 0, MAX_DAMAGE_REGIONS as u32 * 4 + 2, 2)] {
+        return Err("Too many regions not collapsed into their bounds");
+    }
+    _print(format_args!("[Damage Test] ✓ Overlaps merged, {} regions collapsed\n", MAX_DAMAGE_REGIONS + 1));
+
+    // Test 3: moving a window damages its old and new bounds
+    _print(format_args!("[Damage Test] Test 3: Moving a window...\n"));
+    let (framebuffer, windows) = screen()?;
+    let window = windows.create_window("moving".to_string(), WindowRect::new(10, 10, 8, 8), 0).map_err(|_| "Window not created")?;
+    windows.clear_window(window, Color::BLUE).map_err(|_| "Clear failed")?;
+    framebuffer.composite_frame(&windows).map_err(|_| "Composite failed")?;
+    framebuffer.swap_buffers().map_err(|_| "Swap failed")?;
+    windows.set_window_rect(window, WindowRect::new(40, 30, 8, 8)).map_err(|_| "Move failed")?;
+    framebuffer.composite_frame(&windows).map_err(|_| "Composite failed")?;
+    let damage = framebuffer.frame_damage();
+    if damage.regions().len() != 2
+        || !damage.regions().contains(&WindowRect::new(10, 10, 8, 8))
+        || !damage.regions().contains(&WindowRect::new(40, 30, 8, 8))
+    {
+        return Err("Move did not damage exactly the old and new bounds");
+    }
+    framebuffer.swap_buffers().map_err(|_| "Swap failed")?;
+    if framebuffer.front_pixel(12, 12) != Some(BLACK) || framebuffer.front_pixel(42, 32) != Some(BLUE) {
+        return Err("Moved window left behind or not drawn at its new place");
+    }
+    windows.set_window_rect(window, WindowRect::new(44, 30, 8, 8)).map_err(|_| "Move failed")?;
+    framebuffer.composite_frame(&windows).map_err(|_| "Composite failed")?;
+    if framebuffer.frame_damage().regions() != [WindowRect::new(40, 30, 12, 8)] {
+        return Err("Overlapping old and new bounds not merged");
+    }
+    _print(format_args!("[Damage Test] ✓ Old and new bounds recomposited\n"));
+
+    // Test 4: only damaged regions are recomposited
+    _print(format_args!("[Damage Test] Test 4: Recompositing only damage...\n"));
+    let (framebuffer, windows) = screen()?;
+    let window = windows.create_window("tiles".to_string(), WindowRect::new(0, 0, 64, 48), 0).map_err(|_| "Window not created")?;
+    framebuffer.set_debug_overlay_enabled(true).map_err(|_| "Overlay not set")?;
+    windows.draw_rect(window, WindowRect::new(20, 20, 4, 4), Color::RED, true).map_err(|_| "Draw failed")?;
+    framebuffer.composite_frame(&windows).map_err(|_| "Composite failed")?;
+    let overlay = framebuffer.back_pixel(20, 20);
+    if overlay == Some(RED) || overlay == Some(BLACK) || framebuffer.back_pixel(0, 0) != Some(BLACK) {
+        return Err("Composite reached beyond the damaged region");
+    }
+    framebuffer.set_debug_overlay_enabled(false).map_err(|_| "Overlay not set")?;
+    framebuffer.swap_buffers().map_err(|_| "Swap failed")?;
+    if framebuffer.composite_frame(&windows) != Ok(false) || !framebuffer.frame_damage().is_empty() {
+        return Err("Undamaged frame recomposited");
+    }
+    framebuffer.set_compositor_mode(CompositorMode::Safe).map_err(|_| "Mode not set")?;
+    windows.draw_pixel(window, 1, 1, Color::RED).map_err(|_| "Draw failed")?;
+    framebuffer.composite_frame(&windows).map_err(|_| "Composite failed")?;
+    if framebuffer.frame_damage().regions() != [WindowRect::new(0, 0, SCREEN_WIDTH, SCREEN_HEIGHT)] {
+        return Err("Safe mode did not recomposite the whole screen");
+    }
+    _print(format_args!("[Damage Test] ✓ Only damage recomposited; safe mode redraws the screen\n"));
+
+    _print(format_args!("[Damage Test] ✓ All damage tracking tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for compositor damage tracking
+pub fn test_damage_tracking() {
+    _print(format_args!("[Damage Test] ===========================================\n"));
+    _print(format_args!("[Damage Test]          DAMAGE TRACKING TESTS\n"));
+    _print(format_args!("[Damage Test] ===========================================\n"));
+
+    match run_damage_tests() {
+        Ok(_) => _print(format_args!("[Damage Test] ✓ All damage tracking tests PASSED\n")),
+        Err(e) => _print(format_args!("[Damage Test] ✗ Damage tracking tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Damage Test] ===========================================\n"));
+}
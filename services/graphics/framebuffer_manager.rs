@@ -0,0 +1,499 @@
+//! Framebuffer management for rae-compositord
+//! The screen's back and front buffers. Each frame only the damaged parts of the screen are
+//! recomposited into the back buffer and presented: damage reported by the window manager is
+//! clipped to the screen and overlapping rectangles are merged, and the frame's damage list
+//! is cleared once the swap has presented it. A frame with no damage composites nothing.
+//!
+//! The mouse cursor is a plane of its own, blended over the presented frame in the front
+//! buffer and never composited into the back. The pixels it covers are saved, so moving it
+//! only restores them and draws it at its new place, without damaging the frame. A swap that
+//! presents damage under the cursor lifts it first and draws it again over the new pixels.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::super::contracts::graphics::{Color, CompositorMode, FramebufferInfo, PixelFormat, WindowRect};
+use super::super::manager::ServiceError;
+use super::window_manager::{blend, WindowManager};
+
+/// Damage rectangles kept apart before they are merged into their bounding rectangle
+pub const MAX_DAMAGE_REGIONS: usize = 16;
+const BYTES_PER_PIXEL: u32 = 4;
+/// Outline drawn around each recomposited region while the debug overlay is on
+const DEBUG_OVERLAY_COLOR: u32 = 0xFFFF_00FF;
+/// Widest or tallest cursor bitmap accepted
+pub const MAX_CURSOR_SIZE: u32 = 64;
+
+/// Rectangles of the screen that changed, with no two overlapping
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DamageList {
+    regions: Vec<WindowRect>,
+}
+
+impl DamageList {
+    pub fn new() -> Self {
+        Self { regions: Vec::new() }
+    }
+
+    /// Record `rect` as damaged, merging it with every region it overlaps
+    pub fn add(&mut self, rect: WindowRect) {
+        if rect.is_empty() {
+            return;
+        }
+        // The union can reach regions neither part overlapped, so merge until none is left
+        let mut merged = rect;
+        while let Some(index) = self.regions.iter().position(|region| region.intersects(&merged)) {
+            merged = merged.union(&self.regions.swap_remove(index));
+        }
+        self.regions.push(merged);
+
+        if self.regions.len() > MAX_DAMAGE_REGIONS {
+            if let Some(bounds) = self.bounds() {
+                self.regions = vec![bounds];
+            }
+        }
+    }
+
+    pub fn regions(&self) -> &[WindowRect] {
+        &self.regions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// Pixels damaged
+    pub fn area(&self) -> u64 {
+        self.regions.iter().map(WindowRect::area).sum()
+    }
+
+    /// The smallest rectangle holding every region
+    pub fn bounds(&self) -> Option<WindowRect> {
+        let (first, rest) = self.regions.split_first()?;
+        Some(rest.iter().fold(*first, |bounds, region| bounds.union(region)))
+    }
+
+    pub fn clear(&mut self) {
+        self.regions.clear();
+    }
+
+    /// Take the regions, leaving the list empty
+    pub fn take(&mut self) -> Self {
+        core::mem::take(self)
+    }
+}
+
+/// The mouse cursor plane
+struct Cursor {
+    width: u32,
+    height: u32,
+    /// ARGB, row by row
+    pixels: Vec<u32>,
+    /// Top-left corner on screen
+    x: i32,
+    y: i32,
+    visible: bool,
+    /// Where the cursor is drawn over the front buffer, clipped to the screen
+    drawn: Option<WindowRect>,
+    /// The front buffer's pixels under `drawn`
+    under: Vec<u32>,
+}
+
+fn pixel_value(color: Color) -> u32 {
+    ((color.a as u32) << 24) | ((color.r as u32) << 16) | ((color.g as u32) << 8) | (color.b as u32)
+}
+
+struct Framebuffer {
+    width: u32,
+    height: u32,
+    back: Vec<u32>,
+    front: Vec<u32>,
+    background: u32,
+    /// Damage waiting for the next composite
+    damage: DamageList,
+    /// Damage composited into the back buffer, waiting for the swap
+    frame: DamageList,
+    double_buffering: bool,
+    mode: CompositorMode,
+    debug_overlay: bool,
+    /// Whether swaps scan out through the kernel compositor
+    on_screen: bool,
+    initialized: bool,
+    cursor: Cursor,
+}
+
+impl Framebuffer {
+    fn screen(&self) -> WindowRect {
+        WindowRect::new(0, 0, self.width, self.height)
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        let pixels = width as usize * height as usize;
+        self.width = width;
+        self.height = height;
+        self.back = vec![self.background; pixels];
+        self.front = vec![self.background; pixels];
+        self.damage = DamageList::new();
+        self.frame = DamageList::new();
+        self.damage.add(self.screen());
+        self.initialized = true;
+        self.cursor.drawn = None;
+        self.show_cursor();
+    }
+
+    fn add_damage(&mut self, rect: WindowRect) {
+        if let Some(rect) = rect.intersection(&self.screen()) {
+            self.damage.add(rect);
+        }
+    }
+
+    /// Redraw `region` of the back buffer: the background, then the windows over it
+    fn composite_region(&mut self, windows: &WindowManager, region: WindowRect) {
+        let width = self.width as usize;
+        for y in region.y as usize..region.bottom() as usize {
+            let row = y * width;
+            self.back[row + region.x as usize..row + region.right() as usize].fill(self.background);
+        }
+        windows.composite(&mut self.back, self.width, region);
+
+        if self.debug_overlay {
+            let (left, right) = (region.x as usize, region.right() as usize - 1);
+            let (top, bottom) = (region.y as usize, region.bottom() as usize - 1);
+            for x in left..=right {
+                self.back[top * width + x] = DEBUG_OVERLAY_COLOR;
+                self.back[bottom * width + x] = DEBUG_OVERLAY_COLOR;
+            }
+            for y in top..=bottom {
+                self.back[y * width + left] = DEBUG_OVERLAY_COLOR;
+                self.back[y * width + right] = DEBUG_OVERLAY_COLOR;
+            }
+        }
+    }
+
+    /// Copy the frame's damage from the back buffer to the front and the screen, then forget
+    /// it. A cursor over the damage is lifted first and drawn again over the new pixels
+    fn present(&mut self) -> Result<(), ServiceError> {
+        let width = self.width as usize;
+        let mut presented = self.frame.take();
+        let covered = self.cursor.drawn.is_some_and(|cursor| presented.regions().iter().any(|region| region.intersects(&cursor)));
+        if covered {
+            self.hide_cursor();
+        }
+        for region in presented.regions() {
+            let (left, right) = (region.x as usize, region.right() as usize);
+            for y in region.y as usize..region.bottom() as usize {
+                let row = y * width;
+                self.front[row + left..row + right].copy_from_slice(&self.back[row + left..row + right]);
+            }
+        }
+        if covered {
+            if let Some(cursor) = self.show_cursor() {
+                presented.add(cursor);
+            }
+        }
+        self.scan_out(&presented)
+    }
+
+    /// Send `regions` of the front buffer to the screen
+    fn scan_out(&self, regions: &DamageList) -> Result<(), ServiceError> {
+        if !self.on_screen {
+            return Ok(());
+        }
+        let width = self.width as usize;
+        for region in regions.regions() {
+            let (left, right) = (region.x as usize, region.right() as usize);
+            let bytes: Vec<u8> = (region.y as usize..region.bottom() as usize)
+                .flat_map(|y| self.front[y * width + left..y * width + right].iter().flat_map(|pixel| pixel.to_le_bytes()))
+                .collect();
+            crate::kernel::graphics::blit_buffer(
+                &bytes, region.x as u32, region.y as u32, region.width, region.height, region.width * BYTES_PER_PIXEL,
+            ).map_err(|_| ServiceError::InternalError)?;
+        }
+        Ok(())
+    }
+
+    /// Put back the pixels under the cursor; returns where it was
+    fn hide_cursor(&mut self) -> Option<WindowRect> {
+        let drawn = self.cursor.drawn.take()?;
+        let (width, span) = (self.width as usize, drawn.width as usize);
+        for (row, saved) in self.cursor.under.chunks_exact(span).enumerate() {
+            let start = (drawn.y as usize + row) * width + drawn.x as usize;
+            self.front[start..start + span].copy_from_slice(saved);
+        }
+        Some(drawn)
+    }
+
+    /// Save the front buffer's pixels under the cursor and blend it over them, clipped to the
+    /// screen; returns where it was drawn
+    fn show_cursor(&mut self) -> Option<WindowRect> {
+        let cursor = &mut self.cursor;
+        if !cursor.visible || cursor.pixels.is_empty() {
+            return None;
+        }
+        let bounds = WindowRect::new(cursor.x, cursor.y, cursor.width, cursor.height);
+        let drawn = bounds.intersection(&WindowRect::new(0, 0, self.width, self.height))?;
+        let (width, span) = (self.width as usize, drawn.width as usize);
+        cursor.under.clear();
+        for y in drawn.y..drawn.bottom() as i32 {
+            let start = y as usize * width + drawn.x as usize;
+            cursor.under.extend_from_slice(&self.front[start..start + span]);
+            let source = (y - cursor.y) as usize * cursor.width as usize + (drawn.x - cursor.x) as usize;
+            for (target, &pixel) in self.front[start..start + span].iter_mut().zip(&cursor.pixels[source..source + span]) {
+                *target = blend(*target, pixel);
+            }
+        }
+        cursor.drawn = Some(drawn);
+        Some(drawn)
+    }
+
+    /// Draw the cursor as it now is, and send both where it was and where it is to the screen
+    fn redraw_cursor(&mut self) -> Result<(), ServiceError> {
+        if !self.initialized {
+            return Ok(());
+        }
+        let mut changed = DamageList::new();
+        if let Some(old) = self.hide_cursor() {
+            changed.add(old);
+        }
+        if let Some(new) = self.show_cursor() {
+            changed.add(new);
+        }
+        self.scan_out(&changed)
+    }
+}
+
+/// The compositor's framebuffer
+pub struct FramebufferManager {
+    state: Mutex<Framebuffer>,
+}
+
+impl FramebufferManager {
+    /// A framebuffer with no pixels until `initialize` sizes it to the screen
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(Framebuffer {
+                width: 0,
+                height: 0,
+                back: Vec::new(),
+                front: Vec::new(),
+                background: pixel_value(Color::BLACK),
+                damage: DamageList::new(),
+                frame: DamageList::new(),
+                double_buffering: true,
+                mode: CompositorMode::Normal,
+                debug_overlay: false,
+                on_screen: false,
+                initialized: false,
+                cursor: Cursor {
+                    width: 0,
+                    height: 0,
+                    pixels: Vec::new(),
+                    x: 0,
+                    y: 0,
+                    visible: true,
+                    drawn: None,
+                    under: Vec::new(),
+                },
+            }),
+        }
+    }
+
+    /// An off-screen framebuffer of the given size, whose swaps only reach its front buffer
+    pub fn with_size(width: u32, height: u32) -> Self {
+        let manager = Self::new();
+        manager.state.lock().resize(width, height);
+        manager
+    }
+
+    /// Size the buffers to the screen and scan out through the kernel compositor. The first
+    /// frame redraws the whole screen
+    pub fn initialize(&self) -> Result<(), ServiceError> {
+        let (width, height) = {
+            let screen = crate::kernel::graphics::get_screen_buffer().lock();
+            (screen.width, screen.height)
+        };
+        if width == 0 || height == 0 {
+            return Err(ServiceError::DependencyNotMet);
+        }
+        let mut framebuffer = self.state.lock();
+        framebuffer.resize(width, height);
+        framebuffer.on_screen = true;
+        Ok(())
+    }
+
+    pub fn shutdown(&self) -> Result<(), ServiceError> {
+        let mut framebuffer = self.state.lock();
+        framebuffer.back = Vec::new();
+        framebuffer.front = Vec::new();
+        framebuffer.damage.clear();
+        framebuffer.frame.clear();
+        framebuffer.cursor.drawn = None;
+        framebuffer.on_screen = false;
+        framebuffer.initialized = false;
+        Ok(())
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.state.lock().initialized
+    }
+
+    /// Record `rect` of the screen as needing recompositing
+    pub fn mark_damage(&self, rect: WindowRect) {
+        self.state.lock().add_damage(rect);
+    }
+
+    /// Damage waiting for the next composite
+    pub fn pending_damage(&self) -> DamageList {
+        self.state.lock().damage.clone()
+    }
+
+    /// Damage composited and waiting for the swap
+    pub fn frame_damage(&self) -> DamageList {
+        self.state.lock().frame.clone()
+    }
+
+    /// Recomposite the damaged parts of the screen: the window manager's damage and any
+    /// marked here. Returns whether anything was damaged; without double buffering the
+    /// result is presented at once
+    pub fn composite_frame(&self, windows: &WindowManager) -> Result<bool, ServiceError> {
+        let mut framebuffer = self.state.lock();
+        if !framebuffer.initialized {
+            return Err(ServiceError::InvalidState);
+        }
+        for rect in windows.take_damage().regions() {
+            framebuffer.add_damage(*rect);
+        }
+        let mut damage = framebuffer.damage.take();
+        if damage.is_empty() {
+            return Ok(false);
+        }
+        // Safe mode trades partial updates for the simplest path: whole frames
+        if matches!(framebuffer.mode, CompositorMode::Safe) {
+            damage.clear();
+            let screen = framebuffer.screen();
+            damage.add(screen);
+        }
+
+        for region in damage.regions() {
+            framebuffer.composite_region(windows, *region);
+            framebuffer.frame.add(*region);
+        }
+        if !framebuffer.double_buffering {
+            framebuffer.present()?;
+        }
+        Ok(true)
+    }
+
+    /// Present the frame's damage and clear it
+    pub fn swap_buffers(&self) -> Result<(), ServiceError> {
+        let mut framebuffer = self.state.lock();
+        if !framebuffer.initialized {
+            return Err(ServiceError::InvalidState);
+        }
+        framebuffer.present()
+    }
+
+    /// Replace the cursor image with `pixels`, ARGB row by row, at most `MAX_CURSOR_SIZE`
+    /// square. An empty bitmap draws no cursor
+    pub fn set_cursor_bitmap(&self, width: u32, height: u32, pixels: Vec<u32>) -> Result<(), ServiceError> {
+        if width > MAX_CURSOR_SIZE || height > MAX_CURSOR_SIZE {
+            return Err(ServiceError::ResourceLimitExceeded);
+        }
+        if pixels.len() != width as usize * height as usize {
+            return Err(ServiceError::InvalidState);
+        }
+        let mut framebuffer = self.state.lock();
+        framebuffer.cursor.width = width;
+        framebuffer.cursor.height = height;
+        framebuffer.cursor.pixels = pixels;
+        framebuffer.redraw_cursor()
+    }
+
+    /// Put the cursor's top-left corner at `x`, `y`, which may be partly or wholly off
+    /// screen. The frame is not recomposited
+    pub fn move_cursor(&self, x: i32, y: i32) -> Result<(), ServiceError> {
+        let mut framebuffer = self.state.lock();
+        if (framebuffer.cursor.x, framebuffer.cursor.y) == (x, y) {
+            return Ok(());
+        }
+        framebuffer.cursor.x = x;
+        framebuffer.cursor.y = y;
+        framebuffer.redraw_cursor()
+    }
+
+    pub fn set_cursor_visible(&self, visible: bool) -> Result<(), ServiceError> {
+        let mut framebuffer = self.state.lock();
+        if framebuffer.cursor.visible == visible {
+            return Ok(());
+        }
+        framebuffer.cursor.visible = visible;
+        framebuffer.redraw_cursor()
+    }
+
+    /// Fill the screen behind the windows with `color`
+    pub fn clear_framebuffer(&self, color: Color) -> Result<(), ServiceError> {
+        let mut framebuffer = self.state.lock();
+        framebuffer.background = pixel_value(color);
+        let screen = framebuffer.screen();
+        framebuffer.add_damage(screen);
+        Ok(())
+    }
+
+    /// The front buffer's pixel at `x`, `y`, as presented
+    pub fn front_pixel(&self, x: u32, y: u32) -> Option<u32> {
+        let framebuffer = self.state.lock();
+        (x < framebuffer.width && y < framebuffer.height)
+            .then(|| framebuffer.front[(y * framebuffer.width + x) as usize])
+    }
+
+    /// The back buffer's pixel at `x`, `y`, as last composited
+    pub fn back_pixel(&self, x: u32, y: u32) -> Option<u32> {
+        let framebuffer = self.state.lock();
+        (x < framebuffer.width && y < framebuffer.height)
+            .then(|| framebuffer.back[(y * framebuffer.width + x) as usize])
+    }
+
+    pub fn get_framebuffer_info(&self) -> Result<FramebufferInfo, ServiceError> {
+        let framebuffer = self.state.lock();
+        if !framebuffer.initialized {
+            return Err(ServiceError::InvalidState);
+        }
+        Ok(FramebufferInfo {
+            width: framebuffer.width,
+            height: framebuffer.height,
+            pitch: framebuffer.width * BYTES_PER_PIXEL,
+            bpp: BYTES_PER_PIXEL * 8,
+            format: PixelFormat::Bgra8888,
+            physical_address: 0,
+        })
+    }
+
+    pub fn set_compositor_mode(&self, mode: CompositorMode) -> Result<(), ServiceError> {
+        self.state.lock().mode = mode;
+        Ok(())
+    }
+
+    /// Outline each recomposited region, to show what every frame redraws
+    pub fn set_debug_overlay_enabled(&self, enabled: bool) -> Result<(), ServiceError> {
+        self.state.lock().debug_overlay = enabled;
+        Ok(())
+    }
+
+    pub fn set_vsync_enabled(&self, enabled: bool) -> Result<(), ServiceError> {
+        if self.state.lock().on_screen {
+            crate::kernel::graphics::set_vsync(enabled).map_err(|_| ServiceError::InternalError)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_double_buffering(&self, enabled: bool) -> Result<(), ServiceError> {
+        let mut framebuffer = self.state.lock();
+        // A frame composited under double buffering is not left waiting for a swap
+        if !enabled {
+            framebuffer.present()?;
+        }
+        framebuffer.double_buffering = enabled;
+        Ok(())
+    }
+}
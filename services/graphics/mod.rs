@@ -7,12 +7,17 @@ use alloc::collections::BTreeMap;
 use spin::{Mutex, RwLock};
 use super::contracts::graphics::*;
 use super::contracts::*;
+use super::manager::ServiceError;
 
 pub mod window_manager;
 pub mod framebuffer_manager;
 pub mod input_handler;
 pub mod theme_manager;
 pub mod animation_engine;
+pub mod damage_test;
+pub mod cursor_test;
+pub mod blend_test;
+pub mod text_test;
 
 /// Main graphics compositor service
 pub struct GraphicsService {
@@ -108,9 +113,6 @@ impl GraphicsService {
         // Initialize framebuffer
         self.framebuffer_manager.initialize()?;
         
-        // Initialize window manager
-        self.window_manager.initialize()?;
-        
         // Initialize input handler
         self.input_handler.initialize()?;
         
@@ -120,6 +122,9 @@ impl GraphicsService {
         // Start animation engine
         self.animation_engine.start()?;
         
+        // Pick the compositor renderer the configuration allows
+        crate::kernel::graphics::set_hardware_acceleration(self.config.read().hardware_acceleration);
+        
         // Update service status
         self.service_info.health_status = HealthStatus::Healthy;
         
@@ -135,8 +140,8 @@ impl GraphicsService {
         }
         
         match request {
-            GraphicsRequest::CreateWindow { width, height, title, flags } => {
-                let window_id = self.window_manager.create_window(width, height, title, flags)?;
+            GraphicsRequest::CreateWindow { title, rect, z_order } => {
+                let window_id = self.window_manager.create_window(title, rect, z_order)?;
                 
                 // Update active windows count
                 {
@@ -171,6 +176,11 @@ impl GraphicsService {
                 Ok(GraphicsResponse::WindowVisibilitySet { window_id, visible })
             }
             
+            GraphicsRequest::SetWindowOpacity { window_id, opacity } => {
+                self.window_manager.set_window_opacity(window_id, opacity)?;
+                Ok(GraphicsResponse::WindowOpacitySet)
+            }
+            
             GraphicsRequest::DrawPixel { window_id, x, y, color } => {
                 self.window_manager.draw_pixel(window_id, x, y, color)?;
                 
@@ -211,6 +221,21 @@ impl GraphicsService {
                 Ok(GraphicsResponse::FramebufferCleared)
             }
             
+            GraphicsRequest::SetCursorBitmap { width, height, pixels } => {
+                self.framebuffer_manager.set_cursor_bitmap(width, height, pixels)?;
+                Ok(GraphicsResponse::CursorBitmapSet)
+            }
+            
+            GraphicsRequest::MoveCursor { x, y } => {
+                self.framebuffer_manager.move_cursor(x, y)?;
+                Ok(GraphicsResponse::CursorMoved)
+            }
+            
+            GraphicsRequest::SetCursorVisible { visible } => {
+                self.framebuffer_manager.set_cursor_visible(visible)?;
+                Ok(GraphicsResponse::CursorVisibilitySet)
+            }
+            
             GraphicsRequest::SwapBuffers => {
                 self.framebuffer_manager.swap_buffers()?;
                 
@@ -321,7 +346,6 @@ impl GraphicsService {
         config.compositor_mode = mode;
         
         // Apply compositor mode changes
-        self.window_manager.set_compositor_mode(mode)?;
         self.framebuffer_manager.set_compositor_mode(mode)?;
         
         Ok(())
@@ -411,9 +435,11 @@ impl GraphicsService {
         self.animation_engine.set_target_fps(config.target_fps)?;
         
         // Update compositor mode
-        self.window_manager.set_compositor_mode(config.compositor_mode)?;
         self.framebuffer_manager.set_compositor_mode(config.compositor_mode)?;
         
+        // Switch between hardware and software compositing
+        crate::kernel::graphics::set_hardware_acceleration(config.hardware_acceleration);
+        
         Ok(())
     }
     
@@ -424,11 +450,16 @@ impl GraphicsService {
         // Update animations
         self.animation_engine.update()?;
         
-        // Render all windows
-        self.window_manager.render_all_windows()?;
+        // Follow the pointer on the cursor plane, which needs no recomposite
+        let cursor = crate::kernel::graphics::get_cursor();
+        self.framebuffer_manager.set_cursor_visible(cursor.visible)?;
+        self.framebuffer_manager.move_cursor(cursor.x, cursor.y)?;
         
-        // Composite to framebuffer
-        self.framebuffer_manager.composite_frame()?;
+        // Recomposite what the windows damaged; an undamaged frame is neither composited
+        // nor swapped, and does not count towards the frame time
+        if !self.framebuffer_manager.composite_frame(&self.window_manager)? {
+            return Ok(());
+        }
         
         // Swap buffers if double buffering is enabled
         {
@@ -499,8 +530,7 @@ impl GraphicsService {
         match event {
             ServiceEvent::HealthCheck => {
                 // Perform health check
-                let is_healthy = self.window_manager.is_healthy() &&
-                                self.framebuffer_manager.is_healthy() &&
+                let is_healthy = self.framebuffer_manager.is_healthy() &&
                                 self.animation_engine.is_healthy();
                 
                 // Update health status
@@ -0,0 +1,111 @@
+//! Text Rendering Tests
+//! Draws text with the bitmap font into an off-screen framebuffer: "Rae" lights exactly the
+//! pixels of its glyphs, a larger font size scales them by whole pixels, tabs and newlines
+//! move the pen, and text wraps or is clipped at the window's edges
+
+use alloc::string::ToString;
+
+use super::super::contracts::graphics::{Color, WindowRect};
+use super::framebuffer_manager::FramebufferManager;
+use super::window_manager::WindowManager;
+use crate::serial::_print;
+
+const SCREEN_WIDTH: u32 = 64;
+const SCREEN_HEIGHT: u32 = 48;
+const INK: u32 = 0xFFFF_FFFF;
+const PAPER: u32 = 0xFF00_0000;
+
+/// A black window of `width` at the screen's top-left corner
+fn page(width: u32) -> Result<(FramebufferManager, WindowManager, u32), &'static str> {
+    let framebuffer = FramebufferManager::with_size(SCREEN_WIDTH, SCREEN_HEIGHT);
+    let windows = WindowManager::new();
+    let window = windows.create_window("page".to_string(), WindowRect::new(0, 0, width, SCREEN_HEIGHT), 0)
+        .map_err(|_| "Window not created")?;
+    windows.clear_window(window, Color::BLACK).map_err(|_| "Clear failed")?;
+    Ok((framebuffer, windows, window))
+}
+
+/// Whether every pixel in `points` is drawn in ink (`lit`) or left blank
+fn all(framebuffer: &FramebufferManager, windows: &WindowManager, points: &[(u32, u32)], lit: bool) -> Result<bool, &'static str> {
+    framebuffer.composite_frame(windows).map_err(|_| "Composite failed")?;
+    let want = if lit { INK } else { PAPER };
+    Ok(points.iter().all(|&(x, y)| framebuffer.back_pixel(x, y) == Some(want)))
+}
+
+pub fn run_text_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Text Test] Starting text rendering tests...\n"));
+
+    // Test 1: "Rae" lights the pixels of each glyph, one 8-pixel cell after another
+    _print(format_args!("[Text Test] Test 1: Rendering \"Rae\"...\n"));
+    let (framebuffer, windows, window) = page(SCREEN_WIDTH)?;
+    windows.draw_text(window, 0, 0, "Rae".to_string(), Color::WHITE, 16).map_err(|_| "Text not drawn")?;
+    // R's top bar spans columns 0-5 of row 2; its leg splits from the bowl on row 7
+    let r_lit = [(0, 2), (1, 2), (5, 2), (1, 7), (2, 7), (4, 7), (0, 11), (6, 11)];
+    let r_blank = [(6, 2), (0, 7), (3, 7), (6, 7), (3, 11), (0, 0), (7, 5), (1, 12)];
+    if !all(&framebuffer, &windows, &r_lit, true)? || !all(&framebuffer, &windows, &r_blank, false)? {
+        return Err("'R' glyph drawn wrong");
+    }
+    // a's arch on row 5 and e's crossbar on row 7, in the second and third cells
+    if !all(&framebuffer, &windows, &[(9, 5), (12, 5), (16, 7), (22, 7)], true)?
+        || !all(&framebuffer, &windows, &[(8, 5), (13, 5), (23, 7), (9, 2)], false)?
+    {
+        return Err("'a' or 'e' glyph drawn wrong");
+    }
+    _print(format_args!("[Text Test] ✓ \"Rae\" lit the expected pixels\n"));
+
+    // Test 2: a 32-pixel font draws every font pixel as a 2x2 block
+    _print(format_args!("[Text Test] Test 2: Scaling to 2x...\n"));
+    let (framebuffer, windows, window) = page(SCREEN_WIDTH)?;
+    windows.draw_text(window, 0, 0, "R".to_string(), Color::WHITE, 32).map_err(|_| "Text not drawn")?;
+    if !all(&framebuffer, &windows, &[(0, 4), (1, 4), (0, 5), (1, 5), (10, 4), (11, 5), (2, 14), (9, 15)], true)?
+        || !all(&framebuffer, &windows, &[(12, 4), (13, 5), (0, 14), (7, 15), (0, 3), (0, 24)], false)?
+    {
+        return Err("2x glyph not scaled by whole pixels");
+    }
+    _print(format_args!("[Text Test] ✓ Font pixels doubled at 2x\n"));
+
+    // Test 3: tabs move to the next four-cell stop and newlines return to the start column
+    _print(format_args!("[Text Test] Test 3: Tabs and newlines...\n"));
+    let (framebuffer, windows, window) = page(SCREEN_WIDTH)?;
+    windows.draw_text(window, 4, 0, "R\tR\nR".to_string(), Color::WHITE, 16).map_err(|_| "Text not drawn")?;
+    if !all(&framebuffer, &windows, &[(4, 2), (36, 2), (4, 18)], true)?
+        || !all(&framebuffer, &windows, &[(12, 2), (20, 2), (12, 18), (36, 18)], false)?
+    {
+        return Err("Tab or newline moved the pen wrong");
+    }
+    _print(format_args!("[Text Test] ✓ Tab stop and new line placed correctly\n"));
+
+    // Test 4: a glyph that would cross the right edge wraps, and one that cannot fit is clipped
+    _print(format_args!("[Text Test] Test 4: Wrapping and clipping at the window edge...\n"));
+    let (framebuffer, windows, window) = page(20)?;
+    windows.draw_text(window, 0, 0, "RRR".to_string(), Color::WHITE, 16).map_err(|_| "Text not drawn")?;
+    if !all(&framebuffer, &windows, &[(0, 2), (8, 2), (0, 18)], true)? || !all(&framebuffer, &windows, &[(16, 2)], false)? {
+        return Err("Third glyph not wrapped onto the next line");
+    }
+    let (framebuffer, windows, window) = page(20)?;
+    windows.draw_text(window, 16, 0, "R".to_string(), Color::WHITE, 16).map_err(|_| "Text not drawn")?;
+    if !all(&framebuffer, &windows, &[(16, 2), (19, 2)], true)? || !all(&framebuffer, &windows, &[(20, 2), (0, 18)], false)? {
+        return Err("Glyph at the edge not clipped to the window");
+    }
+    if windows.draw_text(999, 0, 0, "R".to_string(), Color::WHITE, 16).is_ok() {
+        return Err("Text drawn in a missing window");
+    }
+    _print(format_args!("[Text Test] ✓ Wrapped at and clipped to the window edge\n"));
+
+    _print(format_args!("[Text Test] ✓ All text rendering tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for text rendering
+pub fn test_text_rendering() {
+    _print(format_args!("[Text Test] ===========================================\n"));
+    _print(format_args!("[Text Test]          TEXT RENDERING TESTS\n"));
+    _print(format_args!("[Text Test] ===========================================\n"));
+
+    match run_text_tests() {
+        Ok(_) => _print(format_args!("[Text Test] ✓ All text rendering tests PASSED\n")),
+        Err(e) => _print(format_args!("[Text Test] ✗ Text rendering tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Text Test] ===========================================\n"));
+}
@@ -0,0 +1,358 @@
+//! Window management for rae-compositord
+//! The windows clients draw into, each with its own pixel buffer, stacked by z-order. Every
+//! change to what a window shows is recorded as damage in screen coordinates, clipped to the
+//! window, for the framebuffer manager to recomposite on the next frame.
+//!
+//! Pixels are straight (not premultiplied) ARGB. Translucent drawing is blended source-over
+//! into the window, and windows are blended over what lies beneath them, each pixel's alpha
+//! scaled by its window's opacity.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use spin::{Mutex, RwLock};
+
+use super::super::contracts::graphics::{Color, WindowInfo, WindowRect};
+use super::super::manager::ServiceError;
+use super::framebuffer_manager::DamageList;
+use crate::kernel::graphics::font::bitmap::{is_lit, GLYPH_HEIGHT, GLYPH_WIDTH};
+use crate::kernel::graphics::{self as pixels, GraphicsBuffer};
+
+pub const DEFAULT_MAX_WINDOWS: u32 = 256;
+/// Widest or tallest window allowed
+pub const MAX_WINDOW_DIMENSION: u32 = 8192;
+/// Largest whole-pixel scale text is drawn at
+pub const MAX_TEXT_SCALE: u32 = 4;
+/// Glyph cells between tab stops
+const TAB_STOP: u32 = 4;
+
+struct Window {
+    info: WindowInfo,
+    buffer: GraphicsBuffer,
+}
+
+impl Window {
+    /// `local`, a rectangle in window coordinates, clipped to the window and put on screen
+    fn on_screen(&self, local: WindowRect) -> Option<WindowRect> {
+        let rect = self.info.rect;
+        let clipped = local.intersection(&WindowRect::new(0, 0, rect.width, rect.height))?;
+        Some(WindowRect::new(rect.x + clipped.x, rect.y + clipped.y, clipped.width, clipped.height))
+    }
+
+    /// Draw `color` over the pixel at `x`, `y`, which must lie within the window
+    fn paint(&mut self, x: u32, y: u32, color: u32) {
+        let pixel = &mut self.buffer.pixels[(y * self.info.rect.width + x) as usize];
+        *pixel = blend(*pixel, color);
+    }
+
+    /// Draw `color` over `local`, already clipped to the window
+    fn fill(&mut self, local: WindowRect, color: u32) {
+        for y in local.y..local.bottom() as i32 {
+            for x in local.x..local.right() as i32 {
+                self.paint(x as u32, y as u32, color);
+            }
+        }
+    }
+
+    /// Draw `ch` from the bitmap font with its top-left corner at `x`, `y`, each font pixel
+    /// a `scale`-pixel square; what falls outside the window is clipped
+    fn glyph(&mut self, ch: char, x: u32, y: u32, scale: u32, color: u32) {
+        let bounds = WindowRect::new(0, 0, self.info.rect.width, self.info.rect.height);
+        for row in 0..GLYPH_HEIGHT {
+            for col in 0..GLYPH_WIDTH {
+                if !is_lit(ch, col, row) {
+                    continue;
+                }
+                let block = WindowRect::new((x + col * scale) as i32, (y + row * scale) as i32, scale, scale);
+                if let Some(visible) = block.intersection(&bounds) {
+                    self.fill(visible, color);
+                }
+            }
+        }
+    }
+}
+
+fn pixel_color(color: Color) -> pixels::Color {
+    pixels::Color::new(color.r, color.g, color.b, color.a)
+}
+
+fn pixel_value(color: Color) -> u32 {
+    ((color.a as u32) << 24) | ((color.r as u32) << 16) | ((color.g as u32) << 8) | (color.b as u32)
+}
+
+/// `src` drawn over `dst` (source-over), both straight-alpha ARGB. Each color is weighted by
+/// its own alpha, so a translucent color over a transparent pixel keeps its color instead of
+/// darkening towards black as it would if straight colors were treated as premultiplied
+pub(super) fn blend(dst: u32, src: u32) -> u32 {
+    let src_alpha = src >> 24;
+    match src_alpha {
+        0 => return dst,
+        255 => return src,
+        _ => {}
+    }
+    // Alphas scaled by 255 so the arithmetic stays in integers; rounded to nearest
+    let dst_weight = (dst >> 24) * (255 - src_alpha);
+    let out_alpha = src_alpha * 255 + dst_weight;
+    let channel = |shift: u32| {
+        let (s, d) = ((src >> shift) & 0xFF, (dst >> shift) & 0xFF);
+        ((s * src_alpha * 255 + d * dst_weight + out_alpha / 2) / out_alpha).min(255) << shift
+    };
+    (((out_alpha + 127) / 255).min(255) << 24) | channel(16) | channel(8) | channel(0)
+}
+
+/// `pixel` with its alpha scaled by `opacity`
+fn with_opacity(pixel: u32, opacity: u8) -> u32 {
+    let alpha = ((pixel >> 24) * opacity as u32 + 127) / 255;
+    (alpha << 24) | (pixel & 0x00FF_FFFF)
+}
+
+/// The compositor's windows
+pub struct WindowManager {
+    windows: RwLock<BTreeMap<u32, Window>>,
+    damage: Mutex<DamageList>,
+    next_id: AtomicU32,
+    max_windows: AtomicU32,
+}
+
+impl WindowManager {
+    pub fn new() -> Self {
+        Self {
+            windows: RwLock::new(BTreeMap::new()),
+            damage: Mutex::new(DamageList::new()),
+            next_id: AtomicU32::new(1),
+            max_windows: AtomicU32::new(DEFAULT_MAX_WINDOWS),
+        }
+    }
+
+    /// Record `rect` of the screen as needing recompositing on the next frame
+    pub fn mark_damage(&self, rect: WindowRect) {
+        self.damage.lock().add(rect);
+    }
+
+    /// The damage recorded since the last call
+    pub fn take_damage(&self) -> DamageList {
+        self.damage.lock().take()
+    }
+
+    /// Run `f` on window `window_id`, then damage the screen rectangle it returns
+    fn update<F>(&self, window_id: u32, f: F) -> Result<(), ServiceError>
+    where
+        F: FnOnce(&mut Window) -> Option<WindowRect>,
+    {
+        let mut windows = self.windows.write();
+        let window = windows.get_mut(&window_id).ok_or(ServiceError::ServiceNotFound)?;
+        if let Some(damage) = f(window) {
+            if window.info.visible {
+                self.mark_damage(damage);
+            }
+        }
+        Ok(())
+    }
+
+    /// A new visible, transparent window on top of those with the same `z_order`
+    pub fn create_window(&self, title: String, rect: WindowRect, z_order: u32) -> Result<u32, ServiceError> {
+        if rect.is_empty() || rect.width > MAX_WINDOW_DIMENSION || rect.height > MAX_WINDOW_DIMENSION {
+            return Err(ServiceError::ResourceLimitExceeded);
+        }
+        let mut windows = self.windows.write();
+        if windows.len() >= self.max_windows.load(Ordering::Relaxed) as usize {
+            return Err(ServiceError::ResourceLimitExceeded);
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let info = WindowInfo { id, title, rect, z_order, visible: true, focused: false, process_id: 0, opacity: u8::MAX };
+        windows.insert(id, Window { info, buffer: GraphicsBuffer::new(rect.width, rect.height) });
+        Ok(id)
+    }
+
+    pub fn destroy_window(&self, window_id: u32) -> Result<(), ServiceError> {
+        let window = self.windows.write().remove(&window_id).ok_or(ServiceError::ServiceNotFound)?;
+        if window.info.visible {
+            self.mark_damage(window.info.rect);
+        }
+        Ok(())
+    }
+
+    pub fn destroy_all_windows(&self) -> Result<(), ServiceError> {
+        let windows = core::mem::take(&mut *self.windows.write());
+        for window in windows.values().filter(|window| window.info.visible) {
+            self.mark_damage(window.info.rect);
+        }
+        Ok(())
+    }
+
+    /// Move or resize a window. Both where it was and where it is now are damaged; content
+    /// that still fits a resized window is kept
+    pub fn set_window_rect(&self, window_id: u32, rect: WindowRect) -> Result<(), ServiceError> {
+        if rect.is_empty() || rect.width > MAX_WINDOW_DIMENSION || rect.height > MAX_WINDOW_DIMENSION {
+            return Err(ServiceError::ResourceLimitExceeded);
+        }
+        let mut windows = self.windows.write();
+        let window = windows.get_mut(&window_id).ok_or(ServiceError::ServiceNotFound)?;
+        let old = window.info.rect;
+        if (old.width, old.height) != (rect.width, rect.height) {
+            let mut buffer = GraphicsBuffer::new(rect.width, rect.height);
+            for y in 0..old.height.min(rect.height) {
+                for x in 0..old.width.min(rect.width) {
+                    buffer.set_pixel(x, y, window.buffer.get_pixel(x, y));
+                }
+            }
+            window.buffer = buffer;
+        }
+        window.info.rect = rect;
+        if window.info.visible {
+            self.mark_damage(old);
+            self.mark_damage(rect);
+        }
+        Ok(())
+    }
+
+    pub fn set_window_visible(&self, window_id: u32, visible: bool) -> Result<(), ServiceError> {
+        let mut windows = self.windows.write();
+        let window = windows.get_mut(&window_id).ok_or(ServiceError::ServiceNotFound)?;
+        if window.info.visible != visible {
+            window.info.visible = visible;
+            self.mark_damage(window.info.rect);
+        }
+        Ok(())
+    }
+
+    /// How much of the window shows over what lies beneath it, from 0 (none) to 255 (all)
+    pub fn set_window_opacity(&self, window_id: u32, opacity: u8) -> Result<(), ServiceError> {
+        let mut windows = self.windows.write();
+        let window = windows.get_mut(&window_id).ok_or(ServiceError::ServiceNotFound)?;
+        if window.info.opacity != opacity {
+            window.info.opacity = opacity;
+            if window.info.visible {
+                self.mark_damage(window.info.rect);
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw one pixel, blended by its alpha; one outside the window draws nothing
+    pub fn draw_pixel(&self, window_id: u32, x: u32, y: u32, color: Color) -> Result<(), ServiceError> {
+        self.update(window_id, |window| {
+            let pixel = window.on_screen(WindowRect::new(x as i32, y as i32, 1, 1))?;
+            window.paint(x, y, pixel_value(color));
+            Some(pixel)
+        })
+    }
+
+    /// Draw `rect`, in window coordinates, filled or as an outline and blended by the
+    /// color's alpha; what falls outside the window is clipped
+    pub fn draw_rect(&self, window_id: u32, rect: WindowRect, color: Color, filled: bool) -> Result<(), ServiceError> {
+        self.update(window_id, |window| {
+            let damage = window.on_screen(rect)?;
+            let bounds = WindowRect::new(0, 0, window.info.rect.width, window.info.rect.height);
+            let color = pixel_value(color);
+            if filled {
+                window.fill(rect.intersection(&bounds)?, color);
+            } else {
+                // Edges that share no corner, so a translucent outline is blended once per pixel
+                let (right, bottom) = (rect.right() as i32 - 1, rect.bottom() as i32 - 1);
+                let side = rect.height.saturating_sub(2);
+                let mut edges = Vec::with_capacity(4);
+                edges.push(WindowRect::new(rect.x, rect.y, rect.width, 1));
+                if rect.height > 1 {
+                    edges.push(WindowRect::new(rect.x, bottom, rect.width, 1));
+                }
+                edges.push(WindowRect::new(rect.x, rect.y + 1, 1, side));
+                if rect.width > 1 {
+                    edges.push(WindowRect::new(right, rect.y + 1, 1, side));
+                }
+                for edge in edges.iter().filter_map(|edge| edge.intersection(&bounds)) {
+                    window.fill(edge, color);
+                }
+            }
+            Some(damage)
+        })
+    }
+
+    /// Draw `text` in the 8x16 bitmap font with its top-left corner at `x`, `y`, blended by the
+    /// color's alpha. Glyphs are scaled by the largest whole number that keeps them no taller
+    /// than `font_size`, at least 1x. `\n` starts a new line at `x`, `\t` moves to the next tab
+    /// stop, and a glyph that would cross the window's right edge wraps onto a new line;
+    /// whatever still falls outside the window is clipped
+    pub fn draw_text(&self, window_id: u32, x: u32, y: u32, text: String, color: Color, font_size: u32) -> Result<(), ServiceError> {
+        let scale = (font_size / GLYPH_HEIGHT).clamp(1, MAX_TEXT_SCALE);
+        let (advance, line_height) = (GLYPH_WIDTH * scale, GLYPH_HEIGHT * scale);
+        let color = pixel_value(color);
+        self.update(window_id, |window| {
+            let (width, height) = (window.info.rect.width, window.info.rect.height);
+            if x >= width || y >= height {
+                return None;
+            }
+            let (mut pen_x, mut pen_y) = (x, y);
+            let mut drawn: Option<WindowRect> = None;
+            for ch in text.chars() {
+                match ch {
+                    '\n' => {
+                        pen_x = x;
+                        pen_y = pen_y.saturating_add(line_height);
+                        continue;
+                    }
+                    '\t' => {
+                        let stop = advance * TAB_STOP;
+                        pen_x = x.saturating_add(((pen_x - x) / stop + 1) * stop);
+                        continue;
+                    }
+                    _ => {}
+                }
+                if pen_x > x && pen_x.saturating_add(advance) > width {
+                    pen_x = x;
+                    pen_y = pen_y.saturating_add(line_height);
+                }
+                if pen_y >= height {
+                    break;
+                }
+                window.glyph(ch, pen_x, pen_y, scale, color);
+                let cell = WindowRect::new(pen_x as i32, pen_y as i32, advance, line_height);
+                drawn = Some(drawn.map_or(cell, |drawn| drawn.union(&cell)));
+                pen_x += advance;
+            }
+            window.on_screen(drawn?)
+        })
+    }
+
+    /// Set every pixel of the window to `color`, alpha included, without blending
+    pub fn clear_window(&self, window_id: u32, color: Color) -> Result<(), ServiceError> {
+        self.update(window_id, |window| {
+            window.buffer.clear(pixel_color(color));
+            Some(window.info.rect)
+        })
+    }
+
+    pub fn get_window_info(&self, window_id: u32) -> Result<WindowInfo, ServiceError> {
+        self.windows.read().get(&window_id).map(|window| window.info.clone()).ok_or(ServiceError::ServiceNotFound)
+    }
+
+    pub fn set_max_windows(&self, max_windows: u32) -> Result<(), ServiceError> {
+        self.max_windows.store(max_windows, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Blend the visible windows, lowest first and each by its opacity, over `region` of
+    /// `target`, a screen `stride` pixels wide. `region` must lie within the screen
+    pub fn composite(&self, target: &mut [u32], stride: u32, region: WindowRect) {
+        let windows = self.windows.read();
+        let mut stack: Vec<&Window> = windows.values()
+            .filter(|window| window.info.visible && window.info.opacity > 0)
+            .collect();
+        stack.sort_by_key(|window| window.info.z_order);
+
+        for window in stack {
+            let rect = window.info.rect;
+            let Some(visible) = rect.intersection(&region) else { continue };
+            for y in visible.y..visible.bottom() as i32 {
+                let source_row = (y - rect.y) as usize * rect.width as usize;
+                let target_row = y as usize * stride as usize;
+                for x in visible.x..visible.right() as i32 {
+                    let source = with_opacity(window.buffer.pixels[source_row + (x - rect.x) as usize], window.info.opacity);
+                    let target = &mut target[target_row + x as usize];
+                    *target = blend(*target, source);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,361 @@
+//! Init Service Tests
+//! Supervises fake processes on a manual clock: services start after their dependencies,
+//! exited children and adopted orphans are reaped, a crashing service is restarted until its
+//! limit, SIGTERM stops services dependents first, killing one that ignores it, and a
+//! socket-activated service starts on its first message and can be stopped and activated again
+
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::{parse_config, EndpointHandle, Init, InitError, Phase, ProcessControl, ServiceState, ServiceStatus};
+use crate::process::{ProcessError, ProcessId, Signal};
+use crate::serial::_print;
+
+/// Processes that run until the test ends them
+struct FakeProcesses {
+    now_ms: u64,
+    next_pid: ProcessId,
+    /// Running children and their programs
+    alive: BTreeMap<ProcessId, String>,
+    /// Exited children not yet reaped
+    zombies: VecDeque<(ProcessId, i32)>,
+    spawned: Vec<String>,
+    signals: Vec<(ProcessId, Signal)>,
+    /// Programs that ignore SIGTERM
+    stubborn: BTreeSet<String>,
+    /// Programs that do not exist
+    missing: BTreeSet<String>,
+    /// Bound endpoints by name
+    endpoints: BTreeMap<String, EndpointHandle>,
+    /// Messages waiting on each endpoint
+    queued: BTreeMap<EndpointHandle, usize>,
+    /// The process each endpoint was handed to
+    receivers: BTreeMap<EndpointHandle, ProcessId>,
+}
+
+impl FakeProcesses {
+    fn new() -> Self {
+        Self {
+            now_ms: 0,
+            next_pid: 100,
+            alive: BTreeMap::new(),
+            zombies: VecDeque::new(),
+            spawned: Vec::new(),
+            signals: Vec::new(),
+            stubborn: BTreeSet::new(),
+            missing: BTreeSet::new(),
+            endpoints: BTreeMap::new(),
+            queued: BTreeMap::new(),
+            receivers: BTreeMap::new(),
+        }
+    }
+
+    /// A client's message to endpoint `name`
+    fn send(&mut self, name: &str) -> Result<(), &'static str> {
+        let endpoint = self.endpoints.get(name).ok_or("Endpoint not bound")?;
+        *self.queued.entry(*endpoint).or_insert(0) += 1;
+        Ok(())
+    }
+
+    /// Process `pid` receives everything queued on the endpoints handed to it; returns how
+    /// many messages it handled
+    fn serve(&mut self, pid: ProcessId) -> usize {
+        let endpoints: Vec<EndpointHandle> = self.receivers.iter()
+            .filter(|(_, &receiver)| receiver == pid && self.alive.contains_key(&pid))
+            .map(|(&endpoint, _)| endpoint)
+            .collect();
+        endpoints.iter().filter_map(|endpoint| self.queued.remove(endpoint)).sum()
+    }
+
+    fn pid_of(&self, path: &str) -> Option<ProcessId> {
+        self.alive.iter().find(|(_, program)| program.as_str() == path).map(|(&pid, _)| pid)
+    }
+
+    fn exit(&mut self, pid: ProcessId, exit_code: i32) {
+        if self.alive.remove(&pid).is_some() {
+            self.zombies.push_back((pid, exit_code));
+        }
+    }
+
+    /// A process whose parent died before it, handed to init already exited
+    fn adopt_orphan(&mut self) -> ProcessId {
+        let pid = self.next_pid;
+        self.next_pid += 1;
+        self.zombies.push_back((pid, 0));
+        pid
+    }
+}
+
+impl ProcessControl for FakeProcesses {
+    fn spawn(&mut self, path: &str) -> Result<ProcessId, ProcessError> {
+        if self.missing.contains(path) {
+            return Err(ProcessError::NotExecutable);
+        }
+        let pid = self.next_pid;
+        self.next_pid += 1;
+        self.alive.insert(pid, path.to_string());
+        self.spawned.push(path.to_string());
+        Ok(pid)
+    }
+
+    fn signal(&mut self, pid: ProcessId, signal: Signal) -> Result<(), &'static str> {
+        let program = self.alive.get(&pid).ok_or("No such process")?.clone();
+        self.signals.push((pid, signal));
+        match signal {
+            Signal::SIGKILL => self.exit(pid, -9),
+            Signal::SIGTERM if !self.stubborn.contains(&program) => self.exit(pid, -1),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn try_wait(&mut self) -> Option<(ProcessId, i32)> {
+        self.zombies.pop_front()
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.now_ms
+    }
+
+    fn bind(&mut self, name: &str) -> Result<EndpointHandle, &'static str> {
+        if self.endpoints.contains_key(name) {
+            return Err("Endpoint name already bound");
+        }
+        let endpoint = self.endpoints.len() as EndpointHandle + 1;
+        self.endpoints.insert(name.to_string(), endpoint);
+        Ok(endpoint)
+    }
+
+    fn pending(&self, endpoint: EndpointHandle) -> bool {
+        self.queued.get(&endpoint).is_some_and(|&messages| messages > 0)
+    }
+
+    fn hand_off(&mut self, endpoint: EndpointHandle, pid: ProcessId, _name: &str) -> Result<(), &'static str> {
+        if !self.alive.contains_key(&pid) {
+            return Err("No such process");
+        }
+        self.receivers.insert(endpoint, pid);
+        Ok(())
+    }
+}
+
+fn supervise(config: &str, processes: FakeProcesses) -> Result<Init<FakeProcesses>, &'static str> {
+    let specs = parse_config(config).map_err(|_| "Valid service list rejected")?;
+    Init::new(processes, specs).map_err(|_| "Valid dependencies rejected")
+}
+
+fn state_of(init: &Init<FakeProcesses>, name: &str) -> Option<ServiceStatus> {
+    init.status().into_iter().find(|service| service.name == name)
+}
+
+pub fn run_init_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Init Test] Starting init tests...\n"));
+
+    // Test 1: services start after their dependencies, whatever order they are listed in
+    _print(format_args!("[Init Test] Test 1: Starting services in dependency order...\n"));
+    let mut processes = FakeProcesses::new();
+    processes.missing.insert("/bin/gone".to_string());
+    let config = "# userspace services\n\
+                  ui /bin/ui after=net,log\n\
+                  net /bin/net after=log restart=always\n\
+                  \n\
+                  log /bin/log\n\
+                  gone /bin/gone\n\
+                  needs-gone /bin/needs-gone after=gone\n";
+    let mut init = supervise(config, processes)?;
+    let order: Vec<String> = init.status().into_iter().map(|service| service.name).collect();
+    if order != ["log", "net", "ui", "gone", "needs-gone"] {
+        return Err("Services not ordered after their dependencies");
+    }
+    init.tick();
+    if init.processes().spawned != ["/bin/log", "/bin/net", "/bin/ui"] {
+        return Err("Services not started in dependency order");
+    }
+    if state_of(&init, "gone").map(|service| service.state) != Some(ServiceState::Failed)
+        || state_of(&init, "needs-gone").map(|service| service.state) != Some(ServiceState::Failed)
+    {
+        return Err("Missing program or its dependent not marked failed");
+    }
+    let cyclic = parse_config("a /bin/a after=b\nb /bin/b after=a\nc /bin/c\n").map_err(|_| "Cyclic list rejected by parser")?;
+    if !matches!(Init::new(FakeProcesses::new(), cyclic), Err(InitError::DependencyCycle(names)) if names == ["a", "b"]) {
+        return Err("Dependency cycle not reported");
+    }
+    let unknown = parse_config("a /bin/a after=nobody\n").map_err(|_| "Unknown dependency rejected by parser")?;
+    if !matches!(Init::new(FakeProcesses::new(), unknown), Err(InitError::UnknownDependency { .. })) {
+        return Err("Unknown dependency not reported");
+    }
+    if !matches!(parse_config("log /bin/log\nbad /bin/bad restart=sometimes\n"), Err(InitError::Parse { line: 2, .. })) {
+        return Err("Bad restart policy not reported on its line");
+    }
+    _print(format_args!("[Init Test] ✓ log, net and ui started in order; missing program failed with its dependent\n"));
+
+    // Test 2: exited children and adopted orphans are reaped, leaving no zombie
+    _print(format_args!("[Init Test] Test 2: Reaping exited children...\n"));
+    let mut init = supervise("setup /bin/setup restart=on-failure\ndaemon /bin/daemon\n", FakeProcesses::new())?;
+    init.tick();
+    let setup = init.processes().pid_of("/bin/setup").ok_or("setup not started")?;
+    init.processes_mut().exit(setup, 0);
+    init.processes_mut().adopt_orphan();
+    init.processes_mut().adopt_orphan();
+    init.tick();
+    if !init.processes().zombies.is_empty() {
+        return Err("Exited child left a zombie");
+    }
+    if init.orphans_reaped() != 2 {
+        return Err("Adopted orphans not reaped");
+    }
+    if state_of(&init, "setup").map(|service| service.state) != Some(ServiceState::Exited(0)) {
+        return Err("Clean exit restarted under on-failure");
+    }
+    if !matches!(state_of(&init, "daemon").map(|service| service.state), Some(ServiceState::Running(_))) {
+        return Err("Reaping orphans disturbed a running service");
+    }
+    _print(format_args!("[Init Test] ✓ Child and 2 orphans reaped, no zombies left\n"));
+
+    // Test 3: a crashing service is restarted after its delay, up to its limit
+    _print(format_args!("[Init Test] Test 3: Restarting a crashing service...\n"));
+    let mut init = supervise("crashy /bin/crashy max-restarts=2 restart-delay=100\n", FakeProcesses::new())?;
+    init.tick();
+    for restart in 1..=2 {
+        let pid = init.processes().pid_of("/bin/crashy").ok_or("crashy not running")?;
+        init.processes_mut().exit(pid, 1);
+        init.tick();
+        if init.processes().spawned.len() != restart {
+            return Err("Crashed service restarted before its delay");
+        }
+        init.processes_mut().now_ms += 100;
+        init.tick();
+        if init.processes().spawned.len() != restart + 1 {
+            return Err("Crashed service not restarted after its delay");
+        }
+    }
+    let pid = init.processes().pid_of("/bin/crashy").ok_or("crashy not running")?;
+    init.processes_mut().exit(pid, 1);
+    init.tick();
+    init.processes_mut().now_ms += 1000;
+    init.tick();
+    let crashy = state_of(&init, "crashy").ok_or("crashy missing from status")?;
+    if crashy.state != ServiceState::Failed || crashy.restarts != 2 || crashy.last_exit != Some(1) {
+        return Err("Service not given up on at its restart limit");
+    }
+    if init.processes().spawned.len() != 3 {
+        return Err("Service restarted past its limit");
+    }
+    _print(format_args!("[Init Test] ✓ Restarted twice, then marked failed\n"));
+
+    // Test 4: SIGTERM stops dependents first and kills a service that ignores it
+    _print(format_args!("[Init Test] Test 4: Shutting down on SIGTERM...\n"));
+    let mut processes = FakeProcesses::new();
+    processes.stubborn.insert("/bin/web".to_string());
+    let mut init = supervise("log /bin/log restart=always\ndb /bin/db after=log\nweb /bin/web after=db stop-timeout=500\n", processes)?;
+    init.tick();
+    let pid = |init: &Init<FakeProcesses>, path: &str| init.processes().pid_of(path).ok_or("Service not started");
+    let (log, db, web) = (pid(&init, "/bin/log")?, pid(&init, "/bin/db")?, pid(&init, "/bin/web")?);
+    init.handle_signal(Signal::SIGTERM);
+    init.tick();
+    if init.processes().signals != [(web, Signal::SIGTERM)] {
+        return Err("Shutdown did not stop the last dependent first");
+    }
+    init.processes_mut().now_ms += 500;
+    for _ in 0..4 {
+        init.tick();
+    }
+    let expected = [(web, Signal::SIGTERM), (web, Signal::SIGKILL), (db, Signal::SIGTERM), (log, Signal::SIGTERM)];
+    if init.processes().signals != expected {
+        return Err("Shutdown did not kill web, then stop db and log in turn");
+    }
+    if init.phase() != Phase::Halted || !init.processes().alive.is_empty() {
+        return Err("Init did not halt once every service stopped");
+    }
+    if init.processes().spawned.len() != 3 {
+        return Err("Service restarted during shutdown");
+    }
+    if state_of(&init, "web").and_then(|service| service.last_exit) != Some(-9) {
+        return Err("Killed service's exit not recorded");
+    }
+    _print(format_args!("[Init Test] ✓ web killed after its timeout, then db and log stopped\n"));
+
+    // Test 5: a socket-activated service starts on its first message, and after being
+    // stopped starts again on the next
+    _print(format_args!("[Init Test] Test 5: Activating a service on its first message...\n"));
+    let mut init = supervise("log /bin/log
+named /bin/named socket=dns after=log
+client /bin/client after=named
+", FakeProcesses::new())?;
+    init.tick();
+    init.tick();
+    if state_of(&init, "named").map(|service| service.state) != Some(ServiceState::Listening)
+        || init.processes().pid_of("/bin/named").is_some()
+    {
+        return Err("Socket-activated service started before any message");
+    }
+    if init.processes().pid_of("/bin/client").is_none() {
+        return Err("Dependent of a listening service not started");
+    }
+    init.processes_mut().send("dns")?;
+    init.tick();
+    let named = init.processes().pid_of("/bin/named").ok_or("First message did not start the service")?;
+    if state_of(&init, "named").map(|service| service.state) != Some(ServiceState::Running(named)) {
+        return Err("Activated service not recorded as running");
+    }
+    if init.processes_mut().serve(named) != 1 {
+        return Err("Activated service did not receive the first message");
+    }
+    init.stop_service("named").map_err(|_| "Known service not stopped")?;
+    init.tick();
+    if init.processes().signals != [(named, Signal::SIGTERM)]
+        || state_of(&init, "named").map(|service| service.state) != Some(ServiceState::Listening)
+    {
+        return Err("Stopped service not returned to listening");
+    }
+    init.processes_mut().now_ms += 10_000;
+    init.tick();
+    if init.processes().pid_of("/bin/named").is_some() {
+        return Err("Stopped service restarted without a message");
+    }
+    init.processes_mut().send("dns")?;
+    init.processes_mut().send("dns")?;
+    init.tick();
+    let again = init.processes().pid_of("/bin/named").ok_or("Next message did not activate the service again")?;
+    if again == named || init.processes_mut().serve(again) != 2 {
+        return Err("Reactivated service did not receive the queued messages");
+    }
+    init.processes_mut().exit(again, 0);
+    init.tick();
+    if state_of(&init, "named").map(|service| service.state) != Some(ServiceState::Listening)
+        || init.processes().spawned.iter().filter(|path| path.as_str() == "/bin/named").count() != 2
+    {
+        return Err("Idle exit not returned to listening");
+    }
+    init.stop_service("log").map_err(|_| "Known service not stopped")?;
+    init.tick();
+    if state_of(&init, "log").map(|service| service.state) != Some(ServiceState::Stopped) {
+        return Err("Stopped service without a socket not left stopped");
+    }
+    init.start_service("log").map_err(|_| "Known service not started")?;
+    if init.processes().spawned.iter().filter(|path| path.as_str() == "/bin/log").count() != 2 {
+        return Err("Stopped service not started again");
+    }
+    if !matches!(init.stop_service("nobody"), Err(InitError::UnknownService(_))) {
+        return Err("Unknown service stopped");
+    }
+    _print(format_args!("[Init Test] ✓ Started on first contact, stopped, and activated again\n"));
+
+    _print(format_args!("[Init Test] ✓ All init tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for the init service
+pub fn test_init_service() {
+    _print(format_args!("[Init Test] ===========================================\n"));
+    _print(format_args!("[Init Test]             INIT SERVICE TESTS\n"));
+    _print(format_args!("[Init Test] ===========================================\n"));
+
+    match run_init_tests() {
+        Ok(_) => _print(format_args!("[Init Test] ✓ All init tests PASSED\n")),
+        Err(e) => _print(format_args!("[Init Test] ✗ Init tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Init Test] ===========================================\n"));
+}
@@ -0,0 +1,591 @@
+//! Init Service (rae-init)
+//! The first userspace process: starts the services listed in `/etc/init.conf` in dependency
+//! order, adopts and reaps orphaned processes, restarts services that die as their policy says,
+//! and on SIGTERM stops the services in reverse order before halting.
+//!
+//! A socket-activated service is not started at boot. Init binds its endpoint instead and
+//! starts it when the first message arrives, handing it the endpoint with the message still
+//! queued. When the service exits cleanly or is stopped, init holds the endpoint again until
+//! the next message.
+
+use alloc::collections::BTreeSet;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::process::{ProcessError, ProcessId, Signal};
+
+pub mod init_test;
+
+/// The service list read at boot
+pub const INIT_CONF: &str = "/etc/init.conf";
+/// Restarts allowed before a service is given up on, unless configured
+const DEFAULT_MAX_RESTARTS: u32 = 5;
+const DEFAULT_RESTART_DELAY_MS: u64 = 1000;
+/// How long a service has to exit after SIGTERM before it is sent SIGKILL
+const DEFAULT_STOP_TIMEOUT_MS: u64 = 5000;
+/// Sleep between supervision passes
+const POLL_INTERVAL_MS: u64 = 50;
+
+/// When a service that exited is started again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    Never,
+    /// Only after a non-zero exit code
+    OnFailure,
+    Always,
+}
+
+/// One entry of the service list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceSpec {
+    pub name: String,
+    pub path: String,
+    pub restart: RestartPolicy,
+    pub max_restarts: u32,
+    pub restart_delay_ms: u64,
+    pub stop_timeout_ms: u64,
+    /// Services that must be up before this one starts, and that it is stopped before
+    pub after: Vec<String>,
+    /// Endpoint whose first message starts the service
+    pub socket: Option<String>,
+}
+
+impl ServiceSpec {
+    pub fn new(name: &str, path: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            path: path.to_string(),
+            restart: RestartPolicy::OnFailure,
+            max_restarts: DEFAULT_MAX_RESTARTS,
+            restart_delay_ms: DEFAULT_RESTART_DELAY_MS,
+            stop_timeout_ms: DEFAULT_STOP_TIMEOUT_MS,
+            after: Vec::new(),
+            socket: None,
+        }
+    }
+}
+
+/// Init errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InitError {
+    /// A line of the service list is malformed (lines count from 1)
+    Parse { line: usize, message: String },
+    DuplicateService(String),
+    UnknownDependency { service: String, dependency: String },
+    /// The services left over once every startable one is ordered
+    DependencyCycle(Vec<String>),
+    UnknownService(String),
+    Process(ProcessError),
+}
+
+/// Parse service list lines: `NAME PATH [restart=always|on-failure|never] [max-restarts=N]
+/// [restart-delay=MS] [stop-timeout=MS] [after=NAME,NAME...] [socket=ENDPOINT]`. Blank lines
+/// and `#` comments are ignored.
+pub fn parse_config(config: &str) -> Result<Vec<ServiceSpec>, InitError> {
+    let mut specs = Vec::new();
+    for (index, line) in config.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: String| InitError::Parse { line: index + 1, message };
+
+        let mut fields = line.split_whitespace();
+        let (name, path) = match (fields.next(), fields.next()) {
+            (Some(name), Some(path)) => (name, path),
+            _ => return Err(error("expected a service name and a program path".to_string())),
+        };
+        let mut spec = ServiceSpec::new(name, path);
+        for option in fields {
+            let (key, value) = option.split_once('=').ok_or_else(|| error(format!("bad option '{}'", option)))?;
+            let number = || value.parse::<u64>().map_err(|_| error(format!("bad number in '{}'", option)));
+            match key {
+                "restart" => {
+                    spec.restart = match value {
+                        "always" => RestartPolicy::Always,
+                        "on-failure" => RestartPolicy::OnFailure,
+                        "never" => RestartPolicy::Never,
+                        _ => return Err(error(format!("unknown restart policy '{}'", value))),
+                    }
+                }
+                "max-restarts" => spec.max_restarts = u32::try_from(number()?).map_err(|_| error(format!("bad number in '{}'", option)))?,
+                "restart-delay" => spec.restart_delay_ms = number()?,
+                "stop-timeout" => spec.stop_timeout_ms = number()?,
+                "after" => spec.after = value.split(',').filter(|name| !name.is_empty()).map(str::to_string).collect(),
+                "socket" if !value.is_empty() => spec.socket = Some(value.to_string()),
+                _ => return Err(error(format!("unknown option '{}'", key))),
+            }
+        }
+        specs.push(spec);
+    }
+    Ok(specs)
+}
+
+/// Order `specs` so every service comes after its dependencies, keeping the listed order
+/// where the dependencies allow
+fn start_order(specs: Vec<ServiceSpec>) -> Result<Vec<ServiceSpec>, InitError> {
+    let mut names = BTreeSet::new();
+    for spec in &specs {
+        if !names.insert(spec.name.as_str()) {
+            return Err(InitError::DuplicateService(spec.name.clone()));
+        }
+    }
+    for spec in &specs {
+        if let Some(dependency) = spec.after.iter().find(|dependency| !names.contains(dependency.as_str())) {
+            return Err(InitError::UnknownDependency { service: spec.name.clone(), dependency: dependency.clone() });
+        }
+    }
+
+    let mut remaining = specs;
+    let mut ordered: Vec<ServiceSpec> = Vec::new();
+    while !remaining.is_empty() {
+        let ready = remaining.iter().position(|spec| {
+            spec.after.iter().all(|dependency| ordered.iter().any(|placed| &placed.name == dependency))
+        });
+        match ready {
+            Some(index) => ordered.push(remaining.remove(index)),
+            None => return Err(InitError::DependencyCycle(remaining.into_iter().map(|spec| spec.name).collect())),
+        }
+    }
+    Ok(ordered)
+}
+
+/// Handle to an endpoint init has bound
+pub type EndpointHandle = u32;
+
+/// The processes and endpoints init manages, injectable for tests
+pub trait ProcessControl {
+    /// Start the program at `path` as a child of init
+    fn spawn(&mut self, path: &str) -> Result<ProcessId, ProcessError>;
+    fn signal(&mut self, pid: ProcessId, signal: Signal) -> Result<(), &'static str>;
+    /// Reap one exited child, whether started by init or adopted, without blocking
+    fn try_wait(&mut self) -> Option<(ProcessId, i32)>;
+    /// Monotonic time in milliseconds
+    fn now_ms(&self) -> u64;
+    /// Bind the endpoint `name` for clients to send to, held by init
+    fn bind(&mut self, name: &str) -> Result<EndpointHandle, &'static str>;
+    /// Whether messages are waiting on a bound endpoint
+    fn pending(&self, endpoint: EndpointHandle) -> bool;
+    /// Let process `pid` receive on a bound endpoint
+    fn hand_off(&mut self, endpoint: EndpointHandle, pid: ProcessId, name: &str) -> Result<(), &'static str>;
+}
+
+/// The kernel's processes, as seen from init
+pub struct KernelProcesses;
+
+impl ProcessControl for KernelProcesses {
+    fn spawn(&mut self, path: &str) -> Result<ProcessId, ProcessError> {
+        crate::process::spawn_program(path)
+    }
+
+    fn signal(&mut self, pid: ProcessId, signal: Signal) -> Result<(), &'static str> {
+        crate::process::send_signal(pid, signal)
+    }
+
+    fn try_wait(&mut self) -> Option<(ProcessId, i32)> {
+        crate::process::try_wait_pid(None).ok().flatten()
+    }
+
+    fn now_ms(&self) -> u64 {
+        crate::time::get_uptime_ms()
+    }
+
+    fn bind(&mut self, name: &str) -> Result<EndpointHandle, &'static str> {
+        crate::ipc::bind_endpoint(crate::process::get_current_process_id() as u32, name)
+            .map_err(|_| "endpoint name already bound")
+    }
+
+    fn pending(&self, endpoint: EndpointHandle) -> bool {
+        crate::ipc::pending_endpoint_messages(crate::process::get_current_process_id() as u32, endpoint)
+            .is_ok_and(|messages| messages > 0)
+    }
+
+    fn hand_off(&mut self, endpoint: EndpointHandle, pid: ProcessId, name: &str) -> Result<(), &'static str> {
+        let rights = crate::ipc::IpcRights { send: true, recv: true, ..crate::ipc::IpcRights::NONE };
+        crate::ipc::delegate_handle(
+            crate::process::get_current_process_id() as u32, pid as u32, endpoint, rights, None, Some(name.to_string()),
+        ).map(|_| ()).map_err(|_| "endpoint not delegable")
+    }
+}
+
+/// Where a service is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceState {
+    /// Waiting for its dependencies to come up
+    Pending,
+    /// Socket-activated and not running: init holds its endpoint until a message arrives
+    Listening,
+    Running(ProcessId),
+    /// Died and starts again at `at_ms`
+    Restarting { at_ms: u64 },
+    /// Sent SIGTERM, and SIGKILL too once `killed`
+    Stopping { pid: ProcessId, deadline_ms: u64, killed: bool },
+    /// Exited with this code and is not restarted
+    Exited(i32),
+    /// Could not be started, a dependency failed, or it died past its restart limit
+    Failed,
+    /// Stopped on request or by shutdown
+    Stopped,
+}
+
+impl ServiceState {
+    fn pid(&self) -> Option<ProcessId> {
+        match *self {
+            Self::Running(pid) | Self::Stopping { pid, .. } => Some(pid),
+            _ => None,
+        }
+    }
+
+    /// Whether dependents may start: running, reachable through its endpoint, or a one-shot
+    /// that finished cleanly
+    fn is_up(&self) -> bool {
+        matches!(self, Self::Running(_) | Self::Listening | Self::Exited(0))
+    }
+}
+
+/// A service as reported by `status`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceStatus {
+    pub name: String,
+    pub state: ServiceState,
+    pub restarts: u32,
+    pub last_exit: Option<i32>,
+}
+
+struct Service {
+    spec: ServiceSpec,
+    state: ServiceState,
+    restarts: u32,
+    last_exit: Option<i32>,
+    /// Bound on first start for a socket-activated service, and kept
+    endpoint: Option<EndpointHandle>,
+    /// Stopped on request rather than by shutdown; not restarted when it exits
+    stop_requested: bool,
+}
+
+/// Whether init is supervising, stopping everything, or done
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Running,
+    ShuttingDown,
+    Halted,
+}
+
+/// The service supervisor
+pub struct Init<P: ProcessControl> {
+    processes: P,
+    /// In start order
+    services: Vec<Service>,
+    phase: Phase,
+    orphans_reaped: u64,
+}
+
+impl<P: ProcessControl> Init<P> {
+    /// Supervise `specs`, checking their dependencies; nothing starts before the first `tick`
+    pub fn new(processes: P, specs: Vec<ServiceSpec>) -> Result<Self, InitError> {
+        let services = start_order(specs)?
+            .into_iter()
+            .map(|spec| Service {
+                spec,
+                state: ServiceState::Pending,
+                restarts: 0,
+                last_exit: None,
+                endpoint: None,
+                stop_requested: false,
+            })
+            .collect();
+        Ok(Self { processes, services, phase: Phase::Running, orphans_reaped: 0 })
+    }
+
+    /// One supervision pass: reap exited children, then start, restart or stop services as due
+    pub fn tick(&mut self) {
+        while let Some((pid, exit_code)) = self.processes.try_wait() {
+            self.reaped(pid, exit_code);
+        }
+        self.kill_overdue();
+        match self.phase {
+            Phase::Running => self.start_due(),
+            Phase::ShuttingDown => self.stop_due(),
+            Phase::Halted => {}
+        }
+    }
+
+    /// React to a signal sent to init: SIGTERM begins the shutdown sequence
+    pub fn handle_signal(&mut self, signal: Signal) {
+        if signal == Signal::SIGTERM && self.phase == Phase::Running {
+            crate::serial::_print(format_args!("[rae-init] Shutting down\n"));
+            self.phase = Phase::ShuttingDown;
+            for service in &mut self.services {
+                if matches!(service.state, ServiceState::Pending | ServiceState::Listening | ServiceState::Restarting { .. }) {
+                    service.state = ServiceState::Stopped;
+                }
+            }
+            self.stop_due();
+        }
+    }
+
+    /// Stop service `name` with SIGTERM, and SIGKILL past its stop timeout. It is not
+    /// restarted; a socket-activated service goes back to waiting for a message
+    pub fn stop_service(&mut self, name: &str) -> Result<(), InitError> {
+        let index = self.index_of(name)?;
+        match self.services[index].state {
+            ServiceState::Running(pid) => {
+                self.services[index].stop_requested = true;
+                self.terminate(index, pid);
+            }
+            ServiceState::Stopping { .. } => self.services[index].stop_requested = true,
+            ServiceState::Pending | ServiceState::Listening | ServiceState::Restarting { .. } => {
+                self.services[index].state = ServiceState::Stopped;
+            }
+            ServiceState::Exited(_) | ServiceState::Failed | ServiceState::Stopped => {}
+        }
+        Ok(())
+    }
+
+    /// Start a stopped, exited or failed service again with a fresh restart count
+    pub fn start_service(&mut self, name: &str) -> Result<(), InitError> {
+        let index = self.index_of(name)?;
+        let service = &mut self.services[index];
+        if self.phase == Phase::Running
+            && matches!(service.state, ServiceState::Stopped | ServiceState::Exited(_) | ServiceState::Failed)
+        {
+            service.state = ServiceState::Pending;
+            service.restarts = 0;
+            self.start_due();
+        }
+        Ok(())
+    }
+
+    fn index_of(&self, name: &str) -> Result<usize, InitError> {
+        self.services.iter().position(|service| service.spec.name == name)
+            .ok_or_else(|| InitError::UnknownService(name.to_string()))
+    }
+
+    /// Ask service `index`, running as `pid`, to exit by its stop timeout
+    fn terminate(&mut self, index: usize, pid: ProcessId) {
+        self.signal(index, pid, Signal::SIGTERM);
+        let deadline_ms = self.processes.now_ms() + self.services[index].spec.stop_timeout_ms;
+        self.services[index].state = ServiceState::Stopping { pid, deadline_ms, killed: false };
+    }
+
+    /// Send SIGKILL to services still alive past their stop timeout
+    fn kill_overdue(&mut self) {
+        let now = self.processes.now_ms();
+        for index in 0..self.services.len() {
+            if let ServiceState::Stopping { pid, deadline_ms, killed: false } = self.services[index].state {
+                if now >= deadline_ms {
+                    self.signal(index, pid, Signal::SIGKILL);
+                    self.services[index].state = ServiceState::Stopping { pid, deadline_ms, killed: true };
+                }
+            }
+        }
+    }
+
+    fn reaped(&mut self, pid: ProcessId, exit_code: i32) {
+        let now = self.processes.now_ms();
+        let shutting_down = self.phase != Phase::Running;
+        let Some(service) = self.services.iter_mut().find(|service| service.state.pid() == Some(pid)) else {
+            // A process orphaned by its parent and handed to init
+            self.orphans_reaped += 1;
+            return;
+        };
+        service.last_exit = Some(exit_code);
+        if shutting_down {
+            service.state = ServiceState::Stopped;
+            return;
+        }
+        let activated = service.spec.socket.is_some();
+        if core::mem::take(&mut service.stop_requested) {
+            service.state = if activated { ServiceState::Listening } else { ServiceState::Stopped };
+            return;
+        }
+        // An activated service exiting cleanly is idle, not finished
+        if activated && exit_code == 0 {
+            service.state = ServiceState::Listening;
+            return;
+        }
+
+        let restart = match service.spec.restart {
+            RestartPolicy::Always => true,
+            RestartPolicy::OnFailure => exit_code != 0,
+            RestartPolicy::Never => false,
+        };
+        service.state = if !restart {
+            ServiceState::Exited(exit_code)
+        } else if service.restarts < service.spec.max_restarts {
+            service.restarts += 1;
+            ServiceState::Restarting { at_ms: now + service.spec.restart_delay_ms }
+        } else {
+            crate::serial::_print(format_args!(
+                "[rae-init] {} exited with {} after {} restarts; giving up\n",
+                service.spec.name, exit_code, service.restarts));
+            ServiceState::Failed
+        };
+    }
+
+    /// Start pending services whose dependencies are up, and restarts that are due
+    fn start_due(&mut self) {
+        let now = self.processes.now_ms();
+        for index in 0..self.services.len() {
+            let ready = match self.services[index].state {
+                ServiceState::Pending => {
+                    let after = &self.services[index].spec.after;
+                    let dependencies: Vec<ServiceState> = self.services.iter()
+                        .filter(|service| after.contains(&service.spec.name))
+                        .map(|service| service.state)
+                        .collect();
+                    if dependencies.contains(&ServiceState::Failed) {
+                        self.services[index].state = ServiceState::Failed;
+                        false
+                    } else {
+                        dependencies.iter().all(ServiceState::is_up)
+                    }
+                }
+                ServiceState::Restarting { at_ms } => now >= at_ms,
+                ServiceState::Listening => {
+                    self.services[index].endpoint.is_some_and(|endpoint| self.processes.pending(endpoint))
+                }
+                _ => false,
+            };
+            if ready {
+                self.services[index].state = self.launch(index);
+            }
+        }
+    }
+
+    /// Start service `index`: a socket-activated one first binds its endpoint and waits
+    /// for a message, then gets the endpoint handed to it once running
+    fn launch(&mut self, index: usize) -> ServiceState {
+        let service = &mut self.services[index];
+        let endpoint = match (&service.spec.socket, service.endpoint) {
+            (None, _) => None,
+            (Some(name), None) => match self.processes.bind(name) {
+                Ok(endpoint) => {
+                    service.endpoint = Some(endpoint);
+                    return ServiceState::Listening;
+                }
+                Err(error) => {
+                    crate::serial::_print(format_args!("[rae-init] Cannot bind {} for {}: {}\n", name, service.spec.name, error));
+                    return ServiceState::Failed;
+                }
+            },
+            (Some(_), Some(endpoint)) if !matches!(service.state, ServiceState::Listening) => {
+                // Restarted or started again: wait for the next message unless one is queued
+                if !self.processes.pending(endpoint) {
+                    return ServiceState::Listening;
+                }
+                Some(endpoint)
+            }
+            (Some(_), Some(endpoint)) => Some(endpoint),
+        };
+
+        let pid = match self.processes.spawn(&service.spec.path) {
+            Ok(pid) => pid,
+            Err(error) => {
+                crate::serial::_print(format_args!(
+                    "[rae-init] Cannot start {} ({}): {:?}\n", service.spec.name, service.spec.path, error));
+                return ServiceState::Failed;
+            }
+        };
+        if let (Some(endpoint), Some(name)) = (endpoint, &service.spec.socket) {
+            if let Err(error) = self.processes.hand_off(endpoint, pid, name) {
+                crate::serial::_print(format_args!("[rae-init] Cannot hand {} to {}: {}\n", name, service.spec.name, error));
+                // Its exit is reaped as an orphan's
+                let _ = self.processes.signal(pid, Signal::SIGKILL);
+                return ServiceState::Failed;
+            }
+        }
+        ServiceState::Running(pid)
+    }
+
+    /// Send SIGTERM to running services whose dependents are all down, last started first;
+    /// halt once none is left
+    fn stop_due(&mut self) {
+        for index in (0..self.services.len()).rev() {
+            if let ServiceState::Running(pid) = self.services[index].state {
+                let name = &self.services[index].spec.name;
+                let dependents_down = self.services.iter()
+                    .filter(|service| service.spec.after.contains(name))
+                    .all(|service| service.state.pid().is_none());
+                if dependents_down {
+                    self.terminate(index, pid);
+                }
+            }
+        }
+        if self.services.iter().all(|service| service.state.pid().is_none()) {
+            crate::serial::_print(format_args!("[rae-init] All services stopped\n"));
+            self.phase = Phase::Halted;
+        }
+    }
+
+    fn signal(&mut self, index: usize, pid: ProcessId, signal: Signal) {
+        if let Err(error) = self.processes.signal(pid, signal) {
+            crate::serial::_print(format_args!(
+                "[rae-init] Cannot send {:?} to {}: {}\n", signal, self.services[index].spec.name, error));
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Every service in start order
+    pub fn status(&self) -> Vec<ServiceStatus> {
+        self.services.iter().map(|service| ServiceStatus {
+            name: service.spec.name.clone(),
+            state: service.state,
+            restarts: service.restarts,
+            last_exit: service.last_exit,
+        }).collect()
+    }
+
+    /// Adopted processes reaped so far
+    pub fn orphans_reaped(&self) -> u64 {
+        self.orphans_reaped
+    }
+
+    pub fn processes(&self) -> &P {
+        &self.processes
+    }
+
+    pub fn processes_mut(&mut self) -> &mut P {
+        &mut self.processes
+    }
+}
+
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+fn request_shutdown(_signal: Signal) {
+    SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Run as init: supervise the services in `INIT_CONF` until SIGTERM has stopped them all.
+/// Without a service list init still reaps the orphans it adopts.
+pub fn main() -> Result<(), InitError> {
+    crate::process::register_init().map_err(InitError::Process)?;
+    let specs = match crate::filesystem::read_file(INIT_CONF) {
+        Ok(data) => {
+            let config = String::from_utf8(data)
+                .map_err(|_| InitError::Parse { line: 1, message: "not UTF-8".to_string() })?;
+            parse_config(&config)?
+        }
+        Err(()) => {
+            crate::serial::_print(format_args!("[rae-init] No {}; supervising no services\n", INIT_CONF));
+            Vec::new()
+        }
+    };
+    let mut init = Init::new(KernelProcesses, specs)?;
+    let _ = crate::process::set_signal_handler(Signal::SIGTERM, Some(request_shutdown));
+
+    while init.phase() != Phase::Halted {
+        crate::process::process_signals();
+        if SHUTDOWN_REQUESTED.swap(false, Ordering::SeqCst) {
+            init.handle_signal(Signal::SIGTERM);
+        }
+        init.tick();
+        crate::process::sleep_current(POLL_INTERVAL_MS);
+    }
+    Ok(())
+}
@@ -1,28 +1,50 @@
 //! Service Manager for RaeenOS microkernel architecture
 //! Manages user-space services and IPC routing
+//!
+//! Services are crash-only: one whose health check reports it unhealthy, or whose process
+//! faults, is stopped and micro-rebooted, its process started again with a fresh IPC
+//! endpoint. Restarts back off exponentially from the service's `restart_delay_ms`, and a
+//! service that keeps failing is given up on after `max_restarts` of them. A service that
+//! stays up for `STABLE_UPTIME_MS` has its restart count reset.
+//!
+//! The service manager is the default restart handler of the kernel's watchdogs: a
+//! watchdog firing for a subsystem a service runs fails that service, as if it had faulted.
 
+use alloc::boxed::Box;
 use alloc::vec::Vec;
 use alloc::string::String;
 use alloc::collections::BTreeMap;
 use core::sync::atomic::{AtomicU32, Ordering};
 use spin::{Mutex, RwLock};
 use crate::ipc::{IpcObject, CapabilityEndpoint, IpcRights};
-use crate::process::ProcessId;
+use crate::observability::{ObservabilityEvent, ServiceOperation, ServiceResult, Subsystem};
+use crate::observability::watchdog::{self, WatchdogError};
+use crate::process::{ProcessId, Signal};
 use super::contracts::*;
+use super::init::{KernelProcesses, ProcessControl};
 
 pub mod service_registry;
 pub mod ipc_router;
 pub mod health_monitor;
 pub mod resource_manager;
+pub mod restart_test;
 
 use service_registry::ServiceRegistry;
 use ipc_router::IpcRouter;
-use health_monitor::HealthMonitor;
+use health_monitor::{HealthMonitor, HealthProbe};
 use resource_manager::ResourceManager;
 
+/// A service up this long since it last started has its restart count reset
+pub const STABLE_UPTIME_MS: u64 = 60_000;
+/// Longest a restart is put off, however many came before it
+pub const MAX_RESTART_DELAY_MS: u64 = 60_000;
+
 /// Global service manager instance
 static SERVICE_MANAGER: Mutex<Option<ServiceManager>> = Mutex::new(None);
 static NEXT_SERVICE_ID: AtomicU32 = AtomicU32::new(1);
+/// Services whose subsystems' watchdogs fired, failed by the next `supervise`. Watchdogs
+/// fire with the observability system locked, so they only queue the service here
+static WATCHDOG_RESTARTS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
 
 /// Service manager for coordinating user-space services
 pub struct ServiceManager {
@@ -32,6 +54,8 @@ pub struct ServiceManager {
     resource_manager: ResourceManager,
     services: RwLock<BTreeMap<u32, ServiceInstance>>,
     endpoints: RwLock<BTreeMap<String, CapabilityEndpoint>>,
+    /// Stops and starts service processes, and keeps the time restarts are due by
+    processes: Box<dyn ProcessControl + Send>,
 }
 
 /// Service instance information
@@ -43,9 +67,14 @@ pub struct ServiceInstance {
     pub endpoint: CapabilityEndpoint,
     pub status: ServiceStatus,
     pub start_time: u64,
+    /// Restarts since the service was last up for `STABLE_UPTIME_MS`
     pub restart_count: u32,
     pub last_health_check: u64,
     pub resource_usage: ResourceUsage,
+    /// When the process last started, in milliseconds of uptime
+    pub started_ms: u64,
+    /// When a service waiting to be restarted is due to be
+    pub restart_at_ms: Option<u64>,
 }
 
 /// Service status
@@ -74,7 +103,10 @@ pub struct ResourceUsage {
 pub struct ServiceConfig {
     pub auto_restart: bool,
     pub max_restarts: u32,
+    /// Delay before the first restart, doubling with each restart after it
     pub restart_delay_ms: u32,
+    /// Program the service runs, started again when it is restarted
+    pub executable: Option<String>,
     pub health_check_interval_ms: u32,
     pub resource_limits: ResourceLimits,
     pub dependencies: Vec<String>,
@@ -99,16 +131,23 @@ impl ServiceManager {
             return Err("Service manager already initialized");
         }
         
-        *manager = Some(ServiceManager {
+        *manager = Some(ServiceManager::with_processes(Box::new(KernelProcesses)));
+        watchdog::set_default_restart_handler(restart_subsystem);
+        
+        Ok(())
+    }
+    
+    /// A service manager starting and stopping service processes through `processes`
+    pub fn with_processes(processes: Box<dyn ProcessControl + Send>) -> Self {
+        ServiceManager {
             registry: ServiceRegistry::new(),
             router: IpcRouter::new(),
             health_monitor: HealthMonitor::new(),
             resource_manager: ResourceManager::new(),
             services: RwLock::new(BTreeMap::new()),
             endpoints: RwLock::new(BTreeMap::new()),
-        });
-        
-        Ok(())
+            processes,
+        }
     }
     
     /// Get the global service manager instance
@@ -142,7 +181,7 @@ impl ServiceManager {
         // Create service instance
         let instance = ServiceInstance {
             id: service_id,
-            info: info.clone(),
+            info: ServiceInfo { process_id: process_id as u32, ipc_handle: endpoint_handle, ..info.clone() },
             process_id,
             endpoint: endpoint.clone(),
             status: ServiceStatus::Starting,
@@ -150,6 +189,8 @@ impl ServiceManager {
             restart_count: 0,
             last_health_check: 0,
             resource_usage: ResourceUsage::default(),
+            started_ms: self.processes.now_ms(),
+            restart_at_ms: None,
         };
         
         // Store service instance
@@ -224,23 +265,137 @@ impl ServiceManager {
         Ok(())
     }
     
-    /// Restart a service
+    /// Restart a service now: stop its process, start its program again and give the new
+    /// process a fresh endpoint under the service's name
     pub fn restart_service(&mut self, service_id: u32) -> Result<(), ServiceError> {
+        let executable = self.registry.find_by_id(service_id)
+            .and_then(|registered| registered.config.executable)
+            .ok_or(ServiceError::InvalidState)?;
+        let info = {
+            let mut services = self.services.write();
+            let service = services.get_mut(&service_id)
+                .ok_or(ServiceError::ServiceNotFound)?;
+            service.status = ServiceStatus::Starting;
+            service.restart_at_ms = None;
+            service.info.clone()
+        };
+        self.stop_process(&info);
+        
+        let process_id = self.processes.spawn(&executable)
+            .map_err(|_| ServiceError::InternalError)?;
+        let endpoint_handle = crate::ipc::create_capability_endpoint(
+            info.name.clone(),
+            process_id as u32,
+            info.capabilities.clone(),
+        ).map_err(|_| ServiceError::EndpointCreationFailed)?;
+        let endpoint = crate::ipc::get_capability_endpoint(process_id as u32, endpoint_handle)
+            .map_err(|_| ServiceError::EndpointCreationFailed)?;
+        self.endpoints.write().insert(info.name.clone(), endpoint.clone());
+        
         let mut services = self.services.write();
         let service = services.get_mut(&service_id)
             .ok_or(ServiceError::ServiceNotFound)?;
-        
-        service.status = ServiceStatus::Restarting;
-        service.restart_count += 1;
-        
-        // TODO: Implement graceful restart
-        
+        service.process_id = process_id;
+        service.info.process_id = process_id as u32;
+        service.info.ipc_handle = endpoint_handle;
+        service.endpoint = endpoint;
         service.status = ServiceStatus::Running;
         service.start_time = crate::time::get_timestamp();
+        service.started_ms = self.processes.now_ms();
         
         Ok(())
     }
     
+    /// Kill a service's process and withdraw its endpoint. The process may be gone already
+    fn stop_process(&mut self, info: &ServiceInfo) {
+        let _ = self.processes.signal(info.process_id as ProcessId, Signal::SIGKILL);
+        let _ = crate::ipc::revoke_handle(info.process_id, info.ipc_handle);
+        self.endpoints.write().remove(&info.name);
+    }
+    
+    /// Take a failed service down and schedule its restart, after a delay doubling with each
+    /// restart in a row, or give up on it once it has had `max_restarts`
+    fn service_failed(&mut self, service_id: u32, reason: &str) {
+        let config = self.registry.find_by_id(service_id).map(|registered| registered.config);
+        let now_ms = self.processes.now_ms();
+        let info = {
+            let mut services = self.services.write();
+            let Some(service) = services.get_mut(&service_id) else {
+                return;
+            };
+            // Stopped on purpose, already waiting to restart, or given up on
+            if !matches!(service.status, ServiceStatus::Starting | ServiceStatus::Running) {
+                return;
+            }
+            crate::serial::_print(format_args!("[ServiceManager] {} {}\n", service.info.name, reason));
+            match config.filter(|config| config.auto_restart) {
+                Some(config) if service.restart_count < config.max_restarts => {
+                    service.restart_count += 1;
+                    service.status = ServiceStatus::Restarting;
+                    service.restart_at_ms = Some(now_ms + restart_delay(config.restart_delay_ms, service.restart_count));
+                }
+                _ => {
+                    crate::serial::_print(format_args!(
+                        "[ServiceManager] {} failed after {} restarts; giving up\n",
+                        service.info.name, service.restart_count));
+                    service.status = ServiceStatus::Failed;
+                    record_restart(service_id, ServiceResult::Failure);
+                }
+            }
+            service.info.clone()
+        };
+        self.stop_process(&info);
+    }
+    
+    /// React to the process of a service faulting: it is restarted like an unhealthy
+    /// service. Returns whether the process was a service's
+    pub fn process_faulted(&mut self, process_id: ProcessId) -> bool {
+        let service_id = self.services.read().values()
+            .find(|service| service.process_id == process_id)
+            .map(|service| service.id);
+        if let Some(service_id) = service_id {
+            self.service_failed(service_id, "faulted");
+        }
+        service_id.is_some()
+    }
+    
+    /// Restart the services whose delay has run out, and reset the restart count of those
+    /// up for `STABLE_UPTIME_MS`. Called periodically
+    pub fn supervise(&mut self) {
+        let hung = core::mem::take(&mut *WATCHDOG_RESTARTS.lock());
+        for name in hung {
+            if let Some(service) = self.find_service_by_name(name) {
+                self.service_failed(service.id, "missed its watchdog deadline");
+            }
+        }
+        
+        let now_ms = self.processes.now_ms();
+        let mut due = Vec::new();
+        for service in self.services.write().values_mut() {
+            match service.status {
+                ServiceStatus::Running if now_ms.saturating_sub(service.started_ms) >= STABLE_UPTIME_MS => {
+                    service.restart_count = 0;
+                }
+                ServiceStatus::Restarting if service.restart_at_ms.is_some_and(|at_ms| now_ms >= at_ms) => {
+                    due.push(service.id);
+                }
+                _ => {}
+            }
+        }
+        for service_id in due {
+            match self.restart_service(service_id) {
+                Ok(()) => record_restart(service_id, ServiceResult::Success),
+                // A restart that fails counts as another crash
+                Err(_) => self.service_failed(service_id, "did not restart"),
+            }
+        }
+    }
+    
+    /// Have the health checks of a service ask the service itself
+    pub fn set_health_probe(&self, service_id: u32, probe: HealthProbe) -> Result<(), ServiceError> {
+        self.health_monitor.set_probe(service_id, probe)
+    }
+    
     /// Get service information
     pub fn get_service_info(&self, service_id: u32) -> Result<ServiceInstance, ServiceError> {
         let services = self.services.read();
@@ -288,28 +443,15 @@ impl ServiceManager {
         for service_id in service_ids {
             match self.health_monitor.check_health(service_id) {
                 Ok(status) => {
-                    results.push(status);
+                    if let Some(service) = self.services.write().get_mut(&service_id) {
+                        service.last_health_check = crate::time::get_timestamp();
+                    }
                     
-                    // Update service status based on health check
-                    if let Ok(mut services) = self.services.try_write() {
-                        if let Some(service) = services.get_mut(&service_id) {
-                            service.last_health_check = crate::time::get_timestamp();
-                            
-                            match status {
-                                HealthStatus::Healthy => {
-                                    if service.status == ServiceStatus::Failed {
-                                        service.status = ServiceStatus::Running;
-                                    }
-                                }
-                                HealthStatus::Unhealthy => {
-                                    service.status = ServiceStatus::Failed;
-                                }
-                                HealthStatus::Unknown => {
-                                    // Keep current status
-                                }
-                            }
-                        }
+                    // An unhealthy service is restarted rather than nursed
+                    if matches!(status, HealthStatus::Unhealthy { .. }) {
+                        self.service_failed(service_id, "is unhealthy");
                     }
+                    results.push(status);
                 }
                 Err(_) => {
                     results.push(HealthStatus::Unknown);
@@ -369,10 +511,47 @@ impl ServiceManager {
             total_cpu_percent: total_cpu,
             total_ipc_messages,
             uptime_seconds: crate::time::get_uptime_seconds(),
+            load_average: crate::process::load_average(),
         }
     }
 }
 
+/// How long to wait before the `attempt`th restart in a row: `base_ms`, doubled for each
+/// restart before it, up to `MAX_RESTART_DELAY_MS`
+pub fn restart_delay(base_ms: u32, attempt: u32) -> u64 {
+    let doublings = attempt.saturating_sub(1).min(32);
+    (base_ms as u64).saturating_mul(1 << doublings).min(MAX_RESTART_DELAY_MS)
+}
+
+/// The service doing a subsystem's work, if a service does it
+fn subsystem_service(subsystem: Subsystem) -> Option<&'static str> {
+    match subsystem {
+        Subsystem::Network => Some("rae-networkd"),
+        Subsystem::Graphics | Subsystem::Compositor => Some("rae-compositord"),
+        Subsystem::Ai => Some("rae-assistantd"),
+        _ => None,
+    }
+}
+
+/// Default watchdog restart handler: queue the restart of the service running `subsystem`
+/// for the next `supervise`
+pub fn restart_subsystem(subsystem: Subsystem) -> Result<(), WatchdogError> {
+    let name = subsystem_service(subsystem).ok_or(WatchdogError::SubsystemNotFound)?;
+    let mut pending = WATCHDOG_RESTARTS.lock();
+    if !pending.contains(&name) {
+        pending.push(name);
+    }
+    Ok(())
+}
+
+fn record_restart(service_id: u32, result: ServiceResult) {
+    crate::observability::record_event(ObservabilityEvent::Service {
+        service_id,
+        operation: ServiceOperation::Restart,
+        result,
+    });
+}
+
 /// Service statistics
 #[derive(Debug, Clone)]
 pub struct ServiceStatistics {
@@ -383,6 +562,8 @@ pub struct ServiceStatistics {
     pub total_cpu_percent: f32,
     pub total_ipc_messages: u64,
     pub uptime_seconds: u64,
+    /// System load averaged over 1, 5 and 15 minutes
+    pub load_average: (f32, f32, f32),
 }
 
 /// Service manager errors
@@ -430,6 +611,16 @@ pub fn route_ipc_message(
     manager.route_message(target_service, message, sender_process)
 }
 
+/// Restart the service whose process `process_id` faulted, if it is one
+pub fn report_process_fault(process_id: ProcessId) -> Result<bool, ServiceError> {
+    let manager_lock = ServiceManager::instance()
+        .map_err(|_| ServiceError::InternalError)?;
+    let mut manager_opt = manager_lock.lock();
+    let manager = manager_opt.as_mut()
+        .ok_or(ServiceError::InternalError)?;
+    Ok(manager.process_faulted(process_id))
+}
+
 pub fn get_service_statistics() -> Result<ServiceStatistics, ServiceError> {
     let manager_lock = ServiceManager::instance()
         .map_err(|_| ServiceError::InternalError)?;
@@ -0,0 +1,260 @@
+//! Service Restart Tests
+//! Supervises services on fake processes and a manual clock: a service whose health check
+//! reports it unhealthy is stopped and started again with a new endpoint after its delay,
+//! restarts in a row back off exponentially until a crash loop is given up on, and a
+//! service that stays up long enough has its restart count reset. A watchdog firing for a
+//! subsystem without its own restart handler restarts the service running it
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use super::super::contracts::{HealthStatus, ServiceInfo};
+use super::super::init::{EndpointHandle, ProcessControl};
+use super::{restart_delay, restart_subsystem, ResourceLimits, ServiceConfig, ServiceManager, ServiceStatus, MAX_RESTART_DELAY_MS, STABLE_UPTIME_MS};
+use crate::observability::{ObservabilityError, ObservabilityEvent, ServiceOperation, ServiceResult, Subsystem};
+use crate::observability::watchdog::WatchdogError;
+use crate::process::{ProcessError, ProcessId, Signal};
+use crate::serial::_print;
+
+const RESTART_DELAY_MS: u32 = 100;
+const MAX_RESTARTS: u32 = 3;
+
+/// What the fake processes have been asked to do, shared with the test
+#[derive(Default)]
+struct FakeState {
+    now_ms: u64,
+    next_pid: ProcessId,
+    spawned: Vec<String>,
+    killed: Vec<ProcessId>,
+}
+
+/// Processes that start and die on request
+struct FakeProcesses(Arc<Mutex<FakeState>>);
+
+impl ProcessControl for FakeProcesses {
+    fn spawn(&mut self, path: &str) -> Result<ProcessId, ProcessError> {
+        let mut state = self.0.lock();
+        state.next_pid += 1;
+        state.spawned.push(path.to_string());
+        Ok(state.next_pid)
+    }
+
+    fn signal(&mut self, pid: ProcessId, signal: Signal) -> Result<(), &'static str> {
+        if signal == Signal::SIGKILL {
+            self.0.lock().killed.push(pid);
+        }
+        Ok(())
+    }
+
+    fn try_wait(&mut self) -> Option<(ProcessId, i32)> {
+        None
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.0.lock().now_ms
+    }
+
+    fn bind(&mut self, _name: &str) -> Result<EndpointHandle, &'static str> {
+        Err("no endpoints")
+    }
+
+    fn pending(&self, _endpoint: EndpointHandle) -> bool {
+        false
+    }
+
+    fn hand_off(&mut self, _endpoint: EndpointHandle, _pid: ProcessId, _name: &str) -> Result<(), &'static str> {
+        Err("no endpoints")
+    }
+}
+
+/// Whether the probed services report themselves unhealthy
+static UNHEALTHY: AtomicBool = AtomicBool::new(false);
+
+fn probe(_service_id: u32) -> HealthStatus {
+    if UNHEALTHY.load(Ordering::SeqCst) {
+        HealthStatus::Unhealthy { reason: "stuck".to_string() }
+    } else {
+        HealthStatus::Healthy
+    }
+}
+
+fn config(executable: &str) -> ServiceConfig {
+    ServiceConfig {
+        auto_restart: true,
+        max_restarts: MAX_RESTARTS,
+        restart_delay_ms: RESTART_DELAY_MS,
+        executable: Some(executable.to_string()),
+        health_check_interval_ms: 1000,
+        resource_limits: ResourceLimits {
+            max_memory_mb: None,
+            max_cpu_percent: None,
+            max_disk_io_mb_per_sec: None,
+            max_network_io_mb_per_sec: None,
+            max_ipc_messages_per_sec: None,
+        },
+        dependencies: Vec::new(),
+        environment: BTreeMap::new(),
+    }
+}
+
+fn register(manager: &mut ServiceManager, name: &str, process_id: ProcessId) -> Result<u32, &'static str> {
+    let info = ServiceInfo { name: name.to_string(), version: 1, capabilities: vec!["test".to_string()], process_id: process_id as u32, ipc_handle: 0 };
+    let executable = alloc::format!("/bin/{}", name);
+    let service_id = manager.register_service(info, process_id, config(&executable)).map_err(|_| "Service not registered")?;
+    manager.set_health_probe(service_id, probe).map_err(|_| "Probe not set")?;
+    Ok(service_id)
+}
+
+/// Successful restarts of `service_id` recorded
+fn restarts_recorded(service_id: u32) -> usize {
+    crate::observability::snapshot()
+        .iter()
+        .filter(|entry| {
+            matches!(entry.event, ObservabilityEvent::Service { service_id: id, operation: ServiceOperation::Restart, result: ServiceResult::Success } if id == service_id)
+        })
+        .count()
+}
+
+pub fn run_restart_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Restart Test] Starting service restart tests...\n"));
+    match crate::observability::init_observability() {
+        Ok(()) | Err(ObservabilityError::AlreadyInitialized) => {}
+        Err(_) => return Err("Failed to initialize observability"),
+    }
+    let state = Arc::new(Mutex::new(FakeState { next_pid: 1000, ..FakeState::default() }));
+    let mut manager = ServiceManager::with_processes(Box::new(FakeProcesses(state.clone())));
+
+    // Test 1: An unhealthy service is stopped, then started again after its delay
+    _print(format_args!("[Restart Test] Test 1: Restart of an unhealthy service...\n"));
+    let flaky = register(&mut manager, "flaky", 900)?;
+    UNHEALTHY.store(true, Ordering::SeqCst);
+    manager.health_check().map_err(|_| "Health check failed")?;
+    let service = manager.get_service_info(flaky).map_err(|_| "Service lost")?;
+    if service.status != ServiceStatus::Restarting || state.lock().killed != [900] {
+        return Err("Unhealthy service not stopped for a restart");
+    }
+    UNHEALTHY.store(false, Ordering::SeqCst);
+    state.lock().now_ms = u64::from(RESTART_DELAY_MS) - 1;
+    manager.supervise();
+    if !state.lock().spawned.is_empty() {
+        return Err("Service restarted before its delay");
+    }
+    state.lock().now_ms = u64::from(RESTART_DELAY_MS);
+    manager.supervise();
+    let service = manager.get_service_info(flaky).map_err(|_| "Service lost")?;
+    if state.lock().spawned != ["/bin/flaky"] || service.status != ServiceStatus::Running || service.restart_count != 1 {
+        return Err("Service not restarted after its delay");
+    }
+    let endpoint_moved = service.process_id == 1001 && service.info.process_id == 1001;
+    if !endpoint_moved || manager.find_service_by_name("flaky").map(|found| found.id) != Some(flaky) {
+        return Err("Restarted service lacks a fresh endpoint");
+    }
+    if restarts_recorded(flaky) != 1 {
+        return Err("Restart not recorded as an observability event");
+    }
+    _print(format_args!("[Restart Test] ✓ Unhealthy service restarted as pid {} after {} ms\n", service.process_id, RESTART_DELAY_MS));
+
+    // Test 2: Restarts in a row back off, and a crash loop is given up on
+    _print(format_args!("[Restart Test] Test 2: Backoff and crash loop...\n"));
+    if (1..=5).map(|attempt| restart_delay(RESTART_DELAY_MS, attempt)).collect::<Vec<u64>>() != [100, 200, 400, 800, 1600] {
+        return Err("Restart delay does not double");
+    }
+    UNHEALTHY.store(true, Ordering::SeqCst);
+    for attempt in 2..=MAX_RESTARTS {
+        manager.health_check().map_err(|_| "Health check failed")?;
+        let due_ms = state.lock().now_ms + restart_delay(RESTART_DELAY_MS, attempt);
+        state.lock().now_ms = due_ms - 1;
+        manager.supervise();
+        if state.lock().spawned.len() as u32 != attempt - 1 {
+            return Err("Backed-off restart came early");
+        }
+        state.lock().now_ms = due_ms;
+        manager.supervise();
+        if state.lock().spawned.len() as u32 != attempt {
+            return Err("Backed-off restart did not come when due");
+        }
+    }
+    manager.health_check().map_err(|_| "Health check failed")?;
+    state.lock().now_ms += MAX_RESTART_DELAY_MS;
+    manager.supervise();
+    let service = manager.get_service_info(flaky).map_err(|_| "Service lost")?;
+    if service.status != ServiceStatus::Failed || state.lock().spawned.len() as u32 != MAX_RESTARTS {
+        return Err("Crash loop not given up on at its restart limit");
+    }
+    manager.process_faulted(service.process_id);
+    state.lock().now_ms += MAX_RESTART_DELAY_MS;
+    manager.supervise();
+    let still_failed = manager.get_service_info(flaky).map(|service| service.status) == Ok(ServiceStatus::Failed);
+    if !still_failed || state.lock().spawned.len() as u32 != MAX_RESTARTS {
+        return Err("Given-up service restarted again");
+    }
+    UNHEALTHY.store(false, Ordering::SeqCst);
+    _print(format_args!("[Restart Test] ✓ {} restarts backed off, then the service was marked failed\n", MAX_RESTARTS));
+
+    // Test 3: A faulting process restarts its service, and a stable service is forgiven
+    _print(format_args!("[Restart Test] Test 3: Fault restart and stable uptime...\n"));
+    let steady = register(&mut manager, "steady", 901)?;
+    if !manager.process_faulted(901) {
+        return Err("Fault of a service process not handled");
+    }
+    state.lock().now_ms += u64::from(RESTART_DELAY_MS);
+    manager.supervise();
+    let restarted = manager.get_service_info(steady).map_err(|_| "Service lost")?;
+    if restarted.status != ServiceStatus::Running || restarted.restart_count != 1 {
+        return Err("Faulted service not restarted");
+    }
+    state.lock().now_ms += STABLE_UPTIME_MS - 1;
+    manager.supervise();
+    if manager.get_service_info(steady).map(|service| service.restart_count) != Ok(1) {
+        return Err("Restart count reset before the service was stable");
+    }
+    state.lock().now_ms += 1;
+    manager.supervise();
+    if manager.get_service_info(steady).map(|service| service.restart_count) != Ok(0) {
+        return Err("Restart count not reset after stable uptime");
+    }
+    _print(format_args!("[Restart Test] ✓ Faulted service restarted, forgiven after {} ms up\n", STABLE_UPTIME_MS));
+
+    // Test 4: A watchdog restart routed to the service manager restarts the subsystem's service
+    _print(format_args!("[Restart Test] Test 4: Watchdog restart of a service...\n"));
+    let networkd = register(&mut manager, "rae-networkd", 902)?;
+    if restart_subsystem(Subsystem::Memory) != Err(WatchdogError::SubsystemNotFound) {
+        return Err("Restart of a subsystem no service runs accepted");
+    }
+    restart_subsystem(Subsystem::Network).map_err(|_| "Watchdog restart not accepted")?;
+    manager.supervise();
+    let hung = manager.get_service_info(networkd).map_err(|_| "Service lost")?;
+    if hung.status != ServiceStatus::Restarting || !state.lock().killed.contains(&902) {
+        return Err("Watchdog restart did not stop the service");
+    }
+    state.lock().now_ms += u64::from(RESTART_DELAY_MS);
+    manager.supervise();
+    let restarted = manager.get_service_info(networkd).map_err(|_| "Service lost")?;
+    if restarted.status != ServiceStatus::Running || state.lock().spawned.last().map(String::as_str) != Some("/bin/rae-networkd") {
+        return Err("Watchdog restart did not start the service again");
+    }
+    _print(format_args!("[Restart Test] ✓ Network watchdog restarted rae-networkd as pid {}\n", restarted.process_id));
+
+    _print(format_args!("[Restart Test] ✓ All service restart tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for service micro-reboots
+pub fn test_service_restarts() {
+    _print(format_args!("[Restart Test] ===========================================\n"));
+    _print(format_args!("[Restart Test]          SERVICE RESTART TESTS\n"));
+    _print(format_args!("[Restart Test] ===========================================\n"));
+
+    match run_restart_tests() {
+        Ok(_) => _print(format_args!("[Restart Test] ✓ All service restart tests PASSED\n")),
+        Err(e) => _print(format_args!("[Restart Test] ✗ Service restart tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Restart Test] ===========================================\n"));
+}
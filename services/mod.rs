@@ -10,6 +10,9 @@
 //! - **Network Service** (`rae-networkd`): Handles all network operations
 //! - **Graphics Service** (`rae-compositord`): Manages graphics, windows, and compositor
 //! - **AI Service** (`rae-assistantd`): Provides AI assistant capabilities
+//! - **Scheduler Service** (`rae-crond`): Runs tasks on cron-style schedules
+//! - **Init** (`rae-init`): Starts, restarts and stops the userspace services and reaps orphans
+//! - **Message Bus** (`rae-busd`): Routes method calls, replies and signals between apps by well-known name
 //!
 //! # Service Communication
 //!
@@ -34,6 +37,9 @@ pub mod manager;
 pub mod network;
 pub mod graphics;
 pub mod ai;
+pub mod scheduler;
+pub mod init;
+pub mod bus;
 
 use contracts::*;
 use manager::ServiceManager;
@@ -0,0 +1,204 @@
+//! ARP Tests
+//! Sends segments off-link through an interface with a gateway, answering for the gateway by
+//! hand: the first packet asks for the next hop and waits with the next until the reply,
+//! later packets go straight out, entries expire after their TTL, unanswered requests are
+//! retried and then given up on, and gratuitous ARP refreshes only what is already cached
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::net::{Ipv4Addr, SocketAddrV4};
+
+use super::super::contracts::network::{ArpEntryState, IpAddress};
+use super::interface_manager::{InterfaceManager, ARP_MAX_REQUESTS, ARP_QUEUE_LEN, ARP_RETRY_MS};
+use super::packet_processor::{
+    ethernet_frame, ArpPacket, PacketProcessor, ARP_REPLY, ARP_REQUEST, BROADCAST_MAC, ETHERNET_HEADER_LEN, ETHERTYPE_ARP,
+    ETHERTYPE_IPV4,
+};
+use super::tcp::{Segment, ACK};
+use crate::serial::_print;
+
+const INTERFACE: &str = "eth0";
+const LOCAL_MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+const LOCAL_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 15);
+const GATEWAY: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 2);
+const GATEWAY_MAC: [u8; 6] = [0x52, 0x55, 0x0a, 0x00, 0x02, 0x02];
+/// The gateway's hardware address after its NIC is swapped
+const GATEWAY_NEW_MAC: [u8; 6] = [0x52, 0x55, 0x0a, 0x00, 0x02, 0xfe];
+const REMOTE: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(192, 0, 2, 1), 80);
+const IPV4_HEADER_LEN: usize = 20;
+const TCP_HEADER_LEN: usize = 20;
+
+fn segment(payload: &[u8]) -> Segment {
+    Segment {
+        source: SocketAddrV4::new(LOCAL_IP, 50000),
+        destination: REMOTE,
+        seq: 1,
+        ack: 1,
+        flags: ACK,
+        window: 65535,
+        payload: payload.to_vec(),
+    }
+}
+
+fn arp_frame(operation: u16, sender: ([u8; 6], Ipv4Addr), target: ([u8; 6], Ipv4Addr), to: [u8; 6]) -> Vec<u8> {
+    let arp = ArpPacket { operation, sender_mac: sender.0, sender_ip: sender.1, target_mac: target.0, target_ip: target.1 };
+    ethernet_frame(to, sender.0, ETHERTYPE_ARP, &arp.encode())
+}
+
+/// The request we broadcast for `address`
+fn request_for(address: Ipv4Addr) -> Vec<u8> {
+    arp_frame(ARP_REQUEST, (LOCAL_MAC, LOCAL_IP), ([0; 6], address), BROADCAST_MAC)
+}
+
+/// The TCP payload of an IPv4 frame sent to `mac` for `REMOTE`, if that is what `frame` is
+fn payload_to(frame: &[u8], mac: [u8; 6]) -> Option<&[u8]> {
+    let ip = frame.get(ETHERNET_HEADER_LEN..)?;
+    if frame[..6] != mac || frame[12..14] != ETHERTYPE_IPV4.to_be_bytes() || ip.get(16..20)? != REMOTE.ip().octets() {
+        return None;
+    }
+    ip.get(IPV4_HEADER_LEN + TCP_HEADER_LEN..)
+}
+
+fn in_table(interfaces: &InterfaceManager, address: Ipv4Addr, now_ms: u64) -> Option<ArpEntryState> {
+    interfaces
+        .arp_table(now_ms)
+        .into_iter()
+        .find(|entry| matches!(entry.ip_address, IpAddress::V4(octets) if octets == address.octets()))
+        .map(|entry| entry.state)
+}
+
+pub fn run_arp_tests() -> Result<(), &'static str> {
+    _print(format_args!("[ARP Test] Starting ARP tests...\n"));
+
+    let interfaces = Arc::new(InterfaceManager::new());
+    interfaces.initialize().map_err(|_| "Interfaces did not initialize")?;
+    interfaces.add_interface(INTERFACE, LOCAL_MAC, 1500).map_err(|_| "Interface not added")?;
+    interfaces.set_address(INTERFACE, Some((LOCAL_IP, 24))).map_err(|_| "Address not set")?;
+    interfaces.set_gateway(Some(GATEWAY));
+    let processor = PacketProcessor::new(interfaces.clone());
+    processor.start().map_err(|_| "Packet processor did not start")?;
+
+    // Test 1: The first packet for an unresolved next hop asks for it, and packets wait for the
+    // reply, which sends them in order
+    _print(format_args!("[ARP Test] Test 1: Resolving the gateway...\n"));
+    let route = processor.route(*REMOTE.ip()).map_err(|_| "No route off-link")?;
+    if route.interface != INTERFACE || route.source != LOCAL_IP || route.next_hop != GATEWAY {
+        return Err("Off-link destination not routed through the gateway");
+    }
+    processor.transmit(&segment(b"first")).map_err(|_| "First send failed")?;
+    if interfaces.take_frames(INTERFACE) != [request_for(GATEWAY)] {
+        return Err("No ARP request broadcast for the gateway");
+    }
+    processor.transmit(&segment(b"second")).map_err(|_| "Second send failed")?;
+    if !interfaces.take_frames(INTERFACE).is_empty() {
+        return Err("Packet sent or asked for again while the gateway was resolving");
+    }
+    let now = crate::time::get_uptime_ms();
+    if !matches!(in_table(&interfaces, GATEWAY, now), Some(ArpEntryState::Incomplete)) {
+        return Err("Resolving gateway not listed as incomplete");
+    }
+    processor.receive_frame(INTERFACE, &arp_frame(ARP_REPLY, (GATEWAY_MAC, GATEWAY), (LOCAL_MAC, LOCAL_IP), LOCAL_MAC));
+    let frames = interfaces.take_frames(INTERFACE);
+    let sent: Vec<Option<&[u8]>> = frames.iter().map(|frame| payload_to(frame, GATEWAY_MAC)).collect();
+    if sent != [Some(&b"first"[..]), Some(&b"second"[..])] {
+        return Err("Waiting packets not sent to the gateway in order on its reply");
+    }
+    if interfaces.arp_lookup(GATEWAY, crate::time::get_uptime_ms()) != Some(GATEWAY_MAC) {
+        return Err("Gateway not cached");
+    }
+    _print(format_args!("[ARP Test] ✓ Gateway resolved after 1 request, 2 packets released\n"));
+
+    // Test 2: A cached next hop is sent to at once, and requests for our address are answered
+    _print(format_args!("[ARP Test] Test 2: Cache hits and answering requests...\n"));
+    processor.transmit(&segment(b"third")).map_err(|_| "Third send failed")?;
+    let frames = interfaces.take_frames(INTERFACE);
+    if frames.len() != 1 || payload_to(&frames[0], GATEWAY_MAC) != Some(&b"third"[..]) {
+        return Err("Cached next hop not sent to directly");
+    }
+    processor.receive_frame(INTERFACE, &arp_frame(ARP_REQUEST, (GATEWAY_MAC, GATEWAY), ([0; 6], LOCAL_IP), BROADCAST_MAC));
+    let reply = arp_frame(ARP_REPLY, (LOCAL_MAC, LOCAL_IP), (GATEWAY_MAC, GATEWAY), GATEWAY_MAC);
+    if interfaces.take_frames(INTERFACE) != [reply] {
+        return Err("Request for our address not answered");
+    }
+    // A request between two other hosts is neither answered nor learned from
+    let other = Ipv4Addr::new(10, 0, 2, 9);
+    processor.receive_frame(INTERFACE, &arp_frame(ARP_REQUEST, ([2; 6], other), ([0; 6], Ipv4Addr::new(10, 0, 2, 99)), BROADCAST_MAC));
+    if !interfaces.take_frames(INTERFACE).is_empty() || in_table(&interfaces, other, crate::time::get_uptime_ms()).is_some() {
+        return Err("Request for another host answered or cached");
+    }
+    _print(format_args!("[ARP Test] ✓ Cache hit sent without a request, request for {} answered\n", LOCAL_IP));
+
+    // Test 3: Gratuitous ARP refreshes an entry already held, and adds none
+    _print(format_args!("[ARP Test] Test 3: Gratuitous ARP...\n"));
+    let announced = Ipv4Addr::new(10, 0, 2, 7);
+    processor.receive_frame(INTERFACE, &arp_frame(ARP_REQUEST, ([7; 6], announced), ([0; 6], announced), BROADCAST_MAC));
+    processor.receive_frame(INTERFACE, &arp_frame(ARP_REQUEST, (GATEWAY_NEW_MAC, GATEWAY), ([0; 6], GATEWAY), BROADCAST_MAC));
+    processor.receive_frame(INTERFACE, &arp_frame(ARP_REPLY, ([6; 6], LOCAL_IP), (BROADCAST_MAC, LOCAL_IP), BROADCAST_MAC));
+    let now = crate::time::get_uptime_ms();
+    if in_table(&interfaces, announced, now).is_some() || in_table(&interfaces, LOCAL_IP, now).is_some() {
+        return Err("Gratuitous ARP added an entry");
+    }
+    processor.transmit(&segment(b"fourth")).map_err(|_| "Fourth send failed")?;
+    let frames = interfaces.take_frames(INTERFACE);
+    if frames.len() != 1 || payload_to(&frames[0], GATEWAY_NEW_MAC) != Some(&b"fourth"[..]) {
+        return Err("Gratuitous ARP did not move the gateway to its new address");
+    }
+    _print(format_args!("[ARP Test] ✓ Gateway moved to its announced address, nothing else learned\n"));
+
+    // Test 4: An entry expires after its TTL, and the next packet asks again
+    _print(format_args!("[ARP Test] Test 4: Expiry...\n"));
+    interfaces.set_arp_ttl_ms(5000).map_err(|_| "TTL not set")?;
+    processor.receive_frame(INTERFACE, &arp_frame(ARP_REPLY, (GATEWAY_MAC, GATEWAY), (LOCAL_MAC, LOCAL_IP), LOCAL_MAC));
+    let learned = crate::time::get_uptime_ms();
+    if interfaces.arp_lookup(GATEWAY, learned) != Some(GATEWAY_MAC) {
+        return Err("Entry not resolved before its TTL");
+    }
+    if interfaces.arp_lookup(GATEWAY, learned + 5000).is_some() || in_table(&interfaces, GATEWAY, learned).is_some() {
+        return Err("Entry not evicted after its TTL");
+    }
+    processor.transmit(&segment(b"fifth")).map_err(|_| "Fifth send failed")?;
+    if interfaces.take_frames(INTERFACE) != [request_for(GATEWAY)] {
+        return Err("Evicted next hop not asked for again");
+    }
+    _print(format_args!("[ARP Test] ✓ Entry evicted after 5000 ms and asked for again\n"));
+
+    // Test 5: Unanswered requests are retried, then the next hop is given up on with its
+    // packets. Too many packets waiting on one next hop are dropped too
+    _print(format_args!("[ARP Test] Test 5: Retries and drops...\n"));
+    let start = crate::time::get_uptime_ms();
+    let mut requests = 1;
+    for retry in 1..=u64::from(ARP_MAX_REQUESTS) {
+        processor.on_timer(start + retry * ARP_RETRY_MS);
+        requests += interfaces.take_frames(INTERFACE).iter().filter(|frame| **frame == request_for(GATEWAY)).count() as u32;
+    }
+    if requests != ARP_MAX_REQUESTS || processor.statistics().unresolved != 1 {
+        return Err("Requests not retried up to the limit before the packet was dropped");
+    }
+    if in_table(&interfaces, GATEWAY, start).is_some() {
+        return Err("Given-up next hop still cached");
+    }
+    for _ in 0..=ARP_QUEUE_LEN {
+        processor.transmit(&segment(b"queued")).map_err(|_| "Queued send failed")?;
+    }
+    if interfaces.take_frames(INTERFACE).len() != 1 || processor.statistics().unresolved != 2 {
+        return Err("Packets beyond the queue not dropped");
+    }
+    _print(format_args!("[ARP Test] ✓ {} requests sent before giving up, {} packets dropped\n", requests, processor.statistics().unresolved));
+
+    _print(format_args!("[ARP Test] ✓ All ARP tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for ARP
+pub fn test_arp() {
+    _print(format_args!("[ARP Test] ===========================================\n"));
+    _print(format_args!("[ARP Test]                 ARP TESTS\n"));
+    _print(format_args!("[ARP Test] ===========================================\n"));
+
+    match run_arp_tests() {
+        Ok(_) => _print(format_args!("[ARP Test] ✓ All ARP tests PASSED\n")),
+        Err(e) => _print(format_args!("[ARP Test] ✗ ARP tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[ARP Test] ===========================================\n"));
+}
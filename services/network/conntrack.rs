@@ -0,0 +1,401 @@
+//! Connection tracking and source NAT for rae-networkd
+//! Every TCP, UDP and ICMP echo packet leaving through an interface or arriving on one belongs
+//! to a flow, known by its 5-tuple: protocol, source and destination addresses and ports, the
+//! echo identifier standing in for both ports of ICMP. A flow is entered by the packet that
+//! begins it and forgotten after a quiet spell whose length depends on its protocol and
+//! state, following RFCs 5382, 4787 and 5508.
+//! A masquerade rule lends an interface's address to a private network, such as a sandboxed
+//! namespace's: flows from the network leaving through the interface have their source
+//! rewritten to the interface's address and a port no other flow's replies come back to, and
+//! the replies have their destination rewritten back before they are delivered.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::net::{Ipv4Addr, SocketAddrV4};
+
+use super::super::contracts::network::{ConnectionState, TrackedConnection};
+use super::super::manager::ServiceError;
+use super::interface_manager::on_link;
+use super::packet_processor::ipv4_bounds;
+use super::socket_manager::socket_address;
+use super::tcp::{ACK, FIN, IPPROTO_TCP, RST, SYN};
+
+pub const IPPROTO_ICMP: u8 = 1;
+pub const IPPROTO_UDP: u8 = 17;
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+
+/// An established TCP flow may idle for 2 hours 4 minutes (RFC 5382)
+pub const TCP_ESTABLISHED_TIMEOUT_MS: u64 = 7_440_000;
+/// A TCP flow opening or closing, for 4 minutes (RFC 5382)
+pub const TCP_TRANSITORY_TIMEOUT_MS: u64 = 240_000;
+/// A TCP flow reset has nothing left to come but stragglers
+pub const TCP_CLOSED_TIMEOUT_MS: u64 = 10_000;
+/// A UDP flow replied to, for 2 minutes (RFC 4787)
+pub const UDP_TIMEOUT_MS: u64 = 120_000;
+/// A UDP flow never replied to, often a lone query
+pub const UDP_UNREPLIED_TIMEOUT_MS: u64 = 30_000;
+/// An ICMP echo flow, for 60 seconds (RFC 5508)
+pub const ICMP_TIMEOUT_MS: u64 = 60_000;
+/// Flows tracked at most; packets beginning more are dropped
+pub const MAX_CONNECTIONS: usize = 4096;
+/// Ports masqueraded flows are moved to when their own is taken
+pub const NAT_PORT_FIRST: u16 = 49152;
+pub const NAT_PORT_LAST: u16 = 65535;
+
+/// The 5-tuple of the packets of one direction of a flow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FlowKey {
+    pub protocol: u8,
+    pub source: SocketAddrV4,
+    pub destination: SocketAddrV4,
+}
+
+impl FlowKey {
+    /// The tuple of the packets going the other way
+    pub fn reverse(self) -> Self {
+        Self { protocol: self.protocol, source: self.destination, destination: self.source }
+    }
+}
+
+/// A packet's flow, where its transport header starts, and its TCP flags or ICMP type
+struct Flow {
+    key: FlowKey,
+    transport: usize,
+    control: u8,
+}
+
+impl Flow {
+    /// The flow of a TCP, UDP or ICMP echo packet. `None` for other packets, and for those
+    /// truncated or fragmented
+    fn parse(packet: &[u8]) -> Option<Self> {
+        let (transport, end) = ipv4_bounds(packet)?;
+        let body = &packet[transport..end];
+        let word = |at: usize| u16::from_be_bytes([body[at], body[at + 1]]);
+        let (source_port, destination_port, control) = match packet[9] {
+            IPPROTO_TCP if body.len() >= 20 => (word(0), word(2), body[13]),
+            IPPROTO_UDP if body.len() >= 8 => (word(0), word(2), 0),
+            IPPROTO_ICMP if body.len() >= 8 && matches!(body[0], ICMP_ECHO_REQUEST | ICMP_ECHO_REPLY) => (word(4), word(4), body[0]),
+            _ => return None,
+        };
+        let source = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+        let destination = Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]);
+        Some(Self {
+            key: FlowKey {
+                protocol: packet[9],
+                source: SocketAddrV4::new(source, source_port),
+                destination: SocketAddrV4::new(destination, destination_port),
+            },
+            transport,
+            control,
+        })
+    }
+
+    /// Whether the packet may open a flow: a TCP SYN, any UDP datagram, an echo request
+    fn begins(&self) -> bool {
+        match self.key.protocol {
+            IPPROTO_TCP => self.control & (SYN | ACK | RST) == SYN,
+            IPPROTO_UDP => true,
+            _ => self.control == ICMP_ECHO_REQUEST,
+        }
+    }
+
+    /// Where the transport checksum covering the addresses and ports is, if there is one.
+    /// ICMP's covers the identifier only
+    fn checksum(&self, packet: &[u8]) -> Option<usize> {
+        match self.key.protocol {
+            IPPROTO_TCP => Some(self.transport + 16),
+            // A UDP datagram sent without a checksum stays without one
+            IPPROTO_UDP if packet[self.transport + 6..self.transport + 8] != [0, 0] => Some(self.transport + 6),
+            _ => None,
+        }
+    }
+
+    /// Rewrite the packet's addresses and ports to those of `to`, updating its checksums
+    fn rewrite(&self, packet: &mut [u8], to: FlowKey) {
+        let checksum = self.checksum(packet);
+        let from = self.key;
+        for (at, old, new) in [(12, from.source.ip(), to.source.ip()), (16, from.destination.ip(), to.destination.ip())] {
+            if old != new {
+                patch(packet, at, &new.octets(), &[Some(10), checksum]);
+            }
+        }
+        if from.protocol == IPPROTO_ICMP {
+            if from.source.port() != to.source.port() {
+                patch(packet, self.transport + 4, &to.source.port().to_be_bytes(), &[Some(self.transport + 2)]);
+            }
+        } else {
+            for (at, old, new) in [(0, from.source.port(), to.source.port()), (2, from.destination.port(), to.destination.port())] {
+                if old != new {
+                    patch(packet, self.transport + at, &new.to_be_bytes(), &[checksum]);
+                }
+            }
+        }
+        // A UDP checksum coming to zero is sent as all ones, zero meaning none
+        if let (IPPROTO_UDP, Some(at)) = (from.protocol, checksum) {
+            if packet[at..at + 2] == [0, 0] {
+                packet[at..at + 2].copy_from_slice(&[0xff, 0xff]);
+            }
+        }
+    }
+}
+
+/// Overwrite the bytes at `at` with `new`, a whole number of 16-bit words, and update the
+/// checksums at `checksums` incrementally (RFC 1624), so a packet corrupt before is still
+/// found corrupt after
+fn patch(packet: &mut [u8], at: usize, new: &[u8], checksums: &[Option<usize>]) {
+    for &checksum_at in checksums.iter().flatten() {
+        let mut sum = u32::from(!u16::from_be_bytes([packet[checksum_at], packet[checksum_at + 1]]));
+        for (old, new) in packet[at..at + new.len()].chunks_exact(2).zip(new.chunks_exact(2)) {
+            sum += u32::from(!u16::from_be_bytes([old[0], old[1]]));
+            sum += u32::from(u16::from_be_bytes([new[0], new[1]]));
+        }
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        packet[checksum_at..checksum_at + 2].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+    }
+    packet[at..at + new.len()].copy_from_slice(new);
+}
+
+fn timeout_ms(protocol: u8, state: ConnectionState) -> u64 {
+    match (protocol, state) {
+        (IPPROTO_TCP, ConnectionState::Established) => TCP_ESTABLISHED_TIMEOUT_MS,
+        (IPPROTO_TCP, ConnectionState::Closed) => TCP_CLOSED_TIMEOUT_MS,
+        (IPPROTO_TCP, _) => TCP_TRANSITORY_TIMEOUT_MS,
+        (IPPROTO_UDP, ConnectionState::New) => UDP_UNREPLIED_TIMEOUT_MS,
+        (IPPROTO_UDP, _) => UDP_TIMEOUT_MS,
+        _ => ICMP_TIMEOUT_MS,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Original,
+    Reply,
+}
+
+#[derive(Debug)]
+struct Connection {
+    /// The tuple of the packets that began the flow, as they were sent
+    original: FlowKey,
+    /// The tuple replies come back with, to the translated source when masqueraded
+    reply: FlowKey,
+    state: ConnectionState,
+    expires_ms: u64,
+}
+
+impl Connection {
+    fn update(&mut self, direction: Direction, control: u8, now_ms: u64) {
+        let protocol = self.original.protocol;
+        self.state = match self.state {
+            ConnectionState::Closed => ConnectionState::Closed,
+            _ if protocol == IPPROTO_TCP && control & RST != 0 => ConnectionState::Closed,
+            _ if protocol == IPPROTO_TCP && control & FIN != 0 => ConnectionState::Closing,
+            ConnectionState::New if direction == Direction::Reply => ConnectionState::Established,
+            state => state,
+        };
+        self.expires_ms = now_ms.saturating_add(timeout_ms(protocol, self.state));
+    }
+
+    /// The tuple packets going `direction` leave with
+    fn translation(&self, direction: Direction) -> FlowKey {
+        match direction {
+            Direction::Original => self.reply.reverse(),
+            Direction::Reply => self.original.reverse(),
+        }
+    }
+}
+
+/// Addresses of a private network leaving through an interface take the interface's
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Masquerade {
+    network: Ipv4Addr,
+    prefix_len: u8,
+    interface: String,
+}
+
+/// The flows passing through the service, and the masquerade rules they are translated by
+#[derive(Debug)]
+pub struct ConnTrack {
+    connections: BTreeMap<u64, Connection>,
+    /// The connection and direction of each tuple tracked
+    tuples: BTreeMap<FlowKey, (u64, Direction)>,
+    rules: Vec<Masquerade>,
+    next_id: u64,
+    next_port: u16,
+}
+
+impl Default for ConnTrack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConnTrack {
+    pub fn new() -> Self {
+        Self { connections: BTreeMap::new(), tuples: BTreeMap::new(), rules: Vec::new(), next_id: 0, next_port: NAT_PORT_FIRST }
+    }
+
+    /// Masquerade the network `prefix_len` bits of `network` give behind `interface`, or move
+    /// it to another interface. Flows already tracked keep their translation
+    pub fn set_masquerade(&mut self, network: Ipv4Addr, prefix_len: u8, interface: &str) -> Result<(), ServiceError> {
+        if prefix_len > 32 {
+            return Err(ServiceError::InvalidState);
+        }
+        self.rules.retain(|rule| (rule.network, rule.prefix_len) != (network, prefix_len));
+        self.rules.push(Masquerade { network, prefix_len, interface: String::from(interface) });
+        Ok(())
+    }
+
+    pub fn remove_masquerade(&mut self, network: Ipv4Addr, prefix_len: u8) -> Result<(), ServiceError> {
+        let rules = self.rules.len();
+        self.rules.retain(|rule| (rule.network, rule.prefix_len) != (network, prefix_len));
+        if self.rules.len() == rules {
+            return Err(ServiceError::ServiceNotFound);
+        }
+        Ok(())
+    }
+
+    /// Track a packet leaving through `interface`, whose address is `address`, translating
+    /// its source when a rule masquerades it. Returns whether it may be sent: not when the
+    /// table is full, nor when it needs translating and cannot be
+    pub fn outbound(&mut self, packet: &mut [u8], interface: &str, address: Ipv4Addr, now_ms: u64) -> bool {
+        let Some(source) = packet.get(12..16).map(|octets| Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3])) else {
+            return false;
+        };
+        let masqueraded = self.rules.iter().any(|rule| rule.interface == interface && on_link(rule.network, rule.prefix_len, source));
+        self.track(packet, masqueraded.then_some(address), now_ms)
+    }
+
+    /// Track a packet received, translating the destination of replies to masqueraded flows
+    /// back. Returns whether it may be delivered
+    pub fn inbound(&mut self, packet: &mut [u8], now_ms: u64) -> bool {
+        self.track(packet, None, now_ms)
+    }
+
+    fn track(&mut self, packet: &mut [u8], masquerade: Option<Ipv4Addr>, now_ms: u64) -> bool {
+        // Packets of no flow go untranslated, so none may leave a masqueraded network
+        let Some(flow) = Flow::parse(packet) else {
+            return masquerade.is_none();
+        };
+        let (id, direction) = match self.tuples.get(&flow.key).copied() {
+            Some((id, direction)) if self.connections.get(&id).is_some_and(|connection| connection.expires_ms > now_ms) => (id, direction),
+            stale => {
+                if let Some((id, _)) = stale {
+                    self.remove(id);
+                }
+                if !flow.begins() {
+                    return masquerade.is_none();
+                }
+                match self.open(flow.key, masquerade) {
+                    Some(id) => (id, Direction::Original),
+                    None => return false,
+                }
+            }
+        };
+        let Some(connection) = self.connections.get_mut(&id) else {
+            return false;
+        };
+        connection.update(direction, flow.control, now_ms);
+        let to = connection.translation(direction);
+        flow.rewrite(packet, to);
+        true
+    }
+
+    /// Enter a flow begun by a packet of tuple `original`, with its source translated to
+    /// `masquerade` when given
+    fn open(&mut self, original: FlowKey, masquerade: Option<Ipv4Addr>) -> Option<u64> {
+        if self.connections.len() >= MAX_CONNECTIONS {
+            return None;
+        }
+        let reply = match masquerade {
+            Some(address) => self.translated(original, address)?,
+            None => original.reverse(),
+        };
+        // Replies that could not be told from another flow's
+        if self.tuples.contains_key(&reply) {
+            return None;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.tuples.insert(original, (id, Direction::Original));
+        self.tuples.insert(reply, (id, Direction::Reply));
+        self.connections.insert(id, Connection { original, reply, state: ConnectionState::New, expires_ms: 0 });
+        Some(id)
+    }
+
+    /// The reply tuple of `original` with its source moved to `address`: its own port when no
+    /// flow's replies come back to it already, otherwise the next free one
+    fn translated(&mut self, original: FlowKey, address: Ipv4Addr) -> Option<FlowKey> {
+        let reply = |port: u16| {
+            let source = SocketAddrV4::new(address, port);
+            // Echo replies carry the identifier back unchanged
+            let destination = match original.protocol {
+                IPPROTO_ICMP => SocketAddrV4::new(*original.destination.ip(), port),
+                _ => original.destination,
+            };
+            FlowKey { protocol: original.protocol, source: destination, destination: source }
+        };
+        if !self.tuples.contains_key(&reply(original.source.port())) {
+            return Some(reply(original.source.port()));
+        }
+        for _ in NAT_PORT_FIRST..=NAT_PORT_LAST {
+            let port = self.next_port;
+            self.next_port = if port == NAT_PORT_LAST { NAT_PORT_FIRST } else { port + 1 };
+            if !self.tuples.contains_key(&reply(port)) {
+                return Some(reply(port));
+            }
+        }
+        None
+    }
+
+    fn remove(&mut self, id: u64) {
+        if let Some(connection) = self.connections.remove(&id) {
+            self.tuples.remove(&connection.original);
+            self.tuples.remove(&connection.reply);
+        }
+    }
+
+    /// Forget the flows quiet for longer than their timeout by `now_ms`. Returns how many
+    pub fn expire(&mut self, now_ms: u64) -> usize {
+        let stale: Vec<u64> = self.connections.iter().filter(|(_, connection)| connection.expires_ms <= now_ms).map(|(&id, _)| id).collect();
+        for &id in &stale {
+            self.remove(id);
+        }
+        stale.len()
+    }
+
+    /// Forget every flow, keeping the rules
+    pub fn clear(&mut self) {
+        self.connections.clear();
+        self.tuples.clear();
+    }
+
+    /// Flows tracked
+    pub fn len(&self) -> usize {
+        self.connections.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.connections.is_empty()
+    }
+
+    pub fn connections(&self, now_ms: u64) -> Vec<TrackedConnection> {
+        self.connections
+            .values()
+            .map(|connection| {
+                let translated = connection.reply.destination;
+                TrackedConnection {
+                    protocol: connection.original.protocol,
+                    source: socket_address(connection.original.source),
+                    destination: socket_address(connection.original.destination),
+                    translated_source: (translated != connection.original.source).then(|| socket_address(translated)),
+                    state: connection.state,
+                    expires_in_ms: connection.expires_ms.saturating_sub(now_ms),
+                }
+            })
+            .collect()
+    }
+}
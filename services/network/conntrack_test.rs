@@ -0,0 +1,251 @@
+//! Connection Tracking Tests
+//! Masquerades a private network behind an interface: TCP, UDP and echo flows from private
+//! addresses leave with the interface's address, on a port of their own when theirs is taken,
+//! and with checksums still valid; replies come back to the private socket that began the
+//! flow; and flows gone quiet are forgotten, after which their replies are no longer
+//! translated and their stragglers no longer leave
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::net::{Ipv4Addr, SocketAddrV4};
+
+use super::super::contracts::network::{ConnectionState, SocketAddress};
+use super::conntrack::{ConnTrack, IPPROTO_ICMP, IPPROTO_UDP, NAT_PORT_FIRST, TCP_ESTABLISHED_TIMEOUT_MS, UDP_UNREPLIED_TIMEOUT_MS};
+use super::interface_manager::InterfaceManager;
+use super::packet_processor::{
+    ethernet_frame, internet_checksum, ArpPacket, PacketProcessor, ARP_REQUEST, BROADCAST_MAC, ETHERNET_HEADER_LEN, ETHERTYPE_ARP, ETHERTYPE_IPV4, IPV4_HEADER_LEN,
+};
+use super::qos::DEFAULT_CLASS;
+use super::tcp::{Segment, ACK, IPPROTO_TCP, SYN};
+use crate::serial::_print;
+
+const INTERFACE: &str = "eth0";
+const LOCAL_MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+const LOCAL_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 15);
+const PEER_MAC: [u8; 6] = [0x52, 0x55, 0x0a, 0x00, 0x02, 0x14];
+const PEER: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(10, 0, 2, 20), 80);
+const DNS: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(10, 0, 2, 20), 53);
+/// A sandboxed namespace's network, behind the interface
+const PRIVATE_NETWORK: Ipv4Addr = Ipv4Addr::new(10, 200, 0, 0);
+const PRIVATE_PREFIX_LEN: u8 = 24;
+const PRIVATE_IP: Ipv4Addr = Ipv4Addr::new(10, 200, 0, 2);
+const PORT: u16 = 40000;
+const ECHO_ID: u16 = 7;
+const ECHO_REQUEST: u8 = 8;
+const ECHO_REPLY: u8 = 0;
+
+fn ipv4(protocol: u8, source: Ipv4Addr, destination: Ipv4Addr, payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(IPV4_HEADER_LEN + payload.len());
+    packet.extend_from_slice(&[0x45, 0]);
+    packet.extend_from_slice(&((IPV4_HEADER_LEN + payload.len()) as u16).to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0x40, 0, 64, protocol, 0, 0]);
+    packet.extend_from_slice(&source.octets());
+    packet.extend_from_slice(&destination.octets());
+    let checksum = internet_checksum(&[&packet]);
+    packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+    packet.extend_from_slice(payload);
+    packet
+}
+
+/// Checksum of a TCP or UDP body over the IPv4 pseudo-header, zero for a valid one
+fn transport_checksum(protocol: u8, source: Ipv4Addr, destination: Ipv4Addr, body: &[u8]) -> u16 {
+    let mut pseudo = [0u8; 12];
+    pseudo[0..4].copy_from_slice(&source.octets());
+    pseudo[4..8].copy_from_slice(&destination.octets());
+    pseudo[9] = protocol;
+    pseudo[10..12].copy_from_slice(&(body.len() as u16).to_be_bytes());
+    internet_checksum(&[&pseudo, body])
+}
+
+fn tcp(source: SocketAddrV4, destination: SocketAddrV4, flags: u8) -> Vec<u8> {
+    let segment = Segment { source, destination, seq: 1000, ack: 1, flags, window: 65535, payload: Vec::new() };
+    ipv4(IPPROTO_TCP, *source.ip(), *destination.ip(), &segment.encode())
+}
+
+fn udp(source: SocketAddrV4, destination: SocketAddrV4, payload: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(8 + payload.len());
+    body.extend_from_slice(&source.port().to_be_bytes());
+    body.extend_from_slice(&destination.port().to_be_bytes());
+    body.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+    body.extend_from_slice(&[0, 0]);
+    body.extend_from_slice(payload);
+    let checksum = transport_checksum(IPPROTO_UDP, *source.ip(), *destination.ip(), &body);
+    body[6..8].copy_from_slice(&checksum.to_be_bytes());
+    ipv4(IPPROTO_UDP, *source.ip(), *destination.ip(), &body)
+}
+
+fn echo(kind: u8, source: Ipv4Addr, destination: Ipv4Addr, id: u16) -> Vec<u8> {
+    let mut body = alloc::vec![kind, 0, 0, 0];
+    body.extend_from_slice(&id.to_be_bytes());
+    body.extend_from_slice(&[0, 1, b'p', b'i', b'n', b'g']);
+    let checksum = internet_checksum(&[&body]);
+    body[2..4].copy_from_slice(&checksum.to_be_bytes());
+    ipv4(IPPROTO_ICMP, source, destination, &body)
+}
+
+/// A packet's source and destination, with its transport ports or echo identifier, when its
+/// IPv4 and transport checksums both hold
+fn checked(packet: &[u8]) -> Option<(SocketAddrV4, SocketAddrV4)> {
+    if packet.len() < IPV4_HEADER_LEN || internet_checksum(&[&packet[..IPV4_HEADER_LEN]]) != 0 {
+        return None;
+    }
+    let source = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+    let destination = Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]);
+    let body = &packet[IPV4_HEADER_LEN..];
+    let word = |at: usize| body.get(at..at + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+    let (source_port, destination_port) = match packet[9] {
+        IPPROTO_ICMP if internet_checksum(&[body]) == 0 => (word(4)?, word(4)?),
+        IPPROTO_ICMP => return None,
+        protocol if transport_checksum(protocol, source, destination, body) == 0 => (word(0)?, word(2)?),
+        _ => return None,
+    };
+    Some((SocketAddrV4::new(source, source_port), SocketAddrV4::new(destination, destination_port)))
+}
+
+/// The packets of the frames waiting to leave through the interface
+fn sent(interfaces: &InterfaceManager) -> Vec<Vec<u8>> {
+    interfaces.take_frames(INTERFACE).iter().filter_map(|frame| frame.get(ETHERNET_HEADER_LEN..)).map(|packet| packet.to_vec()).collect()
+}
+
+pub fn run_conntrack_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Conntrack Test] Starting connection tracking tests...\n"));
+    let interfaces = Arc::new(InterfaceManager::new());
+    interfaces.initialize().map_err(|_| "Interfaces did not initialize")?;
+    interfaces.add_interface(INTERFACE, LOCAL_MAC, 1500).map_err(|_| "Interface not added")?;
+    interfaces.set_address(INTERFACE, Some((LOCAL_IP, 24))).map_err(|_| "Address not set")?;
+    let processor = PacketProcessor::new(interfaces.clone());
+    processor.start().map_err(|_| "Packet processor did not start")?;
+    // The peer asking for our address teaches us its own
+    let request = ArpPacket { operation: ARP_REQUEST, sender_mac: PEER_MAC, sender_ip: *PEER.ip(), target_mac: [0; 6], target_ip: LOCAL_IP };
+    processor.receive_frame(INTERFACE, &ethernet_frame(BROADCAST_MAC, PEER_MAC, ETHERTYPE_ARP, &request.encode()));
+    interfaces.take_frames(INTERFACE);
+    processor.set_masquerade(PRIVATE_NETWORK, PRIVATE_PREFIX_LEN, INTERFACE).map_err(|_| "Masquerade not set")?;
+
+    // Test 1: Flows from the private network leave with the interface's address
+    _print(format_args!("[Conntrack Test] Test 1: Source NAT of outbound flows...\n"));
+    let host = SocketAddrV4::new(LOCAL_IP, PORT);
+    let private = SocketAddrV4::new(PRIVATE_IP, PORT);
+    let private_udp = SocketAddrV4::new(PRIVATE_IP, 5353);
+    // The host's own connection, from the port the private one uses too
+    processor.transmit_packet(tcp(host, PEER, SYN), DEFAULT_CLASS).map_err(|_| "Host SYN not sent")?;
+    processor.transmit_packet(tcp(private, PEER, SYN), DEFAULT_CLASS).map_err(|_| "Private SYN not sent")?;
+    processor.transmit_packet(udp(private_udp, DNS, b"query"), DEFAULT_CLASS).map_err(|_| "Private datagram not sent")?;
+    processor.transmit_packet(echo(ECHO_REQUEST, PRIVATE_IP, *PEER.ip(), ECHO_ID), DEFAULT_CLASS).map_err(|_| "Private echo not sent")?;
+    let packets = sent(&interfaces);
+    let flows: Vec<Option<(SocketAddrV4, SocketAddrV4)>> = packets.iter().map(|packet| checked(packet)).collect();
+    let translated = SocketAddrV4::new(LOCAL_IP, NAT_PORT_FIRST);
+    let expected = [
+        Some((host, PEER)),
+        // Port taken by the host's flow to the same peer
+        Some((translated, PEER)),
+        Some((SocketAddrV4::new(LOCAL_IP, 5353), DNS)),
+        Some((SocketAddrV4::new(LOCAL_IP, ECHO_ID), SocketAddrV4::new(*PEER.ip(), ECHO_ID))),
+    ];
+    if flows != expected {
+        return Err("Private flows not translated to the interface's address with valid checksums");
+    }
+    let table = processor.connections();
+    let private_entry = table.iter().find(|entry| matches!(entry.translated_source, Some(SocketAddress::Inet { port, .. }) if port == NAT_PORT_FIRST));
+    if table.len() != 4 || private_entry.map(|entry| entry.state) != Some(ConnectionState::New) {
+        return Err("Flows not tracked as they began");
+    }
+    _print(format_args!("[Conntrack Test] ✓ TCP, UDP and echo flows left as {}, TCP moved to port {}\n", LOCAL_IP, NAT_PORT_FIRST));
+
+    // Test 2: Replies reach the private socket that began the flow
+    _print(format_args!("[Conntrack Test] Test 2: Replies translated back...\n"));
+    for destination in [translated, host] {
+        let reply = tcp(PEER, destination, SYN | ACK);
+        processor.receive_frame(INTERFACE, &ethernet_frame(LOCAL_MAC, PEER_MAC, ETHERTYPE_IPV4, &reply));
+    }
+    let first = processor.next_segment().ok_or("Reply to the private flow not delivered")?;
+    let second = processor.next_segment().ok_or("Reply to the host's flow not delivered")?;
+    if first.destination != private || !first.has(SYN) || !first.has(ACK) || first.source != PEER {
+        return Err("Reply not translated back to the private socket");
+    }
+    if second.destination != host {
+        return Err("Reply to the host's own flow translated");
+    }
+    let established = processor.connections().iter().filter(|entry| entry.state == ConnectionState::Established).count();
+    if established != 2 {
+        return Err("Replied flows not established");
+    }
+    // Datagram and echo replies, on a table of the test's own
+    let mut table = ConnTrack::new();
+    table.set_masquerade(PRIVATE_NETWORK, PRIVATE_PREFIX_LEN, INTERFACE).map_err(|_| "Masquerade not set")?;
+    let mut query = udp(private_udp, DNS, b"query");
+    let mut ping = echo(ECHO_REQUEST, PRIVATE_IP, *PEER.ip(), ECHO_ID);
+    if !table.outbound(&mut query, INTERFACE, LOCAL_IP, 0) || !table.outbound(&mut ping, INTERFACE, LOCAL_IP, 0) {
+        return Err("Private datagram or echo dropped");
+    }
+    let mut answer = udp(DNS, SocketAddrV4::new(LOCAL_IP, 5353), b"answer");
+    let mut pong = echo(ECHO_REPLY, *PEER.ip(), LOCAL_IP, ECHO_ID);
+    if !table.inbound(&mut answer, 10) || !table.inbound(&mut pong, 10) {
+        return Err("Replies dropped");
+    }
+    if checked(&answer) != Some((DNS, private_udp)) || checked(&pong) != Some((SocketAddrV4::new(*PEER.ip(), ECHO_ID), SocketAddrV4::new(PRIVATE_IP, ECHO_ID))) {
+        return Err("Datagram or echo reply not translated back");
+    }
+    _print(format_args!("[Conntrack Test] ✓ TCP, UDP and echo replies reached {}\n", PRIVATE_IP));
+
+    // Test 3: Flows gone quiet are forgotten
+    _print(format_args!("[Conntrack Test] Test 3: Expiry of stale flows...\n"));
+    let mut table = ConnTrack::new();
+    table.set_masquerade(PRIVATE_NETWORK, PRIVATE_PREFIX_LEN, INTERFACE).map_err(|_| "Masquerade not set")?;
+    let mut query = udp(private_udp, DNS, b"query");
+    if !table.outbound(&mut query, INTERFACE, LOCAL_IP, 0) || table.len() != 1 {
+        return Err("Private datagram not tracked");
+    }
+    if table.expire(UDP_UNREPLIED_TIMEOUT_MS - 1) != 0 || table.expire(UDP_UNREPLIED_TIMEOUT_MS) != 1 || !table.is_empty() {
+        return Err("Unreplied datagram flow not expired on time");
+    }
+    // A late answer is the host's to deal with
+    let mut answer = udp(DNS, SocketAddrV4::new(LOCAL_IP, 5353), b"answer");
+    table.inbound(&mut answer, UDP_UNREPLIED_TIMEOUT_MS);
+    if checked(&answer) != Some((DNS, SocketAddrV4::new(LOCAL_IP, 5353))) {
+        return Err("Reply to an expired flow translated");
+    }
+    table.clear();
+    // An established connection found stale when its next packet comes
+    let mut syn = tcp(private, PEER, SYN);
+    if !table.outbound(&mut syn, INTERFACE, LOCAL_IP, 0) {
+        return Err("Private SYN dropped");
+    }
+    let outside = checked(&syn).map(|(source, _)| source).ok_or("Translated SYN corrupt")?;
+    let mut syn_ack = tcp(PEER, outside, SYN | ACK);
+    if !table.inbound(&mut syn_ack, 1) || checked(&syn_ack) != Some((PEER, private)) {
+        return Err("SYN-ACK not translated back");
+    }
+    let quiet_until = 1 + TCP_ESTABLISHED_TIMEOUT_MS;
+    let mut late_ack = tcp(PEER, outside, ACK);
+    table.inbound(&mut late_ack, quiet_until);
+    if checked(&late_ack) != Some((PEER, outside)) || !table.is_empty() {
+        return Err("Stale connection still translated");
+    }
+    let mut straggler = tcp(private, PEER, ACK);
+    if table.outbound(&mut straggler, INTERFACE, LOCAL_IP, quiet_until) {
+        return Err("Straggler of an expired private flow let out untranslated");
+    }
+    // The processor forgets its flows on a timer tick far enough on
+    processor.on_timer(crate::time::get_uptime_ms() + TCP_ESTABLISHED_TIMEOUT_MS + 1);
+    if processor.connection_count() != 0 {
+        return Err("Processor kept stale flows");
+    }
+    _print(format_args!("[Conntrack Test] ✓ Flows forgotten after {} ms quiet (UDP) and {} ms (TCP)\n", UDP_UNREPLIED_TIMEOUT_MS, TCP_ESTABLISHED_TIMEOUT_MS));
+
+    _print(format_args!("[Conntrack Test] ✓ All connection tracking tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for connection tracking
+pub fn test_conntrack() {
+    _print(format_args!("[Conntrack Test] ===========================================\n"));
+    _print(format_args!("[Conntrack Test]         CONNECTION TRACKING TESTS\n"));
+    _print(format_args!("[Conntrack Test] ===========================================\n"));
+
+    match run_conntrack_tests() {
+        Ok(_) => _print(format_args!("[Conntrack Test] ✓ All connection tracking tests PASSED\n")),
+        Err(e) => _print(format_args!("[Conntrack Test] ✗ Connection tracking tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Conntrack Test] ===========================================\n"));
+}
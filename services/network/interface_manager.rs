@@ -0,0 +1,452 @@
+//! Interface management for rae-networkd
+//! The service's network interfaces with their IPv4 addresses and the default gateway, and
+//! the ARP cache that maps next hops on them to hardware addresses. Resolved entries expire
+//! after a TTL; packets for a next hop still being resolved wait in its entry until a reply
+//! arrives or the requests for it go unanswered. Frames sent on an interface queue until its
+//! driver takes them.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::net::Ipv4Addr;
+use spin::Mutex;
+
+use super::super::contracts::network::{ArpEntry, ArpEntryState, InterfaceStatistics, IpAddress, NetworkInterface};
+use super::super::manager::ServiceError;
+use super::socket_manager::SocketError;
+
+pub const LOOPBACK_NAME: &str = "lo";
+const LOOPBACK_MTU: u32 = 65536;
+pub const DEFAULT_ARP_TTL_MS: u64 = 60_000;
+/// How long an ARP request waits for its reply before it is sent again
+pub const ARP_RETRY_MS: u64 = 1000;
+/// Requests sent for a next hop before the packets waiting on it are dropped
+pub const ARP_MAX_REQUESTS: u32 = 3;
+/// Packets held for one unresolved next hop; more are dropped
+pub const ARP_QUEUE_LEN: usize = 16;
+/// Frames an interface holds for its driver; more are dropped
+const TX_QUEUE_LEN: usize = 256;
+
+#[derive(Debug)]
+enum Slot {
+    Resolved { mac: [u8; 6], interface: String, expires_at: u64 },
+    Pending { interface: String, packets: Vec<Vec<u8>>, requests: u32, retry_at: u64 },
+}
+
+/// What became of a packet handed to the ARP cache
+#[derive(Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// The next hop is known: the packet goes to its hardware address now
+    Send([u8; 6], Vec<u8>),
+    /// The packet waits for the next hop to be resolved. The first to wait asks for a request
+    Queued { request: bool },
+    /// Too many packets wait on the next hop already
+    Dropped,
+}
+
+/// What the ARP timer found to do
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ArpExpiry {
+    /// Next hops to ask for again, with the interface to ask on
+    pub retries: Vec<(Ipv4Addr, String)>,
+    /// Packets dropped with the next hops given up on
+    pub dropped: usize,
+}
+
+/// IPv4 next hops and their hardware addresses
+#[derive(Debug)]
+pub struct ArpCache {
+    entries: BTreeMap<Ipv4Addr, Slot>,
+    ttl_ms: u64,
+}
+
+impl ArpCache {
+    pub fn new(ttl_ms: u64) -> Self {
+        Self { entries: BTreeMap::new(), ttl_ms }
+    }
+
+    /// How long entries learned from now on stay resolved
+    pub fn set_ttl_ms(&mut self, ttl_ms: u64) {
+        self.ttl_ms = ttl_ms;
+    }
+
+    /// The hardware address of `address` while its entry has not expired. An expired entry
+    /// is evicted
+    pub fn lookup(&mut self, address: Ipv4Addr, now_ms: u64) -> Option<[u8; 6]> {
+        match self.entries.get(&address) {
+            Some(Slot::Resolved { mac, expires_at, .. }) if now_ms < *expires_at => Some(*mac),
+            Some(Slot::Resolved { .. }) => {
+                self.entries.remove(&address);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Send `packet` to `address`, or hold it on `interface` until `address` is resolved
+    pub fn resolve(&mut self, address: Ipv4Addr, interface: &str, packet: Vec<u8>, now_ms: u64) -> Resolution {
+        if let Some(mac) = self.lookup(address, now_ms) {
+            return Resolution::Send(mac, packet);
+        }
+        match self.entries.get_mut(&address) {
+            Some(Slot::Pending { packets, .. }) if packets.len() >= ARP_QUEUE_LEN => Resolution::Dropped,
+            Some(Slot::Pending { packets, .. }) => {
+                packets.push(packet);
+                Resolution::Queued { request: false }
+            }
+            _ => {
+                let slot = Slot::Pending {
+                    interface: String::from(interface),
+                    packets: alloc::vec![packet],
+                    requests: 1,
+                    retry_at: now_ms + ARP_RETRY_MS,
+                };
+                self.entries.insert(address, slot);
+                Resolution::Queued { request: true }
+            }
+        }
+    }
+
+    /// Take `mac` as the hardware address of `address`, as an ARP packet from it says. An
+    /// address not in the cache is only added when `create`, so that broadcasts do not fill it
+    /// with every host on the link. Returns the packets that were waiting for the address
+    pub fn learn(&mut self, address: Ipv4Addr, mac: [u8; 6], interface: &str, create: bool, now_ms: u64) -> Vec<Vec<u8>> {
+        let waiting = match self.entries.remove(&address) {
+            Some(Slot::Pending { packets, .. }) => packets,
+            Some(Slot::Resolved { .. }) => Vec::new(),
+            None if create => Vec::new(),
+            None => return Vec::new(),
+        };
+        let slot = Slot::Resolved { mac, interface: String::from(interface), expires_at: now_ms + self.ttl_ms };
+        self.entries.insert(address, slot);
+        waiting
+    }
+
+    /// Evict expired entries and ask again for next hops whose requests went unanswered. A
+    /// next hop asked for `ARP_MAX_REQUESTS` times is given up on, with its packets
+    pub fn on_timer(&mut self, now_ms: u64) -> ArpExpiry {
+        let mut expiry = ArpExpiry::default();
+        self.entries.retain(|address, slot| match slot {
+            Slot::Resolved { expires_at, .. } => now_ms < *expires_at,
+            Slot::Pending { retry_at, .. } if now_ms < *retry_at => true,
+            Slot::Pending { packets, requests, .. } if *requests >= ARP_MAX_REQUESTS => {
+                expiry.dropped += packets.len();
+                false
+            }
+            Slot::Pending { interface, requests, retry_at, .. } => {
+                *requests += 1;
+                *retry_at = now_ms + ARP_RETRY_MS;
+                expiry.retries.push((*address, interface.clone()));
+                true
+            }
+        });
+        expiry
+    }
+
+    /// Forget the entries of `interface`. Returns how many waiting packets were dropped
+    pub fn flush_interface(&mut self, interface: &str) -> usize {
+        let mut dropped = 0;
+        self.entries.retain(|_, slot| match slot {
+            Slot::Resolved { interface: on, .. } => on != interface,
+            Slot::Pending { interface: on, packets, .. } => {
+                if on == interface {
+                    dropped += packets.len();
+                }
+                on != interface
+            }
+        });
+        dropped
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The cache as the contract lists it
+    pub fn entries(&self, now_ms: u64) -> Vec<ArpEntry> {
+        self.entries
+            .iter()
+            .map(|(address, slot)| {
+                let (mac_address, interface, state) = match slot {
+                    Slot::Resolved { mac, interface, expires_at } if now_ms < *expires_at => (*mac, interface, ArpEntryState::Reachable),
+                    Slot::Resolved { mac, interface, .. } => (*mac, interface, ArpEntryState::Stale),
+                    Slot::Pending { interface, .. } => ([0; 6], interface, ArpEntryState::Incomplete),
+                };
+                ArpEntry { ip_address: IpAddress::V4(address.octets()), mac_address, interface: interface.clone(), state }
+            })
+            .collect()
+    }
+}
+
+/// Where a packet for a destination goes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub interface: String,
+    /// The interface's hardware address
+    pub mac: [u8; 6],
+    /// The interface's address, which packets leaving through it come from
+    pub source: Ipv4Addr,
+    /// The destination itself when it is on the link, otherwise the gateway
+    pub next_hop: Ipv4Addr,
+}
+
+#[derive(Debug)]
+struct Interface {
+    mac: [u8; 6],
+    /// Address and prefix length
+    address: Option<(Ipv4Addr, u8)>,
+    mtu: u32,
+    enabled: bool,
+    link_up: bool,
+    statistics: InterfaceStatistics,
+    /// Frames waiting for the driver
+    outgoing: VecDeque<Vec<u8>>,
+}
+
+impl Interface {
+    fn new(mac: [u8; 6], mtu: u32) -> Self {
+        Self {
+            mac,
+            address: None,
+            mtu,
+            enabled: true,
+            link_up: true,
+            statistics: InterfaceStatistics {
+                rx_packets: 0,
+                tx_packets: 0,
+                rx_bytes: 0,
+                tx_bytes: 0,
+                rx_errors: 0,
+                tx_errors: 0,
+                rx_dropped: 0,
+                tx_dropped: 0,
+            },
+            outgoing: VecDeque::new(),
+        }
+    }
+
+    fn is_up(&self) -> bool {
+        self.enabled && self.link_up
+    }
+
+    fn info(&self, name: &str) -> NetworkInterface {
+        NetworkInterface {
+            name: String::from(name),
+            mac_address: self.mac,
+            ip_addresses: self.address.iter().map(|(address, _)| IpAddress::V4(address.octets())).collect(),
+            mtu: self.mtu,
+            enabled: self.enabled,
+            link_up: self.link_up,
+            statistics: self.statistics.clone(),
+        }
+    }
+}
+
+/// Whether `address` is in the network `prefix_len` bits of `network` give
+pub fn on_link(network: Ipv4Addr, prefix_len: u8, address: Ipv4Addr) -> bool {
+    let mask = u32::MAX.checked_shl(32 - u32::from(prefix_len.min(32))).unwrap_or(0);
+    u32::from(network) & mask == u32::from(address) & mask
+}
+
+#[derive(Debug)]
+struct Interfaces {
+    interfaces: BTreeMap<String, Interface>,
+    gateway: Option<Ipv4Addr>,
+}
+
+impl Interfaces {
+    fn get(&mut self, name: &str) -> Result<&mut Interface, ServiceError> {
+        self.interfaces.get_mut(name).ok_or(ServiceError::ServiceNotFound)
+    }
+
+    /// The up interface with the longest prefix holding `address`, other than loopback
+    fn on_link(&self, address: Ipv4Addr) -> Option<(&String, &Interface, Ipv4Addr)> {
+        self.interfaces
+            .iter()
+            .filter(|(name, interface)| name.as_str() != LOOPBACK_NAME && interface.is_up())
+            .filter_map(|(name, interface)| match interface.address {
+                Some((source, prefix_len)) if on_link(source, prefix_len, address) => Some((prefix_len, name, interface, source)),
+                _ => None,
+            })
+            .max_by_key(|&(prefix_len, ..)| prefix_len)
+            .map(|(_, name, interface, source)| (name, interface, source))
+    }
+}
+
+/// The service's network interfaces and ARP cache
+pub struct InterfaceManager {
+    state: Mutex<Interfaces>,
+    arp: Mutex<ArpCache>,
+}
+
+impl InterfaceManager {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(Interfaces { interfaces: BTreeMap::new(), gateway: None }),
+            arp: Mutex::new(ArpCache::new(DEFAULT_ARP_TTL_MS)),
+        }
+    }
+
+    /// Bring up loopback
+    pub fn initialize(&self) -> Result<(), ServiceError> {
+        let mut state = self.state.lock();
+        let loopback = state.interfaces.entry(String::from(LOOPBACK_NAME)).or_insert_with(|| Interface::new([0; 6], LOOPBACK_MTU));
+        loopback.address = Some((Ipv4Addr::LOCALHOST, 8));
+        loopback.enabled = true;
+        Ok(())
+    }
+
+    /// Take down every interface, dropping what they hold, and forget the ARP cache
+    pub fn shutdown(&self) -> Result<(), ServiceError> {
+        let mut state = self.state.lock();
+        for interface in state.interfaces.values_mut() {
+            interface.enabled = false;
+            interface.outgoing.clear();
+        }
+        self.arp.lock().clear();
+        Ok(())
+    }
+
+    /// Healthy while no driver has fallen behind on its frames
+    pub fn is_healthy(&self) -> bool {
+        self.state.lock().interfaces.values().all(|interface| interface.outgoing.len() < TX_QUEUE_LEN)
+    }
+
+    /// Add an interface for a driver, up and without an address
+    pub fn add_interface(&self, name: &str, mac: [u8; 6], mtu: u32) -> Result<(), ServiceError> {
+        let mut state = self.state.lock();
+        if state.interfaces.contains_key(name) {
+            return Err(ServiceError::InvalidState);
+        }
+        state.interfaces.insert(String::from(name), Interface::new(mac, mtu));
+        Ok(())
+    }
+
+    /// Give an interface an address and prefix length, or take its address away
+    pub fn set_address(&self, name: &str, address: Option<(Ipv4Addr, u8)>) -> Result<(), ServiceError> {
+        if matches!(address, Some((_, prefix_len)) if prefix_len > 32) {
+            return Err(ServiceError::InvalidState);
+        }
+        self.state.lock().get(name)?.address = address;
+        Ok(())
+    }
+
+    /// The router for destinations on no interface's link
+    pub fn set_gateway(&self, gateway: Option<Ipv4Addr>) {
+        self.state.lock().gateway = gateway;
+    }
+
+    pub fn list_interfaces(&self) -> Result<Vec<NetworkInterface>, ServiceError> {
+        Ok(self.state.lock().interfaces.iter().map(|(name, interface)| interface.info(name)).collect())
+    }
+
+    pub fn get_interface_info(&self, name: &str) -> Result<NetworkInterface, ServiceError> {
+        Ok(self.state.lock().get(name)?.info(name))
+    }
+
+    /// Bring an interface up or down. Going down drops its frames and its ARP entries, with
+    /// the packets waiting on them
+    pub fn set_interface_state(&self, name: &str, enabled: bool) -> Result<(), ServiceError> {
+        let mut state = self.state.lock();
+        let interface = state.get(name)?;
+        interface.enabled = enabled;
+        if !enabled {
+            let dropped = interface.outgoing.len() + self.arp.lock().flush_interface(name);
+            interface.outgoing.clear();
+            interface.statistics.tx_dropped += dropped as u64;
+        }
+        Ok(())
+    }
+
+    /// Where a packet for `destination` leaves from and goes to next
+    pub fn route(&self, destination: Ipv4Addr) -> Result<Route, SocketError> {
+        let state = self.state.lock();
+        let (next_hop, (name, interface, source)) = match state.on_link(destination) {
+            Some(link) => (destination, link),
+            None => {
+                let gateway = state.gateway.ok_or(SocketError::NetworkUnreachable)?;
+                (gateway, state.on_link(gateway).ok_or(SocketError::NetworkUnreachable)?)
+            }
+        };
+        Ok(Route { interface: name.clone(), mac: interface.mac, source, next_hop })
+    }
+
+    /// The hardware address and IPv4 address of an interface that is up
+    pub fn addresses(&self, name: &str) -> Option<([u8; 6], Option<Ipv4Addr>)> {
+        let state = self.state.lock();
+        let interface = state.interfaces.get(name).filter(|interface| interface.is_up())?;
+        Some((interface.mac, interface.address.map(|(address, _)| address)))
+    }
+
+    /// Queue a frame for an interface's driver
+    pub fn send_frame(&self, name: &str, frame: Vec<u8>) -> Result<(), SocketError> {
+        let mut state = self.state.lock();
+        let interface = state.interfaces.get_mut(name).filter(|interface| interface.is_up()).ok_or(SocketError::NetworkDown)?;
+        // A full queue loses the frame like the wire would
+        if interface.outgoing.len() >= TX_QUEUE_LEN {
+            interface.statistics.tx_dropped += 1;
+            return Ok(());
+        }
+        interface.statistics.tx_packets += 1;
+        interface.statistics.tx_bytes += frame.len() as u64;
+        interface.outgoing.push_back(frame);
+        Ok(())
+    }
+
+    /// The frames waiting for an interface's driver to send, oldest first
+    pub fn take_frames(&self, name: &str) -> Vec<Vec<u8>> {
+        let mut state = self.state.lock();
+        match state.interfaces.get_mut(name) {
+            Some(interface) => interface.outgoing.drain(..).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Count a frame the driver received. Returns whether the interface takes it, being up
+    pub fn frame_received(&self, name: &str, length: usize) -> bool {
+        let mut state = self.state.lock();
+        let Some(interface) = state.interfaces.get_mut(name) else {
+            return false;
+        };
+        if !interface.is_up() {
+            interface.statistics.rx_dropped += 1;
+            return false;
+        }
+        interface.statistics.rx_packets += 1;
+        interface.statistics.rx_bytes += length as u64;
+        true
+    }
+
+    /// How long ARP entries stay resolved
+    pub fn set_arp_ttl_ms(&self, ttl_ms: u64) -> Result<(), ServiceError> {
+        if ttl_ms == 0 {
+            return Err(ServiceError::InvalidState);
+        }
+        self.arp.lock().set_ttl_ms(ttl_ms);
+        Ok(())
+    }
+
+    /// See [`ArpCache::lookup`]
+    pub fn arp_lookup(&self, address: Ipv4Addr, now_ms: u64) -> Option<[u8; 6]> {
+        self.arp.lock().lookup(address, now_ms)
+    }
+
+    /// Send `packet` along `route`, or hold it until the next hop is resolved
+    pub fn resolve(&self, route: &Route, packet: Vec<u8>, now_ms: u64) -> Resolution {
+        self.arp.lock().resolve(route.next_hop, &route.interface, packet, now_ms)
+    }
+
+    /// See [`ArpCache::learn`]
+    pub fn arp_learn(&self, address: Ipv4Addr, mac: [u8; 6], interface: &str, create: bool, now_ms: u64) -> Vec<Vec<u8>> {
+        self.arp.lock().learn(address, mac, interface, create, now_ms)
+    }
+
+    /// See [`ArpCache::on_timer`]
+    pub fn arp_timer(&self, now_ms: u64) -> ArpExpiry {
+        self.arp.lock().on_timer(now_ms)
+    }
+
+    pub fn arp_table(&self, now_ms: u64) -> Vec<ArpEntry> {
+        self.arp.lock().entries(now_ms)
+    }
+}
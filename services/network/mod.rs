@@ -4,23 +4,32 @@
 use alloc::vec::Vec;
 use alloc::string::String;
 use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
 use spin::{Mutex, RwLock};
 use super::contracts::network::*;
 use super::contracts::*;
+use super::manager::ServiceError;
 
 pub mod socket_manager;
 pub mod interface_manager;
 pub mod dhcp_client;
 pub mod dns_resolver;
 pub mod packet_processor;
+pub mod conntrack;
+pub mod qos;
+pub mod tcp;
+pub mod tcp_test;
+pub mod arp_test;
+pub mod qos_test;
+pub mod conntrack_test;
 
 /// Main network service
 pub struct NetworkService {
     socket_manager: socket_manager::SocketManager,
-    interface_manager: interface_manager::InterfaceManager,
+    interface_manager: Arc<interface_manager::InterfaceManager>,
     dhcp_client: dhcp_client::DhcpClient,
     dns_resolver: dns_resolver::DnsResolver,
-    packet_processor: packet_processor::PacketProcessor,
+    packet_processor: Arc<packet_processor::PacketProcessor>,
     service_info: ServiceInfo,
     statistics: RwLock<NetworkServiceStatistics>,
     config: RwLock<NetworkServiceConfig>,
@@ -48,6 +57,7 @@ pub struct NetworkServiceConfig {
     pub dns_servers: Vec<IpAddress>,
     pub dhcp_enabled: bool,
     pub packet_buffer_size: u32,
+    pub arp_ttl_ms: u32,
     pub enable_ipv6: bool,
     pub enable_multicast: bool,
     pub firewall_enabled: bool,
@@ -61,6 +71,7 @@ impl Default for NetworkServiceConfig {
             dns_servers: Vec::new(),
             dhcp_enabled: true,
             packet_buffer_size: 65536,
+            arp_ttl_ms: 60000,
             enable_ipv6: true,
             enable_multicast: false,
             firewall_enabled: true,
@@ -85,12 +96,17 @@ impl NetworkService {
             health_status: HealthStatus::Unknown,
         };
         
+        // Sockets send and receive their segments through the packet processor, which frames
+        // them for the interfaces
+        let interface_manager = Arc::new(interface_manager::InterfaceManager::new());
+        let packet_processor = Arc::new(packet_processor::PacketProcessor::new(interface_manager.clone()));
+        
         Self {
-            socket_manager: socket_manager::SocketManager::new(),
-            interface_manager: interface_manager::InterfaceManager::new(),
+            socket_manager: socket_manager::SocketManager::new(packet_processor.clone()),
+            interface_manager,
             dhcp_client: dhcp_client::DhcpClient::new(),
             dns_resolver: dns_resolver::DnsResolver::new(),
-            packet_processor: packet_processor::PacketProcessor::new(),
+            packet_processor,
             service_info,
             statistics: RwLock::new(NetworkServiceStatistics::default()),
             config: RwLock::new(NetworkServiceConfig::default()),
@@ -145,17 +161,30 @@ impl NetworkService {
             
             NetworkRequest::BindSocket { socket_id, address } => {
                 self.socket_manager.bind_socket(socket_id, address)?;
-                Ok(NetworkResponse::SocketBound { socket_id })
+                Ok(NetworkResponse::SocketBound)
             }
             
             NetworkRequest::ListenSocket { socket_id, backlog } => {
                 self.socket_manager.listen_socket(socket_id, backlog)?;
-                Ok(NetworkResponse::SocketListening { socket_id })
+                Ok(NetworkResponse::SocketListening)
+            }
+            
+            NetworkRequest::AcceptSocket { socket_id } => {
+                let (new_socket_id, peer_address) = self.socket_manager.accept_socket(socket_id, 0)?;
+                
+                // Update active sockets count
+                {
+                    let mut stats = self.statistics.write();
+                    stats.active_sockets += 1;
+                }
+                
+                Ok(NetworkResponse::ConnectionAccepted { new_socket_id, peer_address })
             }
             
             NetworkRequest::ConnectSocket { socket_id, address } => {
+                // Blocks until the connection is established, refused or times out
                 self.socket_manager.connect_socket(socket_id, address)?;
-                Ok(NetworkResponse::SocketConnected { socket_id })
+                Ok(NetworkResponse::SocketConnected)
             }
             
             NetworkRequest::SendData { socket_id, data, flags } => {
@@ -179,7 +208,8 @@ impl NetworkService {
                     stats.bytes_received += data.len() as u64;
                 }
                 
-                Ok(NetworkResponse::DataReceived { data })
+                let sender_address = self.socket_manager.peer_address(socket_id).ok();
+                Ok(NetworkResponse::DataReceived { data, sender_address })
             }
             
             NetworkRequest::CloseSocket { socket_id } => {
@@ -193,7 +223,7 @@ impl NetworkService {
                     }
                 }
                 
-                Ok(NetworkResponse::SocketClosed { socket_id })
+                Ok(NetworkResponse::SocketClosed)
             }
             
             NetworkRequest::ListInterfaces => {
@@ -208,7 +238,47 @@ impl NetworkService {
             
             NetworkRequest::SetInterfaceState { interface_name, enabled } => {
                 self.interface_manager.set_interface_state(&interface_name, enabled)?;
-                Ok(NetworkResponse::InterfaceStateSet { interface_name, enabled })
+                Ok(NetworkResponse::InterfaceStateChanged)
+            }
+            
+            NetworkRequest::SetTrafficClass { class } => {
+                self.packet_processor.set_traffic_class(&class)?;
+                Ok(NetworkResponse::TrafficClassSet)
+            }
+            
+            NetworkRequest::RemoveTrafficClass { class_id } => {
+                self.packet_processor.remove_traffic_class(class_id)?;
+                Ok(NetworkResponse::TrafficClassRemoved)
+            }
+            
+            NetworkRequest::AssignTrafficClass { socket_id, class_id } => {
+                self.socket_manager.set_traffic_class(socket_id, class_id)?;
+                Ok(NetworkResponse::TrafficClassAssigned)
+            }
+            
+            NetworkRequest::SetEgressRate { interface_name, rate_limit } => {
+                self.packet_processor.set_egress_rate(&interface_name, rate_limit)?;
+                Ok(NetworkResponse::EgressRateSet)
+            }
+            
+            NetworkRequest::SetMasquerade { network, prefix_len, interface_name } => {
+                self.packet_processor.set_masquerade(ipv4_network(&network)?, prefix_len, &interface_name)?;
+                Ok(NetworkResponse::MasqueradeSet)
+            }
+            
+            NetworkRequest::RemoveMasquerade { network, prefix_len } => {
+                self.packet_processor.remove_masquerade(ipv4_network(&network)?, prefix_len)?;
+                Ok(NetworkResponse::MasqueradeRemoved)
+            }
+            
+            NetworkRequest::GetConnectionTable => {
+                let connections = self.packet_processor.connections();
+                Ok(NetworkResponse::ConnectionTable { connections })
+            }
+            
+            NetworkRequest::GetArpTable => {
+                let entries = self.interface_manager.arp_table(crate::time::get_uptime_ms());
+                Ok(NetworkResponse::ArpTable { entries })
             }
             
             NetworkRequest::ResolveHostname { hostname, record_type } => {
@@ -293,6 +363,7 @@ impl NetworkService {
     fn apply_config_changes(&self, config: &NetworkServiceConfig) -> Result<(), ServiceError> {
         // Update socket manager limits
         self.socket_manager.set_max_sockets(config.max_sockets)?;
+        self.socket_manager.set_timeout_ms(config.socket_timeout_ms)?;
         
         // Update DNS resolver servers
         self.dns_resolver.set_dns_servers(&config.dns_servers)?;
@@ -304,6 +375,9 @@ impl NetworkService {
             self.dhcp_client.stop()?;
         }
         
+        // Update how long resolved ARP entries last
+        self.interface_manager.set_arp_ttl_ms(u64::from(config.arp_ttl_ms))?;
+        
         // Update packet processor settings
         self.packet_processor.set_buffer_size(config.packet_buffer_size)?;
         self.packet_processor.set_ipv6_enabled(config.enable_ipv6)?;
@@ -380,6 +454,14 @@ impl NetworkService {
     }
 }
 
+/// The IPv4 network a request names; other networks cannot be masqueraded
+fn ipv4_network(address: &IpAddress) -> Result<core::net::Ipv4Addr, ServiceError> {
+    match address {
+        IpAddress::V4(octets) => Ok(core::net::Ipv4Addr::from(*octets)),
+        IpAddress::V6(_) => Err(ServiceError::InvalidState),
+    }
+}
+
 /// Network service entry point
 pub fn main() -> Result<(), ServiceError> {
     // Initialize network service
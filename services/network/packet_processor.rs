@@ -0,0 +1,488 @@
+//! Packet processing for rae-networkd
+//! Wraps outgoing TCP segments in IPv4 and queues the packets arriving for the service,
+//! checking both checksums before a segment is handed on. Packets to 127.0.0.0/8 are
+//! looped back onto the inbound queue; others are framed for the interface their route
+//! leaves through, once ARP has found the next hop's hardware address. ARP requests for the
+//! service's addresses are answered, and replies release the packets waiting on them.
+//! Frames for the interfaces pass through the traffic shaper, which sends them in order of
+//! their sockets' traffic classes and within the rates configured. Packets crossing an
+//! interface either way pass connection tracking, which translates those of masqueraded
+//! private networks.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::net::Ipv4Addr;
+use spin::Mutex;
+
+use super::super::contracts::network::{RateLimit, TrackedConnection, TrafficClass};
+use super::super::manager::ServiceError;
+use super::conntrack::ConnTrack;
+use super::interface_manager::{InterfaceManager, Resolution, Route};
+use super::qos::{Shaper, DEFAULT_CLASS};
+use super::socket_manager::SocketError;
+use super::tcp::{Segment, IPPROTO_TCP};
+
+pub const IPV4_HEADER_LEN: usize = 20;
+const DEFAULT_TTL: u8 = 64;
+const DEFAULT_BUFFER_SIZE: usize = 65536;
+
+pub const ETHERNET_HEADER_LEN: usize = 14;
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+pub const BROADCAST_MAC: [u8; 6] = [0xff; 6];
+pub const ARP_REQUEST: u16 = 1;
+pub const ARP_REPLY: u16 = 2;
+const ARP_PACKET_LEN: usize = 28;
+/// Ethernet hardware and IPv4 protocol, with their address lengths
+const ARP_ETHERNET_IPV4: [u8; 6] = [0, 1, 0x08, 0x00, 6, 4];
+
+/// One's-complement sum of `parts` taken as one run of big-endian 16-bit words
+pub fn internet_checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum = 0u32;
+    let mut odd: Option<u8> = None;
+    for byte in parts.iter().flat_map(|part| part.iter().copied()) {
+        match odd.take() {
+            Some(high) => sum += u32::from(u16::from_be_bytes([high, byte])),
+            None => odd = Some(byte),
+        }
+    }
+    if let Some(high) = odd {
+        sum += u32::from(u16::from_be_bytes([high, 0]));
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Where the payload of an IPv4 packet starts and ends. `None` when it is truncated or a
+/// fragment, as fragments are not reassembled
+pub fn ipv4_bounds(packet: &[u8]) -> Option<(usize, usize)> {
+    if packet.len() < IPV4_HEADER_LEN || packet[0] >> 4 != 4 {
+        return None;
+    }
+    let header_len = usize::from(packet[0] & 0x0f) * 4;
+    let total_len = usize::from(u16::from_be_bytes([packet[2], packet[3]]));
+    if header_len < IPV4_HEADER_LEN || total_len < header_len || total_len > packet.len() {
+        return None;
+    }
+    if u16::from_be_bytes([packet[6], packet[7]]) & 0x3fff != 0 {
+        return None;
+    }
+    Some((header_len, total_len))
+}
+
+pub fn ethernet_frame(destination: [u8; 6], source: [u8; 6], ethertype: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(ETHERNET_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&destination);
+    frame.extend_from_slice(&source);
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// An ARP packet for IPv4 over Ethernet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArpPacket {
+    pub operation: u16,
+    pub sender_mac: [u8; 6],
+    pub sender_ip: Ipv4Addr,
+    pub target_mac: [u8; 6],
+    pub target_ip: Ipv4Addr,
+}
+
+impl ArpPacket {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(ARP_PACKET_LEN);
+        packet.extend_from_slice(&ARP_ETHERNET_IPV4);
+        packet.extend_from_slice(&self.operation.to_be_bytes());
+        packet.extend_from_slice(&self.sender_mac);
+        packet.extend_from_slice(&self.sender_ip.octets());
+        packet.extend_from_slice(&self.target_mac);
+        packet.extend_from_slice(&self.target_ip.octets());
+        packet
+    }
+
+    pub fn decode(packet: &[u8]) -> Option<Self> {
+        let packet = packet.get(..ARP_PACKET_LEN)?;
+        if packet[..6] != ARP_ETHERNET_IPV4 {
+            return None;
+        }
+        let mut sender_mac = [0u8; 6];
+        sender_mac.copy_from_slice(&packet[8..14]);
+        let mut target_mac = [0u8; 6];
+        target_mac.copy_from_slice(&packet[18..24]);
+        Some(Self {
+            operation: u16::from_be_bytes([packet[6], packet[7]]),
+            sender_mac,
+            sender_ip: Ipv4Addr::new(packet[14], packet[15], packet[16], packet[17]),
+            target_mac,
+            target_ip: Ipv4Addr::new(packet[24], packet[25], packet[26], packet[27]),
+        })
+    }
+}
+
+/// Counters of the packet processor
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PacketStatistics {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    /// Packets dropped because the inbound queue was full
+    pub dropped: u64,
+    /// Packets dropped as truncated, corrupt or not TCP
+    pub malformed: u64,
+    /// Packets dropped because ARP never found their next hop, or too many waited on it
+    pub unresolved: u64,
+    /// Packets dropped because their traffic class had too many waiting to leave
+    pub overlimit: u64,
+    /// Packets dropped by connection tracking: beginning a flow with the table full, or
+    /// leaving a masqueraded network in a way that cannot be translated
+    pub untracked: u64,
+}
+
+#[derive(Debug)]
+struct ProcessorState {
+    running: bool,
+    ipv6_enabled: bool,
+    /// Bytes of packets the inbound queue holds at most
+    buffer_size: usize,
+    queued_bytes: usize,
+    inbound: VecDeque<Vec<u8>>,
+    statistics: PacketStatistics,
+    next_id: u16,
+    shaper: Shaper,
+    conntrack: ConnTrack,
+}
+
+/// Moves packets between the network and the service's sockets
+pub struct PacketProcessor {
+    state: Mutex<ProcessorState>,
+    interfaces: Arc<InterfaceManager>,
+}
+
+impl PacketProcessor {
+    pub fn new(interfaces: Arc<InterfaceManager>) -> Self {
+        Self {
+            interfaces,
+            state: Mutex::new(ProcessorState {
+                running: false,
+                ipv6_enabled: true,
+                buffer_size: DEFAULT_BUFFER_SIZE,
+                queued_bytes: 0,
+                inbound: VecDeque::new(),
+                statistics: PacketStatistics::default(),
+                next_id: 0,
+                shaper: Shaper::new(),
+                conntrack: ConnTrack::new(),
+            }),
+        }
+    }
+
+    pub fn start(&self) -> Result<(), ServiceError> {
+        self.state.lock().running = true;
+        Ok(())
+    }
+
+    /// Stop processing, dropping whatever is queued
+    pub fn stop(&self) -> Result<(), ServiceError> {
+        let mut state = self.state.lock();
+        state.running = false;
+        state.inbound.clear();
+        state.queued_bytes = 0;
+        state.shaper.clear();
+        state.conntrack.clear();
+        Ok(())
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state.lock().running
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        let state = self.state.lock();
+        state.queued_bytes <= state.buffer_size
+    }
+
+    pub fn set_buffer_size(&self, size: u32) -> Result<(), ServiceError> {
+        if size < 1500 {
+            return Err(ServiceError::InvalidState);
+        }
+        self.state.lock().buffer_size = size as usize;
+        Ok(())
+    }
+
+    pub fn set_ipv6_enabled(&self, enabled: bool) -> Result<(), ServiceError> {
+        self.state.lock().ipv6_enabled = enabled;
+        Ok(())
+    }
+
+    pub fn ipv6_enabled(&self) -> bool {
+        self.state.lock().ipv6_enabled
+    }
+
+    pub fn statistics(&self) -> PacketStatistics {
+        self.state.lock().statistics
+    }
+
+    /// Add a traffic class or change one
+    pub fn set_traffic_class(&self, class: &TrafficClass) -> Result<(), ServiceError> {
+        self.state.lock().shaper.set_class(class, crate::time::get_uptime_ms())
+    }
+
+    /// Remove a traffic class; its traffic falls back to the default class
+    pub fn remove_traffic_class(&self, class_id: u32) -> Result<(), ServiceError> {
+        self.state.lock().shaper.remove_class(class_id)
+    }
+
+    pub fn has_traffic_class(&self, class_id: u32) -> bool {
+        self.state.lock().shaper.has_class(class_id)
+    }
+
+    /// Cap how fast frames leave through an interface, or lift the cap
+    pub fn set_egress_rate(&self, interface: &str, limit: Option<RateLimit>) -> Result<(), ServiceError> {
+        self.state.lock().shaper.set_link_rate(interface, limit, crate::time::get_uptime_ms())
+    }
+
+    /// Frames the shaper holds back
+    pub fn shaped_frames(&self) -> usize {
+        self.state.lock().shaper.queued()
+    }
+
+    /// Masquerade the network `prefix_len` bits of `network` give behind `interface`: flows
+    /// from it leaving through the interface take the interface's address
+    pub fn set_masquerade(&self, network: Ipv4Addr, prefix_len: u8, interface: &str) -> Result<(), ServiceError> {
+        self.state.lock().conntrack.set_masquerade(network, prefix_len, interface)
+    }
+
+    pub fn remove_masquerade(&self, network: Ipv4Addr, prefix_len: u8) -> Result<(), ServiceError> {
+        self.state.lock().conntrack.remove_masquerade(network, prefix_len)
+    }
+
+    /// The flows connection tracking holds
+    pub fn connections(&self) -> Vec<TrackedConnection> {
+        self.state.lock().conntrack.connections(crate::time::get_uptime_ms())
+    }
+
+    pub fn connection_count(&self) -> usize {
+        self.state.lock().conntrack.len()
+    }
+
+    /// Where a packet for `destination` leaves from and goes to next, off loopback
+    pub fn route(&self, destination: Ipv4Addr) -> Result<Route, SocketError> {
+        self.interfaces.route(destination)
+    }
+
+    /// Send a segment in an IPv4 packet, in the default traffic class
+    pub fn transmit(&self, segment: &Segment) -> Result<(), SocketError> {
+        self.transmit_as(segment, DEFAULT_CLASS)
+    }
+
+    /// Send a segment in an IPv4 packet of traffic class `class`: looped back, shaped on its
+    /// way to the next hop of its route, or held until ARP resolves the next hop
+    pub fn transmit_as(&self, segment: &Segment, class: u32) -> Result<(), SocketError> {
+        let tcp = segment.encode();
+        let id = {
+            let mut state = self.state.lock();
+            let id = state.next_id;
+            state.next_id = id.wrapping_add(1);
+            id
+        };
+        let mut packet = Vec::with_capacity(IPV4_HEADER_LEN + tcp.len());
+        packet.push(0x45);
+        packet.push(0);
+        packet.extend_from_slice(&((IPV4_HEADER_LEN + tcp.len()) as u16).to_be_bytes());
+        packet.extend_from_slice(&id.to_be_bytes());
+        // Don't fragment
+        packet.extend_from_slice(&[0x40, 0]);
+        packet.push(DEFAULT_TTL);
+        packet.push(IPPROTO_TCP);
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(&segment.source.ip().octets());
+        packet.extend_from_slice(&segment.destination.ip().octets());
+        let checksum = internet_checksum(&[&packet]);
+        packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+        packet.extend_from_slice(&tcp);
+        self.transmit_packet(packet, class)
+    }
+
+    /// Send an IPv4 packet built elsewhere, such as one a private network routes out through
+    /// the service, in traffic class `class`. Packets leaving through an interface are
+    /// tracked, and translated when masqueraded
+    pub fn transmit_packet(&self, mut packet: Vec<u8>, class: u32) -> Result<(), SocketError> {
+        if ipv4_bounds(&packet).is_none() {
+            return Err(SocketError::Unsupported);
+        }
+        let destination = Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]);
+        let route = if destination.is_loopback() { None } else { Some(self.interfaces.route(destination)?) };
+        let mut state = self.state.lock();
+        if !state.running {
+            return Err(SocketError::NetworkDown);
+        }
+        state.statistics.packets_sent += 1;
+        let Some(route) = route else {
+            Self::enqueue(&mut state, packet);
+            return Ok(());
+        };
+        let now_ms = crate::time::get_uptime_ms();
+        if !state.conntrack.outbound(&mut packet, &route.interface, route.source, now_ms) {
+            state.statistics.untracked += 1;
+            return Ok(());
+        }
+        drop(state);
+        match self.interfaces.resolve(&route, packet, now_ms) {
+            Resolution::Send(mac, packet) => {
+                self.shape(&route.interface, class, ethernet_frame(mac, route.mac, ETHERTYPE_IPV4, &packet), now_ms);
+                Ok(())
+            }
+            Resolution::Queued { request: true } => self.request(&route.interface, route.mac, route.source, route.next_hop),
+            Resolution::Queued { request: false } => Ok(()),
+            Resolution::Dropped => {
+                self.state.lock().statistics.unresolved += 1;
+                Ok(())
+            }
+        }
+    }
+
+    /// Queue a frame with the shaper and send what it lets through
+    fn shape(&self, interface: &str, class: u32, frame: Vec<u8>, now_ms: u64) {
+        {
+            let mut state = self.state.lock();
+            if !state.shaper.enqueue(class, interface, frame) {
+                state.statistics.overlimit += 1;
+            }
+        }
+        self.release(now_ms);
+    }
+
+    /// Send the frames the shaper lets through by `now_ms`. A frame for an interface gone
+    /// down is lost like any other
+    fn release(&self, now_ms: u64) {
+        loop {
+            let Some((interface, frame)) = self.state.lock().shaper.dequeue(now_ms) else {
+                break;
+            };
+            let _ = self.interfaces.send_frame(&interface, frame);
+        }
+    }
+
+    /// Broadcast an ARP request for `address` on an interface
+    fn request(&self, interface: &str, mac: [u8; 6], source: Ipv4Addr, address: Ipv4Addr) -> Result<(), SocketError> {
+        let request = ArpPacket { operation: ARP_REQUEST, sender_mac: mac, sender_ip: source, target_mac: [0; 6], target_ip: address };
+        self.interfaces.send_frame(interface, ethernet_frame(BROADCAST_MAC, mac, ETHERTYPE_ARP, &request.encode()))
+    }
+
+    /// Ask again for next hops whose ARP requests went unanswered, drop the packets of those
+    /// given up on, forget the flows gone quiet, and send what the shaper has let through since
+    pub fn on_timer(&self, now_ms: u64) {
+        let expiry = self.interfaces.arp_timer(now_ms);
+        for (address, interface) in &expiry.retries {
+            if let Some((mac, Some(source))) = self.interfaces.addresses(interface) {
+                let _ = self.request(interface, mac, source, *address);
+            }
+        }
+        {
+            let mut state = self.state.lock();
+            state.statistics.unresolved += expiry.dropped as u64;
+            state.conntrack.expire(now_ms);
+        }
+        self.release(now_ms);
+    }
+
+    /// Take a frame an interface's driver received. IPv4 packets for the interface join the
+    /// inbound queue; ARP packets teach the cache, release what waited on their sender and,
+    /// when they ask for the interface's address, are answered
+    pub fn receive_frame(&self, interface: &str, frame: &[u8]) {
+        if !self.interfaces.frame_received(interface, frame.len()) {
+            return;
+        }
+        let Some((mac, address)) = self.interfaces.addresses(interface) else {
+            return;
+        };
+        if frame.len() < ETHERNET_HEADER_LEN || (frame[..6] != mac && frame[..6] != BROADCAST_MAC) {
+            return;
+        }
+        let payload = &frame[ETHERNET_HEADER_LEN..];
+        match u16::from_be_bytes([frame[12], frame[13]]) {
+            ETHERTYPE_IPV4 => self.deliver(payload.to_vec()),
+            ETHERTYPE_ARP => match ArpPacket::decode(payload) {
+                Some(arp) => self.receive_arp(interface, mac, address, &arp),
+                None => self.state.lock().statistics.malformed += 1,
+            },
+            _ => {}
+        }
+    }
+
+    fn receive_arp(&self, interface: &str, mac: [u8; 6], address: Option<Ipv4Addr>, arp: &ArpPacket) {
+        // Another host claiming our address is not believed, and probes from hosts without one
+        // have nothing to teach
+        if Some(arp.sender_ip) == address || arp.sender_ip.is_unspecified() {
+            return;
+        }
+        // A host asking for our address is added, as we are about to talk to it. Others,
+        // gratuitous announcements included, only refresh entries already held
+        let for_us = Some(arp.target_ip) == address;
+        let waiting = self.interfaces.arp_learn(arp.sender_ip, arp.sender_mac, interface, for_us, crate::time::get_uptime_ms());
+        if let (ARP_REQUEST, true, Some(address)) = (arp.operation, for_us, address) {
+            let reply = ArpPacket { operation: ARP_REPLY, sender_mac: mac, sender_ip: address, target_mac: arp.sender_mac, target_ip: arp.sender_ip };
+            let _ = self.interfaces.send_frame(interface, ethernet_frame(arp.sender_mac, mac, ETHERTYPE_ARP, &reply.encode()));
+        }
+        for packet in waiting {
+            let _ = self.interfaces.send_frame(interface, ethernet_frame(arp.sender_mac, mac, ETHERTYPE_IPV4, &packet));
+        }
+    }
+
+    /// Queue a packet received from the network once connection tracking has taken it,
+    /// translating replies to masqueraded flows back to their private destination
+    pub fn deliver(&self, mut packet: Vec<u8>) {
+        let mut state = self.state.lock();
+        if !state.running {
+            return;
+        }
+        if !state.conntrack.inbound(&mut packet, crate::time::get_uptime_ms()) {
+            state.statistics.untracked += 1;
+            return;
+        }
+        Self::enqueue(&mut state, packet);
+    }
+
+    fn enqueue(state: &mut ProcessorState, packet: Vec<u8>) {
+        if state.queued_bytes + packet.len() > state.buffer_size {
+            state.statistics.dropped += 1;
+            return;
+        }
+        state.queued_bytes += packet.len();
+        state.inbound.push_back(packet);
+    }
+
+    /// The next well-formed TCP segment received, skipping packets that are not
+    pub fn next_segment(&self) -> Option<Segment> {
+        loop {
+            let packet = {
+                let mut state = self.state.lock();
+                let packet = state.inbound.pop_front()?;
+                state.queued_bytes -= packet.len();
+                packet
+            };
+            let segment = Self::parse(&packet);
+            let mut state = self.state.lock();
+            match segment {
+                Some(segment) => {
+                    state.statistics.packets_received += 1;
+                    return Some(segment);
+                }
+                None => state.statistics.malformed += 1,
+            }
+        }
+    }
+
+    fn parse(packet: &[u8]) -> Option<Segment> {
+        let (header_len, total_len) = ipv4_bounds(packet)?;
+        if packet[9] != IPPROTO_TCP || internet_checksum(&[&packet[..header_len]]) != 0 {
+            return None;
+        }
+        let source = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+        let destination = Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]);
+        Segment::decode(source, destination, &packet[header_len..total_len])
+    }
+}
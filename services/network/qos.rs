@@ -0,0 +1,204 @@
+//! Egress traffic shaping for rae-networkd
+//! Frames leaving through the interfaces queue by traffic class. Classes are served in order
+//! of priority, first come first served within one, so interactive traffic goes ahead of bulk
+//! transfers whenever the link is the bottleneck. A class may have a token bucket capping its
+//! rate, and an interface one capping the whole link: the frame at the head of a class waits
+//! while either bucket lacks the tokens for it, and lower classes may go meanwhile. Buckets
+//! fill with time, so what they hold back leaves on a later send or timer tick.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::super::contracts::network::{RateLimit, TrafficClass};
+use super::super::manager::ServiceError;
+
+/// The class of traffic not assigned to another, which cannot be removed
+pub const DEFAULT_CLASS: u32 = 0;
+/// Priority of the default class, leaving room above and below it
+pub const DEFAULT_PRIORITY: u8 = 4;
+/// Frames a class holds at most; more are dropped
+pub const CLASS_QUEUE_LEN: usize = 256;
+pub const MAX_CLASSES: usize = 64;
+
+/// Tokens are kept in thousandths of a byte, so a slow bucket still fills a little each
+/// millisecond
+const MILLI: u64 = 1000;
+
+/// Bytes a bucket lets through at a steady rate, and in a burst after a quiet spell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: u64,
+    last_ms: u64,
+}
+
+impl TokenBucket {
+    /// A full bucket
+    pub fn new(limit: RateLimit, now_ms: u64) -> Self {
+        Self { limit, tokens: u64::from(limit.burst_bytes) * MILLI, last_ms: now_ms }
+    }
+
+    fn refill(&mut self, now_ms: u64) {
+        let elapsed = now_ms.saturating_sub(self.last_ms);
+        self.last_ms = self.last_ms.max(now_ms);
+        let capacity = u64::from(self.limit.burst_bytes) * MILLI;
+        self.tokens = self.tokens.saturating_add(elapsed.saturating_mul(self.limit.bytes_per_second)).min(capacity);
+    }
+
+    /// What a frame of `length` bytes costs. One larger than the burst costs the whole
+    /// burst, or it could never go
+    fn cost(&self, length: usize) -> u64 {
+        (length as u64).min(u64::from(self.limit.burst_bytes)) * MILLI
+    }
+
+    /// Whether there are the tokens for `length` bytes by `now_ms`
+    pub fn allows(&mut self, length: usize, now_ms: u64) -> bool {
+        self.refill(now_ms);
+        self.tokens >= self.cost(length)
+    }
+
+    /// Spend the tokens for `length` bytes, which `allows` has checked there are
+    pub fn take(&mut self, length: usize) {
+        self.tokens = self.tokens.saturating_sub(self.cost(length));
+    }
+}
+
+fn checked_limit(limit: Option<RateLimit>) -> Result<Option<RateLimit>, ServiceError> {
+    match limit {
+        Some(limit) if limit.bytes_per_second == 0 || limit.burst_bytes == 0 => Err(ServiceError::InvalidState),
+        limit => Ok(limit),
+    }
+}
+
+#[derive(Debug)]
+struct Class {
+    priority: u8,
+    bucket: Option<TokenBucket>,
+    /// Frames waiting, with the interface each leaves through
+    queue: VecDeque<(String, Vec<u8>)>,
+}
+
+impl Class {
+    fn new(priority: u8, limit: Option<RateLimit>, now_ms: u64) -> Self {
+        Self { priority, bucket: limit.map(|limit| TokenBucket::new(limit, now_ms)), queue: VecDeque::new() }
+    }
+}
+
+/// The egress queues of every interface
+#[derive(Debug)]
+pub struct Shaper {
+    classes: BTreeMap<u32, Class>,
+    links: BTreeMap<String, TokenBucket>,
+}
+
+impl Default for Shaper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shaper {
+    pub fn new() -> Self {
+        let mut classes = BTreeMap::new();
+        classes.insert(DEFAULT_CLASS, Class::new(DEFAULT_PRIORITY, None, 0));
+        Self { classes, links: BTreeMap::new() }
+    }
+
+    /// Add a class, or change the priority and rate limit of one. A changed limit starts
+    /// with a full bucket
+    pub fn set_class(&mut self, class: &TrafficClass, now_ms: u64) -> Result<(), ServiceError> {
+        let limit = checked_limit(class.rate_limit)?;
+        if !self.classes.contains_key(&class.class_id) && self.classes.len() >= MAX_CLASSES {
+            return Err(ServiceError::ResourceLimitExceeded);
+        }
+        let entry = self.classes.entry(class.class_id).or_insert_with(|| Class::new(class.priority, limit, now_ms));
+        entry.priority = class.priority;
+        if entry.bucket.map(|bucket| bucket.limit) != limit {
+            entry.bucket = limit.map(|limit| TokenBucket::new(limit, now_ms));
+        }
+        Ok(())
+    }
+
+    /// Remove a class, moving the frames it held to the back of the default class
+    pub fn remove_class(&mut self, class_id: u32) -> Result<(), ServiceError> {
+        if class_id == DEFAULT_CLASS {
+            return Err(ServiceError::InvalidState);
+        }
+        let class = self.classes.remove(&class_id).ok_or(ServiceError::ServiceNotFound)?;
+        if let Some(default) = self.classes.get_mut(&DEFAULT_CLASS) {
+            default.queue.extend(class.queue);
+        }
+        Ok(())
+    }
+
+    pub fn has_class(&self, class_id: u32) -> bool {
+        self.classes.contains_key(&class_id)
+    }
+
+    /// Cap how fast frames leave through `interface`, or lift the cap
+    pub fn set_link_rate(&mut self, interface: &str, limit: Option<RateLimit>, now_ms: u64) -> Result<(), ServiceError> {
+        match checked_limit(limit)? {
+            Some(limit) => self.links.insert(interface.into(), TokenBucket::new(limit, now_ms)),
+            None => self.links.remove(interface),
+        };
+        Ok(())
+    }
+
+    /// Queue a frame of class `class_id`, the default class when there is no such class, to
+    /// leave through `interface`. Returns whether there was room for it
+    pub fn enqueue(&mut self, class_id: u32, interface: &str, frame: Vec<u8>) -> bool {
+        let class_id = if self.classes.contains_key(&class_id) { class_id } else { DEFAULT_CLASS };
+        let Some(class) = self.classes.get_mut(&class_id) else {
+            return false;
+        };
+        if class.queue.len() >= CLASS_QUEUE_LEN {
+            return false;
+        }
+        class.queue.push_back((interface.into(), frame));
+        true
+    }
+
+    /// The next frame allowed to leave by `now_ms`, with its interface: the oldest of the
+    /// class of highest priority, the lowest id of equals, whose class and link both have
+    /// the tokens for it
+    pub fn dequeue(&mut self, now_ms: u64) -> Option<(String, Vec<u8>)> {
+        let mut order: Vec<(u8, u32)> = self.classes.iter().map(|(&id, class)| (class.priority, id)).collect();
+        order.sort_unstable();
+        for (_, id) in order {
+            let Some(class) = self.classes.get_mut(&id) else {
+                continue;
+            };
+            let Some((interface, frame)) = class.queue.front() else {
+                continue;
+            };
+            let length = frame.len();
+            if !class.bucket.as_mut().is_none_or(|bucket| bucket.allows(length, now_ms)) {
+                continue;
+            }
+            if !self.links.get_mut(interface.as_str()).is_none_or(|link| link.allows(length, now_ms)) {
+                continue;
+            }
+            if let Some(bucket) = class.bucket.as_mut() {
+                bucket.take(length);
+            }
+            if let Some(link) = self.links.get_mut(interface.as_str()) {
+                link.take(length);
+            }
+            return class.queue.pop_front();
+        }
+        None
+    }
+
+    /// Frames waiting to leave
+    pub fn queued(&self) -> usize {
+        self.classes.values().map(|class| class.queue.len()).sum()
+    }
+
+    /// Drop every frame waiting, keeping the classes and rates
+    pub fn clear(&mut self) {
+        for class in self.classes.values_mut() {
+            class.queue.clear();
+        }
+    }
+}
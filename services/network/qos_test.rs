@@ -0,0 +1,189 @@
+//! QoS Tests
+//! Shapes egress on a clock of the test's own: under a constrained link, frames of an
+//! interactive class leave ahead of bulk ones queued before them, a rate-limited class never
+//! sends more than its bucket allows while traffic of other classes goes past it, and
+//! segments a packet processor sends in a class are held back and released in priority order
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::net::{Ipv4Addr, SocketAddrV4};
+
+use super::super::contracts::network::{RateLimit, TrafficClass};
+use super::interface_manager::InterfaceManager;
+use super::packet_processor::{ethernet_frame, ArpPacket, PacketProcessor, ARP_REQUEST, BROADCAST_MAC, ETHERNET_HEADER_LEN, ETHERTYPE_ARP};
+use super::qos::{Shaper, DEFAULT_CLASS};
+use super::tcp::{Segment, ACK};
+use crate::serial::_print;
+
+const INTERFACE: &str = "eth0";
+const LOCAL_MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+const LOCAL_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 15);
+const PEER_MAC: [u8; 6] = [0x52, 0x55, 0x0a, 0x00, 0x02, 0x14];
+/// On the local network, so frames go straight to it
+const PEER: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(10, 0, 2, 20), 80);
+const IPV4_HEADER_LEN: usize = 20;
+const TCP_HEADER_LEN: usize = 20;
+
+const INTERACTIVE: u32 = 1;
+const BULK: u32 = 2;
+const CAPPED: u32 = 3;
+
+fn class(class_id: u32, priority: u8, rate_limit: Option<RateLimit>) -> TrafficClass {
+    TrafficClass { class_id, priority, rate_limit }
+}
+
+/// A frame of `length` bytes whose first byte tells it apart
+fn frame(tag: u8, length: usize) -> Vec<u8> {
+    let mut frame = alloc::vec![0u8; length];
+    frame[0] = tag;
+    frame
+}
+
+fn segment(port: u16, payload: &[u8]) -> Segment {
+    Segment {
+        source: SocketAddrV4::new(LOCAL_IP, port),
+        destination: PEER,
+        seq: 1,
+        ack: 1,
+        flags: ACK,
+        window: 65535,
+        payload: payload.to_vec(),
+    }
+}
+
+/// The source port of the TCP segment a frame carries
+fn source_port(frame: &[u8]) -> Option<u16> {
+    let tcp = frame.get(ETHERNET_HEADER_LEN + IPV4_HEADER_LEN..)?;
+    Some(u16::from_be_bytes([*tcp.first()?, *tcp.get(1)?]))
+}
+
+pub fn run_qos_tests() -> Result<(), &'static str> {
+    _print(format_args!("[QoS Test] Starting QoS tests...\n"));
+
+    // Test 1: With the link the bottleneck, interactive frames overtake bulk ones
+    _print(format_args!("[QoS Test] Test 1: Priority queuing...\n"));
+    let mut shaper = Shaper::new();
+    shaper.set_class(&class(INTERACTIVE, 1, None), 0).map_err(|_| "Interactive class not set")?;
+    shaper.set_class(&class(BULK, 6, None), 0).map_err(|_| "Bulk class not set")?;
+    // 10 kB/s with room for one 1000-byte frame at a time
+    let link = RateLimit { bytes_per_second: 10_000, burst_bytes: 1000 };
+    shaper.set_link_rate(INTERFACE, Some(link), 0).map_err(|_| "Link rate not set")?;
+    for tag in 0..8 {
+        shaper.enqueue(BULK, INTERFACE, frame(tag, 1000));
+    }
+    let first = shaper.dequeue(0).map(|(_, frame)| frame[0]);
+    if first != Some(0) || shaper.dequeue(0).is_some() {
+        return Err("Link let through more than its burst");
+    }
+    for tag in 100..103 {
+        shaper.enqueue(INTERACTIVE, INTERFACE, frame(tag, 1000));
+    }
+    // A frame's worth of tokens every 100 ms
+    let mut order = Vec::new();
+    for now in (100..=1000).step_by(100) {
+        while let Some((_, frame)) = shaper.dequeue(now) {
+            order.push(frame[0]);
+        }
+        if order.len() as u64 != now / 100 {
+            return Err("Link not held to its rate");
+        }
+    }
+    if order != [100, 101, 102, 1, 2, 3, 4, 5, 6, 7] {
+        return Err("Interactive frames not sent ahead of bulk ones");
+    }
+    _print(format_args!("[QoS Test] ✓ 3 interactive frames overtook 7 bulk ones at 10 kB/s\n"));
+
+    // Test 2: A capped class stays within its rate, and other classes pass it meanwhile
+    _print(format_args!("[QoS Test] Test 2: Rate limiting...\n"));
+    let mut shaper = Shaper::new();
+    let cap = RateLimit { bytes_per_second: 20_000, burst_bytes: 2000 };
+    // Above the default class, so only its cap lets the default class by
+    shaper.set_class(&class(CAPPED, 0, Some(cap)), 0).map_err(|_| "Capped class not set")?;
+    for _ in 0..200 {
+        shaper.enqueue(CAPPED, INTERFACE, frame(1, 500));
+    }
+    let mut sent = 0;
+    let mut capped_frames = 0;
+    for now in 0..=1000 {
+        if now % 100 == 0 {
+            shaper.enqueue(DEFAULT_CLASS, INTERFACE, frame(2, 500));
+        }
+        while let Some((_, frame)) = shaper.dequeue(now) {
+            if frame[0] == 1 {
+                sent += frame.len() as u64;
+                capped_frames += 1;
+            }
+        }
+        if sent > u64::from(cap.burst_bytes) + cap.bytes_per_second * now / 1000 {
+            return Err("Capped class sent past its rate");
+        }
+        // Default frames leave in the millisecond they come
+        if shaper.queued() != 200 - capped_frames {
+            return Err("Default traffic held behind the capped class");
+        }
+    }
+    if sent < cap.bytes_per_second - 500 {
+        return Err("Capped class held below its rate");
+    }
+    _print(format_args!("[QoS Test] ✓ Capped class sent {} bytes in 1 s at 20 kB/s with a 2 kB burst\n", sent));
+
+    // Test 3: A packet processor sends segments in their class, held to the link's rate
+    _print(format_args!("[QoS Test] Test 3: Shaping sent segments...\n"));
+    let interfaces = Arc::new(InterfaceManager::new());
+    interfaces.initialize().map_err(|_| "Interfaces did not initialize")?;
+    interfaces.add_interface(INTERFACE, LOCAL_MAC, 1500).map_err(|_| "Interface not added")?;
+    interfaces.set_address(INTERFACE, Some((LOCAL_IP, 24))).map_err(|_| "Address not set")?;
+    let processor = PacketProcessor::new(interfaces.clone());
+    processor.start().map_err(|_| "Packet processor did not start")?;
+    // The peer asking for our address teaches us its own
+    let request = ArpPacket { operation: ARP_REQUEST, sender_mac: PEER_MAC, sender_ip: *PEER.ip(), target_mac: [0; 6], target_ip: LOCAL_IP };
+    processor.receive_frame(INTERFACE, &ethernet_frame(BROADCAST_MAC, PEER_MAC, ETHERTYPE_ARP, &request.encode()));
+    interfaces.take_frames(INTERFACE);
+
+    processor.set_traffic_class(&class(INTERACTIVE, 1, None)).map_err(|_| "Interactive class not set")?;
+    processor.set_traffic_class(&class(BULK, 6, None)).map_err(|_| "Bulk class not set")?;
+    let payload = [0u8; 1000];
+    let frame_len = ETHERNET_HEADER_LEN + IPV4_HEADER_LEN + TCP_HEADER_LEN + payload.len();
+    // Room for one frame at a time, refilled far slower than the test runs
+    let link = RateLimit { bytes_per_second: 1, burst_bytes: frame_len as u32 };
+    processor.set_egress_rate(INTERFACE, Some(link)).map_err(|_| "Egress rate not set")?;
+    for port in 5000..5003 {
+        processor.transmit_as(&segment(port, &payload), BULK).map_err(|_| "Bulk send failed")?;
+    }
+    processor.transmit_as(&segment(6000, &payload), INTERACTIVE).map_err(|_| "Interactive send failed")?;
+    let sent: Vec<Option<u16>> = interfaces.take_frames(INTERFACE).iter().map(|frame| source_port(frame)).collect();
+    if sent != [Some(5000)] || processor.shaped_frames() != 3 {
+        return Err("Link let through more than its burst");
+    }
+    // Each tick far enough on to refill the bucket lets one more frame out
+    let start = crate::time::get_uptime_ms();
+    let mut released = Vec::new();
+    for tick in 1..=3u64 {
+        processor.on_timer(start + tick * 10_000_000);
+        released.extend(interfaces.take_frames(INTERFACE).iter().map(|frame| source_port(frame)));
+    }
+    if released != [Some(6000), Some(5001), Some(5002)] || processor.shaped_frames() != 0 {
+        return Err("Held segments not released one a tick, interactive first");
+    }
+    if processor.remove_traffic_class(DEFAULT_CLASS).is_ok() || processor.remove_traffic_class(BULK).is_err() || processor.has_traffic_class(BULK) {
+        return Err("Classes not removed as asked");
+    }
+    _print(format_args!("[QoS Test] ✓ Interactive segment overtook 2 held bulk segments\n"));
+
+    _print(format_args!("[QoS Test] ✓ All QoS tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for traffic shaping
+pub fn test_qos() {
+    _print(format_args!("[QoS Test] ===========================================\n"));
+    _print(format_args!("[QoS Test]                 QOS TESTS\n"));
+    _print(format_args!("[QoS Test] ===========================================\n"));
+
+    match run_qos_tests() {
+        Ok(_) => _print(format_args!("[QoS Test] ✓ All QoS tests PASSED\n")),
+        Err(e) => _print(format_args!("[QoS Test] ✗ QoS tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[QoS Test] ===========================================\n"));
+}
@@ -0,0 +1,609 @@
+//! Socket management for rae-networkd
+//! TCP sockets over `TcpConnection`s: listening sockets answer SYNs up to their backlog and
+//! hand out the connections that complete the handshake; connecting, sending and receiving
+//! block, driving the packet processor, until they can finish or the socket timeout passes.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::net::{Ipv4Addr, SocketAddrV4};
+use spin::Mutex;
+
+use super::super::contracts::error_codes;
+use super::super::contracts::network::{network_errors, IpAddress, SocketAddress, SocketDomain, SocketType};
+use super::super::manager::ServiceError;
+use super::packet_processor::PacketProcessor;
+use super::qos::DEFAULT_CLASS;
+use super::tcp::{Segment, TcpConnection, TcpError, TcpState, ACK, SYN};
+
+/// Return at once instead of waiting
+pub const MSG_DONTWAIT: u32 = 0x40;
+
+const IPPROTO_TCP: u32 = 6;
+const EPHEMERAL_FIRST: u16 = 49152;
+const EPHEMERAL_LAST: u16 = 65535;
+/// Rounds of delivering, timing and transmitting one poll runs before it yields
+const POLL_ROUNDS: usize = 64;
+const DEFAULT_TIMEOUT_MS: u64 = 30_000;
+const DEFAULT_MAX_SOCKETS: usize = 1024;
+
+/// Socket errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketError {
+    SocketNotFound,
+    TooManySockets,
+    Unsupported,
+    InvalidAddress,
+    AddressInUse,
+    InvalidState,
+    NotConnected,
+    ConnectionRefused,
+    ConnectionReset,
+    TimedOut,
+    WouldBlock,
+    NetworkUnreachable,
+    NetworkDown,
+}
+
+impl SocketError {
+    /// The contract's error code
+    pub fn code(self) -> u32 {
+        match self {
+            Self::SocketNotFound => network_errors::SOCKET_NOT_FOUND,
+            Self::TooManySockets => error_codes::RESOURCE_EXHAUSTED,
+            Self::Unsupported | Self::InvalidAddress | Self::InvalidState => error_codes::INVALID_REQUEST,
+            Self::AddressInUse => network_errors::ADDRESS_IN_USE,
+            Self::NotConnected => network_errors::NOT_CONNECTED,
+            Self::ConnectionRefused => network_errors::CONNECTION_REFUSED,
+            Self::ConnectionReset => network_errors::CONNECTION_RESET,
+            Self::TimedOut => network_errors::CONNECTION_TIMEOUT,
+            Self::WouldBlock => network_errors::WOULD_BLOCK,
+            Self::NetworkUnreachable => network_errors::NETWORK_UNREACHABLE,
+            Self::NetworkDown => error_codes::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+impl From<SocketError> for ServiceError {
+    fn from(error: SocketError) -> Self {
+        match error {
+            SocketError::SocketNotFound => ServiceError::ServiceNotFound,
+            SocketError::TooManySockets => ServiceError::ResourceLimitExceeded,
+            SocketError::NetworkUnreachable => ServiceError::IpcRoutingFailed,
+            SocketError::NetworkDown => ServiceError::InternalError,
+            _ => ServiceError::InvalidState,
+        }
+    }
+}
+
+fn connection_error(error: Option<TcpError>) -> SocketError {
+    match error {
+        Some(TcpError::TimedOut) => SocketError::TimedOut,
+        _ => SocketError::ConnectionReset,
+    }
+}
+
+#[derive(Debug)]
+enum SocketKind {
+    /// Created, and perhaps bound
+    Idle,
+    Listening {
+        backlog: usize,
+        /// Connections from SYNs answered, oldest first, by socket id
+        pending: VecDeque<u32>,
+    },
+    Connection(TcpConnection),
+}
+
+#[derive(Debug)]
+struct Socket {
+    local: Option<SocketAddrV4>,
+    kind: SocketKind,
+    /// Closed by its owner and kept only to finish the teardown; the id is no longer theirs
+    orphaned: bool,
+    /// Waiting in a listener's backlog, not yet accepted
+    embryonic: bool,
+    /// Traffic class its segments leave in; a listener's connections take its class
+    traffic_class: u32,
+}
+
+impl Socket {
+    fn connection(&self) -> Option<&TcpConnection> {
+        match &self.kind {
+            SocketKind::Connection(connection) => Some(connection),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Sockets {
+    sockets: BTreeMap<u32, Socket>,
+    next_id: u32,
+    next_port: u16,
+    /// Mixed into initial sequence numbers so that no two connections start alike
+    iss_counter: u32,
+    max_sockets: usize,
+}
+
+impl Sockets {
+    fn allocate_id(&mut self) -> Result<u32, SocketError> {
+        let open = self.sockets.values().filter(|socket| !socket.orphaned).count();
+        if open >= self.max_sockets {
+            return Err(SocketError::TooManySockets);
+        }
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        Ok(id)
+    }
+
+    /// A socket of the caller's: orphans are not
+    fn get(&mut self, socket_id: u32) -> Result<&mut Socket, SocketError> {
+        self.sockets
+            .get_mut(&socket_id)
+            .filter(|socket| !socket.orphaned && !socket.embryonic)
+            .ok_or(SocketError::SocketNotFound)
+    }
+
+    fn connection(&mut self, socket_id: u32) -> Result<&mut TcpConnection, SocketError> {
+        match &mut self.get(socket_id)?.kind {
+            SocketKind::Connection(connection) => Ok(connection),
+            _ => Err(SocketError::NotConnected),
+        }
+    }
+
+    /// Whether a socket already holds the port of `address`, on that address or all of them.
+    /// Connections in TIME-WAIT hold theirs until it ends
+    fn port_in_use(&self, address: SocketAddrV4) -> bool {
+        self.sockets.values().filter_map(|socket| socket.local).any(|local| {
+            local.port() == address.port()
+                && (local.ip() == address.ip() || local.ip().is_unspecified() || address.ip().is_unspecified())
+        })
+    }
+
+    fn ephemeral_port(&mut self, ip: Ipv4Addr) -> Result<u16, SocketError> {
+        for _ in EPHEMERAL_FIRST..=EPHEMERAL_LAST {
+            let port = self.next_port;
+            self.next_port = if port == EPHEMERAL_LAST { EPHEMERAL_FIRST } else { port + 1 };
+            if !self.port_in_use(SocketAddrV4::new(ip, port)) {
+                return Ok(port);
+            }
+        }
+        Err(SocketError::AddressInUse)
+    }
+
+    fn initial_sequence(&mut self, now_ms: u64) -> u32 {
+        self.iss_counter = self.iss_counter.wrapping_add(64_000);
+        // Clock-driven as RFC 793 has it: one tick every 4 microseconds
+        (now_ms as u32).wrapping_mul(250).wrapping_add(self.iss_counter)
+    }
+
+    /// Hand a received segment to its connection, or to a listener when it opens one
+    fn dispatch(&mut self, segment: Segment, now_ms: u64) -> Option<Segment> {
+        let existing = self.sockets.values_mut().find_map(|socket| match &mut socket.kind {
+            SocketKind::Connection(connection)
+                if connection.local() == segment.destination && connection.remote() == segment.source =>
+            {
+                Some(connection)
+            }
+            _ => None,
+        });
+        if let Some(connection) = existing {
+            connection.on_segment(&segment, now_ms);
+            return None;
+        }
+
+        let listener = self.sockets.iter().find_map(|(&id, socket)| match &socket.kind {
+            SocketKind::Listening { backlog, pending } if !socket.orphaned => {
+                let local = socket.local?;
+                let matches = local.port() == segment.destination.port()
+                    && (local.ip().is_unspecified() || *local.ip() == *segment.destination.ip());
+                matches.then_some((id, pending.len() < *backlog, socket.traffic_class))
+            }
+            _ => None,
+        });
+        match listener {
+            Some((listener, room, traffic_class)) if segment.has(SYN) && !segment.has(ACK) => {
+                // With the backlog full the SYN goes unanswered, and the peer tries again
+                if room {
+                    if let Ok(id) = self.allocate_id() {
+                        let iss = self.initial_sequence(now_ms);
+                        let connection = TcpConnection::accept(&segment, iss, now_ms);
+                        self.sockets.insert(id, Socket {
+                            local: Some(connection.local()),
+                            kind: SocketKind::Connection(connection),
+                            orphaned: false,
+                            embryonic: true,
+                            traffic_class,
+                        });
+                        if let Some(SocketKind::Listening { pending, .. }) = self.sockets.get_mut(&listener).map(|s| &mut s.kind) {
+                            pending.push_back(id);
+                        }
+                    }
+                }
+                None
+            }
+            _ => segment.reset_reply(),
+        }
+    }
+
+    /// Drop connections whose owner has closed them and that have finished, and those a
+    /// listener held that were reset before being accepted
+    fn reap(&mut self) {
+        let finished: Vec<u32> = self
+            .sockets
+            .iter()
+            .filter(|(_, socket)| {
+                (socket.orphaned || socket.embryonic)
+                    && socket.connection().is_some_and(|connection| connection.state() == TcpState::Closed)
+            })
+            .map(|(&id, _)| id)
+            .collect();
+        for id in &finished {
+            self.sockets.remove(id);
+        }
+        for socket in self.sockets.values_mut() {
+            if let SocketKind::Listening { pending, .. } = &mut socket.kind {
+                pending.retain(|id| !finished.contains(id));
+            }
+        }
+    }
+}
+
+fn inet_address(address: &SocketAddress) -> Result<SocketAddrV4, SocketError> {
+    match address {
+        SocketAddress::Inet { ip: IpAddress::V4(octets), port } => Ok(SocketAddrV4::new(Ipv4Addr::from(*octets), *port)),
+        _ => Err(SocketError::InvalidAddress),
+    }
+}
+
+pub fn socket_address(address: SocketAddrV4) -> SocketAddress {
+    SocketAddress::Inet { ip: IpAddress::V4(address.ip().octets()), port: address.port() }
+}
+
+/// The service's TCP sockets
+pub struct SocketManager {
+    state: Mutex<Sockets>,
+    processor: Arc<PacketProcessor>,
+    timeout_ms: Mutex<u64>,
+}
+
+impl SocketManager {
+    pub fn new(processor: Arc<PacketProcessor>) -> Self {
+        Self {
+            state: Mutex::new(Sockets {
+                sockets: BTreeMap::new(),
+                next_id: 1,
+                next_port: EPHEMERAL_FIRST,
+                iss_counter: 0,
+                max_sockets: DEFAULT_MAX_SOCKETS,
+            }),
+            processor,
+            timeout_ms: Mutex::new(DEFAULT_TIMEOUT_MS),
+        }
+    }
+
+    fn now_ms() -> u64 {
+        crate::time::get_uptime_ms()
+    }
+
+    pub fn set_max_sockets(&self, max_sockets: u32) -> Result<(), ServiceError> {
+        if max_sockets == 0 {
+            return Err(ServiceError::InvalidState);
+        }
+        self.state.lock().max_sockets = max_sockets as usize;
+        Ok(())
+    }
+
+    /// How long a blocking call waits before giving up
+    pub fn set_timeout_ms(&self, timeout_ms: u32) -> Result<(), ServiceError> {
+        if timeout_ms == 0 {
+            return Err(ServiceError::InvalidState);
+        }
+        *self.timeout_ms.lock() = u64::from(timeout_ms);
+        Ok(())
+    }
+
+    /// Sockets open, not counting those closed and still tearing down
+    pub fn socket_count(&self) -> usize {
+        self.state.lock().sockets.values().filter(|socket| !socket.orphaned && !socket.embryonic).count()
+    }
+
+    /// Sockets can only make progress while the packet processor runs
+    pub fn is_healthy(&self) -> bool {
+        self.processor.is_running()
+    }
+
+    /// Deliver the segments received, run the connections' timers and transmit what they have
+    /// to send, until there is nothing left to do for now
+    pub fn poll(&self) {
+        for _ in 0..POLL_ROUNDS {
+            let now = Self::now_ms();
+            let mut state = self.state.lock();
+            self.processor.on_timer(now);
+            let mut busy = self.deliver(&mut state, now);
+            let mut outgoing = Vec::new();
+            for socket in state.sockets.values_mut() {
+                let traffic_class = socket.traffic_class;
+                if let SocketKind::Connection(connection) = &mut socket.kind {
+                    connection.on_timer(now);
+                    outgoing.extend(connection.take_outgoing().map(|segment| (traffic_class, segment)));
+                }
+            }
+            busy |= !outgoing.is_empty();
+            // A segment that cannot be sent is lost like any other, and retransmitted. Looped
+            // back segments are taken as they arrive, so a window's worth never overflows the
+            // inbound queue
+            for (traffic_class, segment) in &outgoing {
+                let _ = self.processor.transmit_as(segment, *traffic_class);
+                self.deliver(&mut state, now);
+            }
+            state.reap();
+            if !busy {
+                break;
+            }
+        }
+    }
+
+    /// Dispatch every segment waiting in the packet processor, answering strays with resets
+    fn deliver(&self, state: &mut Sockets, now_ms: u64) -> bool {
+        let mut delivered = false;
+        while let Some(segment) = self.processor.next_segment() {
+            delivered = true;
+            if let Some(reset) = state.dispatch(segment, now_ms) {
+                let _ = self.processor.transmit(&reset);
+            }
+        }
+        delivered
+    }
+
+    /// Poll until `ready` has an answer, the timeout passes or, with `nonblocking`, at once
+    fn wait<T>(
+        &self,
+        nonblocking: bool,
+        mut ready: impl FnMut(&mut Sockets, u64) -> Option<Result<T, SocketError>>,
+    ) -> Result<T, SocketError> {
+        let deadline = Self::now_ms() + *self.timeout_ms.lock();
+        loop {
+            self.poll();
+            let now = Self::now_ms();
+            let answer = ready(&mut self.state.lock(), now);
+            if let Some(result) = answer {
+                self.poll();
+                return result;
+            }
+            if nonblocking {
+                return Err(SocketError::WouldBlock);
+            }
+            if now >= deadline {
+                return Err(SocketError::TimedOut);
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    pub fn create_socket(&self, domain: SocketDomain, socket_type: SocketType, protocol: u32) -> Result<u32, SocketError> {
+        if !matches!(domain, SocketDomain::Inet) || !matches!(socket_type, SocketType::Stream) || !matches!(protocol, 0 | IPPROTO_TCP) {
+            return Err(SocketError::Unsupported);
+        }
+        let mut state = self.state.lock();
+        let id = state.allocate_id()?;
+        let socket = Socket { local: None, kind: SocketKind::Idle, orphaned: false, embryonic: false, traffic_class: DEFAULT_CLASS };
+        state.sockets.insert(id, socket);
+        Ok(id)
+    }
+
+    pub fn bind_socket(&self, socket_id: u32, address: SocketAddress) -> Result<(), SocketError> {
+        let mut address = inet_address(&address)?;
+        let mut state = self.state.lock();
+        let socket = state.get(socket_id)?;
+        if socket.local.is_some() || !matches!(socket.kind, SocketKind::Idle) {
+            return Err(SocketError::InvalidState);
+        }
+        if address.port() == 0 {
+            address.set_port(state.ephemeral_port(*address.ip())?);
+        } else if state.port_in_use(address) {
+            return Err(SocketError::AddressInUse);
+        }
+        state.get(socket_id)?.local = Some(address);
+        Ok(())
+    }
+
+    pub fn listen_socket(&self, socket_id: u32, backlog: u32) -> Result<(), SocketError> {
+        let mut state = self.state.lock();
+        if state.get(socket_id)?.local.is_none() {
+            let port = state.ephemeral_port(Ipv4Addr::UNSPECIFIED)?;
+            state.get(socket_id)?.local = Some(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port));
+        }
+        let socket = state.get(socket_id)?;
+        let backlog = backlog.max(1) as usize;
+        match &mut socket.kind {
+            SocketKind::Idle => socket.kind = SocketKind::Listening { backlog, pending: VecDeque::new() },
+            SocketKind::Listening { backlog: current, .. } => *current = backlog,
+            SocketKind::Connection(_) => return Err(SocketError::InvalidState),
+        }
+        Ok(())
+    }
+
+    /// Wait for a connection to a listening socket to complete its handshake, returning its
+    /// new socket and the peer's address
+    pub fn accept_socket(&self, socket_id: u32, flags: u32) -> Result<(u32, SocketAddress), SocketError> {
+        self.wait(flags & MSG_DONTWAIT != 0, |state, _| {
+            let pending = match state.get(socket_id) {
+                Ok(socket) => match &socket.kind {
+                    SocketKind::Listening { pending, .. } => pending.clone(),
+                    _ => return Some(Err(SocketError::InvalidState)),
+                },
+                Err(error) => return Some(Err(error)),
+            };
+            let (id, peer) = pending.iter().find_map(|id| {
+                let connection = state.sockets.get(id)?.connection()?;
+                connection.is_connected().then_some((*id, connection.remote()))
+            })?;
+            if let Some(SocketKind::Listening { pending, .. }) = state.sockets.get_mut(&socket_id).map(|s| &mut s.kind) {
+                pending.retain(|&pending| pending != id);
+            }
+            if let Some(socket) = state.sockets.get_mut(&id) {
+                socket.embryonic = false;
+            }
+            Some(Ok((id, socket_address(peer))))
+        })
+    }
+
+    /// Open a connection, waiting for the handshake to complete
+    pub fn connect_socket(&self, socket_id: u32, address: SocketAddress) -> Result<(), SocketError> {
+        let remote = inet_address(&address)?;
+        if remote.ip().is_unspecified() || remote.port() == 0 {
+            return Err(SocketError::InvalidAddress);
+        }
+        {
+            let now = Self::now_ms();
+            let mut state = self.state.lock();
+            let socket = state.get(socket_id)?;
+            if !matches!(socket.kind, SocketKind::Idle) {
+                return Err(SocketError::InvalidState);
+            }
+            let bound = socket.local;
+            // Without a route a connection fails before a SYN goes out. Not bound to an address,
+            // it comes from that of the interface it leaves through
+            let source = match remote.ip().is_loopback() {
+                true => Ipv4Addr::LOCALHOST,
+                false => self.processor.route(*remote.ip())?.source,
+            };
+            let ip = match bound {
+                Some(local) if !local.ip().is_unspecified() => *local.ip(),
+                _ => source,
+            };
+            let port = match bound {
+                Some(local) => local.port(),
+                None => state.ephemeral_port(ip)?,
+            };
+            let local = SocketAddrV4::new(ip, port);
+            let iss = state.initial_sequence(now);
+            let socket = state.get(socket_id)?;
+            socket.local = Some(local);
+            socket.kind = SocketKind::Connection(TcpConnection::connect(local, remote, iss, now));
+        }
+
+        let result = self.wait(false, |state, _| {
+            let connection = match state.connection(socket_id) {
+                Ok(connection) => connection,
+                Err(error) => return Some(Err(error)),
+            };
+            match connection.state() {
+                TcpState::SynSent => None,
+                TcpState::Closed => Some(Err(match connection.error() {
+                    Some(TcpError::Reset) => SocketError::ConnectionRefused,
+                    error => connection_error(error),
+                })),
+                _ => Some(Ok(())),
+            }
+        });
+        if result.is_err() {
+            // The socket can be connected again, from scratch
+            if let Ok(socket) = self.state.lock().get(socket_id) {
+                if let SocketKind::Connection(connection) = &mut socket.kind {
+                    connection.abort();
+                }
+                socket.kind = SocketKind::Idle;
+            }
+            self.poll();
+        }
+        result
+    }
+
+    /// Send bytes, waiting for room in the send buffer. Returns how many were taken, which
+    /// may be fewer than offered
+    pub fn send_data(&self, socket_id: u32, data: Vec<u8>, flags: u32) -> Result<usize, SocketError> {
+        self.wait(flags & MSG_DONTWAIT != 0, |state, now| {
+            let connection = match state.connection(socket_id) {
+                Ok(connection) => connection,
+                Err(error) => return Some(Err(error)),
+            };
+            if connection.state() == TcpState::Closed {
+                return Some(Err(connection_error(connection.error())));
+            }
+            if data.is_empty() {
+                return Some(Ok(0));
+            }
+            if connection.send_space() == 0 {
+                return None;
+            }
+            Some(connection.send(&data, now).map_err(|_| SocketError::NotConnected))
+        })
+    }
+
+    /// Receive up to `max_length` bytes in order, waiting for some to arrive. Nothing means
+    /// the peer has closed its side and everything it sent has been read
+    pub fn receive_data(&self, socket_id: u32, max_length: usize, flags: u32) -> Result<Vec<u8>, SocketError> {
+        self.wait(flags & MSG_DONTWAIT != 0, |state, _| {
+            let connection = match state.connection(socket_id) {
+                Ok(connection) => connection,
+                Err(error) => return Some(Err(error)),
+            };
+            if connection.available() > 0 && max_length > 0 {
+                return Some(Ok(connection.receive(max_length)));
+            }
+            if connection.peer_closed() || max_length == 0 {
+                return Some(Ok(Vec::new()));
+            }
+            match connection.state() {
+                TcpState::Closed => Some(Err(connection_error(connection.error()))),
+                TcpState::SynSent | TcpState::SynReceived => Some(Err(SocketError::NotConnected)),
+                _ => None,
+            }
+        })
+    }
+
+    /// Send a socket's segments in traffic class `class_id`, which must have been set up
+    pub fn set_traffic_class(&self, socket_id: u32, class_id: u32) -> Result<(), SocketError> {
+        if !self.processor.has_traffic_class(class_id) {
+            return Err(SocketError::InvalidState);
+        }
+        self.state.lock().get(socket_id)?.traffic_class = class_id;
+        Ok(())
+    }
+
+    /// The peer a connected socket is connected to
+    pub fn peer_address(&self, socket_id: u32) -> Result<SocketAddress, SocketError> {
+        Ok(socket_address(self.state.lock().connection(socket_id)?.remote()))
+    }
+
+    /// Close a socket. A connection sends what is left and its FIN in the background; the id
+    /// is free straight away
+    pub fn close_socket(&self, socket_id: u32) -> Result<(), SocketError> {
+        {
+            let now = Self::now_ms();
+            let mut state = self.state.lock();
+            let socket = state.get(socket_id)?;
+            if let SocketKind::Connection(connection) = &mut socket.kind {
+                connection.close(now);
+                socket.orphaned = true;
+            } else if let Some(SocketKind::Listening { pending, .. }) = state.sockets.remove(&socket_id).map(|socket| socket.kind) {
+                // Connections not yet accepted are refused
+                for id in pending {
+                    if let Some(SocketKind::Connection(connection)) = state.sockets.get_mut(&id).map(|socket| &mut socket.kind) {
+                        connection.abort();
+                    }
+                }
+            }
+        }
+        self.poll();
+        Ok(())
+    }
+
+    /// Reset every connection and drop every socket
+    pub fn close_all_sockets(&self) -> Result<(), ServiceError> {
+        {
+            let mut state = self.state.lock();
+            for socket in state.sockets.values_mut() {
+                if let SocketKind::Connection(connection) = &mut socket.kind {
+                    connection.abort();
+                }
+            }
+        }
+        self.poll();
+        self.state.lock().sockets.clear();
+        Ok(())
+    }
+}
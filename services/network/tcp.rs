@@ -0,0 +1,662 @@
+//! TCP for rae-networkd
+//! Segments as they travel inside IPv4, and `TcpConnection`, one end of a connection: the
+//! three-way handshake, sequence and acknowledgement tracking, a retransmission queue with
+//! exponential backoff and fast retransmit on three duplicate ACKs, reassembly of segments
+//! that arrive out of order, and the FIN teardown in each direction, either of which may
+//! close first while the other keeps sending.
+//!
+//! A connection does no I/O of its own: it is fed the segments received for it and the time,
+//! and queues the segments it wants sent for the socket manager to take.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use core::net::{Ipv4Addr, SocketAddrV4};
+
+use super::packet_processor::internet_checksum;
+
+pub const FIN: u8 = 0x01;
+pub const SYN: u8 = 0x02;
+pub const RST: u8 = 0x04;
+pub const PSH: u8 = 0x08;
+pub const ACK: u8 = 0x10;
+
+pub const IPPROTO_TCP: u8 = 6;
+const HEADER_LEN: usize = 20;
+
+/// Largest payload of a segment
+pub const MSS: usize = 1460;
+/// Bytes received and not yet read that a connection holds, and so the most it advertises
+pub const RECEIVE_BUFFER: usize = 65535;
+/// Bytes written and not yet sent that a connection holds
+pub const SEND_BUFFER: usize = 65536;
+const INITIAL_RTO_MS: u64 = 1_000;
+const MAX_RTO_MS: u64 = 60_000;
+/// Retransmissions of one segment before the connection is given up
+const MAX_RETRANSMITS: u32 = 8;
+const DUPLICATE_ACK_THRESHOLD: u32 = 3;
+/// Twice the maximum segment lifetime, spent in TIME-WAIT
+pub const TIME_WAIT_MS: u64 = 60_000;
+
+/// `a` comes before `b` in sequence space, which wraps
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+fn seq_le(a: u32, b: u32) -> bool {
+    a == b || seq_lt(a, b)
+}
+
+/// A TCP segment with the addresses of the IPv4 packet carrying it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub source: SocketAddrV4,
+    pub destination: SocketAddrV4,
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: u8,
+    pub window: u16,
+    pub payload: Vec<u8>,
+}
+
+impl Segment {
+    pub fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Sequence space the segment takes: its payload, and one each for SYN and FIN
+    pub fn len(&self) -> u32 {
+        self.payload.len() as u32 + u32::from(self.has(SYN)) + u32::from(self.has(FIN))
+    }
+
+    /// The TCP header and payload, checksummed over the IPv4 pseudo-header
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        bytes.extend_from_slice(&self.source.port().to_be_bytes());
+        bytes.extend_from_slice(&self.destination.port().to_be_bytes());
+        bytes.extend_from_slice(&self.seq.to_be_bytes());
+        bytes.extend_from_slice(&self.ack.to_be_bytes());
+        bytes.push((HEADER_LEN as u8 / 4) << 4);
+        bytes.push(self.flags);
+        bytes.extend_from_slice(&self.window.to_be_bytes());
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+        bytes.extend_from_slice(&self.payload);
+        let checksum = checksum(&self.source, &self.destination, &bytes);
+        bytes[16..18].copy_from_slice(&checksum.to_be_bytes());
+        bytes
+    }
+
+    /// Parse the TCP part of an IPv4 packet from `source` to `destination`. `None` when it is
+    /// truncated or its checksum is wrong
+    pub fn decode(source: Ipv4Addr, destination: Ipv4Addr, bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER_LEN {
+            return None;
+        }
+        let word = |at: usize| u16::from_be_bytes([bytes[at], bytes[at + 1]]);
+        let long = |at: usize| u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+        let header_len = usize::from(bytes[12] >> 4) * 4;
+        if header_len < HEADER_LEN || header_len > bytes.len() {
+            return None;
+        }
+        let source = SocketAddrV4::new(source, word(0));
+        let destination = SocketAddrV4::new(destination, word(2));
+        if checksum(&source, &destination, bytes) != 0 {
+            return None;
+        }
+        Some(Self {
+            source,
+            destination,
+            seq: long(4),
+            ack: long(8),
+            flags: bytes[13],
+            window: word(14),
+            payload: bytes[header_len..].to_vec(),
+        })
+    }
+
+    /// The reset answering a segment that reached no connection
+    pub fn reset_reply(&self) -> Option<Segment> {
+        if self.has(RST) {
+            return None;
+        }
+        let (seq, ack, flags) = if self.has(ACK) {
+            (self.ack, 0, RST)
+        } else {
+            (0, self.seq.wrapping_add(self.len()), RST | ACK)
+        };
+        Some(Segment {
+            source: self.destination,
+            destination: self.source,
+            seq,
+            ack,
+            flags,
+            window: 0,
+            payload: Vec::new(),
+        })
+    }
+}
+
+fn checksum(source: &SocketAddrV4, destination: &SocketAddrV4, tcp: &[u8]) -> u16 {
+    let mut pseudo = [0u8; 12];
+    pseudo[0..4].copy_from_slice(&source.ip().octets());
+    pseudo[4..8].copy_from_slice(&destination.ip().octets());
+    pseudo[9] = IPPROTO_TCP;
+    pseudo[10..12].copy_from_slice(&(tcp.len() as u16).to_be_bytes());
+    internet_checksum(&[&pseudo, tcp])
+}
+
+/// States of RFC 793, less LISTEN: a listening socket is not a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpState {
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+    Closed,
+}
+
+/// How a connection ended other than by closing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpError {
+    /// The peer reset it, or refused it when connecting
+    Reset,
+    /// A segment went unacknowledged through every retransmission
+    TimedOut,
+}
+
+/// A segment sent and not yet acknowledged
+#[derive(Debug)]
+struct Unacked {
+    seq: u32,
+    flags: u8,
+    payload: Vec<u8>,
+}
+
+impl Unacked {
+    fn end(&self) -> u32 {
+        let control = u32::from(self.flags & SYN != 0) + u32::from(self.flags & FIN != 0);
+        self.seq.wrapping_add(self.payload.len() as u32 + control)
+    }
+}
+
+/// One end of a TCP connection
+#[derive(Debug)]
+pub struct TcpConnection {
+    local: SocketAddrV4,
+    remote: SocketAddrV4,
+    state: TcpState,
+    error: Option<TcpError>,
+
+    /// Oldest sequence number not yet acknowledged
+    snd_una: u32,
+    /// Next sequence number to send
+    snd_nxt: u32,
+    /// Window the peer last advertised
+    snd_wnd: u32,
+    /// Bytes written and not yet sent
+    send_buffer: VecDeque<u8>,
+    /// Segments sent and not yet acknowledged, oldest first
+    unacked: VecDeque<Unacked>,
+    /// Closed for writing: a FIN follows the data still to send
+    fin_pending: bool,
+    /// Sequence number of the FIN sent
+    fin_seq: Option<u32>,
+    rto_ms: u64,
+    retransmits: u32,
+    /// When the oldest unacknowledged segment is retransmitted, or the closed window probed
+    timer_ms: Option<u64>,
+    duplicate_acks: u32,
+
+    /// Initial sequence number of the peer, which out-of-order segments are keyed from
+    irs: u32,
+    /// Next sequence number expected from the peer
+    rcv_nxt: u32,
+    /// Bytes received in order and not yet read
+    receive_buffer: VecDeque<u8>,
+    /// Segments received ahead of `rcv_nxt`, by their offset from `irs`
+    out_of_order: BTreeMap<u32, Vec<u8>>,
+    /// Sequence number of a FIN received ahead of data still missing
+    peer_fin: Option<u32>,
+    /// The peer has closed for writing
+    peer_closed: bool,
+    /// Window in the last segment sent
+    advertised_window: u32,
+    time_wait_until: Option<u64>,
+
+    outgoing: VecDeque<Segment>,
+}
+
+impl TcpConnection {
+    fn new(local: SocketAddrV4, remote: SocketAddrV4, state: TcpState, iss: u32) -> Self {
+        Self {
+            local,
+            remote,
+            state,
+            error: None,
+            snd_una: iss,
+            snd_nxt: iss,
+            snd_wnd: 0,
+            send_buffer: VecDeque::new(),
+            unacked: VecDeque::new(),
+            fin_pending: false,
+            fin_seq: None,
+            rto_ms: INITIAL_RTO_MS,
+            retransmits: 0,
+            timer_ms: None,
+            duplicate_acks: 0,
+            irs: 0,
+            rcv_nxt: 0,
+            receive_buffer: VecDeque::new(),
+            out_of_order: BTreeMap::new(),
+            peer_fin: None,
+            peer_closed: false,
+            advertised_window: 0,
+            time_wait_until: None,
+            outgoing: VecDeque::new(),
+        }
+    }
+
+    /// Open a connection to `remote`, sending a SYN with sequence number `iss`
+    pub fn connect(local: SocketAddrV4, remote: SocketAddrV4, iss: u32, now_ms: u64) -> Self {
+        let mut connection = Self::new(local, remote, TcpState::SynSent, iss);
+        connection.send_tracked(SYN, Vec::new(), now_ms);
+        connection
+    }
+
+    /// Answer `syn`, received by a listening socket, with a SYN-ACK
+    pub fn accept(syn: &Segment, iss: u32, now_ms: u64) -> Self {
+        let mut connection = Self::new(syn.destination, syn.source, TcpState::SynReceived, iss);
+        connection.irs = syn.seq;
+        connection.rcv_nxt = syn.seq.wrapping_add(1);
+        connection.snd_wnd = u32::from(syn.window);
+        connection.send_tracked(SYN | ACK, Vec::new(), now_ms);
+        connection
+    }
+
+    pub fn local(&self) -> SocketAddrV4 {
+        self.local
+    }
+
+    pub fn remote(&self) -> SocketAddrV4 {
+        self.remote
+    }
+
+    pub fn state(&self) -> TcpState {
+        self.state
+    }
+
+    pub fn error(&self) -> Option<TcpError> {
+        self.error
+    }
+
+    /// The handshake has completed, and the connection has not been reset
+    pub fn is_connected(&self) -> bool {
+        !matches!(self.state, TcpState::SynSent | TcpState::SynReceived | TcpState::Closed)
+    }
+
+    /// Bytes received in order and waiting to be read
+    pub fn available(&self) -> usize {
+        self.receive_buffer.len()
+    }
+
+    /// The peer closed for writing and everything it sent has arrived
+    pub fn peer_closed(&self) -> bool {
+        self.peer_closed
+    }
+
+    /// Room for more bytes to `send`
+    pub fn send_space(&self) -> usize {
+        SEND_BUFFER - self.send_buffer.len()
+    }
+
+    /// Segments to transmit, in order
+    pub fn take_outgoing(&mut self) -> impl Iterator<Item = Segment> + '_ {
+        self.outgoing.drain(..)
+    }
+
+    fn receive_window(&self) -> u32 {
+        (RECEIVE_BUFFER - self.receive_buffer.len()) as u32
+    }
+
+    fn queue(&mut self, seq: u32, flags: u8, payload: Vec<u8>) {
+        // Everything after the first SYN acknowledges what has been received
+        let flags = if self.state == TcpState::SynSent { flags } else { flags | ACK };
+        let window = self.receive_window();
+        self.advertised_window = window;
+        self.outgoing.push_back(Segment {
+            source: self.local,
+            destination: self.remote,
+            seq,
+            ack: if flags & ACK != 0 { self.rcv_nxt } else { 0 },
+            flags,
+            window: window.min(u32::from(u16::MAX)) as u16,
+            payload,
+        });
+    }
+
+    fn send_ack(&mut self) {
+        self.queue(self.snd_nxt, ACK, Vec::new());
+    }
+
+    /// Send a segment taking sequence space, keeping it until acknowledged
+    fn send_tracked(&mut self, flags: u8, payload: Vec<u8>, now_ms: u64) {
+        let unacked = Unacked { seq: self.snd_nxt, flags, payload };
+        self.queue(unacked.seq, flags, unacked.payload.clone());
+        self.snd_nxt = unacked.end();
+        self.unacked.push_back(unacked);
+        self.timer_ms.get_or_insert(now_ms + self.rto_ms);
+    }
+
+    fn retransmit_oldest(&mut self) {
+        if let Some(oldest) = self.unacked.front() {
+            let (seq, flags, payload) = (oldest.seq, oldest.flags, oldest.payload.clone());
+            self.queue(seq, flags, payload);
+        }
+    }
+
+    fn close_with(&mut self, error: Option<TcpError>) {
+        self.state = TcpState::Closed;
+        self.error = error;
+        self.unacked.clear();
+        self.send_buffer.clear();
+        self.timer_ms = None;
+    }
+
+    /// Queue bytes to send, returning how many fit in the send buffer
+    pub fn send(&mut self, data: &[u8], now_ms: u64) -> Result<usize, TcpState> {
+        if !matches!(self.state, TcpState::Established | TcpState::CloseWait) || self.fin_pending {
+            return Err(self.state);
+        }
+        let accepted = data.len().min(self.send_space());
+        self.send_buffer.extend(&data[..accepted]);
+        self.transmit(now_ms);
+        Ok(accepted)
+    }
+
+    /// Take up to `max` bytes received in order, opening the window again once reading has
+    /// freed enough of it to be worth telling the peer
+    pub fn receive(&mut self, max: usize) -> Vec<u8> {
+        let count = max.min(self.receive_buffer.len());
+        let data: Vec<u8> = self.receive_buffer.drain(..count).collect();
+        let opened = self.receive_window().saturating_sub(self.advertised_window);
+        if opened as usize >= 2 * MSS && self.is_connected() && !self.peer_closed {
+            self.send_ack();
+        }
+        data
+    }
+
+    /// Close for writing: a FIN follows the data already written. The peer may go on sending
+    /// until it closes too
+    pub fn close(&mut self, now_ms: u64) {
+        match self.state {
+            TcpState::SynSent => self.close_with(None),
+            TcpState::SynReceived => self.abort(),
+            TcpState::Established => self.state = TcpState::FinWait1,
+            TcpState::CloseWait => self.state = TcpState::LastAck,
+            _ => return,
+        }
+        self.fin_pending = true;
+        self.transmit(now_ms);
+    }
+
+    /// Reset the connection, telling the peer
+    pub fn abort(&mut self) {
+        if self.state != TcpState::Closed {
+            if self.state != TcpState::SynSent {
+                self.queue(self.snd_nxt, RST, Vec::new());
+            }
+            self.close_with(None);
+        }
+    }
+
+    /// Send what the peer's window has room for, then the FIN once the data is all out
+    fn transmit(&mut self, now_ms: u64) {
+        if !matches!(self.state, TcpState::Established | TcpState::CloseWait | TcpState::FinWait1 | TcpState::LastAck) {
+            return;
+        }
+        loop {
+            let in_flight = self.snd_nxt.wrapping_sub(self.snd_una);
+            let room = self.snd_wnd.saturating_sub(in_flight) as usize;
+            let length = room.min(MSS).min(self.send_buffer.len());
+            if length == 0 {
+                break;
+            }
+            let payload: Vec<u8> = self.send_buffer.drain(..length).collect();
+            self.send_tracked(PSH, payload, now_ms);
+        }
+        if self.fin_pending && self.fin_seq.is_none() && self.send_buffer.is_empty() {
+            self.fin_seq = Some(self.snd_nxt);
+            self.send_tracked(FIN, Vec::new(), now_ms);
+        }
+        // With the window closed and nothing in flight to bring an ACK, probe it on the timer
+        if self.snd_wnd == 0 && !self.send_buffer.is_empty() && self.unacked.is_empty() {
+            self.timer_ms.get_or_insert(now_ms + self.rto_ms);
+        }
+    }
+
+    /// Handle a segment received for this connection
+    pub fn on_segment(&mut self, segment: &Segment, now_ms: u64) {
+        if self.state == TcpState::Closed {
+            return;
+        }
+        if segment.has(RST) {
+            let acceptable = match self.state {
+                TcpState::SynSent => segment.has(ACK) && segment.ack == self.snd_nxt,
+                _ => self.in_window(segment.seq),
+            };
+            if acceptable {
+                self.close_with(Some(TcpError::Reset));
+            }
+            return;
+        }
+
+        match self.state {
+            TcpState::SynSent => {
+                if segment.has(SYN) && segment.has(ACK) && segment.ack == self.snd_nxt {
+                    self.irs = segment.seq;
+                    self.rcv_nxt = segment.seq.wrapping_add(1);
+                    self.state = TcpState::Established;
+                    self.process_ack(segment, now_ms);
+                    self.send_ack();
+                    self.transmit(now_ms);
+                }
+                return;
+            }
+            _ if segment.has(SYN) => {
+                // The peer missed our SYN-ACK or ACK and sent its SYN again
+                if self.state == TcpState::SynReceived {
+                    self.retransmit_oldest();
+                } else {
+                    self.send_ack();
+                }
+                return;
+            }
+            _ if !segment.has(ACK) => return,
+            TcpState::SynReceived => {
+                if segment.ack != self.snd_nxt {
+                    return;
+                }
+                self.state = TcpState::Established;
+            }
+            _ => {}
+        }
+
+        self.process_ack(segment, now_ms);
+        if self.state != TcpState::Closed {
+            self.process_payload(segment, now_ms);
+            self.transmit(now_ms);
+        }
+    }
+
+    /// Whether `seq` falls in the receive window, or is the next expected when it is closed
+    fn in_window(&self, seq: u32) -> bool {
+        seq == self.rcv_nxt || (seq_le(self.rcv_nxt, seq) && seq_lt(seq, self.rcv_nxt.wrapping_add(self.receive_window())))
+    }
+
+    fn process_ack(&mut self, segment: &Segment, now_ms: u64) {
+        let ack = segment.ack;
+        if seq_lt(self.snd_una, ack) && seq_le(ack, self.snd_nxt) {
+            self.snd_una = ack;
+            while let Some(oldest) = self.unacked.front_mut() {
+                if seq_le(oldest.end(), ack) {
+                    self.unacked.pop_front();
+                } else {
+                    // Part of the segment arrived: only the rest is sent again
+                    if seq_lt(oldest.seq, ack) && oldest.flags & SYN == 0 {
+                        let acked = ack.wrapping_sub(oldest.seq) as usize;
+                        oldest.payload.drain(..acked.min(oldest.payload.len()));
+                        oldest.seq = ack;
+                    }
+                    break;
+                }
+            }
+            self.snd_wnd = u32::from(segment.window);
+            self.duplicate_acks = 0;
+            self.retransmits = 0;
+            self.rto_ms = INITIAL_RTO_MS;
+            self.timer_ms = (!self.unacked.is_empty()).then_some(now_ms + self.rto_ms);
+
+            if self.fin_seq.is_some_and(|fin| seq_lt(fin, ack)) {
+                match self.state {
+                    TcpState::FinWait1 => self.state = TcpState::FinWait2,
+                    TcpState::Closing => self.enter_time_wait(now_ms),
+                    TcpState::LastAck => self.close_with(None),
+                    _ => {}
+                }
+            }
+        } else if ack == self.snd_una {
+            let window = u32::from(segment.window);
+            let window_update = window != self.snd_wnd;
+            self.snd_wnd = window;
+            // The same ACK again, for nothing new, while data is outstanding: a segment after
+            // the one it acknowledges up to arrived, and that one is likely lost
+            if segment.payload.is_empty() && !segment.has(FIN) && !window_update && !self.unacked.is_empty() {
+                self.duplicate_acks += 1;
+                if self.duplicate_acks == DUPLICATE_ACK_THRESHOLD {
+                    self.retransmit_oldest();
+                }
+            }
+        }
+    }
+
+    fn process_payload(&mut self, segment: &Segment, now_ms: u64) {
+        let fin = segment.has(FIN);
+        if segment.payload.is_empty() && !fin {
+            // An old segment, such as a window probe, is answered with where we are
+            if seq_lt(segment.seq, self.rcv_nxt) {
+                self.send_ack();
+            }
+            return;
+        }
+        if !matches!(self.state, TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2) {
+            // Everything up to the peer's FIN is in: this is a retransmission
+            self.send_ack();
+            return;
+        }
+
+        let mut seq = segment.seq;
+        let mut payload = &segment.payload[..];
+        if seq_lt(seq, self.rcv_nxt) {
+            // Drop what already arrived, keeping any new part
+            let old = self.rcv_nxt.wrapping_sub(seq) as usize;
+            if old > payload.len() {
+                self.send_ack();
+                return;
+            }
+            payload = &payload[old..];
+            seq = self.rcv_nxt;
+        }
+
+        let window = self.receive_window() as usize;
+        let offset = seq.wrapping_sub(self.rcv_nxt) as usize;
+        if offset == 0 {
+            let accepted = payload.len().min(window);
+            self.receive_buffer.extend(&payload[..accepted]);
+            self.rcv_nxt = self.rcv_nxt.wrapping_add(accepted as u32);
+            if fin && accepted == payload.len() {
+                self.peer_fin = Some(self.rcv_nxt);
+            }
+            self.reassemble();
+        } else if offset < window {
+            let end = payload.len().min(window - offset);
+            let key = seq.wrapping_sub(self.irs);
+            let kept = self.out_of_order.entry(key).or_default();
+            if kept.len() < end {
+                *kept = payload[..end].to_vec();
+            }
+            if fin && end == payload.len() {
+                self.peer_fin = Some(seq.wrapping_add(end as u32));
+            }
+        }
+
+        // The FIN takes effect once everything before it has arrived
+        if self.peer_fin == Some(self.rcv_nxt) {
+            self.peer_fin = None;
+            self.peer_closed = true;
+            self.rcv_nxt = self.rcv_nxt.wrapping_add(1);
+            match self.state {
+                TcpState::Established => self.state = TcpState::CloseWait,
+                TcpState::FinWait1 => self.state = TcpState::Closing,
+                TcpState::FinWait2 => self.enter_time_wait(now_ms),
+                _ => {}
+            }
+        }
+        // Data out of order is acknowledged too: the repeated ACK tells the peer what is missing
+        self.send_ack();
+    }
+
+    /// Move segments held out of order into the receive buffer as the gap before them fills
+    fn reassemble(&mut self) {
+        while let Some(entry) = self.out_of_order.first_entry() {
+            let seq = self.irs.wrapping_add(*entry.key());
+            if seq_lt(self.rcv_nxt, seq) {
+                break;
+            }
+            let data = entry.remove();
+            let old = self.rcv_nxt.wrapping_sub(seq) as usize;
+            if old < data.len() {
+                let accepted = (data.len() - old).min(self.receive_window() as usize);
+                self.receive_buffer.extend(&data[old..old + accepted]);
+                self.rcv_nxt = self.rcv_nxt.wrapping_add(accepted as u32);
+            }
+        }
+    }
+
+    fn enter_time_wait(&mut self, now_ms: u64) {
+        self.state = TcpState::TimeWait;
+        self.time_wait_until = Some(now_ms + TIME_WAIT_MS);
+        self.timer_ms = None;
+    }
+
+    /// Retransmit what has waited too long for an ACK, probe a closed window, and leave
+    /// TIME-WAIT once it has run out
+    pub fn on_timer(&mut self, now_ms: u64) {
+        if self.time_wait_until.is_some_and(|until| now_ms >= until) {
+            self.close_with(None);
+            return;
+        }
+        if !self.timer_ms.is_some_and(|deadline| now_ms >= deadline) {
+            return;
+        }
+        if !self.unacked.is_empty() {
+            self.retransmits += 1;
+            if self.retransmits > MAX_RETRANSMITS {
+                self.close_with(Some(TcpError::TimedOut));
+                return;
+            }
+            self.retransmit_oldest();
+        } else if self.snd_wnd == 0 && !self.send_buffer.is_empty() {
+            // A byte before the window draws an ACK carrying the peer's window
+            self.queue(self.snd_una.wrapping_sub(1), ACK, Vec::new());
+        } else {
+            self.timer_ms = None;
+            return;
+        }
+        self.rto_ms = (self.rto_ms * 2).min(MAX_RTO_MS);
+        self.timer_ms = Some(now_ms + self.rto_ms);
+    }
+}
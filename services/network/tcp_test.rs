@@ -0,0 +1,290 @@
+//! TCP Tests
+//! Drives pairs of `TcpConnection`s by hand, losing and reordering segments between them, to
+//! check the handshake, reassembly, fast and timed retransmission and each side closing in
+//! turn; then streams 64 KiB between two sockets over loopback
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::net::{Ipv4Addr, SocketAddrV4};
+
+use super::super::contracts::network::{IpAddress, SocketAddress, SocketDomain, SocketType};
+use super::interface_manager::InterfaceManager;
+use super::packet_processor::PacketProcessor;
+use super::socket_manager::{SocketError, SocketManager};
+use super::tcp::{Segment, TcpConnection, TcpError, TcpState, FIN, MSS, TIME_WAIT_MS};
+use crate::serial::_print;
+
+const CLIENT: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 50000);
+const SERVER: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 8080);
+
+fn pattern(length: usize) -> Vec<u8> {
+    (0..length).map(|i| (i * 7 + i / 251) as u8).collect()
+}
+
+/// Carry segments between the two ends until neither has more to send
+fn exchange(a: &mut TcpConnection, b: &mut TcpConnection, now_ms: u64) {
+    loop {
+        let from_a: Vec<Segment> = a.take_outgoing().collect();
+        let from_b: Vec<Segment> = b.take_outgoing().collect();
+        if from_a.is_empty() && from_b.is_empty() {
+            break;
+        }
+        for segment in &from_a {
+            b.on_segment(segment, now_ms);
+        }
+        for segment in &from_b {
+            a.on_segment(segment, now_ms);
+        }
+    }
+}
+
+/// A client connected to a server, the handshake done at time 0
+fn connected_pair() -> Result<(TcpConnection, TcpConnection), &'static str> {
+    let mut client = TcpConnection::connect(CLIENT, SERVER, 1000, 0);
+    let syn: Vec<Segment> = client.take_outgoing().collect();
+    let mut server = TcpConnection::accept(syn.first().ok_or("No SYN sent")?, 5000, 0);
+    exchange(&mut client, &mut server, 0);
+    if client.state() != TcpState::Established || server.state() != TcpState::Established {
+        return Err("Handshake did not establish both ends");
+    }
+    Ok((client, server))
+}
+
+fn inet(address: SocketAddrV4) -> SocketAddress {
+    SocketAddress::Inet { ip: IpAddress::V4(address.ip().octets()), port: address.port() }
+}
+
+pub fn run_tcp_tests() -> Result<(), &'static str> {
+    _print(format_args!("[TCP Test] Starting TCP tests...\n"));
+
+    // Test 1: SYN, SYN-ACK and ACK establish both ends with each other's sequence numbers
+    _print(format_args!("[TCP Test] Test 1: Three-way handshake...\n"));
+    let mut client = TcpConnection::connect(CLIENT, SERVER, u32::MAX - 1, 0);
+    let syn: Vec<Segment> = client.take_outgoing().collect();
+    let syn = syn.first().ok_or("No SYN sent")?;
+    if syn.flags != super::tcp::SYN || syn.seq != u32::MAX - 1 || client.state() != TcpState::SynSent {
+        return Err("Connecting did not send a SYN");
+    }
+    let mut server = TcpConnection::accept(syn, 5000, 0);
+    let syn_ack: Vec<Segment> = server.take_outgoing().collect();
+    let syn_ack = syn_ack.first().ok_or("No SYN-ACK sent")?;
+    if syn_ack.flags != super::tcp::SYN | super::tcp::ACK || syn_ack.ack != u32::MAX || server.state() != TcpState::SynReceived {
+        return Err("Accepting did not answer with a SYN-ACK");
+    }
+    client.on_segment(syn_ack, 0);
+    exchange(&mut client, &mut server, 0);
+    if client.state() != TcpState::Established || server.state() != TcpState::Established {
+        return Err("Handshake did not establish both ends");
+    }
+    // Sequence numbers wrap past zero with the first bytes
+    client.send(b"wrapped", 0).map_err(|_| "Established connection refused data")?;
+    exchange(&mut client, &mut server, 0);
+    if server.receive(64) != b"wrapped" {
+        return Err("Data across the sequence wrap not delivered");
+    }
+    _print(format_args!("[TCP Test] ✓ Both ends established, sequence numbers wrapping\n"));
+
+    // Test 2: Segments arriving out of order are held until the gap fills
+    _print(format_args!("[TCP Test] Test 2: Out-of-order reassembly...\n"));
+    let (mut client, mut server) = connected_pair()?;
+    let data = pattern(3 * MSS);
+    client.send(&data, 0).map_err(|_| "Send failed")?;
+    let segments: Vec<Segment> = client.take_outgoing().collect();
+    if segments.len() != 3 {
+        return Err("Data not split into MSS-sized segments");
+    }
+    server.on_segment(&segments[2], 0);
+    server.on_segment(&segments[1], 0);
+    if server.available() != 0 {
+        return Err("Data delivered ahead of a gap");
+    }
+    // The same segment again is held only once
+    server.on_segment(&segments[2], 0);
+    server.on_segment(&segments[0], 0);
+    if server.receive(usize::MAX) != data {
+        return Err("Out-of-order segments not reassembled in order");
+    }
+    exchange(&mut client, &mut server, 0);
+    if server.available() != 0 {
+        return Err("Duplicate segments delivered twice");
+    }
+    _print(format_args!("[TCP Test] ✓ Three segments delivered last-first reassembled in order\n"));
+
+    // Test 3: A lost segment is resent after three duplicate ACKs, before its timer runs out
+    _print(format_args!("[TCP Test] Test 3: Fast retransmit on duplicate ACKs...\n"));
+    let (mut client, mut server) = connected_pair()?;
+    let data = pattern(5 * MSS);
+    client.send(&data, 0).map_err(|_| "Send failed")?;
+    let segments: Vec<Segment> = client.take_outgoing().collect();
+    for segment in &segments[1..] {
+        server.on_segment(segment, 0);
+    }
+    let duplicates: Vec<Segment> = server.take_outgoing().collect();
+    if duplicates.len() != 4 || duplicates.iter().any(|ack| ack.ack != segments[0].seq) {
+        return Err("Segments after a gap not acknowledged up to it");
+    }
+    for ack in &duplicates[..2] {
+        client.on_segment(ack, 10);
+    }
+    if client.take_outgoing().next().is_some() {
+        return Err("Retransmitted before the third duplicate ACK");
+    }
+    client.on_segment(&duplicates[2], 10);
+    let resent: Vec<Segment> = client.take_outgoing().collect();
+    if resent.len() != 1 || resent[0].seq != segments[0].seq || resent[0].payload != segments[0].payload {
+        return Err("Third duplicate ACK did not resend the lost segment");
+    }
+    server.on_segment(&resent[0], 10);
+    exchange(&mut client, &mut server, 10);
+    if server.receive(usize::MAX) != data {
+        return Err("Stream not complete after fast retransmit");
+    }
+    _print(format_args!("[TCP Test] ✓ Lost segment resent on the third duplicate ACK\n"));
+
+    // Test 4: Unacknowledged data is resent on the retransmission timer, backing off, until
+    // the connection gives up
+    _print(format_args!("[TCP Test] Test 4: Retransmission timeout...\n"));
+    let (mut client, mut server) = connected_pair()?;
+    client.send(b"timed", 0).map_err(|_| "Send failed")?;
+    let lost: Vec<Segment> = client.take_outgoing().collect();
+    client.on_timer(999);
+    if client.take_outgoing().next().is_some() {
+        return Err("Retransmitted before the timeout");
+    }
+    client.on_timer(1000);
+    let resent: Vec<Segment> = client.take_outgoing().collect();
+    if resent.len() != 1 || resent[0].seq != lost[0].seq || resent[0].payload != b"timed" {
+        return Err("Timeout did not resend the segment");
+    }
+    // The next try waits twice as long
+    client.on_timer(2999);
+    if client.take_outgoing().next().is_some() {
+        return Err("Timer did not back off");
+    }
+    server.on_segment(&resent[0], 3000);
+    exchange(&mut client, &mut server, 3000);
+    if server.receive(64) != b"timed" {
+        return Err("Resent segment not delivered");
+    }
+    client.on_timer(1_000_000);
+    if client.take_outgoing().next().is_some() {
+        return Err("Acknowledged data resent");
+    }
+    client.send(b"never acknowledged", 3000).map_err(|_| "Send failed")?;
+    let mut now = 3000;
+    let mut attempts = 0;
+    while client.state() != TcpState::Closed && attempts < 64 {
+        now += 60_000;
+        client.on_timer(now);
+        client.take_outgoing().for_each(drop);
+        attempts += 1;
+    }
+    if client.error() != Some(TcpError::TimedOut) {
+        return Err("Connection never gave up on an unacknowledged segment");
+    }
+    _print(format_args!("[TCP Test] ✓ Resent after 1s, then 2s, given up after {} tries\n", attempts - 1));
+
+    // Test 5: One side closes and the other goes on sending until it closes too
+    _print(format_args!("[TCP Test] Test 5: Half-open close...\n"));
+    let (mut client, mut server) = connected_pair()?;
+    client.close(100);
+    exchange(&mut client, &mut server, 100);
+    if client.state() != TcpState::FinWait2 || server.state() != TcpState::CloseWait || !server.peer_closed() {
+        return Err("FIN did not half-close the connection");
+    }
+    if client.send(b"late", 100).is_ok() {
+        return Err("Closed side still accepted data");
+    }
+    let reply = pattern(2 * MSS + 17);
+    server.send(&reply, 100).map_err(|_| "Half-closed peer refused data")?;
+    exchange(&mut client, &mut server, 100);
+    if client.receive(usize::MAX) != reply {
+        return Err("Data after a half-close not delivered");
+    }
+    server.close(200);
+    let last: Vec<Segment> = server.take_outgoing().collect();
+    if server.state() != TcpState::LastAck || !last.iter().any(|segment| segment.has(FIN)) {
+        return Err("Second close sent no FIN");
+    }
+    for segment in &last {
+        client.on_segment(segment, 200);
+    }
+    exchange(&mut client, &mut server, 200);
+    if client.state() != TcpState::TimeWait || server.state() != TcpState::Closed || server.error().is_some() {
+        return Err("Both FINs acknowledged did not close the connection");
+    }
+    client.on_timer(200 + TIME_WAIT_MS - 1);
+    if client.state() != TcpState::TimeWait {
+        return Err("TIME-WAIT ended early");
+    }
+    client.on_timer(200 + TIME_WAIT_MS);
+    if client.state() != TcpState::Closed {
+        return Err("TIME-WAIT never ended");
+    }
+    _print(format_args!("[TCP Test] ✓ Sent {} bytes after the peer's FIN, then closed through TIME-WAIT\n", reply.len()));
+
+    // Test 6: Two sockets connect over loopback and stream 64 KiB in order
+    _print(format_args!("[TCP Test] Test 6: 64 KiB over loopback sockets...\n"));
+    let processor = Arc::new(PacketProcessor::new(Arc::new(InterfaceManager::new())));
+    processor.start().map_err(|_| "Packet processor did not start")?;
+    let sockets = SocketManager::new(processor.clone());
+    let listener = sockets.create_socket(SocketDomain::Inet, SocketType::Stream, 0).map_err(|_| "Socket not created")?;
+    sockets.bind_socket(listener, inet(SERVER)).map_err(|_| "Bind failed")?;
+    sockets.listen_socket(listener, 4).map_err(|_| "Listen failed")?;
+    let client = sockets.create_socket(SocketDomain::Inet, SocketType::Stream, 0).map_err(|_| "Socket not created")?;
+    sockets.connect_socket(client, inet(SERVER)).map_err(|_| "Connect failed")?;
+    let (server, _) = sockets.accept_socket(listener, 0).map_err(|_| "Accept failed")?;
+
+    let stream = pattern(64 * 1024);
+    let mut sent = 0;
+    let mut received = Vec::new();
+    while sent < stream.len() {
+        sent += sockets.send_data(client, stream[sent..].to_vec(), 0).map_err(|_| "Send failed")?;
+        received.extend(sockets.receive_data(server, 4096, 0).map_err(|_| "Receive failed")?);
+    }
+    sockets.close_socket(client).map_err(|_| "Close failed")?;
+    loop {
+        let chunk = sockets.receive_data(server, 4096, 0).map_err(|_| "Receive failed")?;
+        if chunk.is_empty() {
+            break;
+        }
+        received.extend(chunk);
+    }
+    if received != stream {
+        return Err("Stream not received intact and in order");
+    }
+    sockets.close_socket(server).map_err(|_| "Close failed")?;
+    sockets.close_socket(listener).map_err(|_| "Close failed")?;
+
+    // Nothing listens on the port any more, and nothing routes off loopback
+    let refused = sockets.create_socket(SocketDomain::Inet, SocketType::Stream, 0).map_err(|_| "Socket not created")?;
+    if sockets.connect_socket(refused, inet(SERVER)) != Err(SocketError::ConnectionRefused) {
+        return Err("Connecting to a closed port not refused");
+    }
+    let remote = SocketAddrV4::new(Ipv4Addr::new(192, 0, 2, 1), 80);
+    if sockets.connect_socket(refused, inet(remote)) != Err(SocketError::NetworkUnreachable) {
+        return Err("Connecting off loopback did not fail as unreachable");
+    }
+    sockets.close_socket(refused).map_err(|_| "Close failed")?;
+    if sockets.socket_count() != 0 || processor.statistics().malformed != 0 {
+        return Err("Sockets left open or packets malformed");
+    }
+    _print(format_args!("[TCP Test] ✓ {} bytes streamed in {} packets\n", stream.len(), processor.statistics().packets_sent));
+
+    _print(format_args!("[TCP Test] ✓ All TCP tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for TCP
+pub fn test_tcp() {
+    _print(format_args!("[TCP Test] ===========================================\n"));
+    _print(format_args!("[TCP Test]                 TCP TESTS\n"));
+    _print(format_args!("[TCP Test] ===========================================\n"));
+
+    match run_tcp_tests() {
+        Ok(_) => _print(format_args!("[TCP Test] ✓ All TCP tests PASSED\n")),
+        Err(e) => _print(format_args!("[TCP Test] ✗ TCP tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[TCP Test] ===========================================\n"));
+}
@@ -0,0 +1,380 @@
+//! Scheduled Task Service (rae-crond)
+//! Runs registered maintenance tasks on cron-style schedules against the wall clock.
+//! A failing task is logged and stays scheduled; it never blocks other tasks or future runs.
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::time::timezone::TimeZone;
+use crate::time::DateTime;
+
+pub mod scheduler_test;
+
+/// Runs older than this many minutes are skipped rather than replayed after a clock jump
+const MAX_CATCH_UP_MINUTES: i64 = 60;
+/// Completed runs kept in the log
+const RUN_LOG_CAPACITY: usize = 256;
+
+/// Scheduler errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchedulerError {
+    InvalidSchedule(String),
+    UnknownAction(String),
+    TaskNotFound,
+    DuplicateTask,
+}
+
+/// Source of wall-clock time, injectable for tests
+pub trait Clock: Send {
+    /// Seconds since the Unix epoch (UTC)
+    fn now(&self) -> i64;
+    /// Monotonic high-resolution time in nanoseconds, used to time runs
+    fn monotonic_ns(&self) -> u64;
+}
+
+/// The kernel wall clock
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        crate::time::get_timestamp() as i64
+    }
+
+    fn monotonic_ns(&self) -> u64 {
+        crate::time::get_precise_time_ns()
+    }
+}
+
+/// Allowed values of one cron field as a bitmask
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CronField {
+    allowed: u64,
+    restricted: bool,
+}
+
+impl CronField {
+    /// Parse `*`, `*/n`, `a`, `a-b`, `a-b/n` and comma-separated lists thereof
+    fn parse(field: &str, min: u8, max: u8) -> Result<Self, SchedulerError> {
+        let invalid = || SchedulerError::InvalidSchedule(format!("bad field '{}'", field));
+        let mut allowed = 0u64;
+
+        for part in field.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u8>().map_err(|_| invalid())?),
+                None => (part, 1),
+            };
+            if step == 0 {
+                return Err(invalid());
+            }
+
+            let (start, end) = if range == "*" {
+                (min, max)
+            } else if let Some((a, b)) = range.split_once('-') {
+                (a.parse().map_err(|_| invalid())?, b.parse().map_err(|_| invalid())?)
+            } else {
+                let value = range.parse().map_err(|_| invalid())?;
+                // `5/15` means "from 5, every 15"
+                (value, if part.contains('/') { max } else { value })
+            };
+            if start < min || end > max || start > end {
+                return Err(invalid());
+            }
+
+            for value in (start..=end).step_by(step as usize) {
+                allowed |= 1 << value;
+            }
+        }
+
+        Ok(Self { allowed, restricted: !field.starts_with('*') })
+    }
+
+    fn contains(&self, value: u8) -> bool {
+        (self.allowed & (1 << value)) != 0
+    }
+}
+
+/// A five-field cron schedule: minute hour day-of-month month day-of-week
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minute: CronField,
+    hour: CronField,
+    day_of_month: CronField,
+    month: CronField,
+    day_of_week: CronField,
+    expression: String,
+}
+
+impl CronSchedule {
+    /// Parse a cron expression or one of `@hourly`, `@daily`, `@weekly`, `@monthly`
+    pub fn parse(expression: &str) -> Result<Self, SchedulerError> {
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(SchedulerError::InvalidSchedule(format!("expected 5 fields in '{}'", expression)));
+        }
+
+        // Day-of-week accepts 7 as an alias for Sunday
+        let mut day_of_week = CronField::parse(fields[4], 0, 7)?;
+        if day_of_week.contains(7) {
+            day_of_week.allowed = (day_of_week.allowed & !(1 << 7)) | 1;
+        }
+
+        Ok(Self {
+            minute: CronField::parse(fields[0], 0, 59)?,
+            hour: CronField::parse(fields[1], 0, 23)?,
+            day_of_month: CronField::parse(fields[2], 1, 31)?,
+            month: CronField::parse(fields[3], 1, 12)?,
+            day_of_week,
+            expression: expression.trim().to_string(),
+        })
+    }
+
+    /// Whether the schedule fires at this (local) minute
+    pub fn matches(&self, time: &DateTime) -> bool {
+        if !self.minute.contains(time.minute) || !self.hour.contains(time.hour) || !self.month.contains(time.month) {
+            return false;
+        }
+
+        // As in cron: when both day fields are restricted, either may match
+        let dom = self.day_of_month.contains(time.day);
+        let dow = self.day_of_week.contains(time.weekday());
+        match (self.day_of_month.restricted, self.day_of_week.restricted) {
+            (true, true) => dom || dow,
+            (true, false) => dom,
+            (false, true) => dow,
+            (false, false) => true,
+        }
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+}
+
+/// Work performed by a task; an `Err` is logged and the task stays scheduled
+pub type TaskAction = Box<dyn FnMut() -> Result<(), String> + Send>;
+
+pub type TaskId = u32;
+
+/// Result of one task run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskOutcome {
+    Succeeded,
+    Failed(String),
+}
+
+/// Log entry for one run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskRunRecord {
+    pub task_id: TaskId,
+    pub task_name: String,
+    /// The minute boundary the run was scheduled for (UTC seconds)
+    pub scheduled_for: i64,
+    /// Wall-clock time the run actually started
+    pub started_at: i64,
+    pub duration_ns: u64,
+    pub outcome: TaskOutcome,
+}
+
+/// Per-task counters
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaskStatistics {
+    pub runs: u64,
+    pub failures: u64,
+    pub consecutive_failures: u32,
+    pub last_run: Option<i64>,
+}
+
+struct ScheduledTask {
+    name: String,
+    schedule: CronSchedule,
+    action: TaskAction,
+    statistics: TaskStatistics,
+}
+
+/// Cron-style task scheduler
+pub struct TaskScheduler {
+    clock: Box<dyn Clock>,
+    timezone: TimeZone,
+    tasks: BTreeMap<TaskId, ScheduledTask>,
+    /// Actions available to config-file entries, by name
+    actions: BTreeMap<String, fn() -> Result<(), String>>,
+    next_id: TaskId,
+    /// Last minute (UTC seconds / 60) whose tasks have been run
+    last_minute: i64,
+    run_log: VecDeque<TaskRunRecord>,
+}
+
+impl TaskScheduler {
+    /// Create a scheduler matching schedules in `timezone`. Runs start from the
+    /// next minute boundary after creation.
+    pub fn new(clock: Box<dyn Clock>, timezone: TimeZone) -> Self {
+        let last_minute = clock.now().div_euclid(60);
+        Self {
+            clock,
+            timezone,
+            tasks: BTreeMap::new(),
+            actions: BTreeMap::new(),
+            next_id: 1,
+            last_minute,
+            run_log: VecDeque::new(),
+        }
+    }
+
+    /// Register a task programmatically
+    pub fn register(&mut self, name: &str, expression: &str, action: TaskAction) -> Result<TaskId, SchedulerError> {
+        if self.tasks.values().any(|task| task.name == name) {
+            return Err(SchedulerError::DuplicateTask);
+        }
+        let schedule = CronSchedule::parse(expression)?;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.tasks.insert(id, ScheduledTask {
+            name: name.to_string(),
+            schedule,
+            action,
+            statistics: TaskStatistics::default(),
+        });
+        Ok(id)
+    }
+
+    pub fn unregister(&mut self, id: TaskId) -> Result<(), SchedulerError> {
+        self.tasks.remove(&id).map(|_| ()).ok_or(SchedulerError::TaskNotFound)
+    }
+
+    /// Make a named action available to config files
+    pub fn register_action(&mut self, name: &str, action: fn() -> Result<(), String>) {
+        self.actions.insert(name.to_string(), action);
+    }
+
+    /// Load crontab-style lines: `<5 fields or @macro> <action-name>`.
+    /// Blank lines and `#` comments are ignored. Returns the registered task IDs.
+    pub fn load_config(&mut self, config: &str) -> Result<Vec<TaskId>, SchedulerError> {
+        let mut ids = Vec::new();
+        for line in config.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (expression, action_name) = match line.rsplit_once(char::is_whitespace) {
+                Some((expression, action_name)) => (expression.trim(), action_name),
+                None => return Err(SchedulerError::InvalidSchedule(line.to_string())),
+            };
+            let action = *self.actions.get(action_name)
+                .ok_or_else(|| SchedulerError::UnknownAction(action_name.to_string()))?;
+            ids.push(self.register(action_name, expression, Box::new(action))?);
+        }
+        Ok(ids)
+    }
+
+    /// Run every task due since the last call. Call at least once a minute;
+    /// returns the records of the runs performed.
+    pub fn tick(&mut self) -> Vec<TaskRunRecord> {
+        let current_minute = self.clock.now().div_euclid(60);
+        if current_minute <= self.last_minute {
+            return Vec::new();
+        }
+
+        let first = (self.last_minute + 1).max(current_minute - MAX_CATCH_UP_MINUTES + 1);
+        self.last_minute = current_minute;
+
+        let mut records = Vec::new();
+        for minute in first..=current_minute {
+            let scheduled_for = minute * 60;
+            let local = self.timezone.to_local(scheduled_for).datetime;
+
+            for (&id, task) in self.tasks.iter_mut() {
+                if !task.schedule.matches(&local) {
+                    continue;
+                }
+
+                let started_at = self.clock.now();
+                let start_ns = self.clock.monotonic_ns();
+                let outcome = match (task.action)() {
+                    Ok(()) => {
+                        task.statistics.consecutive_failures = 0;
+                        TaskOutcome::Succeeded
+                    }
+                    Err(error) => {
+                        task.statistics.failures += 1;
+                        task.statistics.consecutive_failures += 1;
+                        TaskOutcome::Failed(error)
+                    }
+                };
+                let duration_ns = self.clock.monotonic_ns().saturating_sub(start_ns);
+                task.statistics.runs += 1;
+                task.statistics.last_run = Some(started_at);
+
+                match &outcome {
+                    TaskOutcome::Succeeded => crate::serial::_print(format_args!(
+                        "[rae-crond] {} ({}) succeeded in {} us\n",
+                        task.name, task.schedule.expression(), duration_ns / 1000)),
+                    TaskOutcome::Failed(error) => crate::serial::_print(format_args!(
+                        "[rae-crond] {} ({}) failed after {} us: {}\n",
+                        task.name, task.schedule.expression(), duration_ns / 1000, error)),
+                }
+
+                records.push(TaskRunRecord {
+                    task_id: id,
+                    task_name: task.name.clone(),
+                    scheduled_for,
+                    started_at,
+                    duration_ns,
+                    outcome,
+                });
+            }
+        }
+
+        for record in &records {
+            if self.run_log.len() == RUN_LOG_CAPACITY {
+                self.run_log.pop_front();
+            }
+            self.run_log.push_back(record.clone());
+        }
+        records
+    }
+
+    pub fn statistics(&self, id: TaskId) -> Option<TaskStatistics> {
+        self.tasks.get(&id).map(|task| task.statistics.clone())
+    }
+
+    /// Most recent runs, oldest first
+    pub fn run_log(&self) -> impl Iterator<Item = &TaskRunRecord> {
+        self.run_log.iter()
+    }
+
+    pub fn task_names(&self) -> Vec<(TaskId, String)> {
+        self.tasks.iter().map(|(&id, task)| (id, task.name.clone())).collect()
+    }
+}
+
+static SCHEDULER: Mutex<Option<TaskScheduler>> = Mutex::new(None);
+
+/// Start the system scheduler on the kernel wall clock and system timezone
+pub fn init() {
+    let mut scheduler = SCHEDULER.lock();
+    if scheduler.is_none() {
+        *scheduler = Some(TaskScheduler::new(Box::new(SystemClock), crate::time::timezone::current()));
+    }
+}
+
+/// Register a task with the system scheduler
+pub fn schedule(name: &str, expression: &str, action: TaskAction) -> Result<TaskId, SchedulerError> {
+    SCHEDULER.lock().as_mut().ok_or(SchedulerError::TaskNotFound)?.register(name, expression, action)
+}
+
+/// Drive the system scheduler; call periodically from the service loop
+pub fn poll() -> usize {
+    SCHEDULER.lock().as_mut().map_or(0, |scheduler| scheduler.tick().len())
+}
@@ -0,0 +1,193 @@
+//! Scheduler Service Tests
+//! Drives the scheduler with an injected clock to check minute-boundary firing and failure isolation
+
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicI64, AtomicU32, Ordering};
+
+use super::{Clock, CronSchedule, TaskOutcome, TaskScheduler};
+use crate::serial::_print;
+use crate::time::timezone::TimeZone;
+use crate::time::DateTime;
+
+/// 2024-08-15 12:00:00 UTC (a Thursday)
+const BASE: i64 = 1_723_723_200;
+
+/// Clock whose time the test sets explicitly
+#[derive(Clone)]
+struct ManualClock {
+    now: Arc<AtomicI64>,
+}
+
+impl ManualClock {
+    fn new(now: i64) -> Self {
+        Self { now: Arc::new(AtomicI64::new(now)) }
+    }
+
+    fn set(&self, now: i64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> i64 {
+        self.now.load(Ordering::SeqCst)
+    }
+
+    fn monotonic_ns(&self) -> u64 {
+        self.now() as u64 * 1_000_000_000
+    }
+}
+
+fn counting_task(counter: &Arc<AtomicU32>) -> super::TaskAction {
+    let counter = counter.clone();
+    Box::new(move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    })
+}
+
+fn datetime(day: u8, hour: u8, minute: u8) -> DateTime {
+    DateTime { year: 2024, month: 8, day, hour, minute, second: 0 }
+}
+
+pub fn run_scheduler_tests() -> Result<(), &'static str> {
+    _print(format_args!("[Scheduler Test] Starting scheduler tests...\n"));
+
+    // Test 1: cron expression parsing and matching
+    _print(format_args!("[Scheduler Test] Test 1: Parsing cron expressions...\n"));
+    let quarter_hours = CronSchedule::parse("*/15 9-17 * * 1-5").map_err(|_| "valid expression rejected")?;
+    if !quarter_hours.matches(&datetime(15, 9, 45)) || quarter_hours.matches(&datetime(15, 9, 50)) {
+        return Err("*/15 minute field matched wrong minutes");
+    }
+    // 2024-08-17 is a Saturday
+    if quarter_hours.matches(&datetime(17, 10, 0)) {
+        return Err("1-5 day-of-week matched a Saturday");
+    }
+    let weekly = CronSchedule::parse("@weekly").map_err(|_| "@weekly rejected")?;
+    if !weekly.matches(&datetime(18, 0, 0)) || weekly.matches(&datetime(19, 0, 0)) {
+        return Err("@weekly did not fire only on Sunday midnight");
+    }
+    // Both day fields restricted: either may match
+    let either = CronSchedule::parse("0 0 1 * 0").map_err(|_| "dom/dow expression rejected")?;
+    if !either.matches(&datetime(18, 0, 0)) || either.matches(&datetime(15, 0, 0)) {
+        return Err("day-of-month/day-of-week OR rule not applied");
+    }
+    for bad in ["* * * *", "60 * * * *", "* 24 * * *", "*/0 * * * *", "5-1 * * * *", "a * * * *"] {
+        if CronSchedule::parse(bad).is_ok() {
+            return Err("invalid cron expression accepted");
+        }
+    }
+    _print(format_args!("[Scheduler Test] ✓ Cron expressions parsed\n"));
+
+    // Test 2: an every-minute task fires once per wall-clock minute boundary
+    _print(format_args!("[Scheduler Test] Test 2: Every-minute task timing...\n"));
+    let clock = ManualClock::new(BASE + 30);
+    let mut scheduler = TaskScheduler::new(Box::new(clock.clone()), TimeZone::utc());
+    let runs = Arc::new(AtomicU32::new(0));
+    let id = scheduler.register("every-minute", "* * * * *", counting_task(&runs))
+        .map_err(|_| "Failed to register task")?;
+
+    clock.set(BASE + 59);
+    if !scheduler.tick().is_empty() {
+        return Err("Task fired before the next minute boundary");
+    }
+
+    clock.set(BASE + 60);
+    let records = scheduler.tick();
+    if records.len() != 1 || records[0].scheduled_for != BASE + 60 {
+        return Err("Task did not fire exactly at the minute boundary");
+    }
+    clock.set(BASE + 75);
+    if !scheduler.tick().is_empty() {
+        return Err("Task fired twice in the same minute");
+    }
+
+    // A late tick catches up on each missed boundary
+    clock.set(BASE + 185);
+    let records = scheduler.tick();
+    let scheduled: alloc::vec::Vec<i64> = records.iter().map(|r| r.scheduled_for).collect();
+    if scheduled != [BASE + 120, BASE + 180] {
+        return Err("Missed minute boundaries not run in order");
+    }
+    if runs.load(Ordering::SeqCst) != 3 || scheduler.statistics(id).map(|s| s.runs) != Some(3) {
+        return Err("Run count does not match fired boundaries");
+    }
+    _print(format_args!("[Scheduler Test] ✓ Task fired at 12:01, 12:02 and 12:03\n"));
+
+    // Test 3: a failing task keeps its schedule and doesn't block other tasks
+    _print(format_args!("[Scheduler Test] Test 3: Failure isolation...\n"));
+    let clock = ManualClock::new(BASE);
+    let mut scheduler = TaskScheduler::new(Box::new(clock.clone()), TimeZone::utc());
+    let attempts = Arc::new(AtomicU32::new(0));
+    let attempts_in_task = attempts.clone();
+    let failing = scheduler.register("failing", "* * * * *", Box::new(move || {
+        attempts_in_task.fetch_add(1, Ordering::SeqCst);
+        Err("disk full".to_string())
+    })).map_err(|_| "Failed to register failing task")?;
+    let healthy_runs = Arc::new(AtomicU32::new(0));
+    scheduler.register("healthy", "* * * * *", counting_task(&healthy_runs))
+        .map_err(|_| "Failed to register healthy task")?;
+
+    for minute in 1..=3 {
+        clock.set(BASE + minute * 60);
+        let records = scheduler.tick();
+        let failed = records.iter()
+            .any(|r| r.task_id == failing && r.outcome == TaskOutcome::Failed("disk full".to_string()));
+        if !failed || records.len() != 2 {
+            return Err("Failing task not retried on its next scheduled minute");
+        }
+    }
+    let stats = scheduler.statistics(failing).ok_or("Failing task was unscheduled")?;
+    if attempts.load(Ordering::SeqCst) != 3 || stats.failures != 3 || stats.consecutive_failures != 3 {
+        return Err("Failure statistics incorrect");
+    }
+    if healthy_runs.load(Ordering::SeqCst) != 3 {
+        return Err("Failing task prevented other tasks from running");
+    }
+    if scheduler.run_log().count() != 6 {
+        return Err("Run log did not record every outcome");
+    }
+    _print(format_args!("[Scheduler Test] ✓ Failing task still ran every minute\n"));
+
+    // Test 4: config file registration
+    _print(format_args!("[Scheduler Test] Test 4: Loading tasks from config...\n"));
+    let clock = ManualClock::new(BASE);
+    let mut scheduler = TaskScheduler::new(Box::new(clock.clone()), TimeZone::utc());
+    scheduler.register_action("rotate-logs", || Ok(()));
+    let ids = scheduler.load_config("# maintenance\n\n30 12 * * * rotate-logs\n")
+        .map_err(|_| "Valid config rejected")?;
+    if ids.len() != 1 {
+        return Err("Config entry not registered");
+    }
+    if scheduler.load_config("0 0 * * * missing-action").is_ok() {
+        return Err("Config with unknown action accepted");
+    }
+    clock.set(BASE + 29 * 60);
+    if !scheduler.tick().is_empty() {
+        return Err("Config task fired early");
+    }
+    clock.set(BASE + 30 * 60);
+    if scheduler.tick().len() != 1 {
+        return Err("Config task did not fire at 12:30");
+    }
+    _print(format_args!("[Scheduler Test] ✓ Config task fired at 12:30\n"));
+
+    _print(format_args!("[Scheduler Test] ✓ All scheduler tests completed successfully!\n"));
+    Ok(())
+}
+
+/// Main test runner for the scheduler service
+pub fn test_scheduler_service() {
+    _print(format_args!("[Scheduler Test] ===========================================\n"));
+    _print(format_args!("[Scheduler Test]          SCHEDULER SERVICE TESTS\n"));
+    _print(format_args!("[Scheduler Test] ===========================================\n"));
+
+    match run_scheduler_tests() {
+        Ok(_) => _print(format_args!("[Scheduler Test] ✓ All scheduler tests PASSED\n")),
+        Err(e) => _print(format_args!("[Scheduler Test] ✗ Scheduler tests FAILED: {}\n", e)),
+    }
+
+    _print(format_args!("[Scheduler Test] ===========================================\n"));
+}
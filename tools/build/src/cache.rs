@@ -0,0 +1,266 @@
+//! Incremental build cache for raeen-build
+//! Each target's source tree and build settings are hashed; a target whose hash matches its
+//! last successful build and whose outputs still exist is reported as cached instead of rebuilt.
+
+use log::{debug, info, warn};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use walkdir::WalkDir;
+
+use raeen_common::json::{self, JsonValue};
+use crate::BuildResult;
+
+/// Cache file, relative to the build directory
+pub const CACHE_FILE: &str = "build-cache.json";
+
+/// Directories never hashed as part of a source tree
+pub const IGNORED_DIRS: &[&str] = &["target", ".git", "build"];
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    hash: String,
+    result: BuildResult,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BuildCache {
+    entries: HashMap<String, CacheEntry>,
+    dirty: bool,
+}
+
+impl BuildCache {
+    /// Load the cache, starting empty if the file is missing or unreadable
+    pub fn load(path: &Path) -> Self {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(_) => return Self::default(),
+        };
+        match Self::from_json(&content) {
+            Ok(cache) => cache,
+            Err(e) => {
+                warn!("Ignoring corrupt build cache {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_json().to_string())?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// The last successful result for `target` if `hash` matches and every output still exists
+    pub fn lookup(&self, target: &str, hash: &str) -> Option<BuildResult> {
+        let entry = self.entries.get(target)?;
+        if entry.hash != hash || !entry.result.output_files.iter().all(|f| f.exists()) {
+            return None;
+        }
+        Some(entry.result.clone())
+    }
+
+    /// Record a build outcome; failures evict the target so it is rebuilt next time
+    pub fn record(&mut self, hash: String, result: &BuildResult) {
+        if result.success {
+            self.entries.insert(result.target.clone(), CacheEntry { hash, result: result.clone() });
+        } else {
+            self.entries.remove(&result.target);
+        }
+        self.dirty = true;
+    }
+
+    /// Reuse the cached result for `target` when `hash` matches (unless `force`),
+    /// otherwise run `build` and record its outcome
+    pub fn build_or_reuse<F>(&mut self, target: &str, hash: String, force: bool, build: F) -> Result<BuildResult, Box<dyn std::error::Error>>
+    where
+        F: FnOnce() -> Result<BuildResult, Box<dyn std::error::Error>>,
+    {
+        if !force {
+            if let Some(mut result) = self.lookup(target, &hash) {
+                info!("{} unchanged, using cached build", target);
+                result.cached = true;
+                result.duration = Duration::ZERO;
+                return Ok(result);
+            }
+        }
+
+        let result = build()?;
+        self.record(hash, &result);
+        Ok(result)
+    }
+
+    fn to_json(&self) -> JsonValue {
+        let mut targets: Vec<&String> = self.entries.keys().collect();
+        targets.sort();
+        JsonValue::Array(
+            targets
+                .into_iter()
+                .map(|target| {
+                    let entry = &self.entries[target];
+                    JsonValue::object(vec![("hash", JsonValue::String(entry.hash.clone())), ("result", entry.result.to_json())])
+                })
+                .collect(),
+        )
+    }
+
+    fn from_json(input: &str) -> Result<Self, String> {
+        let mut entries = HashMap::new();
+        for entry in json::parse(input)?.as_array().ok_or("expected a JSON array of cache entries")? {
+            let hash = entry.field("hash")?.as_str().ok_or("field 'hash' is not a string")?.to_string();
+            let result = BuildResult::from_json(entry.field("result")?)?;
+            entries.insert(result.target.clone(), CacheEntry { hash, result });
+        }
+        Ok(Self { entries, dirty: false })
+    }
+}
+
+/// Hash every file under `source_dir` (path and contents, in a stable order) together with
+/// the extra configuration inputs that affect the build
+pub fn hash_target(source_dir: &Path, config_inputs: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let mut files: Vec<PathBuf> = WalkDir::new(source_dir)
+        .into_iter()
+        .filter_entry(|e| !(e.file_type().is_dir() && e.depth() > 0 && IGNORED_DIRS.iter().any(|d| e.file_name() == *d)))
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .collect();
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for input in config_inputs {
+        hasher.update(input.as_bytes());
+        hasher.update([0u8]);
+    }
+    for file in &files {
+        let relative = file.strip_prefix(source_dir).unwrap_or(file);
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update([0u8]);
+        let contents = fs::read(file)?;
+        hasher.update((contents.len() as u64).to_le_bytes());
+        hasher.update(&contents);
+    }
+
+    let hash = format!("{:x}", hasher.finalize());
+    debug!("Hashed {} files under {}: {}", files.len(), source_dir.display(), hash);
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(target: &str, output: PathBuf, success: bool) -> BuildResult {
+        BuildResult {
+            target: target.to_string(),
+            success,
+            cached: false,
+            duration: Duration::from_millis(10),
+            output_files: vec![output],
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_hash_ignores_target_dir_and_tracks_config() {
+        let dir = std::env::temp_dir().join(format!("raeen-cache-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/lib.rs"), "pub fn a() {}").unwrap();
+        let before = hash_target(&dir, &["release"]).unwrap();
+
+        fs::create_dir_all(dir.join("target")).unwrap();
+        fs::write(dir.join("target/artifact"), "binary").unwrap();
+        assert_eq!(hash_target(&dir, &["release"]).unwrap(), before);
+        assert_ne!(hash_target(&dir, &["debug"]).unwrap(), before);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_round_trip_and_failure_eviction() {
+        let dir = std::env::temp_dir().join(format!("raeen-cache-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.bin");
+        fs::write(&output, "bin").unwrap();
+
+        let mut cache = BuildCache::default();
+        cache.record("abc".to_string(), &result("kernel", output.clone(), true));
+        cache.save(&dir.join(CACHE_FILE)).unwrap();
+
+        let mut loaded = BuildCache::load(&dir.join(CACHE_FILE));
+        assert!(loaded.lookup("kernel", "abc").is_some());
+        assert!(loaded.lookup("kernel", "def").is_none());
+
+        fs::remove_file(&output).unwrap();
+        assert!(loaded.lookup("kernel", "abc").is_none(), "missing outputs must invalidate");
+
+        fs::write(&output, "bin").unwrap();
+        loaded.record("abc".to_string(), &result("kernel", output, false));
+        assert!(loaded.lookup("kernel", "abc").is_none(), "failed builds must not be cached");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Build each target under `workspace` through the cache, returning the names actually rebuilt
+    fn build_workspace(cache: &mut BuildCache, workspace: &Path, force: bool) -> (Vec<BuildResult>, Vec<String>) {
+        let mut rebuilt = Vec::new();
+        let mut results = Vec::new();
+        for target in ["kernel", "userspace/shell"] {
+            let source_dir = workspace.join(target);
+            let hash = hash_target(&source_dir, &["release", ""]).unwrap();
+            let output = workspace.join("out").join(target.replace('/', "_"));
+            let built = cache
+                .build_or_reuse(target, hash, force, || {
+                    rebuilt.push(target.to_string());
+                    fs::create_dir_all(output.parent().unwrap())?;
+                    fs::write(&output, "artifact")?;
+                    Ok(result(target, output.clone(), true))
+                })
+                .unwrap();
+            results.push(built);
+        }
+        (results, rebuilt)
+    }
+
+    #[test]
+    fn test_unchanged_build_is_cached_and_edits_invalidate_one_target() {
+        let workspace = std::env::temp_dir().join(format!("raeen-cache-{}", uuid::Uuid::new_v4()));
+        for target in ["kernel", "userspace/shell"] {
+            fs::create_dir_all(workspace.join(target).join("src")).unwrap();
+            fs::write(workspace.join(target).join("src/main.rs"), "fn main() {}").unwrap();
+        }
+        let cache_path = workspace.join("build").join(CACHE_FILE);
+
+        let mut cache = BuildCache::load(&cache_path);
+        let (_, rebuilt) = build_workspace(&mut cache, &workspace, false);
+        assert_eq!(rebuilt, ["kernel", "userspace/shell"]);
+        cache.save(&cache_path).unwrap();
+
+        // A fresh run with no changes reports every target as cached
+        let mut cache = BuildCache::load(&cache_path);
+        let (results, rebuilt) = build_workspace(&mut cache, &workspace, false);
+        assert!(rebuilt.is_empty());
+        assert!(results.iter().all(|r| r.cached && r.success));
+
+        // Editing one source file invalidates exactly that target
+        fs::write(workspace.join("userspace/shell/src/main.rs"), "fn main() { println!(); }").unwrap();
+        let (results, rebuilt) = build_workspace(&mut cache, &workspace, false);
+        assert_eq!(rebuilt, ["userspace/shell"]);
+        assert!(results[0].cached && !results[1].cached);
+
+        // --force bypasses the cache
+        let (_, rebuilt) = build_workspace(&mut cache, &workspace, true);
+        assert_eq!(rebuilt.len(), 2);
+
+        fs::remove_dir_all(&workspace).unwrap();
+    }
+}
@@ -0,0 +1,254 @@
+//! Build dependency graph for raeen-build
+//! Orders targets so dependencies build first, and rebuilds every dependent of a target
+//! that was rebuilt instead of trusting their cached results.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use crate::cache::{self, BuildCache};
+use crate::{BuildResult, BuildTarget};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mark {
+    Unvisited,
+    Visiting,
+    Done,
+}
+
+/// Dependency DAG over a set of build targets
+pub struct BuildGraph<'a> {
+    targets: &'a [BuildTarget],
+    index: HashMap<&'a str, usize>,
+}
+
+impl<'a> BuildGraph<'a> {
+    /// Index targets by name, rejecting duplicates and dependencies on unknown targets
+    pub fn new(targets: &'a [BuildTarget]) -> Result<Self, String> {
+        let mut index = HashMap::new();
+        for (i, target) in targets.iter().enumerate() {
+            if index.insert(target.name.as_str(), i).is_some() {
+                return Err(format!("duplicate build target '{}'", target.name));
+            }
+        }
+        for target in targets {
+            if let Some(dep) = target.dependencies.iter().find(|d| !index.contains_key(d.as_str())) {
+                return Err(format!("target '{}' depends on unknown target '{}'", target.name, dep));
+            }
+        }
+        Ok(Self { targets, index })
+    }
+
+    /// Targets with every dependency ordered before its dependents; otherwise declaration order
+    pub fn build_order(&self) -> Result<Vec<&'a BuildTarget>, String> {
+        let mut marks = vec![Mark::Unvisited; self.targets.len()];
+        let mut path = Vec::new();
+        let mut order = Vec::new();
+        for i in 0..self.targets.len() {
+            self.visit(i, &mut marks, &mut path, &mut order)?;
+        }
+        Ok(order)
+    }
+
+    fn visit(&self, i: usize, marks: &mut [Mark], path: &mut Vec<usize>, order: &mut Vec<&'a BuildTarget>) -> Result<(), String> {
+        match marks[i] {
+            Mark::Done => return Ok(()),
+            Mark::Visiting => {
+                let start = path.iter().position(|&p| p == i).unwrap_or(0);
+                let cycle: Vec<&str> = path[start..].iter().chain(std::iter::once(&i)).map(|&p| self.targets[p].name.as_str()).collect();
+                return Err(format!("dependency cycle detected: {}", cycle.join(" -> ")));
+            }
+            Mark::Unvisited => {}
+        }
+
+        marks[i] = Mark::Visiting;
+        path.push(i);
+        for dep in &self.targets[i].dependencies {
+            self.visit(self.index[dep.as_str()], marks, path, order)?;
+        }
+        path.pop();
+        marks[i] = Mark::Done;
+        order.push(&self.targets[i]);
+        Ok(())
+    }
+
+    /// `names` plus everything they transitively depend on
+    pub fn with_dependencies(&self, names: &[&str]) -> HashSet<&'a str> {
+        let mut included = HashSet::new();
+        let mut pending: Vec<usize> = names.iter().filter_map(|name| self.index.get(name).copied()).collect();
+        while let Some(i) = pending.pop() {
+            let target = &self.targets[i];
+            if included.insert(target.name.as_str()) {
+                pending.extend(target.dependencies.iter().map(|d| self.index[d.as_str()]));
+            }
+        }
+        included
+    }
+}
+
+/// Build the `selected` targets and their dependencies in dependency order.
+///
+/// Each target's cache hash covers its sources, `config_inputs` and its dependencies' hashes,
+/// and a target is also rebuilt whenever a dependency was rebuilt in this run. Dependents of a
+/// failed target are reported as failed without running `build`.
+pub fn build_targets<I, B>(
+    targets: &[BuildTarget],
+    selected: &[&str],
+    cache: &mut BuildCache,
+    force: bool,
+    config_inputs: I,
+    mut build: B,
+) -> Result<Vec<BuildResult>, Box<dyn std::error::Error>>
+where
+    I: Fn(&BuildTarget) -> Result<Vec<String>, Box<dyn std::error::Error>>,
+    B: FnMut(&BuildTarget) -> Result<BuildResult, Box<dyn std::error::Error>>,
+{
+    let graph = BuildGraph::new(targets)?;
+    let wanted = graph.with_dependencies(selected);
+
+    let mut hashes: HashMap<&str, String> = HashMap::new();
+    let mut rebuilt: HashSet<&str> = HashSet::new();
+    let mut failed: HashSet<&str> = HashSet::new();
+    let mut results = Vec::new();
+
+    for target in graph.build_order()?.into_iter().filter(|t| wanted.contains(t.name.as_str())) {
+        if let Some(dep) = target.dependencies.iter().find(|d| failed.contains(d.as_str())) {
+            failed.insert(target.name.as_str());
+            results.push(BuildResult {
+                target: target.name.clone(),
+                success: false,
+                cached: false,
+                duration: Duration::ZERO,
+                output_files: Vec::new(),
+                errors: vec![format!("dependency '{}' failed to build", dep)],
+                warnings: Vec::new(),
+            });
+            continue;
+        }
+
+        let mut inputs = config_inputs(target)?;
+        inputs.extend(target.dependencies.iter().map(|d| hashes.get(d.as_str()).cloned().unwrap_or_default()));
+        let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+        let hash = cache::hash_target(&target.path, &inputs)?;
+        hashes.insert(target.name.as_str(), hash.clone());
+
+        let dependency_rebuilt = target.dependencies.iter().any(|d| rebuilt.contains(d.as_str()));
+        let result = cache.build_or_reuse(&target.name, hash, force || dependency_rebuilt, || build(target))?;
+
+        if !result.cached {
+            rebuilt.insert(target.name.as_str());
+        }
+        if !result.success {
+            failed.insert(target.name.as_str());
+        }
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TargetType;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn target(workspace: &Path, name: &str, dependencies: &[&str]) -> BuildTarget {
+        BuildTarget {
+            name: name.to_string(),
+            path: workspace.join(name),
+            target_type: if dependencies.is_empty() { TargetType::Library } else { TargetType::Application },
+            dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+            features: Vec::new(),
+            profile: "release".to_string(),
+        }
+    }
+
+    /// Build through the graph with a fake compiler, returning results and the targets it ran
+    fn build(targets: &[BuildTarget], cache: &mut BuildCache, workspace: &Path) -> (Vec<BuildResult>, Vec<String>) {
+        let mut ran = Vec::new();
+        let selected: Vec<&str> = targets.iter().map(|t| t.name.as_str()).collect();
+        let results = build_targets(targets, &selected, cache, false, |t| Ok(vec![t.profile.clone()]), |t| {
+            ran.push(t.name.clone());
+            let output = workspace.join("out").join(&t.name);
+            fs::create_dir_all(workspace.join("out"))?;
+            fs::write(&output, "artifact")?;
+            Ok(BuildResult {
+                target: t.name.clone(),
+                success: true,
+                cached: false,
+                duration: Duration::from_millis(1),
+                output_files: vec![output],
+                errors: Vec::new(),
+                warnings: Vec::new(),
+            })
+        })
+        .unwrap();
+        (results, ran)
+    }
+
+    fn workspace_with(names: &[&str]) -> PathBuf {
+        let workspace = std::env::temp_dir().join(format!("raeen-graph-{}", uuid::Uuid::new_v4()));
+        for name in names {
+            fs::create_dir_all(workspace.join(name).join("src")).unwrap();
+            fs::write(workspace.join(name).join("src/lib.rs"), "pub fn f() {}").unwrap();
+        }
+        workspace
+    }
+
+    #[test]
+    fn test_library_change_rebuilds_dependents_only() {
+        let workspace = workspace_with(&["libui", "shell", "desktop", "init"]);
+        let targets = vec![
+            target(&workspace, "desktop", &["libui"]),
+            target(&workspace, "shell", &["desktop"]),
+            target(&workspace, "init", &[]),
+            target(&workspace, "libui", &[]),
+        ];
+        let mut cache = BuildCache::default();
+
+        let (_, ran) = build(&targets, &mut cache, &workspace);
+        assert_eq!(ran, ["libui", "desktop", "shell", "init"]);
+
+        let (results, ran) = build(&targets, &mut cache, &workspace);
+        assert!(ran.is_empty());
+        assert!(results.iter().all(|r| r.cached));
+
+        fs::write(workspace.join("libui/src/lib.rs"), "pub fn f() { let _ = 1; }").unwrap();
+        let (results, ran) = build(&targets, &mut cache, &workspace);
+        assert_eq!(ran, ["libui", "desktop", "shell"]);
+        assert!(results.iter().any(|r| r.target == "init" && r.cached));
+
+        fs::remove_dir_all(&workspace).unwrap();
+    }
+
+    #[test]
+    fn test_selected_target_pulls_in_dependencies() {
+        let workspace = workspace_with(&["libui", "desktop", "init"]);
+        let targets = vec![
+            target(&workspace, "desktop", &["libui"]),
+            target(&workspace, "init", &[]),
+            target(&workspace, "libui", &[]),
+        ];
+        let graph = BuildGraph::new(&targets).unwrap();
+        let wanted = graph.with_dependencies(&["desktop"]);
+        assert!(wanted.contains("desktop") && wanted.contains("libui") && !wanted.contains("init"));
+
+        fs::remove_dir_all(&workspace).unwrap();
+    }
+
+    #[test]
+    fn test_dependency_cycle_rejected() {
+        let workspace = PathBuf::from("/nonexistent");
+        let targets = vec![
+            target(&workspace, "a", &["b"]),
+            target(&workspace, "b", &["c"]),
+            target(&workspace, "c", &["a"]),
+        ];
+        let error = BuildGraph::new(&targets).unwrap().build_order().unwrap_err();
+        assert_eq!(error, "dependency cycle detected: a -> b -> c -> a");
+
+        let unknown = vec![target(&workspace, "a", &["missing"])];
+        assert!(BuildGraph::new(&unknown).is_err());
+    }
+}
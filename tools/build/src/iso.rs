@@ -0,0 +1,213 @@
+//! Bootable ISO images for raeen-build
+//! The kernel boots from the bootloader crate's BIOS disk image, which `cargo bootimage`
+//! links next to the kernel ELF as `bootimage-raeen_kernel.bin`. The ISO carries that image
+//! as an El Torito hard-disk emulation boot entry: the firmware maps it as the first hard
+//! disk and runs its boot sector. Emulation works out the drive geometry from the image's
+//! MBR partition table, so the staged copy gets a single partition spanning the image and is
+//! padded to whole cylinders. xorriso then writes the ISO from the staging directory.
+//!
+//! The bootloader has no UEFI entry point, so the images boot under BIOS (or CSM) only.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command as ProcessCommand;
+
+pub const XORRISO: &str = "xorriso";
+
+/// Where the boot image sits in the ISO, and where xorriso writes the boot catalog
+pub const BOOT_IMAGE: &str = "boot/raeen.img";
+pub const BOOT_CATALOG: &str = "boot/boot.cat";
+/// The kernel ELF, shipped alongside the image for its symbols
+pub const KERNEL: &str = "boot/raeen_kernel";
+
+const VOLUME_ID: &str = "RAEENOS";
+
+const SECTOR_SIZE: usize = 512;
+const PARTITION_TABLE: usize = 446;
+const PARTITION_ENTRY_SIZE: usize = 16;
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+/// Emulated geometry, the usual translation for small disks
+const HEADS: usize = 16;
+const SECTORS_PER_TRACK: usize = 63;
+const MAX_CYLINDER: usize = 1023;
+const ACTIVE: u8 = 0x80;
+/// "Non-filesystem data": the partition only describes the image's extent
+const PARTITION_TYPE: u8 = 0xDA;
+
+/// The BIOS disk image `cargo bootimage` builds from the kernel ELF at `kernel`
+pub fn boot_image_path(kernel: &Path) -> PathBuf {
+    let name = kernel.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    kernel.with_file_name(format!("bootimage-{}.bin", name))
+}
+
+/// Cylinder, head and sector of `lba`, packed the way partition entries store them
+fn chs(lba: usize) -> [u8; 3] {
+    let cylinder = lba / (HEADS * SECTORS_PER_TRACK);
+    let (cylinder, head, sector) = if cylinder > MAX_CYLINDER {
+        (MAX_CYLINDER, HEADS - 1, SECTORS_PER_TRACK)
+    } else {
+        (cylinder, (lba / SECTORS_PER_TRACK) % HEADS, lba % SECTORS_PER_TRACK + 1)
+    };
+    [head as u8, (sector as u8) | ((cylinder >> 2) as u8 & 0xC0), cylinder as u8]
+}
+
+/// `image` ready for hard-disk emulation: padded to whole cylinders, with a partition
+/// covering everything after the boot sector unless it already has a partition table
+pub fn prepare_boot_image(image: &[u8]) -> Result<Vec<u8>, String> {
+    if image.len() < SECTOR_SIZE || image[SECTOR_SIZE - 2..SECTOR_SIZE] != BOOT_SIGNATURE {
+        return Err("not a BIOS boot image: no 0x55AA boot signature".to_string());
+    }
+    let cylinder = HEADS * SECTORS_PER_TRACK * SECTOR_SIZE;
+    let mut prepared = image.to_vec();
+    prepared.resize(image.len().div_ceil(cylinder) * cylinder, 0);
+
+    let table = &prepared[PARTITION_TABLE..SECTOR_SIZE - 2];
+    if table.iter().all(|&byte| byte == 0) {
+        let sectors = prepared.len() / SECTOR_SIZE;
+        let entry = &mut prepared[PARTITION_TABLE..PARTITION_TABLE + PARTITION_ENTRY_SIZE];
+        entry[0] = ACTIVE;
+        entry[1..4].copy_from_slice(&chs(1));
+        entry[4] = PARTITION_TYPE;
+        entry[5..8].copy_from_slice(&chs(sectors - 1));
+        entry[8..12].copy_from_slice(&1u32.to_le_bytes());
+        entry[12..16].copy_from_slice(&((sectors - 1) as u32).to_le_bytes());
+    } else {
+        let entries = table.chunks(PARTITION_ENTRY_SIZE);
+        let is_table = entries.clone().all(|entry| entry[0] == 0 || entry[0] == ACTIVE) && entries.clone().any(|entry| entry[4] != 0);
+        if !is_table {
+            return Err("boot sector code overlaps the partition table, so the image cannot be emulated as a hard disk".to_string());
+        }
+    }
+    Ok(prepared)
+}
+
+/// Lay out the ISO's contents under `staging`, replacing anything already there
+pub fn stage(staging: &Path, boot_image: &Path, kernel: &Path) -> Result<(), String> {
+    if staging.exists() {
+        fs::remove_dir_all(staging).map_err(|e| format!("cannot clear {}: {}", staging.display(), e))?;
+    }
+    fs::create_dir_all(staging.join("boot")).map_err(|e| format!("cannot create {}: {}", staging.display(), e))?;
+    let image = fs::read(boot_image).map_err(|e| format!("cannot read boot image {}: {}", boot_image.display(), e))?;
+    let image = prepare_boot_image(&image).map_err(|e| format!("{}: {}", boot_image.display(), e))?;
+    fs::write(staging.join(BOOT_IMAGE), image).map_err(|e| format!("cannot stage boot image: {}", e))?;
+    fs::copy(kernel, staging.join(KERNEL)).map_err(|e| format!("cannot stage kernel {}: {}", kernel.display(), e))?;
+    Ok(())
+}
+
+/// `xorriso` in mkisofs mode writing `staging` to `iso` with the boot image as El Torito
+/// hard-disk emulation
+pub fn xorriso_command(xorriso: &str, staging: &Path, iso: &Path) -> ProcessCommand {
+    let mut cmd = ProcessCommand::new(xorriso);
+    cmd.args(["-as", "mkisofs", "-R", "-J", "-V", VOLUME_ID])
+        .arg("-o")
+        .arg(iso)
+        .args(["-b", BOOT_IMAGE, "-hard-disk-boot", "-c", BOOT_CATALOG])
+        .arg(staging);
+    cmd
+}
+
+/// Write `staging` to `iso` with `xorriso`. The error carries xorriso's stderr, or says how
+/// to get xorriso when it is not installed
+pub fn write_iso(xorriso: &str, staging: &Path, iso: &Path) -> Result<(), String> {
+    if iso.exists() {
+        fs::remove_file(iso).map_err(|e| format!("cannot replace {}: {}", iso.display(), e))?;
+    }
+    let output = match xorriso_command(xorriso, staging, iso).output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(format!("{} not found: install xorriso (e.g. `apt install xorriso` or `dnf install xorriso`) to build ISO images", xorriso));
+        }
+        Err(e) => return Err(format!("cannot run {}: {}", xorriso, e)),
+    };
+    if !output.status.success() {
+        return Err(format!("{} failed ({}): {}", xorriso, output.status, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    match fs::metadata(iso) {
+        Ok(metadata) if metadata.len() > 0 => Ok(()),
+        _ => Err(format!("{} exited successfully but {} is missing or empty", xorriso, iso.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A boot sector with some code and the signature, followed by `sectors` more sectors
+    fn boot_image(sectors: usize) -> Vec<u8> {
+        let mut image = vec![0u8; SECTOR_SIZE * (1 + sectors)];
+        image[..64].fill(0x90);
+        image[SECTOR_SIZE - 2..SECTOR_SIZE].copy_from_slice(&BOOT_SIGNATURE);
+        image[SECTOR_SIZE..].fill(0xAB);
+        image
+    }
+
+    #[test]
+    fn test_partition_added_and_image_padded() {
+        let image = boot_image(1500);
+        let prepared = prepare_boot_image(&image).unwrap();
+        // 1501 sectors round up to two cylinders of 16 heads of 63 sectors
+        assert_eq!(prepared.len(), 2 * 1008 * SECTOR_SIZE);
+        assert_eq!(prepared[..PARTITION_TABLE], image[..PARTITION_TABLE]);
+        assert_eq!(prepared[SECTOR_SIZE..image.len()], image[SECTOR_SIZE..]);
+
+        let entry = &prepared[PARTITION_TABLE..PARTITION_TABLE + PARTITION_ENTRY_SIZE];
+        assert_eq!(entry[0], ACTIVE);
+        assert_eq!(entry[1..4], [0, 2, 0]);
+        assert_eq!(entry[4], PARTITION_TYPE);
+        // Last sector: cylinder 1, head 15, sector 63
+        assert_eq!(entry[5..8], [15, 63, 1]);
+        assert_eq!(entry[8..12], 1u32.to_le_bytes());
+        assert_eq!(entry[12..16], 2015u32.to_le_bytes());
+        assert_eq!(prepared[PARTITION_TABLE + PARTITION_ENTRY_SIZE..SECTOR_SIZE - 2], [0; 48]);
+        assert_eq!(prepared[SECTOR_SIZE - 2..SECTOR_SIZE], BOOT_SIGNATURE);
+    }
+
+    #[test]
+    fn test_existing_table_kept_and_bad_images_rejected() {
+        let mut image = boot_image(10);
+        image[PARTITION_TABLE] = ACTIVE;
+        image[PARTITION_TABLE + 4] = 0x83;
+        let prepared = prepare_boot_image(&image).unwrap();
+        assert_eq!(prepared[..SECTOR_SIZE], image[..SECTOR_SIZE]);
+        assert_eq!(prepared.len(), 1008 * SECTOR_SIZE);
+
+        image[PARTITION_TABLE] = 0x90;
+        assert!(prepare_boot_image(&image).unwrap_err().contains("overlaps the partition table"));
+        assert!(prepare_boot_image(&[0; SECTOR_SIZE]).unwrap_err().contains("boot signature"));
+        assert!(prepare_boot_image(&BOOT_SIGNATURE).is_err());
+    }
+
+    #[test]
+    fn test_iso_written_only_when_xorriso_produces_it() {
+        let root = std::env::temp_dir().join(format!("raeen-iso-{}", uuid::Uuid::new_v4()));
+        let (staging, kernel, boot) = (root.join("iso-root"), root.join("raeen_kernel"), root.join("bootimage-raeen_kernel.bin"));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(&kernel, b"\x7fELF").unwrap();
+        fs::write(&boot, boot_image(4)).unwrap();
+        assert_eq!(boot_image_path(&kernel), boot);
+
+        stage(&staging, &boot, &kernel).unwrap();
+        assert_eq!(fs::read(staging.join(BOOT_IMAGE)).unwrap().len(), 1008 * SECTOR_SIZE);
+        assert_eq!(fs::read(staging.join(KERNEL)).unwrap(), b"\x7fELF");
+
+        let iso = root.join("raeen-os.iso");
+        let args: Vec<String> = xorriso_command(XORRISO, &staging, &iso).get_args().map(|a| a.to_string_lossy().into_owned()).collect();
+        let after = |flag: &str| args.iter().position(|a| a == flag).map(|i| args[i + 1].as_str());
+        assert_eq!(after("-b"), Some(BOOT_IMAGE));
+        assert_eq!(after("-o"), Some(iso.to_str().unwrap()));
+        assert!(args.contains(&"-hard-disk-boot".to_string()));
+        assert_eq!(args.last(), Some(&staging.display().to_string()));
+
+        let err = write_iso("raeen-no-such-xorriso", &staging, &iso).unwrap_err();
+        assert!(err.contains("not found") && err.contains("install xorriso"), "{}", err);
+        // A stale ISO does not count as output, nor does a tool that writes nothing
+        fs::write(&iso, b"stale").unwrap();
+        let err = write_iso("true", &staging, &iso).unwrap_err();
+        assert!(err.contains("missing or empty"), "{}", err);
+        assert!(!iso.exists());
+        let err = write_iso("false", &staging, &iso).unwrap_err();
+        assert!(err.starts_with("false failed"), "{}", err);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
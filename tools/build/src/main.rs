@@ -10,11 +10,24 @@ use toml_edit::Document;
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
+mod cache;
+mod graph;
+mod iso;
+mod toolchain;
+mod watch;
+use cache::BuildCache;
+use raeen_common::compat::CompatibilityConfig;
+use raeen_common::json::{self, JsonValue};
+use toolchain::{TargetSpec, ToolchainInfo};
+use std::cell::RefCell;
+
 // #[derive(Debug, Clone, Serialize, Deserialize)] // Temporarily disabled due to serde dependency conflicts
 #[derive(Debug, Clone)]
 struct BuildConfig {
     kernel_target: String,
     userspace_target: String,
+    aarch64_kernel_target: String,
+    aarch64_userspace_target: String,
     bootloader: String,
     iso_name: String,
     vmdk_name: String,
@@ -25,7 +38,7 @@ struct BuildConfig {
     compatibility: CompatibilityConfig,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone)] // Serialize, Deserialize temporarily disabled
 struct PackageConfig {
     repository_url: String,
     mirrors: Vec<String>,
@@ -34,7 +47,7 @@ struct PackageConfig {
     format_version: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone)] // Serialize, Deserialize temporarily disabled
 struct SecurityConfig {
     sandbox_default: String,
     code_signing_required: bool,
@@ -44,7 +57,7 @@ struct SecurityConfig {
     encryption_default: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone)] // Serialize, Deserialize temporarily disabled
 struct DevelopmentConfig {
     test_runner: String,
     benchmark_runner: String,
@@ -54,25 +67,38 @@ struct DevelopmentConfig {
     profiling_enabled: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct CompatibilityConfig {
-    wine_version: String,
-    android_api_level: u32,
-    web_engine: String,
-    flatpak_runtime: String,
-    snap_base: String,
-    appimage_runtime: String,
+/// CPU architecture the kernel and userspace are built for
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Arch {
+    X86_64,
+    Aarch64,
+}
+
+impl std::str::FromStr for Arch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "x86_64" => Ok(Arch::X86_64),
+            "aarch64" => Ok(Arch::Aarch64),
+            _ => Err(format!("unsupported architecture '{}' (expected x86_64 or aarch64)", s)),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 struct BuildContext {
     config: BuildConfig,
+    arch: Arch,
     workspace_root: PathBuf,
     build_dir: PathBuf,
     output_dir: PathBuf,
     target_dir: PathBuf,
     verbose: bool,
     parallel_jobs: usize,
+    /// Rebuild every target even if its cached hash still matches
+    force: bool,
+    cache: RefCell<BuildCache>,
 }
 
 #[derive(Debug, Clone)]
@@ -100,12 +126,48 @@ enum TargetType {
 struct BuildResult {
     target: String,
     success: bool,
+    /// Skipped because sources and settings were unchanged since the last successful build
+    cached: bool,
     duration: std::time::Duration,
     output_files: Vec<PathBuf>,
     errors: Vec<String>,
     warnings: Vec<String>,
 }
 
+impl BuildResult {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object(vec![
+            ("target", JsonValue::String(self.target.clone())),
+            ("success", JsonValue::Bool(self.success)),
+            ("cached", JsonValue::Bool(self.cached)),
+            ("duration_ms", JsonValue::Number(self.duration.as_millis() as f64)),
+            ("errors", JsonValue::string_array(self.errors.iter().cloned())),
+            ("warnings", JsonValue::string_array(self.warnings.iter().cloned())),
+            ("output_files", JsonValue::string_array(self.output_files.iter().map(|p| p.display().to_string()))),
+        ])
+    }
+
+    fn from_json(value: &JsonValue) -> Result<Self, String> {
+        Ok(Self {
+            target: value.field("target")?.as_str().ok_or("field 'target' is not a string")?.to_string(),
+            success: value.field("success")?.as_bool().ok_or("field 'success' is not a boolean")?,
+            cached: value.field("cached")?.as_bool().ok_or("field 'cached' is not a boolean")?,
+            duration: std::time::Duration::from_millis(
+                value.field("duration_ms")?.as_u64().ok_or("field 'duration_ms' is not a whole number")?,
+            ),
+            errors: value.string_list("errors")?,
+            warnings: value.string_list("warnings")?,
+            output_files: value.string_list("output_files")?.into_iter().map(PathBuf::from).collect(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
 fn main() {
     env_logger::init();
     
@@ -116,13 +178,19 @@ fn main() {
         .arg(Arg::new("command")
             .help("Build command to execute")
             .value_parser(["all", "kernel", "userspace", "bootloader", "iso", "vmdk", "test", "bench", "docs", "clean", "check"])
-            .required(true)
+            .required_unless_present("check-toolchain")
             .index(1))
         .arg(Arg::new("target")
             .help("Specific target to build")
             .short('t')
             .long("target")
             .value_name("TARGET"))
+        .arg(Arg::new("arch")
+            .help("Architecture to build for")
+            .long("arch")
+            .value_name("ARCH")
+            .value_parser(|s: &str| s.parse::<Arch>())
+            .default_value("x86_64"))
         .arg(Arg::new("profile")
             .help("Build profile to use")
             .short('p')
@@ -159,12 +227,61 @@ fn main() {
             .long("workspace")
             .value_name("DIR")
             .default_value("."))
+        .arg(Arg::new("format")
+            .help("Summary output format")
+            .long("format")
+            .value_name("FORMAT")
+            .value_parser(["text", "json"])
+            .default_value("text"))
+        .arg(Arg::new("force")
+            .help("Rebuild all targets, ignoring the build cache")
+            .long("force")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("watch")
+            .help("Rebuild affected targets whenever their sources change")
+            .long("watch")
+            .action(clap::ArgAction::SetTrue))
+        .arg(Arg::new("check-toolchain")
+            .help("Verify the toolchain has everything needed to cross-compile the kernel, then exit")
+            .long("check-toolchain")
+            .action(clap::ArgAction::SetTrue))
         .get_matches();
     
+    let format = match matches.get_one::<String>("format").map(String::as_str) {
+        Some("json") => OutputFormat::Json,
+        _ => OutputFormat::Text,
+    };
+    
+    if matches.get_flag("check-toolchain") {
+        let result = match check_toolchain(&matches) {
+            Ok(true) => 0,
+            Ok(false) => 1,
+            Err(e) => {
+                error!("Toolchain check failed: {}", e);
+                1
+            }
+        };
+        std::process::exit(result);
+    }
+    
+    if matches.get_flag("watch") {
+        let result = match run_watch(&matches, format) {
+            Ok(()) => 0,
+            Err(e) => {
+                error!("Watch mode failed: {}", e);
+                1
+            }
+        };
+        std::process::exit(result);
+    }
+    
     let result = match run_build(&matches) {
         Ok(results) => {
             info!("Build completed successfully!");
-            print_build_summary(&results);
+            match format {
+                OutputFormat::Text => print_build_summary(&results),
+                OutputFormat::Json => println!("{}", build_results_to_json(&results)),
+            }
             0
         }
         Err(e) => {
@@ -176,18 +293,21 @@ fn main() {
     std::process::exit(result);
 }
 
-fn run_build(matches: &ArgMatches) -> Result<Vec<BuildResult>, Box<dyn std::error::Error>> {
+fn create_context(matches: &ArgMatches) -> Result<BuildContext, Box<dyn std::error::Error>> {
     let workspace_root = PathBuf::from(matches.get_one::<String>("workspace").unwrap());
     let config = load_build_config(&workspace_root)?;
     
     let context = BuildContext {
         config,
+        arch: *matches.get_one::<Arch>("arch").unwrap(),
         workspace_root: workspace_root.clone(),
         build_dir: workspace_root.join("build"),
         output_dir: PathBuf::from(matches.get_one::<String>("output").unwrap()),
         target_dir: workspace_root.join("target"),
         verbose: matches.get_flag("verbose"),
         parallel_jobs: *matches.get_one::<usize>("jobs").unwrap(),
+        force: matches.get_flag("force"),
+        cache: RefCell::new(BuildCache::load(&workspace_root.join("build").join(cache::CACHE_FILE))),
     };
     
     // Create build directories
@@ -195,19 +315,29 @@ fn run_build(matches: &ArgMatches) -> Result<Vec<BuildResult>, Box<dyn std::erro
     fs::create_dir_all(&context.output_dir)?;
     fs::create_dir_all(&context.target_dir)?;
     
-    let command = matches.get_one::<String>("command").unwrap();
-    let profile = matches.get_one::<String>("profile").unwrap();
-    let features: Vec<String> = matches.get_many::<String>("features")
+    Ok(context)
+}
+
+fn build_features(matches: &ArgMatches) -> Vec<String> {
+    matches.get_many::<String>("features")
         .unwrap_or_default()
         .cloned()
-        .collect();
+        .collect()
+}
+
+fn run_build(matches: &ArgMatches) -> Result<Vec<BuildResult>, Box<dyn std::error::Error>> {
+    let context = create_context(matches)?;
     
-    match command.as_str() {
+    let command = matches.get_one::<String>("command").unwrap();
+    let profile = matches.get_one::<String>("profile").unwrap();
+    let features = build_features(matches);
+    
+    let results = match command.as_str() {
         "all" => build_all(&context, profile, &features),
         "kernel" => build_kernel(&context, profile, &features),
         "userspace" => build_userspace(&context, profile, &features),
         "bootloader" => build_bootloader(&context, profile, &features),
-        "iso" => build_iso(&context),
+        "iso" => build_iso(&context, profile, &features),
         "vmdk" => build_vmdk(&context),
         "test" => run_tests(&context),
         "bench" => run_benchmarks(&context),
@@ -215,6 +345,78 @@ fn run_build(matches: &ArgMatches) -> Result<Vec<BuildResult>, Box<dyn std::erro
         "clean" => clean_build(&context),
         "check" => check_code(&context),
         _ => Err(format!("Unknown command: {}", command).into()),
+    }?;
+    
+    if command != "clean" {
+        context.cache.borrow_mut().save(&context.build_dir.join(cache::CACHE_FILE))?;
+    }
+    
+    Ok(results)
+}
+
+/// Build once, then rebuild whenever the selected targets' sources change
+fn run_watch(matches: &ArgMatches, format: OutputFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let context = create_context(matches)?;
+    let command = matches.get_one::<String>("command").unwrap();
+    let profile = matches.get_one::<String>("profile").unwrap();
+    let features = build_features(matches);
+    
+    let select: fn(&BuildTarget) -> bool = match command.as_str() {
+        "all" => |_| true,
+        "kernel" => |t| t.target_type == TargetType::Kernel,
+        "userspace" => |t| t.target_type == TargetType::Userspace,
+        "bootloader" => |t| t.target_type == TargetType::Bootloader,
+        _ => return Err(format!("--watch is not supported for '{}'", command).into()),
+    };
+    
+    let rebuild = || -> Result<(), Box<dyn std::error::Error>> {
+        let results = build_selected(&context, profile, &features, select)?;
+        context.cache.borrow_mut().save(&context.build_dir.join(cache::CACHE_FILE))?;
+        print_watch_cycle(&results, format);
+        Ok(())
+    };
+    rebuild()?;
+    
+    // Watch the selected targets and everything they depend on
+    let targets = discover_targets(&context, profile, &features);
+    let selected: Vec<&str> = targets.iter().filter(|t| select(t)).map(|t| t.name.as_str()).collect();
+    let watched_names = graph::BuildGraph::new(&targets)?.with_dependencies(&selected);
+    let watched: Vec<BuildTarget> = targets.iter().filter(|t| watched_names.contains(t.name.as_str())).cloned().collect();
+    
+    let watcher = watch::SourceWatcher::new(&watched)?;
+    info!("Watching {} target(s) for changes; press Ctrl+C to stop", watched.len());
+    
+    let never_stop = std::sync::atomic::AtomicBool::new(false);
+    watcher.run(watch::DEFAULT_DEBOUNCE, &never_stop, |changed| {
+        info!("Changes detected in {}", changed.join(", "));
+        rebuild()
+    })
+}
+
+/// One concise line per watch cycle, plus the errors of any failed target
+fn print_watch_cycle(results: &[BuildResult], format: OutputFormat) {
+    if format == OutputFormat::Json {
+        println!("{}", build_results_to_json(results));
+        return;
+    }
+    
+    let cached = results.iter().filter(|r| r.cached).count();
+    let failed: Vec<&BuildResult> = results.iter().filter(|r| !r.success).collect();
+    let duration: std::time::Duration = results.iter().map(|r| r.duration).sum();
+    println!(
+        "[{}] {} target(s): {} rebuilt, {} cached, {} failed in {:?}",
+        Utc::now().format("%H:%M:%S"),
+        results.len(),
+        results.len() - cached - failed.len(),
+        cached,
+        failed.len(),
+        duration,
+    );
+    for result in failed {
+        println!("  FAIL {}", result.target);
+        for error in &result.errors {
+            println!("    {}", error.lines().next().unwrap_or_default());
+        }
     }
 }
 
@@ -229,6 +431,8 @@ fn load_build_config(workspace_root: &Path) -> Result<BuildConfig, Box<dyn std::
     let config = BuildConfig {
         kernel_target: metadata["kernel_target"].as_str().unwrap_or("x86_64-raeen").to_string(),
         userspace_target: metadata["userspace_target"].as_str().unwrap_or("x86_64-unknown-linux-gnu").to_string(),
+        aarch64_kernel_target: metadata.get("aarch64_kernel_target").and_then(|v| v.as_str()).unwrap_or("aarch64-unknown-none").to_string(),
+        aarch64_userspace_target: metadata.get("aarch64_userspace_target").and_then(|v| v.as_str()).unwrap_or("aarch64-unknown-linux-gnu").to_string(),
         bootloader: metadata["bootloader"].as_str().unwrap_or("raeen-bootloader").to_string(),
         iso_name: metadata["iso_name"].as_str().unwrap_or("raeen-os.iso").to_string(),
         vmdk_name: metadata["vmdk_name"].as_str().unwrap_or("raeen-os.vmdk").to_string(),
@@ -258,56 +462,190 @@ fn load_build_config(workspace_root: &Path) -> Result<BuildConfig, Box<dyn std::
             debugging_symbols: true,
             profiling_enabled: true,
         },
-        compatibility: CompatibilityConfig {
-            wine_version: "8.0".to_string(),
-            android_api_level: 33,
-            web_engine: "webkit".to_string(),
-            flatpak_runtime: "org.freedesktop.Platform".to_string(),
-            snap_base: "core22".to_string(),
-            appimage_runtime: "runtime-x86_64".to_string(),
-        },
+        compatibility: CompatibilityConfig::load(workspace_root),
     };
     
     Ok(config)
 }
 
-fn build_all(context: &BuildContext, profile: &str, features: &[String]) -> Result<Vec<BuildResult>, Box<dyn std::error::Error>> {
-    info!("Building all components...");
-    
-    let mut results = Vec::new();
-    
-    // Build in dependency order
-    results.extend(build_kernel(context, profile, features)?);
-    results.extend(build_userspace(context, profile, features)?);
-    results.extend(build_bootloader(context, profile, features)?);
-    results.extend(build_compatibility_layers(context, profile, features)?);
+/// Component directories built by raeen-build, in declaration order
+const COMPONENTS: &[(&str, TargetType)] = &[
+    ("kernel", TargetType::Kernel),
+    ("userspace/init", TargetType::Userspace),
+    ("userspace/shell", TargetType::Userspace),
+    ("userspace/desktop", TargetType::Userspace),
+    ("bootloader", TargetType::Bootloader),
+    ("compatibility/wine", TargetType::Compatibility),
+    ("compatibility/android", TargetType::Compatibility),
+    ("compatibility/web", TargetType::Compatibility),
+];
+
+/// Paths of `path = "..."` dependencies declared in a crate's Cargo.toml
+fn path_dependencies(crate_dir: &Path) -> Vec<PathBuf> {
+    let document = match fs::read_to_string(crate_dir.join("Cargo.toml")).map(|c| c.parse::<Document>()) {
+        Ok(Ok(document)) => document,
+        _ => return Vec::new(),
+    };
     
-    Ok(results)
+    let mut paths = Vec::new();
+    for section in ["dependencies", "build-dependencies"] {
+        if let Some(table) = document.get(section).and_then(|item| item.as_table_like()) {
+            for (_, dependency) in table.iter() {
+                if let Some(path) = dependency.get("path").and_then(|p| p.as_str()) {
+                    paths.push(crate_dir.join(path));
+                }
+            }
+        }
+    }
+    paths
 }
 
-fn build_kernel(context: &BuildContext, profile: &str, features: &[String]) -> Result<Vec<BuildResult>, Box<dyn std::error::Error>> {
-    info!("Building kernel...");
+/// Collect the components present in the workspace plus any workspace-local libraries they
+/// depend on, with dependencies taken from each crate's path dependencies
+fn discover_targets(context: &BuildContext, profile: &str, features: &[String]) -> Vec<BuildTarget> {
+    let mut targets: Vec<BuildTarget> = COMPONENTS
+        .iter()
+        .filter(|(dir, target_type)| *target_type == TargetType::Kernel || context.workspace_root.join(dir).exists())
+        .map(|(dir, target_type)| BuildTarget {
+            name: dir.to_string(),
+            path: context.workspace_root.join(dir),
+            target_type: target_type.clone(),
+            dependencies: Vec::new(),
+            features: features.to_vec(),
+            // Compatibility layers always use the compatibility profile
+            profile: if *target_type == TargetType::Compatibility { "compatibility".to_string() } else { profile.to_string() },
+        })
+        .collect();
     
-    let start_time = std::time::Instant::now();
-    let kernel_dir = context.workspace_root.join("kernel");
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut i = 0;
+    while i < targets.len() {
+        for dependency_dir in path_dependencies(&targets[i].path) {
+            let dependency_dir = canonical(&dependency_dir);
+            let existing = targets.iter().find(|t| canonical(&t.path) == dependency_dir).map(|t| t.name.clone());
+            let name = match existing {
+                Some(name) => name,
+                None => {
+                    // A workspace-local library not listed as a component
+                    let name = match dependency_dir.strip_prefix(canonical(&context.workspace_root)) {
+                        Ok(relative) => relative.display().to_string(),
+                        Err(_) => continue,
+                    };
+                    targets.push(BuildTarget {
+                        name: name.clone(),
+                        path: dependency_dir,
+                        target_type: TargetType::Library,
+                        dependencies: Vec::new(),
+                        features: Vec::new(),
+                        profile: profile.to_string(),
+                    });
+                    name
+                }
+            };
+            if !targets[i].dependencies.contains(&name) {
+                targets[i].dependencies.push(name);
+            }
+        }
+        i += 1;
+    }
     
+    targets
+}
+
+fn kernel_triple(context: &BuildContext) -> &str {
+    match context.arch {
+        Arch::X86_64 => &context.config.kernel_target,
+        Arch::Aarch64 => &context.config.aarch64_kernel_target,
+    }
+}
+
+fn kernel_spec(context: &BuildContext) -> TargetSpec {
+    TargetSpec::resolve(&context.workspace_root, kernel_triple(context))
+}
+
+fn target_triple<'a>(context: &'a BuildContext, target: &BuildTarget) -> Option<&'a str> {
+    match (&target.target_type, context.arch) {
+        (TargetType::Kernel, _) => Some(kernel_triple(context)),
+        (TargetType::Userspace, Arch::X86_64) => Some(context.config.userspace_target.as_str()),
+        (TargetType::Userspace, Arch::Aarch64) => Some(context.config.aarch64_userspace_target.as_str()),
+        _ => None,
+    }
+}
+
+/// Inputs besides the source tree that change a target's build output
+fn cache_inputs(context: &BuildContext, target: &BuildTarget) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut inputs = vec![
+        target.profile.clone(),
+        target.features.join(","),
+        target_triple(context, target).unwrap_or("host").to_string(),
+        fs::read_to_string(context.workspace_root.join("Cargo.toml"))?,
+    ];
+    if target.target_type == TargetType::Kernel {
+        if let TargetSpec::Custom(path) = kernel_spec(context) {
+            inputs.push(fs::read_to_string(path)?);
+        }
+    }
+    Ok(inputs)
+}
+
+/// Where cargo links the kernel ELF for `profile`
+fn kernel_output(context: &BuildContext, profile: &str) -> PathBuf {
+    context.target_dir.join(kernel_spec(context).triple()).join(profile).join("raeen_kernel")
+}
+
+fn cargo_build_command(context: &BuildContext, target: &BuildTarget) -> ProcessCommand {
+    cargo_command(context, target, "build")
+}
+
+/// `cargo <subcommand>` for `target` with its profile, target triple and features
+fn cargo_command(context: &BuildContext, target: &BuildTarget, subcommand: &str) -> ProcessCommand {
     let mut cmd = ProcessCommand::new("cargo");
-    cmd.current_dir(&kernel_dir)
-        .arg("build")
+    cmd.current_dir(&target.path)
+        .arg(subcommand)
         .arg("--profile")
-        .arg(profile)
-        .arg("--target")
-        .arg(&context.config.kernel_target);
+        .arg(&target.profile);
+    
+    if target.target_type == TargetType::Kernel {
+        cmd.args(toolchain::cross_args(&kernel_spec(context)));
+    } else if let Some(triple) = target_triple(context, target) {
+        cmd.arg("--target").arg(triple);
+    }
     
-    if !features.is_empty() {
-        cmd.arg("--features").arg(features.join(","));
+    if !target.features.is_empty() {
+        cmd.arg("--features").arg(target.features.join(","));
     }
     
     if context.verbose {
         cmd.arg("--verbose");
     }
     
-    let output = cmd.output()?;
+    cmd
+}
+
+fn cargo_build(context: &BuildContext, target: &BuildTarget) -> Result<BuildResult, Box<dyn std::error::Error>> {
+    let start_time = std::time::Instant::now();
+    
+    if target.target_type == TargetType::Kernel {
+        let issues = ToolchainInfo::probe(&context.workspace_root)
+            .map(|info| toolchain::verify(&info, &kernel_spec(context)))
+            .unwrap_or_else(|e| vec![e]);
+        if !issues.is_empty() {
+            for issue in &issues {
+                error!("{}", issue);
+            }
+            return Ok(BuildResult {
+                target: target.name.clone(),
+                success: false,
+                cached: false,
+                duration: start_time.elapsed(),
+                output_files: Vec::new(),
+                errors: issues,
+                warnings: Vec::new(),
+            });
+        }
+    }
+    
+    let output = cargo_build_command(context, target).output()?;
     let duration = start_time.elapsed();
     
     let success = output.status.success();
@@ -315,223 +653,156 @@ fn build_kernel(context: &BuildContext, profile: &str, features: &[String]) -> R
     let stderr = String::from_utf8_lossy(&output.stderr);
     
     if context.verbose {
-        println!("Kernel build stdout: {}", stdout);
-        println!("Kernel build stderr: {}", stderr);
+        println!("{} build stdout: {}", target.name, stdout);
+        println!("{} build stderr: {}", target.name, stderr);
     }
     
-    let result = BuildResult {
-        target: "kernel".to_string(),
+    if success {
+        info!("{} build completed in {:?}", target.name, duration);
+    } else {
+        error!("{} build failed: {}", target.name, stderr);
+    }
+    
+    let output_files = match target.target_type {
+        TargetType::Kernel if success => {
+            vec![kernel_output(context, &target.profile)]
+        }
+        _ => Vec::new(), // TODO: Determine output files for other targets
+    };
+    
+    Ok(BuildResult {
+        target: target.name.clone(),
         success,
+        cached: false,
         duration,
-        output_files: if success {
-            vec![context.target_dir.join(&context.config.kernel_target).join(profile).join("raeen_kernel")]
-        } else {
-            Vec::new()
-        },
+        output_files,
         errors: if success { Vec::new() } else { vec![stderr.to_string()] },
         warnings: Vec::new(),
-    };
+    })
+}
+
+/// Build the targets matching `select`, plus their dependencies, in dependency order
+fn build_selected<S>(context: &BuildContext, profile: &str, features: &[String], select: S) -> Result<Vec<BuildResult>, Box<dyn std::error::Error>>
+where
+    S: Fn(&BuildTarget) -> bool,
+{
+    let targets = discover_targets(context, profile, features);
+    let selected: Vec<&str> = targets.iter().filter(|t| select(t)).map(|t| t.name.as_str()).collect();
+
+    graph::build_targets(
+        &targets,
+        &selected,
+        &mut context.cache.borrow_mut(),
+        context.force,
+        |target| cache_inputs(context, target),
+        |target| cargo_build(context, target),
+    )
+}
+
+fn build_all(context: &BuildContext, profile: &str, features: &[String]) -> Result<Vec<BuildResult>, Box<dyn std::error::Error>> {
+    info!("Building all components...");
     
-    if success {
-        info!("Kernel build completed in {:?}", duration);
-    } else {
-        error!("Kernel build failed: {}", stderr);
-    }
+    build_selected(context, profile, features, |_| true)
+}
+
+fn build_kernel(context: &BuildContext, profile: &str, features: &[String]) -> Result<Vec<BuildResult>, Box<dyn std::error::Error>> {
+    info!("Building kernel...");
     
-    Ok(vec![result])
+    build_selected(context, profile, features, |t| t.target_type == TargetType::Kernel)
 }
 
 fn build_userspace(context: &BuildContext, profile: &str, features: &[String]) -> Result<Vec<BuildResult>, Box<dyn std::error::Error>> {
     info!("Building userspace components...");
     
-    let mut results = Vec::new();
-    let userspace_dirs = [
-        "userspace/init",
-        "userspace/shell",
-        "userspace/desktop",
-    ];
-    
-    for dir in &userspace_dirs {
-        let component_dir = context.workspace_root.join(dir);
-        if component_dir.exists() {
-            let start_time = std::time::Instant::now();
-            
-            let mut cmd = ProcessCommand::new("cargo");
-            cmd.current_dir(&component_dir)
-                .arg("build")
-                .arg("--profile")
-                .arg(profile)
-                .arg("--target")
-                .arg(&context.config.userspace_target);
-            
-            if !features.is_empty() {
-                cmd.arg("--features").arg(features.join(","));
-            }
-            
-            if context.verbose {
-                cmd.arg("--verbose");
-            }
-            
-            let output = cmd.output()?;
-            let duration = start_time.elapsed();
-            
-            let success = output.status.success();
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            
-            let result = BuildResult {
-                target: dir.to_string(),
-                success,
-                duration,
-                output_files: Vec::new(), // TODO: Determine actual output files
-                errors: if success { Vec::new() } else { vec![stderr.to_string()] },
-                warnings: Vec::new(),
-            };
-            
-            if success {
-                info!("{} build completed in {:?}", dir, duration);
-            } else {
-                error!("{} build failed: {}", dir, stderr);
-            }
-            
-            results.push(result);
-        }
-    }
-    
-    Ok(results)
+    build_selected(context, profile, features, |t| t.target_type == TargetType::Userspace)
 }
 
 fn build_bootloader(context: &BuildContext, profile: &str, features: &[String]) -> Result<Vec<BuildResult>, Box<dyn std::error::Error>> {
     info!("Building bootloader...");
     
-    let start_time = std::time::Instant::now();
-    let bootloader_dir = context.workspace_root.join("bootloader");
-    
-    if !bootloader_dir.exists() {
+    if !context.workspace_root.join("bootloader").exists() {
         warn!("Bootloader directory not found, skipping...");
         return Ok(Vec::new());
     }
     
-    let mut cmd = ProcessCommand::new("cargo");
-    cmd.current_dir(&bootloader_dir)
-        .arg("build")
-        .arg("--profile")
-        .arg(profile);
-    
-    if !features.is_empty() {
-        cmd.arg("--features").arg(features.join(","));
-    }
-    
-    if context.verbose {
-        cmd.arg("--verbose");
+    build_selected(context, profile, features, |t| t.target_type == TargetType::Bootloader)
+}
+
+/// Link the bootloader and the kernel at `kernel` into a BIOS disk image with `cargo bootimage`
+fn build_boot_image(context: &BuildContext, profile: &str, features: &[String]) -> Result<(), String> {
+    let kernel = discover_targets(context, profile, features)
+        .into_iter()
+        .find(|t| t.target_type == TargetType::Kernel)
+        .ok_or("no kernel target in the workspace")?;
+    let output = cargo_command(context, &kernel, "bootimage")
+        .output()
+        .map_err(|e| format!("cargo not found ({}): install Rust from https://rustup.rs", e))?;
+    if output.status.success() {
+        return Ok(());
     }
-    
-    let output = cmd.output()?;
-    let duration = start_time.elapsed();
-    
-    let success = output.status.success();
     let stderr = String::from_utf8_lossy(&output.stderr);
-    
-    let result = BuildResult {
-        target: "bootloader".to_string(),
-        success,
-        duration,
-        output_files: Vec::new(),
-        errors: if success { Vec::new() } else { vec![stderr.to_string()] },
-        warnings: Vec::new(),
-    };
-    
-    if success {
-        info!("Bootloader build completed in {:?}", duration);
+    if stderr.contains("no such command") {
+        Err("bootimage not installed, needed to link the kernel into a boot image: run `cargo install bootimage`".to_string())
     } else {
-        error!("Bootloader build failed: {}", stderr);
+        Err(format!("cargo bootimage failed: {}", stderr.trim()))
     }
-    
-    Ok(vec![result])
 }
 
-fn build_compatibility_layers(context: &BuildContext, profile: &str, features: &[String]) -> Result<Vec<BuildResult>, Box<dyn std::error::Error>> {
-    info!("Building compatibility layers...");
-    
-    let mut results = Vec::new();
-    let compat_dirs = [
-        "compatibility/wine",
-        "compatibility/android",
-        "compatibility/web",
-    ];
+/// Stage the boot image for the kernel at `kernel`, linking it first if it is missing or
+/// older than the kernel, and write the ISO to `iso_path`
+fn write_iso_image(context: &BuildContext, profile: &str, features: &[String], kernel: &Path, iso_path: &Path) -> Result<(), String> {
+    if context.arch != Arch::X86_64 {
+        return Err("ISO images boot the x86_64 BIOS image; build them with --arch x86_64".to_string());
+    }
+    if !kernel.exists() {
+        return Err(format!("kernel {} was not built", kernel.display()));
+    }
     
-    for dir in &compat_dirs {
-        let compat_dir = context.workspace_root.join(dir);
-        if compat_dir.exists() {
-            let start_time = std::time::Instant::now();
-            
-            let mut cmd = ProcessCommand::new("cargo");
-            cmd.current_dir(&compat_dir)
-                .arg("build")
-                .arg("--profile")
-                .arg("compatibility"); // Use compatibility profile
-            
-            if !features.is_empty() {
-                cmd.arg("--features").arg(features.join(","));
-            }
-            
-            if context.verbose {
-                cmd.arg("--verbose");
-            }
-            
-            let output = cmd.output()?;
-            let duration = start_time.elapsed();
-            
-            let success = output.status.success();
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            
-            let result = BuildResult {
-                target: dir.to_string(),
-                success,
-                duration,
-                output_files: Vec::new(),
-                errors: if success { Vec::new() } else { vec![stderr.to_string()] },
-                warnings: Vec::new(),
-            };
-            
-            if success {
-                info!("{} build completed in {:?}", dir, duration);
-            } else {
-                error!("{} build failed: {}", dir, stderr);
-            }
-            
-            results.push(result);
-        }
+    let boot_image = iso::boot_image_path(kernel);
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    if modified(&boot_image) < modified(kernel) {
+        info!("Linking boot image {}...", boot_image.display());
+        build_boot_image(context, profile, features)?;
     }
     
-    Ok(results)
+    let staging = context.build_dir.join("iso-root");
+    iso::stage(&staging, &boot_image, kernel)?;
+    fs::create_dir_all(&context.output_dir).map_err(|e| format!("cannot create {}: {}", context.output_dir.display(), e))?;
+    iso::write_iso(iso::XORRISO, &staging, iso_path)
 }
 
-fn build_iso(context: &BuildContext) -> Result<Vec<BuildResult>, Box<dyn std::error::Error>> {
+fn build_iso(context: &BuildContext, profile: &str, features: &[String]) -> Result<Vec<BuildResult>, Box<dyn std::error::Error>> {
     info!("Building ISO image...");
     
     let start_time = std::time::Instant::now();
+    let iso_path = context.output_dir.join(&context.config.iso_name);
+    let kernel = kernel_output(context, profile);
     
-    // TODO: Implement ISO creation logic
-    // This would involve:
-    // 1. Creating a temporary directory structure
-    // 2. Copying kernel, bootloader, and userspace binaries
-    // 3. Creating boot configuration
-    // 4. Using tools like genisoimage or xorriso to create the ISO
+    let mut results = Vec::new();
+    if !kernel.exists() {
+        info!("Kernel not built yet, building it first...");
+        results.extend(build_kernel(context, profile, features)?);
+    }
     
+    let outcome = write_iso_image(context, profile, features, &kernel, &iso_path);
     let duration = start_time.elapsed();
     
-    let result = BuildResult {
+    match &outcome {
+        Ok(()) => info!("ISO build completed in {:?}: {}", duration, iso_path.display()),
+        Err(e) => error!("ISO build failed: {}", e),
+    }
+    
+    results.push(BuildResult {
         target: "iso".to_string(),
-        success: true, // TODO: Implement actual ISO creation
+        success: outcome.is_ok(),
+        cached: false,
         duration,
-        output_files: vec![context.output_dir.join(&context.config.iso_name)],
-        errors: Vec::new(),
-        warnings: vec!["ISO creation not yet implemented".to_string()],
-    };
-    
-    info!("ISO build completed in {:?}", duration);
+        output_files: if outcome.is_ok() { vec![iso_path] } else { Vec::new() },
+        errors: outcome.err().into_iter().collect(),
+        warnings: Vec::new(),
+    });
     
-    Ok(vec![result])
+    Ok(results)
 }
 
 fn build_vmdk(context: &BuildContext) -> Result<Vec<BuildResult>, Box<dyn std::error::Error>> {
@@ -546,6 +817,7 @@ fn build_vmdk(context: &BuildContext) -> Result<Vec<BuildResult>, Box<dyn std::e
     let result = BuildResult {
         target: "vmdk".to_string(),
         success: true, // TODO: Implement actual VMDK creation
+        cached: false,
         duration,
         output_files: vec![context.output_dir.join(&context.config.vmdk_name)],
         errors: Vec::new(),
@@ -580,6 +852,7 @@ fn run_tests(context: &BuildContext) -> Result<Vec<BuildResult>, Box<dyn std::er
     let result = BuildResult {
         target: "tests".to_string(),
         success,
+        cached: false,
         duration,
         output_files: Vec::new(),
         errors: if success { Vec::new() } else { vec![stderr.to_string()] },
@@ -618,6 +891,7 @@ fn run_benchmarks(context: &BuildContext) -> Result<Vec<BuildResult>, Box<dyn st
     let result = BuildResult {
         target: "benchmarks".to_string(),
         success,
+        cached: false,
         duration,
         output_files: Vec::new(),
         errors: if success { Vec::new() } else { vec![stderr.to_string()] },
@@ -657,6 +931,7 @@ fn build_documentation(context: &BuildContext) -> Result<Vec<BuildResult>, Box<d
     let result = BuildResult {
         target: "documentation".to_string(),
         success,
+        cached: false,
         duration,
         output_files: vec![context.target_dir.join("doc")],
         errors: if success { Vec::new() } else { vec![stderr.to_string()] },
@@ -699,6 +974,7 @@ fn clean_build(context: &BuildContext) -> Result<Vec<BuildResult>, Box<dyn std::
     let result = BuildResult {
         target: "clean".to_string(),
         success,
+        cached: false,
         duration,
         output_files: Vec::new(),
         errors: Vec::new(),
@@ -720,6 +996,11 @@ fn check_code(context: &BuildContext) -> Result<Vec<BuildResult>, Box<dyn std::e
         .arg("check")
         .arg("--workspace");
     
+    // x86_64 checks against the default target in .cargo/config.toml
+    if context.arch != Arch::X86_64 {
+        cmd.arg("--target").arg(kernel_triple(context));
+    }
+    
     if context.verbose {
         cmd.arg("--verbose");
     }
@@ -733,6 +1014,7 @@ fn check_code(context: &BuildContext) -> Result<Vec<BuildResult>, Box<dyn std::e
     let result = BuildResult {
         target: "check".to_string(),
         success,
+        cached: false,
         duration,
         output_files: Vec::new(),
         errors: if success { Vec::new() } else { vec![stderr.to_string()] },
@@ -748,18 +1030,49 @@ fn check_code(context: &BuildContext) -> Result<Vec<BuildResult>, Box<dyn std::e
     Ok(vec![result])
 }
 
+/// Report whether the toolchain can cross-compile the kernel for the selected arch
+fn check_toolchain(matches: &ArgMatches) -> Result<bool, Box<dyn std::error::Error>> {
+    let context = create_context(matches)?;
+    let spec = kernel_spec(&context);
+    let info = ToolchainInfo::probe(&context.workspace_root)?;
+    let issues = toolchain::verify(&info, &spec);
+    
+    println!("Toolchain: {}", info.version);
+    println!("Sysroot: {}", info.sysroot.display());
+    println!("Kernel target: {}", spec.target_arg());
+    if issues.is_empty() {
+        println!("Toolchain OK");
+    } else {
+        println!("Toolchain is missing {} requirement(s):", issues.len());
+        for issue in &issues {
+            println!("  - {}", issue);
+        }
+    }
+    
+    Ok(issues.is_empty())
+}
+
 fn print_build_summary(results: &[BuildResult]) {
     println!("\n=== Build Summary ===");
     
     let total_duration: std::time::Duration = results.iter().map(|r| r.duration).sum();
     let successful = results.iter().filter(|r| r.success).count();
     let failed = results.len() - successful;
+    let cached: Vec<&BuildResult> = results.iter().filter(|r| r.cached).collect();
     
     println!("Total targets: {}", results.len());
     println!("Successful: {}", successful);
     println!("Failed: {}", failed);
+    println!("Cached: {}", cached.len());
     println!("Total time: {:?}", total_duration);
     
+    if !cached.is_empty() {
+        println!("\nCached targets:");
+        for result in cached {
+            println!("  - {} (cached)", result.target);
+        }
+    }
+    
     if failed > 0 {
         println!("\nFailed targets:");
         for result in results.iter().filter(|r| !r.success) {
@@ -785,4 +1098,142 @@ fn print_build_summary(results: &[BuildResult]) {
             println!("  - {}", file.display());
         }
     }
-}
\ No newline at end of file
+}
+
+/// Serialize results as a JSON array for CI consumption
+fn build_results_to_json(results: &[BuildResult]) -> String {
+    JsonValue::Array(results.iter().map(BuildResult::to_json).collect()).to_string()
+}
+
+#[cfg(test)]
+fn build_results_from_json(input: &str) -> Result<Vec<BuildResult>, String> {
+    json::parse(input)?
+        .as_array()
+        .ok_or("expected a JSON array of build results")?
+        .iter()
+        .map(BuildResult::from_json)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn mixed_results() -> Vec<BuildResult> {
+        vec![
+            BuildResult {
+                target: "kernel".to_string(),
+                success: true,
+                cached: false,
+                duration: Duration::from_millis(12_345),
+                output_files: vec![PathBuf::from("target/x86_64-raeen/release/raeen_kernel")],
+                errors: Vec::new(),
+                warnings: vec!["unused variable `x`".to_string()],
+            },
+            BuildResult {
+                target: "userspace/shell".to_string(),
+                success: false,
+                cached: false,
+                duration: Duration::from_millis(870),
+                output_files: Vec::new(),
+                errors: vec!["error[E0425]: cannot find value `y`\n --> src/main.rs:3:5".to_string()],
+                warnings: Vec::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let results = mixed_results();
+        let parsed = build_results_from_json(&build_results_to_json(&results)).unwrap();
+        assert_eq!(parsed.len(), results.len());
+        for (original, parsed) in results.iter().zip(&parsed) {
+            assert_eq!(parsed.target, original.target);
+            assert_eq!(parsed.success, original.success);
+            assert_eq!(parsed.cached, original.cached);
+            assert_eq!(parsed.duration, original.duration);
+            assert_eq!(parsed.output_files, original.output_files);
+            assert_eq!(parsed.errors, original.errors);
+            assert_eq!(parsed.warnings, original.warnings);
+        }
+    }
+
+    #[test]
+    fn test_json_contains_every_field() {
+        let value = json::parse(&build_results_to_json(&mixed_results())).unwrap();
+        let entries = value.as_array().unwrap();
+        for entry in entries {
+            for field in ["target", "success", "cached", "duration_ms", "errors", "warnings", "output_files"] {
+                assert!(entry.get(field).is_some(), "missing {}", field);
+            }
+        }
+        assert_eq!(entries[0].get("duration_ms").and_then(JsonValue::as_u64), Some(12_345));
+        assert_eq!(entries[1].get("success").and_then(JsonValue::as_bool), Some(false));
+    }
+
+    fn context_for(arch: Arch) -> BuildContext {
+        let workspace_root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        BuildContext {
+            config: load_build_config(&workspace_root).unwrap(),
+            arch,
+            build_dir: workspace_root.join("build"),
+            output_dir: workspace_root.join("build"),
+            target_dir: workspace_root.join("target"),
+            workspace_root,
+            verbose: false,
+            parallel_jobs: 1,
+            force: false,
+            cache: RefCell::new(BuildCache::default()),
+        }
+    }
+
+    fn target_arg(context: &BuildContext, target_type: TargetType) -> Option<String> {
+        let target = BuildTarget {
+            name: "kernel".to_string(),
+            path: context.workspace_root.join("kernel"),
+            target_type,
+            dependencies: Vec::new(),
+            features: Vec::new(),
+            profile: "release".to_string(),
+        };
+        let cmd = cargo_build_command(context, &target);
+        let args: Vec<String> = cmd.get_args().map(|a| a.to_string_lossy().into_owned()).collect();
+        args.iter().position(|a| a == "--target").map(|i| args[i + 1].clone())
+    }
+
+    #[test]
+    fn test_arch_selects_target_triple() {
+        let x86 = context_for(Arch::X86_64);
+        assert!(target_arg(&x86, TargetType::Kernel).unwrap().ends_with("x86_64-raeen.json"));
+        assert_eq!(target_arg(&x86, TargetType::Userspace).as_deref(), Some("x86_64-unknown-linux-gnu"));
+
+        let arm = context_for(Arch::Aarch64);
+        assert_eq!(target_arg(&arm, TargetType::Kernel).as_deref(), Some("aarch64-unknown-none"));
+        assert_eq!(target_arg(&arm, TargetType::Userspace).as_deref(), Some("aarch64-unknown-linux-gnu"));
+        assert_eq!(target_arg(&arm, TargetType::Tool), None);
+    }
+
+    /// Arch-neutral kernel modules must not grow dependencies on x86_64-only ones such as the
+    /// heap. Skipped where the toolchain cannot cross-compile
+    #[test]
+    fn test_aarch64_kernel_checks() {
+        let context = context_for(Arch::Aarch64);
+        let issues = ToolchainInfo::probe(&context.workspace_root)
+            .map(|info| toolchain::verify(&info, &kernel_spec(&context)))
+            .unwrap_or_else(|e| vec![e]);
+        if !issues.is_empty() {
+            eprintln!("skipping aarch64 kernel check: {}", issues.join("; "));
+            return;
+        }
+        let results = check_code(&context).unwrap();
+        assert!(results[0].success, "aarch64 kernel check failed: {:?}", results[0].errors);
+    }
+
+    #[test]
+    fn test_unknown_arch_rejected() {
+        assert_eq!("aarch64".parse::<Arch>(), Ok(Arch::Aarch64));
+        let error = "riscv64".parse::<Arch>().unwrap_err();
+        assert!(error.contains("riscv64"));
+    }
+}
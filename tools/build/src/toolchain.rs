@@ -0,0 +1,210 @@
+//! Cross-compilation toolchain and sysroot management for raeen-build
+//! Kernel targets are built with `-Z build-std` against either a built-in rustc target or a
+//! JSON target spec from `targets/`. The toolchain is verified up front so a missing component
+//! produces an actionable error instead of a raw cargo failure.
+
+use std::path::{Path, PathBuf};
+use std::process::Command as ProcessCommand;
+
+/// Crates rebuilt from rust-src for bare-metal targets
+pub const BUILD_STD_CRATES: &[&str] = &["core", "compiler_builtins", "alloc"];
+
+/// Supplies memcpy/memset and friends, which no libc provides on bare metal
+pub const BUILD_STD_FEATURES: &[&str] = &["compiler-builtins-mem"];
+
+/// Directory, relative to the workspace root, holding custom target specs
+pub const TARGET_SPEC_DIR: &str = "targets";
+
+/// How cargo is told about a kernel target
+#[derive(Debug, Clone, PartialEq)]
+pub enum TargetSpec {
+    /// A target rustc ships, e.g. `aarch64-unknown-none`
+    Builtin(String),
+    /// A custom target described by a JSON spec file
+    Custom(PathBuf),
+}
+
+impl TargetSpec {
+    /// Use `targets/<triple>.json` when it exists, otherwise treat `triple` as built in
+    pub fn resolve(workspace_root: &Path, triple: &str) -> Self {
+        let spec = workspace_root.join(TARGET_SPEC_DIR).join(format!("{}.json", triple));
+        if spec.exists() {
+            TargetSpec::Custom(spec)
+        } else {
+            TargetSpec::Builtin(triple.to_string())
+        }
+    }
+
+    pub fn triple(&self) -> String {
+        match self {
+            TargetSpec::Builtin(name) => name.clone(),
+            TargetSpec::Custom(path) => path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default(),
+        }
+    }
+
+    /// Value for cargo's `--target`
+    pub fn target_arg(&self) -> String {
+        match self {
+            TargetSpec::Builtin(name) => name.clone(),
+            TargetSpec::Custom(path) => path.display().to_string(),
+        }
+    }
+}
+
+/// Arguments that make cargo build the sysroot crates for `spec`
+pub fn cross_args(spec: &TargetSpec) -> Vec<String> {
+    vec![
+        "-Z".to_string(),
+        format!("build-std={}", BUILD_STD_CRATES.join(",")),
+        "-Z".to_string(),
+        format!("build-std-features={}", BUILD_STD_FEATURES.join(",")),
+        "--target".to_string(),
+        spec.target_arg(),
+    ]
+}
+
+/// The parts of the active toolchain that cross builds depend on
+#[derive(Debug, Clone)]
+pub struct ToolchainInfo {
+    /// `rustc --version` output
+    pub version: String,
+    pub host: String,
+    pub sysroot: PathBuf,
+    /// Targets rustc knows without a spec file
+    pub builtin_targets: Vec<String>,
+}
+
+impl ToolchainInfo {
+    /// Query the rustc that cargo will use in `workspace_root` (honouring rust-toolchain.toml)
+    pub fn probe(workspace_root: &Path) -> Result<Self, String> {
+        let rustc = |args: &[&str]| -> Result<String, String> {
+            let output = ProcessCommand::new("rustc")
+                .current_dir(workspace_root)
+                .args(args)
+                .output()
+                .map_err(|e| format!("rustc not found ({}): install Rust from https://rustup.rs", e))?;
+            if !output.status.success() {
+                return Err(format!("`rustc {}` failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+            }
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        };
+
+        let verbose = rustc(&["-vV"])?;
+        let version = verbose.lines().next().unwrap_or_default().to_string();
+        let host = verbose
+            .lines()
+            .find_map(|line| line.strip_prefix("host: "))
+            .ok_or("`rustc -vV` did not report a host triple")?
+            .to_string();
+
+        Ok(Self {
+            version,
+            host,
+            sysroot: PathBuf::from(rustc(&["--print", "sysroot"])?.trim()),
+            builtin_targets: rustc(&["--print", "target-list"])?.lines().map(str::to_string).collect(),
+        })
+    }
+
+    /// `-Z` flags are only accepted by nightly (or locally built) compilers
+    pub fn is_nightly(&self) -> bool {
+        self.version.contains("-nightly") || self.version.contains("-dev")
+    }
+
+    pub fn has_rust_src(&self) -> bool {
+        self.sysroot.join("lib/rustlib/src/rust/library/core/Cargo.toml").exists()
+    }
+
+    pub fn has_llvm_tools(&self) -> bool {
+        let exe = if cfg!(windows) { "llvm-objcopy.exe" } else { "llvm-objcopy" };
+        self.sysroot.join("lib/rustlib").join(&self.host).join("bin").join(exe).exists()
+    }
+}
+
+/// Everything preventing a cross build for `spec`, each with the command that fixes it
+pub fn verify(info: &ToolchainInfo, spec: &TargetSpec) -> Vec<String> {
+    let mut issues = Vec::new();
+    if !info.is_nightly() {
+        issues.push(format!(
+            "{} is not a nightly toolchain, which -Z build-std requires: run `rustup toolchain install nightly` (rust-toolchain.toml selects it)",
+            info.version
+        ));
+    }
+    if !info.has_rust_src() {
+        issues.push("rust-src component missing, needed to rebuild core and alloc: run `rustup component add rust-src`".to_string());
+    }
+    if !info.has_llvm_tools() {
+        issues.push("llvm-tools-preview component missing, needed to link boot images: run `rustup component add llvm-tools-preview`".to_string());
+    }
+    match spec {
+        TargetSpec::Builtin(name) if !info.builtin_targets.iter().any(|t| t == name) => {
+            issues.push(format!(
+                "target '{}' is neither built into {} nor described by {}/{}.json",
+                name, info.version, TARGET_SPEC_DIR, name
+            ));
+        }
+        TargetSpec::Custom(path) if !path.exists() => {
+            issues.push(format!("target spec {} does not exist", path.display()));
+        }
+        _ => {}
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// A fake sysroot with rust-src and llvm-tools installed
+    fn toolchain(root: &Path) -> ToolchainInfo {
+        let info = ToolchainInfo {
+            version: "rustc 1.80.0-nightly (abcdef012 2024-05-01)".to_string(),
+            host: "x86_64-unknown-linux-gnu".to_string(),
+            sysroot: root.to_path_buf(),
+            builtin_targets: vec!["aarch64-unknown-none".to_string(), "x86_64-unknown-none".to_string()],
+        };
+        let core = info.sysroot.join("lib/rustlib/src/rust/library/core");
+        fs::create_dir_all(&core).unwrap();
+        fs::write(core.join("Cargo.toml"), "[package]").unwrap();
+        let bin = info.sysroot.join("lib/rustlib").join(&info.host).join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(bin.join(if cfg!(windows) { "llvm-objcopy.exe" } else { "llvm-objcopy" }), "").unwrap();
+        info
+    }
+
+    #[test]
+    fn test_cross_args_use_build_std_and_spec_path() {
+        let workspace = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let spec = TargetSpec::resolve(&workspace, "x86_64-raeen");
+        assert_eq!(spec, TargetSpec::Custom(workspace.join("targets/x86_64-raeen.json")));
+        assert_eq!(spec.triple(), "x86_64-raeen");
+
+        let args = cross_args(&spec);
+        assert_eq!(args[..4], ["-Z", "build-std=core,compiler_builtins,alloc", "-Z", "build-std-features=compiler-builtins-mem"]);
+        assert_eq!(args[4..], ["--target".to_string(), workspace.join("targets/x86_64-raeen.json").display().to_string()]);
+
+        let builtin = TargetSpec::resolve(&workspace, "aarch64-unknown-none");
+        assert_eq!(cross_args(&builtin).last().map(String::as_str), Some("aarch64-unknown-none"));
+    }
+
+    #[test]
+    fn test_missing_component_reported() {
+        let root = std::env::temp_dir().join(format!("raeen-sysroot-{}", uuid::Uuid::new_v4()));
+        let info = toolchain(&root);
+        let spec = TargetSpec::Builtin("aarch64-unknown-none".to_string());
+        assert!(verify(&info, &spec).is_empty());
+
+        fs::remove_dir_all(root.join("lib/rustlib/src")).unwrap();
+        let issues = verify(&info, &spec);
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].contains("rustup component add rust-src"));
+
+        let stable = ToolchainInfo { version: "rustc 1.80.0 (051478957 2024-07-21)".to_string(), ..info.clone() };
+        assert!(verify(&stable, &spec).iter().any(|i| i.contains("nightly")));
+
+        let unknown = TargetSpec::Builtin("riscv64-raeen".to_string());
+        assert!(verify(&info, &unknown).iter().any(|i| i.contains("riscv64-raeen")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
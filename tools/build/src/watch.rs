@@ -0,0 +1,168 @@
+//! Watch mode for raeen-build
+//! Watches target source trees and reports which targets changed, debouncing bursts of
+//! file events (editor saves, checkouts) into a single rebuild.
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use crate::cache::IGNORED_DIRS;
+use crate::BuildTarget;
+
+/// Quiet period after the last change before a rebuild starts
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// How often the loop checks for a stop request while idle
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct SourceWatcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<Event>,
+    /// Target name and canonical source root
+    roots: Vec<(String, PathBuf)>,
+}
+
+impl SourceWatcher {
+    /// Start watching the source trees of `targets`
+    pub fn new(targets: &[BuildTarget]) -> Result<Self, Box<dyn std::error::Error>> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            if let Ok(event) = event {
+                let _ = sender.send(event);
+            }
+        })?;
+
+        let mut roots = Vec::new();
+        for target in targets {
+            let root = target.path.canonicalize()?;
+            watcher.watch(&root, RecursiveMode::Recursive)?;
+            roots.push((target.name.clone(), root));
+        }
+
+        Ok(Self { _watcher: watcher, events, roots })
+    }
+
+    /// The innermost target whose sources contain `path`, ignoring build output directories
+    fn target_for(&self, path: &Path) -> Option<&str> {
+        self.roots
+            .iter()
+            .filter_map(|(name, root)| path.strip_prefix(root).ok().map(|relative| (name, root, relative)))
+            .filter(|(_, _, relative)| {
+                !relative.components().any(|c| matches!(c, Component::Normal(part) if IGNORED_DIRS.iter().any(|d| part == *d)))
+            })
+            .max_by_key(|(_, root, _)| root.components().count())
+            .map(|(name, _, _)| name.as_str())
+    }
+
+    fn collect(&self, event: &Event, affected: &mut BTreeSet<String>) {
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+            return;
+        }
+        for path in &event.paths {
+            if let Some(name) = self.target_for(path) {
+                affected.insert(name.to_string());
+            }
+        }
+    }
+
+    /// Call `rebuild` with the changed targets each time the sources settle, until `stop` is set
+    pub fn run<R>(&self, debounce: Duration, stop: &AtomicBool, mut rebuild: R) -> Result<(), Box<dyn std::error::Error>>
+    where
+        R: FnMut(&[String]) -> Result<(), Box<dyn std::error::Error>>,
+    {
+        while !stop.load(Ordering::SeqCst) {
+            let first = match self.events.recv_timeout(POLL_INTERVAL) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return Err("file watcher stopped unexpectedly".into()),
+            };
+
+            let mut affected = BTreeSet::new();
+            self.collect(&first, &mut affected);
+
+            // Absorb further events until the tree has been quiet for the debounce interval
+            loop {
+                match self.events.recv_timeout(debounce) {
+                    Ok(event) => self.collect(&event, &mut affected),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return Err("file watcher stopped unexpectedly".into()),
+                }
+            }
+
+            if !affected.is_empty() {
+                let affected: Vec<String> = affected.into_iter().collect();
+                rebuild(&affected)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TargetType;
+    use std::fs;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    fn target(workspace: &Path, name: &str) -> BuildTarget {
+        BuildTarget {
+            name: name.to_string(),
+            path: workspace.join(name),
+            target_type: TargetType::Userspace,
+            dependencies: Vec::new(),
+            features: Vec::new(),
+            profile: "release".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_burst_of_edits_triggers_one_rebuild_of_changed_target() {
+        let workspace = std::env::temp_dir().join(format!("raeen-watch-{}", uuid::Uuid::new_v4()));
+        for name in ["shell", "desktop"] {
+            fs::create_dir_all(workspace.join(name).join("src")).unwrap();
+            fs::create_dir_all(workspace.join(name).join("target")).unwrap();
+            fs::write(workspace.join(name).join("src/main.rs"), "fn main() {}").unwrap();
+        }
+        let targets = vec![target(&workspace, "shell"), target(&workspace, "desktop")];
+
+        let debounce = Duration::from_millis(250);
+        let watcher = SourceWatcher::new(&targets).unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, rebuilds) = mpsc::channel();
+        let loop_stop = stop.clone();
+        let handle = std::thread::spawn(move || {
+            watcher
+                .run(debounce, &loop_stop, |affected| {
+                    sender.send((affected.to_vec(), Instant::now()))?;
+                    Ok(())
+                })
+                .unwrap();
+        });
+
+        // Build output alone never triggers a rebuild
+        fs::write(workspace.join("desktop/target/artifact"), "binary").unwrap();
+        assert!(rebuilds.recv_timeout(debounce * 3).is_err());
+
+        let source = workspace.join("desktop/src/main.rs");
+        let mut last_edit = Instant::now();
+        for i in 0..5 {
+            std::thread::sleep(Duration::from_millis(20));
+            last_edit = Instant::now();
+            fs::write(&source, format!("fn main() {{ let _ = {}; }}", i)).unwrap();
+        }
+
+        let (affected, at) = rebuilds.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(affected, ["desktop"]);
+        assert!(at.duration_since(last_edit) >= debounce);
+        assert!(rebuilds.recv_timeout(debounce * 3).is_err(), "burst produced more than one rebuild");
+
+        stop.store(true, Ordering::SeqCst);
+        handle.join().unwrap();
+        fs::remove_dir_all(&workspace).unwrap();
+    }
+}
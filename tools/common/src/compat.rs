@@ -0,0 +1,76 @@
+//! Compatibility-layer settings shared by raeen-build and raeen-pkg
+//! Read from `[workspace.metadata.raeen.compatibility]` in the workspace Cargo.toml; any
+//! missing key falls back to the default below.
+
+use log::warn;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct CompatibilityConfig {
+    pub wine_version: String,
+    pub android_api_level: u32,
+    pub web_engine: String,
+    pub flatpak_runtime: String,
+    pub flatpak_runtime_version: String,
+    pub snap_base: String,
+    pub appimage_runtime: String,
+}
+
+impl Default for CompatibilityConfig {
+    fn default() -> Self {
+        Self {
+            wine_version: "8.0".to_string(),
+            android_api_level: 33,
+            web_engine: "webkit".to_string(),
+            flatpak_runtime: "org.freedesktop.Platform".to_string(),
+            flatpak_runtime_version: "23.08".to_string(),
+            snap_base: "core22".to_string(),
+            appimage_runtime: "runtime-x86_64".to_string(),
+        }
+    }
+}
+
+impl CompatibilityConfig {
+    /// Load the settings for `workspace_root`, using defaults if its Cargo.toml is missing or unreadable
+    pub fn load(workspace_root: &Path) -> Self {
+        let path = workspace_root.join("Cargo.toml");
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => return Self::default(),
+        };
+        match content.parse::<toml::Value>() {
+            Ok(doc) => Self::from_toml(&doc),
+            Err(e) => {
+                warn!("Ignoring unparsable {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    fn from_toml(doc: &toml::Value) -> Self {
+        let table = doc
+            .get("workspace")
+            .and_then(|w| w.get("metadata"))
+            .and_then(|m| m.get("raeen"))
+            .and_then(|r| r.get("compatibility"));
+        let string = |key: &str, default: String| {
+            table.and_then(|t| t.get(key)).and_then(|v| v.as_str()).map(str::to_string).unwrap_or(default)
+        };
+
+        let defaults = Self::default();
+        Self {
+            wine_version: string("wine_version", defaults.wine_version),
+            android_api_level: table
+                .and_then(|t| t.get("android_api_level"))
+                .and_then(|v| v.as_integer())
+                .and_then(|n| u32::try_from(n).ok())
+                .unwrap_or(defaults.android_api_level),
+            web_engine: string("web_engine", defaults.web_engine),
+            flatpak_runtime: string("flatpak_runtime", defaults.flatpak_runtime),
+            flatpak_runtime_version: string("flatpak_runtime_version", defaults.flatpak_runtime_version),
+            snap_base: string("snap_base", defaults.snap_base),
+            appimage_runtime: string("appimage_runtime", defaults.appimage_runtime),
+        }
+    }
+}
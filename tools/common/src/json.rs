@@ -0,0 +1,317 @@
+//! Minimal JSON values for machine-readable tool output
+//! Used by raeen-build, raeen-test and raeen-pkg for `--format json` while serde is unavailable.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    /// Keys keep insertion order so output is stable
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    pub fn object(fields: Vec<(&str, JsonValue)>) -> Self {
+        JsonValue::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    pub fn string_array<I, S>(items: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        JsonValue::Array(items.into_iter().map(|s| JsonValue::String(s.into())).collect())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            JsonValue::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            JsonValue::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Required field lookup with a readable error
+    pub fn field(&self, key: &str) -> Result<&JsonValue, String> {
+        self.get(key).ok_or_else(|| format!("missing field '{}'", key))
+    }
+
+    /// Required array-of-strings field
+    pub fn string_list(&self, key: &str) -> Result<Vec<String>, String> {
+        self.field(key)?
+            .as_array()
+            .ok_or_else(|| format!("field '{}' is not an array", key))?
+            .iter()
+            .map(|v| v.as_str().map(str::to_string).ok_or_else(|| format!("field '{}' has a non-string entry", key)))
+            .collect()
+    }
+}
+
+fn write_escaped(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonValue::Null => f.write_str("null"),
+            JsonValue::Bool(b) => write!(f, "{}", b),
+            JsonValue::Number(n) if !n.is_finite() => f.write_str("null"),
+            JsonValue::Number(n) if n.fract() == 0.0 && n.abs() < 9.0e15 => write!(f, "{}", *n as i64),
+            JsonValue::Number(n) => write!(f, "{}", n),
+            JsonValue::String(s) => write_escaped(f, s),
+            JsonValue::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str("]")
+            }
+            JsonValue::Object(fields) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_escaped(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+/// Parse a complete JSON document
+pub fn parse(input: &str) -> Result<JsonValue, String> {
+    let mut parser = Parser { chars: input.char_indices().peekable(), input };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.peek() {
+        None => Ok(value),
+        Some(&(pos, _)) => Err(format!("trailing characters at offset {}", pos)),
+    }
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    input: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while matches!(self.chars.peek(), Some((_, ' ' | '\n' | '\r' | '\t'))) {
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((pos, c)) => Err(format!("expected '{}' at offset {}, found '{}'", expected, pos, c)),
+            None => Err(format!("expected '{}', found end of input", expected)),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: JsonValue) -> Result<JsonValue, String> {
+        for expected in word.chars() {
+            self.expect(expected)?;
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<JsonValue, String> {
+        self.skip_whitespace();
+        match self.chars.peek().copied() {
+            Some((_, '{')) => self.object(),
+            Some((_, '[')) => self.array(),
+            Some((_, '"')) => self.string().map(JsonValue::String),
+            Some((_, 't')) => self.literal("true", JsonValue::Bool(true)),
+            Some((_, 'f')) => self.literal("false", JsonValue::Bool(false)),
+            Some((_, 'n')) => self.literal("null", JsonValue::Null),
+            Some((start, c)) if c == '-' || c.is_ascii_digit() => self.number(start),
+            Some((pos, c)) => Err(format!("unexpected '{}' at offset {}", c, pos)),
+            None => Err("unexpected end of input".to_string()),
+        }
+    }
+
+    fn number(&mut self, start: usize) -> Result<JsonValue, String> {
+        let mut end = start;
+        while let Some(&(pos, c)) = self.chars.peek() {
+            if !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+                break;
+            }
+            end = pos + c.len_utf8();
+            self.chars.next();
+        }
+        let text = &self.input[start..end];
+        text.parse::<f64>()
+            .map(JsonValue::Number)
+            .map_err(|_| format!("invalid number '{}' at offset {}", text, start))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(out),
+                Some((pos, '\\')) => match self.chars.next() {
+                    Some((_, '"')) => out.push('"'),
+                    Some((_, '\\')) => out.push('\\'),
+                    Some((_, '/')) => out.push('/'),
+                    Some((_, 'n')) => out.push('\n'),
+                    Some((_, 'r')) => out.push('\r'),
+                    Some((_, 't')) => out.push('\t'),
+                    Some((_, 'b')) => out.push('\u{8}'),
+                    Some((_, 'f')) => out.push('\u{c}'),
+                    Some((_, 'u')) => {
+                        let code = self.hex4(pos)?;
+                        let c = if (0xD800..0xDC00).contains(&code) {
+                            // Surrogate pair
+                            self.expect('\\')?;
+                            self.expect('u')?;
+                            let low = self.hex4(pos)?;
+                            char::from_u32(0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF))
+                        } else {
+                            char::from_u32(code)
+                        };
+                        out.push(c.ok_or_else(|| format!("invalid unicode escape at offset {}", pos))?);
+                    }
+                    _ => return Err(format!("invalid escape at offset {}", pos)),
+                },
+                Some((_, c)) => out.push(c),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+
+    fn hex4(&mut self, pos: usize) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.chars.next()
+                .and_then(|(_, c)| c.to_digit(16))
+                .ok_or_else(|| format!("invalid unicode escape at offset {}", pos))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn array(&mut self) -> Result<JsonValue, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if matches!(self.chars.peek(), Some((_, ']'))) {
+            self.chars.next();
+            return Ok(JsonValue::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, ']')) => return Ok(JsonValue::Array(items)),
+                Some((pos, c)) => return Err(format!("expected ',' or ']' at offset {}, found '{}'", pos, c)),
+                None => return Err("unterminated array".to_string()),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<JsonValue, String> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if matches!(self.chars.peek(), Some((_, '}'))) {
+            self.chars.next();
+            return Ok(JsonValue::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, '}')) => return Ok(JsonValue::Object(fields)),
+                Some((pos, c)) => return Err(format!("expected ',' or '}}' at offset {}, found '{}'", pos, c)),
+                None => return Err("unterminated object".to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escaped_strings_round_trip() {
+        let value = JsonValue::object(vec![
+            ("text", JsonValue::String("line \"one\"\n\ttab \\ \u{1} é".to_string())),
+            ("empty", JsonValue::Array(Vec::new())),
+        ]);
+        assert_eq!(parse(&value.to_string()).unwrap(), value);
+    }
+
+    #[test]
+    fn test_numbers_and_literals() {
+        let value = parse(" [0, 1250, -3.5, 1e3, true, false, null] ").unwrap();
+        assert_eq!(value.to_string(), "[0,1250,-3.5,1000,true,false,null]");
+        assert_eq!(value.as_array().unwrap()[1].as_u64(), Some(1250));
+    }
+
+    #[test]
+    fn test_rejects_malformed_input() {
+        for bad in ["", "{", "[1,]", "{\"a\" 1}", "\"unterminated", "[1] x", "tru"] {
+            assert!(parse(bad).is_err(), "accepted {:?}", bad);
+        }
+    }
+}
@@ -0,0 +1,6 @@
+//! Code shared by the RaeenOS host tools
+//! raeen-build, raeen-test and raeen-pkg each depend on this crate rather than compiling
+//! each other's sources.
+
+pub mod compat;
+pub mod json;
@@ -0,0 +1,502 @@
+//! Android APK export for raeen-pkg
+//! Builds an unsigned, zip-aligned APK for the Android compatibility layer: a compiled (binary
+//! XML) AndroidManifest.xml describing a NativeActivity app, plus the package's shared libraries
+//! under `lib/<abi>/`. Signing is left to `apksigner`, which must run after alignment anyway.
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::version::Version;
+use crate::{FileType, PackageBuilder, PackageFile, PackageManifest};
+
+const ANDROID_NS: &str = "http://schemas.android.com/apk/res/android";
+
+/// Native libraries are stored uncompressed on page boundaries so they can be mapped in place
+const LIB_ALIGNMENT: u16 = 4096;
+
+/// Resource IDs of the `android:` attributes the manifest uses (from android.R.attr)
+const ATTRIBUTE_IDS: &[(&str, u32)] = &[
+    ("label", 0x0101_0001),
+    ("name", 0x0101_0003),
+    ("hasCode", 0x0101_000c),
+    ("exported", 0x0101_0010),
+    ("value", 0x0101_0024),
+    ("minSdkVersion", 0x0101_020c),
+    ("versionCode", 0x0101_021b),
+    ("versionName", 0x0101_021c),
+    ("targetSdkVersion", 0x0101_0270),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttrValue {
+    String(String),
+    Int(u32),
+    Bool(bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    /// In the `android:` namespace
+    pub android: bool,
+    pub name: String,
+    pub value: AttrValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<Attribute>,
+    pub children: Vec<Element>,
+}
+
+impl Element {
+    fn new(name: &str) -> Self {
+        Self { name: name.to_string(), attributes: Vec::new(), children: Vec::new() }
+    }
+
+    fn attr(mut self, name: &str, value: AttrValue) -> Self {
+        let (android, name) = match name.strip_prefix("android:") {
+            Some(name) => (true, name),
+            None => (false, name),
+        };
+        self.attributes.push(Attribute { android, name: name.to_string(), value });
+        self
+    }
+
+    fn child(mut self, child: Element) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    /// Source form of the document rooted at this element
+    #[cfg(test)]
+    pub fn to_xml(&self) -> String {
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        self.write_xml(&mut out, 0);
+        out
+    }
+
+    #[cfg(test)]
+    fn write_xml(&self, out: &mut String, depth: usize) {
+        out.push_str(&format!("{}<{}", "    ".repeat(depth), self.name));
+        if depth == 0 {
+            out.push_str(&format!(" xmlns:android=\"{}\"", ANDROID_NS));
+        }
+        for attr in &self.attributes {
+            let value = match &attr.value {
+                AttrValue::String(s) => s.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;"),
+                AttrValue::Int(n) => n.to_string(),
+                AttrValue::Bool(b) => b.to_string(),
+            };
+            out.push_str(&format!(" {}{}=\"{}\"", if attr.android { "android:" } else { "" }, attr.name, value));
+        }
+        if self.children.is_empty() {
+            out.push_str(" />\n");
+            return;
+        }
+        out.push_str(">\n");
+        for child in &self.children {
+            child.write_xml(out, depth + 1);
+        }
+        out.push_str(&format!("{}</{}>\n", "    ".repeat(depth), self.name));
+    }
+}
+
+/// Android binary XML (the format aapt compiles manifests to)
+mod axml {
+    use super::{AttrValue, Element, ANDROID_NS, ATTRIBUTE_IDS};
+
+    const RES_STRING_POOL_TYPE: u16 = 0x0001;
+    const RES_XML_TYPE: u16 = 0x0003;
+    const RES_XML_START_NAMESPACE_TYPE: u16 = 0x0100;
+    const RES_XML_END_NAMESPACE_TYPE: u16 = 0x0101;
+    const RES_XML_START_ELEMENT_TYPE: u16 = 0x0102;
+    const RES_XML_END_ELEMENT_TYPE: u16 = 0x0103;
+    const RES_XML_RESOURCE_MAP_TYPE: u16 = 0x0180;
+
+    const TYPE_STRING: u8 = 0x03;
+    const TYPE_INT_DEC: u8 = 0x10;
+    const TYPE_INT_BOOLEAN: u8 = 0x12;
+
+    const NONE: u32 = 0xffff_ffff;
+    const PREFIX: &str = "android";
+
+    /// String pool in which attribute names with resource IDs come first, as the resource
+    /// map requires
+    struct Pool {
+        strings: Vec<String>,
+        mapped: usize,
+    }
+
+    impl Pool {
+        fn new(root: &Element) -> Self {
+            fn walk(e: &Element, out: &mut Vec<(bool, String)>) {
+                for attr in &e.attributes {
+                    out.push((attr.android, attr.name.clone()));
+                }
+                e.children.iter().for_each(|c| walk(c, out));
+            }
+            let mut used = Vec::new();
+            walk(root, &mut used);
+            let strings: Vec<String> = ATTRIBUTE_IDS
+                .iter()
+                .filter(|(name, _)| used.iter().any(|(android, n)| *android && n == name))
+                .map(|(name, _)| name.to_string())
+                .collect();
+            Self { mapped: strings.len(), strings }
+        }
+
+        fn index(&mut self, s: &str) -> u32 {
+            if let Some(i) = self.strings.iter().position(|x| x == s) {
+                return i as u32;
+            }
+            self.strings.push(s.to_string());
+            (self.strings.len() - 1) as u32
+        }
+    }
+
+    fn chunk(kind: u16, header_size: u16, body: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + body.len());
+        out.extend_from_slice(&kind.to_le_bytes());
+        out.extend_from_slice(&header_size.to_le_bytes());
+        out.extend_from_slice(&((8 + body.len()) as u32).to_le_bytes());
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn u32s(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn attribute_id(name: &str) -> u32 {
+        ATTRIBUTE_IDS.iter().find(|(n, _)| *n == name).map_or(NONE, |(_, id)| *id)
+    }
+
+    fn element(e: &Element, pool: &mut Pool, ns: u32, out: &mut Vec<u8>) {
+        let name = pool.index(&e.name);
+        let mut attributes: Vec<_> = e.attributes.iter().collect();
+        // Framework attributes are looked up by resource ID, in ascending order
+        attributes.sort_by_key(|a| if a.android { attribute_id(&a.name) } else { 0 });
+
+        let mut body = u32s(&[0, NONE, NONE, name]);
+        body.extend_from_slice(&20u16.to_le_bytes()); // attributeStart
+        body.extend_from_slice(&20u16.to_le_bytes()); // attributeSize
+        body.extend_from_slice(&(attributes.len() as u16).to_le_bytes());
+        body.extend_from_slice(&[0; 6]); // id, class and style attribute indices
+        for attr in attributes {
+            let (raw, data_type, data) = match &attr.value {
+                AttrValue::String(s) => {
+                    let i = pool.index(s);
+                    (i, TYPE_STRING, i)
+                }
+                AttrValue::Int(n) => (NONE, TYPE_INT_DEC, *n),
+                AttrValue::Bool(b) => (NONE, TYPE_INT_BOOLEAN, if *b { NONE } else { 0 }),
+            };
+            let attr_ns = if attr.android { ns } else { NONE };
+            body.extend(u32s(&[attr_ns, pool.index(&attr.name), raw]));
+            body.extend_from_slice(&8u16.to_le_bytes());
+            body.push(0);
+            body.push(data_type);
+            body.extend_from_slice(&data.to_le_bytes());
+        }
+        out.extend(chunk(RES_XML_START_ELEMENT_TYPE, 16, &body));
+        for child in &e.children {
+            element(child, pool, ns, out);
+        }
+        out.extend(chunk(RES_XML_END_ELEMENT_TYPE, 16, &u32s(&[0, NONE, NONE, name])));
+    }
+
+    fn string_pool(strings: &[String]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut offsets = Vec::new();
+        for s in strings {
+            offsets.push(data.len() as u32);
+            let units: Vec<u16> = s.encode_utf16().collect();
+            data.extend_from_slice(&(units.len() as u16).to_le_bytes());
+            units.iter().for_each(|u| data.extend_from_slice(&u.to_le_bytes()));
+            data.extend_from_slice(&[0, 0]);
+        }
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+        let strings_start = 28 + 4 * strings.len() as u32;
+        let mut body = u32s(&[strings.len() as u32, 0, 0, strings_start, 0]);
+        body.extend(u32s(&offsets));
+        body.extend(data);
+        chunk(RES_STRING_POOL_TYPE, 28, &body)
+    }
+
+    pub fn encode(root: &Element) -> Vec<u8> {
+        let mut pool = Pool::new(root);
+        let prefix = pool.index(PREFIX);
+        let uri = pool.index(ANDROID_NS);
+
+        let mut tree = Vec::new();
+        tree.extend(chunk(RES_XML_START_NAMESPACE_TYPE, 16, &u32s(&[0, NONE, prefix, uri])));
+        element(root, &mut pool, uri, &mut tree);
+        tree.extend(chunk(RES_XML_END_NAMESPACE_TYPE, 16, &u32s(&[0, NONE, prefix, uri])));
+
+        let ids: Vec<u32> = pool.strings[..pool.mapped].iter().map(|s| attribute_id(s)).collect();
+        let mut body = string_pool(&pool.strings);
+        body.extend(chunk(RES_XML_RESOURCE_MAP_TYPE, 8, &u32s(&ids)));
+        body.extend(tree);
+        chunk(RES_XML_TYPE, 8, &body)
+    }
+}
+
+/// Android ABI directory for a package architecture
+pub fn abi(architecture: &str) -> Result<&'static str, String> {
+    match architecture {
+        "aarch64" | "arm64" => Ok("arm64-v8a"),
+        "x86_64" => Ok("x86_64"),
+        "armv7" | "arm" => Ok("armeabi-v7a"),
+        "x86" | "i686" => Ok("x86"),
+        other => Err(format!("no Android ABI for architecture '{}'", other)),
+    }
+}
+
+/// Android permissions implied by the package's permissions and sandbox
+pub(crate) fn permissions(manifest: &PackageManifest) -> Vec<&'static str> {
+    let mut permissions = Vec::new();
+    if manifest.permissions.network_access || !manifest.sandbox.enabled {
+        permissions.extend(["android.permission.INTERNET", "android.permission.ACCESS_NETWORK_STATE"]);
+    }
+    for device in &manifest.permissions.hardware_access {
+        permissions.push(match device.as_str() {
+            "camera" => "android.permission.CAMERA",
+            "audio" | "microphone" => "android.permission.RECORD_AUDIO",
+            "location" | "gps" => "android.permission.ACCESS_FINE_LOCATION",
+            "bluetooth" => "android.permission.BLUETOOTH_CONNECT",
+            _ => continue,
+        });
+    }
+    for path in &manifest.sandbox.file_system_access {
+        permissions.push(match path.as_str() {
+            "pictures" => "android.permission.READ_MEDIA_IMAGES",
+            "videos" => "android.permission.READ_MEDIA_VIDEO",
+            "music" => "android.permission.READ_MEDIA_AUDIO",
+            _ => "android.permission.READ_EXTERNAL_STORAGE",
+        });
+    }
+    let mut seen = std::collections::HashSet::new();
+    permissions.retain(|p| seen.insert(*p));
+    permissions
+}
+
+/// versionCode packs major.minor.patch as MMmmpp so it increases with every release
+fn version_code(version: &str) -> u32 {
+    version.parse::<Version>().map_or(1, |v| (v.major * 10_000 + v.minor * 100 + v.patch) as u32).max(1)
+}
+
+/// AndroidManifest for a NativeActivity app loading `lib_name`
+pub(crate) fn android_manifest(manifest: &PackageManifest, api_level: u32, lib_name: &str) -> Element {
+    let mut root = Element::new("manifest")
+        .attr("android:versionCode", AttrValue::Int(version_code(&manifest.version)))
+        .attr("android:versionName", AttrValue::String(manifest.version.clone()))
+        .attr("package", AttrValue::String(manifest.app_id()))
+        .child(
+            Element::new("uses-sdk")
+                .attr("android:minSdkVersion", AttrValue::Int(api_level))
+                .attr("android:targetSdkVersion", AttrValue::Int(api_level)),
+        );
+    for permission in permissions(manifest) {
+        root = root.child(Element::new("uses-permission").attr("android:name", AttrValue::String(permission.to_string())));
+    }
+    root.child(
+        Element::new("application")
+            .attr("android:label", AttrValue::String(manifest.name.clone()))
+            .attr("android:hasCode", AttrValue::Bool(false))
+            .child(
+                Element::new("activity")
+                    .attr("android:name", AttrValue::String("android.app.NativeActivity".to_string()))
+                    .attr("android:exported", AttrValue::Bool(true))
+                    .child(
+                        Element::new("meta-data")
+                            .attr("android:name", AttrValue::String("android.app.lib_name".to_string()))
+                            .attr("android:value", AttrValue::String(lib_name.to_string())),
+                    )
+                    .child(
+                        Element::new("intent-filter")
+                            .child(Element::new("action").attr("android:name", AttrValue::String("android.intent.action.MAIN".to_string())))
+                            .child(
+                                Element::new("category")
+                                    .attr("android:name", AttrValue::String("android.intent.category.LAUNCHER".to_string())),
+                            ),
+                    ),
+            ),
+    )
+}
+
+/// Write the unsigned APK to `path`
+pub(crate) fn write_apk(path: &std::path::Path, manifest: &PackageManifest, files: &[PackageFile], api_level: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let abi = abi(&manifest.architecture)?;
+    let libraries: Vec<&PackageFile> = files
+        .iter()
+        .filter(|f| f.file_type == FileType::Library && f.target_path.extension().map_or(false, |e| e == "so"))
+        .collect();
+    let lib_name = libraries
+        .first()
+        .and_then(|f| f.target_path.file_stem())
+        .map(|s| s.to_string_lossy().trim_start_matches("lib").to_string())
+        .ok_or("APK needs a native library: build the app as a cdylib so it produces a .so")?;
+
+    let mut zip = ZipWriter::new(fs::File::create(path)?);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file("AndroidManifest.xml", deflated)?;
+    zip.write_all(&axml::encode(&android_manifest(manifest, api_level, &lib_name)))?;
+
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    for lib in libraries {
+        let name = lib.target_path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        zip.start_file_aligned(format!("lib/{}/{}", abi, name), stored, LIB_ALIGNMENT)?;
+        zip.write_all(&fs::read(&lib.source_path)?)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// Build `<name>-<version>-<abi>-unsigned.apk`, returning its path
+pub(crate) fn export(builder: &PackageBuilder, manifest: &PackageManifest, files: &[PackageFile]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let apk = builder.output_dir.join(format!("{}-{}-{}-unsigned.apk", manifest.name, manifest.version, abi(&manifest.architecture)?));
+    write_apk(&apk, manifest, files, builder.compatibility.android_api_level)?;
+    Ok(apk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PackageFormat;
+    use std::io::Read;
+
+    fn notes() -> PackageManifest {
+        let mut manifest = PackageManifest::new("notes".to_string(), &PackageFormat::AndroidApk, "aarch64", "android");
+        manifest.version = "1.4.2".to_string();
+        manifest.permissions.network_access = true;
+        manifest.permissions.hardware_access = vec!["camera".to_string()];
+        manifest.sandbox.file_system_access = vec!["pictures".to_string()];
+        manifest
+    }
+
+    /// Write the notes APK, with one native library, into a fresh directory
+    fn notes_apk() -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("raeen-apk-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let lib = dir.join("libnotes.so");
+        fs::write(&lib, b"\x7fELF").unwrap();
+        let files = vec![PackageFile {
+            source_path: lib,
+            target_path: PathBuf::from("/usr/lib/libnotes.so"),
+            file_type: FileType::Library,
+            permissions: 0o755,
+            checksum: String::new(),
+            size: 4,
+        }];
+        let apk = dir.join("notes.apk");
+        write_apk(&apk, &notes(), &files, 33).unwrap();
+        (dir, apk)
+    }
+
+    fn binary_manifest(archive: &mut zip::ZipArchive<fs::File>) -> Vec<u8> {
+        let mut binary = Vec::new();
+        archive.by_name("AndroidManifest.xml").unwrap().read_to_end(&mut binary).unwrap();
+        binary
+    }
+
+    /// Rebuild the element tree from Android binary XML, as the package manager reads it
+    fn decode_axml(binary: &[u8]) -> Element {
+        let u16_at = |at: usize| u16::from_le_bytes([binary[at], binary[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes([binary[at], binary[at + 1], binary[at + 2], binary[at + 3]]);
+        let mut strings = Vec::new();
+        let mut open: Vec<Element> = Vec::new();
+        let mut root = None;
+        let mut offset = 8;
+        while offset < binary.len() {
+            match u16_at(offset) {
+                // String pool
+                0x0001 => {
+                    let strings_start = offset + u32_at(offset + 20) as usize;
+                    for i in 0..u32_at(offset + 8) as usize {
+                        let at = strings_start + u32_at(offset + 28 + 4 * i) as usize;
+                        let units: Vec<u16> = (0..u16_at(at) as usize).map(|j| u16_at(at + 2 + 2 * j)).collect();
+                        strings.push(String::from_utf16(&units).unwrap());
+                    }
+                }
+                // Start of namespace
+                0x0100 => assert_eq!(strings[u32_at(offset + 20) as usize], ANDROID_NS),
+                // Start of element
+                0x0102 => {
+                    let mut element = Element::new(&strings[u32_at(offset + 20) as usize]);
+                    for i in 0..u16_at(offset + 28) as usize {
+                        let at = offset + 36 + 20 * i;
+                        let data = u32_at(at + 16);
+                        let value = match binary[at + 15] {
+                            0x03 => AttrValue::String(strings[data as usize].clone()),
+                            0x10 => AttrValue::Int(data),
+                            0x12 => AttrValue::Bool(data != 0),
+                            other => panic!("unexpected attribute type {:#x}", other),
+                        };
+                        let name = strings[u32_at(at + 4) as usize].clone();
+                        element.attributes.push(Attribute { android: u32_at(at) != u32::MAX, name, value });
+                    }
+                    open.push(element);
+                }
+                // End of element
+                0x0103 => {
+                    let element = open.pop().unwrap();
+                    match open.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => root = Some(element),
+                    }
+                }
+                _ => {}
+            }
+            offset += u32_at(offset + 4) as usize;
+        }
+        root.unwrap()
+    }
+
+    #[test]
+    fn test_manifest_package_version_and_permissions() {
+        let (dir, apk) = notes_apk();
+        let mut archive = zip::ZipArchive::new(fs::File::open(&apk).unwrap()).unwrap();
+        let xml = decode_axml(&binary_manifest(&mut archive)).to_xml();
+        assert!(xml.contains("package=\"dev.raeen.notes\""));
+        assert!(xml.contains("android:versionCode=\"10402\"") && xml.contains("android:versionName=\"1.4.2\""));
+        assert!(xml.contains("<uses-sdk android:minSdkVersion=\"33\" android:targetSdkVersion=\"33\" />"));
+        for permission in ["INTERNET", "ACCESS_NETWORK_STATE", "CAMERA", "READ_MEDIA_IMAGES"] {
+            assert!(xml.contains(&format!("<uses-permission android:name=\"android.permission.{}\" />", permission)), "{}", permission);
+        }
+        assert_eq!(xml.matches("<uses-permission").count(), 4);
+
+        let strict = PackageManifest::new("calc".to_string(), &PackageFormat::AndroidApk, "aarch64", "android");
+        assert!(permissions(&strict).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_apk_layout() {
+        let (dir, apk) = notes_apk();
+        let mut archive = zip::ZipArchive::new(fs::File::open(&apk).unwrap()).unwrap();
+        let mut names: Vec<String> = archive.file_names().map(str::to_string).collect();
+        names.sort();
+        assert_eq!(names, ["AndroidManifest.xml", "lib/arm64-v8a/libnotes.so"]);
+
+        let lib_entry = archive.by_name("lib/arm64-v8a/libnotes.so").unwrap();
+        assert_eq!(lib_entry.compression(), CompressionMethod::Stored);
+        assert_eq!(lib_entry.data_start() % u64::from(LIB_ALIGNMENT), 0);
+        drop(lib_entry);
+
+        let binary = binary_manifest(&mut archive);
+        assert_eq!(binary[..4], [0x03, 0x00, 0x08, 0x00]);
+        assert_eq!(u32::from_le_bytes([binary[4], binary[5], binary[6], binary[7]]) as usize, binary.len());
+        let utf16 = |s: &str| s.encode_utf16().flat_map(u16::to_le_bytes).collect::<Vec<u8>>();
+        assert!(binary.windows(utf16("dev.raeen.notes").len()).any(|w| w == utf16("dev.raeen.notes")));
+
+        assert!(abi("riscv64").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+//! AppImage export for raeen-pkg
+//! Assembles an AppDir (AppRun launcher, desktop entry and icon at the root, package files
+//! under usr/) and turns it into a single self-mounting executable with `appimagetool`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command as ProcessCommand;
+
+use crate::{FileType, PackageBuilder, PackageFile, PackageManifest};
+
+/// Keys appimagetool refuses to package without
+const REQUIRED_DESKTOP_KEYS: &[&str] = &["Type", "Name", "Exec", "Icon", "Categories"];
+
+/// Launcher run when the AppImage is executed; resolves the mount point so bundled
+/// binaries and libraries are found before the host's
+pub fn app_run(command: &str) -> String {
+    format!(
+        "#!/bin/sh\n\
+         HERE=\"$(dirname \"$(readlink -f \"$0\")\")\"\n\
+         export PATH=\"$HERE/usr/bin:$PATH\"\n\
+         export LD_LIBRARY_PATH=\"$HERE/usr/lib${{LD_LIBRARY_PATH:+:$LD_LIBRARY_PATH}}\"\n\
+         export XDG_DATA_DIRS=\"$HERE/usr/share${{XDG_DATA_DIRS:+:$XDG_DATA_DIRS}}\"\n\
+         exec \"$HERE/usr/bin/{}\" \"$@\"\n",
+        command
+    )
+}
+
+/// Desktop entry for the package; `icon` is the icon's name without extension
+pub(crate) fn desktop_entry(manifest: &PackageManifest, command: &str, icon: &str) -> String {
+    let categories = if manifest.categories.is_empty() {
+        "Utility;".to_string()
+    } else {
+        manifest.categories.iter().map(|c| format!("{};", c)).collect()
+    };
+    format!(
+        "[Desktop Entry]\nType=Application\nName={}\nComment={}\nExec={}\nIcon={}\nCategories={}\nTerminal=false\n",
+        manifest.name, manifest.description, command, icon, categories
+    )
+}
+
+/// Check the parts of a desktop entry appimagetool depends on
+pub fn validate_desktop_entry(content: &str) -> Result<(), String> {
+    let mut lines = content.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#'));
+    if lines.next() != Some("[Desktop Entry]") {
+        return Err("desktop entry must start with a [Desktop Entry] group".to_string());
+    }
+    let keys: Vec<(&str, &str)> = lines
+        .take_while(|l| !l.starts_with('['))
+        .filter_map(|l| l.split_once('='))
+        .map(|(k, v)| (k.trim(), v.trim()))
+        .collect();
+    for required in REQUIRED_DESKTOP_KEYS {
+        match keys.iter().find(|(k, _)| k == required) {
+            Some((_, v)) if !v.is_empty() => {}
+            _ => return Err(format!("desktop entry is missing the {} key", required)),
+        }
+    }
+    if keys.iter().any(|(k, v)| *k == "Type" && *v != "Application") {
+        return Err("desktop entry Type must be Application".to_string());
+    }
+    Ok(())
+}
+
+/// Where a packaged file lives inside the AppDir; everything is rooted under usr/
+fn appdir_path(target: &Path) -> PathBuf {
+    let relative = target.strip_prefix("/").unwrap_or(target);
+    if relative.starts_with("usr") {
+        relative.to_path_buf()
+    } else {
+        Path::new("usr").join(relative)
+    }
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    Ok(())
+}
+
+/// Lay out the AppDir for the package at `dir`
+pub(crate) fn write_appdir(dir: &Path, manifest: &PackageManifest, files: &[PackageFile]) -> Result<(), Box<dyn std::error::Error>> {
+    let icon = files
+        .iter()
+        .find(|f| f.file_type == FileType::Icon)
+        .ok_or_else(|| format!("AppImage needs an icon: add assets/{}.png (or .svg) to the package", manifest.name))?;
+    let command = manifest.main_binary(files);
+
+    for file in files {
+        let dest = dir.join(appdir_path(&file.target_path));
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&file.source_path, &dest)?;
+    }
+
+    let main_binary = dir.join("usr/bin").join(&command);
+    if !main_binary.exists() {
+        return Err(format!("AppImage needs a binary to launch, but {} was not packaged", command).into());
+    }
+    set_executable(&main_binary)?;
+
+    let app_run_path = dir.join("AppRun");
+    fs::write(&app_run_path, app_run(&command))?;
+    set_executable(&app_run_path)?;
+
+    let icon_name = manifest.name.clone();
+    let icon_ext = icon.source_path.extension().map(|e| e.to_string_lossy().into_owned()).unwrap_or_else(|| "png".to_string());
+    fs::copy(&icon.source_path, dir.join(format!("{}.{}", icon_name, icon_ext)))?;
+    fs::copy(&icon.source_path, dir.join(".DirIcon"))?;
+
+    let desktop = desktop_entry(manifest, &command, &icon_name);
+    validate_desktop_entry(&desktop)?;
+    fs::write(dir.join(format!("{}.desktop", manifest.name)), desktop)?;
+    Ok(())
+}
+
+/// Build the AppDir and package it, returning the path of the AppImage
+pub(crate) fn export(builder: &PackageBuilder, manifest: &PackageManifest, files: &[PackageFile]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let appdir = builder.temp_dir.join(format!("{}.AppDir", manifest.name));
+    write_appdir(&appdir, manifest, files)?;
+
+    let image = builder.output_dir.join(format!("{}-{}-{}.AppImage", manifest.name, manifest.version, manifest.architecture));
+    let mut cmd = ProcessCommand::new("appimagetool");
+    cmd.env("ARCH", &manifest.architecture).arg("--no-appstream");
+    // A runtime file shipped with the workspace is used in place of the one appimagetool downloads
+    let runtime = builder.workspace_root.join(&builder.compatibility.appimage_runtime);
+    if runtime.is_file() {
+        cmd.arg("--runtime-file").arg(&runtime);
+    }
+    cmd.arg(&appdir).arg(&image);
+
+    let output = cmd
+        .output()
+        .map_err(|_| "appimagetool is not installed: download it from https://github.com/AppImage/appimagetool/releases and put it on PATH")?;
+    if !output.status.success() {
+        return Err(format!("appimagetool failed: {}", String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PackageFormat;
+
+    fn fixture(dir: &Path, name: &str, target: &str, file_type: FileType) -> PackageFile {
+        let source_path = dir.join(name);
+        fs::write(&source_path, name).unwrap();
+        PackageFile {
+            source_path,
+            target_path: PathBuf::from(target),
+            file_type,
+            permissions: 0o644,
+            checksum: String::new(),
+            size: 0,
+        }
+    }
+
+    #[test]
+    fn test_appdir_layout() {
+        let root = std::env::temp_dir().join(format!("raeen-appimage-{}", uuid::Uuid::new_v4()));
+        let src = root.join("src");
+        fs::create_dir_all(&src).unwrap();
+        let mut manifest = PackageManifest::new("notes".to_string(), &PackageFormat::AppImage, "x86_64", "linux");
+        manifest.categories = vec!["Office".to_string()];
+        let files = vec![
+            fixture(&src, "notes", "/usr/bin/notes", FileType::Binary),
+            fixture(&src, "libnotes.so", "/usr/lib/libnotes.so", FileType::Library),
+            fixture(&src, "notes.svg", "/usr/share/pixmaps/notes.svg", FileType::Icon),
+            fixture(&src, "notes.toml", "/etc/notes/notes.toml", FileType::Configuration),
+        ];
+
+        let appdir = root.join("notes.AppDir");
+        write_appdir(&appdir, &manifest, &files).unwrap();
+
+        let app_run = fs::read_to_string(appdir.join("AppRun")).unwrap();
+        assert!(app_run.starts_with("#!/bin/sh\n"));
+        assert!(app_run.contains("exec \"$HERE/usr/bin/notes\" \"$@\""));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |p: &Path| fs::metadata(p).unwrap().permissions().mode();
+            assert_eq!(mode(&appdir.join("AppRun")) & 0o111, 0o111);
+            assert_eq!(mode(&appdir.join("usr/bin/notes")) & 0o111, 0o111);
+        }
+
+        let desktop = fs::read_to_string(appdir.join("notes.desktop")).unwrap();
+        assert_eq!(validate_desktop_entry(&desktop), Ok(()));
+        assert!(desktop.contains("Icon=notes\n") && desktop.contains("Categories=Office;\n"));
+        assert!(appdir.join("notes.svg").is_file() && appdir.join(".DirIcon").is_file());
+        assert!(appdir.join("usr/lib/libnotes.so").is_file());
+        assert!(appdir.join("usr/etc/notes/notes.toml").is_file());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_missing_icon_and_bad_desktop_entry_rejected() {
+        let root = std::env::temp_dir().join(format!("raeen-appimage-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        let manifest = PackageManifest::new("notes".to_string(), &PackageFormat::AppImage, "x86_64", "linux");
+        let files = vec![fixture(&root, "notes", "/usr/bin/notes", FileType::Binary)];
+        let err = write_appdir(&root.join("notes.AppDir"), &manifest, &files).unwrap_err();
+        assert!(err.to_string().contains("needs an icon"));
+
+        assert!(validate_desktop_entry("[Desktop Entry]\nType=Application\nName=notes\nExec=notes\nCategories=Utility;\n")
+            .unwrap_err()
+            .contains("Icon"));
+        assert!(validate_desktop_entry("Name=notes\n").is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,338 @@
+//! Installed-package database for raeen-pkg
+//! One JSON record per package under `<root>/var/lib/raeen-pkg/installed/`, listing every file
+//! the package placed together with the checksum it had at install time. `verify` compares the
+//! files on disk against that record. A database opened on an install prefix lives inside it,
+//! so a relocated install is entirely self-contained.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+
+use raeen_common::json::{self, JsonValue};
+use crate::sha256_file;
+
+/// Location of the records, relative to the install root
+pub const DATABASE_DIR: &str = "var/lib/raeen-pkg/installed";
+
+/// Names of held packages, one per line, relative to the install root
+pub const HOLDS_FILE: &str = "var/lib/raeen-pkg/holds";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstalledFile {
+    /// Absolute path on the installed system, e.g. `/usr/bin/editor`
+    pub path: PathBuf,
+    pub checksum: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstalledPackage {
+    pub name: String,
+    pub version: String,
+    /// Set when the package was installed relocated under a prefix; file paths are then
+    /// relative to it
+    pub prefix: Option<PathBuf>,
+    pub files: Vec<InstalledFile>,
+}
+
+impl InstalledPackage {
+    pub fn to_json(&self) -> JsonValue {
+        let files = self
+            .files
+            .iter()
+            .map(|f| {
+                JsonValue::object(vec![
+                    ("path", JsonValue::String(f.path.display().to_string())),
+                    ("checksum", JsonValue::String(f.checksum.clone())),
+                    ("size", JsonValue::Number(f.size as f64)),
+                ])
+            })
+            .collect();
+        let mut fields = vec![
+            ("name", JsonValue::String(self.name.clone())),
+            ("version", JsonValue::String(self.version.clone())),
+            ("files", JsonValue::Array(files)),
+        ];
+        if let Some(prefix) = &self.prefix {
+            fields.push(("prefix", JsonValue::String(prefix.display().to_string())));
+        }
+        JsonValue::object(fields)
+    }
+
+    pub fn from_json(value: &JsonValue) -> Result<Self, String> {
+        let string = |v: &JsonValue, key: &str| {
+            v.field(key)?.as_str().map(str::to_string).ok_or_else(|| format!("field '{}' is not a string", key))
+        };
+        let files = value
+            .field("files")?
+            .as_array()
+            .ok_or("field 'files' is not an array")?
+            .iter()
+            .map(|f| {
+                Ok(InstalledFile {
+                    path: PathBuf::from(string(f, "path")?),
+                    checksum: string(f, "checksum")?,
+                    size: f.field("size")?.as_u64().ok_or("field 'size' is not an integer")?,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        let prefix = match value.get("prefix") {
+            Some(prefix) => Some(PathBuf::from(prefix.as_str().ok_or("field 'prefix' is not a string")?)),
+            None => None,
+        };
+        Ok(Self { name: string(value, "name")?, version: string(value, "version")?, prefix, files })
+    }
+}
+
+/// The database of packages installed under one root
+#[derive(Debug, Clone)]
+pub struct Database {
+    root: PathBuf,
+    relocated: bool,
+}
+
+impl Database {
+    pub fn open(root: &Path) -> Self {
+        Self { root: root.to_path_buf(), relocated: false }
+    }
+
+    /// Database for relocated installs under `prefix`
+    pub fn open_prefix(prefix: &Path) -> Self {
+        Self { root: prefix.to_path_buf(), relocated: true }
+    }
+
+    /// The install prefix, for a database opened with `open_prefix`
+    pub fn prefix(&self) -> Option<&Path> {
+        self.relocated.then_some(self.root.as_path())
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where an installed path lives on this host
+    pub fn host_path(&self, path: &Path) -> PathBuf {
+        self.root.join(path.strip_prefix("/").unwrap_or(path))
+    }
+
+    fn record_path(&self, name: &str) -> PathBuf {
+        self.root.join(DATABASE_DIR).join(format!("{}.json", name))
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<InstalledPackage>, Box<dyn std::error::Error>> {
+        let path = self.record_path(name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let value = json::parse(&fs::read_to_string(&path)?).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Some(InstalledPackage::from_json(&value).map_err(|e| format!("{}: {}", path.display(), e))?))
+    }
+
+    /// Write the record for `package`, replacing any previous one in a single rename
+    pub fn insert(&self, package: &InstalledPackage) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self.record_path(&package.name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let staging = path.with_extension("json.new");
+        fs::write(&staging, package.to_json().to_string())?;
+        fs::rename(&staging, &path)?;
+        Ok(())
+    }
+
+    pub fn remove(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let path = self.record_path(name);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// All installed packages, sorted by name
+    pub fn list(&self) -> Result<Vec<InstalledPackage>, Box<dyn std::error::Error>> {
+        let dir = self.root.join(DATABASE_DIR);
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut names: Vec<String> = fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+            .filter_map(|path| path.file_stem().map(|s| s.to_string_lossy().into_owned()))
+            .collect();
+        names.sort();
+        let mut packages = Vec::new();
+        for name in names {
+            packages.extend(self.get(&name)?);
+        }
+        Ok(packages)
+    }
+
+    /// Packages `update` must leave at their installed version
+    pub fn holds(&self) -> Result<BTreeSet<String>, Box<dyn std::error::Error>> {
+        let path = self.root.join(HOLDS_FILE);
+        if !path.exists() {
+            return Ok(BTreeSet::new());
+        }
+        Ok(fs::read_to_string(path)?.lines().map(str::trim).filter(|l| !l.is_empty()).map(str::to_string).collect())
+    }
+
+    pub fn set_held(&self, name: &str, held: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let mut holds = self.holds()?;
+        let changed = if held { holds.insert(name.to_string()) } else { holds.remove(name) };
+        if !changed {
+            return Ok(());
+        }
+        let path = self.root.join(HOLDS_FILE);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let staging = path.with_extension("new");
+        fs::write(&staging, holds.into_iter().map(|h| h + "\n").collect::<String>())?;
+        fs::rename(&staging, &path)?;
+        Ok(())
+    }
+}
+
+/// Differences between an installed package's record and the files on disk
+#[derive(Debug, Default, PartialEq)]
+pub struct VerifyReport {
+    pub missing: Vec<PathBuf>,
+    pub modified: Vec<PathBuf>,
+    /// Unrecorded files inside directories that belong to the package alone
+    pub extra: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.modified.is_empty() && self.extra.is_empty()
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (label, paths) in [("missing", &self.missing), ("modified", &self.modified), ("extra", &self.extra)] {
+            for path in paths {
+                out.push_str(&format!("{:<9}{}\n", label, path.display()));
+            }
+        }
+        out
+    }
+}
+
+/// Directories only this package writes to: those named after it, such as
+/// `/usr/share/doc/<name>` or `/etc/<name>`
+fn owned_dirs(package: &InstalledPackage) -> BTreeSet<PathBuf> {
+    package
+        .files
+        .iter()
+        .filter_map(|f| {
+            let mut dir = PathBuf::new();
+            for component in f.path.parent()?.components() {
+                dir.push(component);
+                if component == Component::Normal(package.name.as_ref()) {
+                    return Some(dir);
+                }
+            }
+            None
+        })
+        .collect()
+}
+
+/// Recompute the checksum of every recorded file of `package`
+pub fn verify(db: &Database, package: &InstalledPackage) -> Result<VerifyReport, Box<dyn std::error::Error>> {
+    let mut report = VerifyReport::default();
+    for file in &package.files {
+        let host = db.host_path(&file.path);
+        if !host.is_file() {
+            report.missing.push(file.path.clone());
+        } else if sha256_file(&host)? != file.checksum {
+            report.modified.push(file.path.clone());
+        }
+    }
+
+    let recorded: BTreeSet<&Path> = package.files.iter().map(|f| f.path.as_path()).collect();
+    let mut extra = BTreeSet::new();
+    for dir in owned_dirs(package) {
+        let host_dir = db.host_path(&dir);
+        if !host_dir.is_dir() {
+            continue;
+        }
+        for entry in WalkDir::new(&host_dir) {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let path = dir.join(entry.path().strip_prefix(&host_dir)?);
+            if !recorded.contains(path.as_path()) {
+                extra.insert(path);
+            }
+        }
+    }
+    report.extra = extra.into_iter().collect();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A database under a fresh root with `editor` installed and recorded
+    fn installed() -> (PathBuf, Database, InstalledPackage) {
+        let root = std::env::temp_dir().join(format!("raeen-pkgdb-{}", uuid::Uuid::new_v4()));
+        let db = Database::open(&root);
+        let mut files = Vec::new();
+        for (path, contents) in [("/usr/bin/editor", "binary"), ("/etc/editor/editor.toml", "theme = \"dark\"")] {
+            let host = db.host_path(Path::new(path));
+            fs::create_dir_all(host.parent().unwrap()).unwrap();
+            fs::write(&host, contents).unwrap();
+            files.push(InstalledFile { path: PathBuf::from(path), checksum: sha256_file(&host).unwrap(), size: contents.len() as u64 });
+        }
+        let package = InstalledPackage { name: "editor".to_string(), version: "1.0.0".to_string(), prefix: None, files };
+        db.insert(&package).unwrap();
+        (root, db, package)
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let (root, db, package) = installed();
+        assert_eq!(db.get("editor").unwrap(), Some(package.clone()));
+        assert_eq!(db.list().unwrap(), vec![package.clone()]);
+        db.remove("editor").unwrap();
+        assert_eq!(db.get("editor").unwrap(), None);
+
+        let relocated = InstalledPackage { prefix: Some(PathBuf::from("/opt/editor")), ..package };
+        assert_eq!(InstalledPackage::from_json(&relocated.to_json()), Ok(relocated));
+
+        db.set_held("editor", true).unwrap();
+        db.set_held("shell", true).unwrap();
+        db.set_held("shell", false).unwrap();
+        assert_eq!(db.holds().unwrap().into_iter().collect::<Vec<_>>(), ["editor"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_intact_install_verifies_clean() {
+        let (root, db, package) = installed();
+        let report = verify(&db, &package).unwrap();
+        assert!(report.is_clean(), "{}", report.render());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_modified_missing_and_extra_files_flagged() {
+        let (root, db, package) = installed();
+        fs::write(db.host_path(Path::new("/etc/editor/editor.toml")), "theme = \"light\"").unwrap();
+        fs::remove_file(db.host_path(Path::new("/usr/bin/editor"))).unwrap();
+        fs::write(db.host_path(Path::new("/etc/editor/stray.toml")), "").unwrap();
+        // Unrelated files in shared directories are not the package's concern
+        fs::write(db.host_path(Path::new("/usr/bin/other")), "").unwrap();
+
+        let report = verify(&db, &package).unwrap();
+        assert_eq!(report.missing, vec![PathBuf::from("/usr/bin/editor")]);
+        assert_eq!(report.modified, vec![PathBuf::from("/etc/editor/editor.toml")]);
+        assert_eq!(report.extra, vec![PathBuf::from("/etc/editor/stray.toml")]);
+        assert!(!report.is_clean());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
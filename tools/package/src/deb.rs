@@ -0,0 +1,206 @@
+//! Debian package (.deb) installation for raeen-pkg
+//! A .deb is an ar archive of `debian-binary`, `control.tar[.gz|.xz]` and `data.tar[.gz|.xz]`.
+//! Name and version come from the control file; data.tar is unpacked into the install
+//! transaction's staging area and its regular files are recorded like a native package's.
+//! Maintainer scripts are written against dpkg and are not run.
+
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use log::warn;
+use tar::Archive;
+
+use crate::database::{InstalledFile, InstalledPackage};
+use crate::install::StagedPackage;
+use crate::sha256_file;
+
+const AR_MAGIC: &[u8] = b"!<arch>\n";
+const AR_HEADER_LEN: usize = 60;
+
+/// Whether `header`, the first bytes of a file, start an ar archive
+pub fn is_deb(header: &[u8]) -> bool {
+    header.starts_with(AR_MAGIC)
+}
+
+/// Members of an ar archive as (name, contents)
+fn ar_members(data: &[u8]) -> Result<Vec<(String, &[u8])>, String> {
+    let mut rest = data.strip_prefix(AR_MAGIC).ok_or("not an ar archive")?;
+    let mut members = Vec::new();
+    while !rest.is_empty() {
+        if rest.len() < AR_HEADER_LEN || &rest[58..60] != b"`\n" {
+            return Err("truncated ar member header".to_string());
+        }
+        // GNU ar terminates names with '/'
+        let name = String::from_utf8_lossy(&rest[..16]).trim_end().trim_end_matches('/').to_string();
+        let size: usize = String::from_utf8_lossy(&rest[48..58])
+            .trim()
+            .parse()
+            .map_err(|_| format!("ar member '{}' has an invalid size", name))?;
+        let body = rest.get(AR_HEADER_LEN..AR_HEADER_LEN + size).ok_or_else(|| format!("ar member '{}' is truncated", name))?;
+        members.push((name, body));
+        // Members start at even offsets
+        rest = rest.get((AR_HEADER_LEN + size + 1) & !1..).unwrap_or_default();
+    }
+    Ok(members)
+}
+
+/// The tar inside a `control.tar*` or `data.tar*` member
+fn decompress(name: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    match name.rsplit_once(".tar").map(|(_, ext)| ext) {
+        Some("") => out.extend_from_slice(data),
+        Some(".gz") => {
+            GzDecoder::new(data).read_to_end(&mut out).map_err(|e| format!("{}: {}", name, e))?;
+        }
+        Some(".xz") => {
+            let mut input = data;
+            lzma_rs::xz_decompress(&mut input, &mut out).map_err(|e| format!("{}: {:?}", name, e))?;
+        }
+        _ => return Err(format!("{}: unsupported compression (gzip, xz or none expected)", name)),
+    }
+    Ok(out)
+}
+
+/// Fields of the control file's first paragraph; continuation lines are folded into the
+/// preceding field
+fn parse_control(text: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push('\n');
+                value.push_str(line.trim());
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            fields.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    fields
+}
+
+/// An archive path without `./` or a leading `/`
+fn normalize(path: &Path) -> PathBuf {
+    path.components().filter(|c| matches!(c, Component::Normal(_))).collect()
+}
+
+/// Unpack the .deb at `archive` into `files_dir` and describe what it installs
+pub(crate) fn stage(files_dir: &Path, archive: &Path) -> Result<StagedPackage, Box<dyn std::error::Error>> {
+    let contents = fs::read(archive)?;
+    let members = ar_members(&contents).map_err(|e| format!("{}: {}", archive.display(), e))?;
+    let member = |prefix: &str| {
+        members
+            .iter()
+            .find(|(name, _)| name.starts_with(prefix))
+            .ok_or_else(|| format!("{} has no {} member", archive.display(), prefix))
+    };
+    let (_, format_version) = member("debian-binary")?;
+    if !format_version.starts_with(b"2.") {
+        return Err(format!("{}: unsupported deb format {}", archive.display(), String::from_utf8_lossy(format_version).trim()).into());
+    }
+
+    let (name, control_tar) = member("control.tar")?;
+    let control_tar = decompress(name, control_tar)?;
+    let mut control = None;
+    let mut scripts = Vec::new();
+    for entry in Archive::new(control_tar.as_slice()).entries()? {
+        let mut entry = entry?;
+        let path = normalize(&entry.path()?);
+        match path.to_str() {
+            Some("control") => {
+                let mut text = String::new();
+                entry.read_to_string(&mut text)?;
+                control = Some(parse_control(&text));
+            }
+            Some(script @ ("preinst" | "postinst" | "prerm" | "postrm")) => scripts.push(script.to_string()),
+            _ => {}
+        }
+    }
+    let control = control.ok_or_else(|| format!("{} has no control file", archive.display()))?;
+    let field = |key: &str| {
+        control
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.clone())
+            .ok_or_else(|| format!("{}: control file has no {} field", archive.display(), key))
+    };
+    let package_name = field("Package")?;
+    let version = field("Version")?;
+    if let Ok(depends) = field("Depends") {
+        warn!("{} depends on {}; Debian dependencies are not resolved", package_name, depends);
+    }
+    if !scripts.is_empty() {
+        warn!("Not running maintainer scripts of {} ({})", package_name, scripts.join(", "));
+    }
+
+    let (name, data_tar) = member("data.tar")?;
+    let data_tar = decompress(name, data_tar)?;
+    let mut files = Vec::new();
+    let mut sources = Vec::new();
+    for entry in Archive::new(data_tar.as_slice()).entries()? {
+        let mut entry = entry?;
+        let relative = normalize(&entry.path()?);
+        let kind = entry.header().entry_type();
+        if kind.is_dir() {
+            continue;
+        }
+        if !kind.is_file() {
+            warn!("Skipping /{} of {}: only regular files are recorded", relative.display(), package_name);
+            continue;
+        }
+        if !entry.unpack_in(files_dir)? {
+            return Err(format!("{} contains an entry outside the install root", archive.display()).into());
+        }
+        let staged = files_dir.join(&relative);
+        files.push(InstalledFile {
+            path: Path::new("/").join(&relative),
+            checksum: sha256_file(&staged)?,
+            size: fs::metadata(&staged)?.len(),
+        });
+        sources.push(relative);
+    }
+
+    Ok(StagedPackage {
+        package: InstalledPackage { name: package_name, version, prefix: None, files },
+        sources,
+        pre_install: None,
+        post_install: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ar_members_are_padded_to_even_offsets() {
+        let mut ar = AR_MAGIC.to_vec();
+        for (name, data) in [("debian-binary/", &b"2.0\n"[..]), ("odd", &b"abc"[..]), ("last", &b"z"[..])] {
+            ar.extend(format!("{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", name, 0, 0, 0, 644, data.len()).into_bytes());
+            ar.extend_from_slice(data);
+            if data.len() % 2 == 1 {
+                ar.push(b'\n');
+            }
+        }
+        let members = ar_members(&ar).unwrap();
+        let names: Vec<&str> = members.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["debian-binary", "odd", "last"]);
+        assert_eq!(members[1].1, b"abc");
+
+        ar.truncate(ar.len() - 2);
+        assert!(ar_members(&ar).unwrap_err().contains("'last' is truncated"));
+        assert!(decompress("data.tar.zst", b"").unwrap_err().contains("unsupported compression"));
+    }
+
+    #[test]
+    fn test_control_paragraph() {
+        let control = parse_control("Package: hello\nVersion: 2.10-3\nDescription: example\n greets the world\n\nPackage: other\n");
+        assert_eq!(control.len(), 3);
+        assert_eq!(control[1], ("Version".to_string(), "2.10-3".to_string()));
+        assert_eq!(control[2].1, "example\ngreets the world");
+    }
+}
@@ -0,0 +1,273 @@
+//! Flatpak export for raeen-pkg
+//! Turns a package into a Flatpak build directory (`metadata` plus files under /app), exports
+//! it into a local OSTree repo with `flatpak build-export` and bundles it into a single
+//! `.flatpak` file. A flatpak-builder manifest is written alongside so the app can also be
+//! rebuilt from source with the standard tooling.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command as ProcessCommand;
+
+use raeen_common::compat::CompatibilityConfig;
+use raeen_common::json::JsonValue;
+use crate::{FileType, PackageBuilder, PackageFile, PackageManifest};
+
+/// The parts of a Flatpak application definition derived from a package manifest
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatpakManifest {
+    pub app_id: String,
+    pub runtime: String,
+    pub runtime_version: String,
+    pub sdk: String,
+    pub command: String,
+    pub finish_args: Vec<String>,
+}
+
+impl FlatpakManifest {
+    pub(crate) fn new(manifest: &PackageManifest, files: &[PackageFile], compat: &CompatibilityConfig) -> Self {
+        Self {
+            app_id: manifest.app_id(),
+            runtime: compat.flatpak_runtime.clone(),
+            runtime_version: compat.flatpak_runtime_version.clone(),
+            // Runtimes follow the org.example.Platform / org.example.Sdk naming pair
+            sdk: compat.flatpak_runtime.replace(".Platform", ".Sdk"),
+            command: manifest.main_binary(files),
+            finish_args: finish_args(manifest, files),
+        }
+    }
+
+    /// flatpak-builder manifest installing the prebuilt files into /app
+    pub(crate) fn to_json(&self, manifest: &PackageManifest, files: &[PackageFile]) -> JsonValue {
+        let install: Vec<String> = files
+            .iter()
+            .map(|f| format!("install -Dm{:o} {} /app/{}", f.permissions & 0o777, source_name(f), app_path(&f.target_path).display()))
+            .collect();
+        let sources: Vec<JsonValue> = files
+            .iter()
+            .map(|f| {
+                JsonValue::object(vec![
+                    ("type", JsonValue::String("file".to_string())),
+                    ("path", JsonValue::String(f.source_path.display().to_string())),
+                    ("dest-filename", JsonValue::String(source_name(f))),
+                ])
+            })
+            .collect();
+
+        JsonValue::object(vec![
+            ("app-id", JsonValue::String(self.app_id.clone())),
+            ("runtime", JsonValue::String(self.runtime.clone())),
+            ("runtime-version", JsonValue::String(self.runtime_version.clone())),
+            ("sdk", JsonValue::String(self.sdk.clone())),
+            ("command", JsonValue::String(self.command.clone())),
+            ("finish-args", JsonValue::string_array(self.finish_args.iter().cloned())),
+            (
+                "modules",
+                JsonValue::Array(vec![JsonValue::object(vec![
+                    ("name", JsonValue::String(manifest.name.clone())),
+                    ("buildsystem", JsonValue::String("simple".to_string())),
+                    ("build-commands", JsonValue::string_array(install)),
+                    ("sources", JsonValue::Array(sources)),
+                ])]),
+            ),
+        ])
+    }
+
+    /// The `metadata` keyfile of the build directory; `[Context]` mirrors the finish args
+    pub fn metadata(&self, arch: &str) -> String {
+        let mut shared = Vec::new();
+        let mut sockets = Vec::new();
+        let mut devices = Vec::new();
+        let mut filesystems = Vec::new();
+        for arg in &self.finish_args {
+            if let Some(v) = arg.strip_prefix("--share=") {
+                shared.push(v);
+            } else if let Some(v) = arg.strip_prefix("--socket=") {
+                sockets.push(v);
+            } else if let Some(v) = arg.strip_prefix("--device=") {
+                devices.push(v);
+            } else if let Some(v) = arg.strip_prefix("--filesystem=") {
+                filesystems.push(v);
+            }
+        }
+
+        let mut metadata = format!(
+            "[Application]\nname={}\nruntime={}/{}/{}\nsdk={}/{}/{}\ncommand={}\n",
+            self.app_id, self.runtime, arch, self.runtime_version, self.sdk, arch, self.runtime_version, self.command
+        );
+        let context: Vec<(&str, Vec<&str>)> =
+            vec![("shared", shared), ("sockets", sockets), ("devices", devices), ("filesystems", filesystems)];
+        if context.iter().any(|(_, values)| !values.is_empty()) {
+            metadata.push_str("\n[Context]\n");
+            for (key, values) in context.into_iter().filter(|(_, values)| !values.is_empty()) {
+                metadata.push_str(&format!("{}={};\n", key, values.join(";")));
+            }
+        }
+        metadata
+    }
+}
+
+/// Sandbox holes for the package: display sockets for desktop apps, plus whatever its
+/// permissions and sandbox config grant. An app with its sandbox disabled gets host access.
+pub(crate) fn finish_args(manifest: &PackageManifest, files: &[PackageFile]) -> Vec<String> {
+    let mut args = Vec::new();
+    if files.iter().any(|f| f.file_type == FileType::Desktop) {
+        args.extend(["--share=ipc", "--socket=wayland", "--socket=fallback-x11"].map(String::from));
+    }
+    if manifest.permissions.network_access || !manifest.sandbox.enabled {
+        args.push("--share=network".to_string());
+    }
+    for device in &manifest.permissions.hardware_access {
+        args.push(match device.as_str() {
+            "gpu" => "--device=dri".to_string(),
+            "audio" => "--socket=pulseaudio".to_string(),
+            _ => "--device=all".to_string(),
+        });
+    }
+    for path in &manifest.sandbox.file_system_access {
+        let path = match path.as_str() {
+            "documents" => "xdg-documents",
+            "downloads" => "xdg-download",
+            "music" => "xdg-music",
+            "pictures" => "xdg-pictures",
+            "videos" => "xdg-videos",
+            other => other,
+        };
+        args.push(format!("--filesystem={}", path));
+    }
+    if !manifest.sandbox.enabled {
+        args.extend(["--filesystem=host", "--device=all"].map(String::from));
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    args.retain(|arg| seen.insert(arg.clone()));
+    args
+}
+
+/// Where a file installed at `target` lives under /app: /usr prefixes collapse into /app
+fn app_path(target: &Path) -> PathBuf {
+    let relative = target.strip_prefix("/").unwrap_or(target);
+    relative.strip_prefix("usr").map(Path::to_path_buf).unwrap_or_else(|_| relative.to_path_buf())
+}
+
+fn source_name(file: &PackageFile) -> String {
+    file.target_path.to_string_lossy().trim_start_matches('/').replace('/', "_")
+}
+
+/// Lay out a Flatpak build directory for the package under `dir`
+pub(crate) fn write_build_dir(dir: &Path, flatpak: &FlatpakManifest, arch: &str, files: &[PackageFile]) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(dir.join("files"))?;
+    fs::create_dir_all(dir.join("export"))?;
+    fs::write(dir.join("metadata"), flatpak.metadata(arch))?;
+    for file in files {
+        let dest = dir.join("files").join(app_path(&file.target_path));
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&file.source_path, &dest)?;
+    }
+    Ok(())
+}
+
+fn flatpak(args: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+    let output = ProcessCommand::new("flatpak")
+        .args(args)
+        .output()
+        .map_err(|_| "flatpak is not installed: install flatpak and flatpak-builder (e.g. `apt install flatpak flatpak-builder`)")?;
+    if !output.status.success() {
+        return Err(format!("flatpak {} failed: {}", args[0], String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+    Ok(())
+}
+
+/// Build, export and bundle the package, returning the path of the `.flatpak` bundle
+pub(crate) fn export(builder: &PackageBuilder, manifest: &PackageManifest, files: &[PackageFile]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    flatpak(&["--version"])?;
+
+    let app = FlatpakManifest::new(manifest, files, &builder.compatibility);
+    let manifest_path = builder.output_dir.join(format!("{}.json", app.app_id));
+    fs::write(&manifest_path, app.to_json(manifest, files).to_string())?;
+
+    let build_dir = builder.temp_dir.join("flatpak").join(&app.app_id);
+    write_build_dir(&build_dir, &app, &manifest.architecture, files)?;
+
+    let repo = builder.output_dir.join("flatpak-repo");
+    let bundle = builder.output_dir.join(format!("{}-{}.flatpak", manifest.name, manifest.version));
+    let (repo_arg, build_arg, bundle_arg) = (repo.to_string_lossy(), build_dir.to_string_lossy(), bundle.to_string_lossy());
+    let arch_arg = format!("--arch={}", manifest.architecture);
+    flatpak(&["build-export", &arch_arg, &repo_arg, &build_arg])?;
+    flatpak(&["build-bundle", &arch_arg, &repo_arg, &bundle_arg, &app.app_id])?;
+
+    Ok(bundle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PackageFormat;
+
+    fn file(target: &str, file_type: FileType) -> PackageFile {
+        PackageFile {
+            source_path: PathBuf::from("target/release").join(Path::new(target).file_name().unwrap()),
+            target_path: PathBuf::from(target),
+            file_type,
+            permissions: 0o755,
+            checksum: String::new(),
+            size: 0,
+        }
+    }
+
+    #[test]
+    fn test_manifest_derived_from_package_sandbox() {
+        let mut manifest = PackageManifest::new("photo-viewer".to_string(), &PackageFormat::Flatpak, "x86_64", "linux");
+        manifest.permissions.network_access = true;
+        manifest.permissions.hardware_access = vec!["gpu".to_string()];
+        manifest.sandbox.file_system_access = vec!["pictures".to_string(), "/media:ro".to_string()];
+        let files = vec![
+            file("/usr/bin/photo-viewer", FileType::Binary),
+            file("/usr/share/applications/photo-viewer.desktop", FileType::Desktop),
+        ];
+        let compat = CompatibilityConfig::default();
+
+        let flatpak = FlatpakManifest::new(&manifest, &files, &compat);
+        assert_eq!(flatpak.app_id, "dev.raeen.photo_viewer");
+        assert_eq!(flatpak.command, "photo-viewer");
+        assert_eq!(
+            flatpak.finish_args,
+            [
+                "--share=ipc",
+                "--socket=wayland",
+                "--socket=fallback-x11",
+                "--share=network",
+                "--device=dri",
+                "--filesystem=xdg-pictures",
+                "--filesystem=/media:ro",
+            ]
+        );
+
+        let json = raeen_common::json::parse(&flatpak.to_json(&manifest, &files).to_string()).unwrap();
+        assert_eq!(json.get("app-id").and_then(JsonValue::as_str), Some("dev.raeen.photo_viewer"));
+        assert_eq!(json.get("runtime").and_then(JsonValue::as_str), Some(compat.flatpak_runtime.as_str()));
+        assert_eq!(json.get("sdk").and_then(JsonValue::as_str), Some("org.freedesktop.Sdk"));
+        assert_eq!(json.string_list("finish-args").unwrap(), flatpak.finish_args);
+
+        let metadata = flatpak.metadata("x86_64");
+        assert!(metadata.contains("runtime=org.freedesktop.Platform/x86_64/23.08\n"));
+        assert!(metadata.contains("shared=ipc;network;\n"));
+        assert!(metadata.contains("filesystems=xdg-pictures;/media:ro;\n"));
+    }
+
+    #[test]
+    fn test_strict_sandbox_grants_nothing_and_files_land_under_app() {
+        let manifest = PackageManifest::new("2048".to_string(), &PackageFormat::Flatpak, "x86_64", "linux");
+        assert!(finish_args(&manifest, &[file("/usr/bin/2048", FileType::Binary)]).is_empty());
+        assert_eq!(manifest.app_id(), "dev.raeen._2048");
+
+        assert_eq!(app_path(Path::new("/usr/bin/2048")), Path::new("bin/2048"));
+        assert_eq!(app_path(Path::new("/etc/2048/config.toml")), Path::new("etc/2048/config.toml"));
+
+        let mut open = manifest.clone();
+        open.sandbox.enabled = false;
+        let args = finish_args(&open, &[]);
+        assert!(args.contains(&"--filesystem=host".to_string()) && args.contains(&"--share=network".to_string()));
+    }
+}
@@ -0,0 +1,554 @@
+//! Transactional package installation
+//! Native packages and .debs are told apart by their first bytes. An install stages every file under the install root, runs the pre-install script, moves the
+//! files into place and only then commits the database record. Any failure before the commit
+//! removes what was placed, restores the files it replaced and discards the staging area, so
+//! the system is left exactly as it was.
+//!
+//! Into a database opened on a prefix, files are relocated: `/usr/bin/tool` lands in
+//! `<prefix>/bin/tool` and `/etc/tool` in `<prefix>/etc/tool`.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command as ProcessCommand;
+
+use flate2::read::GzDecoder;
+use log::{debug, warn};
+use tar::Archive;
+use uuid::Uuid;
+
+use crate::database::{Database, InstalledFile, InstalledPackage};
+use raeen_common::json::{self, JsonValue};
+use crate::signing::SIGNATURE_ENTRY;
+use crate::{deb, sha256_file, PackageFormat};
+
+/// Scratch space for installs, relative to the install root; it sits on the same filesystem
+/// as the destination so placing a file is a rename
+pub const STAGING_DIR: &str = "var/lib/raeen-pkg/staging";
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Contents of a package after unpacking into the staging area
+#[derive(Debug)]
+pub struct StagedPackage {
+    pub package: InstalledPackage,
+    /// Path of each of `package.files` inside the staging area's `files/`
+    pub sources: Vec<PathBuf>,
+    pub pre_install: Option<String>,
+    pub post_install: Option<String>,
+}
+
+/// Filesystem changes made by an install, undone in reverse on rollback
+struct Transaction<'a> {
+    db: &'a Database,
+    staging: PathBuf,
+    /// Target paths the install has written
+    placed: Vec<PathBuf>,
+    /// Files the install replaced, with where they were moved aside
+    backups: Vec<(PathBuf, PathBuf)>,
+    /// Directories the install created, outermost first
+    created_dirs: Vec<PathBuf>,
+}
+
+impl<'a> Transaction<'a> {
+    fn begin(db: &'a Database) -> Result<Self, Box<dyn std::error::Error>> {
+        let staging = db.host_path(Path::new(STAGING_DIR)).join(Uuid::new_v4().to_string());
+        fs::create_dir_all(staging.join("files"))?;
+        fs::create_dir_all(staging.join("backup"))?;
+        Ok(Self { db, staging, placed: Vec::new(), backups: Vec::new(), created_dirs: Vec::new() })
+    }
+
+    /// Unpack `archive`, a package in `format`, into the staging area
+    fn stage(&self, archive: &Path, format: &PackageFormat) -> Result<StagedPackage, Box<dyn std::error::Error>> {
+        let files_dir = self.staging.join("files");
+        let mut staged = match format {
+            PackageFormat::RaeNative => stage_native(&files_dir, archive)?,
+            PackageFormat::Deb => deb::stage(&files_dir, archive)?,
+            other => return Err(format!("{:?} packages cannot be installed", other).into()),
+        };
+        if let Some(prefix) = self.db.prefix() {
+            for file in &mut staged.package.files {
+                file.path = relocate(&file.path);
+            }
+            staged.package.prefix = Some(prefix.to_path_buf());
+        }
+        Ok(staged)
+    }
+
+    fn create_parents(&mut self, target: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let mut missing = Vec::new();
+        let mut dir = target.parent();
+        while let Some(d) = dir {
+            if d.exists() {
+                break;
+            }
+            missing.push(d.to_path_buf());
+            dir = d.parent();
+        }
+        for d in missing.into_iter().rev() {
+            fs::create_dir(&d)?;
+            self.created_dirs.push(d);
+        }
+        Ok(())
+    }
+
+    /// Move the staged `source` to the destination of `file`, setting aside whatever was there
+    fn place(&mut self, file: &InstalledFile, source: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let relative = file.path.strip_prefix("/").unwrap_or(&file.path);
+        let target = self.db.host_path(&file.path);
+        if target.is_dir() {
+            return Err(format!("cannot install {}: a directory is in the way", file.path.display()).into());
+        }
+        self.create_parents(&target)?;
+        if target.exists() {
+            let backup = self.staging.join("backup").join(relative);
+            if let Some(parent) = backup.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(&target, &backup)?;
+            self.backups.push((target.clone(), backup));
+        }
+        fs::rename(self.staging.join("files").join(source), &target)?;
+        self.placed.push(target);
+        Ok(())
+    }
+
+    fn run_script(&self, phase: &str, script: &Option<String>, package: &InstalledPackage) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(script) = script else { return Ok(()) };
+        debug!("Running {} script of {}", phase, package.name);
+        let output = ProcessCommand::new("sh")
+            .arg("-c")
+            .arg(script)
+            .env("RAEEN_ROOT", self.db.root())
+            .env("RAEEN_PREFIX", self.db.prefix().unwrap_or(Path::new("/usr")))
+            .env("RAEEN_PACKAGE", &package.name)
+            .env("RAEEN_VERSION", &package.version)
+            .output()?;
+        if !output.status.success() {
+            return Err(format!("{} script of {} failed: {}", phase, package.name, String::from_utf8_lossy(&output.stderr).trim()).into());
+        }
+        Ok(())
+    }
+
+    /// Undo every placement, restore replaced files and drop the staging area
+    fn rollback(self) {
+        for target in self.placed.iter().rev() {
+            if let Err(e) = fs::remove_file(target) {
+                warn!("Rollback could not remove {}: {}", target.display(), e);
+            }
+        }
+        for (target, backup) in self.backups.iter().rev() {
+            if let Err(e) = fs::rename(backup, target) {
+                warn!("Rollback could not restore {}: {}", target.display(), e);
+            }
+        }
+        for dir in self.created_dirs.iter().rev() {
+            // Only empty directories go; anything else was put there by someone else meanwhile
+            let _ = fs::remove_dir(dir);
+        }
+        if let Err(e) = fs::remove_dir_all(&self.staging) {
+            warn!("Could not remove staging area {}: {}", self.staging.display(), e);
+        }
+    }
+
+    /// The record is committed; drop the replaced files and the staging area
+    fn finish(self) {
+        if let Err(e) = fs::remove_dir_all(&self.staging) {
+            warn!("Could not remove staging area {}: {}", self.staging.display(), e);
+        }
+    }
+}
+
+/// Unpack the native package at `archive` into `files_dir` and check every file against its manifest
+fn stage_native(files_dir: &Path, archive: &Path) -> Result<StagedPackage, Box<dyn std::error::Error>> {
+    let mut manifest = None;
+    let mut tar = Archive::new(GzDecoder::new(fs::File::open(archive)?));
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.as_os_str() == MANIFEST_ENTRY {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            manifest = Some(json::parse(&content).map_err(|e| format!("{}: {}", MANIFEST_ENTRY, e))?);
+        } else if entry.path()?.as_os_str() != SIGNATURE_ENTRY && !entry.unpack_in(files_dir)? {
+            return Err(format!("{} contains an entry outside the install root", archive.display()).into());
+        }
+    }
+    let manifest = manifest.ok_or_else(|| format!("{} has no {}", archive.display(), MANIFEST_ENTRY))?;
+    let staged = parse_manifest(&manifest)?;
+
+    for (file, source) in staged.package.files.iter().zip(&staged.sources) {
+        let path = files_dir.join(source);
+        if !path.is_file() {
+            return Err(format!("{} lists {} but does not contain it", archive.display(), file.path.display()).into());
+        }
+        if sha256_file(&path)? != file.checksum {
+            return Err(format!("{} is corrupt: checksum mismatch for {}", archive.display(), file.path.display()).into());
+        }
+    }
+    Ok(staged)
+}
+
+/// Format of the package file at `path`, from its first bytes
+pub(crate) fn detect_format(path: &Path) -> Result<PackageFormat, Box<dyn std::error::Error>> {
+    let mut header = [0u8; 8];
+    let len = fs::File::open(path)?.read(&mut header)?;
+    let header = &header[..len];
+    if deb::is_deb(header) {
+        Ok(PackageFormat::Deb)
+    } else if header.starts_with(GZIP_MAGIC) {
+        Ok(PackageFormat::RaeNative)
+    } else {
+        Err(format!("cannot install {}: unrecognized package format (expected a native .raepkg or a .deb)", path.display()).into())
+    }
+}
+
+/// Read just the manifest of the native package at `archive`
+pub(crate) fn read_manifest(archive: &Path) -> Result<JsonValue, Box<dyn std::error::Error>> {
+    let mut tar = Archive::new(GzDecoder::new(fs::File::open(archive)?));
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.as_os_str() == MANIFEST_ENTRY {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            return Ok(json::parse(&content).map_err(|e| format!("{}: {}: {}", archive.display(), MANIFEST_ENTRY, e))?);
+        }
+    }
+    Err(format!("{} has no {}", archive.display(), MANIFEST_ENTRY).into())
+}
+
+/// The package manifest carries name, version and file list in the same shape as a database record
+fn parse_manifest(manifest: &JsonValue) -> Result<StagedPackage, String> {
+    let script = |name: &str| manifest.get("scripts").and_then(|s| s.get(name)).and_then(JsonValue::as_str).map(str::to_string);
+    let package = InstalledPackage::from_json(manifest)?;
+    Ok(StagedPackage {
+        sources: package.files.iter().map(|f| f.path.strip_prefix("/").unwrap_or(&f.path).to_path_buf()).collect(),
+        package,
+        pre_install: script("pre_install"),
+        post_install: script("post_install"),
+    })
+}
+
+/// Where a file relocates to under a prefix: the prefix stands in for /usr, other top-level
+/// directories keep their name
+pub fn relocate(path: &Path) -> PathBuf {
+    let relative = path.strip_prefix("/").unwrap_or(path);
+    Path::new("/").join(relative.strip_prefix("usr").unwrap_or(relative))
+}
+
+/// Install the package at `archive`, native or .deb, into the database's root, all or nothing
+pub(crate) fn install(db: &Database, archive: &Path) -> Result<InstalledPackage, Box<dyn std::error::Error>> {
+    let format = detect_format(archive)?;
+    let mut tx = Transaction::begin(db)?;
+    let result = (|| {
+        let staged = tx.stage(archive, &format)?;
+        let previous = db.get(&staged.package.name)?;
+        tx.run_script("pre-install", &staged.pre_install, &staged.package)?;
+        for (file, source) in staged.package.files.iter().zip(&staged.sources) {
+            tx.place(file, source)?;
+        }
+        tx.run_script("post-install", &staged.post_install, &staged.package)?;
+        db.insert(&staged.package)?;
+        Ok::<_, Box<dyn std::error::Error>>((staged.package, previous))
+    })();
+
+    match result {
+        Ok((package, previous)) => {
+            tx.finish();
+            // Files the previous version had that this one dropped
+            for old in previous.iter().flat_map(|p| &p.files) {
+                if !package.files.iter().any(|f| f.path == old.path) {
+                    if let Err(e) = fs::remove_file(db.host_path(&old.path)) {
+                        warn!("Could not remove obsolete {}: {}", old.path.display(), e);
+                    }
+                }
+            }
+            Ok(package)
+        }
+        Err(e) => {
+            tx.rollback();
+            Err(e)
+        }
+    }
+}
+
+/// Delete the files of an installed package, then its record; directories left empty are
+/// removed up to the database root
+pub(crate) fn remove(db: &Database, name: &str) -> Result<InstalledPackage, Box<dyn std::error::Error>> {
+    let package = db.get(name)?.ok_or_else(|| format!("Package '{}' is not installed", name))?;
+    delete_files(db, &package.files)?;
+    db.remove(name)?;
+    Ok(package)
+}
+
+/// Remove a package another has replaced: its record goes, with every file of it that no other
+/// installed package has since taken over
+pub(crate) fn retire(db: &Database, name: &str) -> Result<InstalledPackage, Box<dyn std::error::Error>> {
+    let package = db.get(name)?.ok_or_else(|| format!("Package '{}' is not installed", name))?;
+    let taken_over: BTreeSet<PathBuf> = db
+        .list()?
+        .into_iter()
+        .filter(|other| other.name != name)
+        .flat_map(|other| other.files.into_iter().map(|f| f.path))
+        .collect();
+    let orphaned: Vec<InstalledFile> = package.files.iter().filter(|f| !taken_over.contains(&f.path)).cloned().collect();
+    delete_files(db, &orphaned)?;
+    db.remove(name)?;
+    Ok(package)
+}
+
+/// Delete installed files, then the directories they leave empty up to the database root
+fn delete_files(db: &Database, files: &[InstalledFile]) -> Result<(), Box<dyn std::error::Error>> {
+    for file in files {
+        let path = db.host_path(&file.path);
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => warn!("{} was already gone", file.path.display()),
+            Err(e) => return Err(format!("could not remove {}: {}", path.display(), e).into()),
+        }
+        let mut dir = path.parent();
+        while let Some(d) = dir.filter(|d| d.starts_with(db.root()) && *d != db.root()) {
+            if fs::remove_dir(d).is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{write_native_archive, FileType, PackageFile, PackageManifest};
+    use std::io::Write;
+
+    /// A fresh install root and a package `tool` with three files
+    fn setup() -> (PathBuf, PathBuf, Database) {
+        let root = std::env::temp_dir().join(format!("raeen-install-{}", Uuid::new_v4()));
+        let src = root.join("src");
+        fs::create_dir_all(&src).unwrap();
+        let files: Vec<PackageFile> = [
+            ("tool", "/usr/bin/tool", FileType::Binary),
+            ("libtool.so", "/usr/lib/libtool.so", FileType::Library),
+            ("tool.toml", "/etc/tool/tool.toml", FileType::Configuration),
+        ]
+        .into_iter()
+        .map(|(name, target, file_type)| {
+            let source_path = src.join(name);
+            fs::write(&source_path, format!("contents of {}", name)).unwrap();
+            PackageFile {
+                checksum: sha256_file(&source_path).unwrap(),
+                size: fs::metadata(&source_path).unwrap().len(),
+                source_path,
+                target_path: PathBuf::from(target),
+                file_type,
+                permissions: 0o644,
+            }
+        })
+        .collect();
+        let manifest = PackageManifest::new("tool".to_string(), &PackageFormat::RaeNative, "x86_64", "raeen");
+        let archive = root.join("tool.raepkg");
+        write_native_archive(&archive, &manifest, &files, 6).unwrap();
+        let db = Database::open(&root.join("sysroot"));
+        (root, archive, db)
+    }
+
+    fn staging_is_empty(db: &Database) -> bool {
+        fs::read_dir(db.host_path(Path::new(STAGING_DIR))).map_or(true, |mut d| d.next().is_none())
+    }
+
+    /// A .deb laid out as dpkg-deb builds it, with `files` as (path, contents)
+    fn write_deb(path: &Path, control: &str, files: &[(&str, &str)]) {
+        let tar = |entries: &[(&str, &str)]| {
+            let mut builder = tar::Builder::new(Vec::new());
+            for (name, contents) in entries {
+                let mut header = tar::Header::new_gnu();
+                header.set_path(name).unwrap();
+                header.set_size(contents.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder.append(&header, contents.as_bytes()).unwrap();
+            }
+            builder.into_inner().unwrap()
+        };
+        let mut data = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        data.write_all(&tar(files)).unwrap();
+        let members = [
+            ("debian-binary", b"2.0\n".to_vec()),
+            ("control.tar", tar(&[("./control", control)])),
+            ("data.tar.gz", data.finish().unwrap()),
+        ];
+        let mut ar = b"!<arch>\n".to_vec();
+        for (name, contents) in members {
+            ar.extend(format!("{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n", name, 0, 0, 0, 100644, contents.len()).into_bytes());
+            ar.extend(&contents);
+            if contents.len() % 2 == 1 {
+                ar.push(b'\n');
+            }
+        }
+        fs::write(path, ar).unwrap();
+    }
+
+    #[test]
+    fn test_successful_install_commits() {
+        let (root, archive, db) = setup();
+        let package = install(&db, &archive).unwrap();
+
+        assert_eq!(db.get("tool").unwrap(), Some(package.clone()));
+        assert_eq!(package.files.len(), 3);
+        assert_eq!(fs::read_to_string(db.host_path(Path::new("/usr/bin/tool"))).unwrap(), "contents of tool");
+        assert!(crate::database::verify(&db, &package).unwrap().is_clean());
+        assert!(staging_is_empty(&db));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_native_and_deb_installs_are_recorded() {
+        let (root, archive, db) = setup();
+        assert_eq!(detect_format(&archive).unwrap(), PackageFormat::RaeNative);
+        install(&db, &archive).unwrap();
+
+        let deb = root.join("hello_2.10-3_amd64.deb");
+        write_deb(
+            &deb,
+            "Package: hello\nVersion: 2.10-3\nArchitecture: amd64\nDescription: greeter\n prints a greeting\n",
+            &[("./usr/bin/hello", "#!/bin/sh\necho hello\n"), ("./usr/share/doc/hello/copyright", "GPL-3+")],
+        );
+        assert_eq!(detect_format(&deb).unwrap(), PackageFormat::Deb);
+        let package = install(&db, &deb).unwrap();
+
+        assert_eq!((package.name.as_str(), package.version.as_str()), ("hello", "2.10-3"));
+        let paths: Vec<&Path> = package.files.iter().map(|f| f.path.as_path()).collect();
+        assert_eq!(paths, [Path::new("/usr/bin/hello"), Path::new("/usr/share/doc/hello/copyright")]);
+        assert_eq!(db.list().unwrap().iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), ["hello", "tool"]);
+        assert_eq!(db.get("hello").unwrap(), Some(package.clone()));
+        assert_eq!(fs::read_to_string(db.host_path(Path::new("/usr/bin/hello"))).unwrap(), "#!/bin/sh\necho hello\n");
+        assert_eq!(fs::read_to_string(db.host_path(Path::new("/usr/bin/tool"))).unwrap(), "contents of tool");
+        assert!(crate::database::verify(&db, &package).unwrap().is_clean());
+        assert!(staging_is_empty(&db));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_unrecognized_format_is_rejected() {
+        let (root, _, db) = setup();
+        let rpm = root.join("hello-2.10-3.x86_64.rpm");
+        fs::write(&rpm, b"\xed\xab\xee\xdb\x03\x00\x00\x00").unwrap();
+
+        let err = install(&db, &rpm).unwrap_err().to_string();
+        assert!(err.contains("unrecognized package format"), "{}", err);
+        assert!(db.list().unwrap().is_empty());
+        assert!(!db.host_path(Path::new(STAGING_DIR)).exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_failure_during_placement_rolls_back() {
+        let (root, archive, db) = setup();
+        // A file the install will replace, and a directory blocking the last file
+        let existing = db.host_path(Path::new("/usr/bin/tool"));
+        fs::create_dir_all(existing.parent().unwrap()).unwrap();
+        fs::write(&existing, "previous tool").unwrap();
+        let blocker = db.host_path(Path::new("/etc/tool/tool.toml"));
+        fs::create_dir_all(blocker.join("keep")).unwrap();
+
+        let err = install(&db, &archive).unwrap_err();
+        assert!(err.to_string().contains("directory is in the way"));
+
+        assert_eq!(db.get("tool").unwrap(), None);
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "previous tool");
+        assert!(!db.host_path(Path::new("/usr/lib/libtool.so")).exists());
+        assert!(!db.host_path(Path::new("/usr/lib")).exists());
+        assert!(blocker.join("keep").is_dir());
+        assert!(staging_is_empty(&db));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_prefix_install_is_relocated_and_self_contained() {
+        let (root, archive, _) = setup();
+        let prefix = root.join("prefix");
+        let db = Database::open_prefix(&prefix);
+
+        let package = install(&db, &archive).unwrap();
+        assert_eq!(package.prefix.as_deref(), Some(prefix.as_path()));
+        assert_eq!(db.get("tool").unwrap().and_then(|p| p.prefix), Some(prefix.clone()));
+        assert_eq!(fs::read_to_string(prefix.join("bin/tool")).unwrap(), "contents of tool");
+        assert!(prefix.join("lib/libtool.so").is_file() && prefix.join("etc/tool/tool.toml").is_file());
+        assert!(!prefix.join("usr").exists() && !root.join("sysroot").exists());
+        assert!(crate::database::verify(&db, &package).unwrap().is_clean());
+
+        remove(&db, "tool").unwrap();
+        assert_eq!(db.get("tool").unwrap(), None);
+        assert!(!prefix.join("bin").exists() && !prefix.join("etc/tool").exists());
+        // Everything outside the prefix is as the test left it
+        let mut outside: Vec<PathBuf> = fs::read_dir(&root).unwrap().map(|e| e.unwrap().path()).filter(|p| *p != prefix).collect();
+        outside.sort();
+        assert_eq!(outside, [root.join("src"), root.join("tool.raepkg")]);
+        assert_eq!(fs::read_dir(root.join("src")).unwrap().count(), 3);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_relocate() {
+        assert_eq!(relocate(Path::new("/usr/bin/tool")), Path::new("/bin/tool"));
+        assert_eq!(relocate(Path::new("/usr/share/doc/tool/README")), Path::new("/share/doc/tool/README"));
+        assert_eq!(relocate(Path::new("/etc/tool/tool.toml")), Path::new("/etc/tool/tool.toml"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failing_script_rolls_back() {
+        let (root, _, db) = setup();
+        let mut manifest = PackageManifest::new("hooked".to_string(), &PackageFormat::RaeNative, "x86_64", "raeen");
+        manifest.scripts.post_install = Some("echo refusing >&2; exit 1".to_string());
+        let source_path = root.join("src/tool");
+        let file = PackageFile {
+            checksum: sha256_file(&source_path).unwrap(),
+            size: fs::metadata(&source_path).unwrap().len(),
+            source_path,
+            target_path: PathBuf::from("/usr/bin/hooked"),
+            file_type: FileType::Binary,
+            permissions: 0o755,
+        };
+        let archive = root.join("hooked.raepkg");
+        write_native_archive(&archive, &manifest, &[file], 6).unwrap();
+
+        let err = install(&db, &archive).unwrap_err();
+        assert!(err.to_string().contains("refusing"));
+        assert_eq!(db.get("hooked").unwrap(), None);
+        assert!(!db.host_path(Path::new("/usr/bin/hooked")).exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_retire_keeps_files_taken_over() {
+        let (root, archive, db) = setup();
+        install(&db, &archive).unwrap();
+        let source_path = root.join("src/newtool");
+        fs::write(&source_path, "contents of newtool").unwrap();
+        let file = PackageFile {
+            checksum: sha256_file(&source_path).unwrap(),
+            size: fs::metadata(&source_path).unwrap().len(),
+            source_path,
+            target_path: PathBuf::from("/usr/bin/tool"),
+            file_type: FileType::Binary,
+            permissions: 0o755,
+        };
+        let mut manifest = PackageManifest::new("newtool".to_string(), &PackageFormat::RaeNative, "x86_64", "raeen");
+        manifest.replaces.push("tool".to_string());
+        let newtool = root.join("newtool.raepkg");
+        write_native_archive(&newtool, &manifest, &[file], 6).unwrap();
+        install(&db, &newtool).unwrap();
+
+        assert_eq!(retire(&db, "tool").unwrap().files.len(), 3);
+        assert_eq!(fs::read_to_string(db.host_path(Path::new("/usr/bin/tool"))).unwrap(), "contents of newtool");
+        assert!(!db.host_path(Path::new("/usr/lib/libtool.so")).exists());
+        assert!(!db.host_path(Path::new("/etc/tool")).exists());
+        assert_eq!(db.list().unwrap().iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), ["newtool"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
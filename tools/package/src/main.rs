@@ -13,6 +13,25 @@ use tar::Builder;
 use flate2::write::GzEncoder;
 use flate2::Compression;
 
+mod android;
+mod appimage;
+mod database;
+mod deb;
+mod flatpak;
+mod install;
+mod repository;
+mod signing;
+mod version;
+mod webapp;
+mod windows;
+use raeen_common::compat::CompatibilityConfig;
+use database::Database;
+use raeen_common::json::JsonValue;
+use repository::{AvailablePackage, Repository};
+
+/// Reverse-DNS prefix for application IDs of RaeenOS packages
+const APP_ID_PREFIX: &str = "dev.raeen";
+
 #[derive(Debug, Clone)] // Serialize, Deserialize temporarily disabled
 struct PackageManifest {
     name: String,
@@ -45,6 +64,91 @@ struct PackageManifest {
     sandbox: SandboxConfig,
 }
 
+impl PackageManifest {
+    /// Manifest with default metadata and a strict sandbox
+    fn new(name: String, format: &PackageFormat, architecture: &str, target_os: &str) -> Self {
+        Self {
+            name,
+            version: "0.1.0".to_string(),
+            description: "A RaeenOS application".to_string(),
+            author: "Unknown".to_string(),
+            license: "MIT".to_string(),
+            homepage: None,
+            repository: None,
+            keywords: Vec::new(),
+            categories: Vec::new(),
+            dependencies: HashMap::new(),
+            build_dependencies: HashMap::new(),
+            runtime_dependencies: HashMap::new(),
+            conflicts: Vec::new(),
+            provides: Vec::new(),
+            replaces: Vec::new(),
+            architecture: architecture.to_string(),
+            target_os: target_os.to_string(),
+            minimum_os_version: "0.1.0".to_string(),
+            package_format: format.clone(),
+            install_size: 0,
+            download_size: 0,
+            checksum: String::new(),
+            signature: None,
+            build_info: BuildInfo {
+                build_date: Utc::now(),
+                build_host: hostname::get().unwrap_or_default().to_string_lossy().to_string(),
+                build_user: std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_default(),
+                compiler_version: get_rust_version(),
+                build_flags: Vec::new(),
+                source_commit: get_git_commit(),
+            },
+            files: Vec::new(),
+            scripts: PackageScripts {
+                pre_install: None,
+                post_install: None,
+                pre_remove: None,
+                post_remove: None,
+                pre_upgrade: None,
+                post_upgrade: None,
+            },
+            permissions: PackagePermissions {
+                required_capabilities: Vec::new(),
+                optional_capabilities: Vec::new(),
+                file_access: Vec::new(),
+                network_access: false,
+                system_access: false,
+                hardware_access: Vec::new(),
+            },
+            sandbox: SandboxConfig {
+                enabled: true,
+                isolation_level: "strict".to_string(),
+                allowed_syscalls: Vec::new(),
+                blocked_syscalls: Vec::new(),
+                file_system_access: Vec::new(),
+                network_restrictions: Vec::new(),
+                resource_limits: HashMap::new(),
+            },
+        }
+    }
+
+    /// `dev.raeen.<name>`, with characters app stores reject in an ID element replaced by underscores
+    fn app_id(&self) -> String {
+        let mut element: String = self.name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect();
+        if element.is_empty() || element.starts_with(|c: char| c.is_ascii_digit()) {
+            element.insert(0, '_');
+        }
+        format!("{}.{}", APP_ID_PREFIX, element)
+    }
+
+    /// File name of the program launched for this package: the first packaged binary,
+    /// falling back to the package name
+    fn main_binary(&self, files: &[PackageFile]) -> String {
+        files
+            .iter()
+            .find(|f| f.file_type == FileType::Binary)
+            .and_then(|f| f.target_path.file_name())
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.name.clone())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct BuildInfo {
     build_date: DateTime<Utc>,
@@ -128,8 +232,27 @@ struct PackageBuilder {
     output_dir: PathBuf,
     temp_dir: PathBuf,
     signing_key: Option<PathBuf>,
+    /// Public key `verify` checks signatures against instead of the install root's trusted keys
+    trusted_key: Option<PathBuf>,
     compression_level: u32,
     verbose: bool,
+    compatibility: CompatibilityConfig,
+    /// Root of the system packages are installed into
+    install_root: PathBuf,
+    /// Relocatable install prefix, holding its own package database
+    prefix: Option<PathBuf>,
+    /// Directory of native packages that dependencies and updates are resolved from
+    repository_dir: PathBuf,
+}
+
+impl PackageBuilder {
+    /// The database installs, removals and verification operate on
+    fn database(&self) -> Database {
+        match &self.prefix {
+            Some(prefix) => Database::open_prefix(prefix),
+            None => Database::open(&self.install_root),
+        }
+    }
 }
 
 fn main() {
@@ -141,7 +264,7 @@ fn main() {
         .about("Package management tool for RaeenOS")
         .arg(Arg::new("command")
             .help("Package command to execute")
-            .value_parser(["build", "install", "remove", "list", "info", "search", "update", "clean", "sign", "verify"])
+            .value_parser(["build", "install", "remove", "list", "info", "search", "update", "hold", "unhold", "clean", "sign", "verify"])
             .required(true)
             .index(1))
         .arg(Arg::new("package")
@@ -171,6 +294,10 @@ fn main() {
             .short('k')
             .long("signing-key")
             .value_name("KEY"))
+        .arg(Arg::new("trusted-key")
+            .help("Public key to verify package signatures against")
+            .long("trusted-key")
+            .value_name("KEY"))
         .arg(Arg::new("compression")
             .help("Compression level (0-9)")
             .short('c')
@@ -194,6 +321,21 @@ fn main() {
             .long("target-os")
             .value_name("OS")
             .default_value("raeen"))
+        .arg(Arg::new("root")
+            .help("Root directory packages are installed into")
+            .long("root")
+            .value_name("DIR")
+            .default_value("/"))
+        .arg(Arg::new("prefix")
+            .help("Install relocated under this directory instead of the root")
+            .long("prefix")
+            .value_name("DIR")
+            .conflicts_with("root"))
+        .arg(Arg::new("repo")
+            .help("Directory of packages to resolve dependencies and updates from")
+            .long("repo")
+            .value_name("DIR")
+            .default_value("packages"))
         .get_matches();
     
     let result = match run_package_command(&matches) {
@@ -220,8 +362,13 @@ fn run_package_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::E
         output_dir: output_dir.clone(),
         temp_dir: temp_dir.clone(),
         signing_key: matches.get_one::<String>("signing-key").map(PathBuf::from),
+        trusted_key: matches.get_one::<String>("trusted-key").map(PathBuf::from),
         compression_level: *matches.get_one::<u32>("compression").unwrap(),
         verbose: matches.get_flag("verbose"),
+        compatibility: CompatibilityConfig::load(&workspace_root),
+        install_root: PathBuf::from(matches.get_one::<String>("root").unwrap()),
+        repository_dir: PathBuf::from(matches.get_one::<String>("repo").unwrap()),
+        prefix: matches.get_one::<String>("prefix").map(PathBuf::from),
     };
     
     // Create directories
@@ -262,6 +409,11 @@ fn run_package_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::E
             search_packages(&builder, query)?
         }
         "update" => update_packages(&builder)?,
+        "hold" | "unhold" => {
+            let package_name = matches.get_one::<String>("package")
+                .ok_or_else(|| format!("Package name required for {} command", command))?;
+            hold_package(&builder, package_name, command == "hold")?
+        }
         "clean" => clean_cache(&builder)?,
         "sign" => {
             let package_path = matches.get_one::<String>("package")
@@ -269,9 +421,14 @@ fn run_package_command(matches: &ArgMatches) -> Result<(), Box<dyn std::error::E
             sign_package(&builder, &PathBuf::from(package_path))?
         }
         "verify" => {
-            let package_path = matches.get_one::<String>("package")
-                .ok_or("Package path required for verify command")?;
-            verify_package(&builder, &PathBuf::from(package_path))?
+            let target = matches.get_one::<String>("package")
+                .ok_or("Package path or installed package name required for verify command")?;
+            // A package file gets its signature checked; anything else names an installed package
+            if Path::new(target).is_file() {
+                verify_package(&builder, &PathBuf::from(target))?
+            } else {
+                verify_installed_package(&builder, target)?
+            }
         }
         _ => return Err(format!("Unknown command: {}", command).into()),
     }
@@ -394,7 +551,6 @@ fn create_manifest_from_cargo_toml(
         .to_string();
     
     Ok(PackageManifest {
-        name,
         version,
         description,
         author,
@@ -409,54 +565,12 @@ fn create_manifest_from_cargo_toml(
             .and_then(|v| v.as_array())
             .map(|arr| arr.iter().filter_map(|v| v.as_str()).map(|s| s.to_string()).collect())
             .unwrap_or_default(),
-        dependencies: HashMap::new(),
-        build_dependencies: HashMap::new(),
-        runtime_dependencies: HashMap::new(),
-        conflicts: Vec::new(),
-        provides: Vec::new(),
-        replaces: Vec::new(),
-        architecture: matches.get_one::<String>("architecture").unwrap().clone(),
-        target_os: matches.get_one::<String>("target-os").unwrap().clone(),
-        minimum_os_version: "0.1.0".to_string(),
-        package_format: format.clone(),
-        install_size: 0,
-        download_size: 0,
-        checksum: String::new(),
-        signature: None,
-        build_info: BuildInfo {
-            build_date: Utc::now(),
-            build_host: hostname::get().unwrap_or_default().to_string_lossy().to_string(),
-            build_user: std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_default(),
-            compiler_version: get_rust_version(),
-            build_flags: Vec::new(),
-            source_commit: get_git_commit(),
-        },
-        files: Vec::new(),
-        scripts: PackageScripts {
-            pre_install: None,
-            post_install: None,
-            pre_remove: None,
-            post_remove: None,
-            pre_upgrade: None,
-            post_upgrade: None,
-        },
-        permissions: PackagePermissions {
-            required_capabilities: Vec::new(),
-            optional_capabilities: Vec::new(),
-            file_access: Vec::new(),
-            network_access: false,
-            system_access: false,
-            hardware_access: Vec::new(),
-        },
-        sandbox: SandboxConfig {
-            enabled: true,
-            isolation_level: "strict".to_string(),
-            allowed_syscalls: Vec::new(),
-            blocked_syscalls: Vec::new(),
-            file_system_access: Vec::new(),
-            network_restrictions: Vec::new(),
-            resource_limits: HashMap::new(),
-        },
+        ..PackageManifest::new(
+            name,
+            format,
+            matches.get_one::<String>("architecture").unwrap(),
+            matches.get_one::<String>("target-os").unwrap(),
+        )
     })
 }
 
@@ -470,65 +584,12 @@ fn create_default_manifest(
         .unwrap_or("unknown-package")
         .to_string();
     
-    Ok(PackageManifest {
+    Ok(PackageManifest::new(
         name,
-        version: "0.1.0".to_string(),
-        description: "A RaeenOS application".to_string(),
-        author: "Unknown".to_string(),
-        license: "MIT".to_string(),
-        homepage: None,
-        repository: None,
-        keywords: Vec::new(),
-        categories: Vec::new(),
-        dependencies: HashMap::new(),
-        build_dependencies: HashMap::new(),
-        runtime_dependencies: HashMap::new(),
-        conflicts: Vec::new(),
-        provides: Vec::new(),
-        replaces: Vec::new(),
-        architecture: matches.get_one::<String>("architecture").unwrap().clone(),
-        target_os: matches.get_one::<String>("target-os").unwrap().clone(),
-        minimum_os_version: "0.1.0".to_string(),
-        package_format: format.clone(),
-        install_size: 0,
-        download_size: 0,
-        checksum: String::new(),
-        signature: None,
-        build_info: BuildInfo {
-            build_date: Utc::now(),
-            build_host: hostname::get().unwrap_or_default().to_string_lossy().to_string(),
-            build_user: std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_default(),
-            compiler_version: get_rust_version(),
-            build_flags: Vec::new(),
-            source_commit: get_git_commit(),
-        },
-        files: Vec::new(),
-        scripts: PackageScripts {
-            pre_install: None,
-            post_install: None,
-            pre_remove: None,
-            post_remove: None,
-            pre_upgrade: None,
-            post_upgrade: None,
-        },
-        permissions: PackagePermissions {
-            required_capabilities: Vec::new(),
-            optional_capabilities: Vec::new(),
-            file_access: Vec::new(),
-            network_access: false,
-            system_access: false,
-            hardware_access: Vec::new(),
-        },
-        sandbox: SandboxConfig {
-            enabled: true,
-            isolation_level: "strict".to_string(),
-            allowed_syscalls: Vec::new(),
-            blocked_syscalls: Vec::new(),
-            file_system_access: Vec::new(),
-            network_restrictions: Vec::new(),
-            resource_limits: HashMap::new(),
-        },
-    })
+        format,
+        matches.get_one::<String>("architecture").unwrap(),
+        matches.get_one::<String>("target-os").unwrap(),
+    ))
 }
 
 fn build_project(builder: &PackageBuilder, package_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
@@ -605,6 +666,37 @@ fn collect_package_files(
         }
     }
     
+    // Collect application icons
+    let assets_dir = package_path.join("assets");
+    if assets_dir.exists() {
+        for entry in WalkDir::new(&assets_dir).min_depth(1).max_depth(1) {
+            let entry = entry?;
+            let path = entry.path();
+            
+            if path.is_file() && path.extension().map_or(false, |ext| ext == "png" || ext == "svg") {
+                let target_path = PathBuf::from("/usr/share/pixmaps").join(path.file_name().unwrap());
+                let file_info = create_package_file(path, &target_path, FileType::Icon)?;
+                files.push(file_info);
+            }
+        }
+    }
+    
+    // Collect web app assets
+    let web_dir = package_path.join("web");
+    if web_dir.exists() {
+        for entry in WalkDir::new(&web_dir) {
+            let entry = entry?;
+            let path = entry.path();
+            
+            if path.is_file() {
+                let relative_path = path.strip_prefix(&web_dir)?;
+                let target_path = PathBuf::from(webapp::WEB_ROOT).join(&manifest.name).join(relative_path);
+                let file_info = create_package_file(path, &target_path, FileType::Data)?;
+                files.push(file_info);
+            }
+        }
+    }
+    
     // Collect configuration files
     let config_dir = package_path.join("config");
     if config_dir.exists() {
@@ -628,11 +720,7 @@ fn create_package_file(source_path: &Path, target_path: &Path, file_type: FileTy
     let metadata = fs::metadata(source_path)?;
     let size = metadata.len();
     
-    // Calculate checksum
-    let content = fs::read(source_path)?;
-    let mut hasher = Sha256::new();
-    hasher.update(&content);
-    let checksum = format!("{:x}", hasher.finalize());
+    let checksum = sha256_file(source_path)?;
     
     // Get permissions (Unix-style)
     #[cfg(unix)]
@@ -654,6 +742,13 @@ fn create_package_file(source_path: &Path, target_path: &Path, file_type: FileTy
     })
 }
 
+/// Hex SHA-256 of a file's contents
+fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(fs::read(path)?);
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
 fn is_executable(path: &Path) -> bool {
     #[cfg(unix)]
     {
@@ -680,15 +775,25 @@ fn create_native_package(
     
     let package_name = format!("{}-{}-{}.raepkg", manifest.name, manifest.version, manifest.architecture);
     let package_path = builder.output_dir.join(&package_name);
+    write_native_archive(&package_path, manifest, files, builder.compression_level)?;
     
-    // Create package archive
-    let tar_gz = fs::File::create(&package_path)?;
-    let enc = GzEncoder::new(tar_gz, Compression::new(builder.compression_level));
+    info!("Native package created: {}", package_path.display());
+    Ok(())
+}
+
+/// Write a .raepkg: manifest.json followed by the files at their install paths, relative to /
+fn write_native_archive(
+    package_path: &Path,
+    manifest: &PackageManifest,
+    files: &[PackageFile],
+    compression_level: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let tar_gz = fs::File::create(package_path)?;
+    let enc = GzEncoder::new(tar_gz, Compression::new(compression_level));
     let mut tar = Builder::new(enc);
     
     // Add manifest
-    // let manifest_json = serde_json::to_string_pretty(manifest)?; // Temporarily disabled due to serde dependency conflicts
-    let manifest_json = format!("{{\"name\": \"{}\", \"version\": \"{}\", \"description\": \"{}\"}}", manifest.name, manifest.version, manifest.description);
+    let manifest_json = native_manifest_json(manifest, files).to_string();
     let mut header = tar::Header::new_gnu();
     header.set_path("manifest.json")?;
     header.set_size(manifest_json.len() as u64);
@@ -699,7 +804,7 @@ fn create_native_package(
     // Add files
     for file in files {
         let mut header = tar::Header::new_gnu();
-        header.set_path(&file.target_path)?;
+        header.set_path(file.target_path.strip_prefix("/").unwrap_or(&file.target_path))?;
         header.set_size(file.size);
         header.set_mode(file.permissions);
         header.set_cksum();
@@ -708,12 +813,48 @@ fn create_native_package(
         tar.append(&header, &mut file_content)?;
     }
     
-    tar.finish()?;
-    
-    info!("Native package created: {}", package_path.display());
+    tar.into_inner()?.finish()?;
     Ok(())
 }
 
+/// manifest.json of a native package; the file list is what the installed database records
+/// and what `verify` later checks against
+fn native_manifest_json(manifest: &PackageManifest, files: &[PackageFile]) -> JsonValue {
+    let files = files
+        .iter()
+        .map(|f| {
+            JsonValue::object(vec![
+                ("path", JsonValue::String(f.target_path.display().to_string())),
+                ("checksum", JsonValue::String(f.checksum.clone())),
+                ("size", JsonValue::Number(f.size as f64)),
+                ("mode", JsonValue::Number(f64::from(f.permissions & 0o7777))),
+            ])
+        })
+        .collect();
+    let requirements = |map: &HashMap<String, String>| {
+        let mut requirements: Vec<(&String, &String)> = map.iter().collect();
+        requirements.sort();
+        JsonValue::object(requirements.into_iter().map(|(name, req)| (name.as_str(), JsonValue::String(req.clone()))).collect())
+    };
+    let scripts = [("pre_install", &manifest.scripts.pre_install), ("post_install", &manifest.scripts.post_install)]
+        .into_iter()
+        .filter_map(|(name, script)| script.as_ref().map(|s| (name, JsonValue::String(s.clone()))))
+        .collect();
+    JsonValue::object(vec![
+        ("name", JsonValue::String(manifest.name.clone())),
+        ("version", JsonValue::String(manifest.version.clone())),
+        ("description", JsonValue::String(manifest.description.clone())),
+        ("architecture", JsonValue::String(manifest.architecture.clone())),
+        ("dependencies", requirements(&manifest.dependencies)),
+        ("runtime_dependencies", requirements(&manifest.runtime_dependencies)),
+        ("provides", JsonValue::string_array(&manifest.provides)),
+        ("conflicts", JsonValue::string_array(&manifest.conflicts)),
+        ("replaces", JsonValue::string_array(&manifest.replaces)),
+        ("files", JsonValue::Array(files)),
+        ("scripts", JsonValue::object(scripts)),
+    ])
+}
+
 // Placeholder implementations for other package formats
 fn create_deb_package(builder: &PackageBuilder, manifest: &PackageManifest, files: &[PackageFile]) -> Result<(), Box<dyn std::error::Error>> {
     warn!("Debian package creation not yet implemented");
@@ -726,7 +867,11 @@ fn create_rpm_package(builder: &PackageBuilder, manifest: &PackageManifest, file
 }
 
 fn create_flatpak_package(builder: &PackageBuilder, manifest: &PackageManifest, files: &[PackageFile]) -> Result<(), Box<dyn std::error::Error>> {
-    warn!("Flatpak package creation not yet implemented");
+    info!("Creating Flatpak package...");
+    
+    let bundle = flatpak::export(builder, manifest, files)?;
+    
+    info!("Flatpak package created: {}", bundle.display());
     Ok(())
 }
 
@@ -736,35 +881,76 @@ fn create_snap_package(builder: &PackageBuilder, manifest: &PackageManifest, fil
 }
 
 fn create_appimage_package(builder: &PackageBuilder, manifest: &PackageManifest, files: &[PackageFile]) -> Result<(), Box<dyn std::error::Error>> {
-    warn!("AppImage package creation not yet implemented");
+    info!("Creating AppImage package...");
+    
+    let image = appimage::export(builder, manifest, files)?;
+    
+    info!("AppImage package created: {}", image.display());
     Ok(())
 }
 
 fn create_windows_package(builder: &PackageBuilder, manifest: &PackageManifest, files: &[PackageFile]) -> Result<(), Box<dyn std::error::Error>> {
-    warn!("Windows package creation not yet implemented");
+    info!("Creating Windows installer...");
+    
+    let installer = windows::export(builder, manifest, files)?;
+    
+    info!("Windows installer created: {}", installer.display());
     Ok(())
 }
 
 fn create_android_package(builder: &PackageBuilder, manifest: &PackageManifest, files: &[PackageFile]) -> Result<(), Box<dyn std::error::Error>> {
-    warn!("Android package creation not yet implemented");
+    info!("Creating Android package...");
+    
+    let apk = android::export(builder, manifest, files)?;
+    
+    info!("Unsigned APK created: {} (sign it with apksigner before installing)", apk.display());
     Ok(())
 }
 
 fn create_web_package(builder: &PackageBuilder, manifest: &PackageManifest, files: &[PackageFile]) -> Result<(), Box<dyn std::error::Error>> {
-    warn!("Web app package creation not yet implemented");
+    info!("Creating web app package...");
+    
+    let bundle = webapp::export(builder, manifest, files)?;
+    
+    info!("Web app package created: {}", bundle.display());
     Ok(())
 }
 
-// Placeholder implementations for package management operations
 fn install_package(builder: &PackageBuilder, package_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
     info!("Installing package: {}", package_path.display());
-    warn!("Package installation not yet implemented");
+    
+    let db = builder.database();
+    let mut replaced = Vec::new();
+    if install::detect_format(package_path)? == PackageFormat::RaeNative {
+        let manifest = install::read_manifest(package_path)?;
+        let requested = AvailablePackage::from_manifest(&manifest, package_path)?;
+        let repo = Repository::scan(&builder.repository_dir)?;
+        let plan = repo.plan_install(&requested, &db.list()?, &db.holds()?)?;
+        
+        for dependency in &plan.dependencies {
+            info!("Installing dependency {} {}", dependency.name, dependency.version);
+            install::install(&db, &dependency.path)?;
+        }
+        replaced = plan.replaced;
+    }
+    let package = install::install(&db, package_path)?;
+    
+    for name in &replaced {
+        let old = install::retire(&db, name)?;
+        info!("Replaced {} {}", old.name, old.version);
+    }
+    info!("Installed {} {} ({} files)", package.name, package.version, package.files.len());
     Ok(())
 }
 
+// Placeholder implementations for package management operations
+
 fn remove_package(builder: &PackageBuilder, package_name: &str) -> Result<(), Box<dyn std::error::Error>> {
     info!("Removing package: {}", package_name);
-    warn!("Package removal not yet implemented");
+    
+    let package = install::remove(&builder.database(), package_name)?;
+    
+    info!("Removed {} {} ({} files)", package.name, package.version, package.files.len());
     Ok(())
 }
 
@@ -788,7 +974,33 @@ fn search_packages(builder: &PackageBuilder, query: &str) -> Result<(), Box<dyn
 
 fn update_packages(builder: &PackageBuilder) -> Result<(), Box<dyn std::error::Error>> {
     info!("Updating packages...");
-    warn!("Package update not yet implemented");
+    
+    let db = builder.database();
+    let repo = Repository::scan(&builder.repository_dir)?;
+    let plan = repository::plan_update(&repo, &db.list()?, &db.holds()?)?;
+    
+    for (name, installed, available) in &plan.held {
+        info!("Skipping held package {} {} ({} available; `raeen-pkg unhold {}` to allow)", name, installed, available, name);
+    }
+    for package in &plan.installs {
+        info!("Upgrading {} to {}", package.name, package.version);
+        install::install(&db, &package.path)?;
+    }
+    
+    if plan.installs.is_empty() {
+        info!("All packages are up to date");
+    }
+    Ok(())
+}
+
+fn hold_package(builder: &PackageBuilder, package_name: &str, held: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let db = builder.database();
+    if held && db.get(package_name)?.is_none() {
+        return Err(format!("Package '{}' is not installed", package_name).into());
+    }
+    db.set_held(package_name, held)?;
+    
+    info!("{} {}", if held { "Held" } else { "Released hold on" }, package_name);
     Ok(())
 }
 
@@ -800,13 +1012,52 @@ fn clean_cache(builder: &PackageBuilder) -> Result<(), Box<dyn std::error::Error
 
 fn sign_package(builder: &PackageBuilder, package_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
     info!("Signing package: {}", package_path.display());
-    warn!("Package signing not yet implemented");
+    
+    let key_path = builder.signing_key.as_ref().ok_or("Signing key required for sign command (--signing-key)")?;
+    let key = signing::load_signing_key(key_path)?;
+    let checksum = signing::sign(package_path, &key, builder.compression_level)?;
+    
+    info!("Signed {} (sha256 {}) with public key {}", package_path.display(), checksum, signing::public_key_hex(&key));
     Ok(())
 }
 
 fn verify_package(builder: &PackageBuilder, package_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
     info!("Verifying package: {}", package_path.display());
-    warn!("Package verification not yet implemented");
+    
+    let trusted = match &builder.trusted_key {
+        Some(path) => vec![signing::load_public_key(path)?],
+        None => {
+            let dir = builder.install_root.join(signing::TRUSTED_KEYS_DIR);
+            let keys = signing::load_trusted_keys(&dir)?;
+            if keys.is_empty() {
+                return Err(format!("No trusted keys in {}; pass --trusted-key", dir.display()).into());
+            }
+            keys
+        }
+    };
+    let checksum = signing::verify(package_path, &trusted)?;
+    
+    info!("{}: signature good (sha256 {})", package_path.display(), checksum);
+    Ok(())
+}
+
+fn verify_installed_package(builder: &PackageBuilder, package_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    info!("Verifying installed files of: {}", package_name);
+    
+    let db = builder.database();
+    let package = db.get(package_name)?
+        .ok_or_else(|| format!("Package '{}' is not installed", package_name))?;
+    let report = database::verify(&db, &package)?;
+    
+    if !report.is_clean() {
+        print!("{}", report.render());
+        return Err(format!(
+            "{} failed verification: {} missing, {} modified, {} extra",
+            package_name, report.missing.len(), report.modified.len(), report.extra.len()
+        ).into());
+    }
+    
+    info!("{} {}: all {} files intact", package.name, package.version, package.files.len());
     Ok(())
 }
 
@@ -0,0 +1,630 @@
+//! Available packages and dependency resolution
+//! A repository is a directory of native packages. The resolver walks the dependency graph
+//! depth-first, so every package comes after what it needs, picking for each dependency the
+//! newest available version satisfying every constraint on it, unless the installed version
+//! already does. When two dependents disagree about a version already chosen, resolution starts
+//! over with both constraints in hand. A package may also stand in for another name it
+//! `provides`, refuse to coexist with what it `conflicts` with, and take over what it
+//! `replaces`. `update` upgrades every installed package except held ones.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+use crate::database::InstalledPackage;
+use crate::install;
+use raeen_common::json::JsonValue;
+use crate::version::{Version, VersionReq};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AvailablePackage {
+    pub name: String,
+    pub version: Version,
+    pub path: PathBuf,
+    /// Runtime dependencies, from both `dependencies` and `runtime_dependencies`
+    pub dependencies: Vec<(String, VersionReq)>,
+    /// Other names this package answers to, at the given version or else its own
+    pub provides: Vec<(String, Option<Version>)>,
+    pub conflicts: Vec<(String, VersionReq)>,
+    pub replaces: Vec<(String, VersionReq)>,
+}
+
+/// Split a relation such as `libfoo >=1.2` or `libfoo` into the name and its constraint text
+fn split_relation(relation: &str) -> (&str, &str) {
+    let relation = relation.trim();
+    let end = relation.find(|c: char| c.is_whitespace() || "=<>^~".contains(c)).unwrap_or(relation.len());
+    (&relation[..end], relation[end..].trim())
+}
+
+/// `libfoo >=1.2` as a name and constraint; a bare name matches every version
+fn parse_relation(relation: &str) -> Result<(String, VersionReq), String> {
+    let (name, req) = split_relation(relation);
+    if name.is_empty() {
+        return Err(format!("invalid package relation '{}'", relation));
+    }
+    let req = if req.is_empty() { VersionReq::any() } else { req.parse().map_err(|e| format!("'{}': {}", relation, e))? };
+    Ok((name.to_string(), req))
+}
+
+/// `libfoo` or `libfoo =1.2`
+fn parse_provided(relation: &str) -> Result<(String, Option<Version>), String> {
+    let (name, version) = split_relation(relation);
+    let version = match version.strip_prefix('=') {
+        Some(version) => Some(version.parse()?),
+        None if version.is_empty() => None,
+        None => return Err(format!("invalid provided name '{}' (expected 'name' or 'name =version')", relation)),
+    };
+    if name.is_empty() {
+        return Err(format!("invalid provided name '{}' (expected 'name' or 'name =version')", relation));
+    }
+    Ok((name.to_string(), version))
+}
+
+impl AvailablePackage {
+    /// Read name, version and package relations from a native package manifest
+    pub fn from_manifest(manifest: &JsonValue, path: &Path) -> Result<Self, String> {
+        let string = |key: &str| manifest.field(key)?.as_str().ok_or_else(|| format!("field '{}' is not a string", key));
+        let requirements = |key: &str| match manifest.get(key) {
+            Some(JsonValue::Object(fields)) => fields
+                .iter()
+                .map(|(name, req)| {
+                    let req = req.as_str().ok_or_else(|| format!("dependency '{}' has no version constraint", name))?;
+                    Ok((name.clone(), req.parse().map_err(|e| format!("dependency '{}': {}", name, e))?))
+                })
+                .collect::<Result<Vec<_>, String>>(),
+            Some(_) => Err(format!("field '{}' is not an object", key)),
+            None => Ok(Vec::new()),
+        };
+        let relations = |key: &str| match manifest.get(key) {
+            Some(_) => manifest.string_list(key),
+            None => Ok(Vec::new()),
+        };
+        let mut dependencies = requirements("dependencies")?;
+        dependencies.extend(requirements("runtime_dependencies")?);
+        Ok(Self {
+            name: string("name")?.to_string(),
+            version: string("version")?.parse()?,
+            path: path.to_path_buf(),
+            dependencies,
+            provides: relations("provides")?.iter().map(|r| parse_provided(r)).collect::<Result<_, _>>()?,
+            conflicts: relations("conflicts")?.iter().map(|r| parse_relation(r)).collect::<Result<_, _>>()?,
+            replaces: relations("replaces")?.iter().map(|r| parse_relation(r)).collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// `name version`, as errors show it
+    fn label(&self) -> String {
+        format!("{} {}", self.name, self.version)
+    }
+
+    /// The version this package answers to `name` with: its own, or one it provides
+    fn offers(&self, name: &str) -> Option<Version> {
+        if self.name == name {
+            return Some(self.version);
+        }
+        self.provides.iter().find(|(provided, _)| provided == name).map(|(_, version)| version.unwrap_or(self.version))
+    }
+
+    fn satisfies(&self, name: &str, req: &VersionReq) -> bool {
+        self.offers(name).map_or(false, |version| req.matches(&version))
+    }
+}
+
+/// A constraint on a package together with the chain of dependents that imposed it
+#[derive(Debug, Clone, PartialEq)]
+struct Demand {
+    chain: String,
+    req: VersionReq,
+}
+
+/// Why a resolution pass stopped
+enum Failure {
+    /// A version was chosen before a later constraint ruled it out; try again honouring these
+    Retry(String, Vec<Demand>),
+    Unsatisfiable(String),
+}
+
+/// One depth-first pass over the dependency graph
+struct Resolution<'a, 'r> {
+    repo: &'a Repository,
+    installed: &'r [InstalledPackage],
+    holds: &'r BTreeSet<String>,
+    /// Constraints learnt in earlier passes, by package name
+    pinned: &'r BTreeMap<String, Vec<Demand>>,
+    /// Constraints met so far in this pass
+    demands: BTreeMap<String, Vec<Demand>>,
+    /// The packages being visited, outermost first, as (name, `name version`)
+    visiting: Vec<(String, String)>,
+    /// The package picked for each dependency name
+    chosen: BTreeMap<String, &'a AvailablePackage>,
+    order: Vec<&'a AvailablePackage>,
+}
+
+impl<'a, 'r> Resolution<'a, 'r> {
+    fn chain(&self) -> String {
+        self.visiting.iter().map(|(_, label)| label.as_str()).collect::<Vec<_>>().join(" -> ")
+    }
+
+    /// Every constraint on `name` known so far
+    fn all_demands(&self, name: &str) -> Vec<Demand> {
+        let mut all = self.pinned.get(name).cloned().unwrap_or_default();
+        for demand in self.demands.get(name).into_iter().flatten() {
+            if !all.contains(demand) {
+                all.push(demand.clone());
+            }
+        }
+        all
+    }
+
+    /// The version of `name` the installed system answers with
+    fn installed_version(&self, name: &str) -> Option<(&'r InstalledPackage, Version)> {
+        self.installed.iter().find_map(|package| {
+            let version = if package.name == name {
+                package.version.parse().ok()?
+            } else {
+                self.repo.find_installed(package)?.offers(name)?
+            };
+            Some((package, version))
+        })
+    }
+
+    /// No version of `name` meets all of `demands`
+    fn unsatisfiable(&self, name: &str, demands: &[Demand]) -> Failure {
+        let available: Vec<String> = self.repo.offered_versions(name).iter().map(Version::to_string).collect();
+        if let [demand] = demands {
+            let reason = match available.is_empty() {
+                true => format!("no package named '{}' is available", name),
+                false => format!("no version of '{}' satisfies {} (available: {})", name, demand.req, available.join(", ")),
+            };
+            return Failure::Unsatisfiable(format!("{} requires {} {}: {}", demand.chain, name, demand.req, reason));
+        }
+        let mut message = format!("conflicting requirements on '{}' (available: {}):", name, available.join(", "));
+        for demand in demands {
+            message.push_str(&format!("\n  {} requires {} {}", demand.chain, name, demand.req));
+        }
+        Failure::Unsatisfiable(message)
+    }
+
+    fn visit(&mut self, package: &AvailablePackage) -> Result<(), Failure> {
+        self.visiting.push((package.name.clone(), package.label()));
+        for (name, req) in &package.dependencies {
+            let demand = Demand { chain: self.chain(), req: req.clone() };
+            let seen = self.demands.entry(name.clone()).or_default();
+            if !seen.contains(&demand) {
+                seen.push(demand);
+            }
+
+            if let Some(planned) = self.chosen.get(name) {
+                if planned.satisfies(name, req) {
+                    continue;
+                }
+                // Chosen for an earlier dependent, but this one needs another version
+                let all = self.all_demands(name);
+                let reqs: Vec<&VersionReq> = all.iter().map(|d| &d.req).collect();
+                return Err(match self.repo.newest_offering(name, &reqs) {
+                    Some(_) => Failure::Retry(name.clone(), all),
+                    None => self.unsatisfiable(name, &all),
+                });
+            }
+            if self.order.iter().any(|p| p.satisfies(name, req)) {
+                continue;
+            }
+            let current = self.installed_version(name);
+            if current.map_or(false, |(_, version)| req.matches(&version)) {
+                continue;
+            }
+            if let Some((current, _)) = current.filter(|(p, _)| self.holds.contains(&p.name)) {
+                return Err(Failure::Unsatisfiable(format!(
+                    "{} requires {} {}, but {} is held at {}",
+                    self.chain(), name, req, current.name, current.version
+                )));
+            }
+            if self.visiting.iter().any(|(visiting, _)| visiting == name) {
+                return Err(Failure::Unsatisfiable(format!("dependency cycle: {} -> {}", self.chain(), name)));
+            }
+
+            let all = self.all_demands(name);
+            let reqs: Vec<&VersionReq> = all.iter().map(|d| &d.req).collect();
+            let chosen = self.repo.newest_offering(name, &reqs).ok_or_else(|| self.unsatisfiable(name, &all))?;
+            self.visit(chosen)?;
+            self.chosen.insert(name.clone(), chosen);
+            self.order.push(chosen);
+        }
+        self.visiting.pop();
+        Ok(())
+    }
+}
+
+/// What installing a package involves
+#[derive(Debug, Default)]
+pub struct InstallPlan<'a> {
+    /// Packages to install first, each after its own dependencies
+    pub dependencies: Vec<&'a AvailablePackage>,
+    /// Installed packages that the new ones replace, to retire once they are in place
+    pub replaced: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Repository {
+    packages: Vec<AvailablePackage>,
+}
+
+impl Repository {
+    pub fn new(packages: Vec<AvailablePackage>) -> Self {
+        Self { packages }
+    }
+
+    /// Every `.raepkg` in `dir`; unreadable packages are skipped with a warning
+    pub fn scan(dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut packages = Vec::new();
+        if !dir.exists() {
+            return Ok(Self::default());
+        }
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().map_or(true, |ext| ext != "raepkg") {
+                continue;
+            }
+            match install::read_manifest(&path).and_then(|m| Ok(AvailablePackage::from_manifest(&m, &path)?)) {
+                Ok(package) => packages.push(package),
+                Err(e) => warn!("Skipping {}: {}", path.display(), e),
+            }
+        }
+        Ok(Self { packages })
+    }
+
+    /// The newest version of `name` satisfying every one of `reqs`
+    fn newest(&self, name: &str, reqs: &[&VersionReq]) -> Option<&AvailablePackage> {
+        self.packages
+            .iter()
+            .filter(|p| p.name == name && reqs.iter().all(|r| r.matches(&p.version)))
+            .max_by_key(|p| p.version)
+    }
+
+    /// The versions `name` is available in, by that name or provided by another package
+    fn offered_versions(&self, name: &str) -> Vec<Version> {
+        self.packages.iter().filter_map(|p| p.offers(name)).collect()
+    }
+
+    /// The newest package called `name` satisfying every one of `reqs`, or failing that the
+    /// newest that provides it
+    fn newest_offering(&self, name: &str, reqs: &[&VersionReq]) -> Option<&AvailablePackage> {
+        self.newest(name, reqs).or_else(|| {
+            self.packages
+                .iter()
+                .filter_map(|p| p.offers(name).map(|version| (p, version)))
+                .filter(|(_, version)| reqs.iter().all(|r| r.matches(version)))
+                .max_by_key(|(_, version)| *version)
+                .map(|(p, _)| p)
+        })
+    }
+
+    /// The newest version of `name` satisfying `req`
+    pub fn resolve(&self, name: &str, req: &VersionReq) -> Result<&AvailablePackage, String> {
+        let versions: Vec<String> = self.packages.iter().filter(|p| p.name == name).map(|p| p.version.to_string()).collect();
+        if versions.is_empty() {
+            return Err(format!("no package named '{}' is available", name));
+        }
+        self.newest(name, &[req])
+            .ok_or_else(|| format!("no version of '{}' satisfies {} (available: {})", name, req, versions.join(", ")))
+    }
+
+    /// The repository entry for an installed package, if the repository still carries it
+    fn find_installed(&self, package: &InstalledPackage) -> Option<&AvailablePackage> {
+        let version: Version = package.version.parse().ok()?;
+        self.packages.iter().find(|p| p.name == package.name && p.version == version)
+    }
+
+    /// Packages to install before `package`, each after its own dependencies, skipping those
+    /// whose installed version already satisfies the constraint. Fails on a dependency cycle
+    /// or when no version satisfies every constraint, naming the chain of dependents behind
+    /// each constraint
+    pub fn resolve_dependencies<'a>(
+        &'a self,
+        package: &AvailablePackage,
+        installed: &[InstalledPackage],
+        holds: &BTreeSet<String>,
+    ) -> Result<Vec<&'a AvailablePackage>, String> {
+        let mut pinned: BTreeMap<String, Vec<Demand>> = BTreeMap::new();
+        loop {
+            let mut resolution = Resolution {
+                repo: self,
+                installed,
+                holds,
+                pinned: &pinned,
+                demands: BTreeMap::new(),
+                visiting: Vec::new(),
+                chosen: BTreeMap::new(),
+                order: Vec::new(),
+            };
+            match resolution.visit(package) {
+                Ok(()) => return Ok(resolution.order),
+                // Each retry adds a constraint the previous pass broke, so this ends
+                Err(Failure::Retry(name, demands)) => {
+                    pinned.insert(name, demands);
+                }
+                Err(Failure::Unsatisfiable(message)) => return Err(message),
+            }
+        }
+    }
+
+    /// Dependencies to install before `package` and installed packages it and they replace.
+    /// Fails if any of them conflicts with another or with an installed package it does not
+    /// replace
+    pub fn plan_install<'a>(
+        &'a self,
+        package: &AvailablePackage,
+        installed: &[InstalledPackage],
+        holds: &BTreeSet<String>,
+    ) -> Result<InstallPlan<'a>, String> {
+        let dependencies = self.resolve_dependencies(package, installed, holds)?;
+        let incoming: Vec<&AvailablePackage> = dependencies.iter().copied().chain([package]).collect();
+        // Installed packages that stay, with what the repository knows of their relations
+        let remaining: Vec<(&InstalledPackage, Option<&AvailablePackage>)> = installed
+            .iter()
+            .filter(|i| !incoming.iter().any(|p| p.name == i.name))
+            .map(|i| (i, self.find_installed(i)))
+            .collect();
+
+        let mut replaced = Vec::new();
+        for new in &incoming {
+            for other in &incoming {
+                if other.name != new.name && new.conflicts.iter().any(|(name, req)| other.satisfies(name, req)) {
+                    return Err(format!("{} conflicts with {}, which is also being installed", new.label(), other.label()));
+                }
+            }
+            for (current, entry) in &remaining {
+                let version: Option<Version> = current.version.parse().ok();
+                let matches = |(name, req): &(String, VersionReq)| {
+                    (*name == current.name && version.map_or(true, |v| req.matches(&v)))
+                        || entry.map_or(false, |e| e.satisfies(name, req))
+                };
+                let conflict = new.conflicts.iter().any(matches)
+                    || entry.map_or(false, |e| e.conflicts.iter().any(|(name, req)| new.satisfies(name, req)));
+                if !new.replaces.iter().any(matches) {
+                    if conflict {
+                        return Err(format!("{} conflicts with installed {} {}", new.label(), current.name, current.version));
+                    }
+                    continue;
+                }
+                if holds.contains(&current.name) {
+                    return Err(format!("{} replaces {}, which is held at {}", new.label(), current.name, current.version));
+                }
+                if !replaced.contains(&current.name) {
+                    replaced.push(current.name.clone());
+                }
+            }
+        }
+        Ok(InstallPlan { dependencies, replaced })
+    }
+}
+
+/// What `update` will do
+#[derive(Debug, Default)]
+pub struct UpdatePlan<'a> {
+    /// Packages to install, in order
+    pub installs: Vec<&'a AvailablePackage>,
+    /// Held packages with a newer version available, as (name, installed, available)
+    pub held: Vec<(String, String, Version)>,
+}
+
+fn is_newer(candidate: &AvailablePackage, installed: &InstalledPackage) -> bool {
+    installed.version.parse::<Version>().map_or(true, |current| candidate.version > current)
+}
+
+/// Upgrade each installed package to the newest version that the other packages' constraints
+/// still accept, together with whatever that version's dependencies need; held packages stay put
+pub fn plan_update<'a>(
+    repo: &'a Repository,
+    installed: &[InstalledPackage],
+    holds: &BTreeSet<String>,
+) -> Result<UpdatePlan<'a>, String> {
+    let mut plan = UpdatePlan::default();
+    let mut latest: Vec<(&InstalledPackage, &AvailablePackage)> = Vec::new();
+    for package in installed {
+        let Ok(candidate) = repo.resolve(&package.name, &VersionReq::any()) else { continue };
+        if !is_newer(candidate, package) {
+            continue;
+        }
+        if holds.contains(&package.name) {
+            plan.held.push((package.name.clone(), package.version.clone(), candidate.version));
+        } else {
+            latest.push((package, candidate));
+        }
+    }
+
+    // What each package will be after the update, as far as its dependencies are concerned
+    let after_update = |package: &InstalledPackage| {
+        latest.iter().find(|(p, _)| p.name == package.name).map(|(_, c)| *c).or_else(|| repo.find_installed(package))
+    };
+    for (package, _) in &latest {
+        let reqs: Vec<&VersionReq> = installed
+            .iter()
+            .filter(|other| other.name != package.name)
+            .filter_map(after_update)
+            .flat_map(|other| other.dependencies.iter().filter(|(name, _)| *name == package.name).map(|(_, req)| req))
+            .collect();
+        let Some(target) = repo.newest(&package.name, &reqs).filter(|t| is_newer(t, package)) else { continue };
+        for dependency in repo.resolve_dependencies(target, installed, holds)? {
+            if !plan.installs.iter().any(|p| p.name == dependency.name) {
+                plan.installs.push(dependency);
+            }
+        }
+        if !plan.installs.iter().any(|p| p.name == target.name) {
+            plan.installs.push(target);
+        }
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn available(name: &str, version: &str, dependencies: &[(&str, &str)]) -> AvailablePackage {
+        AvailablePackage {
+            name: name.to_string(),
+            version: version.parse().unwrap(),
+            path: PathBuf::from(format!("{}-{}.raepkg", name, version)),
+            dependencies: dependencies.iter().map(|(n, r)| (n.to_string(), r.parse().unwrap())).collect(),
+            provides: Vec::new(),
+            conflicts: Vec::new(),
+            replaces: Vec::new(),
+        }
+    }
+
+    fn names(order: &[&AvailablePackage]) -> Vec<String> {
+        order.iter().map(|p| p.label()).collect()
+    }
+
+    fn installed(name: &str, version: &str) -> InstalledPackage {
+        InstalledPackage { name: name.to_string(), version: version.to_string(), prefix: None, files: Vec::new() }
+    }
+
+    fn repo() -> Repository {
+        Repository::new(vec![
+            available("libui", "1.1.0", &[]),
+            available("libui", "1.4.2", &[]),
+            available("libui", "2.0.0", &[]),
+            available("editor", "1.0.0", &[("libui", ">=1.2, <2.0")]),
+            available("editor", "1.1.0", &[("libui", ">=1.2, <2.0")]),
+        ])
+    }
+
+    #[test]
+    fn test_resolver_honours_range() {
+        let repo = repo();
+        let req: VersionReq = ">=1.2, <2.0".parse().unwrap();
+        assert_eq!(repo.resolve("libui", &req).unwrap().version, Version::new(1, 4, 2));
+
+        let err = repo.resolve("libui", &">=3.0".parse().unwrap()).unwrap_err();
+        assert!(err.contains("no version of 'libui' satisfies >=3.0"), "{}", err);
+        assert!(repo.resolve("missing", &VersionReq::any()).is_err());
+
+        let editor = repo.resolve("editor", &VersionReq::any()).unwrap();
+        let deps = repo.resolve_dependencies(editor, &[], &BTreeSet::new()).unwrap();
+        assert_eq!(deps.iter().map(|p| p.version).collect::<Vec<_>>(), [Version::new(1, 4, 2)]);
+        assert!(repo.resolve_dependencies(editor, &[installed("libui", "1.3.0")], &BTreeSet::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_held_package_not_upgraded() {
+        let repo = repo();
+        let system = [installed("editor", "1.0.0"), installed("libui", "1.4.2")];
+        let mut holds = BTreeSet::from(["editor".to_string()]);
+
+        let plan = plan_update(&repo, &system, &holds).unwrap();
+        assert_eq!(plan.held, vec![("editor".to_string(), "1.0.0".to_string(), Version::new(1, 1, 0))]);
+        // libui 2.0.0 is out, but the installed editor still needs <2.0
+        assert!(plan.installs.is_empty());
+
+        holds.remove("editor");
+        let plan = plan_update(&repo, &system, &holds).unwrap();
+        assert!(plan.held.is_empty());
+        assert_eq!(plan.installs.iter().map(|p| (p.name.as_str(), p.version)).collect::<Vec<_>>(), [("editor", Version::new(1, 1, 0))]);
+    }
+
+    #[test]
+    fn test_held_dependency_blocks_incompatible_upgrade() {
+        let repo = repo();
+        let system = [installed("editor", "1.0.0"), installed("libui", "1.1.0")];
+        let holds = BTreeSet::from(["libui".to_string()]);
+        let err = plan_update(&repo, &system, &holds).unwrap_err();
+        assert!(err.contains("libui is held at 1.1.0"), "{}", err);
+    }
+
+    #[test]
+    fn test_diamond_dependency_shares_one_version() {
+        let repo = Repository::new(vec![
+            available("app", "1.0.0", &[("libb", "^1"), ("libc", "^1")]),
+            available("libb", "1.0.0", &[("libd", "^1.0")]),
+            available("libc", "1.0.0", &[("libd", "<1.3")]),
+            available("libd", "1.2.0", &[]),
+            available("libd", "1.5.0", &[]),
+        ]);
+        let app = repo.resolve("app", &VersionReq::any()).unwrap();
+        // libb alone would take libd 1.5.0; libc's bound brings both down to 1.2.0
+        let order = repo.resolve_dependencies(app, &[], &BTreeSet::new()).unwrap();
+        assert_eq!(names(&order), ["libd 1.2.0", "libb 1.0.0", "libc 1.0.0"]);
+
+        let order = repo.resolve_dependencies(app, &[installed("libd", "1.1.0")], &BTreeSet::new()).unwrap();
+        assert_eq!(names(&order), ["libb 1.0.0", "libc 1.0.0"]);
+
+        let repo = Repository::new(vec![
+            available("libb", "1.0.0", &[("libd", "^1.0")]),
+            available("libc", "1.0.0", &[("libd", ">=2.0")]),
+            available("libd", "1.5.0", &[]),
+            available("libd", "2.0.0", &[]),
+        ]);
+        let app = available("app", "1.0.0", &[("libb", "^1"), ("libc", "^1")]);
+        let err = repo.resolve_dependencies(&app, &[], &BTreeSet::new()).unwrap_err();
+        assert!(err.starts_with("conflicting requirements on 'libd' (available: 1.5.0, 2.0.0):"), "{}", err);
+        assert!(err.contains("\n  app 1.0.0 -> libb 1.0.0 requires libd ^1.0"), "{}", err);
+        assert!(err.contains("\n  app 1.0.0 -> libc 1.0.0 requires libd >=2.0"), "{}", err);
+    }
+
+    #[test]
+    fn test_cycle_and_missing_constraint_name_the_chain() {
+        let repo = Repository::new(vec![
+            available("x", "1.0.0", &[("y", "*")]),
+            available("y", "1.0.0", &[("x", "^1")]),
+            available("libb", "1.0.0", &[("libz", ">=9")]),
+            available("libz", "1.0.0", &[]),
+        ]);
+        let app = available("app", "1.0.0", &[("x", "^1")]);
+        let err = repo.resolve_dependencies(&app, &[], &BTreeSet::new()).unwrap_err();
+        assert_eq!(err, "dependency cycle: app 1.0.0 -> x 1.0.0 -> y 1.0.0 -> x");
+
+        let app = available("app", "1.0.0", &[("libb", "*")]);
+        let err = repo.resolve_dependencies(&app, &[], &BTreeSet::new()).unwrap_err();
+        assert_eq!(err, "app 1.0.0 -> libb 1.0.0 requires libz >=9: no version of 'libz' satisfies >=9 (available: 1.0.0)");
+    }
+
+    #[test]
+    fn test_provides_conflicts_and_replaces() {
+        let mut newui = available("newui", "2.0.0", &[]);
+        newui.provides = vec![("libui".to_string(), Some(Version::new(1, 5, 0)))];
+        newui.conflicts = vec![parse_relation("libui").unwrap()];
+        newui.replaces = vec![parse_relation("libui <2").unwrap()];
+        let repo = Repository::new(vec![available("libui", "1.1.0", &[]), newui.clone()]);
+        let system = [installed("libui", "1.1.0"), installed("editor", "1.0.0")];
+
+        let plan = repo.plan_install(&newui, &system, &BTreeSet::new()).unwrap();
+        assert!(plan.dependencies.is_empty());
+        assert_eq!(plan.replaced, ["libui"]);
+        let err = repo.plan_install(&newui, &system, &BTreeSet::from(["libui".to_string()])).unwrap_err();
+        assert_eq!(err, "newui 2.0.0 replaces libui, which is held at 1.1.0");
+
+        let mut rival = available("rival", "1.0.0", &[]);
+        rival.conflicts = vec![parse_relation("libui <2").unwrap()];
+        let err = repo.plan_install(&rival, &system, &BTreeSet::new()).unwrap_err();
+        assert_eq!(err, "rival 1.0.0 conflicts with installed libui 1.1.0");
+
+        // Only newui provides the virtual name, at the version it declares
+        let viewer = available("viewer", "1.0.0", &[("libui", ">=1.5")]);
+        let plan = repo.plan_install(&viewer, &[], &BTreeSet::new()).unwrap();
+        assert_eq!(names(&plan.dependencies), ["newui 2.0.0"]);
+        let both = available("both", "1.0.0", &[("libui", "=1.1.0"), ("newui", "*")]);
+        let err = repo.plan_install(&both, &[], &BTreeSet::new()).unwrap_err();
+        assert_eq!(err, "newui 2.0.0 conflicts with libui 1.1.0, which is also being installed");
+    }
+
+    #[test]
+    fn test_manifest_relations() {
+        let manifest = raeen_common::json::parse(
+            r#"{"name": "viewer", "version": "1.0.0", "dependencies": {"libui": "^1.2"},
+                "runtime_dependencies": {"codecs": ">=3"}, "provides": ["image-viewer =1.0"],
+                "conflicts": ["oldviewer", "libui <1.0"], "replaces": ["oldviewer"]}"#,
+        )
+        .unwrap();
+        let package = AvailablePackage::from_manifest(&manifest, Path::new("viewer.raepkg")).unwrap();
+        let deps: Vec<String> = package.dependencies.iter().map(|(n, r)| format!("{} {}", n, r)).collect();
+        assert_eq!(deps, ["libui ^1.2", "codecs >=3"]);
+        assert_eq!(package.provides, [("image-viewer".to_string(), Some(Version::new(1, 0, 0)))]);
+        assert_eq!(package.conflicts[1], ("libui".to_string(), "<1.0".parse::<VersionReq>().unwrap()));
+        assert_eq!(package.replaces, [("oldviewer".to_string(), VersionReq::any())]);
+        assert!(parse_provided("image-viewer >=1.0").is_err());
+    }
+}
@@ -0,0 +1,366 @@
+//! Package signing
+//! A native package is signed over its canonical bytes: the uncompressed tar of manifest.json
+//! and the files in archive order, rebuilt with fresh headers that keep only path, size and
+//! mode, and with the manifest's `checksum` and `signature` fields left out. Signing records
+//! the SHA-256 of those bytes as the manifest's `checksum` and the detached ed25519 signature
+//! as its `signature`, and appends the raw signature as a `signature.sig` entry. Verification
+//! rebuilds the same bytes, so recompressing an archive keeps its signature valid while
+//! changing any file or manifest field breaks it.
+
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use sha2::{Digest, Sha256};
+use tar::{Archive, Builder, EntryType, Header};
+
+use raeen_common::json::{self, JsonValue};
+
+pub const SIGNATURE_ENTRY: &str = "signature.sig";
+
+/// Public keys packages are verified against, relative to the install root; every `.pub`
+/// file in it is trusted
+pub const TRUSTED_KEYS_DIR: &str = "etc/raeen-pkg/trusted-keys";
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+
+const SEED_LEN: usize = 32;
+const PUBLIC_KEY_LEN: usize = 32;
+const SIGNATURE_LEN: usize = 64;
+
+/// A native package read back into memory
+struct SignedArchive {
+    manifest: JsonValue,
+    /// Path, mode and contents of every entry other than the manifest and signature
+    entries: Vec<(PathBuf, u32, Vec<u8>)>,
+    signature: Option<Vec<u8>>,
+}
+
+impl SignedArchive {
+    fn read(archive: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let (mut manifest, mut entries, mut signature) = (None, Vec::new(), None);
+        let mut tar = Archive::new(GzDecoder::new(fs::File::open(archive)?));
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            if entry.header().entry_type() != EntryType::Regular {
+                return Err(format!("{}: {} is not a regular file", archive.display(), path.display()).into());
+            }
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents)?;
+            if path.as_os_str() == MANIFEST_ENTRY {
+                let text = String::from_utf8(contents).map_err(|_| format!("{}: {} is not UTF-8", archive.display(), MANIFEST_ENTRY))?;
+                manifest = Some(json::parse(&text).map_err(|e| format!("{}: {}: {}", archive.display(), MANIFEST_ENTRY, e))?);
+            } else if path.as_os_str() == SIGNATURE_ENTRY {
+                signature = Some(contents);
+            } else {
+                entries.push((path, entry.header().mode()?, contents));
+            }
+        }
+        let manifest = manifest.ok_or_else(|| format!("{} has no {}", archive.display(), MANIFEST_ENTRY))?;
+        Ok(Self { manifest, entries, signature })
+    }
+
+    /// The uncompressed tar the checksum and signature cover
+    fn canonical_bytes(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let manifest = with_signing_fields(&self.manifest, None)?.to_string();
+        let mut tar = Builder::new(Vec::new());
+        append(&mut tar, Path::new(MANIFEST_ENTRY), 0o644, manifest.as_bytes())?;
+        for (path, mode, contents) in &self.entries {
+            append(&mut tar, path, *mode, contents)?;
+        }
+        Ok(tar.into_inner()?)
+    }
+
+    /// Write the archive back out with `manifest` first and `signature` as `signature.sig` last
+    fn write(&self, archive: &Path, manifest: &JsonValue, signature: &[u8], compression_level: u32) -> Result<(), Box<dyn std::error::Error>> {
+        let mut tar = Builder::new(GzEncoder::new(fs::File::create(archive)?, Compression::new(compression_level)));
+        append(&mut tar, Path::new(MANIFEST_ENTRY), 0o644, manifest.to_string().as_bytes())?;
+        for (path, mode, contents) in &self.entries {
+            append(&mut tar, path, *mode, contents)?;
+        }
+        append(&mut tar, Path::new(SIGNATURE_ENTRY), 0o644, signature)?;
+        tar.into_inner()?.finish()?;
+        Ok(())
+    }
+}
+
+fn append<W: std::io::Write>(tar: &mut Builder<W>, path: &Path, mode: u32, contents: &[u8]) -> std::io::Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_path(path)?;
+    header.set_size(contents.len() as u64);
+    header.set_mode(mode);
+    header.set_cksum();
+    tar.append(&header, contents)
+}
+
+/// `manifest` with its `checksum` and `signature` replaced by `fields`, or dropped for `None`
+fn with_signing_fields(manifest: &JsonValue, fields: Option<(&str, &str)>) -> Result<JsonValue, String> {
+    let JsonValue::Object(entries) = manifest else {
+        return Err(format!("{} is not an object", MANIFEST_ENTRY));
+    };
+    let mut entries: Vec<(String, JsonValue)> = entries.iter().filter(|(key, _)| key != "checksum" && key != "signature").cloned().collect();
+    if let Some((checksum, signature)) = fields {
+        entries.push(("checksum".to_string(), JsonValue::String(checksum.to_string())));
+        entries.push(("signature".to_string(), JsonValue::String(signature.to_string())));
+    }
+    Ok(JsonValue::Object(entries))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] if high.is_ascii_hexdigit() && low.is_ascii_hexdigit() => std::str::from_utf8(pair).ok().and_then(|pair| u8::from_str_radix(pair, 16).ok()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Load an ed25519 signing key: a raw 32-byte seed or a PKCS#8 document
+pub fn load_signing_key(path: &Path) -> Result<Ed25519KeyPair, Box<dyn std::error::Error>> {
+    let bytes = fs::read(path).map_err(|e| format!("cannot read signing key {}: {}", path.display(), e))?;
+    let key = if bytes.len() == SEED_LEN {
+        Ed25519KeyPair::from_seed_unchecked(&bytes)
+    } else {
+        Ed25519KeyPair::from_pkcs8_maybe_unchecked(&bytes)
+    };
+    Ok(key.map_err(|e| format!("{} is not an ed25519 signing key: {}", path.display(), e))?)
+}
+
+/// Load an ed25519 public key: 32 raw bytes or their 64 hex digits
+pub fn load_public_key(path: &Path) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let bytes = fs::read(path).map_err(|e| format!("cannot read public key {}: {}", path.display(), e))?;
+    let key = if bytes.len() == PUBLIC_KEY_LEN {
+        Some(bytes)
+    } else {
+        std::str::from_utf8(&bytes).ok().and_then(|text| from_hex(text.trim()))
+    };
+    key.filter(|key| key.len() == PUBLIC_KEY_LEN).ok_or_else(|| format!("{} is not an ed25519 public key", path.display()).into())
+}
+
+/// Every `.pub` key in `dir`, sorted by file name
+pub fn load_trusted_keys(dir: &Path) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).filter(|path| path.extension().is_some_and(|ext| ext == "pub")).collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(format!("cannot read trusted keys in {}: {}", dir.display(), e).into()),
+    };
+    paths.sort();
+    paths.iter().map(|path| load_public_key(path)).collect()
+}
+
+/// Hex public key of `key`, the form it is trusted in
+pub fn public_key_hex(key: &Ed25519KeyPair) -> String {
+    to_hex(key.public_key().as_ref())
+}
+
+/// Sign the native package at `archive` in place with `key`, replacing any earlier
+/// signature; returns the checksum that was signed
+pub fn sign(archive: &Path, key: &Ed25519KeyPair, compression_level: u32) -> Result<String, Box<dyn std::error::Error>> {
+    let package = SignedArchive::read(archive)?;
+    let canonical = package.canonical_bytes()?;
+    let checksum = format!("{:x}", Sha256::digest(&canonical));
+    let signature = key.sign(&canonical);
+    let manifest = with_signing_fields(&package.manifest, Some((&checksum, &to_hex(signature.as_ref()))))?;
+
+    // Write next to the archive and rename over it, so a failure leaves the original intact
+    let mut partial = archive.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let result = package.write(&partial, &manifest, signature.as_ref(), compression_level).and_then(|_| Ok(fs::rename(&partial, archive)?));
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result.map(|_| checksum)
+}
+
+/// Check the native package at `archive` against its embedded checksum and its signature
+/// against `trusted` public keys; returns the verified checksum
+pub fn verify(archive: &Path, trusted: &[Vec<u8>]) -> Result<String, Box<dyn std::error::Error>> {
+    let package = SignedArchive::read(archive)?;
+    let embedded = package.manifest.get("signature").and_then(JsonValue::as_str);
+    let (embedded, detached) = match (embedded, &package.signature) {
+        (None, None) => return Err(format!("{} is not signed", archive.display()).into()),
+        (Some(embedded), Some(detached)) => (embedded, detached),
+        (None, Some(_)) => return Err(format!("{} has a {} but its manifest carries no signature", archive.display(), SIGNATURE_ENTRY).into()),
+        (Some(_), None) => return Err(format!("{}: manifest is signed but {} is missing", archive.display(), SIGNATURE_ENTRY).into()),
+    };
+
+    let canonical = package.canonical_bytes()?;
+    let checksum = format!("{:x}", Sha256::digest(&canonical));
+    let recorded = package.manifest.get("checksum").and_then(JsonValue::as_str).ok_or_else(|| format!("{}: signed manifest has no checksum", archive.display()))?;
+    if recorded != checksum {
+        return Err(format!("{} has been tampered with: manifest checksum {} does not match the archive ({})", archive.display(), recorded, checksum).into());
+    }
+
+    let signature = from_hex(embedded).filter(|signature| signature.len() == SIGNATURE_LEN).ok_or_else(|| format!("{}: manifest signature is malformed", archive.display()))?;
+    if signature != *detached {
+        return Err(format!("{} has been tampered with: {} does not match the manifest signature", archive.display(), SIGNATURE_ENTRY).into());
+    }
+    if trusted.is_empty() {
+        return Err(format!("cannot verify {}: no trusted public keys", archive.display()).into());
+    }
+    if !trusted.iter().any(|key| UnparsedPublicKey::new(&ED25519, key).verify(&canonical, &signature).is_ok()) {
+        return Err(format!("{} has been tampered with or was signed by an untrusted key: bad signature", archive.display()).into());
+    }
+    Ok(checksum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sha256_file, write_native_archive, FileType, PackageFile, PackageFormat, PackageManifest};
+    use uuid::Uuid;
+
+    const SEED: [u8; 32] = [7; 32];
+
+    /// A scratch directory holding the package `tool` with two files
+    fn setup() -> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(format!("raeen-signing-{}", Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        let files: Vec<PackageFile> = [("tool", "/usr/bin/tool"), ("tool.toml", "/etc/tool/tool.toml")]
+            .into_iter()
+            .map(|(name, target)| {
+                let source_path = root.join(name);
+                fs::write(&source_path, format!("contents of {}", name)).unwrap();
+                PackageFile {
+                    checksum: sha256_file(&source_path).unwrap(),
+                    size: fs::metadata(&source_path).unwrap().len(),
+                    source_path,
+                    target_path: PathBuf::from(target),
+                    file_type: FileType::Binary,
+                    permissions: 0o755,
+                }
+            })
+            .collect();
+        let manifest = PackageManifest::new("tool".to_string(), &PackageFormat::RaeNative, "x86_64", "raeen");
+        let archive = root.join("tool.raepkg");
+        write_native_archive(&archive, &manifest, &files, 6).unwrap();
+        (root, archive)
+    }
+
+    fn key() -> Ed25519KeyPair {
+        Ed25519KeyPair::from_seed_unchecked(&SEED).unwrap()
+    }
+
+    fn trusted() -> Vec<Vec<u8>> {
+        vec![key().public_key().as_ref().to_vec()]
+    }
+
+    /// Rewrite `archive` with `change` applied to its contents, keeping whatever signature it has
+    fn rewrite(archive: &Path, change: impl FnOnce(&mut SignedArchive)) {
+        let mut package = SignedArchive::read(archive).unwrap();
+        change(&mut package);
+        let signature = package.signature.clone().unwrap();
+        package.write(archive, &package.manifest, &signature, 6).unwrap();
+    }
+
+    #[test]
+    fn test_sign_then_verify() {
+        let (root, archive) = setup();
+        let checksum = sign(&archive, &key(), 6).unwrap();
+        assert_eq!(verify(&archive, &trusted()).unwrap(), checksum);
+
+        let package = SignedArchive::read(&archive).unwrap();
+        assert_eq!(package.manifest.get("checksum").and_then(JsonValue::as_str), Some(checksum.as_str()));
+        assert_eq!(package.signature.as_ref().map(Vec::len), Some(SIGNATURE_LEN));
+        assert_eq!(package.entries.len(), 2);
+
+        // Signing again replaces the signature rather than stacking another
+        sign(&archive, &key(), 9).unwrap();
+        assert_eq!(verify(&archive, &trusted()).unwrap(), checksum);
+        let entries = Archive::new(GzDecoder::new(fs::File::open(&archive).unwrap())).entries().unwrap().count();
+        assert_eq!(entries, 4);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_flipped_byte_fails() {
+        let (root, archive) = setup();
+        sign(&archive, &key(), 6).unwrap();
+        let pristine = fs::read(&archive).unwrap();
+
+        // A byte flipped anywhere in the compressed file
+        let mut bytes = pristine.clone();
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0x01;
+        fs::write(&archive, &bytes).unwrap();
+        assert!(verify(&archive, &trusted()).is_err());
+
+        // A byte flipped in a file, recompressed cleanly
+        fs::write(&archive, &pristine).unwrap();
+        rewrite(&archive, |package| package.entries[0].2[0] ^= 0x01);
+        let err = verify(&archive, &trusted()).unwrap_err().to_string();
+        assert!(err.contains("checksum"), "{}", err);
+
+        // The same change with the checksum recomputed to match
+        fs::write(&archive, &pristine).unwrap();
+        rewrite(&archive, |package| {
+            package.entries[0].2[0] ^= 0x01;
+            let checksum = format!("{:x}", Sha256::digest(package.canonical_bytes().unwrap()));
+            let signature = package.manifest.get("signature").and_then(JsonValue::as_str).unwrap().to_string();
+            package.manifest = with_signing_fields(&package.manifest, Some((&checksum, &signature))).unwrap();
+        });
+        let err = verify(&archive, &trusted()).unwrap_err().to_string();
+        assert!(err.contains("bad signature"), "{}", err);
+
+        // An untrusted key
+        fs::write(&archive, &pristine).unwrap();
+        let other = Ed25519KeyPair::from_seed_unchecked(&[9; 32]).unwrap();
+        assert!(verify(&archive, &[other.public_key().as_ref().to_vec()]).is_err());
+        assert!(verify(&archive, &trusted()).is_ok());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_unsigned_and_mismatched_packages() {
+        let (root, archive) = setup();
+        let err = verify(&archive, &trusted()).unwrap_err().to_string();
+        assert!(err.ends_with("is not signed"), "{}", err);
+
+        sign(&archive, &key(), 6).unwrap();
+        rewrite(&archive, |package| {
+            let signature = package.manifest.get("signature").and_then(JsonValue::as_str).unwrap().to_string();
+            package.manifest = with_signing_fields(&package.manifest, Some(("0000", &signature))).unwrap();
+        });
+        let err = verify(&archive, &trusted()).unwrap_err().to_string();
+        assert!(err.contains("manifest checksum 0000 does not match the archive"), "{}", err);
+
+        sign(&archive, &key(), 6).unwrap();
+        rewrite(&archive, |package| package.signature.as_mut().unwrap()[0] ^= 0x01);
+        let err = verify(&archive, &trusted()).unwrap_err().to_string();
+        assert!(err.contains("does not match the manifest signature"), "{}", err);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_key_files() {
+        let (root, _) = setup();
+        let seed = root.join("raeen-packages.key");
+        fs::write(&seed, SEED).unwrap();
+        let key = load_signing_key(&seed).unwrap();
+        assert_eq!(key.public_key().as_ref(), trusted()[0].as_slice());
+
+        let keys = root.join("trusted-keys");
+        fs::create_dir_all(&keys).unwrap();
+        fs::write(keys.join("raeen-packages.pub"), format!("{}\n", public_key_hex(&key))).unwrap();
+        fs::write(keys.join("mirror.pub"), [1; 32]).unwrap();
+        fs::write(keys.join("README"), "not a key").unwrap();
+        assert_eq!(load_trusted_keys(&keys).unwrap(), vec![vec![1; 32], trusted()[0].clone()]);
+        assert!(load_trusted_keys(&root.join("missing")).unwrap().is_empty());
+
+        fs::write(keys.join("broken.pub"), "abc").unwrap();
+        assert!(load_trusted_keys(&keys).is_err());
+        assert!(load_signing_key(&keys.join("README")).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+//! Package versions and version constraints
+//! Versions are `major.minor.patch` (missing parts are zero; pre-release and build suffixes are
+//! ignored). Constraints are comma-separated comparators that must all hold, e.g. `>=1.2, <2.0`.
+//! A bare version means a caret requirement, as in Cargo.
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl Version {
+    pub const fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self { major, minor, patch }
+    }
+
+    /// Parse a possibly partial version, also returning how many parts were given
+    fn parse_partial(s: &str) -> Result<(Self, usize), String> {
+        let core = s.trim().split(['-', '+']).next().unwrap_or_default();
+        let parts: Vec<&str> = core.split('.').collect();
+        if core.is_empty() || parts.len() > 3 {
+            return Err(format!("invalid version '{}'", s));
+        }
+        let mut numbers = [0u64; 3];
+        for (slot, part) in numbers.iter_mut().zip(&parts) {
+            *slot = part.parse().map_err(|_| format!("invalid version '{}'", s))?;
+        }
+        Ok((Self::new(numbers[0], numbers[1], numbers[2]), parts.len()))
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_partial(s).map(|(version, _)| version)
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Exact,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+}
+
+/// A set of comparators that a version must all satisfy
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionReq {
+    source: String,
+    comparators: Vec<(Op, Version)>,
+}
+
+impl VersionReq {
+    /// Matches every version
+    pub fn any() -> Self {
+        Self { source: "*".to_string(), comparators: Vec::new() }
+    }
+
+    pub fn matches(&self, version: &Version) -> bool {
+        self.comparators.iter().all(|(op, bound)| match op {
+            Op::Exact => version == bound,
+            Op::Greater => version > bound,
+            Op::GreaterEq => version >= bound,
+            Op::Less => version < bound,
+            Op::LessEq => version <= bound,
+        })
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut comparators = Vec::new();
+        for part in s.split(',').map(str::trim) {
+            if part.is_empty() {
+                return Err(format!("empty comparator in version constraint '{}'", s));
+            }
+            if part == "*" {
+                continue;
+            }
+            let (op, rest) = [(">=", Op::GreaterEq), ("<=", Op::LessEq), (">", Op::Greater), ("<", Op::Less), ("=", Op::Exact)]
+                .into_iter()
+                .find_map(|(prefix, op)| part.strip_prefix(prefix).map(|rest| (Some(op), rest)))
+                .unwrap_or((None, part));
+            if let Some(op) = op {
+                comparators.push((op, rest.parse()?));
+                continue;
+            }
+
+            // ~ allows patch updates, ^ (and a bare version) anything left of the first non-zero part
+            let (tilde, rest) = match rest.strip_prefix('~') {
+                Some(rest) => (true, rest),
+                None => (false, rest.strip_prefix('^').unwrap_or(rest)),
+            };
+            let (low, given) = Version::parse_partial(rest)?;
+            let high = if tilde {
+                match given {
+                    1 => Version::new(low.major + 1, 0, 0),
+                    _ => Version::new(low.major, low.minor + 1, 0),
+                }
+            } else if low.major > 0 || given == 1 {
+                Version::new(low.major + 1, 0, 0)
+            } else if low.minor > 0 || given == 2 {
+                Version::new(0, low.minor + 1, 0)
+            } else {
+                Version::new(0, 0, low.patch + 1)
+            };
+            comparators.push((Op::GreaterEq, low));
+            comparators.push((Op::Less, high));
+        }
+        Ok(Self { source: s.trim().to_string(), comparators })
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    fn req(s: &str) -> VersionReq {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_version_parsing_and_order() {
+        assert_eq!(v("1.2"), Version::new(1, 2, 0));
+        assert_eq!(v("1.2.3-beta+abc"), Version::new(1, 2, 3));
+        assert!(v("1.10.0") > v("1.9.9"));
+        assert!("1.x".parse::<Version>().is_err());
+        assert!("".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn test_range_constraint() {
+        let range = req(">=1.2, <2.0");
+        assert!(range.matches(&v("1.2.0")) && range.matches(&v("1.9.7")));
+        assert!(!range.matches(&v("1.1.9")) && !range.matches(&v("2.0.0")));
+        assert_eq!(range.to_string(), ">=1.2, <2.0");
+        assert!(req("*").matches(&v("42.0.0")));
+        assert!("1.2,".parse::<VersionReq>().is_err());
+    }
+
+    #[test]
+    fn test_caret_and_tilde() {
+        assert!(req("1.2").matches(&v("1.7.0")) && !req("1.2").matches(&v("2.0.0")));
+        assert!(req("^0.3.1").matches(&v("0.3.9")) && !req("^0.3.1").matches(&v("0.4.0")));
+        assert!(!req("^0.0.3").matches(&v("0.0.4")));
+        assert!(req("~1.2.3").matches(&v("1.2.9")) && !req("~1.2.3").matches(&v("1.3.0")));
+        assert!(req("=1.2.3").matches(&v("1.2.3")) && !req("=1.2.3").matches(&v("1.2.4")));
+    }
+}
@@ -0,0 +1,256 @@
+//! Web app (PWA) export for raeen-pkg
+//! Bundles the files under `web/` into a zip together with a web app manifest derived from the
+//! package manifest and a service worker that precaches every bundled asset, so the app keeps
+//! working offline in the web-compat layer.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use raeen_common::json::JsonValue;
+use crate::{FileType, PackageBuilder, PackageFile, PackageManifest};
+
+/// Install prefix of web assets; `<WEB_ROOT>/<name>/` is the root of the app
+pub const WEB_ROOT: &str = "/usr/share/raeen/web";
+
+/// Page the app opens on, relative to its root
+pub const ENTRY_HTML: &str = "index.html";
+
+const MANIFEST_FILE: &str = "manifest.json";
+const SERVICE_WORKER_FILE: &str = "sw.js";
+
+/// A file placed in the bundle, keyed by its path relative to the app root
+#[derive(Debug, Clone)]
+pub(crate) struct WebAsset<'a> {
+    pub path: String,
+    pub file: &'a PackageFile,
+}
+
+/// The package files that make up the web app: everything under the app's web root plus its
+/// icons, which are placed in `icons/`. Fails unless there is an entry page and an icon.
+pub(crate) fn bundle_assets<'a>(manifest: &PackageManifest, files: &'a [PackageFile]) -> Result<Vec<WebAsset<'a>>, String> {
+    let root = Path::new(WEB_ROOT).join(&manifest.name);
+    let mut assets = Vec::new();
+    for file in files {
+        let path = if file.file_type == FileType::Icon {
+            file.target_path.file_name().map(|n| format!("icons/{}", n.to_string_lossy()))
+        } else {
+            file.target_path.strip_prefix(&root).ok().map(|p| p.to_string_lossy().replace('\\', "/"))
+        };
+        if let Some(path) = path {
+            assets.push(WebAsset { path, file });
+        }
+    }
+
+    if !assets.iter().any(|a| a.path == ENTRY_HTML) {
+        return Err(format!("web app has no entry page: add web/{}", ENTRY_HTML));
+    }
+    if !assets.iter().any(|a| a.file.file_type == FileType::Icon) {
+        return Err(format!("web app needs at least one icon: add assets/{}.png (or .svg)", manifest.name));
+    }
+    Ok(assets)
+}
+
+/// `sizes` and MIME type of an icon; PNG dimensions come from the IHDR chunk
+fn icon_details(path: &Path) -> (String, &'static str) {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("svg") => ("any".to_string(), "image/svg+xml"),
+        _ => {
+            let sizes = fs::read(path)
+                .ok()
+                .filter(|data| data.len() >= 24 && data.starts_with(b"\x89PNG\r\n\x1a\n"))
+                .map(|data| {
+                    let width = u32::from_be_bytes([data[16], data[17], data[18], data[19]]);
+                    let height = u32::from_be_bytes([data[20], data[21], data[22], data[23]]);
+                    format!("{}x{}", width, height)
+                })
+                .unwrap_or_else(|| "any".to_string());
+            (sizes, "image/png")
+        }
+    }
+}
+
+/// Web app manifest (manifest.json) for the package
+pub(crate) fn web_manifest(manifest: &PackageManifest, assets: &[WebAsset]) -> JsonValue {
+    let icons = assets
+        .iter()
+        .filter(|a| a.file.file_type == FileType::Icon)
+        .map(|a| {
+            let (sizes, mime) = icon_details(&a.file.source_path);
+            JsonValue::object(vec![
+                ("src", JsonValue::String(a.path.clone())),
+                ("sizes", JsonValue::String(sizes)),
+                ("type", JsonValue::String(mime.to_string())),
+            ])
+        })
+        .collect();
+
+    JsonValue::object(vec![
+        ("name", JsonValue::String(manifest.name.clone())),
+        ("short_name", JsonValue::String(manifest.name.chars().take(12).collect())),
+        ("description", JsonValue::String(manifest.description.clone())),
+        ("start_url", JsonValue::String(format!("./{}", ENTRY_HTML))),
+        ("scope", JsonValue::String("./".to_string())),
+        ("display", JsonValue::String("standalone".to_string())),
+        ("background_color", JsonValue::String("#ffffff".to_string())),
+        ("theme_color", JsonValue::String("#ffffff".to_string())),
+        ("categories", JsonValue::string_array(manifest.categories.iter().map(|c| c.to_lowercase()))),
+        ("icons", JsonValue::Array(icons)),
+    ])
+}
+
+/// Cache-first service worker precaching `assets`; the cache is named after the package
+/// version so an update replaces the previous copy
+pub fn service_worker(cache_name: &str, assets: &[String]) -> String {
+    let cache = JsonValue::String(cache_name.to_string());
+    let precache = JsonValue::string_array(assets.iter().map(|a| format!("./{}", a)));
+    format!(
+        r#"const CACHE = {cache};
+const ASSETS = {precache};
+
+self.addEventListener("install", (event) => {{
+  event.waitUntil(caches.open(CACHE).then((cache) => cache.addAll(ASSETS)).then(() => self.skipWaiting()));
+}});
+
+self.addEventListener("activate", (event) => {{
+  event.waitUntil(
+    caches.keys()
+      .then((keys) => Promise.all(keys.filter((key) => key !== CACHE).map((key) => caches.delete(key))))
+      .then(() => self.clients.claim())
+  );
+}});
+
+self.addEventListener("fetch", (event) => {{
+  if (event.request.method !== "GET") return;
+  event.respondWith(caches.match(event.request).then((cached) => cached || fetch(event.request)));
+}});
+"#
+    )
+}
+
+/// Link the manifest and register the service worker from the entry page, unless it already does
+pub fn inject_pwa_tags(html: &str) -> String {
+    if html.contains(MANIFEST_FILE) {
+        return html.to_string();
+    }
+    let tags = format!(
+        "<link rel=\"manifest\" href=\"{}\">\n<script>if (\"serviceWorker\" in navigator) navigator.serviceWorker.register(\"{}\");</script>\n",
+        MANIFEST_FILE, SERVICE_WORKER_FILE
+    );
+    match html.find("</head>") {
+        Some(index) => format!("{}{}{}", &html[..index], tags, &html[index..]),
+        None => format!("{}{}", tags, html),
+    }
+}
+
+/// Write the zipped PWA bundle, returning its path
+pub(crate) fn export(builder: &PackageBuilder, manifest: &PackageManifest, files: &[PackageFile]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let assets = bundle_assets(manifest, files)?;
+    let mut precache: Vec<String> = assets.iter().map(|a| a.path.clone()).collect();
+    precache.push(MANIFEST_FILE.to_string());
+
+    let bundle = builder.output_dir.join(format!("{}-{}.webapp.zip", manifest.name, manifest.version));
+    let mut zip = ZipWriter::new(fs::File::create(&bundle)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for asset in &assets {
+        zip.start_file(asset.path.as_str(), options)?;
+        if asset.path == ENTRY_HTML {
+            zip.write_all(inject_pwa_tags(&fs::read_to_string(&asset.file.source_path)?).as_bytes())?;
+        } else {
+            zip.write_all(&fs::read(&asset.file.source_path)?)?;
+        }
+    }
+    zip.start_file(MANIFEST_FILE, options)?;
+    zip.write_all(web_manifest(manifest, &assets).to_string().as_bytes())?;
+    zip.start_file(SERVICE_WORKER_FILE, options)?;
+    zip.write_all(service_worker(&format!("{}-{}", manifest.name, manifest.version), &precache).as_bytes())?;
+    zip.finish()?;
+
+    Ok(bundle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PackageFormat;
+
+    fn fixture(dir: &Path, name: &str, contents: &[u8], target: &str, file_type: FileType) -> PackageFile {
+        let source_path = dir.join(name);
+        fs::write(&source_path, contents).unwrap();
+        PackageFile {
+            source_path,
+            target_path: PathBuf::from(target),
+            file_type,
+            permissions: 0o644,
+            checksum: String::new(),
+            size: contents.len() as u64,
+        }
+    }
+
+    /// PNG signature followed by an IHDR chunk for a `size`x`size` image
+    fn png(size: u32) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        data.extend_from_slice(&size.to_be_bytes());
+        data.extend_from_slice(&size.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn test_manifest_and_service_worker_cover_bundle() {
+        let dir = std::env::temp_dir().join(format!("raeen-webapp-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let manifest = PackageManifest::new("weather".to_string(), &PackageFormat::WebApp, "wasm32", "web");
+        let files = vec![
+            fixture(&dir, "index.html", b"<html><head></head></html>", "/usr/share/raeen/web/weather/index.html", FileType::Data),
+            fixture(&dir, "app.js", b"", "/usr/share/raeen/web/weather/js/app.js", FileType::Data),
+            fixture(&dir, "weather.png", &png(192), "/usr/share/pixmaps/weather.png", FileType::Icon),
+            fixture(&dir, "weather.svg", b"<svg/>", "/usr/share/pixmaps/weather.svg", FileType::Icon),
+            fixture(&dir, "README", b"", "/usr/share/doc/weather/README", FileType::Documentation),
+        ];
+
+        let assets = bundle_assets(&manifest, &files).unwrap();
+        let paths: Vec<&str> = assets.iter().map(|a| a.path.as_str()).collect();
+        assert_eq!(paths, ["index.html", "js/app.js", "icons/weather.png", "icons/weather.svg"]);
+
+        let web = raeen_common::json::parse(&web_manifest(&manifest, &assets).to_string()).unwrap();
+        assert_eq!(web.get("name").and_then(JsonValue::as_str), Some("weather"));
+        assert_eq!(web.get("start_url").and_then(JsonValue::as_str), Some("./index.html"));
+        let icons = web.get("icons").and_then(JsonValue::as_array).unwrap();
+        assert_eq!(icons.len(), 2);
+        assert_eq!(icons[0].get("src").and_then(JsonValue::as_str), Some("icons/weather.png"));
+        assert_eq!(icons[0].get("sizes").and_then(JsonValue::as_str), Some("192x192"));
+        assert_eq!(icons[1].get("type").and_then(JsonValue::as_str), Some("image/svg+xml"));
+
+        let precache: Vec<String> = paths.iter().map(|p| p.to_string()).collect();
+        let sw = service_worker("weather-0.1.0", &precache);
+        for path in &paths {
+            assert!(sw.contains(&format!("\"./{}\"", path)), "service worker does not cache {}", path);
+        }
+        assert!(sw.starts_with("const CACHE = \"weather-0.1.0\";"));
+
+        let html = inject_pwa_tags("<html><head></head></html>");
+        assert!(html.contains("<link rel=\"manifest\" href=\"manifest.json\">") && html.ends_with("</head></html>"));
+        assert_eq!(inject_pwa_tags(&html), html);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_entry_page_and_icon_required() {
+        let dir = std::env::temp_dir().join(format!("raeen-webapp-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let manifest = PackageManifest::new("weather".to_string(), &PackageFormat::WebApp, "wasm32", "web");
+        let page = fixture(&dir, "index.html", b"", "/usr/share/raeen/web/weather/index.html", FileType::Data);
+        let icon = fixture(&dir, "weather.svg", b"<svg/>", "/usr/share/pixmaps/weather.svg", FileType::Icon);
+
+        assert!(bundle_assets(&manifest, &[icon.clone()]).unwrap_err().contains("index.html"));
+        assert!(bundle_assets(&manifest, &[page.clone()]).unwrap_err().contains("icon"));
+        assert!(bundle_assets(&manifest, &[page, icon]).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
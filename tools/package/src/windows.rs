@@ -0,0 +1,430 @@
+//! Windows installer export for raeen-pkg
+//! Writes an MSI database directly (no WiX or Windows host needed): one component per file,
+//! a directory tree under `ProgramFiles64Folder\<name>` mirroring the package layout, a single
+//! feature, and the files themselves in a cabinet embedded as a stream. A small install script
+//! is emitted next to the MSI for unattended installs, e.g. under the Wine compatibility layer.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Cursor, Read, Seek, Write};
+use std::path::PathBuf;
+
+use msi::{Category, Column, Insert, Package, PackageType, Value};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{PackageBuilder, PackageFile, PackageManifest};
+
+/// Name of the embedded cabinet stream; the Media table refers to it as `#product.cab`
+const CABINET: &str = "product.cab";
+
+/// Root directory keys of the Directory table
+const TARGET_DIR: &str = "TARGETDIR";
+const PROGRAM_FILES: &str = "ProgramFiles64Folder";
+const INSTALL_DIR: &str = "INSTALLDIR";
+
+/// msidbComponentAttributes64bit
+const COMPONENT_64BIT: i32 = 256;
+
+/// Standard actions for a plain file install, with their conventional sequence numbers
+const EXECUTE_SEQUENCE: &[(&str, i32)] = &[
+    ("CostInitialize", 800),
+    ("FileCost", 900),
+    ("CostFinalize", 1000),
+    ("InstallValidate", 1400),
+    ("InstallInitialize", 1500),
+    ("ProcessComponents", 1600),
+    ("UnpublishFeatures", 1800),
+    ("RemoveFiles", 3500),
+    ("InstallFiles", 4000),
+    ("RegisterProduct", 6100),
+    ("PublishFeatures", 6300),
+    ("PublishProduct", 6400),
+    ("InstallFinalize", 6600),
+];
+
+/// A file of the package as the MSI tables describe it
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MsiFile {
+    /// Key shared by the File and Component tables, and the file's name in the cabinet
+    pub key: String,
+    pub directory: String,
+    pub long_name: String,
+    pub source: PathBuf,
+    pub size: u64,
+}
+
+/// A Directory table row below INSTALLDIR
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MsiDirectory {
+    pub key: String,
+    pub parent: String,
+    pub name: String,
+}
+
+/// Turn arbitrary text into an MSI identifier: letters, digits, `_` and `.`, not starting with a digit
+fn identifier(prefix: &str, text: &str) -> String {
+    let body: String = text.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '.' { c } else { '_' }).collect();
+    format!("{}_{}", prefix, body)
+}
+
+/// Deterministic GUID derived from `seed`, so rebuilding a package keeps component identities stable
+fn stable_guid(seed: &str) -> String {
+    let digest = Sha256::digest(seed.as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    format!("{{{}}}", uuid::Builder::from_random_bytes(bytes).into_uuid().hyphenated()).to_uppercase()
+}
+
+/// `SHORT~1.EXT|long name` as the Filename and DefaultDir columns expect; names that already
+/// fit 8.3 are used as they are
+fn msi_filename(long: &str, index: usize) -> String {
+    let (stem, ext) = match long.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, Some(ext)),
+        _ => (long, None),
+    };
+    let valid = |s: &str, max: usize| !s.is_empty() && s.len() <= max && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid(stem, 8) && ext.map_or(true, |e| valid(e, 3)) {
+        return long.to_string();
+    }
+    let clean = |s: &str, max: usize| s.chars().filter(|c| c.is_ascii_alphanumeric()).take(max).collect::<String>().to_uppercase();
+    let tag = format!("~{}", index);
+    let mut short = clean(stem, 8 - tag.len().min(7));
+    short.push_str(&tag);
+    if let Some(ext) = ext.map(|e| clean(e, 3)).filter(|e| !e.is_empty()) {
+        short.push('.');
+        short.push_str(&ext);
+    }
+    format!("{}|{}", short, long)
+}
+
+/// Map the package files onto a directory tree under INSTALLDIR: `/usr` is dropped, so
+/// `/usr/bin/app` installs to `INSTALLDIR\bin\app` and `/etc/app/x` to `INSTALLDIR\etc\app\x`
+pub(crate) fn layout(files: &[PackageFile]) -> (Vec<MsiDirectory>, Vec<MsiFile>) {
+    let mut directories: BTreeMap<PathBuf, MsiDirectory> = BTreeMap::new();
+    let mut msi_files = Vec::new();
+    for (index, file) in files.iter().enumerate() {
+        let relative = file.target_path.strip_prefix("/").unwrap_or(&file.target_path);
+        let relative = relative.strip_prefix("usr").unwrap_or(relative);
+        let mut parent = INSTALL_DIR.to_string();
+        let mut path = PathBuf::new();
+        if let Some(dir) = relative.parent() {
+            for component in dir.components() {
+                path.push(component);
+                let next = directories.len();
+                let entry = directories.entry(path.clone()).or_insert_with(|| MsiDirectory {
+                    key: identifier(&format!("dir{}", next), &component.as_os_str().to_string_lossy()),
+                    parent: parent.clone(),
+                    name: component.as_os_str().to_string_lossy().into_owned(),
+                });
+                parent = entry.key.clone();
+            }
+        }
+        let long_name = relative.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        msi_files.push(MsiFile {
+            key: identifier(&format!("file{}", index), &long_name),
+            directory: parent,
+            long_name,
+            source: file.source_path.clone(),
+            size: file.size,
+        });
+    }
+    let mut directories: Vec<MsiDirectory> = directories.into_values().collect();
+    directories.sort_by(|a, b| a.key.cmp(&b.key));
+    (directories, msi_files)
+}
+
+/// The ProductVersion property only accepts `major.minor.build` numbers
+fn product_version(version: &str) -> String {
+    let numbers: Vec<u32> = version.split(['-', '+']).next().unwrap_or_default().split('.').filter_map(|p| p.parse().ok()).collect();
+    format!("{}.{}.{}", numbers.first().unwrap_or(&0), numbers.get(1).unwrap_or(&0), numbers.get(2).unwrap_or(&0))
+}
+
+fn build_cabinet(files: &[MsiFile]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut builder = cab::CabinetBuilder::new();
+    let folder = builder.add_folder(cab::CompressionType::MsZip);
+    for file in files {
+        folder.add_file(file.key.as_str());
+    }
+    let mut writer = builder.build(Cursor::new(Vec::new()))?;
+    let mut contents = files.iter();
+    while let Some(mut entry) = writer.next_file()? {
+        let file = contents.next().ok_or("cabinet has more entries than files")?;
+        entry.write_all(&fs::read(&file.source)?)?;
+    }
+    Ok(writer.finish()?.into_inner())
+}
+
+/// Write the MSI database for the package into `out`
+pub(crate) fn write_msi<F: Read + Write + Seek>(
+    out: F,
+    manifest: &PackageManifest,
+    files: &[PackageFile],
+    wine_version: &str,
+) -> Result<Package<F>, Box<dyn std::error::Error>> {
+    let (directories, msi_files) = layout(files);
+    let mut package = Package::create(PackageType::Installer, out)?;
+
+    let summary = package.summary_info_mut();
+    summary.set_title(format!("{} Installer", manifest.name));
+    summary.set_subject(manifest.description.clone());
+    summary.set_author(manifest.author.clone());
+    summary.set_arch("x64");
+    summary.set_uuid(Uuid::new_v4());
+    summary.set_creating_application("raeen-pkg".to_string());
+    summary.set_comments(format!("Built for the RaeenOS Windows compatibility layer (Wine {})", wine_version));
+
+    package.create_table(
+        "Property",
+        vec![Column::build("Property").primary_key().id_string(72), Column::build("Value").text_string(0)],
+    )?;
+    let properties = [
+        ("ProductCode", stable_guid(&format!("product/{}/{}", manifest.name, manifest.version))),
+        ("UpgradeCode", stable_guid(&format!("upgrade/{}", manifest.name))),
+        ("ProductName", manifest.name.clone()),
+        ("ProductVersion", product_version(&manifest.version)),
+        ("Manufacturer", manifest.author.clone()),
+        ("ProductLanguage", "1033".to_string()),
+        ("ALLUSERS", "1".to_string()),
+    ];
+    let mut insert = Insert::into("Property");
+    for (key, value) in properties {
+        insert = insert.row(vec![Value::from(key), Value::from(value)]);
+    }
+    package.insert_rows(insert)?;
+
+    package.create_table(
+        "Directory",
+        vec![
+            Column::build("Directory").primary_key().id_string(72),
+            Column::build("Directory_Parent").nullable().id_string(72),
+            Column::build("DefaultDir").category(Category::DefaultDir).string(255),
+        ],
+    )?;
+    let mut insert = Insert::into("Directory")
+        .row(vec![Value::from(TARGET_DIR), Value::Null, Value::from("SourceDir")])
+        .row(vec![Value::from(PROGRAM_FILES), Value::from(TARGET_DIR), Value::from(".")])
+        .row(vec![Value::from(INSTALL_DIR), Value::from(PROGRAM_FILES), Value::from(msi_filename(&manifest.name, 1))]);
+    for (index, dir) in directories.iter().enumerate() {
+        insert = insert.row(vec![Value::from(dir.key.as_str()), Value::from(dir.parent.as_str()), Value::from(msi_filename(&dir.name, index + 1))]);
+    }
+    package.insert_rows(insert)?;
+
+    package.create_table(
+        "Component",
+        vec![
+            Column::build("Component").primary_key().id_string(72),
+            Column::build("ComponentId").nullable().category(Category::Guid).string(38),
+            Column::build("Directory_").id_string(72),
+            Column::build("Attributes").int16(),
+            Column::build("Condition").nullable().category(Category::Condition).string(255),
+            Column::build("KeyPath").nullable().id_string(72),
+        ],
+    )?;
+    package.create_table(
+        "File",
+        vec![
+            Column::build("File").primary_key().id_string(72),
+            Column::build("Component_").id_string(72),
+            Column::build("FileName").category(Category::Filename).string(255),
+            Column::build("FileSize").int32(),
+            Column::build("Version").nullable().category(Category::Version).string(72),
+            Column::build("Language").nullable().category(Category::Language).string(20),
+            Column::build("Attributes").nullable().int16(),
+            Column::build("Sequence").int32(),
+        ],
+    )?;
+    let mut components = Insert::into("Component");
+    let mut file_rows = Insert::into("File");
+    for (index, file) in msi_files.iter().enumerate() {
+        let guid = stable_guid(&format!("component/{}/{}/{}", manifest.name, file.directory, file.long_name));
+        components = components.row(vec![
+            Value::from(file.key.as_str()),
+            Value::from(guid),
+            Value::from(file.directory.as_str()),
+            Value::from(COMPONENT_64BIT),
+            Value::Null,
+            Value::from(file.key.as_str()),
+        ]);
+        file_rows = file_rows.row(vec![
+            Value::from(file.key.as_str()),
+            Value::from(file.key.as_str()),
+            Value::from(msi_filename(&file.long_name, index + 1)),
+            Value::from(i32::try_from(file.size).map_err(|_| format!("{} is too large for an MSI", file.long_name))?),
+            Value::Null,
+            Value::Null,
+            Value::Null,
+            Value::from(index as i32 + 1),
+        ]);
+    }
+    package.insert_rows(components)?;
+    package.insert_rows(file_rows)?;
+
+    package.create_table(
+        "Feature",
+        vec![
+            Column::build("Feature").primary_key().id_string(38),
+            Column::build("Feature_Parent").nullable().id_string(38),
+            Column::build("Title").nullable().localizable().text_string(64),
+            Column::build("Description").nullable().localizable().text_string(255),
+            Column::build("Display").nullable().int16(),
+            Column::build("Level").int16(),
+            Column::build("Directory_").nullable().id_string(72),
+            Column::build("Attributes").int16(),
+        ],
+    )?;
+    package.insert_rows(Insert::into("Feature").row(vec![
+        Value::from("Complete"),
+        Value::Null,
+        Value::from(manifest.name.as_str()),
+        Value::from(manifest.description.as_str()),
+        Value::from(1),
+        Value::from(1),
+        Value::from(INSTALL_DIR),
+        Value::from(0),
+    ]))?;
+    package.create_table(
+        "FeatureComponents",
+        vec![Column::build("Feature_").primary_key().id_string(38), Column::build("Component_").primary_key().id_string(72)],
+    )?;
+    let mut insert = Insert::into("FeatureComponents");
+    for file in &msi_files {
+        insert = insert.row(vec![Value::from("Complete"), Value::from(file.key.as_str())]);
+    }
+    package.insert_rows(insert)?;
+
+    package.create_table(
+        "Media",
+        vec![
+            Column::build("DiskId").primary_key().int16(),
+            Column::build("LastSequence").int32(),
+            Column::build("DiskPrompt").nullable().text_string(64),
+            Column::build("Cabinet").nullable().category(Category::Cabinet).string(255),
+            Column::build("VolumeLabel").nullable().text_string(32),
+            Column::build("Source").nullable().category(Category::Property).string(72),
+        ],
+    )?;
+    package.insert_rows(Insert::into("Media").row(vec![
+        Value::from(1),
+        Value::from(msi_files.len() as i32),
+        Value::Null,
+        Value::from(format!("#{}", CABINET)),
+        Value::Null,
+        Value::Null,
+    ]))?;
+
+    package.create_table(
+        "InstallExecuteSequence",
+        vec![
+            Column::build("Action").primary_key().id_string(72),
+            Column::build("Condition").nullable().category(Category::Condition).string(255),
+            Column::build("Sequence").nullable().int16(),
+        ],
+    )?;
+    let mut insert = Insert::into("InstallExecuteSequence");
+    for (action, sequence) in EXECUTE_SEQUENCE {
+        insert = insert.row(vec![Value::from(*action), Value::Null, Value::from(*sequence)]);
+    }
+    package.insert_rows(insert)?;
+
+    package.write_stream(CABINET)?.write_all(&build_cabinet(&msi_files)?)?;
+    package.flush()?;
+    Ok(package)
+}
+
+/// Batch script that installs the MSI next to it without prompts and reports failure
+pub fn install_script(msi_name: &str) -> String {
+    format!(
+        "@echo off\r\n\
+         rem Unattended install of {msi}\r\n\
+         msiexec /i \"%~dp0{msi}\" /qn /norestart /l*v \"%TEMP%\\{msi}.log\" %*\r\n\
+         if errorlevel 1 (\r\n\
+         \x20 echo Installation failed, see %TEMP%\\{msi}.log\r\n\
+         \x20 exit /b 1\r\n\
+         )\r\n",
+        msi = msi_name
+    )
+}
+
+/// Write `<name>-<version>-x64.msi` and its install script, returning the MSI path
+pub(crate) fn export(builder: &PackageBuilder, manifest: &PackageManifest, files: &[PackageFile]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if files.is_empty() {
+        return Err("Windows installer needs at least one file to install".into());
+    }
+    let msi_name = format!("{}-{}-x64.msi", manifest.name, manifest.version);
+    let msi_path = builder.output_dir.join(&msi_name);
+    write_msi(fs::File::create(&msi_path)?, manifest, files, &builder.compatibility.wine_version)?;
+    fs::write(builder.output_dir.join(format!("{}-{}-install.cmd", manifest.name, manifest.version)), install_script(&msi_name))?;
+    Ok(msi_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FileType, PackageFormat};
+    use msi::Select;
+
+    #[test]
+    fn test_msi_tables_follow_package_files() {
+        let dir = std::env::temp_dir().join(format!("raeen-msi-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let files: Vec<PackageFile> = [
+            ("notepad.exe", "/usr/bin/notepad.exe", FileType::Binary),
+            ("renderer.dll", "/usr/lib/renderer.dll", FileType::Library),
+            ("settings.toml", "/etc/notepad/settings.toml", FileType::Configuration),
+        ]
+        .into_iter()
+        .map(|(name, target, file_type)| {
+            let source_path = dir.join(name);
+            fs::write(&source_path, name).unwrap();
+            PackageFile { source_path, target_path: PathBuf::from(target), file_type, permissions: 0o644, checksum: String::new(), size: name.len() as u64 }
+        })
+        .collect();
+        let mut manifest = PackageManifest::new("notepad".to_string(), &PackageFormat::WindowsExe, "x86_64", "windows");
+        manifest.version = "1.2.3".to_string();
+
+        let msi_path = dir.join("notepad.msi");
+        write_msi(fs::File::create(&msi_path).unwrap(), &manifest, &files, "8.0").unwrap();
+
+        let mut package = msi::open(&msi_path).unwrap();
+        let column = |package: &mut Package<fs::File>, table: &str, column: &str| -> Vec<String> {
+            package.select_rows(Select::table(table)).unwrap().map(|row| row[column].to_string()).collect()
+        };
+
+        let dirs = column(&mut package, "Directory", "DefaultDir");
+        for expected in ["SourceDir", ".", "bin", "lib", "etc"] {
+            assert!(dirs.iter().any(|d| d == expected), "no directory {} in {:?}", expected, dirs);
+        }
+        // INSTALLDIR and etc\notepad
+        assert_eq!(dirs.iter().filter(|d| *d == "notepad").count(), 2);
+        let mut names = column(&mut package, "File", "FileName");
+        names.sort();
+        assert_eq!(names, ["SETTIN~3.TOM|settings.toml", "notepad.exe", "renderer.dll"]);
+
+        let components = column(&mut package, "Component", "Component");
+        assert_eq!(components.len(), 3);
+        assert_eq!(column(&mut package, "Component", "KeyPath"), components);
+        assert_eq!(column(&mut package, "FeatureComponents", "Component_"), components);
+        let settings = components.iter().position(|c| c.ends_with("settings.toml")).unwrap();
+        let etc_notepad = column(&mut package, "Component", "Directory_")[settings].clone();
+        let dir_keys = column(&mut package, "Directory", "Directory");
+        let dir_parents = column(&mut package, "Directory", "Directory_Parent");
+        let parent = &dir_parents[dir_keys.iter().position(|k| *k == etc_notepad).unwrap()];
+        assert!(parent.ends_with("_etc"), "{} should sit under etc", etc_notepad);
+
+        let props = column(&mut package, "Property", "Property");
+        let values = column(&mut package, "Property", "Value");
+        assert_eq!(values[props.iter().position(|p| p == "ProductVersion").unwrap()], "1.2.3");
+        assert!(package.has_stream(CABINET));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_msi_filenames() {
+        assert_eq!(msi_filename("app.exe", 1), "app.exe");
+        assert_eq!(msi_filename("bin", 1), "bin");
+        assert_eq!(msi_filename("my application.config", 12), "MYAPP~12.CON|my application.config");
+        assert_eq!(product_version("2.1.0-beta"), "2.1.0");
+    }
+}
@@ -0,0 +1,368 @@
+//! Code coverage collection for raeen-test
+//! Runs the workspace tests with `-C instrument-coverage`, merges the raw profiles with
+//! llvm-profdata and uses llvm-cov to write an lcov file and a per-crate summary.
+
+use log::{debug, info};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command as ProcessCommand, Stdio};
+
+use raeen_common::json::{self, JsonValue};
+
+/// Sources outside the workspace (std, registry crates) are excluded from the report
+const IGNORE_FILENAME_REGEX: &str = r"(/\.cargo/registry/|/\.cargo/git/|/rustc/|/library/std/)";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CoverageCounts {
+    pub covered: u64,
+    pub count: u64,
+}
+
+impl CoverageCounts {
+    pub fn percent(&self) -> f64 {
+        if self.count == 0 {
+            100.0
+        } else {
+            self.covered as f64 * 100.0 / self.count as f64
+        }
+    }
+
+    fn add(&mut self, other: CoverageCounts) {
+        self.covered += other.covered;
+        self.count += other.count;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrateCoverage {
+    /// Crate directory relative to the workspace root
+    pub name: String,
+    pub lines: CoverageCounts,
+    pub regions: CoverageCounts,
+}
+
+#[derive(Debug, Clone)]
+pub struct CoverageReport {
+    pub crates: Vec<CrateCoverage>,
+    pub lcov_path: PathBuf,
+    pub tests_passed: bool,
+}
+
+impl CoverageReport {
+    pub fn total_lines(&self) -> CoverageCounts {
+        let mut total = CoverageCounts::default();
+        for krate in &self.crates {
+            total.add(krate.lines);
+        }
+        total
+    }
+
+    pub fn total_regions(&self) -> CoverageCounts {
+        let mut total = CoverageCounts::default();
+        for krate in &self.crates {
+            total.add(krate.regions);
+        }
+        total
+    }
+
+    /// Whether total line coverage reaches `min_percent`
+    pub fn meets_threshold(&self, min_percent: f64) -> bool {
+        self.total_lines().percent() >= min_percent
+    }
+
+    pub fn render(&self) -> String {
+        let mut report = String::from("=== Coverage Summary ===\n");
+        report.push_str(&format!("{:<40} {:>16} {:>16}\n", "Crate", "Lines", "Regions"));
+        for krate in &self.crates {
+            report.push_str(&format!(
+                "{:<40} {:>15.2}% {:>15.2}%\n",
+                krate.name,
+                krate.lines.percent(),
+                krate.regions.percent()
+            ));
+        }
+        report.push_str(&format!(
+            "{:<40} {:>15.2}% {:>15.2}%\n",
+            "TOTAL",
+            self.total_lines().percent(),
+            self.total_regions().percent()
+        ));
+        report.push_str(&format!("lcov: {}\n", self.lcov_path.display()));
+        report
+    }
+}
+
+fn counts(summary: &JsonValue, kind: &str) -> Result<CoverageCounts, String> {
+    let section = summary.field(kind)?;
+    Ok(CoverageCounts {
+        covered: section.field("covered")?.as_u64().ok_or_else(|| format!("'{}.covered' is not a count", kind))?,
+        count: section.field("count")?.as_u64().ok_or_else(|| format!("'{}.count' is not a count", kind))?,
+    })
+}
+
+/// The directory (relative to `workspace_root`) of the crate owning `file`
+fn owning_crate(file: &Path, workspace_root: &Path) -> Option<String> {
+    let root_name = workspace_root.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| ".".to_string());
+    file.ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(workspace_root))
+        .find(|dir| dir.join("Cargo.toml").exists())
+        .map(|dir| match dir.strip_prefix(workspace_root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative.display().to_string(),
+            _ => root_name.clone(),
+        })
+}
+
+/// Aggregate an `llvm-cov export -summary-only` document into per-crate coverage
+pub fn summarize(export: &str, workspace_root: &Path) -> Result<Vec<CrateCoverage>, String> {
+    let document = json::parse(export)?;
+    let mut crates: BTreeMap<String, CrateCoverage> = BTreeMap::new();
+
+    for data in document.field("data")?.as_array().ok_or("'data' is not an array")? {
+        for file in data.field("files")?.as_array().ok_or("'files' is not an array")? {
+            let filename = file.field("filename")?.as_str().ok_or("'filename' is not a string")?;
+            let krate = match owning_crate(Path::new(filename), workspace_root) {
+                Some(krate) => krate,
+                None => continue,
+            };
+            let summary = file.field("summary")?;
+            let entry = crates.entry(krate.clone()).or_insert_with(|| CrateCoverage {
+                name: krate,
+                lines: CoverageCounts::default(),
+                regions: CoverageCounts::default(),
+            });
+            entry.lines.add(counts(summary, "lines")?);
+            entry.regions.add(counts(summary, "regions")?);
+        }
+    }
+
+    Ok(crates.into_values().collect())
+}
+
+/// Locate an LLVM tool, preferring the toolchain's llvm-tools component so the profile
+/// format matches the compiler
+pub fn find_llvm_tool(name: &str) -> Result<PathBuf, String> {
+    let sysroot = ProcessCommand::new("rustc").arg("--print").arg("sysroot").output().ok()
+        .filter(|output| output.status.success())
+        .map(|output| PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()));
+
+    if let Some(rustlib) = sysroot.map(|s| s.join("lib").join("rustlib")) {
+        if let Ok(entries) = fs::read_dir(&rustlib) {
+            for entry in entries.flatten() {
+                let tool = entry.path().join("bin").join(name);
+                if tool.exists() {
+                    return Ok(tool);
+                }
+            }
+        }
+    }
+
+    let on_path = ProcessCommand::new(name).arg("--version").stdout(Stdio::null()).stderr(Stdio::null()).status();
+    match on_path {
+        Ok(status) if status.success() => Ok(PathBuf::from(name)),
+        _ => Err(format!("{} not found; install it with `rustup component add llvm-tools-preview`", name)),
+    }
+}
+
+/// Test executables reported by cargo's JSON messages
+fn test_executables(cargo_stdout: &str) -> Vec<PathBuf> {
+    cargo_stdout
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .filter_map(|line| json::parse(line).ok())
+        .filter(|message| message.get("reason").and_then(JsonValue::as_str) == Some("compiler-artifact"))
+        .filter(|message| message.get("profile").and_then(|p| p.get("test")).and_then(JsonValue::as_bool) == Some(true))
+        .filter_map(|message| message.get("executable").and_then(JsonValue::as_str).map(PathBuf::from))
+        .collect()
+}
+
+fn llvm_cov_export(llvm_cov: &Path, profdata: &Path, objects: &[PathBuf], extra: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let mut cmd = ProcessCommand::new(llvm_cov);
+    cmd.arg("export")
+        .args(extra)
+        .arg(format!("-instr-profile={}", profdata.display()))
+        .arg(format!("-ignore-filename-regex={}", IGNORE_FILENAME_REGEX));
+    for (i, object) in objects.iter().enumerate() {
+        if i > 0 {
+            cmd.arg("-object");
+        }
+        cmd.arg(object);
+    }
+
+    let output = cmd.output()?;
+    if !output.status.success() {
+        return Err(format!("llvm-cov export failed: {}", String::from_utf8_lossy(&output.stderr)).into());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Run the tests of the crate or workspace at `workspace_root` under coverage instrumentation.
+/// Intermediate files go to `work_dir`; the lcov report is written to `lcov_path`.
+pub fn collect(workspace_root: &Path, work_dir: &Path, lcov_path: &Path, verbose: bool) -> Result<CoverageReport, Box<dyn std::error::Error>> {
+    let llvm_profdata = find_llvm_tool("llvm-profdata")?;
+    let llvm_cov = find_llvm_tool("llvm-cov")?;
+    let workspace_root = workspace_root.canonicalize()?;
+
+    let profile_dir = work_dir.join("profraw");
+    if profile_dir.exists() {
+        fs::remove_dir_all(&profile_dir)?;
+    }
+    fs::create_dir_all(&profile_dir)?;
+    let profile_dir = profile_dir.canonicalize()?;
+
+    info!("Running instrumented tests in {}", workspace_root.display());
+    let rustflags = match std::env::var("RUSTFLAGS") {
+        Ok(existing) if !existing.is_empty() => format!("{} -C instrument-coverage", existing),
+        _ => "-C instrument-coverage".to_string(),
+    };
+    let mut cmd = ProcessCommand::new("cargo");
+    cmd.current_dir(&workspace_root)
+        .arg("test")
+        .arg("--workspace")
+        .arg("--no-fail-fast")
+        .arg("--message-format=json")
+        .env("RUSTFLAGS", rustflags)
+        .env("LLVM_PROFILE_FILE", profile_dir.join("raeen-%p-%m.profraw"))
+        // Keep instrumented artifacts apart from regular builds
+        .env("CARGO_TARGET_DIR", work_dir.join("target"));
+
+    let output = cmd.output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if verbose {
+        println!("Coverage test stderr: {}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    let objects = test_executables(&stdout);
+    let profiles: Vec<PathBuf> = fs::read_dir(&profile_dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "profraw"))
+        .collect();
+    if objects.is_empty() || profiles.is_empty() {
+        return Err(format!(
+            "no coverage data produced ({} test binaries, {} profiles): {}",
+            objects.len(),
+            profiles.len(),
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+    debug!("Merging {} profiles from {} test binaries", profiles.len(), objects.len());
+
+    let profdata = work_dir.join("coverage.profdata");
+    let merge = ProcessCommand::new(&llvm_profdata)
+        .arg("merge")
+        .arg("-sparse")
+        .args(&profiles)
+        .arg("-o")
+        .arg(&profdata)
+        .output()?;
+    if !merge.status.success() {
+        return Err(format!("llvm-profdata merge failed: {}", String::from_utf8_lossy(&merge.stderr)).into());
+    }
+
+    let lcov = llvm_cov_export(&llvm_cov, &profdata, &objects, &["-format=lcov"])?;
+    if let Some(parent) = lcov_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(lcov_path, lcov)?;
+
+    let summary = llvm_cov_export(&llvm_cov, &profdata, &objects, &["-summary-only"])?;
+    let crates = summarize(&summary, &workspace_root)?;
+
+    Ok(CoverageReport {
+        crates,
+        lcov_path: lcov_path.to_path_buf(),
+        tests_passed: output.status.success(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_entry(path: &Path, lines: (u64, u64), regions: (u64, u64)) -> String {
+        format!(
+            r#"{{"filename":"{}","summary":{{"lines":{{"count":{},"covered":{},"percent":0}},"regions":{{"count":{},"covered":{},"notcovered":0,"percent":0}}}}}}"#,
+            path.display(),
+            lines.0,
+            lines.1,
+            regions.0,
+            regions.1
+        )
+    }
+
+    #[test]
+    fn test_summary_groups_files_by_crate() {
+        let workspace = std::env::temp_dir().join(format!("raeen-cov-{}", uuid::Uuid::new_v4()));
+        for krate in ["tools/build", "tools/test"] {
+            fs::create_dir_all(workspace.join(krate).join("src")).unwrap();
+            fs::write(workspace.join(krate).join("Cargo.toml"), "[package]").unwrap();
+        }
+
+        let files = [
+            file_entry(&workspace.join("tools/build/src/main.rs"), (100, 80), (40, 30)),
+            file_entry(&workspace.join("tools/build/src/cache.rs"), (100, 60), (60, 30)),
+            file_entry(&workspace.join("tools/test/src/main.rs"), (50, 10), (10, 1)),
+            file_entry(Path::new("/home/ci/.cargo/registry/src/clap/lib.rs"), (1000, 0), (500, 0)),
+        ];
+        let export = format!(r#"{{"data":[{{"files":[{}],"totals":{{}}}}],"type":"llvm.coverage.json.export"}}"#, files.join(","));
+
+        let crates = summarize(&export, &workspace).unwrap();
+        assert_eq!(crates.len(), 2);
+        assert_eq!(crates[0].name, "tools/build");
+        assert_eq!(crates[0].lines, CoverageCounts { covered: 140, count: 200 });
+        assert_eq!(crates[0].regions.percent(), 60.0);
+        assert_eq!(crates[1].lines.percent(), 20.0);
+
+        let report = CoverageReport { crates, lcov_path: PathBuf::from("lcov.info"), tests_passed: true };
+        assert!((report.total_lines().percent() - 60.0).abs() < 1e-9);
+        assert!(report.meets_threshold(60.0));
+        assert!(!report.meets_threshold(60.1));
+        assert!(report.render().contains("tools/build"));
+
+        fs::remove_dir_all(&workspace).unwrap();
+    }
+
+    #[test]
+    fn test_collect_on_small_crate() {
+        if find_llvm_tool("llvm-cov").is_err() || find_llvm_tool("llvm-profdata").is_err() {
+            eprintln!("skipping coverage collection test: llvm-tools not installed");
+            return;
+        }
+
+        let krate = std::env::temp_dir().join(format!("raeen-cov-crate-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(krate.join("src")).unwrap();
+        fs::write(
+            krate.join("Cargo.toml"),
+            "[package]\nname = \"cov_sample\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n",
+        )
+        .unwrap();
+        fs::write(
+            krate.join("src/lib.rs"),
+            "pub fn covered(x: u32) -> u32 {\n    if x > 1 {\n        x * 2\n    } else {\n        x + 1\n    }\n}\n\n\
+             pub fn uncovered(x: u32) -> u32 {\n    let cubed = x.pow(3);\n    cubed + 7\n}\n\n\
+             #[cfg(test)]\nmod tests {\n    #[test]\n    fn doubles() {\n        assert_eq!(super::covered(3), 6);\n    }\n}\n",
+        )
+        .unwrap();
+
+        let work_dir = krate.join("target").join("coverage");
+        let lcov_path = work_dir.join("lcov.info");
+        let report = collect(&krate, &work_dir, &lcov_path, false).unwrap();
+
+        assert!(report.tests_passed);
+        assert_eq!(report.crates.len(), 1);
+        let lines = report.total_lines().percent();
+        assert!(lines > 20.0 && lines < 100.0, "implausible line coverage {}", lines);
+        let regions = report.total_regions().percent();
+        assert!(regions > 0.0 && regions < 100.0, "implausible region coverage {}", regions);
+        assert!(report.meets_threshold(10.0));
+        assert!(!report.meets_threshold(99.0));
+
+        let lcov = fs::read_to_string(&lcov_path).unwrap();
+        assert!(lcov.contains("SF:") && lcov.contains("end_of_record"));
+
+        fs::remove_dir_all(&krate).unwrap();
+    }
+}
@@ -11,6 +11,8 @@ use std::time::{Duration, Instant};
 use chrono::{DateTime, Utc};
 use log::{info, warn, error};
 
+use raeen_common::json::{self, JsonValue};
+
 /// SLO test result structure matching the schema
 #[derive(Debug, Clone)] // Serialize, Deserialize temporarily disabled
 pub struct SloResults {
@@ -18,6 +20,92 @@ pub struct SloResults {
     pub metrics: HashMap<String, f64>,
 }
 
+impl SloResults {
+    pub fn to_json(&self) -> JsonValue {
+        let mut names: Vec<&String> = self.metrics.keys().collect();
+        names.sort();
+        JsonValue::object(vec![
+            ("platform", JsonValue::String(self.platform.clone())),
+            ("metrics", JsonValue::Object(names.into_iter().map(|name| (name.clone(), JsonValue::Number(self.metrics[name]))).collect())),
+        ])
+    }
+
+    pub fn from_json(value: &JsonValue) -> Result<Self, String> {
+        let platform = value.field("platform")?.as_str().ok_or("field 'platform' is not a string")?.to_string();
+        let metrics = match value.field("metrics")? {
+            JsonValue::Object(fields) => fields
+                .iter()
+                .map(|(name, v)| match v {
+                    JsonValue::Number(n) => Ok((name.clone(), *n)),
+                    _ => Err(format!("metric '{}' is not a number", name)),
+                })
+                .collect::<Result<HashMap<_, _>, String>>()?,
+            _ => return Err("field 'metrics' is not an object".to_string()),
+        };
+        Ok(Self { platform, metrics })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(path)?;
+        Ok(Self::from_json(&json::parse(&content)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_json().to_string())?;
+        Ok(())
+    }
+}
+
+/// Change in one metric relative to the baseline
+#[derive(Debug, Clone)]
+pub struct MetricDelta {
+    pub metric: String,
+    pub baseline: f64,
+    pub current: f64,
+    /// Percentage change; positive means slower
+    pub delta_percent: f64,
+    pub regressed: bool,
+}
+
+/// Per-metric comparison of a run against the stored baseline
+#[derive(Debug, Clone)]
+pub struct BaselineComparison {
+    pub tolerance_percent: f64,
+    pub deltas: Vec<MetricDelta>,
+}
+
+impl BaselineComparison {
+    pub fn passed(&self) -> bool {
+        self.deltas.iter().all(|d| !d.regressed)
+    }
+
+    pub fn regressions(&self) -> Vec<String> {
+        self.deltas
+            .iter()
+            .filter(|d| d.regressed)
+            .map(|d| format!(
+                "SLO REGRESSION: {} = {:.3}µs vs baseline {:.3}µs ({:+.1}% > +{:.1}%)",
+                d.metric, d.current, d.baseline, d.delta_percent, self.tolerance_percent
+            ))
+            .collect()
+    }
+
+    pub fn render(&self) -> String {
+        let mut report = format!("\n## Baseline Comparison (tolerance +{:.1}%)\n\n", self.tolerance_percent);
+        for delta in &self.deltas {
+            let status = if delta.regressed { "❌" } else { "✅" };
+            report.push_str(&format!(
+                "- {} {} {:.3}µs -> {:.3}µs ({:+.1}%)\n",
+                status, delta.metric, delta.baseline, delta.current, delta.delta_percent
+            ));
+        }
+        report
+    }
+}
+
 /// SLO test configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SloConfig {
@@ -627,16 +715,17 @@ impl SloTestRunner {
         (all_passed, failures)
     }
 
-    /// Export results to slo_results.json
-    pub fn export_results(&self, output_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
-        let slo_results = SloResults {
+    /// Measured metrics for this run
+    pub fn slo_results(&self) -> SloResults {
+        SloResults {
             platform: self.config.reference_sku.clone(),
             metrics: self.results.clone(),
-        };
-        
-        // let json = serde_json::to_string_pretty(&slo_results)?; // Temporarily disabled due to serde dependency conflicts
-        let json = format!("{{\"platform\": \"{}\", \"metrics\": {{}}}}", slo_results.platform);
-        fs::write(output_path, json)?;
+        }
+    }
+
+    /// Export results to slo_results.json
+    pub fn export_results(&self, output_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.slo_results().save(output_path)?;
         
         info!("SLO results exported to {}", output_path.display());
         Ok(())
@@ -696,11 +785,46 @@ impl SloGate {
         }
     }
 
+    /// Allowed slowdown relative to the baseline before a metric counts as regressed
+    pub fn with_drift_threshold(mut self, percent: f64) -> Self {
+        self.drift_threshold_percent = percent;
+        self
+    }
+
+    /// Compare every metric measured in both runs. All SLO metrics are lower-is-better, so a
+    /// metric regresses when it exceeds the baseline by more than the drift threshold, even if
+    /// it is still under its absolute target.
+    pub fn compare_to_baseline(&self, current: &SloResults, baseline: &SloResults) -> BaselineComparison {
+        let mut names: Vec<&String> = current.metrics.keys().filter(|name| baseline.metrics.contains_key(*name)).collect();
+        names.sort();
+
+        let deltas = names
+            .into_iter()
+            .map(|name| {
+                let current = current.metrics[name];
+                let baseline = baseline.metrics[name];
+                let delta_percent = if baseline > 0.0 {
+                    (current - baseline) / baseline * 100.0
+                } else if current > baseline {
+                    f64::INFINITY
+                } else {
+                    0.0
+                };
+                MetricDelta {
+                    metric: name.clone(),
+                    baseline,
+                    current,
+                    delta_percent,
+                    regressed: current > baseline * (1.0 + self.drift_threshold_percent / 100.0),
+                }
+            })
+            .collect();
+
+        BaselineComparison { tolerance_percent: self.drift_threshold_percent, deltas }
+    }
+
     /// Check if SLO results should pass CI gate
     pub fn should_pass_gate(&self, current_results: &SloResults, historical_results: &[SloResults]) -> (bool, String) {
-        // TODO: Implement actual gate logic with historical comparison
-        // For now, just check if all critical metrics are present
-        
         let critical_metrics = [
             "input.latency.p99",
             "compositor.jitter.p99",
@@ -715,7 +839,14 @@ impl SloGate {
             }
         }
         
-        (true, "All critical metrics present".to_string())
+        if let Some(previous) = historical_results.last() {
+            let comparison = self.compare_to_baseline(current_results, previous);
+            if !comparison.passed() {
+                return (false, comparison.regressions().join("; "));
+            }
+        }
+        
+        (true, "All critical metrics present with no regressions".to_string())
     }
 }
 
@@ -763,14 +894,63 @@ mod tests {
             },
         };
         
-        // let json = serde_json::to_string(&results).unwrap(); // Temporarily disabled due to serde dependency conflicts
-        // let deserialized: SloResults = serde_json::from_str(&json).unwrap();
-        
-        // assert_eq!(results.platform, deserialized.platform);
-        // assert_eq!(results.metrics, deserialized.metrics);
-        
-        // Temporary test without serde
-        assert_eq!(results.platform, "test-platform");
-        assert!(results.metrics.contains_key("test.metric"));
+        let deserialized = SloResults::from_json(&json::parse(&results.to_json().to_string()).unwrap()).unwrap();
+        assert_eq!(results.platform, deserialized.platform);
+        assert_eq!(results.metrics, deserialized.metrics);
+    }
+
+    fn results(metrics: &[(&str, f64)]) -> SloResults {
+        SloResults {
+            platform: "desk-sku-a".to_string(),
+            metrics: metrics.iter().map(|(name, value)| (name.to_string(), *value)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_regressed_results_fail_baseline() {
+        let baseline = results(&[("input.latency.p99", 1000.0), ("ipc.rtt.same_core.p99", 2.0)]);
+        // Both metrics remain far below their absolute targets
+        let current = results(&[("input.latency.p99", 1150.0), ("ipc.rtt.same_core.p99", 2.0)]);
+        
+        let comparison = SloGate::new().with_drift_threshold(10.0).compare_to_baseline(&current, &baseline);
+        assert!(!comparison.passed());
+        assert_eq!(comparison.regressions().len(), 1);
+        
+        let input = &comparison.deltas[0];
+        assert_eq!(input.metric, "input.latency.p99");
+        assert!(input.regressed);
+        assert!((input.delta_percent - 15.0).abs() < 1e-9);
+        assert!(!comparison.deltas[1].regressed);
+        assert!(comparison.render().contains("+15.0%"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_improved_results_pass_baseline() {
+        let baseline = results(&[("input.latency.p99", 1000.0), ("cap.revoke.p99", 150.0)]);
+        let current = results(&[("input.latency.p99", 900.0), ("cap.revoke.p99", 120.0)]);
+        
+        let comparison = SloGate::new().with_drift_threshold(10.0).compare_to_baseline(&current, &baseline);
+        assert!(comparison.passed());
+        assert!(comparison.regressions().is_empty());
+        assert!((comparison.deltas[1].delta_percent + 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_baseline_tolerance_applied() {
+        let baseline = results(&[("input.latency.p99", 1000.0)]);
+        let current = results(&[("input.latency.p99", 1080.0)]);
+        
+        assert!(SloGate::new().with_drift_threshold(10.0).compare_to_baseline(&current, &baseline).passed());
+        assert!(!SloGate::new().with_drift_threshold(5.0).compare_to_baseline(&current, &baseline).passed());
+        
+        let (pass, reason) = SloGate::new().should_pass_gate(&results(&[
+            ("input.latency.p99", 1080.0),
+            ("compositor.jitter.p99", 0.2),
+            ("ipc.rtt.same_core.p99", 2.0),
+            ("cap.revoke.p99", 150.0),
+            ("memory.anon_fault.p99", 10.0),
+        ]), &[baseline]);
+        assert!(!pass, "default 5% drift threshold should reject +8%");
+        assert!(reason.contains("input.latency.p99"));
+    }
+}
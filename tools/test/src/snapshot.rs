@@ -0,0 +1,337 @@
+//! QEMU boot snapshots for raeen-test
+//! Boots the ISO once until the kernel reports it is ready, saves the VM with `savevm` into a
+//! qcow2 state disk, and restores later boot tests with `-loadvm` so they skip firmware and
+//! kernel init entirely.
+
+use log::{debug, info};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command as ProcessCommand, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// Name of the saved VM state inside the snapshot image
+pub const SNAPSHOT_TAG: &str = "raeen-ready";
+
+/// qcow2 image holding the saved VM state, relative to the workspace root
+pub const SNAPSHOT_IMAGE: &str = "build/boot-snapshot.qcow2";
+
+/// Serial output that only appears while the kernel initialises; all of it must be seen
+/// before the snapshot is taken, and none of it after a restore
+pub const BOOT_MARKERS: &[&str] = &["[OK:SCHED-PREEMPT]", "[Desktop] Starting RaeenOS Desktop Environment"];
+
+const SERIAL_LOG: &str = "build/snapshot-serial.log";
+const MONITOR_PROMPT: &str = "(qemu) ";
+const MONITOR_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Outcome of booting from the saved snapshot
+#[derive(Debug, Clone)]
+pub struct RestoreOutcome {
+    /// The monitor reported the restored VM as running
+    pub running: bool,
+    /// Serial output produced after the restore
+    pub serial: String,
+    pub duration: Duration,
+}
+
+impl RestoreOutcome {
+    /// Ready without having gone through kernel init again
+    pub fn reached_ready_state(&self) -> bool {
+        self.running && !BOOT_MARKERS.iter().any(|marker| self.serial.contains(marker))
+    }
+}
+
+/// True once every boot marker has appeared in `serial`
+pub fn boot_markers_seen(serial: &str) -> bool {
+    BOOT_MARKERS.iter().all(|marker| serial.contains(marker))
+}
+
+pub struct SnapshotHarness {
+    qemu: String,
+    /// Directory QEMU runs in, so relative drive paths resolve as in a full boot
+    root: PathBuf,
+    /// Machine and device arguments shared by the snapshot and every restore
+    machine_args: Vec<String>,
+}
+
+impl SnapshotHarness {
+    pub fn new(qemu: &str, root: &Path, machine_args: &[String]) -> Self {
+        Self {
+            qemu: qemu.to_string(),
+            root: root.to_path_buf(),
+            machine_args: machine_args.to_vec(),
+        }
+    }
+
+    pub fn image_path(&self) -> PathBuf {
+        self.root.join(SNAPSHOT_IMAGE)
+    }
+
+    fn serial_log(&self) -> PathBuf {
+        self.root.join(SERIAL_LOG)
+    }
+
+    /// Snapshots need qemu-img to create the qcow2 state disk
+    pub fn available(&self) -> bool {
+        ProcessCommand::new("qemu-img")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_or(false, |s| s.success())
+    }
+
+    /// A snapshot exists, was taken after `iso` was last built and contains the ready state
+    pub fn is_fresh(&self, iso: &Path) -> bool {
+        let image = self.image_path();
+        let newer = match (fs::metadata(&image).and_then(|m| m.modified()), fs::metadata(iso).and_then(|m| m.modified())) {
+            (Ok(snapshot), Ok(iso)) => snapshot >= iso,
+            _ => false,
+        };
+        newer
+            && ProcessCommand::new("qemu-img")
+                .args(["snapshot", "-l"])
+                .arg(&image)
+                .output()
+                .map_or(false, |o| o.status.success() && String::from_utf8_lossy(&o.stdout).contains(SNAPSHOT_TAG))
+    }
+
+    /// Full VM command line. `savevm` refuses to run while any writable drive lacks snapshot
+    /// support, so raw drives are attached read-only and the state goes to a qcow2 disk.
+    pub fn vm_args(&self, boot_args: &[String], restore: bool) -> Vec<String> {
+        let mut args: Vec<String> = self
+            .machine_args
+            .iter()
+            .map(|arg| if arg.contains("format=raw") && !arg.contains("readonly=") { format!("{},readonly=on", arg) } else { arg.clone() })
+            .collect();
+        args.extend(boot_args.iter().cloned());
+        args.extend([
+            "-drive".to_string(),
+            format!("if=none,id=snapstate,format=qcow2,file={}", self.image_path().display()),
+            "-display".to_string(),
+            "none".to_string(),
+            "-serial".to_string(),
+            format!("file:{}", self.serial_log().display()),
+            "-monitor".to_string(),
+            "stdio".to_string(),
+        ]);
+        if restore {
+            args.extend(["-loadvm".to_string(), SNAPSHOT_TAG.to_string()]);
+        }
+        args
+    }
+
+    /// Boot from scratch, wait for every boot marker, then save the VM state
+    pub fn create(&self, boot_args: &[String], timeout: Duration) -> Result<Duration, Box<dyn std::error::Error>> {
+        let start = Instant::now();
+        let image = self.image_path();
+        if let Some(parent) = image.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let _ = fs::remove_file(&image);
+        let status = ProcessCommand::new("qemu-img")
+            .args(["create", "-q", "-f", "qcow2"])
+            .arg(&image)
+            .arg("64M")
+            .status()?;
+        if !status.success() {
+            return Err(format!("qemu-img could not create {}", image.display()).into());
+        }
+
+        info!("Booting once to create QEMU snapshot '{}'...", SNAPSHOT_TAG);
+        let mut vm = Monitor::spawn(&self.qemu, &self.root, &self.vm_args(boot_args, false), &self.serial_log())?;
+        let booted = self.wait_for_markers(&mut vm, timeout);
+        let saved = booted.and_then(|_| vm.command(&format!("savevm {}", SNAPSHOT_TAG)));
+        vm.quit();
+
+        match saved {
+            // savevm prints nothing on success
+            Ok(reply) if reply.trim().is_empty() => {
+                info!("QEMU snapshot saved to {} in {:?}", image.display(), start.elapsed());
+                Ok(start.elapsed())
+            }
+            Ok(reply) => {
+                let _ = fs::remove_file(&image);
+                Err(format!("savevm failed: {}", reply.trim()).into())
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&image);
+                Err(e)
+            }
+        }
+    }
+
+    /// Boot from the saved state and report whether the VM came up ready
+    pub fn restore(&self, boot_args: &[String], settle: Duration) -> Result<RestoreOutcome, Box<dyn std::error::Error>> {
+        let start = Instant::now();
+        let mut vm = Monitor::spawn(&self.qemu, &self.root, &self.vm_args(boot_args, true), &self.serial_log())?;
+        let status = vm.command("info status");
+        if status.is_ok() {
+            // Give a VM that wrongly rebooted time to print its boot markers
+            std::thread::sleep(settle);
+        }
+        vm.quit();
+
+        let running = status?.contains("running");
+        let serial = fs::read_to_string(self.serial_log()).unwrap_or_default();
+        let duration = start.elapsed();
+        debug!("Restored QEMU snapshot in {:?} (running: {})", duration, running);
+        Ok(RestoreOutcome { running, serial, duration })
+    }
+
+    fn wait_for_markers(&self, vm: &mut Monitor, timeout: Duration) -> Result<(), Box<dyn std::error::Error>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let serial = fs::read_to_string(self.serial_log()).unwrap_or_default();
+            if boot_markers_seen(&serial) {
+                return Ok(());
+            }
+            if let Some(status) = vm.child.try_wait()? {
+                return Err(format!("QEMU exited during boot ({})", status).into());
+            }
+            if Instant::now() >= deadline {
+                let missing: Vec<&str> = BOOT_MARKERS.iter().copied().filter(|m| !serial.contains(m)).collect();
+                return Err(format!("boot markers not seen within {:?}: {}", timeout, missing.join(", ")).into());
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// QEMU process driven through its human monitor on stdin/stdout
+struct Monitor {
+    child: Child,
+    stdin: ChildStdin,
+    output: Receiver<String>,
+}
+
+impl Monitor {
+    fn spawn(qemu: &str, root: &Path, args: &[String], serial_log: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let _ = fs::remove_file(serial_log);
+        let mut child = ProcessCommand::new(qemu)
+            .current_dir(root)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().ok_or("QEMU monitor stdin unavailable")?;
+        let mut stdout = child.stdout.take().ok_or("QEMU monitor stdout unavailable")?;
+
+        let (sender, output) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut buffer = [0u8; 4096];
+            while let Ok(n) = stdout.read(&mut buffer) {
+                if n == 0 || sender.send(String::from_utf8_lossy(&buffer[..n]).into_owned()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut monitor = Self { child, stdin, output };
+        monitor.read_until_prompt()?;
+        Ok(monitor)
+    }
+
+    /// Output up to the next prompt
+    fn read_until_prompt(&mut self) -> Result<String, Box<dyn std::error::Error>> {
+        let deadline = Instant::now() + MONITOR_TIMEOUT;
+        let mut text = String::new();
+        while !text.contains(MONITOR_PROMPT) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.output.recv_timeout(remaining) {
+                Ok(chunk) => text.push_str(&chunk),
+                Err(RecvTimeoutError::Timeout) => return Err("timed out waiting for the QEMU monitor".into()),
+                Err(RecvTimeoutError::Disconnected) => return Err("QEMU exited before the monitor responded".into()),
+            }
+        }
+        let end = text.find(MONITOR_PROMPT).unwrap_or(text.len());
+        Ok(text[..end].to_string())
+    }
+
+    /// Run a monitor command and return its reply without the echoed command line
+    fn command(&mut self, command: &str) -> Result<String, Box<dyn std::error::Error>> {
+        debug!("QEMU monitor: {}", command);
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()?;
+        let reply = self.read_until_prompt()?;
+        Ok(reply.lines().filter(|line| !line.contains(command)).collect::<Vec<_>>().join("\n"))
+    }
+
+    fn quit(mut self) {
+        let _ = writeln!(self.stdin, "quit");
+        let _ = self.stdin.flush();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine_args() -> Vec<String> {
+        ["-m", "1G", "-drive", "file=build/virtio-test.img,if=none,id=vd0,format=raw", "-device", "virtio-blk-pci,drive=vd0"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_vm_args_make_state_savable() {
+        let harness = SnapshotHarness::new("qemu-system-x86_64", Path::new("."), &machine_args());
+        let boot = vec!["-cdrom".to_string(), "raeen-os.iso".to_string()];
+
+        let args = harness.vm_args(&boot, false);
+        assert!(args.contains(&"file=build/virtio-test.img,if=none,id=vd0,format=raw,readonly=on".to_string()));
+        assert!(args.iter().any(|a| a.contains("format=qcow2") && a.contains(SNAPSHOT_IMAGE)));
+        assert!(!args.contains(&"-loadvm".to_string()));
+
+        let restored = harness.vm_args(&boot, true);
+        assert_eq!(&restored[restored.len() - 2..], ["-loadvm", SNAPSHOT_TAG]);
+    }
+
+    #[test]
+    fn test_ready_state_requires_no_init_markers() {
+        assert!(boot_markers_seen("[OK:SCHED-PREEMPT]\n[Desktop] Starting RaeenOS Desktop Environment...\n"));
+        assert!(!boot_markers_seen("[OK:SCHED-PREEMPT]\n"));
+
+        let restored = RestoreOutcome { running: true, serial: String::new(), duration: Duration::ZERO };
+        assert!(restored.reached_ready_state());
+        let rebooted = RestoreOutcome { running: true, serial: "[OK:SCHED-PREEMPT]\n".to_string(), duration: Duration::ZERO };
+        assert!(!rebooted.reached_ready_state());
+        let stopped = RestoreOutcome { running: false, serial: String::new(), duration: Duration::ZERO };
+        assert!(!stopped.reached_ready_state());
+    }
+
+    #[test]
+    fn test_snapshot_after_boot_markers_restores_ready_state() {
+        let workspace = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+        let iso = workspace.join("build/raeen-os.iso");
+        let root = std::env::temp_dir().join(format!("raeen-snapshot-{}", uuid::Uuid::new_v4()));
+        let harness = SnapshotHarness::new("qemu-system-x86_64", &root, &["-m".to_string(), "1G".to_string()]);
+        if !iso.exists() || !harness.available() || ProcessCommand::new("qemu-system-x86_64").arg("--version").output().is_err() {
+            eprintln!("skipping snapshot test: QEMU, qemu-img or a built ISO is missing");
+            return;
+        }
+
+        let boot = vec!["-cdrom".to_string(), iso.display().to_string(), "-boot".to_string(), "d".to_string()];
+        let full_boot = harness.create(&boot, Duration::from_secs(120)).unwrap();
+        assert!(harness.is_fresh(&iso));
+
+        let outcome = harness.restore(&boot, Duration::from_secs(2)).unwrap();
+        assert!(outcome.reached_ready_state(), "restore re-ran init: {}", outcome.serial);
+        assert!(outcome.duration < full_boot);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}